@@ -1,8 +1,12 @@
 fn main() {
-    varlink_generator::cargo_build_tosource("src/varlink/org.avocado.Extensions.varlink", false);
-    varlink_generator::cargo_build_tosource("src/varlink/org.avocado.Runtimes.varlink", false);
-    varlink_generator::cargo_build_tosource("src/varlink/org.avocado.Hitl.varlink", false);
-    varlink_generator::cargo_build_tosource("src/varlink/org.avocado.RootAuthority.varlink", false);
+    // The varlink interfaces are only compiled into builds with the daemon
+    #[cfg(feature = "daemon")]
+    for interface in ["Extensions", "Runtimes", "Hitl", "RootAuthority"] {
+        varlink_generator::cargo_build_tosource(
+            &format!("src/varlink/org.avocado.{interface}.varlink"),
+            false,
+        );
+    }
 
     // Embed git commit hash for version identification
     let git_hash = std::process::Command::new("git")
@@ -0,0 +1,332 @@
+//! Extensions delivered as zstd-compressed tarballs (`<name>[-<version>].tar.zst`).
+//!
+//! Archives found in the extensions directory are unpacked once into a
+//! persistent cache and then merged like directory extensions. Each cache
+//! entry records the SHA256 of the archive it came from, so replacing the
+//! archive invalidates the entry on the next scan.
+//!
+//! Hashing an archive of hundreds of megabytes on every scan is slow, so
+//! the entry also records the archive's path, size, mtime and inode. While
+//! those match, the entry is used without reading the archive; otherwise
+//! the archive is hashed, and an unchanged checksum only refreshes them.
+
+use crate::hash::{sha256_file, Checksummer};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::BufReader;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// File suffix identifying an archive extension.
+pub const ARCHIVE_SUFFIX: &str = ".tar.zst";
+
+/// Suffix of the checksum marker written next to each unpacked cache entry.
+const CHECKSUM_SUFFIX: &str = ".sha256";
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("Failed to hash {path}: {source}")]
+    Hash {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to unpack {path}: {message}")]
+    Unpack { path: PathBuf, message: String },
+}
+
+/// Directory holding unpacked archive extensions, respecting AVOCADO_TEST_MODE.
+/// Lives on persistent storage so large archives don't fill a tmpfs.
+pub fn cache_dir() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/archive-cache"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path(
+            "/var/lib/avocado/archive-cache",
+        ))
+    }
+}
+
+/// Strip [`ARCHIVE_SUFFIX`] from a file name, if it is an archive.
+pub fn archive_stem(file_name: &str) -> Option<&str> {
+    file_name
+        .strip_suffix(ARCHIVE_SUFFIX)
+        .filter(|stem| !stem.is_empty())
+}
+
+/// Cache entry and checksum marker paths for `archive`.
+fn cache_paths(archive: &Path, cache_root: &Path) -> (PathBuf, PathBuf, String) {
+    let file_name = archive
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = archive_stem(&file_name).unwrap_or(&file_name).to_string();
+    let target = cache_root.join(&stem);
+    let marker = cache_root.join(format!("{stem}{CHECKSUM_SUFFIX}"));
+    (target, marker, stem)
+}
+
+/// What identifies one version of an archive without reading it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Stamp {
+    path: PathBuf,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    inode: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        Some(Self {
+            path: path.to_path_buf(),
+            size: meta.size(),
+            mtime: meta.mtime(),
+            mtime_nsec: meta.mtime_nsec(),
+            inode: meta.ino(),
+        })
+    }
+}
+
+/// Contents of a checksum marker. Markers written before the stamp was
+/// recorded hold only the checksum.
+#[derive(Debug, Serialize, Deserialize)]
+struct Marker {
+    sha256: String,
+    stamp: Option<Stamp>,
+}
+
+impl Marker {
+    fn read(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok().or_else(|| {
+            Some(Self {
+                sha256: content.trim().to_string(),
+                stamp: None,
+            })
+        })
+    }
+
+    fn write(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string(self).map_err(std::io::Error::other)?;
+        fs::write(path, format!("{json}\n"))
+    }
+}
+
+/// How the cache entry of an archive compares to the archive.
+enum Freshness {
+    /// The archive is unchanged since the entry was recorded
+    Current,
+    /// The archive was touched or moved but has the same bytes
+    SameBytes(Marker),
+    /// The archive has other bytes, or there is no entry
+    Stale(String),
+}
+
+/// Compare the entry at `target` and `marker` with `archive`, hashing the
+/// archive only when its stamp changed.
+fn freshness(
+    archive: &Path,
+    target: &Path,
+    marker: &Path,
+    stamp: Option<&Stamp>,
+) -> std::io::Result<Freshness> {
+    let recorded = Marker::read(marker).filter(|_| target.is_dir());
+    if let Some(recorded) = &recorded {
+        if stamp.is_some() && recorded.stamp.as_ref() == stamp {
+            return Ok(Freshness::Current);
+        }
+    }
+    let checksum = sha256_file(&Checksummer::default(), archive)?;
+    Ok(match recorded {
+        Some(recorded) if recorded.sha256 == checksum => Freshness::SameBytes(Marker {
+            sha256: checksum,
+            stamp: stamp.cloned(),
+        }),
+        _ => Freshness::Stale(checksum),
+    })
+}
+
+/// The unpacked directory for `archive` under `cache_root`, if it is cached
+/// from the same bytes. Never writes to the cache.
+pub fn cached(archive: &Path, cache_root: &Path) -> Option<PathBuf> {
+    let (target, marker, _) = cache_paths(archive, cache_root);
+    let stamp = Stamp::of(archive);
+    match freshness(archive, &target, &marker, stamp.as_ref()).ok()? {
+        Freshness::Current | Freshness::SameBytes(_) => Some(target),
+        Freshness::Stale(_) => None,
+    }
+}
+
+/// Return the unpacked directory for `archive` under `cache_root`, unpacking
+/// it first if the cache entry is missing or was built from different bytes.
+pub fn unpack_cached(archive: &Path, cache_root: &Path) -> Result<PathBuf, ArchiveError> {
+    let (target, marker, stem) = cache_paths(archive, cache_root);
+
+    // Taken before hashing: a change made meanwhile misses on the next scan
+    let stamp = Stamp::of(archive);
+    let checksum = match freshness(archive, &target, &marker, stamp.as_ref()) {
+        Ok(Freshness::Current) => return Ok(target),
+        Ok(Freshness::SameBytes(refreshed)) => {
+            // Only the stamp changed; without it the next scan hashes again
+            let _ = refreshed.write(&marker);
+            return Ok(target);
+        }
+        Ok(Freshness::Stale(checksum)) => checksum,
+        Err(e) => {
+            return Err(ArchiveError::Hash {
+                path: archive.to_path_buf(),
+                source: e,
+            })
+        }
+    };
+
+    let unpack_err = |message: String| ArchiveError::Unpack {
+        path: archive.to_path_buf(),
+        message,
+    };
+
+    // Unpack next to the final location and rename, so an interrupted unpack
+    // never leaves a half-populated directory that looks valid.
+    let partial = cache_root.join(format!("{stem}.partial"));
+    let _ = fs::remove_dir_all(&partial);
+    fs::create_dir_all(&partial)
+        .map_err(|e| unpack_err(format!("create {}: {e}", partial.display())))?;
+
+    let file = fs::File::open(archive).map_err(|e| unpack_err(e.to_string()))?;
+    let decoder = zstd::stream::Decoder::new(BufReader::new(file))
+        .map_err(|e| unpack_err(format!("zstd decoder: {e}")))?;
+    let mut tar = tar::Archive::new(decoder);
+    tar.set_preserve_permissions(true);
+    if let Err(e) = tar.unpack(&partial) {
+        let _ = fs::remove_dir_all(&partial);
+        return Err(unpack_err(format!("tar: {e}")));
+    }
+
+    let _ = fs::remove_file(&marker);
+    let _ = fs::remove_dir_all(&target);
+    fs::rename(&partial, &target).map_err(|e| unpack_err(format!("rename: {e}")))?;
+    Marker {
+        sha256: checksum,
+        stamp,
+    }
+    .write(&marker)
+    .map_err(|e| unpack_err(format!("marker: {e}")))?;
+    Ok(target)
+}
+
+/// Remove cache entries whose archive stem is not in `keep`.
+pub fn prune_cache(cache_root: &Path, keep: &[String]) {
+    let Ok(entries) = fs::read_dir(cache_root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let stem = name
+            .strip_suffix(CHECKSUM_SUFFIX)
+            .or_else(|| name.strip_suffix(".partial"))
+            .unwrap_or(&name);
+        if keep.iter().any(|k| k == stem) {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            let _ = fs::remove_dir_all(&path);
+        } else {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn write_archive(path: &Path, release_name: &str, body: &[u8]) {
+        let file = fs::File::create(path).unwrap();
+        let encoder = zstd::stream::Encoder::new(file, 3).unwrap();
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(body.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(
+                &mut header,
+                format!("usr/lib/extension-release.d/extension-release.{release_name}"),
+                body,
+            )
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_archive_stem() {
+        assert_eq!(archive_stem("app-1.0.tar.zst"), Some("app-1.0"));
+        assert_eq!(archive_stem("app-1.0.raw"), None);
+        assert_eq!(archive_stem(".tar.zst"), None);
+    }
+
+    #[test]
+    fn test_unpack_cached_reuses_and_invalidates() {
+        let tmp = TempDir::new().unwrap();
+        let archive = tmp.path().join("app-1.0.tar.zst");
+        let cache = tmp.path().join("cache");
+        write_archive(&archive, "app", b"ID=_any\n");
+        assert_eq!(cached(&archive, &cache), None);
+
+        let dir = unpack_cached(&archive, &cache).unwrap();
+        assert_eq!(cached(&archive, &cache), Some(dir.clone()));
+        let release = dir.join("usr/lib/extension-release.d/extension-release.app");
+        assert_eq!(fs::read_to_string(&release).unwrap(), "ID=_any\n");
+
+        // Same bytes: the cache entry is reused as-is.
+        fs::write(dir.join("marker"), "").unwrap();
+        unpack_cached(&archive, &cache).unwrap();
+        assert!(dir.join("marker").exists());
+
+        // Same size, mtime and inode: trusted without reading the archive.
+        let modified = fs::metadata(&archive).unwrap().modified().unwrap();
+        let len = fs::metadata(&archive).unwrap().len() as usize;
+        let mut file = fs::OpenOptions::new().write(true).open(&archive).unwrap();
+        file.write_all(&vec![0u8; len]).unwrap();
+        file.set_modified(modified).unwrap();
+        drop(file);
+        assert_eq!(cached(&archive, &cache), Some(dir.clone()));
+
+        // Touched with the same bytes: hashed, and the entry is kept.
+        write_archive(&archive, "app", b"ID=_any\n");
+        assert_eq!(cached(&archive, &cache), Some(dir.clone()));
+        unpack_cached(&archive, &cache).unwrap();
+        assert!(dir.join("marker").exists());
+
+        // New bytes: the entry is stale, then rebuilt.
+        write_archive(&archive, "app", b"ID=_any\nVERSION_ID=2\n");
+        assert_eq!(cached(&archive, &cache), None);
+        unpack_cached(&archive, &cache).unwrap();
+        assert!(!dir.join("marker").exists());
+        assert_eq!(
+            fs::read_to_string(&release).unwrap(),
+            "ID=_any\nVERSION_ID=2\n"
+        );
+    }
+
+    #[test]
+    fn test_prune_cache_keeps_listed_entries() {
+        let tmp = TempDir::new().unwrap();
+        for name in ["keep", "drop"] {
+            fs::create_dir_all(tmp.path().join(name)).unwrap();
+            fs::write(tmp.path().join(format!("{name}{CHECKSUM_SUFFIX}")), "x").unwrap();
+        }
+        prune_cache(tmp.path(), &["keep".to_string()]);
+        assert!(tmp.path().join("keep").is_dir());
+        assert!(tmp.path().join(format!("keep{CHECKSUM_SUFFIX}")).exists());
+        assert!(!tmp.path().join("drop").exists());
+        assert!(!tmp.path().join(format!("drop{CHECKSUM_SUFFIX}")).exists());
+    }
+}
@@ -0,0 +1,220 @@
+//! Extension inventory reports for compliance evidence.
+//!
+//! `avocadoctl ext audit` combines the state captured by `ext snapshot` with
+//! the on-disk identity of every extension image (path, SHA256, and the
+//! SHA256 the active runtime manifest expects). The report can be signed
+//! with an ed25519 key; the result uses the same `{"signed", "signatures"}`
+//! envelope and key-id derivation as the TUF metadata verified by
+//! `avocadoctl update`, so existing tooling can check it.
+
+use crate::hash::{hex_decode, hex_encode, sha256_file, Checksummer};
+use crate::snapshot::StateSnapshot;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// Current audit report schema version. Bumped only on non-additive changes.
+pub const AUDIT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditReport {
+    pub version: u32,
+    /// Generation time in seconds since the Unix epoch.
+    pub generated_at: u64,
+    /// SHA256 of the active runtime's manifest.json, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest_sha256: Option<String>,
+    /// Names, versions, sources and merge state.
+    pub state: StateSnapshot,
+    /// On-disk identity of each extension image.
+    #[serde(default)]
+    pub images: Vec<AuditImage>,
+    /// Extensions (`name-version`) past their AVOCADO_EOL date at
+    /// `generated_at`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub eol_reached: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditImage {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub path: String,
+    /// SHA256 of the image file. `None` for directory extensions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// SHA256 recorded for this image in the active runtime manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_sha256: Option<String>,
+    /// Whether `sha256` matches `expected_sha256`, when both are known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+}
+
+impl AuditImage {
+    /// Describe the image at `path`, hashing it when it is a regular file.
+    pub fn inspect(
+        name: &str,
+        version: Option<String>,
+        path: &Path,
+        expected_sha256: Option<String>,
+    ) -> Self {
+        let sha256 = if path.is_file() {
+            sha256_file(&Checksummer::default(), path).ok()
+        } else {
+            None
+        };
+        let verified = match (&sha256, &expected_sha256) {
+            (Some(actual), Some(expected)) => Some(actual.eq_ignore_ascii_case(expected)),
+            _ => None,
+        };
+        Self {
+            name: name.to_string(),
+            version,
+            path: path.display().to_string(),
+            sha256,
+            expected_sha256,
+            verified,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportSignature {
+    pub keyid: String,
+    pub sig: String,
+}
+
+/// A report wrapped in a signature envelope.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedAuditReport {
+    pub signatures: Vec<ReportSignature>,
+    pub signed: AuditReport,
+}
+
+impl AuditReport {
+    /// Serialize the report as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Sign the report with the ed25519 key stored in `key_path`.
+    pub fn sign_with_key_file(self, key_path: &Path) -> Result<SignedAuditReport, String> {
+        let content = fs::read_to_string(key_path)
+            .map_err(|e| format!("Failed to read signing key '{}': {e}", key_path.display()))?;
+        let key_pair = parse_signing_key(&content)
+            .map_err(|e| format!("Invalid signing key '{}': {e}", key_path.display()))?;
+        Ok(self.sign(&key_pair))
+    }
+
+    /// Sign the compact JSON serialization of the report.
+    pub fn sign(self, key_pair: &ed25519_compact::KeyPair) -> SignedAuditReport {
+        let canonical = serde_json::to_string(&self).unwrap_or_default();
+        let sig = key_pair.sk.sign(canonical.as_bytes(), None);
+        SignedAuditReport {
+            signatures: vec![ReportSignature {
+                keyid: key_id(&key_pair.pk),
+                sig: hex_encode(sig.as_ref()),
+            }],
+            signed: self,
+        }
+    }
+}
+
+impl SignedAuditReport {
+    /// Serialize the envelope as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Check that at least one signature was made by `public_key`.
+    pub fn verify(&self, public_key: &ed25519_compact::PublicKey) -> bool {
+        let canonical = serde_json::to_string(&self.signed).unwrap_or_default();
+        let keyid = key_id(public_key);
+        self.signatures.iter().any(|s| {
+            s.keyid == keyid
+                && hex_decode(&s.sig)
+                    .and_then(|b| ed25519_compact::Signature::from_slice(&b).ok())
+                    .is_some_and(|sig| public_key.verify(canonical.as_bytes(), &sig).is_ok())
+        })
+    }
+}
+
+/// TUF key id: SHA256 of the canonical ed25519 public key description.
+pub fn key_id(public_key: &ed25519_compact::PublicKey) -> String {
+    let pk_hex = hex_encode(public_key.as_ref());
+    let canonical =
+        format!(r#"{{"keytype":"ed25519","keyval":{{"public":"{pk_hex}"}},"scheme":"ed25519"}}"#);
+    hex_encode(&Sha256::digest(canonical.as_bytes()))
+}
+
+/// Parse a hex-encoded ed25519 key: a 32-byte seed or a 64-byte secret key.
+pub fn parse_signing_key(content: &str) -> Result<ed25519_compact::KeyPair, String> {
+    let bytes = hex_decode(content.trim()).ok_or("expected a hex-encoded ed25519 key")?;
+    match bytes.len() {
+        32 => {
+            let seed = ed25519_compact::Seed::from_slice(&bytes).map_err(|e| e.to_string())?;
+            Ok(ed25519_compact::KeyPair::from_seed(seed))
+        }
+        64 => ed25519_compact::KeyPair::from_slice(&bytes).map_err(|e| e.to_string()),
+        n => Err(format!("expected 32 or 64 bytes, got {n}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_report() -> AuditReport {
+        AuditReport {
+            version: AUDIT_VERSION,
+            generated_at: 1,
+            manifest_sha256: None,
+            state: StateSnapshot::from_json(r#"{"extensions":[]}"#).unwrap(),
+            images: vec![],
+            eol_reached: vec![],
+        }
+    }
+
+    #[test]
+    fn test_inspect_hashes_files_and_verifies() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let image = tmp.path().join("app-1.0.raw");
+        fs::write(&image, b"image").unwrap();
+        let actual = sha256_file(&Checksummer::default(), &image).unwrap();
+
+        let ok = AuditImage::inspect("app", Some("1.0".into()), &image, Some(actual.clone()));
+        assert_eq!(ok.sha256.as_deref(), Some(actual.as_str()));
+        assert_eq!(ok.verified, Some(true));
+
+        let bad = AuditImage::inspect("app", None, &image, Some("00".into()));
+        assert_eq!(bad.verified, Some(false));
+
+        let dir = AuditImage::inspect("dir", None, tmp.path(), None);
+        assert_eq!(dir.sha256, None);
+        assert_eq!(dir.verified, None);
+    }
+
+    #[test]
+    fn test_sign_and_verify_roundtrip() {
+        let seed_hex = hex_encode(&[7u8; 32]);
+        let key_pair = parse_signing_key(&format!("{seed_hex}\n")).unwrap();
+        let signed = sample_report().sign(&key_pair);
+        assert!(signed.verify(&key_pair.pk));
+
+        let reparsed: SignedAuditReport = serde_json::from_str(&signed.to_json()).unwrap();
+        assert!(reparsed.verify(&key_pair.pk));
+
+        let mut tampered = reparsed;
+        tampered.signed.generated_at = 2;
+        assert!(!tampered.verify(&key_pair.pk));
+    }
+
+    #[test]
+    fn test_parse_signing_key_rejects_bad_input() {
+        assert!(parse_signing_key("not-hex").is_err());
+        assert!(parse_signing_key("abcd").is_err());
+    }
+}
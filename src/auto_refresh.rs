@@ -0,0 +1,413 @@
+//! Automatic extension refresh for `avocadoctl serve`.
+//!
+//! When `[avocado.auto_refresh] enabled = true`, the daemon polls a cheap
+//! fingerprint (path, size, mtime) of the extensions, os-releases and HITL
+//! directories. A change does not refresh immediately: changes are coalesced
+//! until the tree has been quiet for `debounce_ms`, and refreshes are spaced
+//! at least `min_interval_ms` apart, so a burst of edits during HITL
+//! development produces one merge instead of dozens. While a HITL extension
+//! is quiesced or being synced, the refresh is postponed until it is not,
+//! and outside the `[avocado.maintenance]` windows it is held until one
+//! opens.
+//!
+//! Independently of `enabled`, the daemon re-checks os-release VERSION_ID
+//! every [`OS_RELEASE_CHECK_INTERVAL`]. When it changes, for example after
+//! a soft-reboot into a new OS, it refreshes to merge the new VERSION_ID's
+//! enabled set, and auto-refresh watches that set's directory from then on.
+
+use crate::config::{AutoRefreshSettings, Config};
+use crate::output::OutputManager;
+use crate::service;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the daemon re-checks os-release VERSION_ID.
+pub const OS_RELEASE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Counters describing what the auto-refresh loop has done so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AutoRefreshStats {
+    pub enabled: bool,
+    /// Filesystem changes observed.
+    pub triggers: u64,
+    /// Changes folded into an already pending refresh.
+    pub coalesced: u64,
+    /// Pending refreshes delayed by `min_interval_ms`.
+    pub throttled: u64,
+    /// Refreshes that completed successfully.
+    pub refreshes: u64,
+    /// Refreshes skipped because nothing changed since the last merge.
+    pub skipped: u64,
+    /// Refreshes postponed while a HITL extension was being synced.
+    pub deferred: u64,
+    /// Refreshes held until a maintenance window opened.
+    pub queued: u64,
+    /// Refreshes that returned an error.
+    pub failures: u64,
+    /// Time of the last refresh in seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_refresh: Option<u64>,
+}
+
+/// Shared handle to the live counters, read by the varlink server.
+pub type SharedStats = Arc<Mutex<AutoRefreshStats>>;
+
+/// Debounce and rate-limit state machine. Time is passed in explicitly so
+/// the policy can be exercised without sleeping.
+#[derive(Debug)]
+pub struct Throttle {
+    debounce: Duration,
+    min_interval: Duration,
+    last_event: Option<Instant>,
+    last_refresh: Option<Instant>,
+    pending: bool,
+    throttled_pending: bool,
+    deferred_pending: bool,
+    queued_pending: bool,
+    pub stats: AutoRefreshStats,
+}
+
+impl Throttle {
+    pub fn new(settings: &AutoRefreshSettings) -> Self {
+        Self {
+            debounce: Duration::from_millis(settings.debounce_ms),
+            min_interval: Duration::from_millis(settings.min_interval_ms),
+            last_event: None,
+            last_refresh: None,
+            pending: false,
+            throttled_pending: false,
+            deferred_pending: false,
+            queued_pending: false,
+            stats: AutoRefreshStats {
+                enabled: settings.enabled,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Record a filesystem change observed at `now`.
+    pub fn on_event(&mut self, now: Instant) {
+        self.stats.triggers += 1;
+        if self.pending {
+            self.stats.coalesced += 1;
+        }
+        self.pending = true;
+        self.last_event = Some(now);
+    }
+
+    /// Whether a refresh should run at `now`. Returns true at most once per
+    /// pending batch; the caller must follow up with [`Throttle::record_refresh`].
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if !self.pending {
+            return false;
+        }
+        if self
+            .last_event
+            .is_some_and(|t| now.duration_since(t) < self.debounce)
+        {
+            return false;
+        }
+        if self
+            .last_refresh
+            .is_some_and(|t| now.duration_since(t) < self.min_interval)
+        {
+            if !self.throttled_pending {
+                self.stats.throttled += 1;
+                self.throttled_pending = true;
+            }
+            return false;
+        }
+        self.pending = false;
+        self.throttled_pending = false;
+        true
+    }
+
+    /// Record the outcome of a refresh that finished at `now`.
+    pub fn record_refresh(&mut self, now: Instant, ok: bool) {
+        if ok {
+            self.stats.refreshes += 1;
+        } else {
+            self.stats.failures += 1;
+        }
+        self.last_refresh = Some(now);
+        self.deferred_pending = false;
+        self.queued_pending = false;
+        self.stats.last_refresh = Some(crate::clock::now());
+    }
+}
+
+impl Throttle {
+    /// Record a refresh that found nothing to do. It does not delay the next
+    /// refresh, since nothing was unmerged.
+    pub fn record_skip(&mut self) {
+        self.stats.skipped += 1;
+        self.deferred_pending = false;
+        self.queued_pending = false;
+    }
+
+    /// Put back a refresh [`Throttle::poll`] released, because a HITL
+    /// extension is still being synced; the next poll releases it again.
+    pub fn defer(&mut self) {
+        if !self.deferred_pending {
+            self.stats.deferred += 1;
+            self.deferred_pending = true;
+        }
+        self.pending = true;
+    }
+
+    /// Put back a refresh [`Throttle::poll`] released outside the
+    /// maintenance windows; it is released again on every poll until one
+    /// opens.
+    pub fn hold(&mut self) {
+        if !self.queued_pending {
+            self.stats.queued += 1;
+            self.queued_pending = true;
+        }
+        self.pending = true;
+    }
+}
+
+/// Directories whose changes trigger a refresh: those the extension
+/// sources watch, respecting AVOCADO_TEST_MODE.
+pub fn watched_paths(config: &Config) -> Vec<PathBuf> {
+    crate::commands::ext::source::watched_paths(Path::new(&config.get_extensions_dir()))
+}
+
+/// Hash the path, size and mtime of every entry below `roots`. Symlinks are
+/// hashed by their own metadata and target, never followed.
+pub fn fingerprint(roots: &[PathBuf]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for root in roots {
+        hash_tree(root, &mut hasher);
+    }
+    hasher.finish()
+}
+
+fn hash_tree(path: &Path, hasher: &mut DefaultHasher) {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return;
+    };
+    path.hash(hasher);
+    meta.len().hash(hasher);
+    if let Ok(modified) = meta.modified() {
+        modified.hash(hasher);
+    }
+    if meta.file_type().is_symlink() {
+        if let Ok(target) = fs::read_link(path) {
+            target.hash(hasher);
+        }
+        return;
+    }
+    if !meta.is_dir() {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    let mut children: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+    children.sort();
+    for child in children {
+        hash_tree(&child, hasher);
+    }
+}
+
+/// Start the auto-refresh loop on a background thread. Returns the shared
+/// counters; when disabled no thread is started and the counters stay zero.
+pub fn spawn(config: &Config, output: &OutputManager) -> SharedStats {
+    let settings = config.auto_refresh().clone();
+    let stats = Arc::new(Mutex::new(AutoRefreshStats {
+        enabled: settings.enabled,
+        ..Default::default()
+    }));
+    if !settings.enabled {
+        return stats;
+    }
+
+    let schedule = match crate::maintenance::schedule(config) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            output.warning(&format!("Maintenance windows ignored: {e}"));
+            crate::maintenance::Schedule::default()
+        }
+    };
+    let config = config.clone();
+    let shared = Arc::clone(&stats);
+    let output = output.detached();
+    thread::spawn(move || {
+        let mut version_id = crate::os_release::version_id();
+        let mut roots = watched_paths(&config);
+        let poll_interval = Duration::from_millis(settings.poll_interval_ms.max(1));
+        let mut throttle = Throttle::new(&settings);
+        let mut last = fingerprint(&roots);
+        loop {
+            thread::sleep(poll_interval);
+            // The os-release watch refreshes for a new VERSION_ID; from then
+            // on its os-releases directory is the one to watch
+            let current_version_id = crate::os_release::version_id();
+            if current_version_id != version_id {
+                version_id = current_version_id;
+                roots = watched_paths(&config);
+                last = fingerprint(&roots);
+            }
+            let current = fingerprint(&roots);
+            if current != last {
+                throttle.on_event(Instant::now());
+                last = current;
+            }
+            if throttle.poll(Instant::now()) {
+                if !schedule.is_open(crate::clock::now()) {
+                    throttle.hold();
+                } else if !crate::hitl_sync::busy_extensions(config.hitl()).is_empty() {
+                    throttle.defer();
+                } else {
+                    match service::ext::refresh_if_changed(&config, false) {
+                        Ok((_, true)) => throttle.record_refresh(Instant::now(), true),
+                        Ok((_, false)) => throttle.record_skip(),
+                        Err(e) => {
+                            output.warning(&format!("Auto-refresh failed: {e}"));
+                            throttle.record_refresh(Instant::now(), false);
+                        }
+                    }
+                    // Don't count our own refresh as a change.
+                    last = fingerprint(&roots);
+                }
+            }
+            if let Ok(mut s) = shared.lock() {
+                *s = throttle.stats.clone();
+            }
+        }
+    });
+    stats
+}
+
+/// Watch os-release VERSION_ID on a background thread and refresh when it
+/// changes. A failed refresh is retried on the next check.
+pub fn spawn_os_release_watch(config: &Config, output: &OutputManager) {
+    let config = config.clone();
+    let output = output.detached();
+    thread::spawn(move || {
+        let mut merged_for = crate::os_release::version_id();
+        loop {
+            thread::sleep(OS_RELEASE_CHECK_INTERVAL);
+            let current = crate::os_release::version_id();
+            if current == merged_for {
+                continue;
+            }
+            output.log_info(&format!(
+                "os-release VERSION_ID changed from {merged_for} to {current}; refreshing"
+            ));
+            match service::ext::refresh_if_changed(&config, true) {
+                Ok(_) => merged_for = current,
+                Err(e) => output.warning(&format!("Refresh for VERSION_ID {current} failed: {e}")),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(debounce_ms: u64, min_interval_ms: u64) -> AutoRefreshSettings {
+        AutoRefreshSettings {
+            enabled: true,
+            debounce_ms,
+            min_interval_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_burst_is_coalesced_into_one_refresh() {
+        let mut throttle = Throttle::new(&settings(100, 0));
+        let t0 = Instant::now();
+        for i in 0..5 {
+            throttle.on_event(t0 + Duration::from_millis(i * 10));
+        }
+        assert!(!throttle.poll(t0 + Duration::from_millis(90)));
+        assert!(throttle.poll(t0 + Duration::from_millis(150)));
+        assert!(!throttle.poll(t0 + Duration::from_millis(160)));
+        throttle.record_refresh(t0 + Duration::from_millis(160), true);
+
+        assert_eq!(throttle.stats.triggers, 5);
+        assert_eq!(throttle.stats.coalesced, 4);
+        assert_eq!(throttle.stats.refreshes, 1);
+
+        throttle.on_event(t0 + Duration::from_millis(200));
+        assert!(throttle.poll(t0 + Duration::from_millis(400)));
+        throttle.record_skip();
+        assert_eq!(throttle.stats.skipped, 1);
+        assert_eq!(throttle.stats.refreshes, 1);
+    }
+
+    #[test]
+    fn test_deferred_refresh_runs_later() {
+        let mut throttle = Throttle::new(&settings(0, 0));
+        let t0 = Instant::now();
+        throttle.on_event(t0);
+        assert!(throttle.poll(t0));
+        throttle.defer();
+        assert!(throttle.poll(t0 + Duration::from_millis(10)));
+        throttle.defer();
+        assert_eq!(throttle.stats.deferred, 1);
+        assert!(throttle.poll(t0 + Duration::from_millis(20)));
+        throttle.record_refresh(t0 + Duration::from_millis(20), true);
+        assert!(!throttle.poll(t0 + Duration::from_millis(30)));
+        assert_eq!(throttle.stats.refreshes, 1);
+    }
+
+    #[test]
+    fn test_held_refresh_is_counted_once() {
+        let mut throttle = Throttle::new(&settings(0, 0));
+        let t0 = Instant::now();
+        throttle.on_event(t0);
+        for i in 0..3 {
+            assert!(throttle.poll(t0 + Duration::from_millis(i)));
+            throttle.hold();
+        }
+        assert_eq!(throttle.stats.queued, 1);
+        assert!(throttle.poll(t0 + Duration::from_millis(10)));
+        throttle.record_refresh(t0 + Duration::from_millis(10), true);
+        assert!(!throttle.poll(t0 + Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_min_interval_throttles_next_refresh() {
+        let mut throttle = Throttle::new(&settings(0, 1000));
+        let t0 = Instant::now();
+        throttle.on_event(t0);
+        assert!(throttle.poll(t0));
+        throttle.record_refresh(t0, false);
+
+        throttle.on_event(t0 + Duration::from_millis(100));
+        assert!(!throttle.poll(t0 + Duration::from_millis(200)));
+        assert!(!throttle.poll(t0 + Duration::from_millis(500)));
+        assert_eq!(throttle.stats.throttled, 1);
+        assert!(throttle.poll(t0 + Duration::from_millis(1000)));
+
+        assert_eq!(throttle.stats.failures, 1);
+        assert!(throttle.stats.last_refresh.is_some());
+    }
+
+    #[test]
+    fn test_fingerprint_tracks_changes() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let roots = vec![tmp.path().to_path_buf(), tmp.path().join("missing")];
+        let empty = fingerprint(&roots);
+        assert_eq!(empty, fingerprint(&roots));
+
+        fs::create_dir(tmp.path().join("app")).unwrap();
+        fs::write(tmp.path().join("app/file"), "a").unwrap();
+        let populated = fingerprint(&roots);
+        assert_ne!(empty, populated);
+
+        fs::write(tmp.path().join("app/file"), "ab").unwrap();
+        assert_ne!(populated, fingerprint(&roots));
+    }
+}
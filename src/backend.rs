@@ -0,0 +1,231 @@
+//! In-process mock backend for development without systemd.
+//!
+//! Selected with the global `--backend mock` flag (or `AVOCADO_BACKEND=mock`).
+//! Instead of spawning systemd-sysext, systemd-confext, systemd-dissect,
+//! systemctl, depmod, modprobe or AVOCADO_ON_MERGE commands, each intended
+//! invocation is appended to `actions.log` under the backend state directory
+//! and a plausible result is simulated. Merge state is kept per hierarchy so
+//! `status` reflects earlier `merge`/`unmerge` calls. The mock backend
+//! implies test-mode paths, so nothing outside `$TMPDIR/avocado` is written.
+//! Builds with the `dev` feature can script the systemd-sysext and
+//! systemd-confext responses with a scenario (see [`crate::mock_scenario`]).
+
+use crate::commands::ext::SystemdError;
+use crate::error::IoContext;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Environment variable selecting the backend ("system" or "mock").
+pub const BACKEND_ENV: &str = "AVOCADO_BACKEND";
+
+/// File (inside [`state_dir`]) recording every simulated invocation.
+pub const ACTIONS_LOG_FILENAME: &str = "actions.log";
+
+/// Whether the mock backend is active.
+pub fn is_mock() -> bool {
+    std::env::var(BACKEND_ENV).is_ok_and(|v| v == "mock")
+}
+
+/// Activate the mock backend for this process. Also switches to test-mode
+/// paths so that the simulated flows stay under `$TMPDIR`.
+pub fn enable_mock() {
+    std::env::set_var(BACKEND_ENV, "mock");
+    if std::env::var("AVOCADO_TEST_MODE").is_err() {
+        std::env::set_var("AVOCADO_TEST_MODE", "1");
+    }
+}
+
+/// Directory holding the mock backend's state and action log.
+pub fn state_dir() -> PathBuf {
+    let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(format!("{temp_base}/avocado/mock-backend"))
+}
+
+/// Append an intended invocation to the action log.
+pub fn record_action(program: &str, args: &[&str]) {
+    let dir = state_dir();
+    if fs::create_dir_all(&dir).is_err() {
+        return;
+    }
+    let line = serde_json::json!({ "program": program, "args": args });
+    if let Ok(mut file) = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(ACTIONS_LOG_FILENAME))
+    {
+        let _ = writeln!(file, "{line}");
+    }
+}
+
+/// Simulate `program args...` when the mock backend is active.
+///
+/// Returns `None` when the system backend is in use, so callers fall
+/// through to spawning the real command.
+pub fn simulate(program: &str, args: &[&str]) -> Option<Result<String, SystemdError>> {
+    if !is_mock() {
+        return None;
+    }
+    record_action(program, args);
+    Some(match program {
+        "systemd-sysext" => simulate_extension_tool("sysext", "test_extensions", "/usr", args),
+        "systemd-confext" => simulate_extension_tool("confext", "test_confexts", "/etc", args),
+        "systemd-dissect" => simulate_dissect(args),
+        _ => Ok(String::new()),
+    })
+}
+
+/// Simulate systemd-sysext / systemd-confext merge, unmerge, refresh and status.
+fn simulate_extension_tool(
+    kind: &str,
+    link_dir: &str,
+    hierarchy: &str,
+    args: &[&str],
+) -> Result<String, SystemdError> {
+    let state_file = state_dir().join(format!("{kind}-merged.json"));
+    let action = args
+        .iter()
+        .find(|a| !a.starts_with('-'))
+        .copied()
+        .unwrap_or("status");
+    let json = args.iter().any(|a| a.starts_with("--json"));
+
+    #[cfg(feature = "dev")]
+    let scenario = crate::mock_scenario::for_tool(kind)?.unwrap_or_default();
+    #[cfg(feature = "dev")]
+    if let Some(err) = scenario.failure(&format!("systemd-{kind}"), action) {
+        return Err(err);
+    }
+    #[cfg(feature = "dev")]
+    if let (true, "status", Some(status_json)) = (json, action, &scenario.status_json) {
+        return Ok(status_json.clone());
+    }
+
+    let merged: Vec<String> = match action {
+        "merge" | "refresh" => {
+            let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+            let mut names: Vec<String> = fs::read_dir(format!("{temp_base}/{link_dir}"))
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .map(|e| e.file_name().to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            #[cfg(feature = "dev")]
+            names.retain(|name| !scenario.skips(name));
+            names.sort();
+            write_state(&state_file, &names)?;
+            names
+        }
+        "unmerge" => {
+            write_state(&state_file, &[])?;
+            Vec::new()
+        }
+        "status" => {
+            #[cfg(feature = "dev")]
+            if let Some(merged) = &scenario.merged {
+                return Ok(status_output(hierarchy, merged, json));
+            }
+            fs::read_to_string(&state_file)
+                .ok()
+                .and_then(|c| serde_json::from_str(&c).ok())
+                .unwrap_or_default()
+        }
+        other => {
+            return Err(SystemdError::CommandExitedWithError {
+                command: format!("systemd-{kind} {other}"),
+                exit_code: Some(1),
+                stderr: format!("mock backend: unsupported action '{other}'"),
+            })
+        }
+    };
+
+    let output = match (action, json) {
+        ("status", _) => status_output(hierarchy, &merged, json),
+        (_, true) => serde_json::json!({
+            "action": action,
+            "type": kind,
+            "status": "success",
+            "extensions": merged,
+        })
+        .to_string(),
+        (_, false) => String::new(),
+    };
+    Ok(output)
+}
+
+/// `status` output listing `merged` under `hierarchy`.
+fn status_output(hierarchy: &str, merged: &[String], json: bool) -> String {
+    if json {
+        let extensions = if merged.is_empty() {
+            serde_json::json!("none")
+        } else {
+            serde_json::json!(merged)
+        };
+        serde_json::json!([{ "hierarchy": hierarchy, "extensions": extensions, "since": null }])
+            .to_string()
+    } else {
+        format!(
+            "HIERARCHY EXTENSIONS\n{hierarchy:<9} {}\n",
+            if merged.is_empty() {
+                "none".to_string()
+            } else {
+                merged.join(", ")
+            }
+        )
+    }
+}
+
+/// Simulate systemd-dissect mounts by creating (or removing) the mount point.
+fn simulate_dissect(args: &[&str]) -> Result<String, SystemdError> {
+    if let Some(pos) = args.iter().position(|a| *a == "-U") {
+        if let Some(mount_point) = args.get(pos + 1) {
+            let _ = fs::remove_dir(mount_point);
+        }
+    } else if let Some(mount_point) = args.last().filter(|_| args.contains(&"-M")) {
+        fs::create_dir_all(mount_point).context("create directory", mount_point)?;
+    }
+    Ok(String::new())
+}
+
+fn write_state(path: &std::path::Path, names: &[String]) -> Result<(), SystemdError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("create directory", parent)?;
+    }
+    fs::write(path, serde_json::to_string(names).unwrap_or_default()).context("write", path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate_dissect_creates_and_removes_mount_point() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mount_point = tmp.path().join("ext/mnt");
+        let mount_str = mount_point.to_str().unwrap();
+
+        simulate_dissect(&["--mkdir", "-r", "-M", "/img.raw", mount_str]).unwrap();
+        assert!(mount_point.is_dir());
+
+        simulate_dissect(&["-U", mount_str]).unwrap();
+        assert!(!mount_point.exists());
+    }
+
+    #[test]
+    fn test_write_state_reports_the_path() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let file = tmp.path().join("file");
+        fs::write(&file, "").unwrap();
+        let path = file.join("state.json");
+
+        let err = write_state(&path, &["app".to_string()]).unwrap_err();
+        let SystemdError::Io(err) = err else {
+            panic!("expected an I/O error, got {err:?}");
+        };
+        assert_eq!(err.path, file);
+        write_state(&tmp.path().join("state.json"), &["app".to_string()]).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+//! Wall-clock time in seconds since the Unix epoch, and UTC calendar dates.
+//!
+//! Trust key windows, merge history, maintenance windows, events and
+//! extension lifecycles all keep times as plain seconds; these helpers
+//! convert between those and `YYYY-MM-DD HH:MM UTC`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Year, month and day of `days` since the Unix epoch (Howard Hinnant's
+/// algorithm).
+pub fn civil_date(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// A timestamp as `YYYY-MM-DD HH:MM UTC`.
+pub fn format_time(secs: u64) -> String {
+    let (year, month, day) = civil_date((secs / SECONDS_PER_DAY) as i64);
+    let minutes = secs % SECONDS_PER_DAY / 60;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// Seconds since the Unix epoch of a `YYYY-MM-DD [HH:MM[:SS]]` UTC time.
+pub fn parse_utc(value: &str) -> Option<u64> {
+    let value = value
+        .strip_suffix(" UTC")
+        .or_else(|| value.strip_suffix('Z'))
+        .unwrap_or(value);
+    let (date, time) = match value.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    if !crate::extension_release::is_eol_date(date) {
+        return None;
+    }
+    let mut fields = date.split('-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (fields.next()??, fields.next()??, fields.next()??);
+    let days = days_from_civil(year, month, day);
+    if days < 0 || civil_date(days) != (year, month, day) {
+        return None;
+    }
+
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let parts: Vec<&str> = time.split(':').collect();
+        if !(2..=3).contains(&parts.len()) {
+            return None;
+        }
+        for (part, limit) in parts.iter().zip([24, 60, 60]) {
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let field: u64 = part.parse().ok()?;
+            if field >= limit {
+                return None;
+            }
+            seconds = seconds * 60 + field;
+        }
+        if parts.len() == 2 {
+            seconds *= 60;
+        }
+    }
+    Some(days as u64 * SECONDS_PER_DAY + seconds)
+}
+
+/// Days since the Unix epoch of a date, the inverse of
+/// [`civil_date`].
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_time(1_791_936_000 + 3_660), "2026-10-14 01:01 UTC");
+        assert_eq!(format_time(951_782_400), "2000-02-29 00:00 UTC");
+    }
+
+    #[test]
+    fn test_parse_utc() {
+        assert_eq!(
+            parse_utc("2026-10-14 01:01 UTC"),
+            Some(1_791_936_000 + 3_660)
+        );
+        assert_eq!(
+            parse_utc("2026-10-14T01:01:30Z"),
+            Some(1_791_936_000 + 3_690)
+        );
+        assert_eq!(parse_utc("2000-02-29"), Some(951_782_400));
+        assert_eq!(parse_utc("2001-02-29"), None);
+        assert_eq!(parse_utc("2026-10-14 24:00"), None);
+    }
+}
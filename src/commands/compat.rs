@@ -0,0 +1,477 @@
+//! `avocadoctl ext compat` — compatibility matrix of the available extensions.
+//!
+//! Each extension in the extensions directory is checked against every OS
+//! version the device knows about: the running VERSION_ID, the os-releases
+//! directories of installed versions (the other A/B slot among them) and
+//! the VERSION_ID a pending OS update installs. The version checks are
+//! systemd's matching rules (see [`evaluate_release_compatibility`]); the
+//! extension's ARCHITECTURE and scopes are reported alongside. An extension
+//! that merges on the running OS but not on the pending update's version is
+//! flagged as breaking after the update.
+
+use super::ext::{compare_version_ids, evaluate_release_compatibility, ReleaseCompatibility};
+use super::harness::ReleaseFile;
+use super::image_adaptor::parse_scope_from_release_content;
+use crate::os_release::OsRelease;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Why an OS version is in the matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionRole {
+    Running,
+    /// Has an os-releases directory
+    Installed,
+    /// Installed by the pending OS update
+    Pending,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OsVersion {
+    pub version_id: String,
+    pub role: VersionRole,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompatStatus {
+    Compatible,
+    Incompatible,
+    /// The extension or the OS could not be read
+    Unverified,
+}
+
+/// One check of one extension.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Cell {
+    pub status: CompatStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl From<ReleaseCompatibility> for Cell {
+    fn from(compatibility: ReleaseCompatibility) -> Self {
+        let (status, reason) = match compatibility {
+            ReleaseCompatibility::Compatible => (CompatStatus::Compatible, None),
+            ReleaseCompatibility::Incompatible(reason) => {
+                (CompatStatus::Incompatible, Some(reason))
+            }
+            ReleaseCompatibility::Unverified(reason) => (CompatStatus::Unverified, Some(reason)),
+        };
+        Self { status, reason }
+    }
+}
+
+impl Cell {
+    fn label(&self) -> &'static str {
+        match self.status {
+            CompatStatus::Compatible => "ok",
+            CompatStatus::Incompatible => "no",
+            CompatStatus::Unverified => "?",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionCell {
+    pub version_id: String,
+    #[serde(flatten)]
+    pub cell: Cell,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExtensionCompat {
+    pub name: String,
+    pub architecture: Cell,
+    /// Scopes the extension merges in; empty when unrestricted.
+    pub scopes: Vec<String>,
+    /// One cell per [`CompatMatrix::versions`] entry, in the same order.
+    pub versions: Vec<VersionCell>,
+    /// Merges on the running OS but not on the pending update's version.
+    pub breaks_after_update: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CompatMatrix {
+    pub architecture: String,
+    pub versions: Vec<OsVersion>,
+    pub extensions: Vec<ExtensionCompat>,
+}
+
+/// The running version, installed versions and pending update's version,
+/// sorted by VERSION_ID. A version in several roles is listed once, as
+/// running before pending before installed.
+pub fn os_versions(
+    running: Option<&str>,
+    installed: &[String],
+    pending: Option<&str>,
+) -> Vec<OsVersion> {
+    let mut versions: Vec<OsVersion> = Vec::new();
+    let candidates = running
+        .map(|v| (v, VersionRole::Running))
+        .into_iter()
+        .chain(pending.map(|v| (v, VersionRole::Pending)))
+        .chain(
+            installed
+                .iter()
+                .map(|v| (v.as_str(), VersionRole::Installed)),
+        );
+    for (version_id, role) in candidates {
+        if !versions.iter().any(|v| v.version_id == version_id) {
+            versions.push(OsVersion {
+                version_id: version_id.to_string(),
+                role,
+            });
+        }
+    }
+    versions.sort_by(|a, b| compare_version_ids(&a.version_id, &b.version_id));
+    versions
+}
+
+/// systemd's name for the architecture avocadoctl was built for, as used in
+/// the ARCHITECTURE release file key.
+pub fn host_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "x86-64",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64",
+        "loongarch64" => "loongarch64",
+        arch => arch,
+    }
+}
+
+fn level_key(release: &ReleaseFile) -> &'static str {
+    if release.kind == "confext" {
+        "CONFEXT_LEVEL"
+    } else {
+        "SYSEXT_LEVEL"
+    }
+}
+
+fn check_architecture(releases: &[ReleaseFile], host: &str) -> Cell {
+    for release in releases {
+        if let Some(arch) = release.fields().architecture {
+            if arch != host {
+                return ReleaseCompatibility::Incompatible(format!(
+                    "{}: ARCHITECTURE={arch} does not match host architecture {host}",
+                    release.relative_path
+                ))
+                .into();
+            }
+        }
+    }
+    ReleaseCompatibility::Compatible.into()
+}
+
+/// Check against one OS version. Only the running OS's os-release can be
+/// read, so a level other versions would have to match goes unverified.
+fn check_version(
+    releases: &[ReleaseFile],
+    host_os_release: Option<&OsRelease>,
+    version: &OsVersion,
+) -> Cell {
+    if version.role != VersionRole::Running {
+        let leveled = releases.iter().find(|release| {
+            let fields = release.fields();
+            fields.id.as_deref() != Some("_any") && fields.get(level_key(release)).is_some()
+        });
+        if let Some(release) = leveled {
+            return ReleaseCompatibility::Unverified(format!(
+                "{}: {} can only be checked against the running OS",
+                release.relative_path,
+                level_key(release)
+            ))
+            .into();
+        }
+    }
+    evaluate_release_compatibility(releases, host_os_release, &version.version_id).into()
+}
+
+/// Scopes any of the release files allows; empty when one is unrestricted.
+fn scopes(releases: &[ReleaseFile]) -> Vec<String> {
+    let mut scopes: Vec<String> = Vec::new();
+    for release in releases {
+        let key = if release.kind == "confext" {
+            "CONFEXT_SCOPE"
+        } else {
+            "SYSEXT_SCOPE"
+        };
+        let declared = parse_scope_from_release_content(&release.content, key);
+        if declared.is_empty() {
+            return Vec::new();
+        }
+        for scope in declared {
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+    }
+    scopes
+}
+
+impl CompatMatrix {
+    /// Check each extension, given by name with its release files or the
+    /// reason they could not be read.
+    pub fn new(
+        host_os_release: Option<&OsRelease>,
+        architecture: &str,
+        versions: Vec<OsVersion>,
+        extensions: Vec<(String, Result<Vec<ReleaseFile>, String>)>,
+    ) -> Self {
+        let extensions = extensions
+            .into_iter()
+            .map(|(name, releases)| {
+                let (architecture, scopes, cells) = match &releases {
+                    Ok(releases) => (
+                        check_architecture(releases, architecture),
+                        scopes(releases),
+                        versions
+                            .iter()
+                            .map(|v| check_version(releases, host_os_release, v))
+                            .collect(),
+                    ),
+                    Err(reason) => {
+                        let unverified: Cell =
+                            ReleaseCompatibility::Unverified(reason.clone()).into();
+                        (
+                            unverified.clone(),
+                            Vec::new(),
+                            vec![unverified; versions.len()],
+                        )
+                    }
+                };
+                let status_of = |role: VersionRole| {
+                    versions
+                        .iter()
+                        .zip(&cells)
+                        .find(|(v, _)| v.role == role)
+                        .map(|(_, cell): (_, &Cell)| cell.status)
+                };
+                let breaks_after_update = status_of(VersionRole::Running)
+                    == Some(CompatStatus::Compatible)
+                    && status_of(VersionRole::Pending) == Some(CompatStatus::Incompatible);
+                ExtensionCompat {
+                    name,
+                    architecture,
+                    scopes,
+                    versions: versions
+                        .iter()
+                        .zip(cells)
+                        .map(|(v, cell)| VersionCell {
+                            version_id: v.version_id.clone(),
+                            cell,
+                        })
+                        .collect(),
+                    breaks_after_update,
+                }
+            })
+            .collect();
+        Self {
+            architecture: architecture.to_string(),
+            versions,
+            extensions,
+        }
+    }
+
+    /// The pending update's VERSION_ID, if it is in the matrix.
+    pub fn pending(&self) -> Option<&str> {
+        self.versions
+            .iter()
+            .find(|v| v.role == VersionRole::Pending)
+            .map(|v| v.version_id.as_str())
+    }
+
+    /// Extensions that merge now but not after the pending update.
+    pub fn breaking(&self) -> Vec<&str> {
+        self.extensions
+            .iter()
+            .filter(|e| e.breaks_after_update)
+            .map(|e| e.name.as_str())
+            .collect()
+    }
+
+    /// Render as a table, followed by the reason for each check that did
+    /// not pass.
+    pub fn to_table(&self) -> String {
+        let mut text = String::new();
+        if self.extensions.is_empty() {
+            text.push_str("No extensions available.\n");
+            return text;
+        }
+
+        let mut header = vec![
+            "EXTENSION".to_string(),
+            format!("ARCH ({})", self.architecture),
+            "SCOPE".to_string(),
+        ];
+        header.extend(self.versions.iter().map(|v| match v.role {
+            VersionRole::Running => format!("{} (running)", v.version_id),
+            VersionRole::Pending => format!("{} (pending)", v.version_id),
+            VersionRole::Installed => v.version_id.clone(),
+        }));
+        let rows: Vec<Vec<String>> = self
+            .extensions
+            .iter()
+            .map(|e| {
+                let mut row = vec![
+                    e.name.clone(),
+                    e.architecture.label().to_string(),
+                    if e.scopes.is_empty() {
+                        "any".to_string()
+                    } else {
+                        e.scopes.join(",")
+                    },
+                ];
+                row.extend(e.versions.iter().map(|v| v.cell.label().to_string()));
+                if e.breaks_after_update {
+                    row.push("BREAKS AFTER UPDATE".to_string());
+                }
+                row
+            })
+            .collect();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|i| {
+                rows.iter()
+                    .filter_map(|row| row.get(i))
+                    .chain(std::iter::once(&header[i]))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        for row in std::iter::once(&header).chain(&rows) {
+            let line = row
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| match widths.get(i) {
+                    Some(width) => format!("{cell:<width$}"),
+                    None => cell.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            let _ = writeln!(text, "{}", line.trim_end());
+        }
+
+        let mut reasons = Vec::new();
+        for e in &self.extensions {
+            if let Some(reason) = &e.architecture.reason {
+                reasons.push(format!("{}: {reason}", e.name));
+            }
+            for v in &e.versions {
+                if let Some(reason) = &v.cell.reason {
+                    reasons.push(format!("{} on {}: {reason}", e.name, v.version_id));
+                }
+            }
+        }
+        if !reasons.is_empty() {
+            text.push('\n');
+            for reason in reasons {
+                let _ = writeln!(text, "  {reason}");
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(kind: &'static str, content: &str) -> ReleaseFile {
+        ReleaseFile {
+            kind,
+            name: "app".to_string(),
+            relative_path: format!("{kind}/extension-release.app"),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_os_versions_roles_and_order() {
+        let installed = ["1.10".to_string(), "1.9".to_string(), "2.0".to_string()];
+        let versions = os_versions(Some("1.10"), &installed, Some("2.0"));
+        let summary: Vec<(&str, VersionRole)> = versions
+            .iter()
+            .map(|v| (v.version_id.as_str(), v.role))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("1.9", VersionRole::Installed),
+                ("1.10", VersionRole::Running),
+                ("2.0", VersionRole::Pending)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matrix_flags_extensions_breaking_after_update() {
+        let host = OsRelease::parse("ID=avocado\nVERSION_ID=1.0\nSYSEXT_LEVEL=1\n");
+        let versions = os_versions(Some("1.0"), &["0.9".to_string()], Some("2.0"));
+        let matrix = CompatMatrix::new(
+            Some(&host),
+            "arm64",
+            versions,
+            vec![
+                (
+                    "pinned".to_string(),
+                    Ok(vec![release(
+                        "sysext",
+                        "ID=avocado\nVERSION_ID=1.0\nSYSEXT_SCOPE=system initrd\n",
+                    )]),
+                ),
+                (
+                    "portable".to_string(),
+                    Ok(vec![release("sysext", "ID=_any\nARCHITECTURE=x86-64\n")]),
+                ),
+                (
+                    "leveled".to_string(),
+                    Ok(vec![release("sysext", "ID=avocado\nSYSEXT_LEVEL=1\n")]),
+                ),
+                (
+                    "broken".to_string(),
+                    Err("failed to mount image".to_string()),
+                ),
+            ],
+        );
+
+        let pinned = &matrix.extensions[0];
+        let statuses: Vec<CompatStatus> = pinned.versions.iter().map(|v| v.cell.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                CompatStatus::Incompatible,
+                CompatStatus::Compatible,
+                CompatStatus::Incompatible
+            ]
+        );
+        assert!(pinned.breaks_after_update);
+        assert_eq!(pinned.scopes, ["system", "initrd"]);
+
+        let portable = &matrix.extensions[1];
+        assert_eq!(portable.architecture.status, CompatStatus::Incompatible);
+        assert!(portable.scopes.is_empty());
+        assert!(!portable.breaks_after_update);
+
+        let leveled = &matrix.extensions[2];
+        assert_eq!(leveled.versions[1].cell.status, CompatStatus::Compatible);
+        assert_eq!(leveled.versions[2].cell.status, CompatStatus::Unverified);
+        assert!(!leveled.breaks_after_update);
+
+        assert_eq!(
+            matrix.extensions[3].versions[0].cell.status,
+            CompatStatus::Unverified
+        );
+        assert_eq!(matrix.pending(), Some("2.0"));
+        assert_eq!(matrix.breaking(), ["pinned"]);
+
+        let table = matrix.to_table();
+        assert!(table.starts_with("EXTENSION  ARCH (arm64)  SCOPE"));
+        assert!(table.contains("0.9  1.0 (running)  2.0 (pending)"));
+        assert!(table.contains("BREAKS AFTER UPDATE"));
+        assert!(table.contains(
+            "pinned on 2.0: sysext/extension-release.app: VERSION_ID=1.0 does not match target VERSION_ID=2.0"
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+//! `avocadoctl ext preview-etc` — what merging a confext would do to /etc.
+//!
+//! systemd-confext overlays the `etc/` tree of each configuration extension
+//! onto /etc, so a file the extension ships either adds a path or shadows
+//! the one already there. The preview walks the extension's `etc/` tree and
+//! compares each file and symlink with the current /etc. The extension's own
+//! release files under `etc/extension-release.d` are left out: they do not
+//! configure anything.
+
+use serde::Serialize;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What merging the extension does to one /etc path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// Nothing is at the path now
+    Added,
+    /// The extension shadows a different file, symlink or directory
+    Overridden,
+    /// The extension ships what is already there
+    Unchanged,
+}
+
+impl Change {
+    fn marker(self) -> char {
+        match self {
+            Change::Added => '+',
+            Change::Overridden => '~',
+            Change::Unchanged => '=',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EtcPath {
+    /// Path under /etc, e.g. `/etc/app/app.conf`
+    pub path: String,
+    pub change: Change,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EtcPreview {
+    pub extension: String,
+    pub paths: Vec<EtcPath>,
+}
+
+impl EtcPreview {
+    /// Compare the `etc/` tree of the extension rooted at `ext_root` with
+    /// `etc`, the current /etc.
+    pub fn new(extension: &str, ext_root: &Path, etc: &Path) -> Self {
+        let source = ext_root.join("etc");
+        let mut relative = Vec::new();
+        collect(&source, Path::new(""), &mut relative);
+        relative.sort();
+        let paths = relative
+            .into_iter()
+            .map(|rel| EtcPath {
+                path: Path::new("/etc").join(&rel).display().to_string(),
+                change: compare(&source.join(&rel), &etc.join(&rel)),
+            })
+            .collect();
+        Self {
+            extension: extension.to_string(),
+            paths,
+        }
+    }
+
+    pub fn count(&self, change: Change) -> usize {
+        self.paths.iter().filter(|p| p.change == change).count()
+    }
+
+    pub fn to_text(&self, verbose: bool) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}: {} added, {} overridden, {} unchanged in /etc",
+            self.extension,
+            self.count(Change::Added),
+            self.count(Change::Overridden),
+            self.count(Change::Unchanged)
+        );
+        for path in &self.paths {
+            if path.change != Change::Unchanged || verbose {
+                let _ = writeln!(out, "  {} {}", path.change.marker(), path.path);
+            }
+        }
+        out
+    }
+}
+
+/// Files and symlinks below `dir`, relative to the extension's `etc/`.
+fn collect(dir: &Path, relative: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let rel = relative.join(entry.file_name());
+        if rel == Path::new("extension-release.d") {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => collect(&entry.path(), &rel, found),
+            Ok(_) => found.push(rel),
+            Err(_) => {}
+        }
+    }
+}
+
+fn compare(shipped: &Path, current: &Path) -> Change {
+    let Ok(current_meta) = fs::symlink_metadata(current) else {
+        return Change::Added;
+    };
+    let Ok(shipped_meta) = fs::symlink_metadata(shipped) else {
+        return Change::Overridden;
+    };
+    let same = if shipped_meta.file_type().is_symlink() {
+        current_meta.file_type().is_symlink()
+            && fs::read_link(shipped).ok() == fs::read_link(current).ok()
+    } else {
+        current_meta.is_file()
+            && shipped_meta.len() == current_meta.len()
+            && fs::read(shipped).ok() == fs::read(current).ok()
+    };
+    if same {
+        Change::Unchanged
+    } else {
+        Change::Overridden
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs as unix_fs;
+
+    #[test]
+    fn test_preview_classifies_paths() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ext = tmp.path().join("ext");
+        let etc = tmp.path().join("etc");
+        fs::create_dir_all(ext.join("etc/app")).unwrap();
+        fs::create_dir_all(ext.join("etc/extension-release.d")).unwrap();
+        fs::create_dir_all(&etc).unwrap();
+        fs::write(
+            ext.join("etc/extension-release.d/extension-release.app"),
+            "ID=_any\n",
+        )
+        .unwrap();
+        fs::write(ext.join("etc/app/app.conf"), "port=80\n").unwrap();
+        fs::write(ext.join("etc/hosts"), "127.0.0.1 app\n").unwrap();
+        fs::write(etc.join("hosts"), "127.0.0.1 localhost\n").unwrap();
+        fs::write(ext.join("etc/motd"), "hello\n").unwrap();
+        fs::write(etc.join("motd"), "hello\n").unwrap();
+        unix_fs::symlink("/usr/share/zoneinfo/UTC", ext.join("etc/localtime")).unwrap();
+        unix_fs::symlink("/usr/share/zoneinfo/UTC", etc.join("localtime")).unwrap();
+
+        let preview = EtcPreview::new("app", &ext, &etc);
+        let changes: Vec<(&str, Change)> = preview
+            .paths
+            .iter()
+            .map(|p| (p.path.as_str(), p.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("/etc/app/app.conf", Change::Added),
+                ("/etc/hosts", Change::Overridden),
+                ("/etc/localtime", Change::Unchanged),
+                ("/etc/motd", Change::Unchanged),
+            ]
+        );
+
+        let text = preview.to_text(false);
+        assert!(text.starts_with("app: 1 added, 1 overridden, 2 unchanged in /etc\n"));
+        assert!(text.contains("  + /etc/app/app.conf\n"));
+        assert!(text.contains("  ~ /etc/hosts\n"));
+        assert!(!text.contains("/etc/motd"));
+        assert!(preview.to_text(true).contains("  = /etc/motd\n"));
+    }
+}
@@ -1,36 +1,68 @@
+use crate::commands::compat;
+use crate::commands::etc_preview;
+use crate::commands::graph;
+use crate::commands::harness;
 use crate::commands::image_adaptor::{
-    self, analyze_mounted_extension, extension_mount_point, unmount_all_persistent_mounts,
-    ImageAdaptor, ImageType, ImageTypeTag, KabAdaptor, RawAdaptor,
+    self, unmount_all_persistent_mounts, ImageAdaptor, ImageType, ImageTypeTag,
 };
-use crate::config::Config;
+use crate::commands::lint;
+use crate::commands::run;
+use crate::commands::top;
+use crate::config::{Config, ValidationCheck};
+use crate::diagnostics::Diagnose;
+use crate::error::IoContext;
+use crate::extension_release::{
+    self, parse_avocado_on_unmerge_commands, Hierarchy, Lifecycle, Provenance, ServiceDependency,
+};
+use crate::fault::FailPoint;
+use crate::merge_failures::Failure;
+use crate::merge_target::MergeTarget;
+use crate::messages;
+use crate::ordering::read_dir_sorted;
 use crate::output::OutputManager;
+use crate::phases::Phase;
+use crate::service::types::{ExtensionDetail, ExtensionStatus};
+use crate::timeouts::{Stream, TimeoutKind};
+use crate::transaction::TransactionStep;
 use clap::{Arg, ArgMatches, Command};
 use serde_json::Value;
 use std::fs;
 use std::io::Write;
-use std::os::unix::fs as unix_fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command as ProcessCommand, Stdio};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+mod apply;
+pub(crate) mod display;
+mod plan;
+mod scan;
+pub(crate) mod source;
+mod validate;
+
+use apply::{
+    apply_merge_plan, apply_post_merge, remove_modprobe_blacklists, run_hook_command,
+    run_with_progress, HookTarget, HookTargets, EXT_RELEASE_STAGING_DIR,
+};
+use display::{current_environment, eol_warning, scope_label};
+use plan::{
+    custom_release_dirs, hook_owners_in_dirs, on_merge_hook_owners, plan_merge, plan_post_merge,
+    HookOwners, MergeAction, MergePlan,
+};
+use scan::{scan_merge_state, LinkKind};
+pub(crate) use source::{compare_version_ids, read_os_version_id, split_name_version};
+use source::{
+    describe_image, extension_release_files, find_previous_os_release_dir,
+    get_extension_origin_short, scan_all_sources, scan_archive_files, scan_directory_extensions,
+    scan_extensions_from_all_sources, scan_raw_files, versioned_name, Extension,
+};
+pub(crate) use validate::{
+    evaluate_release_compatibility, validate_enable_target, ReleaseCompatibility,
+};
+use validate::{read_enable_target_releases, ValidationProblem};
+
 // Re-export SystemdError so that service/error.rs From impl continues to work
 pub use image_adaptor::SystemdError;
 
-/// Represents an extension and its type(s)
-#[derive(Debug, Clone)]
-struct Extension {
-    name: String,
-    version: Option<String>, // Version extracted from filename (e.g., "1.0.0" from "app-1.0.0.raw")
-    path: PathBuf,
-    is_sysext: bool,
-    is_confext: bool,
-    image_type: ImageTypeTag,
-    /// Merge priority index derived from manifest ordering.
-    /// Used to compute a numerical prefix for deterministic systemd merge order.
-    /// None for extensions discovered outside the manifest (legacy behavior).
-    merge_index: Option<usize>,
-}
-
 /// Print a colored info message
 fn print_colored_info(message: &str) {
     // Use auto-detection but fallback gracefully
@@ -67,27 +99,61 @@ fn read_running_os_build_id() -> Option<String> {
     } else {
         &["/etc/os-release", "/usr/lib/os-release"]
     };
+    crate::os_release::field_from(paths, "AVOCADO_OS_BUILD_ID")
+}
 
-    for path in paths {
-        if let Ok(contents) = fs::read_to_string(path) {
-            if let Some(value) =
-                crate::os_update::parse_os_release_field(&contents, "AVOCADO_OS_BUILD_ID")
-            {
-                return Some(value.to_string());
-            }
-        }
-    }
-    None
+/// `--mount-only` of `merge` and `ext merge`.
+pub fn mount_only_arg() -> Arg {
+    Arg::new("mount-only")
+        .long("mount-only")
+        .help("Only link and loop-mount the enabled extensions, without running systemd-sysext or systemd-confext (for chroots and containers without systemd)")
+        .action(clap::ArgAction::SetTrue)
+}
+
+/// `--keep-going` of `merge`, `refresh` and their `ext` forms.
+pub fn keep_going_arg() -> Arg {
+    Arg::new("keep-going")
+        .long("keep-going")
+        .help("Skip extensions that fail to mount or have an invalid release file, merge the rest and exit 4 with a summary")
+        .action(clap::ArgAction::SetTrue)
+}
+
+/// `config` with `keep_going` turned on when `--keep-going` is given.
+pub(crate) fn with_keep_going(config: &Config, keep_going: bool) -> Config {
+    let mut config = config.clone();
+    config.avocado.ext.keep_going |= keep_going;
+    config
 }
 
 /// Create the ext subcommand definition
 pub fn create_command() -> Command {
     Command::new("ext")
         .about("Extension management commands")
-        .subcommand(Command::new("list").about("List all available extensions"))
+        .subcommand(
+            Command::new("list").about("List all available extensions").arg(
+                Arg::new("detailed")
+                    .long("detailed")
+                    .help("Show version, type, scope, origin and whether each extension is enabled for the current os-release")
+                    .action(clap::ArgAction::SetTrue),
+            ),
+        )
         .subcommand(
             Command::new("merge")
-                .about("Merge extensions using systemd-sysext and systemd-confext"),
+                .about("Merge extensions using systemd-sysext and systemd-confext")
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Print the planned link changes without applying them")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("MACHINE|PATH")
+                        .help("Merge inside a systemd-nspawn container or a chroot directory instead of the host"),
+                )
+                .arg(mount_only_arg().conflicts_with_all(["dry-run", "target"]))
+                .arg(keep_going_arg()),
         )
         .subcommand(
             Command::new("unmerge")
@@ -100,9 +166,62 @@ pub fn create_command() -> Command {
                 ),
         )
         .subcommand(
-            Command::new("refresh").about("Unmerge and then merge extensions (refresh extensions)"),
+            Command::new("refresh")
+                .about("Unmerge and then merge extensions (refresh extensions)")
+                .arg(
+                    Arg::new("soft-reboot")
+                        .long("soft-reboot")
+                        .help("Persist enable-state changes and apply them via systemd soft-reboot instead of a live refresh")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Unmerge and merge even when nothing changed since the last merge")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Print the planned link changes without applying them")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(keep_going_arg().conflicts_with("soft-reboot")),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show status of merged extensions")
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .help("Only check whether the merged extensions match the enabled ones; exit 0 if they do, 1 if a refresh is needed, 2 on errors")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("updates-only")
+                        .long("updates-only")
+                        .help("Only show extensions the update repository offers a newer version of")
+                        .conflicts_with("check")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .value_name("TIME|boot")
+                        .help("Show which extensions were merged, unmerged or changed since a time (Unix seconds or YYYY-MM-DD [HH:MM[:SS]] UTC) or since boot")
+                        .value_parser(crate::merge_history::Reference::parse)
+                        .conflicts_with_all(["check", "updates-only"]),
+                ),
+        )
+        .subcommand(
+            Command::new("env")
+                .about("Print shell exports describing the merged extensions (AVOCADO_MERGED_EXTS, versions, mount points)")
+                .arg(
+                    Arg::new("name")
+                        .value_name("NAME")
+                        .help("Only this extension, with or without its version"),
+                ),
         )
-        .subcommand(Command::new("status").about("Show status of merged extensions"))
         .subcommand(
             Command::new("enable")
                 .about("Mark one or more extensions as enabled (writes to overrides.json)")
@@ -123,2967 +242,4866 @@ pub fn create_command() -> Command {
                         .required(true),
                 ),
         )
+        .subcommand(
+            Command::new("apply")
+                .about("Enable, disable and update several extensions with a single refresh")
+                .arg(
+                    Arg::new("manifest")
+                        .long("manifest")
+                        .value_name("FILE")
+                        .help("TOML or JSON manifest with enable/disable/update lists")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Print the net change without applying it")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("snapshot")
+                .about("Capture the extension/merge state as a JSON snapshot")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("Write the snapshot to FILE instead of stdout"),
+                ),
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Compare two snapshots, or one snapshot against the live state")
+                .arg(
+                    Arg::new("left")
+                        .value_name("SNAPSHOT")
+                        .help("Baseline snapshot file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("right")
+                        .value_name("OTHER")
+                        .help("Snapshot file to compare against (defaults to the live state)"),
+                ),
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("Produce an inventory report of all extensions, optionally signed")
+                .arg(
+                    Arg::new("file")
+                        .value_name("FILE")
+                        .help("Write the report to FILE instead of stdout"),
+                )
+                .arg(
+                    Arg::new("sign")
+                        .long("sign")
+                        .value_name("KEY")
+                        .help("Sign the report with a hex-encoded ed25519 seed or secret key file"),
+                ),
+        )
+        .subcommand(
+            Command::new("prefetch")
+                .about("Download the update repository's runtime without activating it")
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .value_name("URL")
+                        .help("URL of a TUF update repository (default: url in [avocado.update])"),
+                ),
+        )
+        .subcommand(
+            Command::new("upgrade")
+                .about("Upgrade extensions to the repository's versions as their policies allow")
+                .arg(
+                    Arg::new("names")
+                        .value_name("NAME")
+                        .num_args(0..)
+                        .help("Extensions to upgrade (default: all in the active runtime)"),
+                )
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .value_name("URL")
+                        .help("URL of a TUF update repository (default: url in [avocado.update])"),
+                )
+                .arg(
+                    Arg::new("offline")
+                        .long("offline")
+                        .help("Upgrade from the runtime staged by `ext prefetch`")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("url"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Show what would be upgraded without changing anything")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Upgrade now even outside the [avocado.maintenance] windows")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("check-update")
+                .about("Check the repository for extension updates; exit 0 if up to date, 100 if updates are available, 101 if it cannot tell")
+                .arg(
+                    Arg::new("names")
+                        .value_name("NAME")
+                        .num_args(0..)
+                        .help("Extensions to check (default: all in the active runtime)"),
+                )
+                .arg(
+                    Arg::new("url")
+                        .long("url")
+                        .value_name("URL")
+                        .help("URL of a TUF update repository (default: url in [avocado.update])"),
+                )
+                .arg(
+                    Arg::new("offline")
+                        .long("offline")
+                        .help("Check against the runtime staged by `ext prefetch`")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("url"),
+                ),
+        )
+        .subcommand(
+            Command::new("prune-os-releases")
+                .about("Remove os-releases directories of OS versions no longer installed")
+                .arg(
+                    Arg::new("keep")
+                        .long("keep")
+                        .value_name("LIST")
+                        .value_delimiter(',')
+                        .default_value("current,previous")
+                        .help("Directories to keep: 'current', 'previous' (the other A/B slot's) and VERSION_IDs"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Show what would be removed without changing anything")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("auto-refresh")
+                .about("Show counters of the daemon's automatic refresh loop"),
+        )
+        .subcommand(
+            Command::new("top")
+                .about("Show read activity per mounted extension image")
+                .arg(
+                    Arg::new("once")
+                        .long("once")
+                        .help("Print totals since each image was mounted and exit")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .value_name("MS")
+                        .help("Sampling interval in milliseconds")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("2000"),
+                )
+                .arg(
+                    Arg::new("count")
+                        .long("count")
+                        .value_name("N")
+                        .help("Stop after N samples")
+                        .value_parser(clap::value_parser!(u64)),
+                ),
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Run a command with an unmerged extension's /usr overlaid in a private mount namespace")
+                .arg(
+                    Arg::new("name")
+                        .value_name("NAME")
+                        .help("Extension name or absolute path")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("command")
+                        .value_name("CMD")
+                        .help("Command and arguments to run, after --")
+                        .num_args(1..)
+                        .required(true)
+                        .last(true),
+                ),
+        )
+        .subcommand(
+            Command::new("test")
+                .about("Merge an extension in an isolated root and run its checks")
+                .arg(
+                    Arg::new("path")
+                        .value_name("PATH")
+                        .help("Extension directory or .raw image to test")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Show build provenance and recorded AVOCADO_ON_MERGE/ON_UNMERGE runs for an extension")
+                .arg(
+                    Arg::new("name")
+                        .value_name("NAME")
+                        .help("Extension name, with version if it has one (e.g. app-1.0)")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("replay")
+                        .long("replay")
+                        .help("Print the full recorded stdout and stderr of each run")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("graph")
+                .about("Show the dependency graph of the enabled extensions, flagging cycles and missing dependencies")
+                .arg(
+                    Arg::new("dot")
+                        .long("dot")
+                        .help("Print the graph in Graphviz DOT format")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show which extensions a merge now would add, change or remove compared with the last merge"),
+        )
+        .subcommand(
+            Command::new("compat")
+                .about("Show which extensions merge on each installed OS version, flagging those the pending OS update breaks")
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("VERSION_ID")
+                        .help("VERSION_ID the next OS update installs (default: from the pending update)"),
+                ),
+        )
+        .subcommand(
+            Command::new("events")
+                .about("Show lifecycle events (scans, merges, hook results, HITL changes); -o json prints one JSON object per line")
+                .arg(
+                    Arg::new("lines")
+                        .short('n')
+                        .long("lines")
+                        .value_name("N")
+                        .help("Number of recorded events to show first")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20"),
+                )
+                .arg(
+                    Arg::new("follow")
+                        .short('f')
+                        .long("follow")
+                        .help("Keep printing events as they are recorded")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("preview-etc")
+                .about("List the /etc paths merging a configuration extension would add or override")
+                .arg(
+                    Arg::new("name")
+                        .value_name("NAME")
+                        .help("Extension name, with version if it has one (e.g. app-1.0)")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Check an extension against packaging rules and report findings for CI")
+                .arg(
+                    Arg::new("path")
+                        .value_name("PATH")
+                        .help("Extension directory or .raw image to lint")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("sarif")
+                        .long("sarif")
+                        .help("Print findings as a SARIF 2.1.0 log")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("os-id")
+                        .long("os-id")
+                        .value_name("ID")
+                        .help("OS ID the extension must match (default: this host's)"),
+                )
+                .arg(
+                    Arg::new("version-id")
+                        .long("version-id")
+                        .value_name("VERSION")
+                        .help("OS VERSION_ID the extension must match (default: this host's)"),
+                ),
+        )
+}
+
+/// Whether an ext subcommand only works on local files and never needs the
+/// daemon (`test`, `lint`, `run`, `top`, `info`, `diff`, `compat`,
+/// `preview-etc`, `events`, `compare` between two snapshot files, `status
+/// --check` and `--since`, `check-update`, and `--dry-run`
+/// merge/refresh/apply plans, which only read).
+#[cfg(feature = "daemon")]
+pub fn is_local_subcommand(matches: &ArgMatches) -> bool {
+    match matches.subcommand() {
+        Some((
+            "test" | "lint" | "run" | "top" | "info" | "graph" | "diff" | "compat" | "preview-etc"
+            | "events" | "check-update",
+            _,
+        )) => true,
+        Some(("status", sub)) => sub.get_flag("check") || sub.contains_id("since"),
+        Some(("merge", sub)) => sub.get_flag("dry-run") || sub.get_flag("mount-only"),
+        Some(("refresh" | "apply", sub)) => sub.get_flag("dry-run"),
+        Some(("compare", sub)) => sub.contains_id("right"),
+        _ => false,
+    }
 }
 
 /// Handle ext command and its subcommands
 pub fn handle_command(matches: &ArgMatches, config: &Config, output: &OutputManager) {
     match matches.subcommand() {
+        Some(("list", sub)) if sub.get_flag("detailed") => {
+            match collect_extension_details(config, output) {
+                Ok(extensions) => display::print_extension_details(&extensions, output),
+                Err(e) => {
+                    output.error_with(
+                        "Extension List",
+                        &format!("Failed to scan extensions: {e}"),
+                        &e.diagnose(),
+                    );
+                    output.exit(1);
+                }
+            }
+        }
         Some(("list", _)) => {
             list_extensions(config, output);
         }
-        Some(("merge", _)) => {
-            merge_extensions(config, output);
+        Some(("merge", sub)) => {
+            let config = &with_keep_going(config, sub.get_flag("keep-going"));
+            if sub.get_flag("dry-run") {
+                print_merge_plan(config, output);
+            } else if sub.get_flag("mount-only") {
+                mount_extensions_only(config, output);
+            } else if let Some(target) = sub.get_one::<String>("target") {
+                merge_extensions_to_target(config, target, output);
+            } else {
+                merge_extensions(config, output);
+            }
         }
         Some(("unmerge", unmerge_matches)) => {
             let unmount = unmerge_matches.get_flag("unmount");
             unmerge_extensions(unmount, output);
         }
-        Some(("refresh", _)) => {
-            refresh_extensions(config, output);
+        Some(("refresh", sub)) => {
+            let config = &with_keep_going(config, sub.get_flag("keep-going"));
+            if sub.get_flag("dry-run") {
+                print_merge_plan(config, output);
+            } else if sub.get_flag("soft-reboot") {
+                soft_reboot_refresh(config, output);
+            } else if sub.get_flag("force") {
+                refresh_extensions(config, output);
+            } else {
+                refresh_changed_extensions(config, output);
+            }
         }
-        Some(("status", _)) => {
-            status_extensions(config, output);
+        Some(("status", sub)) => {
+            if sub.get_flag("check") {
+                check_extension_status(config, output);
+            } else if let Some(reference) = sub.get_one::<crate::merge_history::Reference>("since")
+            {
+                show_changes_since(*reference, output);
+            } else {
+                status_extensions(config, output, sub.get_flag("updates-only"));
+            }
         }
+        Some(("env", sub)) => match collect_extension_status(config, output) {
+            Ok(extensions) => display::print_extension_env(
+                &extensions,
+                sub.get_one::<String>("name").map(String::as_str),
+                output,
+            ),
+            Err(e) => {
+                output.error_with(
+                    "Extension Env",
+                    &format!("Failed to read extension status: {e}"),
+                    &e.diagnose(),
+                );
+                output.exit(1);
+            }
+        },
         Some(("enable", sub)) => {
+            let names = config
+                .resolve_extension_aliases(sub.get_many::<String>("names").into_iter().flatten());
+            set_extensions_enabled(&names, true, output);
+        }
+        Some(("disable", sub)) => {
+            let names = config
+                .resolve_extension_aliases(sub.get_many::<String>("names").into_iter().flatten());
+            set_extensions_enabled(&names, false, output);
+        }
+        Some(("apply", sub)) => {
+            let path = sub
+                .get_one::<String>("manifest")
+                .expect("manifest is required");
+            let manifest = load_transaction_manifest_or_exit(Path::new(path), output);
+            if sub.get_flag("dry-run") {
+                print_transaction_plan(&manifest, config, output);
+            } else {
+                match crate::service::ext::apply_transaction(&manifest, config) {
+                    Ok(result) => print_apply_result(&result, output),
+                    Err(e) => {
+                        output.error_with("Extension Apply", &e.to_string(), &e.diagnose());
+                        output.exit(1);
+                    }
+                }
+            }
+        }
+        Some(("snapshot", sub)) => {
+            let file = sub.get_one::<String>("file").map(Path::new);
+            match crate::service::ext::capture_snapshot(config) {
+                Ok(snapshot) => write_snapshot(&snapshot, file, output),
+                Err(e) => {
+                    output.error_with("Extension Snapshot", &e.to_string(), &e.diagnose());
+                    output.exit(1);
+                }
+            }
+        }
+        Some(("compare", sub)) => {
+            let left_path = sub
+                .get_one::<String>("left")
+                .expect("left snapshot is required");
+            let left = load_snapshot_or_exit(left_path, output);
+            let (right, right_label) = match sub.get_one::<String>("right") {
+                Some(path) => (load_snapshot_or_exit(path, output), path.clone()),
+                None => match crate::service::ext::capture_snapshot(config) {
+                    Ok(snapshot) => (snapshot, "live".to_string()),
+                    Err(e) => {
+                        output.error_with("Extension Compare", &e.to_string(), &e.diagnose());
+                        output.exit(1);
+                    }
+                },
+            };
+            print_snapshot_comparison(&left, left_path, &right, &right_label, output);
+        }
+        Some(("audit", sub)) => match crate::service::ext::audit_report(config) {
+            Ok(report) => write_audit_report(
+                report,
+                sub.get_one::<String>("file").map(Path::new),
+                sub.get_one::<String>("sign").map(Path::new),
+                output,
+            ),
+            Err(e) => {
+                output.error_with("Extension Audit", &e.to_string(), &e.diagnose());
+                output.exit(1);
+            }
+        },
+        Some(("prefetch", sub)) => {
+            let auth_token = std::env::var("AVOCADO_TUF_AUTH_TOKEN").ok();
+            match crate::service::ext::prefetch(
+                config,
+                sub.get_one::<String>("url").map(String::as_str),
+                auth_token.as_deref(),
+                output.is_verbose(),
+            ) {
+                Ok((record, already_active)) => print_prefetch(
+                    &record.runtime_id,
+                    &record.name,
+                    &record.version,
+                    already_active,
+                    output,
+                ),
+                Err(e) => {
+                    output.error_with("Extension Prefetch", &e.to_string(), &e.diagnose());
+                    output.exit(1);
+                }
+            }
+        }
+        Some(("upgrade", sub)) => {
             let names: Vec<String> = sub
                 .get_many::<String>("names")
-                .map(|vs| vs.cloned().collect())
+                .map(|v| v.cloned().collect())
                 .unwrap_or_default();
-            set_extensions_enabled(&names, true, output);
+            let auth_token = std::env::var("AVOCADO_TUF_AUTH_TOKEN").ok();
+            let dry_run = sub.get_flag("dry-run");
+            match crate::service::ext::upgrade(
+                config,
+                &names,
+                sub.get_one::<String>("url").map(String::as_str),
+                auth_token.as_deref(),
+                sub.get_flag("offline"),
+                dry_run,
+                sub.get_flag("force"),
+                output.is_verbose(),
+            ) {
+                Ok(result) => print_upgrade(&result, dry_run, output),
+                Err(e) => {
+                    output.error_with("Extension Upgrade", &e.to_string(), &e.diagnose());
+                    output.exit(1);
+                }
+            }
         }
-        Some(("disable", sub)) => {
+        Some(("check-update", sub)) => {
             let names: Vec<String> = sub
                 .get_many::<String>("names")
+                .map(|v| v.cloned().collect())
+                .unwrap_or_default();
+            let auth_token = std::env::var("AVOCADO_TUF_AUTH_TOKEN").ok();
+            let result = crate::service::ext::upgrade(
+                config,
+                &names,
+                sub.get_one::<String>("url").map(String::as_str),
+                auth_token.as_deref(),
+                sub.get_flag("offline"),
+                true,
+                false,
+                output.is_verbose(),
+            );
+            check_for_updates(result, output);
+        }
+        Some(("prune-os-releases", sub)) => {
+            let keep = prune_keep(sub);
+            let dry_run = sub.get_flag("dry-run");
+            match crate::service::ext::prune_os_releases(&keep, dry_run) {
+                Ok(result) => print_os_release_prune(&result, dry_run, output),
+                Err(e) => {
+                    output.error_with("Prune OS Releases", &e.to_string(), &e.diagnose());
+                    output.exit(1);
+                }
+            }
+        }
+        Some(("auto-refresh", _)) => {
+            output.error(
+                "Auto-refresh",
+                "Auto-refresh runs inside `avocadoctl serve`; no daemon is used in this mode",
+            );
+            output.exit(1);
+        }
+        Some(("top", sub)) => {
+            top::run_top(
+                sub.get_flag("once"),
+                *sub.get_one::<u64>("interval")
+                    .expect("interval has a default"),
+                sub.get_one::<u64>("count").copied(),
+                output,
+            );
+        }
+        Some(("run", sub)) => {
+            let name = sub.get_one::<String>("name").expect("name is required");
+            let command: Vec<String> = sub
+                .get_many::<String>("command")
                 .map(|vs| vs.cloned().collect())
                 .unwrap_or_default();
-            set_extensions_enabled(&names, false, output);
+            run::run_extension_command(name, &command, config, output);
         }
-        _ => {
-            println!("Use 'avocadoctl ext --help' for available extension commands");
+        Some(("test", sub)) => {
+            let path = sub.get_one::<String>("path").expect("path is required");
+            harness::run_extension_test(path, output);
         }
-    }
-}
-
-/// CLI-facing wrapper around `service::ext::set_extensions_enabled` that
-/// formats success / failure for the terminal. Used only by the
-/// `AVOCADO_TEST_MODE` direct dispatch path — the production path goes
-/// through varlink so the daemon owns serialization across callers.
-pub fn set_extensions_enabled(names: &[String], enabled: bool, output: &OutputManager) {
-    let refs: Vec<&str> = names.iter().map(String::as_str).collect();
-    match crate::service::ext::set_extensions_enabled(&refs, enabled) {
-        Ok(result) => {
-            let verb = if enabled { "enabled" } else { "disabled" };
-            output.success(
-                "Extension Override",
-                &format!(
-                    "{verb}: {} ({} updated, {} missing)",
-                    names.join(", "),
-                    result.updated,
-                    result.missing
-                ),
+        Some(("lint", sub)) => {
+            let path = sub.get_one::<String>("path").expect("path is required");
+            let target = lint::LintTarget::resolve(
+                sub.get_one::<String>("os-id").map(String::as_str),
+                sub.get_one::<String>("version-id").map(String::as_str),
             );
-            output.info(
-                "Extension Override",
-                "Run `avocadoctl ext refresh` to apply.",
+            lint::run_extension_lint(path, &target, sub.get_flag("sarif"), output);
+        }
+        Some(("info", sub)) => {
+            let name = sub.get_one::<String>("name").expect("name is required");
+            let name = config.resolve_extension_alias(name);
+            show_extension_info(config, &name, sub.get_flag("replay"), output);
+        }
+        Some(("graph", sub)) => {
+            show_dependency_graph(config, sub.get_flag("dot"), output);
+        }
+        Some(("diff", _)) => {
+            print_merge_diff(config, output);
+        }
+        Some(("compat", sub)) => {
+            show_compat_matrix(
+                config,
+                sub.get_one::<String>("target").map(String::as_str),
+                output,
             );
         }
-        Err(e) => {
-            output.error("Extension Override", &e.to_string());
-            std::process::exit(1);
+        Some(("events", sub)) => {
+            let lines = sub.get_one::<usize>("lines").copied().unwrap_or(20);
+            show_events(lines, sub.get_flag("follow"), output);
+        }
+        Some(("preview-etc", sub)) => {
+            let name = sub.get_one::<String>("name").expect("name is required");
+            show_etc_preview(config, name, output);
+        }
+        _ => {
+            println!("Use 'avocadoctl ext --help' for available extension commands");
         }
     }
 }
 
-/// List all extensions from disk images, annotating which are currently mounted/active.
-fn list_extensions(_config: &Config, output: &OutputManager) {
-    output.info("Extension List", "Listing available extensions");
+/// Number of recent hook runs `ext info` shows.
+const INFO_HOOK_RUNS: usize = 10;
 
-    let available = match scan_extensions_from_all_sources_with_verbosity(output.is_verbose()) {
-        Ok(exts) => exts,
-        Err(e) => {
-            eprintln!("Error scanning extensions: {e}");
-            std::process::exit(1);
+/// Print the last `lines` recorded lifecycle events, then with `follow`
+/// every new one. JSON output is one object per line, for supervisors to
+/// consume as a stream.
+fn show_events(lines: usize, follow: bool, output: &OutputManager) {
+    let print = |event: &serde_json::Value| {
+        if output.is_json() {
+            println!("{event}");
+        } else {
+            println!("{}", crate::events::to_text(event));
         }
+        let _ = std::io::stdout().flush();
     };
+    let events = crate::events::read();
+    for event in &events[events.len().saturating_sub(lines)..] {
+        print(event);
+    }
+    if follow {
+        crate::events::follow(print);
+    }
+}
 
-    if available.is_empty() {
-        println!("No extensions found.");
-        return;
+/// /etc, or `$TMPDIR/test_etc` in AVOCADO_TEST_MODE.
+fn etc_dir() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/test_etc"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path("/etc"))
     }
+}
 
-    // Collect mounted names for correlation (strip order prefix, ignore errors)
-    let mounted_sysext: std::collections::HashSet<String> =
-        get_mounted_systemd_extensions("systemd-sysext")
-            .unwrap_or_default()
-            .into_iter()
-            .map(|e| e.name)
-            .collect();
-    let mounted_confext: std::collections::HashSet<String> =
-        get_mounted_systemd_extensions("systemd-confext")
-            .unwrap_or_default()
-            .into_iter()
-            .map(|e| e.name)
-            .collect();
+/// Print the /etc paths merging the configuration extension `name` would
+/// add or override.
+fn show_etc_preview(config: &Config, name: &str, output: &OutputManager) {
+    let extensions = match scan_extensions_from_all_sources(config, output) {
+        Ok(extensions) => extensions,
+        Err(e) => {
+            output.error_with(
+                "Extension /etc Preview",
+                &format!("Failed to scan extensions: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    };
+    let Some(extension) = extensions
+        .iter()
+        .find(|ext| ext.name == name || versioned_name(ext) == name)
+    else {
+        output.error(
+            "Extension /etc Preview",
+            &format!("Extension '{name}' not found"),
+        );
+        output.exit(1);
+    };
+    if !extension.is_confext {
+        output.error(
+            "Extension /etc Preview",
+            &format!(
+                "Extension '{name}' is not a configuration extension; it does not change /etc"
+            ),
+        );
+        output.exit(1);
+    }
 
-    // Sort descending by merge_index (highest priority / top layer first).
-    // Extensions without a merge_index sort to the bottom.
-    let mut sorted = available;
-    sorted.sort_by(|a, b| {
-        b.merge_index
-            .cmp(&a.merge_index)
-            .then_with(|| a.name.cmp(&b.name))
-    });
+    let preview = etc_preview::EtcPreview::new(name, &extension.path, &etc_dir());
+    if output.is_json() {
+        output.result_fields(&preview);
+    } else {
+        print!("{}", preview.to_text(output.is_verbose()));
+    }
+}
 
-    // Compute column width
-    let name_width = sorted
+/// Print the dependency graph of the extensions a merge would enable now.
+fn show_dependency_graph(config: &Config, dot: bool, output: &OutputManager) {
+    let plan = match scan_merge_state(config, output) {
+        Ok(scan) => plan_merge(&scan),
+        Err(e) => {
+            output.error_with(
+                "Extension Graph",
+                &format!("Failed to scan extensions: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    };
+
+    let hitl_dir = crate::hitl_health::hitl_dir();
+    let nodes = plan
+        .enabled
         .iter()
-        .map(|e| {
-            if let Some(ver) = &e.version {
-                e.name.len() + 1 + ver.len()
-            } else {
-                e.name.len()
+        .map(|extension| {
+            let mut node = graph::GraphNode {
+                name: extension.name.clone(),
+                requires: Vec::new(),
+                services: Vec::new(),
+                mount: extension.path.starts_with(&hitl_dir).then(|| {
+                    crate::commands::hitl::systemd_escape_mount_path(
+                        &extension.path.to_string_lossy(),
+                    )
+                }),
+            };
+            for release in extension_release_files(extension) {
+                for req in &release.requires {
+                    if !node.requires.contains(req) {
+                        node.requires.push(req.clone());
+                    }
+                }
+                for service in &release.enable_services {
+                    let unit = if service.contains('.') {
+                        service.clone()
+                    } else {
+                        format!("{service}.service")
+                    };
+                    if !node.services.contains(&unit) {
+                        node.services.push(unit);
+                    }
+                }
             }
+            node
         })
-        .max()
-        .unwrap_or(9)
-        .max(9);
+        .collect();
+    let graph = graph::DependencyGraph::new(nodes);
 
-    println!("  (high priority / top layer)");
-    println!(
-        "{:<6}{:<nw$} {:<12} {:<8}",
-        "Order",
-        "Extension",
-        "Type",
-        "Status",
-        nw = name_width
-    );
-    println!("{}", "=".repeat(6 + name_width + 1 + 12 + 1 + 8));
+    if output.is_json() {
+        output.result_fields(&graph);
+    } else if dot {
+        print!("{}", graph.to_dot());
+    } else {
+        print!("{}", graph.to_ascii());
+    }
+    if graph.has_problems() {
+        output.exit(1);
+    }
+}
 
-    for ext in &sorted {
-        let versioned_name = if let Some(ver) = &ext.version {
-            format!("{}-{}", ext.name, ver)
-        } else {
-            ext.name.clone()
-        };
+/// VERSION_ID the pending OS update installs, when its verification checks
+/// VERSION_ID.
+fn pending_update_version(output: &OutputManager) -> Option<String> {
+    let pending = crate::os_update::read_pending_update()?;
+    match pending.verify {
+        Some(verify) if verify.field == "VERSION_ID" => Some(verify.expected),
+        _ => {
+            output.log_info(&format!(
+                "An OS update to build '{}' is pending but does not name its VERSION_ID; pass --target to check against it",
+                pending.os_build_id
+            ));
+            None
+        }
+    }
+}
 
-        let order_str = ext
-            .merge_index
-            .map(|i| format!("#{i:02}"))
-            .unwrap_or_else(|| "-".to_string());
+/// Print the compatibility matrix of the extensions in the extensions
+/// directory. Exits 1 when an extension merges now but not after the
+/// pending OS update (or `target`).
+fn show_compat_matrix(config: &Config, target: Option<&str>, output: &OutputManager) {
+    let extensions_dir = config.get_extensions_dir();
+    let extensions = resolve_enable_targets("*", &extensions_dir)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|target| {
+            let releases =
+                read_enable_target_releases(Path::new(&target.source_path), output.is_verbose());
+            (target.name, releases)
+        })
+        .collect();
 
-        let mut types = Vec::new();
-        if ext.is_sysext {
-            types.push("sys");
-        }
-        if ext.is_confext {
-            types.push("conf");
+    let os_releases_root = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/os-releases")
+    } else {
+        crate::user_mode::system_path("/var/lib/avocado/os-releases")
+    };
+    let installed: Vec<String> = fs::read_dir(&os_releases_root)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().is_dir() && !e.path().is_symlink())
+                .filter_map(|e| e.file_name().to_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+    let host = crate::os_release::host();
+    let running = host.as_ref().and_then(|host| host.version_id.as_deref());
+    let pending = target
+        .map(String::from)
+        .or_else(|| pending_update_version(output));
+    let versions = compat::os_versions(running, &installed, pending.as_deref());
+    let architecture = host
+        .as_ref()
+        .and_then(|host| host.architecture.as_deref())
+        .unwrap_or(compat::host_architecture());
+    let matrix = compat::CompatMatrix::new(host.as_ref(), architecture, versions, extensions);
+
+    if output.is_json() {
+        output.result_fields(&matrix);
+    } else {
+        print!("{}", matrix.to_table());
+    }
+    let breaking = matrix.breaking();
+    if !breaking.is_empty() {
+        // JSON output carries the same as `breaks_after_update`
+        if !output.is_json() {
+            output.error(
+                "Extension Compatibility",
+                &format!(
+                    "{} extension(s) will not merge after the update to {}: {}",
+                    breaking.len(),
+                    matrix.pending().unwrap_or_default(),
+                    breaking.join(", ")
+                ),
+            );
         }
-        let type_str = if types.is_empty() {
-            "?".to_string()
-        } else {
-            types.join("+")
-        };
+        output.exit(1);
+    }
+}
 
-        let in_sysext = mounted_sysext.contains(&versioned_name);
-        let in_confext = mounted_confext.contains(&versioned_name);
-        let status = match (in_sysext, in_confext) {
-            (true, true) => "MERGED",
-            (true, false) => "SYSEXT",
-            (false, true) => "CONFEXT",
-            (false, false) => "READY",
-        };
+/// Show the build provenance and recorded hook runs of an extension
+fn show_extension_info(config: &Config, name: &str, replay: bool, output: &OutputManager) {
+    let records = crate::hook_log::read_records(name);
+    let recent = &records[records.len().saturating_sub(INFO_HOOK_RUNS)..];
+    let log = crate::hook_log::log_path(name);
+    let extensions = scan_extensions_from_all_sources(config, output).unwrap_or_default();
+    let extension = extensions.iter().find(|ext| {
+        ext.name == name
+            || ext
+                .version
+                .as_ref()
+                .is_some_and(|v| format!("{}-{v}", ext.name) == name)
+    });
+    let (provenance, lifecycle) = extension
+        .map(|ext| (extension_provenance(ext), extension_lifecycle(ext)))
+        .unwrap_or_default();
+    let aliases = extension_aliases(config, name, extension);
+    let eol_reached = lifecycle.eol_reached_at(crate::clock::now());
+    let symlinks = crate::symlink_map::recorded();
+    let symlinks = crate::symlink_map::links_of(&symlinks, name);
 
-        println!("{order_str:<6}{versioned_name:<name_width$} {type_str:<12} {status}");
+    if output.is_json() {
+        let info = serde_json::json!({
+            "extension": name,
+            "aliases": aliases,
+            "provenance": (!provenance.is_empty()).then_some(&provenance),
+            "lifecycle": (!lifecycle.is_empty()).then_some(&lifecycle),
+            "eol_reached": eol_reached,
+            "symlinks": symlinks,
+            "hook_log": log.exists().then(|| log.display().to_string()),
+            "hook_runs": recent,
+        });
+        output.result_fields(&info);
+        return;
     }
 
-    println!("  (low priority / base layer)");
-
-    // Manifest-listed extensions that the scan filtered out because they
-    // are effectively disabled. Surfaced separately so the user can see
-    // them and know they exist to enable, rather than having them
-    // silently vanish from `ext list`.
-    let base_dir = crate::manifest::RuntimeManifest::base_dir();
-    let base_path = Path::new(&base_dir);
-    if let Some(manifest) = crate::manifest::RuntimeManifest::load_active(base_path) {
-        let active_dir = base_path.join(crate::manifest::ACTIVE_LINK_NAME);
-        let overrides = crate::overrides::RuntimeOverrides::load(&active_dir);
-        let scanned: std::collections::HashSet<&str> =
-            sorted.iter().map(|e| e.name.as_str()).collect();
-        let disabled: Vec<&crate::manifest::ManifestExtension> = manifest
-            .extensions
-            .iter()
-            .filter(|m| {
-                !scanned.contains(m.name.as_str())
-                    && !crate::overrides::effective_enabled(m, &overrides)
-            })
-            .collect();
-        if !disabled.is_empty() {
-            println!();
-            println!("Disabled (present in manifest, not activated):");
-            for m in &disabled {
-                let reason = match overrides.enabled_override(&m.name) {
-                    Some(false) => "user override",
-                    None => "manifest default",
-                    Some(true) => continue, // shouldn't happen given filter
-                };
-                println!("  {}-{}  ({reason})", m.name, m.version);
-            }
+    println!("Extension: {name}");
+    if !aliases.is_empty() {
+        println!("Alias:     {}", aliases.join(", "));
+    }
+    for (label, value) in [
+        ("Build ID:  ", &provenance.build_id),
+        ("Git SHA:   ", &provenance.git_sha),
+        ("Built:     ", &provenance.build_date),
+    ] {
+        if let Some(value) = value {
+            println!("{label}{value}");
         }
     }
-
+    if let Some(eol) = &lifecycle.eol {
+        let reached = if eol_reached { " (reached)" } else { "" };
+        println!("EOL:       {eol}{reached}");
+    }
+    if let Some(notes) = &lifecycle.notes {
+        println!("Notes:     {notes}");
+    }
+    for link in &symlinks {
+        println!(
+            "Symlink:   {} -> {} ({}, {})",
+            link.path, link.source, link.kind, link.origin
+        );
+    }
+    if records.is_empty() {
+        println!("Hook log:  none recorded");
+        return;
+    }
+    println!("Hook log:  {}", log.display());
     println!();
-    println!("Total: {} active extension(s)", sorted.len());
+    println!("Recent hook runs (oldest first):");
+    for record in recent {
+        println!(
+            "  {} (unix time)  {:<10} {:<7} {}",
+            record.timestamp,
+            record.phase,
+            record.status(),
+            record.command
+        );
+        if replay {
+            for (stream, content) in [("stdout", &record.stdout), ("stderr", &record.stderr)] {
+                if content.trim().is_empty() {
+                    continue;
+                }
+                println!("    --- {stream} ---");
+                for line in content.lines() {
+                    println!("    {line}");
+                }
+            }
+        }
+    }
 }
 
-/// Merge extensions using systemd-sysext and systemd-confext
-pub fn merge_extensions(config: &Config, output: &OutputManager) {
-    match merge_extensions_internal(config, output) {
-        Ok(_) => {
-            output.success("Extension Merge", "Extensions merged successfully");
+/// CLI-facing wrapper around `service::ext::set_extensions_enabled` that
+/// formats success / failure for the terminal. Used only by the
+/// `AVOCADO_TEST_MODE` direct dispatch path — the production path goes
+/// through varlink so the daemon owns serialization across callers.
+pub fn set_extensions_enabled(names: &[String], enabled: bool, output: &OutputManager) {
+    let refs: Vec<&str> = names.iter().map(String::as_str).collect();
+    match crate::service::ext::set_extensions_enabled(&refs, enabled) {
+        Ok(result) => {
+            let verb = if enabled { "enabled" } else { "disabled" };
+            output.success(
+                "Extension Override",
+                &format!(
+                    "{verb}: {} ({} updated, {} missing)",
+                    names.join(", "),
+                    result.updated,
+                    result.missing
+                ),
+            );
+            output.info(
+                "Extension Override",
+                "Run `avocadoctl ext refresh` to apply.",
+            );
         }
         Err(e) => {
-            output.error(
-                "Extension Merge",
-                &format!("Failed to merge extensions: {e}"),
-            );
-            std::process::exit(1);
+            output.error_with("Extension Override", &e.to_string(), &e.diagnose());
+            output.exit(1);
         }
     }
 }
 
-/// Internal merge function that returns a Result
-pub(crate) fn merge_extensions_internal(
-    config: &Config,
+/// Report the outcome of `ext prefetch`.
+pub fn print_prefetch(
+    runtime_id: &str,
+    name: &str,
+    version: &str,
+    already_active: bool,
     output: &OutputManager,
-) -> Result<(), SystemdError> {
-    // Check for pending OS update — verify the new OS booted correctly.
-    // If a runtime_id is set, the runtime hasn't been activated yet and depends
-    // on OS verification. On success, promote the pending runtime to active.
-    // On failure, rollback the boot slot and keep the current active runtime.
-    let base_dir = config.get_avocado_base_dir();
-    let base_path = Path::new(&base_dir);
-    if let Some(pending) = crate::os_update::read_pending_update() {
-        let mut verified = true;
+) {
+    let short_id = &runtime_id[..8.min(runtime_id.len())];
+    let args = [("name", name), ("version", version), ("id", short_id)];
+    if already_active {
+        output.success_msg("Extension Prefetch", messages::EXT_PREFETCH_CURRENT, &args);
+    } else {
+        output.success_msg("Extension Prefetch", messages::EXT_PREFETCHED, &args);
+    }
+    output.json_ok();
+}
 
-        // Verify rootfs os-release (/sysroot/etc/os-release when in initrd)
-        if let Some(ref verify) = pending.verify {
-            match crate::os_update::verify_os_release(verify) {
-                Ok(true) => {
-                    output.step(
-                        "OS Update",
-                        &format!("Verified rootfs — {}={}", verify.field, verify.expected),
-                    );
-                }
-                Ok(false) => {
-                    output.error(
-                        "OS Update",
-                        &format!(
-                            "Rootfs {} mismatch — expected '{}'",
-                            verify.field, verify.expected
-                        ),
-                    );
-                    verified = false;
-                }
-                Err(e) => {
-                    output.error("OS Update", &format!("Rootfs verification error: {e}"));
-                    verified = false;
-                }
-            }
-        }
-
-        // Verify initrd identity (/etc/initrd-release when in initrd)
-        if is_running_in_initrd() {
-            if let Some(ref verify_initramfs) = pending.verify_initramfs {
-                match crate::os_update::verify_os_release_initrd(verify_initramfs) {
-                    Ok(true) => {
-                        output.step(
-                            "OS Update",
-                            &format!(
-                                "Verified initramfs — {}={}",
-                                verify_initramfs.field, verify_initramfs.expected
-                            ),
-                        );
-                    }
-                    Ok(false) => {
-                        output.error(
-                            "OS Update",
-                            &format!(
-                                "Initramfs {} mismatch — expected '{}'",
-                                verify_initramfs.field, verify_initramfs.expected
-                            ),
-                        );
-                        verified = false;
-                    }
-                    Err(e) => {
-                        output.error("OS Update", &format!("Initramfs verification error: {e}"));
-                        verified = false;
-                    }
-                }
-            }
-        }
+/// Report the outcome of `ext upgrade`, one line per extension considered.
+pub fn print_upgrade(
+    result: &crate::service::types::UpgradeResult,
+    dry_run: bool,
+    output: &OutputManager,
+) {
+    use crate::upgrade::UpgradeAction;
 
-        if verified {
-            output.step("OS Update", "Verification passed, clearing pending marker");
-            // Promote pending runtime to active if one is set
-            if let Some(ref runtime_id) = pending.runtime_id {
-                match crate::staging::activate_runtime(runtime_id, base_path) {
-                    Ok(()) => {
-                        output.step(
-                            "OS Update",
-                            &format!("Activated pending runtime: {runtime_id}"),
-                        );
-                    }
-                    Err(e) => {
-                        output.error(
-                            "OS Update",
-                            &format!("Failed to activate pending runtime {runtime_id}: {e}"),
-                        );
-                    }
-                }
-            }
-        } else {
-            output.error("OS Update", "Pending update verification failed");
-            // Rollback boot slot to previous OS
-            if let Err(e) = crate::os_update::rollback_os_update(&pending, false) {
-                output.error("OS Update", &format!("Rollback failed: {e}"));
-            }
-            if pending.runtime_id.is_some() {
-                output.step(
-                    "OS Update",
-                    "Keeping current active runtime (OS update did not succeed)",
-                );
+    if output.is_json() {
+        output.result_fields(result);
+        return;
+    }
+    if result.queued {
+        let until = result.queued_until.map_or_else(
+            || "the next maintenance window".to_string(),
+            crate::clock::format_time,
+        );
+        output.success_msg(
+            "Extension Upgrade",
+            messages::EXT_UPGRADE_QUEUED,
+            &[("until", &until)],
+        );
+        return;
+    }
+    for d in &result.decisions {
+        let available = d.available.as_deref().unwrap_or("");
+        let detail = match d.action {
+            UpgradeAction::Upgrade => format!("-> {available}"),
+            UpgradeAction::UpToDate => "(up to date)".to_string(),
+            UpgradeAction::Held => {
+                format!("({available} held by '{}' policy)", d.policy.as_str())
             }
-        }
-        // Always clear pending marker to avoid re-checking on subsequent boots
-        crate::os_update::clear_pending_update().ok();
+            UpgradeAction::Older => format!("(repository offers older {available})"),
+            UpgradeAction::NotOffered => "(not in the repository)".to_string(),
+        };
+        println!("  {} {} {detail}", d.name, d.current);
     }
 
-    // Verify rootfs matches what the active runtime expects.
-    // If the runtime's os_bundle.os_build_id doesn't match the running rootfs,
-    // try to fall back to a previous runtime that is compatible.
-    // Never refuse to merge extensions — always make a best effort.
-    if let Some(manifest) = crate::manifest::RuntimeManifest::load_active(base_path) {
-        // Spot-check extension image integrity before merging
-        let spot_bytes = config.get_spot_check_bytes();
-        if let Err(e) = crate::staging::verify_spot_hashes(
-            &manifest,
-            base_path,
-            spot_bytes,
-            output.is_verbose(),
-        ) {
-            output.error(
-                "Extension Merge",
-                &format!("Image integrity spot check failed:\n{e}"),
+    let count = result
+        .decisions
+        .iter()
+        .filter(|d| d.action == UpgradeAction::Upgrade)
+        .count()
+        .to_string();
+    match &result.runtime_id {
+        Some(id) => {
+            let short_id = &id[..8.min(id.len())];
+            output.success_msg(
+                "Extension Upgrade",
+                messages::EXT_UPGRADED,
+                &[("count", &count), ("id", short_id)],
             );
-            return Err(SystemdError::ConfigurationError {
-                message: format!("Image integrity spot check failed: {e}"),
-            });
         }
+        None if dry_run && count != "0" => output.success_msg(
+            "Extension Upgrade",
+            messages::EXT_UPGRADE_DRY_RUN,
+            &[("count", &count)],
+        ),
+        None => output.success_msg("Extension Upgrade", messages::EXT_UPGRADE_NOTHING, &[]),
+    }
+}
 
-        if let Some(ref os_bundle) = manifest.os_bundle {
-            if let Some(ref expected_id) = os_bundle.os_build_id {
-                match read_running_os_build_id() {
-                    Some(ref running_id) if running_id == expected_id => {
-                        // Rootfs matches — proceed normally
-                    }
-                    Some(ref running_id) => {
-                        // Mismatch — try to fall back to a compatible runtime
-                        output.error(
-                            "Extension Merge",
-                            &format!(
-                                "Rootfs mismatch: active runtime expects AVOCADO_OS_BUILD_ID={} but running rootfs has {}",
-                                expected_id, running_id
-                            ),
-                        );
-
-                        let all_runtimes = crate::manifest::RuntimeManifest::list_all(base_path);
-                        let fallback = all_runtimes.iter().find(|(rt, is_active)| {
-                            !is_active
-                                && match &rt.os_bundle {
-                                    Some(bundle) => match &bundle.os_build_id {
-                                        Some(rt_id) => rt_id == running_id,
-                                        None => true,
-                                    },
-                                    None => true,
-                                }
-                        });
+/// The `--keep` list of `ext prune-os-releases`.
+pub fn prune_keep(matches: &ArgMatches) -> Vec<String> {
+    matches
+        .get_many::<String>("keep")
+        .map(|v| v.map(|k| k.trim().to_string()).collect())
+        .unwrap_or_default()
+}
 
-                        if let Some((fallback_rt, _)) = fallback {
-                            output.step(
-                                "Extension Merge",
-                                &format!(
-                                    "Falling back to runtime {} {} ({})",
-                                    fallback_rt.runtime.name,
-                                    fallback_rt.runtime.version,
-                                    fallback_rt.id
-                                ),
-                            );
-                            if let Err(e) =
-                                crate::staging::activate_runtime(&fallback_rt.id, base_path)
-                            {
-                                output.error(
-                                    "Extension Merge",
-                                    &format!("Failed to activate fallback runtime: {e}"),
-                                );
-                            }
-                        } else {
-                            output.error(
-                                "Extension Merge",
-                                "No compatible runtime found — proceeding with current runtime (best effort)",
-                            );
-                        }
-                    }
-                    None => {
-                        // AVOCADO_OS_BUILD_ID not present in os-release — skip check
-                        // (initial provisioned rootfs or pre-avocado-cli image)
-                    }
-                }
-            }
-        }
+pub fn print_os_release_prune(
+    result: &crate::service::types::PruneOsReleasesResult,
+    dry_run: bool,
+    output: &OutputManager,
+) {
+    if output.is_json() {
+        output.result_fields(result);
+        return;
+    }
+    for version in &result.kept {
+        let note = if *version == result.current {
+            " (current)"
+        } else {
+            ""
+        };
+        println!("  keep   {version}{note}");
+    }
+    for version in &result.removed {
+        println!("  remove {version}");
     }
 
-    let environment_info = if is_running_in_initrd() {
-        "initrd environment"
+    let count = result.removed.len().to_string();
+    let message = if dry_run {
+        messages::EXT_OS_RELEASES_PRUNE_DRY_RUN
     } else {
-        "system environment"
+        messages::EXT_OS_RELEASES_PRUNED
     };
-    output.info(
-        "Extension Merge",
-        &format!("Starting extension merge process in {environment_info}"),
-    );
-
-    // Prepare the environment by setting up symlinks and get the list of enabled extensions
-    let enabled_extensions = prepare_extension_environment_with_output(output)?;
+    output.success_msg("Prune OS Releases", message, &[("count", &count)]);
+}
 
-    // Get the mutability settings from config (separate for sysext and confext)
-    let sysext_mutability = match config.get_sysext_mutable() {
-        Ok(value) => value,
-        Err(e) => {
-            output.error(
-                "Configuration Error",
-                &format!("Invalid sysext mutable configuration: {e}"),
+/// Write a snapshot to `file`, or to stdout when no file is given.
+pub fn write_snapshot(
+    snapshot: &crate::snapshot::StateSnapshot,
+    file: Option<&Path>,
+    output: &OutputManager,
+) {
+    match file {
+        Some(path) => {
+            if let Err(e) = snapshot.save(path) {
+                output.error_with(
+                    "Extension Snapshot",
+                    &format!("Failed to write '{}': {e}", path.display()),
+                    &e.diagnose(),
+                );
+                output.exit(1);
+            }
+            output.success(
+                "Extension Snapshot",
+                &format!(
+                    "Captured {} extension(s) to {}",
+                    snapshot.extensions.len(),
+                    path.display()
+                ),
             );
-            return Err(SystemdError::ConfigurationError {
-                message: e.to_string(),
-            });
+            output.result("file", &path);
         }
+        None => println!("{}", snapshot.to_json()),
+    }
+}
+
+/// Optionally sign an audit report, then write it to `file` or stdout.
+pub fn write_audit_report(
+    report: crate::audit::AuditReport,
+    file: Option<&Path>,
+    key: Option<&Path>,
+    output: &OutputManager,
+) {
+    let image_count = report.images.len();
+    let (json, signed_by) = match key {
+        Some(key_path) => match report.sign_with_key_file(key_path) {
+            Ok(signed) => {
+                let keyid = signed.signatures[0].keyid.clone();
+                (signed.to_json(), Some(keyid))
+            }
+            Err(e) => {
+                output.error("Extension Audit", &e);
+                output.exit(1);
+            }
+        },
+        None => (report.to_json(), None),
     };
-    let sysext_mutable_arg = format!("--mutable={sysext_mutability}");
 
-    let confext_mutability = match config.get_confext_mutable() {
-        Ok(value) => value,
-        Err(e) => {
-            output.error(
-                "Configuration Error",
-                &format!("Invalid confext mutable configuration: {e}"),
+    match file {
+        Some(path) => {
+            if let Err(e) = fs::write(path, json + "\n") {
+                output.error_with(
+                    "Extension Audit",
+                    &format!("Failed to write '{}': {e}", path.display()),
+                    &e.diagnose(),
+                );
+                output.exit(1);
+            }
+            let signature = signed_by
+                .map(|k| format!(", signed by key {}", &k[..16.min(k.len())]))
+                .unwrap_or_default();
+            output.success(
+                "Extension Audit",
+                &format!(
+                    "Wrote audit report for {image_count} image(s) to {}{signature}",
+                    path.display()
+                ),
             );
-            return Err(SystemdError::ConfigurationError {
-                message: e.to_string(),
-            });
+            output.result("file", &path);
         }
-    };
-    let confext_mutable_arg = format!("--mutable={confext_mutability}");
-
-    // Merge system extensions
-    let sysext_result = run_systemd_command(
-        "systemd-sysext",
-        &["merge", &sysext_mutable_arg, "--json=short"],
-    )?;
-    handle_systemd_output("systemd-sysext merge", &sysext_result, output)?;
-
-    // Merge configuration extensions
-    let confext_result = run_systemd_command(
-        "systemd-confext",
-        &["merge", &confext_mutable_arg, "--json=short"],
-    )?;
-    handle_systemd_output("systemd-confext merge", &confext_result, output)?;
-
-    // Process post-merge tasks for enabled extensions, with daemon-reload
-    // happening after depmod/ldconfig/modprobe but before service commands.
-    // This ensures kernel modules and shared libraries are available when
-    // systemd re-evaluates units during daemon-reload.
-    process_post_merge_tasks_for_extensions(&enabled_extensions, output)?;
-
-    Ok(())
+        None => println!("{json}"),
+    }
 }
 
-/// Unmerge extensions using systemd-sysext and systemd-confext
-pub fn unmerge_extensions(unmount: bool, output: &OutputManager) {
-    match unmerge_extensions_internal(unmount, output) {
-        Ok(_) => {
-            output.success("Extension Unmerge", "Extensions unmerged successfully");
-        }
+/// Load a snapshot file, printing an error and exiting on failure.
+pub fn load_snapshot_or_exit(path: &str, output: &OutputManager) -> crate::snapshot::StateSnapshot {
+    match crate::snapshot::StateSnapshot::load(Path::new(path)) {
+        Ok(snapshot) => snapshot,
         Err(e) => {
-            output.error(
-                "Extension Unmerge",
-                &format!("Failed to unmerge extensions: {e}"),
-            );
-            std::process::exit(1);
+            output.error("Extension Compare", &e);
+            output.exit(1);
         }
     }
 }
 
-/// Internal unmerge function that returns a Result for use in refresh
-fn unmerge_extensions_internal(unmount: bool, output: &OutputManager) -> Result<(), SystemdError> {
-    unmerge_extensions_internal_with_depmod(true, unmount, output)
-}
-
-/// Internal unmerge function with optional depmod control
-fn unmerge_extensions_internal_with_depmod(
-    call_depmod: bool,
-    unmount: bool,
+/// Load an `ext apply` manifest, exiting with an error message on failure.
+pub fn load_transaction_manifest_or_exit(
+    path: &Path,
     output: &OutputManager,
-) -> Result<(), SystemdError> {
-    unmerge_extensions_internal_with_options(call_depmod, unmount, output)
+) -> crate::transaction::TransactionManifest {
+    match crate::transaction::TransactionManifest::load(path) {
+        Ok(manifest) if manifest.is_empty() => {
+            output.error(
+                "Extension Apply",
+                &format!("Manifest '{}' requests no changes", path.display()),
+            );
+            output.exit(1);
+        }
+        Ok(manifest) => manifest,
+        Err(e) => {
+            output.error("Extension Apply", &e);
+            output.exit(1);
+        }
+    }
 }
 
-/// Internal unmerge function with all options
-pub(crate) fn unmerge_extensions_internal_with_options(
-    call_depmod: bool,
-    unmount: bool,
+/// Print the net change of an `ext apply` manifest without applying it.
+pub fn print_transaction_plan(
+    manifest: &crate::transaction::TransactionManifest,
+    config: &Config,
     output: &OutputManager,
-) -> Result<(), SystemdError> {
-    let environment_info = if is_running_in_initrd() {
-        "initrd environment"
-    } else {
-        "system environment"
+) {
+    let plan = match crate::service::ext::plan_transaction(manifest, config) {
+        Ok((_, _, plan)) => plan,
+        Err(e) => {
+            output.error_with("Extension Apply", &e.to_string(), &e.diagnose());
+            output.exit(1);
+        }
     };
-    output.info(
-        "Extension Unmerge",
-        &format!("Starting extension unmerge process in {environment_info}"),
-    );
 
-    // Execute AVOCADO_ON_UNMERGE commands before unmerging extensions
-    // These commands are executed while extensions are still merged
-    if let Err(e) = process_pre_unmerge_tasks(output) {
-        output.progress(&format!(
-            "Warning: Failed to process pre-unmerge tasks: {e}"
-        ));
-        // Continue with unmerge even if pre-unmerge tasks fail
+    if output.is_json() {
+        output.result_fields(&plan);
+        return;
     }
-
-    // Unmerge system extensions
-    let sysext_result = run_systemd_command("systemd-sysext", &["unmerge", "--json=short"])?;
-    handle_systemd_output("systemd-sysext unmerge", &sysext_result, output)?;
-
-    // Unmerge configuration extensions
-    let confext_result = run_systemd_command("systemd-confext", &["unmerge", "--json=short"])?;
-    handle_systemd_output("systemd-confext unmerge", &confext_result, output)?;
-
-    // Clean up extension-release bind mounts and staging directories
-    // Must happen after systemd unmerge but before loop unmount
-    cleanup_extension_release_staging(output)?;
-
-    // Clean up all symlinks to ensure fresh state for next merge
-    cleanup_extension_symlinks(output)?;
-
-    // Run depmod after unmerge if requested
-    if call_depmod {
-        run_depmod(output)?;
+    for name in &plan.not_enabled {
+        println!("Note: '{name}' is not enabled; nothing to disable");
     }
-
-    // Unmount persistent loops if requested
-    if unmount {
-        unmount_all_persistent_mounts()?;
+    if plan.is_empty() {
+        println!("Nothing to do: all extensions are already in the requested state.");
+        return;
     }
-
-    Ok(())
-}
-
-/// Direct access functions for top-level command aliases
-///
-/// Merge extensions - direct access for top-level alias
-pub fn merge_extensions_direct(output: &OutputManager) {
-    // Use default config for direct access
-    let config = Config::default();
-    merge_extensions(&config, output);
-}
-
-/// Unmerge extensions - direct access for top-level alias
-pub fn unmerge_extensions_direct(unmount: bool, output: &OutputManager) {
-    unmerge_extensions(unmount, output);
+    println!("Planned changes (dry run, nothing changed):");
+    for step in &plan.steps {
+        println!("  {step}");
+    }
+    println!("Extensions would be refreshed once.");
 }
 
-/// Refresh extensions - direct access for top-level alias
-pub fn refresh_extensions_direct(output: &OutputManager) {
-    // Use default config for direct access
-    let config = Config::default();
-    refresh_extensions(&config, output);
+/// Report the outcome of `ext apply`.
+pub fn print_apply_result(result: &crate::service::types::ApplyResult, output: &OutputManager) {
+    if output.is_json() {
+        output.result_fields(result);
+    } else if result.refreshed {
+        output.success_msg(
+            "Extension Apply",
+            messages::EXT_APPLIED,
+            &[
+                ("enabled", &result.linked.to_string()),
+                ("disabled", &result.unlinked.to_string()),
+            ],
+        );
+    } else {
+        output.success_msg("Extension Apply", messages::EXT_APPLY_NOTHING, &[]);
+    }
 }
 
-/// Enable extensions for a specific OS release version
-pub fn enable_extensions(
-    os_release_version: Option<&str>,
-    extensions: &[&str],
-    config: &Config,
+/// Print the differences between two snapshots.
+pub fn print_snapshot_comparison(
+    left: &crate::snapshot::StateSnapshot,
+    left_label: &str,
+    right: &crate::snapshot::StateSnapshot,
+    right_label: &str,
     output: &OutputManager,
 ) {
-    // Warn if an active runtime manifest is present
-    let base_dir = config.get_avocado_base_dir();
-    if crate::manifest::RuntimeManifest::load_active(std::path::Path::new(&base_dir)).is_some() {
-        eprintln!("Warning: An active runtime manifest is present. The manifest takes precedence over symlink-based extension discovery during merge/refresh.");
+    let diffs = crate::snapshot::compare(left, right);
+
+    if output.is_json() {
+        let json = serde_json::json!({
+            "left": left_label,
+            "right": right_label,
+            "differences": diffs,
+        });
+        output.result_fields(&json);
+        return;
     }
 
-    // Determine the OS release version to use
-    let version_id = if let Some(version) = os_release_version {
-        version.to_string()
-    } else {
-        read_os_version_id()
+    println!("Comparing {left_label} (left) with {right_label} (right)");
+    println!();
+    if diffs.is_empty() {
+        println!("No differences.");
+        return;
+    }
+    for diff in &diffs {
+        println!("  {diff}");
+    }
+    println!();
+    println!("Total: {} difference(s)", diffs.len());
+}
+
+/// List all extensions from disk images, annotating which are currently mounted/active.
+fn list_extensions(config: &Config, output: &OutputManager) {
+    output.info("Extension List", "Listing available extensions");
+
+    let (available, rejected) = match scan_all_sources(config, output, false, false) {
+        Ok(scanned) => (scanned.extensions, scanned.rejected),
+        Err(e) => {
+            output.error("Extension List", &format!("Failed to scan extensions: {e}"));
+            output.exit(1);
+        }
     };
+    // Versioned names of the images a merge would refuse, with the reason
+    let rejected: std::collections::HashMap<String, String> = rejected
+        .into_iter()
+        .map(|failure| (failure.extension, failure.error))
+        .collect();
 
-    output.info(
-        "Enable Extensions",
-        &format!("Enabling extensions for OS release version: {version_id}"),
-    );
+    if available.is_empty() && !output.is_json() {
+        println!("No extensions found.");
+        return;
+    }
 
-    // Get the extensions directory from config
-    let extensions_dir = config.get_extensions_dir();
+    // Collect mounted names for correlation (strip order prefix, ignore errors)
+    let mounted_sysext: std::collections::HashSet<String> =
+        get_mounted_systemd_extensions("systemd-sysext")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+    let mounted_confext: std::collections::HashSet<String> =
+        get_mounted_systemd_extensions("systemd-confext")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
 
-    // Determine os-releases directory based on test mode
-    let os_releases_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/avocado/os-releases/{version_id}")
-    } else {
-        format!("/var/lib/avocado/os-releases/{version_id}")
-    };
+    // Sort descending by merge_index (highest priority / top layer first).
+    // Extensions without a merge_index sort to the bottom.
+    let mut sorted = available;
+    sorted.sort_by(|a, b| {
+        b.merge_index
+            .cmp(&a.merge_index)
+            .then_with(|| a.name.cmp(&b.name))
+    });
 
-    // Create the os-releases directory if it doesn't exist
-    if let Err(e) = fs::create_dir_all(&os_releases_dir) {
-        output.error(
-            "Enable Extensions",
-            &format!("Failed to create os-releases directory '{os_releases_dir}': {e}"),
+    if output.is_json() {
+        print_extension_list_json(
+            &sorted,
+            &mounted_sysext,
+            &mounted_confext,
+            &rejected,
+            output,
         );
-        std::process::exit(1);
+        return;
     }
 
-    // Sync the parent directory to ensure the os-releases directory is persisted
-    if let Err(e) = sync_directory(
-        Path::new(&os_releases_dir)
-            .parent()
-            .unwrap_or(Path::new("/")),
-    ) {
-        output.progress(&format!("Warning: Failed to sync parent directory: {e}"));
-    }
+    // Compute column width
+    let name_width = sorted
+        .iter()
+        .map(|e| {
+            if let Some(ver) = &e.version {
+                e.name.len() + 1 + ver.len()
+            } else {
+                e.name.len()
+            }
+        })
+        .max()
+        .unwrap_or(9)
+        .max(9);
 
-    output.step(
-        "Enable",
-        &format!("Created os-releases directory: {os_releases_dir}"),
+    println!("  (high priority / top layer)");
+    println!(
+        "{:<6}{:<nw$} {:<12} {:<8}",
+        "Order",
+        "Extension",
+        "Type",
+        "Status",
+        nw = name_width
     );
+    println!("{}", "=".repeat(6 + name_width + 1 + 12 + 1 + 8));
 
-    // Process each extension
-    let mut success_count = 0;
-    let mut error_count = 0;
-
-    for ext_name in extensions {
-        // Check if extension exists - try both directory and .raw file
-        let ext_dir_path = format!("{extensions_dir}/{ext_name}");
-        let ext_raw_path = format!("{extensions_dir}/{ext_name}.raw");
-
-        let source_path = if Path::new(&ext_dir_path).exists() {
-            ext_dir_path
-        } else if Path::new(&ext_raw_path).exists() {
-            ext_raw_path
+    for ext in &sorted {
+        let versioned_name = if let Some(ver) = &ext.version {
+            format!("{}-{}", ext.name, ver)
         } else {
-            output.error(
-                "Enable Extensions",
-                &format!("Extension '{ext_name}' not found in {extensions_dir}"),
-            );
-            error_count += 1;
-            continue;
+            ext.name.clone()
         };
 
-        // Create symlink in os-releases directory
-        let target_path = format!(
-            "{}/{}",
-            os_releases_dir,
-            Path::new(&source_path)
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-        );
+        let order_str = ext
+            .merge_index
+            .map(|i| format!("#{i:02}"))
+            .unwrap_or_else(|| "-".to_string());
 
-        // Remove existing symlink if it exists
-        if Path::new(&target_path).exists() {
-            if let Err(e) = fs::remove_file(&target_path) {
-                output.error(
-                    "Enable Extensions",
-                    &format!("Failed to remove existing symlink '{target_path}': {e}"),
-                );
-                error_count += 1;
-                continue;
-            }
+        let mut types = Vec::new();
+        if ext.is_sysext {
+            types.push("sys");
         }
-
-        // Create the symlink
-        if let Err(e) = unix_fs::symlink(&source_path, &target_path) {
-            output.error(
-                "Enable Extensions",
-                &format!("Failed to create symlink for '{ext_name}': {e}"),
-            );
-            error_count += 1;
-        } else {
-            output.progress(&format!("Enabled extension: {ext_name}"));
-            success_count += 1;
+        if ext.is_confext {
+            types.push("conf");
         }
-    }
+        let type_str = if types.is_empty() {
+            "?".to_string()
+        } else {
+            types.join("+")
+        };
 
-    // Sync the os-releases directory to ensure all symlinks are persisted to disk
-    if success_count > 0 {
-        if let Err(e) = sync_directory(Path::new(&os_releases_dir)) {
-            output.error(
-                "Enable Extensions",
-                &format!("Failed to sync os-releases directory to disk: {e}"),
-            );
-            std::process::exit(1);
-        }
-        output.progress("Synced changes to disk");
+        let in_sysext = mounted_sysext.contains(&versioned_name);
+        let in_confext = mounted_confext.contains(&versioned_name);
+        let status = match (in_sysext, in_confext) {
+            (true, true) => "MERGED",
+            (true, false) => "SYSEXT",
+            (false, true) => "CONFEXT",
+            (false, false) if rejected.contains_key(&versioned_name) => "REJECTED",
+            (false, false) => "READY",
+        };
+
+        println!("{order_str:<6}{versioned_name:<name_width$} {type_str:<12} {status}");
     }
 
-    // Summary
-    if error_count > 0 {
-        output.error(
-            "Enable Extensions",
-            &format!("Completed with errors: {success_count} succeeded, {error_count} failed"),
-        );
-        std::process::exit(1);
-    } else {
-        output.success(
-            "Enable Extensions",
-            &format!(
-                "Successfully enabled {success_count} extension(s) for OS release {version_id}"
-            ),
-        );
-    }
-}
+    println!("  (low priority / base layer)");
 
-/// Sync a directory to ensure all changes are persisted to disk
-pub(crate) fn sync_directory(dir_path: &Path) -> Result<(), SystemdError> {
-    // Open the directory
-    let dir = fs::File::open(dir_path).map_err(|e| SystemdError::CommandFailed {
-        command: format!("open directory {}", dir_path.display()),
-        source: e,
-    })?;
+    // Manifest-listed extensions that the scan filtered out because they
+    // are effectively disabled. Surfaced separately so the user can see
+    // them and know they exist to enable, rather than having them
+    // silently vanish from `ext list`.
+    let base_dir = crate::manifest::RuntimeManifest::base_dir();
+    let base_path = Path::new(&base_dir);
+    if let Some(manifest) = crate::manifest::RuntimeManifest::load_active(base_path) {
+        let active_dir = base_path.join(crate::manifest::ACTIVE_LINK_NAME);
+        let overrides = crate::overrides::RuntimeOverrides::load(&active_dir);
+        let scanned: std::collections::HashSet<&str> =
+            sorted.iter().map(|e| e.name.as_str()).collect();
+        let disabled: Vec<&crate::manifest::ManifestExtension> = manifest
+            .extensions
+            .iter()
+            .filter(|m| {
+                !scanned.contains(m.name.as_str())
+                    && !crate::overrides::effective_enabled(m, &overrides)
+            })
+            .collect();
+        if !disabled.is_empty() {
+            println!();
+            println!("Disabled (present in manifest, not activated):");
+            for m in &disabled {
+                let reason = match overrides.enabled_override(&m.name) {
+                    Some(false) => "user override",
+                    None => "manifest default",
+                    Some(true) => continue, // shouldn't happen given filter
+                };
+                println!("  {}-{}  ({reason})", m.name, m.version);
+            }
+        }
+    }
 
-    // Sync the directory to disk
-    // This ensures directory entries (like new symlinks) are persisted
-    dir.sync_all().map_err(|e| SystemdError::CommandFailed {
-        command: format!("sync directory {}", dir_path.display()),
-        source: e,
-    })?;
+    if !rejected.is_empty() {
+        let mut names: Vec<&String> = rejected.keys().collect();
+        names.sort();
+        println!();
+        println!("Rejected (a merge would refuse them):");
+        for name in names {
+            println!("  {name}  ({})", rejected[name]);
+        }
+    }
 
-    Ok(())
+    println!();
+    println!("Total: {} active extension(s)", sorted.len());
 }
 
-/// Disable extensions for a specific OS release version
-pub fn disable_extensions(
-    os_release_version: Option<&str>,
-    extensions: Option<&[&str]>,
-    all: bool,
-    config: &Config,
+/// Print the scanned extensions as a JSON array in the shape of the
+/// daemon's `Extension` type, plus the merge order and mount status.
+fn print_extension_list_json(
+    sorted: &[Extension],
+    mounted_sysext: &std::collections::HashSet<String>,
+    mounted_confext: &std::collections::HashSet<String>,
+    rejected: &std::collections::HashMap<String, String>,
     output: &OutputManager,
 ) {
-    // Warn if an active runtime manifest is present
-    let base_dir = config.get_avocado_base_dir();
-    if crate::manifest::RuntimeManifest::load_active(std::path::Path::new(&base_dir)).is_some() {
-        eprintln!("Warning: An active runtime manifest is present. The manifest takes precedence over symlink-based extension discovery during merge/refresh.");
+    let list: Vec<serde_json::Value> = sorted
+        .iter()
+        .map(|ext| {
+            let versioned_name = match &ext.version {
+                Some(ver) => format!("{}-{}", ext.name, ver),
+                None => ext.name.clone(),
+            };
+            let status = match (
+                mounted_sysext.contains(&versioned_name),
+                mounted_confext.contains(&versioned_name),
+            ) {
+                (true, true) => "merged",
+                (true, false) => "sysext",
+                (false, true) => "confext",
+                (false, false) if rejected.contains_key(&versioned_name) => "rejected",
+                (false, false) => "ready",
+            };
+            serde_json::json!({
+                "name": ext.name,
+                "version": ext.version,
+                "path": ext.path.to_string_lossy(),
+                "isSysext": ext.is_sysext,
+                "isConfext": ext.is_confext,
+                "isDirectory": ext.path.is_dir(),
+                "order": ext.merge_index,
+                "status": status,
+                "rejected": rejected.get(&versioned_name),
+            })
+        })
+        .collect();
+    if let Err(e) = output.json_document(&list) {
+        output.error("Output", &format!("JSON serialization failed: {e}"));
+        output.exit(1);
     }
+}
 
-    // Determine the OS release version to use
-    let version_id = if let Some(version) = os_release_version {
-        version.to_string()
-    } else {
-        read_os_version_id()
-    };
+/// Merge extensions using systemd-sysext and systemd-confext
+pub fn merge_extensions(config: &Config, output: &OutputManager) {
+    match merge_extensions_internal(config, output) {
+        Ok(_) => {
+            exit_if_partial_failure(output);
+            output.success_msg("Extension Merge", messages::EXT_MERGED, &[]);
+            exit_if_reboot_required(output);
+        }
+        Err(e) => {
+            output.error_with(
+                "Extension Merge",
+                &format!("Failed to merge extensions: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    }
+}
 
-    output.info(
-        "Disable Extensions",
-        &format!("Disabling extensions for OS release version: {version_id}"),
-    );
+/// Link and loop-mount the enabled extensions without merging them
+pub fn mount_extensions_only(config: &Config, output: &OutputManager) {
+    let scanning = crate::phases::enter(Phase::Scanning);
+    let prepared = prepare_extension_environment_with_output(config, output);
+    drop(scanning);
+    match prepared {
+        Ok(extensions) => {
+            exit_if_partial_failure(output);
+            output.success_msg(
+                "Extension Merge",
+                messages::EXT_MOUNTED_ONLY,
+                &[("count", &extensions.len().to_string())],
+            );
+        }
+        Err(e) => {
+            output.error_with(
+                "Extension Merge",
+                &format!("Failed to prepare extensions: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    }
+}
 
-    // Determine os-releases directory based on test mode
-    let os_releases_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/avocado/os-releases/{version_id}")
-    } else {
-        format!("/var/lib/avocado/os-releases/{version_id}")
+/// Merge extensions inside a container or chroot
+pub fn merge_extensions_to_target(config: &Config, target: &str, output: &OutputManager) {
+    let target = match MergeTarget::parse(target) {
+        Ok(target) => target,
+        Err(message) => {
+            let e = SystemdError::ConfigurationError { message };
+            output.error_with("Extension Merge", &e.to_string(), &e.diagnose());
+            output.exit(1);
+        }
     };
-
-    // Check if os-releases directory exists
-    if !Path::new(&os_releases_dir).exists() {
-        output.error(
-            "Disable Extensions",
-            &format!("OS releases directory '{os_releases_dir}' does not exist"),
-        );
-        std::process::exit(1);
+    match merge_extensions_into(config, Some(&target), output) {
+        Ok(_) => {
+            exit_if_partial_failure(output);
+            output.success_msg(
+                "Extension Merge",
+                messages::EXT_MERGED_INTO,
+                &[("target", &target.to_string())],
+            );
+        }
+        Err(e) => {
+            output.error_with(
+                "Extension Merge",
+                &format!("Failed to merge extensions into {target}: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
     }
+}
 
-    let mut success_count = 0;
-    let mut error_count = 0;
+/// Internal merge function that returns a Result
+pub(crate) fn merge_extensions_internal(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    crate::systemd_runtime::require("merge")?;
+    // Drop-ins of HITL mounts a crashed `hitl unmount` left behind
+    crate::commands::hitl::remove_orphaned_dropins(output);
 
-    if all {
-        // Disable all extensions by removing all symlinks in the os-releases directory
-        output.step("Disable", "Removing all extensions");
+    // Check for pending OS update — verify the new OS booted correctly.
+    // If a runtime_id is set, the runtime hasn't been activated yet and depends
+    // on OS verification. On success, promote the pending runtime to active.
+    // On failure, rollback the boot slot and keep the current active runtime.
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = Path::new(&base_dir);
+    if let Some(pending) = crate::os_update::read_pending_update() {
+        let mut verified = true;
 
-        match fs::read_dir(&os_releases_dir) {
-            Ok(entries) => {
-                for entry in entries {
-                    match entry {
-                        Ok(entry) => {
-                            let path = entry.path();
-                            // Only remove symlinks, not regular files or directories
-                            if path.is_symlink() {
-                                if let Some(file_name) = path.file_name() {
-                                    if let Some(name_str) = file_name.to_str() {
-                                        match fs::remove_file(&path) {
-                                            Ok(_) => {
-                                                output.progress(&format!(
-                                                    "Disabled extension: {name_str}"
-                                                ));
-                                                success_count += 1;
-                                            }
-                                            Err(e) => {
-                                                output.error(
-                                                    "Disable Extensions",
-                                                    &format!("Failed to remove symlink '{name_str}': {e}"),
-                                                );
-                                                error_count += 1;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            output.error(
-                                "Disable Extensions",
-                                &format!("Failed to read directory entry: {e}"),
-                            );
-                            error_count += 1;
-                        }
-                    }
+        // Verify rootfs os-release (/sysroot/etc/os-release when in initrd)
+        if let Some(ref verify) = pending.verify {
+            match crate::os_update::verify_os_release(verify) {
+                Ok(true) => {
+                    output.step(
+                        "OS Update",
+                        &format!("Verified rootfs — {}={}", verify.field, verify.expected),
+                    );
+                }
+                Ok(false) => {
+                    output.error(
+                        "OS Update",
+                        &format!(
+                            "Rootfs {} mismatch — expected '{}'",
+                            verify.field, verify.expected
+                        ),
+                    );
+                    verified = false;
+                }
+                Err(e) => {
+                    output.error_with(
+                        "OS Update",
+                        &format!("Rootfs verification error: {e}"),
+                        &e.diagnose(),
+                    );
+                    verified = false;
                 }
-            }
-            Err(e) => {
-                output.error(
-                    "Disable Extensions",
-                    &format!("Failed to read os-releases directory '{os_releases_dir}': {e}"),
-                );
-                std::process::exit(1);
             }
         }
-    } else if let Some(ext_names) = extensions {
-        // Disable specific extensions
-        for ext_name in ext_names {
-            // Check for both directory and .raw file symlinks
-            let symlink_dir = format!("{os_releases_dir}/{ext_name}");
-            let symlink_raw = format!("{os_releases_dir}/{ext_name}.raw");
-
-            let mut found = false;
 
-            // Try to remove directory symlink
-            if Path::new(&symlink_dir).exists() {
-                match fs::remove_file(&symlink_dir) {
-                    Ok(_) => {
-                        output.progress(&format!("Disabled extension: {ext_name}"));
-                        success_count += 1;
-                        found = true;
+        // Verify initrd identity (/etc/initrd-release when in initrd)
+        if is_running_in_initrd() {
+            if let Some(ref verify_initramfs) = pending.verify_initramfs {
+                match crate::os_update::verify_os_release_initrd(verify_initramfs) {
+                    Ok(true) => {
+                        output.step(
+                            "OS Update",
+                            &format!(
+                                "Verified initramfs — {}={}",
+                                verify_initramfs.field, verify_initramfs.expected
+                            ),
+                        );
                     }
-                    Err(e) => {
+                    Ok(false) => {
                         output.error(
-                            "Disable Extensions",
-                            &format!("Failed to remove symlink for '{ext_name}': {e}"),
+                            "OS Update",
+                            &format!(
+                                "Initramfs {} mismatch — expected '{}'",
+                                verify_initramfs.field, verify_initramfs.expected
+                            ),
+                        );
+                        verified = false;
+                    }
+                    Err(e) => {
+                        output.error_with(
+                            "OS Update",
+                            &format!("Initramfs verification error: {e}"),
+                            &e.diagnose(),
                         );
-                        error_count += 1;
-                        found = true;
+                        verified = false;
                     }
                 }
             }
+        }
 
-            // Try to remove .raw symlink
-            if Path::new(&symlink_raw).exists() {
-                match fs::remove_file(&symlink_raw) {
-                    Ok(_) => {
-                        if !found {
-                            output.progress(&format!("Disabled extension: {ext_name}"));
-                            success_count += 1;
-                        }
-                        found = true;
+        if verified {
+            output.step("OS Update", "Verification passed, clearing pending marker");
+            // Promote pending runtime to active if one is set
+            if let Some(ref runtime_id) = pending.runtime_id {
+                match crate::staging::activate_runtime(runtime_id, base_path) {
+                    Ok(()) => {
+                        output.step(
+                            "OS Update",
+                            &format!("Activated pending runtime: {runtime_id}"),
+                        );
                     }
                     Err(e) => {
-                        output.error(
-                            "Disable Extensions",
-                            &format!("Failed to remove .raw symlink for '{ext_name}': {e}"),
+                        output.error_with(
+                            "OS Update",
+                            &format!("Failed to activate pending runtime {runtime_id}: {e}"),
+                            &e.diagnose(),
                         );
-                        error_count += 1;
-                        found = true;
                     }
                 }
             }
-
-            if !found {
-                output.error(
-                    "Disable Extensions",
-                    &format!("Extension '{ext_name}' is not enabled for OS release {version_id}"),
+        } else {
+            output.error("OS Update", "Pending update verification failed");
+            // Rollback boot slot to previous OS
+            if let Err(e) = crate::os_update::rollback_os_update(&pending, false) {
+                output.error_with("OS Update", &format!("Rollback failed: {e}"), &e.diagnose());
+            }
+            if pending.runtime_id.is_some() {
+                output.step(
+                    "OS Update",
+                    "Keeping current active runtime (OS update did not succeed)",
                 );
-                error_count += 1;
             }
         }
-    } else {
-        // This should not happen due to clap validation, but handle it anyway
-        output.error(
-            "Disable Extensions",
-            "No extensions specified. Use --all to disable all extensions or specify extension names.",
-        );
-        std::process::exit(1);
+        // Always clear pending marker to avoid re-checking on subsequent boots
+        crate::os_update::clear_pending_update().ok();
     }
 
-    // Sync the os-releases directory to ensure all removals are persisted to disk
-    if success_count > 0 {
-        if let Err(e) = sync_directory(Path::new(&os_releases_dir)) {
-            output.error(
-                "Disable Extensions",
-                &format!("Failed to sync os-releases directory to disk: {e}"),
-            );
-            std::process::exit(1);
-        }
-        output.progress("Synced changes to disk");
-    }
+    // Verify rootfs matches what the active runtime expects.
+    // If the runtime's os_bundle.os_build_id doesn't match the running rootfs,
+    // try to fall back to a previous runtime that is compatible.
+    // Never refuse to merge extensions — always make a best effort.
+    if let Some(manifest) = crate::manifest::RuntimeManifest::load_active(base_path) {
+        // Spot-check extension image integrity before merging
+        let spot_bytes = config.get_spot_check_bytes();
+        if let Err(e) = crate::staging::verify_spot_hashes(
+            &manifest,
+            base_path,
+            spot_bytes,
+            &crate::hash::Checksummer::from_settings(config.checksum(), None),
+            output.is_verbose(),
+        ) {
+            output.error_with(
+                "Extension Merge",
+                &format!("Image integrity spot check failed:\n{e}"),
+                &e.diagnose(),
+            );
+            return Err(SystemdError::ConfigurationError {
+                message: format!("Image integrity spot check failed: {e}"),
+            });
+        }
 
-    // Summary
-    if error_count > 0 {
-        output.error(
-            "Disable Extensions",
-            &format!("Completed with errors: {success_count} succeeded, {error_count} failed"),
-        );
-        std::process::exit(1);
-    } else {
-        output.success(
-            "Disable Extensions",
-            &format!(
-                "Successfully disabled {success_count} extension(s) for OS release {version_id}"
-            ),
-        );
+        if let Some(ref os_bundle) = manifest.os_bundle {
+            if let Some(ref expected_id) = os_bundle.os_build_id {
+                match read_running_os_build_id() {
+                    Some(ref running_id) if running_id == expected_id => {
+                        // Rootfs matches — proceed normally
+                    }
+                    Some(ref running_id) => {
+                        // Mismatch — try to fall back to a compatible runtime
+                        output.error(
+                            "Extension Merge",
+                            &format!(
+                                "Rootfs mismatch: active runtime expects AVOCADO_OS_BUILD_ID={} but running rootfs has {}",
+                                expected_id, running_id
+                            ),
+                        );
+
+                        let all_runtimes = crate::manifest::RuntimeManifest::list_all(base_path);
+                        let fallback = all_runtimes.iter().find(|(rt, is_active)| {
+                            !is_active
+                                && match &rt.os_bundle {
+                                    Some(bundle) => match &bundle.os_build_id {
+                                        Some(rt_id) => rt_id == running_id,
+                                        None => true,
+                                    },
+                                    None => true,
+                                }
+                        });
+
+                        if let Some((fallback_rt, _)) = fallback {
+                            output.step(
+                                "Extension Merge",
+                                &format!(
+                                    "Falling back to runtime {} {} ({})",
+                                    fallback_rt.runtime.name,
+                                    fallback_rt.runtime.version,
+                                    fallback_rt.id
+                                ),
+                            );
+                            if let Err(e) =
+                                crate::staging::activate_runtime(&fallback_rt.id, base_path)
+                            {
+                                output.error_with(
+                                    "Extension Merge",
+                                    &format!("Failed to activate fallback runtime: {e}"),
+                                    &e.diagnose(),
+                                );
+                            }
+                        } else {
+                            output.error(
+                                "Extension Merge",
+                                "No compatible runtime found — proceeding with current runtime (best effort)",
+                            );
+                        }
+                    }
+                    None => {
+                        // AVOCADO_OS_BUILD_ID not present in os-release — skip check
+                        // (initial provisioned rootfs or pre-avocado-cli image)
+                    }
+                }
+            }
+        }
     }
+
+    merge_extensions_into(config, None, output)
 }
 
-/// Invalidate NFS caches for HITL-mounted extensions
-///
-/// When extensions are mounted via NFS from a HITL server, the client may have
-/// stale cached data after the host rebuilds the extension. This function forces
-/// a remount of each HITL mount to invalidate the NFS client cache, ensuring
-/// fresh data is fetched from the server on the next access.
-pub(crate) fn invalidate_hitl_caches(output: &OutputManager) {
-    let hitl_dir = std::path::Path::new("/run/avocado/hitl");
+/// In container mode only configuration extensions are merged: `/usr`
+/// belongs to the container image.
+fn skip_sysext(operation: &str, output: &OutputManager) -> bool {
+    if !crate::container::is_container() {
+        return false;
+    }
+    output.log_info(&format!(
+        "Container mode: skipping systemd-sysext {operation}"
+    ));
+    true
+}
 
-    // Skip if not in test mode and no HITL directory exists
-    if std::env::var("AVOCADO_TEST_MODE").is_err() && !hitl_dir.exists() {
-        return;
+/// Link the enabled extensions and merge them on the host, or into `target`
+pub(crate) fn merge_extensions_into(
+    config: &Config,
+    target: Option<&MergeTarget>,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    let span = crate::telemetry::operation("merge", output);
+    if let Some(target) = target {
+        span.set_attribute("avocado.merge.target", target.to_string());
     }
+    span.record(run_merge(config, target, output))
+}
 
-    // In test mode, use the test directory
-    let hitl_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
-            .or_else(|_| std::env::var("TMPDIR"))
-            .unwrap_or_else(|_| "/tmp".to_string());
-        std::path::PathBuf::from(format!("{temp_base}/avocado/hitl"))
-    } else {
-        hitl_dir.to_path_buf()
+/// The merge pipeline behind [`merge_extensions_into`].
+fn run_merge(
+    config: &Config,
+    target: Option<&MergeTarget>,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    let environment_info = match target {
+        Some(target) => target.to_string(),
+        None if is_running_in_initrd() => "initrd environment".to_string(),
+        None => "system environment".to_string(),
     };
+    output.info(
+        "Extension Merge",
+        &format!("Starting extension merge process in {environment_info}"),
+    );
 
-    if !hitl_dir.exists() {
-        return;
+    if target.is_none() {
+        crate::merge_inputs::MergeInputs::clear();
     }
 
-    let entries = match std::fs::read_dir(&hitl_dir) {
-        Ok(entries) => entries,
-        Err(_) => return,
-    };
+    crate::phases::reset();
 
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if path.is_dir() {
-            let extension_name = path
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown");
+    // Keep writers away from the images until they are merged
+    let protected = crate::source_guard::protect(config, output)?;
 
-            output.step(
-                "HITL",
-                &format!("Invalidating NFS cache for extension: {extension_name}"),
+    // Prepare the environment by setting up symlinks and get the list of enabled extensions
+    let scanning = crate::phases::enter(Phase::Scanning);
+    let enabled_extensions = prepare_extension_environment_with_output(config, output)?;
+    drop(scanning);
+
+    // Get the mutability settings from config (separate for sysext and confext)
+    let sysext_mutability = match config.get_sysext_mutable() {
+        Ok(value) => value,
+        Err(e) => {
+            output.error_with(
+                "Configuration Error",
+                &format!("Invalid sysext mutable configuration: {e}"),
+                &e.diagnose(),
             );
+            return Err(SystemdError::ConfigurationError {
+                message: e.to_string(),
+            });
+        }
+    };
+    let sysext_mutable_arg = format!("--mutable={sysext_mutability}");
 
-            // Skip actual remount in test mode
-            if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-                output.progress(&format!(
-                    "Skipping remount in test mode for: {}",
-                    path.display()
-                ));
-                continue;
-            }
+    let confext_mutability = match config.get_confext_mutable() {
+        Ok(value) => value,
+        Err(e) => {
+            output.error_with(
+                "Configuration Error",
+                &format!("Invalid confext mutable configuration: {e}"),
+                &e.diagnose(),
+            );
+            return Err(SystemdError::ConfigurationError {
+                message: e.to_string(),
+            });
+        }
+    };
+    let confext_mutable_arg = format!("--mutable={confext_mutability}");
 
-            // Remount to invalidate NFS client cache
-            let result = std::process::Command::new("mount")
-                .args(["-o", "remount"])
-                .arg(&path)
-                .output();
+    let caps = crate::systemd_caps::detect();
+    let version = caps
+        .version
+        .map_or_else(|| "unknown".to_string(), |v| v.to_string());
+    if !caps.mutable && (sysext_mutability != "no" || confext_mutability != "no") {
+        output.info(
+            "Extension Merge",
+            &format!("systemd {version} does not support --mutable; merging read-only"),
+        );
+    }
 
-            match result {
-                Ok(output_result) => {
-                    if !output_result.status.success() {
-                        let stderr = String::from_utf8_lossy(&output_result.stderr);
-                        output.progress(&format!(
-                            "Warning: Failed to remount {}: {}",
-                            path.display(),
-                            stderr.trim()
-                        ));
-                    }
-                }
-                Err(e) => {
-                    output.progress(&format!(
-                        "Warning: Could not execute remount for {}: {}",
-                        path.display(),
-                        e
-                    ));
-                }
-            }
-        }
+    if let Some(target) = target {
+        publish_links_to_target(target, output)?;
     }
-}
 
-/// Refresh extensions (unmerge then merge)
-pub fn refresh_extensions(config: &Config, output: &OutputManager) {
-    let environment_info = if is_running_in_initrd() {
-        "initrd environment"
-    } else {
-        "system environment"
-    };
-    output.info(
-        "Extension Refresh",
-        &format!("Starting extension refresh process in {environment_info}"),
-    );
+    // On the host the daemon-reload happens after post-merge tasks below, so
+    // systemd does not need to reload on its own before depmod/ldconfig have
+    // run; inside a target systemd reloads its own units
+    let merging = crate::phases::enter(Phase::Merging);
+    let no_reload = target.is_none().then_some("--no-reload");
+    let sysext_args: Vec<&str> = ["merge", sysext_mutable_arg.as_str()]
+        .into_iter()
+        .chain(no_reload)
+        .chain(["--json=short"])
+        .collect();
+    if !skip_sysext("merge", output) {
+        let sysext_result =
+            run_systemd_command_in(target, "systemd-sysext", &sysext_args, Some(output))?;
+        handle_systemd_output("systemd-sysext merge", &sysext_result, output)?;
+    }
+    if crate::fault::fail_at(FailPoint::AfterMerge) {
+        return Err(crate::fault::injected(FailPoint::AfterMerge));
+    }
 
-    // First unmerge (skip depmod since we'll call it after merge, don't unmount loops —
-    // the caller may be running from a loop-mounted extension like avocado-connect)
-    if let Err(e) = unmerge_extensions_internal_with_options(false, false, output) {
-        output.error(
-            "Extension Refresh",
-            &format!("Failed to unmerge extensions: {e}"),
+    // Merge configuration extensions
+    if caps.confext {
+        let confext_args: Vec<&str> = ["merge", confext_mutable_arg.as_str()]
+            .into_iter()
+            .chain(no_reload)
+            .chain(["--json=short"])
+            .collect();
+        let confext_result =
+            run_systemd_command_in(target, "systemd-confext", &confext_args, Some(output))?;
+        handle_systemd_output("systemd-confext merge", &confext_result, output)?;
+    } else {
+        output.info(
+            "Extension Merge",
+            &format!("systemd {version} has no systemd-confext; skipping configuration extensions"),
         );
-        std::process::exit(1);
     }
-    output.step("Refresh", "Extensions unmerged");
-
-    // Invalidate NFS caches for any HITL-mounted extensions
-    // This ensures fresh data is fetched from the server after a host rebuild
-    invalidate_hitl_caches(output);
+    drop(merging);
+    drop(protected);
 
-    // Then merge (this will call depmod via post-merge processing)
-    if let Err(e) = merge_extensions_internal(config, output) {
-        output.error(
-            "Extension Refresh",
-            &format!("Failed to merge extensions: {e}"),
+    if let Some(target) = target {
+        output.info(
+            "Extension Merge",
+            &format!("Merged into {target}: skipping host module loading, on-merge commands and daemon-reload"),
         );
-        std::process::exit(1);
+        return Ok(());
     }
-    output.step("Refresh", "Extensions merged");
+    // Merged with --no-reload: systemd reloads once the post-merge tasks
+    // need it
+    crate::daemon_reload::request(output);
 
-    output.success("Extension Refresh", "Extensions refreshed successfully");
-}
+    if crate::fault::fail_at(FailPoint::BeforeHooks) {
+        return Err(crate::fault::injected(FailPoint::BeforeHooks));
+    }
 
-/// Show status of merged extensions
-pub fn status_extensions(config: &Config, output: &OutputManager) {
-    match show_enhanced_status(config, output) {
-        Ok(_) => {}
-        Err(e) => {
-            if output.is_json() {
-                println!(
-                    "{}",
-                    serde_json::json!({"error": format!("Failed to show status: {e}")})
-                );
-                return;
-            }
-            output.error("Extension Status", &format!("Failed to show status: {e}"));
-            show_legacy_status(output);
-        }
+    // Process post-merge tasks for enabled extensions, with daemon-reload
+    // happening after depmod/ldconfig/modprobe but before service commands.
+    // This ensures kernel modules and shared libraries are available when
+    // systemd re-evaluates units during daemon-reload.
+    let hooks = crate::phases::enter(Phase::Hooks);
+    process_post_merge_tasks_for_extensions(&enabled_extensions, output)?;
+    drop(hooks);
+    report_phase_timings(output);
+
+    // Record (and optionally act on) AVOCADO_REBOOT_REQUIRED requests last,
+    // once everything else about the merge has succeeded.
+    handle_reboot_requests(&enabled_extensions, config, output);
+
+    let inputs = merge_inputs(&enabled_extensions, config);
+    if let Err(e) = inputs.save() {
+        output.progress(&format!("Warning: Failed to record merge inputs: {e}"));
     }
+    record_merge_history("merge", &enabled_extensions, &inputs, output);
+    record_module_extensions(&enabled_extensions, output);
+
+    Ok(())
 }
 
-/// Collect extension status data for the varlink Status RPC.
-///
-/// This gathers the same data as `show_enhanced_status` but returns it as
-/// structured `ExtensionStatus` values instead of printing to stdout.
-pub(crate) fn collect_extension_status(
+/// Unmerge extensions using systemd-sysext and systemd-confext
+pub fn unmerge_extensions(unmount: bool, output: &OutputManager) {
+    match unmerge_extensions_internal(unmount, output) {
+        Ok(_) => {
+            output.success_msg("Extension Unmerge", messages::EXT_UNMERGED, &[]);
+        }
+        Err(e) => {
+            output.error_with(
+                "Extension Unmerge",
+                &format!("Failed to unmerge extensions: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    }
+}
+
+/// Internal unmerge function that returns a Result for use in refresh
+fn unmerge_extensions_internal(unmount: bool, output: &OutputManager) -> Result<(), SystemdError> {
+    unmerge_extensions_internal_with_depmod(true, unmount, output)
+}
+
+/// Internal unmerge function with optional depmod control
+fn unmerge_extensions_internal_with_depmod(
+    call_depmod: bool,
+    unmount: bool,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    unmerge_extensions_internal_with_options(call_depmod, unmount, output)
+}
+
+/// Internal unmerge function with all options
+pub(crate) fn unmerge_extensions_internal_with_options(
+    call_depmod: bool,
+    unmount: bool,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    let span = crate::telemetry::operation("unmerge", output);
+    span.record(run_unmerge(call_depmod, unmount, output))
+}
+
+/// The unmerge steps behind [`unmerge_extensions_internal_with_options`].
+fn run_unmerge(
+    call_depmod: bool,
+    unmount: bool,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    crate::systemd_runtime::require("unmerge")?;
+    crate::commands::hitl::remove_orphaned_dropins(output);
+
+    let environment_info = if is_running_in_initrd() {
+        "initrd environment"
+    } else {
+        "system environment"
+    };
+    output.info(
+        "Extension Unmerge",
+        &format!("Starting extension unmerge process in {environment_info}"),
+    );
+
+    crate::merge_inputs::MergeInputs::clear();
+
+    // Execute AVOCADO_ON_UNMERGE commands before unmerging extensions
+    // These commands are executed while extensions are still merged
+    if let Err(e) = process_pre_unmerge_tasks(output) {
+        output.progress(&format!(
+            "Warning: Failed to process pre-unmerge tasks: {e}"
+        ));
+        // Continue with unmerge even if pre-unmerge tasks fail
+    }
+
+    // Unmerge system extensions
+    if !skip_sysext("unmerge", output) {
+        let sysext_result = run_systemd_command(
+            "systemd-sysext",
+            &["unmerge", "--no-reload", "--json=short"],
+            Some(output),
+        )?;
+        handle_systemd_output("systemd-sysext unmerge", &sysext_result, output)?;
+    }
+
+    // Unmerge configuration extensions
+    let confext_result = run_systemd_command(
+        "systemd-confext",
+        &["unmerge", "--no-reload", "--json=short"],
+        Some(output),
+    )?;
+    handle_systemd_output("systemd-confext unmerge", &confext_result, output)?;
+    // Reloaded by the merge that usually follows, or when the command ends
+    crate::daemon_reload::request(output);
+
+    // Clean up extension-release bind mounts and staging directories
+    // Must happen after systemd unmerge but before loop unmount
+    cleanup_extension_release_staging(output)?;
+
+    // Clean up all symlinks to ensure fresh state for next merge
+    cleanup_extension_symlinks(output)?;
+
+    // Blacklists only apply while their extension is merged
+    remove_modprobe_blacklists(output);
+
+    // Run depmod after unmerge if requested and the merged set had modules
+    if call_depmod && !crate::container::is_container() {
+        match crate::kernel_modules::recorded() {
+            Some(with_modules) if with_modules.is_empty() => {
+                output.log_info(
+                    "No extension merged since the last depmod shipped kernel modules; skipping depmod",
+                );
+            }
+            _ => run_depmod(output)?,
+        }
+        // The module tree is back to the base image's
+        if let Err(e) = crate::kernel_modules::record(&[]) {
+            output.progress(&format!(
+                "Warning: Failed to record extensions with kernel modules: {e}"
+            ));
+        }
+    }
+
+    // Unmount persistent loops if requested
+    if unmount {
+        unmount_all_persistent_mounts()?;
+    }
+
+    if let Err(e) = crate::merge_history::record("unmerge", Vec::new()) {
+        output.progress(&format!("Warning: Failed to record merge history: {e}"));
+    }
+
+    Ok(())
+}
+
+/// Direct access functions for top-level command aliases
+///
+/// Merge extensions - direct access for top-level alias
+pub fn merge_extensions_direct(config: &Config, keep_going: bool, output: &OutputManager) {
+    let config = with_keep_going(config, keep_going);
+    merge_extensions(&config, output);
+}
+
+/// Unmerge extensions - direct access for top-level alias
+pub fn unmerge_extensions_direct(unmount: bool, output: &OutputManager) {
+    unmerge_extensions(unmount, output);
+}
+
+/// Refresh extensions - direct access for top-level alias
+pub fn refresh_extensions_direct(
     config: &Config,
-) -> Result<Vec<crate::varlink::org_avocado_Extensions::ExtensionStatus>, SystemdError> {
-    use crate::varlink::org_avocado_Extensions::ExtensionStatus;
+    force: bool,
+    keep_going: bool,
+    output: &OutputManager,
+) {
+    let config = with_keep_going(config, keep_going);
+    if force {
+        refresh_extensions(&config, output);
+    } else {
+        refresh_changed_extensions(&config, output);
+    }
+}
+
+/// Soft-reboot refresh - direct access for top-level alias
+pub fn soft_reboot_refresh_direct(config: &Config, output: &OutputManager) {
+    soft_reboot_refresh(config, output);
+}
 
+/// Enable extensions for a specific OS release version
+pub fn enable_extensions(
+    os_release_version: Option<&str>,
+    extensions: &[&str],
+    force: bool,
+    config: &Config,
+    output: &OutputManager,
+) {
+    // Warn if an active runtime manifest is present
     let base_dir = config.get_avocado_base_dir();
-    let base_path = std::path::Path::new(&base_dir);
-    let active_manifest = crate::manifest::RuntimeManifest::load_active(base_path);
-    let manifest_extensions = active_manifest
-        .as_ref()
-        .map(|m| m.extensions.as_slice())
-        .unwrap_or(&[]);
+    if crate::manifest::RuntimeManifest::load_active(std::path::Path::new(&base_dir)).is_some() {
+        output.warning("An active runtime manifest is present. The manifest takes precedence over symlink-based extension discovery during merge/refresh.");
+    }
 
-    let available_extensions = scan_extensions_from_all_sources_with_verbosity(false)?;
-    let mounted_sysext = get_mounted_systemd_extensions("systemd-sysext")?;
-    let mounted_confext = get_mounted_systemd_extensions("systemd-confext")?;
+    // Determine the OS release version to use
+    let version_id = if let Some(version) = os_release_version {
+        version.to_string()
+    } else {
+        read_os_version_id()
+    };
 
-    // Collect all unique extension names (with versions if present)
-    let mut all_names = std::collections::HashSet::new();
-    for ext in &available_extensions {
-        if let Some(ver) = &ext.version {
-            all_names.insert(format!("{}-{}", ext.name, ver));
+    output.info(
+        "Enable Extensions",
+        &format!("Enabling extensions for OS release version: {version_id}"),
+    );
+
+    // Get the extensions directory from config
+    let extensions_dir = config.get_extensions_dir();
+
+    // Determine os-releases directory based on test mode
+    let os_releases_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/os-releases/{version_id}")
+    } else {
+        crate::user_mode::system_path(&format!("/var/lib/avocado/os-releases/{version_id}"))
+    };
+
+    // Create the os-releases directory if it doesn't exist
+    if let Err(e) = fs::create_dir_all(&os_releases_dir) {
+        output.error_with(
+            "Enable Extensions",
+            &format!("Failed to create os-releases directory '{os_releases_dir}': {e}"),
+            &e.diagnose(),
+        );
+        output.exit(1);
+    }
+
+    // Sync the parent directory to ensure the os-releases directory is persisted
+    if let Err(e) = sync_directory(
+        Path::new(&os_releases_dir)
+            .parent()
+            .unwrap_or(Path::new("/")),
+    ) {
+        output.progress(&format!("Warning: Failed to sync parent directory: {e}"));
+    }
+
+    output.step(
+        "Enable",
+        &format!("Created os-releases directory: {os_releases_dir}"),
+    );
+
+    // Process each extension
+    let mut success_count = 0;
+    let mut error_count = 0;
+
+    // Resolve names, absolute paths and glob patterns to extension sources
+    let mut targets = Vec::new();
+    for arg in extensions {
+        match resolve_enable_targets(arg, &extensions_dir) {
+            Ok(matched) => {
+                if is_glob_pattern(arg) {
+                    let names: Vec<&str> = matched.iter().map(|t| t.name.as_str()).collect();
+                    output.progress(&format!(
+                        "Pattern '{arg}' matched {} extension(s): {}",
+                        names.len(),
+                        names.join(", ")
+                    ));
+                }
+                targets.extend(matched);
+            }
+            Err(message) => {
+                output.error("Enable Extensions", &message);
+                error_count += 1;
+            }
+        }
+    }
+
+    let mut links = Vec::new();
+    for EnableTarget {
+        name: ext_name,
+        source_path,
+    } in &targets
+    {
+        // Reject extensions systemd would refuse to merge for this release,
+        // and whatever else the strictness policy makes fatal
+        let validation = validate_enable_target(
+            ext_name,
+            Path::new(source_path),
+            &version_id,
+            config,
+            output.is_verbose(),
+        );
+        if let Some(reason) = &validation.unverified {
+            output.progress(&format!(
+                "Warning: could not verify os-release compatibility of '{ext_name}': {reason}"
+            ));
+        }
+        let mut rejected = false;
+        for problem in &validation.problems {
+            let ValidationProblem { check, message } = problem;
+            if problem.is_fatal(config, force) {
+                let message = match check {
+                    ValidationCheck::Scope => format!(
+                        "Extension '{ext_name}' is not compatible with OS release {version_id}: {message} (use --force to enable anyway)"
+                    ),
+                    check => format!(
+                        "Extension '{ext_name}' failed the {} check: {message} (use --force to enable anyway)",
+                        check.key()
+                    ),
+                };
+                output.error("Enable Extensions", &message);
+                rejected = true;
+            } else if *check == ValidationCheck::Scope {
+                output.progress(&format!(
+                    "Warning: enabling incompatible extension '{ext_name}': {message}"
+                ));
+            } else {
+                output.progress(&format!("Warning: '{ext_name}': {message}"));
+            }
+        }
+        if rejected {
+            error_count += 1;
+            continue;
+        }
+
+        // Symlink in the os-releases directory, replacing an existing one
+        links.push((
+            ext_name.as_str(),
+            TransactionStep::Link {
+                file_name: Path::new(source_path)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+                source: source_path.clone(),
+            },
+        ));
+    }
+
+    let journal = begin_link_journal("enable", &os_releases_dir, &links, output);
+    for (ext_name, step) in &links {
+        if let Err(e) = crate::link_journal::apply_step(Path::new(&os_releases_dir), step) {
+            output.error_with(
+                "Enable Extensions",
+                &format!("Failed to create symlink for '{ext_name}': {e}"),
+                &e.diagnose(),
+            );
+            error_count += 1;
         } else {
-            all_names.insert(ext.name.clone());
+            output.progress(&format!("Enabled extension: {ext_name}"));
+            success_count += 1;
         }
     }
-    for ext in &mounted_sysext {
-        all_names.insert(ext.name.clone());
+
+    // Sync the os-releases directory to ensure all symlinks are persisted to disk
+    if success_count > 0 {
+        if let Err(e) = sync_directory(Path::new(&os_releases_dir)) {
+            output.error_with(
+                "Enable Extensions",
+                &format!("Failed to sync os-releases directory to disk: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+        output.progress("Synced changes to disk");
     }
-    for ext in &mounted_confext {
-        all_names.insert(ext.name.clone());
+    commit_link_journal(journal, output);
+
+    // Summary
+    output.result("enabled", &success_count);
+    output.result("failed", &error_count);
+    output.result("version_id", &version_id);
+    if error_count > 0 {
+        output.error(
+            "Enable Extensions",
+            &format!("Completed with errors: {success_count} succeeded, {error_count} failed"),
+        );
+        output.exit(1);
+    } else {
+        output.success_msg(
+            "Enable Extensions",
+            messages::EXT_ENABLED,
+            &[
+                ("count", &success_count.to_string()),
+                ("version_id", &version_id),
+            ],
+        );
     }
+}
 
-    let mut result: Vec<ExtensionStatus> = all_names
-        .into_iter()
-        .map(|ext_name| {
-            let available_ext = available_extensions.iter().find(|e| {
-                if let Some(ver) = &e.version {
-                    format!("{}-{}", e.name, ver) == ext_name
-                } else {
-                    e.name == ext_name
-                }
-            });
+/// An extension selected by an `enable` argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EnableTarget {
+    /// Extension name for reporting (file name without `.raw`/`.tar.zst`)
+    pub(crate) name: String,
+    /// Directory, image or archive the os-releases symlink points to
+    pub(crate) source_path: String,
+}
 
-            let is_sysext_mounted = mounted_sysext.iter().any(|e| e.name == ext_name);
-            let is_confext_mounted = mounted_confext.iter().any(|e| e.name == ext_name);
-            let is_merged = is_sysext_mounted || is_confext_mounted;
+/// Whether an `enable` argument is a glob pattern (`*`, `?` or `[...]`).
+pub(crate) fn is_glob_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
 
-            let (is_sysext, is_confext) = if let Some(ext) = available_ext {
-                (ext.is_sysext, ext.is_confext)
+/// Match `text` against a shell-style pattern supporting `*`, `?` and
+/// `[...]` character classes (with `!` or `^` negation and `a-z` ranges).
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+
+    // Match a character class starting at p[i] == '['; returns whether `c`
+    // matched and the index just past the closing ']'.
+    let class = |i: usize, c: char| -> Option<(bool, usize)> {
+        let mut j = i + 1;
+        let negate = matches!(p.get(j), Some('!') | Some('^'));
+        if negate {
+            j += 1;
+        }
+        let mut matched = false;
+        let mut first = true;
+        while let Some(&pc) = p.get(j) {
+            if pc == ']' && !first {
+                return Some((matched != negate, j + 1));
+            }
+            if p.get(j + 1) == Some(&'-') && p.get(j + 2).is_some_and(|&e| e != ']') {
+                matched |= (pc..=p[j + 2]).contains(&c);
+                j += 3;
             } else {
-                (is_sysext_mounted, is_confext_mounted)
-            };
+                matched |= pc == c;
+                j += 1;
+            }
+            first = false;
+        }
+        None
+    };
 
-            let origin = available_ext.map(get_extension_origin_short);
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        let step = match p.get(pi) {
+            Some('*') => {
+                backtrack = Some((pi, ti));
+                pi += 1;
+                continue;
+            }
+            Some('?') => Some(pi + 1),
+            Some('[') => match class(pi, t[ti]) {
+                Some((true, next)) => Some(next),
+                Some((false, _)) => None,
+                None => (t[ti] == '[').then_some(pi + 1),
+            },
+            Some(&c) => (c == t[ti]).then_some(pi + 1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(next), _) => {
+                pi = next;
+                ti += 1;
+            }
+            (None, Some((star, start))) => {
+                pi = star + 1;
+                ti = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
 
-            let image_id_str = lookup_extension_short_id(&ext_name, manifest_extensions);
-            let image_id = if image_id_str == "-" {
-                None
-            } else {
-                Some(image_id_str)
-            };
+/// Extension name for an entry in the extensions directory.
+pub(crate) fn enable_target_name(file_name: &str) -> String {
+    crate::archive::archive_stem(file_name)
+        .or_else(|| file_name.strip_suffix(".raw"))
+        .unwrap_or(file_name)
+        .to_string()
+}
 
-            let (name, version) = if let Some(ext) = available_ext {
-                (ext.name.clone(), ext.version.clone())
-            } else {
-                (ext_name, None)
-            };
+/// Resolve one `enable` argument:
+/// - an absolute path is used as-is (for images outside the extensions dir),
+/// - a glob pattern selects every matching extension in `extensions_dir`,
+///   compared against both the extension name and the file name,
+/// - anything else is an extension name, tried as a directory, `.raw` image
+///   and `.tar.zst` archive in that order.
+pub(crate) fn resolve_enable_targets(
+    arg: &str,
+    extensions_dir: &str,
+) -> Result<Vec<EnableTarget>, String> {
+    if Path::new(arg).is_absolute() {
+        let path = Path::new(arg);
+        if !path.exists() {
+            return Err(format!("Extension path '{arg}' does not exist"));
+        }
+        let file_name = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        return Ok(vec![EnableTarget {
+            name: enable_target_name(&file_name),
+            source_path: arg.to_string(),
+        }]);
+    }
+
+    if is_glob_pattern(arg) {
+        let mut file_names: Vec<String> = fs::read_dir(extensions_dir)
+            .map_err(|e| format!("Failed to read extensions directory '{extensions_dir}': {e}"))?
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        file_names.sort();
+        let matched: Vec<EnableTarget> = file_names
+            .into_iter()
+            .filter_map(|file_name| {
+                let name = enable_target_name(&file_name);
+                let is_extension =
+                    Path::new(extensions_dir).join(&file_name).is_dir() || name != file_name;
+                (is_extension && (glob_match(arg, &name) || glob_match(arg, &file_name))).then(
+                    || EnableTarget {
+                        name,
+                        source_path: format!("{extensions_dir}/{file_name}"),
+                    },
+                )
+            })
+            .collect();
+        if matched.is_empty() {
+            return Err(format!(
+                "Pattern '{arg}' matched no extensions in {extensions_dir}"
+            ));
+        }
+        return Ok(matched);
+    }
 
-            ExtensionStatus {
-                name,
-                version,
-                isSysext: is_sysext,
-                isConfext: is_confext,
-                isMerged: is_merged,
-                origin,
-                imageId: image_id,
-                imageType: available_ext.and_then(|e| match e.image_type {
-                    ImageTypeTag::Kab => Some("kab".to_string()),
-                    _ => None,
-                }),
-            }
+    let candidates = [
+        format!("{extensions_dir}/{arg}"),
+        format!("{extensions_dir}/{arg}.raw"),
+        format!("{extensions_dir}/{arg}{}", crate::archive::ARCHIVE_SUFFIX),
+    ];
+    candidates
+        .into_iter()
+        .find(|p| Path::new(p).exists())
+        .map(|source_path| {
+            vec![EnableTarget {
+                name: arg.to_string(),
+                source_path,
+            }]
         })
-        .collect();
+        .ok_or_else(|| format!("Extension '{arg}' not found in {extensions_dir}"))
+}
 
-    // Sort descending by merge_index (highest priority / top layer first).
-    // Extensions without a merge_index sort to the bottom, then alphabetically.
-    result.sort_by(|a, b| {
-        let versioned_a = match &a.version {
-            Some(v) => format!("{}-{}", a.name, v),
-            None => a.name.clone(),
-        };
-        let versioned_b = match &b.version {
-            Some(v) => format!("{}-{}", b.name, v),
-            None => b.name.clone(),
-        };
-        let idx_a = available_extensions
-            .iter()
-            .find(|e| {
-                if let Some(ver) = &e.version {
-                    format!("{}-{}", e.name, ver) == versioned_a
-                } else {
-                    e.name == versioned_a
-                }
-            })
-            .and_then(|e| e.merge_index);
-        let idx_b = available_extensions
-            .iter()
-            .find(|e| {
-                if let Some(ver) = &e.version {
-                    format!("{}-{}", e.name, ver) == versioned_b
-                } else {
-                    e.name == versioned_b
-                }
-            })
-            .and_then(|e| e.merge_index);
-        idx_b.cmp(&idx_a).then_with(|| a.name.cmp(&b.name))
-    });
+/// Journal the link changes `links` is about to make in `os_releases_dir`
+/// (see [`crate::link_journal`]); exits when the journal cannot be written.
+fn begin_link_journal(
+    operation: &str,
+    os_releases_dir: &str,
+    links: &[(&str, TransactionStep)],
+    output: &OutputManager,
+) -> Option<crate::link_journal::PendingJournal> {
+    if links.is_empty() {
+        return None;
+    }
+    let steps: Vec<TransactionStep> = links.iter().map(|(_, step)| step.clone()).collect();
+    match crate::link_journal::begin(
+        &crate::link_journal::state_dir(),
+        operation,
+        os_releases_dir,
+        &steps,
+    ) {
+        Ok(journal) => Some(journal),
+        Err(e) => {
+            output.error_with(
+                "Link Journal",
+                &format!("Failed to write the link journal: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    }
+}
 
-    Ok(result)
+fn commit_link_journal(
+    journal: Option<crate::link_journal::PendingJournal>,
+    output: &OutputManager,
+) {
+    if let Some(Err(e)) = journal.map(|journal| journal.commit()) {
+        output.progress(&format!("Warning: failed to remove the link journal: {e}"));
+    }
 }
 
-/// Show enhanced status with extension origins and HITL information
-pub(crate) fn show_enhanced_status(
+/// Sync a directory to ensure all changes are persisted to disk
+pub(crate) fn sync_directory(dir_path: &Path) -> Result<(), SystemdError> {
+    // Open the directory
+    let dir = fs::File::open(dir_path).context("open directory", dir_path)?;
+
+    // Sync the directory to disk
+    // This ensures directory entries (like new symlinks) are persisted
+    dir.sync_all().context("sync directory", dir_path)?;
+
+    Ok(())
+}
+
+/// Disable extensions for a specific OS release version
+pub fn disable_extensions(
+    os_release_version: Option<&str>,
+    extensions: Option<&[&str]>,
+    all: bool,
     config: &Config,
     output: &OutputManager,
-) -> Result<(), SystemdError> {
-    // Load active manifest
+) {
+    // Warn if an active runtime manifest is present
     let base_dir = config.get_avocado_base_dir();
-    let base_path = std::path::Path::new(&base_dir);
-    let active_manifest = crate::manifest::RuntimeManifest::load_active(base_path);
-    let manifest_extensions = active_manifest
-        .as_ref()
-        .map(|m| m.extensions.as_slice())
-        .unwrap_or(&[]);
+    if crate::manifest::RuntimeManifest::load_active(std::path::Path::new(&base_dir)).is_some() {
+        output.warning("An active runtime manifest is present. The manifest takes precedence over symlink-based extension discovery during merge/refresh.");
+    }
 
-    // Get our view of available extensions
-    let available_extensions =
-        scan_extensions_from_all_sources_with_verbosity(output.is_verbose())?;
+    // Determine the OS release version to use
+    let version_id = if let Some(version) = os_release_version {
+        version.to_string()
+    } else {
+        read_os_version_id()
+    };
 
-    // Get systemd's view of mounted extensions
-    let mounted_sysext = get_mounted_systemd_extensions("systemd-sysext")?;
-    let mounted_confext = get_mounted_systemd_extensions("systemd-confext")?;
+    output.info(
+        "Disable Extensions",
+        &format!("Disabling extensions for OS release version: {version_id}"),
+    );
 
-    if output.is_json() {
-        let runtime_json = match &active_manifest {
-            Some(m) => {
-                let mut rj = serde_json::json!({
-                    "name": m.runtime.name,
-                    "version": m.runtime.version,
-                    "id": m.id,
-                    "built_at": m.built_at,
-                    "manifest_version": m.manifest_version,
-                });
-                if let Some(ref os_bundle) = m.os_bundle {
-                    rj["os_bundle"] = serde_json::json!({
-                        "image_id": os_bundle.image_id,
-                        "sha256": os_bundle.sha256,
-                        "os_build_id": os_bundle.os_build_id,
-                        "initramfs_build_id": os_bundle.initramfs_build_id,
-                    });
+    // Determine os-releases directory based on test mode
+    let os_releases_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/os-releases/{version_id}")
+    } else {
+        crate::user_mode::system_path(&format!("/var/lib/avocado/os-releases/{version_id}"))
+    };
+
+    // Check if os-releases directory exists
+    if !Path::new(&os_releases_dir).exists() {
+        output.error(
+            "Disable Extensions",
+            &format!("OS releases directory '{os_releases_dir}' does not exist"),
+        );
+        output.exit(1);
+    }
+
+    let mut success_count = 0;
+    let mut error_count = 0;
+
+    // The symlinks to remove, each with the extension it disables
+    let mut links: Vec<(String, TransactionStep)> = Vec::new();
+    let unlink = |file_name: &str| TransactionStep::Unlink {
+        file_name: file_name.to_string(),
+    };
+    if all {
+        // Disable all extensions by removing all symlinks in the os-releases directory
+        output.step("Disable", "Removing all extensions");
+
+        match read_dir_sorted(&os_releases_dir) {
+            Ok(entries) => {
+                for entry in entries {
+                    let path = entry.path();
+                    // Only remove symlinks, not regular files or directories
+                    if !path.is_symlink() {
+                        continue;
+                    }
+                    let Some(name_str) = path.file_name().and_then(|n| n.to_str()) else {
+                        continue;
+                    };
+                    links.push((name_str.to_string(), unlink(name_str)));
+                }
+            }
+            Err(e) => {
+                output.error_with(
+                    "Disable Extensions",
+                    &format!("Failed to read os-releases directory '{os_releases_dir}': {e}"),
+                    &e.diagnose(),
+                );
+                output.exit(1);
+            }
+        }
+    } else if let Some(ext_names) = extensions {
+        // Disable specific extensions: directory, .raw and archive symlinks
+        for ext_name in ext_names {
+            let mut found = false;
+            for file_name in [
+                ext_name.to_string(),
+                format!("{ext_name}.raw"),
+                format!("{ext_name}{}", crate::archive::ARCHIVE_SUFFIX),
+            ] {
+                if Path::new(&os_releases_dir).join(&file_name).exists() {
+                    links.push((ext_name.to_string(), unlink(&file_name)));
+                    found = true;
                 }
-                rj
             }
-            None => serde_json::Value::Null,
-        };
 
-        let extensions_json: Vec<serde_json::Value> = build_extension_json_list(
-            &available_extensions,
-            &mounted_sysext,
-            &mounted_confext,
-            manifest_extensions,
+            if !found {
+                output.error(
+                    "Disable Extensions",
+                    &format!("Extension '{ext_name}' is not enabled for OS release {version_id}"),
+                );
+                error_count += 1;
+            }
+        }
+    } else {
+        // This should not happen due to clap validation, but handle it anyway
+        output.error(
+            "Disable Extensions",
+            "No extensions specified. Use --all to disable all extensions or specify extension names.",
         );
+        output.exit(1);
+    }
+
+    let labeled: Vec<(&str, TransactionStep)> = links
+        .iter()
+        .map(|(name, step)| (name.as_str(), step.clone()))
+        .collect();
+    let journal = begin_link_journal("disable", &os_releases_dir, &labeled, output);
+    let mut failed_names = Vec::new();
+    for (index, (ext_name, step)) in labeled.iter().enumerate() {
+        if let Err(e) = crate::link_journal::apply_step(Path::new(&os_releases_dir), step) {
+            output.error_with(
+                "Disable Extensions",
+                &format!("Failed to remove symlink for '{ext_name}': {e}"),
+                &e.diagnose(),
+            );
+            error_count += 1;
+            failed_names.push(*ext_name);
+        }
+        // Report each extension once, after its last symlink
+        let last_of_extension = labeled
+            .get(index + 1)
+            .is_none_or(|(next, _)| next != ext_name);
+        if last_of_extension && !failed_names.contains(ext_name) {
+            output.progress(&format!("Disabled extension: {ext_name}"));
+            success_count += 1;
+        }
+    }
+
+    // Sync the os-releases directory to ensure all removals are persisted to disk
+    if success_count > 0 {
+        if let Err(e) = sync_directory(Path::new(&os_releases_dir)) {
+            output.error_with(
+                "Disable Extensions",
+                &format!("Failed to sync os-releases directory to disk: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+        output.progress("Synced changes to disk");
+    }
+    commit_link_journal(journal, output);
 
-        let status_json = serde_json::json!({
-            "runtime": runtime_json,
-            "extensions": extensions_json,
-        });
-        println!("{}", serde_json::to_string_pretty(&status_json).unwrap());
-        return Ok(());
+    // Summary
+    output.result("disabled", &success_count);
+    output.result("failed", &error_count);
+    output.result("version_id", &version_id);
+    if error_count > 0 {
+        output.error(
+            "Disable Extensions",
+            &format!("Completed with errors: {success_count} succeeded, {error_count} failed"),
+        );
+        output.exit(1);
+    } else {
+        output.success_msg(
+            "Disable Extensions",
+            messages::EXT_DISABLED,
+            &[
+                ("count", &success_count.to_string()),
+                ("version_id", &version_id),
+            ],
+        );
     }
+}
 
-    output.status_header("Avocado Extension Status");
+/// Invalidate NFS caches for HITL-mounted extensions
+///
+/// When extensions are mounted via NFS from a HITL server, the client may have
+/// stale cached data after the host rebuilds the extension. This function forces
+/// a remount of each HITL mount to invalidate the NFS client cache, ensuring
+/// fresh data is fetched from the server on the next access.
+pub(crate) fn invalidate_hitl_caches(output: &OutputManager) {
+    let hitl_dir = crate::user_mode::system_path("/run/avocado/hitl");
+    let hitl_dir = std::path::Path::new(&hitl_dir);
 
-    // Display active runtime info
-    display_active_runtime(config, output);
+    // Skip if not in test mode and no HITL directory exists
+    if std::env::var("AVOCADO_TEST_MODE").is_err() && !hitl_dir.exists() {
+        return;
+    }
 
-    // Create comprehensive status
-    display_extension_status(
-        &available_extensions,
-        &mounted_sysext,
-        &mounted_confext,
-        manifest_extensions,
-    )?;
+    // In test mode, use the test directory
+    let hitl_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
+            .or_else(|_| std::env::var("TMPDIR"))
+            .unwrap_or_else(|_| "/tmp".to_string());
+        std::path::PathBuf::from(format!("{temp_base}/avocado/hitl"))
+    } else {
+        hitl_dir.to_path_buf()
+    };
 
-    Ok(())
-}
+    if !hitl_dir.exists() {
+        return;
+    }
 
-/// Display the active runtime configuration
-fn display_active_runtime(config: &Config, output: &OutputManager) {
-    let base_dir = config.get_avocado_base_dir();
-    let base_path = std::path::Path::new(&base_dir);
+    let entries = match read_dir_sorted(&hitl_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
 
-    match crate::manifest::RuntimeManifest::load_active(base_path) {
-        Some(manifest) => {
-            let short_id = if manifest.id.len() >= 8 {
-                &manifest.id[..8]
-            } else {
-                &manifest.id
-            };
-            println!("Active Runtime:");
-            println!(
-                "  {} {} ({short_id})",
-                manifest.runtime.name, manifest.runtime.version
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            let extension_name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("unknown");
+
+            output.step(
+                "HITL",
+                &format!("Invalidating NFS cache for extension: {extension_name}"),
             );
-            println!("  Built: {}", manifest.built_at);
-            println!("  Extensions: {}", manifest.extensions.len());
-            if let Some(ref os_bundle) = manifest.os_bundle {
-                if let Some(ref id) = os_bundle.os_build_id {
-                    println!("  OS Build ID (manifest): {id}");
-                }
-                if let Some(ref id) = os_bundle.initramfs_build_id {
-                    println!("  Initramfs Build ID:     {id}");
-                }
+
+            // Skip actual remount in test mode
+            if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+                output.progress(&format!(
+                    "Skipping remount in test mode for: {}",
+                    path.display()
+                ));
+                continue;
             }
-            // Show the running system's AVOCADO_OS_BUILD_ID for comparison
-            let os_release_path = if is_running_in_initrd() {
-                "/etc/os-release-initrd"
-            } else {
-                "/etc/os-release"
-            };
-            if let Ok(contents) = std::fs::read_to_string(os_release_path) {
-                for line in contents.lines() {
-                    if let Some(value) = line.strip_prefix("AVOCADO_OS_BUILD_ID=") {
-                        let label = if is_running_in_initrd() {
-                            "Initramfs Build ID (running)"
-                        } else {
-                            "OS Build ID (running)"
-                        };
-                        println!("  {label}:  {}", value.trim_matches('"'));
-                        break;
+
+            // Remount to invalidate NFS client cache
+            let result = std::process::Command::new("mount")
+                .args(["-o", "remount"])
+                .arg(&path)
+                .output();
+
+            match result {
+                Ok(output_result) => {
+                    if !output_result.status.success() {
+                        let stderr = String::from_utf8_lossy(&output_result.stderr);
+                        output.progress(&format!(
+                            "Warning: Failed to remount {}: {}",
+                            path.display(),
+                            stderr.trim()
+                        ));
                     }
                 }
-            }
-            if output.is_verbose() {
-                println!("  Build ID: {}", manifest.id);
-                for ext in &manifest.extensions {
-                    let id_display = ext.image_id.as_deref().unwrap_or("?");
-                    println!("    - {} {} ({})", ext.name, ext.version, id_display);
+                Err(e) => {
+                    output.progress(&format!(
+                        "Warning: Could not execute remount for {}: {}",
+                        path.display(),
+                        e
+                    ));
                 }
             }
-            println!();
-        }
-        None => {
-            println!("Active Runtime: none (using legacy extension discovery)");
-            println!();
         }
     }
 }
 
-/// Legacy status display for fallback
-fn show_legacy_status(output: &OutputManager) {
-    output.status("Legacy status display not yet implemented");
-    println!("Extension Status");
-    println!("================");
-    println!();
+/// Refresh extensions (unmerge then merge)
+pub fn refresh_extensions(config: &Config, output: &OutputManager) {
+    let environment_info = if is_running_in_initrd() {
+        "initrd environment"
+    } else {
+        "system environment"
+    };
+    output.info(
+        "Extension Refresh",
+        &format!("Starting extension refresh process in {environment_info}"),
+    );
 
-    // Get system extensions status
-    println!("System Extensions (/opt, /usr):");
-    println!("--------------------------------");
-    match run_systemd_command("systemd-sysext", &["status"]) {
-        Ok(output) => {
-            if output.trim().is_empty() {
-                println!("No system extensions currently merged.");
-            } else {
-                format_status_output(&output);
-            }
-        }
-        Err(e) => {
-            eprintln!("Error getting system extensions status: {e}");
-        }
+    require_systemd_for_refresh(output);
+    wait_for_hitl_sync(config, output);
+    let before = crate::merge_history::latest();
+    let span = crate::telemetry::operation("refresh", output);
+
+    // First unmerge (skip depmod since we'll call it after merge, don't unmount loops —
+    // the caller may be running from a loop-mounted extension like avocado-connect)
+    if let Err(e) = span.record(unmerge_extensions_internal_with_options(
+        false, false, output,
+    )) {
+        drop(span);
+        output.error_with(
+            "Extension Refresh",
+            &format!("Failed to unmerge extensions: {e}"),
+            &e.diagnose(),
+        );
+        output.exit(1);
     }
+    output.step("Refresh", "Extensions unmerged");
 
-    println!();
+    // Invalidate NFS caches for any HITL-mounted extensions
+    // This ensures fresh data is fetched from the server after a host rebuild
+    invalidate_hitl_caches(output);
 
-    // Get configuration extensions status
-    println!("Configuration Extensions (/etc):");
-    println!("---------------------------------");
-    match run_systemd_command("systemd-confext", &["status"]) {
-        Ok(output) => {
-            if output.trim().is_empty() {
-                println!("No configuration extensions currently merged.");
-            } else {
-                format_status_output(&output);
-            }
+    // Then merge (this will call depmod via post-merge processing)
+    if let Err(e) = span.record(merge_extensions_internal(config, output)) {
+        drop(span);
+        output.error_with(
+            "Extension Refresh",
+            &format!("Failed to merge extensions: {e}"),
+            &e.diagnose(),
+        );
+        output.exit(1);
+    }
+    output.step("Refresh", "Extensions merged");
+    drop(span);
+    run_change_hook(config, &before, output);
+
+    exit_if_partial_failure(output);
+    output.success_msg("Extension Refresh", messages::EXT_REFRESHED, &[]);
+    exit_if_reboot_required(output);
+}
+
+/// Exit before a refresh starts when systemd is not running.
+fn require_systemd_for_refresh(output: &OutputManager) {
+    if let Err(e) = crate::systemd_runtime::require("refresh") {
+        output.error_with("Extension Refresh", &e.to_string(), &e.diagnose());
+        output.exit(1);
+    }
+}
+
+/// Hold a refresh while HITL extensions are being synced, exiting if they
+/// still are after `[avocado.hitl] sync_wait_ms`.
+fn wait_for_hitl_sync(config: &Config, output: &OutputManager) {
+    if let Err(e) = crate::hitl_sync::wait_until_idle(config.hitl(), output) {
+        output.error_with("Extension Refresh", &e.to_string(), &e.diagnose());
+        output.exit(1);
+    }
+}
+
+/// Apply enable-state changes through a systemd soft-reboot instead of a live refresh
+pub fn soft_reboot_refresh(config: &Config, output: &OutputManager) {
+    match soft_reboot_refresh_internal(config, output) {
+        Ok(()) => {
+            output.success_msg(
+                "Extension Refresh",
+                messages::EXT_SOFT_REBOOT_REQUESTED,
+                &[],
+            );
         }
         Err(e) => {
-            eprintln!("Error getting configuration extensions status: {e}");
+            output.error_with(
+                "Extension Refresh",
+                &format!("Failed to request soft-reboot: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
         }
     }
 }
 
-/// Structure to represent mounted extension info from systemd
-#[derive(Debug, Clone)]
-struct MountedExtension {
-    name: String,
-    #[allow(dead_code)] // May be used in future for hierarchy-specific logic
-    hierarchy: String,
-}
-
-/// Strip a numeric order prefix (e.g. "00-", "03-") from an extension name.
-/// These prefixes are added by avocadoctl to enforce systemd merge ordering.
-fn strip_order_prefix(name: &str) -> &str {
-    let end = name.bytes().take_while(|b| b.is_ascii_digit()).count();
-    if end > 0 && name.as_bytes().get(end) == Some(&b'-') {
-        &name[end + 1..]
+/// Persist the enable state and hand off to `systemctl soft-reboot`.
+///
+/// Nothing is merged or unmerged live: the os-releases symlinks, the active
+/// runtime link and its overrides are fsynced first, so the merge that runs
+/// on the next userspace boot is guaranteed to see them, and only then is
+/// the soft-reboot requested.
+pub(crate) fn soft_reboot_refresh_internal(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    crate::systemd_runtime::require("refresh")?;
+    let version_id = read_os_version_id();
+    let os_releases_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/os-releases/{version_id}")
     } else {
-        name
+        crate::user_mode::system_path(&format!("/var/lib/avocado/os-releases/{version_id}"))
+    };
+
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = Path::new(&base_dir);
+    let active_dir = base_path.join(crate::manifest::ACTIVE_LINK_NAME);
+    let overrides_path = crate::overrides::RuntimeOverrides::path(&active_dir);
+
+    // Children before parents so new entries are durable before the
+    // directory that references them.
+    let os_releases_path = Path::new(&os_releases_dir);
+    let mut to_sync: Vec<PathBuf> = vec![
+        os_releases_path.to_path_buf(),
+        os_releases_path
+            .parent()
+            .unwrap_or(Path::new("/"))
+            .to_path_buf(),
+        overrides_path,
+    ];
+    if let Ok(target) = fs::canonicalize(&active_dir) {
+        to_sync.push(target);
+    }
+    to_sync.push(base_path.to_path_buf());
+
+    for path in to_sync.iter().filter(|p| p.exists()) {
+        sync_directory(path)?;
+        output.info("Extension Refresh", &format!("Synced {}", path.display()));
     }
+    output.log_info("Extension enable state synced to disk");
+
+    crate::reboot::trigger(crate::config::RebootAction::SoftReboot)?;
+    output.log_info("Requested soft-reboot from systemd");
+
+    Ok(())
 }
 
-/// Get mounted extensions from systemd using JSON format
-fn get_mounted_systemd_extensions(command: &str) -> Result<Vec<MountedExtension>, SystemdError> {
-    let mut mounted = Vec::new();
+/// Show status of merged extensions
+pub fn status_extensions(config: &Config, output: &OutputManager, updates_only: bool) {
+    if updates_only && crate::repo_index::configured(config).is_none() {
+        let e = crate::service::error::AvocadoError::ConfigurationError {
+            message: crate::repo_index::NO_INDEX.into(),
+        };
+        output.error_with("Extension Status", &e.to_string(), &e.diagnose());
+        output.exit(1);
+    }
+    match show_enhanced_status(config, output, updates_only) {
+        Ok(_) => {}
+        Err(e) => {
+            output.error_with(
+                "Extension Status",
+                &format!("Failed to show status: {e}"),
+                &e.diagnose(),
+            );
+            if output.is_json() {
+                output.exit(1);
+            }
+            show_legacy_status(output);
+        }
+    }
+}
 
-    let output = run_systemd_command(command, &["status", "--json=short"])?;
-    if output.trim().is_empty() {
-        return Ok(mounted);
-    }
+/// Exit code of `ext check-update` when the repository offers updates the
+/// upgrade policies allow. Out of the range of the failure exit codes, so
+/// a failing command is never taken for available updates.
+pub const UPDATE_CHECK_AVAILABLE: i32 = 100;
 
-    // Parse JSON output
-    let json_data: serde_json::Value =
-        serde_json::from_str(&output).map_err(|e| SystemdError::CommandFailed {
-            command: format!("{command} status --json=short"),
-            source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
-        })?;
+/// Exit code of `ext check-update` when the repository could not be checked.
+pub const UPDATE_CHECK_UNKNOWN: i32 = 101;
 
-    // Handle both single object and array formats
-    let hierarchies = if json_data.is_array() {
-        json_data.as_array().unwrap()
-    } else {
-        std::slice::from_ref(&json_data)
-    };
+/// Exit code of `ext status --check` when a refresh is needed.
+pub const STATUS_CHECK_REFRESH_NEEDED: i32 = 1;
 
-    for hierarchy_obj in hierarchies {
-        let hierarchy = hierarchy_obj["hierarchy"]
-            .as_str()
-            .unwrap_or("unknown")
-            .to_string();
-
-        // Handle extensions field - can be string "none" or array of strings
-        if let Some(extensions) = hierarchy_obj["extensions"].as_array() {
-            // Array of extension names — strip any "NN-" ordering prefix before storing
-            for ext in extensions {
-                if let Some(ext_name) = ext.as_str() {
-                    mounted.push(MountedExtension {
-                        name: strip_order_prefix(ext_name).to_string(),
-                        hierarchy: hierarchy.clone(),
-                    });
-                }
-            }
-        } else if let Some(ext_str) = hierarchy_obj["extensions"].as_str() {
-            // Single string - skip if it's "none"
-            if ext_str != "none" {
-                mounted.push(MountedExtension {
-                    name: strip_order_prefix(ext_str).to_string(),
-                    hierarchy: hierarchy.clone(),
-                });
-            }
-        }
+/// Exit code of `ext status --check` when the state could not be determined.
+pub const STATUS_CHECK_ERROR: i32 = 2;
+
+/// What a host merge of `extensions` is made from.
+fn merge_inputs(extensions: &[Extension], config: &Config) -> crate::merge_inputs::MergeInputs {
+    let mut hitl_mounts: Vec<String> = crate::hitl_health::load_mounts()
+        .iter()
+        .map(|m| format!("{} {}:{}", m.extension, m.server, m.port))
+        .collect();
+    hitl_mounts.sort();
+    crate::merge_inputs::MergeInputs {
+        images: extensions
+            .iter()
+            .map(|ext| crate::merge_inputs::MergedImage {
+                name: versioned_name(ext),
+                sysext: ext.is_sysext,
+                confext: ext.is_confext,
+                path: ext.path.to_string_lossy().to_string(),
+                fingerprint: crate::merge_inputs::fingerprint(&ext.path),
+            })
+            .collect(),
+        hitl_mounts,
+        sysext_mutable: config.get_sysext_mutable().unwrap_or_default(),
+        confext_mutable: config.get_confext_mutable().unwrap_or_default(),
     }
+}
 
-    Ok(mounted)
+/// How a refresh gets from the last merge to the current inputs.
+enum RefreshMode {
+    /// Nothing changed and systemd still has the last merge in place
+    UpToDate,
+    /// Re-merge only the extensions in the delta
+    Partial {
+        reason: String,
+        plan: MergePlan,
+        delta: crate::merge_inputs::MergeDelta,
+    },
+    /// Unmerge and merge everything, for the given reason
+    Full(String),
 }
 
-/// Build a JSON representation of all extensions for machine-readable output
-fn build_extension_json_list(
-    available: &[Extension],
-    mounted_sysext: &[MountedExtension],
-    mounted_confext: &[MountedExtension],
-    manifest_extensions: &[crate::manifest::ManifestExtension],
-) -> Vec<serde_json::Value> {
-    let mut all_extensions = std::collections::HashSet::new();
+/// Decide how to refresh. A partial refresh needs a record of the last
+/// merge that systemd still has in place, and no extension renamed for
+/// merge ordering (their staged release files are only rebuilt by a full
+/// cycle).
+fn plan_refresh(config: &Config, output: &OutputManager) -> Result<RefreshMode, SystemdError> {
+    let Some(previous) = crate::merge_inputs::MergeInputs::load() else {
+        return Ok(RefreshMode::Full("no record of the last merge".to_string()));
+    };
+    crate::phases::reset();
+    let scanning = crate::phases::enter(Phase::Scanning);
+    let plan = plan_merge(&scan_merge_state(config, output)?);
+    drop(scanning);
+    record_merge_failures(&plan.failed, output);
+    let current = merge_inputs(&plan.enabled, config);
+    let Some(reason) = current.changes_since(&previous) else {
+        return Ok(match reconciliation_drift(config, output)? {
+            None => RefreshMode::UpToDate,
+            Some(drift) => RefreshMode::Full(drift),
+        });
+    };
 
-    for ext in available {
-        if let Some(ver) = &ext.version {
-            all_extensions.insert(format!("{}-{}", ext.name, ver));
-        } else {
-            all_extensions.insert(ext.name.clone());
-        }
-    }
-    for ext in mounted_sysext {
-        all_extensions.insert(ext.name.clone());
+    let full = |why: String| Ok(RefreshMode::Full(format!("{reason} ({why})")));
+    let delta = match current.delta_since(&previous) {
+        Ok(delta) => delta,
+        Err(why) => return full(why),
+    };
+    if plan
+        .actions
+        .iter()
+        .any(|action| matches!(action, MergeAction::StageRelease { .. }))
+    {
+        return full("extensions are renamed for merge ordering".to_string());
     }
-    for ext in mounted_confext {
-        all_extensions.insert(ext.name.clone());
+    let recorded: Vec<(String, bool, bool)> = previous
+        .images
+        .iter()
+        .map(|image| (image.name.clone(), image.sysext, image.confext))
+        .collect();
+    if let Some(drift) = merged_drift(&recorded)? {
+        return full(drift);
     }
+    Ok(RefreshMode::Partial {
+        reason,
+        plan,
+        delta,
+    })
+}
 
-    let mut sorted: Vec<_> = all_extensions.into_iter().collect();
-    sorted.sort();
+/// Outcome of [`refresh_incrementally`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IncrementalRefresh {
+    /// Nothing changed since the last merge
+    UpToDate,
+    /// Only the changed extensions were re-merged
+    Refreshed,
+    /// The caller has to run a full refresh
+    FullNeeded,
+}
 
-    sorted
-        .iter()
-        .map(|ext_name| {
-            let available_ext = available.iter().find(|e| {
-                if let Some(ver) = &e.version {
-                    format!("{}-{}", e.name, ver) == *ext_name
-                } else {
-                    e.name == *ext_name
-                }
-            });
+/// Refresh touching only what changed since the last merge. Anything that
+/// rules out a partial refresh, including an error during one, is reported
+/// and answers [`IncrementalRefresh::FullNeeded`].
+pub(crate) fn refresh_incrementally(config: &Config, output: &OutputManager) -> IncrementalRefresh {
+    let (reason, plan, delta) = match plan_refresh(config, output) {
+        Ok(RefreshMode::UpToDate) => return IncrementalRefresh::UpToDate,
+        Ok(RefreshMode::Full(reason)) => {
+            output.step("Refresh", &format!("Refresh needed: {reason}"));
+            return IncrementalRefresh::FullNeeded;
+        }
+        Ok(RefreshMode::Partial {
+            reason,
+            plan,
+            delta,
+        }) => (reason, plan, delta),
+        Err(e) => {
+            output.step(
+                "Refresh",
+                &format!("Could not compare with the last merge ({e}); refreshing"),
+            );
+            return IncrementalRefresh::FullNeeded;
+        }
+    };
+    output.step("Refresh", &format!("Refresh needed: {reason}"));
+
+    let before = crate::merge_history::latest();
+    let span = crate::telemetry::operation("refresh", output);
+    span.set_attribute("avocado.refresh.mode", "partial");
+    match span.record(partial_refresh(config, &plan, &delta, output)) {
+        Ok(()) => {
+            drop(span);
+            run_change_hook(config, &before, output);
+            IncrementalRefresh::Refreshed
+        }
+        Err(e) => {
+            output.warning(&format!(
+                "Partial refresh failed ({e}); falling back to a full refresh"
+            ));
+            IncrementalRefresh::FullNeeded
+        }
+    }
+}
 
-            let is_sysext = mounted_sysext.iter().any(|e| e.name == *ext_name);
-            let is_confext = mounted_confext.iter().any(|e| e.name == *ext_name);
+/// Re-merge only the extensions in `delta`: run the on-unmerge commands of
+/// the leaving ones, update their links, let systemd-sysext/confext
+/// `refresh` swap the changed images in, then run the post-merge tasks of
+/// the entering ones.
+fn partial_refresh(
+    config: &Config,
+    plan: &MergePlan,
+    delta: &crate::merge_inputs::MergeDelta,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    output.log_info(&format!(
+        "Refreshing changed extensions only (leaving: {}; entering: {})",
+        display_names(&delta.leaving),
+        display_names(&delta.entering)
+    ));
 
-            let status = match (is_sysext, is_confext) {
-                (true, true) => "MERGED",
-                (true, false) => "SYSEXT",
-                (false, true) => "CONFEXT",
-                (false, false) => {
-                    if available_ext.is_some() {
-                        "READY"
-                    } else {
-                        "UNKNOWN"
-                    }
-                }
-            };
+    // A partial refresh that stops halfway must not look up to date
+    crate::merge_inputs::MergeInputs::clear();
 
-            let mut types = Vec::new();
-            if let Some(ext) = available_ext {
-                if ext.is_sysext {
-                    types.push("sys");
-                }
-                if ext.is_confext {
-                    types.push("conf");
-                }
-            }
+    if let Err(e) = process_pre_unmerge_tasks_for(Some(&delta.leaving), output) {
+        output.progress(&format!(
+            "Warning: Failed to process pre-unmerge tasks: {e}"
+        ));
+    }
 
-            let origin = available_ext
-                .map(get_extension_origin_short)
-                .unwrap_or_else(|| "?".to_string());
+    let protected = crate::source_guard::protect(config, output)?;
+    let scanning = crate::phases::enter(Phase::Scanning);
+    apply_merge_plan(plan, config.limits(), output)?;
+    drop(scanning);
 
-            let short_id = lookup_extension_short_id(ext_name, manifest_extensions);
+    let sysext_mutable_arg = format!(
+        "--mutable={}",
+        config
+            .get_sysext_mutable()
+            .map_err(|e| SystemdError::ConfigurationError {
+                message: e.to_string()
+            })?
+    );
+    let confext_mutable_arg = format!(
+        "--mutable={}",
+        config
+            .get_confext_mutable()
+            .map_err(|e| SystemdError::ConfigurationError {
+                message: e.to_string()
+            })?
+    );
+    let merging = crate::phases::enter(Phase::Merging);
+    if !skip_sysext("refresh", output) {
+        let sysext_result = run_systemd_command(
+            "systemd-sysext",
+            &[
+                "refresh",
+                &sysext_mutable_arg,
+                "--no-reload",
+                "--json=short",
+            ],
+            Some(output),
+        )?;
+        handle_systemd_output("systemd-sysext refresh", &sysext_result, output)?;
+    }
+    if crate::fault::fail_at(FailPoint::AfterMerge) {
+        return Err(crate::fault::injected(FailPoint::AfterMerge));
+    }
+    if crate::systemd_caps::detect().confext {
+        let confext_result = run_systemd_command(
+            "systemd-confext",
+            &[
+                "refresh",
+                &confext_mutable_arg,
+                "--no-reload",
+                "--json=short",
+            ],
+            Some(output),
+        )?;
+        handle_systemd_output("systemd-confext refresh", &confext_result, output)?;
+    }
+    drop(merging);
+    drop(protected);
+    crate::daemon_reload::request(output);
+
+    if crate::fault::fail_at(FailPoint::BeforeHooks) {
+        return Err(crate::fault::injected(FailPoint::BeforeHooks));
+    }
+    let hooks = crate::phases::enter(Phase::Hooks);
+    process_post_merge_tasks(&plan.enabled, Some(&delta.entering), output)?;
+    drop(hooks);
+    report_phase_timings(output);
+
+    let entering: Vec<Extension> = plan
+        .enabled
+        .iter()
+        .filter(|ext| delta.entering.contains(&versioned_name(ext)))
+        .cloned()
+        .collect();
+    handle_reboot_requests(&entering, config, output);
 
-            let order = available_ext.and_then(|e| e.merge_index);
+    let inputs = merge_inputs(&plan.enabled, config);
+    if let Err(e) = inputs.save() {
+        output.progress(&format!("Warning: Failed to record merge inputs: {e}"));
+    }
+    record_merge_history("refresh", &plan.enabled, &inputs, output);
+    record_module_extensions(&plan.enabled, output);
+    Ok(())
+}
 
-            serde_json::json!({
-                "name": ext_name,
-                "order": order,
-                "id": if short_id == "-" { serde_json::Value::Null } else { serde_json::Value::String(short_id) },
-                "status": status,
-                "type": if types.is_empty() { vec!["?"] } else { types },
-                "origin": origin,
-            })
+/// Append the merged set after `operation` to the merge history that
+/// `ext status --since` compares against.
+fn record_merge_history(
+    operation: &str,
+    extensions: &[Extension],
+    inputs: &crate::merge_inputs::MergeInputs,
+    output: &OutputManager,
+) {
+    let merged = extensions
+        .iter()
+        .zip(&inputs.images)
+        .map(|(ext, image)| crate::merge_history::MergedExtension {
+            name: ext.name.clone(),
+            version: ext.version.clone(),
+            fingerprint: image.fingerprint.clone(),
         })
-        .collect()
+        .collect();
+    if let Err(e) = crate::merge_history::record(operation, merged) {
+        output.progress(&format!("Warning: Failed to record merge history: {e}"));
+    }
 }
 
-/// Display comprehensive extension status
-fn display_extension_status(
-    available: &[Extension],
-    mounted_sysext: &[MountedExtension],
-    mounted_confext: &[MountedExtension],
-    manifest_extensions: &[crate::manifest::ManifestExtension],
-) -> Result<(), SystemdError> {
-    // Collect all unique extension names (with versions if present)
-    let mut all_extensions = std::collections::HashSet::new();
-
-    // For available extensions, use versioned name if available
-    for ext in available {
-        if let Some(ver) = &ext.version {
-            all_extensions.insert(format!("{}-{}", ext.name, ver));
-        } else {
-            all_extensions.insert(ext.name.clone());
+/// Add the merged system extensions that ship kernel modules to the record
+/// a later unmerge checks. Extensions from earlier merges stay until an
+/// unmerge has run depmod: a refresh away from them leaves their entries in
+/// modules.dep behind.
+fn record_module_extensions(extensions: &[Extension], output: &OutputManager) {
+    let mut with_modules = crate::kernel_modules::recorded().unwrap_or_default();
+    for ext in extensions {
+        let name = versioned_name(ext);
+        if ext.is_sysext
+            && crate::kernel_modules::ships_modules(&ext.path)
+            && !with_modules.contains(&name)
+        {
+            with_modules.push(name);
         }
     }
-
-    // Add mounted extensions (these already include versions in their names)
-    for ext in mounted_sysext {
-        all_extensions.insert(ext.name.clone());
-    }
-    for ext in mounted_confext {
-        all_extensions.insert(ext.name.clone());
+    if let Err(e) = crate::kernel_modules::record(&with_modules) {
+        output.progress(&format!(
+            "Warning: Failed to record extensions with kernel modules: {e}"
+        ));
     }
+}
 
-    if all_extensions.is_empty() {
-        println!("No extensions found or mounted.");
-        return Ok(());
+/// Log and announce how long each merge phase took.
+fn report_phase_timings(output: &OutputManager) {
+    let timings = crate::phases::format_timings(&crate::phases::take_timings());
+    output.log_info(&format!("Merge phases: {timings}"));
+    crate::phases::notify(&format!("STATUS=Extensions merged ({timings})"));
+}
+
+fn display_names(names: &[String]) -> String {
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
     }
+}
 
-    // Sort descending by merge_index (highest priority / top layer first).
-    // Extensions without a merge_index sort to the bottom.
-    let mut sorted_extensions: Vec<_> = all_extensions.into_iter().collect();
-    sorted_extensions.sort_by(|a, b| {
-        let idx_a = available
-            .iter()
-            .find(|e| {
-                if let Some(ver) = &e.version {
-                    format!("{}-{}", e.name, ver) == *a
-                } else {
-                    e.name == *a
-                }
-            })
-            .and_then(|e| e.merge_index);
-        let idx_b = available
-            .iter()
-            .find(|e| {
-                if let Some(ver) = &e.version {
-                    format!("{}-{}", e.name, ver) == *b
-                } else {
-                    e.name == *b
-                }
-            })
-            .and_then(|e| e.merge_index);
-        // Descending by index; None sorts last
-        idx_b.cmp(&idx_a).then_with(|| a.cmp(b))
-    });
+/// Refresh, re-merging only the extensions that changed when possible and
+/// running the full unmerge/merge cycle otherwise.
+pub fn refresh_changed_extensions(config: &Config, output: &OutputManager) {
+    require_systemd_for_refresh(output);
+    wait_for_hitl_sync(config, output);
+    match refresh_incrementally(config, output) {
+        IncrementalRefresh::UpToDate => {
+            exit_if_partial_failure(output);
+            output.success_msg("Extension Refresh", messages::EXT_UP_TO_DATE, &[]);
+        }
+        IncrementalRefresh::Refreshed => {
+            exit_if_partial_failure(output);
+            output.success_msg("Extension Refresh", messages::EXT_REFRESHED, &[]);
+            exit_if_reboot_required(output);
+        }
+        IncrementalRefresh::FullNeeded => refresh_extensions(config, output),
+    }
+}
 
-    // Compute dynamic column width from the longest extension name
-    let name_width = sorted_extensions
+/// Compare the extensions a merge would link now with what systemd reports
+/// as merged. Returns why a refresh is needed, or `None` when they match.
+fn reconciliation_drift(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<Option<String>, SystemdError> {
+    let plan = plan_merge(&scan_merge_state(config, output)?);
+    let desired: Vec<(String, bool, bool)> = plan
+        .enabled
         .iter()
-        .map(|n| n.len())
-        .max()
-        .unwrap_or(9)
-        .max(9); // at least as wide as "Extension"
-
-    let total_width = 6 + name_width + 1 + 10 + 1 + 10 + 1 + 12 + 1 + 10;
-
-    // Display header — top-of-stack indicator makes the overlay direction explicit
-    println!("  (high priority / top layer)");
-    println!(
-        "{:<6}{:<nw$} {:<10} {:<10} {:<12} Origin",
-        "Order",
-        "Extension",
-        "ID",
-        "Status",
-        "Type",
-        nw = name_width
-    );
-    println!("{}", "=".repeat(total_width));
+        .map(|ext| (versioned_name(ext), ext.is_sysext, ext.is_confext))
+        .collect();
+    merged_drift(&desired)
+}
 
-    for ext_name in &sorted_extensions {
-        display_extension_info(
-            ext_name,
-            available,
-            mounted_sysext,
-            mounted_confext,
-            manifest_extensions,
-            name_width,
-        );
+/// Compare `(name, sysext, confext)` entries with what systemd reports as
+/// merged. Returns the differences, or `None` when they match.
+fn merged_drift(desired: &[(String, bool, bool)]) -> Result<Option<String>, SystemdError> {
+    let kinds: &[LinkKind] = if crate::systemd_caps::detect().confext {
+        &[LinkKind::Sysext, LinkKind::Confext]
+    } else {
+        &[LinkKind::Sysext]
+    };
+    let mut problems = Vec::new();
+    for &kind in kinds {
+        let command = match kind {
+            LinkKind::Sysext => "systemd-sysext",
+            LinkKind::Confext => "systemd-confext",
+        };
+        let desired: std::collections::BTreeSet<String> = desired
+            .iter()
+            .filter(|(_, sysext, confext)| match kind {
+                LinkKind::Sysext => *sysext,
+                LinkKind::Confext => *confext,
+            })
+            .map(|(name, _, _)| name.clone())
+            .collect();
+        let merged: std::collections::BTreeSet<String> = get_mounted_systemd_extensions(command)?
+            .into_iter()
+            .map(|ext| ext.name)
+            .collect();
+        for name in desired.difference(&merged) {
+            problems.push(format!("{} {name} is enabled but not merged", kind.label()));
+        }
+        for name in merged.difference(&desired) {
+            problems.push(format!("{} {name} is merged but not enabled", kind.label()));
+        }
     }
-
-    println!("  (low priority / base layer)");
-
-    // Display summary
-    println!();
-    display_status_summary(available, mounted_sysext, mounted_confext);
-
-    Ok(())
+    Ok((!problems.is_empty()).then(|| problems.join("; ")))
 }
 
-/// Display information for a single extension
-fn display_extension_info(
-    ext_name: &str,
-    available: &[Extension],
-    mounted_sysext: &[MountedExtension],
-    mounted_confext: &[MountedExtension],
-    manifest_extensions: &[crate::manifest::ManifestExtension],
-    name_width: usize,
-) {
-    // Find extension in available list (match by full versioned name or base name)
-    let available_ext = available.iter().find(|e| {
-        if let Some(ver) = &e.version {
-            format!("{}-{}", e.name, ver) == ext_name
-        } else {
-            e.name == ext_name
-        }
-    });
+/// `ext status --check`: print a one-line verdict and exit 0 when the merged
+/// extensions match the enabled ones, 1 when a refresh is needed and 2 on errors.
+fn check_extension_status(config: &Config, output: &OutputManager) {
+    let (code, state, reason) = match reconciliation_drift(config, output) {
+        Ok(None) => (
+            0,
+            "in-sync",
+            "merged extensions match the enabled extensions".to_string(),
+        ),
+        Ok(Some(reason)) => (STATUS_CHECK_REFRESH_NEEDED, "refresh-needed", reason),
+        Err(e) => (STATUS_CHECK_ERROR, "error", e.to_string()),
+    };
+    if output.is_json() {
+        output.result("state", &state);
+        output.result("reason", &reason);
+    } else {
+        println!("{state}: {reason}");
+    }
+    output.exit(code);
+}
 
-    let sysext_mount = mounted_sysext.iter().find(|e| e.name == ext_name);
-    let confext_mount = mounted_confext.iter().find(|e| e.name == ext_name);
+/// `ext check-update`: report the dry-run upgrade `result` and exit with
+/// 0 when nothing would be upgraded, [`UPDATE_CHECK_AVAILABLE`] when
+/// something would, and [`UPDATE_CHECK_UNKNOWN`] when the repository could
+/// not be checked.
+fn check_for_updates(
+    result: Result<crate::service::types::UpgradeResult, crate::service::error::AvocadoError>,
+    output: &OutputManager,
+) {
+    use crate::upgrade::UpgradeAction;
 
-    // Determine status
-    let status = match (sysext_mount.is_some(), confext_mount.is_some()) {
-        (true, true) => "MERGED",
-        (true, false) => "SYSEXT",
-        (false, true) => "CONFEXT",
-        (false, false) => {
-            if available_ext.is_some() {
-                "READY"
+    let (code, state, reason, decisions) = match result {
+        Ok(result) => {
+            let updates: Vec<String> = result
+                .decisions
+                .iter()
+                .filter(|d| d.action == UpgradeAction::Upgrade)
+                .map(|d| {
+                    format!(
+                        "{} {} -> {}",
+                        d.name,
+                        d.current,
+                        d.available.as_deref().unwrap_or("?")
+                    )
+                })
+                .collect();
+            if updates.is_empty() {
+                (
+                    0,
+                    "up-to-date",
+                    "no extension can be upgraded".to_string(),
+                    result.decisions,
+                )
             } else {
-                "UNKNOWN"
+                (
+                    UPDATE_CHECK_AVAILABLE,
+                    "updates-available",
+                    updates.join(", "),
+                    result.decisions,
+                )
             }
         }
+        Err(e) => (UPDATE_CHECK_UNKNOWN, "unknown", e.to_string(), Vec::new()),
     };
-
-    // Determine types
-    let mut types = Vec::new();
-    if let Some(ext) = available_ext {
-        if ext.is_sysext {
-            types.push("sys");
-        }
-        if ext.is_confext {
-            types.push("conf");
-        }
-    }
-    let type_str = if types.is_empty() {
-        "?".to_string()
+    if output.is_json() {
+        output.result("state", &state);
+        output.result("reason", &reason);
+        output.result("decisions", &decisions);
     } else {
-        let base = types.join("+");
-        if available_ext.is_some_and(|e| e.image_type == ImageTypeTag::Kab) {
-            format!("kab:{base}")
-        } else {
-            base
-        }
-    };
+        println!("{state}: {reason}");
+    }
+    output.exit(code);
+}
 
-    // Determine origin
-    let origin = if let Some(ext) = available_ext {
-        get_extension_origin_short(ext)
-    } else {
-        "?".to_string()
+/// `ext status --since`: what the merge history says changed in the merged
+/// set between `reference` and the last merge, refresh or unmerge.
+fn show_changes_since(reference: crate::merge_history::Reference, output: &OutputManager) {
+    use crate::merge_history::{snapshot_at, Changes, Reference};
+
+    let snapshots = crate::merge_history::snapshots();
+    let baseline = snapshot_at(&snapshots, reference, &crate::merge_history::boot_id());
+    let now = snapshots
+        .last()
+        .map(|s| s.extensions.as_slice())
+        .unwrap_or(&[]);
+    let changes = Changes::between(
+        baseline.map(|s| s.extensions.as_slice()).unwrap_or(&[]),
+        now,
+    );
+    let since = match reference {
+        Reference::Time(at) => serde_json::json!(at),
+        Reference::Boot => serde_json::json!("boot"),
     };
 
-    // Look up short image ID from manifest extensions
-    let short_id = lookup_extension_short_id(ext_name, manifest_extensions);
+    if output.is_json() {
+        output.result_fields(&serde_json::json!({
+            "since": since,
+            "baseline_at": baseline.map(|s| s.at),
+            "last_change_at": snapshots.last().map(|s| s.at),
+            "merged": changes.merged,
+            "unmerged": changes.unmerged,
+            "changed": changes.changed,
+            "unchanged": changes.unchanged,
+        }));
+        return;
+    }
 
-    // Show merge order if available
-    let order_str = if let Some(ext) = available_ext {
-        if let Some(idx) = ext.merge_index {
-            format!("#{idx:02}")
+    let reference = match reference {
+        Reference::Time(at) => crate::clock::format_time(at),
+        Reference::Boot => "boot".to_string(),
+    };
+    output.status_header(&format!("Extension Changes Since {reference}"));
+    if snapshots.is_empty() {
+        println!("No merge history recorded yet.");
+        return;
+    }
+    if baseline.is_none() {
+        print_colored_info(&format!(
+            "No merge recorded before {reference}; the oldest record is from {}",
+            crate::clock::format_time(snapshots[0].at)
+        ));
+    }
+    if changes.is_empty() {
+        println!("No extensions changed.");
+    }
+    for name in &changes.merged {
+        println!("+ {name} (merged)");
+    }
+    for name in &changes.unmerged {
+        println!("- {name} (unmerged)");
+    }
+    for changed in &changes.changed {
+        if changed.from == changed.to {
+            println!("~ {} (image changed)", changed.to);
         } else {
-            "-".to_string()
+            println!("~ {} -> {} (changed)", changed.from, changed.to);
         }
-    } else {
-        "-".to_string()
+    }
+    for name in &changes.unchanged {
+        println!("  {name}");
+    }
+}
+
+/// `hierarchy` of a partition: its own in a combined image, the
+/// extension's otherwise.
+fn partition_hierarchy(extension: &Extension, partition: &crate::ddi::Partition) -> String {
+    if crate::ddi::is_combined(&extension.partitions) {
+        return match partition.hierarchy() {
+            crate::extension_release::Hierarchy::Sysext => "sysext",
+            crate::extension_release::Hierarchy::Confext => "confext",
+        }
+        .to_string();
+    }
+    let kinds: Vec<&str> = [
+        (extension.is_sysext, "sysext"),
+        (extension.is_confext, "confext"),
+    ]
+    .into_iter()
+    .filter_map(|(is, kind)| is.then_some(kind))
+    .collect();
+    kinds.join("+")
+}
+
+fn partition_status(extension: &Extension) -> Vec<crate::ddi::PartitionStatus> {
+    extension
+        .partitions
+        .iter()
+        .map(|p| crate::ddi::PartitionStatus {
+            designator: p.designator.as_str().to_string(),
+            hierarchy: Some(partition_hierarchy(extension, p)).filter(|h| !h.is_empty()),
+            architecture: p.architecture.to_string(),
+            uuid: p.uuid.clone(),
+            label: Some(p.label.clone()).filter(|l| !l.is_empty()),
+            size: p.size,
+            verity: p.verity,
+        })
+        .collect()
+}
+
+/// The loop device a mounted `.raw` or KAB extension is attached to.
+fn loop_status(extension: &Extension) -> Option<crate::loop_device::LoopDevice> {
+    let adaptor = match extension.image_type {
+        ImageTypeTag::Raw => ImageType::Raw(image_adaptor::RawAdaptor),
+        ImageTypeTag::Kab => ImageType::Kab(image_adaptor::KabAdaptor),
+        ImageTypeTag::Directory => return None,
     };
+    crate::loop_device::query(&adaptor.loop_device(&versioned_name(extension))?)
+}
 
-    println!(
-        "{order_str:<6}{ext_name:<name_width$} {short_id:<10} {status:<10} {type_str:<12} {origin}"
-    );
+/// Scopes declared by the release files of `extension`, in file order and
+/// without duplicates. `None` when neither hierarchy has a release file.
+fn extension_scopes(extension: &Extension) -> Option<Vec<String>> {
+    let mut scopes: Option<Vec<String>> = None;
+    for hierarchy in [Hierarchy::Sysext, Hierarchy::Confext] {
+        let Some(release) = extension_release::find(&extension.path, &extension.name, hierarchy)
+        else {
+            continue;
+        };
+        let found = scopes.get_or_insert_with(Vec::new);
+        for scope in
+            image_adaptor::parse_scope_from_release_content(&release.content, hierarchy.scope_key())
+        {
+            if !found.contains(&scope) {
+                found.push(scope);
+            }
+        }
+    }
+    scopes
 }
 
-/// Look up the short image ID (first 8 chars) for an extension by matching
-/// the versioned name (e.g. "app-0.2.0") against manifest extension entries.
-fn lookup_extension_short_id(
-    ext_name: &str,
-    manifest_extensions: &[crate::manifest::ManifestExtension],
-) -> String {
-    let matched = manifest_extensions.iter().find(|me| {
-        let versioned = format!("{}-{}", me.name, me.version);
-        versioned == ext_name || me.name == ext_name
-    });
-    match matched {
-        Some(me) => match &me.image_id {
-            Some(id) if id.len() >= 8 => id[..8].to_string(),
-            Some(id) => id.clone(),
-            None => "-".to_string(),
-        },
-        None => "-".to_string(),
+/// Whether `extension` merges in the current environment (initrd or
+/// system): one of its release files is in scope. `None` when no release
+/// file could be read.
+fn extension_applicable(extension: &Extension) -> Option<bool> {
+    let releases: Vec<_> = [Hierarchy::Sysext, Hierarchy::Confext]
+        .into_iter()
+        .filter_map(|hierarchy| {
+            extension_release::find(&extension.path, &extension.name, hierarchy)
+        })
+        .collect();
+    (!releases.is_empty()).then(|| releases.iter().any(|release| release.in_scope))
+}
+
+/// Status of an extension neither hierarchy has merged.
+fn unmerged_status(available: Option<&Extension>) -> &'static str {
+    match available {
+        Some(ext) if extension_applicable(ext) == Some(false) => "SKIPPED(scope)",
+        Some(_) => "READY",
+        None => "UNKNOWN",
     }
 }
 
-/// Get short extension origin description (for 80-column display)
-fn get_extension_origin_short(ext: &Extension) -> String {
-    let path_str = ext.path.to_string_lossy();
+fn extension_detail(extension: &Extension, enabled: bool) -> ExtensionDetail {
+    ExtensionDetail {
+        name: extension.name.clone(),
+        version: extension.version.clone(),
+        path: extension.path.display().to_string(),
+        is_sysext: extension.is_sysext,
+        is_confext: extension.is_confext,
+        is_directory: extension.image_type == ImageTypeTag::Directory,
+        scopes: extension_scopes(extension),
+        origin: Some(get_extension_origin_short(extension)),
+        enabled: Some(enabled),
+    }
+}
 
-    if path_str.contains("/hitl") {
-        "HITL".to_string()
-    } else {
-        match ext.image_type {
-            ImageTypeTag::Directory => "Dir".to_string(),
-            ImageTypeTag::Kab => {
-                if let Some(filename) = ext.path.file_name() {
-                    format!("KAB:{}", filename.to_string_lossy())
-                } else {
-                    "KAB".to_string()
-                }
-            }
-            ImageTypeTag::Raw => {
-                if let Some(filename) = ext.path.file_name() {
-                    format!("Loop:{}", filename.to_string_lossy())
-                } else {
-                    "Loop".to_string()
-                }
+/// Installed images in `dir`, described without mounting them. Archives are
+/// read from the unpack cache when they were unpacked before.
+fn installed_images(dir: &str) -> Vec<Extension> {
+    let mut images = scan_directory_extensions(dir).unwrap_or_default();
+    for (name, version, path) in scan_raw_files(dir).unwrap_or_default() {
+        images.push(describe_image(&name, &version, &path, ImageTypeTag::Raw));
+    }
+    for (name, version, path) in scan_archive_files(dir) {
+        let path = crate::archive::cached(&path, &crate::archive::cache_dir()).unwrap_or(path);
+        images.push(describe_image(
+            &name,
+            &version,
+            &path,
+            ImageTypeTag::Directory,
+        ));
+    }
+    images
+}
+
+/// Collect the entries of `ext list --detailed`: the extensions enabled for
+/// the current os-release, then the installed ones that are not (extensions
+/// the active runtime manifest disables, or without a manifest, images not
+/// enabled for this os-release), each group sorted by name.
+pub(crate) fn collect_extension_details(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<Vec<ExtensionDetail>, SystemdError> {
+    let mut active = scan_extensions_from_all_sources(config, output)?;
+    active.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut details: Vec<_> = active.iter().map(|e| extension_detail(e, true)).collect();
+
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = Path::new(&base_dir);
+    let mut disabled = Vec::new();
+    if let Some(manifest) = crate::manifest::RuntimeManifest::load_active(base_path) {
+        let active_dir = base_path.join(crate::manifest::ACTIVE_LINK_NAME);
+        let overrides = crate::overrides::RuntimeOverrides::load(&active_dir);
+        for mext in &manifest.extensions {
+            if crate::overrides::effective_enabled(mext, &overrides)
+                || active.iter().any(|e| e.name == mext.name)
+            {
+                continue;
             }
+            let path = mext.resolve_path(base_path);
+            let image_type = if path.is_dir() {
+                ImageTypeTag::Directory
+            } else {
+                ImageType::from_manifest(&mext.image_type).type_tag()
+            };
+            disabled.push(describe_image(
+                &mext.name,
+                &Some(mext.version.clone()),
+                &path,
+                image_type,
+            ));
         }
+    } else {
+        let enabled: std::collections::HashSet<String> =
+            active.iter().map(versioned_name).collect();
+        disabled = installed_images(&config.get_extensions_dir())
+            .into_iter()
+            .filter(|image| !enabled.contains(&versioned_name(image)))
+            .collect();
     }
+    disabled.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+    details.extend(disabled.iter().map(|e| extension_detail(e, false)));
+    Ok(details)
 }
 
-/// Display status summary
-fn display_status_summary(
-    available: &[Extension],
-    mounted_sysext: &[MountedExtension],
-    mounted_confext: &[MountedExtension],
-) {
-    let hitl_count = available
-        .iter()
-        .filter(|e| e.path.to_string_lossy().contains("/hitl"))
-        .count();
-    let directory_count = available
-        .iter()
-        .filter(|e| {
-            e.image_type == ImageTypeTag::Directory && !e.path.to_string_lossy().contains("/hitl")
+/// Collect extension status data for the varlink Status RPC.
+///
+/// This gathers the same data as `show_enhanced_status` but returns it as
+/// structured `ExtensionStatus` values instead of printing to stdout.
+pub(crate) fn collect_extension_status(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<Vec<ExtensionStatus>, SystemdError> {
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = std::path::Path::new(&base_dir);
+    let active_manifest = crate::manifest::RuntimeManifest::load_active(base_path);
+    let manifest_extensions = active_manifest
+        .as_ref()
+        .map(|m| m.extensions.as_slice())
+        .unwrap_or(&[]);
+
+    let available_extensions = scan_extensions_from_all_sources(config, output)?;
+    let mounted_sysext = get_mounted_systemd_extensions("systemd-sysext")?;
+    let mounted_confext = get_mounted_systemd_extensions("systemd-confext")?;
+    let reboot_pending = crate::reboot::pending();
+    let safe_mode_skipped = crate::safe_mode::skipped();
+    let repo_index = crate::repo_index::configured(config);
+
+    // Collect all unique extension names (with versions if present)
+    let mut all_names = std::collections::HashSet::new();
+    for ext in &available_extensions {
+        if let Some(ver) = &ext.version {
+            all_names.insert(format!("{}-{}", ext.name, ver));
+        } else {
+            all_names.insert(ext.name.clone());
+        }
+    }
+    for ext in &mounted_sysext {
+        all_names.insert(ext.name.clone());
+    }
+    for ext in &mounted_confext {
+        all_names.insert(ext.name.clone());
+    }
+
+    let mut result: Vec<ExtensionStatus> = all_names
+        .into_iter()
+        .map(|ext_name| {
+            let available_ext = available_extensions.iter().find(|e| {
+                if let Some(ver) = &e.version {
+                    format!("{}-{}", e.name, ver) == ext_name
+                } else {
+                    e.name == ext_name
+                }
+            });
+
+            let is_sysext_mounted = mounted_sysext.iter().any(|e| e.name == ext_name);
+            let is_confext_mounted = mounted_confext.iter().any(|e| e.name == ext_name);
+            let is_merged = is_sysext_mounted || is_confext_mounted;
+            let since = merged_since(&ext_name, &mounted_sysext, &mounted_confext);
+            let aliases = extension_aliases(config, &ext_name, available_ext);
+
+            let (is_sysext, is_confext) = if let Some(ext) = available_ext {
+                (ext.is_sysext, ext.is_confext)
+            } else {
+                (is_sysext_mounted, is_confext_mounted)
+            };
+
+            let origin = available_ext.map(get_extension_origin_short);
+
+            let image_id_str = lookup_extension_short_id(&ext_name, manifest_extensions);
+            let image_id = if image_id_str == "-" {
+                None
+            } else {
+                Some(image_id_str)
+            };
+
+            let (name, version) = if let Some(ext) = available_ext {
+                (ext.name.clone(), ext.version.clone())
+            } else {
+                (ext_name, None)
+            };
+
+            let reboot_required = reboot_pending.contains(&name);
+            let skipped_in_safe_mode = safe_mode_skipped.contains(&name);
+            let (latest_version, update_available) =
+                update_hint(config, repo_index.as_ref(), available_ext);
+            let provenance = available_ext.map(extension_provenance).unwrap_or_default();
+            let lifecycle = available_ext.map(extension_lifecycle).unwrap_or_default();
+
+            ExtensionStatus {
+                name,
+                version,
+                is_sysext,
+                is_confext,
+                is_merged,
+                origin,
+                image_id,
+                image_type: available_ext.and_then(|e| match e.image_type {
+                    ImageTypeTag::Kab => Some("kab".to_string()),
+                    _ => None,
+                }),
+                reboot_required: Some(reboot_required),
+                build_id: provenance.build_id,
+                git_sha: provenance.git_sha,
+                build_date: provenance.build_date,
+                eol: lifecycle.eol,
+                notes: lifecycle.notes,
+                path: available_ext.map(|e| e.path.display().to_string()),
+                partitions: available_ext
+                    .filter(|e| !e.partitions.is_empty())
+                    .map(partition_status),
+                latest_version,
+                update_available,
+                loop_device: available_ext.and_then(loop_status),
+                safe_mode_skipped: Some(skipped_in_safe_mode),
+                scopes: available_ext.and_then(extension_scopes),
+                applicable: available_ext.and_then(extension_applicable),
+                merged_since: since.map(|since| since as i64),
+                aliases: Some(aliases).filter(|aliases| !aliases.is_empty()),
+            }
         })
-        .count();
-    let loop_count = available
-        .iter()
-        .filter(|e| e.image_type != ImageTypeTag::Directory)
-        .count();
+        .collect();
+
+    // Sort descending by merge_index (highest priority / top layer first).
+    // Extensions without a merge_index sort to the bottom, then alphabetically.
+    result.sort_by(|a, b| {
+        let versioned_a = match &a.version {
+            Some(v) => format!("{}-{}", a.name, v),
+            None => a.name.clone(),
+        };
+        let versioned_b = match &b.version {
+            Some(v) => format!("{}-{}", b.name, v),
+            None => b.name.clone(),
+        };
+        let idx_a = available_extensions
+            .iter()
+            .find(|e| {
+                if let Some(ver) = &e.version {
+                    format!("{}-{}", e.name, ver) == versioned_a
+                } else {
+                    e.name == versioned_a
+                }
+            })
+            .and_then(|e| e.merge_index);
+        let idx_b = available_extensions
+            .iter()
+            .find(|e| {
+                if let Some(ver) = &e.version {
+                    format!("{}-{}", e.name, ver) == versioned_b
+                } else {
+                    e.name == versioned_b
+                }
+            })
+            .and_then(|e| e.merge_index);
+        idx_b
+            .cmp(&idx_a)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| a.version.cmp(&b.version))
+    });
 
-    let unique_sysext: std::collections::HashSet<&str> =
-        mounted_sysext.iter().map(|e| e.name.as_str()).collect();
-    let unique_confext: std::collections::HashSet<&str> =
-        mounted_confext.iter().map(|e| e.name.as_str()).collect();
+    Ok(result)
+}
 
-    println!("Summary:");
-    println!("  Available Extensions: {} total", available.len());
-    println!("    - HITL mounted: {hitl_count}");
-    println!("    - Local directories: {directory_count}");
-    println!("    - Loop devices: {loop_count}");
-    println!("  Mounted Extensions:");
-    println!("    - System extensions: {}", unique_sysext.len());
-    println!("    - Configuration extensions: {}", unique_confext.len());
+/// Show enhanced status with extension origins and HITL information
+pub(crate) fn show_enhanced_status(
+    config: &Config,
+    output: &OutputManager,
+    updates_only: bool,
+) -> Result<(), SystemdError> {
+    // Load active manifest
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = std::path::Path::new(&base_dir);
+    let active_manifest = crate::manifest::RuntimeManifest::load_active(base_path);
+    let manifest_extensions = active_manifest
+        .as_ref()
+        .map(|m| m.extensions.as_slice())
+        .unwrap_or(&[]);
 
-    if hitl_count > 0 {
-        print_colored_info("HITL extensions are active - development mode");
-    }
-}
+    // Get our view of available extensions; with keep_going configured an
+    // image that fails to mount is left out rather than failing the status
+    let scanned = scan_all_sources(config, output, config.keep_going(), false)?;
+    let available_extensions = scanned.extensions;
 
-/// Format status output from systemd commands
-fn format_status_output(output: &str) {
-    let lines: Vec<&str> = output.lines().collect();
+    // Get systemd's view of mounted extensions
+    let mounted_sysext = get_mounted_systemd_extensions("systemd-sysext")?;
+    let mounted_confext = get_mounted_systemd_extensions("systemd-confext")?;
 
-    // Skip the header line if present and process the data
-    let data_lines: Vec<&str> = lines
+    let repo_index = crate::repo_index::configured(config);
+
+    let now = crate::clock::now();
+    let eol_reached: Vec<(String, String)> = available_extensions
         .iter()
-        .skip_while(|line| line.starts_with("HIERARCHY") || line.trim().is_empty())
-        .copied()
+        .filter_map(|ext| {
+            let lifecycle = extension_lifecycle(ext);
+            let eol = lifecycle
+                .eol
+                .clone()
+                .filter(|_| lifecycle.eol_reached_at(now))?;
+            Some((versioned_name(ext), eol))
+        })
         .collect();
 
-    if data_lines.is_empty() {
-        println!("No extensions currently merged.");
-        return;
-    }
-
-    for line in data_lines {
-        if line.trim().is_empty() {
-            continue;
-        }
+    if output.is_json() {
+        let runtime_json = match &active_manifest {
+            Some(m) => {
+                let mut rj = serde_json::json!({
+                    "name": m.runtime.name,
+                    "version": m.runtime.version,
+                    "id": m.id,
+                    "built_at": m.built_at,
+                    "manifest_version": m.manifest_version,
+                });
+                if let Some(ref os_bundle) = m.os_bundle {
+                    rj["os_bundle"] = serde_json::json!({
+                        "image_id": os_bundle.image_id,
+                        "sha256": os_bundle.sha256,
+                        "os_build_id": os_bundle.os_build_id,
+                        "initramfs_build_id": os_bundle.initramfs_build_id,
+                    });
+                }
+                rj
+            }
+            None => serde_json::Value::Null,
+        };
 
-        // Parse the line format: HIERARCHY EXTENSIONS SINCE
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 3 {
-            let hierarchy = parts[0];
-            let extensions = parts[1];
-            let since = parts[2..].join(" ");
+        let extensions_json: Vec<serde_json::Value> = build_extension_json_list(
+            config,
+            &available_extensions,
+            &mounted_sysext,
+            &mounted_confext,
+            manifest_extensions,
+            repo_index.as_ref(),
+            updates_only,
+        );
 
-            println!("  {hierarchy} -> {extensions} (since {since})");
-        } else {
-            // Fallback: just print the line as-is
-            println!("  {line}");
-        }
+        let status_json = serde_json::json!({
+            "runtime": runtime_json,
+            "extensions": extensions_json,
+            "repo_index": repo_index.as_ref().map(|index| serde_json::json!({
+                "url": index.url,
+                "fetched_at": index.fetched_at,
+            })),
+            "reboot_required": crate::reboot::pending(),
+            "safe_mode_skipped": crate::safe_mode::skipped(),
+            "merge_failures": crate::merge_failures::recorded(),
+            "trust_rejected": scanned.rejected,
+            "environment": current_environment(),
+            "eol_reached": eol_reached.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            "systemd": crate::systemd_caps::detect(),
+        });
+        println!("{}", serde_json::to_string_pretty(&status_json).unwrap());
+        return Ok(());
     }
-}
 
-/// Prepare the extension environment by setting up symlinks with output manager
-fn prepare_extension_environment_with_output(
-    output: &OutputManager,
-) -> Result<Vec<Extension>, SystemdError> {
-    output.step("Environment", "Preparing extension environment");
+    output.status_header("Avocado Extension Status");
 
-    // Verify clean state by ensuring no stale symlinks exist
-    verify_clean_extension_environment(output)?;
+    // Display active runtime info
+    display_active_runtime(config, output);
 
-    // Scan for available extensions from multiple sources
-    let extensions = scan_extensions_from_all_sources_with_verbosity(output.is_verbose())?;
+    // Create comprehensive status
+    display_extension_status(
+        config,
+        &available_extensions,
+        &mounted_sysext,
+        &mounted_confext,
+        manifest_extensions,
+        repo_index.as_ref(),
+        updates_only,
+    )?;
 
-    if extensions.is_empty() {
-        output.progress("No extensions found in any source location");
-        return Ok(Vec::new());
+    let reboot_pending = crate::reboot::pending();
+    if !reboot_pending.is_empty() {
+        print_colored_info(&format!(
+            "Reboot required by: {}",
+            reboot_pending.join(", ")
+        ));
+    }
+    let safe_mode_skipped = crate::safe_mode::skipped();
+    if !safe_mode_skipped.is_empty() {
+        print_colored_info(&format!(
+            "Skipped in safe mode: {}",
+            safe_mode_skipped.join(", ")
+        ));
+    }
+    for failure in crate::merge_failures::recorded() {
+        print_colored_info(&format!(
+            "Failed in the last merge: {}: {}",
+            failure.extension, failure.error
+        ));
+    }
+    for rejected in &scanned.rejected {
+        print_colored_info(&format!(
+            "A merge would refuse: {}: {}",
+            rejected.extension, rejected.error
+        ));
+    }
+    for (name, eol) in &eol_reached {
+        println!("{}", eol_warning(name, eol));
     }
 
-    // Create target directories
-    create_target_directories()?;
-
-    // Track which extensions are actually enabled and linked
-    let mut enabled_extensions = Vec::new();
+    Ok(())
+}
 
-    // Create symlinks for sysext and confext extensions, using prefixed names for ordering
-    for extension in &extensions {
-        let mut extension_enabled = false;
-        let prefixed_name = compute_prefixed_name(extension);
+/// Display the active runtime configuration
+fn display_active_runtime(config: &Config, output: &OutputManager) {
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = std::path::Path::new(&base_dir);
 
-        // Stage extension-release files with prefixed name if ordering is active
-        if extension.merge_index.is_some() {
-            let original_name = if let Some(ver) = &extension.version {
-                format!("{}-{}", extension.name, ver)
+    match crate::manifest::RuntimeManifest::load_active(base_path) {
+        Some(manifest) => {
+            let short_id = if manifest.id.len() >= 8 {
+                &manifest.id[..8]
             } else {
-                extension.name.clone()
+                &manifest.id
             };
-            // Only stage if the prefixed name differs from the original
-            if prefixed_name != original_name {
-                stage_extension_release(extension, &prefixed_name, output.is_verbose())?;
+            println!("Active Runtime:");
+            println!(
+                "  {} {} ({short_id})",
+                manifest.runtime.name, manifest.runtime.version
+            );
+            println!("  Built: {}", manifest.built_at);
+            println!("  Extensions: {}", manifest.extensions.len());
+            if let Some(ref os_bundle) = manifest.os_bundle {
+                if let Some(ref id) = os_bundle.os_build_id {
+                    println!("  OS Build ID (manifest): {id}");
+                }
+                if let Some(ref id) = os_bundle.initramfs_build_id {
+                    println!("  Initramfs Build ID:     {id}");
+                }
             }
+            // Show the running system's AVOCADO_OS_BUILD_ID for comparison
+            let os_release_path = if is_running_in_initrd() {
+                "/etc/os-release-initrd"
+            } else {
+                "/etc/os-release"
+            };
+            if let Ok(contents) = std::fs::read_to_string(os_release_path) {
+                for line in contents.lines() {
+                    if let Some(value) = line.strip_prefix("AVOCADO_OS_BUILD_ID=") {
+                        let label = if is_running_in_initrd() {
+                            "Initramfs Build ID (running)"
+                        } else {
+                            "OS Build ID (running)"
+                        };
+                        println!("  {label}:  {}", value.trim_matches('"'));
+                        break;
+                    }
+                }
+            }
+            if output.is_verbose() {
+                println!("  Build ID: {}", manifest.id);
+                for ext in &manifest.extensions {
+                    let id_display = ext.image_id.as_deref().unwrap_or("?");
+                    println!("    - {} {} ({})", ext.name, ext.version, id_display);
+                }
+            }
+            println!();
         }
-
-        if extension.is_sysext {
-            create_sysext_symlink_with_verbosity(extension, &prefixed_name, output.is_verbose())?;
-            extension_enabled = true;
-        }
-        if extension.is_confext {
-            create_confext_symlink_with_verbosity(extension, &prefixed_name, output.is_verbose())?;
-            extension_enabled = true;
-        }
-
-        // Only add to enabled list if at least one type was linked
-        if extension_enabled {
-            enabled_extensions.push(extension.clone());
+        None => {
+            println!("Active Runtime: none (using legacy extension discovery)");
+            println!();
         }
     }
-
-    // Important: After creating symlinks for enabled extensions, ensure no stale symlinks remain
-    // This handles the case where an extension was previously enabled but is now disabled
-    cleanup_stale_extension_symlinks(&enabled_extensions, output)?;
-
-    output.progress("Extension environment prepared successfully");
-    Ok(enabled_extensions)
 }
 
-/// Remove any symlinks in /run/extensions and /run/confexts that are NOT in the enabled list
-/// This ensures disabled extensions are not merged
-fn cleanup_stale_extension_symlinks(
-    enabled_extensions: &[Extension],
-    output: &OutputManager,
-) -> Result<(), SystemdError> {
-    let sysext_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/test_extensions")
-    } else {
-        "/run/extensions".to_string()
-    };
-
-    let confext_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/test_confexts")
-    } else {
-        "/run/confexts".to_string()
-    };
-
-    // Build a set of expected symlink names (using prefixed names when ordering is active)
-    let mut expected_names = std::collections::HashSet::new();
-    // Also track base names without versions for masking logic
-    let mut non_versioned_base_names = std::collections::HashSet::new();
-
-    for ext in enabled_extensions {
-        // Use the same prefixed name that was used when creating the symlink
-        let prefixed = compute_prefixed_name(ext);
-        expected_names.insert(prefixed);
-
-        // Track non-versioned extensions (e.g., HITL mounts) for masking
-        if ext.version.is_none() && ext.merge_index.is_none() {
-            non_versioned_base_names.insert(ext.name.clone());
-        }
-    }
-
-    // Clean up sysext directory
-    if Path::new(&sysext_dir).exists() {
-        if let Ok(entries) = fs::read_dir(&sysext_dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_symlink() {
-                    if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                        // Remove .raw suffix if present for comparison
-                        let name_without_raw = file_name.strip_suffix(".raw").unwrap_or(file_name);
-
-                        // Check if this symlink should be removed
-                        let should_remove = if !expected_names.contains(file_name)
-                            && !expected_names.contains(name_without_raw)
-                        {
-                            // Not in expected list, should be removed
-                            true
-                        } else {
-                            // Check if this is a versioned symlink that should be masked by a non-versioned one
-                            // e.g., "myext-1.0.0" should be removed if "myext" (HITL mount) exists
-                            if let Some(last_dash) = name_without_raw.rfind('-') {
-                                let base_name = &name_without_raw[..last_dash];
-                                let potential_version = &name_without_raw[last_dash + 1..];
-                                // Check if this looks like a version (contains digits or dots)
-                                if potential_version
-                                    .chars()
-                                    .any(|c| c.is_ascii_digit() || c == '.')
-                                {
-                                    // This is a versioned symlink, check if we have a non-versioned version
-                                    non_versioned_base_names.contains(base_name)
-                                } else {
-                                    false
-                                }
-                            } else {
-                                false
-                            }
-                        };
+/// Legacy status display for fallback
+fn show_legacy_status(output: &OutputManager) {
+    output.status("Legacy status display not yet implemented");
+    println!("Extension Status");
+    println!("================");
+    println!();
 
-                        if should_remove {
-                            if let Err(e) = fs::remove_file(&path) {
-                                output.progress(&format!(
-                        "Warning: Failed to remove stale sysext symlink {file_name}: {e}"
-                    ));
-                            } else {
-                                output.progress(&format!(
-                                    "Removed stale sysext symlink: {file_name}"
-                                ));
-                            }
-                        }
-                    }
-                }
+    // Get system extensions status
+    println!("System Extensions (/opt, /usr):");
+    println!("--------------------------------");
+    match run_systemd_command("systemd-sysext", &["status"], None) {
+        Ok(status) => {
+            if status.trim().is_empty() {
+                println!("No system extensions currently merged.");
+            } else {
+                format_status_output(&status);
             }
         }
+        Err(e) => {
+            output.error(
+                "Extension Status",
+                &format!("Failed to get system extensions status: {e}"),
+            );
+        }
     }
 
-    // Clean up confext directory
-    if Path::new(&confext_dir).exists() {
-        if let Ok(entries) = fs::read_dir(&confext_dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_symlink() {
-                    if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                        // Remove .raw suffix if present for comparison
-                        let name_without_raw = file_name.strip_suffix(".raw").unwrap_or(file_name);
-
-                        // Check if this symlink should be removed
-                        let should_remove = if !expected_names.contains(file_name)
-                            && !expected_names.contains(name_without_raw)
-                        {
-                            // Not in expected list, should be removed
-                            true
-                        } else {
-                            // Check if this is a versioned symlink that should be masked by a non-versioned one
-                            // e.g., "myext-1.0.0" should be removed if "myext" (HITL mount) exists
-                            if let Some(last_dash) = name_without_raw.rfind('-') {
-                                let base_name = &name_without_raw[..last_dash];
-                                let potential_version = &name_without_raw[last_dash + 1..];
-                                // Check if this looks like a version (contains digits or dots)
-                                if potential_version
-                                    .chars()
-                                    .any(|c| c.is_ascii_digit() || c == '.')
-                                {
-                                    // This is a versioned symlink, check if we have a non-versioned version
-                                    non_versioned_base_names.contains(base_name)
-                                } else {
-                                    false
-                                }
-                            } else {
-                                false
-                            }
-                        };
+    println!();
 
-                        if should_remove {
-                            if let Err(e) = fs::remove_file(&path) {
-                                output.progress(&format!(
-                        "Warning: Failed to remove stale confext symlink {file_name}: {e}"
-                    ));
-                            } else {
-                                output.progress(&format!(
-                                    "Removed stale confext symlink: {file_name}"
-                                ));
-                            }
-                        }
-                    }
-                }
+    // Get configuration extensions status
+    println!("Configuration Extensions (/etc):");
+    println!("---------------------------------");
+    match run_systemd_command("systemd-confext", &["status"], None) {
+        Ok(status) => {
+            if status.trim().is_empty() {
+                println!("No configuration extensions currently merged.");
+            } else {
+                format_status_output(&status);
             }
         }
+        Err(e) => {
+            output.error(
+                "Extension Status",
+                &format!("Failed to get configuration extensions status: {e}"),
+            );
+        }
     }
-
-    Ok(())
 }
 
-/// Read VERSION_ID from /etc/os-release
-pub(crate) fn read_os_version_id() -> String {
-    let os_release_path = "/etc/os-release";
+/// Structure to represent mounted extension info from systemd
+#[derive(Debug, Clone)]
+struct MountedExtension {
+    name: String,
+    #[allow(dead_code)] // May be used in future for hierarchy-specific logic
+    hierarchy: String,
+    /// When systemd merged it, in seconds since the Unix epoch
+    since: Option<u64>,
+}
 
-    if let Ok(contents) = fs::read_to_string(os_release_path) {
-        for line in contents.lines() {
-            if line.starts_with("VERSION_ID=") {
-                // Parse VERSION_ID value, removing quotes if present
-                let value = line.trim_start_matches("VERSION_ID=");
-                let value = value.trim_matches('"').trim_matches('\'');
-                if !value.is_empty() {
-                    return value.to_string();
-                }
-            }
-        }
+/// Strip a numeric order prefix (e.g. "00-", "03-") from an extension name.
+/// These prefixes are added by avocadoctl to enforce systemd merge ordering.
+fn strip_order_prefix(name: &str) -> &str {
+    let end = name.bytes().take_while(|b| b.is_ascii_digit()).count();
+    if end > 0 && name.as_bytes().get(end) == Some(&b'-') {
+        &name[end + 1..]
+    } else {
+        name
     }
+}
+
+/// Get mounted extensions from systemd using JSON format
+fn get_mounted_systemd_extensions(command: &str) -> Result<Vec<MountedExtension>, SystemdError> {
+    let output = run_systemd_command(command, &["status", "--json=short"], None)?;
+    let merged = crate::sysext_status::parse(&output).map_err(|e| SystemdError::CommandFailed {
+        command: format!("{command} status --json=short"),
+        source: std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+    })?;
 
-    // Return default if VERSION_ID not found or file doesn't exist
-    "unknown".to_string()
+    // Strip any "NN-" ordering prefix before storing
+    Ok(merged
+        .into_iter()
+        .map(|m| MountedExtension {
+            name: strip_order_prefix(&m.name).to_string(),
+            hierarchy: m.hierarchy,
+            since: m.since,
+        })
+        .collect())
 }
 
-/// Scan all extension sources in priority order with verbosity control
-fn scan_extensions_from_all_sources_with_verbosity(
-    verbose: bool,
-) -> Result<Vec<Extension>, SystemdError> {
-    let mut extensions = Vec::new();
-    let mut extension_map = std::collections::HashMap::new();
+/// The earliest time `ext_name` was merged by systemd-sysext or
+/// systemd-confext, when systemd reported one.
+fn merged_since(
+    ext_name: &str,
+    mounted_sysext: &[MountedExtension],
+    mounted_confext: &[MountedExtension],
+) -> Option<u64> {
+    mounted_sysext
+        .iter()
+        .chain(mounted_confext)
+        .filter(|e| e.name == ext_name)
+        .filter_map(|e| e.since)
+        .min()
+}
 
-    // Define search paths in priority order: HITL → Runtime/<VERSION_ID> → Directory → Loop-mounted
-    let hitl_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/avocado/hitl")
-    } else {
-        "/run/avocado/hitl".to_string()
+/// Build a JSON representation of all extensions for machine-readable output
+/// The version the repository index offers for `ext` and whether it is an
+/// update; both unset without an index or when it lacks the extension.
+/// Extensions without a detected version fall back to the one in their
+/// name (`app-1.0.0`).
+fn update_hint(
+    config: &Config,
+    index: Option<&crate::repo_index::RepoIndex>,
+    ext: Option<&Extension>,
+) -> (Option<String>, Option<bool>) {
+    let (Some(index), Some(ext)) = (index, ext) else {
+        return (None, None);
+    };
+    let (name, installed) = match &ext.version {
+        Some(version) => (ext.name.clone(), Some(version.clone())),
+        None => split_name_version(&ext.name),
     };
+    let Some(latest) = index.latest(&name) else {
+        return (None, None);
+    };
+    let available = installed.and_then(|installed| {
+        index.update_available(&name, &installed, config.upgrade_prereleases())
+    });
+    (Some(latest.to_string()), available)
+}
 
-    // Read OS VERSION_ID for runtime-specific extensions
-    let version_id = read_os_version_id();
+fn build_extension_json_list(
+    config: &Config,
+    available: &[Extension],
+    mounted_sysext: &[MountedExtension],
+    mounted_confext: &[MountedExtension],
+    manifest_extensions: &[crate::manifest::ManifestExtension],
+    repo_index: Option<&crate::repo_index::RepoIndex>,
+    updates_only: bool,
+) -> Vec<serde_json::Value> {
+    let mut all_extensions = std::collections::HashSet::new();
 
-    // Fallback to the images directory where extension images are installed
-    let extensions_dir = std::env::var("AVOCADO_EXTENSIONS_PATH")
-        .unwrap_or_else(|_| "/var/lib/avocado/images".to_string());
-
-    // 1. First priority: HITL mounted extensions
-    if verbose {
-        println!("Scanning HITL extensions in {hitl_dir}");
-    }
-    if let Ok(hitl_extensions) = scan_directory_extensions(&hitl_dir) {
-        for ext in hitl_extensions {
-            if verbose {
-                println!(
-                    "Found HITL extension: {} at {}",
-                    ext.name,
-                    ext.path.display()
-                );
-            }
-            extension_map.insert(ext.name.clone(), ext);
+    for ext in available {
+        if let Some(ver) = &ext.version {
+            all_extensions.insert(format!("{}-{}", ext.name, ver));
+        } else {
+            all_extensions.insert(ext.name.clone());
         }
     }
+    for ext in mounted_sysext {
+        all_extensions.insert(ext.name.clone());
+    }
+    for ext in mounted_confext {
+        all_extensions.insert(ext.name.clone());
+    }
 
-    // 2. Second priority: Active runtime manifest
-    // If a manifest exists, use it to determine extensions and skip legacy os-releases scanning
-    let base_dir = crate::manifest::RuntimeManifest::base_dir();
-    let base_path = Path::new(&base_dir);
-    let active_manifest = crate::manifest::RuntimeManifest::load_active(base_path);
-    let used_manifest = if let Some(ref manifest) = active_manifest {
-        if verbose {
-            println!(
-                "Found active runtime manifest: {} {} ({})",
-                manifest.runtime.name,
-                manifest.runtime.version,
-                &manifest.id[..8.min(manifest.id.len())]
-            );
-        }
-
-        // Per-runtime user overrides sit alongside the manifest. The
-        // `active` symlink resolves to runtimes/<id>/, so overrides.json
-        // (when present) lives at the same path.
-        let active_dir = base_path.join(crate::manifest::ACTIVE_LINK_NAME);
-        let overrides = crate::overrides::RuntimeOverrides::load(&active_dir);
-
-        let ext_count = manifest.extensions.len();
-        for (index, mext) in manifest.extensions.iter().enumerate() {
-            // Skip extensions the user (or the build) has marked disabled.
-            // `effective_enabled` is the single policy point — never read
-            // `mext.enabled` directly outside of it.
-            if !crate::overrides::effective_enabled(mext, &overrides) {
-                if verbose {
-                    println!(
-                        "Skipping disabled extension '{}' (manifest={}, override={:?})",
-                        mext.name,
-                        mext.enabled,
-                        overrides.enabled_override(&mext.name)
-                    );
-                }
-                continue;
-            }
-            // Inverted index: manifest[0] = highest priority = highest prefix number
-            let merge_idx = ext_count - 1 - index;
-
-            // If HITL version exists, let it inherit the manifest's merge priority
-            if let Some(existing) = extension_map.get_mut(&mext.name) {
-                existing.merge_index = Some(merge_idx);
-                if verbose {
-                    println!(
-                        "HITL extension {} inherits manifest priority #{:02}",
-                        mext.name, merge_idx
-                    );
-                }
-                continue;
-            }
+    let mut sorted: Vec<_> = all_extensions.into_iter().collect();
+    sorted.sort();
+    let symlinks = crate::symlink_map::recorded();
 
-            // Resolve the on-disk path for this extension image
-            let raw_path = mext.resolve_path(base_path);
-            if raw_path.exists() {
-                if raw_path.is_dir() {
-                    if let Ok(dir_exts) =
-                        scan_directory_extensions(raw_path.to_str().unwrap_or_default())
-                    {
-                        for mut ext in dir_exts {
-                            if !extension_map.contains_key(&ext.name) {
-                                ext.merge_index = Some(merge_idx);
-                                if verbose {
-                                    println!(
-                                        "Found manifest extension: {} at {} (priority #{:02})",
-                                        ext.name,
-                                        ext.path.display(),
-                                        merge_idx
-                                    );
-                                }
-                                extension_map.insert(ext.name.clone(), ext);
-                            }
-                        }
-                    }
+    sorted
+        .iter()
+        .filter_map(|ext_name| {
+            let available_ext = available.iter().find(|e| {
+                if let Some(ver) = &e.version {
+                    format!("{}-{}", e.name, ver) == *ext_name
                 } else {
-                    // Image file extension — adaptor selected by manifest image_type
-                    let adaptor = ImageType::from_manifest(&mext.image_type);
-                    match analyze_image_extension(
-                        &mext.name,
-                        &Some(mext.version.clone()),
-                        &raw_path,
-                        &adaptor,
-                        verbose,
-                    ) {
-                        Ok(mut ext) => {
-                            ext.merge_index = Some(merge_idx);
-                            if verbose {
-                                println!(
-                                    "Found manifest extension: {} at {} (priority #{:02})",
-                                    ext.name,
-                                    ext.path.display(),
-                                    merge_idx
-                                );
-                            }
-                            extension_map.insert(ext.name.clone(), ext);
-                        }
-                        Err(e) => {
-                            eprintln!(
-                                "Warning: Failed to analyze manifest extension '{}': {e}",
-                                mext.name
-                            );
-                        }
-                    }
+                    e.name == *ext_name
                 }
-            } else if verbose {
-                let display_name = mext.image_id.as_deref().unwrap_or(&mext.name);
-                eprintln!(
-                    "Warning: Extension image '{}' from manifest not found at {}",
-                    display_name,
-                    raw_path.display()
-                );
-            }
-        }
-
-        true
-    } else {
-        if verbose {
-            println!("No active runtime manifest found, using legacy extension discovery");
-        }
-        false
-    };
-
-    // Legacy extension discovery: only used when no manifest is present
-    if !used_manifest {
-        // 2b. Legacy: OS release-specific extensions (/var/lib/avocado/os-releases/<VERSION_ID>)
-        let os_releases_extensions_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-            let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-            format!("{temp_base}/avocado/os-releases/{version_id}")
-        } else {
-            format!("/var/lib/avocado/os-releases/{version_id}")
-        };
+            });
 
-        if verbose {
-            println!(
-            "Scanning OS release extensions in {os_releases_extensions_dir} (VERSION_ID: {version_id})"
-        );
-        }
+            let is_sysext = mounted_sysext.iter().any(|e| e.name == *ext_name);
+            let is_confext = mounted_confext.iter().any(|e| e.name == *ext_name);
 
-        if !Path::new(&os_releases_extensions_dir).exists() {
-            if verbose {
-                println!(
-                    "OS releases directory {os_releases_extensions_dir} does not exist, skipping"
-                );
-            }
-            if std::env::var("AVOCADO_TEST_MODE").is_err() {
-                eprintln!("Warning: No extensions are enabled for VERSION_ID '{version_id}'. Directory not found: {os_releases_extensions_dir}");
-            }
-        } else {
-            if let Ok(os_releases_extensions) =
-                scan_directory_extensions(&os_releases_extensions_dir)
-            {
-                for ext in os_releases_extensions {
-                    if !extension_map.contains_key(&ext.name) {
-                        if verbose {
-                            println!(
-                                "Found OS release extension: {} at {}",
-                                ext.name,
-                                ext.path.display()
-                            );
-                        }
-                        extension_map.insert(ext.name.clone(), ext);
-                    } else if verbose {
-                        println!(
-                            "Skipping runtime extension {} (higher priority version preferred)",
-                            ext.name
-                        );
-                    }
-                }
-            }
+            let status = match (is_sysext, is_confext) {
+                (true, true) => "MERGED",
+                (true, false) => "SYSEXT",
+                (false, true) => "CONFEXT",
+                (false, false) => unmerged_status(available_ext),
+            };
 
-            if let Ok(os_releases_raw_files) = scan_raw_files(&os_releases_extensions_dir) {
-                for (ext_name, ext_version, ext_path) in os_releases_raw_files {
-                    use std::collections::hash_map::Entry;
-                    match extension_map.entry(ext_name.clone()) {
-                        Entry::Vacant(entry) => {
-                            let adaptor = ImageType::Raw(RawAdaptor);
-                            if let Ok(ext) = analyze_image_extension(
-                                &ext_name,
-                                &ext_version,
-                                &ext_path,
-                                &adaptor,
-                                verbose,
-                            ) {
-                                if verbose {
-                                    println!(
-                                        "Found OS release raw extension: {} at {}",
-                                        ext.name,
-                                        ext.path.display()
-                                    );
-                                }
-                                entry.insert(ext);
-                            }
-                        }
-                        Entry::Occupied(_) => {
-                            if verbose {
-                                println!(
-                        "Skipping OS release raw extension {ext_name} (higher priority version preferred)"
-                    );
-                            }
-                        }
-                    }
+            let mut types = Vec::new();
+            if let Some(ext) = available_ext {
+                if ext.is_sysext {
+                    types.push("sys");
                 }
-            }
-        }
-
-        let os_releases_dir_exists = Path::new(&os_releases_extensions_dir).exists();
-
-        if verbose {
-            println!("Scanning directory extensions in {extensions_dir}");
-        }
-
-        if !os_releases_dir_exists {
-            if verbose {
-                println!("No OS releases directory found, scanning base extensions directory");
-            }
-            if let Ok(dir_extensions) = scan_directory_extensions(&extensions_dir) {
-                for ext in dir_extensions {
-                    if !extension_map.contains_key(&ext.name) {
-                        if verbose {
-                            println!(
-                                "Found directory extension: {} at {}",
-                                ext.name,
-                                ext.path.display()
-                            );
-                        }
-                        extension_map.insert(ext.name.clone(), ext);
-                    } else if verbose {
-                        println!(
-                            "Skipping directory extension {} (HITL or runtime version preferred)",
-                            ext.name
-                        );
-                    }
+                if ext.is_confext {
+                    types.push("conf");
                 }
             }
-        } else if verbose {
-            println!("OS releases directory exists, skipping base extensions directory (use enable/disable to manage extensions)");
-        }
-
-        if verbose {
-            println!("Scanning raw file extensions in {extensions_dir}");
-        }
-
-        if !os_releases_dir_exists {
-            if verbose {
-                println!("No OS releases directory found, scanning base raw files");
-            }
-            let raw_files = scan_raw_files(&extensions_dir)?;
 
-            let mut available_loop_names: Vec<String> = Vec::new();
+            let origin = available_ext
+                .map(get_extension_origin_short)
+                .unwrap_or_else(|| "?".to_string());
 
-            for ext in extension_map.values() {
-                if let Some(ver) = &ext.version {
-                    available_loop_names.push(format!("{}-{}", ext.name, ver));
-                } else {
-                    available_loop_names.push(ext.name.clone());
-                }
-            }
+            let short_id = lookup_extension_short_id(ext_name, manifest_extensions);
 
-            for (name, version, _path) in &raw_files {
-                if let Some(ver) = version {
-                    available_loop_names.push(format!("{name}-{ver}"));
-                } else {
-                    available_loop_names.push(name.clone());
-                }
+            let order = available_ext.and_then(|e| e.merge_index);
+            let provenance = available_ext
+                .map(extension_provenance)
+                .filter(|p| !p.is_empty());
+            let lifecycle = available_ext
+                .map(extension_lifecycle)
+                .filter(|l| !l.is_empty());
+            let (latest_version, update_available) = update_hint(config, repo_index, available_ext);
+            if updates_only && update_available != Some(true) {
+                return None;
             }
 
-            cleanup_stale_mounts(&available_loop_names)?;
-
-            for (ext_name, ext_version, path) in raw_files {
-                match extension_map.entry(ext_name.clone()) {
-                    std::collections::hash_map::Entry::Vacant(entry) => {
-                        if verbose {
-                            println!("Found raw file extension: {ext_name} at {}", path.display());
-                        }
-                        let adaptor = ImageType::Raw(RawAdaptor);
-                        let extension = analyze_image_extension(
-                            &ext_name,
-                            &ext_version,
-                            &path,
-                            &adaptor,
-                            verbose,
-                        )?;
-                        entry.insert(extension);
-                    }
-                    std::collections::hash_map::Entry::Occupied(_) => {
-                        if verbose {
-                            println!(
-                            "Skipping raw file extension {ext_name} (higher priority version preferred)"
-                        );
-                        }
-                    }
-                }
-            }
-        } else if verbose {
-            println!("OS releases directory exists, skipping base raw files (use enable/disable to manage extensions)");
-        }
-    } // end !used_manifest
+            Some(serde_json::json!({
+                "name": ext_name,
+                "order": order,
+                "id": if short_id == "-" { serde_json::Value::Null } else { serde_json::Value::String(short_id) },
+                "status": status,
+                "merged_since": merged_since(ext_name, mounted_sysext, mounted_confext),
+                "type": if types.is_empty() { vec!["?"] } else { types },
+                "origin": origin,
+                "provenance": provenance,
+                "lifecycle": lifecycle,
+                "partitions": available_ext
+                    .filter(|e| !e.partitions.is_empty())
+                    .map(partition_status),
+                "latest_version": latest_version,
+                "update_available": update_available,
+                "aliases": extension_aliases(config, ext_name, available_ext),
+                "loop_device": available_ext.and_then(loop_status),
+                "scopes": available_ext.and_then(extension_scopes),
+                "applicable": available_ext.and_then(extension_applicable),
+                "symlinks": symlinks
+                    .iter()
+                    .filter(|link| link.versioned_name() == *ext_name)
+                    .collect::<Vec<_>>(),
+            }))
+        })
+        .collect()
+}
 
-    // Convert map to vector
-    extensions.extend(extension_map.into_values());
-    Ok(extensions)
+/// Aliases configured for the extension shown as `ext_name`.
+fn extension_aliases(
+    config: &Config,
+    ext_name: &str,
+    available: Option<&Extension>,
+) -> Vec<String> {
+    match available {
+        Some(ext) => config.extension_aliases(&ext.name, ext.version.as_deref()),
+        None => config.extension_aliases(ext_name, None),
+    }
 }
 
-/// Scan a single directory for directory-based extensions
-fn scan_directory_extensions(dir_path: &str) -> Result<Vec<Extension>, SystemdError> {
-    let mut extensions = Vec::new();
+/// Display comprehensive extension status
+fn display_extension_status(
+    config: &Config,
+    available: &[Extension],
+    mounted_sysext: &[MountedExtension],
+    mounted_confext: &[MountedExtension],
+    manifest_extensions: &[crate::manifest::ManifestExtension],
+    repo_index: Option<&crate::repo_index::RepoIndex>,
+    updates_only: bool,
+) -> Result<(), SystemdError> {
+    // Collect all unique extension names (with versions if present)
+    let mut all_extensions = std::collections::HashSet::new();
 
-    if !Path::new(dir_path).exists() {
-        return Ok(extensions);
+    // For available extensions, use versioned name if available
+    for ext in available {
+        if let Some(ver) = &ext.version {
+            all_extensions.insert(format!("{}-{}", ext.name, ver));
+        } else {
+            all_extensions.insert(ext.name.clone());
+        }
     }
 
-    let entries = fs::read_dir(dir_path).map_err(|e| SystemdError::CommandFailed {
-        command: "scan_directory_extensions".to_string(),
-        source: e,
-    })?;
-
-    for entry in entries {
-        let entry = entry.map_err(|e| SystemdError::CommandFailed {
-            command: "scan_directory_extensions".to_string(),
-            source: e,
-        })?;
+    // Add mounted extensions (these already include versions in their names)
+    for ext in mounted_sysext {
+        all_extensions.insert(ext.name.clone());
+    }
+    for ext in mounted_confext {
+        all_extensions.insert(ext.name.clone());
+    }
 
-        let path = entry.path();
+    if all_extensions.is_empty() {
+        println!("No extensions found or mounted.");
+        return Ok(());
+    }
 
-        if path.is_dir() {
-            if let Some(file_name) = path.file_name() {
-                if let Some(name_str) = file_name.to_str() {
-                    let extension = analyze_directory_extension(name_str, &path)?;
-                    extensions.push(extension);
+    // Sort descending by merge_index (highest priority / top layer first).
+    // Extensions without a merge_index sort to the bottom.
+    let mut sorted_extensions: Vec<_> = all_extensions.into_iter().collect();
+    sorted_extensions.sort_by(|a, b| {
+        let idx_a = available
+            .iter()
+            .find(|e| {
+                if let Some(ver) = &e.version {
+                    format!("{}-{}", e.name, ver) == *a
+                } else {
+                    e.name == *a
+                }
+            })
+            .and_then(|e| e.merge_index);
+        let idx_b = available
+            .iter()
+            .find(|e| {
+                if let Some(ver) = &e.version {
+                    format!("{}-{}", e.name, ver) == *b
+                } else {
+                    e.name == *b
                 }
+            })
+            .and_then(|e| e.merge_index);
+        // Descending by index; None sorts last
+        idx_b.cmp(&idx_a).then_with(|| a.cmp(b))
+    });
+
+    let update_of = |ext_name: &str| {
+        let available_ext = available.iter().find(|e| {
+            if let Some(ver) = &e.version {
+                format!("{}-{}", e.name, ver) == ext_name
+            } else {
+                e.name == ext_name
             }
+        });
+        update_hint(config, repo_index, available_ext)
+    };
+    let update_count = sorted_extensions
+        .iter()
+        .filter(|n| update_of(n).1 == Some(true))
+        .count();
+    if updates_only {
+        sorted_extensions.retain(|n| update_of(n).1 == Some(true));
+        if sorted_extensions.is_empty() {
+            println!("No extension updates available.");
+            return Ok(());
         }
     }
 
-    Ok(extensions)
-}
-
-/// Scan a directory for raw file extensions
-fn scan_raw_files(dir_path: &str) -> Result<Vec<(String, Option<String>, PathBuf)>, SystemdError> {
-    let mut raw_files = Vec::new();
-
-    if !Path::new(dir_path).exists() {
-        return Ok(raw_files);
-    }
-
-    let entries = fs::read_dir(dir_path).map_err(|e| SystemdError::CommandFailed {
-        command: "scan_raw_files".to_string(),
-        source: e,
-    })?;
-
-    for entry in entries {
-        let entry = entry.map_err(|e| SystemdError::CommandFailed {
-            command: "scan_raw_files".to_string(),
-            source: e,
-        })?;
+    // Compute dynamic column width from the longest extension name
+    let name_width = sorted_extensions
+        .iter()
+        .map(|n| n.len())
+        .max()
+        .unwrap_or(9)
+        .max(9); // at least as wide as "Extension"
 
-        let path = entry.path();
+    // The update column is only shown with a repository index
+    let update_header = if repo_index.is_some() {
+        format!("{:<12} ", "Update")
+    } else {
+        String::new()
+    };
+    let total_width =
+        6 + name_width + 1 + 10 + 1 + 14 + 1 + 12 + 1 + 14 + 1 + update_header.len() + 10;
 
-        if path.is_file() {
-            if let Some(file_name) = path.file_name() {
-                if let Some(name_str) = file_name.to_str() {
-                    if name_str.ends_with(".raw") {
-                        // Strip .raw suffix to get the extension name (with version)
-                        let ext_name_with_version =
-                            name_str.strip_suffix(".raw").unwrap_or(name_str);
-
-                        // Extract base extension name and version
-                        // Extension name pattern: <name>-<version>.raw -> extract <name> and <version>
-                        let (ext_name, ext_version) =
-                            if let Some(last_dash) = ext_name_with_version.rfind('-') {
-                                // Check if what follows the last dash looks like a version (contains digits or dots)
-                                let potential_version = &ext_name_with_version[last_dash + 1..];
-                                if potential_version
-                                    .chars()
-                                    .any(|c| c.is_ascii_digit() || c == '.')
-                                {
-                                    // This looks like a version, split name and version
-                                    let name = &ext_name_with_version[..last_dash];
-                                    let version = potential_version;
-                                    (name.to_string(), Some(version.to_string()))
-                                } else {
-                                    // No version pattern found, use full name without version
-                                    (ext_name_with_version.to_string(), None)
-                                }
-                            } else {
-                                // No dash found, use full name without version
-                                (ext_name_with_version.to_string(), None)
-                            };
+    // Display header — top-of-stack indicator makes the overlay direction explicit
+    println!("  (high priority / top layer)");
+    println!(
+        "{:<6}{:<nw$} {:<10} {:<14} {:<12} {:<14} {update_header}Origin",
+        "Order",
+        "Extension",
+        "ID",
+        "Status",
+        "Type",
+        "Scope",
+        nw = name_width
+    );
+    println!("{}", "=".repeat(total_width));
 
-                        raw_files.push((ext_name, ext_version, path));
-                    }
-                }
-            }
+    for ext_name in &sorted_extensions {
+        let update_str = match update_of(ext_name) {
+            _ if repo_index.is_none() => String::new(),
+            (Some(latest), Some(true)) => format!("{latest:<12} "),
+            (Some(_), Some(false)) => format!("{:<12} ", "-"),
+            _ => format!("{:<12} ", "?"),
+        };
+        display_extension_info(
+            ext_name,
+            available,
+            mounted_sysext,
+            mounted_confext,
+            manifest_extensions,
+            &update_str,
+            name_width,
+        );
+        let aliases = extension_aliases(
+            config,
+            ext_name,
+            available.iter().find(|e| versioned_name(e) == *ext_name),
+        );
+        if !aliases.is_empty() {
+            println!("      alias: {}", aliases.join(", "));
         }
     }
 
-    Ok(raw_files)
-}
+    println!("  (low priority / base layer)");
 
-/// Analyze an image file extension using the given adaptor for mount/unmount.
-/// This unified function replaces the former `analyze_raw_extension_with_loop` and
-/// `analyze_kab_extension` functions.
-fn analyze_image_extension(
-    name: &str,
-    version: &Option<String>,
-    path: &Path,
-    adaptor: &ImageType,
-    verbose: bool,
-) -> Result<Extension, SystemdError> {
-    if verbose {
-        println!("Analyzing image extension: {name}");
+    // Display summary
+    println!();
+    display_status_summary(available, mounted_sysext, mounted_confext);
+    let out_of_scope: Vec<String> = available
+        .iter()
+        .filter(|ext| extension_applicable(ext) == Some(false))
+        .map(versioned_name)
+        .collect();
+    println!("  Environment: {}", current_environment());
+    if !out_of_scope.is_empty() {
+        print_colored_info(&format!(
+            "Out of scope in {}, not merged: {}",
+            current_environment(),
+            out_of_scope.join(", ")
+        ));
+    }
+    if let Some(index) = repo_index {
+        println!(
+            "  Updates available: {update_count} (repository index from {})",
+            index.url
+        );
     }
 
-    let mount_name = if let Some(ver) = version {
-        format!("{name}-{ver}")
-    } else {
-        name.to_string()
-    };
+    Ok(())
+}
 
-    let mount_point = if adaptor.is_mounted(&mount_name) {
-        if adaptor.needs_remount(&mount_name, path) {
-            if verbose {
-                println!("Backing file changed for {mount_name}, remounting...");
-            }
-            if let Err(e) = adaptor.unmount(&mount_name, verbose) {
-                if verbose {
-                    println!("Warning: failed to unmount stale {mount_name}: {e}");
-                }
-            }
-            adaptor.mount(&mount_name, path, verbose)?
+/// Display information for a single extension; `update_str` is its padded
+/// update column, empty when the column is not shown
+fn display_extension_info(
+    ext_name: &str,
+    available: &[Extension],
+    mounted_sysext: &[MountedExtension],
+    mounted_confext: &[MountedExtension],
+    manifest_extensions: &[crate::manifest::ManifestExtension],
+    update_str: &str,
+    name_width: usize,
+) {
+    // Find extension in available list (match by full versioned name or base name)
+    let available_ext = available.iter().find(|e| {
+        if let Some(ver) = &e.version {
+            format!("{}-{}", e.name, ver) == ext_name
         } else {
-            if verbose {
-                println!("Using existing mount for {mount_name}");
-            }
-            PathBuf::from(extension_mount_point(&mount_name))
+            e.name == ext_name
         }
-    } else {
-        adaptor.mount(&mount_name, path, verbose)?
-    };
-
-    let (sysext_enabled, confext_enabled, _detected_version) =
-        analyze_mounted_extension(name, version, &mount_point);
-
-    Ok(Extension {
-        name: name.to_string(),
-        version: version.clone(),
-        path: mount_point,
-        is_sysext: sysext_enabled,
-        is_confext: confext_enabled,
-        image_type: adaptor.type_tag(),
-        merge_index: None,
-    })
-}
-
-/// Analyze a directory extension to determine if it's sysext, confext, or both
-fn analyze_directory_extension(name: &str, path: &Path) -> Result<Extension, SystemdError> {
-    let (sysext_enabled, confext_enabled, detected_version) =
-        analyze_mounted_extension(name, &None, path);
-
-    Ok(Extension {
-        name: name.to_string(),
-        version: detected_version,
-        path: path.to_path_buf(),
-        is_sysext: sysext_enabled,
-        is_confext: confext_enabled,
-        image_type: ImageTypeTag::Directory,
-        merge_index: None,
-    })
-}
+    });
 
-/// Staging base directory for extension-release overrides used to control merge ordering.
-const EXT_RELEASE_STAGING_DIR: &str = "/run/avocado/ext-release-staging";
+    let sysext_mount = mounted_sysext.iter().find(|e| e.name == ext_name);
+    let confext_mount = mounted_confext.iter().find(|e| e.name == ext_name);
 
-/// Compute the prefixed symlink name for an extension based on its merge index.
-/// When a merge_index is set, returns "NN-name" or "NN-name-version".
-/// Without a merge_index (legacy), returns "name" or "name-version".
-fn compute_prefixed_name(extension: &Extension) -> String {
-    let base_name = if let Some(ver) = &extension.version {
-        format!("{}-{}", extension.name, ver)
-    } else {
-        extension.name.clone()
+    // Determine status
+    let status = match (sysext_mount.is_some(), confext_mount.is_some()) {
+        (true, true) => "MERGED",
+        (true, false) => "SYSEXT",
+        (false, true) => "CONFEXT",
+        (false, false) => unmerged_status(available_ext),
     };
+    let scope = scope_label(available_ext.and_then(extension_scopes).as_deref());
 
-    if let Some(index) = extension.merge_index {
-        format!("{index:02}-{base_name}")
-    } else {
-        base_name
+    // Determine types
+    let mut types = Vec::new();
+    if let Some(ext) = available_ext {
+        if ext.is_sysext {
+            types.push("sys");
+        }
+        if ext.is_confext {
+            types.push("conf");
+        }
     }
-}
-
-/// Stage extension-release files with a prefixed name so systemd recognizes the renamed extension.
-///
-/// For each extension that needs ordering, this:
-/// 1. Creates a staging directory with copies of the original extension-release.d contents
-/// 2. Adds a new extension-release file named to match the prefixed symlink name
-/// 3. Bind mounts the staging directory over the original extension-release.d
-///
-/// This allows systemd-sysext/confext to find extension-release.{prefixed-name} even though
-/// the extension image was built with extension-release.{original-name}.
-fn stage_extension_release(
-    extension: &Extension,
-    prefixed_name: &str,
-    verbose: bool,
-) -> Result<(), SystemdError> {
-    let staging_base = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/avocado/ext-release-staging")
+    let type_str = if types.is_empty() {
+        "?".to_string()
     } else {
-        EXT_RELEASE_STAGING_DIR.to_string()
+        let base = types.join("+");
+        if available_ext.is_some_and(|e| e.image_type == ImageTypeTag::Kab) {
+            format!("kab:{base}")
+        } else {
+            base
+        }
     };
 
-    // Determine the original extension-release name (without prefix)
-    let original_name = if let Some(ver) = &extension.version {
-        format!("{}-{}", extension.name, ver)
+    // Determine origin
+    let origin = if let Some(ext) = available_ext {
+        get_extension_origin_short(ext)
     } else {
-        extension.name.clone()
+        "?".to_string()
     };
 
-    // Handle sysext release directory
-    if extension.is_sysext {
-        let original_release_dir = extension.path.join("usr/lib/extension-release.d");
-        if original_release_dir.exists() {
-            let staging_dir = PathBuf::from(&staging_base)
-                .join(prefixed_name)
-                .join("sysext");
-            fs::create_dir_all(&staging_dir).map_err(|e| SystemdError::CommandFailed {
-                command: "create_dir_all (sysext staging)".to_string(),
-                source: e,
-            })?;
-
-            // Copy all existing files from original release dir
-            if let Ok(entries) = fs::read_dir(&original_release_dir) {
-                for entry in entries.flatten() {
-                    if entry.path().is_file() {
-                        let dest = staging_dir.join(entry.file_name());
-                        fs::copy(entry.path(), &dest).map_err(|e| SystemdError::CommandFailed {
-                            command: format!("copy extension-release file {:?}", entry.file_name()),
-                            source: e,
-                        })?;
-                    }
-                }
-            }
+    // Look up short image ID from manifest extensions
+    let short_id = lookup_extension_short_id(ext_name, manifest_extensions);
 
-            // Create the prefixed release file by copying content from original
-            let original_release =
-                original_release_dir.join(format!("extension-release.{original_name}"));
-            // Also try without version if versioned doesn't exist
-            let original_release = if original_release.exists() {
-                original_release
-            } else {
-                original_release_dir.join(format!("extension-release.{}", extension.name))
-            };
+    // Show merge order if available
+    let order_str = if let Some(ext) = available_ext {
+        if let Some(idx) = ext.merge_index {
+            format!("#{idx:02}")
+        } else {
+            "-".to_string()
+        }
+    } else {
+        "-".to_string()
+    };
 
-            let prefixed_release = staging_dir.join(format!("extension-release.{prefixed_name}"));
-            if original_release.exists() && !prefixed_release.exists() {
-                fs::copy(&original_release, &prefixed_release).map_err(|e| {
-                    SystemdError::CommandFailed {
-                        command: "copy prefixed extension-release (sysext)".to_string(),
-                        source: e,
-                    }
-                })?;
-            }
+    println!(
+        "{order_str:<6}{ext_name:<name_width$} {short_id:<10} {status:<14} {type_str:<12} {scope:<14} {update_str}{origin}"
+    );
+    for partition in available_ext.map(partition_status).unwrap_or_default() {
+        println!("{:6}  {}", "", crate::ddi::describe(&partition));
+    }
+}
 
-            // Bind mount staging dir over original release dir
-            run_bind_mount(
-                staging_dir.to_str().unwrap_or_default(),
-                original_release_dir.to_str().unwrap_or_default(),
-                verbose,
-            )?;
-        }
-    }
-
-    // Handle confext release directory
-    if extension.is_confext {
-        let original_release_dir = extension.path.join("etc/extension-release.d");
-        if original_release_dir.exists() {
-            let staging_dir = PathBuf::from(&staging_base)
-                .join(prefixed_name)
-                .join("confext");
-            fs::create_dir_all(&staging_dir).map_err(|e| SystemdError::CommandFailed {
-                command: "create_dir_all (confext staging)".to_string(),
-                source: e,
-            })?;
-
-            // Copy all existing files from original release dir
-            if let Ok(entries) = fs::read_dir(&original_release_dir) {
-                for entry in entries.flatten() {
-                    if entry.path().is_file() {
-                        let dest = staging_dir.join(entry.file_name());
-                        fs::copy(entry.path(), &dest).map_err(|e| SystemdError::CommandFailed {
-                            command: format!("copy extension-release file {:?}", entry.file_name()),
-                            source: e,
-                        })?;
-                    }
-                }
-            }
+/// Look up the short image ID (first 8 chars) for an extension by matching
+/// the versioned name (e.g. "app-0.2.0") against manifest extension entries.
+fn lookup_extension_short_id(
+    ext_name: &str,
+    manifest_extensions: &[crate::manifest::ManifestExtension],
+) -> String {
+    let matched = manifest_extensions.iter().find(|me| {
+        let versioned = format!("{}-{}", me.name, me.version);
+        versioned == ext_name || me.name == ext_name
+    });
+    match matched {
+        Some(me) => match &me.image_id {
+            Some(id) if id.len() >= 8 => id[..8].to_string(),
+            Some(id) => id.clone(),
+            None => "-".to_string(),
+        },
+        None => "-".to_string(),
+    }
+}
 
-            let original_release =
-                original_release_dir.join(format!("extension-release.{original_name}"));
-            let original_release = if original_release.exists() {
-                original_release
-            } else {
-                original_release_dir.join(format!("extension-release.{}", extension.name))
-            };
+/// Display status summary
+fn display_status_summary(
+    available: &[Extension],
+    mounted_sysext: &[MountedExtension],
+    mounted_confext: &[MountedExtension],
+) {
+    let hitl_count = available
+        .iter()
+        .filter(|e| e.path.to_string_lossy().contains("/hitl"))
+        .count();
+    let directory_count = available
+        .iter()
+        .filter(|e| {
+            e.image_type == ImageTypeTag::Directory && !e.path.to_string_lossy().contains("/hitl")
+        })
+        .count();
+    let loop_count = available
+        .iter()
+        .filter(|e| e.image_type != ImageTypeTag::Directory)
+        .count();
 
-            let prefixed_release = staging_dir.join(format!("extension-release.{prefixed_name}"));
-            if original_release.exists() && !prefixed_release.exists() {
-                fs::copy(&original_release, &prefixed_release).map_err(|e| {
-                    SystemdError::CommandFailed {
-                        command: "copy prefixed extension-release (confext)".to_string(),
-                        source: e,
-                    }
-                })?;
-            }
+    let unique_sysext: std::collections::HashSet<&str> =
+        mounted_sysext.iter().map(|e| e.name.as_str()).collect();
+    let unique_confext: std::collections::HashSet<&str> =
+        mounted_confext.iter().map(|e| e.name.as_str()).collect();
 
-            run_bind_mount(
-                staging_dir.to_str().unwrap_or_default(),
-                original_release_dir.to_str().unwrap_or_default(),
-                verbose,
-            )?;
-        }
+    println!("Summary:");
+    println!("  Available Extensions: {} total", available.len());
+    println!("    - HITL mounted: {hitl_count}");
+    println!("    - Local directories: {directory_count}");
+    println!("    - Loop devices: {loop_count}");
+    println!("  Mounted Extensions:");
+    println!(
+        "    - System extensions: {}{}",
+        unique_sysext.len(),
+        since_suffix(mounted_sysext)
+    );
+    println!(
+        "    - Configuration extensions: {}{}",
+        unique_confext.len(),
+        since_suffix(mounted_confext)
+    );
+
+    if hitl_count > 0 {
+        print_colored_info("HITL extensions are active - development mode");
     }
+}
 
-    Ok(())
+/// ` (since <time>)` for the latest merge systemd reported among `mounted`,
+/// empty when it reported none.
+fn since_suffix(mounted: &[MountedExtension]) -> String {
+    mounted
+        .iter()
+        .filter_map(|e| e.since)
+        .max()
+        .map(|since| format!(" (since {})", crate::clock::format_time(since)))
+        .unwrap_or_default()
 }
 
-/// Execute a bind mount, or simulate in test mode.
-fn run_bind_mount(source: &str, target: &str, verbose: bool) -> Result<(), SystemdError> {
-    if verbose {
-        println!("Bind mounting {source} -> {target}");
-    }
+/// Format status output from systemd commands
+fn format_status_output(output: &str) {
+    let lines: Vec<&str> = output.lines().collect();
 
-    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        // In test mode, skip actual mount syscall
-        return Ok(());
+    // Skip the header line if present and process the data
+    let data_lines: Vec<&str> = lines
+        .iter()
+        .skip_while(|line| line.starts_with("HIERARCHY") || line.trim().is_empty())
+        .copied()
+        .collect();
+
+    if data_lines.is_empty() {
+        println!("No extensions currently merged.");
+        return;
     }
 
-    let output = ProcessCommand::new("mount")
-        .args(["--bind", source, target])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| SystemdError::CommandFailed {
-            command: "mount --bind".to_string(),
-            source: e,
-        })?;
+    for line in data_lines {
+        if line.trim().is_empty() {
+            continue;
+        }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(SystemdError::CommandExitedWithError {
-            command: format!("mount --bind {source} {target}"),
-            exit_code: output.status.code(),
-            stderr: stderr.to_string(),
-        });
-    }
+        // Parse the line format: HIERARCHY EXTENSIONS SINCE
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 3 {
+            let hierarchy = parts[0];
+            let extensions = parts[1];
+            let since = parts[2..].join(" ");
 
-    Ok(())
+            println!("  {hierarchy} -> {extensions} (since {since})");
+        } else {
+            // Fallback: just print the line as-is
+            println!("  {line}");
+        }
+    }
 }
 
-/// Create target directories for symlinks
-fn create_target_directories() -> Result<(), SystemdError> {
-    let (sysext_dir, confext_dir) = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        // In test mode, use temporary directories
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        (
-            format!("{temp_base}/test_extensions"),
-            format!("{temp_base}/test_confexts"),
-        )
-    } else {
-        ("/run/extensions".to_string(), "/run/confexts".to_string())
+/// Print the plan a merge would apply, without applying it.
+pub fn print_merge_plan(config: &Config, output: &OutputManager) {
+    let plan = match scan_merge_state(config, output) {
+        Ok(scan) => plan_merge(&scan),
+        Err(e) => {
+            output.error_with(
+                "Merge Plan",
+                &format!("Failed to scan extensions: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    };
+    let tasks = match plan_post_merge(&plan.enabled, None, output) {
+        Ok(tasks) => tasks,
+        Err(e) => {
+            output.error_with(
+                "Merge Plan",
+                &format!("Failed to plan post-merge tasks: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
     };
 
-    // Create /run/extensions (or test equivalent) if it doesn't exist
-    if !Path::new(&sysext_dir).exists() {
-        fs::create_dir_all(&sysext_dir).map_err(|e| SystemdError::CommandFailed {
-            command: "create_dir_all".to_string(),
-            source: e,
-        })?;
+    if output.is_json() {
+        let json = serde_json::json!({
+            "actions": plan.actions,
+            "tasks": tasks,
+            "extensions": plan.enabled.iter().map(|ext| ext.name.as_str()).collect::<Vec<_>>(),
+            "failed": plan.failed,
+        });
+        output.result_fields(&json);
+        return;
     }
 
-    // Create /run/confexts (or test equivalent) if it doesn't exist
-    if !Path::new(&confext_dir).exists() {
-        fs::create_dir_all(&confext_dir).map_err(|e| SystemdError::CommandFailed {
-            command: "create_dir_all".to_string(),
-            source: e,
-        })?;
+    if plan.actions.is_empty() {
+        println!("Nothing to do: no extensions found in any source location.");
+        return;
+    }
+    println!("Planned actions (dry run, nothing changed):");
+    for action in &plan.actions {
+        println!("  {action}");
+    }
+    if tasks.is_empty() {
+        println!("Then: merge with systemd-sysext and systemd-confext");
+    } else {
+        println!("Then: merge with systemd-sysext and systemd-confext, and:");
+        for task in &tasks {
+            println!("  {task}");
+        }
+    }
+    for failure in &plan.failed {
+        println!(
+            "Skipped (--keep-going): {}: {}",
+            failure.extension, failure.error
+        );
     }
-
-    Ok(())
 }
 
-/// Create a symlink for a sysext extension with verbosity control.
-/// The `symlink_name` parameter is the (possibly prefixed) name to use for the symlink.
-fn create_sysext_symlink_with_verbosity(
-    extension: &Extension,
-    symlink_name: &str,
-    verbose: bool,
-) -> Result<(), SystemdError> {
-    let sysext_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/test_extensions")
-    } else {
-        "/run/extensions".to_string()
+/// Print how the extensions a merge would link now differ from those of the
+/// last merge.
+fn print_merge_diff(config: &Config, output: &OutputManager) {
+    let plan = match scan_merge_state(config, output) {
+        Ok(scan) => plan_merge(&scan),
+        Err(e) => {
+            output.error_with(
+                "Extension Diff",
+                &format!("Failed to scan extensions: {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    };
+    let current = merge_inputs(&plan.enabled, config);
+    let previous = crate::merge_inputs::MergeInputs::load();
+    let changes = match &previous {
+        Some(previous) => current.image_changes(previous),
+        None => current.image_changes(&crate::merge_inputs::MergeInputs {
+            images: Vec::new(),
+            ..current.clone()
+        }),
     };
 
-    let target_path = format!("{sysext_dir}/{symlink_name}");
-
-    // Remove existing symlink or file if it exists
-    if Path::new(&target_path).exists() {
-        let path = Path::new(&target_path);
+    if output.is_json() {
+        let json = serde_json::json!({
+            "recorded": previous.is_some(),
+            "changes": changes,
+        });
+        output.result_fields(&json);
+        return;
+    }
 
-        // Try to remove as file first (works for symlinks and regular files)
-        if fs::remove_file(&target_path).is_err() {
-            // If that fails, it might be a directory
-            if path.is_dir() {
-                fs::remove_dir_all(&target_path).map_err(|e| SystemdError::CommandFailed {
-                    command: "remove_dir_all".to_string(),
-                    source: e,
-                })?;
+    if previous.is_none() {
+        println!("No record of the last merge; every enabled extension is new.");
+    }
+    if changes.is_empty() {
+        println!("No changes since the last merge.");
+        return;
+    }
+    for change in &changes {
+        let line = match change {
+            crate::merge_inputs::ImageChange::Added { name } => format!("+ {name}"),
+            crate::merge_inputs::ImageChange::Removed { name } => format!("- {name}"),
+            crate::merge_inputs::ImageChange::Changed { name } => {
+                format!("~ {name} (image changed)")
             }
-        }
+            crate::merge_inputs::ImageChange::Retyped { name } => {
+                format!("~ {name} (type changed)")
+            }
+        };
+        println!("  {line}");
     }
+}
 
-    // Create symlink
-    unix_fs::symlink(&extension.path, &target_path).map_err(|e| SystemdError::CommandFailed {
-        command: "symlink".to_string(),
-        source: e,
-    })?;
-
-    if verbose {
-        println!(
-            "Created sysext symlink: {} -> {}",
-            target_path,
-            extension.path.display()
-        );
-    }
-    Ok(())
+/// What a refresh would do now, for `avocadoctl plan`.
+pub(crate) struct RefreshPreview {
+    /// Extensions linked after the refresh, as `name-version`.
+    pub(crate) extensions: Vec<String>,
+    /// Link changes, in order.
+    pub(crate) actions: Vec<String>,
+    /// Hook commands run, on-unmerge first: (phase, command, extensions).
+    pub(crate) hooks: Vec<(&'static str, String, Vec<String>)>,
 }
 
-/// Create a symlink for a confext extension with verbosity control.
-/// The `symlink_name` parameter is the (possibly prefixed) name to use for the symlink.
-fn create_confext_symlink_with_verbosity(
-    extension: &Extension,
-    symlink_name: &str,
-    verbose: bool,
-) -> Result<(), SystemdError> {
-    let confext_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/test_confexts")
-    } else {
-        "/run/confexts".to_string()
-    };
+/// Compute what a refresh would do, without changing anything.
+pub(crate) fn preview_refresh(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<RefreshPreview, SystemdError> {
+    let plan = plan_merge(&scan_merge_state(config, output)?);
+    let hooks = on_unmerge_hook_owners()
+        .into_iter()
+        .map(|(command, owners)| ("on-unmerge", command, owners))
+        .chain(
+            on_merge_hook_owners(&plan.enabled)
+                .into_iter()
+                .map(|(command, owners)| ("on-merge", command, owners)),
+        )
+        .collect();
+    Ok(RefreshPreview {
+        extensions: plan
+            .enabled
+            .iter()
+            .map(|ext| match &ext.version {
+                Some(version) => format!("{}-{version}", ext.name),
+                None => ext.name.clone(),
+            })
+            .collect(),
+        actions: plan.actions.iter().map(ToString::to_string).collect(),
+        hooks,
+    })
+}
 
-    let target_path = format!("{confext_dir}/{symlink_name}");
+/// Prepare the extension environment by setting up symlinks with output manager
+fn prepare_extension_environment_with_output(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<Vec<Extension>, SystemdError> {
+    output.step("Environment", "Preparing extension environment");
 
-    // Remove existing symlink or file if it exists
-    if Path::new(&target_path).exists() {
-        let path = Path::new(&target_path);
+    let scan = scan_merge_state(config, output)?;
+    let plan = plan_merge(&scan);
 
-        // Try to remove as file first (works for symlinks and regular files)
-        if fs::remove_file(&target_path).is_err() {
-            // If that fails, it might be a directory
-            if path.is_dir() {
-                fs::remove_dir_all(&target_path).map_err(|e| SystemdError::CommandFailed {
-                    command: "remove_dir_all".to_string(),
-                    source: e,
-                })?;
-            }
-        }
+    if plan.enabled.is_empty() {
+        output.progress("No extensions found in any source location");
     }
 
-    // Create symlink
-    unix_fs::symlink(&extension.path, &target_path).map_err(|e| SystemdError::CommandFailed {
-        command: "symlink".to_string(),
-        source: e,
-    })?;
+    apply_merge_plan(&plan, config.limits(), output)?;
+    record_merge_failures(&plan.failed, output);
+    if let Err(e) = crate::safe_mode::record_skipped(&scan.safe_mode_skipped) {
+        output.warning(&format!(
+            "Failed to record extensions skipped in safe mode: {e}"
+        ));
+    }
 
-    if verbose {
-        println!(
-            "Created confext symlink: {} -> {}",
-            target_path,
-            extension.path.display()
-        );
+    if !plan.enabled.is_empty() {
+        output.progress("Extension environment prepared successfully");
     }
-    Ok(())
+    Ok(plan.enabled)
 }
 
-/// Cleanup stale loop refs and KAB loops for extensions that no longer exist.
-fn cleanup_stale_mounts(available_extensions: &[String]) -> Result<(), SystemdError> {
-    // Skip cleanup in test mode to avoid interfering with system loops
-    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        return Ok(());
+/// Record the extensions `--keep-going` left out of this merge (none
+/// clears the last merge's record).
+fn record_merge_failures(failed: &[Failure], output: &OutputManager) {
+    if let Err(e) = crate::merge_failures::record(failed) {
+        output.warning(&format!("Failed to record merge failures: {e}"));
     }
+}
 
-    // Clean up stale raw loop refs
-    let loop_ref_dir = "/dev/disk/by-loop-ref";
-    if Path::new(loop_ref_dir).exists() {
-        let entries = fs::read_dir(loop_ref_dir).map_err(|e| SystemdError::CommandFailed {
-            command: "read_dir".to_string(),
-            source: e,
-        })?;
-
-        let raw = RawAdaptor;
-        for entry in entries.flatten() {
-            if let Some(loop_name) = entry.file_name().to_str() {
-                if !available_extensions.contains(&loop_name.to_string()) {
-                    println!("Cleaning up stale raw loop for: {loop_name}");
-                    raw.unmount(loop_name, false)?;
-                }
-            }
-        }
-    }
+/// Split the os-releases directories under `root` into those `ext
+/// prune-os-releases` keeps and those it removes, each sorted by VERSION_ID.
+/// The running VERSION_ID `current` is always kept; `keep` adds `previous`
+/// (the closest earlier VERSION_ID, the one on the other A/B slot) and
+/// explicit VERSION_IDs.
+pub(crate) fn plan_os_release_prune(
+    root: &Path,
+    current: &str,
+    keep: &[String],
+) -> (Vec<String>, Vec<String>) {
+    let mut versions: Vec<String> = fs::read_dir(root)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().is_dir() && !e.path().is_symlink())
+                .filter_map(|e| e.file_name().to_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default();
+    versions.sort_by(|a, b| compare_version_ids(a, b));
 
-    // Clean up stale KAB offset loops
-    let kab_loops_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/avocado/kab-loops")
-    } else {
-        "/run/avocado/kab-loops".to_string()
-    };
+    let previous = keep
+        .iter()
+        .any(|k| k == "previous")
+        .then(|| find_previous_os_release_dir(root, current))
+        .flatten()
+        .and_then(|p| p.file_name()?.to_str().map(String::from));
+    versions
+        .into_iter()
+        .partition(|v| v == current || previous.as_deref() == Some(v.as_str()) || keep.contains(v))
+}
 
-    if Path::new(&kab_loops_dir).exists() {
-        if let Ok(entries) = fs::read_dir(&kab_loops_dir) {
-            let kab = KabAdaptor;
-            for entry in entries.flatten() {
-                if let Some(loop_name) = entry.file_name().to_str() {
-                    if !available_extensions.contains(&loop_name.to_string()) {
-                        println!("Cleaning up stale KAB loop for: {loop_name}");
-                        let _ = kab.unmount(loop_name, false);
-                    }
+/// Bind every extension linked on the host into the same link directory
+/// inside a merge target
+fn publish_links_to_target(
+    target: &MergeTarget,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    for (kind, target_dir) in [
+        (LinkKind::Sysext, "/run/extensions"),
+        (LinkKind::Confext, "/run/confexts"),
+    ] {
+        let Ok(entries) = fs::read_dir(kind.dir()) else {
+            continue;
+        };
+        let mut names: Vec<String> = entries
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        for name in names {
+            let link = Path::new(&kind.dir()).join(&name);
+            let source = match fs::canonicalize(&link) {
+                Ok(source) => source,
+                Err(e) => {
+                    output.progress(&format!(
+                        "Warning: Skipping {} {name} for {target}: {e}",
+                        kind.label()
+                    ));
+                    continue;
                 }
-            }
+            };
+            target.publish(target_dir, &name, &source, output.is_verbose())?;
+            output.progress(&format!("Published {} {name} to {target}", kind.label()));
         }
     }
-
     Ok(())
 }
 
@@ -3096,7 +5114,7 @@ fn cleanup_extension_release_staging(output: &OutputManager) -> Result<(), Syste
         let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/avocado/ext-release-staging")
     } else {
-        EXT_RELEASE_STAGING_DIR.to_string()
+        crate::user_mode::system_path(EXT_RELEASE_STAGING_DIR)
     };
 
     if !Path::new(&staging_base).exists() {
@@ -3106,13 +5124,13 @@ fn cleanup_extension_release_staging(output: &OutputManager) -> Result<(), Syste
     if std::env::var("AVOCADO_TEST_MODE").is_err() {
         // Unmount bind mounts over extension-release.d directories.
         // These are bind mounts from the staging dir onto the extension's release dir.
-        let ext_mount_base = "/run/avocado/extensions";
+        let ext_mount_base = crate::user_mode::system_path("/run/avocado/extensions");
         if let Ok(mounts_content) = fs::read_to_string("/proc/mounts") {
             for line in mounts_content.lines() {
                 let parts: Vec<&str> = line.split_whitespace().collect();
                 if parts.len() >= 2 {
                     let mount_point = parts[1];
-                    if mount_point.starts_with(ext_mount_base)
+                    if mount_point.starts_with(&ext_mount_base)
                         && mount_point.contains("extension-release.d")
                     {
                         let result = ProcessCommand::new("umount")
@@ -3160,7 +5178,7 @@ fn cleanup_extension_symlinks(output: &OutputManager) -> Result<(), SystemdError
         let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/test_extensions")
     } else {
-        "/run/extensions".to_string()
+        crate::user_mode::system_path("/run/extensions")
     };
 
     cleanup_symlinks_in_directory(&sysext_dir, output)?;
@@ -3170,11 +5188,14 @@ fn cleanup_extension_symlinks(output: &OutputManager) -> Result<(), SystemdError
         let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/test_confexts")
     } else {
-        "/run/confexts".to_string()
+        crate::user_mode::system_path("/run/confexts")
     };
 
     cleanup_symlinks_in_directory(&confext_dir, output)?;
 
+    if let Err(e) = crate::symlink_map::record(&[]) {
+        output.progress(&format!("Warning: Failed to clear symlink map: {e}"));
+    }
     output.progress("Extension symlinks cleaned up");
     Ok(())
 }
@@ -3182,518 +5203,324 @@ fn cleanup_extension_symlinks(output: &OutputManager) -> Result<(), SystemdError
 /// Clean up all symlinks in a specific directory
 fn cleanup_symlinks_in_directory(
     directory: &str,
-    output: &OutputManager,
-) -> Result<(), SystemdError> {
-    if !Path::new(directory).exists() {
-        return Ok(());
-    }
-
-    let entries = fs::read_dir(directory).map_err(|e| SystemdError::CommandFailed {
-        command: "read_dir".to_string(),
-        source: e,
-    })?;
-
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if path.is_symlink() {
-            if let Err(e) = fs::remove_file(&path) {
-                output.progress(&format!(
-                    "Warning: Failed to remove symlink {}: {}",
-                    path.display(),
-                    e
-                ));
-            } else {
-                output.progress(&format!("Removed symlink: {}", path.display()));
-            }
-        }
-    }
-
-    Ok(())
-}
-
-/// Verify that extension directories are clean before merge
-fn verify_clean_extension_environment(output: &OutputManager) -> Result<(), SystemdError> {
-    let sysext_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/test_extensions")
-    } else {
-        "/run/extensions".to_string()
-    };
-
-    let confext_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/test_confexts")
-    } else {
-        "/run/confexts".to_string()
-    };
-
-    // Check for stale symlinks in sysext directory
-    if let Some(stale_symlinks) = check_for_stale_symlinks(&sysext_dir)? {
-        output.progress(&format!(
-            "Warning: Found {} stale symlinks in {}, cleaning up",
-            stale_symlinks.len(),
-            sysext_dir
-        ));
-        cleanup_symlinks_in_directory(&sysext_dir, output)?;
-    }
-
-    // Check for stale symlinks in confext directory
-    if let Some(stale_symlinks) = check_for_stale_symlinks(&confext_dir)? {
-        output.progress(&format!(
-            "Warning: Found {} stale symlinks in {}, cleaning up",
-            stale_symlinks.len(),
-            confext_dir
-        ));
-        cleanup_symlinks_in_directory(&confext_dir, output)?;
-    }
-
-    Ok(())
-}
-
-/// Check for stale symlinks in a directory
-fn check_for_stale_symlinks(directory: &str) -> Result<Option<Vec<String>>, SystemdError> {
-    if !Path::new(directory).exists() {
-        return Ok(None);
-    }
-
-    let entries = fs::read_dir(directory).map_err(|e| SystemdError::CommandFailed {
-        command: "read_dir".to_string(),
-        source: e,
-    })?;
-
-    let mut stale_symlinks = Vec::new();
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if path.is_symlink() {
-            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                stale_symlinks.push(name.to_string());
-            }
-        }
-    }
-
-    if stale_symlinks.is_empty() {
-        Ok(None)
-    } else {
-        Ok(Some(stale_symlinks))
-    }
-}
-
-/// Scan release files for only the enabled extensions
-fn scan_release_files_for_enabled_extensions(
-    enabled_extensions: &[Extension],
-) -> Result<(Vec<String>, Vec<String>), SystemdError> {
-    let mut on_merge_commands = Vec::new();
-    let mut modprobe_modules = Vec::new();
-
-    // Handle test mode with custom release directory (for backwards compatibility)
-    if let Ok(custom_dir) = std::env::var("AVOCADO_EXTENSION_RELEASE_DIR") {
-        return scan_custom_release_directory(&custom_dir);
-    }
-
-    for extension in enabled_extensions {
-        // Scan release files from each enabled extension mount point
-        scan_extension_release_files(extension, &mut on_merge_commands, &mut modprobe_modules)?;
-    }
-
-    Ok((on_merge_commands, modprobe_modules))
-}
-
-/// Scan release files from a custom directory (test mode)
-fn scan_custom_release_directory(
-    custom_dir: &str,
-) -> Result<(Vec<String>, Vec<String>), SystemdError> {
-    let mut on_merge_commands = Vec::new();
-    let mut modprobe_modules = Vec::new();
-
-    let custom_path = Path::new(custom_dir);
-    let mut dirs: Vec<(String, Option<&str>)> = Vec::new();
-
-    // Check if it's a single directory with release files (legacy behavior)
-    if custom_path.join("extension-release.d").exists() {
-        dirs.push((custom_dir.to_string(), None));
-    } else {
-        // Look for sysext and confext subdirectories
-        let sysext_dir = custom_path.join("usr/lib/extension-release.d");
-        let confext_dir = custom_path.join("etc/extension-release.d");
-
-        if sysext_dir.exists() {
-            dirs.push((
-                sysext_dir.to_string_lossy().to_string(),
-                Some("SYSEXT_SCOPE"),
-            ));
-        }
-        if confext_dir.exists() {
-            dirs.push((
-                confext_dir.to_string_lossy().to_string(),
-                Some("CONFEXT_SCOPE"),
-            ));
-        }
-
-        // If neither subdirectory structure exists, use the custom dir directly
-        if dirs.is_empty() {
-            dirs.push((custom_dir.to_string(), None));
-        }
-    }
-
-    for (release_dir, scope_key) in &dirs {
-        scan_directory_for_release_files(
-            release_dir,
-            &mut on_merge_commands,
-            &mut modprobe_modules,
-            *scope_key,
-        );
-    }
-
-    Ok((on_merge_commands, modprobe_modules))
-}
-
-/// Scan release files from a specific extension's trusted mount point.
-/// Only processes sysext release files if the extension is enabled as sysext for the
-/// current scope, and confext release files if enabled as confext for the current scope.
-/// Also verifies scope from the release file content as defense in depth.
-fn scan_extension_release_files(
-    extension: &Extension,
-    on_merge_commands: &mut Vec<String>,
-    modprobe_modules: &mut Vec<String>,
-) -> Result<(), SystemdError> {
-    if extension.is_sysext {
-        // Check for sysext release file - try both versioned and non-versioned
-        let sysext_release_path = extension
-            .path
-            .join("usr/lib/extension-release.d")
-            .join(format!("extension-release.{}", extension.name));
-
-        if sysext_release_path.exists() {
-            if let Ok(content) = fs::read_to_string(&sysext_release_path) {
-                if is_scope_enabled_for_current_environment(&content, "SYSEXT_SCOPE") {
-                    let mut commands = parse_avocado_on_merge_commands(&content);
-                    on_merge_commands.append(&mut commands);
-
-                    let mut modules = parse_avocado_modprobe(&content);
-                    modprobe_modules.append(&mut modules);
-                }
-            }
-        } else {
-            // Try to find versioned release file
-            let sysext_dir = extension.path.join("usr/lib/extension-release.d");
-            if sysext_dir.exists() {
-                if let Ok(entries) = fs::read_dir(&sysext_dir) {
-                    for entry in entries.flatten() {
-                        let filename = entry.file_name();
-                        let filename_str = filename.to_string_lossy();
-                        if filename_str
-                            .starts_with(&format!("extension-release.{}-", extension.name))
-                        {
-                            if let Ok(content) = fs::read_to_string(entry.path()) {
-                                if is_scope_enabled_for_current_environment(
-                                    &content,
-                                    "SYSEXT_SCOPE",
-                                ) {
-                                    let mut commands = parse_avocado_on_merge_commands(&content);
-                                    on_merge_commands.append(&mut commands);
-
-                                    let mut modules = parse_avocado_modprobe(&content);
-                                    modprobe_modules.append(&mut modules);
-                                }
-                            }
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    if extension.is_confext {
-        // Check for confext release file - try both versioned and non-versioned
-        let confext_release_path = extension
-            .path
-            .join("etc/extension-release.d")
-            .join(format!("extension-release.{}", extension.name));
-
-        if confext_release_path.exists() {
-            if let Ok(content) = fs::read_to_string(&confext_release_path) {
-                if is_scope_enabled_for_current_environment(&content, "CONFEXT_SCOPE") {
-                    let mut commands = parse_avocado_on_merge_commands(&content);
-                    on_merge_commands.append(&mut commands);
-
-                    let mut modules = parse_avocado_modprobe(&content);
-                    modprobe_modules.append(&mut modules);
-                }
-            }
-        } else {
-            // Try to find versioned release file
-            let confext_dir = extension.path.join("etc/extension-release.d");
-            if confext_dir.exists() {
-                if let Ok(entries) = fs::read_dir(&confext_dir) {
-                    for entry in entries.flatten() {
-                        let filename = entry.file_name();
-                        let filename_str = filename.to_string_lossy();
-                        if filename_str
-                            .starts_with(&format!("extension-release.{}-", extension.name))
-                        {
-                            if let Ok(content) = fs::read_to_string(entry.path()) {
-                                if is_scope_enabled_for_current_environment(
-                                    &content,
-                                    "CONFEXT_SCOPE",
-                                ) {
-                                    let mut commands = parse_avocado_on_merge_commands(&content);
-                                    on_merge_commands.append(&mut commands);
-
-                                    let mut modules = parse_avocado_modprobe(&content);
-                                    modprobe_modules.append(&mut modules);
-                                }
-                            }
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(())
-}
-
-/// Scan extension release files for AVOCADO_ENABLE_SERVICES
-/// This is used by HITL to determine which services need mount dependencies
-pub fn scan_extension_for_enable_services(
-    extension_path: &Path,
-    extension_name: &str,
-) -> Vec<String> {
-    let mut services = Vec::new();
-
-    // Check for sysext release file - try both versioned and non-versioned
-    let sysext_release_path = extension_path
-        .join("usr/lib/extension-release.d")
-        .join(format!("extension-release.{extension_name}"));
-
-    if sysext_release_path.exists() {
-        if let Ok(content) = fs::read_to_string(&sysext_release_path) {
-            let mut svc = parse_avocado_enable_services(&content);
-            for s in svc.drain(..) {
-                if !services.contains(&s) {
-                    services.push(s);
-                }
-            }
-        }
-    } else {
-        // Try to find versioned release file
-        let sysext_dir = extension_path.join("usr/lib/extension-release.d");
-        if sysext_dir.exists() {
-            if let Ok(entries) = fs::read_dir(&sysext_dir) {
-                for entry in entries.flatten() {
-                    let filename = entry.file_name();
-                    let filename_str = filename.to_string_lossy();
-                    if filename_str.starts_with(&format!("extension-release.{extension_name}-")) {
-                        if let Ok(content) = fs::read_to_string(entry.path()) {
-                            let mut svc = parse_avocado_enable_services(&content);
-                            for s in svc.drain(..) {
-                                if !services.contains(&s) {
-                                    services.push(s);
-                                }
-                            }
-                        }
-                        break;
-                    }
-                }
-            }
-        }
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    if !Path::new(directory).exists() {
+        return Ok(());
     }
 
-    // Check for confext release file - try both versioned and non-versioned
-    let confext_release_path = extension_path
-        .join("etc/extension-release.d")
-        .join(format!("extension-release.{extension_name}"));
+    let entries = read_dir_sorted(directory).context("read directory", directory)?;
 
-    if confext_release_path.exists() {
-        if let Ok(content) = fs::read_to_string(&confext_release_path) {
-            let mut svc = parse_avocado_enable_services(&content);
-            for s in svc.drain(..) {
-                if !services.contains(&s) {
-                    services.push(s);
-                }
-            }
-        }
-    } else {
-        // Try to find versioned release file
-        let confext_dir = extension_path.join("etc/extension-release.d");
-        if confext_dir.exists() {
-            if let Ok(entries) = fs::read_dir(&confext_dir) {
-                for entry in entries.flatten() {
-                    let filename = entry.file_name();
-                    let filename_str = filename.to_string_lossy();
-                    if filename_str.starts_with(&format!("extension-release.{extension_name}-")) {
-                        if let Ok(content) = fs::read_to_string(entry.path()) {
-                            let mut svc = parse_avocado_enable_services(&content);
-                            for s in svc.drain(..) {
-                                if !services.contains(&s) {
-                                    services.push(s);
-                                }
-                            }
-                        }
-                        break;
-                    }
-                }
+    for entry in entries {
+        let path = entry.path();
+        if path.is_symlink() {
+            if let Err(e) = fs::remove_file(&path) {
+                output.progress(&format!(
+                    "Warning: Failed to remove symlink {}: {}",
+                    path.display(),
+                    e
+                ));
+            } else {
+                output.progress(&format!("Removed symlink: {}", path.display()));
             }
         }
     }
 
-    services
+    Ok(())
 }
 
-/// Scan a directory for release files (used in test mode).
-/// Only includes commands from release files whose scope matches the current environment.
-fn scan_directory_for_release_files(
-    release_dir: &str,
-    on_merge_commands: &mut Vec<String>,
-    modprobe_modules: &mut Vec<String>,
-    scope_key: Option<&str>,
-) {
-    if !Path::new(release_dir).exists() {
-        return;
-    }
+/// Targets of the merged extensions, from the symlink map of the last merge
+fn merged_hook_targets() -> HookTargets {
+    crate::symlink_map::recorded()
+        .into_iter()
+        .map(|link| {
+            let target = HookTarget {
+                name: link.extension.clone(),
+                version: link.version.clone(),
+                mount_point: link.source.clone(),
+            };
+            (link.versioned_name(), target)
+        })
+        .collect()
+}
 
-    if let Ok(entries) = fs::read_dir(release_dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_file() {
-                if let Ok(content) = fs::read_to_string(&path) {
-                    if let Some(key) = scope_key {
-                        if !is_scope_enabled_for_current_environment(&content, key) {
-                            continue;
-                        }
-                    }
-                    let mut commands = parse_avocado_on_merge_commands(&content);
-                    on_merge_commands.append(&mut commands);
+/// Owners of the AVOCADO_ON_UNMERGE commands of the merged extensions
+fn on_unmerge_hook_owners() -> HookOwners {
+    let dirs = match std::env::var("AVOCADO_EXTENSION_RELEASE_DIR") {
+        Ok(custom_dir) => custom_release_dirs(&custom_dir),
+        Err(_) => vec![
+            (
+                "/usr/lib/extension-release.d".to_string(),
+                Some("SYSEXT_SCOPE"),
+            ),
+            (
+                "/etc/extension-release.d".to_string(),
+                Some("CONFEXT_SCOPE"),
+            ),
+        ],
+    };
+    hook_owners_in_dirs(&dirs, parse_avocado_on_unmerge_commands)
+}
+
+/// Build provenance declared by an extension's release files, the sysext
+/// file taking precedence over the confext file.
+fn extension_provenance(extension: &Extension) -> Provenance {
+    extension_release_files(extension)
+        .iter()
+        .fold(Provenance::default(), |provenance, release| {
+            provenance.or(&release.provenance)
+        })
+}
+
+/// Lifecycle keys declared by an extension's release files, the sysext file
+/// taking precedence over the confext file.
+fn extension_lifecycle(extension: &Extension) -> Lifecycle {
+    extension_release_files(extension)
+        .iter()
+        .fold(Lifecycle::default(), |lifecycle, release| {
+            lifecycle.or(&release.lifecycle)
+        })
+}
 
-                    let mut modules = parse_avocado_modprobe(&content);
-                    modprobe_modules.append(&mut modules);
+/// Names of enabled extensions whose release file sets AVOCADO_REBOOT_REQUIRED=yes.
+fn scan_extensions_requiring_reboot(enabled_extensions: &[Extension]) -> Vec<String> {
+    // Handle test mode with custom release directory (for backwards compatibility)
+    if let Ok(custom_dir) = std::env::var("AVOCADO_EXTENSION_RELEASE_DIR") {
+        let mut names = Vec::new();
+        if let Ok(entries) = read_dir_sorted(&custom_dir) {
+            for entry in entries {
+                let filename = entry.file_name().to_string_lossy().to_string();
+                let Some(name) = filename.strip_prefix("extension-release.") else {
+                    continue;
+                };
+                if let Ok(content) = fs::read_to_string(entry.path()) {
+                    if crate::reboot::parse_reboot_required(&content) {
+                        names.push(name.to_string());
+                    }
                 }
             }
         }
+        names.sort();
+        return names;
     }
-}
 
-/// Process post-merge tasks for only the enabled extensions
-/// Commands that must run before daemon-reload so that kernel modules
-/// and shared libraries are available when systemd re-evaluates units.
-const PRE_DAEMON_RELOAD_COMMANDS: &[&str] = &["depmod", "ldconfig"];
-
-/// Check if a command should run before daemon-reload
-fn is_pre_daemon_reload_command(command: &str) -> bool {
-    let first_word = command.split_whitespace().next().unwrap_or("");
-    PRE_DAEMON_RELOAD_COMMANDS.contains(&first_word)
+    enabled_extensions
+        .iter()
+        .filter(|ext| {
+            extension_release_files(ext)
+                .iter()
+                .any(|release| release.reboot_required)
+        })
+        .map(|ext| ext.name.clone())
+        .collect()
 }
 
-fn process_post_merge_tasks_for_extensions(
+/// Record reboot requests from the just-merged extensions and, if configured,
+/// ask systemd to act on them.
+fn handle_reboot_requests(
     enabled_extensions: &[Extension],
+    config: &Config,
     output: &OutputManager,
-) -> Result<(), SystemdError> {
-    let (on_merge_commands, modprobe_modules) =
-        scan_release_files_for_enabled_extensions(enabled_extensions)?;
-
-    // Remove duplicates while preserving order
-    let mut unique_commands = Vec::new();
-    for command in on_merge_commands {
-        if !unique_commands.contains(&command) {
-            unique_commands.push(command);
-        }
+) {
+    let requested = scan_extensions_requiring_reboot(enabled_extensions);
+    if requested.is_empty() {
+        return;
     }
 
-    // Split commands into pre-daemon-reload (depmod, ldconfig) and post-daemon-reload
-    let (pre_reload, post_reload): (Vec<_>, Vec<_>) = unique_commands
-        .into_iter()
-        .partition(|cmd| is_pre_daemon_reload_command(cmd));
+    if let Err(e) = crate::reboot::record(&requested) {
+        output.warning(&format!("Failed to record reboot request: {e}"));
+    }
+    output.log_info(&format!("Reboot required by: {}", requested.join(", ")));
 
-    // Phase 1: Run depmod/ldconfig so modules and libraries are available
-    if !pre_reload.is_empty() {
-        run_avocado_on_merge_commands(&pre_reload, output)?;
+    let action = config.reboot_action();
+    if action != crate::config::RebootAction::None {
+        match crate::reboot::trigger(action) {
+            Ok(()) => output.log_info(&format!("Requested {action:?} from systemd")),
+            Err(e) => output.warning(&e.to_string()),
+        }
     }
+}
 
-    // Phase 2: Load kernel modules (requires depmod to have run first)
-    if !modprobe_modules.is_empty() {
-        run_modprobe(&modprobe_modules, output)?;
+/// After a successful merge/refresh, report any outstanding reboot request
+/// and exit with [`crate::reboot::EXIT_REBOOT_REQUIRED`] so scripts can act on it.
+pub fn exit_if_reboot_required(output: &OutputManager) {
+    let pending = crate::reboot::pending();
+    if pending.is_empty() {
+        return;
+    }
+    if output.is_json() {
+        output.result("reboot_required", &pending);
+    } else {
+        print_colored_info(&format!("Reboot required by: {}", pending.join(", ")));
     }
+    output.exit(crate::reboot::EXIT_REBOOT_REQUIRED);
+}
 
-    // Phase 3: Reload systemd's unit database now that modules and libraries
-    // are available, so units like proc-fs-nfsd.mount can start successfully
-    match std::process::Command::new("systemctl")
-        .arg("daemon-reload")
-        .output()
-    {
-        Ok(result) if result.status.success() => {
-            output.log_info("Reloaded systemd daemon after extension merge");
-        }
-        Ok(result) => {
-            let stderr = String::from_utf8_lossy(&result.stderr);
-            output.log_info(&format!("Warning: daemon-reload failed: {stderr}"));
+/// After a merge or refresh that `--keep-going` left extensions out of,
+/// summarize them and exit with
+/// [`crate::merge_failures::EXIT_PARTIAL_FAILURE`].
+pub fn exit_if_partial_failure(output: &OutputManager) {
+    let failures = crate::merge_failures::recorded();
+    if failures.is_empty() {
+        return;
+    }
+    let reboot_required = crate::reboot::pending();
+    if output.is_json() {
+        output.result("failed", &failures);
+        output.result("reboot_required", &reboot_required);
+        output.json_partial();
+    } else {
+        print_colored_info(&format!(
+            "Merged with failures: {} extension(s) skipped",
+            failures.len()
+        ));
+        for failure in &failures {
+            println!("  {}: {}", failure.extension, failure.error);
         }
-        Err(e) => {
-            output.log_info(&format!("Warning: Failed to run daemon-reload: {e}"));
+        if !reboot_required.is_empty() {
+            print_colored_info(&format!(
+                "Reboot required by: {}",
+                reboot_required.join(", ")
+            ));
         }
     }
+    output.exit(crate::merge_failures::EXIT_PARTIAL_FAILURE);
+}
 
-    // Phase 4: Run remaining post-merge commands (service restarts, etc.)
-    if !post_reload.is_empty() {
-        run_avocado_on_merge_commands(&post_reload, output)?;
+/// Scan extension release files for AVOCADO_ENABLE_SERVICES
+/// This is used by HITL to determine which services need mount dependencies
+pub fn scan_extension_for_enable_services(
+    extension_path: &Path,
+    extension_name: &str,
+) -> Vec<String> {
+    let mut services: Vec<String> = Vec::new();
+    for hierarchy in [Hierarchy::Sysext, Hierarchy::Confext] {
+        let Some(release) = extension_release::find(extension_path, extension_name, hierarchy)
+        else {
+            continue;
+        };
+        for service in &release.enable_services {
+            if !services.contains(service) {
+                services.push(service.clone());
+            }
+        }
     }
-
-    Ok(())
+    services
 }
 
-/// Parse all AVOCADO_ON_MERGE commands from release file content
-fn parse_avocado_on_merge_commands(content: &str) -> Vec<String> {
-    let mut commands = Vec::new();
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.starts_with("AVOCADO_ON_MERGE=") {
-            let value = line
-                .split_once('=')
-                .map(|x| x.1)
-                .unwrap_or("")
-                .trim_matches('"')
-                .trim();
-
-            if !value.is_empty() {
-                commands.push(value.to_string());
-            }
+/// Dependency types the extension's release files declare for the
+/// services in AVOCADO_ENABLE_SERVICES, by service name.
+pub fn scan_extension_for_service_dependencies(
+    extension_path: &Path,
+    extension_name: &str,
+) -> std::collections::BTreeMap<String, Vec<ServiceDependency>> {
+    let mut dependencies = std::collections::BTreeMap::new();
+    for hierarchy in [Hierarchy::Sysext, Hierarchy::Confext] {
+        let Some(release) = extension_release::find(extension_path, extension_name, hierarchy)
+        else {
+            continue;
+        };
+        for (service, deps) in &release.service_dependencies {
+            dependencies
+                .entry(service.clone())
+                .or_insert_with(|| deps.clone());
         }
     }
+    dependencies
+}
 
-    commands
+fn process_post_merge_tasks_for_extensions(
+    enabled_extensions: &[Extension],
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    process_post_merge_tasks(enabled_extensions, None, output)
 }
 
-/// Parse all AVOCADO_ON_UNMERGE commands from release file content
-fn parse_avocado_on_unmerge_commands(content: &str) -> Vec<String> {
-    let mut commands = Vec::new();
+/// Plan and run the post-merge tasks, with module loading and service
+/// commands limited to the `entering` extensions when given (a partial
+/// refresh).
+fn process_post_merge_tasks(
+    enabled_extensions: &[Extension],
+    entering: Option<&[String]>,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    let tasks = plan_post_merge(enabled_extensions, entering, output)?;
+    apply_post_merge(&tasks, enabled_extensions, output)
+}
 
-    for line in content.lines() {
-        let line = line.trim();
-        if line.starts_with("AVOCADO_ON_UNMERGE=") {
-            let value = line
-                .split_once('=')
-                .map(|x| x.1)
-                .unwrap_or("")
-                .trim_matches('"')
-                .trim();
+/// Run `[avocado.ext] on_change_exec` when the merged set differs from
+/// `before`, the set recorded before the refresh, with the JSON summary of
+/// the change on its stdin. Failures are warnings: the refresh itself
+/// already succeeded.
+pub(crate) fn run_change_hook(
+    config: &Config,
+    before: &[crate::merge_history::MergedExtension],
+    out: &OutputManager,
+) {
+    let Some(command) = config.on_change_exec() else {
+        return;
+    };
+    let now = crate::merge_history::latest();
+    let changes = crate::merge_history::Changes::between(before, &now);
+    if changes.is_empty() {
+        return;
+    }
+    let summary = serde_json::json!({
+        "added": changes.merged,
+        "removed": changes.unmerged,
+        "updated": changes.changed,
+        "extensions": now
+            .iter()
+            .map(crate::merge_history::MergedExtension::versioned_name)
+            .collect::<Vec<_>>(),
+    });
+    out.log_info(&format!("Extension set changed; running {command}"));
 
-            if !value.is_empty() {
-                commands.push(value.to_string());
-            }
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    let Some((program, args)) = parts.split_first() else {
+        return;
+    };
+    if crate::backend::simulate(program, args).is_some() {
+        return;
+    }
+    // The summary goes to stdin: in the environment it would be inherited
+    // by everything the command starts and show on a phase unit's command
+    // line
+    let output = match crate::timeouts::output_streaming_with_input(
+        ProcessCommand::new(crate::tools::program(program)).args(args),
+        TimeoutKind::HookCmd,
+        summary.to_string().as_bytes(),
+        &|_, line| out.command_output(program, line),
+    ) {
+        Ok(output) => output,
+        Err(e) => {
+            out.warning(&format!("on_change_exec '{command}': {e}"));
+            return;
+        }
+    };
+    let names: Vec<String> = changes
+        .merged
+        .iter()
+        .chain(&changes.unmerged)
+        .chain(changes.changed.iter().map(|changed| &changed.to))
+        .cloned()
+        .collect();
+    let logs = crate::hook_log::record(&names, "on-change", command, &output);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let exit = output.status.code().map_or_else(
+            || "a signal".to_string(),
+            |code| format!("exit code {code}"),
+        );
+        let mut message = format!(
+            "on_change_exec '{command}' failed with {exit}: {}",
+            crate::hook_log::stderr_summary(&stderr)
+        );
+        if let Some(log) = logs.first() {
+            message.push_str(&format!(" (full output: {})", log.display()));
         }
+        out.warning(&message);
     }
-
-    commands
-}
-
-/// Check if a release file content contains AVOCADO_ON_MERGE=depmod
-/// (Kept for backward compatibility with existing tests)
-#[allow(dead_code)]
-fn check_avocado_on_merge_depmod(content: &str) -> bool {
-    let commands = parse_avocado_on_merge_commands(content);
-    commands.contains(&"depmod".to_string())
 }
 
 /// Scan currently merged extensions for AVOCADO_ON_UNMERGE commands.
@@ -3720,8 +5547,8 @@ fn scan_merged_extensions_for_on_unmerge_commands() -> Result<Vec<String>, Syste
             continue;
         }
 
-        if let Ok(entries) = fs::read_dir(path) {
-            for entry in entries.flatten() {
+        if let Ok(entries) = read_dir_sorted(path) {
+            for entry in entries {
                 let file_path = entry.path();
                 if file_path.is_file() {
                     if let Ok(content) = fs::read_to_string(&file_path) {
@@ -3745,35 +5572,7 @@ fn scan_custom_release_directory_for_on_unmerge(
 ) -> Result<Vec<String>, SystemdError> {
     let mut on_unmerge_commands = Vec::new();
 
-    let custom_path = Path::new(custom_dir);
-    let mut dirs: Vec<(String, Option<&str>)> = Vec::new();
-
-    // Check if it's a single directory with release files (legacy behavior)
-    if custom_path.join("extension-release.d").exists() {
-        dirs.push((custom_dir.to_string(), None));
-    } else {
-        // Look for sysext and confext subdirectories
-        let sysext_dir = custom_path.join("usr/lib/extension-release.d");
-        let confext_dir = custom_path.join("etc/extension-release.d");
-
-        if sysext_dir.exists() {
-            dirs.push((
-                sysext_dir.to_string_lossy().to_string(),
-                Some("SYSEXT_SCOPE"),
-            ));
-        }
-        if confext_dir.exists() {
-            dirs.push((
-                confext_dir.to_string_lossy().to_string(),
-                Some("CONFEXT_SCOPE"),
-            ));
-        }
-
-        // If neither subdirectory structure exists, use the custom dir directly
-        if dirs.is_empty() {
-            dirs.push((custom_dir.to_string(), None));
-        }
-    }
+    let dirs = custom_release_dirs(custom_dir);
 
     for (release_dir, scope_key) in &dirs {
         scan_directory_for_on_unmerge_commands(release_dir, &mut on_unmerge_commands, *scope_key);
@@ -3793,8 +5592,8 @@ fn scan_directory_for_on_unmerge_commands(
         return;
     }
 
-    if let Ok(entries) = fs::read_dir(release_dir) {
-        for entry in entries.flatten() {
+    if let Ok(entries) = read_dir_sorted(release_dir) {
+        for entry in entries {
             let path = entry.path();
             if path.is_file() {
                 if let Ok(content) = fs::read_to_string(&path) {
@@ -3802,262 +5601,93 @@ fn scan_directory_for_on_unmerge_commands(
                         if !is_scope_enabled_for_current_environment(&content, key) {
                             continue;
                         }
-                    }
-                    let mut commands = parse_avocado_on_unmerge_commands(&content);
-                    on_unmerge_commands.append(&mut commands);
-                }
-            }
-        }
-    }
-}
-
-/// Process pre-unmerge tasks: execute AVOCADO_ON_UNMERGE commands
-fn process_pre_unmerge_tasks(output: &OutputManager) -> Result<(), SystemdError> {
-    let on_unmerge_commands = scan_merged_extensions_for_on_unmerge_commands()?;
-
-    // Remove duplicates while preserving order
-    let mut unique_commands = Vec::new();
-    for command in on_unmerge_commands {
-        if !unique_commands.contains(&command) {
-            unique_commands.push(command);
-        }
-    }
-
-    // Execute accumulated AVOCADO_ON_UNMERGE commands
-    if !unique_commands.is_empty() {
-        run_avocado_on_unmerge_commands(&unique_commands, output)?;
-    }
-
-    Ok(())
-}
-
-/// Parse AVOCADO_MODPROBE modules from release file content
-fn parse_avocado_modprobe(content: &str) -> Vec<String> {
-    let mut modules = Vec::new();
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.starts_with("AVOCADO_MODPROBE=") {
-            let value = line
-                .split_once('=')
-                .map(|x| x.1)
-                .unwrap_or("")
-                .trim_matches('"')
-                .trim();
-
-            // Parse space-separated list of modules
-            for module in value.split_whitespace() {
-                if !module.is_empty() {
-                    modules.push(module.to_string());
-                }
-            }
-            break; // Only process the first AVOCADO_MODPROBE line
-        }
-    }
-
-    modules
-}
-
-/// Parse AVOCADO_ENABLE_SERVICES from release file content
-/// Returns a list of systemd service unit names that should depend on the extension's mount
-pub fn parse_avocado_enable_services(content: &str) -> Vec<String> {
-    let mut services = Vec::new();
-
-    for line in content.lines() {
-        let line = line.trim();
-        if line.starts_with("AVOCADO_ENABLE_SERVICES=") {
-            let value = line
-                .split_once('=')
-                .map(|x| x.1)
-                .unwrap_or("")
-                .trim_matches('"')
-                .trim();
-
-            // Parse space-separated list of services
-            for service in value.split_whitespace() {
-                if !service.is_empty() && !services.contains(&service.to_string()) {
-                    services.push(service.to_string());
-                }
-            }
-        }
-    }
-
-    services
-}
-
-/// Run the depmod command
-fn run_depmod(out: &OutputManager) -> Result<(), SystemdError> {
-    out.log_info("Running depmod to update kernel module dependencies...");
-
-    // Check if we're in test mode and should use mock commands
-    let command_name = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        "mock-depmod"
-    } else {
-        "depmod"
-    };
-
-    let output = ProcessCommand::new(command_name)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| SystemdError::CommandFailed {
-            command: command_name.to_string(),
-            source: e,
-        })?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(SystemdError::CommandExitedWithError {
-            command: command_name.to_string(),
-            exit_code: output.status.code(),
-            stderr: stderr.to_string(),
-        });
-    }
-
-    out.log_success("depmod completed successfully.");
-    Ok(())
-}
-
-/// Run modprobe for a list of modules
-fn run_modprobe(modules: &[String], out: &OutputManager) -> Result<(), SystemdError> {
-    if modules.is_empty() {
-        return Ok(());
-    }
-
-    out.log_info(&format!("Loading kernel modules: {}", modules.join(", ")));
-
-    for module in modules {
-        // Check if we're in test mode and should use mock commands
-        let command_name = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-            "mock-modprobe"
-        } else {
-            "modprobe"
-        };
-
-        let output = ProcessCommand::new(command_name)
-            .arg(module)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(|e| SystemdError::CommandFailed {
-                command: format!("{command_name} {module}"),
-                source: e,
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            eprintln!("Warning: Failed to load module {module}: {stderr}");
-            // Don't fail the entire operation for individual module failures
-            // Just log the warning and continue with other modules
-        } else {
-            out.log_success(&format!("Module {module} loaded successfully."));
-        }
-    }
-
-    out.log_success("Module loading completed.");
-    Ok(())
-}
-
-/// Execute a single command with its arguments
-fn execute_single_command(command_str: &str, out: &OutputManager) -> Result<(), SystemdError> {
-    // Parse the command string to handle commands with arguments
-    // Commands may be quoted or contain spaces
-    let parts: Vec<&str> = if command_str.starts_with('"') && command_str.ends_with('"') {
-        // Handle quoted commands
-        let unquoted = &command_str[1..command_str.len() - 1];
-        unquoted.split_whitespace().collect()
-    } else {
-        // Handle unquoted commands
-        command_str.split_whitespace().collect()
-    };
-
-    if parts.is_empty() {
-        eprintln!("Warning: Empty command in AVOCADO_ON_MERGE, skipping");
-        return Ok(());
-    }
-
-    let (command_name, args) = parts.split_first().unwrap();
-
-    // Check if we're in test mode and should use mock commands
-    let mock_command_name = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        match *command_name {
-            "depmod" => "mock-depmod".to_string(),
-            "modprobe" => "mock-modprobe".to_string(),
-            _ => {
-                // For other commands in test mode, prefix with mock- if not already
-                if command_name.starts_with("mock-") {
-                    command_name.to_string()
-                } else {
-                    format!("mock-{command_name}")
+                    }
+                    let mut commands = parse_avocado_on_unmerge_commands(&content);
+                    on_unmerge_commands.append(&mut commands);
                 }
             }
         }
-    } else {
-        command_name.to_string()
-    };
+    }
+}
 
-    let actual_command = &mock_command_name;
+/// Process pre-unmerge tasks: execute AVOCADO_ON_UNMERGE commands
+fn process_pre_unmerge_tasks(output: &OutputManager) -> Result<(), SystemdError> {
+    process_pre_unmerge_tasks_for(None, output)
+}
 
-    let output = ProcessCommand::new(actual_command)
-        .args(args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| SystemdError::CommandFailed {
-            command: command_str.to_string(),
-            source: e,
-        })?;
+/// Pre-unmerge tasks, limited to the commands of the `leaving` extensions
+/// when given (a partial refresh)
+fn process_pre_unmerge_tasks_for(
+    leaving: Option<&[String]>,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    let on_unmerge_commands = scan_merged_extensions_for_on_unmerge_commands()?;
+    let mut owners = on_unmerge_hook_owners();
+    if let Some(leaving) = leaving {
+        for extensions in owners.values_mut() {
+            extensions.retain(|extension| leaving.contains(extension));
+        }
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Warning: Command '{command_str}' failed: {stderr}");
-        // Log warning but don't fail the entire operation
-        // This matches the behavior of modprobe failures
-    } else {
-        out.log_success(&format!("Command '{command_str}' completed successfully"));
+    // Remove duplicates while preserving order; each command runs once for
+    // each extension declaring it
+    let mut unique_commands = Vec::new();
+    for command in on_unmerge_commands {
+        if leaving.is_some() && owners.get(&command).is_none_or(Vec::is_empty) {
+            continue;
+        }
+        if !unique_commands.contains(&command) {
+            unique_commands.push(command);
+        }
+    }
+
+    // Execute accumulated AVOCADO_ON_UNMERGE commands
+    if !unique_commands.is_empty() {
+        run_avocado_on_unmerge_commands(&unique_commands, &owners, &merged_hook_targets(), output)?;
     }
 
     Ok(())
 }
 
-/// Run accumulated AVOCADO_ON_MERGE commands
-fn run_avocado_on_merge_commands(
-    commands: &[String],
-    out: &OutputManager,
-) -> Result<(), SystemdError> {
-    if commands.is_empty() {
+/// Run the depmod command
+fn run_depmod(out: &OutputManager) -> Result<(), SystemdError> {
+    out.log_info("Running depmod to update kernel module dependencies...");
+
+    if let Some(result) = crate::backend::simulate("depmod", &[]) {
+        result?;
+        out.log_success("depmod completed successfully.");
         return Ok(());
     }
 
-    out.log_info(&format!("Executing {} post-merge commands", commands.len()));
-
-    for command_str in commands {
-        out.log_info(&format!("Running command: {command_str}"));
+    let command_name = crate::tools::program("depmod");
 
-        // Check if the command contains shell operators like semicolons
-        if command_str.contains(';') {
-            // Split the command by semicolons and execute each part sequentially
-            let sub_commands: Vec<&str> = command_str.split(';').map(|s| s.trim()).collect();
+    let output = run_with_progress(
+        &mut ProcessCommand::new(&command_name),
+        TimeoutKind::HookCmd,
+        "depmod",
+        &[Stream::Stdout, Stream::Stderr],
+        out,
+    )
+    .map_err(|e| e.into_systemd_error(&command_name))?;
 
-            for sub_command in sub_commands {
-                if !sub_command.is_empty() {
-                    out.log_info(&format!("Running sub-command: {sub_command}"));
-                    execute_single_command(sub_command, out)?;
-                }
-            }
-        } else {
-            // Execute as a single command
-            execute_single_command(command_str, out)?;
-        }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(SystemdError::CommandExitedWithError {
+            command: command_name.to_string(),
+            exit_code: output.status.code(),
+            stderr: stderr.to_string(),
+        });
     }
 
-    out.log_success("Post-merge command execution completed.");
+    out.log_success("depmod completed successfully.");
     Ok(())
 }
 
 /// Run accumulated AVOCADO_ON_UNMERGE commands
 fn run_avocado_on_unmerge_commands(
     commands: &[String],
+    owners: &HookOwners,
+    targets: &HookTargets,
     out: &OutputManager,
 ) -> Result<(), SystemdError> {
     if commands.is_empty() {
@@ -4069,24 +5699,18 @@ fn run_avocado_on_unmerge_commands(
         commands.len()
     ));
 
+    let os_version = read_os_version_id();
     for command_str in commands {
         out.log_info(&format!("Running command: {command_str}"));
-
-        // Check if the command contains shell operators like semicolons
-        if command_str.contains(';') {
-            // Split the command by semicolons and execute each part sequentially
-            let sub_commands: Vec<&str> = command_str.split(';').map(|s| s.trim()).collect();
-
-            for sub_command in sub_commands {
-                if !sub_command.is_empty() {
-                    out.log_info(&format!("Running sub-command: {sub_command}"));
-                    execute_single_command(sub_command, out)?;
-                }
-            }
-        } else {
-            // Execute as a single command
-            execute_single_command(command_str, out)?;
-        }
+        let extensions = owners.get(command_str).map(Vec::as_slice).unwrap_or(&[]);
+        run_hook_command(
+            command_str,
+            "unmerge",
+            extensions,
+            targets,
+            &os_version,
+            out,
+        )?;
     }
 
     out.log_success("Pre-unmerge command execution completed.");
@@ -4094,35 +5718,96 @@ fn run_avocado_on_unmerge_commands(
 }
 
 /// Run a systemd command with proper error handling
-fn run_systemd_command(command: &str, args: &[&str]) -> Result<String, SystemdError> {
-    // Check if we're in test mode and should use mock commands
-    let command_name = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        // In test mode, use mock commands from PATH
-        format!("mock-{command}")
+fn run_systemd_command(
+    command: &str,
+    args: &[&str],
+    output: Option<&OutputManager>,
+) -> Result<String, SystemdError> {
+    run_systemd_command_in(None, command, args, output)
+}
+
+/// Run a systemd command on the host, or against a merge target. With
+/// `output`, what the command logs to stderr is shown while it runs (see
+/// [`run_with_progress`]); stdout is its result and returned.
+fn run_systemd_command_in(
+    target: Option<&MergeTarget>,
+    command: &str,
+    args: &[&str],
+    output: Option<&OutputManager>,
+) -> Result<String, SystemdError> {
+    // In user mode, merge into the user-owned root prefix; merges also carry
+    // the configured image policy and noexec setting
+    let mut extra_args = Vec::new();
+    if matches!(command, "systemd-sysext" | "systemd-confext") {
+        if matches!(args.first(), Some(&"merge") | Some(&"refresh")) {
+            extra_args.extend(crate::image_policy::merge_args());
+        }
+        if target.is_none() {
+            extra_args.extend(crate::user_mode::extension_tool_args());
+        }
+    }
+    let with_extra: Vec<&str>;
+    let args = if extra_args.is_empty() {
+        args
+    } else {
+        with_extra = args
+            .iter()
+            .copied()
+            .chain(extra_args.iter().map(String::as_str))
+            .collect();
+        &with_extra[..]
+    };
+
+    // Drop options the installed systemd does not understand; without
+    // systemd-confext there is nothing to merge into /etc
+    let caps = crate::systemd_caps::detect();
+    if command == "systemd-confext" && !caps.confext {
+        return Ok(String::new());
+    }
+    let supported: Vec<&str>;
+    let args = if matches!(command, "systemd-sysext" | "systemd-confext") {
+        supported = caps.filter_args(args);
+        &supported[..]
     } else {
-        command.to_string()
+        args
     };
 
-    let output = ProcessCommand::new(&command_name)
-        .args(args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| SystemdError::CommandFailed {
-            command: command.to_string(),
-            source: e,
-        })?;
+    let (program, args) = match target {
+        Some(target) => target.command(command, args),
+        None => (
+            command.to_string(),
+            args.iter().map(|a| a.to_string()).collect(),
+        ),
+    };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    if let Some(result) = crate::backend::simulate(&program, &args) {
+        return result;
+    }
+
+    // Tools inside a container target keep their names; host tools resolve
+    // to their configured path (mock-<name> in test mode)
+    let command_name = crate::tools::program(&program);
+
+    let mut cmd = ProcessCommand::new(&command_name);
+    cmd.args(&args);
+    let kind = TimeoutKind::SystemdCmd;
+    let result = match output {
+        Some(out) => run_with_progress(&mut cmd, kind, command, &[Stream::Stderr], out),
+        None => crate::timeouts::output(&mut cmd, kind),
+    }
+    .map_err(|e| e.into_systemd_error(command))?;
+
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
         return Err(SystemdError::CommandExitedWithError {
             command: command.to_string(),
-            exit_code: output.status.code(),
+            exit_code: result.status.code(),
             stderr: stderr.to_string(),
         });
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = String::from_utf8_lossy(&result.stdout);
     Ok(stdout.to_string())
 }
 
@@ -4261,7 +5946,7 @@ mod tests {
 
         // Check that all subcommands exist
         let subcommands: Vec<_> = cmd.get_subcommands().collect();
-        assert_eq!(subcommands.len(), 7);
+        assert_eq!(subcommands.len(), 27);
 
         let subcommand_names: Vec<&str> = subcommands.iter().map(|cmd| cmd.get_name()).collect();
         assert!(subcommand_names.contains(&"list"));
@@ -4269,16 +5954,36 @@ mod tests {
         assert!(subcommand_names.contains(&"unmerge"));
         assert!(subcommand_names.contains(&"refresh"));
         assert!(subcommand_names.contains(&"status"));
+        assert!(subcommand_names.contains(&"env"));
         assert!(subcommand_names.contains(&"enable"));
         assert!(subcommand_names.contains(&"disable"));
+        assert!(subcommand_names.contains(&"snapshot"));
+        assert!(subcommand_names.contains(&"compare"));
+        assert!(subcommand_names.contains(&"test"));
+        assert!(subcommand_names.contains(&"audit"));
+        assert!(subcommand_names.contains(&"auto-refresh"));
+        assert!(subcommand_names.contains(&"prefetch"));
+        assert!(subcommand_names.contains(&"upgrade"));
+        assert!(subcommand_names.contains(&"prune-os-releases"));
+        assert!(subcommand_names.contains(&"run"));
+        assert!(subcommand_names.contains(&"top"));
+        assert!(subcommand_names.contains(&"apply"));
+        assert!(subcommand_names.contains(&"info"));
+        assert!(subcommand_names.contains(&"lint"));
+        assert!(subcommand_names.contains(&"graph"));
+        assert!(subcommand_names.contains(&"diff"));
+        assert!(subcommand_names.contains(&"compat"));
+        assert!(subcommand_names.contains(&"preview-etc"));
+        assert!(subcommand_names.contains(&"events"));
+        assert!(subcommand_names.contains(&"check-update"));
     }
 
     #[test]
     fn test_extension_preference() {
         // Directory should be preferred over .raw file
-        use std::collections::HashMap;
+        use std::collections::BTreeMap;
 
-        let mut extension_map = HashMap::new();
+        let mut extension_map = BTreeMap::new();
 
         // Simulate adding a .raw file first
         let raw_extension = Extension {
@@ -4289,6 +5994,7 @@ mod tests {
             is_confext: false,
             image_type: ImageTypeTag::Raw,
             merge_index: None,
+            partitions: Vec::new(),
         };
         extension_map.insert("test_ext".to_string(), raw_extension);
 
@@ -4301,6 +6007,7 @@ mod tests {
             is_confext: true,
             image_type: ImageTypeTag::Directory,
             merge_index: None,
+            partitions: Vec::new(),
         };
         extension_map.insert("test_ext".to_string(), dir_extension);
 
@@ -4309,18 +6016,6 @@ mod tests {
         assert!(extension.is_confext);
     }
 
-    #[test]
-    fn test_analyze_directory_extension() {
-        // Test with no release files
-        let test_path = PathBuf::from("/tmp/test_extension");
-        let extension = analyze_directory_extension("test_ext", &test_path).unwrap();
-
-        assert_eq!(extension.name, "test_ext");
-        assert!(extension.is_sysext);
-        assert!(extension.is_confext);
-        assert_eq!(extension.image_type, ImageTypeTag::Directory);
-    }
-
     #[test]
     fn test_symlink_naming() {
         // Test directory extension symlink naming
@@ -4332,6 +6027,7 @@ mod tests {
             is_confext: true,
             image_type: ImageTypeTag::Directory,
             merge_index: None,
+            partitions: Vec::new(),
         };
 
         // Test loop-mounted raw file extension symlink naming
@@ -4343,6 +6039,7 @@ mod tests {
             is_confext: false,
             image_type: ImageTypeTag::Raw,
             merge_index: None,
+            partitions: Vec::new(),
         };
 
         // Directory extensions should use just the name (no version)
@@ -4363,251 +6060,15 @@ mod tests {
     }
 
     #[test]
-    fn test_check_avocado_on_merge_depmod() {
-        // Test case with AVOCADO_ON_MERGE=depmod
-        let content_with_depmod = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE=depmod
-OTHER_KEY=value
-"#;
-        assert!(check_avocado_on_merge_depmod(content_with_depmod));
-
-        // Test case with AVOCADO_ON_MERGE=depmod with quotes
-        let content_with_quoted_depmod = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE="depmod"
-OTHER_KEY=value
-"#;
-        assert!(check_avocado_on_merge_depmod(content_with_quoted_depmod));
-
-        // Test case with different AVOCADO_ON_MERGE value
-        let content_with_other_value = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE=something_else
-OTHER_KEY=value
-"#;
-        assert!(!check_avocado_on_merge_depmod(content_with_other_value));
-
-        // Test case without AVOCADO_ON_MERGE
-        let content_without_key = r#"
-VERSION_ID=1.0
-OTHER_KEY=value
-"#;
-        assert!(!check_avocado_on_merge_depmod(content_without_key));
-
-        // Test case with empty content
-        assert!(!check_avocado_on_merge_depmod(""));
-
-        // Test case with AVOCADO_ON_MERGE but empty value
-        let content_with_empty_value = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE=
-OTHER_KEY=value
-"#;
-        assert!(!check_avocado_on_merge_depmod(content_with_empty_value));
-    }
-
-    #[test]
-    fn test_parse_avocado_modprobe() {
-        // Test case with multiple modules
-        let content_with_modules = r#"
-VERSION_ID=2.0
-AVOCADO_MODPROBE="nvidia i915 radeon"
-OTHER_KEY=value
-"#;
-        let modules = parse_avocado_modprobe(content_with_modules);
-        assert_eq!(modules, vec!["nvidia", "i915", "radeon"]);
-
-        // Test case with single module without quotes
-        let content_single_module = r#"
-VERSION_ID=1.5
-AVOCADO_MODPROBE=snd_hda_intel
-OTHER_KEY=value
-"#;
-        let modules = parse_avocado_modprobe(content_single_module);
-        assert_eq!(modules, vec!["snd_hda_intel"]);
-
-        // Test case with no AVOCADO_MODPROBE
-        let content_no_modprobe = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE=depmod
-OTHER_KEY=value
-"#;
-        let modules = parse_avocado_modprobe(content_no_modprobe);
-        assert!(modules.is_empty());
-
-        // Test case with empty AVOCADO_MODPROBE
-        let content_empty_modprobe = r#"
-VERSION_ID=1.0
-AVOCADO_MODPROBE=""
-OTHER_KEY=value
-"#;
-        let modules = parse_avocado_modprobe(content_empty_modprobe);
-        assert!(modules.is_empty());
-
-        // Test case with extra whitespace
-        let content_with_whitespace = r#"
-VERSION_ID=1.0
-AVOCADO_MODPROBE="  nvidia   i915  radeon  "
-OTHER_KEY=value
-"#;
-        let modules = parse_avocado_modprobe(content_with_whitespace);
-        assert_eq!(modules, vec!["nvidia", "i915", "radeon"]);
-
-        // Test case with mixed quotes and no quotes in different lines (only first should be processed)
-        let content_multiple_lines = r#"
-VERSION_ID=1.0
-AVOCADO_MODPROBE="nvidia i915"
-AVOCADO_MODPROBE=should_be_ignored
-OTHER_KEY=value
-"#;
-        let modules = parse_avocado_modprobe(content_multiple_lines);
-        assert_eq!(modules, vec!["nvidia", "i915"]);
-    }
-
-    #[test]
-    fn test_parse_avocado_on_merge_commands_with_equals() {
-        // Test case with command containing equals signs in arguments
-        let content_with_equals = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE="udevadm trigger --action=add"
-AVOCADO_ON_MERGE=command --option=value --other=setting
-OTHER_KEY=value
-"#;
-        let commands = parse_avocado_on_merge_commands(content_with_equals);
-        assert_eq!(
-            commands,
-            vec![
-                "udevadm trigger --action=add",
-                "command --option=value --other=setting"
-            ]
-        );
-
-        // Test case with multiple equals signs in same argument
-        let content_multiple_equals = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE="systemctl set-property --runtime some.service CPUQuota=50% MemoryLimit=1G"
-"#;
-        let commands = parse_avocado_on_merge_commands(content_multiple_equals);
-        assert_eq!(
-            commands,
-            vec!["systemctl set-property --runtime some.service CPUQuota=50% MemoryLimit=1G"]
-        );
-
-        // Test case ensuring backwards compatibility with simple commands
-        let content_simple = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE=depmod
-AVOCADO_ON_MERGE="systemctl restart some-service"
-"#;
-        let commands = parse_avocado_on_merge_commands(content_simple);
-        assert_eq!(commands, vec!["depmod", "systemctl restart some-service"]);
-    }
-
-    #[test]
-    fn test_parse_avocado_on_merge_commands_with_semicolons() {
-        // Test case with semicolon-separated commands
-        let content_with_semicolons = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE="systemctl --no-block restart dbus; systemctl --no-block restart avahi-daemon"
-AVOCADO_ON_MERGE="command1 --arg=value; command2; command3 --option"
-OTHER_KEY=value
-"#;
-        let commands = parse_avocado_on_merge_commands(content_with_semicolons);
-        assert_eq!(
-            commands,
-            vec![
-                "systemctl --no-block restart dbus; systemctl --no-block restart avahi-daemon",
-                "command1 --arg=value; command2; command3 --option"
-            ]
-        );
-
-        // Test case with mixed semicolons and regular commands
-        let content_mixed = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE=depmod
-AVOCADO_ON_MERGE="systemctl restart service1; systemctl restart service2"
-AVOCADO_ON_MERGE="single-command --arg"
-"#;
-        let commands = parse_avocado_on_merge_commands(content_mixed);
+    fn test_scope_label() {
+        assert_eq!(scope_label(None), "-");
+        assert_eq!(scope_label(Some(&[])), "all");
         assert_eq!(
-            commands,
-            vec![
-                "depmod",
-                "systemctl restart service1; systemctl restart service2",
-                "single-command --arg"
-            ]
+            scope_label(Some(&["system".to_string(), "initrd".to_string()])),
+            "system,initrd"
         );
     }
 
-    #[test]
-    fn test_parse_avocado_enable_services() {
-        // Test case with multiple services
-        let content_with_services = r#"
-VERSION_ID=1.0
-AVOCADO_ENABLE_SERVICES="nginx.service prometheus.service"
-OTHER_KEY=value
-"#;
-        let services = parse_avocado_enable_services(content_with_services);
-        assert_eq!(services, vec!["nginx.service", "prometheus.service"]);
-
-        // Test case with services without .service suffix
-        let content_short_names = r#"
-VERSION_ID=1.0
-AVOCADO_ENABLE_SERVICES="nginx prometheus redis"
-OTHER_KEY=value
-"#;
-        let services = parse_avocado_enable_services(content_short_names);
-        assert_eq!(services, vec!["nginx", "prometheus", "redis"]);
-
-        // Test case with no AVOCADO_ENABLE_SERVICES
-        let content_no_services = r#"
-VERSION_ID=1.0
-AVOCADO_ON_MERGE=depmod
-OTHER_KEY=value
-"#;
-        let services = parse_avocado_enable_services(content_no_services);
-        assert!(services.is_empty());
-
-        // Test case with empty AVOCADO_ENABLE_SERVICES
-        let content_empty_services = r#"
-VERSION_ID=1.0
-AVOCADO_ENABLE_SERVICES=""
-OTHER_KEY=value
-"#;
-        let services = parse_avocado_enable_services(content_empty_services);
-        assert!(services.is_empty());
-
-        // Test case with extra whitespace
-        let content_with_whitespace = r#"
-VERSION_ID=1.0
-AVOCADO_ENABLE_SERVICES="  nginx   redis  "
-OTHER_KEY=value
-"#;
-        let services = parse_avocado_enable_services(content_with_whitespace);
-        assert_eq!(services, vec!["nginx", "redis"]);
-
-        // Test case with multiple AVOCADO_ENABLE_SERVICES lines (all should be processed)
-        let content_multiple_lines = r#"
-VERSION_ID=1.0
-AVOCADO_ENABLE_SERVICES="nginx prometheus"
-AVOCADO_ENABLE_SERVICES="redis"
-OTHER_KEY=value
-"#;
-        let services = parse_avocado_enable_services(content_multiple_lines);
-        assert_eq!(services, vec!["nginx", "prometheus", "redis"]);
-
-        // Test case with duplicates (should be deduplicated)
-        let content_with_duplicates = r#"
-VERSION_ID=1.0
-AVOCADO_ENABLE_SERVICES="nginx redis"
-AVOCADO_ENABLE_SERVICES="nginx worker"
-OTHER_KEY=value
-"#;
-        let services = parse_avocado_enable_services(content_with_duplicates);
-        assert_eq!(services, vec!["nginx", "redis", "worker"]);
-    }
-
     #[test]
     fn test_parse_scope_from_release_content() {
         // Test case with SYSEXT_SCOPE
@@ -4852,117 +6313,108 @@ OTHER_KEY=value
     }
 
     #[test]
-    fn test_both_merge_and_unmerge_commands() {
-        // Test case with both AVOCADO_ON_MERGE and AVOCADO_ON_UNMERGE commands
-        let content = r#"
-VERSION_ID=1.0
-DESCRIPTION="Extension with both merge and unmerge commands"
-AVOCADO_ON_MERGE="systemctl start service"
-AVOCADO_ON_MERGE=depmod
-AVOCADO_ON_UNMERGE="systemctl stop service"
-OTHER_KEY=value
-"#;
-        let merge_commands = parse_avocado_on_merge_commands(content);
-        let unmerge_commands = parse_avocado_on_unmerge_commands(content);
-
-        assert_eq!(merge_commands, vec!["systemctl start service", "depmod"]);
-        assert_eq!(unmerge_commands, vec!["systemctl stop service"]);
+    fn test_glob_match() {
+        assert!(glob_match("sensor-*", "sensor-temp-1.0"));
+        assert!(glob_match("*-1.?", "app-1.2"));
+        assert!(glob_match("app-[0-9]*", "app-2.0"));
+        assert!(glob_match("app-[!0-9]*", "app-beta"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("sensor-*", "app-sensor-1.0"));
+        assert!(!glob_match("app-[0-9]*", "app-beta"));
+        assert!(!glob_match("app?", "app"));
     }
 
     #[test]
-    fn test_compute_prefixed_name_with_merge_index() {
-        let ext = Extension {
-            name: "app".to_string(),
-            version: Some("1.0.0".to_string()),
-            path: PathBuf::from("/test/app"),
-            is_sysext: true,
-            is_confext: false,
-            image_type: ImageTypeTag::Raw,
-            merge_index: Some(2),
+    fn test_resolve_enable_targets() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir = tmp.path().to_str().unwrap();
+        fs::create_dir(tmp.path().join("sensor-a-1.0")).unwrap();
+        fs::write(tmp.path().join("sensor-b-1.0.raw"), "").unwrap();
+        fs::write(tmp.path().join("app-1.0.raw"), "").unwrap();
+        fs::write(tmp.path().join("notes.txt"), "").unwrap();
+
+        let names = |arg: &str| -> Vec<String> {
+            resolve_enable_targets(arg, dir)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.name)
+                .collect()
         };
-        assert_eq!(compute_prefixed_name(&ext), "02-app-1.0.0");
-    }
+        assert_eq!(names("sensor-*"), vec!["sensor-a-1.0", "sensor-b-1.0"]);
+        assert_eq!(names("*.raw"), vec!["app-1.0", "sensor-b-1.0"]);
+        assert_eq!(names("app-1.0"), vec!["app-1.0"]);
+        assert!(resolve_enable_targets("notes*", dir).is_err());
+        assert!(resolve_enable_targets("missing", dir).is_err());
 
-    #[test]
-    fn test_compute_prefixed_name_no_version() {
-        let ext = Extension {
-            name: "networking".to_string(),
-            version: None,
-            path: PathBuf::from("/test/networking"),
-            is_sysext: true,
-            is_confext: false,
-            image_type: ImageTypeTag::Directory,
-            merge_index: Some(1),
-        };
-        assert_eq!(compute_prefixed_name(&ext), "01-networking");
+        let outside = tmp.path().join("app-1.0.raw");
+        let targets = resolve_enable_targets(outside.to_str().unwrap(), "/nonexistent").unwrap();
+        assert_eq!(targets[0].name, "app-1.0");
+        assert_eq!(targets[0].source_path, outside.to_str().unwrap());
+        assert!(resolve_enable_targets("/nonexistent/app.raw", dir).is_err());
     }
 
     #[test]
-    fn test_compute_prefixed_name_no_merge_index() {
-        // Legacy extension without ordering — no prefix
-        let ext = Extension {
-            name: "legacy".to_string(),
-            version: Some("0.5.0".to_string()),
-            path: PathBuf::from("/test/legacy"),
-            is_sysext: true,
-            is_confext: false,
-            image_type: ImageTypeTag::Directory,
-            merge_index: None,
+    fn test_evaluate_release_compatibility() {
+        let release = |kind: &'static str, content: &str| harness::ReleaseFile {
+            kind,
+            name: "app".to_string(),
+            relative_path: "extension-release.app".to_string(),
+            content: content.to_string(),
+        };
+        let host =
+            &crate::os_release::OsRelease::parse("ID=avocado\nVERSION_ID=2.0\nSYSEXT_LEVEL=1\n");
+        let check = |content: &str, host: Option<&crate::os_release::OsRelease>| {
+            evaluate_release_compatibility(&[release("sysext", content)], host, "2.0")
         };
-        assert_eq!(compute_prefixed_name(&ext), "legacy-0.5.0");
-    }
 
-    #[test]
-    fn test_compute_prefixed_name_inverted_ordering() {
-        // Simulate a manifest with 3 extensions: [highest, middle, lowest]
-        // manifest[0] = highest priority → merge_index = 2
-        // manifest[1] = middle → merge_index = 1
-        // manifest[2] = lowest → merge_index = 0
-        let n = 3;
-        let names = ["highest", "middle", "lowest"];
-        let expected = ["02-highest", "01-middle", "00-lowest"];
-
-        for (index, name) in names.iter().enumerate() {
-            let ext = Extension {
-                name: name.to_string(),
-                version: None,
-                path: PathBuf::from(format!("/test/{name}")),
-                is_sysext: true,
-                is_confext: false,
-                image_type: ImageTypeTag::Directory,
-                merge_index: Some(n - 1 - index),
-            };
-            assert_eq!(
-                compute_prefixed_name(&ext),
-                expected[index],
-                "manifest[{index}] should get prefix {:02}",
-                n - 1 - index
-            );
-        }
+        assert_eq!(check("ID=_any\n", None), ReleaseCompatibility::Compatible);
+        assert_eq!(
+            check("ID=avocado\nVERSION_ID=2.0\n", Some(host)),
+            ReleaseCompatibility::Compatible
+        );
+        assert_eq!(
+            check("ID=avocado\nVERSION_ID=1.0\nSYSEXT_LEVEL=1\n", Some(host)),
+            ReleaseCompatibility::Compatible
+        );
+        assert!(matches!(
+            check("ID=avocado\nVERSION_ID=1.0\n", Some(host)),
+            ReleaseCompatibility::Incompatible(_)
+        ));
+        assert!(matches!(
+            check("ID=debian\n", Some(host)),
+            ReleaseCompatibility::Incompatible(_)
+        ));
+        assert!(matches!(
+            check("VERSION_ID=2.0\n", Some(host)),
+            ReleaseCompatibility::Incompatible(_)
+        ));
+        assert!(matches!(
+            check("ID=avocado\n", None),
+            ReleaseCompatibility::Unverified(_)
+        ));
+        assert!(matches!(
+            evaluate_release_compatibility(&[], Some(host), "2.0"),
+            ReleaseCompatibility::Unverified(_)
+        ));
     }
 
     #[test]
-    fn test_hitl_inherits_manifest_priority() {
-        // When a HITL extension overrides a manifest extension,
-        // it should inherit the same merge_index
-        let mut hitl_ext = Extension {
-            name: "networking".to_string(),
-            version: None,
-            path: PathBuf::from("/run/avocado/hitl/networking"),
-            is_sysext: true,
-            is_confext: false,
-            image_type: ImageTypeTag::Directory,
-            merge_index: None, // Initially no index (HITL discovery)
-        };
+    fn test_plan_os_release_prune() {
+        use tempfile::TempDir;
+
+        let tmp = TempDir::new().unwrap();
+        for v in ["1.8", "1.9", "1.10", "2.0"] {
+            fs::create_dir_all(tmp.path().join(v)).unwrap();
+        }
+        let keep = |list: &[&str]| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
 
-        // Simulate the manifest scanning assigning the index
-        // For a 3-extension manifest where networking is at position 1:
-        let ext_count = 3;
-        let manifest_index = 1;
-        let merge_idx = ext_count - 1 - manifest_index; // = 1
-        hitl_ext.merge_index = Some(merge_idx);
+        let (kept, removed) =
+            plan_os_release_prune(tmp.path(), "1.10", &keep(&["current", "previous"]));
+        assert_eq!(kept, vec!["1.9", "1.10"]);
+        assert_eq!(removed, vec!["1.8", "2.0"]);
 
-        // The HITL extension now gets the same prefix as the manifest entry
-        assert_eq!(compute_prefixed_name(&hitl_ext), "01-networking");
+        let (kept, removed) = plan_os_release_prune(tmp.path(), "1.10", &keep(&["current", "1.8"]));
+        assert_eq!(kept, vec!["1.8", "1.10"]);
+        assert_eq!(removed, vec!["1.9", "2.0"]);
     }
 }
@@ -0,0 +1,924 @@
+//! Apply phase of a merge.
+//!
+//! Executes the actions the [plan](super::plan) phase computed, in order:
+//! [`apply_merge_plan`] the link changes before systemd merges,
+//! [`apply_post_merge`] the tasks after it.
+
+use super::plan::{
+    compute_prefixed_name, is_modprobe_blacklist, modprobe_blacklist_dir, HookOwners, MergeAction,
+    MergePlan, ModuleRequests, MODPROBE_BLACKLIST_PREFIX,
+};
+use super::scan::{list_symlinks, LinkKind};
+use super::source::{get_extension_origin_short, read_os_version_id, versioned_name, Extension};
+use crate::commands::image_adaptor::SystemdError;
+use crate::config::LimitSettings;
+use crate::error::{ExtensionContext, IoContext};
+use crate::ordering::read_dir_sorted;
+use crate::output::OutputManager;
+use crate::timeouts::{Stream, TimeoutKind};
+use std::fs;
+use std::os::unix::fs as unix_fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Stdio};
+use std::time::{Duration, Instant};
+
+/// The links of the sysext and confext directories and their targets.
+type LinkSnapshot = Vec<(LinkKind, String, PathBuf)>;
+
+fn snapshot_links() -> LinkSnapshot {
+    let mut links = Vec::new();
+    for kind in [LinkKind::Sysext, LinkKind::Confext] {
+        let dir = kind.dir();
+        for name in list_symlinks(&dir) {
+            if let Ok(target) = fs::read_link(Path::new(&dir).join(&name)) {
+                links.push((kind, name, target));
+            }
+        }
+    }
+    links
+}
+
+/// Put the link directories back to `snapshot`: links made since are
+/// removed, and those removed since are made again.
+fn restore_links(snapshot: &LinkSnapshot, output: &OutputManager) {
+    for kind in [LinkKind::Sysext, LinkKind::Confext] {
+        let dir = kind.dir();
+        for name in list_symlinks(&dir) {
+            let path = Path::new(&dir).join(&name);
+            let unchanged = snapshot.iter().any(|(k, n, target)| {
+                *k == kind && *n == name && fs::read_link(&path).is_ok_and(|t| t == *target)
+            });
+            if !unchanged {
+                if let Err(e) = fs::remove_file(&path) {
+                    output.progress(&format!(
+                        "Warning: Failed to remove {} symlink {name}: {e}",
+                        kind.label()
+                    ));
+                }
+            }
+        }
+    }
+    for (kind, name, target) in snapshot {
+        let path = Path::new(&kind.dir()).join(name);
+        if path.symlink_metadata().is_err() {
+            if let Err(e) = std::os::unix::fs::symlink(target, &path) {
+                output.progress(&format!(
+                    "Warning: Failed to restore {} symlink {name}: {e}",
+                    kind.label()
+                ));
+            }
+        }
+    }
+}
+
+/// Execute the link changes of a plan. Stops, and puts back the links that
+/// were in place before, if the configured merge time budget runs out.
+pub(super) fn apply_merge_plan(
+    plan: &MergePlan,
+    limits: &LimitSettings,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    let started = Instant::now();
+    create_target_directories()?;
+    let before = limits.merge_time_budget_ms.map(|_| snapshot_links());
+
+    for action in &plan.actions {
+        match action {
+            MergeAction::Unlink { kind, name } => {
+                let path = Path::new(&kind.dir()).join(name);
+                if let Err(e) = fs::remove_file(&path) {
+                    output.progress(&format!(
+                        "Warning: Failed to remove stale {} symlink {name}: {e}",
+                        kind.label()
+                    ));
+                } else {
+                    output.progress(&format!("Removed stale {} symlink: {name}", kind.label()));
+                }
+            }
+            MergeAction::StageRelease { name, source } => {
+                if let Some(extension) = plan.enabled.iter().find(|ext| &ext.path == source) {
+                    stage_extension_release(extension, name, output.is_verbose())
+                        .for_extension(&versioned_name(extension))?;
+                }
+            }
+            MergeAction::Link { kind, name, source } => {
+                create_extension_symlink(*kind, name, source, output.is_verbose())?;
+
+                if let (Some(budget_ms), Some(before)) = (limits.merge_time_budget_ms, &before) {
+                    let elapsed = started.elapsed();
+                    if elapsed > Duration::from_millis(budget_ms) {
+                        // Leave /run/extensions and /run/confexts as we found them
+                        restore_links(before, output);
+                        return Err(SystemdError::ConfigurationError {
+                            message: format!(
+                                "Merge time budget of {budget_ms}ms exceeded after {}ms while preparing extension '{name}'; the previous links were restored",
+                                elapsed.as_millis()
+                            ),
+                        });
+                    }
+                }
+            }
+            // Post-merge tasks are applied by apply_post_merge
+            MergeAction::Blacklist { .. }
+            | MergeAction::Hook { .. }
+            | MergeAction::Modprobe { .. }
+            | MergeAction::DaemonReload
+            | MergeAction::Migrate { .. } => {}
+        }
+    }
+    record_symlink_map(&plan.enabled, output);
+    Ok(())
+}
+
+/// Whether `a` and `b` are on-merge commands of the same stage.
+fn same_hook_stage(a: &MergeAction, b: &MergeAction) -> bool {
+    matches!(
+        (a, b),
+        (MergeAction::Hook { stage: x, .. }, MergeAction::Hook { stage: y, .. }) if x == y
+    )
+}
+
+/// Execute the post-merge tasks planned for `enabled`. Consecutive
+/// on-merge commands of the same stage run as one batch.
+pub(super) fn apply_post_merge(
+    tasks: &[MergeAction],
+    enabled: &[Extension],
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    let mut owners = HookOwners::new();
+    for task in tasks {
+        if let MergeAction::Hook {
+            command,
+            extensions,
+            ..
+        } = task
+        {
+            owners.insert(command.clone(), extensions.clone());
+        }
+    }
+    let targets = hook_targets_of(enabled);
+
+    for batch in tasks.chunk_by(same_hook_stage) {
+        match &batch[0] {
+            MergeAction::Blacklist { blacklists } => {
+                let requests: Vec<ModuleRequests> = blacklists
+                    .iter()
+                    .map(|(extension, modules)| ModuleRequests {
+                        extension: extension.clone(),
+                        blacklist: modules.clone(),
+                        modprobe: Vec::new(),
+                    })
+                    .collect();
+                write_modprobe_blacklists(&requests, output)?;
+            }
+            MergeAction::Hook { .. } => {
+                let commands: Vec<String> = batch
+                    .iter()
+                    .filter_map(|task| match task {
+                        MergeAction::Hook { command, .. } => Some(command.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                run_avocado_on_merge_commands(&commands, &owners, &targets, output)?;
+            }
+            MergeAction::Modprobe { modules } => run_modprobe(modules, output)?,
+            MergeAction::DaemonReload => crate::daemon_reload::flush_or_warn(output),
+            MergeAction::Migrate {
+                extension,
+                script,
+                version,
+            } => {
+                if let Some(ext) = enabled.iter().find(|ext| &versioned_name(ext) == extension) {
+                    run_data_migration(ext, script, version, output);
+                }
+            }
+            // Link changes are applied by apply_merge_plan
+            MergeAction::Unlink { .. }
+            | MergeAction::StageRelease { .. }
+            | MergeAction::Link { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+/// Record which extension each symlink in /run/extensions and /run/confexts
+/// belongs to, for `ext status` and `ext info`.
+pub(super) fn record_symlink_map(enabled: &[Extension], output: &OutputManager) {
+    let mut links = Vec::new();
+    for ext in enabled {
+        let name = compute_prefixed_name(ext);
+        for (kind, wanted) in [
+            (LinkKind::Sysext, ext.is_sysext),
+            (LinkKind::Confext, ext.is_confext),
+        ] {
+            if wanted {
+                links.push(crate::symlink_map::Link {
+                    path: format!("{}/{name}", kind.dir()),
+                    kind: kind.label().to_string(),
+                    extension: ext.name.clone(),
+                    version: ext.version.clone(),
+                    origin: get_extension_origin_short(ext),
+                    source: ext.path.to_string_lossy().to_string(),
+                });
+            }
+        }
+    }
+    if let Err(e) = crate::symlink_map::record(&links) {
+        output.progress(&format!("Warning: Failed to record symlink map: {e}"));
+    }
+}
+
+/// Staging base directory for extension-release overrides used to control merge ordering.
+pub(super) const EXT_RELEASE_STAGING_DIR: &str = "/run/avocado/ext-release-staging";
+
+/// Stage extension-release files with a prefixed name so systemd recognizes the renamed extension.
+///
+/// For each extension that needs ordering, this:
+/// 1. Creates a staging directory with copies of the original extension-release.d contents
+/// 2. Adds a new extension-release file named to match the prefixed symlink name
+/// 3. Bind mounts the staging directory over the original extension-release.d
+///
+/// This allows systemd-sysext/confext to find extension-release.{prefixed-name} even though
+/// the extension image was built with extension-release.{original-name}.
+pub(super) fn stage_extension_release(
+    extension: &Extension,
+    prefixed_name: &str,
+    verbose: bool,
+) -> Result<(), SystemdError> {
+    let staging_base = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/ext-release-staging")
+    } else {
+        crate::user_mode::system_path(EXT_RELEASE_STAGING_DIR)
+    };
+
+    // Determine the original extension-release name (without prefix)
+    let original_name = if let Some(ver) = &extension.version {
+        format!("{}-{}", extension.name, ver)
+    } else {
+        extension.name.clone()
+    };
+
+    // Handle sysext release directory
+    if extension.is_sysext {
+        let original_release_dir = extension.path.join("usr/lib/extension-release.d");
+        if original_release_dir.exists() {
+            let staging_dir = PathBuf::from(&staging_base)
+                .join(prefixed_name)
+                .join("sysext");
+            fs::create_dir_all(&staging_dir).context("create directory", &staging_dir)?;
+
+            // Copy all existing files from original release dir
+            if let Ok(entries) = read_dir_sorted(&original_release_dir) {
+                for entry in entries {
+                    if entry.path().is_file() {
+                        let dest = staging_dir.join(entry.file_name());
+                        fs::copy(entry.path(), &dest)
+                            .context("copy extension-release file to", &dest)?;
+                    }
+                }
+            }
+
+            // Create the prefixed release file by copying content from original
+            let original_release =
+                original_release_dir.join(format!("extension-release.{original_name}"));
+            // Also try without version if versioned doesn't exist
+            let original_release = if original_release.exists() {
+                original_release
+            } else {
+                original_release_dir.join(format!("extension-release.{}", extension.name))
+            };
+
+            let prefixed_release = staging_dir.join(format!("extension-release.{prefixed_name}"));
+            if original_release.exists() && !prefixed_release.exists() {
+                fs::copy(&original_release, &prefixed_release)
+                    .context("copy extension-release file to", &prefixed_release)?;
+            }
+
+            // Bind mount staging dir over original release dir
+            run_bind_mount(
+                staging_dir.to_str().unwrap_or_default(),
+                original_release_dir.to_str().unwrap_or_default(),
+                verbose,
+            )?;
+        }
+    }
+
+    // Handle confext release directory
+    if extension.is_confext {
+        let original_release_dir = extension.path.join("etc/extension-release.d");
+        if original_release_dir.exists() {
+            let staging_dir = PathBuf::from(&staging_base)
+                .join(prefixed_name)
+                .join("confext");
+            fs::create_dir_all(&staging_dir).context("create directory", &staging_dir)?;
+
+            // Copy all existing files from original release dir
+            if let Ok(entries) = read_dir_sorted(&original_release_dir) {
+                for entry in entries {
+                    if entry.path().is_file() {
+                        let dest = staging_dir.join(entry.file_name());
+                        fs::copy(entry.path(), &dest)
+                            .context("copy extension-release file to", &dest)?;
+                    }
+                }
+            }
+
+            let original_release =
+                original_release_dir.join(format!("extension-release.{original_name}"));
+            let original_release = if original_release.exists() {
+                original_release
+            } else {
+                original_release_dir.join(format!("extension-release.{}", extension.name))
+            };
+
+            let prefixed_release = staging_dir.join(format!("extension-release.{prefixed_name}"));
+            if original_release.exists() && !prefixed_release.exists() {
+                fs::copy(&original_release, &prefixed_release)
+                    .context("copy extension-release file to", &prefixed_release)?;
+            }
+
+            run_bind_mount(
+                staging_dir.to_str().unwrap_or_default(),
+                original_release_dir.to_str().unwrap_or_default(),
+                verbose,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create target directories for symlinks
+pub(super) fn create_target_directories() -> Result<(), SystemdError> {
+    let (sysext_dir, confext_dir) = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        // In test mode, use temporary directories
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        (
+            format!("{temp_base}/test_extensions"),
+            format!("{temp_base}/test_confexts"),
+        )
+    } else {
+        (
+            crate::user_mode::system_path("/run/extensions"),
+            crate::user_mode::system_path("/run/confexts"),
+        )
+    };
+
+    // Create /run/extensions (or test equivalent) if it doesn't exist
+    if !Path::new(&sysext_dir).exists() {
+        fs::create_dir_all(&sysext_dir).context("create directory", &sysext_dir)?;
+    }
+
+    // Create /run/confexts (or test equivalent) if it doesn't exist
+    if !Path::new(&confext_dir).exists() {
+        fs::create_dir_all(&confext_dir).context("create directory", &confext_dir)?;
+    }
+
+    Ok(())
+}
+
+/// Create a sysext or confext symlink named `symlink_name` (possibly
+/// prefixed for merge ordering) pointing at `source`.
+pub(super) fn create_extension_symlink(
+    kind: LinkKind,
+    symlink_name: &str,
+    source: &Path,
+    verbose: bool,
+) -> Result<(), SystemdError> {
+    let target_path = format!("{}/{symlink_name}", kind.dir());
+
+    // Remove existing symlink or file if it exists
+    if Path::new(&target_path).exists() {
+        let path = Path::new(&target_path);
+
+        // Try to remove as file first (works for symlinks and regular files)
+        if fs::remove_file(&target_path).is_err() {
+            // If that fails, it might be a directory
+            if path.is_dir() {
+                fs::remove_dir_all(&target_path).context("remove directory", &target_path)?;
+            }
+        }
+    }
+
+    // Create symlink
+    unix_fs::symlink(source, &target_path).context("create symlink", &target_path)?;
+
+    if verbose {
+        println!(
+            "Created {} symlink: {} -> {}",
+            kind.label(),
+            target_path,
+            source.display()
+        );
+    }
+    Ok(())
+}
+
+/// An extension as hook commands see it in their environment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct HookTarget {
+    pub name: String,
+    pub version: Option<String>,
+    pub mount_point: String,
+}
+
+/// Hook targets by versioned name, the name [`HookOwners`] use
+pub(super) type HookTargets = std::collections::BTreeMap<String, HookTarget>;
+
+pub(super) fn hook_targets_of(extensions: &[Extension]) -> HookTargets {
+    extensions
+        .iter()
+        .map(|ext| {
+            let target = HookTarget {
+                name: ext.name.clone(),
+                version: ext.version.clone(),
+                mount_point: ext.path.to_string_lossy().to_string(),
+            };
+            (versioned_name(ext), target)
+        })
+        .collect()
+}
+
+/// Environment of a hook command declared by `owner` and run at
+/// `operation` (`merge` or `unmerge`)
+pub(super) fn hook_env(
+    operation: &str,
+    owner: Option<&str>,
+    targets: &HookTargets,
+    os_version: &str,
+) -> Vec<(&'static str, String)> {
+    let target = owner.map(|owner| {
+        targets.get(owner).cloned().unwrap_or_else(|| HookTarget {
+            name: owner.to_string(),
+            version: None,
+            mount_point: String::new(),
+        })
+    });
+    vec![
+        ("AVOCADO_OPERATION", operation.to_string()),
+        ("AVOCADO_OS_VERSION", os_version.to_string()),
+        (
+            "AVOCADO_EXTENSION",
+            target.as_ref().map(|t| t.name.clone()).unwrap_or_default(),
+        ),
+        (
+            "AVOCADO_VERSION",
+            target
+                .as_ref()
+                .and_then(|t| t.version.clone())
+                .unwrap_or_default(),
+        ),
+        (
+            "AVOCADO_MOUNT_POINT",
+            target.map(|t| t.mount_point).unwrap_or_default(),
+        ),
+    ]
+}
+
+/// Run one migration script, recording `version` as migrated only when it
+/// succeeds. Failures are warnings, so the script runs again on the next
+/// merge.
+pub(super) fn run_data_migration(
+    extension: &Extension,
+    script: &str,
+    version: &str,
+    out: &OutputManager,
+) {
+    let from = crate::migrations::migrated_version(&extension.name);
+    out.log_info(&format!(
+        "Migrating data of '{}' from {} to {version}: {script}",
+        extension.name,
+        from.as_deref().unwrap_or("(none)")
+    ));
+
+    let parts: Vec<&str> = script.split_whitespace().collect();
+    let Some((program, args)) = parts.split_first() else {
+        return;
+    };
+
+    let succeeded = if let Some(result) = crate::backend::simulate(program, args) {
+        result.is_ok()
+    } else {
+        let output = match run_with_progress(
+            ProcessCommand::new(crate::tools::program(program))
+                .args(args)
+                .env(crate::migrations::FROM_ENV, from.unwrap_or_default())
+                .env(crate::migrations::TO_ENV, version),
+            TimeoutKind::HookCmd,
+            program,
+            &[Stream::Stdout, Stream::Stderr],
+            out,
+        ) {
+            Ok(output) => output,
+            Err(e) => {
+                out.warning(&format!(
+                    "Migration '{script}' of '{}': {e}",
+                    extension.name
+                ));
+                return;
+            }
+        };
+        let logs =
+            crate::hook_log::record(&[versioned_name(extension)], "migrate", script, &output);
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let exit = output.status.code().map_or_else(
+                || "a signal".to_string(),
+                |code| format!("exit code {code}"),
+            );
+            let mut message = format!(
+                "Migration '{script}' of '{}' failed with {exit}: {}; it runs again on the next merge",
+                extension.name,
+                crate::hook_log::stderr_summary(&stderr)
+            );
+            if let Some(log) = logs.first() {
+                message.push_str(&format!(" (full output: {})", log.display()));
+            }
+            out.warning(&message);
+        }
+        output.status.success()
+    };
+
+    if succeeded {
+        match crate::migrations::record(&extension.name, version) {
+            Ok(()) => out.log_success(&format!(
+                "Migrated data of '{}' to {version}",
+                extension.name
+            )),
+            Err(e) => out.warning(&format!(
+                "Failed to record the migration of '{}' to {version}: {e}",
+                extension.name
+            )),
+        }
+    }
+}
+
+/// Remove every blacklist file previously written by avocadoctl.
+pub(super) fn remove_modprobe_blacklists(out: &OutputManager) {
+    let dir = modprobe_blacklist_dir();
+    let Ok(entries) = read_dir_sorted(&dir) else {
+        return;
+    };
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if is_modprobe_blacklist(&name) {
+            match fs::remove_file(entry.path()) {
+                Ok(()) => out.log_info(&format!("Removed module blacklist {name}")),
+                Err(e) => out.warning(&format!("Failed to remove module blacklist {name}: {e}")),
+            }
+        }
+    }
+}
+
+/// Replace the blacklist files with one per extension that requests any.
+pub(super) fn write_modprobe_blacklists(
+    requests: &[ModuleRequests],
+    out: &OutputManager,
+) -> Result<(), SystemdError> {
+    remove_modprobe_blacklists(out);
+
+    let dir = modprobe_blacklist_dir();
+    for request in requests.iter().filter(|r| !r.blacklist.is_empty()) {
+        fs::create_dir_all(&dir).context("create directory", &dir)?;
+        let mut content = format!(
+            "# Written by avocadoctl for extension {}\n",
+            request.extension
+        );
+        for module in &request.blacklist {
+            content.push_str(&format!("blacklist {module}\n"));
+        }
+        let path = dir.join(format!(
+            "{MODPROBE_BLACKLIST_PREFIX}{}.conf",
+            request.extension
+        ));
+        fs::write(&path, content).context("write", &path)?;
+        out.log_info(&format!(
+            "Blacklisted kernel modules for {}: {}",
+            request.extension,
+            request.blacklist.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Run modprobe for a list of modules
+pub(super) fn run_modprobe(modules: &[String], out: &OutputManager) -> Result<(), SystemdError> {
+    if modules.is_empty() {
+        return Ok(());
+    }
+
+    out.log_info(&format!("Loading kernel modules: {}", modules.join(", ")));
+
+    for module in modules {
+        if let Some(result) = crate::backend::simulate("modprobe", &[module]) {
+            result?;
+            out.log_success(&format!("Module {module} loaded successfully."));
+            continue;
+        }
+
+        let command_name = crate::tools::program("modprobe");
+
+        let output = match run_with_progress(
+            ProcessCommand::new(&command_name).arg(module),
+            TimeoutKind::HookCmd,
+            "modprobe",
+            &[Stream::Stdout, Stream::Stderr],
+            out,
+        ) {
+            Ok(output) => output,
+            // A module that hangs while loading is stopped and skipped like
+            // one that fails to load
+            Err(e @ crate::timeouts::RunError::TimedOut { .. }) => {
+                out.warning(&format!("Loading module {module} {e}; stopped modprobe"));
+                continue;
+            }
+            Err(e) => return Err(e.into_systemd_error(format!("{command_name} {module}"))),
+        };
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            out.warning(&format!("Failed to load module {module}: {stderr}"));
+            // Don't fail the entire operation for individual module failures
+            // Just log the warning and continue with other modules
+        } else {
+            out.log_success(&format!("Module {module} loaded successfully."));
+        }
+    }
+
+    out.log_success("Module loading completed.");
+    Ok(())
+}
+
+/// Execute a single command with its arguments and `env`, recording its
+/// output in the hook logs of `extensions`
+pub(super) fn execute_single_command(
+    command_str: &str,
+    phase: &str,
+    extensions: &[String],
+    env: &[(&str, String)],
+    out: &OutputManager,
+) -> Result<(), SystemdError> {
+    // Parse the command string to handle commands with arguments
+    // Commands may be quoted or contain spaces
+    let parts: Vec<&str> = if command_str.starts_with('"') && command_str.ends_with('"') {
+        // Handle quoted commands
+        let unquoted = &command_str[1..command_str.len() - 1];
+        unquoted.split_whitespace().collect()
+    } else {
+        // Handle unquoted commands
+        command_str.split_whitespace().collect()
+    };
+
+    if parts.is_empty() {
+        out.warning("Empty command in AVOCADO_ON_MERGE, skipping");
+        return Ok(());
+    }
+
+    let (command_name, args) = parts.split_first().unwrap();
+
+    if let Some(result) = crate::backend::simulate(command_name, args) {
+        result?;
+        out.log_success(&format!("Command '{command_str}' completed successfully"));
+        return Ok(());
+    }
+
+    // Configured tools (depmod, modprobe) resolve to their override; other
+    // commands run by name, or as mock-<name> in test mode
+    let actual_command = &crate::tools::program(command_name);
+
+    let output = match run_with_progress(
+        ProcessCommand::new(actual_command)
+            .args(args)
+            .envs(env.iter().cloned()),
+        TimeoutKind::HookCmd,
+        command_name,
+        &[Stream::Stdout, Stream::Stderr],
+        out,
+    ) {
+        Ok(output) => output,
+        Err(e @ crate::timeouts::RunError::TimedOut { .. }) => {
+            out.warning(&format!("Command '{command_str}' {e}; stopped it"));
+            return Ok(());
+        }
+        Err(e) => return Err(e.into_systemd_error(command_str)),
+    };
+
+    let logs = crate::hook_log::record(extensions, phase, command_str, &output);
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let exit = output.status.code().map_or_else(
+            || "a signal".to_string(),
+            |code| format!("exit code {code}"),
+        );
+        let mut message = format!(
+            "Command '{command_str}' failed with {exit}: {}",
+            crate::hook_log::stderr_summary(&stderr)
+        );
+        if let Some(log) = logs.first() {
+            message.push_str(&format!(" (full output: {})", log.display()));
+        }
+        out.warning(&message);
+        // Log warning but don't fail the entire operation
+        // This matches the behavior of modprobe failures
+    } else {
+        out.log_success(&format!("Command '{command_str}' completed successfully"));
+    }
+
+    Ok(())
+}
+
+/// Span of one hook command run at `kind` (`on-merge`, `on-unmerge`)
+pub(super) fn hook_span(command: &str, kind: &str) -> crate::telemetry::Span {
+    let span = crate::telemetry::span("hook");
+    span.set_attribute("avocado.hook.kind", kind);
+    span.set_attribute("avocado.hook.command", command);
+    span
+}
+
+/// Run the hook `command_str` at `operation` (`merge` or `unmerge`) once
+/// for each of the `extensions` declaring it, with that extension's
+/// environment, or once without an extension when none is known.
+pub(super) fn run_hook_command(
+    command_str: &str,
+    operation: &str,
+    extensions: &[String],
+    targets: &HookTargets,
+    os_version: &str,
+    out: &OutputManager,
+) -> Result<(), SystemdError> {
+    let kind = format!("on-{operation}");
+    let owners: Vec<Option<&String>> = if extensions.is_empty() {
+        vec![None]
+    } else {
+        extensions.iter().map(Some).collect()
+    };
+    for owner in owners {
+        let env = hook_env(operation, owner.map(String::as_str), targets, os_version);
+        let recorded = owner.map(std::slice::from_ref).unwrap_or(&[]);
+
+        // Check if the command contains shell operators like semicolons
+        if command_str.contains(';') {
+            // Split the command by semicolons and execute each part sequentially
+            let sub_commands: Vec<&str> = command_str.split(';').map(|s| s.trim()).collect();
+
+            for sub_command in sub_commands {
+                if !sub_command.is_empty() {
+                    out.log_info(&format!("Running sub-command: {sub_command}"));
+                    hook_span(sub_command, &kind).record(execute_single_command(
+                        sub_command,
+                        &kind,
+                        recorded,
+                        &env,
+                        out,
+                    ))?;
+                }
+            }
+        } else {
+            // Execute as a single command
+            hook_span(command_str, &kind).record(execute_single_command(
+                command_str,
+                &kind,
+                recorded,
+                &env,
+                out,
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Run accumulated AVOCADO_ON_MERGE commands
+pub(super) fn run_avocado_on_merge_commands(
+    commands: &[String],
+    owners: &HookOwners,
+    targets: &HookTargets,
+    out: &OutputManager,
+) -> Result<(), SystemdError> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    out.log_info(&format!("Executing {} post-merge commands", commands.len()));
+
+    let os_version = read_os_version_id();
+    for command_str in commands {
+        out.log_info(&format!("Running command: {command_str}"));
+        let extensions = owners.get(command_str).map(Vec::as_slice).unwrap_or(&[]);
+        run_hook_command(command_str, "merge", extensions, targets, &os_version, out)?;
+    }
+
+    out.log_success("Post-merge command execution completed.");
+    Ok(())
+}
+
+/// Execute a bind mount, or simulate in test mode.
+pub(super) fn run_bind_mount(
+    source: &str,
+    target: &str,
+    verbose: bool,
+) -> Result<(), SystemdError> {
+    if verbose {
+        println!("Bind mounting {source} -> {target}");
+    }
+
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        // In test mode, skip actual mount syscall
+        return Ok(());
+    }
+
+    let output = ProcessCommand::new("mount")
+        .args(["--bind", source, target])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| SystemdError::CommandFailed {
+            command: "mount --bind".to_string(),
+            source: e,
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(SystemdError::CommandExitedWithError {
+            command: format!("mount --bind {source} {target}"),
+            exit_code: output.status.code(),
+            stderr: stderr.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Run `cmd` within its `kind` limit, showing each line it writes to
+/// `streams` as progress of `label` while it runs (see
+/// [`OutputManager::command_output`]). Output is still captured in full.
+pub(super) fn run_with_progress(
+    cmd: &mut ProcessCommand,
+    kind: TimeoutKind,
+    label: &str,
+    streams: &[Stream],
+    out: &OutputManager,
+) -> Result<std::process::Output, crate::timeouts::RunError> {
+    crate::timeouts::output_streaming(cmd, kind, &|stream, line| {
+        if streams.contains(&stream) {
+            out.command_output(label, line);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::ext::plan::HookStage;
+
+    #[test]
+    fn test_hook_stage_batches() {
+        let hook = |stage, command: &str| MergeAction::Hook {
+            stage,
+            command: command.to_string(),
+            extensions: Vec::new(),
+        };
+        let tasks = [
+            hook(HookStage::BeforeReload, "depmod"),
+            hook(HookStage::BeforeReload, "ldconfig"),
+            MergeAction::DaemonReload,
+            hook(HookStage::AfterReload, "systemctl restart a"),
+            hook(HookStage::AfterReload, "systemctl restart b"),
+        ];
+        let sizes: Vec<usize> = tasks.chunk_by(same_hook_stage).map(<[_]>::len).collect();
+        assert_eq!(sizes, vec![2, 1, 2]);
+    }
+
+    #[test]
+    fn test_hook_env() {
+        let mut targets = HookTargets::new();
+        targets.insert(
+            "camera-1.2".to_string(),
+            HookTarget {
+                name: "camera".to_string(),
+                version: Some("1.2".to_string()),
+                mount_point: "/run/avocado/images/camera-1.2".to_string(),
+            },
+        );
+        let env = hook_env("merge", Some("camera-1.2"), &targets, "3.0");
+        assert_eq!(
+            env,
+            [
+                ("AVOCADO_OPERATION", "merge".to_string()),
+                ("AVOCADO_OS_VERSION", "3.0".to_string()),
+                ("AVOCADO_EXTENSION", "camera".to_string()),
+                ("AVOCADO_VERSION", "1.2".to_string()),
+                (
+                    "AVOCADO_MOUNT_POINT",
+                    "/run/avocado/images/camera-1.2".to_string()
+                ),
+            ]
+        );
+
+        // A target the last merge did not record still has its name
+        let env = hook_env("unmerge", Some("tools"), &targets, "3.0");
+        assert_eq!(env[2], ("AVOCADO_EXTENSION", "tools".to_string()));
+        assert_eq!(env[3], ("AVOCADO_VERSION", String::new()));
+    }
+}
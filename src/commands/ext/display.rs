@@ -0,0 +1,314 @@
+//! Tables of `ext list --detailed` and `ext status`, and the `ext env`
+//! exports, printed from the service-layer types whether the daemon or this
+//! process collected them.
+
+use crate::commands::image_adaptor::is_running_in_initrd;
+#[cfg(feature = "daemon")]
+use crate::extension_release::Lifecycle;
+use crate::output::OutputManager;
+use crate::service::types::{ExtensionDetail, ExtensionStatus};
+
+pub(crate) fn type_label(is_sysext: bool, is_confext: bool) -> String {
+    match (is_sysext, is_confext) {
+        (true, true) => "sys+conf",
+        (true, false) => "sys",
+        (false, true) => "conf",
+        (false, false) => "?",
+    }
+    .to_string()
+}
+
+/// Print the table of `ext list --detailed`.
+pub fn print_extension_details(extensions: &[ExtensionDetail], output: &OutputManager) {
+    if output.is_json() {
+        if let Err(e) = output.json_document(&extensions) {
+            output.error("Output", &format!("JSON serialization failed: {e}"));
+            output.exit(1);
+        }
+        return;
+    }
+
+    if extensions.is_empty() {
+        println!("No extensions found.");
+        return;
+    }
+
+    let width = |header: &str, value: &dyn Fn(&ExtensionDetail) -> usize| {
+        extensions
+            .iter()
+            .map(value)
+            .max()
+            .unwrap_or(0)
+            .max(header.len())
+    };
+    let name_width = width("Extension", &|e| e.name.len());
+    let version_width = width("Version", &|e| e.version.as_deref().unwrap_or("-").len());
+
+    println!(
+        "{:<nw$} {:<vw$} {:<9} {:<16} {:<8} Origin",
+        "Extension",
+        "Version",
+        "Type",
+        "Scope",
+        "Enabled",
+        nw = name_width,
+        vw = version_width
+    );
+    println!(
+        "{}",
+        "=".repeat(name_width + version_width + 9 + 16 + 8 + 4 + 20)
+    );
+
+    for ext in extensions {
+        let scope = match &ext.scopes {
+            Some(scopes) if scopes.is_empty() => "any".to_string(),
+            Some(scopes) => scopes.join(","),
+            None => "?".to_string(),
+        };
+        let enabled = if ext.enabled.unwrap_or(false) {
+            "yes"
+        } else {
+            "no"
+        };
+        println!(
+            "{:<nw$} {:<vw$} {:<9} {:<16} {:<8} {}",
+            ext.name,
+            ext.version.as_deref().unwrap_or("-"),
+            type_label(ext.is_sysext, ext.is_confext),
+            scope,
+            enabled,
+            ext.origin.as_deref().unwrap_or("-"),
+            nw = name_width,
+            vw = version_width
+        );
+    }
+
+    let enabled = extensions
+        .iter()
+        .filter(|e| e.enabled.unwrap_or(false))
+        .count();
+    println!();
+    println!(
+        "Total: {} extension(s), {enabled} enabled for this os-release",
+        extensions.len()
+    );
+}
+
+/// Print the table of `ext status` as the daemon reports it.
+#[cfg(feature = "daemon")]
+pub fn print_extension_status(
+    extensions: &[ExtensionStatus],
+    updates_only: bool,
+    output: &OutputManager,
+) {
+    if output.is_json() {
+        if let Err(e) = output.json_document(&extensions) {
+            output.error("Output", &format!("JSON serialization failed: {e}"));
+            output.exit(1);
+        }
+        return;
+    }
+
+    if extensions.is_empty() {
+        if updates_only {
+            println!("No extension updates available.");
+        } else {
+            println!("No extensions currently merged.");
+        }
+        return;
+    }
+
+    // The update column is only shown when the daemon has a repository index
+    let show_updates = extensions.iter().any(|e| e.latest_version.is_some());
+    let update_header = if show_updates {
+        format!("{:<12} ", "Update")
+    } else {
+        String::new()
+    };
+
+    let name_width = extensions
+        .iter()
+        .map(|e| e.name.len() + e.version.as_ref().map(|v| v.len() + 1).unwrap_or(0))
+        .max()
+        .unwrap_or(9)
+        .max(9);
+
+    println!(
+        "{:<nw$} {:<12} {:<14} {:<14} {update_header}Origin",
+        "Extension",
+        "Type",
+        "Merged",
+        "Scope",
+        nw = name_width
+    );
+    println!(
+        "{}",
+        "=".repeat(name_width + 1 + 12 + 1 + 14 + 1 + 14 + 1 + update_header.len() + 20)
+    );
+
+    for ext in extensions {
+        let versioned_name = match &ext.version {
+            Some(v) => format!("{}-{}", ext.name, v),
+            None => ext.name.clone(),
+        };
+
+        let mut types = Vec::new();
+        if ext.is_sysext {
+            types.push("sys");
+        }
+        if ext.is_confext {
+            types.push("conf");
+        }
+        let type_str = if types.is_empty() {
+            "?".to_string()
+        } else {
+            let base = types.join("+");
+            if ext.image_type.as_deref() == Some("kab") {
+                format!("kab:{base}")
+            } else {
+                base
+            }
+        };
+
+        let merged_str = match (ext.is_merged, ext.applicable) {
+            (true, _) => "yes",
+            (false, Some(false)) => "SKIPPED(scope)",
+            (false, _) => "no",
+        };
+        let scope = scope_label(ext.scopes.as_deref());
+        let origin = ext.origin.as_deref().unwrap_or("-");
+        let update_str = match (&ext.latest_version, ext.update_available) {
+            _ if !show_updates => String::new(),
+            (Some(latest), Some(true)) => format!("{latest:<12} "),
+            (Some(_), Some(false)) => format!("{:<12} ", "-"),
+            _ => format!("{:<12} ", "?"),
+        };
+
+        println!(
+            "{versioned_name:<name_width$} {type_str:<12} {merged_str:<14} {scope:<14} {update_str}{origin}"
+        );
+        for partition in ext.partitions.iter().flatten() {
+            println!("  {}", crate::ddi::describe(partition));
+        }
+        if let Some(aliases) = ext.aliases.as_ref().filter(|a| !a.is_empty()) {
+            println!("  alias: {}", aliases.join(", "));
+        }
+    }
+
+    println!();
+    let merged_count = extensions.iter().filter(|e| e.is_merged).count();
+    let since = extensions
+        .iter()
+        .filter_map(|e| e.merged_since)
+        .max()
+        .map(|since| format!(" (since {})", crate::clock::format_time(since as u64)))
+        .unwrap_or_default();
+    println!(
+        "Total: {} extension(s), {} merged{since}",
+        extensions.len(),
+        merged_count
+    );
+    if show_updates {
+        let update_count = extensions
+            .iter()
+            .filter(|e| e.update_available == Some(true))
+            .count();
+        println!("Updates available: {update_count}");
+    }
+
+    let reboot_required: Vec<&str> = extensions
+        .iter()
+        .filter(|e| e.reboot_required == Some(true))
+        .map(|e| e.name.as_str())
+        .collect();
+    if !reboot_required.is_empty() {
+        println!("Reboot required by: {}", reboot_required.join(", "));
+    }
+    let out_of_scope: Vec<&str> = extensions
+        .iter()
+        .filter(|e| !e.is_merged && e.applicable == Some(false))
+        .map(|e| e.name.as_str())
+        .collect();
+    if !out_of_scope.is_empty() {
+        println!(
+            "Out of scope in {}, not merged: {}",
+            current_environment(),
+            out_of_scope.join(", ")
+        );
+    }
+    let safe_mode_skipped: Vec<&str> = extensions
+        .iter()
+        .filter(|e| e.safe_mode_skipped == Some(true))
+        .map(|e| e.name.as_str())
+        .collect();
+    if !safe_mode_skipped.is_empty() {
+        println!("Skipped in safe mode: {}", safe_mode_skipped.join(", "));
+    }
+
+    let now = crate::clock::now();
+    for ext in extensions {
+        let lifecycle = Lifecycle {
+            eol: ext.eol.clone(),
+            ..Default::default()
+        };
+        if let Some(eol) = lifecycle
+            .eol
+            .as_deref()
+            .filter(|_| lifecycle.eol_reached_at(now))
+        {
+            let name = match &ext.version {
+                Some(v) => format!("{}-{v}", ext.name),
+                None => ext.name.clone(),
+            };
+            println!("{}", eol_warning(&name, eol));
+        }
+    }
+}
+
+/// Print `ext env` exports for the merged extensions, or only `name`.
+pub fn print_extension_env(
+    extensions: &[ExtensionStatus],
+    name: Option<&str>,
+    output: &OutputManager,
+) {
+    let vars = match crate::shell_env::exports(extensions, name) {
+        Ok(vars) => vars,
+        Err(e) => {
+            output.error("Extension Env", &e);
+            output.exit(1);
+        }
+    };
+    if output.is_json() {
+        let map: serde_json::Map<String, serde_json::Value> = vars
+            .into_iter()
+            .map(|(k, v)| (k, serde_json::Value::String(v)))
+            .collect();
+        output.result("variables", &map);
+        return;
+    }
+    print!("{}", crate::shell_env::render(&vars));
+}
+
+/// The environment scopes are checked against.
+pub(crate) fn current_environment() -> &'static str {
+    if is_running_in_initrd() {
+        "initrd"
+    } else {
+        "system"
+    }
+}
+
+/// Scope column of `ext status`: the declared scopes, `all` without a
+/// scope key, `-` when unknown.
+pub(crate) fn scope_label(scopes: Option<&[String]>) -> String {
+    match scopes {
+        None => "-".to_string(),
+        Some([]) => "all".to_string(),
+        Some(scopes) => scopes.join(","),
+    }
+}
+
+/// The status line warning that `name` reached its end of life on `eol`.
+pub fn eol_warning(name: &str, eol: &str) -> String {
+    format!("Warning: {name} reached its end of life on {eol}")
+}
@@ -0,0 +1,984 @@
+//! Plan phase of a merge.
+//!
+//! [`plan_merge`] turns a [scan](super::scan) into the link changes that
+//! prepare `/run/extensions` and `/run/confexts`. It is pure, so it backs
+//! `--dry-run` and `ext diff` and is tested without touching the system.
+//! [`plan_post_merge`] lists what runs once systemd has merged the linked
+//! extensions: module blacklists, the on-merge commands that have to run
+//! before the daemon-reload (depmod, ldconfig), module loading, the
+//! daemon-reload, data migrations and the remaining on-merge commands. It
+//! reads release files but changes nothing. The [apply](super::apply) phase
+//! executes both lists in order.
+
+use super::scan::LinkKind;
+use super::scan::MergeScan;
+use super::source::{extension_release_files, versioned_name, Extension};
+use crate::commands::image_adaptor::{is_scope_enabled_for_current_environment, SystemdError};
+use crate::extension_release::{
+    parse_avocado_modprobe, parse_avocado_on_merge_commands, ReleaseFile,
+};
+use crate::merge_failures::Failure;
+use crate::ordering::{hook_order, read_dir_sorted, HookRank};
+use crate::output::OutputManager;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// When an on-merge command runs relative to the daemon-reload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum HookStage {
+    /// depmod and ldconfig, so modules and libraries are in place when
+    /// systemd re-evaluates units
+    BeforeReload,
+    /// Everything else, e.g. service restarts
+    AfterReload,
+}
+
+/// One step of a merge, as planned before any of them runs.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub(super) enum MergeAction {
+    /// Remove a symlink that no longer belongs to an enabled extension.
+    Unlink { kind: LinkKind, name: String },
+    /// Stage `extension-release.<name>` so systemd accepts the prefixed name.
+    StageRelease { name: String, source: PathBuf },
+    /// Point `<kind dir>/<name>` at `source`, replacing whatever is there.
+    Link {
+        kind: LinkKind,
+        name: String,
+        source: PathBuf,
+    },
+    /// Replace the modprobe.d blacklists avocadoctl wrote with these, by
+    /// extension.
+    Blacklist {
+        blacklists: BTreeMap<String, Vec<String>>,
+    },
+    /// Run an AVOCADO_ON_MERGE command declared by `extensions`.
+    Hook {
+        stage: HookStage,
+        command: String,
+        extensions: Vec<String>,
+    },
+    /// Load kernel modules with modprobe.
+    Modprobe { modules: Vec<String> },
+    /// Reload systemd's units, if anything asked for a reload.
+    DaemonReload,
+    /// Run the AVOCADO_MIGRATE script of `extension` for data `version`.
+    Migrate {
+        extension: String,
+        script: String,
+        version: String,
+    },
+}
+
+impl std::fmt::Display for MergeAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeAction::Unlink { kind, name } => write!(f, "unlink {}/{name}", kind.label()),
+            MergeAction::StageRelease { name, source } => {
+                write!(f, "stage extension-release.{name} for {}", source.display())
+            }
+            MergeAction::Link { kind, name, source } => {
+                write!(f, "link {}/{name} -> {}", kind.label(), source.display())
+            }
+            MergeAction::Blacklist { blacklists } if blacklists.is_empty() => {
+                write!(f, "remove module blacklists")
+            }
+            MergeAction::Blacklist { blacklists } => {
+                let lists: Vec<String> = blacklists
+                    .iter()
+                    .map(|(extension, modules)| format!("{} ({extension})", modules.join(", ")))
+                    .collect();
+                write!(f, "blacklist modules {}", lists.join("; "))
+            }
+            MergeAction::Hook {
+                stage,
+                command,
+                extensions,
+            } => {
+                write!(f, "run on-merge command: {command}")?;
+                if *stage == HookStage::BeforeReload {
+                    write!(f, " before daemon-reload")?;
+                }
+                if !extensions.is_empty() {
+                    write!(f, " ({})", extensions.join(", "))?;
+                }
+                Ok(())
+            }
+            MergeAction::Modprobe { modules } => {
+                write!(f, "load kernel modules {}", modules.join(", "))
+            }
+            MergeAction::DaemonReload => write!(f, "daemon-reload"),
+            MergeAction::Migrate {
+                extension,
+                script,
+                version,
+            } => write!(f, "migrate data of {extension} to {version}: {script}"),
+        }
+    }
+}
+
+/// The link changes that turn the scanned state into the desired one, and
+/// the extensions that end up linked (used for post-merge tasks).
+pub(super) struct MergePlan {
+    pub actions: Vec<MergeAction>,
+    pub enabled: Vec<Extension>,
+    pub failed: Vec<Failure>,
+}
+
+/// Whether an existing link should be removed given the expected link names.
+/// Besides links that are no longer expected, a versioned link ("myext-1.0.0")
+/// is masked by a non-versioned extension of the same name (a HITL mount).
+pub(super) fn is_stale_link(
+    file_name: &str,
+    expected: &HashSet<String>,
+    non_versioned: &HashSet<String>,
+) -> bool {
+    // Remove .raw suffix if present for comparison
+    let name_without_raw = file_name.strip_suffix(".raw").unwrap_or(file_name);
+    if !expected.contains(file_name) && !expected.contains(name_without_raw) {
+        return true;
+    }
+    match name_without_raw.rsplit_once('-') {
+        // Only treat the suffix as a version if it contains digits or dots
+        Some((base_name, potential_version))
+            if potential_version
+                .chars()
+                .any(|c| c.is_ascii_digit() || c == '.') =>
+        {
+            non_versioned.contains(base_name)
+        }
+        _ => false,
+    }
+}
+
+/// Compute the link changes for a merge from a scan.
+pub(super) fn plan_merge(scan: &MergeScan) -> MergePlan {
+    let linked: Vec<&Extension> = scan
+        .extensions
+        .iter()
+        .filter(|ext| ext.is_sysext || ext.is_confext)
+        .collect();
+
+    let expected: HashSet<String> = linked
+        .iter()
+        .map(|ext| compute_prefixed_name(ext))
+        .collect();
+    let non_versioned: HashSet<String> = linked
+        .iter()
+        .filter(|ext| ext.version.is_none() && ext.merge_index.is_none())
+        .map(|ext| ext.name.clone())
+        .collect();
+
+    let mut actions = Vec::new();
+    for (kind, links) in [
+        (LinkKind::Sysext, &scan.sysext_links),
+        (LinkKind::Confext, &scan.confext_links),
+    ] {
+        for name in links {
+            if is_stale_link(name, &expected, &non_versioned) {
+                actions.push(MergeAction::Unlink {
+                    kind,
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    let mut enabled = Vec::new();
+    for ext in linked {
+        let prefixed_name = compute_prefixed_name(ext);
+        if is_stale_link(&prefixed_name, &expected, &non_versioned) {
+            continue;
+        }
+
+        // Stage extension-release files only if ordering renamed the extension
+        let original_name = match &ext.version {
+            Some(ver) => format!("{}-{}", ext.name, ver),
+            None => ext.name.clone(),
+        };
+        if ext.merge_index.is_some() && prefixed_name != original_name {
+            actions.push(MergeAction::StageRelease {
+                name: prefixed_name.clone(),
+                source: ext.path.clone(),
+            });
+        }
+        for (kind, wanted) in [
+            (LinkKind::Sysext, ext.is_sysext),
+            (LinkKind::Confext, ext.is_confext),
+        ] {
+            if wanted {
+                actions.push(MergeAction::Link {
+                    kind,
+                    name: prefixed_name.clone(),
+                    source: ext.path.clone(),
+                });
+            }
+        }
+        enabled.push(ext.clone());
+    }
+
+    MergePlan {
+        actions,
+        enabled,
+        failed: scan.failed.clone(),
+    }
+}
+
+/// Compute the tasks after systemd merged `enabled`, in the order they run.
+/// With `entering` (a partial refresh), module loading and the commands
+/// after the daemon-reload are limited to those extensions. depmod and
+/// ldconfig still run for every merged extension: their results depend on
+/// the whole merged set.
+pub(super) fn plan_post_merge(
+    enabled: &[Extension],
+    entering: Option<&[String]>,
+    output: &OutputManager,
+) -> Result<Vec<MergeAction>, SystemdError> {
+    // Host-wide side effects don't apply to a user-mode prefix
+    if crate::user_mode::is_user() {
+        output.log_info("User mode: skipping on-merge commands, module loading and daemon-reload");
+        return Ok(Vec::new());
+    }
+
+    let (on_merge_commands, mut modprobe_modules) =
+        scan_release_files_for_enabled_extensions(&in_hook_order(enabled))?;
+    let hook_owners = on_merge_hook_owners(enabled);
+    if let Some(entering) = entering {
+        let entering_extensions: Vec<Extension> = enabled
+            .iter()
+            .filter(|ext| entering.contains(&versioned_name(ext)))
+            .cloned()
+            .collect();
+        modprobe_modules = scan_release_files_for_enabled_extensions(&entering_extensions)?.1;
+    }
+
+    // Blacklists must be in place before any module is loaded. When one
+    // extension blacklists a module another extension loads, the blacklist wins.
+    let module_requests = collect_module_requests(enabled);
+    let conflicts = find_blacklist_conflicts(&module_requests);
+    for conflict in &conflicts {
+        output.progress(&format!(
+            "Warning: module {} is blacklisted by '{}' but loaded by '{}'; not loading it",
+            conflict.module, conflict.blacklisted_by, conflict.loaded_by
+        ));
+    }
+    let modprobe_modules: Vec<String> = modprobe_modules
+        .into_iter()
+        .filter(|module| !conflicts.iter().any(|c| &c.module == module))
+        .collect();
+
+    // Remove duplicates while preserving order
+    let mut unique_commands = Vec::new();
+    for command in on_merge_commands {
+        if !unique_commands.contains(&command) {
+            unique_commands.push(command);
+        }
+    }
+    let (pre_reload, post_reload): (Vec<_>, Vec<_>) = unique_commands
+        .into_iter()
+        .partition(|cmd| is_pre_daemon_reload_command(cmd));
+    // A command runs once for each extension declaring it; in a partial
+    // refresh, after the reload only for the entering ones
+    let hook = |stage, command: String| MergeAction::Hook {
+        stage,
+        extensions: hook_owners
+            .get(&command)
+            .into_iter()
+            .flatten()
+            .filter(|owner| {
+                stage == HookStage::BeforeReload || entering.is_none_or(|e| e.contains(owner))
+            })
+            .cloned()
+            .collect(),
+        command,
+    };
+
+    let mut actions = Vec::new();
+    // The kernel belongs to the host in a container: no blacklists, depmod,
+    // ldconfig or module loading
+    if crate::container::is_container() {
+        output.log_info("Container mode: skipping module loading, depmod and ldconfig");
+    } else {
+        let blacklists: BTreeMap<String, Vec<String>> = module_requests
+            .iter()
+            .filter(|request| !request.blacklist.is_empty())
+            .map(|request| (request.extension.clone(), request.blacklist.clone()))
+            .collect();
+        if !blacklists.is_empty() || has_modprobe_blacklists() {
+            actions.push(MergeAction::Blacklist { blacklists });
+        }
+        actions.extend(
+            pre_reload
+                .into_iter()
+                .map(|command| hook(HookStage::BeforeReload, command)),
+        );
+        if !modprobe_modules.is_empty() {
+            actions.push(MergeAction::Modprobe {
+                modules: modprobe_modules,
+            });
+        }
+    }
+
+    // Also the reload for any HITL drop-ins changed before the merge
+    actions.push(MergeAction::DaemonReload);
+
+    // Extension data is migrated before services restart on it
+    for extension in in_hook_order(enabled) {
+        let Some((script, data_version)) = extension_release_files(&extension)
+            .into_iter()
+            .find_map(|release| Some((release.migrate.clone()?, release.data_version.clone())))
+        else {
+            continue;
+        };
+        let Some(version) = data_version.or_else(|| extension.version.clone()) else {
+            output.warning(&format!(
+                "'{}' sets AVOCADO_MIGRATE without AVOCADO_DATA_VERSION or a versioned image; not migrating",
+                extension.name
+            ));
+            continue;
+        };
+        if crate::migrations::pending(&extension.name, &version) {
+            actions.push(MergeAction::Migrate {
+                extension: versioned_name(&extension),
+                script,
+                version,
+            });
+        }
+    }
+
+    actions.extend(
+        post_reload
+            .into_iter()
+            .filter(|command| entering.is_none_or(|e| hook_owned_by(&hook_owners, command, e)))
+            .map(|command| hook(HookStage::AfterReload, command)),
+    );
+    Ok(actions)
+}
+
+/// Compute the prefixed symlink name for an extension based on its merge index.
+/// When a merge_index is set, returns "NN-name" or "NN-name-version".
+/// Without a merge_index (legacy), returns "name" or "name-version".
+pub(super) fn compute_prefixed_name(extension: &Extension) -> String {
+    let base_name = if let Some(ver) = &extension.version {
+        format!("{}-{}", extension.name, ver)
+    } else {
+        extension.name.clone()
+    };
+
+    if let Some(index) = extension.merge_index {
+        format!("{index:02}-{base_name}")
+    } else {
+        base_name
+    }
+}
+
+/// The enabled extensions in the order their AVOCADO_ON_MERGE hooks run:
+/// by AVOCADO_ON_MERGE_PRIORITY (unset counts as 0), then dependencies
+/// first, then merge order
+pub(super) fn in_hook_order(enabled_extensions: &[Extension]) -> Vec<Extension> {
+    let releases: Vec<Vec<Arc<ReleaseFile>>> = enabled_extensions
+        .iter()
+        .map(extension_release_files)
+        .collect();
+    let requires: Vec<Vec<String>> = releases
+        .iter()
+        .map(|releases| {
+            let mut requires: Vec<String> = Vec::new();
+            for name in releases.iter().flat_map(|r| &r.requires) {
+                if !requires.contains(name) {
+                    requires.push(name.clone());
+                }
+            }
+            requires
+        })
+        .collect();
+    let ranks: Vec<HookRank> = enabled_extensions
+        .iter()
+        .zip(&releases)
+        .zip(&requires)
+        .map(|((extension, releases), requires)| HookRank {
+            name: &extension.name,
+            priority: releases
+                .iter()
+                .find_map(|r| r.on_merge_priority)
+                .unwrap_or(0),
+            requires,
+        })
+        .collect();
+    hook_order(&ranks)
+        .into_iter()
+        .map(|i| enabled_extensions[i].clone())
+        .collect()
+}
+
+/// Scan release files for only the enabled extensions
+pub(super) fn scan_release_files_for_enabled_extensions(
+    enabled_extensions: &[Extension],
+) -> Result<(Vec<String>, Vec<String>), SystemdError> {
+    let mut on_merge_commands = Vec::new();
+    let mut modprobe_modules = Vec::new();
+
+    // Handle test mode with custom release directory (for backwards compatibility)
+    if let Ok(custom_dir) = std::env::var("AVOCADO_EXTENSION_RELEASE_DIR") {
+        return scan_custom_release_directory(&custom_dir);
+    }
+
+    for extension in enabled_extensions {
+        // Scan release files from each enabled extension mount point
+        scan_extension_release_files(extension, &mut on_merge_commands, &mut modprobe_modules)?;
+    }
+
+    Ok((on_merge_commands, modprobe_modules))
+}
+
+/// Release file directories below a custom release directory (test mode),
+/// with the scope key their files are checked against
+pub(super) fn custom_release_dirs(custom_dir: &str) -> Vec<(String, Option<&'static str>)> {
+    let custom_path = Path::new(custom_dir);
+    let mut dirs: Vec<(String, Option<&'static str>)> = Vec::new();
+
+    // Check if it's a single directory with release files (legacy behavior)
+    if custom_path.join("extension-release.d").exists() {
+        dirs.push((custom_dir.to_string(), None));
+    } else {
+        // Look for sysext and confext subdirectories
+        let sysext_dir = custom_path.join("usr/lib/extension-release.d");
+        let confext_dir = custom_path.join("etc/extension-release.d");
+
+        if sysext_dir.exists() {
+            dirs.push((
+                sysext_dir.to_string_lossy().to_string(),
+                Some("SYSEXT_SCOPE"),
+            ));
+        }
+        if confext_dir.exists() {
+            dirs.push((
+                confext_dir.to_string_lossy().to_string(),
+                Some("CONFEXT_SCOPE"),
+            ));
+        }
+
+        // If neither subdirectory structure exists, use the custom dir directly
+        if dirs.is_empty() {
+            dirs.push((custom_dir.to_string(), None));
+        }
+    }
+
+    dirs
+}
+
+/// Hook commands mapped to the extensions declaring them, used to file each
+/// command's recorded output under the right extension
+pub(super) type HookOwners = std::collections::BTreeMap<String, Vec<String>>;
+
+pub(super) fn add_hook_owner(owners: &mut HookOwners, commands: Vec<String>, extension: &str) {
+    for command in commands {
+        let entry = owners.entry(command).or_default();
+        if !entry.iter().any(|e| e == extension) {
+            entry.push(extension.to_string());
+        }
+    }
+}
+
+/// Whether one of `extensions` declares `command`
+pub(super) fn hook_owned_by(owners: &HookOwners, command: &str, extensions: &[String]) -> bool {
+    owners
+        .get(command)
+        .is_some_and(|owners| owners.iter().any(|owner| extensions.contains(owner)))
+}
+
+/// Owners of the hook commands in release file directories, named after
+/// their `extension-release.<name>` files
+pub(super) fn hook_owners_in_dirs(
+    dirs: &[(String, Option<&str>)],
+    parse: fn(&str) -> Vec<String>,
+) -> HookOwners {
+    let mut owners = HookOwners::new();
+    for (release_dir, scope_key) in dirs {
+        let Ok(entries) = read_dir_sorted(release_dir) else {
+            continue;
+        };
+        for entry in entries {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let Some(extension) = file_name.strip_prefix("extension-release.") else {
+                continue;
+            };
+            let Ok(content) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            if scope_key.is_some_and(|key| !is_scope_enabled_for_current_environment(&content, key))
+            {
+                continue;
+            }
+            add_hook_owner(&mut owners, parse(&content), extension);
+        }
+    }
+    owners
+}
+
+/// Owners of the AVOCADO_ON_MERGE commands of the enabled extensions
+pub(super) fn on_merge_hook_owners(enabled_extensions: &[Extension]) -> HookOwners {
+    if let Ok(custom_dir) = std::env::var("AVOCADO_EXTENSION_RELEASE_DIR") {
+        return hook_owners_in_dirs(
+            &custom_release_dirs(&custom_dir),
+            parse_avocado_on_merge_commands,
+        );
+    }
+    let mut owners = HookOwners::new();
+    for extension in enabled_extensions {
+        let name = match &extension.version {
+            Some(version) => format!("{}-{version}", extension.name),
+            None => extension.name.clone(),
+        };
+        for release in extension_release_files(extension) {
+            add_hook_owner(&mut owners, release.on_merge.clone(), &name);
+        }
+    }
+    owners
+}
+
+/// Scan release files from a custom directory (test mode)
+pub(super) fn scan_custom_release_directory(
+    custom_dir: &str,
+) -> Result<(Vec<String>, Vec<String>), SystemdError> {
+    let mut on_merge_commands = Vec::new();
+    let mut modprobe_modules = Vec::new();
+
+    let dirs = custom_release_dirs(custom_dir);
+
+    for (release_dir, scope_key) in &dirs {
+        scan_directory_for_release_files(
+            release_dir,
+            &mut on_merge_commands,
+            &mut modprobe_modules,
+            *scope_key,
+        );
+    }
+
+    Ok((on_merge_commands, modprobe_modules))
+}
+
+/// Collect the on-merge commands and modules from a specific extension's
+/// trusted mount point. Only the release files the extension is enabled for
+/// and whose scope matches the current environment count.
+pub(super) fn scan_extension_release_files(
+    extension: &Extension,
+    on_merge_commands: &mut Vec<String>,
+    modprobe_modules: &mut Vec<String>,
+) -> Result<(), SystemdError> {
+    for release in extension_release_files(extension) {
+        on_merge_commands.extend(release.on_merge.iter().cloned());
+        modprobe_modules.extend(release.modprobe.iter().cloned());
+    }
+    Ok(())
+}
+
+/// Process post-merge tasks for only the enabled extensions
+/// Commands that must run before daemon-reload so that kernel modules
+/// and shared libraries are available when systemd re-evaluates units.
+pub(super) const PRE_DAEMON_RELOAD_COMMANDS: &[&str] = &["depmod", "ldconfig"];
+
+/// Check if a command should run before daemon-reload
+pub(super) fn is_pre_daemon_reload_command(command: &str) -> bool {
+    let first_word = command.split_whitespace().next().unwrap_or("");
+    PRE_DAEMON_RELOAD_COMMANDS.contains(&first_word)
+}
+
+/// Prefix of the modprobe.d files avocadoctl writes for extension blacklists.
+pub(super) const MODPROBE_BLACKLIST_PREFIX: &str = "avocado-ext-";
+
+/// Kernel modules an enabled extension asks to blacklist and to load.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct ModuleRequests {
+    pub extension: String,
+    pub blacklist: Vec<String>,
+    pub modprobe: Vec<String>,
+}
+
+/// A module blacklisted by one extension but loaded by another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct BlacklistConflict {
+    pub module: String,
+    pub blacklisted_by: String,
+    pub loaded_by: String,
+}
+
+/// Directory for blacklist drop-ins. /etc may be covered by a confext
+/// overlay, so the files go to /run/modprobe.d, which modprobe also reads.
+pub(super) fn modprobe_blacklist_dir() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/modprobe.d"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path("/run/modprobe.d"))
+    }
+}
+
+/// Collect the blacklist and modprobe requests of each enabled extension.
+pub(super) fn collect_module_requests(enabled_extensions: &[Extension]) -> Vec<ModuleRequests> {
+    enabled_extensions
+        .iter()
+        .filter_map(|extension| {
+            let mut requests = ModuleRequests {
+                extension: extension.name.clone(),
+                blacklist: Vec::new(),
+                modprobe: Vec::new(),
+            };
+            for release in extension_release_files(extension) {
+                requests
+                    .blacklist
+                    .extend(release.modprobe_blacklist.iter().cloned());
+                requests.modprobe.extend(release.modprobe.iter().cloned());
+            }
+            if requests.blacklist.is_empty() && requests.modprobe.is_empty() {
+                None
+            } else {
+                Some(requests)
+            }
+        })
+        .collect()
+}
+
+/// Find modules one extension blacklists while another loads them. An
+/// extension loading a module it blacklists itself is not a conflict.
+pub(super) fn find_blacklist_conflicts(requests: &[ModuleRequests]) -> Vec<BlacklistConflict> {
+    let mut conflicts = Vec::new();
+    for blacklister in requests {
+        for module in &blacklister.blacklist {
+            for loader in requests {
+                if loader.extension != blacklister.extension && loader.modprobe.contains(module) {
+                    conflicts.push(BlacklistConflict {
+                        module: module.clone(),
+                        blacklisted_by: blacklister.extension.clone(),
+                        loaded_by: loader.extension.clone(),
+                    });
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+/// Whether `name` is a blacklist file written by avocadoctl.
+pub(super) fn is_modprobe_blacklist(name: &str) -> bool {
+    name.starts_with(MODPROBE_BLACKLIST_PREFIX) && name.ends_with(".conf")
+}
+
+/// Whether any blacklist file written by avocadoctl is in place.
+pub(super) fn has_modprobe_blacklists() -> bool {
+    read_dir_sorted(modprobe_blacklist_dir()).is_ok_and(|entries| {
+        entries
+            .iter()
+            .any(|entry| is_modprobe_blacklist(&entry.file_name().to_string_lossy()))
+    })
+}
+
+/// Scan a directory for release files (used in test mode).
+/// Only includes commands from release files whose scope matches the current environment.
+fn scan_directory_for_release_files(
+    release_dir: &str,
+    on_merge_commands: &mut Vec<String>,
+    modprobe_modules: &mut Vec<String>,
+    scope_key: Option<&str>,
+) {
+    if !Path::new(release_dir).exists() {
+        return;
+    }
+
+    if let Ok(entries) = read_dir_sorted(release_dir) {
+        for entry in entries {
+            let path = entry.path();
+            if path.is_file() {
+                if let Ok(content) = fs::read_to_string(&path) {
+                    if let Some(key) = scope_key {
+                        if !is_scope_enabled_for_current_environment(&content, key) {
+                            continue;
+                        }
+                    }
+                    let mut commands = parse_avocado_on_merge_commands(&content);
+                    on_merge_commands.append(&mut commands);
+
+                    let mut modules = parse_avocado_modprobe(&content);
+                    modprobe_modules.append(&mut modules);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::image_adaptor::ImageTypeTag;
+
+    fn planned_extension(
+        name: &str,
+        version: Option<&str>,
+        merge_index: Option<usize>,
+        is_confext: bool,
+    ) -> Extension {
+        Extension {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+            path: PathBuf::from(format!("/test/{name}")),
+            is_sysext: true,
+            is_confext,
+            image_type: ImageTypeTag::Directory,
+            merge_index,
+            partitions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_plan_merge_links_and_unlinks() {
+        let scan = MergeScan {
+            extensions: vec![
+                planned_extension("app", Some("1.0.0"), None, true),
+                planned_extension("base", None, None, false),
+            ],
+            sysext_links: vec!["app-1.0.0".to_string(), "old-2.0".to_string()],
+            confext_links: vec!["old-2.0".to_string()],
+            safe_mode_skipped: Vec::new(),
+            failed: Vec::new(),
+        };
+        let plan = plan_merge(&scan);
+        let shown: Vec<String> = plan.actions.iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            shown,
+            vec![
+                "unlink sysext/old-2.0",
+                "unlink confext/old-2.0",
+                "link sysext/app-1.0.0 -> /test/app",
+                "link confext/app-1.0.0 -> /test/app",
+                "link sysext/base -> /test/base",
+            ]
+        );
+        assert_eq!(plan.enabled.len(), 2);
+    }
+
+    #[test]
+    fn test_plan_merge_stages_prefixed_release_and_masks_versions() {
+        let scan = MergeScan {
+            extensions: vec![
+                planned_extension("net", Some("1.0"), Some(1), false),
+                planned_extension("tool", Some("2.0"), None, false),
+                planned_extension("tool", None, None, false),
+            ],
+            sysext_links: vec!["tool-2.0".to_string()],
+            confext_links: Vec::new(),
+            safe_mode_skipped: Vec::new(),
+            failed: Vec::new(),
+        };
+        let plan = plan_merge(&scan);
+        assert_eq!(
+            plan.actions,
+            vec![
+                MergeAction::Unlink {
+                    kind: LinkKind::Sysext,
+                    name: "tool-2.0".to_string(),
+                },
+                MergeAction::StageRelease {
+                    name: "01-net-1.0".to_string(),
+                    source: PathBuf::from("/test/net"),
+                },
+                MergeAction::Link {
+                    kind: LinkKind::Sysext,
+                    name: "01-net-1.0".to_string(),
+                    source: PathBuf::from("/test/net"),
+                },
+                MergeAction::Link {
+                    kind: LinkKind::Sysext,
+                    name: "tool".to_string(),
+                    source: PathBuf::from("/test/tool"),
+                },
+            ]
+        );
+        let names: Vec<&str> = plan.enabled.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["net", "tool"]);
+    }
+
+    #[test]
+    fn test_post_merge_actions_display_and_serialize() {
+        let actions = [
+            MergeAction::Blacklist {
+                blacklists: BTreeMap::from([(
+                    "net".to_string(),
+                    vec!["e1000".to_string(), "igb".to_string()],
+                )]),
+            },
+            MergeAction::Hook {
+                stage: HookStage::BeforeReload,
+                command: "depmod".to_string(),
+                extensions: vec!["drivers-1.0".to_string()],
+            },
+            MergeAction::Modprobe {
+                modules: vec!["nfsd".to_string()],
+            },
+            MergeAction::DaemonReload,
+            MergeAction::Migrate {
+                extension: "db-2.0".to_string(),
+                script: "/usr/libexec/db-migrate".to_string(),
+                version: "2.0".to_string(),
+            },
+            MergeAction::Hook {
+                stage: HookStage::AfterReload,
+                command: "systemctl restart db".to_string(),
+                extensions: Vec::new(),
+            },
+        ];
+        let shown: Vec<String> = actions.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            shown,
+            vec![
+                "blacklist modules e1000, igb (net)",
+                "run on-merge command: depmod before daemon-reload (drivers-1.0)",
+                "load kernel modules nfsd",
+                "daemon-reload",
+                "migrate data of db-2.0 to 2.0: /usr/libexec/db-migrate",
+                "run on-merge command: systemctl restart db",
+            ]
+        );
+        assert_eq!(
+            MergeAction::Blacklist {
+                blacklists: BTreeMap::new()
+            }
+            .to_string(),
+            "remove module blacklists"
+        );
+
+        let json = serde_json::to_value(&actions[1]).unwrap();
+        assert_eq!(json["action"], "hook");
+        assert_eq!(json["stage"], "before-reload");
+        assert_eq!(
+            serde_json::to_value(&actions[3]).unwrap(),
+            serde_json::json!({"action": "daemon-reload"})
+        );
+    }
+
+    #[test]
+    fn test_find_blacklist_conflicts() {
+        let request = |extension: &str, blacklist: &[&str], modprobe: &[&str]| ModuleRequests {
+            extension: extension.to_string(),
+            blacklist: blacklist.iter().map(|m| m.to_string()).collect(),
+            modprobe: modprobe.iter().map(|m| m.to_string()).collect(),
+        };
+        let requests = vec![
+            request("camera", &["uvcvideo"], &["vendor_cam"]),
+            request("webcam", &[], &["uvcvideo"]),
+            request("self", &["foo"], &["foo"]),
+        ];
+        assert_eq!(
+            find_blacklist_conflicts(&requests),
+            vec![BlacklistConflict {
+                module: "uvcvideo".to_string(),
+                blacklisted_by: "camera".to_string(),
+                loaded_by: "webcam".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_compute_prefixed_name_with_merge_index() {
+        let ext = Extension {
+            name: "app".to_string(),
+            version: Some("1.0.0".to_string()),
+            path: PathBuf::from("/test/app"),
+            is_sysext: true,
+            is_confext: false,
+            image_type: ImageTypeTag::Raw,
+            merge_index: Some(2),
+            partitions: Vec::new(),
+        };
+        assert_eq!(compute_prefixed_name(&ext), "02-app-1.0.0");
+    }
+
+    #[test]
+    fn test_compute_prefixed_name_no_version() {
+        let ext = Extension {
+            name: "networking".to_string(),
+            version: None,
+            path: PathBuf::from("/test/networking"),
+            is_sysext: true,
+            is_confext: false,
+            image_type: ImageTypeTag::Directory,
+            merge_index: Some(1),
+            partitions: Vec::new(),
+        };
+        assert_eq!(compute_prefixed_name(&ext), "01-networking");
+    }
+
+    #[test]
+    fn test_compute_prefixed_name_no_merge_index() {
+        // Legacy extension without ordering — no prefix
+        let ext = Extension {
+            name: "legacy".to_string(),
+            version: Some("0.5.0".to_string()),
+            path: PathBuf::from("/test/legacy"),
+            is_sysext: true,
+            is_confext: false,
+            image_type: ImageTypeTag::Directory,
+            merge_index: None,
+            partitions: Vec::new(),
+        };
+        assert_eq!(compute_prefixed_name(&ext), "legacy-0.5.0");
+    }
+
+    #[test]
+    fn test_compute_prefixed_name_inverted_ordering() {
+        // Simulate a manifest with 3 extensions: [highest, middle, lowest]
+        // manifest[0] = highest priority → merge_index = 2
+        // manifest[1] = middle → merge_index = 1
+        // manifest[2] = lowest → merge_index = 0
+        let n = 3;
+        let names = ["highest", "middle", "lowest"];
+        let expected = ["02-highest", "01-middle", "00-lowest"];
+
+        for (index, name) in names.iter().enumerate() {
+            let ext = Extension {
+                name: name.to_string(),
+                version: None,
+                path: PathBuf::from(format!("/test/{name}")),
+                is_sysext: true,
+                is_confext: false,
+                image_type: ImageTypeTag::Directory,
+                merge_index: Some(n - 1 - index),
+                partitions: Vec::new(),
+            };
+            assert_eq!(
+                compute_prefixed_name(&ext),
+                expected[index],
+                "manifest[{index}] should get prefix {:02}",
+                n - 1 - index
+            );
+        }
+    }
+
+    #[test]
+    fn test_hitl_inherits_manifest_priority() {
+        // When a HITL extension overrides a manifest extension,
+        // it should inherit the same merge_index
+        let mut hitl_ext = Extension {
+            name: "networking".to_string(),
+            version: None,
+            path: PathBuf::from("/run/avocado/hitl/networking"),
+            is_sysext: true,
+            is_confext: false,
+            image_type: ImageTypeTag::Directory,
+            merge_index: None, // Initially no index (HITL discovery)
+            partitions: Vec::new(),
+        };
+
+        // Simulate the manifest scanning assigning the index
+        // For a 3-extension manifest where networking is at position 1:
+        let ext_count = 3;
+        let manifest_index = 1;
+        let merge_idx = ext_count - 1 - manifest_index; // = 1
+        hitl_ext.merge_index = Some(merge_idx);
+
+        // The HITL extension now gets the same prefix as the manifest entry
+        assert_eq!(compute_prefixed_name(&hitl_ext), "01-networking");
+    }
+}
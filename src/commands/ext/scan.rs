@@ -0,0 +1,417 @@
+//! Scan phase of a merge.
+//!
+//! [`scan_merge_state`] gathers everything the planner decides on: the
+//! extensions the [sources](super::source) provide, after the release
+//! checks, the strictness checks enable runs (see [`super::validate`]),
+//! limits, permission audit and safe mode, and the links already in
+//! `/run/extensions` and `/run/confexts`. Nothing is linked, merged or run.
+//! Image files are the exception to a read-only scan: their release files
+//! live inside the image, so they are attached to a loop device and mounted
+//! (or unpacked) to be analyzed.
+
+use super::source::{
+    extension_release_files, read_os_version_id, scan_all_sources, versioned_name, Extension,
+};
+use super::validate::{validate_extension, ValidationProblem};
+use crate::commands::harness;
+use crate::commands::image_adaptor::{ImageTypeTag, SystemdError};
+use crate::config::{
+    Config, FindingAction, LimitSettings, OversizeAction, PermissionAuditSettings, ValidationCheck,
+    ValidationPolicy,
+};
+use crate::manifest::RuntimeManifest;
+use crate::merge_failures::Failure;
+use crate::output::OutputManager;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Everything the planner needs, gathered by [`scan_merge_state`].
+pub(super) struct MergeScan {
+    pub extensions: Vec<Extension>,
+    pub sysext_links: Vec<String>,
+    pub confext_links: Vec<String>,
+    /// Extensions left out because the system is in safe mode
+    pub safe_mode_skipped: Vec<String>,
+    /// Extensions left out by `--keep-going` or the strictness policy
+    pub failed: Vec<Failure>,
+}
+
+/// Names of the symlinks currently in `dir`, sorted.
+pub(super) fn list_symlinks(dir: &str) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_symlink())
+                .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Discover available extensions and the links already in place.
+pub(super) fn scan_merge_state(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<MergeScan, SystemdError> {
+    let scanned = scan_all_sources(config, output, config.keep_going(), true)?;
+    let (extensions, mut failed) = (scanned.extensions, scanned.failed);
+    let extensions = if config.keep_going() {
+        apply_release_checks(extensions, &mut failed, output)
+    } else {
+        extensions
+    };
+    let extensions = apply_validation_checks(extensions, &mut failed, config, output);
+    let extensions = apply_extension_limits(extensions, config.limits(), output);
+    let extensions = apply_permission_audit(extensions, config.permissions(), output);
+    let (extensions, safe_mode_skipped) = apply_safe_mode(extensions, output);
+    crate::events::emit(
+        "scan.finished",
+        serde_json::json!({
+            "extensions": extensions.iter().map(versioned_name).collect::<Vec<_>>(),
+            "failed": failed.iter().map(|f| &f.extension).collect::<Vec<_>>(),
+        }),
+    );
+    Ok(MergeScan {
+        extensions,
+        sysext_links: list_symlinks(&LinkKind::Sysext.dir()),
+        confext_links: list_symlinks(&LinkKind::Confext.dir()),
+        safe_mode_skipped,
+        failed,
+    })
+}
+
+/// Run the checks enable runs (see [`validate_extension`]) on the scanned
+/// extensions, against the running OS release. An extension with a problem
+/// the strictness policy makes fatal is left out of the merge; any other
+/// problem is reported as a warning. Images are only hashed for the
+/// checksum check when it is fatal, so a default merge does not read every
+/// image in full.
+fn apply_validation_checks(
+    extensions: Vec<Extension>,
+    failed: &mut Vec<Failure>,
+    config: &Config,
+    output: &OutputManager,
+) -> Vec<Extension> {
+    let version_id = read_os_version_id();
+    let manifest = (config.validation_policy(ValidationCheck::Checksum) == ValidationPolicy::Error)
+        .then(|| RuntimeManifest::load_active(Path::new(&config.get_avocado_base_dir())))
+        .flatten();
+    extensions
+        .into_iter()
+        .filter(|extension| {
+            let name = versioned_name(extension);
+            let image = manifest
+                .as_ref()
+                .and_then(|manifest| manifest_image(manifest, extension, config));
+            // An image read-only mode left unmounted cannot be checked
+            let releases = if extension.path.is_dir() {
+                Ok(harness::find_release_files(&extension.path))
+            } else {
+                Err(format!("{} is not mounted", extension.path.display()))
+            };
+            let validation =
+                validate_extension(&name, releases, image.as_deref(), &version_id, config);
+            let mut fatal = Vec::new();
+            for problem in &validation.problems {
+                let ValidationProblem { check, message } = problem;
+                if problem.is_fatal(config, false) {
+                    fatal.push(format!("{} check: {message}", check.key()));
+                } else {
+                    output.progress(&format!("Warning: '{name}': {message}"));
+                }
+            }
+            if fatal.is_empty() {
+                return true;
+            }
+            let failure = Failure::new(name, format!("failed the {}", fatal.join("; ")));
+            output.error(
+                "Merge Extensions",
+                &format!(
+                    "Skipping extension {}: {}",
+                    failure.extension, failure.error
+                ),
+            );
+            failed.push(failure);
+            false
+        })
+        .collect()
+}
+
+/// The image file the runtime manifest records for `extension`, if it was
+/// merged from one.
+fn manifest_image(
+    manifest: &RuntimeManifest,
+    extension: &Extension,
+    config: &Config,
+) -> Option<PathBuf> {
+    if extension.image_type == ImageTypeTag::Directory {
+        return None;
+    }
+    manifest
+        .extensions
+        .iter()
+        .find(|e| e.name == extension.name && extension.version.as_ref() == Some(&e.version))
+        .map(|e| e.resolve_path(Path::new(&config.get_avocado_base_dir())))
+        .filter(|path| path.is_file())
+}
+
+/// Directory a merge symlink lives in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LinkKind {
+    Sysext,
+    Confext,
+}
+
+impl LinkKind {
+    pub(super) fn label(self) -> &'static str {
+        match self {
+            LinkKind::Sysext => "sysext",
+            LinkKind::Confext => "confext",
+        }
+    }
+
+    /// /run/extensions or /run/confexts, respecting AVOCADO_TEST_MODE and user mode.
+    pub(super) fn dir(self) -> String {
+        if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+            let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+            match self {
+                LinkKind::Sysext => format!("{temp_base}/test_extensions"),
+                LinkKind::Confext => format!("{temp_base}/test_confexts"),
+            }
+        } else {
+            match self {
+                LinkKind::Sysext => crate::user_mode::system_path("/run/extensions"),
+                LinkKind::Confext => crate::user_mode::system_path("/run/confexts"),
+            }
+        }
+    }
+}
+
+/// Total size of an extension: the file size of an image, or the summed size
+/// of every regular file below a directory extension (symlinks not followed).
+fn extension_size(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| extension_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Apply the configured size and count limits to the scanned extensions.
+/// Oversized extensions are skipped (or only warned about), and extensions
+/// beyond `max_total_extensions` are dropped from the end of the merge order.
+pub(super) fn apply_extension_limits(
+    extensions: Vec<Extension>,
+    limits: &LimitSettings,
+    output: &OutputManager,
+) -> Vec<Extension> {
+    let mut kept = Vec::with_capacity(extensions.len());
+    for extension in extensions {
+        if let Some(max_size) = limits.max_extension_size {
+            let size = extension_size(&extension.path);
+            if size > max_size {
+                let message = format!(
+                    "Extension '{}' is {size} bytes, exceeding max_extension_size of {max_size} bytes",
+                    extension.name
+                );
+                if limits.on_oversize == OversizeAction::Skip {
+                    output.error("Extension Limits", &format!("{message}; skipping"));
+                    continue;
+                }
+                output.progress(&format!("Warning: {message}"));
+            }
+        }
+        kept.push(extension);
+    }
+
+    if let Some(max_total) = limits.max_total_extensions {
+        if kept.len() > max_total {
+            let skipped: Vec<String> = kept.drain(max_total..).map(|e| e.name).collect();
+            output.error(
+                "Extension Limits",
+                &format!(
+                    "{} extensions found, exceeding max_total_extensions of {max_total}; skipping: {}",
+                    max_total + skipped.len(),
+                    skipped.join(", ")
+                ),
+            );
+        }
+    }
+    kept
+}
+
+/// With `--keep-going`, leave out extensions with an invalid release file:
+/// one without `ID=`, which systemd-sysext and systemd-confext cannot
+/// match against the host.
+pub(super) fn apply_release_checks(
+    extensions: Vec<Extension>,
+    failed: &mut Vec<Failure>,
+    output: &OutputManager,
+) -> Vec<Extension> {
+    extensions
+        .into_iter()
+        .filter(|extension| {
+            let Some(release) = extension_release_files(extension)
+                .into_iter()
+                .find(|release| {
+                    crate::os_release::OsRelease::parse(&release.content)
+                        .id
+                        .is_none()
+                })
+            else {
+                return true;
+            };
+            let failure = Failure::new(
+                versioned_name(extension),
+                format!(
+                    "invalid release file {}: ID= is missing",
+                    release.path.display()
+                ),
+            );
+            output.progress(&format!(
+                "Skipping extension {}: {}",
+                failure.extension, failure.error
+            ));
+            failed.push(failure);
+            false
+        })
+        .collect()
+}
+
+/// In safe mode (see [`crate::safe_mode`]), leave out every extension whose
+/// release file does not set AVOCADO_ESSENTIAL=yes. Returns the extensions
+/// kept and the names of those skipped.
+pub(super) fn apply_safe_mode(
+    extensions: Vec<Extension>,
+    output: &OutputManager,
+) -> (Vec<Extension>, Vec<String>) {
+    let Some(trigger) = crate::safe_mode::detect() else {
+        return (extensions, Vec::new());
+    };
+    let (kept, skipped): (Vec<Extension>, Vec<Extension>) =
+        extensions.into_iter().partition(|extension| {
+            extension_release_files(extension)
+                .iter()
+                .any(|release| release.essential)
+        });
+    let skipped: Vec<String> = skipped.into_iter().map(|e| e.name).collect();
+    if skipped.is_empty() {
+        output.log_info(&format!(
+            "Safe mode ({trigger}): all {} extension(s) are essential",
+            kept.len()
+        ));
+    } else {
+        output.log_info(&format!(
+            "Safe mode ({trigger}): skipping {} non-essential extension(s): {}",
+            skipped.len(),
+            skipped.join(", ")
+        ));
+    }
+    (kept, skipped)
+}
+
+/// Most findings listed per extension before the rest are summarized.
+const MAX_LISTED_FINDINGS: usize = 10;
+
+/// Run the permission audit over the scanned extensions when enabled.
+/// Extensions with findings are skipped, or only warned about.
+pub(super) fn apply_permission_audit(
+    extensions: Vec<Extension>,
+    settings: &PermissionAuditSettings,
+    output: &OutputManager,
+) -> Vec<Extension> {
+    if !settings.enabled {
+        return extensions;
+    }
+    let mut kept = Vec::with_capacity(extensions.len());
+    for extension in extensions {
+        let findings = crate::permission_audit::audit_tree(&extension.path, settings);
+        if findings.is_empty() {
+            kept.push(extension);
+            continue;
+        }
+        let mut lines: Vec<String> = findings
+            .iter()
+            .take(MAX_LISTED_FINDINGS)
+            .map(|finding| format!("  {finding}"))
+            .collect();
+        if findings.len() > MAX_LISTED_FINDINGS {
+            lines.push(format!(
+                "  ... and {} more",
+                findings.len() - MAX_LISTED_FINDINGS
+            ));
+        }
+        let message = format!(
+            "Extension '{}' failed the permission audit with {} finding(s):\n{}",
+            extension.name,
+            findings.len(),
+            lines.join("\n")
+        );
+        if settings.on_finding == FindingAction::Skip {
+            output.error("Permission Audit", &format!("{message}\nSkipping it"));
+            continue;
+        }
+        output.error("Permission Audit", &format!("{message}\nMerging it anyway"));
+        kept.push(extension);
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::image_adaptor::ImageTypeTag;
+
+    #[test]
+    fn test_apply_extension_limits_size_and_count() {
+        use crate::config::{LimitSettings, OversizeAction};
+        use tempfile::TempDir;
+
+        let tmp = TempDir::new().unwrap();
+        let make = |name: &str, bytes: usize| {
+            let dir = tmp.path().join(name);
+            fs::create_dir_all(dir.join("usr/bin")).unwrap();
+            fs::write(dir.join("usr/bin/tool"), vec![0u8; bytes]).unwrap();
+            Extension {
+                name: name.to_string(),
+                version: None,
+                path: dir,
+                is_sysext: true,
+                is_confext: false,
+                image_type: ImageTypeTag::Directory,
+                merge_index: None,
+                partitions: Vec::new(),
+            }
+        };
+        let extensions = vec![make("small", 10), make("big", 4096), make("tiny", 1)];
+        assert_eq!(extension_size(&extensions[1].path), 4096);
+
+        let output = OutputManager::new(false, false);
+        let mut limits = LimitSettings {
+            max_extension_size: Some(1024),
+            ..Default::default()
+        };
+        let kept = apply_extension_limits(extensions.clone(), &limits, &output);
+        let names: Vec<&str> = kept.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["small", "tiny"]);
+
+        limits.on_oversize = OversizeAction::Warn;
+        limits.max_total_extensions = Some(2);
+        let kept = apply_extension_limits(extensions, &limits, &output);
+        let names: Vec<&str> = kept.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["small", "big"]);
+    }
+}
@@ -0,0 +1,1640 @@
+//! Places extensions are found.
+//!
+//! Each place is a [`Source`]: the HITL mounts, the active runtime manifest,
+//! the os-releases directory of the running OS, the directories and raw
+//! images of the extensions directory, and in a container the host's
+//! extensions. [`scan`] asks the sources in priority order and keeps the
+//! first extension of each name, so a HITL mount masks the installed image
+//! of the same extension. A source only lists what it has; an extension is
+//! fetched (mounted, unpacked, downloaded) once no higher-priority source
+//! provides it. Once the trust store holds a key, an image file it does
+//! not accept fails to fetch before it is mounted or unpacked.
+//!
+//! Further sources, such as an OCI registry, an HTTP repository or a
+//! partition, are compiled in behind a cargo feature and added to
+//! [`sources`] at their priority.
+
+use crate::commands::image_adaptor::{
+    self, analyze_mounted_extension, extension_mount_point, ImageAdaptor, ImageType, ImageTypeTag,
+    KabAdaptor, RawAdaptor, SystemdError,
+};
+use crate::config::{Config, MissingDirPolicy, OsReleaseFallback};
+use crate::error::{ExtensionContext, IoContext};
+use crate::extension_release::{self, Hierarchy, ReleaseFile};
+use crate::manifest::RuntimeManifest;
+use crate::merge_failures::Failure;
+use crate::ordering::read_dir_sorted;
+use crate::output::OutputManager;
+use crate::phases::Phase;
+use crate::trust::TrustStore;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// An extension a source has, before it is fetched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Candidate {
+    pub name: String,
+    pub version: Option<String>,
+    pub path: PathBuf,
+    pub layout: Layout,
+    /// Merge priority from the runtime manifest
+    pub merge_index: Option<usize>,
+    /// What `--verbose` calls it, e.g. "OS release raw"
+    pub label: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Layout {
+    Directory,
+    /// Disk image, mounted by the adaptor of its manifest `image_type`
+    Image(Option<String>),
+    /// `.tar.zst` archive, unpacked to the archive cache
+    Archive,
+}
+
+pub(super) trait Source {
+    /// The extensions this source has, highest priority first. `found`
+    /// holds what the sources before it provided.
+    fn scan(
+        &self,
+        found: &BTreeMap<String, Extension>,
+        output: &OutputManager,
+    ) -> Result<Vec<Candidate>, SystemdError>;
+
+    /// Make `candidate` usable. `Ok(None)` leaves it out of the scan.
+    fn fetch(
+        &self,
+        candidate: &Candidate,
+        output: &OutputManager,
+    ) -> Result<Option<Extension>, SystemdError> {
+        fetch_local_or_skip_archive(candidate, output)
+    }
+
+    /// Paths whose changes change what the source has (for auto-refresh).
+    #[cfg(feature = "daemon")]
+    fn watch(&self) -> Vec<PathBuf>;
+}
+
+/// Analyze a candidate on the local filesystem, mounting or unpacking it as
+/// its layout requires.
+fn fetch_local(candidate: &Candidate, output: &OutputManager) -> Result<Extension, SystemdError> {
+    let verbose = output.is_verbose();
+    match &candidate.layout {
+        Layout::Directory => analyze_directory_extension(&candidate.name, &candidate.path),
+        Layout::Image(image_type) => analyze_image_extension(
+            &candidate.name,
+            &candidate.version,
+            &candidate.path,
+            &ImageType::from_manifest(image_type),
+            output,
+        ),
+        Layout::Archive => analyze_archive_extension(
+            &candidate.name,
+            &candidate.version,
+            &candidate.path,
+            verbose,
+        ),
+    }
+}
+
+fn fetch_local_or_skip_archive(
+    candidate: &Candidate,
+    output: &OutputManager,
+) -> Result<Option<Extension>, SystemdError> {
+    match fetch_local(candidate, output) {
+        Ok(extension) => Ok(Some(extension)),
+        // An archive that cannot be unpacked is skipped, not fatal
+        Err(e) if candidate.layout == Layout::Archive => {
+            output.warning(&format!(
+                "Skipping archive extension '{}': {e}",
+                candidate.name
+            ));
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// How [`scan`] checks extensions against the signing trust store.
+#[derive(Clone, Copy)]
+pub(super) struct TrustCheck<'a> {
+    pub store: &'a TrustStore,
+    /// Refuse what the store does not accept before it is fetched, as a
+    /// merge does. Otherwise it is fetched as usual and recorded in
+    /// [`Scan::rejected`], without hashing images (see
+    /// [`crate::trust::precheck_image`]), for listings.
+    pub enforce: bool,
+    /// Accept directories, which cannot be signed, while the store has keys
+    pub allow_directories: bool,
+}
+
+/// Why `trust` does not accept `candidate`, if it does not.
+fn check_signature(candidate: &Candidate, trust: TrustCheck) -> Result<(), SystemdError> {
+    let now = crate::clock::now();
+    let checked = match candidate.layout {
+        Layout::Directory if trust.allow_directories || trust.store.keys.is_empty() => Ok(()),
+        Layout::Directory => Err("directories cannot be signed".to_string()),
+        _ if !candidate.path.is_file() => Ok(()),
+        _ if trust.enforce => crate::trust::check_image(&candidate.path, trust.store, now),
+        _ => crate::trust::precheck_image(&candidate.path, trust.store, now),
+    };
+    checked.map_err(|reason| SystemdError::Untrusted {
+        path: candidate.path.clone(),
+        reason,
+    })
+}
+
+/// Every directory in `dir`, named after the directory.
+fn directory_candidates(
+    dir: &Path,
+    label: &'static str,
+    merge_index: Option<usize>,
+) -> Vec<Candidate> {
+    let Ok(entries) = read_dir_sorted(dir) else {
+        return Vec::new();
+    };
+    entries
+        .into_iter()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter_map(|path| {
+            Some(Candidate {
+                name: path.file_name()?.to_str()?.to_string(),
+                version: None,
+                path,
+                layout: Layout::Directory,
+                merge_index,
+                label,
+            })
+        })
+        .collect()
+}
+
+fn image_candidates(
+    images: Vec<(String, Option<String>, PathBuf)>,
+    layout: Layout,
+    label: &'static str,
+) -> impl Iterator<Item = Candidate> {
+    images
+        .into_iter()
+        .map(move |(name, version, path)| Candidate {
+            name,
+            version,
+            path,
+            layout: layout.clone(),
+            merge_index: None,
+            label,
+        })
+}
+
+/// Extensions mounted from a developer's machine by `hitl mount`.
+pub(super) struct HitlSource {
+    pub dir: PathBuf,
+}
+
+impl Source for HitlSource {
+    fn scan(
+        &self,
+        _found: &BTreeMap<String, Extension>,
+        output: &OutputManager,
+    ) -> Result<Vec<Candidate>, SystemdError> {
+        if output.is_verbose() {
+            println!("Scanning HITL extensions in {}", self.dir.display());
+        }
+        Ok(directory_candidates(&self.dir, "HITL", None))
+    }
+
+    fn fetch(
+        &self,
+        candidate: &Candidate,
+        output: &OutputManager,
+    ) -> Result<Option<Extension>, SystemdError> {
+        let mut extension = fetch_local(candidate, output)?;
+        let mount_type = crate::hitl_health::mount_type(&extension.name);
+        apply_hitl_mount_type(&mut extension, mount_type);
+        Ok(Some(extension))
+    }
+
+    #[cfg(feature = "daemon")]
+    fn watch(&self) -> Vec<PathBuf> {
+        vec![self.dir.clone()]
+    }
+}
+
+/// The extensions of the active runtime, in the order of its manifest.
+pub(super) struct ManifestSource {
+    pub base_dir: PathBuf,
+    pub manifest: RuntimeManifest,
+}
+
+impl Source for ManifestSource {
+    fn scan(
+        &self,
+        found: &BTreeMap<String, Extension>,
+        output: &OutputManager,
+    ) -> Result<Vec<Candidate>, SystemdError> {
+        // Per-runtime user overrides sit alongside the manifest. The
+        // `active` symlink resolves to runtimes/<id>/, so overrides.json
+        // (when present) lives at the same path.
+        let active_dir = self.base_dir.join(crate::manifest::ACTIVE_LINK_NAME);
+        let overrides = crate::overrides::RuntimeOverrides::load(&active_dir);
+
+        let mut candidates = Vec::new();
+        let ext_count = self.manifest.extensions.len();
+        for (index, mext) in self.manifest.extensions.iter().enumerate() {
+            // Skip extensions the user (or the build) has marked disabled.
+            // `effective_enabled` is the single policy point — never read
+            // `mext.enabled` directly outside of it.
+            if !crate::overrides::effective_enabled(mext, &overrides) {
+                if output.is_verbose() {
+                    println!(
+                        "Skipping disabled extension '{}' (manifest={}, override={:?})",
+                        mext.name,
+                        mext.enabled,
+                        overrides.enabled_override(&mext.name)
+                    );
+                }
+                continue;
+            }
+            // Inverted index: manifest[0] = highest priority = highest prefix number
+            let merge_idx = ext_count - 1 - index;
+            let image = Candidate {
+                name: mext.name.clone(),
+                version: Some(mext.version.clone()),
+                path: mext.resolve_path(&self.base_dir),
+                layout: Layout::Image(mext.image_type.clone()),
+                merge_index: Some(merge_idx),
+                label: "manifest",
+            };
+
+            // A HITL version only takes the manifest's merge priority
+            if found.contains_key(&mext.name) {
+                candidates.push(image);
+            } else if image.path.is_dir() {
+                candidates.extend(directory_candidates(
+                    &image.path,
+                    "manifest",
+                    Some(merge_idx),
+                ));
+            } else if image.path.exists() {
+                candidates.push(image);
+            } else if output.is_verbose() {
+                let display_name = mext.image_id.as_deref().unwrap_or(&mext.name);
+                output.warning(&format!(
+                    "Extension image '{}' from manifest not found at {}",
+                    display_name,
+                    image.path.display()
+                ));
+            }
+        }
+        Ok(candidates)
+    }
+
+    fn fetch(
+        &self,
+        candidate: &Candidate,
+        output: &OutputManager,
+    ) -> Result<Option<Extension>, SystemdError> {
+        match fetch_local(candidate, output) {
+            Ok(extension) => Ok(Some(extension)),
+            Err(e) => {
+                output.warning(&format!(
+                    "Failed to analyze manifest extension '{}': {e}",
+                    candidate.name
+                ));
+                Ok(None)
+            }
+        }
+    }
+
+    #[cfg(feature = "daemon")]
+    fn watch(&self) -> Vec<PathBuf> {
+        vec![self.base_dir.join(crate::manifest::ACTIVE_LINK_NAME)]
+    }
+}
+
+/// The extensions enabled for the running OS release: the links in
+/// `os-releases/<VERSION_ID>`.
+pub(super) struct OsReleaseSource {
+    /// The os-releases directory of every release
+    #[cfg(feature = "daemon")]
+    pub root: PathBuf,
+    /// The directory of the release scanned
+    pub dir: PathBuf,
+    pub version_id: String,
+    pub fallback: OsReleaseFallback,
+}
+
+impl OsReleaseSource {
+    /// The source of `version_id`. With the "previous" policy, a release
+    /// without its own directory inherits the enabled set of the closest
+    /// earlier release.
+    pub fn new(
+        root: PathBuf,
+        version_id: String,
+        fallback: OsReleaseFallback,
+        verbose: bool,
+    ) -> Self {
+        let mut dir = root.join(&version_id);
+        if fallback == OsReleaseFallback::Previous && !dir.exists() {
+            if let Some(previous) = find_previous_os_release_dir(&root, &version_id) {
+                if verbose {
+                    println!(
+                        "No os-releases directory for VERSION_ID '{version_id}', inheriting {}",
+                        previous.display()
+                    );
+                }
+                dir = previous;
+            }
+        }
+        Self {
+            #[cfg(feature = "daemon")]
+            root,
+            dir,
+            version_id,
+            fallback,
+        }
+    }
+}
+
+impl Source for OsReleaseSource {
+    fn scan(
+        &self,
+        _found: &BTreeMap<String, Extension>,
+        output: &OutputManager,
+    ) -> Result<Vec<Candidate>, SystemdError> {
+        let dir = self.dir.display();
+        let version_id = &self.version_id;
+        if output.is_verbose() {
+            println!("Scanning OS release extensions in {dir} (VERSION_ID: {version_id})");
+        }
+
+        if !self.dir.exists() {
+            if output.is_verbose() {
+                println!("OS releases directory {dir} does not exist, skipping");
+            }
+            if std::env::var("AVOCADO_TEST_MODE").is_err()
+                && self.fallback != OsReleaseFallback::None
+            {
+                output.warning(&format!("No extensions are enabled for VERSION_ID '{version_id}'. Directory not found: {dir}"));
+            }
+            return Ok(Vec::new());
+        }
+
+        let dir_str = self.dir.to_string_lossy();
+        let mut candidates = directory_candidates(&self.dir, "OS release", None);
+        if let Ok(raw_files) = scan_raw_files(&dir_str) {
+            candidates.extend(image_candidates(
+                raw_files,
+                Layout::Image(None),
+                "OS release raw",
+            ));
+        }
+        candidates.extend(image_candidates(
+            scan_archive_files(&dir_str),
+            Layout::Archive,
+            "archive",
+        ));
+        Ok(candidates)
+    }
+
+    fn fetch(
+        &self,
+        candidate: &Candidate,
+        output: &OutputManager,
+    ) -> Result<Option<Extension>, SystemdError> {
+        match candidate.layout {
+            // An enabled image that cannot be analyzed is left out
+            Layout::Image(_) => Ok(fetch_local(candidate, output).ok()),
+            _ => fetch_local_or_skip_archive(candidate, output),
+        }
+    }
+
+    #[cfg(feature = "daemon")]
+    fn watch(&self) -> Vec<PathBuf> {
+        vec![self.root.clone()]
+    }
+}
+
+/// Directory extensions in the extensions directory, merged when no
+/// os-releases directory applies.
+pub(super) struct DirSource {
+    pub dir: PathBuf,
+    /// Whether the extensions directory is merged at all
+    pub active: bool,
+}
+
+impl Source for DirSource {
+    fn scan(
+        &self,
+        _found: &BTreeMap<String, Extension>,
+        output: &OutputManager,
+    ) -> Result<Vec<Candidate>, SystemdError> {
+        if output.is_verbose() {
+            println!("Scanning directory extensions in {}", self.dir.display());
+        }
+        if !self.active {
+            return Ok(Vec::new());
+        }
+        if output.is_verbose() {
+            println!("No OS releases directory found, scanning base extensions directory");
+        }
+        Ok(directory_candidates(&self.dir, "directory", None))
+    }
+
+    #[cfg(feature = "daemon")]
+    fn watch(&self) -> Vec<PathBuf> {
+        vec![self.dir.clone()]
+    }
+}
+
+/// Raw images and archives in the extensions directory, merged when no
+/// os-releases directory applies.
+pub(super) struct RawSource {
+    pub dir: PathBuf,
+    /// Whether the extensions directory is merged at all
+    pub active: bool,
+}
+
+impl Source for RawSource {
+    fn scan(
+        &self,
+        found: &BTreeMap<String, Extension>,
+        output: &OutputManager,
+    ) -> Result<Vec<Candidate>, SystemdError> {
+        if output.is_verbose() {
+            println!("Scanning raw file extensions in {}", self.dir.display());
+        }
+        if !self.active {
+            return Ok(Vec::new());
+        }
+        if output.is_verbose() {
+            println!("No OS releases directory found, scanning base raw files");
+        }
+        let dir = self.dir.to_string_lossy();
+        let raw_files = scan_raw_files(&dir)?;
+
+        // Loop mounts of images that are no longer available are released
+        let versioned = |name: &str, version: &Option<String>| match version {
+            Some(ver) => format!("{name}-{ver}"),
+            None => name.to_string(),
+        };
+        let available_loop_names: Vec<String> = found
+            .values()
+            .map(|ext| versioned(&ext.name, &ext.version))
+            .chain(
+                raw_files
+                    .iter()
+                    .map(|(name, version, _)| versioned(name, version)),
+            )
+            .collect();
+        cleanup_stale_mounts(&available_loop_names)?;
+
+        let mut candidates: Vec<Candidate> =
+            image_candidates(raw_files, Layout::Image(None), "raw file").collect();
+        candidates.extend(image_candidates(
+            scan_archive_files(&dir),
+            Layout::Archive,
+            "archive",
+        ));
+        Ok(candidates)
+    }
+
+    #[cfg(feature = "daemon")]
+    fn watch(&self) -> Vec<PathBuf> {
+        vec![self.dir.clone()]
+    }
+}
+
+/// Extensions the host provides to a container.
+pub(super) struct HostSource {
+    pub dir: PathBuf,
+}
+
+impl Source for HostSource {
+    fn scan(
+        &self,
+        _found: &BTreeMap<String, Extension>,
+        output: &OutputManager,
+    ) -> Result<Vec<Candidate>, SystemdError> {
+        if output.is_verbose() {
+            println!("Scanning host extensions in {}", self.dir.display());
+        }
+        Ok(directory_candidates(&self.dir, "host", None))
+    }
+
+    #[cfg(feature = "daemon")]
+    fn watch(&self) -> Vec<PathBuf> {
+        vec![self.dir.clone()]
+    }
+}
+
+fn hitl_dir() -> PathBuf {
+    PathBuf::from(if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/hitl")
+    } else {
+        crate::user_mode::system_path("/run/avocado/hitl")
+    })
+}
+
+fn os_releases_root() -> PathBuf {
+    PathBuf::from(if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/os-releases")
+    } else {
+        crate::user_mode::system_path("/var/lib/avocado/os-releases")
+    })
+}
+
+/// Create `dir` and its missing parents with mode 0755, owned like the
+/// closest directory above them that exists.
+fn create_dir_owned(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let mut missing = Vec::new();
+    let mut existing = dir;
+    while !existing.exists() {
+        missing.push(existing);
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => break,
+        }
+    }
+    let owner = std::fs::metadata(existing)?;
+    for path in missing.into_iter().rev() {
+        match std::fs::create_dir(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e),
+            _ => {}
+        }
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+        std::os::unix::fs::chown(path, Some(owner.uid()), Some(owner.gid()))?;
+    }
+    Ok(())
+}
+
+/// Apply `[avocado.ext] create_dirs` and `missing_dir` to `dir`, the
+/// `what` directory a command is about to read: create it when asked to,
+/// otherwise fail, warn or carry on as the policy says.
+pub(crate) fn check_dir(
+    config: &Config,
+    what: &str,
+    dir: &Path,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    if dir.exists() {
+        return Ok(());
+    }
+    let mut reason = "does not exist".to_string();
+    if config.avocado.ext.create_dirs && !crate::unprivileged::is_read_only() {
+        match create_dir_owned(dir) {
+            Ok(()) => return Ok(()),
+            Err(e) => reason = format!("does not exist and could not be created: {e}"),
+        }
+    }
+    let message = format!("{what} directory '{}' {reason}", dir.display());
+    match config.avocado.ext.missing_dir {
+        MissingDirPolicy::Error => Err(SystemdError::ConfigurationError { message }),
+        MissingDirPolicy::Warn => {
+            output.warning(&message);
+            Ok(())
+        }
+        MissingDirPolicy::Ignore => Ok(()),
+    }
+}
+
+/// The sources of a scan, in priority order: HITL mounts, then the active
+/// runtime `manifest` or, without one, the os-releases directory and the
+/// extensions directory, then the host's extensions in a container. The
+/// latter two are checked with [`check_dir`] first.
+pub(super) fn sources(
+    config: &Config,
+    manifest: Option<RuntimeManifest>,
+    output: &OutputManager,
+) -> Result<Vec<Box<dyn Source>>, SystemdError> {
+    let verbose = output.is_verbose();
+    let fallback = config.os_release_fallback();
+    let mut sources: Vec<Box<dyn Source>> = vec![Box::new(HitlSource { dir: hitl_dir() })];
+
+    if let Some(manifest) = manifest {
+        if verbose {
+            println!(
+                "Found active runtime manifest: {} {} ({})",
+                manifest.runtime.name,
+                manifest.runtime.version,
+                &manifest.id[..8.min(manifest.id.len())]
+            );
+        }
+        sources.push(Box::new(ManifestSource {
+            base_dir: PathBuf::from(RuntimeManifest::base_dir()),
+            manifest,
+        }));
+    } else {
+        if verbose {
+            println!("No active runtime manifest found, using legacy extension discovery");
+        }
+        let os_releases_root = os_releases_root();
+        check_dir(config, "OS releases", &os_releases_root, output)?;
+        let os_release =
+            OsReleaseSource::new(os_releases_root, read_os_version_id(), fallback, verbose);
+        // Fallback to the images directory where extension images are installed
+        let extensions_dir = PathBuf::from(
+            std::env::var("AVOCADO_EXTENSIONS_PATH")
+                .unwrap_or_else(|_| crate::user_mode::system_path("/var/lib/avocado/images")),
+        );
+        check_dir(config, "Extensions", &extensions_dir, output)?;
+
+        // The base directory is only consulted when no os-releases directory
+        // applies and the fallback policy allows it.
+        let os_releases_dir_exists = os_release.dir.exists();
+        let active = !os_releases_dir_exists && fallback != OsReleaseFallback::None;
+        if verbose && !os_releases_dir_exists && !active {
+            println!(
+                "os_release_fallback = none, not merging extensions from {}",
+                extensions_dir.display()
+            );
+        }
+        if verbose && os_releases_dir_exists {
+            println!("OS releases directory exists, skipping base extensions directory and raw files (use enable/disable to manage extensions)");
+        }
+        sources.push(Box::new(os_release));
+        sources.push(Box::new(DirSource {
+            dir: extensions_dir.clone(),
+            active,
+        }));
+        sources.push(Box::new(RawSource {
+            dir: extensions_dir,
+            active,
+        }));
+    }
+
+    if crate::container::is_container() {
+        sources.push(Box::new(HostSource {
+            dir: crate::container::host_extensions_dir(),
+        }));
+    }
+    Ok(sources)
+}
+
+/// Paths auto-refresh watches: those of every built-in source, whether or
+/// not a runtime manifest is active.
+#[cfg(feature = "daemon")]
+pub(crate) fn watched_paths(extensions_dir: &Path) -> Vec<PathBuf> {
+    let sources: Vec<Box<dyn Source>> = vec![
+        Box::new(DirSource {
+            dir: extensions_dir.to_path_buf(),
+            active: true,
+        }),
+        Box::new(OsReleaseSource::new(
+            os_releases_root(),
+            read_os_version_id(),
+            OsReleaseFallback::default(),
+            false,
+        )),
+        Box::new(HitlSource { dir: hitl_dir() }),
+    ];
+    sources.iter().flat_map(|source| source.watch()).collect()
+}
+
+/// What a scan found.
+pub(super) struct Scan {
+    /// The extension of each name from the highest-priority source having it
+    pub extensions: BTreeMap<String, Extension>,
+    /// `<name>-<version>` of every archive seen, for pruning the archive cache
+    pub archive_stems: Vec<String>,
+    /// Extensions that failed to fetch, with `keep_going`
+    pub failed: Vec<Failure>,
+    /// Extensions the trust store would refuse, unless [`TrustCheck::enforce`]
+    pub rejected: Vec<Failure>,
+}
+
+/// Scan `sources` in priority order. A name found again lower down is
+/// skipped, but an extension without a merge priority takes the one the
+/// runtime manifest gives the name. With `keep_going` an extension that
+/// fails to fetch is recorded in [`Scan::failed`] and left out, including
+/// from the sources below, instead of failing the scan. Extensions are
+/// checked against the trust store as `trust` says before they are fetched.
+pub(super) fn scan(
+    sources: &[Box<dyn Source>],
+    trust: TrustCheck,
+    output: &OutputManager,
+    keep_going: bool,
+) -> Result<Scan, SystemdError> {
+    let verbose = output.is_verbose();
+    let mut found: BTreeMap<String, Extension> = BTreeMap::new();
+    let mut archive_stems = Vec::new();
+    let mut failed: Vec<Failure> = Vec::new();
+    let mut rejected: Vec<Failure> = Vec::new();
+    let mut failed_names = std::collections::BTreeSet::new();
+    for source in sources {
+        for candidate in source.scan(&found, output)? {
+            let versioned = match &candidate.version {
+                Some(ver) => format!("{}-{ver}", candidate.name),
+                None => candidate.name.clone(),
+            };
+            if candidate.layout == Layout::Archive {
+                archive_stems.push(versioned.clone());
+            }
+            if failed_names.contains(&candidate.name) {
+                continue;
+            }
+
+            if let Some(existing) = found.get_mut(&candidate.name) {
+                match candidate.merge_index {
+                    Some(index) if existing.merge_index.is_none() => {
+                        existing.merge_index = Some(index);
+                        if verbose {
+                            println!(
+                                "Extension {} inherits manifest priority #{index:02}",
+                                candidate.name
+                            );
+                        }
+                    }
+                    _ if verbose => println!(
+                        "Skipping {} extension {} (higher priority version preferred)",
+                        candidate.label, candidate.name
+                    ),
+                    _ => {}
+                }
+                continue;
+            }
+
+            let (fetched, rejection) = if trust.enforce {
+                let fetched = check_signature(&candidate, trust)
+                    .and_then(|()| source.fetch(&candidate, output));
+                (fetched, None)
+            } else {
+                let rejection = check_signature(&candidate, trust).err();
+                (source.fetch(&candidate, output), rejection)
+            };
+            let fetched = match fetched {
+                Err(e) if keep_going => {
+                    if verbose {
+                        println!("Skipping extension {versioned}: {e}");
+                    }
+                    failed.push(Failure::new(versioned, &e));
+                    failed_names.insert(candidate.name);
+                    continue;
+                }
+                fetched => fetched.for_extension(&versioned)?,
+            };
+            let Some(mut extension) = fetched else {
+                continue;
+            };
+            if candidate.merge_index.is_some() {
+                extension.merge_index = candidate.merge_index;
+            }
+            if verbose {
+                match extension.merge_index {
+                    Some(index) => println!(
+                        "Found {} extension: {} at {} (priority #{index:02})",
+                        candidate.label,
+                        extension.name,
+                        extension.path.display()
+                    ),
+                    None => println!(
+                        "Found {} extension: {} at {}",
+                        candidate.label,
+                        extension.name,
+                        extension.path.display()
+                    ),
+                }
+            }
+            if let Some(e) = rejection {
+                rejected.push(Failure::new(versioned, &e));
+            }
+            found.insert(candidate.name, extension);
+        }
+    }
+    Ok(Scan {
+        extensions: found,
+        archive_stems,
+        failed,
+        rejected,
+    })
+}
+
+/// Represents an extension and its type(s)
+#[derive(Debug, Clone)]
+pub(super) struct Extension {
+    pub name: String,
+    pub version: Option<String>, // Version extracted from filename (e.g., "1.0.0" from "app-1.0.0.raw")
+    pub path: PathBuf,
+    pub is_sysext: bool,
+    pub is_confext: bool,
+    pub image_type: ImageTypeTag,
+    /// Merge priority index derived from manifest ordering.
+    /// Used to compute a numerical prefix for deterministic systemd merge order.
+    /// None for extensions discovered outside the manifest (legacy behavior).
+    pub merge_index: Option<usize>,
+    /// Data partitions of a GPT `.raw` image; empty for anything else
+    pub partitions: Vec<crate::ddi::Partition>,
+}
+
+/// VERSION_ID of the host's os-release, re-read when the file changes.
+pub(crate) fn read_os_version_id() -> String {
+    crate::os_release::version_id()
+}
+
+/// Compare two VERSION_ID strings segment by segment, numerically where both
+/// segments are numbers (so "1.10" sorts after "1.9").
+pub(crate) fn compare_version_ids(a: &str, b: &str) -> std::cmp::Ordering {
+    let segments = |v: &str| -> Vec<String> {
+        v.split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect()
+    };
+    let (sa, sb) = (segments(a), segments(b));
+    for (x, y) in sa.iter().zip(sb.iter()) {
+        let ord = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(nx), Ok(ny)) => nx.cmp(&ny),
+            _ => x.cmp(y),
+        };
+        if ord != std::cmp::Ordering::Equal {
+            return ord;
+        }
+    }
+    sa.len().cmp(&sb.len())
+}
+
+/// Find the os-releases directory of the closest VERSION_ID before `current`.
+/// When `current` is unknown, the highest available VERSION_ID is used.
+pub(super) fn find_previous_os_release_dir(
+    os_releases_root: &Path,
+    current: &str,
+) -> Option<PathBuf> {
+    let entries = fs::read_dir(os_releases_root).ok()?;
+    entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| e.file_name().to_str().map(String::from))
+        .filter(|v| {
+            v != current
+                && (current == "unknown"
+                    || compare_version_ids(v, current) == std::cmp::Ordering::Less)
+        })
+        .max_by(|a, b| compare_version_ids(a, b))
+        .map(|v| os_releases_root.join(v))
+}
+
+/// Scan all extension sources in priority order
+pub(super) fn scan_extensions_from_all_sources(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<Vec<Extension>, SystemdError> {
+    scan_all_sources(config, output, false, false).map(|scanned| scanned.extensions)
+}
+
+/// What [`scan_all_sources`] found.
+pub(super) struct SourceScan {
+    /// Manifest priority first (highest first), then by name
+    pub extensions: Vec<Extension>,
+    /// Extensions left out by `keep_going`
+    pub failed: Vec<Failure>,
+    /// Extensions a merge would refuse, found without `enforce_trust`
+    pub rejected: Vec<Failure>,
+}
+
+/// Scan all extension sources; with `keep_going` extensions that fail to
+/// fetch are returned as failures instead of failing the scan. With
+/// `enforce_trust`, as for a merge, extensions the trust store does not
+/// accept are refused; otherwise they are scanned and reported as rejected.
+pub(super) fn scan_all_sources(
+    config: &Config,
+    output: &OutputManager,
+    keep_going: bool,
+    enforce_trust: bool,
+) -> Result<SourceScan, SystemdError> {
+    // Release files are re-read once per scan
+    extension_release::invalidate();
+
+    // If a manifest exists, use it to determine extensions and skip legacy os-releases scanning
+    let base_dir = crate::manifest::RuntimeManifest::base_dir();
+    let active_manifest = crate::manifest::RuntimeManifest::load_active(Path::new(&base_dir));
+    let used_manifest = active_manifest.is_some();
+
+    let sources = sources(config, active_manifest, output)?;
+    let trust =
+        crate::trust::TrustStore::load(Path::new(&config.get_avocado_base_dir())).map_err(|e| {
+            SystemdError::ConfigurationError {
+                message: e.to_string(),
+            }
+        })?;
+    let trust_check = TrustCheck {
+        store: &trust,
+        enforce: enforce_trust,
+        allow_directories: config.allow_unsigned_directories(),
+    };
+    let scan = scan(&sources, trust_check, output, keep_going)?;
+    if !used_manifest && !crate::unprivileged::is_read_only() {
+        crate::archive::prune_cache(&crate::archive::cache_dir(), &scan.archive_stems);
+    }
+
+    // Convert map to vector: manifest priority first (highest first, the
+    // order of the manifest), then by name
+    let mut extensions: Vec<Extension> = scan.extensions.into_values().collect();
+    extensions.sort_by_key(|ext| std::cmp::Reverse(ext.merge_index));
+    Ok(SourceScan {
+        extensions,
+        failed: scan.failed,
+        rejected: scan.rejected,
+    })
+}
+
+/// Scan a single directory for directory-based extensions
+pub(super) fn scan_directory_extensions(dir_path: &str) -> Result<Vec<Extension>, SystemdError> {
+    let mut extensions = Vec::new();
+
+    if !Path::new(dir_path).exists() {
+        return Ok(extensions);
+    }
+
+    let entries = read_dir_sorted(dir_path).context("read directory", dir_path)?;
+
+    for entry in entries {
+        let path = entry.path();
+
+        if path.is_dir() {
+            if let Some(file_name) = path.file_name() {
+                if let Some(name_str) = file_name.to_str() {
+                    let extension = analyze_directory_extension(name_str, &path)?;
+                    extensions.push(extension);
+                }
+            }
+        }
+    }
+
+    Ok(extensions)
+}
+
+/// Apply the type given to `hitl mount --type` to a scanned HITL extension,
+/// keeping the scope checks of the forced kind.
+pub(super) fn apply_hitl_mount_type(
+    extension: &mut Extension,
+    mount_type: crate::hitl_health::MountType,
+) {
+    use crate::hitl_health::MountType;
+    let scope_name = match &extension.version {
+        Some(version) => format!("{}-{version}", extension.name),
+        None => extension.name.clone(),
+    };
+    match mount_type {
+        MountType::Auto => {}
+        MountType::Sysext => {
+            extension.is_sysext = image_adaptor::is_sysext_enabled_for_current_environment(
+                &extension.path,
+                &scope_name,
+            );
+            extension.is_confext = false;
+        }
+        MountType::Confext => {
+            extension.is_sysext = false;
+            extension.is_confext = image_adaptor::is_confext_enabled_for_current_environment(
+                &extension.path,
+                &scope_name,
+            );
+        }
+    }
+}
+
+/// Split `<name>-<version>` into its parts.
+/// The suffix after the last dash counts as a version only if it contains
+/// digits or dots; otherwise the whole string is the name.
+pub(crate) fn split_name_version(name_with_version: &str) -> (String, Option<String>) {
+    if let Some(last_dash) = name_with_version.rfind('-') {
+        let potential_version = &name_with_version[last_dash + 1..];
+        if potential_version
+            .chars()
+            .any(|c| c.is_ascii_digit() || c == '.')
+        {
+            return (
+                name_with_version[..last_dash].to_string(),
+                Some(potential_version.to_string()),
+            );
+        }
+    }
+    (name_with_version.to_string(), None)
+}
+
+/// Scan a directory for `.tar.zst` archive extensions
+pub(super) fn scan_archive_files(dir_path: &str) -> Vec<(String, Option<String>, PathBuf)> {
+    let Ok(entries) = read_dir_sorted(dir_path) else {
+        return Vec::new();
+    };
+    let mut archives = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if let Some(stem) = crate::archive::archive_stem(&file_name) {
+            let (name, version) = split_name_version(stem);
+            archives.push((name, version, path));
+        }
+    }
+    archives
+}
+
+/// Unpack (or reuse the cached copy of) an archive extension and analyze it
+/// like a directory extension.
+pub(super) fn analyze_archive_extension(
+    name: &str,
+    version: &Option<String>,
+    path: &Path,
+    verbose: bool,
+) -> Result<Extension, SystemdError> {
+    if verbose {
+        println!("Unpacking archive extension: {}", path.display());
+    }
+    let cache = crate::archive::cache_dir();
+    let unpacked = if crate::unprivileged::is_read_only() {
+        crate::archive::cached(path, &cache).ok_or_else(|| crate::archive::ArchiveError::Unpack {
+            path: path.to_path_buf(),
+            message: "not unpacked yet, and read-only mode does not unpack".to_string(),
+        })
+    } else {
+        crate::archive::unpack_cached(path, &cache)
+    };
+    let dir = unpacked.map_err(|e| SystemdError::ConfigurationError {
+        message: e.to_string(),
+    })?;
+    let (is_sysext, is_confext, detected_version) = analyze_mounted_extension(name, version, &dir);
+
+    Ok(Extension {
+        name: name.to_string(),
+        version: detected_version,
+        path: dir,
+        is_sysext,
+        is_confext,
+        image_type: ImageTypeTag::Directory,
+        merge_index: None,
+        partitions: Vec::new(),
+    })
+}
+
+/// Scan a directory for raw file extensions
+pub(super) fn scan_raw_files(
+    dir_path: &str,
+) -> Result<Vec<(String, Option<String>, PathBuf)>, SystemdError> {
+    let mut raw_files = Vec::new();
+
+    if !Path::new(dir_path).exists() {
+        return Ok(raw_files);
+    }
+
+    let entries = read_dir_sorted(dir_path).context("read directory", dir_path)?;
+
+    for entry in entries {
+        let path = entry.path();
+
+        if path.is_file() {
+            if let Some(file_name) = path.file_name() {
+                if let Some(name_str) = file_name.to_str() {
+                    if name_str.ends_with(".raw") {
+                        // Strip .raw suffix to get the extension name (with version)
+                        let ext_name_with_version =
+                            name_str.strip_suffix(".raw").unwrap_or(name_str);
+
+                        let (ext_name, ext_version) = split_name_version(ext_name_with_version);
+                        raw_files.push((ext_name, ext_version, path));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(raw_files)
+}
+
+/// Analyze an image file extension using the given adaptor for mount/unmount.
+/// This unified function replaces the former `analyze_raw_extension_with_loop` and
+/// `analyze_kab_extension` functions.
+pub(super) fn analyze_image_extension(
+    name: &str,
+    version: &Option<String>,
+    path: &Path,
+    adaptor: &ImageType,
+    output: &OutputManager,
+) -> Result<Extension, SystemdError> {
+    let verbose = output.is_verbose();
+    if verbose {
+        println!("Analyzing image extension: {name}");
+    }
+
+    let mount_name = if let Some(ver) = version {
+        format!("{name}-{ver}")
+    } else {
+        name.to_string()
+    };
+
+    if crate::unprivileged::is_read_only() && !adaptor.is_mounted(&mount_name) {
+        return Ok(describe_unmounted_image(name, version, path, adaptor));
+    }
+
+    let mounting = crate::phases::enter(Phase::Mounting);
+    let span = crate::telemetry::span("mount");
+    span.set_attribute("avocado.extension", mount_name.as_str());
+    let mount_point = if crate::unprivileged::is_read_only() {
+        PathBuf::from(extension_mount_point(&mount_name))
+    } else if adaptor.is_mounted(&mount_name) {
+        if adaptor.needs_remount(&mount_name, path) {
+            if verbose {
+                println!("Backing file changed for {mount_name}, remounting...");
+            }
+            if let Err(e) = adaptor.unmount(&mount_name, verbose) {
+                if verbose {
+                    println!("Warning: failed to unmount stale {mount_name}: {e}");
+                }
+            }
+            span.record(adaptor.mount(&mount_name, path, verbose))?
+        } else {
+            if verbose {
+                println!("Using existing mount for {mount_name}");
+            }
+            PathBuf::from(extension_mount_point(&mount_name))
+        }
+    } else {
+        span.record(adaptor.mount(&mount_name, path, verbose))?
+    };
+    drop(span);
+    drop(mounting);
+
+    let (sysext_enabled, confext_enabled, _detected_version) =
+        analyze_mounted_extension(name, version, &mount_point);
+
+    let partitions = if adaptor.type_tag() == ImageTypeTag::Raw {
+        crate::ddi::discover(path).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    if crate::ddi::is_combined(&partitions) {
+        check_combined_image(&mount_name, &mount_point, &partitions, output);
+    }
+
+    Ok(Extension {
+        name: name.to_string(),
+        version: version.clone(),
+        path: mount_point,
+        is_sysext: sysext_enabled,
+        is_confext: confext_enabled,
+        image_type: adaptor.type_tag(),
+        merge_index: None,
+        partitions,
+    })
+}
+
+/// An image read-only mode may not mount, described from its partition
+/// table: a combined image is both a sysext and a confext, anything else
+/// of unknown type.
+pub(super) fn describe_unmounted_image(
+    name: &str,
+    version: &Option<String>,
+    path: &Path,
+    adaptor: &ImageType,
+) -> Extension {
+    let extension = describe_image(name, version, path, adaptor.type_tag());
+    if !extension.is_sysext && !extension.is_confext {
+        crate::unprivileged::note(format!(
+            "{} is not mounted; its type is unknown without root",
+            versioned_name(&extension)
+        ));
+    }
+    extension
+}
+
+/// An extension described without mounting it: a directory from its
+/// release files, a combined image as both a sysext and a confext, and
+/// anything else of unknown type.
+pub(super) fn describe_image(
+    name: &str,
+    version: &Option<String>,
+    path: &Path,
+    image_type: ImageTypeTag,
+) -> Extension {
+    let partitions = if image_type == ImageTypeTag::Raw {
+        crate::ddi::discover(path).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    let combined = crate::ddi::is_combined(&partitions);
+    let (is_sysext, is_confext) = if path.is_dir() {
+        let (is_sysext, is_confext, _) = analyze_mounted_extension(name, version, path);
+        (is_sysext, is_confext)
+    } else {
+        (combined, combined)
+    };
+    Extension {
+        name: name.to_string(),
+        version: version.clone(),
+        path: path.to_path_buf(),
+        is_sysext,
+        is_confext,
+        image_type,
+        merge_index: None,
+        partitions,
+    }
+}
+
+/// A combined image registers its `usr` partition as a sysext and its
+/// `root` partition as a confext. Warn about a half without release data,
+/// which systemd-sysext or systemd-confext would refuse to merge.
+pub(super) fn check_combined_image(
+    mount_name: &str,
+    mount_point: &Path,
+    partitions: &[crate::ddi::Partition],
+    output: &OutputManager,
+) {
+    for partition in partitions {
+        let hierarchy = partition.hierarchy();
+        let kind = match hierarchy {
+            crate::extension_release::Hierarchy::Sysext => "sysext",
+            crate::extension_release::Hierarchy::Confext => "confext",
+        };
+        if mount_point.join(hierarchy.release_dir()).is_dir() {
+            if output.is_verbose() {
+                println!(
+                    "Combined image {mount_name}: {} partition registered as {kind}",
+                    partition.designator.as_str()
+                );
+            }
+        } else {
+            output.warning(&format!(
+                "combined image {mount_name}: {} partition has no {}; its {kind} will not be merged",
+                partition.designator.as_str(),
+                hierarchy.release_dir()
+            ));
+        }
+    }
+}
+
+/// Analyze a directory extension to determine if it's sysext, confext, or both
+pub(super) fn analyze_directory_extension(
+    name: &str,
+    path: &Path,
+) -> Result<Extension, SystemdError> {
+    let (sysext_enabled, confext_enabled, detected_version) =
+        analyze_mounted_extension(name, &None, path);
+
+    Ok(Extension {
+        name: name.to_string(),
+        version: detected_version,
+        path: path.to_path_buf(),
+        is_sysext: sysext_enabled,
+        is_confext: confext_enabled,
+        image_type: ImageTypeTag::Directory,
+        merge_index: None,
+        partitions: Vec::new(),
+    })
+}
+
+/// `<name>-<version>`, or the bare name of an unversioned extension.
+pub(super) fn versioned_name(extension: &Extension) -> String {
+    match &extension.version {
+        Some(version) => format!("{}-{version}", extension.name),
+        None => extension.name.clone(),
+    }
+}
+
+/// Cleanup stale loop refs and KAB loops for extensions that no longer exist.
+pub(super) fn cleanup_stale_mounts(available_extensions: &[String]) -> Result<(), SystemdError> {
+    // Skip cleanup in test mode to avoid interfering with system loops,
+    // and in read-only mode, which must not unmount anything
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() || crate::unprivileged::is_read_only() {
+        return Ok(());
+    }
+
+    // Clean up stale raw loop refs
+    let loop_ref_dir = "/dev/disk/by-loop-ref";
+    if Path::new(loop_ref_dir).exists() {
+        let entries = read_dir_sorted(loop_ref_dir).context("read directory", loop_ref_dir)?;
+
+        let raw = RawAdaptor;
+        for entry in entries {
+            if let Some(loop_name) = entry.file_name().to_str() {
+                if !available_extensions.contains(&loop_name.to_string()) {
+                    println!("Cleaning up stale raw loop for: {loop_name}");
+                    raw.unmount(loop_name, false)?;
+                }
+            }
+        }
+    }
+
+    // Clean up stale KAB offset loops
+    let kab_loops_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/kab-loops")
+    } else {
+        crate::user_mode::system_path("/run/avocado/kab-loops")
+    };
+
+    if Path::new(&kab_loops_dir).exists() {
+        if let Ok(entries) = read_dir_sorted(&kab_loops_dir) {
+            let kab = KabAdaptor;
+            for entry in entries {
+                if let Some(loop_name) = entry.file_name().to_str() {
+                    if !available_extensions.contains(&loop_name.to_string()) {
+                        println!("Cleaning up stale KAB loop for: {loop_name}");
+                        let _ = kab.unmount(loop_name, false);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The in-scope extension-release files of an extension: the sysext and/or
+/// confext release file (exact or versioned name) depending on how the
+/// extension is enabled, skipping files whose SYSEXT_SCOPE / CONFEXT_SCOPE
+/// excludes the current environment.
+pub(super) fn extension_release_files(extension: &Extension) -> Vec<Arc<ReleaseFile>> {
+    [
+        (extension.is_sysext, Hierarchy::Sysext),
+        (extension.is_confext, Hierarchy::Confext),
+    ]
+    .into_iter()
+    .filter(|(enabled, _)| *enabled)
+    .filter_map(|(_, hierarchy)| {
+        extension_release::find(&extension.path, &extension.name, hierarchy)
+    })
+    .filter(|release| release.in_scope)
+    .collect()
+}
+
+/// Get short extension origin description (for 80-column display)
+pub(super) fn get_extension_origin_short(ext: &Extension) -> String {
+    let path_str = ext.path.to_string_lossy();
+
+    if path_str.contains("/hitl") {
+        "HITL".to_string()
+    } else {
+        match ext.image_type {
+            ImageTypeTag::Directory => "Dir".to_string(),
+            ImageTypeTag::Kab => {
+                if let Some(filename) = ext.path.file_name() {
+                    format!("KAB:{}", filename.to_string_lossy())
+                } else {
+                    "KAB".to_string()
+                }
+            }
+            ImageTypeTag::Raw => {
+                if let Some(filename) = ext.path.file_name() {
+                    format!("Loop:{}", filename.to_string_lossy())
+                } else {
+                    "Loop".to_string()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::image_adaptor::ImageTypeTag;
+
+    /// A source holding directory extensions in memory.
+    struct FakeSource {
+        id: &'static str,
+        extensions: Vec<(&'static str, Option<usize>)>,
+    }
+
+    impl Source for FakeSource {
+        fn scan(
+            &self,
+            _found: &BTreeMap<String, Extension>,
+            _output: &OutputManager,
+        ) -> Result<Vec<Candidate>, SystemdError> {
+            Ok(self
+                .extensions
+                .iter()
+                .map(|(name, merge_index)| Candidate {
+                    name: name.to_string(),
+                    version: None,
+                    path: PathBuf::from(format!("/{}/{name}", self.id)),
+                    layout: Layout::Directory,
+                    merge_index: *merge_index,
+                    label: self.id,
+                })
+                .collect())
+        }
+
+        fn fetch(
+            &self,
+            candidate: &Candidate,
+            _output: &OutputManager,
+        ) -> Result<Option<Extension>, SystemdError> {
+            if candidate.name.starts_with("broken") {
+                return Err(SystemdError::ConfigurationError {
+                    message: format!("cannot mount {}", candidate.path.display()),
+                });
+            }
+            Ok(Some(Extension {
+                name: candidate.name.clone(),
+                version: None,
+                path: candidate.path.clone(),
+                is_sysext: true,
+                is_confext: false,
+                image_type: ImageTypeTag::Directory,
+                merge_index: None,
+                partitions: Vec::new(),
+            }))
+        }
+
+        #[cfg(feature = "daemon")]
+        fn watch(&self) -> Vec<PathBuf> {
+            Vec::new()
+        }
+    }
+
+    fn quiet() -> OutputManager {
+        OutputManager::new(false, false)
+    }
+
+    fn enforce(store: &TrustStore) -> TrustCheck<'_> {
+        TrustCheck {
+            store,
+            enforce: true,
+            allow_directories: false,
+        }
+    }
+
+    #[test]
+    fn test_scan_keeps_highest_priority_source() {
+        let sources: Vec<Box<dyn Source>> = vec![
+            Box::new(FakeSource {
+                id: "hitl",
+                extensions: vec![("app", None)],
+            }),
+            Box::new(FakeSource {
+                id: "manifest",
+                extensions: vec![("app", Some(1)), ("base", Some(0))],
+            }),
+            Box::new(FakeSource {
+                id: "dir",
+                extensions: vec![("app", None), ("tools", None), ("base", None)],
+            }),
+        ];
+        let scan = scan(&sources, enforce(&TrustStore::default()), &quiet(), false).unwrap();
+
+        // The HITL mount masks the others but takes the manifest's priority
+        let app = &scan.extensions["app"];
+        assert_eq!(app.path, Path::new("/hitl/app"));
+        assert_eq!(app.merge_index, Some(1));
+        assert_eq!(scan.extensions["base"].path, Path::new("/manifest/base"));
+        assert_eq!(scan.extensions["base"].merge_index, Some(0));
+        assert_eq!(scan.extensions["tools"].path, Path::new("/dir/tools"));
+        assert!(scan.archive_stems.is_empty());
+    }
+
+    #[test]
+    fn test_scan_keep_going_skips_failed_fetch() {
+        let sources: Vec<Box<dyn Source>> = vec![
+            Box::new(FakeSource {
+                id: "hitl",
+                extensions: vec![("broken-app", None)],
+            }),
+            Box::new(FakeSource {
+                id: "dir",
+                extensions: vec![("broken-app", None), ("tools", None)],
+            }),
+        ];
+        assert!(scan(&sources, enforce(&TrustStore::default()), &quiet(), false).is_err());
+
+        let scan = scan(&sources, enforce(&TrustStore::default()), &quiet(), true).unwrap();
+        assert_eq!(scan.extensions.keys().collect::<Vec<_>>(), ["tools"]);
+        assert_eq!(scan.failed.len(), 1);
+        assert_eq!(scan.failed[0].extension, "broken-app");
+        assert!(scan.failed[0].error.contains("/hitl/broken-app"));
+    }
+
+    #[test]
+    fn test_scan_refuses_directories_once_the_store_has_keys() {
+        let sources: Vec<Box<dyn Source>> = vec![Box::new(FakeSource {
+            id: "dir",
+            extensions: vec![("tools", None)],
+        })];
+        let mut store = TrustStore::default();
+        let key = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([1; 32]));
+        store.rotate(&key.pk, None, None, 0, 0).unwrap();
+
+        let err = scan(&sources, enforce(&store), &quiet(), false)
+            .err()
+            .expect("the directory should be refused");
+        assert!(
+            err.to_string().contains("directories cannot be signed"),
+            "{err}"
+        );
+
+        // Listings keep the directory and report it
+        let mut check = TrustCheck {
+            enforce: false,
+            ..enforce(&store)
+        };
+        let listed = scan(&sources, check, &quiet(), false).unwrap();
+        assert!(listed.extensions.contains_key("tools"));
+        assert_eq!(listed.rejected.len(), 1);
+        assert_eq!(listed.rejected[0].extension, "tools");
+
+        check.enforce = true;
+        check.allow_directories = true;
+        let allowed = scan(&sources, check, &quiet(), false).unwrap();
+        assert!(allowed.extensions.contains_key("tools"));
+        assert!(allowed.rejected.is_empty());
+    }
+
+    #[test]
+    fn test_check_dir_policies() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let missing = tmp.path().join("images");
+        let mut config = Config::default();
+        assert!(check_dir(&config, "Extensions", &missing, &quiet()).is_ok());
+
+        config.avocado.ext.missing_dir = MissingDirPolicy::Error;
+        let err = check_dir(&config, "Extensions", &missing, &quiet()).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+
+        config.avocado.ext.create_dirs = true;
+        let nested = missing.join("os-releases");
+        check_dir(&config, "OS releases", &nested, &quiet()).unwrap();
+        let parent = std::fs::metadata(tmp.path()).unwrap();
+        for dir in [&missing, &nested] {
+            let meta = std::fs::metadata(dir).unwrap();
+            assert!(meta.is_dir());
+            assert_eq!((meta.uid(), meta.gid()), (parent.uid(), parent.gid()));
+            assert_eq!(meta.mode() & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn test_analyze_directory_extension() {
+        // Test with no release files
+        let test_path = PathBuf::from("/tmp/test_extension");
+        let extension = analyze_directory_extension("test_ext", &test_path).unwrap();
+
+        assert_eq!(extension.name, "test_ext");
+        assert!(extension.is_sysext);
+        assert!(extension.is_confext);
+        assert_eq!(extension.image_type, ImageTypeTag::Directory);
+    }
+
+    #[test]
+    fn test_scans_are_sorted_by_file_name() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path().to_str().unwrap();
+        for file in ["b-1.0.raw", "a-2.0.raw", "c.raw", "a-1.0.raw"] {
+            fs::write(temp.path().join(file), "").unwrap();
+        }
+        for name in ["zeta", "alpha", "mid"] {
+            fs::create_dir(temp.path().join(name)).unwrap();
+        }
+
+        let raw: Vec<String> = scan_raw_files(dir)
+            .unwrap()
+            .into_iter()
+            .map(|(name, version, _)| format!("{name}/{}", version.unwrap_or_default()))
+            .collect();
+        assert_eq!(raw, ["a/1.0", "a/2.0", "b/1.0", "c/"]);
+
+        let dirs: Vec<String> = scan_directory_extensions(dir)
+            .unwrap()
+            .into_iter()
+            .map(|ext| ext.name)
+            .collect();
+        assert_eq!(dirs, ["alpha", "mid", "zeta"]);
+    }
+
+    #[test]
+    fn test_compare_version_ids() {
+        use std::cmp::Ordering;
+        assert_eq!(compare_version_ids("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_version_ids("2024.1", "2024.1"), Ordering::Equal);
+        assert_eq!(compare_version_ids("1.2", "1.2.1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_find_previous_os_release_dir() {
+        use tempfile::TempDir;
+
+        let tmp = TempDir::new().unwrap();
+        for v in ["1.8", "1.9", "1.11", "2.0"] {
+            fs::create_dir_all(tmp.path().join(v)).unwrap();
+        }
+        assert_eq!(
+            find_previous_os_release_dir(tmp.path(), "1.10"),
+            Some(tmp.path().join("1.9"))
+        );
+        assert_eq!(
+            find_previous_os_release_dir(tmp.path(), "unknown"),
+            Some(tmp.path().join("2.0"))
+        );
+        assert_eq!(find_previous_os_release_dir(tmp.path(), "1.0"), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! Checks an extension has to pass to be enabled or merged.
+//!
+//! [`validate_extension`] checks the release files against the host
+//! os-release (systemd's matching rules, see
+//! [`evaluate_release_compatibility`]), for unknown AVOCADO_* keys and a
+//! missing release file, and the image against the SHA256 the active
+//! runtime manifest records. Enable runs it on the extension being enabled,
+//! the [scan](super::scan) phase on every extension a merge finds; the
+//! strictness policy decides which problems are fatal.
+
+use crate::commands::harness;
+use crate::commands::image_adaptor;
+use crate::config::{Config, ValidationCheck, ValidationPolicy};
+use crate::extension_release;
+use std::fs;
+use std::path::Path;
+
+/// Outcome of checking an extension against the os-release it is enabled for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ReleaseCompatibility {
+    Compatible,
+    /// The extension or host release data could not be read; enabling proceeds.
+    Unverified(String),
+    Incompatible(String),
+}
+
+/// Reason reported when an extension has no extension-release file.
+const NO_RELEASE_FILE: &str = "no extension-release file found";
+
+/// Apply systemd's extension matching rules to each release file: `ID` must
+/// be `_any` or the host ID, and then `{SYSEXT,CONFEXT}_LEVEL` must match the
+/// host's level when the extension sets one, otherwise `VERSION_ID` must
+/// match the release being enabled.
+pub(crate) fn evaluate_release_compatibility(
+    releases: &[harness::ReleaseFile],
+    host_os_release: Option<&crate::os_release::OsRelease>,
+    version_id: &str,
+) -> ReleaseCompatibility {
+    if releases.is_empty() {
+        return ReleaseCompatibility::Unverified(NO_RELEASE_FILE.to_string());
+    }
+    for release in releases {
+        let file = &release.relative_path;
+        let fields = release.fields();
+        let Some(id) = fields.id.as_deref() else {
+            return ReleaseCompatibility::Incompatible(format!("{file} has no ID="));
+        };
+        if id == "_any" {
+            continue;
+        }
+        let Some(host) = host_os_release else {
+            return ReleaseCompatibility::Unverified("host os-release unavailable".to_string());
+        };
+        match host.id.as_deref() {
+            Some(host_id) if host_id == id => {}
+            host_id => {
+                return ReleaseCompatibility::Incompatible(format!(
+                    "{file}: ID={id} does not match host ID={}",
+                    host_id.unwrap_or("<unset>")
+                ))
+            }
+        }
+        let level_key = if release.kind == "confext" {
+            "CONFEXT_LEVEL"
+        } else {
+            "SYSEXT_LEVEL"
+        };
+        if let Some(level) = fields.get(level_key) {
+            let host_level = host.level(release.kind);
+            if host_level != Some(level) {
+                return ReleaseCompatibility::Incompatible(format!(
+                    "{file}: {level_key}={level} does not match host {level_key}={}",
+                    host_level.unwrap_or("<unset>")
+                ));
+            }
+        } else if let Some(ext_version) = fields.version_id.as_deref() {
+            if ext_version != version_id {
+                return ReleaseCompatibility::Incompatible(format!(
+                    "{file}: VERSION_ID={ext_version} does not match target VERSION_ID={version_id}"
+                ));
+            }
+        }
+    }
+    ReleaseCompatibility::Compatible
+}
+
+/// Read the release files of the extension at `source_path` (a directory,
+/// `.raw` image or `.tar.zst` archive). Raw images are mounted read-only just
+/// long enough to read their release files.
+pub(crate) fn read_enable_target_releases(
+    source_path: &Path,
+    verbose: bool,
+) -> Result<Vec<harness::ReleaseFile>, String> {
+    let file_name = source_path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    if source_path.is_dir() {
+        Ok(harness::find_release_files(source_path))
+    } else if crate::archive::archive_stem(&file_name).is_some() {
+        crate::archive::unpack_cached(source_path, &crate::archive::cache_dir())
+            .map(|dir| harness::find_release_files(&dir))
+            .map_err(|e| e.to_string())
+    } else {
+        let name = harness::image_name(source_path);
+        let mount_point = std::env::temp_dir().join(format!(
+            "avocado-enable-check-{}/{name}",
+            std::process::id()
+        ));
+        let mount_str = mount_point.to_string_lossy().to_string();
+        if let Err(e) = image_adaptor::mount_image_once(&name, source_path, &mount_str, verbose) {
+            return Err(format!("failed to mount image: {e}"));
+        }
+        let releases = harness::find_release_files(&mount_point);
+        let _ = image_adaptor::unmount_image_once(&mount_str, verbose);
+        if let Some(parent) = mount_point.parent() {
+            let _ = fs::remove_dir_all(parent);
+        }
+        Ok(releases)
+    }
+}
+
+/// A problem found by the checks run before an extension is enabled.
+/// Whether it aborts is decided by the strictness policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ValidationProblem {
+    pub(crate) check: ValidationCheck,
+    pub(crate) message: String,
+}
+
+impl ValidationProblem {
+    /// Whether the problem stops the extension from being enabled. `force`
+    /// turns every problem into a warning.
+    pub(crate) fn is_fatal(&self, config: &Config, force: bool) -> bool {
+        !force && config.validation_policy(self.check) == ValidationPolicy::Error
+    }
+}
+
+/// Result of [`validate_enable_target`].
+#[derive(Debug, Default)]
+pub(crate) struct EnableValidation {
+    pub(crate) problems: Vec<ValidationProblem>,
+    /// Why os-release compatibility could not be checked, if it could not.
+    pub(crate) unverified: Option<String>,
+}
+
+/// Run the enable-time checks on extension `name` at `source_path`: its
+/// release files against the host os-release for `version_id`, the
+/// presence of a release file, unknown AVOCADO_* keys, and the image
+/// against the SHA256 the active runtime manifest records for it.
+pub(crate) fn validate_enable_target(
+    name: &str,
+    source_path: &Path,
+    version_id: &str,
+    config: &Config,
+    verbose: bool,
+) -> EnableValidation {
+    let releases = read_enable_target_releases(source_path, verbose);
+    let image = source_path.is_file().then_some(source_path);
+    validate_extension(name, releases, image, version_id, config)
+}
+
+/// The checks of [`validate_enable_target`] on release files already read,
+/// or why they could not be. `image`, when given, is hashed for the
+/// checksum check.
+pub(crate) fn validate_extension(
+    name: &str,
+    releases: Result<Vec<harness::ReleaseFile>, String>,
+    image: Option<&Path>,
+    version_id: &str,
+    config: &Config,
+) -> EnableValidation {
+    let mut validation = EnableValidation::default();
+    let mut problem = |check, message: String| {
+        validation
+            .problems
+            .push(ValidationProblem { check, message })
+    };
+
+    if let Ok(releases) = &releases {
+        for release in releases {
+            for key in extension_release::unknown_avocado_keys(&release.content) {
+                problem(
+                    ValidationCheck::UnknownKeys,
+                    format!("{}: unknown key {key}", release.relative_path),
+                );
+            }
+        }
+    }
+
+    if let Some(image) = image {
+        let base_dir = config.get_avocado_base_dir();
+        let expected = crate::manifest::RuntimeManifest::load_active(Path::new(&base_dir))
+            .and_then(|manifest| {
+                manifest
+                    .extensions
+                    .into_iter()
+                    .find(|e| name == format!("{}-{}", e.name, e.version) || name == e.name)
+                    .and_then(|e| e.sha256)
+            });
+        if let Some(expected) = expected {
+            match crate::hash::sha256_file(&crate::hash::Checksummer::default(), image) {
+                Ok(actual) if actual.eq_ignore_ascii_case(&expected) => {}
+                Ok(actual) => problem(
+                    ValidationCheck::Checksum,
+                    format!(
+                        "SHA256 {actual} does not match {expected} recorded in the active runtime manifest"
+                    ),
+                ),
+                Err(e) => problem(
+                    ValidationCheck::Checksum,
+                    format!("failed to hash {}: {e}", image.display()),
+                ),
+            }
+        }
+    }
+
+    let compatibility = match releases {
+        Ok(releases) => evaluate_release_compatibility(
+            &releases,
+            crate::os_release::host().as_ref(),
+            version_id,
+        ),
+        Err(reason) => ReleaseCompatibility::Unverified(reason),
+    };
+    match compatibility {
+        ReleaseCompatibility::Compatible => {}
+        ReleaseCompatibility::Unverified(reason) if reason == NO_RELEASE_FILE => {
+            problem(ValidationCheck::MissingReleaseFile, reason)
+        }
+        ReleaseCompatibility::Unverified(reason) => validation.unverified = Some(reason),
+        ReleaseCompatibility::Incompatible(reason) => problem(ValidationCheck::Scope, reason),
+    }
+    validation
+}
@@ -0,0 +1,321 @@
+//! `avocadoctl ext graph` — dependency graph of the enabled extensions.
+//!
+//! Edges come from each extension's release files: AVOCADO_REQUIRES names
+//! the extensions it depends on and AVOCADO_ENABLE_SERVICES the units it
+//! enables. HITL extensions are backed by an NFS mount unit, which the
+//! services they enable are bound to through the drop-ins `hitl mount`
+//! writes. Requirements on extensions that are not enabled and requirement
+//! cycles are reported; either makes the command exit 1.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// One enabled extension and what it depends on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphNode {
+    pub name: String,
+    /// Extensions required through AVOCADO_REQUIRES, in file order.
+    pub requires: Vec<String>,
+    /// Units enabled through AVOCADO_ENABLE_SERVICES.
+    pub services: Vec<String>,
+    /// Mount unit backing a HITL extension.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mount: Option<String>,
+}
+
+/// An AVOCADO_REQUIRES entry naming an extension that is not enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MissingDependency {
+    pub extension: String,
+    pub requires: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub missing: Vec<MissingDependency>,
+    /// Each cycle starts at its alphabetically first extension and lists
+    /// every extension on it once.
+    pub cycles: Vec<Vec<String>>,
+}
+
+impl DependencyGraph {
+    pub fn new(mut nodes: Vec<GraphNode>) -> Self {
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+        let index: BTreeMap<&str, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.name.as_str(), i))
+            .collect();
+
+        let missing = nodes
+            .iter()
+            .flat_map(|node| {
+                node.requires
+                    .iter()
+                    .filter(|req| !index.contains_key(req.as_str()))
+                    .map(|req| MissingDependency {
+                        extension: node.name.clone(),
+                        requires: req.clone(),
+                    })
+            })
+            .collect();
+
+        let mut cycles = Vec::new();
+        let mut state = vec![Visit::New; nodes.len()];
+        let mut stack = Vec::new();
+        for start in 0..nodes.len() {
+            find_cycles(start, &nodes, &index, &mut state, &mut stack, &mut cycles);
+        }
+        cycles.sort();
+        cycles.dedup();
+
+        Self {
+            nodes,
+            missing,
+            cycles,
+        }
+    }
+
+    /// Whether the graph has a missing dependency or a cycle.
+    pub fn has_problems(&self) -> bool {
+        !self.missing.is_empty() || !self.cycles.is_empty()
+    }
+
+    fn is_missing(&self, extension: &str, requires: &str) -> bool {
+        self.missing
+            .iter()
+            .any(|m| m.extension == extension && m.requires == requires)
+    }
+
+    fn on_cycle(&self, extension: &str, requires: &str) -> bool {
+        self.cycles.iter().any(|cycle| {
+            cycle
+                .iter()
+                .zip(cycle.iter().cycle().skip(1))
+                .any(|(from, to)| from == extension && to == requires)
+        })
+    }
+
+    /// Render as a Graphviz digraph. Missing extensions are drawn dashed,
+    /// cycle edges red.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph extensions {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            let _ = writeln!(dot, "    \"{}\" [shape=box];", node.name);
+        }
+        for missing in &self.missing {
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [shape=box, style=dashed, color=red, label=\"{} (missing)\"];",
+                missing.requires, missing.requires
+            );
+        }
+        for node in &self.nodes {
+            for req in &node.requires {
+                let style = if self.is_missing(&node.name, req) {
+                    " [style=dashed, color=red]"
+                } else if self.on_cycle(&node.name, req) {
+                    " [color=red, label=\"cycle\"]"
+                } else {
+                    ""
+                };
+                let _ = writeln!(dot, "    \"{}\" -> \"{req}\"{style};", node.name);
+            }
+            for service in &node.services {
+                let _ = writeln!(
+                    dot,
+                    "    \"{service}\" [shape=ellipse];\n    \"{}\" -> \"{service}\" [style=dotted, label=\"enables\"];",
+                    node.name
+                );
+            }
+            if let Some(mount) = &node.mount {
+                let _ = writeln!(
+                    dot,
+                    "    \"{mount}\" [shape=cylinder];\n    \"{}\" -> \"{mount}\" [style=dotted, label=\"mounted by\"];",
+                    node.name
+                );
+                for service in &node.services {
+                    let _ = writeln!(
+                        dot,
+                        "    \"{service}\" -> \"{mount}\" [style=dotted, label=\"BindsTo\"];"
+                    );
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render as an indented text tree, followed by the problems found.
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::new();
+        if self.nodes.is_empty() {
+            text.push_str("No enabled extensions.\n");
+        }
+        for node in &self.nodes {
+            let _ = writeln!(text, "{}", node.name);
+            let mut lines: Vec<String> = node
+                .requires
+                .iter()
+                .map(|req| {
+                    let flag = if self.is_missing(&node.name, req) {
+                        " (missing)"
+                    } else if self.on_cycle(&node.name, req) {
+                        " (cycle)"
+                    } else {
+                        ""
+                    };
+                    format!("requires {req}{flag}")
+                })
+                .collect();
+            lines.extend(node.services.iter().map(|service| match &node.mount {
+                Some(mount) => format!("enables {service} (bound to {mount})"),
+                None => format!("enables {service}"),
+            }));
+            if let Some(mount) = &node.mount {
+                lines.push(format!("mounted by {mount}"));
+            }
+            for (i, line) in lines.iter().enumerate() {
+                let branch = if i + 1 == lines.len() {
+                    "└──"
+                } else {
+                    "├──"
+                };
+                let _ = writeln!(text, "  {branch} {line}");
+            }
+        }
+        if !self.missing.is_empty() {
+            text.push_str("\nMissing dependencies:\n");
+            for missing in &self.missing {
+                let _ = writeln!(
+                    text,
+                    "  {} requires {}, which is not enabled",
+                    missing.extension, missing.requires
+                );
+            }
+        }
+        if !self.cycles.is_empty() {
+            text.push_str("\nDependency cycles:\n");
+            for cycle in &self.cycles {
+                let _ = writeln!(text, "  {} -> {}", cycle.join(" -> "), cycle[0]);
+            }
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    New,
+    OnStack,
+    Done,
+}
+
+/// Depth-first search recording every cycle reachable from `node`.
+fn find_cycles(
+    node: usize,
+    nodes: &[GraphNode],
+    index: &BTreeMap<&str, usize>,
+    state: &mut [Visit],
+    stack: &mut Vec<usize>,
+    cycles: &mut Vec<Vec<String>>,
+) {
+    if state[node] != Visit::New {
+        return;
+    }
+    state[node] = Visit::OnStack;
+    stack.push(node);
+    for req in &nodes[node].requires {
+        let Some(&next) = index.get(req.as_str()) else {
+            continue;
+        };
+        match state[next] {
+            Visit::New => find_cycles(next, nodes, index, state, stack, cycles),
+            Visit::OnStack => {
+                let from = stack.iter().position(|&n| n == next).unwrap_or(0);
+                let mut cycle: Vec<String> = stack[from..]
+                    .iter()
+                    .map(|&n| nodes[n].name.clone())
+                    .collect();
+                let first = (0..cycle.len())
+                    .min_by(|&a, &b| cycle[a].cmp(&cycle[b]))
+                    .unwrap_or(0);
+                cycle.rotate_left(first);
+                cycles.push(cycle);
+            }
+            Visit::Done => {}
+        }
+    }
+    stack.pop();
+    state[node] = Visit::Done;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, requires: &[&str]) -> GraphNode {
+        GraphNode {
+            name: name.to_string(),
+            requires: requires.iter().map(|r| r.to_string()).collect(),
+            services: Vec::new(),
+            mount: None,
+        }
+    }
+
+    #[test]
+    fn test_missing_and_cycles() {
+        let graph = DependencyGraph::new(vec![
+            node("web", &["runtime", "tls"]),
+            node("runtime", &["base"]),
+            node("base", &[]),
+            node("b", &["c"]),
+            node("c", &["a"]),
+            node("a", &["b"]),
+            node("self", &["self"]),
+        ]);
+        assert_eq!(
+            graph.missing,
+            vec![MissingDependency {
+                extension: "web".to_string(),
+                requires: "tls".to_string(),
+            }]
+        );
+        assert_eq!(graph.cycles, vec![vec!["a", "b", "c"], vec!["self"]]);
+        assert!(graph.has_problems());
+        assert!(graph.on_cycle("c", "a"));
+        assert!(!graph.on_cycle("web", "runtime"));
+
+        let clean = DependencyGraph::new(vec![node("runtime", &["base"]), node("base", &[])]);
+        assert!(!clean.has_problems());
+    }
+
+    #[test]
+    fn test_render() {
+        let mut web = node("web", &["base", "tls"]);
+        web.services = vec!["nginx.service".to_string()];
+        web.mount = Some("run-avocado-hitl-web.mount".to_string());
+        let graph = DependencyGraph::new(vec![web, node("base", &[])]);
+
+        assert_eq!(
+            graph.to_ascii(),
+            "base\n\
+             web\n  \
+             ├── requires base\n  \
+             ├── requires tls (missing)\n  \
+             ├── enables nginx.service (bound to run-avocado-hitl-web.mount)\n  \
+             └── mounted by run-avocado-hitl-web.mount\n\
+             \n\
+             Missing dependencies:\n  \
+             web requires tls, which is not enabled\n"
+        );
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph extensions {"));
+        assert!(dot.contains("\"web\" -> \"base\";"));
+        assert!(dot.contains("\"web\" -> \"tls\" [style=dashed, color=red];"));
+        assert!(dot.contains("\"nginx.service\" -> \"run-avocado-hitl-web.mount\""));
+    }
+}
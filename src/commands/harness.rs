@@ -0,0 +1,479 @@
+//! `avocadoctl ext test <path>` — an isolated test run for extension authors.
+//!
+//! The extension is inspected and merged against a throwaway root (a temp
+//! directory with a copy of the host os-release) inside a private mount
+//! namespace, so nothing under the real `/run/extensions`, `/usr` or `/etc`
+//! is touched. AVOCADO_ON_MERGE hooks are never executed — each hook is only
+//! resolved against the extension and the host PATH — while the declared
+//! `AVOCADO_HEALTH_CHECK` commands run with the merged tree first on PATH.
+
+use crate::commands::ext::SystemdError;
+use crate::commands::image_adaptor;
+use crate::diagnostics::Diagnose;
+use crate::error::IoContext;
+use crate::extension_release::{parse_avocado_modprobe, parse_avocado_on_merge_commands};
+use crate::os_release::OsRelease;
+use crate::output::OutputManager;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+/// Outcome of a single harness check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    Skip,
+}
+
+/// One line of the `ext test` report.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub check: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(check: &str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Parse all AVOCADO_HEALTH_CHECK commands from release file content.
+pub(crate) fn parse_avocado_health_checks(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("AVOCADO_HEALTH_CHECK="))
+        .map(|value| value.trim_matches('"').trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// A release file found inside an extension tree.
+pub(crate) struct ReleaseFile {
+    /// "sysext" or "confext"
+    pub(crate) kind: &'static str,
+    /// Name after the `extension-release.` prefix
+    pub(crate) name: String,
+    pub(crate) relative_path: String,
+    pub(crate) content: String,
+}
+
+impl ReleaseFile {
+    /// The file's `KEY=value` fields.
+    pub(crate) fn fields(&self) -> OsRelease {
+        OsRelease::parse(&self.content)
+    }
+}
+
+/// Collect the sysext and confext release files of an extension tree.
+pub(crate) fn find_release_files(root: &Path) -> Vec<ReleaseFile> {
+    let mut found = Vec::new();
+    for (kind, dir) in [
+        ("sysext", "usr/lib/extension-release.d"),
+        ("confext", "etc/extension-release.d"),
+    ] {
+        let Ok(entries) = fs::read_dir(root.join(dir)) else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+        files.sort();
+        for path in files {
+            let file_name = path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let Some(name) = file_name.strip_prefix("extension-release.") else {
+                continue;
+            };
+            if let Ok(content) = fs::read_to_string(&path) {
+                found.push(ReleaseFile {
+                    kind,
+                    name: name.to_string(),
+                    relative_path: format!("{dir}/{file_name}"),
+                    content,
+                });
+            }
+        }
+    }
+    found
+}
+
+/// Image name systemd will derive from the path: the directory name, or the
+/// file name without `.raw`.
+pub(crate) fn image_name(path: &Path) -> String {
+    let file_name = path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    file_name
+        .strip_suffix(".raw")
+        .map(str::to_string)
+        .unwrap_or(file_name)
+}
+
+/// PATH hooks and health checks of an extension resolve against: the
+/// extension's own bin directories ahead of the host PATH.
+pub(crate) fn hook_search_path(ext_path: &Path) -> String {
+    format!(
+        "{}:{}:{}",
+        ext_path.join("usr/bin").display(),
+        ext_path.join("usr/sbin").display(),
+        std::env::var("PATH").unwrap_or_default()
+    )
+}
+
+/// Whether a hook program resolves on `search_path`.
+pub(crate) fn hook_program_found(search_path: &str, program: &str) -> bool {
+    std::env::split_paths(search_path).any(|dir| dir.join(program).is_file())
+}
+
+/// Build a command that runs inside a private mount namespace, so any mounts
+/// it makes disappear with it. In test mode the mock binary runs directly.
+fn isolated_command(program: &str) -> ProcessCommand {
+    let program = crate::tools::program(program);
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        ProcessCommand::new(program)
+    } else {
+        let mut cmd = ProcessCommand::new("unshare");
+        cmd.args(["--mount", "--propagation", "private", &program]);
+        cmd
+    }
+}
+
+/// Populate the throwaway root: host os-release plus the extension linked
+/// into the root's own /run/extensions or /run/confexts.
+fn prepare_root(root: &Path, name: &str, ext_path: &Path) -> std::io::Result<()> {
+    let os_release = fs::read_to_string(crate::os_release::path()).unwrap_or_default();
+    for dir in ["usr/lib", "etc", "run/extensions", "run/confexts"] {
+        fs::create_dir_all(root.join(dir))?;
+    }
+    fs::write(root.join("usr/lib/os-release"), &os_release)?;
+    fs::write(root.join("etc/os-release"), &os_release)?;
+    std::os::unix::fs::symlink(ext_path, root.join("run/extensions").join(name))?;
+    std::os::unix::fs::symlink(ext_path, root.join("run/confexts").join(name))?;
+    Ok(())
+}
+
+/// Run all checks against the extension tree at `ext_path`.
+fn run_checks(
+    name: &str,
+    ext_path: &Path,
+    root: &Path,
+    output: &OutputManager,
+) -> Vec<CheckResult> {
+    let mut results = Vec::new();
+
+    // ── release files ──
+    let releases = find_release_files(ext_path);
+    if releases.is_empty() {
+        results.push(CheckResult::new(
+            "release-file",
+            CheckStatus::Fail,
+            "no extension-release file in usr/lib/extension-release.d or etc/extension-release.d",
+        ));
+        return results;
+    }
+    for release in &releases {
+        let status = if release.name == name {
+            CheckStatus::Pass
+        } else {
+            CheckStatus::Fail
+        };
+        let detail = if status == CheckStatus::Pass {
+            release.relative_path.clone()
+        } else {
+            format!(
+                "{} does not match image name '{name}'",
+                release.relative_path
+            )
+        };
+        results.push(CheckResult::new("release-file", status, detail));
+    }
+
+    // ── os compatibility ──
+    let host_id = crate::os_release::host().and_then(|host| host.id);
+    for release in &releases {
+        let fields = release.fields();
+        let (status, detail) = match (fields.id.as_deref(), host_id.as_deref()) {
+            (None, _) => (CheckStatus::Fail, "ID= is missing".to_string()),
+            (Some("_any"), _) => (CheckStatus::Pass, "ID=_any".to_string()),
+            (Some(id), Some(host)) if id == host => (CheckStatus::Pass, format!("ID={id}")),
+            (Some(id), Some(host)) => (
+                CheckStatus::Fail,
+                format!("ID={id} does not match host ID={host}"),
+            ),
+            (Some(id), None) => (
+                CheckStatus::Skip,
+                format!("ID={id} (host os-release unavailable)"),
+            ),
+        };
+        results.push(CheckResult::new(
+            &format!("{}-os-id", release.kind),
+            status,
+            detail,
+        ));
+    }
+
+    // ── merge against the throwaway root ──
+    let root_arg = format!("--root={}", root.display());
+    for kind in ["sysext", "confext"] {
+        if !releases.iter().any(|r| r.kind == kind) {
+            continue;
+        }
+        let program = format!("systemd-{kind}");
+        output.info(
+            "Extension Test",
+            &format!("Running {program} {root_arg} merge"),
+        );
+        let result = isolated_command(&program)
+            .args([root_arg.as_str(), "merge"])
+            .output();
+        let check = format!("{kind}-merge");
+        results.push(match result {
+            Ok(out) if out.status.success() => {
+                CheckResult::new(&check, CheckStatus::Pass, "merged in isolated root")
+            }
+            Ok(out) => CheckResult::new(
+                &check,
+                CheckStatus::Fail,
+                String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            ),
+            Err(e) => CheckResult::new(&check, CheckStatus::Fail, format!("{program}: {e}")),
+        });
+    }
+
+    // ── AVOCADO_ON_MERGE hooks (resolved, never executed) ──
+    let search_path = hook_search_path(ext_path);
+    let hooks: Vec<String> = releases
+        .iter()
+        .flat_map(|r| parse_avocado_on_merge_commands(&r.content))
+        .collect();
+    for hook in &hooks {
+        for part in hook.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            let program = part.split_whitespace().next().unwrap_or("");
+            let found = hook_program_found(&search_path, program);
+            results.push(if found {
+                CheckResult::new("on-merge", CheckStatus::Pass, format!("{part} (mocked)"))
+            } else {
+                CheckResult::new(
+                    "on-merge",
+                    CheckStatus::Fail,
+                    format!("{part}: '{program}' not found in extension or PATH"),
+                )
+            });
+        }
+    }
+
+    for module in releases
+        .iter()
+        .flat_map(|r| parse_avocado_modprobe(&r.content))
+    {
+        results.push(CheckResult::new(
+            "modprobe",
+            CheckStatus::Skip,
+            format!("{module} (not loaded in test)"),
+        ));
+    }
+
+    // ── declared health checks ──
+    let health_checks: Vec<String> = releases
+        .iter()
+        .flat_map(|r| parse_avocado_health_checks(&r.content))
+        .collect();
+    if health_checks.is_empty() {
+        results.push(CheckResult::new(
+            "health-check",
+            CheckStatus::Skip,
+            "no AVOCADO_HEALTH_CHECK declared",
+        ));
+    }
+    for check in &health_checks {
+        output.info("Extension Test", &format!("Running health check: {check}"));
+        let result = ProcessCommand::new("sh")
+            .args(["-c", check])
+            .env("PATH", &search_path)
+            .env("AVOCADO_TEST_ROOT", root)
+            .output();
+        results.push(match result {
+            Ok(out) if out.status.success() => {
+                CheckResult::new("health-check", CheckStatus::Pass, check.clone())
+            }
+            Ok(out) => CheckResult::new(
+                "health-check",
+                CheckStatus::Fail,
+                format!(
+                    "{check} exited with {}: {}",
+                    out.status.code().unwrap_or(-1),
+                    String::from_utf8_lossy(&out.stderr).trim()
+                ),
+            ),
+            Err(e) => CheckResult::new("health-check", CheckStatus::Fail, format!("{check}: {e}")),
+        });
+    }
+
+    results
+}
+
+/// Run `f` on the tree of an extension directory or `.raw` image, which is
+/// mounted for the duration of the call. `f` receives the image name, the
+/// tree and a scratch directory that is removed afterwards.
+pub(crate) fn with_extension_tree<T>(
+    path: &Path,
+    scratch_prefix: &str,
+    output: &OutputManager,
+    f: impl FnOnce(&str, &Path, &Path) -> T,
+) -> Result<T, SystemdError> {
+    if !path.exists() {
+        return Err(SystemdError::ConfigurationError {
+            message: format!("Extension path '{}' does not exist", path.display()),
+        });
+    }
+    let path = path.canonicalize().context("resolve", path)?;
+    let name = image_name(&path);
+
+    let work_dir = std::env::temp_dir().join(format!("{scratch_prefix}-{}", std::process::id()));
+    let image_mount = work_dir.join("image");
+    fs::create_dir_all(&work_dir).context("create directory", &work_dir)?;
+
+    let mut mounted = false;
+    let ext_path = if path.is_dir() {
+        path.clone()
+    } else {
+        let mount_point = image_mount.to_string_lossy().to_string();
+        image_adaptor::mount_image_once(&name, &path, &mount_point, output.is_verbose())?;
+        mounted = true;
+        image_mount.clone()
+    };
+
+    let result = f(&name, &ext_path, &work_dir);
+
+    if mounted {
+        if let Err(e) =
+            image_adaptor::unmount_image_once(&image_mount.to_string_lossy(), output.is_verbose())
+        {
+            output.progress(&format!("Warning: failed to unmount extension image: {e}"));
+        }
+    }
+    let _ = fs::remove_dir_all(&work_dir);
+
+    Ok(result)
+}
+
+/// Test an extension directory or `.raw` image in isolation.
+pub fn test_extension(
+    path: &Path,
+    output: &OutputManager,
+) -> Result<Vec<CheckResult>, SystemdError> {
+    with_extension_tree(
+        path,
+        "avocado-ext-test",
+        output,
+        |name, ext_path, work_dir| {
+            let root = work_dir.join("root");
+            match prepare_root(&root, name, ext_path) {
+                Ok(()) => run_checks(name, ext_path, &root, output),
+                Err(e) => vec![CheckResult::new(
+                    "setup",
+                    CheckStatus::Fail,
+                    format!("failed to prepare isolated root: {e}"),
+                )],
+            }
+        },
+    )
+}
+
+/// CLI entry point for `ext test`: prints the report and exits non-zero on failure.
+pub fn run_extension_test(path: &str, output: &OutputManager) {
+    let results = match test_extension(Path::new(path), output) {
+        Ok(results) => results,
+        Err(e) => {
+            output.error_with("Extension Test", &e.to_string(), &e.diagnose());
+            output.exit(1);
+        }
+    };
+    let failed = results
+        .iter()
+        .filter(|r| r.status == CheckStatus::Fail)
+        .count();
+
+    if output.is_json() {
+        let json = serde_json::json!({
+            "path": path,
+            "passed": failed == 0,
+            "checks": results,
+        });
+        output.result_fields(&json);
+    } else {
+        let check_width = results
+            .iter()
+            .map(|r| r.check.len())
+            .max()
+            .unwrap_or(5)
+            .max(5);
+        for r in &results {
+            let status = match r.status {
+                CheckStatus::Pass => "PASS",
+                CheckStatus::Fail => "FAIL",
+                CheckStatus::Skip => "SKIP",
+            };
+            println!("{status:<5} {:<check_width$} {}", r.check, r.detail);
+        }
+        println!();
+        if failed == 0 {
+            output.success("Extension Test", &format!("All checks passed for {path}"));
+        } else {
+            output.error(
+                "Extension Test",
+                &format!("{failed} check(s) failed for {path}"),
+            );
+        }
+    }
+
+    if failed > 0 {
+        output.exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_avocado_health_checks() {
+        let content =
+            "ID=_any\nAVOCADO_HEALTH_CHECK=\"myapp --self-test\"\nAVOCADO_HEALTH_CHECK=true\n";
+        assert_eq!(
+            parse_avocado_health_checks(content),
+            vec!["myapp --self-test".to_string(), "true".to_string()]
+        );
+        assert!(parse_avocado_health_checks("ID=_any\n").is_empty());
+    }
+
+    #[test]
+    fn test_image_name_strips_raw_suffix() {
+        assert_eq!(image_name(Path::new("/x/app-1.0.raw")), "app-1.0");
+        assert_eq!(image_name(Path::new("/x/app")), "app");
+    }
+
+    #[test]
+    fn test_find_release_files_reports_kind_and_name() {
+        let tmp = TempDir::new().unwrap();
+        let sysext = tmp.path().join("usr/lib/extension-release.d");
+        fs::create_dir_all(&sysext).unwrap();
+        fs::write(sysext.join("extension-release.app"), "ID=_any\n").unwrap();
+
+        let found = find_release_files(tmp.path());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, "sysext");
+        assert_eq!(found[0].name, "app");
+    }
+}
@@ -1,9 +1,23 @@
 use crate::commands::ext;
+use crate::config::{Config, HitlDropinSettings, HitlSettings};
+use crate::diagnostics::Diagnose;
+pub use crate::error::HitlError;
+use crate::error::IoContext;
+use crate::extension_release::ServiceDependency;
+use crate::hitl_handshake::{self, ServerInfo};
+use crate::hitl_health::{self, HitlMount, MountType, NfsTransport};
+use crate::hitl_overrides::{self, HitlOverrides};
+use crate::messages;
 use crate::output::OutputManager;
 use clap::{Arg, ArgMatches, Command};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 use std::process::{Command as ProcessCommand, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Create the hitl subcommand definition
 pub fn create_command() -> Command {
@@ -17,25 +31,52 @@ pub fn create_command() -> Command {
                         .short('s')
                         .long("server-ip")
                         .value_name("IP")
-                        .help("Server IP address")
-                        .required(true),
+                        .help("Server IP address for extensions given without @SERVER"),
                 )
                 .arg(
                     Arg::new("server-port")
                         .short('p')
                         .long("server-port")
                         .value_name("PORT")
-                        .help("Server port number")
-                        .default_value("12049"),
+                        .help("Server port number for extensions given without a port")
+                        .default_value(DEFAULT_NFS_PORT),
                 )
                 .arg(
                     Arg::new("extension")
                         .short('e')
                         .long("extension")
-                        .value_name("NAME")
-                        .help("Extension name to mount (can be specified multiple times)")
+                        .value_name("NAME[@SERVER[:PORT]]")
+                        .help("Extension to mount, optionally from its own server (can be specified multiple times)")
                         .action(clap::ArgAction::Append)
                         .required(true),
+                )
+                .arg(
+                    Arg::new("type")
+                        .long("type")
+                        .value_name("TYPE")
+                        .help("Merge as sysext, confext, or detect from the extension tree")
+                        .value_parser(["auto", "sysext", "confext"])
+                        .default_value("auto"),
+                )
+                .arg(
+                    Arg::new("keep-going")
+                        .long("keep-going")
+                        .help("Mount every extension even when some fail, then merge the ones that mounted (default)")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("fail-fast"),
+                )
+                .arg(
+                    Arg::new("fail-fast")
+                        .long("fail-fast")
+                        .help("Start no further mounts after the first failure and merge nothing")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("control-port")
+                        .long("control-port")
+                        .value_name("PORT")
+                        .help("Check the server's version and exported extensions on its HITL control port before mounting")
+                        .value_parser(clap::value_parser!(u16).range(1..)),
                 ),
         )
         .subcommand(
@@ -44,21 +85,128 @@ pub fn create_command() -> Command {
                     .short('e')
                     .long("extension")
                     .value_name("NAME")
-                    .help("Extension name to unmount (can be specified multiple times)")
+                    .help("Extension name to unmount (can be specified multiple times); a trailing @SERVER[:PORT] is ignored")
                     .action(clap::ArgAction::Append)
                     .required(true),
             ),
         )
+        .subcommand(
+            Command::new("quiesce")
+                .about("Hold refreshes while files are synced into HITL extensions")
+                .arg(
+                    Arg::new("extension")
+                        .value_name("NAME")
+                        .help("Extension being synced")
+                        .num_args(1..)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("resume")
+                .about("Release HITL extensions held by quiesce")
+                .arg(
+                    Arg::new("extension")
+                        .value_name("NAME")
+                        .help("Extension whose sync is done")
+                        .num_args(1..)
+                        .required(true),
+                ),
+        )
+}
+
+/// NFS port used when neither `-p` nor the extension spec gives one.
+pub const DEFAULT_NFS_PORT: &str = "12049";
+
+/// An extension to mount and the server exporting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountSpec {
+    pub extension: String,
+    pub server: String,
+    pub port: String,
+}
+
+/// Parse an `-e NAME[@SERVER[:PORT]]` value. Extensions without a server use
+/// `default_server` (`-s`), extensions without a port `default_port` (`-p`).
+/// IPv6 servers are written in brackets: `app@[fd00::1]:2049`.
+pub fn parse_mount_spec(
+    spec: &str,
+    default_server: Option<&str>,
+    default_port: &str,
+) -> Result<MountSpec, String> {
+    let (extension, remote) = match spec.split_once('@') {
+        Some((extension, remote)) => (extension, Some(remote)),
+        None => (spec, None),
+    };
+    if extension.is_empty() || extension.contains('/') {
+        return Err(format!("Invalid extension name in '{spec}'"));
+    }
+
+    let (server, port) = match remote {
+        None => match default_server {
+            Some(server) => (server, None),
+            None => {
+                return Err(format!(
+                    "No server for extension '{extension}': use -s SERVER or -e {extension}@SERVER"
+                ))
+            }
+        },
+        Some(remote) if remote.starts_with('[') => {
+            let Some(end) = remote.find(']') else {
+                return Err(format!("Unterminated IPv6 address in '{spec}'"));
+            };
+            let port = match &remote[end + 1..] {
+                "" => None,
+                rest => match rest.strip_prefix(':') {
+                    Some(port) => Some(port),
+                    None => return Err(format!("Invalid server in '{spec}'")),
+                },
+            };
+            (&remote[..=end], port)
+        }
+        Some(remote) => match remote.split_once(':') {
+            Some((_, port)) if port.contains(':') => {
+                return Err(format!(
+                "IPv6 server in '{spec}' must be written in brackets, e.g. {extension}@[fd00::1]"
+            ))
+            }
+            Some((server, port)) => (server, Some(port)),
+            None => (remote, None),
+        },
+    };
+    if server.is_empty() || server == "[]" {
+        return Err(format!("Empty server in '{spec}'"));
+    }
+    let port = port.unwrap_or(default_port);
+    if !port.parse::<u16>().is_ok_and(|p| p != 0) {
+        return Err(format!("Invalid port '{port}' in '{spec}'"));
+    }
+
+    Ok(MountSpec {
+        extension: extension.to_string(),
+        server: server.to_string(),
+        port: port.to_string(),
+    })
+}
+
+/// Extension name of an `-e` value, dropping a `@SERVER[:PORT]` suffix.
+pub fn spec_extension_name(spec: &str) -> &str {
+    spec.split_once('@').map_or(spec, |(name, _)| name)
 }
 
 /// Handle hitl command and its subcommands
-pub fn handle_command(matches: &ArgMatches, output: &OutputManager) {
+pub fn handle_command(matches: &ArgMatches, config: &Config, output: &OutputManager) {
     match matches.subcommand() {
         Some(("mount", mount_matches)) => {
-            mount_extensions(mount_matches, output);
+            mount_extensions(mount_matches, config, output);
         }
         Some(("unmount", unmount_matches)) => {
-            unmount_extensions(unmount_matches, output);
+            unmount_extensions(unmount_matches, config, output);
+        }
+        Some(("quiesce", quiesce_matches)) => {
+            quiesce_extensions(quiesce_matches, config, output);
+        }
+        Some(("resume", resume_matches)) => {
+            resume_extensions(resume_matches, config, output);
         }
         _ => {
             println!("Use 'avocadoctl hitl --help' for available HITL commands");
@@ -66,25 +214,198 @@ pub fn handle_command(matches: &ArgMatches, output: &OutputManager) {
     }
 }
 
-/// Mount NFS extensions from a remote server
-fn mount_extensions(matches: &ArgMatches, output: &OutputManager) {
-    let server_ip = matches
-        .get_one::<String>("server-ip")
-        .expect("server-ip is required");
-    let server_port = matches
-        .get_one::<String>("server-port")
-        .expect("server-port has default value");
-    let extensions: Vec<&String> = matches
-        .get_many::<String>("extension")
-        .expect("at least one extension is required")
-        .collect();
+/// Hold refreshes for the named extensions until `hitl resume`
+fn quiesce_extensions(matches: &ArgMatches, config: &Config, output: &OutputManager) {
+    for extension in &config.resolve_extension_aliases(
+        matches
+            .get_many::<String>("extension")
+            .into_iter()
+            .flatten(),
+    ) {
+        match crate::hitl_sync::quiesce(extension) {
+            Ok(true) => output.log_info(&format!("Holding refreshes for '{extension}'")),
+            Ok(false) => output.log_info(&format!("'{extension}' is already quiesced")),
+            Err(e) => {
+                output.error_with(
+                    "HITL Quiesce",
+                    &format!("Failed to quiesce '{extension}': {e}"),
+                    &e.diagnose(),
+                );
+                output.exit(1);
+            }
+        }
+    }
+    output.success_msg("HITL Quiesce", messages::HITL_QUIESCED, &[]);
+}
 
-    output.info(
-        "HITL Mount",
-        &format!("Mounting extensions from {server_ip}:{server_port}"),
-    );
+/// Release extensions held by `hitl quiesce`
+fn resume_extensions(matches: &ArgMatches, config: &Config, output: &OutputManager) {
+    for extension in &config.resolve_extension_aliases(
+        matches
+            .get_many::<String>("extension")
+            .into_iter()
+            .flatten(),
+    ) {
+        match crate::hitl_sync::resume(extension) {
+            Ok(true) => output.log_info(&format!("Released '{extension}'")),
+            Ok(false) => output.log_info(&format!("'{extension}' was not quiesced")),
+            Err(e) => {
+                output.error_with(
+                    "HITL Resume",
+                    &format!("Failed to resume '{extension}': {e}"),
+                    &e.diagnose(),
+                );
+                output.exit(1);
+            }
+        }
+    }
+    output.success_msg("HITL Resume", messages::HITL_RESUMED, &[]);
+}
 
-    let extensions_base_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+/// What `hitl mount` does when an extension fails to mount.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Mount the others, then merge the extensions that mounted
+    #[default]
+    KeepGoing,
+    /// Start no further mounts and merge nothing
+    FailFast,
+}
+
+impl FailurePolicy {
+    pub fn from_fail_fast(fail_fast: bool) -> Self {
+        if fail_fast {
+            Self::FailFast
+        } else {
+            Self::KeepGoing
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountStatus {
+    Mounted,
+    Failed,
+    /// Not started: an earlier mount failed with `--fail-fast`
+    Skipped,
+}
+
+impl MountStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mounted => "mounted",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "mounted" => Some(Self::Mounted),
+            "failed" => Some(Self::Failed),
+            "skipped" => Some(Self::Skipped),
+            _ => None,
+        }
+    }
+}
+
+/// How mounting one extension went, a row of the `hitl mount` summary.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct MountReport {
+    pub extension: String,
+    /// `server:port` the share was requested from
+    pub server: String,
+    pub status: MountStatus,
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// One extension mounted by [`mount_specs`].
+pub struct MountOutcome {
+    pub report: MountReport,
+    /// Why it failed
+    pub error: Option<HitlError>,
+    /// Services its `hitl.toml` restarts once it is merged
+    pub restart: Vec<String>,
+}
+
+/// `config` with the HITL control port set to `port`, when given.
+pub(crate) fn with_control_port(config: &Config, port: Option<u16>) -> Config {
+    let mut config = config.clone();
+    if port.is_some() {
+        config.avocado.hitl.control_port = port;
+    }
+    config
+}
+
+/// Run the handshake with each server of `specs` on `[avocado.hitl]
+/// control_port` and check that it exports the extension asked for. Returns
+/// why each spec must not be mounted, in the order of `specs`; nothing is
+/// checked without a control port.
+fn preflight(
+    specs: &[MountSpec],
+    settings: &HitlSettings,
+    output: &OutputManager,
+) -> Vec<Option<HitlError>> {
+    let Some(port) = settings.control_port else {
+        return specs.iter().map(|_| None).collect();
+    };
+    let timeout = Duration::from_millis(settings.probe_timeout_ms.max(1));
+    let mut servers: HashMap<&str, Result<ServerInfo, HitlError>> = HashMap::new();
+    specs
+        .iter()
+        .map(|spec| {
+            let server = servers.entry(spec.server.as_str()).or_insert_with(|| {
+                let result = hitl_handshake::handshake(&spec.server, port, timeout);
+                if let Ok(info) = &result {
+                    output.log_info(&format!(
+                        "HITL server {}:{port}: {}, protocol {}, capabilities: {}",
+                        spec.server,
+                        info.tool(),
+                        info.protocol.unwrap_or_default(),
+                        if info.capabilities.is_empty() {
+                            "none".to_string()
+                        } else {
+                            info.capabilities.join(", ")
+                        }
+                    ));
+                }
+                result
+            });
+            match server {
+                Ok(info) => info.check_extension(&spec.extension).err().map(|reason| {
+                    HitlError::NotExported {
+                        extension: spec.extension.clone(),
+                        server: spec.server.clone(),
+                        reason,
+                    }
+                }),
+                // Every extension of the server fails the same way
+                Err(HitlError::ServerOutdated {
+                    server,
+                    tool,
+                    required,
+                }) => Some(HitlError::ServerOutdated {
+                    server: server.clone(),
+                    tool: tool.clone(),
+                    required: *required,
+                }),
+                Err(e) => Some(HitlError::Handshake {
+                    server: format!("{}:{port}", spec.server),
+                    reason: match e {
+                        HitlError::Handshake { reason, .. } => reason.clone(),
+                        e => e.to_string(),
+                    },
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Directory the HITL shares are mounted below, respecting AVOCADO_TEST_MODE.
+pub(crate) fn hitl_base_dir() -> String {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
         // Use AVOCADO_TEST_TMPDIR if set (to avoid affecting TempDir::new()),
         // otherwise fall back to TMPDIR, then /tmp
         let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
@@ -92,33 +413,143 @@ fn mount_extensions(matches: &ArgMatches, output: &OutputManager) {
             .unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/avocado/hitl")
     } else {
-        "/run/avocado/hitl".to_string()
-    };
-    let mut success = true;
+        crate::user_mode::system_path("/run/avocado/hitl")
+    }
+}
 
-    for extension in &extensions {
-        output.step("HITL Mount", &format!("Setting up extension: {extension}"));
+/// Mount `specs`, `[avocado.hitl] parallel_mounts` at a time, reporting
+/// each extension as it finishes. The outcomes are in the order of `specs`.
+pub fn mount_specs(
+    specs: &[MountSpec],
+    mount_type: MountType,
+    config: &Config,
+    policy: FailurePolicy,
+    output: &OutputManager,
+) -> Vec<MountOutcome> {
+    crate::extension_release::invalidate();
+    let base_dir = hitl_base_dir();
+    let workers = config.hitl().parallel_mounts.clamp(1, specs.len().max(1));
+    let next = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    // One slot per spec, filled in by the worker that mounted it
+    type Slot = Option<(MountOutcome, Option<HitlMount>)>;
+    let outcomes: Mutex<Vec<Slot>> = Mutex::new(specs.iter().map(|_| None).collect());
+    let rejected = Mutex::new(preflight(specs, config.hitl(), output));
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(spec) = specs.get(index) else {
+                    break;
+                };
+                let started = Instant::now();
+                let rejection = rejected.lock().unwrap_or_else(|e| e.into_inner())[index].take();
+                let result = match rejection {
+                    Some(e) => Err(e),
+                    None => mount_share(spec, &base_dir, mount_type, config, output),
+                };
+                let elapsed = started.elapsed();
+                if result.is_err() && policy == FailurePolicy::FailFast {
+                    stop.store(true, Ordering::SeqCst);
+                }
 
-        // Create extension directory
-        let extension_dir = format!("{extensions_base_dir}/{extension}");
-        if let Err(e) = create_extension_directory(&extension_dir, output) {
-            output.error(
-                "HITL Mount",
-                &format!("Failed to create directory {extension_dir}: {e}"),
-            );
-            success = false;
-            continue;
+                let done = finished.fetch_add(1, Ordering::SeqCst) + 1;
+                let progress = format!("[{done}/{}] {}", specs.len(), spec.extension);
+                let outcome = match result {
+                    Ok(mount) => {
+                        output.log_info(&format!(
+                            "{progress}: mounted in {:.1}s",
+                            elapsed.as_secs_f64()
+                        ));
+                        let outcome = MountOutcome {
+                            report: report(spec, MountStatus::Mounted, None, elapsed),
+                            error: None,
+                            restart: mount.overrides.restart_units(),
+                        };
+                        (outcome, Some(mount))
+                    }
+                    Err(e) => {
+                        output.log_info(&format!(
+                            "{progress}: failed after {:.1}s",
+                            elapsed.as_secs_f64()
+                        ));
+                        let outcome = MountOutcome {
+                            report: report(spec, MountStatus::Failed, Some(e.to_string()), elapsed),
+                            error: Some(e),
+                            restart: Vec::new(),
+                        };
+                        (outcome, None)
+                    }
+                };
+                outcomes.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(outcome);
+            });
         }
+    });
+
+    // Recorded once all are done so the registry keeps the command-line order
+    outcomes
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .zip(specs)
+        .map(|(outcome, spec)| match outcome {
+            Some((outcome, mount)) => {
+                if let Some(mount) = mount {
+                    hitl_health::record_mount(mount);
+                }
+                outcome
+            }
+            None => MountOutcome {
+                report: report(spec, MountStatus::Skipped, None, Duration::ZERO),
+                error: None,
+                restart: Vec::new(),
+            },
+        })
+        .collect()
+}
 
-        // Mount NFS share
-        if let Err(e) =
-            mount_nfs_extension(server_ip, server_port, extension, &extension_dir, output)
-        {
-            output.error(
-                "HITL Mount",
-                &format!("Failed to mount extension {extension}: {e}"),
-            );
+fn report(
+    spec: &MountSpec,
+    status: MountStatus,
+    error: Option<String>,
+    elapsed: Duration,
+) -> MountReport {
+    MountReport {
+        extension: spec.extension.clone(),
+        server: format!("{}:{}", spec.server, spec.port),
+        status,
+        error,
+        elapsed_ms: elapsed.as_millis() as u64,
+    }
+}
 
+/// Mount one extension: create its directory, mount the share, apply its
+/// `hitl.toml` and write the drop-ins of the services it enables. Returns
+/// the mount to record in the server registry.
+fn mount_share(
+    spec: &MountSpec,
+    base_dir: &str,
+    mount_type: MountType,
+    config: &Config,
+    output: &OutputManager,
+) -> Result<HitlMount, HitlError> {
+    let extension = &spec.extension;
+    output.step("HITL Mount", &format!("Setting up extension: {extension}"));
+
+    // Create extension directory
+    let extension_dir = format!("{base_dir}/{extension}");
+    create_extension_directory(&extension_dir, output)
+        .context("create directory", &extension_dir)?;
+
+    // Mount NFS share, falling back to other ports and versions as configured
+    let transport = match mount_nfs_extension(spec, &extension_dir, config.hitl(), output) {
+        Ok(transport) => transport,
+        Err(e) => {
             // Clean up the directory that was created since the mount failed
             if let Err(cleanup_err) = cleanup_extension_directory(&extension_dir, output) {
                 output.error(
@@ -126,58 +557,213 @@ fn mount_extensions(matches: &ArgMatches, output: &OutputManager) {
                     &format!("Failed to cleanup directory for {extension}: {cleanup_err}"),
                 );
             }
-
-            success = false;
-            continue;
+            return Err(e);
         }
+    };
 
-        // Scan for enabled services and create drop-ins
-        let enabled_services =
-            ext::scan_extension_for_enable_services(Path::new(&extension_dir), extension);
-        if !enabled_services.is_empty() {
-            output.info(
+    // Apply the extension's own hitl.toml, if it has one
+    let overrides = hitl_overrides::load_or_warn(extension, &extension_dir, output);
+    if let Err(e) = remount_with_options(
+        spec,
+        &transport,
+        &extension_dir,
+        &overrides.mount_options,
+        output,
+    ) {
+        // The remount unmounts first; when that failed the share is still
+        // mounted, and its directory holds the files on the server
+        if is_mount_point(&extension_dir) {
+            output.error(
                 "HITL Mount",
                 &format!(
-                    "Found {} enabled service(s) in extension {}: {}",
-                    enabled_services.len(),
-                    extension,
-                    enabled_services.join(", ")
+                    "Failed to apply the hitl.toml mount options of {extension}; leaving the share mounted at {extension_dir}"
                 ),
             );
-            if let Err(e) =
-                create_service_dropins(extension, &extension_dir, &enabled_services, output)
-            {
-                output.error(
-                    "HITL Mount",
-                    &format!("Failed to create service drop-ins for {extension}: {e}"),
-                );
-                // Continue even if drop-in creation fails - the mount still succeeded
+        } else if let Err(cleanup_err) = cleanup_extension_directory(&extension_dir, output) {
+            output.error(
+                "HITL Mount",
+                &format!("Failed to cleanup directory for {extension}: {cleanup_err}"),
+            );
+        }
+        return Err(e);
+    }
+
+    // Scan for enabled services and create drop-ins
+    let enabled_services =
+        ext::scan_extension_for_enable_services(Path::new(&extension_dir), extension);
+    if !enabled_services.is_empty() {
+        output.info(
+            "HITL Mount",
+            &format!(
+                "Found {} enabled service(s) in extension {}: {}",
+                enabled_services.len(),
+                extension,
+                enabled_services.join(", ")
+            ),
+        );
+        let dependencies =
+            ext::scan_extension_for_service_dependencies(Path::new(&extension_dir), extension);
+        if let Err(e) = create_service_dropins(
+            extension,
+            &extension_dir,
+            &enabled_services,
+            &dependencies,
+            &config.hitl().dropin,
+            &overrides,
+            output,
+        ) {
+            output.error_with(
+                "HITL Mount",
+                &format!("Failed to create service drop-ins for {extension}: {e}"),
+                &e.diagnose(),
+            );
+            // Continue even if drop-in creation fails - the mount still succeeded
+        }
+    }
+
+    output.progress(&format!("Successfully mounted extension: {extension}"));
+    Ok(HitlMount {
+        extension: extension.to_string(),
+        server: spec.server.clone(),
+        port: transport.port,
+        services: enabled_services,
+        mount_type,
+        nfs_version: Some(transport.version),
+        overrides,
+    })
+}
+
+/// Print the summary table of a `hitl mount` and record it as `mounts` in
+/// the JSON result.
+pub fn print_mount_summary(reports: &[MountReport], output: &OutputManager) {
+    output.result("mounts", &reports);
+    let width = |header: &str, column: fn(&MountReport) -> &str| {
+        reports
+            .iter()
+            .map(|r| column(r).len())
+            .chain([header.len()])
+            .max()
+            .unwrap_or_default()
+    };
+    let name_width = width("EXTENSION", |r| &r.extension);
+    let server_width = width("SERVER", |r| &r.server);
+
+    output.status(&format!(
+        "{:<name_width$}  {:<server_width$}  {:<8}  TIME",
+        "EXTENSION", "SERVER", "RESULT"
+    ));
+    for r in reports {
+        let time = match r.status {
+            MountStatus::Skipped => "-".to_string(),
+            _ => format!("{:.1}s", r.elapsed_ms as f64 / 1000.0),
+        };
+        output.status(&format!(
+            "{:<name_width$}  {:<server_width$}  {:<8}  {time}",
+            r.extension,
+            r.server,
+            r.status.as_str()
+        ));
+    }
+}
+
+/// Whether the extensions that mounted are merged: always with
+/// `--keep-going`, only when all mounted with `--fail-fast`.
+pub fn merge_after_mount(reports: &[MountReport], policy: FailurePolicy) -> bool {
+    let mounted = reports.iter().any(|r| r.status == MountStatus::Mounted);
+    let all_mounted = reports.iter().all(|r| r.status == MountStatus::Mounted);
+    mounted && (all_mounted || policy == FailurePolicy::KeepGoing)
+}
+
+/// Mount NFS extensions from one or more remote servers
+fn mount_extensions(matches: &ArgMatches, config: &Config, output: &OutputManager) {
+    let server_ip = matches.get_one::<String>("server-ip").map(String::as_str);
+    let server_port = matches
+        .get_one::<String>("server-port")
+        .expect("server-port has default value");
+    let mut specs = Vec::new();
+    for spec in &config.resolve_extension_aliases(
+        matches
+            .get_many::<String>("extension")
+            .expect("at least one extension is required"),
+    ) {
+        match parse_mount_spec(spec, server_ip, server_port) {
+            Ok(spec) => specs.push(spec),
+            Err(e) => {
+                output.error("HITL Mount", &e);
+                output.exit(1);
             }
         }
+    }
+    let mount_type = matches
+        .get_one::<String>("type")
+        .and_then(|t| MountType::parse(t))
+        .unwrap_or_default();
+    let policy = FailurePolicy::from_fail_fast(matches.get_flag("fail-fast"));
+    let config = &with_control_port(config, matches.get_one::<u16>("control-port").copied());
+
+    let mut servers: Vec<String> = Vec::new();
+    for spec in &specs {
+        let server = format!("{}:{}", spec.server, spec.port);
+        if !servers.contains(&server) {
+            output.info("HITL Mount", &format!("Mounting extensions from {server}"));
+            servers.push(server);
+        }
+    }
 
-        output.progress(&format!("Successfully mounted extension: {extension}"));
+    let span = crate::telemetry::operation("hitl.mount", output);
+    let outcomes = mount_specs(&specs, mount_type, config, policy, output);
+    let reports: Vec<MountReport> = outcomes.iter().map(|o| o.report.clone()).collect();
+    print_mount_summary(&reports, output);
+    for outcome in &outcomes {
+        if let Some(e) = &outcome.error {
+            output.error_with(
+                "HITL Mount",
+                &format!(
+                    "Failed to mount extension {}: {e}",
+                    outcome.report.extension
+                ),
+                &e.diagnose(),
+            );
+        }
     }
+    let failed = reports
+        .iter()
+        .filter(|r| r.status != MountStatus::Mounted)
+        .count();
+    if failed > 0 {
+        span.set_error("Some extensions failed to mount");
+    }
+    drop(span);
 
-    if success {
-        // Reload systemd to apply any drop-in changes
+    if merge_after_mount(&reports, policy) {
+        output.info(
+            "HITL Mount",
+            "Refreshing extensions to apply mounted changes",
+        );
+        // The refresh reloads systemd for the new drop-ins along with the
+        // merged units; this reloads only when it did not
+        ext::refresh_extensions(&Config::default(), output);
         if let Err(e) = systemd_daemon_reload(output) {
-            output.error(
+            output.error_with(
                 "HITL Mount",
                 &format!("Failed to reload systemd daemon: {e}"),
+                &e.diagnose(),
             );
             // Continue even if daemon-reload fails
         }
-
-        output.success("HITL Mount", "All extensions mounted successfully");
-        output.info(
+        let restarts: Vec<String> = outcomes.into_iter().flat_map(|o| o.restart).collect();
+        restart_override_services(&restarts, "HITL Mount", output);
+        // Reported after the refresh so it is the outcome of the command
+        if failed == 0 {
+            output.success_msg("HITL Mount", messages::HITL_MOUNTED, &[]);
+        }
+    }
+    if failed > 0 {
+        output.error(
             "HITL Mount",
-            "Refreshing extensions to apply mounted changes",
+            &format!("{failed} of {} extension(s) failed to mount", reports.len()),
         );
-        let config = crate::config::Config::default();
-        ext::refresh_extensions(&config, output);
-    } else {
-        output.error("HITL Mount", "Some extensions failed to mount");
-        std::process::exit(1);
+        output.exit(1);
     }
 }
 
@@ -195,18 +781,176 @@ fn create_extension_directory(
     Ok(())
 }
 
-/// Mount NFS extension using systemd-mount for proper dependency tracking
-/// This ensures the mount is properly tracked by systemd and will be unmounted
-/// in the correct order during shutdown (before network teardown)
-fn mount_nfs_extension(
-    server_ip: &str,
-    server_port: &str,
-    extension: &str,
+/// NFS version used when `[avocado.hitl] nfs_versions` is empty.
+const DEFAULT_NFS_VERSION: &str = "4";
+
+/// Port/version combinations to try for `spec`, most promising first: the
+/// combination remembered for the server, then the requested port and each
+/// fallback port with every configured version.
+fn transport_candidates(
+    spec: &MountSpec,
+    settings: &HitlSettings,
+    remembered: Option<NfsTransport>,
+) -> Vec<NfsTransport> {
+    let versions: Vec<String> = if settings.nfs_versions.is_empty() {
+        vec![DEFAULT_NFS_VERSION.to_string()]
+    } else {
+        settings.nfs_versions.clone()
+    };
+    let mut ports = vec![spec.port.clone()];
+    for port in &settings.fallback_ports {
+        let port = port.to_string();
+        if !ports.contains(&port) {
+            ports.push(port);
+        }
+    }
+
+    let mut candidates: Vec<NfsTransport> = remembered.into_iter().collect();
+    for port in &ports {
+        for version in &versions {
+            let candidate = NfsTransport {
+                port: port.clone(),
+                version: version.clone(),
+            };
+            if !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+    }
+    candidates
+}
+
+/// Mount an NFS extension, trying the configured fallback ports and NFS
+/// versions and retrying as configured. Returns the combination that worked.
+///
+/// Without fallbacks or retries this is a single non-blocking systemd-mount
+/// with NFSv4 on the requested port. Otherwise ports that refuse TCP
+/// connections are skipped, each mount waits for its result so a rejected
+/// version can be told apart, and the working combination is remembered for
+/// the server until reboot.
+pub fn mount_nfs_extension(
+    spec: &MountSpec,
     mount_point: &str,
+    settings: &HitlSettings,
+    output: &OutputManager,
+) -> Result<NfsTransport, HitlError> {
+    let span = crate::telemetry::span("hitl.nfs_mount");
+    span.set_attribute("avocado.extension", spec.extension.as_str());
+    span.set_attribute("avocado.hitl.server", spec.server.as_str());
+    let transport = span.record(negotiate_nfs_mount(spec, mount_point, settings, output))?;
+    span.set_attribute("avocado.hitl.port", transport.port.as_str());
+    span.set_attribute("avocado.hitl.nfs_version", transport.version.as_str());
+    Ok(transport)
+}
+
+/// The mount attempts behind [`mount_nfs_extension`].
+fn negotiate_nfs_mount(
+    spec: &MountSpec,
+    mount_point: &str,
+    settings: &HitlSettings,
+    output: &OutputManager,
+) -> Result<NfsTransport, HitlError> {
+    let negotiate = !settings.fallback_ports.is_empty()
+        || settings.nfs_versions.len() > 1
+        || settings.mount_retries > 0;
+    let remembered = negotiate
+        .then(|| hitl_health::remembered_transport(&spec.server))
+        .flatten();
+    let candidates = transport_candidates(spec, settings, remembered);
+    let probe_ports = !settings.fallback_ports.is_empty();
+    let timeout = Duration::from_millis(settings.probe_timeout_ms.max(1));
+
+    let mut last_error = None;
+    for attempt in 0..=settings.mount_retries {
+        if attempt > 0 {
+            output.progress(&format!(
+                "Retrying mount of {} ({attempt}/{})",
+                spec.extension, settings.mount_retries
+            ));
+            thread::sleep(Duration::from_millis(settings.retry_delay_ms));
+        }
+        let mut reachable: HashMap<String, bool> = HashMap::new();
+        for candidate in &candidates {
+            if probe_ports
+                && !*reachable
+                    .entry(candidate.port.clone())
+                    .or_insert_with(|| hitl_health::probe(&spec.server, &candidate.port, timeout))
+            {
+                output.progress(&format!(
+                    "{}:{} does not accept connections",
+                    spec.server, candidate.port
+                ));
+                continue;
+            }
+            match run_systemd_mount(spec, candidate, mount_point, negotiate, &[], output) {
+                Ok(()) => {
+                    if negotiate {
+                        hitl_health::remember_transport(&spec.server, candidate);
+                        output.log_info(&format!(
+                            "Mounted {} from {}:{} using NFSv{}",
+                            spec.extension, spec.server, candidate.port, candidate.version
+                        ));
+                    }
+                    return Ok(candidate.clone());
+                }
+                Err(e) => {
+                    output.progress(&format!(
+                        "NFSv{} on port {} failed: {e}",
+                        candidate.version, candidate.port
+                    ));
+                    last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| HitlError::Mount {
+        extension: spec.extension.clone(),
+        mount_point: mount_point.to_string(),
+        error: format!(
+            "no reachable NFS port on {} (tried {})",
+            spec.server,
+            candidates
+                .iter()
+                .map(|c| c.port.as_str())
+                .fold(Vec::<&str>::new(), |mut ports, port| {
+                    if !ports.contains(&port) {
+                        ports.push(port);
+                    }
+                    ports
+                })
+                .join(", ")
+        ),
+    }))
+}
+
+/// Run systemd-mount for one port/version combination.
+/// systemd-mount creates a transient mount unit that systemd tracks, so the
+/// mount is unmounted in the correct order during shutdown (before network
+/// teardown). With `wait` unset, --no-block returns as soon as the mount job
+/// is queued.
+fn run_systemd_mount(
+    spec: &MountSpec,
+    transport: &NfsTransport,
+    mount_point: &str,
+    wait: bool,
+    extra_options: &[String],
     output: &OutputManager,
 ) -> Result<(), HitlError> {
-    let nfs_source = format!("{server_ip}:/{extension}");
-    let mount_options = format!("port={server_port},vers=4,hard,timeo=600,retrans=2,acregmin=0,acregmax=1,acdirmin=0,acdirmax=1,lookupcache=none");
+    let nfs_source = format!("{}:/{}", spec.server, spec.extension);
+    let fs_type = if transport.version.starts_with('4') {
+        "nfs4"
+    } else {
+        "nfs"
+    };
+    let mut mount_options = format!(
+        "port={},vers={},hard,timeo=600,retrans=2,acregmin=0,acregmax=1,acdirmin=0,acdirmax=1,lookupcache=none",
+        transport.port, transport.version
+    );
+    for option in extra_options {
+        mount_options.push(',');
+        mount_options.push_str(option);
+    }
 
     output.step(
         "NFS Mount",
@@ -220,52 +964,78 @@ fn mount_nfs_extension(
         "systemd-mount"
     };
 
-    // systemd-mount creates a transient mount unit that systemd tracks
-    // This ensures proper shutdown ordering (unmount before network goes down)
-    // --no-block allows the command to return immediately
     // --collect removes the unit after unmounting
-    let result = ProcessCommand::new(command_name)
-        .args([
-            "--no-block",
-            "--collect",
-            "-t",
-            "nfs4",
-            "-o",
-            &mount_options,
-            &nfs_source,
-            mount_point,
-        ])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| HitlError::Command {
-            command: command_name.to_string(),
-            source: e,
-        })?;
+    let mut args = vec!["--collect", "-t", fs_type, "-o", &mount_options];
+    if !wait {
+        args.insert(0, "--no-block");
+    }
+    args.extend([nfs_source.as_str(), mount_point]);
+    let result = match crate::timeouts::output(
+        ProcessCommand::new(command_name).args(&args),
+        crate::timeouts::TimeoutKind::NfsMount,
+    ) {
+        Ok(result) => result,
+        Err(crate::timeouts::RunError::Spawn(source)) => {
+            return Err(HitlError::Command {
+                command: command_name.to_string(),
+                source,
+            })
+        }
+        Err(crate::timeouts::RunError::TimedOut { limit, .. }) => {
+            return Err(HitlError::MountTimedOut {
+                extension: spec.extension.clone(),
+                mount_point: mount_point.to_string(),
+                seconds: limit.as_secs(),
+            })
+        }
+    };
 
     if !result.status.success() {
         let stderr = String::from_utf8_lossy(&result.stderr);
         return Err(HitlError::Mount {
-            extension: extension.to_string(),
+            extension: spec.extension.clone(),
             mount_point: mount_point.to_string(),
-            error: stderr.to_string(),
+            error: stderr.trim().to_string(),
         });
     }
 
     Ok(())
 }
 
+/// Mount the share again with the extension's extra `options` from
+/// `hitl.toml` added. Does nothing without extra options.
+pub fn remount_with_options(
+    spec: &MountSpec,
+    transport: &NfsTransport,
+    mount_point: &str,
+    options: &[String],
+    output: &OutputManager,
+) -> Result<(), HitlError> {
+    if options.is_empty() {
+        return Ok(());
+    }
+    output.step(
+        "HITL Overrides",
+        &format!("Remounting {} with {}", spec.extension, options.join(",")),
+    );
+    unmount_nfs_extension(mount_point, output)?;
+    run_systemd_mount(spec, transport, mount_point, true, options, output)
+}
+
 /// Unmount NFS extensions
-fn unmount_extensions(matches: &ArgMatches, output: &OutputManager) {
-    let extensions: Vec<&String> = matches
-        .get_many::<String>("extension")
-        .expect("at least one extension is required")
-        .collect();
+fn unmount_extensions(matches: &ArgMatches, config: &Config, output: &OutputManager) {
+    let specs = config.resolve_extension_aliases(
+        matches
+            .get_many::<String>("extension")
+            .expect("at least one extension is required"),
+    );
+    let extensions: Vec<&str> = specs.iter().map(|spec| spec_extension_name(spec)).collect();
 
     output.info(
         "HITL Unmount",
         &format!("Unmounting {} extension(s)", extensions.len()),
     );
+    crate::extension_release::invalidate();
 
     let extensions_base_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
         // Use AVOCADO_TEST_TMPDIR if set (to avoid affecting TempDir::new()),
@@ -275,7 +1045,7 @@ fn unmount_extensions(matches: &ArgMatches, output: &OutputManager) {
             .unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/avocado/hitl")
     } else {
-        "/run/avocado/hitl".to_string()
+        crate::user_mode::system_path("/run/avocado/hitl")
     };
 
     // Step 1: Scan for enabled services before unmerging (while mounts are still accessible)
@@ -305,26 +1075,28 @@ fn unmount_extensions(matches: &ArgMatches, output: &OutputManager) {
     // Step 3: Clean up service drop-ins
     for (extension, services) in &extension_services {
         if let Err(e) = cleanup_service_dropins(extension, services, output) {
-            output.error(
+            output.error_with(
                 "HITL Unmount",
                 &format!("Failed to cleanup service drop-ins for {extension}: {e}"),
+                &e.diagnose(),
             );
             // Continue even if drop-in cleanup fails
         }
     }
 
-    // Step 4: Reload systemd to apply drop-in removals
-    if !extension_services.is_empty() {
-        if let Err(e) = systemd_daemon_reload(output) {
-            output.error(
-                "HITL Unmount",
-                &format!("Failed to reload systemd daemon: {e}"),
-            );
-            // Continue even if daemon-reload fails
-        }
+    // Step 4: Reload systemd once for the unmerge and drop-in removals
+    if let Err(e) = systemd_daemon_reload(output) {
+        output.error_with(
+            "HITL Unmount",
+            &format!("Failed to reload systemd daemon: {e}"),
+            &e.diagnose(),
+        );
+        // Continue even if daemon-reload fails
     }
 
     let mut success = true;
+    let mut restarts: Vec<String> = Vec::new();
+    let span = crate::telemetry::operation("hitl.unmount", output);
 
     // Step 5: Unmount NFS shares and clean up directories
     for extension in &extensions {
@@ -337,9 +1109,10 @@ fn unmount_extensions(matches: &ArgMatches, output: &OutputManager) {
 
         // Unmount NFS share
         if let Err(e) = unmount_nfs_extension(&extension_dir, output) {
-            output.error(
+            output.error_with(
                 "HITL Unmount",
                 &format!("Failed to unmount extension {extension}: {e}"),
+                &e.diagnose(),
             );
             success = false;
             continue;
@@ -347,32 +1120,60 @@ fn unmount_extensions(matches: &ArgMatches, output: &OutputManager) {
 
         // Remove the directory
         if let Err(e) = cleanup_extension_directory(&extension_dir, output) {
-            output.error(
+            output.error_with(
                 "HITL Unmount",
                 &format!("Failed to cleanup directory for {extension}: {e}"),
+                &e.diagnose(),
             );
             success = false;
             continue;
         }
 
+        restarts.extend(hitl_health::recorded_overrides(extension).restart_units());
+        crate::hitl_health::forget_mount(extension);
+
         output.progress(&format!("Successfully unmounted extension: {extension}"));
     }
+    if !success {
+        span.set_error("Some extensions failed to unmount");
+    }
+    drop(span);
 
     if success {
-        output.success("HITL Unmount", "All extensions unmounted successfully");
+        output.success_msg("HITL Unmount", messages::HITL_UNMOUNTED, &[]);
         output.info("HITL Unmount", "Refreshing extensions to apply changes");
         // Step 6: Merge remaining extensions
         let config = crate::config::Config::default();
         ext::merge_extensions(&config, output);
+        restart_override_services(&restarts, "HITL Unmount", output);
     } else {
         output.error("HITL Unmount", "Some extensions failed to unmount");
-        std::process::exit(1);
+        output.exit(1);
+    }
+}
+
+/// Restart the services listed in the extensions' `hitl.toml`, exiting
+/// if that fails.
+fn restart_override_services(units: &[String], operation: &str, output: &OutputManager) {
+    let mut units = units.to_vec();
+    units.sort();
+    units.dedup();
+    if let Err(e) = hitl_overrides::restart_services(&units, output) {
+        output.error_with(operation, &e.to_string(), &e.diagnose());
+        output.exit(1);
     }
 }
 
 /// Unmount NFS extension using systemd-umount for proper cleanup
 /// This properly stops the transient mount unit created by systemd-mount
 fn unmount_nfs_extension(mount_point: &str, output: &OutputManager) -> Result<(), HitlError> {
+    let span = crate::telemetry::span("hitl.nfs_unmount");
+    span.set_attribute("avocado.hitl.mount_point", mount_point);
+    span.record(systemd_umount(mount_point, output))
+}
+
+/// The systemd-umount call behind [`unmount_nfs_extension`].
+fn systemd_umount(mount_point: &str, output: &OutputManager) -> Result<(), HitlError> {
     // Check if the directory is actually mounted
     if !Path::new(mount_point).exists() {
         output.progress(&format!("Directory doesn't exist: {mount_point}"));
@@ -413,11 +1214,33 @@ fn unmount_nfs_extension(mount_point: &str, output: &OutputManager) -> Result<()
     Ok(())
 }
 
-/// Clean up extension directory after unmounting
+/// Whether mountinfo `content` lists a mount at `path`
+fn mounted_at(content: &str, path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    content.lines().any(|line| {
+        line.split_whitespace().nth(4).is_some_and(|mount_point| {
+            crate::commands::top::unescape_mount_path(mount_point) == path
+        })
+    })
+}
+
+/// Whether something is mounted at `path`
+fn is_mount_point(path: &str) -> bool {
+    fs::read_to_string("/proc/self/mountinfo").is_ok_and(|content| mounted_at(&content, path))
+}
+
+/// Clean up extension directory after unmounting. Refuses while something
+/// is still mounted there: removing it would delete the files on the
+/// server.
 fn cleanup_extension_directory(
     dir_path: &str,
     output: &OutputManager,
 ) -> Result<(), std::io::Error> {
+    if is_mount_point(dir_path) {
+        return Err(std::io::Error::other(format!(
+            "{dir_path} is still mounted; not removing it"
+        )));
+    }
     if Path::new(dir_path).exists() {
         fs::remove_dir_all(dir_path)?;
         output.progress(&format!("Removed directory: {dir_path}"));
@@ -429,7 +1252,7 @@ fn cleanup_extension_directory(
 
 /// Convert a mount path to a systemd mount unit name
 /// e.g., /run/avocado/hitl/my-ext -> run-avocado-hitl-my\x2dext.mount
-fn systemd_escape_mount_path(path: &str) -> String {
+pub(crate) fn systemd_escape_mount_path(path: &str) -> String {
     // Remove leading slash and replace / with -
     let without_leading_slash = path.trim_start_matches('/');
     // Escape dashes in path components (except separators)
@@ -439,12 +1262,103 @@ fn systemd_escape_mount_path(path: &str) -> String {
     format!("{escaped}.mount")
 }
 
+/// `[Unit]` section tying a service to the mount: the dependency types the
+/// extension declared for it, or the default ones when it declared none.
+fn render_dependencies(
+    dependencies: &[ServiceDependency],
+    mount_point: &str,
+    mount_unit: &str,
+) -> String {
+    if dependencies.is_empty() {
+        // - RequiresMountsFor: Ensures the mount path is available
+        // - BindsTo: Binds service lifecycle to mount (stops service when mount stops)
+        // - After: Service starts after mount is ready; during shutdown, service stops BEFORE mount
+        // - After=remote-fs.target: During shutdown, service stops BEFORE remote-fs.target
+        //   This ensures the service is stopped before NFS mounts are unmounted
+        return format!(
+            "[Unit]\n\
+            RequiresMountsFor={mount_point}\n\
+            BindsTo={mount_unit}\n\
+            After={mount_unit}\n\
+            After=remote-fs.target\n"
+        );
+    }
+    let mut section = "[Unit]\n".to_string();
+    for dependency in dependencies {
+        section.push_str(&format!("{}={mount_unit}\n", dependency.directive()));
+        if *dependency == ServiceDependency::After {
+            section.push_str("After=remote-fs.target\n");
+        }
+    }
+    section
+}
+
+/// Whether a service with these declared dependency types is ordered after
+/// the mount, and so must stop before it is unmounted.
+fn ordered_after_mount(dependencies: &[ServiceDependency]) -> bool {
+    dependencies.is_empty() || dependencies.contains(&ServiceDependency::After)
+}
+
+/// First line of every drop-in written for a HITL extension, followed by
+/// the extension's name.
+const DROPIN_HEADER: &str = "# Auto-generated by avocadoctl hitl mount for extension: ";
+
+/// Content of the drop-in for `service_unit`: the configured template with
+/// its placeholders filled in, or the mount dependencies alone.
+pub(crate) fn render_service_dropin(
+    templates: &HitlDropinSettings,
+    extension: &str,
+    service_unit: &str,
+    dependencies: &[ServiceDependency],
+    mount_point: &str,
+    mount_unit: &str,
+) -> String {
+    let dependencies = render_dependencies(dependencies, mount_point, mount_unit);
+    let mut body = match templates.template_for(service_unit) {
+        Some(template) => [
+            ("extension", extension),
+            ("service", service_unit),
+            ("mount_point", mount_point),
+            ("mount_unit", mount_unit),
+            ("dependencies", &dependencies),
+        ]
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), value)
+        }),
+        None => dependencies,
+    };
+    if !body.ends_with('\n') {
+        body.push('\n');
+    }
+    format!("{DROPIN_HEADER}{extension}\n{body}")
+}
+
+/// Base directory of the runtime drop-ins.
+fn systemd_run_dir() -> String {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        // Use AVOCADO_TEST_TMPDIR if set (to avoid affecting TempDir::new()),
+        // otherwise fall back to TMPDIR, then /tmp
+        let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
+            .or_else(|_| std::env::var("TMPDIR"))
+            .unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/run/systemd/system")
+    } else {
+        "/run/systemd/system".to_string()
+    }
+}
+
 /// Create systemd drop-in files for services that depend on the HITL mount
 /// This ensures services are stopped before the NFS mount is unmounted during shutdown
+/// `dependencies` holds the dependency types declared for some of
+/// `services`; the others get the default ones.
 pub fn create_service_dropins(
     extension: &str,
     mount_point: &str,
     services: &[String],
+    dependencies: &BTreeMap<String, Vec<ServiceDependency>>,
+    templates: &HitlDropinSettings,
+    overrides: &HitlOverrides,
     output: &OutputManager,
 ) -> Result<(), HitlError> {
     if services.is_empty() {
@@ -461,97 +1375,101 @@ pub fn create_service_dropins(
         ),
     );
 
-    // Determine the base directory for drop-ins
-    let systemd_run_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        // Use AVOCADO_TEST_TMPDIR if set (to avoid affecting TempDir::new()),
-        // otherwise fall back to TMPDIR, then /tmp
-        let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
-            .or_else(|_| std::env::var("TMPDIR"))
-            .unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/run/systemd/system")
-    } else {
-        "/run/systemd/system".to_string()
-    };
+    let systemd_run_dir = systemd_run_dir();
 
-    // Collect service unit names for the mount unit drop-in
-    let service_units: Vec<String> = services
+    // Collect service unit names and their declared dependency types
+    let service_units: Vec<(String, &[ServiceDependency])> = services
         .iter()
         .map(|s| {
-            if s.ends_with(".service") {
+            let unit = if s.ends_with(".service") {
                 s.clone()
             } else {
                 format!("{s}.service")
-            }
+            };
+            (unit, dependencies.get(s).map_or(&[][..], Vec::as_slice))
         })
         .collect();
 
     // Create drop-ins for each service
-    for service_unit in &service_units {
+    for (service_unit, service_dependencies) in &service_units {
         let dropin_dir = format!("{systemd_run_dir}/{service_unit}.d");
         let dropin_file = format!("{dropin_dir}/10-hitl-{extension}.conf");
 
         // Create the drop-in directory
         if let Err(e) = fs::create_dir_all(&dropin_dir) {
-            output.error(
+            output.error_with(
                 "Service Dependencies",
                 &format!("Failed to create drop-in directory {dropin_dir}: {e}"),
+                &e.diagnose(),
             );
             continue;
         }
 
         // Create the drop-in content
-        // - RequiresMountsFor: Ensures the mount path is available
-        // - BindsTo: Binds service lifecycle to mount (stops service when mount stops)
-        // - After: Service starts after mount is ready; during shutdown, service stops BEFORE mount
-        // - After=remote-fs.target: During shutdown, service stops BEFORE remote-fs.target
-        //   This ensures the service is stopped before NFS mounts are unmounted
-        let dropin_content = format!(
-            "# Auto-generated by avocadoctl hitl mount for extension: {extension}\n\
-            [Unit]\n\
-            RequiresMountsFor={mount_point}\n\
-            BindsTo={mount_unit}\n\
-            After={mount_unit}\n\
-            After=remote-fs.target\n"
+        let mut dropin_content = render_service_dropin(
+            templates,
+            extension,
+            service_unit,
+            service_dependencies,
+            mount_point,
+            &mount_unit,
         );
+        if let Some(section) = overrides.dropin_section() {
+            dropin_content.push_str(&section);
+        }
 
         // Write the drop-in file
         if let Err(e) = fs::write(&dropin_file, &dropin_content) {
-            output.error(
+            output.error_with(
                 "Service Dependencies",
                 &format!("Failed to write drop-in file {dropin_file}: {e}"),
+                &e.diagnose(),
             );
             continue;
         }
 
         output.progress(&format!("Created drop-in: {dropin_file}"));
     }
+    crate::daemon_reload::request(output);
 
     // Create a drop-in for the mount unit to ensure services stop before unmount
     // This is critical for proper shutdown ordering - the mount unit needs to know
-    // it should wait for services to stop before unmounting
+    // it should wait for services to stop before unmounting. Services declared
+    // without `after` are left out, since Before= would order them after the mount.
+    let ordered_units: Vec<&str> = service_units
+        .iter()
+        .filter(|(_, deps)| ordered_after_mount(deps))
+        .map(|(unit, _)| unit.as_str())
+        .collect();
     let mount_dropin_dir = format!("{systemd_run_dir}/{mount_unit}.d");
     let mount_dropin_file = format!("{mount_dropin_dir}/10-hitl-{extension}-services.conf");
+    if ordered_units.is_empty() {
+        let _ = fs::remove_file(&mount_dropin_file);
+        return Ok(());
+    }
 
     if let Err(e) = fs::create_dir_all(&mount_dropin_dir) {
-        output.error(
+        output.error_with(
             "Service Dependencies",
             &format!("Failed to create mount drop-in directory {mount_dropin_dir}: {e}"),
+            &e.diagnose(),
         );
     } else {
         // Before= ensures the mount unit stops AFTER the services stop
         // (i.e., services stop first, then mount is unmounted)
-        let services_list = service_units.join(" ");
+        let services_list = ordered_units.join(" ");
         let mount_dropin_content = format!(
-            "# Auto-generated by avocadoctl hitl mount for extension: {extension}\n\
+            "{DROPIN_HEADER}{extension}\n\
             # Ensures services are stopped before this mount is unmounted during shutdown\n\
             [Unit]\n\
             Before={services_list}\n"
         );
 
         if let Err(e) = fs::write(&mount_dropin_file, &mount_dropin_content) {
-            output.error(
+            output.error_with(
                 "Service Dependencies",
                 &format!("Failed to write mount drop-in file {mount_dropin_file}: {e}"),
+                &e.diagnose(),
             );
         } else {
             output.progress(&format!("Created drop-in: {mount_dropin_file}"));
@@ -561,6 +1479,91 @@ pub fn create_service_dropins(
     Ok(())
 }
 
+/// HITL drop-ins below `systemd_run_dir` whose extension `is_mounted`
+/// rejects, as (drop-in file, extension).
+fn orphaned_dropins(
+    systemd_run_dir: &Path,
+    is_mounted: impl Fn(&str) -> bool,
+) -> Vec<(std::path::PathBuf, String)> {
+    let Ok(units) = fs::read_dir(systemd_run_dir) else {
+        return Vec::new();
+    };
+    let mut orphans = Vec::new();
+    for unit in units.flatten() {
+        let Ok(files) = fs::read_dir(unit.path()) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let name = file.file_name().to_string_lossy().to_string();
+            if !name.starts_with("10-hitl-") || !name.ends_with(".conf") {
+                continue;
+            }
+            let extension = fs::read_to_string(file.path()).ok().and_then(|content| {
+                content
+                    .lines()
+                    .next()
+                    .and_then(|line| line.strip_prefix(DROPIN_HEADER))
+                    .map(|extension| extension.trim().to_string())
+            });
+            match extension {
+                Some(extension) if !is_mounted(&extension) => {
+                    orphans.push((file.path(), extension))
+                }
+                _ => {}
+            }
+        }
+    }
+    orphans.sort();
+    orphans
+}
+
+/// Whether the HITL share of `extension` is mounted. In test mode mounts
+/// are plain directories.
+fn is_hitl_mounted(extension: &str) -> bool {
+    let mount_point = Path::new(&hitl_base_dir()).join(extension);
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        return mount_point.is_dir();
+    }
+    use std::os::unix::fs::MetadataExt;
+    match (
+        fs::metadata(&mount_point),
+        mount_point.parent().map(fs::metadata),
+    ) {
+        (Ok(mount), Some(Ok(parent))) => mount.dev() != parent.dev(),
+        _ => false,
+    }
+}
+
+/// Remove the drop-ins of HITL extensions that are no longer mounted and
+/// reload systemd. A `hitl unmount` that crashed before its cleanup leaves
+/// them behind, wedging their services on a mount that will not come back.
+/// Run by merge and unmerge, which change what those services see.
+pub fn remove_orphaned_dropins(output: &OutputManager) {
+    let mut removed = false;
+    for (dropin, extension) in orphaned_dropins(Path::new(&systemd_run_dir()), is_hitl_mounted) {
+        if let Err(e) = fs::remove_file(&dropin) {
+            output.warning(&format!(
+                "failed to remove drop-in {} of unmounted HITL extension '{extension}': {e}",
+                dropin.display()
+            ));
+            continue;
+        }
+        output.log_info(&format!(
+            "Removed drop-in {} of HITL extension '{extension}', which is no longer mounted",
+            dropin.display()
+        ));
+        removed = true;
+        if let Some(dir) = dropin.parent() {
+            if fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_none()) {
+                let _ = fs::remove_dir(dir);
+            }
+        }
+    }
+    if removed {
+        crate::daemon_reload::request(output);
+    }
+}
+
 /// Clean up systemd drop-in files for services when unmounting HITL extensions
 pub fn cleanup_service_dropins(
     extension: &str,
@@ -579,18 +1582,9 @@ pub fn cleanup_service_dropins(
             extension
         ),
     );
+    crate::daemon_reload::request(output);
 
-    // Determine the base directory for drop-ins
-    let systemd_run_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        // Use AVOCADO_TEST_TMPDIR if set (to avoid affecting TempDir::new()),
-        // otherwise fall back to TMPDIR, then /tmp
-        let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
-            .or_else(|_| std::env::var("TMPDIR"))
-            .unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/run/systemd/system")
-    } else {
-        "/run/systemd/system".to_string()
-    };
+    let systemd_run_dir = systemd_run_dir();
 
     for service in services {
         // Ensure service name ends with .service
@@ -606,9 +1600,10 @@ pub fn cleanup_service_dropins(
         // Remove the drop-in file if it exists
         if Path::new(&dropin_file).exists() {
             if let Err(e) = fs::remove_file(&dropin_file) {
-                output.error(
+                output.error_with(
                     "Service Dependencies",
                     &format!("Failed to remove drop-in file {dropin_file}: {e}"),
+                    &e.diagnose(),
                 );
                 continue;
             }
@@ -635,11 +1630,12 @@ pub fn cleanup_service_dropins(
                     format!("{systemd_run_dir}/{filename_str}/10-hitl-{extension}-services.conf");
                 if Path::new(&mount_dropin_file).exists() {
                     if let Err(e) = fs::remove_file(&mount_dropin_file) {
-                        output.error(
+                        output.error_with(
                             "Service Dependencies",
                             &format!(
                                 "Failed to remove mount drop-in file {mount_dropin_file}: {e}"
                             ),
+                            &e.diagnose(),
                         );
                     } else {
                         output.progress(&format!("Removed drop-in: {mount_dropin_file}"));
@@ -660,68 +1656,146 @@ pub fn cleanup_service_dropins(
     Ok(())
 }
 
-/// Call systemctl daemon-reload to apply drop-in changes
+/// Reload systemd if drop-ins or merged units changed since the last
+/// reload (see [`crate::daemon_reload`])
 pub fn systemd_daemon_reload(output: &OutputManager) -> Result<(), HitlError> {
-    // Skip daemon-reload in test mode
-    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        output.progress("Skipping daemon-reload in test mode");
-        return Ok(());
-    }
+    crate::daemon_reload::flush(output)
+        .map(|_| ())
+        .map_err(|e| HitlError::DaemonReload {
+            error: e.to_string(),
+        })
+}
 
-    output.step(
-        "Systemd",
-        "Reloading systemd daemon to apply drop-in changes",
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let result = ProcessCommand::new("systemctl")
-        .arg("daemon-reload")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| HitlError::Command {
-            command: "systemctl daemon-reload".to_string(),
-            source: e,
-        })?;
+    #[test]
+    fn test_mounted_at() {
+        let mountinfo = "\
+36 25 0:32 / /run/avocado/hitl/app rw,relatime - nfs4 10.0.0.1:/app rw
+37 25 0:33 / /run/avocado/hitl/my\\040ext rw,relatime - nfs4 10.0.0.1:/x rw
+";
+        assert!(mounted_at(mountinfo, "/run/avocado/hitl/app"));
+        assert!(mounted_at(mountinfo, "/run/avocado/hitl/app/"));
+        assert!(mounted_at(mountinfo, "/run/avocado/hitl/my ext"));
+        assert!(!mounted_at(mountinfo, "/run/avocado/hitl"));
+        assert!(!mounted_at(mountinfo, "/run/avocado/hitl/other"));
+    }
 
-    if !result.status.success() {
-        let stderr = String::from_utf8_lossy(&result.stderr);
-        output.error("Systemd", &format!("daemon-reload failed: {stderr}"));
-        return Err(HitlError::DaemonReload {
-            error: stderr.to_string(),
-        });
+    #[test]
+    fn test_orphaned_dropins() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let run_dir = tmp.path();
+        let write = |unit: &str, file: &str, content: &str| {
+            let dir = run_dir.join(format!("{unit}.d"));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(file), content).unwrap();
+        };
+        write(
+            "app.service",
+            "10-hitl-gone.conf",
+            &format!("{DROPIN_HEADER}gone\n[Unit]\n"),
+        );
+        write(
+            "app.service",
+            "10-hitl-live.conf",
+            &format!("{DROPIN_HEADER}live\n[Unit]\n"),
+        );
+        write(
+            "run-avocado-hitl-gone.mount",
+            "10-hitl-gone-services.conf",
+            &format!("{DROPIN_HEADER}gone\n[Unit]\nBefore=app.service\n"),
+        );
+        write("app.service", "10-hitl-local.conf", "[Unit]\n");
+        write(
+            "app.service",
+            "override.conf",
+            &format!("{DROPIN_HEADER}gone\n"),
+        );
+
+        let orphans = orphaned_dropins(run_dir, |extension| extension == "live");
+        assert_eq!(
+            orphans,
+            [
+                (
+                    run_dir.join("app.service.d/10-hitl-gone.conf"),
+                    "gone".to_string()
+                ),
+                (
+                    run_dir.join("run-avocado-hitl-gone.mount.d/10-hitl-gone-services.conf"),
+                    "gone".to_string()
+                ),
+            ]
+        );
     }
+    use crate::commands::test_env::ENV_VAR_MUTEX;
 
-    output.progress("Systemd daemon reloaded successfully");
-    Ok(())
-}
+    #[test]
+    fn test_parse_mount_spec() {
+        let spec = parse_mount_spec("app", Some("10.0.0.1"), "12049").unwrap();
+        assert_eq!(
+            spec,
+            MountSpec {
+                extension: "app".to_string(),
+                server: "10.0.0.1".to_string(),
+                port: "12049".to_string(),
+            }
+        );
 
-/// Errors related to HITL operations
-#[derive(Debug, thiserror::Error)]
-pub enum HitlError {
-    #[error("Failed to run command '{command}': {source}")]
-    Command {
-        command: String,
-        source: std::io::Error,
-    },
+        let spec = parse_mount_spec("fw@10.0.0.2:2049", Some("10.0.0.1"), "12049").unwrap();
+        assert_eq!(
+            (spec.server.as_str(), spec.port.as_str()),
+            ("10.0.0.2", "2049")
+        );
 
-    #[error("Failed to mount extension '{extension}' to '{mount_point}': {error}")]
-    Mount {
-        extension: String,
-        mount_point: String,
-        error: String,
-    },
+        let spec = parse_mount_spec("fw@laptop", None, "12049").unwrap();
+        assert_eq!(
+            (spec.server.as_str(), spec.port.as_str()),
+            ("laptop", "12049")
+        );
 
-    #[error("Failed to unmount '{mount_point}': {error}")]
-    Unmount { mount_point: String, error: String },
+        let spec = parse_mount_spec("app@[fd00::1]:2049", None, "12049").unwrap();
+        assert_eq!(
+            (spec.server.as_str(), spec.port.as_str()),
+            ("[fd00::1]", "2049")
+        );
 
-    #[error("Failed to reload systemd daemon: {error}")]
-    DaemonReload { error: String },
-}
+        assert!(parse_mount_spec("app", None, "12049").is_err());
+        assert!(parse_mount_spec("app@fd00::1", None, "12049").is_err());
+        assert!(parse_mount_spec("app@host:nope", None, "12049").is_err());
+        assert!(parse_mount_spec("@host", None, "12049").is_err());
+        assert_eq!(spec_extension_name("app@host:2049"), "app");
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::commands::test_env::ENV_VAR_MUTEX;
+    #[test]
+    fn test_merge_after_mount_follows_policy() {
+        let report = |extension: &str, status| MountReport {
+            extension: extension.to_string(),
+            server: "10.0.0.1:12049".to_string(),
+            status,
+            error: None,
+            elapsed_ms: 0,
+        };
+        for status in [
+            MountStatus::Mounted,
+            MountStatus::Failed,
+            MountStatus::Skipped,
+        ] {
+            assert_eq!(MountStatus::parse(status.as_str()), Some(status));
+        }
+
+        let all = [report("app", MountStatus::Mounted)];
+        let partial = [
+            report("app", MountStatus::Mounted),
+            report("fw", MountStatus::Failed),
+        ];
+        let none = [report("fw", MountStatus::Failed)];
+        assert!(merge_after_mount(&all, FailurePolicy::FailFast));
+        assert!(merge_after_mount(&partial, FailurePolicy::KeepGoing));
+        assert!(!merge_after_mount(&partial, FailurePolicy::FailFast));
+        assert!(!merge_after_mount(&none, FailurePolicy::KeepGoing));
+    }
 
     #[test]
     fn test_create_command() {
@@ -730,11 +1804,13 @@ mod tests {
 
         // Check that both mount and unmount subcommands exist
         let subcommands: Vec<_> = cmd.get_subcommands().collect();
-        assert_eq!(subcommands.len(), 2);
+        assert_eq!(subcommands.len(), 4);
 
         let subcommand_names: Vec<&str> = subcommands.iter().map(|cmd| cmd.get_name()).collect();
         assert!(subcommand_names.contains(&"mount"));
         assert!(subcommand_names.contains(&"unmount"));
+        assert!(subcommand_names.contains(&"quiesce"));
+        assert!(subcommand_names.contains(&"resume"));
     }
 
     #[test]
@@ -752,6 +1828,7 @@ mod tests {
         assert!(arg_names.contains(&"server-ip"));
         assert!(arg_names.contains(&"server-port"));
         assert!(arg_names.contains(&"extension"));
+        assert!(arg_names.contains(&"control-port"));
     }
 
     #[test]
@@ -821,7 +1898,15 @@ mod tests {
         let services = vec!["nginx".to_string(), "prometheus.service".to_string()];
 
         // Create drop-ins
-        let result = create_service_dropins(extension, mount_point, &services, &output);
+        let result = create_service_dropins(
+            extension,
+            mount_point,
+            &services,
+            &BTreeMap::new(),
+            &HitlDropinSettings::default(),
+            &HitlOverrides::default(),
+            &output,
+        );
         assert!(result.is_ok());
 
         // Verify service drop-ins were created
@@ -883,13 +1968,104 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_render_service_dropin_template() {
+        let mount_point = "/run/avocado/hitl/app";
+        let mount_unit = systemd_escape_mount_path(mount_point);
+        let default = render_service_dropin(
+            &HitlDropinSettings::default(),
+            "app",
+            "app.service",
+            &[],
+            mount_point,
+            &mount_unit,
+        );
+        assert!(default.contains("RequiresMountsFor=/run/avocado/hitl/app\n"));
+
+        let mut templates = HitlDropinSettings::default();
+        templates.services.insert(
+            "app".to_string(),
+            "{dependencies}\n[Service]\nExecStartPre=/usr/bin/wait-for {mount_point}\nEnvironment=EXT={extension}"
+                .to_string(),
+        );
+        templates.template = Some("[Unit]\nAfter={mount_unit}".to_string());
+
+        let app = render_service_dropin(
+            &templates,
+            "app",
+            "app.service",
+            &[],
+            mount_point,
+            &mount_unit,
+        );
+        assert!(app.starts_with("# Auto-generated"));
+        assert!(app.contains("BindsTo=run-avocado-hitl-app.mount\n"));
+        assert!(app.contains("ExecStartPre=/usr/bin/wait-for /run/avocado/hitl/app\n"));
+        assert!(app.ends_with("Environment=EXT=app\n"));
+
+        let db = render_service_dropin(
+            &templates,
+            "app",
+            "db.service",
+            &[],
+            mount_point,
+            &mount_unit,
+        );
+        assert!(db.contains("[Unit]\nAfter=run-avocado-hitl-app.mount\n"));
+        assert!(!db.contains("RequiresMountsFor"));
+    }
+
+    #[test]
+    fn test_render_declared_service_dependencies() {
+        let mount_point = "/run/avocado/hitl/app";
+        let mount_unit = systemd_escape_mount_path(mount_point);
+        let templates = HitlDropinSettings::default();
+
+        let requires = render_service_dropin(
+            &templates,
+            "app",
+            "nginx.service",
+            &[ServiceDependency::Requires],
+            mount_point,
+            &mount_unit,
+        );
+        assert!(requires.contains("[Unit]\nRequires=run-avocado-hitl-app.mount\n"));
+        assert!(!requires.contains("BindsTo="));
+        assert!(!requires.contains("RequiresMountsFor="));
+        assert!(!requires.contains("After="));
+
+        let wants_after = render_service_dropin(
+            &templates,
+            "app",
+            "worker.service",
+            &[ServiceDependency::Wants, ServiceDependency::After],
+            mount_point,
+            &mount_unit,
+        );
+        assert!(wants_after.contains(
+            "Wants=run-avocado-hitl-app.mount\nAfter=run-avocado-hitl-app.mount\nAfter=remote-fs.target\n"
+        ));
+
+        assert!(ordered_after_mount(&[]));
+        assert!(ordered_after_mount(&[ServiceDependency::After]));
+        assert!(!ordered_after_mount(&[ServiceDependency::BindsTo]));
+    }
+
     #[test]
     fn test_create_service_dropins_empty_services() {
         let output = OutputManager::new(false, false);
         let services: Vec<String> = vec![];
 
         // Should return Ok without doing anything
-        let result = create_service_dropins("test-ext", "/run/test", &services, &output);
+        let result = create_service_dropins(
+            "test-ext",
+            "/run/test",
+            &services,
+            &BTreeMap::new(),
+            &HitlDropinSettings::default(),
+            &HitlOverrides::default(),
+            &output,
+        );
         assert!(result.is_ok());
     }
 }
@@ -1,30 +1,10 @@
+use crate::error::IoContext;
+use crate::extension_release::{self, Hierarchy, ReleaseFile};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::{Command as ProcessCommand, Stdio};
+use std::process::Command as ProcessCommand;
 
-// ---------------------------------------------------------------------------
-// Error type (moved from ext.rs)
-// ---------------------------------------------------------------------------
-
-/// Errors related to system command execution during image operations.
-#[derive(Debug, thiserror::Error)]
-pub enum SystemdError {
-    #[error("Failed to run command '{command}': {source}")]
-    CommandFailed {
-        command: String,
-        source: std::io::Error,
-    },
-
-    #[error("Command '{command}' exited with error code {exit_code:?}: {stderr}")]
-    CommandExitedWithError {
-        command: String,
-        exit_code: Option<i32>,
-        stderr: String,
-    },
-
-    #[error("Configuration error: {message}")]
-    ConfigurationError { message: String },
-}
+pub use crate::error::SystemdError;
 
 // ---------------------------------------------------------------------------
 // Image type tag (replaces is_directory + is_kab booleans on Extension)
@@ -63,6 +43,9 @@ pub trait ImageAdaptor {
     /// Check whether the backing image has changed and requires remounting.
     fn needs_remount(&self, mount_name: &str, image_path: &Path) -> bool;
 
+    /// The loop device (or a link to it) the mounted image is attached to.
+    fn loop_device(&self, mount_name: &str) -> Option<PathBuf>;
+
     /// The tag identifying this adaptor type.
     fn type_tag(&self) -> ImageTypeTag;
 }
@@ -127,6 +110,13 @@ impl ImageAdaptor for ImageType {
         }
     }
 
+    fn loop_device(&self, mount_name: &str) -> Option<PathBuf> {
+        match self {
+            ImageType::Raw(a) => a.loop_device(mount_name),
+            ImageType::Kab(a) => a.loop_device(mount_name),
+        }
+    }
+
     fn type_tag(&self) -> ImageTypeTag {
         match self {
             ImageType::Raw(a) => a.type_tag(),
@@ -145,17 +135,13 @@ pub fn extension_mount_point(mount_name: &str) -> String {
         let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/avocado/extensions/{mount_name}")
     } else {
-        format!("/run/avocado/extensions/{mount_name}")
+        crate::user_mode::system_path(&format!("/run/avocado/extensions/{mount_name}"))
     }
 }
 
-/// Resolve the systemd-dissect command name (real or mock in test mode).
-fn dissect_command() -> &'static str {
-    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        "mock-systemd-dissect"
-    } else {
-        "systemd-dissect"
-    }
+/// Resolve the systemd-dissect command (configured path, or mock in test mode).
+fn dissect_command() -> String {
+    crate::tools::program("systemd-dissect")
 }
 
 fn is_test_mode() -> bool {
@@ -176,10 +162,7 @@ fn mount_with_dissect(
 ) -> Result<(), SystemdError> {
     // Create mount point parent directory
     if let Some(parent) = Path::new(mount_point).parent() {
-        fs::create_dir_all(parent).map_err(|e| SystemdError::CommandFailed {
-            command: "create_dir_all".to_string(),
-            source: e,
-        })?;
+        fs::create_dir_all(parent).context("create directory", parent)?;
     }
 
     if verbose {
@@ -192,6 +175,8 @@ fn mount_with_dissect(
     if use_loop_ref {
         args.push(format!("--loop-ref={mount_name}"));
     }
+    let policy_args = crate::image_policy::dissect_args();
+    args.extend(policy_args.iter().cloned());
     args.extend_from_slice(&[
         "--mkdir".to_string(),
         "-r".to_string(),
@@ -200,17 +185,44 @@ fn mount_with_dissect(
         mount_point.to_string(),
     ]);
 
+    let result = run_dissect_mount(&cmd, &args);
+    let result = match result {
+        Err(e) if !policy_args.is_empty() && crate::image_policy::allows_fallback() => {
+            eprintln!(
+                "Warning: {mount_name} does not satisfy the image policy ({e}); mounting without it (verity = warn)"
+            );
+            args.retain(|a| !policy_args.contains(a));
+            run_dissect_mount(&cmd, &args)
+        }
+        other => other,
+    };
+    let mocked = result?;
+
+    if verbose {
+        if mocked {
+            println!("Mounted {mount_name} to {mount_point} (mock backend)");
+        } else {
+            println!("Mounted {mount_name} to {mount_point}");
+        }
+    }
+    Ok(())
+}
+
+/// Run one systemd-dissect mount attempt. Returns whether the mock backend
+/// handled it.
+fn run_dissect_mount(cmd: &str, args: &[String]) -> Result<bool, SystemdError> {
     let arg_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
 
-    let output = ProcessCommand::new(cmd)
-        .args(&arg_refs)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| SystemdError::CommandFailed {
-            command: cmd.to_string(),
-            source: e,
-        })?;
+    if let Some(result) = crate::backend::simulate("systemd-dissect", &arg_refs) {
+        result?;
+        return Ok(true);
+    }
+
+    let output = crate::timeouts::output(
+        ProcessCommand::new(cmd).args(&arg_refs),
+        crate::timeouts::TimeoutKind::LoopMount,
+    )
+    .map_err(|e| e.into_systemd_error(cmd))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -220,26 +232,22 @@ fn mount_with_dissect(
             stderr: stderr.to_string(),
         });
     }
-
-    if verbose {
-        println!("Mounted {mount_name} to {mount_point}");
-    }
-    Ok(())
+    Ok(false)
 }
 
 /// Unmount using systemd-dissect -U.
 fn unmount_with_dissect(mount_point: &str, verbose: bool) -> Result<(), SystemdError> {
+    if let Some(result) = crate::backend::simulate("systemd-dissect", &["-U", mount_point]) {
+        return result.map(|_| ());
+    }
+
     let cmd = dissect_command();
 
-    let output = ProcessCommand::new(cmd)
-        .args(["-U", mount_point])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .map_err(|e| SystemdError::CommandFailed {
-            command: cmd.to_string(),
-            source: e,
-        })?;
+    let output = crate::timeouts::output(
+        ProcessCommand::new(&cmd).args(["-U", mount_point]),
+        crate::timeouts::TimeoutKind::LoopMount,
+    )
+    .map_err(|e| e.into_systemd_error(&cmd))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -256,6 +264,22 @@ fn unmount_with_dissect(mount_point: &str, verbose: bool) -> Result<(), SystemdE
     Ok(())
 }
 
+/// Mount an image read-only at `mount_point` without a persistent loop
+/// reference. Used for one-off inspection such as `ext test`.
+pub(crate) fn mount_image_once(
+    mount_name: &str,
+    image_path: &Path,
+    mount_point: &str,
+    verbose: bool,
+) -> Result<(), SystemdError> {
+    mount_with_dissect(mount_name, image_path, mount_point, false, verbose)
+}
+
+/// Unmount an image mounted with [`mount_image_once`].
+pub(crate) fn unmount_image_once(mount_point: &str, verbose: bool) -> Result<(), SystemdError> {
+    unmount_with_dissect(mount_point, verbose)
+}
+
 /// Check if a loop device's backing file differs from the expected path.
 /// `loop_dev` can be a symlink (e.g. `/dev/disk/by-loop-ref/name`) or a direct
 /// device path (`/dev/loopN`).
@@ -407,6 +431,16 @@ pub(crate) fn is_scope_enabled_for_current_environment(content: &str, scope_key:
 // Shared extension analysis (deduplicates ext.rs analysis functions)
 // ---------------------------------------------------------------------------
 
+/// Whether an extension without release files is a sysext and/or confext,
+/// judged by which of `usr/` and `etc/` it has.
+fn kinds_from_tree(path: &Path) -> (bool, bool) {
+    match (path.join("usr").is_dir(), path.join("etc").is_dir()) {
+        (true, false) => (true, false),
+        (false, true) => (false, true),
+        _ => (true, true),
+    }
+}
+
 /// After mounting an extension image at `mount_path`, detect whether it contains
 /// sysext and/or confext release files, and check scope for the current environment.
 ///
@@ -420,94 +454,31 @@ pub fn analyze_mounted_extension(
     version: &Option<String>,
     mount_path: &Path,
 ) -> (bool, bool, Option<String>) {
-    let mut is_sysext = false;
-    let mut is_confext = false;
-    let mut detected_version: Option<String> = version.clone();
-
-    // --- sysext release file detection ---
-    let sysext_release_path = mount_path
-        .join("usr/lib/extension-release.d")
-        .join(format!("extension-release.{name}"));
-
-    if sysext_release_path.exists() {
-        is_sysext = true;
+    let sysext = extension_release::find(mount_path, name, Hierarchy::Sysext);
+    let confext = extension_release::find(mount_path, name, Hierarchy::Confext);
+
+    let detected_version = version.clone().or_else(|| {
+        [&sysext, &confext]
+            .into_iter()
+            .flatten()
+            .find_map(|release| release.version.clone())
+    });
+
+    // Without release files, go by the tree: a confext-only extension (no
+    // usr/) must not be linked into /run/extensions and vice versa. Only
+    // when neither or both trees exist is it treated as both.
+    let (is_sysext, is_confext) = if sysext.is_none() && confext.is_none() {
+        kinds_from_tree(mount_path)
     } else {
-        let sysext_dir = mount_path.join("usr/lib/extension-release.d");
-        if sysext_dir.exists() {
-            if let Ok(entries) = fs::read_dir(&sysext_dir) {
-                let prefix = format!("extension-release.{name}-");
-                for entry in entries.flatten() {
-                    let filename = entry.file_name();
-                    let filename_str = filename.to_string_lossy();
-                    if filename_str.starts_with(&prefix) {
-                        is_sysext = true;
-                        if detected_version.is_none() {
-                            let ver = filename_str.strip_prefix(&prefix).unwrap_or("");
-                            if !ver.is_empty() {
-                                detected_version = Some(ver.to_string());
-                            }
-                        }
-                        break;
-                    }
-                }
-            }
-        }
-    }
-
-    // --- confext release file detection ---
-    let confext_release_path = mount_path
-        .join("etc/extension-release.d")
-        .join(format!("extension-release.{name}"));
-
-    if confext_release_path.exists() {
-        is_confext = true;
-    } else {
-        let confext_dir = mount_path.join("etc/extension-release.d");
-        if confext_dir.exists() {
-            if let Ok(entries) = fs::read_dir(&confext_dir) {
-                let prefix = format!("extension-release.{name}-");
-                for entry in entries.flatten() {
-                    let filename = entry.file_name();
-                    let filename_str = filename.to_string_lossy();
-                    if filename_str.starts_with(&prefix) {
-                        is_confext = true;
-                        if detected_version.is_none() {
-                            let ver = filename_str.strip_prefix(&prefix).unwrap_or("");
-                            if !ver.is_empty() {
-                                detected_version = Some(ver.to_string());
-                            }
-                        }
-                        break;
-                    }
-                }
-            }
-        }
-    }
-
-    // Default to both if no release files found
-    if !is_sysext && !is_confext {
-        is_sysext = true;
-        is_confext = true;
-    }
-
-    // Scope checking
-    let scope_check_name = if let Some(ref ver) = detected_version {
-        format!("{name}-{ver}")
-    } else {
-        name.to_string()
-    };
-
-    let sysext_enabled = if is_sysext {
-        is_sysext_enabled_for_current_environment(mount_path, &scope_check_name)
-    } else {
-        false
+        (sysext.is_some(), confext.is_some())
     };
 
-    let confext_enabled = if is_confext {
-        is_confext_enabled_for_current_environment(mount_path, &scope_check_name)
-    } else {
-        false
+    // Scope checking; extensions without a release file are in scope
+    let in_scope = |release: &Option<std::sync::Arc<ReleaseFile>>| {
+        release.as_ref().is_none_or(|release| release.in_scope)
     };
+    let sysext_enabled = is_sysext && in_scope(&sysext);
+    let confext_enabled = is_confext && in_scope(&confext);
 
     (sysext_enabled, confext_enabled, detected_version)
 }
@@ -562,10 +533,7 @@ impl ImageAdaptor for RawAdaptor {
             return Ok(());
         }
 
-        let entries = fs::read_dir(loop_ref_dir).map_err(|e| SystemdError::CommandFailed {
-            command: "read_dir".to_string(),
-            source: e,
-        })?;
+        let entries = fs::read_dir(loop_ref_dir).context("read directory", loop_ref_dir)?;
 
         for entry in entries.flatten() {
             if let Some(loop_name) = entry.file_name().to_str() {
@@ -585,6 +553,11 @@ impl ImageAdaptor for RawAdaptor {
         check_backing_file_changed(Path::new(&loop_ref), expected_path)
     }
 
+    fn loop_device(&self, mount_name: &str) -> Option<PathBuf> {
+        let loop_ref = crate::loop_device::by_loop_ref(mount_name);
+        loop_ref.symlink_metadata().is_ok().then_some(loop_ref)
+    }
+
     fn type_tag(&self) -> ImageTypeTag {
         ImageTypeTag::Raw
     }
@@ -618,7 +591,7 @@ impl KabAdaptor {
             let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
             format!("{temp_base}/avocado/kab-loops")
         } else {
-            "/run/avocado/kab-loops".to_string()
+            crate::user_mode::system_path("/run/avocado/kab-loops")
         }
     }
 
@@ -626,43 +599,25 @@ impl KabAdaptor {
     fn find_image_entry(path: &Path) -> Result<KabEntry, SystemdError> {
         use std::io::{Read, Seek, SeekFrom};
 
-        let mut file = fs::File::open(path).map_err(|e| SystemdError::CommandFailed {
-            command: format!("open KAB file {}", path.display()),
-            source: e,
-        })?;
+        let mut file = fs::File::open(path).context("open KAB file", path)?;
 
-        let file_len = file
-            .metadata()
-            .map_err(|e| SystemdError::CommandFailed {
-                command: "stat KAB file".to_string(),
-                source: e,
-            })?
-            .len();
+        let file_len = file.metadata().context("stat KAB file", path)?.len();
 
         if file_len < KAB_SIGNATURE_LEN + KAB_FOOTER_LEN {
-            return Err(SystemdError::ConfigurationError {
-                message: format!(
-                    "KAB file too small: {} bytes ({})",
-                    file_len,
-                    path.display()
-                ),
+            return Err(SystemdError::InvalidImage {
+                path: path.to_path_buf(),
+                reason: format!("KAB file too small: {file_len} bytes"),
             });
         }
 
         // Read footer (12 bytes before the 256-byte signature at EOF)
         let footer_offset = file_len - KAB_SIGNATURE_LEN - KAB_FOOTER_LEN;
         file.seek(SeekFrom::Start(footer_offset))
-            .map_err(|e| SystemdError::CommandFailed {
-                command: "seek to KAB footer".to_string(),
-                source: e,
-            })?;
+            .context("seek to the footer of KAB file", path)?;
 
         let mut footer_buf = [0u8; 12];
         file.read_exact(&mut footer_buf)
-            .map_err(|e| SystemdError::CommandFailed {
-                command: "read KAB footer".to_string(),
-                source: e,
-            })?;
+            .context("read the footer of KAB file", path)?;
 
         let symbol_table_len = u16::from_be_bytes([footer_buf[0], footer_buf[1]]) as u64;
         let directory_count = u16::from_be_bytes([footer_buf[2], footer_buf[3]]) as usize;
@@ -672,12 +627,10 @@ impl KabAdaptor {
             u32::from_be_bytes([footer_buf[8], footer_buf[9], footer_buf[10], footer_buf[11]]);
 
         if marker != KAB_DIRECTORY_MARKER {
-            return Err(SystemdError::ConfigurationError {
-                message: format!(
-                    "Invalid KAB directory marker: 0x{:08X} (expected 0x{:08X}) in {}",
-                    marker,
-                    KAB_DIRECTORY_MARKER,
-                    path.display()
+            return Err(SystemdError::InvalidImage {
+                path: path.to_path_buf(),
+                reason: format!(
+                    "invalid KAB directory marker 0x{marker:08X} (expected 0x{KAB_DIRECTORY_MARKER:08X})"
                 ),
             });
         }
@@ -685,17 +638,11 @@ impl KabAdaptor {
         // Read directory table
         let dir_offset = footer_offset - symbol_table_len - directory_len;
         file.seek(SeekFrom::Start(dir_offset))
-            .map_err(|e| SystemdError::CommandFailed {
-                command: "seek to KAB directory".to_string(),
-                source: e,
-            })?;
+            .context("seek to the directory of KAB file", path)?;
 
         let mut dir_buf = vec![0u8; directory_len as usize];
         file.read_exact(&mut dir_buf)
-            .map_err(|e| SystemdError::CommandFailed {
-                command: "read KAB directory".to_string(),
-                source: e,
-            })?;
+            .context("read the directory of KAB file", path)?;
 
         // Parse directory entries to find "layer.img"
         // Each entry: u16 name_len, name bytes, u32 offset, u32 len, u8 flags,
@@ -740,33 +687,27 @@ impl KabAdaptor {
             }
         }
 
-        Err(SystemdError::ConfigurationError {
-            message: format!(
-                "No layer.img entry found in KAB directory table ({})",
-                path.display()
-            ),
+        Err(SystemdError::InvalidImage {
+            path: path.to_path_buf(),
+            reason: "no layer.img entry in the KAB directory table".to_string(),
         })
     }
 
     /// Create an offset-based loop device exposing the inner image.
     /// Returns the loop device path (e.g. `/dev/loop0`).
     fn setup_offset_loop(kab_path: &Path, entry: &KabEntry) -> Result<PathBuf, SystemdError> {
-        let output = ProcessCommand::new("losetup")
-            .args([
+        let output = crate::timeouts::output(
+            ProcessCommand::new("losetup").args([
                 "--find",
                 "--show",
                 "--read-only",
                 &format!("--offset={}", entry.offset),
                 &format!("--sizelimit={}", entry.len),
                 kab_path.to_str().unwrap_or(""),
-            ])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(|e| SystemdError::CommandFailed {
-                command: "losetup".to_string(),
-                source: e,
-            })?;
+            ]),
+            crate::timeouts::TimeoutKind::LoopMount,
+        )
+        .map_err(|e| e.into_systemd_error("losetup"))?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -784,18 +725,10 @@ impl KabAdaptor {
     /// Save outer loop device path for later cleanup.
     fn save_loop_state(mount_name: &str, loop_dev: &Path) -> Result<(), SystemdError> {
         let dir = Self::kab_loops_dir();
-        fs::create_dir_all(&dir).map_err(|e| SystemdError::CommandFailed {
-            command: "create_dir_all kab-loops".to_string(),
-            source: e,
-        })?;
+        fs::create_dir_all(&dir).context("create directory", &dir)?;
 
         let state_path = format!("{dir}/{mount_name}");
-        fs::write(&state_path, loop_dev.to_str().unwrap_or("")).map_err(|e| {
-            SystemdError::CommandFailed {
-                command: "write kab loop state".to_string(),
-                source: e,
-            }
-        })?;
+        fs::write(&state_path, loop_dev.to_str().unwrap_or("")).context("write", &state_path)?;
         Ok(())
     }
 
@@ -809,15 +742,11 @@ impl KabAdaptor {
 
     /// Detach the outer offset loop device.
     fn detach_offset_loop(loop_dev: &Path) -> Result<(), SystemdError> {
-        let output = ProcessCommand::new("losetup")
-            .args(["-d", loop_dev.to_str().unwrap_or("")])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(|e| SystemdError::CommandFailed {
-                command: "losetup -d".to_string(),
-                source: e,
-            })?;
+        let output = crate::timeouts::output(
+            ProcessCommand::new("losetup").args(["-d", loop_dev.to_str().unwrap_or("")]),
+            crate::timeouts::TimeoutKind::LoopMount,
+        )
+        .map_err(|e| e.into_systemd_error("losetup -d"))?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -859,10 +788,7 @@ impl ImageAdaptor for KabAdaptor {
 
         if is_test_mode() {
             // In test mode, skip actual losetup and dissect
-            fs::create_dir_all(&mount_point).map_err(|e| SystemdError::CommandFailed {
-                command: "create_dir_all".to_string(),
-                source: e,
-            })?;
+            fs::create_dir_all(&mount_point).context("create directory", &mount_point)?;
             if verbose {
                 println!("Test mode: skipping mount for KAB {mount_name}");
             }
@@ -925,10 +851,7 @@ impl ImageAdaptor for KabAdaptor {
             return Ok(());
         }
 
-        let entries = fs::read_dir(&loops_dir).map_err(|e| SystemdError::CommandFailed {
-            command: "read_dir kab-loops".to_string(),
-            source: e,
-        })?;
+        let entries = fs::read_dir(&loops_dir).context("read directory", &loops_dir)?;
 
         for entry in entries.flatten() {
             if let Some(mount_name) = entry.file_name().to_str() {
@@ -954,6 +877,10 @@ impl ImageAdaptor for KabAdaptor {
         }
     }
 
+    fn loop_device(&self, mount_name: &str) -> Option<PathBuf> {
+        Self::read_loop_state(mount_name)
+    }
+
     fn type_tag(&self) -> ImageTypeTag {
         ImageTypeTag::Kab
     }
@@ -980,6 +907,18 @@ mod tests {
     use super::*;
     use crate::commands::test_env::ENV_VAR_MUTEX;
 
+    #[test]
+    fn test_kinds_from_tree() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(kinds_from_tree(dir.path()), (true, true));
+        fs::create_dir(dir.path().join("etc")).unwrap();
+        assert_eq!(kinds_from_tree(dir.path()), (false, true));
+        fs::create_dir(dir.path().join("usr")).unwrap();
+        assert_eq!(kinds_from_tree(dir.path()), (true, true));
+        fs::remove_dir(dir.path().join("etc")).unwrap();
+        assert_eq!(kinds_from_tree(dir.path()), (true, false));
+    }
+
     #[test]
     fn test_image_type_from_manifest() {
         // None or unknown defaults to Raw
@@ -0,0 +1,536 @@
+//! `avocadoctl init` — first-device setup.
+//!
+//! Writes a configuration file, creates the extensions and os-releases
+//! directories, installs the avocadoctl socket and service units and checks
+//! that the external tools avocadoctl runs are available, then prints a
+//! summary of what it did. Values are asked for on a terminal and taken from
+//! flags (or their defaults) otherwise. Existing files are kept unless
+//! `--force` is given, so running it again is safe.
+
+use crate::config::Config;
+use crate::output::OutputManager;
+use clap::{Arg, ArgMatches, Command};
+use serde::Serialize;
+use std::fs;
+use std::io::{BufRead, IsTerminal, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+const MUTABLE_VALUES: [&str; 6] = [
+    "no",
+    "auto",
+    "yes",
+    "import",
+    "ephemeral",
+    "ephemeral-import",
+];
+
+/// Units installed by `init`, with the one enabled afterwards first.
+const UNITS: &[(&str, &str)] = &[
+    (
+        "avocadoctl.socket",
+        include_str!("../../systemd/avocadoctl.socket"),
+    ),
+    (
+        "avocadoctl.service",
+        include_str!("../../systemd/avocadoctl.service"),
+    ),
+];
+
+/// Tools checked by `init`; merging is impossible without the first one.
+const TOOLS: &[&str] = &[
+    "systemd-sysext",
+    "systemd-confext",
+    "systemd-dissect",
+    "systemd-mount",
+    "depmod",
+    "modprobe",
+];
+
+/// Create the init command definition
+pub fn create_command() -> Command {
+    Command::new("init")
+        .about("Set up a device: configuration, directories, systemd units and tool checks")
+        .arg(
+            Arg::new("extensions-dir")
+                .long("extensions-dir")
+                .value_name("DIR")
+                .help(
+                    "Directory holding extension images (default: from the current configuration)",
+                ),
+        )
+        .arg(
+            Arg::new("sysext-mutable")
+                .long("sysext-mutable")
+                .value_name("MODE")
+                .help("Mutability of /usr and /opt")
+                .value_parser(MUTABLE_VALUES)
+                .default_value("ephemeral"),
+        )
+        .arg(
+            Arg::new("confext-mutable")
+                .long("confext-mutable")
+                .value_name("MODE")
+                .help("Mutability of /etc")
+                .value_parser(MUTABLE_VALUES)
+                .default_value("ephemeral"),
+        )
+        .arg(
+            Arg::new("unit-dir")
+                .long("unit-dir")
+                .value_name("DIR")
+                .help("Directory to install systemd units to (default: /etc/systemd/system)"),
+        )
+        .arg(
+            Arg::new("no-units")
+                .long("no-units")
+                .help("Do not install or enable the systemd units")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Do not ask; use the flags and defaults")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Overwrite an existing configuration file and units")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
+
+/// Outcome of one setup step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum StepState {
+    Created,
+    Written,
+    Enabled,
+    Kept,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct Step {
+    item: String,
+    path: String,
+    state: StepState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolCheck {
+    tool: &'static str,
+    /// Program that will be run, when it was found.
+    program: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct InitSummary {
+    steps: Vec<Step>,
+    tools: Vec<ToolCheck>,
+    systemd_version: Option<u32>,
+    ok: bool,
+}
+
+/// Values `init` sets up the device with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InitAnswers {
+    extensions_dir: String,
+    sysext_mutable: String,
+    confext_mutable: String,
+    install_units: bool,
+}
+
+/// Ask `question` on stdout and read the answer from `input`; an empty
+/// answer (or end of input) selects `default`. Answers `accept` rejects are
+/// asked again.
+fn ask<R: BufRead>(
+    input: &mut R,
+    question: &str,
+    default: &str,
+    accept: impl Fn(&str) -> bool,
+) -> String {
+    loop {
+        print!("{question} [{default}]: ");
+        let _ = std::io::stdout().flush();
+        let mut line = String::new();
+        if input.read_line(&mut line).unwrap_or(0) == 0 {
+            println!();
+            return default.to_string();
+        }
+        let answer = line.trim();
+        if answer.is_empty() {
+            return default.to_string();
+        }
+        if accept(answer) {
+            return answer.to_string();
+        }
+        println!("  '{answer}' is not a valid answer");
+    }
+}
+
+fn ask_answers<R: BufRead>(input: &mut R, defaults: InitAnswers) -> InitAnswers {
+    let mutable = |v: &str| MUTABLE_VALUES.contains(&v);
+    let extensions_dir = ask(
+        input,
+        "Extensions directory",
+        &defaults.extensions_dir,
+        |v| v.starts_with('/'),
+    );
+    let sysext_mutable = ask(
+        input,
+        "Mutability of /usr and /opt (no, auto, yes, import, ephemeral, ephemeral-import)",
+        &defaults.sysext_mutable,
+        mutable,
+    );
+    let confext_mutable = ask(
+        input,
+        "Mutability of /etc",
+        &defaults.confext_mutable,
+        mutable,
+    );
+    let install_units = ask(
+        input,
+        "Install and enable the avocadoctl systemd units (yes/no)",
+        if defaults.install_units { "yes" } else { "no" },
+        |v| matches!(v, "yes" | "y" | "no" | "n"),
+    )
+    .starts_with('y');
+    InitAnswers {
+        extensions_dir,
+        sysext_mutable,
+        confext_mutable,
+        install_units,
+    }
+}
+
+/// Configuration file content for `answers`.
+fn render_config(answers: &InitAnswers) -> String {
+    format!(
+        "# Written by `avocadoctl init`. See example-config.toml for all options.\n\
+         \n\
+         [avocado.ext]\n\
+         dir = \"{}\"\n\
+         sysext_mutable = \"{}\"\n\
+         confext_mutable = \"{}\"\n",
+        answers.extensions_dir, answers.sysext_mutable, answers.confext_mutable
+    )
+}
+
+/// Create `dir` with mode 0755 unless it exists.
+fn ensure_dir(item: &str, dir: &Path) -> Step {
+    let path = dir.display().to_string();
+    if dir.is_dir() {
+        return Step {
+            item: item.to_string(),
+            path,
+            state: StepState::Kept,
+            detail: None,
+        };
+    }
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| fs::set_permissions(dir, fs::Permissions::from_mode(0o755)));
+    match result {
+        Ok(()) => Step {
+            item: item.to_string(),
+            path,
+            state: StepState::Created,
+            detail: None,
+        },
+        Err(e) => Step {
+            item: item.to_string(),
+            path,
+            state: StepState::Failed,
+            detail: Some(e.to_string()),
+        },
+    }
+}
+
+/// Write `content` to `path` unless it exists and `force` is unset.
+fn write_file(item: &str, path: &Path, content: &str, mode: u32, force: bool) -> Step {
+    let display = path.display().to_string();
+    if path.exists() && !force {
+        return Step {
+            item: item.to_string(),
+            path: display,
+            state: StepState::Kept,
+            detail: Some("exists; use --force to overwrite".to_string()),
+        };
+    }
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, content))
+        .and_then(|_| fs::set_permissions(path, fs::Permissions::from_mode(mode)));
+    match result {
+        Ok(()) => Step {
+            item: item.to_string(),
+            path: display,
+            state: StepState::Written,
+            detail: None,
+        },
+        Err(e) => Step {
+            item: item.to_string(),
+            path: display,
+            state: StepState::Failed,
+            detail: Some(e.to_string()),
+        },
+    }
+}
+
+/// Run `systemctl daemon-reload` and `systemctl enable` for the socket unit.
+fn enable_units() -> Step {
+    let (socket, _) = UNITS[0];
+    let command = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        "mock-systemctl"
+    } else {
+        "systemctl"
+    };
+    let mut failure = None;
+    for args in [vec!["daemon-reload"], vec!["enable", socket]] {
+        if let Some(result) = crate::backend::simulate("systemctl", &args) {
+            if let Err(e) = result {
+                failure = Some(e.to_string());
+            }
+            continue;
+        }
+        match ProcessCommand::new(command).args(&args).output() {
+            Ok(out) if out.status.success() => {}
+            Ok(out) => {
+                failure = Some(format!(
+                    "systemctl {} failed: {}",
+                    args.join(" "),
+                    String::from_utf8_lossy(&out.stderr).trim()
+                ))
+            }
+            Err(e) => failure = Some(format!("failed to run {command}: {e}")),
+        }
+        if failure.is_some() {
+            break;
+        }
+    }
+    Step {
+        item: "enable".to_string(),
+        path: socket.to_string(),
+        state: if failure.is_some() {
+            StepState::Failed
+        } else {
+            StepState::Enabled
+        },
+        detail: failure,
+    }
+}
+
+fn test_base() -> Option<PathBuf> {
+    std::env::var("AVOCADO_TEST_MODE").ok().map(|_| {
+        PathBuf::from(std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string()))
+            .join("avocado")
+    })
+}
+
+fn os_releases_dir() -> PathBuf {
+    match test_base() {
+        Some(base) => base.join("os-releases"),
+        None => PathBuf::from(crate::user_mode::system_path(
+            "/var/lib/avocado/os-releases",
+        )),
+    }
+}
+
+fn default_unit_dir() -> PathBuf {
+    match test_base() {
+        Some(base) => base.join("systemd/system"),
+        None => PathBuf::from("/etc/systemd/system"),
+    }
+}
+
+/// Handle `avocadoctl init`. `config_path` is where the configuration file
+/// is written; `config` supplies the defaults for unanswered values.
+pub fn handle_command(
+    matches: &ArgMatches,
+    config_path: &str,
+    config: &Config,
+    output: &OutputManager,
+) {
+    let force = matches.get_flag("force");
+    let user_mode = crate::user_mode::is_user();
+    let mut answers = InitAnswers {
+        extensions_dir: matches
+            .get_one::<String>("extensions-dir")
+            .cloned()
+            .unwrap_or_else(|| config.get_extensions_dir()),
+        sysext_mutable: matches
+            .get_one::<String>("sysext-mutable")
+            .cloned()
+            .expect("sysext-mutable has a default"),
+        confext_mutable: matches
+            .get_one::<String>("confext-mutable")
+            .cloned()
+            .expect("confext-mutable has a default"),
+        install_units: !matches.get_flag("no-units") && !user_mode,
+    };
+    if !matches.get_flag("yes") && !output.is_json() && std::io::stdin().is_terminal() {
+        answers = ask_answers(&mut std::io::stdin().lock(), answers);
+    }
+
+    let mut steps = vec![write_file(
+        "config",
+        Path::new(config_path),
+        &render_config(&answers),
+        0o644,
+        force,
+    )];
+    steps.push(ensure_dir("extensions", Path::new(&answers.extensions_dir)));
+    let os_releases = os_releases_dir();
+    steps.push(ensure_dir("os-releases", &os_releases));
+    let version_id = crate::commands::ext::read_os_version_id();
+    if version_id != "unknown" {
+        steps.push(ensure_dir("os-release", &os_releases.join(&version_id)));
+    }
+
+    if answers.install_units {
+        let unit_dir = matches
+            .get_one::<String>("unit-dir")
+            .map(PathBuf::from)
+            .unwrap_or_else(default_unit_dir);
+        for (name, content) in UNITS {
+            steps.push(write_file(
+                "unit",
+                &unit_dir.join(name),
+                content,
+                0o644,
+                force,
+            ));
+        }
+        if steps.iter().all(|s| s.state != StepState::Failed) {
+            steps.push(enable_units());
+        }
+    } else {
+        steps.push(Step {
+            item: "unit".to_string(),
+            path: "-".to_string(),
+            state: StepState::Skipped,
+            detail: Some(if user_mode { "user mode" } else { "--no-units" }.to_string()),
+        });
+    }
+
+    let tools: Vec<ToolCheck> = TOOLS
+        .iter()
+        .map(|tool| ToolCheck {
+            tool,
+            program: crate::tools::locate(tool),
+        })
+        .collect();
+    let systemd_version = crate::systemd_caps::detect().version;
+    let ok = steps.iter().all(|s| s.state != StepState::Failed) && tools[0].program.is_some();
+    let summary = InitSummary {
+        steps,
+        tools,
+        systemd_version,
+        ok,
+    };
+
+    print_summary(&summary, output);
+    if !summary.ok {
+        output.exit(1);
+    }
+}
+
+fn print_summary(summary: &InitSummary, output: &OutputManager) {
+    if output.is_json() {
+        output.result_fields(summary);
+        return;
+    }
+
+    println!("Setup:");
+    for step in &summary.steps {
+        let state = match step.state {
+            StepState::Created => "created",
+            StepState::Written => "written",
+            StepState::Enabled => "enabled",
+            StepState::Kept => "kept",
+            StepState::Skipped => "skipped",
+            StepState::Failed => "FAILED",
+        };
+        let detail = step
+            .detail
+            .as_ref()
+            .map(|d| format!(" ({d})"))
+            .unwrap_or_default();
+        println!("  {:<12} {:<8} {}{detail}", step.item, state, step.path);
+    }
+    println!("Tools:");
+    for check in &summary.tools {
+        println!(
+            "  {:<16} {}",
+            check.tool,
+            check.program.as_deref().unwrap_or("MISSING")
+        );
+    }
+    match summary.systemd_version {
+        Some(version) => println!("systemd:     {version}"),
+        None => println!("systemd:     version unknown"),
+    }
+
+    if summary.ok {
+        output.success("Init", "Device is set up for avocadoctl");
+    } else {
+        output.error(
+            "Init",
+            "Setup is incomplete; fix the items marked FAILED or MISSING and run 'avocadoctl init' again",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ask_answers_uses_defaults_and_rejects_invalid() {
+        let defaults = InitAnswers {
+            extensions_dir: "/var/lib/avocado/images".to_string(),
+            sysext_mutable: "ephemeral".to_string(),
+            confext_mutable: "ephemeral".to_string(),
+            install_units: true,
+        };
+        let mut input = "/data/ext\nmaybe\nyes\n\nn\n".as_bytes();
+        let answers = ask_answers(&mut input, defaults.clone());
+        assert_eq!(
+            answers,
+            InitAnswers {
+                extensions_dir: "/data/ext".to_string(),
+                sysext_mutable: "yes".to_string(),
+                confext_mutable: "ephemeral".to_string(),
+                install_units: false,
+            }
+        );
+
+        let answers = ask_answers(&mut "".as_bytes(), defaults.clone());
+        assert_eq!(answers, defaults);
+    }
+
+    #[test]
+    fn test_render_config_parses() {
+        let answers = InitAnswers {
+            extensions_dir: "/data/ext".to_string(),
+            sysext_mutable: "no".to_string(),
+            confext_mutable: "import".to_string(),
+            install_units: false,
+        };
+        let config: Config = toml::from_str(&render_config(&answers)).unwrap();
+        assert_eq!(config.avocado.ext.dir, "/data/ext");
+        assert_eq!(config.avocado.ext.sysext_mutable.as_deref(), Some("no"));
+        assert_eq!(
+            config.avocado.ext.confext_mutable.as_deref(),
+            Some("import")
+        );
+    }
+}
@@ -0,0 +1,636 @@
+//! `avocadoctl ext lint <path>` — static policy checks for extension builds.
+//!
+//! Unlike `ext test`, nothing is merged and no command runs: the
+//! extension-release files are read and checked against a set of rules with
+//! stable IDs and severities. Findings are printed as text, as JSON with
+//! `-o json`, or as SARIF 2.1.0 with `--sarif` so CI can annotate pull
+//! requests. The command exits non-zero when any error-level finding is
+//! reported.
+
+use crate::commands::harness::{
+    find_release_files, hook_program_found, hook_search_path, with_extension_tree,
+};
+use crate::commands::image_adaptor::parse_scope_from_release_content;
+use crate::diagnostics::Diagnose;
+use crate::extension_release::{parse_hook_declarations, parse_service_declarations};
+use crate::output::OutputManager;
+use serde::Serialize;
+use std::path::Path;
+
+/// How serious a finding is. Values match SARIF result levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARN",
+        }
+    }
+}
+
+/// A lint rule. IDs are never reused or renumbered.
+#[derive(Debug, Clone, Copy)]
+pub struct Rule {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+}
+
+pub const MISSING_RELEASE_FILE: Rule = Rule {
+    id: "AVL001",
+    name: "missing-release-file",
+    severity: Severity::Error,
+    description: "The extension has no extension-release file",
+};
+pub const RELEASE_NAME_MISMATCH: Rule = Rule {
+    id: "AVL002",
+    name: "release-name-mismatch",
+    severity: Severity::Error,
+    description: "The extension-release file name does not match the image name",
+};
+pub const MISSING_OS_ID: Rule = Rule {
+    id: "AVL003",
+    name: "missing-os-id",
+    severity: Severity::Error,
+    description: "ID= is missing from the extension-release file",
+};
+pub const OS_ID_MISMATCH: Rule = Rule {
+    id: "AVL004",
+    name: "os-id-mismatch",
+    severity: Severity::Error,
+    description: "ID= does not match the target OS",
+};
+pub const MISSING_SCOPE: Rule = Rule {
+    id: "AVL005",
+    name: "missing-scope",
+    severity: Severity::Warning,
+    description:
+        "SYSEXT_SCOPE/CONFEXT_SCOPE is not set, so the extension merges in every environment",
+};
+pub const UNKNOWN_SCOPE: Rule = Rule {
+    id: "AVL006",
+    name: "unknown-scope",
+    severity: Severity::Error,
+    description: "The scope names an environment other than initrd, system or portable",
+};
+pub const VERSION_ID_MISMATCH: Rule = Rule {
+    id: "AVL007",
+    name: "version-id-mismatch",
+    severity: Severity::Error,
+    description: "VERSION_ID= does not match the target OS version",
+};
+pub const MISSING_VERSION_ID: Rule = Rule {
+    id: "AVL008",
+    name: "missing-version-id",
+    severity: Severity::Warning,
+    description: "Neither VERSION_ID= nor an extension level is set while ID= names a specific OS",
+};
+pub const BROAD_HOOK: Rule = Rule {
+    id: "AVL009",
+    name: "broad-hook",
+    severity: Severity::Warning,
+    description: "A hook command runs a shell, uses wildcards or acts beyond the extension",
+};
+pub const HOOK_NOT_FOUND: Rule = Rule {
+    id: "AVL010",
+    name: "hook-not-found",
+    severity: Severity::Warning,
+    description: "A hook command is not provided by the extension or the host PATH",
+};
+pub const INVALID_EOL: Rule = Rule {
+    id: "AVL011",
+    name: "invalid-eol",
+    severity: Severity::Error,
+    description: "AVOCADO_EOL is not a YYYY-MM-DD date",
+};
+pub const UNKNOWN_HOOK_CONDITION: Rule = Rule {
+    id: "AVL012",
+    name: "unknown-hook-condition",
+    severity: Severity::Error,
+    description: "A hook command's [condition] names an environment other than initrd or system, so it never runs",
+};
+pub const UNKNOWN_SERVICE_DEPENDENCY: Rule = Rule {
+    id: "AVL013",
+    name: "unknown-service-dependency",
+    severity: Severity::Error,
+    description: "An AVOCADO_ENABLE_SERVICES entry declares a dependency type other than wants, requires, bindsto or after",
+};
+pub const INVALID_ON_MERGE_PRIORITY: Rule = Rule {
+    id: "AVL014",
+    name: "invalid-on-merge-priority",
+    severity: Severity::Error,
+    description:
+        "AVOCADO_ON_MERGE_PRIORITY is not an integer, so the hooks run at the default priority",
+};
+
+/// Every rule, in ID order.
+pub const RULES: &[Rule] = &[
+    MISSING_RELEASE_FILE,
+    RELEASE_NAME_MISMATCH,
+    MISSING_OS_ID,
+    OS_ID_MISMATCH,
+    MISSING_SCOPE,
+    UNKNOWN_SCOPE,
+    VERSION_ID_MISMATCH,
+    MISSING_VERSION_ID,
+    BROAD_HOOK,
+    HOOK_NOT_FOUND,
+    INVALID_EOL,
+    UNKNOWN_HOOK_CONDITION,
+    UNKNOWN_SERVICE_DEPENDENCY,
+    INVALID_ON_MERGE_PRIORITY,
+];
+
+/// One rule violation.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub name: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// File relative to the extension root.
+    pub file: Option<String>,
+    /// 1-based line in `file`.
+    pub line: Option<usize>,
+}
+
+impl Finding {
+    fn new(rule: Rule, message: impl Into<String>) -> Self {
+        Self {
+            rule: rule.id,
+            name: rule.name,
+            severity: rule.severity,
+            message: message.into(),
+            file: None,
+            line: None,
+        }
+    }
+
+    fn at(mut self, file: &str, line: Option<usize>) -> Self {
+        self.file = Some(file.to_string());
+        self.line = line;
+        self
+    }
+}
+
+/// The OS an extension is checked against.
+#[derive(Debug, Clone, Default)]
+pub struct LintTarget {
+    pub os_id: Option<String>,
+    pub version_id: Option<String>,
+}
+
+impl LintTarget {
+    /// The given values, falling back to the host os-release for any left out.
+    pub fn resolve(os_id: Option<&str>, version_id: Option<&str>) -> Self {
+        let host = crate::os_release::host().unwrap_or_default();
+        Self {
+            os_id: os_id.map(str::to_string).or(host.id),
+            version_id: version_id.map(str::to_string).or(host.version_id),
+        }
+    }
+}
+
+/// 1-based line of the first `KEY=` assignment in release file content.
+fn key_line(content: &str, key: &str) -> Option<usize> {
+    content
+        .lines()
+        .position(|line| line.trim().starts_with(&format!("{key}=")))
+        .map(|index| index + 1)
+}
+
+/// Why a hook command is overly broad, if it is.
+pub(crate) fn broad_hook_reason(command: &str) -> Option<&'static str> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let program = words.first()?.rsplit('/').next().unwrap_or_default();
+    if matches!(program, "sh" | "bash" | "dash" | "zsh") {
+        return Some("runs a shell");
+    }
+    if words[1..].iter().any(|w| w.contains(['*', '?'])) {
+        return Some("uses wildcards");
+    }
+    if program == "systemctl"
+        && words[1..].iter().any(|w| {
+            matches!(
+                *w,
+                "--all" | "daemon-reexec" | "isolate" | "reboot" | "poweroff" | "halt"
+            )
+        })
+    {
+        return Some("acts on the whole system");
+    }
+    if program == "rm"
+        && words[1..]
+            .iter()
+            .any(|w| w.starts_with('-') && !w.starts_with("--") && w.contains(['r', 'R']))
+    {
+        return Some("removes files recursively");
+    }
+    None
+}
+
+/// Lint the extension tree at `tree`, whose image name is `name`.
+pub fn lint_tree(name: &str, tree: &Path, target: &LintTarget) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let releases = find_release_files(tree);
+    if releases.is_empty() {
+        findings.push(Finding::new(
+            MISSING_RELEASE_FILE,
+            "no extension-release file in usr/lib/extension-release.d or etc/extension-release.d",
+        ));
+        return findings;
+    }
+
+    let search_path = hook_search_path(tree);
+    for release in &releases {
+        let file = release.relative_path.as_str();
+        let content = release.content.as_str();
+
+        if release.name != name {
+            findings.push(
+                Finding::new(
+                    RELEASE_NAME_MISMATCH,
+                    format!(
+                        "extension-release.{} does not match image name '{name}'",
+                        release.name
+                    ),
+                )
+                .at(file, None),
+            );
+        }
+
+        let fields = release.fields();
+        let os_id = fields.id.as_deref();
+        match (os_id, target.os_id.as_deref()) {
+            (None, _) => {
+                findings.push(Finding::new(MISSING_OS_ID, "ID= is missing").at(file, None))
+            }
+            (Some("_any"), _) | (Some(_), None) => {}
+            (Some(id), Some(expected)) if id != expected => findings.push(
+                Finding::new(
+                    OS_ID_MISMATCH,
+                    format!("ID={id} does not match target ID={expected}"),
+                )
+                .at(file, key_line(content, "ID")),
+            ),
+            _ => {}
+        }
+
+        let scope_key = if release.kind == "sysext" {
+            "SYSEXT_SCOPE"
+        } else {
+            "CONFEXT_SCOPE"
+        };
+        let scopes = parse_scope_from_release_content(content, scope_key);
+        if scopes.is_empty() {
+            findings.push(
+                Finding::new(
+                    MISSING_SCOPE,
+                    format!("{scope_key} is not set; the extension merges in initrd and system"),
+                )
+                .at(file, None),
+            );
+        }
+        for scope in scopes
+            .iter()
+            .filter(|s| !matches!(s.as_str(), "initrd" | "system" | "portable"))
+        {
+            findings.push(
+                Finding::new(
+                    UNKNOWN_SCOPE,
+                    format!("{scope_key} names unknown scope '{scope}'"),
+                )
+                .at(file, key_line(content, scope_key)),
+            );
+        }
+
+        // systemd compares SYSEXT_LEVEL/CONFEXT_LEVEL instead of VERSION_ID
+        // when the extension sets one
+        let level_key = if release.kind == "sysext" {
+            "SYSEXT_LEVEL"
+        } else {
+            "CONFEXT_LEVEL"
+        };
+        if os_id.is_some_and(|id| id != "_any") && fields.get(level_key).is_none() {
+            match (fields.version_id.as_deref(), target.version_id.as_deref()) {
+                (None, _) => findings.push(
+                    Finding::new(
+                        MISSING_VERSION_ID,
+                        format!("neither VERSION_ID= nor {level_key}= is set"),
+                    )
+                    .at(file, None),
+                ),
+                (Some(version), Some(expected)) if version != expected => findings.push(
+                    Finding::new(
+                        VERSION_ID_MISMATCH,
+                        format!("VERSION_ID={version} does not match target VERSION_ID={expected}"),
+                    )
+                    .at(file, key_line(content, "VERSION_ID")),
+                ),
+                _ => {}
+            }
+        }
+
+        if let Some(eol) = fields.get("AVOCADO_EOL") {
+            if !crate::extension_release::is_eol_date(eol) {
+                findings.push(
+                    Finding::new(
+                        INVALID_EOL,
+                        format!("AVOCADO_EOL={eol} is not a YYYY-MM-DD date"),
+                    )
+                    .at(file, key_line(content, "AVOCADO_EOL")),
+                );
+            }
+        }
+
+        if let Some(priority) = fields.get("AVOCADO_ON_MERGE_PRIORITY") {
+            if priority.trim().parse::<i32>().is_err() {
+                findings.push(
+                    Finding::new(
+                        INVALID_ON_MERGE_PRIORITY,
+                        format!("AVOCADO_ON_MERGE_PRIORITY={priority} is not an integer"),
+                    )
+                    .at(file, key_line(content, "AVOCADO_ON_MERGE_PRIORITY")),
+                );
+            }
+        }
+
+        for declaration in parse_service_declarations(content) {
+            for dependency in &declaration.unknown_dependencies {
+                findings.push(
+                    Finding::new(
+                        UNKNOWN_SERVICE_DEPENDENCY,
+                        format!(
+                            "AVOCADO_ENABLE_SERVICES entry '{}' has unknown dependency type '{dependency}'",
+                            declaration.service
+                        ),
+                    )
+                    .at(file, key_line(content, "AVOCADO_ENABLE_SERVICES")),
+                );
+            }
+        }
+
+        for hook in ["AVOCADO_ON_MERGE", "AVOCADO_ON_UNMERGE"]
+            .into_iter()
+            .flat_map(|key| parse_hook_declarations(content, key))
+        {
+            let key = hook.key.as_str();
+            let line = key_line(content, key);
+            if let Some(condition) = &hook.unknown_condition {
+                findings.push(
+                    Finding::new(
+                        UNKNOWN_HOOK_CONDITION,
+                        format!(
+                            "{key} command '{}' has unknown condition '[{condition}]'",
+                            hook.command
+                        ),
+                    )
+                    .at(file, line),
+                );
+            }
+            for part in hook
+                .command
+                .split(';')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+            {
+                if let Some(reason) = broad_hook_reason(part) {
+                    findings.push(
+                        Finding::new(BROAD_HOOK, format!("{key} command '{part}' {reason}"))
+                            .at(file, line),
+                    );
+                }
+                let program = part.split_whitespace().next().unwrap_or("");
+                if !hook_program_found(&search_path, program) {
+                    findings.push(
+                        Finding::new(
+                            HOOK_NOT_FOUND,
+                            format!("{key} program '{program}' not found in extension or PATH"),
+                        )
+                        .at(file, line),
+                    );
+                }
+            }
+        }
+    }
+    findings
+}
+
+/// The findings as a SARIF 2.1.0 log.
+pub fn sarif_report(path: &str, findings: &[Finding]) -> serde_json::Value {
+    let rules: Vec<serde_json::Value> = RULES
+        .iter()
+        .map(|rule| {
+            serde_json::json!({
+                "id": rule.id,
+                "name": rule.name,
+                "shortDescription": { "text": rule.description },
+                "defaultConfiguration": { "level": rule.severity },
+            })
+        })
+        .collect();
+    let results: Vec<serde_json::Value> = findings
+        .iter()
+        .map(|finding| {
+            let uri = match &finding.file {
+                Some(file) => format!("{}/{file}", path.trim_end_matches('/')),
+                None => path.to_string(),
+            };
+            let mut location = serde_json::json!({ "artifactLocation": { "uri": uri } });
+            if let Some(line) = finding.line {
+                location["region"] = serde_json::json!({ "startLine": line });
+            }
+            serde_json::json!({
+                "ruleId": finding.rule,
+                "level": finding.severity,
+                "message": { "text": finding.message },
+                "locations": [{ "physicalLocation": location }],
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "avocadoctl",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// CLI entry point for `ext lint`: prints the findings and exits non-zero on
+/// any error-level finding.
+pub fn run_extension_lint(path: &str, target: &LintTarget, sarif: bool, output: &OutputManager) {
+    let findings = match with_extension_tree(
+        Path::new(path),
+        "avocado-ext-lint",
+        output,
+        |name, tree, _| lint_tree(name, tree, target),
+    ) {
+        Ok(findings) => findings,
+        Err(e) => {
+            output.error_with("Extension Lint", &e.to_string(), &e.diagnose());
+            output.exit(1);
+        }
+    };
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Error)
+        .count();
+    let warnings = findings.len() - errors;
+
+    if sarif {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&sarif_report(path, &findings)).unwrap()
+        );
+    } else if output.is_json() {
+        let json = serde_json::json!({
+            "path": path,
+            "passed": errors == 0,
+            "errors": errors,
+            "warnings": warnings,
+            "findings": findings,
+        });
+        output.result_fields(&json);
+    } else {
+        for finding in &findings {
+            let location = match (&finding.file, finding.line) {
+                (Some(file), Some(line)) => format!("{file}:{line}"),
+                (Some(file), None) => file.clone(),
+                (None, _) => "-".to_string(),
+            };
+            println!(
+                "{:<5} {} {:<22} {location}: {}",
+                finding.severity.label(),
+                finding.rule,
+                finding.name,
+                finding.message
+            );
+        }
+        if !findings.is_empty() {
+            println!();
+        }
+        let summary = format!("{errors} error(s), {warnings} warning(s) in {path}");
+        if errors == 0 {
+            output.success("Extension Lint", &summary);
+        } else {
+            output.error("Extension Lint", &summary);
+        }
+    }
+
+    if errors > 0 {
+        output.exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn tree_with_release(name: &str, content: &str) -> TempDir {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("usr/lib/extension-release.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("extension-release.{name}")), content).unwrap();
+        tmp
+    }
+
+    fn rules_of(findings: &[Finding]) -> Vec<&'static str> {
+        findings.iter().map(|f| f.rule).collect()
+    }
+
+    #[test]
+    fn test_rule_ids_are_unique_and_sequential() {
+        for (index, rule) in RULES.iter().enumerate() {
+            assert_eq!(rule.id, format!("AVL{:03}", index + 1));
+        }
+    }
+
+    #[test]
+    fn test_clean_extension_has_no_findings() {
+        let tree = tree_with_release("app", "ID=avocado\nVERSION_ID=1.0\nSYSEXT_SCOPE=system\n");
+        let target = LintTarget {
+            os_id: Some("avocado".to_string()),
+            version_id: Some("1.0".to_string()),
+        };
+        assert!(lint_tree("app", tree.path(), &target).is_empty());
+    }
+
+    #[test]
+    fn test_policy_violations_are_reported_with_lines() {
+        let tree = tree_with_release(
+            "app",
+            "ID=avocado\nVERSION_ID=0.9\nSYSEXT_SCOPE=system kiosk\nAVOCADO_ON_MERGE=\"sh -c 'rm -rf /tmp/x'\"\nAVOCADO_ON_UNMERGE_INITRD=\"[kiosk] true\"\nAVOCADO_EOL=Q3-2026\nAVOCADO_ENABLE_SERVICES=\"app:wants,after db:needs\"\nAVOCADO_ON_MERGE_PRIORITY=early\n",
+        );
+        let target = LintTarget {
+            os_id: Some("avocado".to_string()),
+            version_id: Some("1.0".to_string()),
+        };
+        let findings = lint_tree("other", tree.path(), &target);
+        let rules = rules_of(&findings);
+        assert!(rules.contains(&RELEASE_NAME_MISMATCH.id));
+        assert!(rules.contains(&UNKNOWN_SCOPE.id));
+        assert!(rules.contains(&BROAD_HOOK.id));
+        assert!(rules.contains(&INVALID_EOL.id));
+        let priority = findings
+            .iter()
+            .find(|f| f.rule == INVALID_ON_MERGE_PRIORITY.id)
+            .unwrap();
+        assert_eq!(priority.line, Some(8));
+        let dependency = findings
+            .iter()
+            .find(|f| f.rule == UNKNOWN_SERVICE_DEPENDENCY.id)
+            .unwrap();
+        assert_eq!(dependency.line, Some(7));
+        assert!(dependency.message.contains("'db'"));
+        let condition = findings
+            .iter()
+            .find(|f| f.rule == UNKNOWN_HOOK_CONDITION.id)
+            .unwrap();
+        assert_eq!(condition.line, Some(5));
+        let version = findings
+            .iter()
+            .find(|f| f.rule == VERSION_ID_MISMATCH.id)
+            .unwrap();
+        assert_eq!(version.line, Some(2));
+        assert_eq!(
+            version.file.as_deref(),
+            Some("usr/lib/extension-release.d/extension-release.app")
+        );
+    }
+
+    #[test]
+    fn test_broad_hook_reason() {
+        assert_eq!(broad_hook_reason("bash -c true"), Some("runs a shell"));
+        assert_eq!(
+            broad_hook_reason("chmod 644 /etc/app/*"),
+            Some("uses wildcards")
+        );
+        assert_eq!(
+            broad_hook_reason("systemctl restart --all"),
+            Some("acts on the whole system")
+        );
+        assert_eq!(
+            broad_hook_reason("rm -rf /var/cache/app"),
+            Some("removes files recursively")
+        );
+        assert_eq!(broad_hook_reason("systemctl restart app.service"), None);
+        assert_eq!(broad_hook_reason("depmod -a"), None);
+    }
+}
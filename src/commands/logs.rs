@@ -0,0 +1,235 @@
+//! `avocadoctl logs` — view, follow and purge the recorded hook logs.
+//!
+//! The logs themselves are written and rotated by [`crate::hook_log`]; this
+//! command only reads them, or deletes them to free space. Everything runs
+//! in-process: the logs are plain files, with nothing for the daemon to do.
+
+use crate::hook_log::{self, HookRecord};
+use crate::output::OutputManager;
+use clap::{Arg, ArgMatches, Command};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::thread;
+use std::time::Duration;
+
+/// How often `logs tail --follow` checks the log for new records.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Create the logs command definition
+pub fn create_command() -> Command {
+    Command::new("logs")
+        .about("View, follow and purge the recorded output of extension hooks")
+        .subcommand_required(true)
+        .subcommand(Command::new("list").about("List hook logs with their size and span"))
+        .subcommand(
+            Command::new("view")
+                .about("Show every recorded run of an extension's hooks with full output")
+                .arg(extension_arg().required(true)),
+        )
+        .subcommand(
+            Command::new("tail")
+                .about("Show the last recorded runs of an extension's hooks")
+                .arg(extension_arg().required(true))
+                .arg(
+                    Arg::new("lines")
+                        .short('n')
+                        .long("lines")
+                        .value_name("N")
+                        .help("Number of runs to show")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::new("follow")
+                        .short('f')
+                        .long("follow")
+                        .help("Keep printing runs as they are recorded")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("purge")
+                .about("Delete hook logs (all of them unless an extension is given)")
+                .arg(extension_arg())
+                .arg(
+                    Arg::new("older-than")
+                        .long("older-than")
+                        .value_name("DAYS")
+                        .help("Only delete log files with no record newer than DAYS days")
+                        .value_parser(clap::value_parser!(u64)),
+                ),
+        )
+}
+
+fn extension_arg() -> Arg {
+    Arg::new("extension")
+        .value_name("EXTENSION")
+        .help("Extension name, as in ext info (or 'unattributed')")
+}
+
+/// Handle `avocadoctl logs`
+pub fn handle_command(matches: &ArgMatches, output: &OutputManager) {
+    match matches.subcommand() {
+        Some(("list", _)) => list_logs(output),
+        Some(("view", sub)) => view_log(extension(sub), output),
+        Some(("tail", sub)) => {
+            let lines = sub.get_one::<usize>("lines").copied().unwrap_or(10);
+            tail_log(extension(sub), lines, sub.get_flag("follow"), output);
+        }
+        Some(("purge", sub)) => purge_logs(
+            sub.get_one::<String>("extension").map(String::as_str),
+            sub.get_one::<u64>("older-than").copied(),
+            output,
+        ),
+        _ => unreachable!("logs requires a subcommand"),
+    }
+}
+
+fn extension(matches: &ArgMatches) -> &str {
+    matches
+        .get_one::<String>("extension")
+        .map(String::as_str)
+        .expect("extension is required")
+}
+
+fn list_logs(output: &OutputManager) {
+    let logs = hook_log::list();
+    if output.is_json() {
+        output.result("logs", &logs);
+        return;
+    }
+    if logs.is_empty() {
+        println!("No hook logs in {}", hook_log::hooks_dir().display());
+        return;
+    }
+    println!(
+        "{:<32} {:>5} {:>8} {:>7}  {:<12} {:<12}",
+        "NAME", "FILES", "SIZE", "RUNS", "OLDEST", "NEWEST"
+    );
+    for log in &logs {
+        let time = |t: Option<u64>| t.map_or_else(|| "-".to_string(), |t| t.to_string());
+        println!(
+            "{:<32} {:>5} {:>8} {:>7}  {:<12} {:<12}",
+            log.name,
+            log.files,
+            crate::ddi::format_size(log.bytes),
+            log.records,
+            time(log.oldest),
+            time(log.newest)
+        );
+    }
+    println!();
+    println!("Times are seconds since the Unix epoch.");
+}
+
+/// One-line summary of a run, as in `ext info`.
+fn summary_line(record: &HookRecord) -> String {
+    format!(
+        "{} (unix time)  {:<10} {:<7} {}",
+        record.timestamp,
+        record.phase,
+        record.status(),
+        record.command
+    )
+}
+
+fn view_log(name: &str, output: &OutputManager) {
+    let records = hook_log::read_records(name);
+    if output.is_json() {
+        output.result("records", &records);
+        return;
+    }
+    if records.is_empty() {
+        println!("No hook runs recorded for {name}");
+        return;
+    }
+    for record in &records {
+        println!("{}", summary_line(record));
+        for (stream, content) in [("stdout", &record.stdout), ("stderr", &record.stderr)] {
+            if content.trim().is_empty() {
+                continue;
+            }
+            println!("  --- {stream} ---");
+            for line in content.lines() {
+                println!("  {line}");
+            }
+        }
+    }
+}
+
+fn print_record(record: &HookRecord, output: &OutputManager) {
+    if output.is_json() {
+        println!("{}", serde_json::to_string(record).unwrap());
+    } else {
+        println!("{}", summary_line(record));
+    }
+}
+
+fn tail_log(name: &str, lines: usize, follow: bool, output: &OutputManager) {
+    let records = hook_log::read_records(name);
+    let recent = &records[records.len().saturating_sub(lines)..];
+    if output.is_json() && !follow {
+        output.result("records", &recent);
+        return;
+    }
+    for record in recent {
+        print_record(record, output);
+    }
+    if !follow {
+        return;
+    }
+
+    // Records appended from here on; a rotation replaces the file with an
+    // empty one, which starts the offset over
+    let log = hook_log::log_path(name);
+    let mut offset = fs::metadata(&log).map(|m| m.len()).unwrap_or(0);
+    let mut pending = String::new();
+    loop {
+        thread::sleep(FOLLOW_INTERVAL);
+        let Ok(mut file) = fs::File::open(&log) else {
+            offset = 0;
+            continue;
+        };
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len < offset {
+            offset = 0;
+            pending.clear();
+        }
+        if len == offset || file.seek(SeekFrom::Start(offset)).is_err() {
+            continue;
+        }
+        let mut appended = String::new();
+        if file.read_to_string(&mut appended).is_err() {
+            continue;
+        }
+        offset += appended.len() as u64;
+        pending.push_str(&appended);
+        // Keep a partly written last line for the next round
+        let complete = pending.rfind('\n').map_or(0, |end| end + 1);
+        for record in hook_log::parse_records(&pending[..complete]) {
+            print_record(&record, output);
+        }
+        pending.drain(..complete);
+    }
+}
+
+fn purge_logs(name: Option<&str>, older_than_days: Option<u64>, output: &OutputManager) {
+    match hook_log::purge(name, older_than_days.map(|days| days * 24 * 60 * 60)) {
+        Ok(removed) => {
+            if output.is_json() {
+                let removed: Vec<String> =
+                    removed.iter().map(|p| p.display().to_string()).collect();
+                output.result("removed", &removed);
+                return;
+            }
+            for path in &removed {
+                output.info("Logs", &format!("Removed {}", path.display()));
+            }
+            output.success("Logs", &format!("Removed {} log file(s)", removed.len()));
+        }
+        Err(e) => {
+            output.error("Logs", &format!("Failed to purge logs: {e}"));
+            output.exit(1);
+        }
+    }
+}
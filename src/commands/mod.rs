@@ -1,8 +1,22 @@
+pub mod compat;
+pub mod etc_preview;
 pub mod ext;
+pub mod graph;
+pub mod harness;
 pub mod hitl;
 pub mod image_adaptor;
+pub mod init;
+pub mod lint;
+pub mod logs;
+pub mod plan;
+pub mod provision;
+pub mod remote;
+#[cfg(feature = "network")]
 pub mod root_authority;
+pub mod run;
 pub mod runtime;
+pub mod top;
+pub mod trust;
 
 #[cfg(test)]
 pub(crate) mod test_env {
@@ -0,0 +1,137 @@
+use crate::config::Config;
+use crate::diagnostics::Diagnose;
+use crate::messages;
+use crate::output::OutputManager;
+use crate::plan::ChangePlan;
+use crate::service::error::AvocadoError;
+use clap::{Arg, ArgMatches, Command};
+use std::path::Path;
+
+/// Create the top-level plan subcommand definition
+pub fn create_plan_command() -> Command {
+    Command::new("plan")
+        .about("Compute the changes a refresh would make and save them for review")
+        .arg(
+            Arg::new("file")
+                .value_name("FILE")
+                .help("Write the plan to FILE instead of stdout"),
+        )
+}
+
+/// Create the top-level apply subcommand definition
+pub fn create_apply_command() -> Command {
+    Command::new("apply")
+        .about("Apply a reviewed plan, refusing if the system changed since planning")
+        .arg(
+            Arg::new("plan")
+                .value_name("PLAN")
+                .help("Plan file written by 'avocadoctl plan'")
+                .required(true),
+        )
+}
+
+/// Handle `plan` without the daemon
+pub fn handle_plan(matches: &ArgMatches, config: &Config, output: &OutputManager) {
+    let file = matches.get_one::<String>("file").map(Path::new);
+    match crate::service::ext::create_plan(config) {
+        Ok(plan) => write_plan(&plan, file, output),
+        Err(e) => {
+            output.error_with("Plan", &e.to_string(), &e.diagnose());
+            output.exit(1);
+        }
+    }
+}
+
+/// Handle `apply` without the daemon
+pub fn handle_apply(matches: &ArgMatches, config: &Config, output: &OutputManager) {
+    let plan = load_plan_or_exit(matches, output);
+    let (rx, handle) = crate::service::ext::apply_plan_streaming(config, plan);
+    for message in rx {
+        output.relay(&message);
+    }
+    let result = handle.join().unwrap_or_else(|_| {
+        Err(AvocadoError::MergeFailed {
+            reason: "internal panic".into(),
+        })
+    });
+    match result {
+        Ok(()) => {
+            output.success_msg("Apply", messages::PLAN_APPLIED, &[]);
+            crate::commands::ext::exit_if_reboot_required(output);
+            output.json_ok();
+        }
+        Err(e) => exit_with_apply_error(&e, output),
+    }
+}
+
+/// Load the plan named on the command line, or exit.
+pub fn load_plan_or_exit(matches: &ArgMatches, output: &OutputManager) -> ChangePlan {
+    let path = matches.get_one::<String>("plan").expect("plan is required");
+    match ChangePlan::load(Path::new(path)) {
+        Ok(plan) => plan,
+        Err(e) => {
+            output.error("Apply", &e);
+            output.exit(1);
+        }
+    }
+}
+
+/// Report a failed apply, listing the drift when the plan is out of date.
+pub fn exit_with_apply_error(e: &AvocadoError, output: &OutputManager) -> ! {
+    if let AvocadoError::PlanDrifted { reasons } = e {
+        if !output.is_json() {
+            println!("System state changed since the plan was made:");
+            for reason in reasons {
+                println!("  {reason}");
+            }
+        }
+    }
+    output.error_with("Apply", &e.to_string(), &e.diagnose());
+    output.exit(1);
+}
+
+/// Write a plan to `file` (with a summary) or print it as JSON.
+pub fn write_plan(plan: &ChangePlan, file: Option<&Path>, output: &OutputManager) {
+    let Some(path) = file else {
+        println!("{}", plan.to_json());
+        return;
+    };
+    if let Err(e) = plan.save(path) {
+        output.error_with(
+            "Plan",
+            &format!("Failed to write '{}': {e}", path.display()),
+            &e.diagnose(),
+        );
+        output.exit(1);
+    }
+    if !output.is_json() {
+        print_plan_summary(plan);
+    }
+    let file = path.display().to_string();
+    output.success_msg("Plan", messages::PLAN_SAVED, &[("file", &file)]);
+    output.json_ok();
+}
+
+fn print_plan_summary(plan: &ChangePlan) {
+    if !plan.has_changes() {
+        println!("No changes: the merged extensions already match the enabled set.");
+        return;
+    }
+    for name in &plan.merge {
+        println!("  + merge   {name}");
+    }
+    for name in &plan.unmerge {
+        println!("  - unmerge {name}");
+    }
+    for name in &plan.keep {
+        println!("    keep    {name}");
+    }
+    for hook in &plan.hooks {
+        println!(
+            "  run {} hook: {} ({})",
+            hook.phase,
+            hook.command,
+            hook.extensions.join(", ")
+        );
+    }
+}
@@ -0,0 +1,453 @@
+//! `avocadoctl provision` — apply a provisioning manifest in one run.
+//!
+//! Merges the manifest's configuration into the configuration file,
+//! installs its images into the extensions directory, enables its
+//! extensions and refreshes once, then prints a report of every step.
+//! Like `init` it prepares the device itself, so it always runs
+//! in-process. See [`crate::provision`] for the manifest format.
+
+use crate::config::Config;
+use crate::messages;
+use crate::output::OutputManager;
+use crate::provision::{merge_tables, ProvisionManifest};
+use crate::service;
+use crate::transaction::TransactionManifest;
+use crate::trust::TrustStore;
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use serde::Serialize;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Create the provision command definition
+pub fn create_command() -> Command {
+    Command::new("provision")
+        .about("Provision a device from a JSON or TOML manifest: configuration, images and enabled extensions")
+        .arg(
+            Arg::new("stdin")
+                .long("stdin")
+                .help("Read the manifest from stdin")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("manifest")
+                .value_name("MANIFEST")
+                .help("Manifest file"),
+        )
+        .group(
+            ArgGroup::new("source")
+                .args(["stdin", "manifest"])
+                .required(true),
+        )
+}
+
+/// Outcome of one provisioning step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum StepState {
+    Written,
+    Installed,
+    Enabled,
+    Refreshed,
+    Unchanged,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct Step {
+    item: &'static str,
+    target: String,
+    state: StepState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl Step {
+    fn new(item: &'static str, target: impl Into<String>, state: StepState) -> Self {
+        Self {
+            item,
+            target: target.into(),
+            state,
+            detail: None,
+        }
+    }
+
+    fn failed(item: &'static str, target: impl Into<String>, detail: String) -> Self {
+        Self {
+            detail: Some(detail),
+            ..Self::new(item, target, StepState::Failed)
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ProvisionReport {
+    steps: Vec<Step>,
+    /// Steps that changed the device
+    changed: usize,
+    ok: bool,
+}
+
+fn read_manifest(matches: &ArgMatches) -> Result<ProvisionManifest, String> {
+    let content = match matches.get_one::<String>("manifest") {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read manifest '{path}': {e}"))?,
+        None => {
+            let mut content = String::new();
+            std::io::stdin()
+                .read_to_string(&mut content)
+                .map_err(|e| format!("Failed to read manifest from stdin: {e}"))?;
+            content
+        }
+    };
+    ProvisionManifest::parse(&content)
+}
+
+/// Merge `fragment` into the configuration file at `path`, writing it only
+/// when that changes it.
+fn apply_config(path: &Path, fragment: &toml::Table) -> Step {
+    let display = path.display().to_string();
+    let mut table = match fs::read_to_string(path) {
+        Ok(content) => match toml::from_str::<toml::Table>(&content) {
+            Ok(table) => table,
+            Err(e) => return Step::failed("config", display, e.to_string()),
+        },
+        // A new file starts with the default [avocado.ext], which every
+        // configuration file needs
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let mut avocado = toml::Table::new();
+            if let Ok(ext) = toml::Value::try_from(&Config::default().avocado.ext) {
+                avocado.insert("ext".to_string(), ext);
+            }
+            let mut table = toml::Table::new();
+            table.insert("avocado".to_string(), avocado.into());
+            table
+        }
+        Err(e) => return Step::failed("config", display, e.to_string()),
+    };
+    if !merge_tables(&mut table, fragment) {
+        return Step::new("config", display, StepState::Unchanged);
+    }
+    // The result must still be a valid configuration
+    if let Err(e) = toml::from_str::<Config>(&table.to_string()) {
+        return Step::failed("config", display, e.to_string());
+    }
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, table.to_string()));
+    match result {
+        Ok(()) => Step::new("config", display, StepState::Written),
+        Err(e) => Step::failed("config", display, e.to_string()),
+    }
+}
+
+/// Whether `a` and `b` are files with the same content.
+fn same_file_content(a: &Path, b: &Path) -> bool {
+    let checksum = crate::hash::Checksummer::default();
+    let digest = |path| crate::hash::sha256_file(&checksum, path).ok();
+    a.is_file()
+        && b.is_file()
+        && fs::metadata(a).map(|m| m.len()).ok() == fs::metadata(b).map(|m| m.len()).ok()
+        && digest(a) == digest(b)
+}
+
+/// Copy `source` to `dest` unless it already has the same content. The copy
+/// is written next to the destination and renamed, so an interrupted copy
+/// never leaves a truncated file under the real name. Returns whether it
+/// copied.
+fn copy_into_place(source: &Path, dest: &Path) -> std::io::Result<bool> {
+    if same_file_content(source, dest) {
+        return Ok(false);
+    }
+    let file_name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let partial = dest.with_file_name(format!(".{file_name}.partial"));
+    let result = fs::copy(source, &partial).and_then(|_| fs::rename(&partial, dest));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result.map(|()| true)
+}
+
+/// Copy the image at `source`, and its signature if it has one, into
+/// `extensions_dir` unless identical files are already there. Images the
+/// trust store does not accept are refused.
+fn install_image(source: &str, extensions_dir: &str, store: &TrustStore) -> Step {
+    let source_path = Path::new(source);
+    let Some(file_name) = source_path.file_name().filter(|_| source_path.is_file()) else {
+        return Step::failed("install", source, "not an image file".to_string());
+    };
+    if let Err(reason) = crate::trust::check_image(source_path, store, crate::clock::now()) {
+        return Step::failed(
+            "install",
+            source,
+            format!("not accepted by the trust store: {reason}"),
+        );
+    }
+    let dest = Path::new(extensions_dir).join(file_name);
+    let display = dest.display().to_string();
+    let signature = crate::trust::signature_path(source_path);
+
+    let result = fs::create_dir_all(extensions_dir).and_then(|_| {
+        let copied = copy_into_place(source_path, &dest)?;
+        // The signature follows the image, so a half-installed pair is
+        // refused rather than merged
+        if signature.is_file() {
+            copy_into_place(&signature, &crate::trust::signature_path(&dest))?;
+        }
+        Ok(copied)
+    });
+    match result {
+        Ok(true) => Step::new("install", display, StepState::Installed),
+        Ok(false) => Step::new("install", display, StepState::Unchanged),
+        Err(e) => Step::failed("install", display, e.to_string()),
+    }
+}
+
+/// Enable the manifest's extensions; refreshes when a link changed.
+fn enable_extensions(manifest: &ProvisionManifest, config: &Config) -> (Step, bool) {
+    let transaction = TransactionManifest {
+        os_release: manifest.os_release.clone(),
+        enable: manifest.enable.clone(),
+        force: manifest.force,
+        ..Default::default()
+    };
+    let target = manifest.enable.join(", ");
+    match service::ext::apply_transaction(&transaction, config) {
+        Ok(result) if result.linked > 0 => {
+            let mut step = Step::new("enable", target, StepState::Enabled);
+            step.detail = Some(format!("{} linked", result.linked));
+            (step, result.refreshed)
+        }
+        Ok(_) => (Step::new("enable", target, StepState::Unchanged), false),
+        Err(e) => (Step::failed("enable", target, e.to_string()), false),
+    }
+}
+
+/// Apply the manifest, stopping at the first step that fails.
+fn provision(manifest: &ProvisionManifest, config_path: &str, config: &Config) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let failed = |steps: &Vec<Step>| steps.iter().any(|s| s.state == StepState::Failed);
+
+    let fragment = manifest.config_fragment();
+    let mut config = config.clone();
+    if !fragment.is_empty() {
+        let step = apply_config(Path::new(config_path), &fragment);
+        let written = step.state == StepState::Written;
+        steps.push(step);
+        if written {
+            match Config::load(config_path) {
+                Ok(mut reloaded) => {
+                    crate::settings::reload(&reloaded);
+                    if crate::user_mode::is_user() {
+                        crate::user_mode::apply_to_config(&mut reloaded);
+                    }
+                    config = reloaded;
+                }
+                Err(e) => steps.push(Step::failed("config", config_path, e.to_string())),
+            }
+        }
+        if failed(&steps) {
+            return steps;
+        }
+    }
+
+    let extensions_dir = config.get_extensions_dir();
+    if !manifest.install.is_empty() {
+        let base_dir = config.get_avocado_base_dir();
+        match TrustStore::load(Path::new(&base_dir)) {
+            Ok(store) => {
+                for source in &manifest.install {
+                    steps.push(install_image(source, &extensions_dir, &store));
+                }
+            }
+            Err(e) => steps.push(Step::failed(
+                "install",
+                TrustStore::path(Path::new(&base_dir)).display().to_string(),
+                e.to_string(),
+            )),
+        }
+    }
+    if failed(&steps) {
+        return steps;
+    }
+
+    let mut refreshed = false;
+    if !manifest.enable.is_empty() {
+        let (step, did_refresh) = enable_extensions(manifest, &config);
+        steps.push(step);
+        refreshed = did_refresh;
+        if failed(&steps) {
+            return steps;
+        }
+    }
+
+    // New images or configuration behind links that did not change still
+    // need a refresh to take effect
+    let changed = steps.iter().any(|s| s.state != StepState::Unchanged);
+    if changed && !refreshed {
+        steps.push(match service::ext::refresh_if_changed(&config, false) {
+            Ok((_, true)) => Step::new("refresh", "extensions", StepState::Refreshed),
+            Ok((_, false)) => Step::new("refresh", "extensions", StepState::Unchanged),
+            Err(e) => Step::failed("refresh", "extensions", e.to_string()),
+        });
+    }
+    steps
+}
+
+/// Handle `avocadoctl provision`. `config_path` is the configuration file
+/// the manifest's configuration is merged into.
+pub fn handle_command(
+    matches: &ArgMatches,
+    config_path: &str,
+    config: &Config,
+    output: &OutputManager,
+) {
+    let manifest = match read_manifest(matches) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            output.error("Provision", &e);
+            output.exit(1);
+        }
+    };
+
+    let steps = provision(&manifest, config_path, config);
+    let changed = steps
+        .iter()
+        .filter(|s| !matches!(s.state, StepState::Unchanged | StepState::Failed))
+        .count();
+    let ok = steps.iter().all(|s| s.state != StepState::Failed);
+    let report = ProvisionReport { steps, changed, ok };
+
+    print_report(&report, output);
+    if !report.ok {
+        output.exit(1);
+    }
+}
+
+fn print_report(report: &ProvisionReport, output: &OutputManager) {
+    if output.is_json() {
+        output.result_fields(report);
+        return;
+    }
+
+    println!("Provisioning:");
+    for step in &report.steps {
+        let state = match step.state {
+            StepState::Written => "written",
+            StepState::Installed => "installed",
+            StepState::Enabled => "enabled",
+            StepState::Refreshed => "refreshed",
+            StepState::Unchanged => "unchanged",
+            StepState::Failed => "FAILED",
+        };
+        let detail = step
+            .detail
+            .as_ref()
+            .map(|d| format!(" ({d})"))
+            .unwrap_or_default();
+        println!("  {:<8} {:<10} {}{detail}", step.item, state, step.target);
+    }
+
+    if report.ok {
+        output.success_msg(
+            "Provision",
+            messages::PROVISIONED,
+            &[("count", &report.changed.to_string())],
+        );
+    } else {
+        output.error(
+            "Provision",
+            "Provisioning stopped at the step marked FAILED; fix it and run the same manifest again",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_config_writes_only_changes() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("avocadoctl.conf");
+        fs::write(&path, "[avocado.ext]\ndir = \"/data/ext\"\n").unwrap();
+        let fragment: toml::Table =
+            toml::from_str("[avocado.gc]\nruntime_retention = 2\n").unwrap();
+
+        assert_eq!(apply_config(&path, &fragment).state, StepState::Written);
+        assert_eq!(apply_config(&path, &fragment).state, StepState::Unchanged);
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.avocado.ext.dir, "/data/ext");
+
+        let invalid: toml::Table =
+            toml::from_str("[avocado.gc]\nruntime_retention = \"two\"\n").unwrap();
+        assert_eq!(apply_config(&path, &invalid).state, StepState::Failed);
+    }
+
+    #[test]
+    fn test_install_image_skips_identical_copy() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let source = tmp.path().join("camera-2.1.0.raw");
+        fs::write(&source, b"image").unwrap();
+        let dir = tmp.path().join("extensions");
+        let dir = dir.to_str().unwrap();
+        let source = source.to_str().unwrap();
+
+        let store = TrustStore::default();
+        assert_eq!(
+            install_image(source, dir, &store).state,
+            StepState::Installed
+        );
+        assert_eq!(
+            install_image(source, dir, &store).state,
+            StepState::Unchanged
+        );
+        fs::write(source, b"newer image").unwrap();
+        assert_eq!(
+            install_image(source, dir, &store).state,
+            StepState::Installed
+        );
+        assert_eq!(
+            install_image(tmp.path().to_str().unwrap(), dir, &store).state,
+            StepState::Failed
+        );
+    }
+
+    #[test]
+    fn test_install_image_checks_the_trust_store() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let source = tmp.path().join("camera-2.1.0.raw");
+        fs::write(&source, b"image").unwrap();
+        let dir = tmp.path().join("extensions");
+
+        let key = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([1; 32]));
+        let mut store = TrustStore::default();
+        store.rotate(&key.pk, None, None, 0, 0).unwrap();
+        let step = install_image(source.to_str().unwrap(), dir.to_str().unwrap(), &store);
+        assert_eq!(step.state, StepState::Failed);
+        assert_eq!(
+            step.detail.as_deref(),
+            Some("not accepted by the trust store: it is not signed")
+        );
+        assert!(!dir.join("camera-2.1.0.raw").exists());
+
+        let sha256 =
+            crate::hash::sha256_file(&crate::hash::Checksummer::default(), &source).unwrap();
+        let signature = crate::trust::ImageSignature {
+            signatures: vec![crate::audit::ReportSignature {
+                keyid: crate::audit::key_id(&key.pk),
+                sig: crate::hash::hex_encode(key.sk.sign(sha256.as_bytes(), None).as_ref()),
+            }],
+            sha256,
+        };
+        fs::write(
+            crate::trust::signature_path(&source),
+            serde_json::to_string(&signature).unwrap(),
+        )
+        .unwrap();
+        let step = install_image(source.to_str().unwrap(), dir.to_str().unwrap(), &store);
+        assert_eq!(step.state, StepState::Installed);
+        assert!(dir.join("camera-2.1.0.raw.sig").is_file());
+    }
+}
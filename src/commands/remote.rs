@@ -0,0 +1,573 @@
+//! `avocadoctl remote --host <[user@]host> <command>...` — run an
+//! avocadoctl command on a device over SSH.
+//!
+//! The command is run as `avocadoctl <command>...` on the device with its
+//! output streamed back and its exit code returned. Local files the
+//! command names (images for `ext enable`, manifests, ...) are first
+//! copied to an upload directory on the device with scp, and the command
+//! is given their remote paths instead. With `--sudo` they are copied to a
+//! staging directory the SSH user owns and installed from there, as the
+//! upload directory belongs to root. ssh and scp run with the user's
+//! own SSH configuration, so hosts, keys and jump hosts set up in
+//! `~/.ssh/config` work as they do for plain ssh.
+
+use crate::diagnostics::Diagnose;
+use crate::output::OutputManager;
+use clap::{Arg, ArgMatches, Command};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+/// Upload directory on the device. Images enabled by path stay linked from
+/// the os-releases directory, so this must survive reboots.
+pub const DEFAULT_UPLOAD_DIR: &str = "/var/lib/avocado/uploads";
+
+/// Exit code ssh reports when it cannot connect or authenticate.
+pub const SSH_CONNECTION_FAILED: i32 = 255;
+
+/// Create the remote command definition
+pub fn create_command() -> Command {
+    Command::new("remote")
+        .about("Run an avocadoctl command on a device over SSH, uploading the local files it names")
+        .arg(
+            Arg::new("host")
+                .long("host")
+                .short('H')
+                .value_name("[USER@]HOST")
+                .help("Device to run the command on")
+                .required(true),
+        )
+        .arg(
+            Arg::new("port")
+                .long("port")
+                .short('p')
+                .value_name("PORT")
+                .help("SSH port (default: from the SSH configuration)"),
+        )
+        .arg(
+            Arg::new("identity")
+                .long("identity")
+                .short('i')
+                .value_name("FILE")
+                .help("SSH private key"),
+        )
+        .arg(
+            Arg::new("ssh-option")
+                .long("ssh-option")
+                .value_name("OPTION")
+                .help("Extra ssh/scp option, e.g. StrictHostKeyChecking=no (repeatable)")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("sudo")
+                .long("sudo")
+                .help("Run avocadoctl through 'sudo -n' on the device")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("remote-bin")
+                .long("remote-bin")
+                .value_name("PATH")
+                .help("avocadoctl on the device")
+                .default_value("avocadoctl"),
+        )
+        .arg(
+            Arg::new("upload-dir")
+                .long("upload-dir")
+                .value_name("DIR")
+                .help("Directory on the device local files are uploaded to")
+                .default_value(DEFAULT_UPLOAD_DIR),
+        )
+        .arg(
+            Arg::new("command")
+                .value_name("COMMAND")
+                .help("avocadoctl command and arguments to run on the device, e.g. ext enable ./app-1.0.raw")
+                .required(true)
+                .num_args(1..)
+                .trailing_var_arg(true)
+                .allow_hyphen_values(true),
+        )
+}
+
+/// How to reach the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteTarget {
+    pub host: String,
+    pub port: Option<String>,
+    pub identity: Option<String>,
+    pub ssh_options: Vec<String>,
+    pub sudo: bool,
+    pub bin: String,
+}
+
+impl RemoteTarget {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            host: matches
+                .get_one::<String>("host")
+                .cloned()
+                .expect("host is required"),
+            port: matches.get_one::<String>("port").cloned(),
+            identity: matches.get_one::<String>("identity").cloned(),
+            ssh_options: matches
+                .get_many::<String>("ssh-option")
+                .map(|v| v.cloned().collect())
+                .unwrap_or_default(),
+            sudo: matches.get_flag("sudo"),
+            bin: matches
+                .get_one::<String>("remote-bin")
+                .cloned()
+                .expect("remote-bin has a default"),
+        }
+    }
+
+    /// Connection options; ssh takes the port with `-p`, scp with `-P`.
+    fn connection_args(&self, port_flag: &str) -> Vec<String> {
+        let mut args = vec!["-o".to_string(), "BatchMode=yes".to_string()];
+        if let Some(port) = &self.port {
+            args.extend([port_flag.to_string(), port.clone()]);
+        }
+        if let Some(identity) = &self.identity {
+            args.extend(["-i".to_string(), identity.clone()]);
+        }
+        for option in &self.ssh_options {
+            args.extend(["-o".to_string(), option.clone()]);
+        }
+        args
+    }
+
+    /// ssh arguments running the shell command `command` on the device.
+    pub fn ssh_args(&self, command: &str, tty: bool) -> Vec<String> {
+        let mut args = self.connection_args("-p");
+        if tty {
+            args.push("-t".to_string());
+        }
+        args.extend([self.host.clone(), "--".to_string(), command.to_string()]);
+        args
+    }
+
+    /// scp arguments copying `files` into `dir` on the device. The remote
+    /// path may be expanded by the device's shell, so `dir` is quoted.
+    pub fn scp_args(&self, files: &[PathBuf], dir: &str) -> Vec<String> {
+        let mut args = self.connection_args("-P");
+        args.push("-q".to_string());
+        args.extend(files.iter().map(|f| f.to_string_lossy().to_string()));
+        args.push(format!("{}:{}/", self.host, shell_quote(dir)));
+        args
+    }
+
+    /// The shell command running avocadoctl with `args` on the device.
+    pub fn remote_command(&self, args: &[String]) -> String {
+        let mut words = Vec::new();
+        if self.sudo {
+            words.extend(["sudo".to_string(), "-n".to_string()]);
+        }
+        words.push(shell_quote(&self.bin));
+        words.extend(args.iter().map(|a| shell_quote(a)));
+        words.join(" ")
+    }
+}
+
+/// `arg` quoted for a POSIX shell, left alone when it needs no quoting.
+pub fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@,+%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Whether `arg` names a local file to upload: an existing file given as a
+/// path, or an image file in the current directory.
+fn is_local_file(arg: &str) -> bool {
+    let path = Path::new(arg);
+    let image = matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("raw" | "kab")
+    );
+    (arg.contains('/') || image) && path.is_file()
+}
+
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// The command arguments with local files replaced by their path in
+/// `upload_dir`, and the files to upload. Two different files with the
+/// same name would overwrite each other in `upload_dir`, so they are an
+/// error.
+pub fn plan_uploads(
+    args: &[String],
+    upload_dir: &str,
+) -> Result<(Vec<String>, Vec<PathBuf>), RemoteError> {
+    let mut uploads: Vec<PathBuf> = Vec::new();
+    let mut remote_args = Vec::with_capacity(args.len());
+    for arg in args {
+        if !is_local_file(arg) {
+            remote_args.push(arg.clone());
+            continue;
+        }
+        let local = PathBuf::from(arg);
+        let name = file_name_of(&local);
+        let same_file = |other: &PathBuf| match (local.canonicalize(), other.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => *other == local,
+        };
+        match uploads.iter().find(|other| file_name_of(other) == name) {
+            Some(other) if same_file(other) => {}
+            Some(other) => {
+                return Err(RemoteError::NameCollision {
+                    name,
+                    first: other.clone(),
+                    second: local,
+                })
+            }
+            None => uploads.push(local),
+        }
+        remote_args.push(format!("{}/{name}", upload_dir.trim_end_matches('/')));
+    }
+    Ok((remote_args, uploads))
+}
+
+/// The shell command installing the uploaded `files` from `staging` into
+/// `upload_dir` as root, then removing `staging` whatever the outcome.
+pub fn install_command(staging: &str, upload_dir: &str, files: &[PathBuf]) -> String {
+    let staged: Vec<String> = files
+        .iter()
+        .map(|f| shell_quote(&format!("{staging}/{}", file_name_of(f))))
+        .collect();
+    let dir = shell_quote(upload_dir);
+    format!(
+        "sudo -n mkdir -p {dir} && sudo -n install -m 0644 {} {dir}/; status=$?; rm -rf {}; exit $status",
+        staged.join(" "),
+        shell_quote(staging)
+    )
+}
+
+/// Errors of `avocadoctl remote` itself; the remote command reports its own.
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteError {
+    #[error("Failed to run command '{command}': {source}")]
+    Command {
+        command: String,
+        source: std::io::Error,
+    },
+
+    #[error("Cannot reach {host} over SSH")]
+    Unreachable { host: String },
+
+    #[error("Failed to create {host}:{dir} (exit code {code})")]
+    CreateDir {
+        host: String,
+        dir: String,
+        code: i32,
+    },
+
+    #[error("Failed to upload {files} to {host}:{dir} (exit code {code})")]
+    Upload {
+        files: String,
+        host: String,
+        dir: String,
+        code: i32,
+    },
+
+    #[error("Failed to install the uploaded files into {host}:{dir} (exit code {code})")]
+    Install {
+        host: String,
+        dir: String,
+        code: i32,
+    },
+
+    #[error(
+        "{} and {} are both uploaded as '{name}'",
+        first.display(),
+        second.display()
+    )]
+    NameCollision {
+        name: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+}
+
+/// Run `program` with `args`, stdio inherited. Returns its exit code.
+fn run(program: &str, args: &[String], output: &OutputManager) -> Result<i32, RemoteError> {
+    output.info(
+        "Remote",
+        &format!(
+            "Running: {program} {}",
+            args.iter()
+                .map(|a| shell_quote(a))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+    );
+    let status = ProcessCommand::new(program)
+        .args(args)
+        .status()
+        .map_err(|source| RemoteError::Command {
+            command: program.to_string(),
+            source,
+        })?;
+    // A command killed by a signal is reported like ssh failing
+    Ok(status.code().unwrap_or(SSH_CONNECTION_FAILED))
+}
+
+/// Upload the local files, then run the command on the device. Returns
+/// the remote command's exit code.
+pub fn run_remote(
+    target: &RemoteTarget,
+    args: &[String],
+    upload_dir: &str,
+    output: &OutputManager,
+) -> Result<i32, RemoteError> {
+    let ssh = crate::tools::program("ssh");
+    let (remote_args, uploads) = plan_uploads(args, upload_dir)?;
+
+    if !uploads.is_empty() {
+        // The upload directory belongs to root under --sudo, and scp runs as
+        // the SSH user: copy to a directory of the user's own first
+        let staging = target
+            .sudo
+            .then(|| format!("/tmp/avocadoctl-upload-{}", uuid::Uuid::new_v4()));
+        let (mkdir, copy_dir) = match &staging {
+            Some(staging) => (
+                format!("mkdir -m 700 {}", shell_quote(staging)),
+                staging.as_str(),
+            ),
+            None => (format!("mkdir -p {}", shell_quote(upload_dir)), upload_dir),
+        };
+        match run(&ssh, &target.ssh_args(&mkdir, false), output)? {
+            0 => {}
+            SSH_CONNECTION_FAILED => {
+                return Err(RemoteError::Unreachable {
+                    host: target.host.clone(),
+                })
+            }
+            code => {
+                return Err(RemoteError::CreateDir {
+                    host: target.host.clone(),
+                    dir: copy_dir.to_string(),
+                    code,
+                })
+            }
+        }
+        output.log_info(&format!(
+            "Uploading {} file(s) to {}:{upload_dir}",
+            uploads.len(),
+            target.host
+        ));
+        let code = run(
+            &crate::tools::program("scp"),
+            &target.scp_args(&uploads, copy_dir),
+            output,
+        )?;
+        if code != 0 {
+            if let Some(staging) = &staging {
+                let cleanup = format!("rm -rf {}", shell_quote(staging));
+                let _ = run(&ssh, &target.ssh_args(&cleanup, false), output);
+            }
+            return Err(RemoteError::Upload {
+                files: uploads
+                    .iter()
+                    .map(|f| f.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                host: target.host.clone(),
+                dir: copy_dir.to_string(),
+                code,
+            });
+        }
+        if let Some(staging) = &staging {
+            let install = install_command(staging, upload_dir, &uploads);
+            match run(&ssh, &target.ssh_args(&install, false), output)? {
+                0 => {}
+                SSH_CONNECTION_FAILED => {
+                    return Err(RemoteError::Unreachable {
+                        host: target.host.clone(),
+                    })
+                }
+                code => {
+                    return Err(RemoteError::Install {
+                        host: target.host.clone(),
+                        dir: upload_dir.to_string(),
+                        code,
+                    })
+                }
+            }
+        }
+    }
+
+    // A terminal on both ends lets interactive commands such as init prompt
+    let tty = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let code = run(
+        &ssh,
+        &target.ssh_args(&target.remote_command(&remote_args), tty),
+        output,
+    )?;
+    if code == SSH_CONNECTION_FAILED {
+        return Err(RemoteError::Unreachable {
+            host: target.host.clone(),
+        });
+    }
+    Ok(code)
+}
+
+/// Handle `avocadoctl remote`; exits with the remote command's exit code.
+pub fn handle_command(matches: &ArgMatches, output: &OutputManager) -> ! {
+    let target = RemoteTarget::from_matches(matches);
+    let upload_dir = matches
+        .get_one::<String>("upload-dir")
+        .expect("upload-dir has a default");
+
+    // The global flags given before `remote` apply on the device too. The
+    // output manager hides verbosity in JSON mode; the device decides itself.
+    let mut args: Vec<String> = Vec::new();
+    if matches.get_flag("verbose") {
+        args.push("--verbose".to_string());
+    }
+    if output.is_json() {
+        args.extend(["--output".to_string(), "json".to_string()]);
+    }
+    args.extend(
+        matches
+            .get_many::<String>("command")
+            .expect("command is required")
+            .cloned(),
+    );
+
+    match run_remote(&target, &args, upload_dir, output) {
+        Ok(code) => output.exit(code),
+        Err(e) => {
+            output.error_with("Remote", &e.to_string(), &e.diagnose());
+            output.exit(SSH_CONNECTION_FAILED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> RemoteTarget {
+        RemoteTarget {
+            host: "dev@10.0.0.5".to_string(),
+            port: Some("2222".to_string()),
+            identity: None,
+            ssh_options: vec!["StrictHostKeyChecking=no".to_string()],
+            sudo: true,
+            bin: "avocadoctl".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("ext"), "ext");
+        assert_eq!(shell_quote("/var/lib/app-1.0.raw"), "/var/lib/app-1.0.raw");
+        assert_eq!(shell_quote("sensor-*"), "'sensor-*'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_remote_command_and_args() {
+        let target = target();
+        assert_eq!(
+            target.remote_command(&["ext".into(), "enable".into(), "sensor-*".into()]),
+            "sudo -n avocadoctl ext enable 'sensor-*'"
+        );
+        assert_eq!(
+            target.ssh_args("true", false),
+            [
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                "2222",
+                "-o",
+                "StrictHostKeyChecking=no",
+                "dev@10.0.0.5",
+                "--",
+                "true"
+            ]
+        );
+        let scp = target.scp_args(&[PathBuf::from("./app.raw")], "/var/lib/avocado/uploads");
+        assert_eq!(&scp[2..4], ["-P", "2222"]);
+        assert_eq!(
+            &scp[scp.len() - 2..],
+            ["./app.raw", "dev@10.0.0.5:/var/lib/avocado/uploads/"]
+        );
+        let scp = target.scp_args(&[PathBuf::from("./app.raw")], "/data/my uploads");
+        assert_eq!(scp[scp.len() - 1], "dev@10.0.0.5:'/data/my uploads'/");
+    }
+
+    #[test]
+    fn test_plan_uploads_replaces_local_files() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let image = tmp.path().join("app-1.0.raw");
+        std::fs::write(&image, b"raw").unwrap();
+        let image = image.to_string_lossy().to_string();
+
+        let args: Vec<String> = ["ext", "enable", &image, "base", "/nonexistent/x.raw"]
+            .map(String::from)
+            .to_vec();
+        let (remote, uploads) = plan_uploads(&args, "/var/lib/avocado/uploads/").unwrap();
+        assert_eq!(
+            remote,
+            [
+                "ext",
+                "enable",
+                "/var/lib/avocado/uploads/app-1.0.raw",
+                "base",
+                "/nonexistent/x.raw"
+            ]
+        );
+        assert_eq!(uploads, [PathBuf::from(&image)]);
+    }
+
+    #[test]
+    fn test_plan_uploads_rejects_name_collisions() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        for dir in ["a", "b"] {
+            std::fs::create_dir_all(tmp.path().join(dir)).unwrap();
+            std::fs::write(tmp.path().join(dir).join("app-1.0.raw"), dir).unwrap();
+        }
+        let path = |dir: &str| {
+            tmp.path()
+                .join(dir)
+                .join("app-1.0.raw")
+                .to_string_lossy()
+                .to_string()
+        };
+
+        // The same file named twice is uploaded once
+        let args = vec![path("a"), path("a")];
+        let (_, uploads) = plan_uploads(&args, "/uploads").unwrap();
+        assert_eq!(uploads.len(), 1);
+
+        let args = vec![path("a"), path("b")];
+        assert!(matches!(
+            plan_uploads(&args, "/uploads"),
+            Err(RemoteError::NameCollision { name, .. }) if name == "app-1.0.raw"
+        ));
+    }
+
+    #[test]
+    fn test_install_command_installs_as_root_and_cleans_up() {
+        assert_eq!(
+            install_command(
+                "/tmp/avocadoctl-upload-1",
+                "/var/lib/avocado/uploads",
+                &[PathBuf::from("./build/app 1.0.raw")]
+            ),
+            "sudo -n mkdir -p /var/lib/avocado/uploads && sudo -n install -m 0644 \
+             '/tmp/avocadoctl-upload-1/app 1.0.raw' /var/lib/avocado/uploads/; \
+             status=$?; rm -rf /tmp/avocadoctl-upload-1; exit $status"
+        );
+    }
+}
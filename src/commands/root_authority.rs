@@ -1,4 +1,5 @@
 use crate::config::Config;
+use crate::diagnostics::Diagnose;
 use crate::output::OutputManager;
 use clap::Command;
 use std::path::Path;
@@ -32,9 +33,10 @@ pub fn handle_command(config: &Config, output: &OutputManager) {
         match serde_json::from_str(&content) {
             Ok(r) => r,
             Err(e) => {
-                output.error(
+                output.error_with(
                     "Root Authority",
                     &format!("Failed to parse {}: {e}", root_path.display()),
+                    &e.diagnose(),
                 );
                 return;
             }
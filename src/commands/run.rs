@@ -0,0 +1,156 @@
+//! `avocadoctl ext run <name> -- <cmd> [args]` — run a tool shipped in an
+//! extension without merging it.
+//!
+//! The extension is resolved like `enable` resolves names (a name in the
+//! extensions directory or an absolute path). Raw images are mounted for the
+//! duration of the run and archives are unpacked into the archive cache. The
+//! command then runs inside a private mount namespace in which the
+//! extension's `usr/` is overlaid on top of the host `/usr`, so nothing under
+//! `/run/extensions` or the system-wide `/usr` changes. In test mode no
+//! namespace is created; the command runs directly with the extension's
+//! `usr/bin` and `usr/sbin` first on PATH.
+
+use crate::commands::ext::{self, SystemdError};
+use crate::commands::{harness, image_adaptor};
+use crate::config::Config;
+use crate::diagnostics::Diagnose;
+use crate::error::IoContext;
+use crate::output::OutputManager;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+/// Shell snippet run inside the namespace: overlay the extension's usr/ on
+/// /usr, then exec the command. `$1` is the extension tree.
+const OVERLAY_SCRIPT: &str = r#"root="$1"; shift
+mount -t overlay avocado-run -o "lowerdir=$root/usr:/usr" /usr || exit 125
+exec "$@""#;
+
+/// Build the process that runs `command` with the extension at `ext_path`
+/// overlaid on /usr.
+fn overlay_command(ext_path: &Path, command: &[String]) -> ProcessCommand {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let search_path = format!(
+            "{}:{}:{}",
+            ext_path.join("usr/bin").display(),
+            ext_path.join("usr/sbin").display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        let mut cmd = ProcessCommand::new(&command[0]);
+        cmd.args(&command[1..]).env("PATH", search_path);
+        cmd
+    } else {
+        let mut cmd = ProcessCommand::new("unshare");
+        cmd.args(["--mount", "--propagation", "private", "sh", "-c"])
+            .arg(OVERLAY_SCRIPT)
+            .arg("avocado-run")
+            .arg(ext_path)
+            .args(command);
+        cmd
+    }
+}
+
+/// Run `command` against the extension `name` and return its exit code.
+pub fn run_in_extension(
+    name: &str,
+    command: &[String],
+    config: &Config,
+    output: &OutputManager,
+) -> Result<i32, SystemdError> {
+    if command.is_empty() {
+        return Err(SystemdError::ConfigurationError {
+            message: "No command given; usage: ext run <name> -- <cmd> [args]".to_string(),
+        });
+    }
+    let extensions_dir = config.get_extensions_dir();
+    let source = match ext::resolve_enable_targets(name, &extensions_dir) {
+        Ok(targets) if targets.len() == 1 => PathBuf::from(&targets[0].source_path),
+        Ok(targets) => {
+            return Err(SystemdError::ConfigurationError {
+                message: format!(
+                    "'{name}' matches {} extensions; ext run needs exactly one",
+                    targets.len()
+                ),
+            })
+        }
+        Err(message) => return Err(SystemdError::ConfigurationError { message }),
+    };
+
+    let file_name = source
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut mount_point = None;
+    let ext_path = if source.is_dir() {
+        source.clone()
+    } else if crate::archive::archive_stem(&file_name).is_some() {
+        crate::archive::unpack_cached(&source, &crate::archive::cache_dir()).map_err(|e| {
+            SystemdError::ConfigurationError {
+                message: e.to_string(),
+            }
+        })?
+    } else {
+        let image_name = harness::image_name(&source);
+        let dir = std::env::temp_dir().join(format!(
+            "avocado-ext-run-{}/{image_name}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).context("create directory", &dir)?;
+        let dir_str = dir.to_string_lossy().to_string();
+        image_adaptor::mount_image_once(&image_name, &source, &dir_str, output.is_verbose())?;
+        mount_point = Some(dir);
+        PathBuf::from(dir_str)
+    };
+
+    if !ext_path.join("usr").is_dir() {
+        output.progress(&format!(
+            "Warning: extension '{name}' has no usr/ tree; running with the host /usr"
+        ));
+    }
+    output.info(
+        "Extension Run",
+        &format!(
+            "Running '{}' with {} overlaid",
+            command.join(" "),
+            ext_path.display()
+        ),
+    );
+
+    let status = overlay_command(&ext_path, command)
+        .env("AVOCADO_EXTENSION_ROOT", &ext_path)
+        .status();
+
+    if let Some(dir) = mount_point {
+        match image_adaptor::unmount_image_once(&dir.to_string_lossy(), output.is_verbose()) {
+            Ok(()) => {
+                let _ = fs::remove_dir(&dir);
+                if let Some(parent) = dir.parent() {
+                    let _ = fs::remove_dir(parent);
+                }
+            }
+            Err(e) => output.progress(&format!("Warning: failed to unmount extension image: {e}")),
+        }
+    }
+
+    let status = status.map_err(|e| SystemdError::CommandFailed {
+        command: command[0].clone(),
+        source: e,
+    })?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// CLI entry point for `ext run`: exits with the command's exit code.
+pub fn run_extension_command(
+    name: &str,
+    command: &[String],
+    config: &Config,
+    output: &OutputManager,
+) {
+    match run_in_extension(name, command, config, output) {
+        Ok(code) => output.exit(code),
+        Err(e) => {
+            output.error_with("Extension Run", &e.to_string(), &e.diagnose());
+            output.exit(1);
+        }
+    }
+}
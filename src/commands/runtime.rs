@@ -1,4 +1,6 @@
 use crate::config::Config;
+use crate::diagnostics::Diagnose;
+use crate::hash::Checksummer;
 use crate::manifest::{RuntimeManifest, IMAGES_DIR_NAME};
 use crate::output::OutputManager;
 use crate::{staging, update};
@@ -148,6 +150,8 @@ fn handle_add(matches: &ArgMatches, config: &Config, output: &OutputManager) {
             config.stream_os_to_partition(),
             output.is_verbose(),
             config.get_spot_check_bytes(),
+            config.storage(),
+            &Checksummer::from_settings(config.checksum(), Some(url)),
         ) {
             Ok(reboot_required) => {
                 if reboot_required {
@@ -165,8 +169,8 @@ fn handle_add(matches: &ArgMatches, config: &Config, output: &OutputManager) {
             }
             Err(e) => {
                 println!();
-                output.error("Runtime Add", &format!("{e}"));
-                std::process::exit(1);
+                output.error_with("Runtime Add", &format!("{e}"), &e.diagnose());
+                output.exit(1);
             }
         }
     } else if let Some(manifest_path) = matches.get_one::<String>("manifest") {
@@ -177,29 +181,41 @@ fn handle_add(matches: &ArgMatches, config: &Config, output: &OutputManager) {
         let manifest_content = match std::fs::read_to_string(manifest_path) {
             Ok(c) => c,
             Err(e) => {
-                output.error("Runtime Add", &format!("Failed to read manifest: {e}"));
-                std::process::exit(1);
+                output.error_with(
+                    "Runtime Add",
+                    &format!("Failed to read manifest: {e}"),
+                    &e.diagnose(),
+                );
+                output.exit(1);
             }
         };
 
         let manifest: RuntimeManifest = match serde_json::from_str(&manifest_content) {
             Ok(m) => m,
             Err(e) => {
-                output.error("Runtime Add", &format!("Invalid manifest.json: {e}"));
-                std::process::exit(1);
+                output.error_with(
+                    "Runtime Add",
+                    &format!("Invalid manifest.json: {e}"),
+                    &e.diagnose(),
+                );
+                output.exit(1);
             }
         };
 
-        if let Err(e) = staging::validate_manifest_images(&manifest, base_path) {
-            output.error("Runtime Add", &format!("{e}"));
-            std::process::exit(1);
+        if let Err(e) = staging::validate_manifest_images(
+            &manifest,
+            base_path,
+            &Checksummer::from_settings(config.checksum(), None),
+        ) {
+            output.error_with("Runtime Add", &format!("{e}"), &e.diagnose());
+            output.exit(1);
         }
 
         if let Err(e) =
             staging::stage_manifest(&manifest, &manifest_content, base_path, output.is_verbose())
         {
-            output.error("Runtime Add", &format!("{e}"));
-            std::process::exit(1);
+            output.error_with("Runtime Add", &format!("{e}"), &e.diagnose());
+            output.exit(1);
         }
 
         // Best-effort spot hash cache generation
@@ -211,8 +227,8 @@ fn handle_add(matches: &ArgMatches, config: &Config, output: &OutputManager) {
         }
 
         if let Err(e) = staging::activate_runtime(&manifest.id, base_path) {
-            output.error("Runtime Add", &format!("{e}"));
-            std::process::exit(1);
+            output.error_with("Runtime Add", &format!("{e}"), &e.diagnose());
+            output.exit(1);
         }
 
         let short_id = &manifest.id[..8.min(manifest.id.len())];
@@ -239,8 +255,8 @@ fn handle_remove(matches: &ArgMatches, config: &Config, output: &OutputManager)
     };
 
     if let Err(e) = staging::remove_runtime(&matched.id, base_path) {
-        output.error("Runtime Remove", &format!("{e}"));
-        std::process::exit(1);
+        output.error_with("Runtime Remove", &format!("{e}"), &e.diagnose());
+        output.exit(1);
     }
 
     let short_id = &matched.id[..8.min(matched.id.len())];
@@ -300,7 +316,7 @@ fn handle_activate(matches: &ArgMatches, config: &Config, output: &OutputManager
                         "Runtime Activate",
                         &format!("OS bundle image not found: {}", aos_path.display()),
                     );
-                    std::process::exit(1);
+                    output.exit(1);
                 }
 
                 output.step(
@@ -312,16 +328,21 @@ fn handle_activate(matches: &ArgMatches, config: &Config, output: &OutputManager
                 );
 
                 if let Err(e) = crate::os_update::apply_os_update(&aos_path, base_path, false) {
-                    output.error("Runtime Activate", &format!("OS update failed: {e}"));
-                    std::process::exit(1);
+                    output.error_with(
+                        "Runtime Activate",
+                        &format!("OS update failed: {e}"),
+                        &e.diagnose(),
+                    );
+                    output.exit(1);
                 }
 
                 if let Err(e) = crate::os_update::set_pending_runtime_id(&matched.id, base_path) {
-                    output.error(
+                    output.error_with(
                         "Runtime Activate",
                         &format!("Failed to set pending runtime: {e}"),
+                        &e.diagnose(),
                     );
-                    std::process::exit(1);
+                    output.exit(1);
                 }
 
                 output.step(
@@ -341,16 +362,17 @@ fn handle_activate(matches: &ArgMatches, config: &Config, output: &OutputManager
         base_path,
         &runtime_dir,
         config.get_spot_check_bytes(),
+        &Checksummer::from_settings(config.checksum(), None),
         output.is_verbose(),
     ) {
-        output.error("Runtime Activate", &format!("{e}"));
-        std::process::exit(1);
+        output.error_with("Runtime Activate", &format!("{e}"), &e.diagnose());
+        output.exit(1);
     }
 
     // No OS change needed — activate immediately and refresh
     if let Err(e) = staging::activate_runtime(&matched.id, base_path) {
-        output.error("Runtime Activate", &format!("{e}"));
-        std::process::exit(1);
+        output.error_with("Runtime Activate", &format!("{e}"), &e.diagnose());
+        output.exit(1);
     }
 
     println!(
@@ -385,7 +407,7 @@ fn handle_inspect(matches: &ArgMatches, config: &Config, output: &OutputManager)
             Some((m, _)) => (m, true),
             None => {
                 output.error("Runtime Inspect", "No active runtime found.");
-                std::process::exit(1);
+                output.exit(1);
             }
         }
     };
@@ -395,7 +417,7 @@ fn handle_inspect(matches: &ArgMatches, config: &Config, output: &OutputManager)
             Ok(json) => println!("{json}"),
             Err(e) => {
                 output.error("Runtime Inspect", &format!("Failed to serialize: {e}"));
-                std::process::exit(1);
+                output.exit(1);
             }
         }
         return;
@@ -506,7 +528,7 @@ fn resolve_runtime_id<'a>(
                 "Runtime",
                 &format!("No runtime found with ID starting with '{id_prefix}'."),
             );
-            std::process::exit(1);
+            output.exit(1);
         }
         1 => Some((&matches[0].0, matches[0].1)),
         _ => {
@@ -528,7 +550,7 @@ fn resolve_runtime_id<'a>(
                     ids.join("\n")
                 ),
             );
-            std::process::exit(1);
+            output.exit(1);
         }
     }
 }
@@ -537,11 +559,8 @@ fn handle_gc(config: &Config, output: &OutputManager) {
     match crate::service::runtime::garbage_collect(config) {
         Ok(result) => {
             if output.is_json() {
-                let json = serde_json::json!({
-                    "removed_runtimes": result.removed_runtimes,
-                    "removed_images": result.removed_images,
-                });
-                println!("{}", serde_json::to_string_pretty(&json).unwrap());
+                output.result("removed_runtimes", &result.removed_runtimes);
+                output.result("removed_images", &result.removed_images);
                 return;
             }
             if result.removed_runtimes.is_empty() && result.removed_images.is_empty() {
@@ -566,8 +585,8 @@ fn handle_gc(config: &Config, output: &OutputManager) {
             }
         }
         Err(e) => {
-            output.error("Runtime GC", &format!("{e}"));
-            std::process::exit(1);
+            output.error_with("Runtime GC", &format!("{e}"), &e.diagnose());
+            output.exit(1);
         }
     }
 }
@@ -593,15 +612,12 @@ fn handle_metadata_set(matches: &ArgMatches, config: &Config, output: &OutputMan
 
     match crate::service::runtime::metadata_set(id, key, value, config) {
         Ok(()) => {
-            if output.is_json() {
-                println!("{{\"status\":\"ok\"}}");
-            } else {
-                output.success("Metadata Set", &format!("Set '{key}' on runtime {id}"));
-            }
+            output.success("Metadata Set", &format!("Set '{key}' on runtime {id}"));
+            output.json_ok();
         }
         Err(e) => {
-            output.error("Metadata Set", &format!("{e}"));
-            std::process::exit(1);
+            output.error_with("Metadata Set", &format!("{e}"), &e.diagnose());
+            output.exit(1);
         }
     }
 }
@@ -619,8 +635,8 @@ fn handle_metadata_get(matches: &ArgMatches, config: &Config, output: &OutputMan
             }
         }
         Err(e) => {
-            output.error("Metadata Get", &format!("{e}"));
-            std::process::exit(1);
+            output.error_with("Metadata Get", &format!("{e}"), &e.diagnose());
+            output.exit(1);
         }
     }
 }
@@ -655,8 +671,8 @@ fn handle_metadata_list(matches: &ArgMatches, config: &Config, output: &OutputMa
             }
         }
         Err(e) => {
-            output.error("Metadata List", &format!("{e}"));
-            std::process::exit(1);
+            output.error_with("Metadata List", &format!("{e}"), &e.diagnose());
+            output.exit(1);
         }
     }
 }
@@ -667,18 +683,15 @@ fn handle_metadata_delete(matches: &ArgMatches, config: &Config, output: &Output
 
     match crate::service::runtime::metadata_delete(id, key, config) {
         Ok(()) => {
-            if output.is_json() {
-                println!("{{\"status\":\"ok\"}}");
-            } else {
-                output.success(
-                    "Metadata Delete",
-                    &format!("Deleted '{key}' from runtime {id}"),
-                );
-            }
+            output.success(
+                "Metadata Delete",
+                &format!("Deleted '{key}' from runtime {id}"),
+            );
+            output.json_ok();
         }
         Err(e) => {
-            output.error("Metadata Delete", &format!("{e}"));
-            std::process::exit(1);
+            output.error_with("Metadata Delete", &format!("{e}"), &e.diagnose());
+            output.exit(1);
         }
     }
 }
@@ -767,6 +780,8 @@ mod tests {
                 image_id: Some("img-id".to_string()),
                 image_type: None,
                 sha256: None,
+                sha512: None,
+                blake3: None,
                 enabled: true,
             }],
             os_bundle: None,
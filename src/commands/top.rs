@@ -0,0 +1,316 @@
+//! `avocadoctl ext top` — which image-backed extensions are actually read.
+//!
+//! Every `.raw` / `.kab` extension is mounted from its own block device
+//! (a loop device, its partition, or the dm-verity device on top of it), so
+//! the kernel's per-device I/O counters in `/sys/dev/block/<maj:min>/stat`
+//! attribute reads to a single extension layer without eBPF. Mounts are
+//! found in `/proc/self/mountinfo` below the extension mount directory.
+//! Directory extensions share the host filesystem's device and have no
+//! per-layer counters, so they are not listed.
+//!
+//! `--once` prints the counters accumulated since each image was mounted,
+//! which for images mounted at boot is the boot-time and steady-state usage
+//! so far; otherwise read rates are sampled every `--interval` milliseconds.
+
+use crate::commands::image_adaptor::extension_mount_point;
+use crate::output::OutputManager;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+/// Sector size used by the block layer's stat counters.
+const SECTOR_BYTES: u64 = 512;
+
+/// A mounted extension image and the block device backing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ExtensionDevice {
+    name: String,
+    device: String,
+}
+
+/// Read counters of one block device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ReadCounters {
+    reads: u64,
+    sectors: u64,
+}
+
+/// One row of `ext top` output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtensionActivity {
+    pub name: String,
+    pub device: String,
+    /// Read requests completed since the image was mounted.
+    pub total_reads: u64,
+    /// Bytes read since the image was mounted.
+    pub total_bytes: u64,
+    /// Read requests per second over the last interval.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reads_per_sec: Option<f64>,
+    /// Bytes per second over the last interval.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_per_sec: Option<f64>,
+}
+
+/// Undo the octal escapes (`\040` etc.) mountinfo uses in paths.
+pub(crate) fn unescape_mount_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let digits: String = chars.clone().take(3).collect();
+            if digits.len() == 3 {
+                if let Ok(code) = u8::from_str_radix(&digits, 8) {
+                    out.push(code as char);
+                    chars.nth(2);
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Extension mounts directly below `base` in mountinfo `content`.
+fn extension_devices(content: &str, base: &str) -> Vec<ExtensionDevice> {
+    let prefix = format!("{}/", base.trim_end_matches('/'));
+    let mut devices: Vec<ExtensionDevice> = content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let device = fields.get(2)?;
+            let mount_point = unescape_mount_path(fields.get(4)?);
+            let name = mount_point.strip_prefix(&prefix)?;
+            (!name.is_empty() && !name.contains('/')).then(|| ExtensionDevice {
+                name: name.to_string(),
+                device: device.to_string(),
+            })
+        })
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices.dedup_by(|a, b| a.name == b.name);
+    devices
+}
+
+/// Parse the read fields of a `/sys/block/*/stat` line.
+fn parse_block_stat(content: &str) -> Option<ReadCounters> {
+    let fields: Vec<u64> = content
+        .split_whitespace()
+        .map(|f| f.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    Some(ReadCounters {
+        reads: *fields.first()?,
+        sectors: *fields.get(2)?,
+    })
+}
+
+fn read_counters(device: &str) -> Option<ReadCounters> {
+    fs::read_to_string(format!("/sys/dev/block/{device}/stat"))
+        .ok()
+        .and_then(|c| parse_block_stat(&c))
+}
+
+fn sample(devices: &[ExtensionDevice]) -> HashMap<String, ReadCounters> {
+    devices
+        .iter()
+        .filter_map(|d| read_counters(&d.device).map(|c| (d.name.clone(), c)))
+        .collect()
+}
+
+/// Build the rows for a sample, with rates when a previous sample exists.
+/// Rows are ordered hottest first.
+fn activity(
+    devices: &[ExtensionDevice],
+    current: &HashMap<String, ReadCounters>,
+    previous: Option<(&HashMap<String, ReadCounters>, Duration)>,
+) -> Vec<ExtensionActivity> {
+    let mut rows: Vec<ExtensionActivity> = devices
+        .iter()
+        .filter_map(|d| {
+            let now = current.get(&d.name)?;
+            let rate = previous.and_then(|(prev, elapsed)| {
+                let before = prev.get(&d.name)?;
+                let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+                Some((
+                    now.reads.saturating_sub(before.reads) as f64 / secs,
+                    (now.sectors.saturating_sub(before.sectors) * SECTOR_BYTES) as f64 / secs,
+                ))
+            });
+            Some(ExtensionActivity {
+                name: d.name.clone(),
+                device: d.device.clone(),
+                total_reads: now.reads,
+                total_bytes: now.sectors * SECTOR_BYTES,
+                reads_per_sec: rate.map(|r| r.0),
+                bytes_per_sec: rate.map(|r| r.1),
+            })
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        let key = |r: &ExtensionActivity| r.bytes_per_sec.unwrap_or(r.total_bytes as f64);
+        key(b)
+            .partial_cmp(&key(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    rows
+}
+
+fn print_rows(rows: &[ExtensionActivity], output: &OutputManager) {
+    if output.is_json() {
+        let _ = output.json_document(&rows);
+        return;
+    }
+    let width = rows.iter().map(|r| r.name.len()).max().unwrap_or(4).max(9);
+    if rows.iter().any(|r| r.bytes_per_sec.is_some()) {
+        println!(
+            "{:<width$} {:>10} {:>12} {:>12}",
+            "EXTENSION", "READS/s", "KiB/s", "TOTAL KiB"
+        );
+    } else {
+        println!(
+            "{:<width$} {:>10} {:>12}",
+            "EXTENSION", "READS", "TOTAL KiB"
+        );
+    }
+    for r in rows {
+        match (r.reads_per_sec, r.bytes_per_sec) {
+            (Some(reads), Some(bytes)) => println!(
+                "{:<width$} {:>10.1} {:>12.1} {:>12}",
+                r.name,
+                reads,
+                bytes / 1024.0,
+                r.total_bytes / 1024
+            ),
+            _ => println!(
+                "{:<width$} {:>10} {:>12}",
+                r.name,
+                r.total_reads,
+                r.total_bytes / 1024
+            ),
+        }
+    }
+}
+
+/// Run `ext top`. With `once`, print the totals since mount and return;
+/// otherwise print `count` samples (forever when `None`).
+pub fn run_top(once: bool, interval_ms: u64, count: Option<u64>, output: &OutputManager) {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    let base = extension_mount_point("");
+    let devices = extension_devices(&mountinfo, &base);
+    if devices.is_empty() {
+        if output.is_json() {
+            let _ = output.json_document(&Vec::<ExtensionActivity>::new());
+        } else {
+            output.info(
+                "Extension Top",
+                &format!("Looked for image mounts below {base}"),
+            );
+            println!("No image-backed extensions are mounted.");
+        }
+        return;
+    }
+
+    let mut previous = sample(&devices);
+    if once {
+        print_rows(&activity(&devices, &previous, None), output);
+        return;
+    }
+
+    let interval = Duration::from_millis(interval_ms.max(1));
+    let mut printed = 0;
+    while count.is_none_or(|n| printed < n) {
+        thread::sleep(interval);
+        let current = sample(&devices);
+        if printed > 0 && !output.is_json() {
+            println!();
+        }
+        print_rows(
+            &activity(&devices, &current, Some((&previous, interval))),
+            output,
+        );
+        previous = current;
+        printed += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension_devices_from_mountinfo() {
+        let mountinfo = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+95 22 7:3 / /run/avocado/extensions/camera-1.0 ro,relatime shared:50 - erofs /dev/loop3 ro
+96 22 253:0 / /run/avocado/extensions/my\\040tool ro,relatime shared:51 - squashfs /dev/mapper/v ro
+97 95 7:4 / /run/avocado/extensions/camera-1.0/nested ro shared:52 - erofs /dev/loop4 ro
+98 22 7:5 / /run/avocado/extensions-other/x ro shared:53 - erofs /dev/loop5 ro
+";
+        let devices = extension_devices(mountinfo, "/run/avocado/extensions/");
+        assert_eq!(
+            devices,
+            vec![
+                ExtensionDevice {
+                    name: "camera-1.0".to_string(),
+                    device: "7:3".to_string(),
+                },
+                ExtensionDevice {
+                    name: "my tool".to_string(),
+                    device: "253:0".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_block_stat() {
+        let stat = "    1520        0   120480      812        0        0        0        0        0      900      812        0        0        0        0        0        0";
+        assert_eq!(
+            parse_block_stat(stat),
+            Some(ReadCounters {
+                reads: 1520,
+                sectors: 120480,
+            })
+        );
+        assert_eq!(parse_block_stat("garbage"), None);
+    }
+
+    #[test]
+    fn test_activity_orders_by_rate() {
+        let devices = vec![
+            ExtensionDevice {
+                name: "cold".to_string(),
+                device: "7:1".to_string(),
+            },
+            ExtensionDevice {
+                name: "hot".to_string(),
+                device: "7:2".to_string(),
+            },
+        ];
+        let counters = |reads, sectors| ReadCounters { reads, sectors };
+        let before: HashMap<String, ReadCounters> = [
+            ("cold".to_string(), counters(100, 10_000)),
+            ("hot".to_string(), counters(10, 100)),
+        ]
+        .into();
+        let after: HashMap<String, ReadCounters> = [
+            ("cold".to_string(), counters(100, 10_000)),
+            ("hot".to_string(), counters(30, 2_148)),
+        ]
+        .into();
+
+        let totals = activity(&devices, &after, None);
+        assert_eq!(totals[0].name, "cold");
+        assert_eq!(totals[0].reads_per_sec, None);
+
+        let rates = activity(&devices, &after, Some((&before, Duration::from_secs(2))));
+        assert_eq!(rates[0].name, "hot");
+        assert_eq!(rates[0].reads_per_sec, Some(10.0));
+        assert_eq!(rates[0].bytes_per_sec, Some(524_288.0));
+        assert_eq!(rates[0].total_bytes, 2_148 * 512);
+    }
+}
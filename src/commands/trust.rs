@@ -0,0 +1,223 @@
+//! `avocadoctl trust` — manage the keys trusted to sign extension images.
+//!
+//! `trust list` shows the keys and their validity windows. `trust rotate`
+//! installs a new key, optionally retiring an old one after an overlap, and
+//! reports which installed images would no longer validate. See
+//! [`crate::trust`] for the store and signature format.
+
+use crate::clock;
+use crate::config::Config;
+use crate::diagnostics::Diagnose;
+use crate::messages;
+use crate::output::OutputManager;
+use crate::service;
+use crate::service::types::RotationResult;
+use crate::trust::SigningKey;
+use clap::{Arg, ArgMatches, Command};
+
+/// Create the trust command definition
+pub fn create_command() -> Command {
+    Command::new("trust")
+        .about("Manage the keys trusted to sign extension images")
+        .subcommand_required(true)
+        .subcommand(Command::new("list").about("List trusted signing keys and their validity"))
+        .subcommand(
+            Command::new("rotate")
+                .about("Install a new signing key and re-verify installed extensions")
+                .arg(
+                    Arg::new("key")
+                        .value_name("KEY_FILE")
+                        .required(true)
+                        .help("File with the new hex-encoded ed25519 public key"),
+                )
+                .arg(
+                    Arg::new("retire")
+                        .long("retire")
+                        .value_name("KEY_ID")
+                        .help("Key (id or unique id prefix) the new key replaces"),
+                )
+                .arg(
+                    Arg::new("overlap")
+                        .long("overlap")
+                        .value_name("DAYS")
+                        .requires("retire")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("0")
+                        .help("Days the retired key stays valid alongside the new one"),
+                )
+                .arg(
+                    Arg::new("valid-for")
+                        .long("valid-for")
+                        .value_name("DAYS")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("Days the new key is valid (default: no expiry)"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Report the effect on installed images without changing the trust store")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+}
+
+/// Arguments of `trust rotate`, with the key file already read.
+pub struct RotateArgs {
+    pub public_key: String,
+    pub retire: Option<String>,
+    pub overlap_days: u64,
+    pub valid_days: Option<u64>,
+    pub dry_run: bool,
+}
+
+/// Read the arguments of `trust rotate`, exiting if the key file is unreadable.
+pub fn rotate_args(matches: &ArgMatches, output: &OutputManager) -> RotateArgs {
+    let key_path = matches.get_one::<String>("key").expect("key is required");
+    let public_key = match std::fs::read_to_string(key_path) {
+        Ok(content) => content,
+        Err(e) => {
+            output.error_with(
+                "Trust Rotate",
+                &format!("Failed to read '{key_path}': {e}"),
+                &e.diagnose(),
+            );
+            output.exit(1);
+        }
+    };
+    RotateArgs {
+        public_key,
+        retire: matches.get_one::<String>("retire").cloned(),
+        overlap_days: matches.get_one::<u64>("overlap").copied().unwrap_or(0),
+        valid_days: matches.get_one::<u64>("valid-for").copied(),
+        dry_run: matches.get_flag("dry-run"),
+    }
+}
+
+/// Run a trust subcommand in-process.
+pub fn handle_command(matches: &ArgMatches, config: &Config, output: &OutputManager) {
+    match matches.subcommand() {
+        Some(("list", _)) => match service::root_authority::trusted_keys(config) {
+            Ok(keys) => print_keys(&keys, output),
+            Err(e) => {
+                output.error_with("Trust List", &e.to_string(), &e.diagnose());
+                output.exit(1);
+            }
+        },
+        Some(("rotate", sub)) => {
+            let args = rotate_args(sub, output);
+            match service::root_authority::rotate(
+                config,
+                &args.public_key,
+                args.retire.as_deref(),
+                args.overlap_days,
+                args.valid_days,
+                args.dry_run,
+            ) {
+                Ok(result) => print_rotation(&result, args.dry_run, output),
+                Err(e) => {
+                    output.error_with("Trust Rotate", &e.to_string(), &e.diagnose());
+                    output.exit(1);
+                }
+            }
+        }
+        _ => println!("Use 'avocadoctl trust --help' for available trust commands"),
+    }
+}
+
+fn short_id(keyid: &str) -> &str {
+    &keyid[..keyid.len().min(16)]
+}
+
+fn validity(key: &SigningKey) -> String {
+    let from = key.not_before.map(clock::format_time);
+    let until = key.not_after.map(clock::format_time);
+    match (from, until) {
+        (Some(from), Some(until)) => format!("{from} - {until}"),
+        (Some(from), None) => format!("from {from}"),
+        (None, Some(until)) => format!("until {until}"),
+        (None, None) => "always".to_string(),
+    }
+}
+
+pub fn print_keys(keys: &[SigningKey], output: &OutputManager) {
+    if output.is_json() {
+        output.result("keys", &keys);
+        return;
+    }
+    if keys.is_empty() {
+        println!("No extension signing keys trusted.");
+        return;
+    }
+    let now = clock::now();
+    println!("{:<18} {:<8} VALIDITY", "KEY ID", "STATE");
+    for key in keys {
+        println!(
+            "{:<18} {:<8} {}",
+            short_id(&key.keyid),
+            key.state_at(now),
+            validity(key)
+        );
+    }
+}
+
+pub fn print_rotation(result: &RotationResult, dry_run: bool, output: &OutputManager) {
+    if output.is_json() {
+        output.result_fields(result);
+        return;
+    }
+    println!(
+        "  New key {} ({})",
+        short_id(&result.added.keyid),
+        validity(&result.added)
+    );
+    if let Some(retired) = &result.retired {
+        println!(
+            "  Retiring key {} ({})",
+            short_id(&retired.keyid),
+            validity(retired)
+        );
+    }
+
+    if !result.images.is_empty() {
+        println!();
+        println!(
+            "  Installed images, as of {}:",
+            clock::format_time(result.checked_at)
+        );
+        for image in &result.images {
+            let label = match &image.version {
+                Some(version) => format!("{} {version}", image.name),
+                None => image.name.clone(),
+            };
+            let detail = match (&image.signed_by, &image.reason) {
+                (Some(keyid), _) => format!("{} ({})", image.after, short_id(keyid)),
+                (None, Some(reason)) => format!("{} ({reason})", image.after),
+                (None, None) => image.after.clone(),
+            };
+            let marker = if image.lost() {
+                "  NO LONGER VALID"
+            } else {
+                ""
+            };
+            println!("    {label:<24} {detail}{marker}");
+        }
+        println!();
+    }
+
+    let lost = result
+        .images
+        .iter()
+        .filter(|i| i.lost())
+        .count()
+        .to_string();
+    let message = if dry_run {
+        messages::TRUST_ROTATE_DRY_RUN
+    } else {
+        messages::TRUST_ROTATED
+    };
+    output.success_msg(
+        "Trust Rotate",
+        message,
+        &[("keyid", short_id(&result.added.keyid)), ("lost", &lost)],
+    );
+}
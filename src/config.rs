@@ -27,9 +27,67 @@ pub struct AvocadoConfig {
     /// Update settings (streaming, etc.)
     #[serde(default)]
     pub update: UpdateSettings,
+    /// Free-space preflight and partition growth for runtime updates
+    #[serde(default)]
+    pub storage: StorageSettings,
+    /// Version policies for `ext upgrade`
+    #[serde(default)]
+    pub upgrade: UpgradeSettings,
     /// Garbage collection settings
     #[serde(default)]
     pub gc: GcSettings,
+    /// Reboot handling for extensions that set AVOCADO_REBOOT_REQUIRED
+    #[serde(default)]
+    pub reboot: RebootSettings,
+    /// Size, count and merge-time budgets for extensions
+    #[serde(default)]
+    pub limits: LimitSettings,
+    /// Permission and ownership audit of extension contents before merge
+    #[serde(default)]
+    pub permissions: PermissionAuditSettings,
+    /// Daemon-mode refresh when extension directories change
+    #[serde(default)]
+    pub auto_refresh: AutoRefreshSettings,
+    /// When auto-refresh and `ext upgrade` may change extensions
+    #[serde(default)]
+    pub maintenance: MaintenanceSettings,
+    /// Settings for the unprivileged `--user` mode
+    #[serde(default)]
+    pub user: UserSettings,
+    /// Container mode (confexts scoped to a container)
+    #[serde(default)]
+    pub container: ContainerSettings,
+    /// HITL mount health monitoring in daemon mode
+    #[serde(default)]
+    pub hitl: HitlSettings,
+    /// Paths of the external tools avocadoctl runs
+    #[serde(default)]
+    pub tools: ToolSettings,
+    /// How long external commands may run before they are stopped
+    #[serde(default)]
+    pub timeouts: TimeoutSettings,
+    /// Boot profiling of the merge pipeline
+    #[serde(default)]
+    pub profiling: ProfilingSettings,
+    /// OpenTelemetry trace export of merge pipeline and HITL operations
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    /// Locale and translation directory for user-facing messages
+    #[serde(default)]
+    pub messages: MessageSettings,
+    /// Rotation and retention of the logs under /var/log/avocado
+    #[serde(default)]
+    pub logs: LogSettings,
+    /// Digest algorithm and implementation used to verify images
+    #[serde(default)]
+    pub checksum: ChecksumSettings,
+    /// Make every validation problem abort (`true`) or only warn (`false`).
+    /// Unset keeps each check's own default; see [`ValidationCheck`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+    /// Per-check overrides of `strict`
+    #[serde(default)]
+    pub strictness: StrictnessSettings,
 }
 
 /// Update configuration
@@ -40,6 +98,81 @@ pub struct UpdateSettings {
     /// Default: false
     #[serde(default)]
     pub stream_os_to_partition: bool,
+    /// TUF repository `ext prefetch` and `ext upgrade` download from when no
+    /// `--url` is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Storage configuration for runtime updates
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StorageSettings {
+    /// How to grow the data partition when an update does not fit.
+    /// Default: none (the update fails before downloading).
+    #[serde(default)]
+    pub grow: StorageGrowth,
+    /// Device `resize2fs` grows. Default: the device holding the avocado
+    /// base directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Space in MiB to leave free after an update. Default: 0.
+    #[serde(default)]
+    pub reserve_mb: u64,
+}
+
+/// How the data partition is grown when an update does not fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageGrowth {
+    /// Never grow; fail the update
+    #[default]
+    None,
+    /// `systemd-repart`, then `systemd-growfs` on the mount point
+    Repart,
+    /// `resize2fs` on the device, for partitions that are already larger
+    Resize2fs,
+}
+
+/// `ext upgrade` configuration
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UpgradeSettings {
+    /// Policy for extensions without an entry in `extensions`.
+    /// Default: latest.
+    #[serde(default)]
+    pub policy: UpgradePolicy,
+    /// Per-extension policies, keyed by extension name
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub extensions: std::collections::BTreeMap<String, UpgradePolicy>,
+    /// Whether pre-release versions (`1.2.0-rc1`) count as upgrades.
+    /// Default: false.
+    #[serde(default)]
+    pub prereleases: bool,
+}
+
+/// Which versions `ext upgrade` may move an extension to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpgradePolicy {
+    /// Never upgrade
+    Pin,
+    /// Only newer patch releases of the same major.minor
+    Patch,
+    /// Only newer releases of the same major version
+    Minor,
+    /// Any newer release
+    #[default]
+    Latest,
+}
+
+impl UpgradePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UpgradePolicy::Pin => "pin",
+            UpgradePolicy::Patch => "patch",
+            UpgradePolicy::Minor => "minor",
+            UpgradePolicy::Latest => "latest",
+        }
+    }
 }
 
 /// Garbage collection configuration
@@ -74,6 +207,609 @@ fn default_runtime_retention() -> u32 {
     3
 }
 
+/// Reboot configuration
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RebootSettings {
+    /// What to do after a merge when an extension sets AVOCADO_REBOOT_REQUIRED=yes.
+    /// The request is always recorded and reported; this only controls whether
+    /// avocadoctl also asks systemd to act on it. Default: none.
+    #[serde(default)]
+    pub on_required: RebootAction,
+}
+
+/// Action taken when a merged extension requests a reboot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RebootAction {
+    /// Only record and report the request
+    #[default]
+    None,
+    /// Trigger `systemctl soft-reboot` (userspace-only reboot)
+    SoftReboot,
+    /// Trigger a full `systemctl reboot`
+    Reboot,
+}
+
+/// Extension budget configuration. Every limit is disabled when unset.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LimitSettings {
+    /// Maximum size in bytes of a single extension (image file size, or the
+    /// total size of a directory extension).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_extension_size: Option<u64>,
+    /// Maximum number of extensions merged at once. Extensions beyond the
+    /// limit (lowest merge priority first) are skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_extensions: Option<usize>,
+    /// Time budget in milliseconds for preparing and mounting extensions
+    /// during merge. When exceeded the merge is aborted and the extension
+    /// being prepared at that point is reported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_time_budget_ms: Option<u64>,
+    /// What to do with an extension larger than `max_extension_size`.
+    /// Default: skip.
+    #[serde(default)]
+    pub on_oversize: OversizeAction,
+}
+
+/// Action taken for an extension that exceeds `max_extension_size`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OversizeAction {
+    /// Leave the extension out of the merge
+    #[default]
+    Skip,
+    /// Merge it anyway but report a warning
+    Warn,
+}
+
+/// HITL health monitoring configuration for `avocadoctl serve`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HitlSettings {
+    /// Probe the servers behind HITL NFS mounts and detach their extensions
+    /// when a server disappears. Default: true.
+    #[serde(default = "default_hitl_monitor")]
+    pub monitor: bool,
+    /// How long a server may stay unreachable before its extensions are
+    /// unmerged and its mounts detached, in milliseconds. Default: 30000.
+    #[serde(default = "default_hitl_grace_period_ms")]
+    pub grace_period_ms: u64,
+    /// Interval between reachability probes, in milliseconds. Default: 5000.
+    #[serde(default = "default_hitl_probe_interval_ms")]
+    pub probe_interval_ms: u64,
+    /// TCP connect timeout for a single probe, in milliseconds. Default: 2000.
+    #[serde(default = "default_hitl_probe_timeout_ms")]
+    pub probe_timeout_ms: u64,
+    /// Ports tried, in order, when the requested NFS port does not accept
+    /// connections. Empty (the default) disables port probing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_ports: Vec<u16>,
+    /// NFS protocol versions tried, in order, such as `["4.2", "4.1", "3"]`.
+    /// Default: NFSv4 only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nfs_versions: Vec<String>,
+    /// How often a failed mount is retried with all ports and versions.
+    /// Default: 0.
+    #[serde(default)]
+    pub mount_retries: u32,
+    /// Delay between mount retries, in milliseconds. Default: 1000.
+    #[serde(default = "default_hitl_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    /// Treat extensions holding rsync temporary files as being synced, so
+    /// refreshes wait for the sync to finish. Default: true.
+    #[serde(default = "default_hitl_detect_sync")]
+    pub detect_sync: bool,
+    /// How long a refresh waits for quiesced or syncing HITL extensions
+    /// before failing, in milliseconds. Default: 300000.
+    #[serde(default = "default_hitl_sync_wait_ms")]
+    pub sync_wait_ms: u64,
+    /// How many extensions `hitl mount` mounts at the same time. Default: 4.
+    #[serde(default = "default_hitl_parallel_mounts")]
+    pub parallel_mounts: usize,
+    /// Control port of the workstation-side HITL server. When set,
+    /// `hitl mount` checks the server's protocol version and exported
+    /// extensions before mounting. Default: unset (no handshake).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_port: Option<u16>,
+    /// Templates for the drop-ins written for AVOCADO_ENABLE_SERVICES
+    #[serde(default)]
+    pub dropin: HitlDropinSettings,
+}
+
+/// Drop-in templates for the services a HITL extension enables, at
+/// `[avocado.hitl.dropin]`. Templates may use `{extension}`, `{service}`,
+/// `{mount_point}`, `{mount_unit}` and `{dependencies}`, the mount ordering
+/// written when no template is set.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HitlDropinSettings {
+    /// Drop-in content for every service without an entry in `services`.
+    /// Default: `{dependencies}` only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Per-service templates, keyed by unit name (`.service` optional)
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub services: std::collections::BTreeMap<String, String>,
+}
+
+impl HitlDropinSettings {
+    /// The template for `service_unit` (`name.service`), if one is configured.
+    pub fn template_for(&self, service_unit: &str) -> Option<&str> {
+        let name = service_unit.trim_end_matches(".service");
+        self.services
+            .get(service_unit)
+            .or_else(|| self.services.get(name))
+            .or(self.template.as_ref())
+            .map(String::as_str)
+    }
+}
+
+impl Default for HitlSettings {
+    fn default() -> Self {
+        Self {
+            monitor: default_hitl_monitor(),
+            grace_period_ms: default_hitl_grace_period_ms(),
+            probe_interval_ms: default_hitl_probe_interval_ms(),
+            probe_timeout_ms: default_hitl_probe_timeout_ms(),
+            fallback_ports: Vec::new(),
+            nfs_versions: Vec::new(),
+            mount_retries: 0,
+            retry_delay_ms: default_hitl_retry_delay_ms(),
+            detect_sync: default_hitl_detect_sync(),
+            sync_wait_ms: default_hitl_sync_wait_ms(),
+            parallel_mounts: default_hitl_parallel_mounts(),
+            control_port: None,
+            dropin: HitlDropinSettings::default(),
+        }
+    }
+}
+
+fn default_hitl_monitor() -> bool {
+    true
+}
+
+fn default_hitl_grace_period_ms() -> u64 {
+    30000
+}
+
+fn default_hitl_probe_interval_ms() -> u64 {
+    5000
+}
+
+fn default_hitl_detect_sync() -> bool {
+    true
+}
+
+fn default_hitl_sync_wait_ms() -> u64 {
+    300000
+}
+
+fn default_hitl_retry_delay_ms() -> u64 {
+    1000
+}
+
+fn default_hitl_parallel_mounts() -> usize {
+    4
+}
+
+fn default_hitl_probe_timeout_ms() -> u64 {
+    2000
+}
+
+/// User-mode configuration
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UserSettings {
+    /// Root prefix that system paths are relocated under in `--user` mode.
+    /// Default: $XDG_STATE_HOME/avocado/root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+}
+
+/// Container mode settings
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ContainerSettings {
+    /// Enter container mode without `--container` when running in a
+    /// container that provides /run/host. Default: false
+    #[serde(default)]
+    pub auto: bool,
+}
+
+/// Paths or names of external tools. Each unset tool is looked up on PATH,
+/// then in /usr/lib/systemd.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ToolSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub systemd_sysext: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub systemd_confext: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub systemd_dissect: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depmod: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modprobe: Option<String>,
+}
+
+/// Time limits, in seconds, for the external commands avocadoctl runs. A
+/// command still running at its limit is sent SIGTERM, then SIGKILL. 0
+/// disables the limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeoutSettings {
+    /// systemd-sysext, systemd-confext, systemctl and the storage growth
+    /// commands. Default: 90
+    #[serde(default = "default_systemd_cmd_timeout")]
+    pub systemd_cmd: u64,
+    /// AVOCADO_ON_MERGE/ON_UNMERGE commands, depmod and modprobe. Default: 60
+    #[serde(default = "default_hook_cmd_timeout")]
+    pub hook_cmd: u64,
+    /// systemd-mount of a HITL NFS export. Default: 60
+    #[serde(default = "default_nfs_mount_timeout")]
+    pub nfs_mount: u64,
+    /// Loop device setup and mounts of disk images. Default: 30
+    #[serde(default = "default_loop_mount_timeout")]
+    pub loop_mount: u64,
+}
+
+impl Default for TimeoutSettings {
+    fn default() -> Self {
+        Self {
+            systemd_cmd: default_systemd_cmd_timeout(),
+            hook_cmd: default_hook_cmd_timeout(),
+            nfs_mount: default_nfs_mount_timeout(),
+            loop_mount: default_loop_mount_timeout(),
+        }
+    }
+}
+
+/// Boot profiling of the merge pipeline. Each phase (scanning, mounting,
+/// merging, hooks) is always reported through sd_notify `STATUS=` updates.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProfilingSettings {
+    /// Run the external commands of each phase in their own transient
+    /// systemd units (`avocado-merge-<phase>-*`), so `systemd-analyze
+    /// blame` attributes their time to the phase. Default: false
+    #[serde(default)]
+    pub phase_units: bool,
+}
+
+/// OpenTelemetry trace export. Spans for scanning, mounting, merging,
+/// hooks and HITL operations are sent as OTLP/HTTP JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP collector base URL, e.g. `http://collector:4318`; traces
+    /// are posted to `<endpoint>/v1/traces`. Default: none (no export)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` resource attribute. Default: "avocadoctl"
+    #[serde(default = "default_telemetry_service_name")]
+    pub service_name: String,
+    /// How long an export may take before it is dropped. Default: 1000
+    #[serde(default = "default_telemetry_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: default_telemetry_service_name(),
+            timeout_ms: default_telemetry_timeout_ms(),
+        }
+    }
+}
+
+fn default_telemetry_service_name() -> String {
+    "avocadoctl".to_string()
+}
+
+fn default_telemetry_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_systemd_cmd_timeout() -> u64 {
+    90
+}
+
+fn default_hook_cmd_timeout() -> u64 {
+    60
+}
+
+fn default_nfs_mount_timeout() -> u64 {
+    60
+}
+
+fn default_loop_mount_timeout() -> u64 {
+    30
+}
+
+/// Message localization. Unset values fall back to the locale environment
+/// variables and /usr/share/avocado/messages.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MessageSettings {
+    /// Locale such as `de_DE`; `en` or `C` select the built-in English text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Directory holding `<locale>.toml` translations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+}
+
+/// Rotation and retention of the logs under /var/log/avocado. A log is
+/// rotated when it reaches `max_size_kb` or its oldest record is older than
+/// `max_age_days`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogSettings {
+    /// Size in KiB at which a log is rotated. Default: 256
+    #[serde(default = "default_log_max_size_kb")]
+    pub max_size_kb: u64,
+    /// Rotated files kept per log. Default: 3
+    #[serde(default = "default_log_keep")]
+    pub keep: usize,
+    /// Age in days after which records are rotated out and rotated files
+    /// deleted; 0 keeps them until `keep` drops them. Default: 30
+    #[serde(default = "default_log_max_age_days")]
+    pub max_age_days: u64,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            max_size_kb: default_log_max_size_kb(),
+            keep: default_log_keep(),
+            max_age_days: default_log_max_age_days(),
+        }
+    }
+}
+
+fn default_log_max_size_kb() -> u64 {
+    256
+}
+
+fn default_log_keep() -> usize {
+    3
+}
+
+fn default_log_max_age_days() -> u64 {
+    30
+}
+
+/// Digest verified for images a runtime manifest lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+            ChecksumAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Implementation computing digests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumBackend {
+    /// The kernel crypto API when built with it and the kernel offers the
+    /// algorithm, else software
+    #[default]
+    Auto,
+    /// In-process implementations
+    Software,
+    /// The kernel crypto API (AF_ALG), falling back to software for
+    /// algorithms the kernel lacks
+    Kernel,
+}
+
+/// How images are checksummed when installed and verified. `algorithm`
+/// picks the manifest digest to verify; images without it are verified by
+/// their SHA-256. `repositories` overrides either setting for runtimes
+/// downloaded from an update repository URL.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ChecksumSettings {
+    /// Default: sha256
+    #[serde(default)]
+    pub algorithm: ChecksumAlgorithm,
+    /// Default: auto
+    #[serde(default)]
+    pub backend: ChecksumBackend,
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub repositories: std::collections::BTreeMap<String, RepositoryChecksum>,
+}
+
+/// Per-repository overrides of [`ChecksumSettings`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+pub struct RepositoryChecksum {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<ChecksumAlgorithm>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<ChecksumBackend>,
+}
+
+impl ChecksumSettings {
+    /// Algorithm and backend for images from `repository`, or for images
+    /// installed from a local manifest when `None`. Repository URLs match
+    /// without trailing slashes.
+    pub fn resolve(&self, repository: Option<&str>) -> (ChecksumAlgorithm, ChecksumBackend) {
+        let overrides = repository.and_then(|url| {
+            let url = url.trim_end_matches('/');
+            self.repositories
+                .iter()
+                .find(|(key, _)| key.trim_end_matches('/') == url)
+                .map(|(_, overrides)| *overrides)
+        });
+        let overrides = overrides.unwrap_or_default();
+        (
+            overrides.algorithm.unwrap_or(self.algorithm),
+            overrides.backend.unwrap_or(self.backend),
+        )
+    }
+}
+
+/// Whether a validation problem aborts the operation or is only reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationPolicy {
+    Error,
+    Warn,
+}
+
+/// Permission and ownership audit of extension contents, run before the
+/// extensions are linked for a merge. Disabled by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionAuditSettings {
+    /// Scan every extension for the anomalies below before merging.
+    #[serde(default)]
+    pub enabled: bool,
+    /// What to do with an extension that has findings. Default: skip.
+    #[serde(default)]
+    pub on_finding: FindingAction,
+    /// Paths (as merged, e.g. "/usr/bin/sudo") allowed to be setuid or setgid.
+    #[serde(default)]
+    pub allowed_setuid: Vec<String>,
+    /// Owners files and directories may have; anything else is reported.
+    /// Default: [0] (root).
+    #[serde(default = "default_allowed_uids")]
+    pub allowed_uids: Vec<u32>,
+}
+
+impl Default for PermissionAuditSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_finding: FindingAction::default(),
+            allowed_setuid: Vec::new(),
+            allowed_uids: default_allowed_uids(),
+        }
+    }
+}
+
+fn default_allowed_uids() -> Vec<u32> {
+    vec![0]
+}
+
+/// Action taken for an extension the permission audit reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum FindingAction {
+    /// Leave the extension out of the merge
+    #[default]
+    Skip,
+    /// Merge it anyway but report the findings
+    Warn,
+}
+
+/// Validation performed before an extension is enabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationCheck {
+    /// The release file's ID, VERSION_ID or *_LEVEL does not match the
+    /// target OS release. Default: error.
+    Scope,
+    /// The extension has no extension-release file. Default: warn.
+    MissingReleaseFile,
+    /// The release file sets an AVOCADO_* key avocadoctl does not know,
+    /// usually a typo. Default: warn.
+    UnknownKeys,
+    /// The image does not match the SHA256 the active runtime manifest
+    /// records for it. Default: warn.
+    Checksum,
+}
+
+impl ValidationCheck {
+    /// Policy when neither `strict` nor an override is configured.
+    fn default_policy(self) -> ValidationPolicy {
+        match self {
+            ValidationCheck::Scope => ValidationPolicy::Error,
+            _ => ValidationPolicy::Warn,
+        }
+    }
+
+    /// Configuration key of the check in `[avocado.strictness]`.
+    pub fn key(self) -> &'static str {
+        match self {
+            ValidationCheck::Scope => "scope",
+            ValidationCheck::MissingReleaseFile => "missing_release_file",
+            ValidationCheck::UnknownKeys => "unknown_keys",
+            ValidationCheck::Checksum => "checksum",
+        }
+    }
+}
+
+/// Per-check overrides of `[avocado] strict`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StrictnessSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<ValidationPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub missing_release_file: Option<ValidationPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unknown_keys: Option<ValidationPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<ValidationPolicy>,
+}
+
+/// Auto-refresh configuration for `avocadoctl serve`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoRefreshSettings {
+    /// Refresh extensions when the extensions, os-releases or HITL
+    /// directories change. Default: false.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Quiet period in milliseconds after the last change before refreshing.
+    /// Changes within the window are coalesced. Default: 500.
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    /// Minimum time in milliseconds between two refreshes. Default: 5000.
+    #[serde(default = "default_min_interval_ms")]
+    pub min_interval_ms: u64,
+    /// How often the watched directories are scanned for changes, in
+    /// milliseconds. Default: 1000.
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+impl Default for AutoRefreshSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            debounce_ms: default_debounce_ms(),
+            min_interval_ms: default_min_interval_ms(),
+            poll_interval_ms: default_poll_interval_ms(),
+        }
+    }
+}
+
+fn default_debounce_ms() -> u64 {
+    500
+}
+
+fn default_min_interval_ms() -> u64 {
+    5000
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+/// Maintenance windows for auto-refresh and `ext upgrade`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MaintenanceSettings {
+    /// Cron-like expressions (`minute hour day-of-month month day-of-week`)
+    /// of the minutes changes are allowed in. Default: none (always allowed).
+    #[serde(default)]
+    pub windows: Vec<String>,
+    /// Offset from UTC the windows are evaluated at, as `+HH:MM` or
+    /// `-HH:MM`. Default: UTC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utc_offset: Option<String>,
+}
+
 /// Extension configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExtConfig {
@@ -90,6 +826,103 @@ pub struct ExtConfig {
     /// Total I/O per file = 2 * spot_check_bytes. Default: 4096.
     #[serde(default = "default_spot_check_bytes")]
     pub spot_check_bytes: u64,
+    /// Which extensions to merge when the running VERSION_ID has no
+    /// os-releases directory (legacy, manifest-less discovery). Default: base.
+    #[serde(default)]
+    pub os_release_fallback: OsReleaseFallback,
+    /// systemd image policy (systemd.image-policy(7)) passed to systemd-dissect
+    /// when mounting images and to systemd-sysext/confext on merge. Replaces
+    /// the policy implied by `verity`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_policy: Option<String>,
+    /// dm-verity requirement for extension images. Unset keeps systemd's
+    /// default policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verity: Option<VerityMode>,
+    /// Pass `--noexec=` to systemd-sysext/confext merge.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noexec: Option<bool>,
+    /// Skip extensions that fail to mount or validate and merge the rest,
+    /// as `--keep-going` does. Default: false.
+    #[serde(default)]
+    pub keep_going: bool,
+    /// Command run with a JSON summary on its stdin whenever a
+    /// refresh changes the merged extension set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_change_exec: Option<String>,
+    /// Keep writers away from the extension images while a merge mounts
+    /// them. Default: off.
+    #[serde(default)]
+    pub protect_source: SourceProtection,
+    /// Short names accepted in place of an extension name by enable,
+    /// disable, info and hitl: `camera = "vendor-camera-stack"`.
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub aliases: std::collections::BTreeMap<String, String>,
+    /// What a scan does when the extensions directory or the os-releases
+    /// directory does not exist. Default: ignore.
+    #[serde(default)]
+    pub missing_dir: MissingDirPolicy,
+    /// Create a missing extensions or os-releases directory, owned like the
+    /// closest existing directory above it. Default: false.
+    #[serde(default)]
+    pub create_dirs: bool,
+    /// Merge extension directories, which cannot be signed, while the
+    /// signing trust store holds keys. Meant for HITL development.
+    /// Default: false (they are refused like unsigned images).
+    #[serde(default)]
+    pub allow_unsigned_directories: bool,
+}
+
+/// What to do when a directory extensions are read from does not exist
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingDirPolicy {
+    /// Fail the command
+    Error,
+    /// Print a warning and continue as if the directory were empty
+    Warn,
+    /// Continue as if the directory were empty
+    #[default]
+    Ignore,
+}
+
+/// How strictly dm-verity protection is required for extension images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerityMode {
+    /// Refuse images whose partitions are not verity protected
+    Enforce,
+    /// Try the verity-only policy first; on failure warn and mount anyway
+    Warn,
+    /// Use partitions without verity even when verity data is present
+    Off,
+}
+
+/// How the extensions directory is protected against writes during a merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceProtection {
+    /// No protection
+    #[default]
+    Off,
+    /// Shared flock(2) on every image; writers taking an exclusive lock wait
+    Lock,
+    /// Read-only bind mount over the directory; every write fails
+    ReadOnly,
+}
+
+/// Extension set used when the current VERSION_ID has no os-releases directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OsReleaseFallback {
+    /// Inherit the enabled set of the closest earlier VERSION_ID, falling
+    /// back to the base directory when there is none
+    Previous,
+    /// Merge no extensions from the extensions directory
+    None,
+    /// Merge every extension in the base extensions directory
+    #[default]
+    Base,
 }
 
 fn default_spot_check_bytes() -> u64 {
@@ -106,11 +939,41 @@ impl Default for Config {
                     confext_mutable: None,
                     mutable: None,
                     spot_check_bytes: default_spot_check_bytes(),
+                    os_release_fallback: OsReleaseFallback::default(),
+                    image_policy: None,
+                    verity: None,
+                    noexec: None,
+                    keep_going: false,
+                    on_change_exec: None,
+                    protect_source: SourceProtection::default(),
+                    aliases: std::collections::BTreeMap::new(),
+                    missing_dir: MissingDirPolicy::default(),
+                    create_dirs: false,
+                    allow_unsigned_directories: false,
                 },
                 runtimes_dir: None,
                 socket: None,
                 update: UpdateSettings::default(),
+                storage: StorageSettings::default(),
+                upgrade: UpgradeSettings::default(),
                 gc: GcSettings::default(),
+                reboot: RebootSettings::default(),
+                limits: LimitSettings::default(),
+                permissions: PermissionAuditSettings::default(),
+                auto_refresh: AutoRefreshSettings::default(),
+                maintenance: MaintenanceSettings::default(),
+                user: UserSettings::default(),
+                container: ContainerSettings::default(),
+                hitl: HitlSettings::default(),
+                tools: ToolSettings::default(),
+                timeouts: TimeoutSettings::default(),
+                profiling: ProfilingSettings::default(),
+                telemetry: TelemetrySettings::default(),
+                messages: MessageSettings::default(),
+                logs: LogSettings::default(),
+                checksum: ChecksumSettings::default(),
+                strict: None,
+                strictness: StrictnessSettings::default(),
             },
         }
     }
@@ -191,6 +1054,195 @@ impl Config {
         self.avocado.gc.auto_gc
     }
 
+    /// Action to take after a merge when an extension requests a reboot.
+    pub fn reboot_action(&self) -> RebootAction {
+        self.avocado.reboot.on_required
+    }
+
+    /// Policy for a VERSION_ID without an os-releases directory.
+    pub fn os_release_fallback(&self) -> OsReleaseFallback {
+        self.avocado.ext.os_release_fallback
+    }
+
+    /// The extension `name` stands for: the target of the alias `name`,
+    /// else `name` itself.
+    pub fn resolve_extension_alias(&self, name: &str) -> String {
+        self.avocado
+            .ext
+            .aliases
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// `names` with each alias resolved. A HITL `NAME@SERVER` spec keeps
+    /// its server.
+    pub fn resolve_extension_aliases<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<String> {
+        names
+            .into_iter()
+            .map(|name| match name.split_once('@') {
+                Some((name, server)) => {
+                    format!("{}@{server}", self.resolve_extension_alias(name))
+                }
+                None => self.resolve_extension_alias(name),
+            })
+            .collect()
+    }
+
+    /// Aliases of the extension `name` at `version`, whether they target
+    /// the bare or the versioned name.
+    pub fn extension_aliases(&self, name: &str, version: Option<&str>) -> Vec<String> {
+        let versioned = version.map(|v| format!("{name}-{v}"));
+        self.avocado
+            .ext
+            .aliases
+            .iter()
+            .filter(|(_, target)| *target == name || Some(*target) == versioned.as_ref())
+            .map(|(alias, _)| alias.clone())
+            .collect()
+    }
+
+    /// Explicit systemd image policy for extension images, if configured.
+    pub fn image_policy(&self) -> Option<&str> {
+        self.avocado.ext.image_policy.as_deref()
+    }
+
+    /// Configured dm-verity requirement for extension images.
+    pub fn verity(&self) -> Option<VerityMode> {
+        self.avocado.ext.verity
+    }
+
+    /// Configured `--noexec=` setting for merges.
+    pub fn noexec(&self) -> Option<bool> {
+        self.avocado.ext.noexec
+    }
+
+    /// Whether merges skip extensions that fail to prepare instead of
+    /// failing.
+    pub fn keep_going(&self) -> bool {
+        self.avocado.ext.keep_going
+    }
+
+    /// Whether extension directories are merged while the trust store holds keys.
+    pub fn allow_unsigned_directories(&self) -> bool {
+        self.avocado.ext.allow_unsigned_directories
+    }
+
+    /// How the extensions directory is protected during a merge.
+    pub fn protect_source(&self) -> SourceProtection {
+        self.avocado.ext.protect_source
+    }
+
+    /// Command to run when a refresh changes the merged extension set.
+    pub fn on_change_exec(&self) -> Option<&str> {
+        self.avocado.ext.on_change_exec.as_deref()
+    }
+
+    /// Size, count and merge-time budgets for extensions.
+    pub fn limits(&self) -> &LimitSettings {
+        &self.avocado.limits
+    }
+
+    /// Permission and ownership audit settings for merges.
+    pub fn permissions(&self) -> &PermissionAuditSettings {
+        &self.avocado.permissions
+    }
+
+    /// HITL mount health monitoring settings.
+    pub fn hitl(&self) -> &HitlSettings {
+        &self.avocado.hitl
+    }
+
+    /// Free-space preflight settings for runtime updates.
+    pub fn storage(&self) -> &StorageSettings {
+        &self.avocado.storage
+    }
+
+    /// `ext upgrade` policy for an extension.
+    pub fn upgrade_policy(&self, extension: &str) -> UpgradePolicy {
+        let upgrade = &self.avocado.upgrade;
+        upgrade
+            .extensions
+            .get(extension)
+            .copied()
+            .unwrap_or(upgrade.policy)
+    }
+
+    /// Whether `ext upgrade` and update checks consider pre-releases.
+    pub fn upgrade_prereleases(&self) -> bool {
+        self.avocado.upgrade.prereleases
+    }
+
+    /// Configured external tool paths.
+    pub fn tools(&self) -> &ToolSettings {
+        &self.avocado.tools
+    }
+
+    /// Command time limits.
+    pub fn timeouts(&self) -> &TimeoutSettings {
+        &self.avocado.timeouts
+    }
+
+    /// Container mode settings.
+    pub fn container(&self) -> &ContainerSettings {
+        &self.avocado.container
+    }
+
+    /// Merge pipeline profiling settings.
+    pub fn profiling(&self) -> &ProfilingSettings {
+        &self.avocado.profiling
+    }
+
+    /// OpenTelemetry export settings.
+    pub fn telemetry(&self) -> &TelemetrySettings {
+        &self.avocado.telemetry
+    }
+
+    /// Message localization settings.
+    pub fn messages(&self) -> &MessageSettings {
+        &self.avocado.messages
+    }
+
+    /// Log rotation settings.
+    pub fn logs(&self) -> &LogSettings {
+        &self.avocado.logs
+    }
+
+    /// Image checksum settings.
+    pub fn checksum(&self) -> &ChecksumSettings {
+        &self.avocado.checksum
+    }
+
+    /// Whether a problem found by `check` aborts or only warns: the per-check
+    /// override, else `strict`, else the check's default.
+    pub fn validation_policy(&self, check: ValidationCheck) -> ValidationPolicy {
+        let strictness = &self.avocado.strictness;
+        let override_policy = match check {
+            ValidationCheck::Scope => strictness.scope,
+            ValidationCheck::MissingReleaseFile => strictness.missing_release_file,
+            ValidationCheck::UnknownKeys => strictness.unknown_keys,
+            ValidationCheck::Checksum => strictness.checksum,
+        };
+        override_policy.unwrap_or_else(|| match self.avocado.strict {
+            Some(true) => ValidationPolicy::Error,
+            Some(false) => ValidationPolicy::Warn,
+            None => check.default_policy(),
+        })
+    }
+
+    /// Daemon auto-refresh settings.
+    pub fn auto_refresh(&self) -> &AutoRefreshSettings {
+        &self.avocado.auto_refresh
+    }
+
+    /// Maintenance window settings.
+    pub fn maintenance(&self) -> &MaintenanceSettings {
+        &self.avocado.maintenance
+    }
+
     /// Get the sysext mutable mode, defaulting to "ephemeral" if not set
     /// Validates that the value is one of the supported systemd options
     pub fn get_sysext_mutable(&self) -> Result<String, ConfigError> {
@@ -714,6 +1766,595 @@ stream_os_to_partition = true
         assert!(config.stream_os_to_partition());
     }
 
+    #[test]
+    fn test_reboot_action_default_none() {
+        let config = Config::default();
+        assert_eq!(config.reboot_action(), RebootAction::None);
+    }
+
+    #[test]
+    fn test_reboot_action_from_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("reboot_test.toml");
+
+        let config_content = r#"
+[avocado.ext]
+dir = "/var/lib/avocado/images"
+
+[avocado.reboot]
+on_required = "soft-reboot"
+"#;
+
+        fs::write(&config_path, config_content).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.reboot_action(), RebootAction::SoftReboot);
+    }
+
+    #[test]
+    fn test_os_release_fallback() {
+        assert_eq!(
+            Config::default().os_release_fallback(),
+            OsReleaseFallback::Base
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("fallback_test.toml");
+        let config_content = r#"
+[avocado.ext]
+dir = "/var/lib/avocado/images"
+os_release_fallback = "previous"
+"#;
+        fs::write(&config_path, config_content).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.os_release_fallback(), OsReleaseFallback::Previous);
+    }
+
+    #[test]
+    fn test_image_policy_and_verity() {
+        let config = Config::default();
+        assert_eq!(config.image_policy(), None);
+        assert_eq!(config.verity(), None);
+        assert_eq!(config.noexec(), None);
+        assert!(!config.keep_going());
+        assert_eq!(config.on_change_exec(), None);
+        assert_eq!(config.protect_source(), SourceProtection::Off);
+
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("image_policy_test.toml");
+        let config_content = r#"
+[avocado.ext]
+dir = "/var/lib/avocado/images"
+image_policy = "root=verity+signed:usr=verity+signed"
+verity = "warn"
+noexec = true
+keep_going = true
+on_change_exec = "/usr/libexec/product/ext-changed --notify"
+protect_source = "read-only"
+"#;
+        fs::write(&config_path, config_content).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(
+            config.image_policy(),
+            Some("root=verity+signed:usr=verity+signed")
+        );
+        assert_eq!(config.verity(), Some(VerityMode::Warn));
+        assert_eq!(config.noexec(), Some(true));
+        assert!(config.keep_going());
+        assert_eq!(
+            config.on_change_exec(),
+            Some("/usr/libexec/product/ext-changed --notify")
+        );
+        assert_eq!(config.protect_source(), SourceProtection::ReadOnly);
+
+        fs::write(
+            &config_path,
+            "[avocado.ext]\ndir = \"/x\"\nverity = \"strict\"\n",
+        )
+        .unwrap();
+        assert!(Config::load(&config_path).is_err());
+    }
+
+    #[test]
+    fn test_limits_default_disabled() {
+        let config = Config::default();
+        assert_eq!(config.limits().max_extension_size, None);
+        assert_eq!(config.limits().max_total_extensions, None);
+        assert_eq!(config.limits().merge_time_budget_ms, None);
+        assert_eq!(config.limits().on_oversize, OversizeAction::Skip);
+    }
+
+    #[test]
+    fn test_limits_from_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("limits_test.toml");
+
+        let config_content = r#"
+[avocado.ext]
+dir = "/var/lib/avocado/images"
+
+[avocado.limits]
+max_extension_size = 1048576
+max_total_extensions = 8
+merge_time_budget_ms = 5000
+on_oversize = "warn"
+"#;
+
+        fs::write(&config_path, config_content).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.limits().max_extension_size, Some(1048576));
+        assert_eq!(config.limits().max_total_extensions, Some(8));
+        assert_eq!(config.limits().merge_time_budget_ms, Some(5000));
+        assert_eq!(config.limits().on_oversize, OversizeAction::Warn);
+    }
+
+    #[test]
+    fn test_storage_settings() {
+        let config = Config::default();
+        assert_eq!(config.storage().grow, StorageGrowth::None);
+        assert_eq!(config.storage().reserve_mb, 0);
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.storage]
+grow = "resize2fs"
+device = "/dev/mmcblk0p4"
+reserve_mb = 64
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.storage().grow, StorageGrowth::Resize2fs);
+        assert_eq!(config.storage().device.as_deref(), Some("/dev/mmcblk0p4"));
+        assert_eq!(config.storage().reserve_mb, 64);
+    }
+
+    #[test]
+    fn test_upgrade_policy() {
+        let config = Config::default();
+        assert_eq!(config.upgrade_policy("app"), UpgradePolicy::Latest);
+        assert!(!config.upgrade_prereleases());
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.upgrade]
+policy = "minor"
+prereleases = true
+
+[avocado.upgrade.extensions]
+app = "patch"
+kernel-modules = "pin"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.upgrade_policy("app"), UpgradePolicy::Patch);
+        assert_eq!(config.upgrade_policy("kernel-modules"), UpgradePolicy::Pin);
+        assert_eq!(config.upgrade_policy("other"), UpgradePolicy::Minor);
+        assert!(config.upgrade_prereleases());
+    }
+
+    #[test]
+    fn test_container_settings() {
+        let config = Config::default();
+        assert!(!config.container().auto);
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.container]
+auto = true
+"#,
+        )
+        .unwrap();
+        assert!(config.container().auto);
+    }
+
+    #[test]
+    fn test_profiling_settings() {
+        let config = Config::default();
+        assert!(!config.profiling().phase_units);
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.profiling]
+phase_units = true
+"#,
+        )
+        .unwrap();
+        assert!(config.profiling().phase_units);
+    }
+
+    #[test]
+    fn test_permission_audit_settings() {
+        let config = Config::default();
+        assert!(!config.permissions().enabled);
+        assert_eq!(config.permissions().on_finding, FindingAction::Skip);
+        assert_eq!(config.permissions().allowed_uids, vec![0]);
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.permissions]
+enabled = true
+on_finding = "warn"
+allowed_setuid = ["/usr/bin/sudo"]
+"#,
+        )
+        .unwrap();
+        assert!(config.permissions().enabled);
+        assert_eq!(config.permissions().on_finding, FindingAction::Warn);
+        assert_eq!(config.permissions().allowed_setuid, vec!["/usr/bin/sudo"]);
+        assert_eq!(config.permissions().allowed_uids, vec![0]);
+    }
+
+    #[test]
+    fn test_timeout_settings() {
+        let config = Config::default();
+        assert_eq!(config.timeouts().systemd_cmd, 90);
+        assert_eq!(config.timeouts().hook_cmd, 60);
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.timeouts]
+hook_cmd = 5
+nfs_mount = 0
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.timeouts().hook_cmd, 5);
+        assert_eq!(config.timeouts().nfs_mount, 0);
+        assert_eq!(config.timeouts().loop_mount, 30);
+    }
+
+    #[test]
+    fn test_hitl_settings_defaults_and_overrides() {
+        let config = Config::default();
+        assert!(config.hitl().monitor);
+        assert_eq!(config.hitl().grace_period_ms, 30000);
+        assert!(config.hitl().detect_sync);
+        assert_eq!(config.hitl().sync_wait_ms, 300000);
+        assert_eq!(config.hitl().control_port, None);
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.hitl]
+monitor = false
+grace_period_ms = 5000
+fallback_ports = [2049, 20049]
+nfs_versions = ["4.2", "3"]
+detect_sync = false
+sync_wait_ms = 10000
+control_port = 12050
+"#,
+        )
+        .unwrap();
+        assert!(!config.hitl().monitor);
+        assert_eq!(config.hitl().grace_period_ms, 5000);
+        assert_eq!(config.hitl().probe_interval_ms, 5000);
+        assert_eq!(config.hitl().probe_timeout_ms, 2000);
+        assert_eq!(config.hitl().fallback_ports, vec![2049, 20049]);
+        assert_eq!(config.hitl().nfs_versions, vec!["4.2", "3"]);
+        assert_eq!(config.hitl().mount_retries, 0);
+        assert_eq!(config.hitl().retry_delay_ms, 1000);
+        assert!(!config.hitl().detect_sync);
+        assert_eq!(config.hitl().sync_wait_ms, 10000);
+        assert_eq!(config.hitl().control_port, Some(12050));
+    }
+
+    #[test]
+    fn test_hitl_dropin_templates() {
+        let config = Config::default();
+        assert!(config.hitl().dropin.template_for("app.service").is_none());
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.hitl.dropin]
+template = "{dependencies}"
+
+[avocado.hitl.dropin.services]
+app = "{dependencies}[Service]\nExecStartPre=/usr/bin/true\n"
+"#,
+        )
+        .unwrap();
+        let dropin = &config.hitl().dropin;
+        assert!(dropin
+            .template_for("app.service")
+            .unwrap()
+            .contains("ExecStartPre"));
+        assert_eq!(dropin.template_for("db.service"), Some("{dependencies}"));
+    }
+
+    #[test]
+    fn test_auto_refresh_defaults_and_overrides() {
+        let config = Config::default();
+        assert!(!config.auto_refresh().enabled);
+        assert_eq!(config.auto_refresh().debounce_ms, 500);
+        assert_eq!(config.auto_refresh().min_interval_ms, 5000);
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.auto_refresh]
+enabled = true
+debounce_ms = 250
+"#,
+        )
+        .unwrap();
+        assert!(config.auto_refresh().enabled);
+        assert_eq!(config.auto_refresh().debounce_ms, 250);
+        assert_eq!(config.auto_refresh().min_interval_ms, 5000);
+        assert_eq!(config.auto_refresh().poll_interval_ms, 1000);
+    }
+
+    #[test]
+    fn test_maintenance_windows() {
+        assert!(Config::default().maintenance().windows.is_empty());
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.maintenance]
+windows = ["* 22-5 * * mon-fri", "* * * * sat,sun"]
+utc_offset = "+01:00"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.maintenance().windows.len(), 2);
+        assert_eq!(config.maintenance().utc_offset.as_deref(), Some("+01:00"));
+    }
+
+    #[test]
+    fn test_telemetry_settings() {
+        let config = Config::default();
+        assert!(config.telemetry().otlp_endpoint.is_none());
+        assert_eq!(config.telemetry().service_name, "avocadoctl");
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.telemetry]
+otlp_endpoint = "http://collector:4318"
+timeout_ms = 250
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.telemetry().otlp_endpoint.as_deref(),
+            Some("http://collector:4318")
+        );
+        assert_eq!(config.telemetry().service_name, "avocadoctl");
+        assert_eq!(config.telemetry().timeout_ms, 250);
+    }
+
+    #[test]
+    fn test_tool_overrides() {
+        let config = Config::default();
+        assert!(config.tools().systemd_sysext.is_none());
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.tools]
+systemd_sysext = "/usr/lib/systemd/systemd-sysext"
+depmod = "/bin/busybox-depmod"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.tools().systemd_sysext.as_deref(),
+            Some("/usr/lib/systemd/systemd-sysext")
+        );
+        assert_eq!(
+            config.tools().depmod.as_deref(),
+            Some("/bin/busybox-depmod")
+        );
+        assert!(config.tools().modprobe.is_none());
+    }
+
+    #[test]
+    fn test_message_settings() {
+        let config = Config::default();
+        assert!(config.messages().locale.is_none());
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.messages]
+locale = "de_DE"
+dir = "/opt/vendor/messages"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.messages().locale.as_deref(), Some("de_DE"));
+        assert_eq!(
+            config.messages().dir.as_deref(),
+            Some("/opt/vendor/messages")
+        );
+    }
+
+    #[test]
+    fn test_log_settings() {
+        let config = Config::default();
+        assert_eq!(config.logs().max_size_kb, 256);
+        assert_eq!(config.logs().keep, 3);
+        assert_eq!(config.logs().max_age_days, 30);
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.logs]
+max_size_kb = 64
+max_age_days = 0
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.logs().max_size_kb, 64);
+        assert_eq!(config.logs().keep, 3);
+        assert_eq!(config.logs().max_age_days, 0);
+    }
+
+    #[test]
+    fn test_extension_aliases() {
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.ext.aliases]
+camera = "vendor-camera-stack"
+tools = "vendor-tools-2.1"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.resolve_extension_alias("camera"),
+            "vendor-camera-stack"
+        );
+        assert_eq!(config.resolve_extension_alias("other"), "other");
+        let specs = ["camera@10.0.0.2:2049".to_string(), "other".to_string()];
+        assert_eq!(
+            config.resolve_extension_aliases(&specs),
+            ["vendor-camera-stack@10.0.0.2:2049", "other"]
+        );
+        assert_eq!(
+            config.extension_aliases("vendor-camera-stack", Some("1.0")),
+            ["camera"]
+        );
+        assert_eq!(
+            config.extension_aliases("vendor-tools", Some("2.1")),
+            ["tools"]
+        );
+        assert!(config
+            .extension_aliases("vendor-tools", Some("2.2"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_checksum_settings() {
+        let config = Config::default();
+        assert_eq!(
+            config
+                .checksum()
+                .resolve(Some("https://updates.example.com")),
+            (ChecksumAlgorithm::Sha256, ChecksumBackend::Auto)
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.checksum]
+algorithm = "sha512"
+
+[avocado.checksum.repositories."https://updates.example.com/device/"]
+algorithm = "blake3"
+backend = "software"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.checksum().resolve(None),
+            (ChecksumAlgorithm::Sha512, ChecksumBackend::Auto)
+        );
+        assert_eq!(
+            config
+                .checksum()
+                .resolve(Some("https://updates.example.com/device")),
+            (ChecksumAlgorithm::Blake3, ChecksumBackend::Software)
+        );
+        assert_eq!(
+            config.checksum().resolve(Some("https://other.example.com")),
+            (ChecksumAlgorithm::Sha512, ChecksumBackend::Auto)
+        );
+    }
+
+    #[test]
+    fn test_validation_policy() {
+        let config = Config::default();
+        assert_eq!(
+            config.validation_policy(ValidationCheck::Scope),
+            ValidationPolicy::Error
+        );
+        assert_eq!(
+            config.validation_policy(ValidationCheck::Checksum),
+            ValidationPolicy::Warn
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado]
+strict = true
+
+[avocado.ext]
+dir = "/tmp/ext"
+
+[avocado.strictness]
+unknown_keys = "warn"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.validation_policy(ValidationCheck::MissingReleaseFile),
+            ValidationPolicy::Error
+        );
+        assert_eq!(
+            config.validation_policy(ValidationCheck::UnknownKeys),
+            ValidationPolicy::Warn
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+[avocado]
+strict = false
+
+[avocado.ext]
+dir = "/tmp/ext"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.validation_policy(ValidationCheck::Scope),
+            ValidationPolicy::Warn
+        );
+    }
+
     #[test]
     fn test_load_with_override() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,71 @@
+//! Container mode: confexts scoped to a container.
+//!
+//! Inside a systemd-nspawn (or other systemd-style) container the host
+//! publishes `/run/host`, with the container manager's name in
+//! `/run/host/container-manager`. Selected with the global `--container`
+//! flag, or automatically with `[avocado.container] auto = true` when
+//! `/run/host` is detected, container mode:
+//!
+//! - adds the extensions the host provides in `/run/host/extensions` to
+//!   the container's own, at the lowest priority;
+//! - merges configuration extensions only: `/usr` and the kernel belong to
+//!   the container image and the host, so systemd-sysext, depmod, ldconfig
+//!   and module loading are skipped;
+//! - runs in-process, like `--user` mode, since the container has no
+//!   avocadoctl daemon of its own.
+//!
+//! The container's systemd is still reloaded and AVOCADO_ON_MERGE service
+//! commands still run, inside the container.
+
+use std::path::PathBuf;
+
+/// Environment variable entering container mode, like `--container`.
+pub const CONTAINER_MODE_ENV: &str = "AVOCADO_CONTAINER";
+
+/// Environment variable overriding the `/run/host` directory.
+pub const HOST_DIR_ENV: &str = "AVOCADO_HOST_DIR";
+
+/// Whether container mode is active.
+pub fn is_container() -> bool {
+    crate::settings::current().container
+}
+
+/// The directory the host publishes into the container.
+pub fn host_dir() -> PathBuf {
+    PathBuf::from(std::env::var(HOST_DIR_ENV).unwrap_or_else(|_| "/run/host".to_string()))
+}
+
+/// Directory of the extensions the host provides.
+pub fn host_extensions_dir() -> PathBuf {
+    host_dir().join("extensions")
+}
+
+/// The container manager, when running in a container with `/run/host`.
+pub fn detect() -> Option<String> {
+    let manager = std::fs::read_to_string(host_dir().join("container-manager")).ok()?;
+    let manager = manager.trim();
+    Some(if manager.is_empty() {
+        "unknown".to_string()
+    } else {
+        manager.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_env::ENV_VAR_MUTEX;
+
+    #[test]
+    fn test_detect_reads_container_manager() {
+        let _lock = ENV_VAR_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let tmp = tempfile::TempDir::new().unwrap();
+        std::env::set_var(HOST_DIR_ENV, tmp.path());
+        assert_eq!(detect(), None);
+
+        std::fs::write(tmp.path().join("container-manager"), "systemd-nspawn\n").unwrap();
+        assert_eq!(detect().as_deref(), Some("systemd-nspawn"));
+        assert_eq!(host_extensions_dir(), tmp.path().join("extensions"));
+        std::env::remove_var(HOST_DIR_ENV);
+    }
+}
@@ -0,0 +1,108 @@
+//! One `systemctl daemon-reload` per command.
+//!
+//! A daemon-reload takes seconds on a busy system. Steps that change what
+//! systemd has to re-read (HITL service drop-ins written or removed,
+//! extensions merged or refreshed with `--no-reload`) used to reload on
+//! their own, so `hitl mount` reloaded for its drop-ins and again for the
+//! refresh that followed. Those steps now only [`request`] a reload. It is
+//! performed by [`flush`] where systemd has to see the changes: before
+//! on-merge commands and HITL service restarts, and when the command
+//! finishes. A flush without a pending request does nothing.
+//!
+//! The request is kept on the operation's [`OutputManager`], not in the
+//! process: the varlink daemon runs operations concurrently, and one must
+//! not perform or swallow the reload another requested.
+
+use crate::error::SystemdError;
+use crate::output::OutputManager;
+use crate::timeouts::{Stream, TimeoutKind};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether an operation has a reload pending, held by its [`OutputManager`].
+#[derive(Default)]
+pub struct Pending(AtomicBool);
+
+impl Pending {
+    fn set(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Clear the pending request, returning whether there was one.
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// Note that systemd has to reload its units before the operation of
+/// `output` uses them next.
+pub fn request(output: &OutputManager) {
+    output.pending_reload().set();
+}
+
+/// Reload systemd if the operation of `output` requested a reload since
+/// its last one. Returns whether it reloaded. A failed reload is not retried
+/// by a later flush.
+pub fn flush(output: &OutputManager) -> Result<bool, SystemdError> {
+    if !output.pending_reload().take() {
+        return Ok(false);
+    }
+    // Host-wide side effects don't apply to a user-mode prefix
+    if crate::user_mode::is_user() {
+        return Ok(false);
+    }
+    if let Some(result) = crate::backend::simulate("systemctl", &["daemon-reload"]) {
+        result?;
+        output.log_info("Reloaded systemd daemon");
+        return Ok(true);
+    }
+    let command_name = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        "mock-systemctl"
+    } else {
+        "systemctl"
+    };
+
+    output.step("Systemd", "Reloading systemd daemon");
+    let result = crate::timeouts::output_streaming(
+        Command::new(command_name).arg("daemon-reload"),
+        TimeoutKind::SystemdCmd,
+        &|stream, line| {
+            if stream == Stream::Stderr {
+                output.command_output("systemctl", line);
+            }
+        },
+    )
+    .map_err(|e| e.into_systemd_error("systemctl daemon-reload"))?;
+    if !result.status.success() {
+        return Err(SystemdError::CommandExitedWithError {
+            command: "systemctl daemon-reload".to_string(),
+            exit_code: result.status.code(),
+            stderr: String::from_utf8_lossy(&result.stderr).to_string(),
+        });
+    }
+    output.log_info("Reloaded systemd daemon");
+    Ok(true)
+}
+
+/// [`flush`], reporting a failure as a warning: the work of the command is
+/// done, and systemd picks the changes up on its next reload.
+pub fn flush_or_warn(output: &OutputManager) {
+    if let Err(e) = flush(output) {
+        output.warning(&format!("daemon-reload failed: {e}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_are_per_operation() {
+        let first = OutputManager::new(false, false);
+        let second = OutputManager::new(false, false);
+        request(&first);
+        assert!(!second.pending_reload().take());
+        assert!(first.pending_reload().take());
+        assert!(!first.pending_reload().take());
+    }
+}
@@ -0,0 +1,426 @@
+//! GPT discovery of discoverable disk images (DDIs).
+//!
+//! A `.raw` extension is either a bare file system or a GPT disk image
+//! whose partitions are identified by the type UUIDs of the Discoverable
+//! Partitions Specification. An image with both a `usr` and a `root`
+//! partition is a combined image: one artifact carrying a sysext (the
+//! `usr` partition) and a confext (the `root` partition, holding `/etc`).
+//! systemd-dissect mounts the `usr` partition below the `root` one, so a
+//! single mount exposes both hierarchies; [`discover`] reads the partition
+//! table so each half can be registered and reported on its own.
+//!
+//! Only the partition table is read, never the file systems. Partition
+//! types of the x86-64 and arm64 architectures are recognized; images for
+//! other architectures are treated like bare file systems.
+
+use crate::extension_release::Hierarchy;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use thiserror::Error;
+
+/// Largest partition table read; DDIs have a handful of partitions.
+const MAX_ENTRIES: u32 = 256;
+
+/// Partition entry sizes accepted; the specification requires a power of
+/// two of at least 128.
+const ENTRY_SIZES: std::ops::RangeInclusive<u32> = 128..=4096;
+
+/// Why the partition table of an image could not be read.
+#[derive(Debug, Error)]
+pub enum DdiError {
+    #[error("Failed to read the partition table: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid GPT header: {0}")]
+    InvalidHeader(String),
+    #[error("Invalid partition entry: {0}")]
+    InvalidEntry(String),
+}
+
+/// Role of a data partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Designator {
+    Root,
+    Usr,
+}
+
+impl Designator {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Designator::Root => "root",
+            Designator::Usr => "usr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Data,
+    Verity,
+    VeritySig,
+}
+
+/// Partition type UUIDs: designator, kind and architecture.
+#[rustfmt::skip]
+const PARTITION_TYPES: &[(&str, Designator, Kind, &str)] = &[
+    ("4f68bce3-e8cd-4db1-96e7-fbcaf984b709", Designator::Root, Kind::Data, "x86-64"),
+    ("b921b045-1df0-41c3-af44-4c6f280d3fae", Designator::Root, Kind::Data, "arm64"),
+    ("8484680c-9521-48c6-9c11-b0720656f69e", Designator::Usr, Kind::Data, "x86-64"),
+    ("b0e01050-ee5f-4390-949a-9101b17104e9", Designator::Usr, Kind::Data, "arm64"),
+    ("2c7357ed-ebd2-46d9-aec1-23d437ec2bf5", Designator::Root, Kind::Verity, "x86-64"),
+    ("df3300ce-d69f-4c92-978c-9bfb0f38d820", Designator::Root, Kind::Verity, "arm64"),
+    ("77ff5f63-e7b6-4633-acf4-1565b864c0e6", Designator::Usr, Kind::Verity, "x86-64"),
+    ("6e11a4e7-fbca-4ded-b9e9-e1a512bb664e", Designator::Usr, Kind::Verity, "arm64"),
+    ("41092b05-9fc8-4523-994f-2def0408b176", Designator::Root, Kind::VeritySig, "x86-64"),
+    ("6db69de6-29f4-4758-a7a5-962190f00ce3", Designator::Root, Kind::VeritySig, "arm64"),
+    ("e7bb33fb-06cf-4e81-8273-e543b413e2e2", Designator::Usr, Kind::VeritySig, "x86-64"),
+    ("c23ce4ff-44bd-4b00-b2d4-b41b3419e02a", Designator::Usr, Kind::VeritySig, "arm64"),
+];
+
+/// A data partition of a DDI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    pub designator: Designator,
+    pub architecture: &'static str,
+    /// Partition UUID
+    pub uuid: String,
+    /// GPT partition label
+    pub label: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Whether a dm-verity hash partition accompanies it
+    pub verity: bool,
+}
+
+impl Partition {
+    /// The hierarchy this partition provides in a combined image.
+    pub fn hierarchy(&self) -> Hierarchy {
+        match self.designator {
+            Designator::Usr => Hierarchy::Sysext,
+            Designator::Root => Hierarchy::Confext,
+        }
+    }
+}
+
+/// A data partition as `ext status` reports it: `hierarchy` is set for
+/// combined sysext + confext images, `label` when the partition has one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionStatus {
+    pub designator: String,
+    pub hierarchy: Option<String>,
+    pub architecture: String,
+    pub uuid: String,
+    pub label: Option<String>,
+    /// Size in bytes
+    pub size: u64,
+    pub verity: bool,
+}
+
+/// Whether `partitions` make a combined sysext + confext image.
+pub fn is_combined(partitions: &[Partition]) -> bool {
+    let has = |d| partitions.iter().any(|p| p.designator == d);
+    has(Designator::Usr) && has(Designator::Root)
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// GPT stores the first three UUID fields little-endian.
+fn format_guid(bytes: &[u8]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{}",
+        le_u32(bytes, 0),
+        u16::from_le_bytes([bytes[4], bytes[5]]),
+        u16::from_le_bytes([bytes[6], bytes[7]]),
+        bytes[8],
+        bytes[9],
+        bytes[10..16]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+    )
+}
+
+/// The data partitions of the GPT image at `path`, in table order. Empty
+/// for images without a GPT. A header whose partition table does not fit
+/// in the image is an error, nothing is allocated for it.
+pub fn discover(path: &Path) -> Result<Vec<Partition>, DdiError> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    // The header is in the second sector; try both common sector sizes
+    for sector in [512u64, 4096] {
+        if len < sector * 2 {
+            continue;
+        }
+        let mut header = [0u8; 92];
+        file.seek(SeekFrom::Start(sector))?;
+        file.read_exact(&mut header)?;
+        if &header[..8] != b"EFI PART" {
+            continue;
+        }
+        let entries_lba = le_u64(&header, 72);
+        let count = le_u32(&header, 80).min(MAX_ENTRIES);
+        let entry_size = le_u32(&header, 84);
+        if !ENTRY_SIZES.contains(&entry_size) || !entry_size.is_power_of_two() {
+            return Err(DdiError::InvalidHeader(format!(
+                "partition entry size {entry_size}"
+            )));
+        }
+        let table_size = (entry_size as u64)
+            .checked_mul(count as u64)
+            .ok_or_else(|| DdiError::InvalidHeader("partition table size".to_string()))?;
+        let table_start = entries_lba
+            .checked_mul(sector)
+            .filter(|start| start.checked_add(table_size).is_some_and(|end| end <= len))
+            .ok_or_else(|| {
+                DdiError::InvalidHeader(format!(
+                    "partition table at LBA {entries_lba} is beyond the end of the image"
+                ))
+            })?;
+
+        let entry_size = entry_size as usize;
+        let mut table = vec![0u8; table_size as usize];
+        file.seek(SeekFrom::Start(table_start))?;
+        file.read_exact(&mut table)?;
+
+        let mut found = Vec::new();
+        for entry in table.chunks_exact(entry_size) {
+            let type_guid = format_guid(&entry[..16]);
+            let Some(&(_, designator, kind, architecture)) =
+                PARTITION_TYPES.iter().find(|(guid, ..)| *guid == type_guid)
+            else {
+                continue;
+            };
+            let first = le_u64(entry, 32);
+            let last = le_u64(entry, 40);
+            let size = last
+                .checked_sub(first)
+                .and_then(|sectors| sectors.checked_add(1))
+                .and_then(|sectors| sectors.checked_mul(sector))
+                .filter(|&size| {
+                    first
+                        .checked_mul(sector)
+                        .and_then(|offset| offset.checked_add(size))
+                        .is_some_and(|end| end <= len)
+                })
+                .ok_or_else(|| {
+                    DdiError::InvalidEntry(format!(
+                        "partition spanning LBA {first} to {last} does not fit in the image"
+                    ))
+                })?;
+            let label: Vec<u16> = entry[56..128]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|&c| c != 0)
+                .collect();
+            found.push((
+                kind,
+                Partition {
+                    designator,
+                    architecture,
+                    uuid: format_guid(&entry[16..32]),
+                    label: String::from_utf16_lossy(&label),
+                    size,
+                    verity: false,
+                },
+            ));
+        }
+
+        let verity: Vec<Designator> = found
+            .iter()
+            .filter(|(kind, _)| *kind == Kind::Verity)
+            .map(|(_, p)| p.designator)
+            .collect();
+        return Ok(found
+            .into_iter()
+            .filter(|(kind, _)| *kind == Kind::Data)
+            .map(|(_, mut p)| {
+                p.verity = verity.contains(&p.designator);
+                p
+            })
+            .collect());
+    }
+    Ok(Vec::new())
+}
+
+/// One `ext status` line for a partition, such as
+/// `usr partition: sysext (x86-64, 64.0M, verity)`.
+pub fn describe(partition: &PartitionStatus) -> String {
+    let mut details = vec![partition.architecture.clone(), format_size(partition.size)];
+    if partition.verity {
+        details.push("verity".to_string());
+    }
+    format!(
+        "{} partition: {} ({})",
+        partition.designator,
+        partition.hierarchy.as_deref().unwrap_or("?"),
+        details.join(", ")
+    )
+}
+
+/// Human-readable size, such as `64.0M`.
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1}{}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// GPT-encoded bytes of a textual UUID.
+    fn guid_bytes(guid: &str) -> [u8; 16] {
+        let hex: String = guid.chars().filter(|c| *c != '-').collect();
+        let mut raw = [0u8; 16];
+        for (i, byte) in raw.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        raw[..4].reverse();
+        raw[4..6].reverse();
+        raw[6..8].reverse();
+        raw
+    }
+
+    /// A GPT header sector for 512-byte sectors.
+    fn gpt_header(entries_lba: u64, count: u32, entry_size: u32) -> [u8; 512] {
+        let mut header = [0u8; 512];
+        header[..8].copy_from_slice(b"EFI PART");
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&count.to_le_bytes());
+        header[84..88].copy_from_slice(&entry_size.to_le_bytes());
+        header
+    }
+
+    /// Write a GPT image with 512-byte sectors and the given partitions
+    /// (type UUID, label, size in sectors).
+    fn write_gpt_image(path: &Path, partitions: &[(&str, &str, u64)]) {
+        let header = gpt_header(2, partitions.len() as u32, 128);
+
+        let mut table = vec![0u8; 128 * partitions.len()];
+        let mut next_lba = 34u64;
+        for (i, (type_guid, label, sectors)) in partitions.iter().enumerate() {
+            let entry = &mut table[i * 128..(i + 1) * 128];
+            entry[..16].copy_from_slice(&guid_bytes(type_guid));
+            entry[16..32].copy_from_slice(&guid_bytes(&format!(
+                "00000000-0000-4000-8000-{:012x}",
+                i + 1
+            )));
+            entry[32..40].copy_from_slice(&next_lba.to_le_bytes());
+            entry[40..48].copy_from_slice(&(next_lba + sectors - 1).to_le_bytes());
+            for (j, unit) in label.encode_utf16().enumerate() {
+                entry[56 + j * 2..58 + j * 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            next_lba += sectors;
+        }
+
+        let mut file = File::create(path).unwrap();
+        file.write_all(&[0u8; 512]).unwrap();
+        file.write_all(&header).unwrap();
+        file.write_all(&table).unwrap();
+        file.set_len(next_lba * 512).unwrap();
+    }
+
+    #[test]
+    fn test_discover_combined_image() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let image = tmp.path().join("app-1.0.raw");
+        write_gpt_image(
+            &image,
+            &[
+                ("8484680c-9521-48c6-9c11-b0720656f69e", "app-usr", 2048),
+                ("77ff5f63-e7b6-4633-acf4-1565b864c0e6", "app-usr-verity", 64),
+                ("4f68bce3-e8cd-4db1-96e7-fbcaf984b709", "app-root", 128),
+                ("0fc63daf-8483-4772-8e79-3d69d8477de4", "data", 8),
+            ],
+        );
+
+        let partitions = discover(&image).unwrap();
+        assert_eq!(partitions.len(), 2);
+        assert!(is_combined(&partitions));
+        assert_eq!(partitions[0].designator, Designator::Usr);
+        assert_eq!(partitions[0].hierarchy(), Hierarchy::Sysext);
+        assert_eq!(partitions[0].architecture, "x86-64");
+        assert_eq!(partitions[0].label, "app-usr");
+        assert_eq!(partitions[0].size, 2048 * 512);
+        assert!(partitions[0].verity);
+        assert_eq!(partitions[0].uuid, "00000000-0000-4000-8000-000000000001");
+        assert_eq!(partitions[1].hierarchy(), Hierarchy::Confext);
+        assert!(!partitions[1].verity);
+    }
+
+    #[test]
+    fn test_discover_bare_file_system() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let image = tmp.path().join("app.raw");
+        std::fs::write(&image, vec![0u8; 8192]).unwrap();
+        assert!(discover(&image).unwrap().is_empty());
+        assert_eq!(format_size(64 * 1024 * 1024), "64.0M");
+        assert_eq!(format_size(1536), "1.5K");
+    }
+
+    #[test]
+    fn test_discover_rejects_malformed_header() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let image = tmp.path().join("bad.raw");
+        for (entries_lba, count, entry_size) in [
+            (2, 4, 0xFFFF_FFF0),
+            (2, 4, 64),
+            (2, 4, 200),
+            (u64::MAX, 4, 128),
+            (1 << 40, 4, 128),
+            (2, 256, 4096),
+        ] {
+            let mut bytes = vec![0u8; 512];
+            bytes.extend_from_slice(&gpt_header(entries_lba, count, entry_size));
+            bytes.extend_from_slice(&[0u8; 4096]);
+            std::fs::write(&image, bytes).unwrap();
+            assert!(
+                matches!(discover(&image), Err(DdiError::InvalidHeader(_))),
+                "{entries_lba} {count} {entry_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_discover_rejects_malformed_entry() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let image = tmp.path().join("bad.raw");
+        // The first entry starts after the MBR and header sectors. The last
+        // two are small enough for the image but start past its end.
+        for (first, last) in [
+            (100, 99),
+            (0, u64::MAX),
+            (34, 1 << 60),
+            (34, 1 << 20),
+            (1000, 1000),
+            (u64::MAX - 1, u64::MAX - 1),
+        ] {
+            write_gpt_image(
+                &image,
+                &[("8484680c-9521-48c6-9c11-b0720656f69e", "app-usr", 1)],
+            );
+            let mut bytes = std::fs::read(&image).unwrap();
+            bytes[1024 + 32..1024 + 40].copy_from_slice(&u64::to_le_bytes(first));
+            bytes[1024 + 40..1024 + 48].copy_from_slice(&u64::to_le_bytes(last));
+            std::fs::write(&image, bytes).unwrap();
+            assert!(
+                matches!(discover(&image), Err(DdiError::InvalidEntry(_))),
+                "{first} {last}"
+            );
+        }
+    }
+}
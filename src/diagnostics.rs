@@ -0,0 +1,813 @@
+//! Error codes and remediation hints.
+//!
+//! Every error avocadoctl reports maps to a stable code from [`CATALOG`]
+//! (`E0001`, `E0002`, ...) so scripts and fleet tooling can match on the
+//! code instead of the message text. Where the cause is recognisable (a
+//! missing tool, a permission problem, a rejected image) a hint says what
+//! to do about it. [`OutputManager::error_with`] prints both and includes
+//! them in the JSON error object.
+//!
+//! Codes are never renumbered; new ones are appended.
+//!
+//! [`OutputManager::error_with`]: crate::output::OutputManager::error_with
+
+use crate::error::{HitlError, IoError, SystemdError};
+use crate::service::error::AvocadoError;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// One entry of the error catalogue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub summary: &'static str,
+}
+
+pub const TOOL_NOT_FOUND: ErrorCode = ErrorCode {
+    code: "E0001",
+    summary: "a required system tool is not installed",
+};
+pub const PERMISSION_DENIED: ErrorCode = ErrorCode {
+    code: "E0002",
+    summary: "insufficient privileges",
+};
+pub const COMMAND_FAILED: ErrorCode = ErrorCode {
+    code: "E0003",
+    summary: "a system command could not be run",
+};
+pub const COMMAND_EXITED: ErrorCode = ErrorCode {
+    code: "E0004",
+    summary: "a system command reported an error",
+};
+pub const CONFIGURATION: ErrorCode = ErrorCode {
+    code: "E0005",
+    summary: "invalid configuration or request",
+};
+pub const EXTENSION_NOT_FOUND: ErrorCode = ErrorCode {
+    code: "E0006",
+    summary: "extension not found",
+};
+pub const MERGE_FAILED: ErrorCode = ErrorCode {
+    code: "E0007",
+    summary: "merging extensions failed",
+};
+pub const UNMERGE_FAILED: ErrorCode = ErrorCode {
+    code: "E0008",
+    summary: "unmerging extensions failed",
+};
+pub const HITL_MOUNT_FAILED: ErrorCode = ErrorCode {
+    code: "E0009",
+    summary: "mounting a HITL extension failed",
+};
+pub const HITL_UNMOUNT_FAILED: ErrorCode = ErrorCode {
+    code: "E0010",
+    summary: "unmounting a HITL extension failed",
+};
+pub const DAEMON_RELOAD_FAILED: ErrorCode = ErrorCode {
+    code: "E0011",
+    summary: "systemctl daemon-reload failed",
+};
+pub const RUNTIME_NOT_FOUND: ErrorCode = ErrorCode {
+    code: "E0012",
+    summary: "runtime not found",
+};
+pub const AMBIGUOUS_RUNTIME: ErrorCode = ErrorCode {
+    code: "E0013",
+    summary: "runtime ID prefix matches several runtimes",
+};
+pub const ACTIVE_RUNTIME: ErrorCode = ErrorCode {
+    code: "E0014",
+    summary: "operation not allowed on the active runtime",
+};
+pub const STAGING_FAILED: ErrorCode = ErrorCode {
+    code: "E0015",
+    summary: "staging a runtime failed",
+};
+pub const UPDATE_FAILED: ErrorCode = ErrorCode {
+    code: "E0016",
+    summary: "update failed",
+};
+pub const NO_ROOT_AUTHORITY: ErrorCode = ErrorCode {
+    code: "E0017",
+    summary: "no root authority configured",
+};
+pub const METADATA_KEY_NOT_FOUND: ErrorCode = ErrorCode {
+    code: "E0018",
+    summary: "runtime metadata key not found",
+};
+pub const PARSE_FAILED: ErrorCode = ErrorCode {
+    code: "E0019",
+    summary: "data could not be parsed",
+};
+pub const IO: ErrorCode = ErrorCode {
+    code: "E0020",
+    summary: "file system error",
+};
+pub const DAEMON_UNAVAILABLE: ErrorCode = ErrorCode {
+    code: "E0021",
+    summary: "the avocadoctl daemon is not reachable",
+};
+pub const RPC_FAILED: ErrorCode = ErrorCode {
+    code: "E0022",
+    summary: "the avocadoctl daemon returned an error",
+};
+pub const PLAN_DRIFTED: ErrorCode = ErrorCode {
+    code: "E0023",
+    summary: "system state changed since the plan was made",
+};
+pub const COMMAND_TIMED_OUT: ErrorCode = ErrorCode {
+    code: "E0024",
+    summary: "a system command did not finish in time",
+};
+pub const HITL_SYNC_IN_PROGRESS: ErrorCode = ErrorCode {
+    code: "E0025",
+    summary: "a HITL extension is still being synced",
+};
+pub const STORAGE_FULL: ErrorCode = ErrorCode {
+    code: "E0026",
+    summary: "not enough space for the update",
+};
+pub const TRUST_STORE: ErrorCode = ErrorCode {
+    code: "E0027",
+    summary: "the extension signing trust store could not be used or changed",
+};
+pub const SYSTEMD_NOT_RUNNING: ErrorCode = ErrorCode {
+    code: "E0028",
+    summary: "systemd is not running",
+};
+pub const REMOTE_UNREACHABLE: ErrorCode = ErrorCode {
+    code: "E0029",
+    summary: "the remote device could not be reached over SSH",
+};
+pub const INVALID_IMAGE: ErrorCode = ErrorCode {
+    code: "E0030",
+    summary: "an extension image is malformed",
+};
+pub const HITL_SERVER_INCOMPATIBLE: ErrorCode = ErrorCode {
+    code: "E0031",
+    summary: "the HITL server did not complete the handshake",
+};
+
+/// All error codes, in code order.
+#[cfg_attr(not(test), allow(dead_code))]
+pub const CATALOG: &[ErrorCode] = &[
+    TOOL_NOT_FOUND,
+    PERMISSION_DENIED,
+    COMMAND_FAILED,
+    COMMAND_EXITED,
+    CONFIGURATION,
+    EXTENSION_NOT_FOUND,
+    MERGE_FAILED,
+    UNMERGE_FAILED,
+    HITL_MOUNT_FAILED,
+    HITL_UNMOUNT_FAILED,
+    DAEMON_RELOAD_FAILED,
+    RUNTIME_NOT_FOUND,
+    AMBIGUOUS_RUNTIME,
+    ACTIVE_RUNTIME,
+    STAGING_FAILED,
+    UPDATE_FAILED,
+    NO_ROOT_AUTHORITY,
+    METADATA_KEY_NOT_FOUND,
+    PARSE_FAILED,
+    IO,
+    DAEMON_UNAVAILABLE,
+    RPC_FAILED,
+    PLAN_DRIFTED,
+    COMMAND_TIMED_OUT,
+    HITL_SYNC_IN_PROGRESS,
+    STORAGE_FULL,
+    TRUST_STORE,
+    SYSTEMD_NOT_RUNNING,
+    REMOTE_UNREACHABLE,
+    INVALID_IMAGE,
+    HITL_SERVER_INCOMPATIBLE,
+];
+
+/// Code, hint and context attached to a reported error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub hint: Option<String>,
+    /// What the error happened to (`path`, `extension`, `command`,
+    /// `mount_point`), innermost first
+    pub context: Vec<(&'static str, String)>,
+}
+
+impl Diagnostic {
+    pub fn new(code: ErrorCode, hint: Option<String>) -> Self {
+        Self {
+            code,
+            hint,
+            context: Vec::new(),
+        }
+    }
+
+    /// Add a context field; a key already set keeps its value.
+    pub fn with(mut self, key: &'static str, value: impl ToString) -> Self {
+        if !self.context.iter().any(|(k, _)| *k == key) {
+            self.context.push((key, value.to_string()));
+        }
+        self
+    }
+}
+
+/// Errors that can explain themselves.
+pub trait Diagnose {
+    fn diagnose(&self) -> Diagnostic;
+}
+
+/// Program name of a command line as recorded in error variants.
+fn program(command: &str) -> &str {
+    let first = command.split_whitespace().next().unwrap_or(command);
+    Path::new(first)
+        .file_name()
+        .and_then(|f| f.to_str())
+        .unwrap_or(first)
+}
+
+/// What to install when `command` is missing.
+pub fn tool_hint(command: &str) -> String {
+    let program = program(command);
+    let package = match program {
+        "systemd-sysext" | "systemd-confext" => "systemd >= 251",
+        "systemd-dissect" => "systemd >= 254 (for systemd-dissect --mount)",
+        "systemctl" | "systemd-repart" => "systemd",
+        "depmod" | "modprobe" => "kmod",
+        "mount" | "umount" | "losetup" | "unshare" => "util-linux",
+        "mount.nfs" => "nfs-utils",
+        "veritysetup" => "cryptsetup",
+        "ssh" | "scp" => "openssh-client",
+        _ => return format!("{program} not found: install it or add its directory to PATH"),
+    };
+    format!("{program} not found: install {package}")
+}
+
+fn spawn_failure(command: &str, source: &std::io::Error) -> Diagnostic {
+    match source.kind() {
+        ErrorKind::NotFound => Diagnostic::new(TOOL_NOT_FOUND, Some(tool_hint(command))),
+        ErrorKind::PermissionDenied => Diagnostic::new(
+            PERMISSION_DENIED,
+            Some("run avocadoctl as root, or pass --user to work on a user-owned root".into()),
+        ),
+        _ => Diagnostic::new(COMMAND_FAILED, None),
+    }
+}
+
+fn exit_failure(command: &str, stderr: &str) -> Diagnostic {
+    let hint = match program(command) {
+        program @ ("systemd-sysext" | "systemd-confext") => Some(format!(
+            "run '{program} status' and check 'journalctl -b' for rejected images; an extension's ID and VERSION_ID must match the host os-release"
+        )),
+        "systemd-dissect" if stderr.contains("policy") => Some(
+            "the image does not satisfy the image policy; set [avocado.ext] verity = \"warn\" to mount it anyway".into(),
+        ),
+        "mount" | "systemd-dissect" | "losetup" => {
+            Some("check 'dmesg' for the kernel's reason".into())
+        }
+        _ if stderr.contains("Permission denied") || stderr.contains("Operation not permitted") => {
+            Some("run avocadoctl as root".into())
+        }
+        _ => None,
+    };
+    Diagnostic::new(COMMAND_EXITED, hint)
+}
+
+/// Configuration errors also cover bad requests; only point at the
+/// configuration file when the message is about it.
+fn configuration_failure(message: &str) -> Diagnostic {
+    let hint = message.to_lowercase().contains("config").then(|| {
+        format!(
+            "check {} (or the file passed with --config)",
+            crate::config::DEFAULT_CONFIG_PATH
+        )
+    });
+    Diagnostic::new(CONFIGURATION, hint)
+}
+
+fn timeout_failure(setting: &str) -> Diagnostic {
+    Diagnostic::new(
+        COMMAND_TIMED_OUT,
+        Some(format!(
+            "find out why it hangs, or raise {setting} in [avocado.timeouts] (0 disables the limit)"
+        )),
+    )
+}
+
+fn hitl_sync_failure() -> Diagnostic {
+    Diagnostic::new(
+        HITL_SYNC_IN_PROGRESS,
+        Some(
+            "run 'avocadoctl hitl resume <name>' once the sync is done, or raise sync_wait_ms in [avocado.hitl]"
+                .into(),
+        ),
+    )
+}
+
+fn systemd_not_running() -> Diagnostic {
+    Diagnostic::new(
+        SYSTEMD_NOT_RUNNING,
+        Some(
+            "in a chroot or minimal container, run 'avocadoctl merge --mount-only' to prepare the extensions without merging them, or merge from the host with 'avocadoctl ext merge --target <path>'"
+                .into(),
+        ),
+    )
+}
+
+fn hitl_mount_failure() -> Diagnostic {
+    Diagnostic::new(
+        HITL_MOUNT_FAILED,
+        Some(
+            "check that the NFS server is reachable and exports the extension (showmount -e <server>)"
+                .into(),
+        ),
+    )
+}
+
+fn hitl_unmount_failure(mount_point: &str) -> Diagnostic {
+    Diagnostic::new(
+        HITL_UNMOUNT_FAILED,
+        Some(format!(
+            "stop processes using the mount (fuser -vm {mount_point}) and retry"
+        )),
+    )
+}
+
+fn invalid_image() -> Diagnostic {
+    Diagnostic::new(
+        INVALID_IMAGE,
+        Some("rebuild or re-download the image; 'avocadoctl ext lint <path>' checks it".into()),
+    )
+}
+
+fn untrusted_image() -> Diagnostic {
+    Diagnostic::new(
+        TRUST_STORE,
+        Some(
+            "sign the image with a key 'avocadoctl trust list' shows; directories need allow_unsigned_directories = true in [avocado.ext]"
+                .into(),
+        ),
+    )
+}
+
+impl Diagnose for SystemdError {
+    fn diagnose(&self) -> Diagnostic {
+        match self {
+            SystemdError::CommandFailed { command, source } => {
+                spawn_failure(command, source).with("command", command)
+            }
+            SystemdError::CommandExitedWithError {
+                command, stderr, ..
+            } => exit_failure(command, stderr).with("command", command),
+            SystemdError::ConfigurationError { message } => configuration_failure(message),
+            SystemdError::CommandTimedOut {
+                command, setting, ..
+            } => timeout_failure(setting).with("command", command),
+            SystemdError::HitlSyncInProgress { .. } => hitl_sync_failure(),
+            SystemdError::SystemdNotRunning { .. } => systemd_not_running(),
+            SystemdError::Io(e) => e.diagnose(),
+            SystemdError::InvalidImage { path, .. } => invalid_image().with("path", path.display()),
+            SystemdError::Untrusted { path, .. } => untrusted_image().with("path", path.display()),
+            SystemdError::Extension { extension, source } => {
+                source.diagnose().with("extension", extension)
+            }
+        }
+    }
+}
+
+impl Diagnose for IoError {
+    fn diagnose(&self) -> Diagnostic {
+        let diagnostic = match self.source.kind() {
+            ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => Diagnostic::new(
+                PERMISSION_DENIED,
+                Some("run avocadoctl as root, or pass --user to work on a user-owned root".into()),
+            ),
+            ErrorKind::StorageFull => Diagnostic::new(STORAGE_FULL, None),
+            _ => Diagnostic::new(IO, None),
+        };
+        diagnostic.with("path", self.path.display())
+    }
+}
+
+impl Diagnose for HitlError {
+    fn diagnose(&self) -> Diagnostic {
+        match self {
+            HitlError::Command { command, source } => {
+                spawn_failure(command, source).with("command", command)
+            }
+            HitlError::Mount {
+                extension,
+                mount_point,
+                ..
+            } => hitl_mount_failure()
+                .with("extension", extension)
+                .with("mount_point", mount_point),
+            HitlError::MountTimedOut {
+                extension,
+                mount_point,
+                ..
+            } => timeout_failure("nfs_mount")
+                .with("extension", extension)
+                .with("mount_point", mount_point),
+            HitlError::Io(e) => e.diagnose(),
+            HitlError::Unmount { mount_point, .. } => {
+                hitl_unmount_failure(mount_point).with("mount_point", mount_point)
+            }
+            HitlError::DaemonReload { .. } => Diagnostic::new(
+                DAEMON_RELOAD_FAILED,
+                Some("check 'journalctl -b' for unit file errors in the service drop-ins".into()),
+            ),
+            HitlError::Overrides { extension, .. } => Diagnostic::new(
+                CONFIGURATION,
+                Some("fix or remove hitl.toml at the root of the extension's NFS share".into()),
+            )
+            .with("extension", extension),
+            HitlError::Restart { units, .. } => Diagnostic::new(
+                COMMAND_EXITED,
+                Some(format!("check 'systemctl status {units}'")),
+            ),
+            HitlError::Handshake { server, .. } => Diagnostic::new(
+                HITL_SERVER_INCOMPATIBLE,
+                Some("check that the workstation serves HITL and that --control-port matches its control port".into()),
+            )
+            .with("server", server),
+            HitlError::ServerOutdated { server, .. } => Diagnostic::new(
+                HITL_SERVER_INCOMPATIBLE,
+                Some("update the avocado CLI on the workstation".into()),
+            )
+            .with("server", server),
+            HitlError::NotExported {
+                extension, server, ..
+            } => Diagnostic::new(
+                EXTENSION_NOT_FOUND,
+                Some("check the extension name and that the workstation has built it".into()),
+            )
+            .with("extension", extension)
+            .with("server", server),
+        }
+    }
+}
+
+impl Diagnose for AvocadoError {
+    fn diagnose(&self) -> Diagnostic {
+        match self {
+            AvocadoError::CommandFailed { command, source } => {
+                spawn_failure(command, source).with("command", command)
+            }
+            AvocadoError::CommandExitedWithError {
+                command, stderr, ..
+            } => exit_failure(command, stderr).with("command", command),
+            AvocadoError::ConfigurationError { message } => configuration_failure(message),
+            AvocadoError::CommandTimedOut {
+                command, setting, ..
+            } => timeout_failure(setting).with("command", command),
+            AvocadoError::HitlSyncInProgress { .. } => hitl_sync_failure(),
+            AvocadoError::SystemdNotRunning { .. } => systemd_not_running(),
+            AvocadoError::ExtensionNotFound { name } => Diagnostic::new(
+                EXTENSION_NOT_FOUND,
+                Some("run 'avocadoctl ext list' to see available extensions".into()),
+            )
+            .with("extension", name),
+            AvocadoError::RuntimeNotFound { .. } => Diagnostic::new(
+                RUNTIME_NOT_FOUND,
+                Some("run 'avocadoctl runtime list' to see installed runtimes".into()),
+            ),
+            AvocadoError::AmbiguousRuntimeId { .. } => Diagnostic::new(
+                AMBIGUOUS_RUNTIME,
+                Some("use a longer prefix of the runtime ID".into()),
+            ),
+            AvocadoError::RemoveActiveRuntime => Diagnostic::new(
+                ACTIVE_RUNTIME,
+                Some("activate a different runtime first".into()),
+            ),
+            AvocadoError::StagingFailed { .. } => Diagnostic::new(STAGING_FAILED, None),
+            AvocadoError::UpdateFailed { .. } => Diagnostic::new(UPDATE_FAILED, None),
+            AvocadoError::MergeFailed { .. } => Diagnostic::new(
+                MERGE_FAILED,
+                Some("run 'avocadoctl ext merge --dry-run' to inspect the planned links".into()),
+            ),
+            AvocadoError::UnmergeFailed { .. } => Diagnostic::new(UNMERGE_FAILED, None),
+            AvocadoError::MountFailed { extension, .. } => {
+                hitl_mount_failure().with("extension", extension)
+            }
+            AvocadoError::UnmountFailed { extension, .. } => hitl_unmount_failure(extension),
+            AvocadoError::QuiesceFailed { .. } => Diagnostic::new(IO, None),
+            AvocadoError::NoRootAuthority => Diagnostic::new(
+                NO_ROOT_AUTHORITY,
+                Some("provision /var/lib/avocado/metadata/root.json before updating".into()),
+            ),
+            AvocadoError::MetadataKeyNotFound { .. } => Diagnostic::new(
+                METADATA_KEY_NOT_FOUND,
+                Some("run 'avocadoctl runtime metadata list <id>' to see the keys".into()),
+            ),
+            AvocadoError::ParseFailed { .. } => Diagnostic::new(PARSE_FAILED, None),
+            AvocadoError::TrustFailed { reason } => trust_failure(reason),
+            AvocadoError::PlanDrifted { .. } => {
+                Diagnostic::new(PLAN_DRIFTED, Some(PLAN_DRIFTED_HINT.into()))
+            }
+            AvocadoError::Io(e) => e.diagnose(),
+        }
+    }
+}
+
+impl Diagnose for std::io::Error {
+    fn diagnose(&self) -> Diagnostic {
+        match self.kind() {
+            ErrorKind::PermissionDenied => spawn_failure("", self),
+            _ => Diagnostic::new(IO, None),
+        }
+    }
+}
+
+impl Diagnose for serde_json::Error {
+    fn diagnose(&self) -> Diagnostic {
+        Diagnostic::new(PARSE_FAILED, None)
+    }
+}
+
+impl Diagnose for crate::config::ConfigError {
+    fn diagnose(&self) -> Diagnostic {
+        configuration_failure("config")
+    }
+}
+
+impl Diagnose for crate::staging::StagingError {
+    fn diagnose(&self) -> Diagnostic {
+        match self {
+            crate::staging::StagingError::RemoveActiveRuntime => {
+                AvocadoError::RemoveActiveRuntime.diagnose()
+            }
+            crate::staging::StagingError::RuntimeNotFound(id) => {
+                AvocadoError::RuntimeNotFound { id: id.clone() }.diagnose()
+            }
+            _ => Diagnostic::new(STAGING_FAILED, None),
+        }
+    }
+}
+
+impl Diagnose for crate::update::UpdateError {
+    fn diagnose(&self) -> Diagnostic {
+        match self {
+            crate::update::UpdateError::NoTrustAnchor => AvocadoError::NoRootAuthority.diagnose(),
+            crate::update::UpdateError::FetchFailed(..) => Diagnostic::new(
+                UPDATE_FAILED,
+                Some("check network access to the update repository URL".into()),
+            ),
+            crate::update::UpdateError::Storage(e) => e.diagnose(),
+            crate::update::UpdateError::NetworkDisabled => Diagnostic::new(
+                UPDATE_FAILED,
+                Some("use an avocadoctl built with the default features; the initrd build has no update client".into()),
+            ),
+            _ => Diagnostic::new(UPDATE_FAILED, None),
+        }
+    }
+}
+
+fn storage_full() -> Diagnostic {
+    Diagnostic::new(
+        STORAGE_FULL,
+        Some(
+            "free space, run 'avocadoctl runtime gc', or set grow in [avocado.storage] to grow the data partition"
+                .into(),
+        ),
+    )
+}
+
+impl Diagnose for crate::storage::StorageError {
+    fn diagnose(&self) -> Diagnostic {
+        match self {
+            crate::storage::StorageError::Insufficient { .. } => storage_full(),
+            crate::storage::StorageError::GrowFailed { command, .. } => Diagnostic::new(
+                STORAGE_FULL,
+                Some(format!(
+                    "check that {command} can grow the data partition, or set grow = \"none\" in [avocado.storage]"
+                )),
+            ),
+        }
+    }
+}
+
+impl Diagnose for crate::os_update::OsUpdateError {
+    fn diagnose(&self) -> Diagnostic {
+        Diagnostic::new(UPDATE_FAILED, None)
+    }
+}
+
+impl Diagnose for crate::commands::remote::RemoteError {
+    fn diagnose(&self) -> Diagnostic {
+        use crate::commands::remote::RemoteError;
+        match self {
+            RemoteError::Command { command, source } => spawn_failure(command, source),
+            RemoteError::Unreachable { host } => Diagnostic::new(
+                REMOTE_UNREACHABLE,
+                Some(format!(
+                    "check that 'ssh {host} true' works without a password prompt"
+                )),
+            ),
+            RemoteError::CreateDir { dir, .. } => Diagnostic::new(
+                COMMAND_EXITED,
+                Some(format!(
+                    "check that {dir} can be created on the device, pass --sudo, or pass --upload-dir"
+                )),
+            ),
+            RemoteError::Upload { dir, .. } => Diagnostic::new(
+                COMMAND_EXITED,
+                Some(format!(
+                    "check that {dir} on the device is writable and has space, or pass --upload-dir"
+                )),
+            ),
+            RemoteError::Install { .. } => Diagnostic::new(
+                COMMAND_EXITED,
+                Some("check that the SSH user may run 'sudo -n install' on the device".to_string()),
+            ),
+            RemoteError::NameCollision { .. } => Diagnostic::new(
+                CONFIGURATION,
+                Some("rename one of the files; uploads share one directory".to_string()),
+            ),
+        }
+    }
+}
+
+/// A key id that matched no key, or several, is the mistake with a fix.
+fn trust_failure(reason: &str) -> Diagnostic {
+    let hint = (reason.contains("No key in the trust store matches")
+        || reason.contains("matches several keys"))
+    .then(|| "run 'avocadoctl trust list' to see the trusted keys".to_string());
+    Diagnostic::new(TRUST_STORE, hint)
+}
+
+const PLAN_DRIFTED_HINT: &str = "run 'avocadoctl plan' again and review the new plan";
+
+/// Diagnose an error reply from the daemon from its rendered text
+/// (`org.avocado.<Interface>.<Error>: <parameters>`), which is all the
+/// client sees of the server-side error.
+#[cfg(feature = "daemon")]
+pub fn diagnose_remote(text: &str) -> Diagnostic {
+    let name = text
+        .split_whitespace()
+        .find_map(|word| word.strip_prefix("org.avocado."))
+        .and_then(|qualified| qualified.trim_end_matches(':').rsplit('.').next())
+        .unwrap_or("");
+    let code = match name {
+        "ExtensionNotFound" => EXTENSION_NOT_FOUND,
+        "MergeFailed" => MERGE_FAILED,
+        "UnmergeFailed" => UNMERGE_FAILED,
+        "ConfigurationError" => CONFIGURATION,
+        "MountFailed" => HITL_MOUNT_FAILED,
+        "UnmountFailed" => HITL_UNMOUNT_FAILED,
+        "QuiesceFailed" => IO,
+        "RuntimeNotFound" => RUNTIME_NOT_FOUND,
+        "AmbiguousRuntimeId" => AMBIGUOUS_RUNTIME,
+        "RemoveActiveRuntime" => ACTIVE_RUNTIME,
+        "StagingFailed" => STAGING_FAILED,
+        "UpdateFailed" => UPDATE_FAILED,
+        "MetadataKeyNotFound" => METADATA_KEY_NOT_FOUND,
+        "NoRootAuthority" => NO_ROOT_AUTHORITY,
+        "ParseFailed" => PARSE_FAILED,
+        "PlanDrifted" => PLAN_DRIFTED,
+        "TrustFailed" => return trust_failure(text),
+        _ => RPC_FAILED,
+    };
+    // The daemon flattens command failures into text; recover the
+    // missing-tool case, which is the one with an actionable fix.
+    if text.contains("No such file or directory") {
+        if let Some(command) = text
+            .split('\'')
+            .nth(1)
+            .filter(|_| text.contains("Failed to run command '"))
+        {
+            return Diagnostic::new(TOOL_NOT_FOUND, Some(tool_hint(command)));
+        }
+    }
+    if code == RPC_FAILED && text.contains("' timed out after ") {
+        return Diagnostic::new(
+            COMMAND_TIMED_OUT,
+            Some("raise the limit in [avocado.timeouts] on the device".into()),
+        );
+    }
+    if code == RPC_FAILED && text.contains("HITL extensions still syncing") {
+        return hitl_sync_failure();
+    }
+    if code == RPC_FAILED && text.contains("systemd is not running") {
+        return systemd_not_running();
+    }
+    if text.contains("Not enough space in") {
+        return storage_full();
+    }
+    let hint = match code {
+        CONFIGURATION => configuration_failure(text).hint,
+        EXTENSION_NOT_FOUND => Some("run 'avocadoctl ext list' to see available extensions".into()),
+        RUNTIME_NOT_FOUND => Some("run 'avocadoctl runtime list' to see installed runtimes".into()),
+        RPC_FAILED => Some("check 'journalctl -u avocadoctl' on the device".into()),
+        PLAN_DRIFTED => Some(PLAN_DRIFTED_HINT.into()),
+        _ => None,
+    };
+    Diagnostic::new(code, hint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_codes_are_unique_and_sequential() {
+        for (i, entry) in CATALOG.iter().enumerate() {
+            assert_eq!(entry.code, format!("E{:04}", i + 1), "{}", entry.summary);
+        }
+    }
+
+    #[test]
+    fn test_missing_tool_hint() {
+        let err = SystemdError::CommandFailed {
+            command: "systemd-sysext".to_string(),
+            source: std::io::Error::from(ErrorKind::NotFound),
+        };
+        let diagnostic = err.diagnose();
+        assert_eq!(diagnostic.code, TOOL_NOT_FOUND);
+        assert_eq!(
+            diagnostic.hint.as_deref(),
+            Some("systemd-sysext not found: install systemd >= 251")
+        );
+        assert_eq!(
+            tool_hint("/usr/local/bin/frobnicate --x"),
+            "frobnicate not found: install it or add its directory to PATH"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_context() {
+        use crate::error::{ExtensionContext, IoContext};
+
+        let denied = Err::<(), _>(std::io::Error::from(ErrorKind::PermissionDenied))
+            .context("create directory", "/run/extensions")
+            .for_extension("app-1.0")
+            .unwrap_err();
+        let diagnostic = denied.diagnose();
+        assert_eq!(diagnostic.code, PERMISSION_DENIED);
+        assert_eq!(
+            diagnostic.context,
+            [
+                ("path", "/run/extensions".to_string()),
+                ("extension", "app-1.0".to_string())
+            ]
+        );
+
+        // A missing file is not a missing tool
+        let missing = SystemdError::from(crate::error::IoError {
+            action: "read directory".to_string(),
+            path: "/var/lib/avocado/images".into(),
+            source: std::io::Error::from(ErrorKind::NotFound),
+        });
+        assert_eq!(missing.diagnose().code, IO);
+
+        let exited = SystemdError::CommandExitedWithError {
+            command: "losetup -d".to_string(),
+            exit_code: Some(1),
+            stderr: String::new(),
+        };
+        assert_eq!(
+            exited.diagnose().context,
+            [("command", "losetup -d".to_string())]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "daemon")]
+    fn test_diagnose_remote() {
+        let merge = diagnose_remote(
+            "org.avocado.Extensions.MergeFailed: Some(MergeFailed_Args { reason: \"x\" })",
+        );
+        assert_eq!(merge.code, MERGE_FAILED);
+
+        let missing = diagnose_remote(
+            "org.avocado.Extensions.CommandFailed: Some(CommandFailed_Args { command: \"avocadoctl\", message: \"Failed to run command 'systemd-confext': No such file or directory (os error 2)\" })",
+        );
+        assert_eq!(missing.code, TOOL_NOT_FOUND);
+        assert_eq!(
+            missing.hint.as_deref(),
+            Some("systemd-confext not found: install systemd >= 251")
+        );
+
+        let timed_out = diagnose_remote(
+            "org.avocado.Extensions.CommandFailed: Some(CommandFailed_Args { command: \"avocadoctl\", message: \"Command 'modprobe' timed out after 60s\" })",
+        );
+        assert_eq!(timed_out.code, COMMAND_TIMED_OUT);
+
+        let syncing = diagnose_remote(
+            "org.avocado.Extensions.CommandFailed: Some(CommandFailed_Args { command: \"avocadoctl\", message: \"HITL extensions still syncing after 300000ms: app\" })",
+        );
+        assert_eq!(syncing.code, HITL_SYNC_IN_PROGRESS);
+
+        let no_systemd = diagnose_remote(
+            "org.avocado.Extensions.CommandFailed: Some(CommandFailed_Args { command: \"avocadoctl\", message: \"systemd is not running (no /run/systemd/system): cannot merge extensions\" })",
+        );
+        assert_eq!(no_systemd.code, SYSTEMD_NOT_RUNNING);
+
+        let full = diagnose_remote(
+            "org.avocado.Runtimes.UpdateFailed: Some(UpdateFailed_Args { reason: \"Not enough space in /var/lib/avocado: 2048 bytes needed, 1024 available\" })",
+        );
+        assert_eq!(full.code, STORAGE_FULL);
+
+        assert_eq!(diagnose_remote("Varlink Error").code, RPC_FAILED);
+    }
+}
@@ -0,0 +1,205 @@
+//! Error types shared across commands.
+//!
+//! Errors are grouped by what failed:
+//!
+//! - configuration: [`crate::config::ConfigError`] for the config file, and
+//!   [`SystemdError::ConfigurationError`] for invalid settings or requests
+//! - io: [`IoError`], a filesystem operation with the action and path it
+//!   failed on
+//! - systemd: [`SystemdError`], running systemd-sysext/confext and the other
+//!   system tools an extension operation needs
+//! - hitl: [`HitlError`], NFS mounts, service restarts and the server
+//!   handshake of HITL
+//! - validation: [`SystemdError::InvalidImage`], an extension image that is
+//!   not what it claims to be, and [`SystemdError::Untrusted`], one the
+//!   signing trust store does not accept
+//! - network: [`crate::update::UpdateError`] and
+//!   [`crate::commands::remote::RemoteError`]
+//!
+//! Every type converts into [`crate::service::error::AvocadoError`], the
+//! varlink error set, and implements [`crate::diagnostics::Diagnose`], whose
+//! diagnostic carries the context (path, extension, command) the error was
+//! raised with so the JSON error output can report it as fields.
+//!
+//! Context is attached where it is known: [`IoContext::context`] on an
+//! `io::Result` names the action and path, and
+//! [`ExtensionContext::for_extension`] wraps an error with the extension it
+//! happened for.
+
+use std::path::{Path, PathBuf};
+
+/// A filesystem operation that failed, with what it tried to do and where.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to {action} {}: {source}", path.display())]
+pub struct IoError {
+    /// What was attempted, such as `create directory`
+    pub action: String,
+    pub path: PathBuf,
+    pub source: std::io::Error,
+}
+
+/// Attach the action and path to an I/O error.
+pub trait IoContext<T> {
+    fn context(self, action: &str, path: impl AsRef<Path>) -> Result<T, IoError>;
+}
+
+impl<T> IoContext<T> for std::io::Result<T> {
+    fn context(self, action: &str, path: impl AsRef<Path>) -> Result<T, IoError> {
+        self.map_err(|source| IoError {
+            action: action.to_string(),
+            path: path.as_ref().to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Errors related to system command execution during image operations.
+#[derive(Debug, thiserror::Error)]
+pub enum SystemdError {
+    #[error("Failed to run command '{command}': {source}")]
+    CommandFailed {
+        command: String,
+        source: std::io::Error,
+    },
+
+    #[error("Command '{command}' exited with error code {exit_code:?}: {stderr}")]
+    CommandExitedWithError {
+        command: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+
+    #[error("Configuration error: {message}")]
+    ConfigurationError { message: String },
+
+    #[error("Command '{command}' timed out after {seconds}s")]
+    CommandTimedOut {
+        command: String,
+        /// `[avocado.timeouts]` key that set the limit
+        setting: &'static str,
+        seconds: u64,
+    },
+
+    #[error("HITL extensions still syncing after {waited_ms}ms: {}", extensions.join(", "))]
+    HitlSyncInProgress {
+        extensions: Vec<String>,
+        waited_ms: u64,
+    },
+
+    #[error("systemd is not running (no /run/systemd/system): cannot {operation} extensions")]
+    SystemdNotRunning { operation: String },
+
+    #[error(transparent)]
+    Io(#[from] IoError),
+
+    #[error("Invalid extension image {}: {reason}", path.display())]
+    InvalidImage { path: PathBuf, reason: String },
+
+    #[error("Extension image {} is not accepted by the trust store: {reason}", path.display())]
+    Untrusted { path: PathBuf, reason: String },
+
+    #[error("Extension '{extension}': {source}")]
+    Extension {
+        extension: String,
+        source: Box<SystemdError>,
+    },
+}
+
+/// Attach the extension an error happened for. An error that already names
+/// an extension keeps its own.
+pub trait ExtensionContext<T> {
+    fn for_extension(self, extension: &str) -> Result<T, SystemdError>;
+}
+
+impl<T, E: Into<SystemdError>> ExtensionContext<T> for Result<T, E> {
+    fn for_extension(self, extension: &str) -> Result<T, SystemdError> {
+        self.map_err(|e| match e.into() {
+            e @ SystemdError::Extension { .. } => e,
+            e => SystemdError::Extension {
+                extension: extension.to_string(),
+                source: Box::new(e),
+            },
+        })
+    }
+}
+
+/// Errors related to HITL operations
+#[derive(Debug, thiserror::Error)]
+pub enum HitlError {
+    #[error("Failed to run command '{command}': {source}")]
+    Command {
+        command: String,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to mount extension '{extension}' to '{mount_point}': {error}")]
+    Mount {
+        extension: String,
+        mount_point: String,
+        error: String,
+    },
+
+    #[error("Mounting extension '{extension}' to '{mount_point}' timed out after {seconds}s")]
+    MountTimedOut {
+        extension: String,
+        mount_point: String,
+        seconds: u64,
+    },
+
+    #[error(transparent)]
+    Io(#[from] IoError),
+
+    #[error("Failed to unmount '{mount_point}': {error}")]
+    Unmount { mount_point: String, error: String },
+
+    #[error("Failed to reload systemd daemon: {error}")]
+    DaemonReload { error: String },
+
+    #[error("Invalid hitl.toml in extension '{extension}': {reason}")]
+    Overrides { extension: String, reason: String },
+
+    #[error("Failed to restart {units}: {error}")]
+    Restart { units: String, error: String },
+
+    #[error("Handshake with HITL server {server} failed: {reason}")]
+    Handshake { server: String, reason: String },
+
+    #[error("HITL server {server} runs {tool}, which does not speak handshake protocol {required}; update the avocado CLI on the workstation")]
+    ServerOutdated {
+        server: String,
+        tool: String,
+        required: u32,
+    },
+
+    #[error("Extension '{extension}' is not available from {server}: {reason}")]
+    NotExported {
+        extension: String,
+        server: String,
+        reason: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_names_action_path_and_extension() {
+        let missing = std::fs::read_dir("/nonexistent/avocado")
+            .context("read directory", "/nonexistent/avocado")
+            .for_extension("app-1.0")
+            .unwrap_err();
+        assert_eq!(
+            missing.to_string(),
+            "Extension 'app-1.0': Failed to read directory /nonexistent/avocado: No such file or directory (os error 2)"
+        );
+        assert!(matches!(
+            &missing,
+            SystemdError::Extension { source, .. } if matches!(**source, SystemdError::Io(_))
+        ));
+
+        // The innermost extension wins
+        let nested = Err::<(), _>(missing).for_extension("other").unwrap_err();
+        assert!(nested.to_string().starts_with("Extension 'app-1.0': "));
+    }
+}
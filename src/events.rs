@@ -0,0 +1,173 @@
+//! Lifecycle events behind `ext events`.
+//!
+//! Merge, unmerge and refresh (their start and finish), extension scans,
+//! hook runs and HITL mount changes each append a JSON line to
+//! `/run/avocado/events.jsonl`:
+//!
+//! ```json
+//! {"time":1736868600,"event":"merge.finished","status":"failed","error":"..."}
+//! ```
+//!
+//! The CLI and the daemon append to the same log, so `ext events --follow`
+//! sees an operation whichever process runs it. Recording is best effort
+//! and never fails the operation. When the log outgrows [`MAX_LOG_BYTES`]
+//! it is moved to `events.jsonl.1`, replacing the one before.
+
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+pub const EVENTS_FILENAME: &str = "events.jsonl";
+
+/// Size at which the log is rotated.
+pub const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// How often [`follow`] checks the log for new events.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// `/run/avocado/events.jsonl`, or under `$TMPDIR/avocado` in test mode.
+pub fn path() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/{EVENTS_FILENAME}"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path(&format!(
+            "/run/avocado/{EVENTS_FILENAME}"
+        )))
+    }
+}
+
+/// Append `event` with the members of the `fields` object.
+pub fn emit(event: &str, fields: Value) {
+    let time = crate::clock::now();
+    let mut line = Map::new();
+    line.insert("time".to_string(), time.into());
+    line.insert("event".to_string(), event.into());
+    if let Value::Object(fields) = fields {
+        line.extend(fields);
+    }
+
+    let path = path();
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if fs::metadata(&path).is_ok_and(|m| m.len() >= MAX_LOG_BYTES) {
+        let _ = fs::rename(&path, path.with_extension("jsonl.1"));
+    }
+    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(&path) {
+        let _ = writeln!(file, "{}", Value::Object(line));
+    }
+}
+
+/// `<operation>.finished`, with the error when it failed.
+pub fn finished(operation: &str, error: Option<&str>) {
+    let fields = match error {
+        Some(error) => serde_json::json!({ "status": "failed", "error": error }),
+        None => serde_json::json!({ "status": "ok" }),
+    };
+    emit(&format!("{operation}.finished"), fields);
+}
+
+/// Events in `content`, skipping lines that are not JSON objects.
+pub fn parse(content: &str) -> Vec<Value> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(Value::is_object)
+        .collect()
+}
+
+/// The recorded events, oldest first, across the rotated log.
+pub fn read() -> Vec<Value> {
+    let path = path();
+    let mut events = Vec::new();
+    for file in [path.with_extension("jsonl.1"), path] {
+        if let Ok(content) = fs::read_to_string(file) {
+            events.extend(parse(&content));
+        }
+    }
+    events
+}
+
+/// Call `on_event` for every event appended from now on, forever. A
+/// rotation replaces the log with a shorter one, which starts the offset
+/// over.
+pub fn follow(mut on_event: impl FnMut(&Value)) {
+    let path = path();
+    let mut offset = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let mut pending = String::new();
+    loop {
+        thread::sleep(FOLLOW_INTERVAL);
+        let Ok(mut file) = fs::File::open(&path) else {
+            offset = 0;
+            continue;
+        };
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len < offset {
+            offset = 0;
+            pending.clear();
+        }
+        if len == offset || file.seek(SeekFrom::Start(offset)).is_err() {
+            continue;
+        }
+        let mut appended = String::new();
+        if file.read_to_string(&mut appended).is_err() {
+            continue;
+        }
+        offset += appended.len() as u64;
+        pending.push_str(&appended);
+        // Keep a partly written last line for the next round
+        let complete = pending.rfind('\n').map_or(0, |end| end + 1);
+        for event in parse(&pending[..complete]) {
+            on_event(&event);
+        }
+        pending.drain(..complete);
+    }
+}
+
+/// One event as a line of text: time, name and the other members.
+pub fn to_text(event: &Value) -> String {
+    let time = event["time"]
+        .as_u64()
+        .map_or_else(|| "-".to_string(), crate::clock::format_time);
+    let mut line = format!("{time}  {}", event["event"].as_str().unwrap_or("?"));
+    if let Some(fields) = event.as_object() {
+        for (key, value) in fields {
+            if key == "time" || key == "event" {
+                continue;
+            }
+            match value {
+                Value::String(text) => line.push_str(&format!(" {key}={text}")),
+                other => line.push_str(&format!(" {key}={other}")),
+            }
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_events() {
+        let events = parse(concat!(
+            "{\"time\":1736868600,\"event\":\"merge.finished\",\"status\":\"ok\"}\n",
+            "not json\n",
+            "{\"time\":1736868601,\"event\":\"hook.finished\",\"command\":\"depmod\",\"exit_code\":0}\n",
+            "{\"time\":1736868602,\"event\":\"hook.fini",
+        ));
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            to_text(&events[0]),
+            "2025-01-14 15:30 UTC  merge.finished status=ok"
+        );
+        assert_eq!(
+            to_text(&events[1]),
+            "2025-01-14 15:30 UTC  hook.finished command=depmod exit_code=0"
+        );
+    }
+}
@@ -0,0 +1,1168 @@
+//! Cached, parsed extension-release files.
+//!
+//! A merge looks at each extension's release file several times: to detect
+//! whether it is a sysext or confext and its version, to check its scope, and
+//! again for AVOCADO_ON_MERGE, AVOCADO_ON_MERGE_PRIORITY, AVOCADO_MODPROBE,
+//! AVOCADO_REBOOT_REQUIRED, AVOCADO_ESSENTIAL, AVOCADO_ENABLE_SERVICES,
+//! AVOCADO_REQUIRES, the build provenance keys and the lifecycle keys. HITL
+//! extensions live on NFS, where each of those lookups is a round trip when
+//! attribute caching is disabled. [`find`] resolves and parses a release
+//! file once and hands out the parsed result until [`invalidate`] is called.
+//!
+//! The cache is process-wide. Every discovery scan and HITL mount/unmount
+//! starts with [`invalidate`], so the long-running daemon never reuses
+//! results from an earlier request.
+
+use crate::commands::image_adaptor::{
+    is_running_in_initrd, is_scope_enabled_for_current_environment,
+};
+use crate::os_release::OsRelease;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Which release file of an extension to look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hierarchy {
+    Sysext,
+    Confext,
+}
+
+impl Hierarchy {
+    /// Directory holding the release file, relative to the extension root.
+    pub fn release_dir(self) -> &'static str {
+        match self {
+            Hierarchy::Sysext => "usr/lib/extension-release.d",
+            Hierarchy::Confext => "etc/extension-release.d",
+        }
+    }
+
+    /// Release file key restricting where the extension is merged.
+    pub fn scope_key(self) -> &'static str {
+        match self {
+            Hierarchy::Sysext => "SYSEXT_SCOPE",
+            Hierarchy::Confext => "CONFEXT_SCOPE",
+        }
+    }
+}
+
+/// Where an extension was built, from the AVOCADO_BUILD_ID, AVOCADO_GIT_SHA
+/// and AVOCADO_BUILD_DATE keys of its release file. Lets support map an
+/// extension on a device to the CI build that produced it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_sha: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_date: Option<String>,
+}
+
+impl Provenance {
+    /// Read the provenance keys from release file content. Empty values
+    /// count as unset.
+    pub fn parse(content: &str) -> Self {
+        let release = OsRelease::parse(content);
+        let value = |key: &str| release.get(key).map(str::to_string);
+        Self {
+            build_id: value("AVOCADO_BUILD_ID"),
+            git_sha: value("AVOCADO_GIT_SHA"),
+            build_date: value("AVOCADO_BUILD_DATE"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.build_id.is_none() && self.git_sha.is_none() && self.build_date.is_none()
+    }
+
+    /// Keep the keys set here and take the missing ones from `other`.
+    pub fn or(self, other: &Provenance) -> Self {
+        Self {
+            build_id: self.build_id.or_else(|| other.build_id.clone()),
+            git_sha: self.git_sha.or_else(|| other.git_sha.clone()),
+            build_date: self.build_date.or_else(|| other.build_date.clone()),
+        }
+    }
+}
+
+/// Support status of an extension, from the AVOCADO_EOL and AVOCADO_NOTES
+/// keys of its release file. Lets devices report extensions that are
+/// deprecated or past their end of life.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lifecycle {
+    /// Date (YYYY-MM-DD) from which the extension is no longer supported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eol: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+impl Lifecycle {
+    /// Read the lifecycle keys from release file content. Empty values
+    /// count as unset.
+    pub fn parse(content: &str) -> Self {
+        let release = OsRelease::parse(content);
+        Self {
+            eol: release.get("AVOCADO_EOL").map(str::to_string),
+            notes: release.get("AVOCADO_NOTES").map(str::to_string),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.eol.is_none() && self.notes.is_none()
+    }
+
+    /// Keep the keys set here and take the missing ones from `other`.
+    pub fn or(self, other: &Lifecycle) -> Self {
+        Self {
+            eol: self.eol.or_else(|| other.eol.clone()),
+            notes: self.notes.or_else(|| other.notes.clone()),
+        }
+    }
+
+    /// Whether the EOL date has been reached at `secs` since the Unix epoch
+    /// (UTC). A malformed date never counts as reached.
+    pub fn eol_reached_at(&self, secs: u64) -> bool {
+        let today = &crate::clock::format_time(secs)[..10];
+        self.eol
+            .as_deref()
+            .is_some_and(|eol| is_eol_date(eol) && eol <= today)
+    }
+}
+
+/// Whether `value` is a valid AVOCADO_EOL date (YYYY-MM-DD).
+pub fn is_eol_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return false;
+    };
+    let number = |s: &str, len: usize, range: std::ops::RangeInclusive<u32>| {
+        s.len() == len
+            && s.bytes().all(|b| b.is_ascii_digit())
+            && s.parse().is_ok_and(|n| range.contains(&n))
+    };
+    number(year, 4, 0..=9999) && number(month, 2, 1..=12) && number(day, 2, 1..=31)
+}
+
+/// A parsed extension-release file. An unreadable file parses as empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseFile {
+    pub path: PathBuf,
+    /// Version taken from a versioned file name
+    /// (`extension-release.<name>-<version>`).
+    pub version: Option<String>,
+    pub content: String,
+    /// Whether the scope key allows merging in the current environment.
+    pub in_scope: bool,
+    pub on_merge: Vec<String>,
+    /// AVOCADO_ON_MERGE_PRIORITY, when set to an integer; lower runs its
+    /// hooks first.
+    pub on_merge_priority: Option<i32>,
+    pub on_unmerge: Vec<String>,
+    pub modprobe: Vec<String>,
+    pub modprobe_blacklist: Vec<String>,
+    pub enable_services: Vec<String>,
+    /// Dependency types declared for some of `enable_services`
+    /// (`nginx.service:requires`).
+    pub service_dependencies: BTreeMap<String, Vec<ServiceDependency>>,
+    /// Extensions this one depends on (AVOCADO_REQUIRES).
+    pub requires: Vec<String>,
+    pub reboot_required: bool,
+    /// Merged in safe mode (AVOCADO_ESSENTIAL).
+    pub essential: bool,
+    /// Data migration script (AVOCADO_MIGRATE).
+    pub migrate: Option<String>,
+    /// Data schema version the migration targets (AVOCADO_DATA_VERSION).
+    pub data_version: Option<String>,
+    pub provenance: Provenance,
+    pub lifecycle: Lifecycle,
+}
+
+impl ReleaseFile {
+    fn parse(path: PathBuf, version: Option<String>, hierarchy: Hierarchy) -> Self {
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        let fields = OsRelease::parse(&content);
+        Self {
+            path,
+            version,
+            in_scope: is_scope_enabled_for_current_environment(&content, hierarchy.scope_key()),
+            on_merge: parse_avocado_on_merge_commands(&content),
+            on_merge_priority: on_merge_priority(&fields),
+            on_unmerge: parse_avocado_on_unmerge_commands(&content),
+            modprobe: parse_avocado_modprobe(&content),
+            modprobe_blacklist: parse_avocado_modprobe_blacklist(&content),
+            enable_services: parse_avocado_enable_services(&content),
+            service_dependencies: parse_avocado_service_dependencies(&content),
+            requires: requires(&fields),
+            reboot_required: flag(&fields, crate::reboot::REBOOT_REQUIRED_KEY),
+            essential: flag(&fields, "AVOCADO_ESSENTIAL"),
+            migrate: fields.get("AVOCADO_MIGRATE").map(str::to_string),
+            data_version: fields.get("AVOCADO_DATA_VERSION").map(str::to_string),
+            provenance: Provenance::parse(&content),
+            lifecycle: Lifecycle::parse(&content),
+            content,
+        }
+    }
+}
+
+/// AVOCADO_ON_MERGE_PRIORITY; `None` when unset or not an integer.
+fn on_merge_priority(fields: &OsRelease) -> Option<i32> {
+    fields
+        .get("AVOCADO_ON_MERGE_PRIORITY")
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// AVOCADO_REQUIRES: names of the extensions this one depends on, separated
+/// by whitespace, without duplicates.
+fn requires(fields: &OsRelease) -> Vec<String> {
+    let mut requires: Vec<String> = Vec::new();
+    for name in fields
+        .get("AVOCADO_REQUIRES")
+        .unwrap_or_default()
+        .split_whitespace()
+    {
+        if !requires.iter().any(|r| r == name) {
+            requires.push(name.to_string());
+        }
+    }
+    requires
+}
+
+/// Whether the boolean `key` is set in parsed release file `fields`: `yes`,
+/// `true` or `1`, case-insensitive.
+pub fn flag(fields: &OsRelease, key: &str) -> bool {
+    fields
+        .get(key)
+        .is_some_and(|value| matches!(value.to_ascii_lowercase().as_str(), "yes" | "true" | "1"))
+}
+
+/// AVOCADO_* release file keys avocadoctl acts on.
+pub const KNOWN_AVOCADO_KEYS: &[&str] = &[
+    "AVOCADO_ON_MERGE",
+    "AVOCADO_ON_MERGE_SYSTEM",
+    "AVOCADO_ON_MERGE_INITRD",
+    "AVOCADO_ON_MERGE_PRIORITY",
+    "AVOCADO_ON_UNMERGE",
+    "AVOCADO_ON_UNMERGE_SYSTEM",
+    "AVOCADO_ON_UNMERGE_INITRD",
+    "AVOCADO_MODPROBE",
+    "AVOCADO_MODPROBE_BLACKLIST",
+    "AVOCADO_ENABLE_SERVICES",
+    "AVOCADO_REQUIRES",
+    "AVOCADO_REBOOT_REQUIRED",
+    "AVOCADO_ESSENTIAL",
+    "AVOCADO_MIGRATE",
+    "AVOCADO_DATA_VERSION",
+    "AVOCADO_BUILD_ID",
+    "AVOCADO_GIT_SHA",
+    "AVOCADO_BUILD_DATE",
+    "AVOCADO_EOL",
+    "AVOCADO_NOTES",
+];
+
+/// AVOCADO_* keys set in release file content that are not in
+/// [`KNOWN_AVOCADO_KEYS`], in file order and without duplicates.
+pub fn unknown_avocado_keys(content: &str) -> Vec<String> {
+    let mut unknown: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let Some((key, _)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.starts_with("AVOCADO_")
+            && !KNOWN_AVOCADO_KEYS.contains(&key)
+            && !unknown.iter().any(|k| k == key)
+        {
+            unknown.push(key.to_string());
+        }
+    }
+    unknown
+}
+
+type CacheKey = (PathBuf, String, Hierarchy);
+
+/// Release files looked up so far, including the ones found missing.
+#[derive(Default)]
+struct ReleaseCache {
+    entries: Mutex<HashMap<CacheKey, Option<Arc<ReleaseFile>>>>,
+}
+
+impl ReleaseCache {
+    fn find(
+        &self,
+        extension_path: &Path,
+        name: &str,
+        hierarchy: Hierarchy,
+    ) -> Option<Arc<ReleaseFile>> {
+        let key = (extension_path.to_path_buf(), name.to_string(), hierarchy);
+        if let Some(cached) = self.entries.lock().ok().and_then(|c| c.get(&key).cloned()) {
+            return cached;
+        }
+        let found = locate(extension_path, name, hierarchy).map(Arc::new);
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(key, found.clone());
+        }
+        found
+    }
+
+    fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+fn cache() -> &'static ReleaseCache {
+    static CACHE: OnceLock<ReleaseCache> = OnceLock::new();
+    CACHE.get_or_init(ReleaseCache::default)
+}
+
+/// Drop everything cached, so the next lookups read the files again.
+pub fn invalidate() {
+    cache().clear();
+}
+
+/// Locate the release file of `name` below `extension_path`: the exact
+/// `extension-release.<name>`, else the first `extension-release.<name>-*`.
+fn locate(extension_path: &Path, name: &str, hierarchy: Hierarchy) -> Option<ReleaseFile> {
+    let dir = extension_path.join(hierarchy.release_dir());
+    let exact = dir.join(format!("extension-release.{name}"));
+    if exact.exists() {
+        return Some(ReleaseFile::parse(exact, None, hierarchy));
+    }
+    let prefix = format!("extension-release.{name}-");
+    crate::ordering::read_dir_sorted(&dir)
+        .ok()?
+        .into_iter()
+        .find_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let version = file_name.strip_prefix(&prefix)?;
+            let version = (!version.is_empty()).then(|| version.to_string());
+            Some(ReleaseFile::parse(entry.path(), version, hierarchy))
+        })
+}
+
+/// The release file of extension `name` at `extension_path`, if it has one
+/// for `hierarchy`. Read and parsed on first use, then served from the cache.
+pub fn find(extension_path: &Path, name: &str, hierarchy: Hierarchy) -> Option<Arc<ReleaseFile>> {
+    cache().find(extension_path, name, hierarchy)
+}
+
+/// Environment a hook command can be restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HookEnvironment {
+    Initrd,
+    System,
+}
+
+impl HookEnvironment {
+    /// Environments in the order their key suffixes are documented.
+    pub(crate) const ALL: [HookEnvironment; 2] = [HookEnvironment::System, HookEnvironment::Initrd];
+
+    pub(crate) fn current() -> Self {
+        if is_running_in_initrd() {
+            HookEnvironment::Initrd
+        } else {
+            HookEnvironment::System
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            HookEnvironment::Initrd => "initrd",
+            HookEnvironment::System => "system",
+        }
+    }
+
+    /// Suffix of the key variant restricted to this environment.
+    fn key_suffix(self) -> &'static str {
+        match self {
+            HookEnvironment::Initrd => "_INITRD",
+            HookEnvironment::System => "_SYSTEM",
+        }
+    }
+}
+
+/// One hook command as declared in a release file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HookDeclaration {
+    /// Key as written, e.g. `AVOCADO_ON_MERGE_INITRD`
+    pub key: String,
+    pub command: String,
+    /// Environments the command runs in; empty when its `[condition]`
+    /// prefix is not understood, so it never runs
+    pub environments: Vec<HookEnvironment>,
+    /// The `[condition]` prefix that was not understood
+    pub unknown_condition: Option<String>,
+}
+
+/// Environments a `[condition]` hook prefix selects: `initrd` or `system`,
+/// several separated by `|`, or one negated with `!`.
+fn parse_hook_condition(condition: &str) -> Option<Vec<HookEnvironment>> {
+    let by_name = |name: &str| {
+        HookEnvironment::ALL
+            .into_iter()
+            .find(|env| env.name() == name.trim())
+    };
+    if let Some(negated) = condition.trim().strip_prefix('!') {
+        let excluded = by_name(negated)?;
+        return Some(
+            HookEnvironment::ALL
+                .into_iter()
+                .filter(|env| *env != excluded)
+                .collect(),
+        );
+    }
+    condition.split('|').map(by_name).collect()
+}
+
+/// All hook commands declared for `key` (`AVOCADO_ON_MERGE` or
+/// `AVOCADO_ON_UNMERGE`) in release file content, in file order: `KEY=` for
+/// every environment, `KEY_SYSTEM=` / `KEY_INITRD=` for one, each
+/// optionally narrowed further by a `[condition]` prefix on the command.
+pub(crate) fn parse_hook_declarations(content: &str, key: &str) -> Vec<HookDeclaration> {
+    let mut declarations = Vec::new();
+
+    for line in content.lines() {
+        let Some((line_key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let line_key = line_key.trim();
+        let Some(suffix) = line_key.strip_prefix(key) else {
+            continue;
+        };
+        let mut environments = if suffix.is_empty() {
+            HookEnvironment::ALL.to_vec()
+        } else {
+            match HookEnvironment::ALL
+                .into_iter()
+                .find(|env| env.key_suffix() == suffix)
+            {
+                Some(env) => vec![env],
+                None => continue,
+            }
+        };
+
+        let mut command = value.trim_matches('"').trim();
+        let mut unknown_condition = None;
+        if let Some((condition, rest)) = command
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+        {
+            match parse_hook_condition(condition) {
+                Some(selected) => environments.retain(|env| selected.contains(env)),
+                None => {
+                    environments.clear();
+                    unknown_condition = Some(condition.to_string());
+                }
+            }
+            command = rest.trim();
+        }
+
+        if !command.is_empty() {
+            declarations.push(HookDeclaration {
+                key: line_key.to_string(),
+                command: command.to_string(),
+                environments,
+                unknown_condition,
+            });
+        }
+    }
+
+    declarations
+}
+
+/// Hook commands declared for `key` that run in `environment`.
+pub(crate) fn parse_hook_commands(
+    content: &str,
+    key: &str,
+    environment: HookEnvironment,
+) -> Vec<String> {
+    parse_hook_declarations(content, key)
+        .into_iter()
+        .filter(|hook| hook.environments.contains(&environment))
+        .map(|hook| hook.command)
+        .collect()
+}
+
+/// Parse the AVOCADO_ON_MERGE commands for the current environment from
+/// release file content
+pub(crate) fn parse_avocado_on_merge_commands(content: &str) -> Vec<String> {
+    parse_hook_commands(content, "AVOCADO_ON_MERGE", HookEnvironment::current())
+}
+
+/// Parse the AVOCADO_ON_UNMERGE commands for the current environment from
+/// release file content
+pub(crate) fn parse_avocado_on_unmerge_commands(content: &str) -> Vec<String> {
+    parse_hook_commands(content, "AVOCADO_ON_UNMERGE", HookEnvironment::current())
+}
+
+/// Check if a release file content contains AVOCADO_ON_MERGE=depmod
+/// (Kept for backward compatibility with existing tests)
+#[allow(dead_code)]
+fn check_avocado_on_merge_depmod(content: &str) -> bool {
+    let commands = parse_avocado_on_merge_commands(content);
+    commands.contains(&"depmod".to_string())
+}
+
+/// Parse AVOCADO_MODPROBE modules from release file content
+pub(crate) fn parse_avocado_modprobe(content: &str) -> Vec<String> {
+    let mut modules = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with("AVOCADO_MODPROBE=") {
+            let value = line
+                .split_once('=')
+                .map(|x| x.1)
+                .unwrap_or("")
+                .trim_matches('"')
+                .trim();
+
+            // Parse space-separated list of modules
+            for module in value.split_whitespace() {
+                if !module.is_empty() {
+                    modules.push(module.to_string());
+                }
+            }
+            break; // Only process the first AVOCADO_MODPROBE line
+        }
+    }
+
+    modules
+}
+
+/// Parse AVOCADO_MODPROBE_BLACKLIST modules from release file content
+pub(crate) fn parse_avocado_modprobe_blacklist(content: &str) -> Vec<String> {
+    let mut modules = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with("AVOCADO_MODPROBE_BLACKLIST=") {
+            let value = line
+                .split_once('=')
+                .map(|x| x.1)
+                .unwrap_or("")
+                .trim_matches('"')
+                .trim();
+
+            // Parse space-separated list of modules
+            for module in value.split_whitespace() {
+                if !modules.contains(&module.to_string()) {
+                    modules.push(module.to_string());
+                }
+            }
+            break; // Only process the first AVOCADO_MODPROBE_BLACKLIST line
+        }
+    }
+
+    modules
+}
+
+/// How a service listed in AVOCADO_ENABLE_SERVICES depends on the
+/// extension's HITL mount unit, from a `:type[,type]` suffix on its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceDependency {
+    Wants,
+    Requires,
+    BindsTo,
+    After,
+}
+
+impl ServiceDependency {
+    pub const ALL: [ServiceDependency; 4] = [
+        ServiceDependency::Wants,
+        ServiceDependency::Requires,
+        ServiceDependency::BindsTo,
+        ServiceDependency::After,
+    ];
+
+    /// Name as written in the suffix, e.g. `bindsto`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ServiceDependency::Wants => "wants",
+            ServiceDependency::Requires => "requires",
+            ServiceDependency::BindsTo => "bindsto",
+            ServiceDependency::After => "after",
+        }
+    }
+
+    /// The `[Unit]` setting it becomes in the service drop-in.
+    pub fn directive(self) -> &'static str {
+        match self {
+            ServiceDependency::Wants => "Wants",
+            ServiceDependency::Requires => "Requires",
+            ServiceDependency::BindsTo => "BindsTo",
+            ServiceDependency::After => "After",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|dep| dep.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// One service as declared in AVOCADO_ENABLE_SERVICES.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ServiceDeclaration {
+    /// Service name without the suffix, `.service` optional
+    pub service: String,
+    /// Dependency types from the suffix; empty without one, for the default
+    /// drop-in
+    pub dependencies: Vec<ServiceDependency>,
+    /// Suffix entries that are not dependency types
+    pub unknown_dependencies: Vec<String>,
+}
+
+/// All services declared in AVOCADO_ENABLE_SERVICES in release file
+/// content, in file order, the first declaration of each service only.
+pub(crate) fn parse_service_declarations(content: &str) -> Vec<ServiceDeclaration> {
+    let mut declarations: Vec<ServiceDeclaration> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with("AVOCADO_ENABLE_SERVICES=") {
+            let value = line
+                .split_once('=')
+                .map(|x| x.1)
+                .unwrap_or("")
+                .trim_matches('"')
+                .trim();
+
+            // Parse space-separated list of services
+            for entry in value.split_whitespace() {
+                let (service, suffix) = entry.split_once(':').unwrap_or((entry, ""));
+                if service.is_empty() || declarations.iter().any(|d| d.service == service) {
+                    continue;
+                }
+                let mut dependencies = Vec::new();
+                let mut unknown_dependencies = Vec::new();
+                for name in suffix.split(',').filter(|n| !n.is_empty()) {
+                    match ServiceDependency::parse(name) {
+                        Some(dep) if !dependencies.contains(&dep) => dependencies.push(dep),
+                        Some(_) => {}
+                        None => unknown_dependencies.push(name.to_string()),
+                    }
+                }
+                declarations.push(ServiceDeclaration {
+                    service: service.to_string(),
+                    dependencies,
+                    unknown_dependencies,
+                });
+            }
+        }
+    }
+
+    declarations
+}
+
+/// Parse AVOCADO_ENABLE_SERVICES from release file content
+/// Returns a list of systemd service unit names that should depend on the extension's mount
+pub fn parse_avocado_enable_services(content: &str) -> Vec<String> {
+    parse_service_declarations(content)
+        .into_iter()
+        .map(|d| d.service)
+        .collect()
+}
+
+/// Dependency types declared with a `:type` suffix in
+/// AVOCADO_ENABLE_SERVICES, by service name. Services without a (valid)
+/// suffix are left out and get the default drop-in.
+pub fn parse_avocado_service_dependencies(
+    content: &str,
+) -> std::collections::BTreeMap<String, Vec<ServiceDependency>> {
+    parse_service_declarations(content)
+        .into_iter()
+        .filter(|d| !d.dependencies.is_empty())
+        .map(|d| (d.service, d.dependencies))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_release(root: &Path, hierarchy: Hierarchy, file: &str, content: &str) {
+        let dir = root.join(hierarchy.release_dir());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(file), content).unwrap();
+    }
+
+    #[test]
+    fn test_find_parses_versioned_release_file() {
+        let temp = TempDir::new().unwrap();
+        write_release(
+            temp.path(),
+            Hierarchy::Sysext,
+            "extension-release.app-1.2",
+            "ID=_any\nAVOCADO_ON_MERGE=depmod\nAVOCADO_ENABLE_SERVICES=\"app.service\"\n",
+        );
+
+        let release = find(temp.path(), "app", Hierarchy::Sysext).unwrap();
+        assert_eq!(release.version.as_deref(), Some("1.2"));
+        assert_eq!(release.on_merge, vec!["depmod"]);
+        assert_eq!(release.enable_services, vec!["app.service"]);
+        assert!(release.in_scope);
+        assert!(find(temp.path(), "app", Hierarchy::Confext).is_none());
+    }
+
+    #[test]
+    fn test_provenance_parse() {
+        let provenance = Provenance::parse(
+            "ID=_any\nAVOCADO_BUILD_ID=\"ci-4312\"\nAVOCADO_GIT_SHA=9f1c2ab\nAVOCADO_BUILD_DATE=\nAVOCADO_BUILD_IDX=no\n",
+        );
+        assert_eq!(provenance.build_id.as_deref(), Some("ci-4312"));
+        assert_eq!(provenance.git_sha.as_deref(), Some("9f1c2ab"));
+        assert_eq!(provenance.build_date, None);
+        assert!(Provenance::parse("ID=_any\n").is_empty());
+
+        let merged = provenance.or(&Provenance {
+            build_date: Some("2026-10-01".to_string()),
+            git_sha: Some("other".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(merged.git_sha.as_deref(), Some("9f1c2ab"));
+        assert_eq!(merged.build_date.as_deref(), Some("2026-10-01"));
+    }
+
+    #[test]
+    fn test_lifecycle_eol() {
+        let lifecycle =
+            Lifecycle::parse("ID=_any\nAVOCADO_EOL=2026-10-14\nAVOCADO_NOTES=\"Use app2\"\n");
+        assert_eq!(lifecycle.notes.as_deref(), Some("Use app2"));
+        // 2026-10-13 23:59 and 2026-10-14 00:00 UTC
+        assert!(!lifecycle.eol_reached_at(1_791_935_940));
+        assert!(lifecycle.eol_reached_at(1_791_936_000));
+
+        assert!(is_eol_date("2026-01-31"));
+        assert!(!is_eol_date("2026-1-31"));
+        assert!(!is_eol_date("2026-13-01"));
+        let malformed = Lifecycle::parse("AVOCADO_EOL=soon\n");
+        assert!(!malformed.eol_reached_at(u64::MAX / 2));
+    }
+
+    #[test]
+    fn test_on_merge_priority() {
+        let priority = |content: &str| on_merge_priority(&OsRelease::parse(content));
+        assert_eq!(
+            priority("ID=_any\nAVOCADO_ON_MERGE_PRIORITY=\"-10\"\n"),
+            Some(-10)
+        );
+        assert_eq!(priority("AVOCADO_ON_MERGE_PRIORITY='20'\n"), Some(20));
+        assert_eq!(priority("AVOCADO_ON_MERGE_PRIORITY=early\n"), None);
+        assert_eq!(priority("# AVOCADO_ON_MERGE_PRIORITY=5\nID=_any\n"), None);
+    }
+
+    #[test]
+    fn test_requires() {
+        let content =
+            "ID=avocado\n# AVOCADO_REQUIRES=old\nAVOCADO_REQUIRES=\"runtime base runtime\"\n";
+        assert_eq!(requires(&OsRelease::parse(content)), ["runtime", "base"]);
+        // A later assignment replaces an earlier one, as in the shell
+        let content = "AVOCADO_REQUIRES=runtime\nAVOCADO_REQUIRES='base tls'\n";
+        assert_eq!(requires(&OsRelease::parse(content)), ["base", "tls"]);
+        assert!(requires(&OsRelease::parse("ID=avocado\n")).is_empty());
+    }
+
+    #[test]
+    fn test_flag() {
+        let fields = OsRelease::parse(
+            "ID=_any\nAVOCADO_ESSENTIAL=\"TRUE\"\n# AVOCADO_REBOOT_REQUIRED=yes\nAVOCADO_MIGRATE=no\n",
+        );
+        assert!(flag(&fields, "AVOCADO_ESSENTIAL"));
+        assert!(!flag(&fields, "AVOCADO_REBOOT_REQUIRED"));
+        assert!(!flag(&fields, "AVOCADO_MIGRATE"));
+        assert!(!flag(&fields, "ID"));
+    }
+
+    #[test]
+    fn test_unknown_avocado_keys() {
+        let content = "ID=_any\nAVOCADO_ON_MERGE=depmod\nAVOCADO_ON_MEGRE=ldconfig\n# AVOCADO_X=1\nAVOCADO_ON_MEGRE=x\nFOO=bar\n";
+        assert_eq!(unknown_avocado_keys(content), vec!["AVOCADO_ON_MEGRE"]);
+    }
+
+    #[test]
+    fn test_find_serves_cache_until_invalidated() {
+        let temp = TempDir::new().unwrap();
+        write_release(
+            temp.path(),
+            Hierarchy::Confext,
+            "extension-release.cfg",
+            "ID=_any\n",
+        );
+
+        let cache = ReleaseCache::default();
+        let first = cache.find(temp.path(), "cfg", Hierarchy::Confext).unwrap();
+        write_release(
+            temp.path(),
+            Hierarchy::Confext,
+            "extension-release.cfg",
+            "ID=_any\nAVOCADO_REBOOT_REQUIRED=yes\n",
+        );
+        let second = cache.find(temp.path(), "cfg", Hierarchy::Confext).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        cache.clear();
+        let third = cache.find(temp.path(), "cfg", Hierarchy::Confext).unwrap();
+        assert!(third.reboot_required);
+    }
+
+    #[test]
+    fn test_check_avocado_on_merge_depmod() {
+        // Test case with AVOCADO_ON_MERGE=depmod
+        let content_with_depmod = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE=depmod
+OTHER_KEY=value
+"#;
+        assert!(check_avocado_on_merge_depmod(content_with_depmod));
+
+        // Test case with AVOCADO_ON_MERGE=depmod with quotes
+        let content_with_quoted_depmod = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE="depmod"
+OTHER_KEY=value
+"#;
+        assert!(check_avocado_on_merge_depmod(content_with_quoted_depmod));
+
+        // Test case with different AVOCADO_ON_MERGE value
+        let content_with_other_value = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE=something_else
+OTHER_KEY=value
+"#;
+        assert!(!check_avocado_on_merge_depmod(content_with_other_value));
+
+        // Test case without AVOCADO_ON_MERGE
+        let content_without_key = r#"
+VERSION_ID=1.0
+OTHER_KEY=value
+"#;
+        assert!(!check_avocado_on_merge_depmod(content_without_key));
+
+        // Test case with empty content
+        assert!(!check_avocado_on_merge_depmod(""));
+
+        // Test case with AVOCADO_ON_MERGE but empty value
+        let content_with_empty_value = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE=
+OTHER_KEY=value
+"#;
+        assert!(!check_avocado_on_merge_depmod(content_with_empty_value));
+    }
+
+    #[test]
+    fn test_parse_avocado_modprobe() {
+        // Test case with multiple modules
+        let content_with_modules = r#"
+VERSION_ID=2.0
+AVOCADO_MODPROBE="nvidia i915 radeon"
+OTHER_KEY=value
+"#;
+        let modules = parse_avocado_modprobe(content_with_modules);
+        assert_eq!(modules, vec!["nvidia", "i915", "radeon"]);
+
+        // Test case with single module without quotes
+        let content_single_module = r#"
+VERSION_ID=1.5
+AVOCADO_MODPROBE=snd_hda_intel
+OTHER_KEY=value
+"#;
+        let modules = parse_avocado_modprobe(content_single_module);
+        assert_eq!(modules, vec!["snd_hda_intel"]);
+
+        // Test case with no AVOCADO_MODPROBE
+        let content_no_modprobe = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE=depmod
+OTHER_KEY=value
+"#;
+        let modules = parse_avocado_modprobe(content_no_modprobe);
+        assert!(modules.is_empty());
+
+        // Test case with empty AVOCADO_MODPROBE
+        let content_empty_modprobe = r#"
+VERSION_ID=1.0
+AVOCADO_MODPROBE=""
+OTHER_KEY=value
+"#;
+        let modules = parse_avocado_modprobe(content_empty_modprobe);
+        assert!(modules.is_empty());
+
+        // Test case with extra whitespace
+        let content_with_whitespace = r#"
+VERSION_ID=1.0
+AVOCADO_MODPROBE="  nvidia   i915  radeon  "
+OTHER_KEY=value
+"#;
+        let modules = parse_avocado_modprobe(content_with_whitespace);
+        assert_eq!(modules, vec!["nvidia", "i915", "radeon"]);
+
+        // Test case with mixed quotes and no quotes in different lines (only first should be processed)
+        let content_multiple_lines = r#"
+VERSION_ID=1.0
+AVOCADO_MODPROBE="nvidia i915"
+AVOCADO_MODPROBE=should_be_ignored
+OTHER_KEY=value
+"#;
+        let modules = parse_avocado_modprobe(content_multiple_lines);
+        assert_eq!(modules, vec!["nvidia", "i915"]);
+    }
+
+    #[test]
+    fn test_parse_avocado_modprobe_blacklist() {
+        let content = r#"
+VERSION_ID=1.0
+AVOCADO_MODPROBE="vendor_cam"
+AVOCADO_MODPROBE_BLACKLIST="uvcvideo  ov5640 uvcvideo"
+"#;
+        assert_eq!(
+            parse_avocado_modprobe_blacklist(content),
+            vec!["uvcvideo", "ov5640"]
+        );
+        // The two keys don't leak into each other
+        assert_eq!(parse_avocado_modprobe(content), vec!["vendor_cam"]);
+        assert!(parse_avocado_modprobe_blacklist("VERSION_ID=1.0").is_empty());
+    }
+
+    #[test]
+    fn test_parse_avocado_on_merge_commands_with_equals() {
+        // Test case with command containing equals signs in arguments
+        let content_with_equals = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE="udevadm trigger --action=add"
+AVOCADO_ON_MERGE=command --option=value --other=setting
+OTHER_KEY=value
+"#;
+        let commands = parse_avocado_on_merge_commands(content_with_equals);
+        assert_eq!(
+            commands,
+            vec![
+                "udevadm trigger --action=add",
+                "command --option=value --other=setting"
+            ]
+        );
+
+        // Test case with multiple equals signs in same argument
+        let content_multiple_equals = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE="systemctl set-property --runtime some.service CPUQuota=50% MemoryLimit=1G"
+"#;
+        let commands = parse_avocado_on_merge_commands(content_multiple_equals);
+        assert_eq!(
+            commands,
+            vec!["systemctl set-property --runtime some.service CPUQuota=50% MemoryLimit=1G"]
+        );
+
+        // Test case ensuring backwards compatibility with simple commands
+        let content_simple = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE=depmod
+AVOCADO_ON_MERGE="systemctl restart some-service"
+"#;
+        let commands = parse_avocado_on_merge_commands(content_simple);
+        assert_eq!(commands, vec!["depmod", "systemctl restart some-service"]);
+    }
+
+    #[test]
+    fn test_parse_avocado_on_merge_commands_with_semicolons() {
+        // Test case with semicolon-separated commands
+        let content_with_semicolons = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE="systemctl --no-block restart dbus; systemctl --no-block restart avahi-daemon"
+AVOCADO_ON_MERGE="command1 --arg=value; command2; command3 --option"
+OTHER_KEY=value
+"#;
+        let commands = parse_avocado_on_merge_commands(content_with_semicolons);
+        assert_eq!(
+            commands,
+            vec![
+                "systemctl --no-block restart dbus; systemctl --no-block restart avahi-daemon",
+                "command1 --arg=value; command2; command3 --option"
+            ]
+        );
+
+        // Test case with mixed semicolons and regular commands
+        let content_mixed = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE=depmod
+AVOCADO_ON_MERGE="systemctl restart service1; systemctl restart service2"
+AVOCADO_ON_MERGE="single-command --arg"
+"#;
+        let commands = parse_avocado_on_merge_commands(content_mixed);
+        assert_eq!(
+            commands,
+            vec![
+                "depmod",
+                "systemctl restart service1; systemctl restart service2",
+                "single-command --arg"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_avocado_enable_services() {
+        // Test case with multiple services
+        let content_with_services = r#"
+VERSION_ID=1.0
+AVOCADO_ENABLE_SERVICES="nginx.service prometheus.service"
+OTHER_KEY=value
+"#;
+        let services = parse_avocado_enable_services(content_with_services);
+        assert_eq!(services, vec!["nginx.service", "prometheus.service"]);
+
+        // Test case with services without .service suffix
+        let content_short_names = r#"
+VERSION_ID=1.0
+AVOCADO_ENABLE_SERVICES="nginx prometheus redis"
+OTHER_KEY=value
+"#;
+        let services = parse_avocado_enable_services(content_short_names);
+        assert_eq!(services, vec!["nginx", "prometheus", "redis"]);
+
+        // Test case with no AVOCADO_ENABLE_SERVICES
+        let content_no_services = r#"
+VERSION_ID=1.0
+AVOCADO_ON_MERGE=depmod
+OTHER_KEY=value
+"#;
+        let services = parse_avocado_enable_services(content_no_services);
+        assert!(services.is_empty());
+
+        // Test case with empty AVOCADO_ENABLE_SERVICES
+        let content_empty_services = r#"
+VERSION_ID=1.0
+AVOCADO_ENABLE_SERVICES=""
+OTHER_KEY=value
+"#;
+        let services = parse_avocado_enable_services(content_empty_services);
+        assert!(services.is_empty());
+
+        // Test case with extra whitespace
+        let content_with_whitespace = r#"
+VERSION_ID=1.0
+AVOCADO_ENABLE_SERVICES="  nginx   redis  "
+OTHER_KEY=value
+"#;
+        let services = parse_avocado_enable_services(content_with_whitespace);
+        assert_eq!(services, vec!["nginx", "redis"]);
+
+        // Test case with multiple AVOCADO_ENABLE_SERVICES lines (all should be processed)
+        let content_multiple_lines = r#"
+VERSION_ID=1.0
+AVOCADO_ENABLE_SERVICES="nginx prometheus"
+AVOCADO_ENABLE_SERVICES="redis"
+OTHER_KEY=value
+"#;
+        let services = parse_avocado_enable_services(content_multiple_lines);
+        assert_eq!(services, vec!["nginx", "prometheus", "redis"]);
+
+        // Test case with duplicates (should be deduplicated)
+        let content_with_duplicates = r#"
+VERSION_ID=1.0
+AVOCADO_ENABLE_SERVICES="nginx redis"
+AVOCADO_ENABLE_SERVICES="nginx worker"
+OTHER_KEY=value
+"#;
+        let services = parse_avocado_enable_services(content_with_duplicates);
+        assert_eq!(services, vec!["nginx", "redis", "worker"]);
+    }
+
+    #[test]
+    fn test_parse_avocado_service_dependencies() {
+        let content = r#"
+AVOCADO_ENABLE_SERVICES="nginx.service:requires worker.service:wants,after,wants plain"
+AVOCADO_ENABLE_SERVICES="db:BindsTo,later nginx.service:wants"
+"#;
+        assert_eq!(
+            parse_avocado_enable_services(content),
+            vec!["nginx.service", "worker.service", "plain", "db"]
+        );
+
+        let dependencies = parse_avocado_service_dependencies(content);
+        assert_eq!(
+            dependencies.get("nginx.service").unwrap(),
+            &[ServiceDependency::Requires]
+        );
+        assert_eq!(
+            dependencies.get("worker.service").unwrap(),
+            &[ServiceDependency::Wants, ServiceDependency::After]
+        );
+        assert_eq!(
+            dependencies.get("db").unwrap(),
+            &[ServiceDependency::BindsTo]
+        );
+        assert!(!dependencies.contains_key("plain"));
+
+        let db = parse_service_declarations(content)
+            .into_iter()
+            .find(|d| d.service == "db")
+            .unwrap();
+        assert_eq!(db.unknown_dependencies, vec!["later"]);
+    }
+
+    #[test]
+    fn test_parse_hook_commands_by_environment() {
+        let content = r#"
+AVOCADO_ON_MERGE=depmod
+AVOCADO_ON_MERGE_SYSTEM="modprobe vendor_cam"
+AVOCADO_ON_MERGE_INITRD="udevadm trigger"
+AVOCADO_ON_MERGE="[initrd] plymouth update"
+AVOCADO_ON_MERGE="[!initrd] systemctl restart app.service"
+AVOCADO_ON_MERGE="[system|initrd] ldconfig"
+AVOCADO_ON_MERGE_INITRD="[system] never"
+AVOCADO_ON_MERGE="[kiosk] unknown"
+AVOCADO_ON_MERGEX=ignored
+AVOCADO_ON_UNMERGE_SYSTEM="rmmod vendor_cam"
+"#;
+        assert_eq!(
+            parse_hook_commands(content, "AVOCADO_ON_MERGE", HookEnvironment::System),
+            vec![
+                "depmod",
+                "modprobe vendor_cam",
+                "systemctl restart app.service",
+                "ldconfig"
+            ]
+        );
+        assert_eq!(
+            parse_hook_commands(content, "AVOCADO_ON_MERGE", HookEnvironment::Initrd),
+            vec!["depmod", "udevadm trigger", "plymouth update", "ldconfig"]
+        );
+        assert!(
+            parse_hook_commands(content, "AVOCADO_ON_UNMERGE", HookEnvironment::Initrd).is_empty()
+        );
+
+        let unknown: Vec<HookDeclaration> = parse_hook_declarations(content, "AVOCADO_ON_MERGE")
+            .into_iter()
+            .filter(|hook| hook.unknown_condition.is_some())
+            .collect();
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].command, "unknown");
+        assert_eq!(unknown[0].unknown_condition.as_deref(), Some("kiosk"));
+    }
+
+    #[test]
+    fn test_both_merge_and_unmerge_commands() {
+        // Test case with both AVOCADO_ON_MERGE and AVOCADO_ON_UNMERGE commands
+        let content = r#"
+VERSION_ID=1.0
+DESCRIPTION="Extension with both merge and unmerge commands"
+AVOCADO_ON_MERGE="systemctl start service"
+AVOCADO_ON_MERGE=depmod
+AVOCADO_ON_UNMERGE="systemctl stop service"
+OTHER_KEY=value
+"#;
+        let merge_commands = parse_avocado_on_merge_commands(content);
+        let unmerge_commands = parse_avocado_on_unmerge_commands(content);
+
+        assert_eq!(merge_commands, vec!["systemctl start service", "depmod"]);
+        assert_eq!(unmerge_commands, vec!["systemctl stop service"]);
+    }
+}
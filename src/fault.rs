@@ -0,0 +1,74 @@
+//! Failure injection for robustness tests.
+//!
+//! Builds with the `fault-injection` feature accept a hidden global
+//! `--fail-at <step>` flag (or `AVOCADO_FAIL_AT=<step>`) that makes the
+//! named pipeline step fail as if the operation behind it had, so the
+//! rollback and cleanup paths can be exercised by integration tests.
+//! Without the feature the flag does not exist and [`fail_at`] is always
+//! false, so release builds carry no injection points.
+
+use crate::commands::ext::SystemdError;
+
+/// Environment variable naming the step to fail. A daemon started with
+/// `serve --fail-at` injects into the calls made to it through varlink.
+#[cfg(feature = "fault-injection")]
+pub const FAIL_AT_ENV: &str = "AVOCADO_FAIL_AT";
+
+/// Pipeline steps a failure can be injected at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailPoint {
+    /// Right after `systemd-sysext merge` (or `refresh`), before confexts
+    AfterMerge,
+    /// After merging, before the post-merge tasks and on-merge hooks run
+    BeforeHooks,
+    /// Halfway through swapping os-releases links in `ext apply`
+    SymlinkSwap,
+    /// After the first journaled link change of `ext enable`/`disable`,
+    /// exiting without cleanup as if power was lost
+    LinkJournal,
+}
+
+impl FailPoint {
+    #[cfg(feature = "fault-injection")]
+    pub const ALL: [FailPoint; 4] = [
+        FailPoint::AfterMerge,
+        FailPoint::BeforeHooks,
+        FailPoint::SymlinkSwap,
+        FailPoint::LinkJournal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FailPoint::AfterMerge => "after-merge",
+            FailPoint::BeforeHooks => "before-hooks",
+            FailPoint::SymlinkSwap => "symlink-swap",
+            FailPoint::LinkJournal => "link-journal",
+        }
+    }
+}
+
+/// Arm the failure at `step` for this process and the processes it starts.
+#[cfg(feature = "fault-injection")]
+pub fn enable(step: &str) {
+    std::env::set_var(FAIL_AT_ENV, step);
+}
+
+/// Whether a failure is injected at `point`.
+#[cfg(feature = "fault-injection")]
+pub fn fail_at(point: FailPoint) -> bool {
+    std::env::var(FAIL_AT_ENV).is_ok_and(|step| step == point.as_str())
+}
+
+#[cfg(not(feature = "fault-injection"))]
+pub fn fail_at(_point: FailPoint) -> bool {
+    false
+}
+
+/// The error an injected failure at `point` reports.
+pub fn injected(point: FailPoint) -> SystemdError {
+    SystemdError::CommandExitedWithError {
+        command: format!("--fail-at {}", point.as_str()),
+        exit_code: Some(1),
+        stderr: "injected failure".to_string(),
+    }
+}
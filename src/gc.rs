@@ -16,8 +16,9 @@ pub struct GcResult {
 
 /// Run garbage collection: remove old runtimes and unreferenced images.
 ///
-/// Keeps at most `retention` runtimes. The active runtime and any runtime
-/// referenced by `pending-update.json` are always kept regardless of the limit.
+/// Keeps at most `retention` runtimes. The active runtime, any runtime
+/// referenced by `pending-update.json` and the runtime staged by
+/// `ext prefetch` are always kept regardless of the limit.
 pub fn collect_garbage(base_dir: &Path, retention: u32) -> Result<GcResult, StagingError> {
     let retention = retention.max(1) as usize;
     let mut result = GcResult::default();
@@ -47,6 +48,11 @@ pub fn collect_garbage(base_dir: &Path, retention: u32) -> Result<GcResult, Stag
         }
     }
 
+    // Protect the runtime staged by `ext prefetch`
+    if let Some(prefetched) = crate::prefetch::load(base_dir) {
+        protected.insert(prefetched.runtime_id);
+    }
+
     // 3. Build the keep set: iterate sorted list, add until retention reached
     //    list_all is sorted active-first then by built_at DESC, so this
     //    naturally keeps the active runtime and the newest inactive ones.
@@ -103,8 +109,8 @@ fn cleanup_orphaned_images(base_dir: &Path, runtimes: &[(RuntimeManifest, bool)]
     let images_dir = base_dir.join(IMAGES_DIR_NAME);
     let mut removed = Vec::new();
 
-    if let Ok(entries) = fs::read_dir(&images_dir) {
-        for entry in entries.flatten() {
+    if let Ok(entries) = crate::ordering::read_dir_sorted(&images_dir) {
+        for entry in entries {
             let filename = entry.file_name().to_string_lossy().to_string();
             if !referenced.contains(&filename) && fs::remove_file(entry.path()).is_ok() {
                 removed.push(filename);
@@ -137,6 +143,8 @@ mod tests {
                 image_id: Some(image_id.to_string()),
                 image_type: None,
                 sha256: None,
+                sha512: None,
+                blake3: None,
                 enabled: true,
             }],
             os_bundle: None,
@@ -265,6 +273,28 @@ mod tests {
         assert!(!tmp.path().join("runtimes/rt-2").exists());
     }
 
+    #[test]
+    fn test_gc_protects_prefetched_runtime() {
+        let tmp = TempDir::new().unwrap();
+        let m1 = make_manifest("rt-1", "2026-01-01T00:00:00Z", "img-1");
+        let m2 = make_manifest("rt-2", "2026-01-02T00:00:00Z", "img-2");
+        let m3 = make_manifest("rt-3", "2026-01-03T00:00:00Z", "img-3");
+
+        for m in [&m1, &m2, &m3] {
+            write_manifest(tmp.path(), m);
+        }
+        set_active(tmp.path(), "rt-3");
+        crate::prefetch::save(
+            tmp.path(),
+            &crate::prefetch::PrefetchRecord::new("rt-1", "dev", "0.1.0", "http://repo"),
+        )
+        .unwrap();
+
+        let result = collect_garbage(tmp.path(), 1).unwrap();
+        assert_eq!(result.removed_runtimes, vec!["rt-2".to_string()]);
+        assert!(tmp.path().join("runtimes/rt-1").exists());
+    }
+
     #[test]
     fn test_gc_cleans_orphaned_images() {
         let tmp = TempDir::new().unwrap();
@@ -364,6 +394,8 @@ mod tests {
         m1.os_bundle = Some(OsBundleRef {
             image_id: "os-img-1".to_string(),
             sha256: "abc".to_string(),
+            sha512: None,
+            blake3: None,
             os_build_id: None,
             initramfs_build_id: None,
         });
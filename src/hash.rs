@@ -1,20 +1,167 @@
-use sha2::{Digest, Sha256};
+//! Image digests.
+//!
+//! Installing and verifying runtime images hashes files of hundreds of
+//! megabytes, which dominates CPU time on small ARM cores. Digests are
+//! computed by a [`Backend`]: [`Software`] always works, and with the
+//! `kernel-crypto` feature the kernel crypto API can offload SHA-2 to a
+//! hardware engine. BLAKE3 comes from the `blake3` crate with the feature of
+//! that name. A [`Checksummer`] picks the algorithm and backend from
+//! `[avocado.checksum]`, per update repository.
+
+use crate::config::{ChecksumAlgorithm, ChecksumBackend, ChecksumSettings};
+use sha2::{Digest, Sha256, Sha512};
 use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 
-/// Compute SHA256 hash of a file by streaming with 64KB buffers.
-pub fn sha256_file(path: &Path) -> std::io::Result<String> {
-    let mut file = std::fs::File::open(path)?;
-    let mut hasher = Sha256::new();
-    let mut buf = [0u8; 64 * 1024];
-    loop {
-        let n = file.read(&mut buf)?;
-        if n == 0 {
-            break;
+#[cfg(feature = "kernel-crypto")]
+mod af_alg;
+
+/// Incremental digest computation.
+pub trait Hasher {
+    fn update(&mut self, data: &[u8]) -> std::io::Result<()>;
+    fn finish(self: Box<Self>) -> std::io::Result<Vec<u8>>;
+}
+
+/// A digest implementation.
+pub trait Backend {
+    fn name(&self) -> &'static str;
+    /// A hasher for `algorithm`, or `None` when this backend cannot
+    /// compute it.
+    fn hasher(&self, algorithm: ChecksumAlgorithm) -> Option<Box<dyn Hasher>>;
+}
+
+/// In-process implementations. sha2 uses the CPU's SHA instructions where
+/// it detects them (x86 SHA-NI, ARMv8 crypto extensions). BLAKE3 is only
+/// offered by a build with the `blake3` feature.
+pub struct Software;
+
+/// A sha2 hasher.
+struct Sha2<D>(D);
+
+impl<D: Digest> Hasher for Sha2<D> {
+    fn update(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.0.update(data);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> std::io::Result<Vec<u8>> {
+        Ok(self.0.finalize().to_vec())
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) -> std::io::Result<()> {
+        blake3::Hasher::update(self, data);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> std::io::Result<Vec<u8>> {
+        Ok(self.finalize().as_bytes().to_vec())
+    }
+}
+
+impl Backend for Software {
+    fn name(&self) -> &'static str {
+        "software"
+    }
+
+    fn hasher(&self, algorithm: ChecksumAlgorithm) -> Option<Box<dyn Hasher>> {
+        Some(match algorithm {
+            ChecksumAlgorithm::Sha256 => Box::new(Sha2(Sha256::new())),
+            ChecksumAlgorithm::Sha512 => Box::new(Sha2(Sha512::new())),
+            #[cfg(feature = "blake3")]
+            ChecksumAlgorithm::Blake3 => Box::new(blake3::Hasher::new()),
+            #[cfg(not(feature = "blake3"))]
+            ChecksumAlgorithm::Blake3 => return None,
+        })
+    }
+}
+
+/// Digest algorithm and backends used to verify images.
+pub struct Checksummer {
+    algorithm: ChecksumAlgorithm,
+    /// Asked in order; the first offering an algorithm computes it
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Default for Checksummer {
+    fn default() -> Self {
+        Self::new(ChecksumAlgorithm::default(), ChecksumBackend::default())
+    }
+}
+
+impl Checksummer {
+    pub fn new(algorithm: ChecksumAlgorithm, backend: ChecksumBackend) -> Self {
+        let mut backends: Vec<Box<dyn Backend>> = Vec::new();
+        // Without the kernel-crypto feature, `kernel` and `auto` are software
+        if backend != ChecksumBackend::Software {
+            #[cfg(feature = "kernel-crypto")]
+            backends.push(Box::new(af_alg::Kernel));
+        }
+        backends.push(Box::new(Software));
+        Self {
+            algorithm,
+            backends,
         }
-        hasher.update(&buf[..n]);
     }
-    Ok(hex_encode(&hasher.finalize()))
+
+    /// The configured checksummer for images from `repository`, or for
+    /// images of a local manifest.
+    pub fn from_settings(settings: &ChecksumSettings, repository: Option<&str>) -> Self {
+        let (algorithm, backend) = settings.resolve(repository);
+        Self::new(algorithm, backend)
+    }
+
+    /// The digest to verify among those published for an image: the
+    /// preferred algorithm's when this build can compute it, else the
+    /// SHA-256.
+    pub fn pick<'a>(
+        &self,
+        digest: impl Fn(ChecksumAlgorithm) -> Option<&'a str>,
+    ) -> Option<(ChecksumAlgorithm, &'a str)> {
+        [self.algorithm, ChecksumAlgorithm::Sha256]
+            .into_iter()
+            .filter(|&algorithm| Software.hasher(algorithm).is_some())
+            .find_map(|algorithm| Some((algorithm, digest(algorithm)?)))
+    }
+
+    /// Lowercase hex `algorithm` digest of the file at `path`.
+    pub fn file_digest(
+        &self,
+        algorithm: ChecksumAlgorithm,
+        path: &Path,
+    ) -> std::io::Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = self.hasher(algorithm)?;
+        let mut buf = vec![0u8; 256 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n])?;
+        }
+        Ok(hex_encode(&hasher.finish()?))
+    }
+
+    fn hasher(&self, algorithm: ChecksumAlgorithm) -> std::io::Result<Box<dyn Hasher>> {
+        self.backends
+            .iter()
+            .find_map(|backend| backend.hasher(algorithm))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("built without {} support", algorithm.as_str()),
+                )
+            })
+    }
+}
+
+/// Lowercase hex SHA-256 of the file at `path`, computed by the backends
+/// of `checksum`.
+pub fn sha256_file(checksum: &Checksummer, path: &Path) -> std::io::Result<String> {
+    checksum.file_digest(ChecksumAlgorithm::Sha256, path)
 }
 
 /// Compute a fast spot-check hash by hashing the file size, first `spot_size` bytes,
@@ -50,6 +197,17 @@ pub fn hex_encode(bytes: &[u8]) -> String {
     bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
 
+/// Decode a hex string; `None` if it is not valid hex.
+pub fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,7 +219,7 @@ mod tests {
         let mut tmp = NamedTempFile::new().unwrap();
         tmp.write_all(b"hello world").unwrap();
         tmp.flush().unwrap();
-        let hash = sha256_file(tmp.path()).unwrap();
+        let hash = sha256_file(&Checksummer::default(), tmp.path()).unwrap();
         // sha256("hello world") = b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9
         assert_eq!(
             hash,
@@ -72,7 +230,7 @@ mod tests {
     #[test]
     fn test_sha256_file_empty() {
         let tmp = NamedTempFile::new().unwrap();
-        let hash = sha256_file(tmp.path()).unwrap();
+        let hash = sha256_file(&Checksummer::default(), tmp.path()).unwrap();
         // sha256("") = e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855
         assert_eq!(
             hash,
@@ -80,9 +238,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_checksummer_file_digests() {
+        let mut tmp = NamedTempFile::new().unwrap();
+        tmp.write_all(b"hello world").unwrap();
+        tmp.flush().unwrap();
+        for backend in [ChecksumBackend::Software, ChecksumBackend::Auto] {
+            let checksum = Checksummer::new(ChecksumAlgorithm::Sha256, backend);
+            assert_eq!(
+                checksum
+                    .file_digest(ChecksumAlgorithm::Sha256, tmp.path())
+                    .unwrap(),
+                "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+            );
+            assert_eq!(
+                checksum
+                    .file_digest(ChecksumAlgorithm::Sha512, tmp.path())
+                    .unwrap(),
+                "309ecc489c12d6eb4cc40f50c902f2b4d0ed77ee511a7c7a9bcd3ca86d4cd86f989dd35bc5ff499670da34255b45b0cfd830e81f605dcf7dc5542e93ae9cd76f"
+            );
+            #[cfg(feature = "blake3")]
+            assert_eq!(
+                checksum
+                    .file_digest(ChecksumAlgorithm::Blake3, tmp.path())
+                    .unwrap(),
+                "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24"
+            );
+            #[cfg(not(feature = "blake3"))]
+            assert_eq!(
+                checksum
+                    .file_digest(ChecksumAlgorithm::Blake3, tmp.path())
+                    .unwrap_err()
+                    .kind(),
+                std::io::ErrorKind::Unsupported
+            );
+        }
+    }
+
+    #[test]
+    fn test_checksummer_picks_preferred_digest_then_sha256() {
+        let digests = |algorithm| match algorithm {
+            ChecksumAlgorithm::Sha256 => Some("aa"),
+            ChecksumAlgorithm::Blake3 => Some("bb"),
+            ChecksumAlgorithm::Sha512 => None,
+        };
+        let blake3 = Checksummer::new(ChecksumAlgorithm::Blake3, ChecksumBackend::Software);
+        #[cfg(feature = "blake3")]
+        assert_eq!(
+            blake3.pick(digests),
+            Some((ChecksumAlgorithm::Blake3, "bb"))
+        );
+        // A build without BLAKE3 verifies the SHA-256 instead
+        #[cfg(not(feature = "blake3"))]
+        assert_eq!(
+            blake3.pick(digests),
+            Some((ChecksumAlgorithm::Sha256, "aa"))
+        );
+        let sha512 = Checksummer::new(ChecksumAlgorithm::Sha512, ChecksumBackend::Software);
+        assert_eq!(
+            sha512.pick(digests),
+            Some((ChecksumAlgorithm::Sha256, "aa"))
+        );
+        assert_eq!(sha512.pick(|_| None), None);
+    }
+
     #[test]
     fn test_sha256_file_not_found() {
-        let result = sha256_file(Path::new("/nonexistent/file"));
+        let result = sha256_file(&Checksummer::default(), Path::new("/nonexistent/file"));
         assert!(result.is_err());
     }
 
@@ -0,0 +1,153 @@
+//! Kernel crypto API backend (AF_ALG hash sockets).
+//!
+//! The kernel picks its highest-priority driver for an algorithm, so SHA-2
+//! runs on a hardware crypto engine or the kernel's NEON code when the
+//! device has one. Data is written to the socket and the digest read back;
+//! algorithms the kernel does not offer fall through to the next backend.
+
+use super::{Backend, Hasher};
+use crate::config::ChecksumAlgorithm;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+pub struct Kernel;
+
+impl Backend for Kernel {
+    fn name(&self) -> &'static str {
+        "kernel"
+    }
+
+    fn hasher(&self, algorithm: ChecksumAlgorithm) -> Option<Box<dyn Hasher>> {
+        let (name, digest_len) = match algorithm {
+            ChecksumAlgorithm::Sha256 => ("sha256", 32),
+            ChecksumAlgorithm::Sha512 => ("sha512", 64),
+            ChecksumAlgorithm::Blake3 => return None,
+        };
+        AlgHasher::open(name, digest_len)
+            .ok()
+            .map(|hasher| Box::new(hasher) as Box<dyn Hasher>)
+    }
+}
+
+/// An accepted AF_ALG operation socket.
+struct AlgHasher {
+    op: OwnedFd,
+    digest_len: usize,
+}
+
+impl AlgHasher {
+    fn open(name: &str, digest_len: usize) -> io::Result<Self> {
+        // SAFETY: plain socket syscalls; every returned descriptor is owned
+        // by an OwnedFd right away, and the address is a zeroed, fully
+        // initialized sockaddr_alg passed with its own size.
+        unsafe {
+            let fd = libc::socket(libc::AF_ALG, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let tfm = OwnedFd::from_raw_fd(fd);
+
+            let mut addr: libc::sockaddr_alg = std::mem::zeroed();
+            addr.salg_family = libc::AF_ALG as libc::sa_family_t;
+            addr.salg_type[..4].copy_from_slice(b"hash");
+            addr.salg_name[..name.len()].copy_from_slice(name.as_bytes());
+            if libc::bind(
+                tfm.as_raw_fd(),
+                &addr as *const libc::sockaddr_alg as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_alg>() as libc::socklen_t,
+            ) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+
+            let op = libc::accept4(
+                tfm.as_raw_fd(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                libc::SOCK_CLOEXEC,
+            );
+            if op < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                op: OwnedFd::from_raw_fd(op),
+                digest_len,
+            })
+        }
+    }
+}
+
+impl Hasher for AlgHasher {
+    fn update(&mut self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            // SAFETY: `data` is a valid buffer of the given length.
+            let sent = unsafe {
+                libc::send(
+                    self.op.as_raw_fd(),
+                    data.as_ptr().cast(),
+                    data.len(),
+                    libc::MSG_MORE,
+                )
+            };
+            if sent < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            data = &data[sent as usize..];
+        }
+        Ok(())
+    }
+
+    /// Reading the digest finalizes the hash, including one of no data.
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        let mut digest = vec![0u8; self.digest_len];
+        // SAFETY: `digest` is a valid, writable buffer of the given length.
+        let read = unsafe {
+            libc::read(
+                self.op.as_raw_fd(),
+                digest.as_mut_ptr().cast(),
+                digest.len(),
+            )
+        };
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if read as usize != self.digest_len {
+            return Err(io::Error::other(format!(
+                "kernel returned a {read}-byte digest, expected {}",
+                self.digest_len
+            )));
+        }
+        Ok(digest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{hex_encode, Software};
+
+    /// Matches the software digests, when the kernel offers AF_ALG at all.
+    #[test]
+    fn test_kernel_digests_match_software() {
+        let input: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        for algorithm in [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Sha512] {
+            let Some(mut kernel) = Kernel.hasher(algorithm) else {
+                return;
+            };
+            let mut software = Software.hasher(algorithm).unwrap();
+            for piece in input.chunks(70_000) {
+                kernel.update(piece).unwrap();
+                software.update(piece).unwrap();
+            }
+            assert_eq!(
+                hex_encode(&kernel.finish().unwrap()),
+                hex_encode(&software.finish().unwrap())
+            );
+        }
+        assert!(Kernel.hasher(ChecksumAlgorithm::Blake3).is_none());
+    }
+}
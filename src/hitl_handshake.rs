@@ -0,0 +1,282 @@
+//! Handshake with the workstation-side HITL server.
+//!
+//! Before mounting, `hitl mount` can ask the `avocado` development CLI that
+//! exports the NFS shares which protocol it speaks, what it supports and
+//! which extensions it serves. The exchange is one line of JSON each way
+//! over TCP on the server's control port:
+//!
+//! ```text
+//! > {"client":"avocadoctl","version":"0.10.0","protocol":{"min":1,"max":1}}
+//! < {"protocol":1,"server":"avocado","version":"0.21.0",
+//!    "capabilities":["overlay"],"extensions":[{"name":"app","version":"1.0.0"}]}
+//! ```
+//!
+//! The server answers with the protocol it chose from the offered range, or
+//! with `{"error": "..."}`. A server that does not answer, or answers with a
+//! protocol outside the range, runs a tool too old (or too new) for this
+//! avocadoctl, which is reported before any NFS mount is attempted.
+
+use crate::error::HitlError;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Oldest protocol version avocadoctl speaks.
+pub const PROTOCOL_MIN: u32 = 1;
+/// Newest protocol version avocadoctl speaks.
+pub const PROTOCOL_MAX: u32 = 1;
+
+#[derive(Debug, Serialize)]
+struct Hello<'a> {
+    client: &'a str,
+    version: &'a str,
+    protocol: ProtocolRange,
+}
+
+#[derive(Debug, Serialize)]
+struct ProtocolRange {
+    min: u32,
+    max: u32,
+}
+
+/// The server's answer to the handshake.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ServerInfo {
+    /// Protocol chosen by the server; missing from servers predating it
+    #[serde(default)]
+    pub protocol: Option<u32>,
+    /// Name of the serving tool
+    #[serde(default)]
+    pub server: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Features the server supports, such as `overlay`
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Extensions the server exports
+    #[serde(default)]
+    pub extensions: Vec<ExportedExtension>,
+    /// Why the server refused the handshake
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// An extension exported by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ExportedExtension {
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+impl ExportedExtension {
+    fn versioned_name(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}-{version}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+impl ServerInfo {
+    /// `tool version` for messages, as far as the server told.
+    pub fn tool(&self) -> String {
+        let name = self.server.as_deref().unwrap_or("the HITL server");
+        match &self.version {
+            Some(version) => format!("{name} {version}"),
+            None => name.to_string(),
+        }
+    }
+
+    /// Whether `extension`, a name or versioned name, is exported. The error
+    /// says what the server has instead.
+    pub fn check_extension(&self, extension: &str) -> Result<(), String> {
+        if self
+            .extensions
+            .iter()
+            .any(|ext| ext.name == extension || ext.versioned_name() == extension)
+        {
+            return Ok(());
+        }
+        if let Some(other) = self.extensions.iter().find(|ext| {
+            ext.version.is_some()
+                && extension
+                    .strip_prefix(ext.name.as_str())
+                    .is_some_and(|rest| rest.starts_with('-'))
+        }) {
+            return Err(format!(
+                "the server exports {}, not {extension}",
+                other.versioned_name()
+            ));
+        }
+        let exported: Vec<String> = self
+            .extensions
+            .iter()
+            .map(ExportedExtension::versioned_name)
+            .collect();
+        Err(format!(
+            "not exported by the server (exports: {})",
+            if exported.is_empty() {
+                "none".to_string()
+            } else {
+                exported.join(", ")
+            }
+        ))
+    }
+}
+
+/// Run the handshake with `server` on `port`, waiting at most `timeout` to
+/// connect and for each line.
+pub fn handshake(server: &str, port: u16, timeout: Duration) -> Result<ServerInfo, HitlError> {
+    let address = format!("{server}:{port}");
+    let failed = |reason: String| HitlError::Handshake {
+        server: address.clone(),
+        reason,
+    };
+    let host = server.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<_> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| failed(format!("cannot resolve {host}: {e}")))?
+        .collect();
+    let mut last_error = None;
+    let stream = addrs
+        .iter()
+        .find_map(|addr| {
+            TcpStream::connect_timeout(addr, timeout)
+                .map_err(|e| last_error = Some(e))
+                .ok()
+        })
+        .ok_or_else(|| {
+            failed(format!(
+                "no HITL control service answers ({}); the avocado CLI on the workstation may predate the handshake",
+                last_error.map_or_else(|| "no address".to_string(), |e| e.to_string())
+            ))
+        })?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|()| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| failed(e.to_string()))?;
+
+    let hello = Hello {
+        client: "avocadoctl",
+        version: env!("CARGO_PKG_VERSION"),
+        protocol: ProtocolRange {
+            min: PROTOCOL_MIN,
+            max: PROTOCOL_MAX,
+        },
+    };
+    let mut line = serde_json::to_string(&hello).map_err(|e| failed(e.to_string()))?;
+    line.push('\n');
+    (&stream)
+        .write_all(line.as_bytes())
+        .map_err(|e| failed(format!("sending the handshake: {e}")))?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .map_err(|e| failed(format!("waiting for the reply: {e}")))?;
+    parse_reply(&address, &reply)
+}
+
+/// Check the server's reply line against the protocols avocadoctl speaks.
+fn parse_reply(address: &str, reply: &str) -> Result<ServerInfo, HitlError> {
+    let failed = |reason: String| HitlError::Handshake {
+        server: address.to_string(),
+        reason,
+    };
+    if reply.trim().is_empty() {
+        return Err(failed(
+            "the server closed the connection without replying".to_string(),
+        ));
+    }
+    let info: ServerInfo =
+        serde_json::from_str(reply.trim()).map_err(|e| failed(format!("unexpected reply: {e}")))?;
+    if let Some(error) = &info.error {
+        return Err(failed(format!("the server refused: {error}")));
+    }
+    match info.protocol {
+        Some(protocol) if protocol > PROTOCOL_MAX => Err(failed(format!(
+            "{} requires protocol {protocol}, newer than this avocadoctl supports ({PROTOCOL_MAX}); update avocadoctl",
+            info.tool()
+        ))),
+        Some(protocol) if protocol >= PROTOCOL_MIN => Ok(info),
+        _ => Err(HitlError::ServerOutdated {
+            server: address.to_string(),
+            tool: info.tool(),
+            required: PROTOCOL_MIN,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_check_extension_by_name_and_version() {
+        let info: ServerInfo = serde_json::from_str(
+            r#"{"protocol":1,"extensions":[{"name":"app","version":"1.0.0"},{"name":"tools"}]}"#,
+        )
+        .unwrap();
+        assert!(info.check_extension("app").is_ok());
+        assert!(info.check_extension("app-1.0.0").is_ok());
+        assert!(info.check_extension("tools").is_ok());
+        assert_eq!(
+            info.check_extension("app-2.0.0").unwrap_err(),
+            "the server exports app-1.0.0, not app-2.0.0"
+        );
+        assert_eq!(
+            info.check_extension("debug").unwrap_err(),
+            "not exported by the server (exports: app-1.0.0, tools)"
+        );
+    }
+
+    #[test]
+    fn test_parse_reply_rejects_outdated_and_newer_servers() {
+        let info = parse_reply(
+            "dev:12050",
+            r#"{"protocol":1,"server":"avocado","version":"0.21.0","capabilities":["overlay"]}"#,
+        )
+        .unwrap();
+        assert_eq!(info.capabilities, ["overlay"]);
+        assert_eq!(info.tool(), "avocado 0.21.0");
+
+        let outdated =
+            parse_reply("dev:12050", r#"{"server":"avocado","version":"0.9.0"}"#).unwrap_err();
+        assert!(matches!(
+            outdated,
+            HitlError::ServerOutdated { required: 1, .. }
+        ));
+        assert!(outdated.to_string().contains("avocado 0.9.0"), "{outdated}");
+
+        let newer = parse_reply("dev:12050", r#"{"protocol":9}"#).unwrap_err();
+        assert!(newer.to_string().contains("update avocadoctl"), "{newer}");
+
+        let refused = parse_reply("dev:12050", r#"{"error":"busy"}"#).unwrap_err();
+        assert!(refused.to_string().contains("busy"), "{refused}");
+        assert!(parse_reply("dev:12050", "").is_err());
+    }
+
+    #[test]
+    fn test_handshake_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut hello = String::new();
+            BufReader::new(&stream).read_line(&mut hello).unwrap();
+            (&stream)
+                .write_all(b"{\"protocol\":1,\"extensions\":[{\"name\":\"app\"}]}\n")
+                .unwrap();
+            hello
+        });
+
+        let info = handshake("127.0.0.1", port, Duration::from_secs(5)).unwrap();
+        assert!(info.check_extension("app").is_ok());
+        let hello: serde_json::Value = serde_json::from_str(&server.join().unwrap()).unwrap();
+        assert_eq!(hello["client"], "avocadoctl");
+        assert_eq!(hello["protocol"]["min"], PROTOCOL_MIN);
+    }
+}
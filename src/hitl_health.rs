@@ -0,0 +1,490 @@
+//! Reachability monitoring for HITL NFS mounts in `avocadoctl serve`.
+//!
+//! `hitl mount` records the server behind each mounted extension (with the
+//! services it has drop-ins for and its `hitl.toml` overrides) in `hitl-servers.json` next to the HITL
+//! mount directory. The daemon probes each recorded server with a TCP
+//! connect. When a server stays unreachable for longer than
+//! `[avocado.hitl] grace_period_ms`, its extensions are detached: the
+//! extensions are unmerged, the NFS mounts are lazily force-unmounted, the
+//! service drop-ins are removed and the remaining extensions are merged
+//! again, so the versioned copies the HITL mounts were masking come back.
+//! Every state change is appended to `hitl-events.log` as a JSON line.
+
+#[cfg(feature = "daemon")]
+use crate::commands::hitl;
+#[cfg(feature = "daemon")]
+use crate::config::{Config, HitlSettings};
+use crate::hitl_overrides::HitlOverrides;
+#[cfg(feature = "daemon")]
+use crate::output::OutputManager;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+#[cfg(feature = "daemon")]
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+#[cfg(feature = "daemon")]
+use std::process::{Command as ProcessCommand, Stdio};
+use std::sync::Mutex;
+#[cfg(feature = "daemon")]
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "daemon")]
+use std::time::Instant;
+
+/// Registry file (next to the HITL mount directory) listing mounted servers.
+pub const REGISTRY_FILENAME: &str = "hitl-servers.json";
+
+/// Event log file (next to the HITL mount directory).
+#[cfg(feature = "daemon")]
+pub const EVENTS_FILENAME: &str = "hitl-events.log";
+
+/// Port and NFS version that worked per server during this boot (next to
+/// the HITL mount directory).
+pub const TRANSPORTS_FILENAME: &str = "hitl-transports.json";
+
+/// Serializes updates of the state files: `hitl mount` records several
+/// extensions at once.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// How a HITL extension is merged: detected from its tree, or forced by
+/// `hitl mount --type`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MountType {
+    #[default]
+    Auto,
+    Sysext,
+    Confext,
+}
+
+impl MountType {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Self::Auto),
+            "sysext" => Some(Self::Sysext),
+            "confext" => Some(Self::Confext),
+            _ => None,
+        }
+    }
+
+    fn is_auto(&self) -> bool {
+        *self == Self::Auto
+    }
+}
+
+/// A HITL extension mounted from a remote server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HitlMount {
+    pub extension: String,
+    pub server: String,
+    pub port: String,
+    /// Services that got drop-ins when the extension was mounted.
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default, skip_serializing_if = "MountType::is_auto")]
+    pub mount_type: MountType,
+    /// NFS version the mount was made with, when it was negotiated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nfs_version: Option<String>,
+    /// Overrides applied from the extension's `hitl.toml`.
+    #[serde(default, skip_serializing_if = "HitlOverrides::is_empty")]
+    pub overrides: HitlOverrides,
+}
+
+/// NFS port and protocol version a server was mounted with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NfsTransport {
+    pub port: String,
+    pub version: String,
+}
+
+/// HITL mount directory, respecting AVOCADO_TEST_MODE and user mode.
+pub fn hitl_dir() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
+            .or_else(|_| std::env::var("TMPDIR"))
+            .unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/hitl"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path("/run/avocado/hitl"))
+    }
+}
+
+fn state_file(name: &str) -> PathBuf {
+    let dir = hitl_dir();
+    dir.parent().map(|p| p.join(name)).unwrap_or(dir)
+}
+
+/// Load the recorded HITL mounts.
+pub fn load_mounts() -> Vec<HitlMount> {
+    fs::read_to_string(state_file(REGISTRY_FILENAME))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+fn save_mounts(mounts: &[HitlMount]) {
+    let path = state_file(REGISTRY_FILENAME);
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = fs::write(
+        &path,
+        serde_json::to_string_pretty(mounts).unwrap_or_default(),
+    );
+}
+
+/// Record (or replace) the server behind a mounted extension.
+pub fn record_mount(mount: HitlMount) {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut mounts = load_mounts();
+    mounts.retain(|m| m.extension != mount.extension);
+    crate::events::emit(
+        "hitl.mounted",
+        serde_json::json!({
+            "extension": mount.extension,
+            "server": format!("{}:{}", mount.server, mount.port),
+        }),
+    );
+    mounts.push(mount);
+    save_mounts(&mounts);
+}
+
+/// The mount type recorded for a HITL extension (`Auto` when unknown).
+pub fn mount_type(extension: &str) -> MountType {
+    load_mounts()
+        .into_iter()
+        .find(|m| m.extension == extension)
+        .map(|m| m.mount_type)
+        .unwrap_or_default()
+}
+
+/// The `hitl.toml` overrides recorded for a HITL extension.
+pub fn recorded_overrides(extension: &str) -> HitlOverrides {
+    load_mounts()
+        .into_iter()
+        .find(|m| m.extension == extension)
+        .map(|m| m.overrides)
+        .unwrap_or_default()
+}
+
+/// Drop a mounted extension from the registry.
+pub fn forget_mount(extension: &str) {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut mounts = load_mounts();
+    let before = mounts.len();
+    mounts.retain(|m| m.extension != extension);
+    if mounts.len() != before {
+        save_mounts(&mounts);
+        crate::events::emit(
+            "hitl.unmounted",
+            serde_json::json!({ "extension": extension }),
+        );
+    }
+}
+
+fn load_transports() -> BTreeMap<String, NfsTransport> {
+    fs::read_to_string(state_file(TRANSPORTS_FILENAME))
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+/// The transport that last worked for `server` during this boot.
+pub fn remembered_transport(server: &str) -> Option<NfsTransport> {
+    load_transports().remove(server)
+}
+
+/// Remember the transport that worked for `server`, so later mounts from the
+/// same server try it first.
+pub fn remember_transport(server: &str, transport: &NfsTransport) {
+    let _lock = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut transports = load_transports();
+    if transports.get(server) == Some(transport) {
+        return;
+    }
+    transports.insert(server.to_string(), transport.clone());
+    let path = state_file(TRANSPORTS_FILENAME);
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = fs::write(
+        &path,
+        serde_json::to_string_pretty(&transports).unwrap_or_default(),
+    );
+}
+
+/// Append an event to the HITL event log.
+#[cfg(feature = "daemon")]
+pub fn record_event(event: &str, mount: &HitlMount, detail: &str) {
+    let path = state_file(EVENTS_FILENAME);
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let time = crate::clock::now();
+    let line = serde_json::json!({
+        "time": time,
+        "event": event,
+        "extension": mount.extension,
+        "server": format!("{}:{}", mount.server, mount.port),
+        "detail": detail,
+    });
+    crate::events::emit(
+        &format!("hitl.{event}"),
+        serde_json::json!({
+            "extension": mount.extension,
+            "server": format!("{}:{}", mount.server, mount.port),
+            "detail": detail,
+        }),
+    );
+    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(file, "{line}");
+    }
+}
+
+/// Whether `server:port` accepts a TCP connection within `timeout`.
+pub fn probe(server: &str, port: &str, timeout: Duration) -> bool {
+    let host = server.trim_start_matches('[').trim_end_matches(']');
+    let Ok(port) = port.parse::<u16>() else {
+        return false;
+    };
+    let Ok(addrs) = (host, port).to_socket_addrs() else {
+        return false;
+    };
+    addrs
+        .into_iter()
+        .any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok())
+}
+
+/// Reachability transition reported by [`HealthTracker::observe`].
+#[cfg(feature = "daemon")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// Nothing changed.
+    None,
+    /// The server just stopped answering.
+    Unreachable,
+    /// The server answered again within the grace period.
+    Recovered,
+    /// The server has been unreachable for longer than the grace period.
+    Lost,
+}
+
+/// Per-server grace period bookkeeping. Time is passed in explicitly so the
+/// policy can be exercised without sleeping.
+#[cfg(feature = "daemon")]
+#[derive(Debug)]
+pub struct HealthTracker {
+    grace: Duration,
+    down_since: BTreeMap<String, Instant>,
+}
+
+#[cfg(feature = "daemon")]
+impl HealthTracker {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            down_since: BTreeMap::new(),
+        }
+    }
+
+    /// Feed one probe result for `server`. `Lost` is reported once; the
+    /// caller detaches the server's mounts and stops probing it.
+    pub fn observe(&mut self, server: &str, reachable: bool, now: Instant) -> Transition {
+        match (reachable, self.down_since.get(server).copied()) {
+            (true, None) => Transition::None,
+            (true, Some(_)) => {
+                self.down_since.remove(server);
+                Transition::Recovered
+            }
+            (false, None) => {
+                self.down_since.insert(server.to_string(), now);
+                if self.grace.is_zero() {
+                    self.down_since.remove(server);
+                    Transition::Lost
+                } else {
+                    Transition::Unreachable
+                }
+            }
+            (false, Some(since)) if now.duration_since(since) >= self.grace => {
+                self.down_since.remove(server);
+                Transition::Lost
+            }
+            (false, Some(_)) => Transition::None,
+        }
+    }
+}
+
+/// Detach HITL mounts whose server is gone and merge the remaining extensions.
+#[cfg(feature = "daemon")]
+pub fn detach_lost_mounts(lost: &[HitlMount], config: &Config, output: &OutputManager) {
+    if lost.is_empty() {
+        return;
+    }
+
+    let _ = crate::service::ext::unmerge_extensions(false);
+
+    let umount = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        "mock-umount"
+    } else {
+        "umount"
+    };
+    for mount in lost {
+        let mount_point = hitl_dir().join(&mount.extension);
+        // Lazy + force: the server is gone, so a regular unmount would hang
+        // on the same dead NFS handles we are trying to get rid of.
+        let detached = ProcessCommand::new(umount)
+            .args(["-f", "-l"])
+            .arg(&mount_point)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        let _ = fs::remove_dir(&mount_point);
+        let _ = hitl::cleanup_service_dropins(&mount.extension, &mount.services, output);
+        forget_mount(&mount.extension);
+        record_event(
+            "detached",
+            mount,
+            if detached {
+                "unmounted after server loss"
+            } else {
+                "unmount failed after server loss"
+            },
+        );
+    }
+    // The merge reloads systemd for the removed drop-ins
+    match crate::service::ext::merge_extensions(config, None) {
+        Ok(_) => {
+            for mount in lost {
+                record_event("remerged", mount, "merged without HITL mount");
+            }
+        }
+        Err(e) => {
+            crate::daemon_reload::flush_or_warn(output);
+            for mount in lost {
+                record_event("remerge-failed", mount, &e.to_string());
+            }
+        }
+    }
+}
+
+/// Start the HITL health monitor on a background thread when enabled.
+#[cfg(feature = "daemon")]
+pub fn spawn(config: &Config, output: &OutputManager) {
+    let settings: HitlSettings = config.hitl().clone();
+    if !settings.monitor {
+        return;
+    }
+    let config = config.clone();
+    let output = output.detached();
+    thread::spawn(move || {
+        let interval = Duration::from_millis(settings.probe_interval_ms.max(1));
+        let timeout = Duration::from_millis(settings.probe_timeout_ms.max(1));
+        let mut tracker = HealthTracker::new(Duration::from_millis(settings.grace_period_ms));
+        loop {
+            thread::sleep(interval);
+            let mounts = load_mounts();
+            let mut servers: Vec<(String, String)> = mounts
+                .iter()
+                .map(|m| (m.server.clone(), m.port.clone()))
+                .collect();
+            servers.sort();
+            servers.dedup();
+
+            let mut lost = Vec::new();
+            for (server, port) in servers {
+                let key = format!("{server}:{port}");
+                let reachable = probe(&server, &port, timeout);
+                let transition = tracker.observe(&key, reachable, Instant::now());
+                let affected = mounts
+                    .iter()
+                    .filter(|m| m.server == server && m.port == port);
+                match transition {
+                    Transition::None => {}
+                    Transition::Unreachable => {
+                        output.warning(&format!("HITL server {key} unreachable"));
+                        affected.for_each(|m| record_event("unreachable", m, ""));
+                    }
+                    Transition::Recovered => {
+                        output.log_info(&format!("HITL server {key} reachable again"));
+                        affected.for_each(|m| record_event("recovered", m, ""));
+                    }
+                    Transition::Lost => {
+                        output.warning(&format!(
+                            "HITL server {key} lost for more than {}ms, detaching its extensions",
+                            settings.grace_period_ms
+                        ));
+                        for m in affected {
+                            record_event("lost", m, "");
+                            lost.push(m.clone());
+                        }
+                    }
+                }
+            }
+            detach_lost_mounts(&lost, &config, &output);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "daemon")]
+    fn test_tracker_waits_for_grace_period() {
+        let mut tracker = HealthTracker::new(Duration::from_secs(10));
+        let t0 = Instant::now();
+        assert_eq!(tracker.observe("s", true, t0), Transition::None);
+        assert_eq!(tracker.observe("s", false, t0), Transition::Unreachable);
+        assert_eq!(
+            tracker.observe("s", false, t0 + Duration::from_secs(5)),
+            Transition::None
+        );
+        assert_eq!(
+            tracker.observe("s", true, t0 + Duration::from_secs(6)),
+            Transition::Recovered
+        );
+
+        assert_eq!(
+            tracker.observe("s", false, t0 + Duration::from_secs(20)),
+            Transition::Unreachable
+        );
+        assert_eq!(
+            tracker.observe("s", false, t0 + Duration::from_secs(30)),
+            Transition::Lost
+        );
+        // After a loss the next failure starts a fresh grace period
+        assert_eq!(
+            tracker.observe("s", false, t0 + Duration::from_secs(31)),
+            Transition::Unreachable
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "daemon")]
+    fn test_tracker_zero_grace_is_immediate() {
+        let mut tracker = HealthTracker::new(Duration::ZERO);
+        assert_eq!(
+            tracker.observe("s", false, Instant::now()),
+            Transition::Lost
+        );
+    }
+
+    #[test]
+    fn test_probe_detects_listening_and_closed_ports() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        assert!(probe("127.0.0.1", &port, Duration::from_secs(1)));
+        drop(listener);
+        assert!(!probe("127.0.0.1", &port, Duration::from_millis(200)));
+        assert!(!probe(
+            "127.0.0.1",
+            "not-a-port",
+            Duration::from_millis(200)
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+//! Extension-specific HITL overrides from `hitl.toml`.
+//!
+//! A developer can put a `hitl.toml` at the root of the NFS share of an
+//! extension to carry dev-mode tweaks with the extension instead of in
+//! scripts on the device:
+//!
+//! ```toml
+//! mount_options = ["noac", "rsize=1048576"]   # remounted with these
+//! restart = ["app.service"]                    # restarted after merge
+//!
+//! [environment]                                # added to the drop-ins
+//! APP_LOG_LEVEL = "debug"
+//! ```
+//!
+//! `hitl mount` reads the file once the share is mounted, remounts it with
+//! the extra options, adds the environment to the service drop-ins and
+//! restarts the services once the extensions are refreshed. The overrides
+//! are recorded with the mount (see [`crate::hitl_health`]) so `hitl
+//! unmount` restarts the same services after the share is gone.
+
+use crate::commands::hitl::HitlError;
+use crate::diagnostics::Diagnose;
+use crate::output::OutputManager;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command as ProcessCommand;
+
+/// File read from the root of a mounted HITL share.
+pub const OVERRIDES_FILENAME: &str = "hitl.toml";
+
+/// Mount options avocadoctl sets itself from the negotiated transport.
+const RESERVED_OPTIONS: &[&str] = &["port", "vers", "nfsvers"];
+
+/// Overrides an extension declares in its `hitl.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HitlOverrides {
+    /// NFS mount options added to the defaults, one option per entry
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mount_options: Vec<String>,
+    /// Services restarted after the extension is merged and after it is
+    /// unmounted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub restart: Vec<String>,
+    /// Environment variables set in the service drop-ins
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+}
+
+impl HitlOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.mount_options.is_empty() && self.restart.is_empty() && self.environment.is_empty()
+    }
+
+    /// Read `hitl.toml` from the share mounted at `mount_point`. No file
+    /// means no overrides.
+    pub fn load(extension: &str, mount_point: &Path) -> Result<Self, HitlError> {
+        let path = mount_point.join(OVERRIDES_FILENAME);
+        let invalid = |reason: String| HitlError::Overrides {
+            extension: extension.to_string(),
+            reason,
+        };
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(invalid(format!("failed to read {}: {e}", path.display()))),
+        };
+        let overrides: Self = toml::from_str(&content).map_err(|e| invalid(e.message().into()))?;
+        overrides.validate().map_err(invalid)?;
+        Ok(overrides)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for option in &self.mount_options {
+            if option.is_empty() || option.contains(',') || option.contains(char::is_whitespace) {
+                return Err(format!(
+                    "mount option '{option}' must be a single option without commas or spaces"
+                ));
+            }
+            let name = option.split('=').next().unwrap_or_default();
+            if RESERVED_OPTIONS.contains(&name) {
+                return Err(format!(
+                    "mount option '{name}' is negotiated by avocadoctl and cannot be overridden"
+                ));
+            }
+        }
+        for service in &self.restart {
+            if service.is_empty() || service.contains(['/', ' ']) {
+                return Err(format!("invalid service name '{service}'"));
+            }
+        }
+        for (name, value) in &self.environment {
+            let valid_name = name
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid_name {
+                return Err(format!("invalid environment variable name '{name}'"));
+            }
+            if value.contains('\n') {
+                return Err(format!("environment variable {name} spans several lines"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The `[Service]` section appended to each drop-in, if any.
+    pub fn dropin_section(&self) -> Option<String> {
+        if self.environment.is_empty() {
+            return None;
+        }
+        let mut section = String::from("[Service]\n");
+        for (name, value) in &self.environment {
+            // Quoted for systemd: backslashes and quotes escaped, `%`
+            // doubled so it is not read as a specifier
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('%', "%%");
+            section.push_str(&format!("Environment=\"{name}={value}\"\n"));
+        }
+        Some(section)
+    }
+
+    /// Service units to restart, with `.service` added where missing.
+    pub fn restart_units(&self) -> Vec<String> {
+        self.restart
+            .iter()
+            .map(|s| {
+                if s.contains('.') {
+                    s.clone()
+                } else {
+                    format!("{s}.service")
+                }
+            })
+            .collect()
+    }
+}
+
+/// The overrides of the share mounted at `mount_point`. An invalid
+/// `hitl.toml` is reported and ignored, so the extension is still mounted.
+pub fn load_or_warn(extension: &str, mount_point: &str, output: &OutputManager) -> HitlOverrides {
+    match HitlOverrides::load(extension, Path::new(mount_point)) {
+        Ok(overrides) => {
+            if !overrides.is_empty() {
+                output.info(
+                    "HITL Overrides",
+                    &format!("Applying {OVERRIDES_FILENAME} of extension {extension}"),
+                );
+            }
+            overrides
+        }
+        Err(e) => {
+            output.error_with("HITL Overrides", &e.to_string(), &e.diagnose());
+            HitlOverrides::default()
+        }
+    }
+}
+
+/// Restart `units` with `systemctl try-restart`, so services that are not
+/// running stay stopped.
+pub fn restart_services(units: &[String], output: &OutputManager) -> Result<(), HitlError> {
+    if units.is_empty() {
+        return Ok(());
+    }
+    output.step(
+        "HITL Overrides",
+        &format!("Restarting {}", units.join(", ")),
+    );
+
+    let mut args = vec!["try-restart"];
+    args.extend(units.iter().map(String::as_str));
+    if let Some(result) = crate::backend::simulate("systemctl", &args) {
+        return result.map(|_| ()).map_err(|e| HitlError::Restart {
+            units: units.join(" "),
+            error: e.to_string(),
+        });
+    }
+    let command = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        "mock-systemctl"
+    } else {
+        "systemctl"
+    };
+    let result = ProcessCommand::new(command)
+        .args(&args)
+        .output()
+        .map_err(|e| HitlError::Command {
+            command: format!("{command} try-restart"),
+            source: e,
+        })?;
+    if !result.status.success() {
+        return Err(HitlError::Restart {
+            units: units.join(" "),
+            error: String::from_utf8_lossy(&result.stderr).trim().to_string(),
+        });
+    }
+    output.progress(&String::from_utf8_lossy(&result.stdout));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_validate_overrides() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        assert!(HitlOverrides::load("app", tmp.path()).unwrap().is_empty());
+
+        std::fs::write(
+            tmp.path().join(OVERRIDES_FILENAME),
+            "mount_options = [\"noac\"]\nrestart = [\"app\"]\n\n[environment]\nAPP_OPTS = 'say \"hi\" at 100%'\n",
+        )
+        .unwrap();
+        let overrides = HitlOverrides::load("app", tmp.path()).unwrap();
+        assert_eq!(overrides.mount_options, ["noac"]);
+        assert_eq!(overrides.restart_units(), ["app.service"]);
+        assert_eq!(
+            overrides.dropin_section().unwrap(),
+            "[Service]\nEnvironment=\"APP_OPTS=say \\\"hi\\\" at 100%%\"\n"
+        );
+
+        for invalid in [
+            "mount_options = [\"vers=3\"]",
+            "mount_options = [\"noac,ro\"]",
+            "[environment]\n\"1X\" = \"y\"",
+            "restart_services = [\"app\"]",
+        ] {
+            std::fs::write(tmp.path().join(OVERRIDES_FILENAME), invalid).unwrap();
+            assert!(
+                matches!(
+                    HitlOverrides::load("app", tmp.path()),
+                    Err(HitlError::Overrides { .. })
+                ),
+                "{invalid}"
+            );
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Holding refreshes while a HITL extension is being synced.
+//!
+//! A developer syncing a new build into a HITL-mounted extension can hold
+//! refreshes with `hitl quiesce <name>` and release them with `hitl resume
+//! <name>`; the held extensions are listed in `hitl-quiesced.json` next to
+//! the HITL mount directory. With `[avocado.hitl] detect_sync` (the
+//! default), an extension whose tree holds rsync temporary files is treated
+//! the same way, so an unannounced rsync also holds refreshes until it
+//! finishes.
+//!
+//! `refresh` waits up to `sync_wait_ms` for the held extensions and fails
+//! if they are still held by then; auto-refresh in `avocadoctl serve`
+//! postpones its refresh instead of waiting.
+
+use crate::commands::ext::SystemdError;
+use crate::config::HitlSettings;
+use crate::output::OutputManager;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Quiesced extensions file (next to the HITL mount directory).
+pub const QUIESCED_FILENAME: &str = "hitl-quiesced.json";
+
+/// How often a waiting refresh checks again.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// An extension held by `hitl quiesce`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quiesced {
+    pub extension: String,
+    /// Seconds since the Unix epoch
+    pub since: u64,
+}
+
+fn quiesced_path() -> PathBuf {
+    let dir = crate::hitl_health::hitl_dir();
+    dir.parent()
+        .map(|p| p.join(QUIESCED_FILENAME))
+        .unwrap_or(dir)
+}
+
+/// The extensions currently held by `hitl quiesce`.
+pub fn load_quiesced() -> Vec<Quiesced> {
+    fs::read_to_string(quiesced_path())
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+fn save_quiesced(quiesced: &[Quiesced]) -> std::io::Result<()> {
+    let path = quiesced_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(
+        path,
+        serde_json::to_string_pretty(quiesced).unwrap_or_default(),
+    )
+}
+
+/// Hold refreshes for `extension`. Returns false if it was already held.
+pub fn quiesce(extension: &str) -> std::io::Result<bool> {
+    if extension.is_empty() || extension.contains('/') {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid extension name '{extension}'"),
+        ));
+    }
+    let mut quiesced = load_quiesced();
+    if quiesced.iter().any(|q| q.extension == extension) {
+        return Ok(false);
+    }
+    quiesced.push(Quiesced {
+        extension: extension.to_string(),
+        since: crate::clock::now(),
+    });
+    save_quiesced(&quiesced)?;
+    Ok(true)
+}
+
+/// Release `extension`. Returns false if it was not held.
+pub fn resume(extension: &str) -> std::io::Result<bool> {
+    let mut quiesced = load_quiesced();
+    let before = quiesced.len();
+    quiesced.retain(|q| q.extension != extension);
+    if quiesced.len() == before {
+        return Ok(false);
+    }
+    save_quiesced(&quiesced)?;
+    Ok(true)
+}
+
+/// Whether `name` is a temporary file rsync writes while copying: the
+/// default `.<file>.XXXXXX`, or the `.~tmp~` directory of `--delay-updates`.
+pub fn is_rsync_temp(name: &str) -> bool {
+    if name == ".~tmp~" {
+        return true;
+    }
+    let Some(rest) = name.strip_prefix('.') else {
+        return false;
+    };
+    match rest.rsplit_once('.') {
+        Some((file, suffix)) => {
+            !file.is_empty()
+                && suffix.len() == 6
+                && suffix.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+        None => false,
+    }
+}
+
+/// The first rsync temporary file below `dir`, if any.
+fn find_rsync_temp(dir: &Path) -> Option<PathBuf> {
+    let entries = crate::ordering::read_dir_sorted(dir).ok()?;
+    for entry in entries {
+        let path = entry.path();
+        if is_rsync_temp(&entry.file_name().to_string_lossy()) {
+            return Some(path);
+        }
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+        if is_dir {
+            if let Some(found) = find_rsync_temp(&path) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+/// HITL extensions a refresh should wait for, each with the reason.
+pub fn busy_extensions(settings: &HitlSettings) -> Vec<(String, String)> {
+    let mut busy: Vec<(String, String)> = load_quiesced()
+        .into_iter()
+        .map(|q| (q.extension, "quiesced".to_string()))
+        .collect();
+    if settings.detect_sync {
+        let hitl_dir = crate::hitl_health::hitl_dir();
+        for mount in crate::hitl_health::load_mounts() {
+            if busy.iter().any(|(name, _)| *name == mount.extension) {
+                continue;
+            }
+            let root = hitl_dir.join(&mount.extension);
+            if let Some(temp) = find_rsync_temp(&root) {
+                let relative = temp.strip_prefix(&root).unwrap_or(&temp);
+                busy.push((
+                    mount.extension,
+                    format!("sync in progress ({})", relative.display()),
+                ));
+            }
+        }
+    }
+    busy
+}
+
+fn describe(busy: &[(String, String)]) -> String {
+    busy.iter()
+        .map(|(name, reason)| format!("{name} ({reason})"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Wait until no HITL extension is held, for at most `sync_wait_ms`.
+pub fn wait_until_idle(
+    settings: &HitlSettings,
+    output: &OutputManager,
+) -> Result<(), SystemdError> {
+    let mut busy = busy_extensions(settings);
+    if busy.is_empty() {
+        return Ok(());
+    }
+    output.log_info(&format!(
+        "Waiting for HITL extensions to finish syncing: {}",
+        describe(&busy)
+    ));
+    let deadline = Instant::now() + Duration::from_millis(settings.sync_wait_ms);
+    while Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+        busy = busy_extensions(settings);
+        if busy.is_empty() {
+            output.log_info("HITL sync finished; continuing");
+            return Ok(());
+        }
+    }
+    Err(SystemdError::HitlSyncInProgress {
+        extensions: busy.into_iter().map(|(name, _)| name).collect(),
+        waited_ms: settings.sync_wait_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_rsync_temp() {
+        assert!(is_rsync_temp(".app.Xy12Ab"));
+        assert!(is_rsync_temp(".libfoo.so.1.aB3dE9"));
+        assert!(is_rsync_temp(".~tmp~"));
+        assert!(!is_rsync_temp("app.Xy12Ab"));
+        assert!(!is_rsync_temp(".gitignore"));
+        assert!(!is_rsync_temp(".config.toml"));
+        assert!(!is_rsync_temp("..Xy12Ab"));
+    }
+}
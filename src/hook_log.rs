@@ -0,0 +1,443 @@
+//! Recorded output of AVOCADO_ON_MERGE / AVOCADO_ON_UNMERGE commands.
+//!
+//! Every hook run appends one JSON line (command, exit code, full stdout and
+//! stderr) to `/var/log/avocado/hooks/<extension>.log` for each extension
+//! declaring the command, so a failure reported as one line during a merge
+//! can be replayed in full later with `ext info <extension> --replay` or
+//! `avocadoctl logs`. Logs live on flash on most devices, so they are
+//! bounded: `[avocado.logs]` sets the size and age at which a log is rotated
+//! to `<extension>.log.1` (older files shift to `.2`, ...), how many rotated
+//! files are kept and when they expire.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+/// Directory holding avocadoctl's logs.
+pub const LOG_DIR: &str = "/var/log/avocado";
+
+/// Environment variable overriding `max_size_kb`.
+pub const MAX_SIZE_ENV: &str = "AVOCADO_LOG_MAX_SIZE_KB";
+/// Environment variable overriding `keep`.
+pub const KEEP_ENV: &str = "AVOCADO_LOG_KEEP";
+/// Environment variable overriding `max_age_days`.
+pub const MAX_AGE_ENV: &str = "AVOCADO_LOG_MAX_AGE_DAYS";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// When logs are rotated and for how long they are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    /// Size at which a log is rotated.
+    pub max_bytes: u64,
+    /// Rotated files kept per log.
+    pub keep: usize,
+    /// Age at which records are rotated out and rotated files deleted;
+    /// `None` when disabled.
+    pub max_age_secs: Option<u64>,
+}
+
+impl Rotation {
+    /// The limits of this process's settings.
+    pub fn current() -> Self {
+        let settings = crate::settings::current();
+        let logs = &settings.logs;
+        Self {
+            max_bytes: logs.max_size_kb.saturating_mul(1024),
+            keep: logs.keep,
+            max_age_secs: (logs.max_age_days > 0).then(|| logs.max_age_days * SECONDS_PER_DAY),
+        }
+    }
+}
+
+/// Log name used for commands no enabled extension could be matched to.
+pub const UNATTRIBUTED: &str = "unattributed";
+
+/// One recorded hook command run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// `on-merge` or `on-unmerge`.
+    pub phase: String,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookRecord {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// `ok`, `exit <code>` or `killed`.
+    pub fn status(&self) -> String {
+        if self.succeeded() {
+            "ok".to_string()
+        } else {
+            self.exit_code
+                .map_or_else(|| "killed".to_string(), |code| format!("exit {code}"))
+        }
+    }
+}
+
+/// Directory holding the per-extension hook logs.
+pub fn hooks_dir() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/log/hooks"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path(LOG_DIR)).join("hooks")
+    }
+}
+
+/// Hook log of an extension.
+pub fn log_path(extension: &str) -> PathBuf {
+    hooks_dir().join(format!("{}.log", extension.replace('/', "_")))
+}
+
+fn rotated_path(log: &Path, index: usize) -> PathBuf {
+    let mut path = log.as_os_str().to_owned();
+    path.push(format!(".{index}"));
+    PathBuf::from(path)
+}
+
+/// Rotated files of `log` that exist, newest (`.1`) first.
+fn rotated_files(log: &Path) -> Vec<(usize, PathBuf)> {
+    let (Some(dir), Some(name)) = (log.parent(), log.file_name()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let mut rotated: Vec<(usize, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let index = file_name.strip_prefix(&prefix)?.parse().ok()?;
+            Some((index, entry.path()))
+        })
+        .collect();
+    rotated.sort();
+    rotated
+}
+
+/// Shift `log` to `.1`, `.1` to `.2`, ..., dropping files beyond `keep`.
+fn rotate(log: &Path, keep: usize) {
+    for (index, path) in rotated_files(log).into_iter().rev() {
+        if index >= keep {
+            let _ = fs::remove_file(path);
+        } else {
+            let _ = fs::rename(path, rotated_path(log, index + 1));
+        }
+    }
+    if keep == 0 {
+        let _ = fs::remove_file(log);
+    } else {
+        let _ = fs::rename(log, rotated_path(log, 1));
+    }
+}
+
+/// Timestamp of the first record in `log`.
+fn oldest_timestamp(log: &Path) -> Option<u64> {
+    let mut first = String::new();
+    BufReader::new(fs::File::open(log).ok()?)
+        .read_line(&mut first)
+        .ok()?;
+    serde_json::from_str::<HookRecord>(&first)
+        .ok()
+        .map(|r| r.timestamp)
+}
+
+/// Whether `log` is due for rotation at `now` (seconds since the epoch).
+fn needs_rotation(log: &Path, rotation: &Rotation, now: u64) -> bool {
+    let Ok(metadata) = fs::metadata(log) else {
+        return false;
+    };
+    metadata.len() >= rotation.max_bytes
+        || rotation.max_age_secs.is_some_and(|max_age| {
+            oldest_timestamp(log).is_some_and(|oldest| now.saturating_sub(oldest) > max_age)
+        })
+}
+
+/// Delete the rotated files of `log` whose newest record is older than the
+/// age limit.
+fn expire(log: &Path, rotation: &Rotation, now: u64) {
+    let Some(max_age) = rotation.max_age_secs else {
+        return;
+    };
+    for (_, path) in rotated_files(log) {
+        if modified_secs(&path).is_some_and(|modified| now.saturating_sub(modified) > max_age) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn modified_secs(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Append a hook run to the log of every extension in `extensions` (or the
+/// unattributed log when empty). Returns the logs written. Logging is best
+/// effort and never fails the hook itself.
+pub fn record(extensions: &[String], phase: &str, command: &str, output: &Output) -> Vec<PathBuf> {
+    let record = HookRecord {
+        timestamp: crate::clock::now(),
+        phase: phase.to_string(),
+        command: command.to_string(),
+        exit_code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    };
+    crate::events::emit(
+        "hook.finished",
+        serde_json::json!({
+            "extensions": extensions,
+            "phase": phase,
+            "command": command,
+            "exit_code": record.exit_code,
+        }),
+    );
+    let Ok(line) = serde_json::to_string(&record) else {
+        return Vec::new();
+    };
+    if fs::create_dir_all(hooks_dir()).is_err() {
+        return Vec::new();
+    }
+
+    let fallback = [UNATTRIBUTED.to_string()];
+    let owners = if extensions.is_empty() {
+        &fallback[..]
+    } else {
+        extensions
+    };
+    let rotation = Rotation::current();
+    let mut written = Vec::new();
+    for extension in owners {
+        let path = log_path(extension);
+        if needs_rotation(&path, &rotation, record.timestamp) {
+            rotate(&path, rotation.keep);
+        }
+        expire(&path, &rotation, record.timestamp);
+        let appended = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{line}"));
+        if appended.is_ok() {
+            written.push(path);
+        }
+    }
+    written
+}
+
+/// Recorded runs for an extension, oldest first, across rotated logs.
+pub fn read_records(extension: &str) -> Vec<HookRecord> {
+    let log = log_path(extension);
+    let mut paths: Vec<PathBuf> = rotated_files(&log)
+        .into_iter()
+        .rev()
+        .map(|(_, path)| path)
+        .collect();
+    paths.push(log);
+    paths
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| parse_records(&content))
+        .collect()
+}
+
+/// Records in log content; lines that don't parse are skipped.
+pub fn parse_records(content: &str) -> Vec<HookRecord> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Size and span of one extension's logs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogSummary {
+    /// Extension the log belongs to, or [`UNATTRIBUTED`].
+    pub name: String,
+    /// The log and its rotated files.
+    pub files: usize,
+    pub bytes: u64,
+    pub records: usize,
+    /// Timestamps of the oldest and newest record.
+    pub oldest: Option<u64>,
+    pub newest: Option<u64>,
+}
+
+/// Every hook log, by name.
+pub fn list() -> Vec<LogSummary> {
+    let names: std::collections::BTreeSet<String> = fs::read_dir(hooks_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let (stem, suffix) = file_name.rsplit_once(".log")?;
+            let rotated = suffix
+                .strip_prefix('.')
+                .is_some_and(|index| index.parse::<usize>().is_ok());
+            (suffix.is_empty() || rotated).then(|| stem.to_string())
+        })
+        .collect();
+    names
+        .into_iter()
+        .map(|name| {
+            let log = log_path(&name);
+            let files: Vec<PathBuf> = std::iter::once(log.clone())
+                .filter(|path| path.exists())
+                .chain(rotated_files(&log).into_iter().map(|(_, path)| path))
+                .collect();
+            let records = read_records(&name);
+            LogSummary {
+                files: files.len(),
+                bytes: files
+                    .iter()
+                    .filter_map(|path| fs::metadata(path).ok())
+                    .map(|m| m.len())
+                    .sum(),
+                records: records.len(),
+                oldest: records.first().map(|r| r.timestamp),
+                newest: records.last().map(|r| r.timestamp),
+                name,
+            }
+        })
+        .collect()
+}
+
+/// Delete hook logs: those of `extension`, or every log when `None`. With
+/// `older_than` (seconds), only files whose newest record is older go.
+/// Returns the files deleted.
+pub fn purge(extension: Option<&str>, older_than: Option<u64>) -> io::Result<Vec<PathBuf>> {
+    let names: Vec<String> = match extension {
+        Some(name) => vec![name.to_string()],
+        None => list().into_iter().map(|summary| summary.name).collect(),
+    };
+    let now = crate::clock::now();
+    let mut removed = Vec::new();
+    for name in names {
+        let log = log_path(&name);
+        let files = std::iter::once(log.clone())
+            .chain(rotated_files(&log).into_iter().map(|(_, path)| path));
+        for path in files {
+            let expired = match older_than {
+                Some(age) => {
+                    modified_secs(&path).is_some_and(|modified| now.saturating_sub(modified) > age)
+                }
+                None => true,
+            };
+            if !expired {
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => removed.push(path),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Last non-empty line of a command's stderr, for one-line failure reports.
+pub fn stderr_summary(stderr: &str) -> &str {
+    stderr
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stderr_summary_uses_last_line() {
+        assert_eq!(stderr_summary("warning: one\nerror: two\n\n"), "error: two");
+        assert_eq!(stderr_summary(""), "");
+    }
+
+    #[test]
+    fn test_rotated_path_appends_index() {
+        assert!(rotated_path(&log_path("app-1.0"), 2)
+            .to_string_lossy()
+            .ends_with("hooks/app-1.0.log.2"));
+    }
+
+    fn write_records(path: &Path, timestamps: &[u64]) {
+        let lines: String = timestamps
+            .iter()
+            .map(|&timestamp| {
+                let record = HookRecord {
+                    timestamp,
+                    phase: "on-merge".to_string(),
+                    command: "true".to_string(),
+                    exit_code: Some(0),
+                    stdout: String::new(),
+                    stderr: String::new(),
+                };
+                serde_json::to_string(&record).unwrap() + "\n"
+            })
+            .collect();
+        fs::write(path, lines).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_keeps_configured_number_of_files() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let log = tmp.path().join("app.log");
+        for round in 0..4 {
+            write_records(&log, &[round]);
+            rotate(&log, 2);
+        }
+        let rotated: Vec<usize> = rotated_files(&log).iter().map(|(i, _)| *i).collect();
+        assert_eq!(rotated, [1, 2]);
+        assert!(!log.exists());
+        let newest = fs::read_to_string(rotated_path(&log, 1)).unwrap();
+        assert_eq!(parse_records(&newest)[0].timestamp, 3);
+
+        // Lowering keep drops the files beyond it
+        write_records(&log, &[4]);
+        rotate(&log, 1);
+        assert_eq!(rotated_files(&log).len(), 1);
+        write_records(&log, &[5]);
+        rotate(&log, 0);
+        assert!(rotated_files(&log).is_empty());
+        assert!(!log.exists());
+    }
+
+    #[test]
+    fn test_needs_rotation_by_size_and_age() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let log = tmp.path().join("app.log");
+        let rotation = Rotation {
+            max_bytes: 1024 * 1024,
+            keep: 3,
+            max_age_secs: Some(SECONDS_PER_DAY),
+        };
+        assert!(!needs_rotation(&log, &rotation, 0));
+        write_records(&log, &[1_000_000, 1_000_100]);
+        assert!(!needs_rotation(&log, &rotation, 1_000_200));
+        assert!(needs_rotation(&log, &rotation, 1_000_001 + SECONDS_PER_DAY));
+        let small = Rotation {
+            max_bytes: 10,
+            max_age_secs: None,
+            ..rotation
+        };
+        assert!(needs_rotation(&log, &small, 1_000_200));
+    }
+}
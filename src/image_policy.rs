@@ -0,0 +1,117 @@
+//! Image policy, verity and noexec options for systemd-dissect and
+//! systemd-sysext / systemd-confext.
+//!
+//! `[avocado.ext] image_policy`, `verity` and `noexec` apply one
+//! device-wide policy to image mounts during extension scanning and to the
+//! merge commands. The variables below override the configuration file (see
+//! [`crate::settings`]).
+
+use crate::config::VerityMode;
+
+/// Environment variable overriding the systemd image policy.
+pub const IMAGE_POLICY_ENV: &str = "AVOCADO_IMAGE_POLICY";
+
+/// Environment variable overriding the verity mode (enforce, warn or off).
+pub const VERITY_ENV: &str = "AVOCADO_VERITY";
+
+/// Environment variable overriding the merge noexec setting (yes or no).
+pub const NOEXEC_ENV: &str = "AVOCADO_NOEXEC";
+
+/// Policy requiring verity (signed or not) on root and usr partitions.
+pub const VERITY_POLICY: &str = "root=verity+signed+absent:usr=verity+signed+absent";
+
+/// Policy using root and usr partitions without verity.
+pub const UNPROTECTED_POLICY: &str = "root=unprotected+absent:usr=unprotected+absent";
+
+/// The policy implied by an explicit policy and a verity mode. An explicit
+/// policy always wins; `None` leaves systemd's default in place.
+fn policy_for(explicit: Option<&str>, verity: Option<VerityMode>) -> Option<String> {
+    if let Some(policy) = explicit.filter(|p| !p.is_empty()) {
+        return Some(policy.to_string());
+    }
+    match verity? {
+        VerityMode::Enforce | VerityMode::Warn => Some(VERITY_POLICY.to_string()),
+        VerityMode::Off => Some(UNPROTECTED_POLICY.to_string()),
+    }
+}
+
+/// Policy to mount extension images with, if any.
+pub fn mount_policy() -> Option<String> {
+    let settings = crate::settings::current();
+    policy_for(settings.image_policy.as_deref(), settings.verity)
+}
+
+/// Whether an image rejected by [`mount_policy`] may be mounted without it
+/// (verity = warn).
+pub fn allows_fallback() -> bool {
+    crate::settings::current().verity == Some(VerityMode::Warn)
+}
+
+/// `--image-policy=` argument for systemd-dissect, if a policy applies.
+pub fn dissect_args() -> Vec<String> {
+    mount_policy()
+        .map(|policy| vec![format!("--image-policy={policy}")])
+        .unwrap_or_default()
+}
+
+fn merge_args_for(policy: Option<String>, warn: bool, noexec: Option<&str>) -> Vec<String> {
+    let mut args = Vec::new();
+    // In warn mode images may legitimately fail the policy; don't let the
+    // merge reject them after the mount already fell back.
+    if let Some(policy) = policy.filter(|_| !warn) {
+        args.push(format!("--image-policy={policy}"));
+    }
+    if let Some(noexec) = noexec {
+        args.push(format!("--noexec={noexec}"));
+    }
+    args
+}
+
+/// Extra arguments for `systemd-sysext merge` / `systemd-confext merge`.
+pub fn merge_args() -> Vec<String> {
+    merge_args_for(
+        mount_policy(),
+        allows_fallback(),
+        crate::settings::current().noexec.as_deref(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_for() {
+        assert_eq!(policy_for(None, None), None);
+        assert_eq!(
+            policy_for(None, Some(VerityMode::Enforce)).as_deref(),
+            Some(VERITY_POLICY)
+        );
+        assert_eq!(
+            policy_for(None, Some(VerityMode::Warn)).as_deref(),
+            Some(VERITY_POLICY)
+        );
+        assert_eq!(
+            policy_for(None, Some(VerityMode::Off)).as_deref(),
+            Some(UNPROTECTED_POLICY)
+        );
+        assert_eq!(
+            policy_for(Some("usr=signed"), Some(VerityMode::Off)).as_deref(),
+            Some("usr=signed")
+        );
+        assert_eq!(policy_for(Some(""), None), None);
+    }
+
+    #[test]
+    fn test_merge_args_for() {
+        assert!(merge_args_for(None, false, None).is_empty());
+        assert_eq!(
+            merge_args_for(Some("usr=signed".to_string()), false, Some("yes")),
+            vec!["--image-policy=usr=signed", "--noexec=yes"]
+        );
+        assert_eq!(
+            merge_args_for(Some(VERITY_POLICY.to_string()), true, None),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Merged extensions that ship kernel modules.
+//!
+//! depmod only has work to do when a merge or unmerge changes the module
+//! tree under `/usr/lib/modules`. Every merge adds its system extensions
+//! that ship modules to `/run/avocado/module-extensions`, and unmerge skips
+//! depmod when the record is empty, then clears it once the module tree is
+//! back to the base image's. A missing record (first unmerge after boot, or
+//! after an older avocadoctl) counts as unknown, and depmod runs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MODULES_FILENAME: &str = "module-extensions";
+
+/// `/run/avocado/module-extensions`, or under `$TMPDIR/avocado` in test mode.
+fn path() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/{MODULES_FILENAME}"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path(&format!(
+            "/run/avocado/{MODULES_FILENAME}"
+        )))
+    }
+}
+
+/// Whether the extension rooted at `root` has anything under
+/// `usr/lib/modules`.
+pub fn ships_modules(root: &Path) -> bool {
+    fs::read_dir(root.join("usr/lib/modules"))
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false)
+}
+
+/// Record the extensions that ship modules, replacing the last record. An
+/// empty list is kept: it means "none", not "unknown".
+pub fn record(extensions: &[String]) -> std::io::Result<()> {
+    record_at(&path(), extensions)
+}
+
+fn record_at(path: &Path, extensions: &[String]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(extensions).map_err(std::io::Error::other)?;
+    fs::write(path, json + "\n")
+}
+
+/// The merged extensions that ship modules, or `None` when unknown.
+pub fn recorded() -> Option<Vec<String>> {
+    recorded_from(&path())
+}
+
+fn recorded_from(path: &Path) -> Option<Vec<String>> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_distinguishes_none_from_unknown() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("avocado").join(MODULES_FILENAME);
+        assert_eq!(recorded_from(&path), None);
+        record_at(&path, &["gpu-driver-1.0".to_string()]).unwrap();
+        assert_eq!(
+            recorded_from(&path),
+            Some(vec!["gpu-driver-1.0".to_string()])
+        );
+        record_at(&path, &[]).unwrap();
+        assert_eq!(recorded_from(&path), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_ships_modules_needs_content() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        assert!(!ships_modules(tmp.path()));
+        let modules = tmp.path().join("usr/lib/modules");
+        fs::create_dir_all(&modules).unwrap();
+        assert!(!ships_modules(tmp.path()));
+        fs::create_dir_all(modules.join("6.6.0/extra")).unwrap();
+        assert!(ships_modules(tmp.path()));
+    }
+}
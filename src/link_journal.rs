@@ -0,0 +1,312 @@
+//! Write-ahead journal for os-releases link changes.
+//!
+//! `ext enable`, `ext disable` and `ext apply` change several links in an
+//! os-releases directory. `sync_directory` makes each finished change
+//! durable, but power lost between removing one link and creating the next
+//! leaves the directory half changed. Before touching the directory the
+//! planned steps, and the links they replace, are written to
+//! `link-journal.json` in the state directory and fsync'd; the journal is
+//! removed once the directory has been synced.
+//!
+//! The daemon at startup, and every command run in-process, start with a
+//! recovery pass: a journal left behind is completed when all the link
+//! targets it creates still exist, and rolled back to the links it recorded
+//! otherwise.
+//!
+//! The writer holds an exclusive `flock` on the state directory from
+//! [`begin`] until [`PendingJournal::commit`], and the recovery pass takes
+//! it too: a journal whose lock is held belongs to a change still in
+//! progress in another process, so recovery leaves it alone. The kernel
+//! drops the lock of a process that dies, which is what leaves a journal
+//! behind to recover.
+
+use crate::fault::FailPoint;
+use crate::output::OutputManager;
+use crate::transaction::TransactionStep;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, TryLockError};
+use std::io::Write;
+use std::os::unix::fs as unix_fs;
+use std::path::{Path, PathBuf};
+
+/// Journal file (in the state directory).
+pub const JOURNAL_FILENAME: &str = "link-journal.json";
+
+/// Link changes in progress in one os-releases directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkJournal {
+    /// `enable`, `disable` or `apply`
+    pub operation: String,
+    /// The os-releases directory being changed
+    pub dir: String,
+    pub steps: Vec<TransactionStep>,
+    /// Target of each link the steps touch before the change; `None` for
+    /// links that did not exist
+    pub previous: BTreeMap<String, Option<String>>,
+}
+
+/// What the recovery pass did with a journal left behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    Completed(LinkJournal),
+    RolledBack(LinkJournal),
+}
+
+/// A journal written by [`begin`]; [`PendingJournal::commit`] removes it.
+#[must_use]
+pub struct PendingJournal {
+    path: PathBuf,
+    /// The locked state directory; dropping it releases the lock
+    _lock: fs::File,
+}
+
+impl PendingJournal {
+    /// Mark the change durable: call after the os-releases directory is synced.
+    pub fn commit(self) -> std::io::Result<()> {
+        fs::remove_file(&self.path)?;
+        sync_dir(self.path.parent().unwrap_or(Path::new("/")))
+    }
+}
+
+/// State directory holding the journal: the parent of the os-releases
+/// directories.
+pub fn state_dir() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path("/var/lib/avocado"))
+    }
+}
+
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+fn read_link_target(path: &Path) -> Option<String> {
+    fs::read_link(path)
+        .ok()
+        .map(|target| target.to_string_lossy().to_string())
+}
+
+fn file_name_of(step: &TransactionStep) -> &str {
+    match step {
+        TransactionStep::Unlink { file_name } | TransactionStep::Link { file_name, .. } => {
+            file_name
+        }
+    }
+}
+
+/// Record `steps` for `dir` in the journal in `state_dir` and make it
+/// durable before anything in `dir` changes.
+pub fn begin(
+    state_dir: &Path,
+    operation: &str,
+    dir: &str,
+    steps: &[TransactionStep],
+) -> std::io::Result<PendingJournal> {
+    let previous = steps
+        .iter()
+        .map(|step| {
+            let file_name = file_name_of(step);
+            (
+                file_name.to_string(),
+                read_link_target(&Path::new(dir).join(file_name)),
+            )
+        })
+        .collect();
+    let journal = LinkJournal {
+        operation: operation.to_string(),
+        dir: dir.to_string(),
+        steps: steps.to_vec(),
+        previous,
+    };
+
+    fs::create_dir_all(state_dir)?;
+    // Waits for a recovery pass or another change to finish
+    let lock = fs::File::open(state_dir)?;
+    lock.lock()?;
+    let path = state_dir.join(JOURNAL_FILENAME);
+    let tmp = state_dir.join(format!("{JOURNAL_FILENAME}.tmp"));
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(
+        serde_json::to_string_pretty(&journal)
+            .map_err(std::io::Error::other)?
+            .as_bytes(),
+    )?;
+    file.sync_all()?;
+    fs::rename(&tmp, &path)?;
+    sync_dir(state_dir)?;
+    Ok(PendingJournal { path, _lock: lock })
+}
+
+/// Make one link change. Both steps can be repeated: unlinking a missing
+/// link and relinking an existing one succeed.
+fn change_link(dir: &Path, step: &TransactionStep) -> std::io::Result<()> {
+    let link = dir.join(file_name_of(step));
+    if link.symlink_metadata().is_ok() {
+        fs::remove_file(&link)?;
+    }
+    if let TransactionStep::Link { source, .. } = step {
+        unix_fs::symlink(source, &link)?;
+    }
+    Ok(())
+}
+
+/// Make one journaled link change in `dir`.
+pub fn apply_step(dir: &Path, step: &TransactionStep) -> std::io::Result<()> {
+    change_link(dir, step)?;
+    // Stop dead after the first change, as if power was lost
+    if crate::fault::fail_at(FailPoint::LinkJournal) {
+        eprintln!("{}", crate::fault::injected(FailPoint::LinkJournal));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Finish the journal left in `state_dir`, if any, and remove it. Does
+/// nothing while another process holds the journal lock.
+pub fn recover(state_dir: &Path) -> std::io::Result<Option<Recovery>> {
+    let lock = match fs::File::open(state_dir) {
+        Ok(lock) => lock,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok(None),
+        Err(TryLockError::Error(e)) => return Err(e),
+    }
+    let path = state_dir.join(JOURNAL_FILENAME);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    // A journal that cannot be parsed was never completely written, so
+    // nothing was changed under it
+    let Ok(journal) = serde_json::from_str::<LinkJournal>(&content) else {
+        fs::remove_file(&path)?;
+        sync_dir(state_dir)?;
+        return Ok(None);
+    };
+
+    let dir = Path::new(&journal.dir);
+    let complete = journal.steps.iter().all(|step| match step {
+        TransactionStep::Link { source, .. } => Path::new(source).exists(),
+        TransactionStep::Unlink { .. } => true,
+    });
+    if dir.is_dir() {
+        if complete {
+            for step in &journal.steps {
+                change_link(dir, step)?;
+            }
+        } else {
+            for (file_name, target) in &journal.previous {
+                let step = match target {
+                    Some(source) => TransactionStep::Link {
+                        file_name: file_name.clone(),
+                        source: source.clone(),
+                    },
+                    None => TransactionStep::Unlink {
+                        file_name: file_name.clone(),
+                    },
+                };
+                change_link(dir, &step)?;
+            }
+        }
+        sync_dir(dir)?;
+    }
+
+    fs::remove_file(&path)?;
+    sync_dir(state_dir)?;
+    Ok(Some(if complete {
+        Recovery::Completed(journal)
+    } else {
+        Recovery::RolledBack(journal)
+    }))
+}
+
+/// The recovery pass run at daemon startup and before in-process commands.
+pub fn recover_or_warn(output: &OutputManager) {
+    match recover(&state_dir()) {
+        Ok(Some(Recovery::Completed(journal))) => output.log_info(&format!(
+            "Completed an interrupted '{}' in {}",
+            journal.operation, journal.dir
+        )),
+        Ok(Some(Recovery::RolledBack(journal))) => output.log_info(&format!(
+            "Rolled back an interrupted '{}' in {}: a link target no longer exists",
+            journal.operation, journal.dir
+        )),
+        Ok(None) => {}
+        Err(e) => output.warning(&format!(
+            "failed to recover interrupted extension link changes: {e}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(file_name: &str, source: &Path) -> TransactionStep {
+        TransactionStep::Link {
+            file_name: file_name.to_string(),
+            source: source.to_string_lossy().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_interrupted_change_is_completed_or_rolled_back() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let state = tmp.path().join("state");
+        let dir = tmp.path().join("os-releases/1.0");
+        fs::create_dir_all(&dir).unwrap();
+        let (old, new) = (tmp.path().join("app-1.0"), tmp.path().join("app-2.0"));
+        fs::create_dir_all(&old).unwrap();
+        fs::create_dir_all(&new).unwrap();
+        unix_fs::symlink(&old, dir.join("app")).unwrap();
+        let dir_str = dir.to_string_lossy().to_string();
+        let steps = [
+            link("app", &new),
+            TransactionStep::Unlink {
+                file_name: "tools".to_string(),
+            },
+        ];
+
+        // In progress elsewhere: left alone while the lock is held
+        let pending = begin(&state, "enable", &dir_str, &steps).unwrap();
+        assert!(recover(&state).unwrap().is_none());
+        assert!(state.join(JOURNAL_FILENAME).exists());
+        assert_eq!(fs::read_link(dir.join("app")).unwrap(), old);
+
+        // Interrupted after the journal was written (the lock dies with the
+        // process): completed
+        drop(pending);
+        assert!(matches!(
+            recover(&state).unwrap(),
+            Some(Recovery::Completed(_))
+        ));
+        assert_eq!(fs::read_link(dir.join("app")).unwrap(), new);
+        assert!(recover(&state).unwrap().is_none());
+
+        // A target vanished before recovery: rolled back
+        let steps = [link("app", &old), link("extra", &tmp.path().join("gone"))];
+        drop(begin(&state, "enable", &dir_str, &steps).unwrap());
+        change_link(&dir, &steps[0]).unwrap();
+        assert!(matches!(
+            recover(&state).unwrap(),
+            Some(Recovery::RolledBack(_))
+        ));
+        assert_eq!(fs::read_link(dir.join("app")).unwrap(), new);
+        assert!(dir.join("extra").symlink_metadata().is_err());
+
+        // A committed change leaves nothing to recover
+        begin(&state, "disable", &dir_str, &steps[..1])
+            .unwrap()
+            .commit()
+            .unwrap();
+        assert!(recover(&state).unwrap().is_none());
+    }
+}
@@ -0,0 +1,141 @@
+//! Loop devices backing mounted `.raw` and KAB extensions.
+//!
+//! systemd-dissect attaches each `.raw` extension to a loop device that udev
+//! names `/dev/disk/by-loop-ref/<name>`; a KAB's outer offset loop is
+//! recorded in the kab-loops state directory. [`query`] resolves either to
+//! the `/dev/loopN` device and reads its backing file, read-only flag and
+//! whether a dm-verity device sits on top of it from `/sys/block`, so
+//! `ext status` can report which loop device (and so which I/O statistics)
+//! belongs to which extension.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A loop device as reported by `ext status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopDevice {
+    /// Device node, e.g. `/dev/loop3`
+    pub device: String,
+    /// File the device is attached to; `None` when sysfs does not say
+    pub backing_file: Option<String>,
+    pub read_only: bool,
+    /// Whether a dm-verity device is stacked on the loop device or one of
+    /// its partitions
+    pub verity: bool,
+}
+
+/// Root `/dev` and `/sys` are found under; `$TMPDIR` in test mode.
+fn root() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        PathBuf::from(std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string()))
+    } else {
+        PathBuf::from("/")
+    }
+}
+
+/// The udev link systemd-dissect's `--loop-ref=<name>` creates.
+pub fn by_loop_ref(name: &str) -> PathBuf {
+    root().join("dev/disk/by-loop-ref").join(name)
+}
+
+/// Describe the loop device `device` (a `/dev/loopN` path or a link to
+/// one). `None` when it is not an attached loop device.
+pub fn query(device: &Path) -> Option<LoopDevice> {
+    query_in(&root().join("sys/block"), device)
+}
+
+fn query_in(sys_block: &Path, device: &Path) -> Option<LoopDevice> {
+    // by-loop-ref links are relative (`../../loop3`); only the name matters
+    let resolved = fs::read_link(device).unwrap_or_else(|_| device.to_path_buf());
+    let name = resolved.file_name()?.to_str()?;
+    if !name.starts_with("loop") {
+        return None;
+    }
+    let sys = sys_block.join(name);
+    if !sys.join("loop").is_dir() {
+        return None;
+    }
+
+    let read = |path: PathBuf| {
+        fs::read_to_string(path)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    Some(LoopDevice {
+        device: format!("/dev/{name}"),
+        backing_file: read(sys.join("loop/backing_file")),
+        read_only: read(sys.join("ro")).is_some_and(|ro| ro == "1"),
+        verity: has_verity_holder(sys_block, &sys, name),
+    })
+}
+
+/// Whether a dm-verity device holds the loop device or one of its
+/// partitions (`loopNp1`, ...). systemd names verity devices' dm UUIDs
+/// `CRYPT-VERITY-...`.
+fn has_verity_holder(sys_block: &Path, sys: &Path, name: &str) -> bool {
+    let mut holder_dirs = vec![sys.join("holders")];
+    if let Ok(entries) = fs::read_dir(sys) {
+        holder_dirs.extend(
+            entries
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().starts_with(name))
+                .map(|e| e.path().join("holders")),
+        );
+    }
+    holder_dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .any(|holder| {
+            fs::read_to_string(sys_block.join(holder.file_name()).join("dm/uuid"))
+                .is_ok_and(|uuid| uuid.starts_with("CRYPT-VERITY-"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs as unix_fs;
+
+    #[test]
+    fn test_query_reads_sysfs() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let sys_block = tmp.path().join("sys/block");
+        let loop3 = sys_block.join("loop3");
+        fs::create_dir_all(loop3.join("loop")).unwrap();
+        fs::write(
+            loop3.join("loop/backing_file"),
+            "/var/lib/avocado/images/app.raw\n",
+        )
+        .unwrap();
+        fs::write(loop3.join("ro"), "1\n").unwrap();
+        fs::create_dir_all(loop3.join("loop3p1/holders/dm-0")).unwrap();
+        fs::create_dir_all(sys_block.join("dm-0/dm")).unwrap();
+        fs::write(sys_block.join("dm-0/dm/uuid"), "CRYPT-VERITY-abc-usr\n").unwrap();
+
+        let link = tmp.path().join("app-1.0");
+        unix_fs::symlink("../../loop3", &link).unwrap();
+        let device = query_in(&sys_block, &link).unwrap();
+        assert_eq!(
+            device,
+            LoopDevice {
+                device: "/dev/loop3".to_string(),
+                backing_file: Some("/var/lib/avocado/images/app.raw".to_string()),
+                read_only: true,
+                verity: true,
+            }
+        );
+
+        fs::remove_dir_all(loop3.join("loop3p1")).unwrap();
+        fs::write(loop3.join("ro"), "0\n").unwrap();
+        let device = query_in(&sys_block, Path::new("/dev/loop3")).unwrap();
+        assert!(!device.read_only);
+        assert!(!device.verity);
+
+        assert!(query_in(&sys_block, Path::new("/dev/loop9")).is_none());
+        assert!(query_in(&sys_block, Path::new("/dev/sda")).is_none());
+    }
+}
@@ -1,27 +1,92 @@
+pub mod archive;
+pub mod audit;
+#[cfg(feature = "daemon")]
+mod auto_refresh;
+pub mod backend;
+mod clock;
 mod commands;
 mod config;
+mod container;
+mod daemon_reload;
+mod ddi;
+mod diagnostics;
+mod error;
+mod events;
+mod extension_release;
+mod fault;
 pub mod gc;
 pub mod hash;
+mod hitl_handshake;
+mod hitl_health;
+mod hitl_overrides;
+mod hitl_sync;
+mod hook_log;
+mod image_policy;
+mod kernel_modules;
+mod link_journal;
+mod loop_device;
+mod maintenance;
 pub mod manifest;
+mod merge_failures;
+mod merge_history;
+mod merge_inputs;
+mod merge_target;
+mod messages;
 pub mod metadata;
+mod migrations;
+#[cfg(feature = "dev")]
+mod mock_scenario;
+mod ordering;
+mod os_release;
 pub mod os_update;
 mod output;
 pub mod overrides;
+mod permission_audit;
+mod phases;
+pub mod plan;
+mod prefetch;
+mod provision;
+pub mod reboot;
+mod repo_index;
+mod safe_mode;
 pub mod service;
+mod settings;
+mod shell_env;
+pub mod snapshot;
+mod source_guard;
 pub mod staging;
+mod storage;
+mod symlink_map;
+mod sysext_status;
+mod systemd_caps;
+mod systemd_runtime;
+mod telemetry;
+mod timeouts;
+mod tools;
+pub mod transaction;
+mod trust;
+mod unprivileged;
 pub mod update;
+mod upgrade;
+mod user_mode;
+#[cfg(feature = "daemon")]
 mod varlink;
+#[cfg(feature = "daemon")]
 mod varlink_client;
+#[cfg(feature = "daemon")]
 mod varlink_server;
 
 use clap::{Arg, Command};
-use commands::{ext, hitl, root_authority, runtime};
+use commands::{ext, hitl, runtime};
 use config::Config;
+use diagnostics::Diagnose;
 use output::OutputManager;
-use varlink::org_avocado_Extensions as vl_ext;
-use varlink::org_avocado_Hitl as vl_hitl;
-use varlink::org_avocado_RootAuthority as vl_ra;
-use varlink::org_avocado_Runtimes as vl_rt;
+#[cfg(feature = "daemon")]
+use varlink::{
+    org_avocado_Extensions as vl_ext, org_avocado_Hitl as vl_hitl,
+    org_avocado_RootAuthority as vl_ra, org_avocado_Runtimes as vl_rt,
+};
+#[cfg(feature = "daemon")]
 use varlink_client::{
     ExtClientInterface, HitlClientInterface, RaClientInterface, RtClientInterface,
 };
@@ -56,6 +121,13 @@ fn main() {
                 .global(true)
                 .default_value("table"),
         )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Same as --output json: the result as JSON on stdout, progress as JSON events on stderr")
+                .action(clap::ArgAction::SetTrue)
+                .global(true),
+        )
         .arg(
             Arg::new("socket")
                 .long("socket")
@@ -63,17 +135,46 @@ fn main() {
                 .help("Varlink daemon socket address (overrides config)")
                 .global(true),
         )
+        .arg(
+            Arg::new("backend")
+                .long("backend")
+                .value_name("BACKEND")
+                .help("Execution backend: system (default) or mock (simulate systemd, no daemon)")
+                .value_parser(["system", "mock"])
+                .global(true)
+                .default_value("system"),
+        )
+        .arg(
+            Arg::new("user")
+                .long("user")
+                .help("Manage a user-owned root prefix without root privileges (XDG config/state, no daemon)")
+                .action(clap::ArgAction::SetTrue)
+                .global(true),
+        )
+        .arg(
+            Arg::new("container")
+                .long("container")
+                .help("Merge configuration extensions scoped to this container, including those the host provides in /run/host/extensions (no daemon)")
+                .action(clap::ArgAction::SetTrue)
+                .global(true),
+        )
         .subcommand(commands::ext::create_command())
         .subcommand(commands::hitl::create_command())
-        .subcommand(commands::root_authority::create_command())
+        .subcommand(commands::init::create_command())
+        .subcommand(commands::logs::create_command())
+        .subcommand(commands::provision::create_command())
+        .subcommand(commands::remote::create_command())
         .subcommand(commands::runtime::create_command())
+        .subcommand(commands::trust::create_command())
         .subcommand(
             Command::new("status").about("Show overall system status including extensions"),
         )
         // Top-level aliases for common ext commands
         .subcommand(
             Command::new("merge")
-                .about("Merge extensions using systemd-sysext and systemd-confext (alias for 'ext merge')"),
+                .about("Merge extensions using systemd-sysext and systemd-confext (alias for 'ext merge')")
+                .arg(ext::mount_only_arg())
+                .arg(ext::keep_going_arg()),
         )
         .subcommand(
             Command::new("unmerge")
@@ -87,7 +188,20 @@ fn main() {
         )
         .subcommand(
             Command::new("refresh")
-                .about("Unmerge and then merge extensions (alias for 'ext refresh')"),
+                .about("Unmerge and then merge extensions (alias for 'ext refresh')")
+                .arg(
+                    Arg::new("soft-reboot")
+                        .long("soft-reboot")
+                        .help("Persist enable-state changes and apply them via systemd soft-reboot instead of a live refresh")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Unmerge and merge even when nothing changed since the last merge")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(ext::keep_going_arg().conflicts_with("soft-reboot")),
         )
         .subcommand(
             Command::new("enable")
@@ -98,9 +212,15 @@ fn main() {
                         .value_name("VERSION")
                         .help("OS release version (defaults to current os-release VERSION_ID)"),
                 )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Enable even if the extension's ID/VERSION_ID does not match the target os-release")
+                        .action(clap::ArgAction::SetTrue),
+                )
                 .arg(
                     Arg::new("extensions")
-                        .help("Extension names to enable")
+                        .help("Extension names, absolute image paths, or glob patterns (e.g. 'sensor-*') to enable")
                         .required(true)
                         .num_args(1..)
                         .value_name("EXTENSION"),
@@ -129,85 +249,264 @@ fn main() {
                         .value_name("EXTENSION"),
                 ),
         )
-        .subcommand(
-            Command::new("serve")
-                .about("Start the Varlink IPC server")
-                .arg(
-                    Arg::new("address")
-                        .long("address")
-                        .value_name("ADDRESS")
-                        .help("Listen address (e.g. unix:/run/avocado/avocadoctl.sock)")
-                        .default_value("unix:/run/avocado/avocadoctl.sock"),
-                ),
-        );
+        .subcommand(commands::plan::create_plan_command())
+        .subcommand(commands::plan::create_apply_command());
+
+    #[cfg(feature = "network")]
+    let app = app.subcommand(commands::root_authority::create_command());
+
+    #[cfg(feature = "daemon")]
+    let app = app.subcommand(
+        Command::new("serve")
+            .about("Start the Varlink IPC server")
+            .arg(
+                Arg::new("address")
+                    .long("address")
+                    .value_name("ADDRESS")
+                    .help("Listen address (e.g. unix:/run/avocado/avocadoctl.sock)")
+                    .default_value("unix:/run/avocado/avocadoctl.sock"),
+            ),
+    );
+
+    #[cfg(feature = "fault-injection")]
+    let app = app.arg(
+        Arg::new("fail-at")
+            .long("fail-at")
+            .value_name("STEP")
+            .value_parser(fault::FailPoint::ALL.map(fault::FailPoint::as_str))
+            .global(true)
+            .hide(true),
+    );
+
+    #[cfg(feature = "dev")]
+    let app = app.subcommand(
+        Command::new("test-daemon")
+            .about("Serve the varlink API over the mock backend, scripted by a scenario")
+            .hide(true)
+            .arg(
+                Arg::new("address")
+                    .long("address")
+                    .value_name("ADDRESS")
+                    .required(true)
+                    .help("Listen address (e.g. unix:/tmp/avocadoctl-test.sock)"),
+            )
+            .arg(
+                Arg::new("scenario")
+                    .long("scenario")
+                    .value_name("FILE")
+                    .help("TOML file scripting systemd-sysext and systemd-confext responses"),
+            ),
+    );
 
     let matches = app.get_matches();
 
+    #[cfg(feature = "fault-injection")]
+    if let Some(step) = matches.get_one::<String>("fail-at") {
+        fault::enable(step);
+    }
+
+    if matches.get_one::<String>("backend").map(String::as_str) == Some("mock") {
+        backend::enable_mock();
+    }
+
     // Initialize output manager with global verbose and format settings
     let verbose = matches.get_flag("verbose");
-    let json_output = matches
-        .get_one::<String>("output")
-        .map(|s| s == "json")
-        .unwrap_or(false);
+    let json_output = matches.get_flag("json")
+        || matches
+            .get_one::<String>("output")
+            .map(|s| s == "json")
+            .unwrap_or(false);
     let output = OutputManager::new(verbose, json_output);
+    let startup_failure = startup_failure_code(&matches);
+
+    #[cfg(feature = "dev")]
+    if let Some(("test-daemon", sub)) = matches.subcommand() {
+        if let Some(file) = sub.get_one::<String>("scenario") {
+            if let Err(e) = mock_scenario::arm(std::path::Path::new(file)) {
+                output.error("Test Daemon", &e.to_string());
+                output.exit(1);
+            }
+        }
+        backend::enable_mock();
+    }
+
+    let user = matches.get_flag("user");
+    let container = matches.get_flag("container");
+    if container && container::detect().is_none() {
+        output.error(
+            "Container Mode",
+            &format!(
+                "--container: not running in a container ({} has no container-manager)",
+                container::host_dir().display()
+            ),
+        );
+        output.exit(startup_failure);
+    }
+
+    // remote runs on a development machine, which has no configuration;
+    // the device it targets reads its own
+    if let Some(("remote", remote_matches)) = matches.subcommand() {
+        commands::remote::handle_command(remote_matches, &output);
+    }
 
     // Load configuration
-    let config_path = matches.get_one::<String>("config").map(|s| s.as_str());
-    let config = match Config::load_with_override(config_path) {
+    let user_config_path = user_mode::config_path().to_string_lossy().to_string();
+    let config_path = matches
+        .get_one::<String>("config")
+        .map(|s| s.as_str())
+        .or_else(|| user.then_some(user_config_path.as_str()));
+    let mut config = match Config::load_with_override(config_path) {
         Ok(config) => config,
         Err(e) => {
-            output.error(
+            output.error_with(
                 "Configuration Error",
                 &format!("Failed to load configuration: {e}"),
+                &e.diagnose(),
             );
-            std::process::exit(1);
+            output.exit(startup_failure);
         }
     };
 
-    // Resolve socket address: CLI flag > config > default
-    let socket_address = matches
-        .get_one::<String>("socket")
-        .cloned()
-        .unwrap_or_else(|| config.socket_address().to_string());
-
-    // In test mode, skip the varlink daemon and call service functions directly.
-    // This allows existing integration tests (which use AVOCADO_TEST_MODE=1 with mock
-    // executables) to keep running without needing a live daemon.
-    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        handle_direct(&matches, &config, &output);
+    let mut resolved = settings::Settings::resolve(&config);
+    resolved.user = user;
+    resolved.container |= container;
+    // Unprivileged callers such as monitoring agents run read-only commands
+    // in read-only mode, which never mounts or writes anything
+    resolved.read_only |=
+        !user && unprivileged::is_unprivileged() && unprivileged::is_read_only_command(&matches);
+    settings::install(resolved);
+    if user {
+        user_mode::apply_to_config(&mut config);
+    }
+
+    // init prepares the device the daemon runs on, so it always runs in-process
+    if let Some(("init", init_matches)) = matches.subcommand() {
+        commands::init::handle_command(
+            init_matches,
+            config_path.unwrap_or(config::DEFAULT_CONFIG_PATH),
+            &config,
+            &output,
+        );
+        output.finish();
+        return;
+    }
+
+    // provision writes the configuration and enables extensions on the
+    // device itself, like init
+    if let Some(("provision", provision_matches)) = matches.subcommand() {
+        commands::provision::handle_command(
+            provision_matches,
+            config_path.unwrap_or(config::DEFAULT_CONFIG_PATH),
+            &config,
+            &output,
+        );
+        output.finish();
+        return;
+    }
+
+    // The hook logs are plain files on this device, so logs runs in-process
+    if let Some(("logs", logs_matches)) = matches.subcommand() {
+        commands::logs::handle_command(logs_matches, &output);
+        output.finish();
         return;
     }
 
+    // A build without the daemon feature, such as the initrd build, has no
+    // daemon to talk to
+    #[cfg(feature = "daemon")]
+    {
+        // Resolve socket address: CLI flag > config > default
+        let socket_address = matches
+            .get_one::<String>("socket")
+            .cloned()
+            .unwrap_or_else(|| config.socket_address().to_string());
+        if !runs_in_process(&matches, &socket_address) {
+            handle_client(&matches, &config, &socket_address, &output);
+            return;
+        }
+    }
+    handle_direct(&matches, &config, &output);
+}
+
+/// Whether a command runs in-process instead of through the daemon at
+/// `socket_address`.
+///
+/// In test mode, skip the varlink daemon and call service functions directly.
+/// This allows existing integration tests (which use AVOCADO_TEST_MODE=1 with mock
+/// executables) to keep running without needing a live daemon.
+/// User mode never talks to the system daemon either, nor does container
+/// mode, whose container has no daemon of its own. Without a running
+/// systemd (a chroot or minimal container) merges run in-process too, to
+/// report that instead of a missing daemon, and `merge --mount-only` only
+/// prepares extensions. Without access to the daemon socket, read-only
+/// commands are answered in-process.
+#[cfg(feature = "daemon")]
+fn runs_in_process(matches: &clap::ArgMatches, socket_address: &str) -> bool {
+    std::env::var("AVOCADO_TEST_MODE").is_ok()
+        || backend::is_mock()
+        || user_mode::is_user()
+        || container::is_container()
+        || (systemd_runtime::is_merge_command(matches)
+            && !systemd_runtime::is_running()
+            && !varlink_client::daemon_reachable(socket_address))
+        || matches
+            .subcommand_matches("merge")
+            .is_some_and(|sub| sub.get_flag("mount-only"))
+        || (unprivileged::is_read_only() && !varlink_client::daemon_reachable(socket_address))
+}
+
+/// Send a command to the varlink daemon at `socket_address` and report its
+/// replies.
+#[cfg(feature = "daemon")]
+fn handle_client(
+    matches: &clap::ArgMatches,
+    config: &Config,
+    socket_address: &str,
+    output: &OutputManager,
+) {
     match matches.subcommand() {
         // ── ext subcommands ──────────────────────────────────────────────────
+        Some(("ext", ext_matches)) if ext::is_local_subcommand(ext_matches) => {
+            ext::handle_command(ext_matches, config, output);
+            daemon_reload::flush_or_warn(output);
+            output.finish();
+            unprivileged::print_notes(output);
+        }
         Some(("ext", ext_matches)) => {
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             match ext_matches.subcommand() {
-                Some(("list", _)) => {
+                Some(("list", sub)) => {
+                    let detailed = sub.get_flag("detailed");
                     let mut client = vl_ext::VarlinkClient::new(conn);
-                    match client.list().call() {
-                        Ok(reply) => varlink_client::print_extensions(&reply.extensions, &output),
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                    match client.list(Some(detailed)).call() {
+                        Ok(reply) if detailed => ext::display::print_extension_details(
+                            &varlink_client::details_from_varlink(reply.extensions),
+                            output,
+                        ),
+                        Ok(reply) => varlink_client::print_extensions(&reply.extensions, output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
                 }
-                Some(("merge", _)) => {
+                Some(("merge", merge_matches)) => {
+                    let target = merge_matches.get_one::<String>("target").cloned();
+                    let keep_going = merge_matches.get_flag("keep-going");
                     let mut client = vl_ext::VarlinkClient::new(conn);
-                    match client.merge().more() {
+                    match client.merge(target, Some(keep_going)).more() {
                         Ok(iter) => {
                             for reply in iter {
                                 match reply {
-                                    Ok(r) if !r.done => {
-                                        varlink_client::print_single_log(&r.message, &output)
-                                    }
+                                    Ok(r) if !r.done => output.relay(&r.message),
                                     Ok(_) => {}
-                                    Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                    Err(e) => varlink_client::exit_with_rpc_error(e, output),
                                 }
                             }
-                            output.success("Merge", "Extensions merged successfully");
+                            ext::exit_if_partial_failure(output);
+                            output.success_msg("Merge", messages::EXT_MERGED, &[]);
                         }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                    ext::exit_if_reboot_required(output);
+                    output.json_ok();
                 }
                 Some(("unmerge", unmerge_matches)) => {
                     let unmount = unmerge_matches.get_flag("unmount");
@@ -216,45 +515,77 @@ fn main() {
                         Ok(iter) => {
                             for reply in iter {
                                 match reply {
-                                    Ok(r) if !r.done => {
-                                        varlink_client::print_single_log(&r.message, &output)
-                                    }
+                                    Ok(r) if !r.done => output.relay(&r.message),
                                     Ok(_) => {}
-                                    Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                    Err(e) => varlink_client::exit_with_rpc_error(e, output),
                                 }
                             }
-                            output.success("Unmerge", "Extensions unmerged successfully");
+                            output.success_msg("Unmerge", messages::EXT_UNMERGED, &[]);
                         }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                    output.json_ok();
                 }
-                Some(("refresh", _)) => {
+                Some(("refresh", sub)) => {
+                    let soft_reboot = sub.get_flag("soft-reboot");
+                    let force = sub.get_flag("force");
+                    let keep_going = sub.get_flag("keep-going");
                     let mut client = vl_ext::VarlinkClient::new(conn);
-                    match client.refresh().more() {
+                    match client
+                        .refresh(Some(soft_reboot), Some(force), Some(keep_going))
+                        .more()
+                    {
                         Ok(iter) => {
+                            let mut up_to_date = false;
                             for reply in iter {
                                 match reply {
-                                    Ok(r) if !r.done => {
-                                        varlink_client::print_single_log(&r.message, &output)
-                                    }
-                                    Ok(_) => {}
-                                    Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                    Ok(r) if !r.done => output.relay(&r.message),
+                                    Ok(r) => up_to_date = r.upToDate.unwrap_or(false),
+                                    Err(e) => varlink_client::exit_with_rpc_error(e, output),
                                 }
                             }
-                            output.success("Refresh", "Extensions refreshed successfully");
+                            output.result("up_to_date", &up_to_date);
+                            if !soft_reboot {
+                                ext::exit_if_partial_failure(output);
+                            }
+                            if soft_reboot {
+                                output.success_msg(
+                                    "Refresh",
+                                    messages::EXT_SOFT_REBOOT_REQUESTED,
+                                    &[],
+                                );
+                            } else if up_to_date {
+                                output.success_msg("Refresh", messages::EXT_UP_TO_DATE, &[]);
+                            } else {
+                                output.success_msg("Refresh", messages::EXT_REFRESHED, &[]);
+                            }
                         }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                    ext::exit_if_reboot_required(output);
+                    output.json_ok();
                 }
-                Some(("status", _)) => {
+                Some(("status", sub)) => {
+                    let updates_only = sub.get_flag("updates-only");
                     let mut client = vl_ext::VarlinkClient::new(conn);
-                    match client.status().call() {
-                        Ok(reply) => {
-                            varlink_client::print_extension_status(&reply.extensions, &output)
-                        }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                    match client.status(Some(updates_only)).call() {
+                        Ok(reply) => ext::display::print_extension_status(
+                            &varlink_client::statuses_from_varlink(reply.extensions),
+                            updates_only,
+                            output,
+                        ),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                }
+                Some(("env", sub)) => {
+                    let mut client = vl_ext::VarlinkClient::new(conn);
+                    match client.status(None).call() {
+                        Ok(reply) => ext::display::print_extension_env(
+                            &varlink_client::statuses_from_varlink(reply.extensions),
+                            sub.get_one::<String>("name").map(String::as_str),
+                            output,
+                        ),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
                 }
                 // `enable` / `disable` go through the varlink server like
@@ -262,13 +593,14 @@ fn main() {
                 // invocations serialize through the daemon and remote
                 // clients get the same interface.
                 Some(("enable", sub)) => {
-                    let names: Vec<String> = sub
-                        .get_many::<String>("names")
-                        .map(|vs| vs.cloned().collect())
-                        .unwrap_or_default();
+                    let names = config.resolve_extension_aliases(
+                        sub.get_many::<String>("names").into_iter().flatten(),
+                    );
                     let mut client = vl_ext::VarlinkClient::new(conn);
                     match client.set_enabled(names.clone(), true).call() {
                         Ok(reply) => {
+                            output.result("updated", &reply.updated);
+                            output.result("missing", &reply.missing);
                             let msg = format!(
                                 "enabled: {} ({} updated, {} missing)",
                                 names.join(", "),
@@ -277,18 +609,19 @@ fn main() {
                             );
                             output.success("Extension Override", &msg);
                         }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                    output.json_ok();
                 }
                 Some(("disable", sub)) => {
-                    let names: Vec<String> = sub
-                        .get_many::<String>("names")
-                        .map(|vs| vs.cloned().collect())
-                        .unwrap_or_default();
+                    let names = config.resolve_extension_aliases(
+                        sub.get_many::<String>("names").into_iter().flatten(),
+                    );
                     let mut client = vl_ext::VarlinkClient::new(conn);
                     match client.set_enabled(names.clone(), false).call() {
                         Ok(reply) => {
+                            output.result("updated", &reply.updated);
+                            output.result("missing", &reply.missing);
                             let msg = format!(
                                 "disabled: {} ({} updated, {} missing)",
                                 names.join(", "),
@@ -297,9 +630,170 @@ fn main() {
                             );
                             output.success("Extension Override", &msg);
                         }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                    output.json_ok();
+                }
+                Some(("apply", sub)) => {
+                    let path = sub
+                        .get_one::<String>("manifest")
+                        .expect("manifest is required");
+                    let manifest =
+                        ext::load_transaction_manifest_or_exit(std::path::Path::new(path), output);
+                    let mut client = vl_ext::VarlinkClient::new(conn);
+                    match client
+                        .apply(
+                            manifest.enable,
+                            manifest.disable,
+                            manifest.update,
+                            manifest.os_release,
+                            Some(manifest.force),
+                        )
+                        .call()
+                    {
+                        Ok(reply) => ext::print_apply_result(
+                            &service::types::ApplyResult {
+                                linked: reply.linked as usize,
+                                unlinked: reply.unlinked as usize,
+                                refreshed: reply.refreshed,
+                            },
+                            output,
+                        ),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                }
+                Some(("snapshot", sub)) => {
+                    let file = sub.get_one::<String>("file").map(std::path::Path::new);
+                    let mut client = vl_ext::VarlinkClient::new(conn);
+                    match client.snapshot().call() {
+                        Ok(reply) => match snapshot::StateSnapshot::from_json(&reply.snapshot) {
+                            Ok(snapshot) => ext::write_snapshot(&snapshot, file, output),
+                            Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                        },
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                }
+                Some(("audit", sub)) => {
+                    let mut client = vl_ext::VarlinkClient::new(conn);
+                    match client.audit().call() {
+                        Ok(reply) => {
+                            match serde_json::from_str::<audit::AuditReport>(&reply.report) {
+                                Ok(report) => ext::write_audit_report(
+                                    report,
+                                    sub.get_one::<String>("file").map(std::path::Path::new),
+                                    sub.get_one::<String>("sign").map(std::path::Path::new),
+                                    output,
+                                ),
+                                Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                            }
+                        }
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                }
+                Some(("prefetch", sub)) => {
+                    let url = sub.get_one::<String>("url").cloned();
+                    let auth_token = std::env::var("AVOCADO_TUF_AUTH_TOKEN").ok();
+                    let mut client = vl_ext::VarlinkClient::new(conn);
+                    match client.prefetch(url, auth_token).call() {
+                        Ok(reply) => ext::print_prefetch(
+                            &reply.runtimeId,
+                            &reply.name,
+                            &reply.version,
+                            reply.alreadyActive,
+                            output,
+                        ),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                }
+                Some(("upgrade", sub)) => {
+                    let names: Vec<String> = sub
+                        .get_many::<String>("names")
+                        .map(|v| v.cloned().collect())
+                        .unwrap_or_default();
+                    let url = sub.get_one::<String>("url").cloned();
+                    let auth_token = std::env::var("AVOCADO_TUF_AUTH_TOKEN").ok();
+                    let dry_run = sub.get_flag("dry-run");
+                    let mut client = vl_ext::VarlinkClient::new(conn);
+                    match client
+                        .upgrade(
+                            names,
+                            url,
+                            auth_token,
+                            sub.get_flag("offline"),
+                            dry_run,
+                            Some(sub.get_flag("force")),
+                        )
+                        .call()
+                    {
+                        Ok(reply) => {
+                            // The reply's upgrades have the same shape as the
+                            // service's decisions
+                            match serde_json::to_value(&reply.upgrades)
+                                .and_then(serde_json::from_value)
+                            {
+                                Ok(decisions) => ext::print_upgrade(
+                                    &service::types::UpgradeResult {
+                                        decisions,
+                                        runtime_id: reply.runtimeId,
+                                        queued: reply.queued.unwrap_or(false),
+                                        queued_until: reply.queuedUntil.map(|t| t as u64),
+                                    },
+                                    dry_run,
+                                    output,
+                                ),
+                                Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                            }
+                        }
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                }
+                Some(("prune-os-releases", sub)) => {
+                    let dry_run = sub.get_flag("dry-run");
+                    let mut client = vl_ext::VarlinkClient::new(conn);
+                    match client
+                        .prune_os_releases(ext::prune_keep(sub), dry_run)
+                        .call()
+                    {
+                        Ok(reply) => ext::print_os_release_prune(
+                            &service::types::PruneOsReleasesResult {
+                                current: reply.current,
+                                kept: reply.kept,
+                                removed: reply.removed,
+                            },
+                            dry_run,
+                            output,
+                        ),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                }
+                Some(("auto-refresh", _)) => {
+                    let mut client = vl_ext::VarlinkClient::new(conn);
+                    match client.auto_refresh_status().call() {
+                        Ok(reply) => varlink_client::print_auto_refresh_stats(&reply.stats, output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                }
+                Some(("compare", sub)) => {
+                    let left_path = sub
+                        .get_one::<String>("left")
+                        .expect("left snapshot is required");
+                    let left = ext::load_snapshot_or_exit(left_path, output);
+                    let (right, right_label) = match sub.get_one::<String>("right") {
+                        Some(path) => (ext::load_snapshot_or_exit(path, output), path.clone()),
+                        None => {
+                            let mut client = vl_ext::VarlinkClient::new(conn);
+                            match client.snapshot().call() {
+                                Ok(reply) => {
+                                    match snapshot::StateSnapshot::from_json(&reply.snapshot) {
+                                        Ok(snapshot) => (snapshot, "live".to_string()),
+                                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                                    }
+                                }
+                                Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                            }
+                        }
+                    };
+                    ext::print_snapshot_comparison(&left, left_path, &right, &right_label, output);
                 }
                 _ => {
                     println!("Use 'avocadoctl ext --help' for available extension commands");
@@ -309,40 +803,82 @@ fn main() {
 
         // ── hitl subcommands ─────────────────────────────────────────────────
         Some(("hitl", hitl_matches)) => {
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             match hitl_matches.subcommand() {
                 Some(("mount", mount_matches)) => {
-                    let server_ip = mount_matches
-                        .get_one::<String>("server-ip")
-                        .expect("server-ip is required")
-                        .clone();
+                    let server_ip = mount_matches.get_one::<String>("server-ip").cloned();
                     let server_port = mount_matches.get_one::<String>("server-port").cloned();
-                    let extensions: Vec<String> = mount_matches
-                        .get_many::<String>("extension")
-                        .expect("at least one extension is required")
-                        .cloned()
-                        .collect();
+                    let extensions = config.resolve_extension_aliases(
+                        mount_matches
+                            .get_many::<String>("extension")
+                            .expect("at least one extension is required"),
+                    );
+                    let mount_type = mount_matches.get_one::<String>("type").cloned();
+                    let fail_fast = mount_matches.get_flag("fail-fast");
+                    let control_port = mount_matches
+                        .get_one::<u16>("control-port")
+                        .map(|port| i64::from(*port));
                     let mut client = vl_hitl::VarlinkClient::new(conn);
-                    match client.mount(server_ip, server_port, extensions).call() {
-                        Ok(_) => output.success("HITL Mount", "Extensions mounted successfully"),
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                    match client
+                        .mount(
+                            server_ip,
+                            server_port,
+                            extensions,
+                            mount_type,
+                            Some(fail_fast),
+                            control_port,
+                        )
+                        .call()
+                    {
+                        Ok(reply) => {
+                            if varlink_client::print_mount_results(reply.results, output) {
+                                output.success_msg("HITL Mount", messages::HITL_MOUNTED, &[]);
+                            } else {
+                                output.exit(1);
+                            }
+                        }
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                    output.json_ok();
                 }
                 Some(("unmount", unmount_matches)) => {
-                    let extensions: Vec<String> = unmount_matches
-                        .get_many::<String>("extension")
-                        .expect("at least one extension is required")
-                        .cloned()
-                        .collect();
+                    let extensions = config.resolve_extension_aliases(
+                        unmount_matches
+                            .get_many::<String>("extension")
+                            .expect("at least one extension is required"),
+                    );
                     let mut client = vl_hitl::VarlinkClient::new(conn);
                     match client.unmount(extensions).call() {
-                        Ok(_) => {
-                            output.success("HITL Unmount", "Extensions unmounted successfully")
-                        }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Ok(_) => output.success_msg("HITL Unmount", messages::HITL_UNMOUNTED, &[]),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                    output.json_ok();
+                }
+                Some(("quiesce", quiesce_matches)) => {
+                    let extensions = config.resolve_extension_aliases(
+                        quiesce_matches
+                            .get_many::<String>("extension")
+                            .expect("at least one extension is required"),
+                    );
+                    let mut client = vl_hitl::VarlinkClient::new(conn);
+                    match client.quiesce(extensions).call() {
+                        Ok(_) => output.success_msg("HITL Quiesce", messages::HITL_QUIESCED, &[]),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                    output.json_ok();
+                }
+                Some(("resume", resume_matches)) => {
+                    let extensions = config.resolve_extension_aliases(
+                        resume_matches
+                            .get_many::<String>("extension")
+                            .expect("at least one extension is required"),
+                    );
+                    let mut client = vl_hitl::VarlinkClient::new(conn);
+                    match client.resume(extensions).call() {
+                        Ok(_) => output.success_msg("HITL Resume", messages::HITL_RESUMED, &[]),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                    output.json_ok();
                 }
                 _ => {
                     println!("Use 'avocadoctl hitl --help' for available HITL commands");
@@ -352,23 +888,63 @@ fn main() {
 
         // ── root-authority ───────────────────────────────────────────────────
         Some(("root-authority", _)) => {
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             let mut client = vl_ra::VarlinkClient::new(conn);
             match client.show().call() {
-                Ok(reply) => varlink_client::print_root_authority(&reply.authority, &output),
-                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                Ok(reply) => varlink_client::print_root_authority(&reply.authority, output),
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
+            }
+        }
+
+        // ── trust subcommands ────────────────────────────────────────────────
+        Some(("trust", trust_matches)) => {
+            let conn = varlink_client::connect_or_exit(socket_address, output);
+            let mut client = vl_ra::VarlinkClient::new(conn);
+            match trust_matches.subcommand() {
+                Some(("list", _)) => match client.trusted_keys().call() {
+                    Ok(reply) => commands::trust::print_keys(
+                        &reply
+                            .keys
+                            .into_iter()
+                            .map(varlink_client::signing_key_from_varlink)
+                            .collect::<Vec<_>>(),
+                        output,
+                    ),
+                    Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                },
+                Some(("rotate", sub)) => {
+                    let args = commands::trust::rotate_args(sub, output);
+                    match client
+                        .rotate(
+                            args.public_key,
+                            args.retire,
+                            Some(args.overlap_days as i64),
+                            args.valid_days.map(|d| d as i64),
+                            args.dry_run,
+                        )
+                        .call()
+                    {
+                        Ok(reply) => commands::trust::print_rotation(
+                            &varlink_client::rotation_from_varlink(reply),
+                            args.dry_run,
+                            output,
+                        ),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                    }
+                }
+                _ => println!("Use 'avocadoctl trust --help' for available trust commands"),
             }
         }
 
         // ── runtime subcommands ──────────────────────────────────────────────
         Some(("runtime", runtime_matches)) => {
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             match runtime_matches.subcommand() {
                 Some(("list", _)) => {
                     let mut client = vl_rt::VarlinkClient::new(conn);
                     match client.list().call() {
-                        Ok(reply) => varlink_client::print_runtimes(&reply.runtimes, &output),
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Ok(reply) => varlink_client::print_runtimes(&reply.runtimes, output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
                 }
                 Some(("add", add_matches)) => {
@@ -379,16 +955,14 @@ fn main() {
                             Ok(iter) => {
                                 for reply in iter {
                                     match reply {
-                                        Ok(r) if !r.done => {
-                                            varlink_client::print_single_log(&r.message, &output)
-                                        }
+                                        Ok(r) if !r.done => output.relay(&r.message),
                                         Ok(_) => {}
-                                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                                     }
                                 }
-                                output.success("Runtime Add", "Runtime added successfully");
+                                output.success_msg("Runtime Add", messages::RUNTIME_ADDED, &[]);
                             }
-                            Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                            Err(e) => varlink_client::exit_with_rpc_error(e, output),
                         }
                     } else if let Some(manifest) = add_matches.get_one::<String>("manifest") {
                         let mut client = vl_rt::VarlinkClient::new(conn);
@@ -396,19 +970,17 @@ fn main() {
                             Ok(iter) => {
                                 for reply in iter {
                                     match reply {
-                                        Ok(r) if !r.done => {
-                                            varlink_client::print_single_log(&r.message, &output)
-                                        }
+                                        Ok(r) if !r.done => output.relay(&r.message),
                                         Ok(_) => {}
-                                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                                     }
                                 }
-                                output.success("Runtime Add", "Runtime added successfully");
+                                output.success_msg("Runtime Add", messages::RUNTIME_ADDED, &[]);
                             }
-                            Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                            Err(e) => varlink_client::exit_with_rpc_error(e, output),
                         }
                     }
-                    json_ok(&output);
+                    output.json_ok();
                 }
                 Some(("remove", remove_matches)) => {
                     let id = remove_matches
@@ -417,10 +989,12 @@ fn main() {
                         .clone();
                     let mut client = vl_rt::VarlinkClient::new(conn);
                     match client.remove(id).call() {
-                        Ok(_) => output.success("Runtime Remove", "Runtime removed successfully"),
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Ok(_) => {
+                            output.success_msg("Runtime Remove", messages::RUNTIME_REMOVED, &[])
+                        }
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                    output.json_ok();
                 }
                 Some(("activate", activate_matches)) => {
                     let id = activate_matches
@@ -432,25 +1006,27 @@ fn main() {
                         Ok(iter) => {
                             for reply in iter {
                                 match reply {
-                                    Ok(r) if !r.done => {
-                                        varlink_client::print_single_log(&r.message, &output)
-                                    }
+                                    Ok(r) if !r.done => output.relay(&r.message),
                                     Ok(_) => {}
-                                    Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                    Err(e) => varlink_client::exit_with_rpc_error(e, output),
                                 }
                             }
-                            output.success("Runtime Activate", "Runtime activated successfully");
+                            output.success_msg(
+                                "Runtime Activate",
+                                messages::RUNTIME_ACTIVATED,
+                                &[],
+                            );
                         }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
-                    json_ok(&output);
+                    output.json_ok();
                 }
                 Some(("inspect", inspect_matches)) => {
                     let id = inspect_matches.get_one::<String>("id").cloned();
                     let mut client = vl_rt::VarlinkClient::new(conn);
                     match client.inspect(id).call() {
-                        Ok(reply) => varlink_client::print_runtime_detail(&reply.runtime, &output),
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Ok(reply) => varlink_client::print_runtime_detail(&reply.runtime, output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
                 }
                 Some(("gc", _)) => {
@@ -459,11 +1035,8 @@ fn main() {
                         Ok(reply) => {
                             let result = &reply.result;
                             if output.is_json() {
-                                let json = serde_json::json!({
-                                    "removed_runtimes": result.removedRuntimes,
-                                    "removed_images": result.removedImages,
-                                });
-                                println!("{}", serde_json::to_string_pretty(&json).unwrap());
+                                output.result("removed_runtimes", &result.removedRuntimes);
+                                output.result("removed_images", &result.removedImages);
                             } else if result.removedRuntimes.is_empty()
                                 && result.removedImages.is_empty()
                             {
@@ -487,7 +1060,7 @@ fn main() {
                                 );
                             }
                         }
-                        Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                        Err(e) => varlink_client::exit_with_rpc_error(e, output),
                     }
                 }
                 Some(("metadata", meta_matches)) => {
@@ -508,16 +1081,13 @@ fn main() {
                                 .clone();
                             match client.metadata_set(id.clone(), key.clone(), value).call() {
                                 Ok(_) => {
-                                    if output.is_json() {
-                                        println!("{{\"status\":\"ok\"}}");
-                                    } else {
-                                        output.success(
-                                            "Metadata Set",
-                                            &format!("Set '{key}' on runtime {id}"),
-                                        );
-                                    }
+                                    output.success(
+                                        "Metadata Set",
+                                        &format!("Set '{key}' on runtime {id}"),
+                                    );
+                                    output.json_ok();
                                 }
-                                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                Err(e) => varlink_client::exit_with_rpc_error(e, output),
                             }
                         }
                         Some(("get", get_matches)) => {
@@ -530,12 +1100,10 @@ fn main() {
                                 .expect("key is required")
                                 .clone();
                             match client.metadata_get(id, key.clone()).call() {
-                                Ok(reply) => varlink_client::print_metadata_value(
-                                    &key,
-                                    &reply.value,
-                                    &output,
-                                ),
-                                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                Ok(reply) => {
+                                    varlink_client::print_metadata_value(&key, &reply.value, output)
+                                }
+                                Err(e) => varlink_client::exit_with_rpc_error(e, output),
                             }
                         }
                         Some(("list", list_matches)) => {
@@ -545,9 +1113,9 @@ fn main() {
                                 .clone();
                             match client.metadata_list(id).call() {
                                 Ok(reply) => {
-                                    varlink_client::print_metadata_list(&reply.entries, &output)
+                                    varlink_client::print_metadata_list(&reply.entries, output)
                                 }
-                                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                Err(e) => varlink_client::exit_with_rpc_error(e, output),
                             }
                         }
                         Some(("delete", del_matches)) => {
@@ -561,16 +1129,13 @@ fn main() {
                                 .clone();
                             match client.metadata_delete(id.clone(), key.clone()).call() {
                                 Ok(_) => {
-                                    if output.is_json() {
-                                        println!("{{\"status\":\"ok\"}}");
-                                    } else {
-                                        output.success(
-                                            "Metadata Delete",
-                                            &format!("Deleted '{key}' from runtime {id}"),
-                                        );
-                                    }
+                                    output.success(
+                                        "Metadata Delete",
+                                        &format!("Deleted '{key}' from runtime {id}"),
+                                    );
+                                    output.json_ok();
                                 }
-                                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                                Err(e) => varlink_client::exit_with_rpc_error(e, output),
                             }
                         }
                         _ => {
@@ -588,20 +1153,59 @@ fn main() {
         }
 
         // ── serve (starts the daemon — direct, no varlink client) ────────────
+        #[cfg(feature = "daemon")]
         Some(("serve", serve_matches)) => {
             let address = serve_matches
                 .get_one::<String>("address")
                 .expect("address has a default value");
-            if let Err(e) = varlink_server::run_server(address, config) {
+            // The daemon makes every link change of the commands it serves
+            link_journal::recover_or_warn(output);
+            if let Err(e) = varlink_server::run_server(address, config.clone(), output) {
                 output.error("Server Error", &format!("Varlink server failed: {e}"));
-                std::process::exit(1);
+                output.exit(1);
+            }
+        }
+
+        // ── plan / apply ─────────────────────────────────────────────────────
+        Some(("plan", plan_matches)) => {
+            let file = plan_matches
+                .get_one::<String>("file")
+                .map(std::path::Path::new);
+            let conn = varlink_client::connect_or_exit(socket_address, output);
+            let mut client = vl_ext::VarlinkClient::new(conn);
+            match client.plan().call() {
+                Ok(reply) => match crate::plan::ChangePlan::from_json(&reply.plan) {
+                    Ok(change_plan) => commands::plan::write_plan(&change_plan, file, output),
+                    Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                },
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
+            }
+        }
+        Some(("apply", apply_matches)) => {
+            let change_plan = commands::plan::load_plan_or_exit(apply_matches, output);
+            let conn = varlink_client::connect_or_exit(socket_address, output);
+            let mut client = vl_ext::VarlinkClient::new(conn);
+            match client.apply_plan(change_plan.to_json()).more() {
+                Ok(iter) => {
+                    for reply in iter {
+                        match reply {
+                            Ok(r) if !r.done => output.relay(&r.message),
+                            Ok(_) => {}
+                            Err(e) => varlink_client::exit_with_rpc_error(e, output),
+                        }
+                    }
+                    output.success_msg("Apply", messages::PLAN_APPLIED, &[]);
+                }
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
             }
+            ext::exit_if_reboot_required(output);
+            output.json_ok();
         }
 
         // ── status (top-level) ───────────────────────────────────────────────
         Some(("status", _)) => {
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
-            let conn2 = varlink_client::connect_or_exit(&socket_address, &output);
+            let conn = varlink_client::connect_or_exit(socket_address, output);
+            let conn2 = varlink_client::connect_or_exit(socket_address, output);
             let mut ext_client = vl_ext::VarlinkClient::new(conn);
             let mut rt_client = vl_rt::VarlinkClient::new(conn2);
 
@@ -610,139 +1214,152 @@ fn main() {
             // Show active runtime OS release info
             if let Ok(reply) = rt_client.list().call() {
                 if let Some(active) = reply.runtimes.iter().find(|r| r.active) {
-                    let short_id = &active.id[..active.id.len().min(8)];
-                    println!(
-                        "Runtime: {} {} ({short_id})",
-                        active.runtime.name, active.runtime.version
+                    print_active_runtime(
+                        &format!("{} {}", active.runtime.name, active.runtime.version),
+                        &active.id,
+                        active.osBuildId.as_deref(),
+                        active.initramfsBuildId.as_deref(),
+                        output,
                     );
-                    if let Some(ref id) = active.osBuildId {
-                        println!("Rootfs Build ID:    {id}");
-                    }
-                    if let Some(ref id) = active.initramfsBuildId {
-                        println!("Initramfs Build ID: {id}");
-                    }
-                    println!();
                 }
             }
 
-            match ext_client.status().call() {
-                Ok(reply) => {
-                    varlink_client::print_extension_status(&reply.extensions, &output);
-                }
-                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+            match ext_client.status(None).call() {
+                Ok(reply) => ext::display::print_extension_status(
+                    &varlink_client::statuses_from_varlink(reply.extensions),
+                    false,
+                    output,
+                ),
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
             }
         }
 
         // ── Top-level aliases ────────────────────────────────────────────────
-        Some(("merge", _)) => {
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+        Some(("merge", merge_matches)) => {
+            let keep_going = merge_matches.get_flag("keep-going");
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             let mut client = vl_ext::VarlinkClient::new(conn);
-            match client.merge().more() {
+            match client.merge(None, Some(keep_going)).more() {
                 Ok(iter) => {
                     for reply in iter {
                         match reply {
-                            Ok(r) if !r.done => {
-                                varlink_client::print_single_log(&r.message, &output)
-                            }
+                            Ok(r) if !r.done => output.relay(&r.message),
                             Ok(_) => {}
-                            Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                            Err(e) => varlink_client::exit_with_rpc_error(e, output),
                         }
                     }
-                    output.success("Merge", "Extensions merged successfully");
+                    ext::exit_if_partial_failure(output);
+                    output.success_msg("Merge", messages::EXT_MERGED, &[]);
                 }
-                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
             }
-            json_ok(&output);
+            ext::exit_if_reboot_required(output);
+            output.json_ok();
         }
         Some(("unmerge", unmerge_matches)) => {
             let unmount = unmerge_matches.get_flag("unmount");
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             let mut client = vl_ext::VarlinkClient::new(conn);
             match client.unmerge(Some(unmount)).more() {
                 Ok(iter) => {
                     for reply in iter {
                         match reply {
-                            Ok(r) if !r.done => {
-                                varlink_client::print_single_log(&r.message, &output)
-                            }
+                            Ok(r) if !r.done => output.relay(&r.message),
                             Ok(_) => {}
-                            Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                            Err(e) => varlink_client::exit_with_rpc_error(e, output),
                         }
                     }
-                    output.success("Unmerge", "Extensions unmerged successfully");
+                    output.success_msg("Unmerge", messages::EXT_UNMERGED, &[]);
                 }
-                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
             }
-            json_ok(&output);
+            output.json_ok();
         }
-        Some(("refresh", _)) => {
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+        Some(("refresh", refresh_matches)) => {
+            let soft_reboot = refresh_matches.get_flag("soft-reboot");
+            let force = refresh_matches.get_flag("force");
+            let keep_going = refresh_matches.get_flag("keep-going");
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             let mut client = vl_ext::VarlinkClient::new(conn);
-            match client.refresh().more() {
+            match client
+                .refresh(Some(soft_reboot), Some(force), Some(keep_going))
+                .more()
+            {
                 Ok(iter) => {
+                    let mut up_to_date = false;
                     for reply in iter {
                         match reply {
-                            Ok(r) if !r.done => {
-                                varlink_client::print_single_log(&r.message, &output)
-                            }
-                            Ok(_) => {}
-                            Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                            Ok(r) if !r.done => output.relay(&r.message),
+                            Ok(r) => up_to_date = r.upToDate.unwrap_or(false),
+                            Err(e) => varlink_client::exit_with_rpc_error(e, output),
                         }
                     }
-                    output.success("Refresh", "Extensions refreshed successfully");
+                    output.result("up_to_date", &up_to_date);
+                    if !soft_reboot {
+                        ext::exit_if_partial_failure(output);
+                    }
+                    if soft_reboot {
+                        output.success_msg("Refresh", messages::EXT_SOFT_REBOOT_REQUESTED, &[]);
+                    } else if up_to_date {
+                        output.success_msg("Refresh", messages::EXT_UP_TO_DATE, &[]);
+                    } else {
+                        output.success_msg("Refresh", messages::EXT_REFRESHED, &[]);
+                    }
                 }
-                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
             }
-            json_ok(&output);
+            ext::exit_if_reboot_required(output);
+            output.json_ok();
         }
         Some(("enable", enable_matches)) => {
             let os_release = enable_matches.get_one::<String>("os_release").cloned();
-            let extensions: Vec<String> = enable_matches
-                .get_many::<String>("extensions")
-                .unwrap()
-                .cloned()
-                .collect();
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+            let extensions = config.resolve_extension_aliases(
+                enable_matches.get_many::<String>("extensions").unwrap(),
+            );
+            let force = enable_matches.get_flag("force");
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             let mut client = vl_ext::VarlinkClient::new(conn);
-            match client.enable(extensions, os_release).call() {
+            match client.enable(extensions, os_release, Some(force)).call() {
                 Ok(reply) => {
-                    if !output.is_json() {
-                        output.success(
-                            "Enable",
-                            &format!(
-                                "{} extension(s) enabled, {} failed",
-                                reply.enabled, reply.failed
-                            ),
-                        );
-                    }
+                    output.result("enabled", &reply.enabled);
+                    output.result("failed", &reply.failed);
+                    output.success_msg(
+                        "Enable",
+                        messages::EXT_ENABLE_SUMMARY,
+                        &[
+                            ("enabled", &reply.enabled.to_string()),
+                            ("failed", &reply.failed.to_string()),
+                        ],
+                    );
                 }
-                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
             }
-            json_ok(&output);
+            output.json_ok();
         }
         Some(("disable", disable_matches)) => {
             let os_release = disable_matches.get_one::<String>("os_release").cloned();
             let all = disable_matches.get_flag("all");
             let extensions: Option<Vec<String>> = disable_matches
                 .get_many::<String>("extensions")
-                .map(|values| values.cloned().collect());
-            let conn = varlink_client::connect_or_exit(&socket_address, &output);
+                .map(|values| config.resolve_extension_aliases(values));
+            let conn = varlink_client::connect_or_exit(socket_address, output);
             let mut client = vl_ext::VarlinkClient::new(conn);
             match client.disable(extensions, Some(all), os_release).call() {
                 Ok(reply) => {
-                    if !output.is_json() {
-                        output.success(
-                            "Disable",
-                            &format!(
-                                "{} extension(s) disabled, {} failed",
-                                reply.disabled, reply.failed
-                            ),
-                        );
-                    }
+                    output.result("disabled", &reply.disabled);
+                    output.result("failed", &reply.failed);
+                    output.success_msg(
+                        "Disable",
+                        messages::EXT_DISABLE_SUMMARY,
+                        &[
+                            ("disabled", &reply.disabled.to_string()),
+                            ("failed", &reply.failed.to_string()),
+                        ],
+                    );
                 }
-                Err(e) => varlink_client::exit_with_rpc_error(e, &output),
+                Err(e) => varlink_client::exit_with_rpc_error(e, output),
             }
-            json_ok(&output);
+            output.json_ok();
         }
 
         _ => {
@@ -754,33 +1371,97 @@ fn main() {
             println!("Use --help for more information or --version for version details");
         }
     }
+    output.finish();
 }
 
-/// Direct dispatch used when AVOCADO_TEST_MODE is set.
+/// The active runtime lines heading the text of `status`. The JSON result
+/// is the extension listing alone, so nothing is printed in JSON mode.
+fn print_active_runtime(
+    runtime: &str,
+    id: &str,
+    os_build_id: Option<&str>,
+    initramfs_build_id: Option<&str>,
+    output: &OutputManager,
+) {
+    if output.is_json() {
+        return;
+    }
+    let short_id = &id[..id.len().min(8)];
+    println!("Runtime: {runtime} ({short_id})");
+    if let Some(id) = os_build_id {
+        println!("Rootfs Build ID:    {id}");
+    }
+    if let Some(id) = initramfs_build_id {
+        println!("Initramfs Build ID: {id}");
+    }
+    println!();
+}
+
+/// Exit code for a failure before the command runs. `ext status --check`
+/// reports it as an error rather than as a needed refresh, and
+/// `ext check-update` as updates it cannot determine.
+fn startup_failure_code(matches: &clap::ArgMatches) -> i32 {
+    match matches.subcommand() {
+        Some(("ext", ext_matches)) => match ext_matches.subcommand() {
+            Some(("status", sub)) if sub.get_flag("check") => ext::STATUS_CHECK_ERROR,
+            Some(("check-update", _)) => ext::UPDATE_CHECK_UNKNOWN,
+            _ => 1,
+        },
+        _ => 1,
+    }
+}
+
+/// Direct dispatch used when AVOCADO_TEST_MODE is set or the mock backend is active.
 /// Calls service functions directly, bypassing the varlink daemon.
 /// This keeps existing integration tests (with mock executables) working
 /// without needing a live daemon process.
 fn handle_direct(matches: &clap::ArgMatches, config: &Config, output: &OutputManager) {
+    // Finish any enable/disable that power loss interrupted before running
+    // anything that reads the os-releases directories
+    if !unprivileged::is_unprivileged() {
+        link_journal::recover_or_warn(output);
+    }
     match matches.subcommand() {
         Some(("ext", ext_matches)) => {
             ext::handle_command(ext_matches, config, output);
         }
         Some(("hitl", hitl_matches)) => {
-            hitl::handle_command(hitl_matches, output);
+            hitl::handle_command(hitl_matches, config, output);
         }
+        #[cfg(feature = "network")]
         Some(("root-authority", _)) => {
-            root_authority::handle_command(config, output);
+            commands::root_authority::handle_command(config, output);
+        }
+        Some(("trust", trust_matches)) => {
+            commands::trust::handle_command(trust_matches, config, output);
         }
         Some(("runtime", runtime_matches)) => {
             runtime::handle_command(runtime_matches, config, output);
         }
+        Some(("plan", plan_matches)) => {
+            commands::plan::handle_plan(plan_matches, config, output);
+        }
+        Some(("apply", apply_matches)) => {
+            commands::plan::handle_apply(apply_matches, config, output);
+        }
+        #[cfg(feature = "daemon")]
         Some(("serve", serve_matches)) => {
             let address = serve_matches
                 .get_one::<String>("address")
                 .expect("address has a default value");
-            if let Err(e) = varlink_server::run_server(address, config.clone()) {
+            if let Err(e) = varlink_server::run_server(address, config.clone(), output) {
                 output.error("Server Error", &format!("Varlink server failed: {e}"));
-                std::process::exit(1);
+                output.exit(1);
+            }
+        }
+        #[cfg(feature = "dev")]
+        Some(("test-daemon", sub)) => {
+            let address = sub
+                .get_one::<String>("address")
+                .expect("address is required");
+            if let Err(e) = varlink_server::run_server(address, config.clone(), output) {
+                output.error("Server Error", &format!("Varlink server failed: {e}"));
+                output.exit(1);
             }
         }
         Some(("status", _)) => {
@@ -788,54 +1469,68 @@ fn handle_direct(matches: &clap::ArgMatches, config: &Config, output: &OutputMan
             // Show active runtime OS release info
             if let Ok(runtimes) = crate::service::runtime::list_runtimes(config) {
                 if let Some(active) = runtimes.iter().find(|r| r.active) {
-                    let short_id = &active.id[..active.id.len().min(8)];
-                    println!("Runtime: {} {} ({short_id})", active.name, active.version);
-                    if let Some(ref id) = active.os_build_id {
-                        println!("Rootfs Build ID:    {id}");
-                    }
-                    if let Some(ref id) = active.initramfs_build_id {
-                        println!("Initramfs Build ID: {id}");
-                    }
-                    println!();
+                    print_active_runtime(
+                        &format!("{} {}", active.name, active.version),
+                        &active.id,
+                        active.os_build_id.as_deref(),
+                        active.initramfs_build_id.as_deref(),
+                        output,
+                    );
                 }
             }
-            ext::status_extensions(config, output);
+            ext::status_extensions(config, output, false);
         }
-        Some(("merge", _)) => {
-            ext::merge_extensions_direct(output);
-            json_ok(output);
+        Some(("merge", sub)) if sub.get_flag("mount-only") => {
+            ext::mount_extensions_only(config, output);
+            output.json_ok();
+        }
+        Some(("merge", sub)) => {
+            ext::merge_extensions_direct(config, sub.get_flag("keep-going"), output);
+            output.json_ok();
         }
         Some(("unmerge", unmerge_matches)) => {
             let unmount = unmerge_matches.get_flag("unmount");
             ext::unmerge_extensions_direct(unmount, output);
-            json_ok(output);
+            output.json_ok();
         }
-        Some(("refresh", _)) => {
-            ext::refresh_extensions_direct(output);
-            json_ok(output);
+        Some(("refresh", refresh_matches)) => {
+            if refresh_matches.get_flag("soft-reboot") {
+                ext::soft_reboot_refresh_direct(config, output);
+            } else {
+                ext::refresh_extensions_direct(
+                    config,
+                    refresh_matches.get_flag("force"),
+                    refresh_matches.get_flag("keep-going"),
+                    output,
+                );
+            }
+            output.json_ok();
         }
         Some(("enable", enable_matches)) => {
             let os_release = enable_matches
                 .get_one::<String>("os_release")
                 .map(|s| s.as_str());
-            let extensions: Vec<&str> = enable_matches
-                .get_many::<String>("extensions")
-                .unwrap()
-                .map(|s| s.as_str())
-                .collect();
-            ext::enable_extensions(os_release, &extensions, config, output);
-            json_ok(output);
+            let extensions = config.resolve_extension_aliases(
+                enable_matches.get_many::<String>("extensions").unwrap(),
+            );
+            let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+            let force = enable_matches.get_flag("force");
+            ext::enable_extensions(os_release, &extensions, force, config, output);
+            output.json_ok();
         }
         Some(("disable", disable_matches)) => {
             let os_release = disable_matches
                 .get_one::<String>("os_release")
                 .map(|s| s.as_str());
             let all = disable_matches.get_flag("all");
-            let extensions: Option<Vec<&str>> = disable_matches
+            let extensions: Option<Vec<String>> = disable_matches
                 .get_many::<String>("extensions")
-                .map(|values| values.map(|s| s.as_str()).collect());
+                .map(|values| config.resolve_extension_aliases(values));
+            let extensions: Option<Vec<&str>> = extensions
+                .as_ref()
+                .map(|names| names.iter().map(String::as_str).collect());
             ext::disable_extensions(os_release, extensions.as_deref(), all, config, output);
-            json_ok(output);
+            output.json_ok();
         }
         _ => {
             println!(
@@ -846,13 +1541,7 @@ fn handle_direct(matches: &clap::ArgMatches, config: &Config, output: &OutputMan
             println!("Use --help for more information or --version for version details");
         }
     }
-}
-
-/// Emit a JSON success result when in JSON mode (no-op otherwise).
-/// Action commands that exit(1) on failure never reach this,
-/// so it only runs on success.
-fn json_ok(output: &OutputManager) {
-    if output.is_json() {
-        println!("{{\"status\":\"ok\"}}");
-    }
+    daemon_reload::flush_or_warn(output);
+    output.finish();
+    unprivileged::print_notes(output);
 }
@@ -0,0 +1,419 @@
+//! Maintenance windows for unattended extension changes.
+//!
+//! Devices on a production line must not refresh while a shift is running.
+//! `[avocado.maintenance] windows` lists cron-like expressions
+//! (`minute hour day-of-month month day-of-week`); a minute matching any of
+//! them is inside a window. Outside every window the daemon's auto-refresh
+//! holds its pending refresh, and `ext upgrade` queues the request in
+//! `maintenance-queue.json` in the avocado base directory for the daemon to
+//! run once a window opens. `ext upgrade --force` ignores the windows.
+//!
+//! Without windows every time is inside one.
+
+use crate::config::Config;
+#[cfg(feature = "daemon")]
+use crate::output::OutputManager;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "daemon")]
+use std::thread;
+#[cfg(feature = "daemon")]
+use std::time::Duration;
+use thiserror::Error;
+
+/// Queued upgrade file (in the avocado base directory).
+pub const QUEUE_FILENAME: &str = "maintenance-queue.json";
+
+/// How often the daemon checks whether a queued upgrade may run.
+#[cfg(feature = "daemon")]
+const QUEUE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How far ahead [`Schedule::next_open`] looks for the next window.
+const LOOKAHEAD_MINUTES: u64 = 366 * 24 * 60;
+
+#[derive(Error, Debug)]
+pub enum MaintenanceError {
+    #[error("Invalid maintenance window '{window}': {reason}")]
+    InvalidWindow { window: String, reason: String },
+
+    #[error("Invalid maintenance utc_offset '{value}': expected +HH:MM or -HH:MM")]
+    InvalidOffset { value: String },
+}
+
+/// Allowed values of one cron field, as a bit mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    mask: u64,
+    any: bool,
+}
+
+impl Field {
+    fn contains(self, value: u32) -> bool {
+        self.mask & (1 << value) != 0
+    }
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parse one cron field with values in `min..=max`. Items are `*`, `N`,
+/// `A-B` (wrapping around when `A > B`, e.g. hours `22-5`), optionally
+/// followed by `/STEP`, separated by commas. `names` are accepted for the
+/// values starting at `first_name`.
+fn parse_field(
+    text: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    first_name: u32,
+) -> Result<Field, String> {
+    let value = |v: &str| -> Result<u32, String> {
+        let lower = v.to_ascii_lowercase();
+        if let Some(i) = names.iter().position(|n| *n == lower) {
+            return Ok(first_name + i as u32);
+        }
+        let n: u32 = v.parse().map_err(|_| format!("'{v}' is not a number"))?;
+        if n < min || n > max {
+            return Err(format!("{n} is outside {min}-{max}"));
+        }
+        Ok(n)
+    };
+
+    let mut mask = 0u64;
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step in '{item}'"))?;
+                (range, step)
+            }
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                None => {
+                    let v = value(range)?;
+                    (v, if step > 1 { max } else { v })
+                }
+            },
+        };
+        let span = if end >= start {
+            end - start
+        } else {
+            max - start + 1 + end - min
+        };
+        for offset in (0..=span).step_by(step as usize) {
+            let mut v = start + offset;
+            if v > max {
+                v = v - max - 1 + min;
+            }
+            mask |= 1 << v;
+        }
+    }
+    Ok(Field {
+        mask,
+        any: text == "*",
+    })
+}
+
+/// One window: a cron expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Window {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl Window {
+    fn parse(expression: &str) -> Result<Self, MaintenanceError> {
+        let invalid = |reason: String| MaintenanceError::InvalidWindow {
+            window: expression.to_string(),
+            reason,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(format!(
+                "expected 5 fields (minute hour day-of-month month day-of-week), found {}",
+                fields.len()
+            )));
+        };
+        let mut weekday = parse_field(weekday, 0, 7, &DAY_NAMES, 0).map_err(invalid)?;
+        // 7 is Sunday too
+        if weekday.contains(7) {
+            weekday.mask |= 1;
+        }
+        Ok(Self {
+            minute: parse_field(minute, 0, 59, &[], 0).map_err(invalid)?,
+            hour: parse_field(hour, 0, 23, &[], 0).map_err(invalid)?,
+            day: parse_field(day, 1, 31, &[], 0).map_err(invalid)?,
+            month: parse_field(month, 1, 12, &MONTH_NAMES, 1).map_err(invalid)?,
+            weekday,
+        })
+    }
+
+    /// Whether the local minute `t` is inside the window. As in cron, a
+    /// restricted day of month and day of week match when either does.
+    fn matches(&self, t: &LocalTime) -> bool {
+        let day = match (self.day.any, self.weekday.any) {
+            (false, false) => self.day.contains(t.day) || self.weekday.contains(t.weekday),
+            _ => self.day.contains(t.day) && self.weekday.contains(t.weekday),
+        };
+        day && self.minute.contains(t.minute)
+            && self.hour.contains(t.hour)
+            && self.month.contains(t.month)
+    }
+}
+
+/// Broken-down local time of one minute.
+struct LocalTime {
+    minute: u32,
+    hour: u32,
+    day: u32,
+    month: u32,
+    /// 0 is Sunday
+    weekday: u32,
+}
+
+impl LocalTime {
+    fn at(local_secs: i64) -> Self {
+        let days = local_secs.div_euclid(86_400);
+        let minutes = local_secs.rem_euclid(86_400) / 60;
+        let (_, month, day) = crate::clock::civil_date(days);
+        Self {
+            minute: (minutes % 60) as u32,
+            hour: (minutes / 60) as u32,
+            day: day as u32,
+            month: month as u32,
+            // 1970-01-01 was a Thursday
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+}
+
+/// The configured maintenance windows.
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    windows: Vec<Window>,
+    offset_secs: i64,
+}
+
+impl Schedule {
+    /// Parse `windows`, evaluated at UTC plus `utc_offset` (`+HH:MM`).
+    pub fn parse(windows: &[String], utc_offset: Option<&str>) -> Result<Self, MaintenanceError> {
+        let offset_secs = match utc_offset {
+            Some(value) => parse_offset(value).ok_or_else(|| MaintenanceError::InvalidOffset {
+                value: value.to_string(),
+            })?,
+            None => 0,
+        };
+        Ok(Self {
+            windows: windows
+                .iter()
+                .map(|w| Window::parse(w))
+                .collect::<Result<_, _>>()?,
+            offset_secs,
+        })
+    }
+
+    /// Whether windows are configured at all.
+    #[cfg(feature = "daemon")]
+    pub fn is_restricted(&self) -> bool {
+        !self.windows.is_empty()
+    }
+
+    /// Whether `secs` (since the Unix epoch) is inside a window.
+    pub fn is_open(&self, secs: u64) -> bool {
+        if self.windows.is_empty() {
+            return true;
+        }
+        let local = LocalTime::at(secs as i64 + self.offset_secs);
+        self.windows.iter().any(|w| w.matches(&local))
+    }
+
+    /// Start of the next window at or after `secs`, within a year.
+    pub fn next_open(&self, secs: u64) -> Option<u64> {
+        if self.is_open(secs) {
+            return Some(secs);
+        }
+        let first_minute = (secs / 60 + 1) * 60;
+        (0..LOOKAHEAD_MINUTES)
+            .map(|i| first_minute + i * 60)
+            .find(|t| self.is_open(*t))
+    }
+}
+
+fn parse_offset(value: &str) -> Option<i64> {
+    let (sign, rest) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    let hours: i64 = hours.parse().ok().filter(|h| *h <= 14)?;
+    let minutes: i64 = minutes.parse().ok().filter(|m| *m < 60)?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// The schedule configured in `[avocado.maintenance]`.
+pub fn schedule(config: &Config) -> Result<Schedule, MaintenanceError> {
+    let settings = config.maintenance();
+    Schedule::parse(&settings.windows, settings.utc_offset.as_deref())
+}
+
+/// An `ext upgrade` waiting for the next maintenance window. The
+/// authentication token is not stored; the daemon uses its own
+/// `AVOCADO_TUF_AUTH_TOKEN`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedUpgrade {
+    pub names: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub offline: bool,
+    /// Seconds since the Unix epoch
+    pub queued_at: u64,
+}
+
+/// The queued upgrade, if any.
+#[cfg(feature = "daemon")]
+pub fn queued(base_dir: &Path) -> Option<QueuedUpgrade> {
+    let content = fs::read_to_string(base_dir.join(QUEUE_FILENAME)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Queue `upgrade`, replacing an upgrade queued earlier.
+pub fn queue(base_dir: &Path, upgrade: &QueuedUpgrade) -> std::io::Result<()> {
+    fs::create_dir_all(base_dir)?;
+    fs::write(
+        base_dir.join(QUEUE_FILENAME),
+        serde_json::to_string_pretty(upgrade).unwrap_or_default(),
+    )
+}
+
+/// Drop the queued upgrade.
+#[cfg(feature = "daemon")]
+pub fn clear(base_dir: &Path) {
+    let _ = fs::remove_file(base_dir.join(QUEUE_FILENAME));
+}
+
+/// Start the daemon's queue runner: once a minute, a queued upgrade runs
+/// if a window is open. Not started without windows or a queued upgrade.
+#[cfg(feature = "daemon")]
+pub fn spawn(config: &Config, output: &OutputManager) {
+    let base_dir = config.get_avocado_base_dir();
+    let schedule = match schedule(config) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            output.warning(&format!("Maintenance windows ignored: {e}"));
+            Schedule::default()
+        }
+    };
+    if !schedule.is_restricted() && queued(Path::new(&base_dir)).is_none() {
+        return;
+    }
+    let config = config.clone();
+    let output = output.detached();
+    thread::spawn(move || loop {
+        let base_path = Path::new(&base_dir);
+        if let Some(upgrade) = queued(base_path) {
+            if schedule.is_open(crate::clock::now()) {
+                clear(base_path);
+                let auth_token = std::env::var("AVOCADO_TUF_AUTH_TOKEN").ok();
+                match crate::service::ext::upgrade(
+                    &config,
+                    &upgrade.names,
+                    upgrade.url.as_deref(),
+                    auth_token.as_deref(),
+                    upgrade.offline,
+                    false,
+                    true,
+                    false,
+                ) {
+                    Ok(result) => match result.runtime_id {
+                        Some(id) => {
+                            output.log_info(&format!("Queued upgrade activated runtime {id}"))
+                        }
+                        None => output.log_info("Queued upgrade: nothing to upgrade"),
+                    },
+                    Err(e) => output.warning(&format!("Queued upgrade failed: {e}")),
+                }
+            }
+        }
+        thread::sleep(QUEUE_CHECK_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-10-15 (a Thursday) at `hour:minute` UTC.
+    fn at(hour: u64, minute: u64) -> u64 {
+        1_792_022_400 + hour * 3600 + minute * 60
+    }
+
+    fn schedule(windows: &[&str], offset: Option<&str>) -> Schedule {
+        let windows: Vec<String> = windows.iter().map(|w| w.to_string()).collect();
+        Schedule::parse(&windows, offset).unwrap()
+    }
+
+    #[test]
+    fn test_windows_match_cron_fields() {
+        assert_eq!(crate::clock::format_time(at(0, 0)), "2026-10-15 00:00 UTC");
+
+        let night = schedule(&["* 22-5 * * *"], None);
+        assert!(night.is_open(at(23, 30)));
+        assert!(night.is_open(at(3, 0)));
+        assert!(!night.is_open(at(12, 0)));
+        assert_eq!(night.next_open(at(12, 0)), Some(at(22, 0)));
+        assert_eq!(night.next_open(at(23, 0)), Some(at(23, 0)));
+
+        let weekend = schedule(&["* * * * sat,sun"], None);
+        assert!(!weekend.is_open(at(12, 0)));
+        assert_eq!(weekend.next_open(at(12, 0)), Some(at(0, 0) + 2 * 86_400));
+
+        // Thursday 12:00 UTC is 14:00 at +02:00
+        let afternoon = schedule(&["*/15 14 * * thu"], Some("+02:00"));
+        assert!(afternoon.is_open(at(12, 15)));
+        assert!(!afternoon.is_open(at(12, 16)));
+
+        assert!(Schedule::default().is_open(at(12, 0)));
+    }
+
+    #[test]
+    fn test_invalid_windows_are_rejected() {
+        let parse = |w: &str| Schedule::parse(&[w.to_string()], None);
+        assert!(parse("* * * *").is_err());
+        assert!(parse("60 * * * *").is_err());
+        assert!(parse("* * * * funday").is_err());
+        assert!(parse("*/0 * * * *").is_err());
+        assert!(Schedule::parse(&[], Some("2:00")).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "daemon")]
+    fn test_queue_roundtrip() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        assert!(queued(tmp.path()).is_none());
+        let upgrade = QueuedUpgrade {
+            names: vec!["app".to_string()],
+            url: None,
+            offline: true,
+            queued_at: 1,
+        };
+        queue(tmp.path(), &upgrade).unwrap();
+        assert_eq!(queued(tmp.path()), Some(upgrade));
+        clear(tmp.path());
+        assert!(queued(tmp.path()).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::config::ChecksumAlgorithm;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -17,6 +18,11 @@ pub const AVOCADO_IMAGE_NAMESPACE: uuid::Uuid = uuid::uuid!("7488fa35-6390-425b-
 pub struct OsBundleRef {
     pub image_id: String,
     pub sha256: String,
+    /// Digests in the other algorithms of `[avocado.checksum]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blake3: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub os_build_id: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -53,6 +59,12 @@ pub struct ManifestExtension {
     /// SHA256 hash of the extension image for integrity verification
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sha256: Option<String>,
+    /// SHA-512 hash of the extension image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    /// BLAKE3 hash of the extension image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blake3: Option<String>,
     /// Build-time default activation state. Absent/true = auto-activated
     /// at refresh; false = present-but-inactive (user must opt in via
     /// `avocadoctl ext enable`). Skipped from JSON when true to keep
@@ -64,6 +76,17 @@ pub struct ManifestExtension {
     pub enabled: bool,
 }
 
+impl OsBundleRef {
+    /// Published `algorithm` digest of the image.
+    pub fn digest(&self, algorithm: ChecksumAlgorithm) -> Option<&str> {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => Some(&self.sha256),
+            ChecksumAlgorithm::Sha512 => self.sha512.as_deref(),
+            ChecksumAlgorithm::Blake3 => self.blake3.as_deref(),
+        }
+    }
+}
+
 impl ManifestExtension {
     fn default_enabled() -> bool {
         true
@@ -73,6 +96,15 @@ impl ManifestExtension {
         *v
     }
 
+    /// Published `algorithm` digest of the image.
+    pub fn digest(&self, algorithm: ChecksumAlgorithm) -> Option<&str> {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => self.sha256.as_deref(),
+            ChecksumAlgorithm::Sha512 => self.sha512.as_deref(),
+            ChecksumAlgorithm::Blake3 => self.blake3.as_deref(),
+        }
+    }
+
     /// Returns true if this extension image is a KAB file.
     pub fn is_kab(&self) -> bool {
         self.image_type.as_deref() == Some("kab")
@@ -198,6 +230,8 @@ mod tests {
                 image_id: Some("a1b2c3d4-e5f6-5789-abcd-ef0123456789".to_string()),
                 image_type: None,
                 sha256: None,
+                sha512: None,
+                blake3: None,
                 enabled: true,
             }],
             os_bundle: None,
@@ -326,6 +360,8 @@ mod tests {
             image_id: Some("a1b2c3d4-e5f6-5789-abcd-ef0123456789".to_string()),
             image_type: None,
             sha256: None,
+            sha512: None,
+            blake3: None,
             enabled: true,
         };
         let base = Path::new("/var/lib/avocado");
@@ -378,6 +414,8 @@ mod tests {
         manifest.os_bundle = Some(OsBundleRef {
             image_id: "deadbeef-1234-5678-abcd-000000000000".to_string(),
             sha256: "abc".to_string(),
+            sha512: None,
+            blake3: None,
             os_build_id: None,
             initramfs_build_id: None,
         });
@@ -439,6 +477,8 @@ mod tests {
             image_id: None,
             image_type: None,
             sha256: None,
+            sha512: None,
+            blake3: None,
             enabled: true,
         };
         let base = Path::new("/var/lib/avocado");
@@ -454,6 +494,8 @@ mod tests {
             image_id: None,
             image_type: None,
             sha256: None,
+            sha512: None,
+            blake3: None,
             enabled: true,
         };
         assert!(!raw_ext.is_kab());
@@ -464,6 +506,8 @@ mod tests {
             image_id: None,
             image_type: Some("kab".to_string()),
             sha256: None,
+            sha512: None,
+            blake3: None,
             enabled: true,
         };
         assert!(kab_ext.is_kab());
@@ -477,6 +521,8 @@ mod tests {
             image_id: Some("a1b2c3d4-e5f6-5789-abcd-ef0123456789".to_string()),
             image_type: Some("kab".to_string()),
             sha256: None,
+            sha512: None,
+            blake3: None,
             enabled: true,
         };
         let base = Path::new("/var/lib/avocado");
@@ -495,6 +541,8 @@ mod tests {
             image_id: None,
             image_type: Some("kab".to_string()),
             sha256: None,
+            sha512: None,
+            blake3: None,
             enabled: true,
         };
         let base = Path::new("/var/lib/avocado");
@@ -0,0 +1,96 @@
+//! Extensions a `--keep-going` merge left out.
+//!
+//! With `--keep-going` (or `keep_going = true` in `[avocado.ext]`) a merge
+//! or refresh skips an extension that cannot be prepared, such as an image
+//! that fails to loop-mount or a release file without `ID=`, and merges the
+//! rest. The skipped extensions and their errors are recorded in
+//! `/run/avocado/merge-failures`, replaced by every merge, and the command
+//! exits with [`EXIT_PARTIAL_FAILURE`].
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FAILURES_FILENAME: &str = "merge-failures";
+
+/// Exit code of merge/refresh when `--keep-going` left extensions out.
+pub const EXIT_PARTIAL_FAILURE: i32 = 4;
+
+/// An extension left out of a merge, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Failure {
+    /// `<name>-<version>`, or the bare name of an unversioned extension
+    pub extension: String,
+    pub error: String,
+}
+
+impl Failure {
+    pub fn new(extension: impl Into<String>, error: impl ToString) -> Self {
+        Self {
+            extension: extension.into(),
+            error: error.to_string().trim_end().to_string(),
+        }
+    }
+}
+
+/// `/run/avocado/merge-failures`, or under `$TMPDIR/avocado` in test mode.
+fn path() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/{FAILURES_FILENAME}"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path(&format!(
+            "/run/avocado/{FAILURES_FILENAME}"
+        )))
+    }
+}
+
+/// Record the failures of the current merge, replacing the last merge's
+/// record; none removes it.
+pub fn record(failures: &[Failure]) -> std::io::Result<()> {
+    record_at(&path(), failures)
+}
+
+fn record_at(path: &Path, failures: &[Failure]) -> std::io::Result<()> {
+    if failures.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(failures).map_err(std::io::Error::other)?;
+    fs::write(path, json + "\n")
+}
+
+/// Extensions the last merge left out.
+pub fn recorded() -> Vec<Failure> {
+    recorded_from(&path())
+}
+
+fn recorded_from(path: &Path) -> Vec<Failure> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_replaces_previous_record() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("avocado").join(FAILURES_FILENAME);
+        let broken = Failure::new("broken-1.0", "loop device setup failed");
+        record_at(&path, &[broken.clone(), Failure::new("tools", "no ID=")]).unwrap();
+        record_at(&path, std::slice::from_ref(&broken)).unwrap();
+        assert_eq!(recorded_from(&path), [broken]);
+        record_at(&path, &[]).unwrap();
+        assert!(!path.exists());
+        assert!(recorded_from(&path).is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+//! History of the merged extension set, for `ext status --since`.
+//!
+//! Every host merge, refresh and unmerge appends a snapshot of the merged
+//! extensions (name, version and image fingerprint) with the time and the
+//! kernel's boot ID to `/var/lib/avocado/merge-history.jsonl`, keeping the
+//! last [`MAX_SNAPSHOTS`]. Comparing the snapshot in place at a reference
+//! point with the latest one tells which extensions were merged, unmerged or
+//! changed since then. The reference is either a time or `boot`: the last
+//! snapshot taken during an earlier boot, so a reboot that merges nothing
+//! new reports no changes.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const HISTORY_FILENAME: &str = "merge-history.jsonl";
+
+/// Snapshots kept; older ones are dropped when a new one is appended.
+pub const MAX_SNAPSHOTS: usize = 200;
+
+/// A merged extension.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergedExtension {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// [`crate::merge_inputs::fingerprint`] of the image
+    pub fingerprint: String,
+}
+
+impl MergedExtension {
+    /// `<name>-<version>`, or the bare name of an unversioned extension
+    pub fn versioned_name(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}-{version}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// The merged extensions after an operation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    pub boot_id: String,
+    /// `merge`, `refresh` or `unmerge`
+    pub operation: String,
+    pub extensions: Vec<MergedExtension>,
+}
+
+/// Point `ext status --since` compares against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    /// Seconds since the Unix epoch
+    Time(u64),
+    /// The start of the current boot
+    Boot,
+}
+
+impl Reference {
+    /// Parse `boot`, seconds since the Unix epoch, `YYYY-MM-DD` or
+    /// `YYYY-MM-DD HH:MM[:SS]` (UTC; `T` may separate date and time).
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if value == "boot" {
+            return Ok(Self::Boot);
+        }
+        if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            return value
+                .parse()
+                .map(Self::Time)
+                .map_err(|e| format!("invalid timestamp '{value}': {e}"));
+        }
+        crate::clock::parse_utc(value)
+            .map(Self::Time)
+            .ok_or_else(|| {
+                format!(
+                "'{value}' is not 'boot', a Unix timestamp or a YYYY-MM-DD [HH:MM[:SS]] UTC time"
+            )
+            })
+    }
+}
+
+/// An extension whose image differs between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Changed {
+    pub name: String,
+    /// Versioned name before
+    pub from: String,
+    /// Versioned name now
+    pub to: String,
+}
+
+/// What changed in the merged set between two snapshots, by versioned name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Changes {
+    pub merged: Vec<String>,
+    pub unmerged: Vec<String>,
+    pub changed: Vec<Changed>,
+    pub unchanged: Vec<String>,
+}
+
+impl Changes {
+    /// Compare the merged extensions `before` with those `now`. An extension
+    /// merged in both with another version or image counts as changed.
+    pub fn between(before: &[MergedExtension], now: &[MergedExtension]) -> Self {
+        let mut changes = Self::default();
+        for current in now {
+            match before.iter().find(|ext| ext.name == current.name) {
+                None => changes.merged.push(current.versioned_name()),
+                Some(previous)
+                    if previous.version != current.version
+                        || previous.fingerprint != current.fingerprint =>
+                {
+                    changes.changed.push(Changed {
+                        name: current.name.clone(),
+                        from: previous.versioned_name(),
+                        to: current.versioned_name(),
+                    })
+                }
+                Some(_) => changes.unchanged.push(current.versioned_name()),
+            }
+        }
+        changes.unmerged = before
+            .iter()
+            .filter(|previous| !now.iter().any(|ext| ext.name == previous.name))
+            .map(MergedExtension::versioned_name)
+            .collect();
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.merged.is_empty() && self.unmerged.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// `/var/lib/avocado/merge-history.jsonl`, or under `$TMPDIR/avocado` in
+/// test mode.
+fn path() -> PathBuf {
+    crate::link_journal::state_dir().join(HISTORY_FILENAME)
+}
+
+/// The kernel's ID of the current boot. In test mode it is read from
+/// `$TMPDIR/avocado/boot_id`, so tests can simulate a reboot.
+pub fn boot_id() -> String {
+    let path = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        crate::link_journal::state_dir().join("boot_id")
+    } else {
+        PathBuf::from("/proc/sys/kernel/random/boot_id")
+    };
+    fs::read_to_string(path)
+        .map(|id| id.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Append a snapshot of the merged `extensions` after `operation`.
+pub fn record(operation: &str, extensions: Vec<MergedExtension>) -> std::io::Result<()> {
+    let snapshot = Snapshot {
+        at: crate::clock::now(),
+        boot_id: boot_id(),
+        operation: operation.to_string(),
+        extensions,
+    };
+    record_at(&path(), &snapshot)
+}
+
+fn record_at(path: &Path, snapshot: &Snapshot) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut lines: Vec<String> = fs::read_to_string(path)
+        .map(|content| content.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    lines.push(serde_json::to_string(snapshot).map_err(std::io::Error::other)?);
+    let keep = lines.len().saturating_sub(MAX_SNAPSHOTS);
+    if keep == 0 {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        return writeln!(file, "{}", lines[lines.len() - 1]);
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    fs::write(&tmp, lines[keep..].join("\n") + "\n")?;
+    fs::rename(tmp, path)
+}
+
+/// Recorded snapshots, oldest first. Unreadable lines are skipped.
+pub fn snapshots() -> Vec<Snapshot> {
+    snapshots_from(&path())
+}
+
+/// The merged set recorded last, empty when nothing was recorded.
+pub fn latest() -> Vec<MergedExtension> {
+    snapshots()
+        .pop()
+        .map(|snapshot| snapshot.extensions)
+        .unwrap_or_default()
+}
+
+fn snapshots_from(path: &Path) -> Vec<Snapshot> {
+    fs::read_to_string(path)
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The snapshot in place at `reference`, if any was taken before it: the
+/// last one taken at or before the time, or the last one of an earlier boot.
+pub fn snapshot_at<'a>(
+    snapshots: &'a [Snapshot],
+    reference: Reference,
+    current_boot_id: &str,
+) -> Option<&'a Snapshot> {
+    match reference {
+        Reference::Time(at) => snapshots.iter().rev().find(|s| s.at <= at),
+        Reference::Boot => snapshots
+            .iter()
+            .rev()
+            .find(|s| s.boot_id != current_boot_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext(name: &str, version: &str, fingerprint: &str) -> MergedExtension {
+        MergedExtension {
+            name: name.to_string(),
+            version: Some(version.to_string()),
+            fingerprint: fingerprint.to_string(),
+        }
+    }
+
+    fn snapshot(at: u64, boot_id: &str, extensions: Vec<MergedExtension>) -> Snapshot {
+        Snapshot {
+            at,
+            boot_id: boot_id.to_string(),
+            operation: "merge".to_string(),
+            extensions,
+        }
+    }
+
+    #[test]
+    fn test_reference_parse() {
+        assert_eq!(Reference::parse("boot"), Ok(Reference::Boot));
+        assert_eq!(
+            Reference::parse("1700000000"),
+            Ok(Reference::Time(1_700_000_000))
+        );
+        assert_eq!(
+            Reference::parse("2023-11-14"),
+            Ok(Reference::Time(1_699_920_000))
+        );
+        assert_eq!(
+            Reference::parse("2023-11-14 22:13"),
+            Ok(Reference::Time(1_699_999_980))
+        );
+        assert_eq!(
+            Reference::parse("2023-11-14T22:13:20Z"),
+            Ok(Reference::Time(1_700_000_000))
+        );
+        assert_eq!(
+            Reference::parse("2024-02-29"),
+            Ok(Reference::Time(1_709_164_800))
+        );
+        for invalid in [
+            "",
+            "yesterday",
+            "2023-02-29",
+            "2023-11-14 24:00",
+            "2023-11-14 1:00",
+        ] {
+            assert!(Reference::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_changes_between_snapshots() {
+        let before = [
+            ext("app", "1.0", "a"),
+            ext("base", "2.0", "b"),
+            ext("tools", "1.0", "t"),
+        ];
+        let now = [
+            ext("app", "1.1", "a2"),
+            ext("base", "2.0", "b"),
+            ext("debug", "0.1", "d"),
+        ];
+        let changes = Changes::between(&before, &now);
+        assert_eq!(changes.merged, ["debug-0.1"]);
+        assert_eq!(changes.unmerged, ["tools-1.0"]);
+        assert_eq!(
+            changes.changed,
+            [Changed {
+                name: "app".to_string(),
+                from: "app-1.0".to_string(),
+                to: "app-1.1".to_string(),
+            }]
+        );
+        assert_eq!(changes.unchanged, ["base-2.0"]);
+
+        // A rebuilt image with the same version counts as changed
+        let rebuilt = Changes::between(&[ext("app", "1.0", "a")], &[ext("app", "1.0", "x")]);
+        assert_eq!(rebuilt.changed.len(), 1);
+        assert!(Changes::between(&now, &now).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_at_reference() {
+        let snapshots = [
+            snapshot(100, "boot-1", vec![ext("app", "1.0", "a")]),
+            snapshot(200, "boot-1", vec![ext("app", "1.1", "a")]),
+            snapshot(300, "boot-2", vec![ext("app", "1.2", "a")]),
+        ];
+        let at = |reference| snapshot_at(&snapshots, reference, "boot-2").map(|s| s.at);
+        assert_eq!(at(Reference::Time(50)), None);
+        assert_eq!(at(Reference::Time(100)), Some(100));
+        assert_eq!(at(Reference::Time(299)), Some(200));
+        assert_eq!(at(Reference::Boot), Some(200));
+        assert_eq!(
+            snapshot_at(&snapshots[..1], Reference::Boot, "boot-1"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_record_keeps_last_snapshots() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("avocado").join(HISTORY_FILENAME);
+        for at in 0..MAX_SNAPSHOTS as u64 + 5 {
+            record_at(&path, &snapshot(at, "boot", Vec::new())).unwrap();
+        }
+        let snapshots = snapshots_from(&path);
+        assert_eq!(snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(snapshots[0].at, 5);
+        assert_eq!(snapshots.last().unwrap().at, MAX_SNAPSHOTS as u64 + 4);
+    }
+}
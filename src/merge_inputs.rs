@@ -0,0 +1,378 @@
+//! What the last successful merge was made from.
+//!
+//! After merging on the host, avocadoctl records the merged extensions with
+//! a fingerprint of each image, the HITL mounts and the mutability settings
+//! in `/run/avocado/merge-inputs.json`. `refresh` compares the record with
+//! what a merge would use now and, when nothing differs and systemd still
+//! has those extensions merged, reports the extensions as up to date instead
+//! of unmerging and merging them again. Unmerging removes the record, and
+//! so does a reboot, so the first refresh after either always runs.
+//!
+//! When only some extensions differ, [`MergeInputs::delta_since`] names the
+//! ones leaving and entering the merged set so `refresh` can re-merge just
+//! those instead of running the full unmerge/merge cycle, and
+//! [`MergeInputs::image_changes`] backs `ext diff`.
+
+use crate::hash::{hex_encode, spot_hash_file};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Record file name (next to the HITL mount directory).
+pub const RECORD_FILENAME: &str = "merge-inputs.json";
+
+/// Bytes hashed from each end of an image file.
+const SPOT_BYTES: u64 = 64 * 1024;
+
+/// One merged extension.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergedImage {
+    /// `<name>-<version>`, or the bare name of an unversioned extension
+    pub name: String,
+    pub sysext: bool,
+    pub confext: bool,
+    pub path: String,
+    /// [`fingerprint`] of the image file or directory
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeInputs {
+    /// Merged extensions, in merge order.
+    pub images: Vec<MergedImage>,
+    /// HITL mounts as `<extension> <server>:<port>`, sorted.
+    #[serde(default)]
+    pub hitl_mounts: Vec<String>,
+    pub sysext_mutable: String,
+    pub confext_mutable: String,
+}
+
+/// Extensions a partial refresh touches. A changed extension both leaves
+/// (with its old image) and enters (with its new one).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeDelta {
+    /// Removed or changed since the last merge
+    pub leaving: Vec<String>,
+    /// Added or changed since the last merge
+    pub entering: Vec<String>,
+}
+
+/// How one extension differs from the last merge.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "kebab-case")]
+pub enum ImageChange {
+    /// Not merged last time
+    Added { name: String },
+    /// Merged last time, no longer enabled
+    Removed { name: String },
+    /// A different image or image contents
+    Changed { name: String },
+    /// Merged as a sysext, a confext or both, differently than last time
+    Retyped { name: String },
+}
+
+impl std::fmt::Display for ImageChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageChange::Added { name } => write!(f, "{name} is newly enabled"),
+            ImageChange::Removed { name } => write!(f, "{name} is no longer enabled"),
+            ImageChange::Changed { name } => write!(f, "{name} changed"),
+            ImageChange::Retyped { name } => write!(f, "{name} changed type"),
+        }
+    }
+}
+
+/// Location of the record, respecting AVOCADO_TEST_MODE and user mode.
+pub fn record_path() -> PathBuf {
+    let hitl_dir = crate::hitl_health::hitl_dir();
+    hitl_dir
+        .parent()
+        .map(|dir| dir.join(RECORD_FILENAME))
+        .unwrap_or(hitl_dir)
+}
+
+impl MergeInputs {
+    /// The recorded inputs of the last merge, if any.
+    pub fn load() -> Option<Self> {
+        fs::read_to_string(record_path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = record_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            path,
+            serde_json::to_string_pretty(self).unwrap_or_default() + "\n",
+        )
+    }
+
+    /// Forget the last merge.
+    pub fn clear() {
+        let _ = fs::remove_file(record_path());
+    }
+
+    /// What changed between `previous` and these inputs, or `None` when a
+    /// merge would use exactly what it used before.
+    pub fn changes_since(&self, previous: &MergeInputs) -> Option<String> {
+        let mut changes: Vec<String> = self
+            .image_changes(previous)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let order = |inputs: &MergeInputs| -> Vec<String> {
+            inputs.images.iter().map(|i| i.name.clone()).collect()
+        };
+        if changes.is_empty() && order(self) != order(previous) {
+            changes.push("merge order changed".to_string());
+        }
+        if self.hitl_mounts != previous.hitl_mounts {
+            changes.push("HITL mounts changed".to_string());
+        }
+        if self.sysext_mutable != previous.sysext_mutable
+            || self.confext_mutable != previous.confext_mutable
+        {
+            changes.push("mutable settings changed".to_string());
+        }
+        (!changes.is_empty()).then(|| changes.join("; "))
+    }
+
+    /// The extensions added, changed or removed since `previous`: the
+    /// current ones in merge order, then the removed ones.
+    pub fn image_changes(&self, previous: &MergeInputs) -> Vec<ImageChange> {
+        let mut changes = Vec::new();
+        for image in &self.images {
+            let name = image.name.clone();
+            match previous.images.iter().find(|p| p.name == image.name) {
+                None => changes.push(ImageChange::Added { name }),
+                Some(p) if p.path != image.path || p.fingerprint != image.fingerprint => {
+                    changes.push(ImageChange::Changed { name })
+                }
+                Some(p) if p.sysext != image.sysext || p.confext != image.confext => {
+                    changes.push(ImageChange::Retyped { name })
+                }
+                Some(_) => {}
+            }
+        }
+        for image in &previous.images {
+            if !self.images.iter().any(|i| i.name == image.name) {
+                changes.push(ImageChange::Removed {
+                    name: image.name.clone(),
+                });
+            }
+        }
+        changes
+    }
+
+    /// The extensions to re-merge to get from `previous` to these inputs, or
+    /// why only a full refresh can. HITL mounts, mutability and the relative
+    /// order of the extensions kept have to be unchanged.
+    pub fn delta_since(&self, previous: &MergeInputs) -> Result<MergeDelta, String> {
+        if self.hitl_mounts != previous.hitl_mounts {
+            return Err("HITL mounts changed".to_string());
+        }
+        if self.sysext_mutable != previous.sysext_mutable
+            || self.confext_mutable != previous.confext_mutable
+        {
+            return Err("mutable settings changed".to_string());
+        }
+        let kept = |inputs: &MergeInputs, other: &MergeInputs| -> Vec<String> {
+            inputs
+                .images
+                .iter()
+                .filter(|i| other.images.iter().any(|o| o.name == i.name))
+                .map(|i| i.name.clone())
+                .collect()
+        };
+        if kept(self, previous) != kept(previous, self) {
+            return Err("merge order changed".to_string());
+        }
+
+        let mut delta = MergeDelta::default();
+        for image in &previous.images {
+            match self.images.iter().find(|i| i.name == image.name) {
+                Some(current) if current == image => {}
+                _ => delta.leaving.push(image.name.clone()),
+            }
+        }
+        for image in &self.images {
+            match previous.images.iter().find(|p| p.name == image.name) {
+                Some(p) if p == image => {}
+                _ => delta.entering.push(image.name.clone()),
+            }
+        }
+        Ok(delta)
+    }
+}
+
+/// Fingerprint of an extension image. Image files hash their size, the
+/// first and last 64 KiB and their modification time; directories hash the
+/// path, size and modification time of every entry below them. Symlinks are
+/// not followed, except for `path` itself.
+pub fn fingerprint(path: &Path) -> String {
+    let mut hasher = Sha256::new();
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => walk(path, Path::new(""), &mut hasher),
+        Ok(meta) => {
+            hasher.update(spot_hash_file(path, SPOT_BYTES).unwrap_or_default());
+            hasher.update(mtime_nanos(&meta).to_le_bytes());
+        }
+        Err(_) => hasher.update(b"missing"),
+    }
+    hex_encode(&hasher.finalize())
+}
+
+fn mtime_nanos(meta: &fs::Metadata) -> u128 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos())
+}
+
+fn walk(dir: &Path, relative: &Path, hasher: &mut Sha256) {
+    let Ok(entries) = crate::ordering::read_dir_sorted(dir) else {
+        return;
+    };
+    for entry in entries {
+        let Ok(meta) = fs::symlink_metadata(entry.path()) else {
+            continue;
+        };
+        let relative = relative.join(entry.file_name());
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(meta.len().to_le_bytes());
+        hasher.update(mtime_nanos(&meta).to_le_bytes());
+        if meta.file_type().is_symlink() {
+            if let Ok(target) = fs::read_link(entry.path()) {
+                hasher.update(target.to_string_lossy().as_bytes());
+            }
+        } else if meta.is_dir() {
+            walk(&entry.path(), &relative, hasher);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(name: &str, fingerprint: &str) -> MergedImage {
+        MergedImage {
+            name: name.to_string(),
+            sysext: true,
+            confext: false,
+            path: format!("/var/lib/avocado/images/{name}"),
+            fingerprint: fingerprint.to_string(),
+        }
+    }
+
+    fn inputs(images: Vec<MergedImage>) -> MergeInputs {
+        MergeInputs {
+            images,
+            hitl_mounts: Vec::new(),
+            sysext_mutable: "ephemeral".to_string(),
+            confext_mutable: "ephemeral".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_image_changes() {
+        let before = inputs(vec![image("app-1.0", "a"), image("base-1.0", "b")]);
+        let mut retyped = image("base-1.0", "b");
+        retyped.confext = true;
+        let after = inputs(vec![image("app-1.1", "c"), retyped]);
+        assert_eq!(
+            after.image_changes(&before),
+            vec![
+                ImageChange::Added {
+                    name: "app-1.1".to_string()
+                },
+                ImageChange::Retyped {
+                    name: "base-1.0".to_string()
+                },
+                ImageChange::Removed {
+                    name: "app-1.0".to_string()
+                },
+            ]
+        );
+        assert!(before.image_changes(&before.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let before = inputs(vec![image("app-1.0", "a"), image("base-1.0", "b")]);
+        assert_eq!(before.changes_since(&before.clone()), None);
+
+        let after = inputs(vec![image("app-1.1", "c"), image("base-1.0", "x")]);
+        assert_eq!(
+            after.changes_since(&before).as_deref(),
+            Some("app-1.1 is newly enabled; base-1.0 changed; app-1.0 is no longer enabled")
+        );
+
+        let mut reordered = inputs(vec![image("base-1.0", "b"), image("app-1.0", "a")]);
+        reordered.hitl_mounts = vec!["app 10.0.0.1:2049".to_string()];
+        assert_eq!(
+            reordered.changes_since(&before).as_deref(),
+            Some("merge order changed; HITL mounts changed")
+        );
+    }
+
+    #[test]
+    fn test_delta_since() {
+        let before = inputs(vec![image("app-1.0", "a"), image("base-1.0", "b")]);
+        assert_eq!(before.delta_since(&before), Ok(MergeDelta::default()));
+
+        let after = inputs(vec![
+            image("app-1.1", "c"),
+            image("base-1.0", "x"),
+            image("tools-2.0", "t"),
+        ]);
+        assert_eq!(
+            after.delta_since(&before),
+            Ok(MergeDelta {
+                leaving: vec!["app-1.0".to_string(), "base-1.0".to_string()],
+                entering: vec![
+                    "app-1.1".to_string(),
+                    "base-1.0".to_string(),
+                    "tools-2.0".to_string()
+                ],
+            })
+        );
+
+        let reordered = inputs(vec![image("base-1.0", "b"), image("app-1.0", "a")]);
+        assert_eq!(
+            reordered.delta_since(&before),
+            Err("merge order changed".to_string())
+        );
+        let mut mounted = before.clone();
+        mounted.hitl_mounts = vec!["app 10.0.0.1:2049".to_string()];
+        assert_eq!(
+            mounted.delta_since(&before),
+            Err("HITL mounts changed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_tracks_contents() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path().join("app-1.0");
+        fs::create_dir_all(dir.join("usr/bin")).unwrap();
+        fs::write(dir.join("usr/bin/app"), "v1").unwrap();
+        let first = fingerprint(&dir);
+        assert_eq!(fingerprint(&dir), first);
+
+        fs::write(dir.join("usr/bin/app"), "v2 longer").unwrap();
+        assert_ne!(fingerprint(&dir), first);
+
+        let raw = temp.path().join("app-1.0.raw");
+        fs::write(&raw, "image").unwrap();
+        let raw_first = fingerprint(&raw);
+        fs::write(&raw, "IMAGE").unwrap();
+        assert_ne!(fingerprint(&raw), raw_first);
+        assert_ne!(fingerprint(&temp.path().join("missing.raw")), raw_first);
+    }
+}
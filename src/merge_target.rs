@@ -0,0 +1,255 @@
+//! Merging extensions into a container or chroot instead of the host.
+//!
+//! `ext merge --target <machine>` activates the host's enabled extensions
+//! inside a running systemd-nspawn container: each linked image is
+//! bind-mounted into the container's `/run/extensions` or `/run/confexts`
+//! with `machinectl bind`, and systemd-sysext / systemd-confext run inside
+//! it through `systemd-run -M`. `--target /path` does the same for a chroot
+//! directory: images are bind-mounted below the directory and the host's
+//! tools merge into it with `--root=`.
+//!
+//! Host-wide merge steps (depmod, modprobe, AVOCADO_ON_MERGE commands, the
+//! host daemon-reload, reboot requests) are skipped for targets; systemd
+//! inside a container reloads its own units after the merge.
+
+use crate::commands::ext::SystemdError;
+use crate::error::IoError;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Where `ext merge --target` activates extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeTarget {
+    /// A running systemd-nspawn container, by machine name.
+    Machine(String),
+    /// A chroot directory.
+    Root(PathBuf),
+}
+
+impl MergeTarget {
+    /// Parse a `--target` value: an absolute path names a chroot directory,
+    /// anything else a machine.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if value.starts_with('/') {
+            return Ok(MergeTarget::Root(PathBuf::from(value)));
+        }
+        let valid = !value.is_empty()
+            && value.len() <= 64
+            && !value.starts_with(['.', '-'])
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if valid {
+            Ok(MergeTarget::Machine(value.to_string()))
+        } else {
+            Err(format!(
+                "Invalid merge target '{value}': expected a machine name or an absolute chroot path"
+            ))
+        }
+    }
+
+    /// Program and arguments running `program args...` against the target.
+    pub fn command(&self, program: &str, args: &[&str]) -> (String, Vec<String>) {
+        match self {
+            MergeTarget::Machine(machine) => {
+                let mut wrapped: Vec<String> = [
+                    "-M",
+                    machine.as_str(),
+                    "--wait",
+                    "--pipe",
+                    "--quiet",
+                    "--",
+                    program,
+                ]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+                wrapped.extend(args.iter().map(|s| s.to_string()));
+                ("systemd-run".to_string(), wrapped)
+            }
+            MergeTarget::Root(root) => {
+                let mut with_root: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+                with_root.push(format!("--root={}", root.display()));
+                (program.to_string(), with_root)
+            }
+        }
+    }
+
+    /// Make the extension `source`, linked on the host as `name` in
+    /// `dir` (`/run/extensions` or `/run/confexts`), visible at the same
+    /// place inside the target.
+    pub fn publish(
+        &self,
+        dir: &str,
+        name: &str,
+        source: &Path,
+        verbose: bool,
+    ) -> Result<(), SystemdError> {
+        let source_str = source.to_string_lossy();
+        let destination = format!("{dir}/{name}");
+        match self {
+            MergeTarget::Machine(machine) => run_machinectl(
+                &[
+                    "bind",
+                    "--read-only",
+                    "--mkdir",
+                    machine,
+                    &source_str,
+                    &destination,
+                ],
+                verbose,
+            ),
+            MergeTarget::Root(root) => {
+                if !root.is_dir() {
+                    return Err(SystemdError::ConfigurationError {
+                        message: format!("Chroot target {} is not a directory", root.display()),
+                    });
+                }
+                let mount_point = root.join(destination.trim_start_matches('/'));
+                create_mount_point(&mount_point, source.is_dir())?;
+                run_rbind_mount(&source_str, &mount_point.to_string_lossy(), verbose)
+            }
+        }
+    }
+}
+
+impl fmt::Display for MergeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeTarget::Machine(machine) => write!(f, "container '{machine}'"),
+            MergeTarget::Root(root) => write!(f, "chroot {}", root.display()),
+        }
+    }
+}
+
+/// Create an empty file or directory to bind-mount an image or directory
+/// extension onto.
+fn create_mount_point(path: &Path, directory: bool) -> Result<(), SystemdError> {
+    let io_error = |e| {
+        SystemdError::from(IoError {
+            action: "create mount point".to_string(),
+            path: path.to_path_buf(),
+            source: e,
+        })
+    };
+    if directory {
+        return fs::create_dir_all(path).map_err(io_error);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    if !path.exists() {
+        fs::File::create(path).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+/// Recursively bind-mount `source` (carrying the staged extension-release
+/// mounts of directory extensions along), or simulate in test mode.
+fn run_rbind_mount(source: &str, target: &str, verbose: bool) -> Result<(), SystemdError> {
+    if verbose {
+        println!("Bind mounting {source} -> {target}");
+    }
+    if let Some(result) = crate::backend::simulate("mount", &["--rbind", source, target]) {
+        return result.map(|_| ());
+    }
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        return Ok(());
+    }
+    let output = Command::new("mount")
+        .args(["--rbind", source, target])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| SystemdError::CommandFailed {
+            command: "mount --rbind".to_string(),
+            source: e,
+        })?;
+    if !output.status.success() {
+        return Err(SystemdError::CommandExitedWithError {
+            command: format!("mount --rbind {source} {target}"),
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn run_machinectl(args: &[&str], verbose: bool) -> Result<(), SystemdError> {
+    if verbose {
+        println!("Running machinectl {}", args.join(" "));
+    }
+    if let Some(result) = crate::backend::simulate("machinectl", args) {
+        return result.map(|_| ());
+    }
+    let program = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        "mock-machinectl"
+    } else {
+        "machinectl"
+    };
+    let output = Command::new(program)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| SystemdError::CommandFailed {
+            command: "machinectl".to_string(),
+            source: e,
+        })?;
+    if !output.status.success() {
+        return Err(SystemdError::CommandExitedWithError {
+            command: format!("machinectl {}", args.join(" ")),
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            MergeTarget::parse("web-1").unwrap(),
+            MergeTarget::Machine("web-1".to_string())
+        );
+        assert_eq!(
+            MergeTarget::parse("/srv/app").unwrap(),
+            MergeTarget::Root(PathBuf::from("/srv/app"))
+        );
+        assert!(MergeTarget::parse("").is_err());
+        assert!(MergeTarget::parse("../etc").is_err());
+        assert!(MergeTarget::parse("a b").is_err());
+    }
+
+    #[test]
+    fn test_target_command() {
+        let machine = MergeTarget::Machine("web".to_string());
+        let (program, args) = machine.command("systemd-sysext", &["merge", "--json=short"]);
+        assert_eq!(program, "systemd-run");
+        assert_eq!(
+            args,
+            [
+                "-M",
+                "web",
+                "--wait",
+                "--pipe",
+                "--quiet",
+                "--",
+                "systemd-sysext",
+                "merge",
+                "--json=short"
+            ]
+        );
+
+        let root = MergeTarget::Root(PathBuf::from("/srv/app"));
+        let (program, args) = root.command("systemd-confext", &["merge"]);
+        assert_eq!(program, "systemd-confext");
+        assert_eq!(args, ["merge", "--root=/srv/app"]);
+    }
+}
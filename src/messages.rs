@@ -0,0 +1,360 @@
+//! User-facing message catalogue.
+//!
+//! Outcome messages carry a stable identifier from [`CATALOG`]
+//! (`ext.merged`, `plan.applied`, ...) next to their English text. The
+//! identifier is included in JSON results, so automation can match on it
+//! while the wording changes or is translated.
+//!
+//! Translations are TOML files mapping identifiers to text, looked up as
+//! `<dir>/<locale>.toml` and then `<dir>/<language>.toml`:
+//!
+//! ```toml
+//! "ext.merged" = "Erweiterungen erfolgreich zusammengeführt"
+//! "ext.enabled" = "{count} Erweiterung(en) für OS-Release {version_id} aktiviert"
+//! ```
+//!
+//! The locale comes from `AVOCADO_LOCALE`, then `LC_ALL`, `LC_MESSAGES`
+//! and `LANG`; the directory from `AVOCADO_MESSAGES_DIR`, defaulting to
+//! [`DEFAULT_MESSAGES_DIR`]. `[avocado.messages]` sets both when the
+//! `AVOCADO_*` variables do not.
+//! Identifiers missing from a translation fall back to English.
+//!
+//! Identifiers are never reused or renamed; new ones are appended.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Directory searched for translations when none is configured.
+pub const DEFAULT_MESSAGES_DIR: &str = "/usr/share/avocado/messages";
+
+/// One entry of the message catalogue. `text` is the English template;
+/// `{name}` placeholders are filled in by [`render`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageId {
+    pub id: &'static str,
+    pub text: &'static str,
+}
+
+pub const EXT_MERGED: MessageId = MessageId {
+    id: "ext.merged",
+    text: "Extensions merged successfully",
+};
+pub const EXT_MERGED_INTO: MessageId = MessageId {
+    id: "ext.merged-into",
+    text: "Extensions merged into {target}",
+};
+pub const EXT_UNMERGED: MessageId = MessageId {
+    id: "ext.unmerged",
+    text: "Extensions unmerged successfully",
+};
+pub const EXT_REFRESHED: MessageId = MessageId {
+    id: "ext.refreshed",
+    text: "Extensions refreshed successfully",
+};
+pub const EXT_UP_TO_DATE: MessageId = MessageId {
+    id: "ext.up-to-date",
+    text: "Extensions already up to date",
+};
+pub const EXT_SOFT_REBOOT_REQUESTED: MessageId = MessageId {
+    id: "ext.soft-reboot-requested",
+    text: "Extension state synced, soft-reboot requested",
+};
+pub const EXT_ENABLED: MessageId = MessageId {
+    id: "ext.enabled",
+    text: "Successfully enabled {count} extension(s) for OS release {version_id}",
+};
+pub const EXT_DISABLED: MessageId = MessageId {
+    id: "ext.disabled",
+    text: "Successfully disabled {count} extension(s) for OS release {version_id}",
+};
+pub const EXT_ENABLE_SUMMARY: MessageId = MessageId {
+    id: "ext.enable-summary",
+    text: "{enabled} extension(s) enabled, {failed} failed",
+};
+pub const EXT_DISABLE_SUMMARY: MessageId = MessageId {
+    id: "ext.disable-summary",
+    text: "{disabled} extension(s) disabled, {failed} failed",
+};
+pub const EXT_APPLIED: MessageId = MessageId {
+    id: "ext.applied",
+    text: "{enabled} enabled, {disabled} disabled, extensions refreshed",
+};
+pub const EXT_APPLY_NOTHING: MessageId = MessageId {
+    id: "ext.apply-nothing",
+    text: "Nothing to do: all extensions are already in the requested state",
+};
+pub const EXT_PREFETCHED: MessageId = MessageId {
+    id: "ext.prefetched",
+    text: "Prefetched runtime {name} {version}; run 'avocadoctl runtime activate {id}' to switch to it",
+};
+pub const EXT_PREFETCH_CURRENT: MessageId = MessageId {
+    id: "ext.prefetch-current",
+    text: "Runtime {name} {version} is already active; nothing to prefetch",
+};
+pub const EXT_UPGRADED: MessageId = MessageId {
+    id: "ext.upgraded",
+    text: "Upgraded {count} extension(s); activated runtime {id}",
+};
+pub const EXT_UPGRADE_DRY_RUN: MessageId = MessageId {
+    id: "ext.upgrade-dry-run",
+    text: "{count} extension(s) would be upgraded (dry run, nothing changed)",
+};
+pub const EXT_UPGRADE_NOTHING: MessageId = MessageId {
+    id: "ext.upgrade-nothing",
+    text: "Nothing to upgrade: extensions are up to date or held by policy",
+};
+pub const EXT_UPGRADE_QUEUED: MessageId = MessageId {
+    id: "ext.upgrade-queued",
+    text: "Outside the maintenance windows: upgrade queued until {until} (--force to upgrade now)",
+};
+pub const EXT_MOUNTED_ONLY: MessageId = MessageId {
+    id: "ext.mounted-only",
+    text: "Prepared {count} extension(s) without merging them (--mount-only)",
+};
+pub const EXT_OS_RELEASES_PRUNED: MessageId = MessageId {
+    id: "ext.os-releases-pruned",
+    text: "Removed {count} os-releases directory(ies) of OS versions no longer installed",
+};
+pub const EXT_OS_RELEASES_PRUNE_DRY_RUN: MessageId = MessageId {
+    id: "ext.os-releases-prune-dry-run",
+    text: "{count} os-releases directory(ies) would be removed (dry run, nothing changed)",
+};
+pub const PROVISIONED: MessageId = MessageId {
+    id: "provision.done",
+    text: "Device provisioned ({count} change(s))",
+};
+pub const TRUST_ROTATED: MessageId = MessageId {
+    id: "trust.rotated",
+    text: "Installed signing key {keyid}; {lost} installed image(s) will no longer validate",
+};
+pub const TRUST_ROTATE_DRY_RUN: MessageId = MessageId {
+    id: "trust.rotate-dry-run",
+    text: "Signing key {keyid} would be installed; {lost} installed image(s) would no longer validate (dry run, nothing changed)",
+};
+pub const HITL_MOUNTED: MessageId = MessageId {
+    id: "hitl.mounted",
+    text: "All extensions mounted successfully",
+};
+pub const HITL_UNMOUNTED: MessageId = MessageId {
+    id: "hitl.unmounted",
+    text: "All extensions unmounted successfully",
+};
+pub const HITL_QUIESCED: MessageId = MessageId {
+    id: "hitl.quiesced",
+    text: "Refreshes held until 'avocadoctl hitl resume'",
+};
+pub const HITL_RESUMED: MessageId = MessageId {
+    id: "hitl.resumed",
+    text: "Refreshes released",
+};
+pub const RUNTIME_ADDED: MessageId = MessageId {
+    id: "runtime.added",
+    text: "Runtime added successfully",
+};
+pub const RUNTIME_REMOVED: MessageId = MessageId {
+    id: "runtime.removed",
+    text: "Runtime removed successfully",
+};
+pub const RUNTIME_ACTIVATED: MessageId = MessageId {
+    id: "runtime.activated",
+    text: "Runtime activated successfully",
+};
+pub const PLAN_SAVED: MessageId = MessageId {
+    id: "plan.saved",
+    text: "Saved to {file}; review it, then run 'avocadoctl apply {file}'",
+};
+pub const PLAN_APPLIED: MessageId = MessageId {
+    id: "plan.applied",
+    text: "Plan applied",
+};
+
+/// All messages, in the order they were added.
+#[cfg_attr(not(test), allow(dead_code))]
+pub const CATALOG: &[MessageId] = &[
+    EXT_MERGED,
+    EXT_MERGED_INTO,
+    EXT_UNMERGED,
+    EXT_REFRESHED,
+    EXT_UP_TO_DATE,
+    EXT_SOFT_REBOOT_REQUESTED,
+    EXT_ENABLED,
+    EXT_DISABLED,
+    EXT_ENABLE_SUMMARY,
+    EXT_DISABLE_SUMMARY,
+    EXT_APPLIED,
+    EXT_APPLY_NOTHING,
+    HITL_MOUNTED,
+    HITL_UNMOUNTED,
+    RUNTIME_ADDED,
+    RUNTIME_REMOVED,
+    RUNTIME_ACTIVATED,
+    PLAN_SAVED,
+    PLAN_APPLIED,
+    HITL_QUIESCED,
+    HITL_RESUMED,
+    EXT_PREFETCHED,
+    EXT_PREFETCH_CURRENT,
+    EXT_UPGRADED,
+    EXT_UPGRADE_DRY_RUN,
+    EXT_UPGRADE_NOTHING,
+    EXT_UPGRADE_QUEUED,
+    EXT_MOUNTED_ONLY,
+    EXT_OS_RELEASES_PRUNED,
+    EXT_OS_RELEASES_PRUNE_DRY_RUN,
+    PROVISIONED,
+    TRUST_ROTATED,
+    TRUST_ROTATE_DRY_RUN,
+];
+
+/// Environment variable overriding `[avocado.messages] locale`.
+pub const LOCALE_ENV: &str = "AVOCADO_LOCALE";
+/// Environment variable overriding `[avocado.messages] dir`.
+pub const DIR_ENV: &str = "AVOCADO_MESSAGES_DIR";
+
+/// The requested locale, `configured` or else from the locale variables,
+/// or `None` for the built-in English text.
+fn locale(configured: Option<&str>) -> Option<String> {
+    let value = match configured {
+        Some(locale) => locale.to_string(),
+        None => ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|v| !v.is_empty())?,
+    };
+    // Strip the encoding and modifier: de_DE.UTF-8@euro -> de_DE
+    let locale = value.split(['.', '@']).next().unwrap_or_default();
+    match locale {
+        "" | "C" | "POSIX" => None,
+        l if l == "en" || l.starts_with("en_") => None,
+        l => Some(l.to_string()),
+    }
+}
+
+/// Load the translation for `locale` from `dir`, trying the full locale
+/// first and then its language.
+fn load_translation(dir: &Path, locale: &str) -> HashMap<String, String> {
+    let language = locale.split('_').next().unwrap_or(locale);
+    for name in [locale, language] {
+        let Ok(content) = std::fs::read_to_string(dir.join(format!("{name}.toml"))) else {
+            continue;
+        };
+        match toml::from_str(&content) {
+            Ok(table) => return table,
+            Err(e) => eprintln!(
+                "Warning: ignoring invalid translation {}/{name}.toml: {e}",
+                dir.display()
+            ),
+        }
+    }
+    HashMap::new()
+}
+
+type Translation = (Option<String>, String, Arc<HashMap<String, String>>);
+
+/// The translation for the current locale and directory, loaded again
+/// only when either changes.
+fn translations() -> Arc<HashMap<String, String>> {
+    static TRANSLATIONS: Mutex<Option<Translation>> = Mutex::new(None);
+    let settings = crate::settings::current();
+    let locale = locale(settings.messages.locale.as_deref());
+    let dir = settings
+        .messages
+        .dir
+        .clone()
+        .unwrap_or_else(|| DEFAULT_MESSAGES_DIR.to_string());
+    let mut cached = TRANSLATIONS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_locale, cached_dir, table)) = cached.as_ref() {
+        if *cached_locale == locale && *cached_dir == dir {
+            return Arc::clone(table);
+        }
+    }
+    let table = Arc::new(match &locale {
+        Some(locale) => load_translation(Path::new(&dir), locale),
+        None => HashMap::new(),
+    });
+    *cached = Some((locale, dir, Arc::clone(&table)));
+    table
+}
+
+fn fill(template: &str, args: &[(&str, &str)]) -> String {
+    args.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+/// Text of `message` in the current locale with its placeholders filled in.
+pub fn render(message: MessageId, args: &[(&str, &str)]) -> String {
+    let translations = translations();
+    let template = translations
+        .get(message.id)
+        .map(String::as_str)
+        .unwrap_or(message.text);
+    fill(template, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_env::ENV_VAR_MUTEX;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_catalog_ids_are_unique() {
+        let mut seen = std::collections::HashSet::new();
+        for message in CATALOG {
+            assert!(seen.insert(message.id), "duplicate id {}", message.id);
+            assert!(!message.text.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_render_fills_placeholders() {
+        assert_eq!(
+            fill(EXT_ENABLED.text, &[("count", "2"), ("version_id", "1.0")]),
+            "Successfully enabled 2 extension(s) for OS release 1.0"
+        );
+        assert_eq!(
+            fill(PLAN_SAVED.text, &[("file", "p.json")]),
+            "Saved to p.json; review it, then run 'avocadoctl apply p.json'"
+        );
+    }
+
+    #[test]
+    fn test_locale_and_translation_lookup() {
+        let _guard = ENV_VAR_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let saved: Vec<_> = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .map(|var| (*var, std::env::var(var).ok()))
+            .collect();
+        for (var, _) in &saved {
+            std::env::remove_var(var);
+        }
+
+        std::env::set_var("LANG", "de_DE.UTF-8");
+        assert_eq!(locale(None).as_deref(), Some("de_DE"));
+        assert_eq!(locale(Some("en_US")), None);
+        assert_eq!(locale(Some("C.UTF-8")), None);
+
+        for (var, value) in saved {
+            match value {
+                Some(value) => std::env::set_var(var, value),
+                None => std::env::remove_var(var),
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("de.toml"),
+            "\"ext.merged\" = \"Erweiterungen zusammengeführt\"\n",
+        )
+        .unwrap();
+        let table = load_translation(dir.path(), "de_AT");
+        assert_eq!(
+            table.get("ext.merged").map(String::as_str),
+            Some("Erweiterungen zusammengeführt")
+        );
+        assert!(load_translation(dir.path(), "fr_FR").is_empty());
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 
@@ -8,14 +8,14 @@ use crate::manifest::METADATA_FILENAME;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuntimeMetadata {
     pub version: u32,
-    pub entries: HashMap<String, String>,
+    pub entries: BTreeMap<String, String>,
 }
 
 impl Default for RuntimeMetadata {
     fn default() -> Self {
         Self {
             version: 1,
-            entries: HashMap::new(),
+            entries: BTreeMap::new(),
         }
     }
 }
@@ -0,0 +1,69 @@
+//! One-time data migrations run when an extension is upgraded.
+//!
+//! An extension-release file may set `AVOCADO_MIGRATE=<script>`. After a
+//! merge, the script runs once for every new data schema version: the
+//! version is `AVOCADO_DATA_VERSION` when set, otherwise the extension's
+//! version. The version a script last completed for is stored per extension
+//! name in `/var/lib/avocado/migrations/<name>`, which survives reboots, so a
+//! script is never re-run for a version it already migrated to. A failed
+//! script records nothing and runs again on the next merge.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MIGRATIONS_DIRNAME: &str = "migrations";
+
+/// Environment variable holding the version the data was last migrated to;
+/// empty on the first migration.
+pub const FROM_ENV: &str = "AVOCADO_MIGRATE_FROM";
+/// Environment variable holding the version being migrated to.
+pub const TO_ENV: &str = "AVOCADO_MIGRATE_TO";
+
+/// `/var/lib/avocado/migrations`, or under `$TMPDIR/avocado` in test mode.
+fn dir() -> PathBuf {
+    crate::link_journal::state_dir().join(MIGRATIONS_DIRNAME)
+}
+
+/// Data version `extension` was last migrated to.
+pub fn migrated_version(extension: &str) -> Option<String> {
+    migrated_version_in(&dir(), extension)
+}
+
+fn migrated_version_in(dir: &Path, extension: &str) -> Option<String> {
+    fs::read_to_string(dir.join(extension))
+        .ok()
+        .map(|content| content.trim().to_string())
+        .filter(|version| !version.is_empty())
+}
+
+/// Record that `extension`'s migration to `version` completed.
+pub fn record(extension: &str, version: &str) -> std::io::Result<()> {
+    record_in(&dir(), extension, version)
+}
+
+fn record_in(dir: &Path, extension: &str, version: &str) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(extension), format!("{version}\n"))
+}
+
+/// Whether `extension` needs migrating to `version`.
+pub fn pending(extension: &str, version: &str) -> bool {
+    migrated_version(extension).as_deref() != Some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_replaces_migrated_version() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir = tmp.path().join(MIGRATIONS_DIRNAME);
+        assert_eq!(migrated_version_in(&dir, "app"), None);
+        record_in(&dir, "app", "1").unwrap();
+        record_in(&dir, "app", "2").unwrap();
+        record_in(&dir, "tools", "1.0").unwrap();
+        assert_eq!(migrated_version_in(&dir, "app").as_deref(), Some("2"));
+        assert_eq!(migrated_version_in(&dir, "tools").as_deref(), Some("1.0"));
+    }
+}
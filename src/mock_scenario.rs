@@ -0,0 +1,173 @@
+//! Scripted systemd-sysext / systemd-confext responses for the mock backend.
+//!
+//! Builds with the `dev` feature read a TOML scenario named by
+//! `AVOCADO_MOCK_SCENARIO` (set by the hidden `avocadoctl test-daemon
+//! --scenario <file>`) and let it override what [`crate::backend`]
+//! simulates, per tool:
+//!
+//! ```toml
+//! [sysext]
+//! merged = ["app-1.0", "base-2.0"]  # what `status` reports, whatever was merged
+//! skip = ["broken-1.0"]             # left out of merge and refresh
+//! fail = ["refresh"]                # actions that exit with an error
+//! exit_code = 1
+//! stderr = "Failed to merge: Device or resource busy"
+//!
+//! [confext]
+//! status_json = '[{"hierarchy":"/etc","extensions":"none","since":null}]'
+//! ```
+//!
+//! `status_json` is returned verbatim by `status --json`, so tests can feed
+//! several hierarchies or malformed output. Without the feature nothing
+//! reads the variable and the mock backend behaves as before.
+
+use crate::commands::ext::SystemdError;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Environment variable naming the scenario file. A daemon started with
+/// `test-daemon --scenario` serves it to every call made through varlink.
+pub const SCENARIO_ENV: &str = "AVOCADO_MOCK_SCENARIO";
+
+#[derive(Error, Debug)]
+pub enum ScenarioError {
+    #[error("Failed to read scenario {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid scenario {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+}
+
+/// A scenario file: one section per tool, both optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub sysext: ToolScenario,
+    #[serde(default)]
+    pub confext: ToolScenario,
+}
+
+/// Overrides for one of systemd-sysext or systemd-confext.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolScenario {
+    /// Extensions `status` reports as merged, regardless of earlier merges
+    pub merged: Option<Vec<String>>,
+    /// Verbatim `status --json` output
+    pub status_json: Option<String>,
+    /// Extensions merge and refresh leave out, as a partial merge would
+    pub skip: Vec<String>,
+    /// Actions (`merge`, `unmerge`, `refresh`, `status`) that fail
+    pub fail: Vec<String>,
+    /// Exit code of a failing action (default 1)
+    pub exit_code: Option<i32>,
+    /// Standard error of a failing action
+    pub stderr: Option<String>,
+}
+
+impl ToolScenario {
+    /// The error `command action` reports, if the scenario fails it.
+    pub fn failure(&self, command: &str, action: &str) -> Option<SystemdError> {
+        self.fail
+            .iter()
+            .any(|a| a == action)
+            .then(|| SystemdError::CommandExitedWithError {
+                command: format!("{command} {action}"),
+                exit_code: Some(self.exit_code.unwrap_or(1)),
+                stderr: self
+                    .stderr
+                    .clone()
+                    .unwrap_or_else(|| format!("mock scenario: {action} failed")),
+            })
+    }
+
+    /// Whether a merge leaves the extension linked as `link_name` out.
+    /// Entries match with or without an `NN-` ordering prefix and `.raw`.
+    pub fn skips(&self, link_name: &str) -> bool {
+        let name = link_name.strip_suffix(".raw").unwrap_or(link_name);
+        let digits = name.bytes().take_while(|b| b.is_ascii_digit()).count();
+        let unordered = match name.as_bytes().get(digits) {
+            Some(b'-') if digits > 0 => &name[digits + 1..],
+            _ => name,
+        };
+        self.skip
+            .iter()
+            .any(|s| s == link_name || s == name || s == unordered)
+    }
+}
+
+/// Read and validate a scenario file.
+pub fn load(path: &Path) -> Result<Scenario, ScenarioError> {
+    let content = std::fs::read_to_string(path).map_err(|e| ScenarioError::Read {
+        path: path.to_path_buf(),
+        source: e,
+    })?;
+    toml::from_str(&content).map_err(|e| ScenarioError::Parse {
+        path: path.to_path_buf(),
+        message: e.message().to_string(),
+    })
+}
+
+/// Validate `path` and serve it to this process and the processes it starts.
+pub fn arm(path: &Path) -> Result<(), ScenarioError> {
+    load(path)?;
+    let absolute = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    std::env::set_var(SCENARIO_ENV, absolute);
+    Ok(())
+}
+
+/// The armed scenario for `kind` ("sysext" or "confext"), if any.
+pub fn for_tool(kind: &str) -> Result<Option<ToolScenario>, SystemdError> {
+    let Some(path) = std::env::var_os(SCENARIO_ENV) else {
+        return Ok(None);
+    };
+    let scenario = load(Path::new(&path)).map_err(|e| SystemdError::CommandExitedWithError {
+        command: format!("systemd-{kind}"),
+        exit_code: Some(1),
+        stderr: e.to_string(),
+    })?;
+    Ok(Some(match kind {
+        "confext" => scenario.confext,
+        _ => scenario.sysext,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenario_failures_and_skips() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("scenario.toml");
+        std::fs::write(
+            &path,
+            "[sysext]\nskip = [\"broken-1.0\"]\nfail = [\"refresh\"]\nexit_code = 3\n",
+        )
+        .unwrap();
+        let scenario = load(&path).unwrap();
+        assert!(scenario.confext.fail.is_empty());
+
+        let sysext = scenario.sysext;
+        assert!(sysext.skips("broken-1.0"));
+        assert!(sysext.skips("20-broken-1.0.raw"));
+        assert!(!sysext.skips("app-1.0"));
+        assert!(sysext.failure("systemd-sysext", "merge").is_none());
+        let err = sysext.failure("systemd-sysext", "refresh").unwrap();
+        assert!(matches!(
+            err,
+            SystemdError::CommandExitedWithError {
+                exit_code: Some(3),
+                ..
+            }
+        ));
+
+        std::fs::write(&path, "[sysext]\nmerge = true\n").unwrap();
+        assert!(matches!(load(&path), Err(ScenarioError::Parse { .. })));
+    }
+}
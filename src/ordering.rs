@@ -0,0 +1,122 @@
+//! Stable iteration order for everything avocadoctl reports or acts on.
+//!
+//! Two runs over the same system must print the same lines, emit the same
+//! JSON and run hooks in the same order. Directory listings and hash maps
+//! have no defined order, so:
+//!
+//! - directories are listed through [`read_dir_sorted`], by file name;
+//! - maps whose iteration order is observable are `BTreeMap`s (or
+//!   `BTreeSet`s), keyed by extension or file name;
+//! - anything else is sorted explicitly before it is printed or returned.
+//!
+//! Scanned extensions are ordered by manifest priority (the manifest's own
+//! order), then by name. Links, hooks and modules follow that order, and
+//! within one extension keep the order of the lines in its release file.
+//! AVOCADO_ON_MERGE hooks are the exception: [`hook_order`] runs them by
+//! AVOCADO_ON_MERGE_PRIORITY and dependencies first.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The entries of `dir`, sorted by file name. Unlike [`fs::read_dir`], an
+/// error reading any entry fails the whole listing.
+pub fn read_dir_sorted(dir: impl AsRef<Path>) -> io::Result<Vec<fs::DirEntry>> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(fs::DirEntry::file_name);
+    Ok(entries)
+}
+
+/// An extension's place in AVOCADO_ON_MERGE hook order.
+#[derive(Debug, Clone, Copy)]
+pub struct HookRank<'a> {
+    pub name: &'a str,
+    /// AVOCADO_ON_MERGE_PRIORITY; lower runs first.
+    pub priority: i32,
+    /// Extensions named in AVOCADO_REQUIRES.
+    pub requires: &'a [String],
+}
+
+/// Indices of `ranks` in the order their hooks run: by ascending priority,
+/// then extensions before the ones requiring them, then in the given
+/// order. Requirements across priorities don't reorder anything, and
+/// extensions on a dependency cycle run in the given order.
+pub fn hook_order(ranks: &[HookRank]) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..ranks.len()).collect();
+    remaining.sort_by_key(|&i| ranks[i].priority);
+
+    let mut order = Vec::with_capacity(ranks.len());
+    while let Some(&first) = remaining.first() {
+        let priority = ranks[first].priority;
+        let waits = |i: usize| {
+            remaining.iter().any(|&j| {
+                j != i
+                    && ranks[j].priority == priority
+                    && ranks[i].requires.iter().any(|r| r == ranks[j].name)
+            })
+        };
+        let next = remaining
+            .iter()
+            .take_while(|&&i| ranks[i].priority == priority)
+            .position(|&i| !waits(i))
+            .unwrap_or(0);
+        order.push(remaining.remove(next));
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_dir_sorted() {
+        let temp = tempfile::TempDir::new().unwrap();
+        for name in ["b", "c", "a", "a-1.0", "B"] {
+            fs::write(temp.path().join(name), "").unwrap();
+        }
+        let names: Vec<String> = read_dir_sorted(temp.path())
+            .unwrap()
+            .iter()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["B", "a", "a-1.0", "b", "c"]);
+        assert!(read_dir_sorted(temp.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_hook_order() {
+        let none: Vec<String> = Vec::new();
+        let db = ["db".to_string()];
+        let app = ["app".to_string()];
+        let rank = |name, priority, requires| HookRank {
+            name,
+            priority,
+            requires,
+        };
+
+        // Priority first, then dependencies, then the given order
+        let ranks = [
+            rank("app", 0, &db[..]),
+            rank("restart", 10, &none[..]),
+            rank("db", 0, &none[..]),
+            rank("modules", -5, &none[..]),
+            rank("web", 0, &none[..]),
+        ];
+        let names: Vec<&str> = hook_order(&ranks).iter().map(|&i| ranks[i].name).collect();
+        assert_eq!(names, ["modules", "db", "app", "web", "restart"]);
+
+        // Cycles run after the rest of their priority, in the given order;
+        // requirements on another priority are ignored
+        let ranks = [
+            rank("db", 0, &app[..]),
+            rank("app", 0, &db[..]),
+            rank("late", 5, &none[..]),
+            rank("early", 0, &none[..]),
+        ];
+        let ranks_late = [rank("app", 0, &db[..]), rank("db", 1, &none[..])];
+        assert_eq!(hook_order(&ranks), [3, 0, 1, 2]);
+        assert_eq!(hook_order(&ranks_late), [0, 1]);
+        assert!(hook_order(&[]).is_empty());
+    }
+}
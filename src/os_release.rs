@@ -0,0 +1,313 @@
+//! The host's os-release: a typed parse of the fields avocadoctl checks
+//! extensions against, and a cached VERSION_ID.
+//!
+//! The file is the first of `/etc/os-release` and `/usr/lib/os-release`
+//! that exists; in an initrd `/etc/initrd-release` takes precedence over
+//! both, as it does for systemd. In test mode (`AVOCADO_TEST_MODE`) only
+//! `$TMPDIR/avocado/os-release` is read.
+//!
+//! VERSION_ID picks the os-releases directory whose enabled set is merged.
+//! It can change under a running process: a soft-reboot switches to a new
+//! root, and a confext merged over /etc can carry its own os-release. The
+//! value is cached against the file's device, inode, size and mtime, so a
+//! changed file is re-read on the next lookup and an unchanged one is not.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// VERSION_ID reported when os-release is missing or does not set one.
+pub const UNKNOWN_VERSION_ID: &str = "unknown";
+
+/// `$TMPDIR/avocado/os-release`, in test mode.
+fn test_path() -> Option<PathBuf> {
+    std::env::var("AVOCADO_TEST_MODE").ok()?;
+    let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+    Some(PathBuf::from(temp_base).join("avocado/os-release"))
+}
+
+/// Files the host's os-release is read from, in order of precedence: only
+/// `test_path` when there is one.
+fn candidates(test_path: Option<PathBuf>) -> Vec<PathBuf> {
+    match test_path {
+        Some(path) => vec![path],
+        None => system_candidates(),
+    }
+}
+
+fn system_candidates() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if Path::new("/etc/initrd-release").exists() {
+        paths.push(PathBuf::from("/etc/initrd-release"));
+    }
+    paths.push(PathBuf::from("/etc/os-release"));
+    paths.push(PathBuf::from("/usr/lib/os-release"));
+    paths
+}
+
+/// The first of `candidates` that exists, else the last one.
+fn first_existing(candidates: &[PathBuf]) -> PathBuf {
+    candidates
+        .iter()
+        .find(|path| path.exists())
+        .or(candidates.last())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Path of the host's os-release file.
+pub fn path() -> PathBuf {
+    first_existing(&candidates(test_path()))
+}
+
+/// A parsed os-release (or extension-release) file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OsRelease {
+    pub id: Option<String>,
+    pub version_id: Option<String>,
+    pub architecture: Option<String>,
+    pub sysext_level: Option<String>,
+    pub confext_level: Option<String>,
+    fields: BTreeMap<String, String>,
+}
+
+impl OsRelease {
+    /// Parse `KEY=value` lines as the shell would: comments and blank lines
+    /// are skipped, quotes and backslash escapes removed, and a later
+    /// assignment replaces an earlier one. Empty values count as unset.
+    pub fn parse(contents: &str) -> Self {
+        let mut fields = BTreeMap::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = unquote(value.trim());
+            if value.is_empty() {
+                fields.remove(key.trim());
+            } else {
+                fields.insert(key.trim().to_string(), value);
+            }
+        }
+        let field = |key: &str| fields.get(key).cloned();
+        Self {
+            id: field("ID"),
+            version_id: field("VERSION_ID"),
+            architecture: field("ARCHITECTURE"),
+            sysext_level: field("SYSEXT_LEVEL"),
+            confext_level: field("CONFEXT_LEVEL"),
+            fields,
+        }
+    }
+
+    /// Read and parse the file at `path`.
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        fs::read_to_string(path).map(|contents| Self::parse(&contents))
+    }
+
+    /// Any field, e.g. `AVOCADO_OS_BUILD_ID`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    /// SYSEXT_LEVEL or CONFEXT_LEVEL, for a `"sysext"` or `"confext"`.
+    pub fn level(&self, kind: &str) -> Option<&str> {
+        if kind == "confext" {
+            self.confext_level.as_deref()
+        } else {
+            self.sysext_level.as_deref()
+        }
+    }
+}
+
+/// `value` without its quotes; backslash escapes are resolved inside
+/// double quotes and unquoted values.
+fn unquote(value: &str) -> String {
+    if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        return inner.to_string();
+    }
+    let inner = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unquoted.extend(chars.next());
+        } else {
+            unquoted.push(c);
+        }
+    }
+    unquoted
+}
+
+/// The host's os-release, or None when no candidate file can be read.
+pub fn host() -> Option<OsRelease> {
+    OsRelease::read(&path()).ok()
+}
+
+/// The first of `paths` that can be read and sets `key`.
+pub fn field_from(paths: &[&str], key: &str) -> Option<String> {
+    paths
+        .iter()
+        .filter_map(|path| OsRelease::read(Path::new(path)).ok())
+        .find_map(|release| release.get(key).map(str::to_string))
+}
+
+/// What identifies one version of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stamp {
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        Some(Self {
+            path: path.to_path_buf(),
+            dev: meta.dev(),
+            ino: meta.ino(),
+            len: meta.len(),
+            modified: meta.modified().ok(),
+        })
+    }
+}
+
+type Cache = Mutex<Option<(Stamp, String)>>;
+
+static CACHE: Cache = Mutex::new(None);
+
+/// The host's VERSION_ID, re-read only when os-release changed since the
+/// last lookup.
+pub fn version_id() -> String {
+    version_id_at(&path(), &CACHE)
+}
+
+fn version_id_at(path: &Path, cache: &Cache) -> String {
+    let Some(stamp) = Stamp::of(path) else {
+        return UNKNOWN_VERSION_ID.to_string();
+    };
+    let mut cached = cache.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((cached_stamp, version_id)) = cached.as_ref() {
+        if *cached_stamp == stamp {
+            return version_id.clone();
+        }
+    }
+    let version_id = fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| parse_version_id(&contents))
+        .unwrap_or_else(|| UNKNOWN_VERSION_ID.to_string());
+    *cached = Some((stamp, version_id.clone()));
+    version_id
+}
+
+/// VERSION_ID of os-release `contents`, without quotes.
+fn parse_version_id(contents: &str) -> Option<String> {
+    OsRelease::parse(contents).version_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_id() {
+        assert_eq!(
+            parse_version_id("ID=avocado\nVERSION_ID=\"1.2\"\n").as_deref(),
+            Some("1.2")
+        );
+        assert_eq!(parse_version_id("VERSION_ID='3'").as_deref(), Some("3"));
+        assert_eq!(parse_version_id("ID=avocado\nVERSION_ID=\n"), None);
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        let release = OsRelease::parse(concat!(
+            "# comment\n",
+            "ID=avocado\n",
+            "VERSION_ID=\"1.0\"\n",
+            "ARCHITECTURE=arm64\n",
+            "SYSEXT_LEVEL=2\n",
+            "PRETTY_NAME=\"Avocado \\\"OS\\\"\"\n",
+            "VERSION_ID=1.1\n",
+            "BUILD_ID=\n",
+        ));
+        assert_eq!(release.id.as_deref(), Some("avocado"));
+        assert_eq!(release.version_id.as_deref(), Some("1.1"));
+        assert_eq!(release.architecture.as_deref(), Some("arm64"));
+        assert_eq!(release.level("sysext"), Some("2"));
+        assert_eq!(release.level("confext"), None);
+        assert_eq!(release.get("PRETTY_NAME"), Some("Avocado \"OS\""));
+        assert_eq!(release.get("BUILD_ID"), None);
+    }
+
+    #[test]
+    fn test_first_existing_follows_precedence() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let initrd = tmp.path().join("initrd-release");
+        let etc = tmp.path().join("etc-os-release");
+        let usr = tmp.path().join("usr-os-release");
+        let candidates = [initrd.clone(), etc.clone(), usr.clone()];
+        assert_eq!(first_existing(&candidates), usr);
+
+        fs::write(&usr, "ID=avocado\n").unwrap();
+        assert_eq!(first_existing(&candidates), usr);
+        fs::write(&etc, "ID=avocado\n").unwrap();
+        assert_eq!(first_existing(&candidates), etc);
+        fs::write(&initrd, "ID=avocado-initrd\n").unwrap();
+        assert_eq!(first_existing(&candidates), initrd);
+    }
+
+    #[test]
+    fn test_accessors_agree_on_the_test_root() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let test_path = tmp.path().join("avocado/os-release");
+        let cache = Cache::new(None);
+
+        // The system's os-release is never read in its place
+        let path = first_existing(&candidates(Some(test_path.clone())));
+        assert_eq!(path, test_path);
+        assert_eq!(OsRelease::read(&path).ok(), None);
+        assert_eq!(version_id_at(&path, &cache), UNKNOWN_VERSION_ID);
+
+        fs::create_dir_all(test_path.parent().unwrap()).unwrap();
+        fs::write(&test_path, "ID=avocado\nVERSION_ID=2.1\n").unwrap();
+        let path = first_existing(&candidates(Some(test_path.clone())));
+        assert_eq!(path, test_path);
+        let release = OsRelease::read(&path).unwrap();
+        assert_eq!(release.version_id.as_deref(), Some("2.1"));
+        assert_eq!(version_id_at(&path, &cache), "2.1");
+    }
+
+    #[test]
+    fn test_version_id_is_reread_when_the_file_changes() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("os-release");
+        let cache = Cache::new(None);
+        assert_eq!(version_id_at(&path, &cache), UNKNOWN_VERSION_ID);
+
+        fs::write(&path, "ID=avocado\nVERSION_ID=1.0\n").unwrap();
+        assert_eq!(version_id_at(&path, &cache), "1.0");
+        assert_eq!(version_id_at(&path, &cache), "1.0");
+
+        // A soft-reboot or confext replaces the file rather than editing it
+        let next = tmp.path().join("os-release.next");
+        fs::write(&next, "ID=avocado\nVERSION_ID=1.10\n").unwrap();
+        fs::rename(&next, &path).unwrap();
+        assert_eq!(version_id_at(&path, &cache), "1.10");
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(version_id_at(&path, &cache), UNKNOWN_VERSION_ID);
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
@@ -98,7 +98,9 @@ pub enum SlotDetection {
     #[serde(rename = "command")]
     Command { command: Vec<String> },
     #[serde(rename = "sdboot-efi")]
-    SdbootEfi { partitions: HashMap<String, String> },
+    SdbootEfi {
+        partitions: BTreeMap<String, String>,
+    },
 }
 
 #[derive(Debug, Deserialize)]
@@ -109,7 +111,7 @@ pub struct Artifact {
     #[serde(default)]
     pub size: Option<u64>,
     #[serde(default)]
-    pub slot_targets: HashMap<String, SlotTarget>,
+    pub slot_targets: BTreeMap<String, SlotTarget>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -121,17 +123,17 @@ pub struct SlotTarget {
 #[serde(tag = "type")]
 pub enum SlotAction {
     #[serde(rename = "uboot-env")]
-    UbootEnv { set: HashMap<String, String> },
+    UbootEnv { set: BTreeMap<String, String> },
     #[serde(rename = "command")]
     Command { command: Vec<String> },
     #[serde(rename = "mbr-switch")]
     MbrSwitch {
         devpath: String,
-        slot_layouts: HashMap<String, Vec<String>>,
+        slot_layouts: BTreeMap<String, Vec<String>>,
     },
     #[serde(rename = "efibootmgr")]
     Efibootmgr {
-        slot_entries: HashMap<String, String>,
+        slot_entries: BTreeMap<String, String>,
     },
 }
 
@@ -381,11 +383,8 @@ pub fn verify_os_release_from(
     let contents = fs::read_to_string(os_release_path)
         .map_err(|e| OsUpdateError::UpdateFailed(format!("Failed to read os-release: {e}")))?;
 
-    let actual = parse_os_release_field(&contents, &verify.field);
-    match actual {
-        Some(value) => Ok(value == verify.expected),
-        None => Ok(false),
-    }
+    let release = crate::os_release::OsRelease::parse(&contents);
+    Ok(release.get(&verify.field) == Some(verify.expected.as_str()))
 }
 
 /// Execute rollback: switch back to previous slot and clear the pending marker.
@@ -1135,19 +1134,6 @@ fn maybe_patch_bls_entries(
     Ok(())
 }
 
-pub(crate) fn parse_os_release_field<'a>(contents: &'a str, field: &str) -> Option<&'a str> {
-    let prefix = format!("{field}=");
-    for line in contents.lines() {
-        if let Some(value) = line.strip_prefix(&prefix) {
-            let value = value.trim_matches('"').trim_matches('\'');
-            if !value.is_empty() {
-                return Some(value);
-            }
-        }
-    }
-    None
-}
-
 // --- Streaming update support ---
 
 /// A writer that computes SHA256 inline as data is written through it.
@@ -1375,7 +1361,7 @@ pub fn apply_os_update_streaming<R: Read>(
     println!("    Current slot: {current_slot}, inactive slot: {inactive_slot}");
 
     // 4. Build lookup: archive path → artifact metadata
-    let artifact_map: HashMap<&str, &Artifact> = update
+    let artifact_map: BTreeMap<&str, &Artifact> = update
         .artifacts
         .iter()
         .map(|a| (a.file.as_str(), a))
@@ -1497,21 +1483,23 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_os_release_field() {
-        let content = r#"NAME="Avocado Linux"
-VERSION_ID="2024.1"
-BUILD_ID=abc123-def456
-PRETTY_NAME="Avocado Linux 2024.1"
-"#;
-        assert_eq!(
-            parse_os_release_field(content, "BUILD_ID"),
-            Some("abc123-def456")
-        );
-        assert_eq!(
-            parse_os_release_field(content, "VERSION_ID"),
-            Some("2024.1")
-        );
-        assert_eq!(parse_os_release_field(content, "MISSING"), None);
+    fn test_verify_os_release_quoted_field() {
+        let tmp = TempDir::new().unwrap();
+        let os_release = tmp.path().join("os-release");
+        fs::write(
+            &os_release,
+            "NAME=\"Avocado Linux\"\nVERSION_ID=\"2024.1\"\nBUILD_ID=abc123-def456\n",
+        )
+        .unwrap();
+
+        let verify = |field: &str, expected: &str| VerifyConfig {
+            verify_type: "os-release".to_string(),
+            field: field.to_string(),
+            expected: expected.to_string(),
+        };
+        assert!(verify_os_release_from(&verify("VERSION_ID", "2024.1"), &os_release).unwrap());
+        assert!(verify_os_release_from(&verify("BUILD_ID", "abc123-def456"), &os_release).unwrap());
+        assert!(!verify_os_release_from(&verify("MISSING", ""), &os_release).unwrap());
     }
 
     #[test]
@@ -1559,7 +1547,7 @@ PRETTY_NAME="Avocado Linux 2024.1"
                 expected: "initramfs-456".to_string(),
             }),
             rollback: Some(vec![SlotAction::UbootEnv {
-                set: HashMap::from([(
+                set: BTreeMap::from([(
                     "avocado_boot_slot".to_string(),
                     "{previous_slot}".to_string(),
                 )]),
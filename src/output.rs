@@ -2,45 +2,73 @@
 //!
 //! This module provides a consistent interface for all output in the CLI,
 //! handling verbosity levels and formatting consistently across all commands.
+//!
+//! Commands report through [`OutputManager`], which decides what is shown at
+//! the current verbosity and hands it to a backend: [`TextBackend`] prints
+//! colored text, [`JsonBackend`] (`--json` or `-o json`) prints each message
+//! as a JSON event on stderr and the command's result as one JSON object on
+//! stdout, so stdout stays a single document a caller can parse:
+//!
+//! ```text
+//! stderr: {"event":"info","message":"Scanning extensions"}
+//! stdout: {"status":"ok","message_id":"ext.merged","message":"Extensions merged successfully"}
+//! ```
 
+use crate::diagnostics::Diagnostic;
+use crate::messages::{self, MessageId};
+use crate::telemetry::TraceIds;
+use serde::Serialize;
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::SyncSender;
+use std::sync::Mutex;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
-/// Output manager that handles verbosity and formatting consistently
-pub struct OutputManager {
-    verbose: bool,
-    json: bool,
-    /// When set, messages are streamed through this channel as they are produced.
-    /// Used by the varlink streaming handlers for real-time progress.
-    sender: Option<SyncSender<String>>,
+/// What a message reports, named by the `event` field of a JSON event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Info,
+    Success,
+    /// Something went wrong without failing the command
+    Warning,
+    /// Detail of a running operation, e.g. a line a command printed
+    Progress,
+    /// A step of an operation; its name is the operation
+    Step,
+    /// A title above a block of status lines
+    Header,
+    /// A line of a command's result
+    Status,
 }
 
-impl OutputManager {
-    /// Create a new output manager with the specified verbosity and format level
-    pub fn new(verbose: bool, json: bool) -> Self {
-        Self {
-            verbose,
-            json,
-            sender: None,
-        }
-    }
+/// Renders what an [`OutputManager`] decided to show.
+trait Backend: Send + Sync {
+    fn is_json(&self) -> bool;
 
-    /// Create an output manager that streams messages through a channel.
-    /// Each `log_info` / `log_success` call sends a message immediately.
-    pub fn new_streaming(sender: SyncSender<String>) -> Self {
-        Self {
-            verbose: false,
-            json: false,
-            sender: Some(sender),
-        }
-    }
+    /// Show a message, from `operation` when it names one.
+    fn message(&self, kind: Kind, operation: Option<&str>, message: &str);
 
-    /// Whether output should be machine-readable JSON
-    pub fn is_json(&self) -> bool {
-        self.json
-    }
+    /// Show an error with its catalogue diagnostic; `trace` is the trace of
+    /// the failed operation, if it was recorded.
+    fn error(
+        &self,
+        operation: &str,
+        message: &str,
+        diagnostic: Option<&Diagnostic>,
+        trace: Option<&TraceIds>,
+    );
+
+    /// Show the result of the command.
+    fn result(&self, result: &serde_json::Value);
+}
+
+/// Colored text on stdout, errors on stderr.
+struct TextBackend {
+    verbose: bool,
+}
 
+impl TextBackend {
     /// Determine the color choice for terminal output
     fn color_choice() -> ColorChoice {
         if std::env::var("NO_COLOR").is_ok() || std::env::var("AVOCADO_TEST_MODE").is_ok() {
@@ -50,9 +78,14 @@ impl OutputManager {
         }
     }
 
-    /// Print a colored prefix with message
-    fn print_colored_prefix(&self, prefix: &str, color: Color, message: &str) {
+    /// Print a colored `[prefix]` with the message, after the operation when
+    /// one is given
+    fn print_colored_prefix(prefix: &str, color: Color, operation: Option<&str>, message: &str) {
         let color_choice = Self::color_choice();
+        let line = match operation {
+            Some(operation) => format!("{operation}: {message}"),
+            None => message.to_string(),
+        };
 
         let mut stdout = StandardStream::stdout(color_choice);
         let mut color_spec = ColorSpec::new();
@@ -61,152 +94,504 @@ impl OutputManager {
         if stdout.set_color(&color_spec).is_ok() && color_choice != ColorChoice::Never {
             let _ = write!(&mut stdout, "[{prefix}]");
             let _ = stdout.reset();
-            println!(" {message}");
+            println!(" {line}");
         } else {
             // Fallback for environments without color support
-            println!("[{prefix}] {message}");
+            println!("[{prefix}] {line}");
+        }
+    }
+}
+
+impl Backend for TextBackend {
+    fn is_json(&self) -> bool {
+        false
+    }
+
+    fn message(&self, kind: Kind, operation: Option<&str>, message: &str) {
+        match kind {
+            Kind::Info => Self::print_colored_prefix("INFO", Color::Blue, operation, message),
+            Kind::Success => {
+                Self::print_colored_prefix("SUCCESS", Color::Green, operation, message)
+            }
+            Kind::Warning => match operation {
+                Some(operation) => eprintln!("Warning: {operation}: {message}"),
+                None => eprintln!("Warning: {message}"),
+            },
+            Kind::Progress => println!("   {message}"),
+            Kind::Step => println!("   → {}: {message}", operation.unwrap_or_default()),
+            Kind::Header if self.verbose => {
+                println!("\n{message}");
+                println!("{}", "=".repeat(message.len()));
+                println!();
+            }
+            Kind::Header | Kind::Status => println!("{message}"),
         }
     }
 
-    /// Print a colored prefix with operation and message
-    fn print_colored_prefix_with_op(
+    fn error(
         &self,
-        prefix: &str,
-        color: Color,
         operation: &str,
         message: &str,
+        diagnostic: Option<&Diagnostic>,
+        _trace: Option<&TraceIds>,
     ) {
+        let message = match diagnostic {
+            Some(d) => format!("{message} [{}]", d.code.code),
+            None => message.to_string(),
+        };
         let color_choice = Self::color_choice();
 
-        let mut stdout = StandardStream::stdout(color_choice);
+        let mut stderr = StandardStream::stderr(color_choice);
         let mut color_spec = ColorSpec::new();
-        color_spec.set_fg(Some(color)).set_bold(true);
+        color_spec.set_fg(Some(Color::Red)).set_bold(true);
 
-        if stdout.set_color(&color_spec).is_ok() && color_choice != ColorChoice::Never {
-            let _ = write!(&mut stdout, "[{prefix}]");
-            let _ = stdout.reset();
-            println!(" {operation}: {message}");
+        if stderr.set_color(&color_spec).is_ok() && color_choice != ColorChoice::Never {
+            let _ = write!(&mut stderr, "[ERROR]");
+            let _ = stderr.reset();
+            eprintln!(" {operation}: {message}");
         } else {
-            // Fallback for environments without color support
-            println!("[{prefix}] {operation}: {message}");
+            eprintln!("[ERROR] {operation}: {message}");
+        }
+
+        if let Some(hint) = diagnostic.and_then(|d| d.hint.as_deref()) {
+            eprintln!("   Hint: {hint}");
+        }
+        if !self.verbose {
+            eprintln!("   Use --verbose for more details");
+        }
+    }
+
+    fn result(&self, _result: &serde_json::Value) {}
+}
+
+/// JSON events on stderr, one object per line, and the result as one JSON
+/// object on stdout.
+struct JsonBackend;
+
+/// Add `trace_id` and `span_id` to a JSON object when a trace was recorded.
+fn add_trace(json: &mut serde_json::Value, trace: Option<&TraceIds>) {
+    if let (Some(ids), Some(object)) = (trace, json.as_object_mut()) {
+        object.insert("trace_id".to_string(), ids.trace_id.clone().into());
+        object.insert("span_id".to_string(), ids.span_id.clone().into());
+    }
+}
+
+/// An error as a JSON object, with its catalogue code, hint and context.
+fn error_object(
+    operation: &str,
+    message: &str,
+    diagnostic: Option<&Diagnostic>,
+    trace: Option<&TraceIds>,
+) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "operation": operation,
+        "message": message,
+        "code": diagnostic.map(|d| d.code.code),
+        "hint": diagnostic.and_then(|d| d.hint.as_deref()),
+    });
+    if let Some(d) = diagnostic.filter(|d| !d.context.is_empty()) {
+        json["context"] = d
+            .context
+            .iter()
+            .map(|(key, value)| (key.to_string(), serde_json::json!(value)))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+    }
+    add_trace(&mut json, trace);
+    json
+}
+
+impl Backend for JsonBackend {
+    fn is_json(&self) -> bool {
+        true
+    }
+
+    fn message(&self, kind: Kind, operation: Option<&str>, message: &str) {
+        let mut event = serde_json::json!({ "event": kind, "message": message });
+        if let Some(operation) = operation {
+            event["operation"] = operation.into();
+        }
+        eprintln!("{event}");
+    }
+
+    /// An `error` event; the error is also part of the result (see
+    /// [`OutputManager::finish`]).
+    fn error(
+        &self,
+        operation: &str,
+        message: &str,
+        diagnostic: Option<&Diagnostic>,
+        trace: Option<&TraceIds>,
+    ) {
+        let mut event = serde_json::json!({ "event": "error" });
+        if let (Some(object), serde_json::Value::Object(error)) = (
+            event.as_object_mut(),
+            error_object(operation, message, diagnostic, trace),
+        ) {
+            object.extend(error);
+        }
+        eprintln!("{event}");
+    }
+
+    fn result(&self, result: &serde_json::Value) {
+        println!("{result}");
+    }
+}
+
+/// Output manager that handles verbosity and formatting consistently
+pub struct OutputManager {
+    verbose: bool,
+    backend: Box<dyn Backend>,
+    /// When set, messages are streamed through this channel as they are produced.
+    /// Used by the varlink streaming handlers for real-time progress.
+    sender: Option<SyncSender<String>>,
+    /// Last catalogue message reported through [`Self::success_msg`], as
+    /// identifier and rendered text, for the JSON result.
+    outcome: Mutex<Option<(&'static str, String)>>,
+    /// Trace of the operation, for the JSON result (see [`crate::telemetry`])
+    trace: Mutex<Option<TraceIds>>,
+    /// Fields recorded with [`Self::result`], for the JSON result
+    data: Mutex<serde_json::Map<String, serde_json::Value>>,
+    /// Errors reported so far, for the JSON result
+    errors: Mutex<Vec<serde_json::Value>>,
+    /// Whether the JSON result has been printed
+    emitted: AtomicBool,
+    /// Daemon-reload requested by this operation (see [`crate::daemon_reload`])
+    pending_reload: crate::daemon_reload::Pending,
+}
+
+impl OutputManager {
+    /// Create a new output manager with the specified verbosity and format level
+    pub fn new(verbose: bool, json: bool) -> Self {
+        let text = TextBackend { verbose };
+        let backend: Box<dyn Backend> = if json {
+            Box::new(JsonBackend)
+        } else {
+            Box::new(text)
+        };
+        Self {
+            verbose,
+            backend,
+            sender: None,
+            outcome: Mutex::new(None),
+            trace: Mutex::new(None),
+            data: Mutex::new(serde_json::Map::new()),
+            errors: Mutex::new(Vec::new()),
+            emitted: AtomicBool::new(false),
+            pending_reload: Default::default(),
+        }
+    }
+
+    /// Create an output manager that streams messages through a channel.
+    /// Each `log_info` / `log_success` call sends a message immediately.
+    pub fn new_streaming(sender: SyncSender<String>) -> Self {
+        Self {
+            sender: Some(sender),
+            ..Self::new(false, false)
+        }
+    }
+
+    /// A manager reporting like this one, for what outlives the call it was
+    /// given to, such as a daemon thread or a guard that warns when it is
+    /// dropped. It streams through the same channel but keeps its own result.
+    pub fn detached(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            ..Self::new(self.verbose, self.is_json())
+        }
+    }
+
+    /// Show a message a streaming manager sent (see [`Self::new_streaming`]),
+    /// as the message it was on the sending side.
+    pub fn relay(&self, message: &str) {
+        if message.is_empty() {
+            return;
+        }
+        if let Some(rest) = message.strip_prefix("[INFO] ") {
+            self.log_info(rest);
+        } else if let Some(rest) = message.strip_prefix("[SUCCESS] ") {
+            self.log_success(rest);
+        } else if let Some(rest) = message.strip_prefix("[WARNING] ") {
+            self.warning(rest);
+        } else if let Some(rest) = message.strip_prefix("[OUTPUT] ") {
+            self.progress(rest);
+        } else if let Some(rest) = message.strip_prefix("[TRACE] ") {
+            if let Some(ids) = TraceIds::parse(rest) {
+                self.trace(ids);
+            }
+        } else {
+            self.status(message);
+        }
+    }
+
+    /// Whether output should be machine-readable JSON
+    pub fn is_json(&self) -> bool {
+        self.backend.is_json()
+    }
+
+    /// The daemon-reload request of this operation
+    pub(crate) fn pending_reload(&self) -> &crate::daemon_reload::Pending {
+        &self.pending_reload
+    }
+
+    /// Record `value` as the `key` field of the JSON result, e.g. how many
+    /// extensions were enabled.
+    pub fn result<T: Serialize>(&self, key: &str, value: &T) {
+        if let (Ok(value), Ok(mut data)) = (serde_json::to_value(value), self.data.lock()) {
+            data.insert(key.to_string(), value);
+        }
+    }
+
+    /// Record the fields of `value`, a report serialized as an object, as
+    /// fields of the JSON result.
+    pub fn result_fields<T: Serialize>(&self, value: &T) {
+        if let (Ok(serde_json::Value::Object(fields)), Ok(mut data)) =
+            (serde_json::to_value(value), self.data.lock())
+        {
+            data.extend(fields);
         }
     }
 
     /// Print a success message
     /// In non-verbose mode: shows brief success
     /// In verbose mode: shows detailed success with context
-    /// Suppressed in JSON mode (structured output only)
     pub fn success(&self, operation: &str, message: &str) {
-        if self.json {
+        let operation = Some(operation).filter(|_| self.verbose);
+        self.backend.message(Kind::Success, operation, message);
+    }
+
+    /// Print a success message from the message catalogue in the current
+    /// locale. Its identifier is included in the JSON result.
+    pub fn success_msg(&self, operation: &str, message: MessageId, args: &[(&str, &str)]) {
+        let text = messages::render(message, args);
+        self.success(operation, &text);
+        if let Ok(mut outcome) = self.outcome.lock() {
+            *outcome = Some((message.id, text));
+        }
+    }
+
+    /// Record the trace of the operation being reported. A streaming
+    /// manager passes it on to the client as a `[TRACE]` message.
+    pub fn trace(&self, ids: TraceIds) {
+        if let Some(ref tx) = self.sender {
+            let _ = tx.send(format!("[TRACE] {ids}"));
             return;
         }
-        if self.verbose {
-            self.print_colored_prefix_with_op("SUCCESS", Color::Green, operation, message);
-        } else {
-            self.print_colored_prefix("SUCCESS", Color::Green, message);
+        if let Ok(mut trace) = self.trace.lock() {
+            *trace = Some(ids);
+        }
+    }
+
+    fn recorded_trace(&self) -> Option<TraceIds> {
+        self.trace.lock().ok().and_then(|t| t.clone())
+    }
+
+    /// Emit the JSON success result when in JSON mode (no-op otherwise),
+    /// with the identifier and text of the last catalogue message, the
+    /// fields recorded with [`Self::result`] and the errors the command
+    /// reported but got past.
+    pub fn json_ok(&self) {
+        self.json_success("ok");
+    }
+
+    /// Print `value` on stdout as the JSON result of a command whose result
+    /// is a listing rather than a status object (JSON mode only). A command
+    /// that reports several, like `ext top`, prints one per line.
+    pub fn json_document<T: Serialize>(&self, value: &T) -> serde_json::Result<()> {
+        if !self.is_json() {
+            return Ok(());
+        }
+        let json = serde_json::to_value(value)?;
+        self.emitted.store(true, Ordering::Relaxed);
+        self.backend.result(&json);
+        Ok(())
+    }
+
+    /// Emit the JSON result of a command that succeeded for only some of
+    /// what it was asked to do, like [`Self::json_ok`] with status
+    /// `partial`.
+    pub fn json_partial(&self) {
+        self.json_success("partial");
+    }
+
+    fn json_success(&self, status: &str) {
+        if !self.is_json() || self.emitted.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let outcome = self.outcome.lock().ok().and_then(|o| o.clone());
+        let mut json = match outcome {
+            Some((id, text)) => {
+                serde_json::json!({ "status": status, "message_id": id, "message": text })
+            }
+            None => serde_json::json!({ "status": status }),
+        };
+        self.add_data_and_errors(&mut json);
+        add_trace(&mut json, self.recorded_trace().as_ref());
+        self.backend.result(&json);
+    }
+
+    /// Emit the JSON error result: the fields of the first error reported,
+    /// with all of them under `errors`.
+    fn json_error(&self) {
+        if !self.is_json() || self.emitted.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let first = self
+            .errors
+            .lock()
+            .ok()
+            .and_then(|errors| errors.first().cloned());
+        let mut json = serde_json::json!({ "status": "error" });
+        if let (Some(object), Some(serde_json::Value::Object(first))) =
+            (json.as_object_mut(), first)
+        {
+            object.extend(first);
+        }
+        self.add_data_and_errors(&mut json);
+        self.backend.result(&json);
+    }
+
+    fn add_data_and_errors(&self, json: &mut serde_json::Value) {
+        let Some(object) = json.as_object_mut() else {
+            return;
+        };
+        if let Ok(data) = self.data.lock() {
+            object.extend(data.clone());
+        }
+        if let Ok(errors) = self.errors.lock() {
+            if !errors.is_empty() {
+                object.insert("errors".to_string(), errors.clone().into());
+            }
         }
     }
 
+    fn has_errors(&self) -> bool {
+        self.errors.lock().is_ok_and(|errors| !errors.is_empty())
+    }
+
+    /// Emit the JSON result of the command unless it was printed already:
+    /// the error result when errors were reported, otherwise the success
+    /// result when the command reported an outcome or result fields.
+    pub fn finish(&self) {
+        if self.emitted.load(Ordering::Relaxed) {
+            return;
+        }
+        let reported = self.outcome.lock().is_ok_and(|o| o.is_some())
+            || self.data.lock().is_ok_and(|d| !d.is_empty());
+        if self.has_errors() {
+            self.json_error();
+        } else if reported {
+            self.json_ok();
+        }
+    }
+
+    /// Emit the JSON result (see [`Self::finish`]) and exit with `code`.
+    pub fn exit(&self, code: i32) -> ! {
+        self.finish();
+        std::process::exit(code)
+    }
+
     /// Print an error message
     /// Always shows detailed error information for developers
     pub fn error(&self, operation: &str, message: &str) {
-        let color_choice = Self::color_choice();
+        self.print_error(operation, message, None);
+    }
 
-        let mut stderr = StandardStream::stderr(color_choice);
-        let mut color_spec = ColorSpec::new();
-        color_spec.set_fg(Some(Color::Red)).set_bold(true);
+    /// Print an error message with its catalogue code and remediation hint
+    pub fn error_with(&self, operation: &str, message: &str, diagnostic: &Diagnostic) {
+        self.print_error(operation, message, Some(diagnostic));
+    }
 
-        if stderr.set_color(&color_spec).is_ok() && color_choice != ColorChoice::Never {
-            let _ = write!(&mut stderr, "[ERROR]");
-            let _ = stderr.reset();
-            eprintln!(" {operation}: {message}");
-        } else {
-            eprintln!("[ERROR] {operation}: {message}");
+    /// Errors always go to stderr. In JSON mode they are also kept for the
+    /// result, so callers parsing stdout see why a command failed.
+    fn print_error(&self, operation: &str, message: &str, diagnostic: Option<&Diagnostic>) {
+        let trace = self.recorded_trace();
+        self.backend
+            .error(operation, message, diagnostic, trace.as_ref());
+        if self.is_json() {
+            if let Ok(mut errors) = self.errors.lock() {
+                errors.push(error_object(operation, message, diagnostic, trace.as_ref()));
+            }
         }
+    }
 
-        if !self.verbose {
-            eprintln!("   Use --verbose for more details");
+    /// Report something that went wrong without failing the command, such
+    /// as a hook that exited with an error. Always shown: on stderr as text,
+    /// or as a `warning` event in JSON mode. In streaming mode it is sent
+    /// through the channel immediately.
+    pub fn warning(&self, message: &str) {
+        if let Some(ref tx) = self.sender {
+            let _ = tx.send(format!("[WARNING] {message}"));
+        } else {
+            self.backend.message(Kind::Warning, None, message);
         }
     }
 
-    /// Print an informational message
-    /// Suppressed in JSON mode
+    /// Print an informational message (verbose only)
     pub fn info(&self, operation: &str, message: &str) {
-        if self.json {
-            return;
-        }
         if self.verbose {
-            self.print_colored_prefix_with_op("INFO", Color::Blue, operation, message);
+            self.backend.message(Kind::Info, Some(operation), message);
         }
     }
 
-    /// Print detailed progress information (verbose only, suppressed in JSON mode)
+    /// Print detailed progress information (verbose only)
     pub fn progress(&self, message: &str) {
-        if self.json {
-            return;
-        }
         if self.verbose {
-            println!("   {message}");
+            self.backend.message(Kind::Progress, None, message);
         }
     }
 
-    /// Print a step in a process (verbose only, suppressed in JSON mode)
-    pub fn step(&self, step: &str, description: &str) {
-        if self.json {
-            return;
+    /// Show a line written by a running command, prefixed with the command
+    /// (verbose only). In streaming mode the line is always sent, and the
+    /// client shows it by its own verbosity.
+    pub fn command_output(&self, command: &str, line: &str) {
+        if let Some(ref tx) = self.sender {
+            let _ = tx.send(format!("[OUTPUT] {command}: {line}"));
+        } else {
+            self.progress(&format!("{command}: {line}"));
         }
+    }
+
+    /// Print a step in a process (verbose only)
+    pub fn step(&self, step: &str, description: &str) {
         if self.verbose {
-            println!("   → {step}: {description}");
+            self.backend.message(Kind::Step, Some(step), description);
         }
     }
 
-    /// Print raw output (like command results, suppressed in JSON mode)
+    /// Print raw output, like command results (verbose only)
     pub fn raw(&self, content: &str) {
-        if self.json {
-            return;
-        }
         if self.verbose {
-            println!("{content}");
+            self.backend.message(Kind::Status, None, content);
         }
     }
 
-    /// Get the verbosity level
+    /// Whether callers should print verbose detail themselves. False in
+    /// JSON mode, where such text would corrupt the result on stdout; the
+    /// verbose messages of this manager are still sent as events.
     pub fn is_verbose(&self) -> bool {
-        self.verbose
+        self.verbose && !self.is_json()
     }
 
-    /// Print a status header (suppressed in JSON mode)
+    /// Print a status header
     pub fn status_header(&self, title: &str) {
-        if self.json {
-            return;
-        }
-        if self.verbose {
-            println!("\n{title}");
-            println!("{}", "=".repeat(title.len()));
-            println!();
-        } else {
-            println!("{title}");
-        }
+        self.backend.message(Kind::Header, None, title);
     }
 
-    /// Print a brief status (suppressed in JSON mode)
+    /// Print a brief status
     pub fn status(&self, message: &str) {
-        if self.json {
-            return;
-        }
-        println!("{message}");
+        self.backend.message(Kind::Status, None, message);
     }
 
     /// Log an informational message.
     ///
     /// In normal mode: prints to stdout with color (always, regardless of verbosity).
     /// In streaming mode: sends through channel immediately.
-    /// In capture mode: captures to the message buffer for returning via varlink.
     pub fn log_info(&self, message: &str) {
         if let Some(ref tx) = self.sender {
             let _ = tx.send(format!("[INFO] {message}"));
-        } else if !self.json {
-            self.print_colored_prefix("INFO", Color::Blue, message);
+        } else {
+            self.backend.message(Kind::Info, None, message);
         }
     }
 
@@ -217,8 +602,8 @@ impl OutputManager {
     pub fn log_success(&self, message: &str) {
         if let Some(ref tx) = self.sender {
             let _ = tx.send(format!("[SUCCESS] {message}"));
-        } else if !self.json {
-            self.print_colored_prefix("SUCCESS", Color::Green, message);
+        } else {
+            self.backend.message(Kind::Success, None, message);
         }
     }
 }
@@ -10,7 +10,7 @@
 
 use crate::manifest::ManifestExtension;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -24,7 +24,7 @@ pub struct RuntimeOverrides {
     pub version: u32,
     /// Per-extension overrides keyed by extension name.
     #[serde(default)]
-    pub extensions: HashMap<String, ExtensionOverride>,
+    pub extensions: BTreeMap<String, ExtensionOverride>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -114,6 +114,8 @@ mod tests {
             image_id: None,
             image_type: None,
             sha256: None,
+            sha512: None,
+            blake3: None,
             enabled,
         }
     }
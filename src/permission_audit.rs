@@ -0,0 +1,174 @@
+//! Permission and ownership audit of extension contents.
+//!
+//! With `[avocado.permissions] enabled = true`, every extension tree is
+//! walked before it is linked for a merge. World-writable entries, setuid or
+//! setgid files and entries owned by an unexpected user end up on the host
+//! once merged, so they are reported and, unless `on_finding = "warn"`, the
+//! extension is left out of the merge. Symlinks are not followed.
+
+use crate::config::PermissionAuditSettings;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+const S_ISUID: u32 = 0o4000;
+const S_ISGID: u32 = 0o2000;
+const S_ISVTX: u32 = 0o1000;
+const S_IWOTH: u32 = 0o0002;
+
+/// What is wrong with an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingKind {
+    /// Writable by any user (sticky directories excepted)
+    WorldWritable,
+    /// Regular file with the setuid or setgid bit
+    Setuid,
+    /// Owned by a uid outside `allowed_uids`
+    Ownership,
+}
+
+/// One anomaly, with the path as it appears once merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub path: String,
+    pub mode: u32,
+    pub uid: u32,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = self.mode & 0o7777;
+        match self.kind {
+            FindingKind::WorldWritable => write!(f, "{} is world-writable ({mode:04o})", self.path),
+            FindingKind::Setuid => write!(f, "{} is setuid/setgid ({mode:04o})", self.path),
+            FindingKind::Ownership => write!(f, "{} is owned by uid {}", self.path, self.uid),
+        }
+    }
+}
+
+/// Check a single entry's metadata.
+fn check_entry(
+    merged_path: &str,
+    meta: &fs::Metadata,
+    settings: &PermissionAuditSettings,
+    findings: &mut Vec<Finding>,
+) {
+    let mode = meta.mode();
+    let file_type = meta.file_type();
+    let mut push = |kind| {
+        findings.push(Finding {
+            kind,
+            path: merged_path.to_string(),
+            mode,
+            uid: meta.uid(),
+        })
+    };
+
+    if !file_type.is_symlink()
+        && mode & S_IWOTH != 0
+        && !(file_type.is_dir() && mode & S_ISVTX != 0)
+    {
+        push(FindingKind::WorldWritable);
+    }
+    if file_type.is_file()
+        && mode & (S_ISUID | S_ISGID) != 0
+        && !settings.allowed_setuid.iter().any(|p| p == merged_path)
+    {
+        push(FindingKind::Setuid);
+    }
+    if !settings.allowed_uids.contains(&meta.uid()) {
+        push(FindingKind::Ownership);
+    }
+}
+
+fn walk(
+    dir: &Path,
+    merged_dir: &str,
+    settings: &PermissionAuditSettings,
+    findings: &mut Vec<Finding>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.flatten().collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let Ok(meta) = fs::symlink_metadata(entry.path()) else {
+            continue;
+        };
+        let merged_path = format!("{merged_dir}/{}", entry.file_name().to_string_lossy());
+        check_entry(&merged_path, &meta, settings, findings);
+        if meta.is_dir() {
+            walk(&entry.path(), &merged_path, settings, findings);
+        }
+    }
+}
+
+/// Audit an extension tree. The root directory itself is not reported.
+pub fn audit_tree(root: &Path, settings: &PermissionAuditSettings) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    walk(root, "", settings, &mut findings);
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn settings_for(root: &Path) -> PermissionAuditSettings {
+        PermissionAuditSettings {
+            enabled: true,
+            allowed_uids: vec![fs::metadata(root).unwrap().uid()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_audit_tree() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::create_dir_all(root.join("usr/share/tmp")).unwrap();
+        fs::write(root.join("usr/bin/tool"), "").unwrap();
+        fs::write(root.join("usr/bin/helper"), "").unwrap();
+        fs::write(root.join("usr/bin/open"), "").unwrap();
+        fs::set_permissions(
+            root.join("usr/bin/helper"),
+            fs::Permissions::from_mode(0o4755),
+        )
+        .unwrap();
+        fs::set_permissions(root.join("usr/bin/open"), fs::Permissions::from_mode(0o666)).unwrap();
+        fs::set_permissions(
+            root.join("usr/share/tmp"),
+            fs::Permissions::from_mode(0o1777),
+        )
+        .unwrap();
+
+        let mut settings = settings_for(root);
+        let findings = audit_tree(root, &settings);
+        let found: Vec<(FindingKind, &str)> =
+            findings.iter().map(|f| (f.kind, f.path.as_str())).collect();
+        assert_eq!(
+            found,
+            vec![
+                (FindingKind::Setuid, "/usr/bin/helper"),
+                (FindingKind::WorldWritable, "/usr/bin/open"),
+            ]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "/usr/bin/helper is setuid/setgid (4755)"
+        );
+
+        settings.allowed_setuid = vec!["/usr/bin/helper".to_string()];
+        settings.allowed_uids = vec![u32::MAX];
+        let findings = audit_tree(root, &settings);
+        assert!(findings.iter().all(|f| f.kind != FindingKind::Setuid));
+        assert!(findings
+            .iter()
+            .any(|f| f.kind == FindingKind::Ownership && f.path == "/usr/bin/tool"));
+    }
+}
@@ -0,0 +1,258 @@
+//! Named phases of the merge pipeline, for boot profiling.
+//!
+//! A merge spends its time scanning extensions, loop mounting images,
+//! running systemd-sysext/confext and running post-merge tasks and hooks.
+//! Under systemd all of it used to show up as one opaque service in
+//! `systemd-analyze blame`. Each phase is now announced with an sd_notify
+//! `STATUS=` update (effective when the unit sets `NotifyAccess=`), the time
+//! spent in each phase is reported when the merge finishes, and with
+//! `[avocado.profiling] phase_units` the external commands of each phase
+//! run in their own transient units named after the phase
+//! (`avocado-merge-mounting-<pid>-<n>.service`), so boot profiling tools
+//! attribute their time to the phase.
+//!
+//! Phases nest: an image mounted while scanning counts towards mounting,
+//! not scanning. With OpenTelemetry export enabled each phase entered is
+//! also a span (see [`crate::telemetry`]).
+
+use std::process::Command;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Environment variable overriding `[avocado.profiling] phase_units`.
+pub const PHASE_UNITS_ENV: &str = "AVOCADO_PHASE_UNITS";
+
+/// A phase of the merge pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Discovering extensions and planning links
+    Scanning,
+    /// Loop mounting extension images
+    Mounting,
+    /// Running systemd-sysext and systemd-confext
+    Merging,
+    /// depmod, ldconfig, modprobe, daemon-reload and on-merge commands
+    Hooks,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [
+        Phase::Scanning,
+        Phase::Mounting,
+        Phase::Merging,
+        Phase::Hooks,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Scanning => "scanning",
+            Phase::Mounting => "mounting",
+            Phase::Merging => "merging",
+            Phase::Hooks => "hooks",
+        }
+    }
+
+    fn index(self) -> usize {
+        Phase::ALL.iter().position(|p| *p == self).unwrap_or(0)
+    }
+}
+
+struct State {
+    current: Option<Phase>,
+    since: Instant,
+    totals: [Duration; 4],
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+static UNIT_SEQ: AtomicU32 = AtomicU32::new(0);
+
+/// Restores the enclosing phase when dropped.
+pub struct PhaseGuard {
+    previous: Option<Phase>,
+    /// Span of the phase, when entering it changed the phase
+    _span: Option<crate::telemetry::Span>,
+}
+
+impl Drop for PhaseGuard {
+    fn drop(&mut self) {
+        switch(self.previous);
+    }
+}
+
+/// Enter `phase` until the returned guard is dropped.
+pub fn enter(phase: Phase) -> PhaseGuard {
+    let previous = switch(Some(phase));
+    PhaseGuard {
+        previous,
+        _span: (previous != Some(phase)).then(|| crate::telemetry::span(phase.as_str())),
+    }
+}
+
+/// The phase being run, if any.
+pub fn current() -> Option<Phase> {
+    STATE
+        .lock()
+        .ok()
+        .and_then(|s| s.as_ref().and_then(|s| s.current))
+}
+
+/// Make `to` the current phase, charging the time since the last switch to
+/// the phase being left. Returns that phase.
+fn switch(to: Option<Phase>) -> Option<Phase> {
+    let Ok(mut guard) = STATE.lock() else {
+        return None;
+    };
+    let state = guard.get_or_insert_with(|| State {
+        current: None,
+        since: Instant::now(),
+        totals: [Duration::ZERO; 4],
+    });
+    let now = Instant::now();
+    let previous = state.current;
+    if let Some(phase) = previous {
+        state.totals[phase.index()] += now - state.since;
+    }
+    state.current = to;
+    state.since = now;
+    drop(guard);
+
+    if let Some(phase) = to.filter(|phase| Some(*phase) != previous) {
+        notify(&format!("STATUS=Merging extensions: {}", phase.as_str()));
+    }
+    previous
+}
+
+/// Forget the time recorded so far, e.g. by a merge that failed.
+pub fn reset() {
+    let _ = take_timings();
+}
+
+/// Time spent in each phase since the last call, for phases that ran.
+pub fn take_timings() -> Vec<(Phase, Duration)> {
+    let Ok(mut guard) = STATE.lock() else {
+        return Vec::new();
+    };
+    let Some(state) = guard.as_mut() else {
+        return Vec::new();
+    };
+    let totals = std::mem::replace(&mut state.totals, [Duration::ZERO; 4]);
+    Phase::ALL
+        .into_iter()
+        .zip(totals)
+        .filter(|(_, total)| !total.is_zero())
+        .collect()
+}
+
+/// Timings as `scanning 12ms, mounting 340ms, ...`.
+pub fn format_timings(timings: &[(Phase, Duration)]) -> String {
+    timings
+        .iter()
+        .map(|(phase, total)| format!("{} {}ms", phase.as_str(), total.as_millis()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Send `state` to the service manager when running under a unit with a
+/// notification socket. Errors are ignored: notifications are advisory.
+pub fn notify(state: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let Ok(sock) = std::os::unix::net::UnixDatagram::unbound() else {
+        return;
+    };
+    let socket = socket.to_string_lossy();
+    if let Some(name) = socket.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        if let Ok(addr) = std::os::unix::net::SocketAddr::from_abstract_name(name) {
+            let _ = sock.send_to_addr(state.as_bytes(), &addr);
+        }
+    } else {
+        let _ = sock.send_to(state.as_bytes(), socket.as_ref() as &str);
+    }
+}
+
+/// Phase units need the system manager, so `--user` mode never uses them.
+fn units_enabled() -> bool {
+    crate::settings::current().phase_units && !crate::user_mode::is_user()
+}
+
+/// `cmd` wrapped in `systemd-run` so it runs in a transient unit named
+/// after the current phase, or `None` when phase units are off, no phase
+/// is running or `cmd` already is a `systemd-run` invocation. The unit
+/// gets only the environment set on `cmd`, not avocadoctl's own.
+pub fn unit_command(cmd: &Command, limit: Option<Duration>) -> Option<Command> {
+    if !units_enabled() {
+        return None;
+    }
+    let phase = current()?;
+    let program = cmd.get_program().to_string_lossy().to_string();
+    if program.ends_with("systemd-run") {
+        return None;
+    }
+
+    let seq = UNIT_SEQ.fetch_add(1, Ordering::Relaxed);
+    let mut wrapped = Command::new(crate::tools::program("systemd-run"));
+    wrapped
+        .arg(format!(
+            "--unit=avocado-merge-{}-{}-{seq}",
+            phase.as_str(),
+            std::process::id()
+        ))
+        .arg(format!(
+            "--description=avocadoctl merge phase: {}",
+            phase.as_str()
+        ))
+        // Early boot: do not wait for basic.target
+        .arg("--property=DefaultDependencies=no")
+        .args(["--wait", "--pipe", "--quiet", "--collect"]);
+    if let Some(limit) = limit {
+        wrapped.arg(format!("--property=RuntimeMaxSec={}", limit.as_secs()));
+    }
+    if let Some(dir) = cmd.get_current_dir() {
+        wrapped.arg(format!("--working-directory={}", dir.display()));
+    }
+    for (key, value) in cmd.get_envs() {
+        if let Some(value) = value {
+            wrapped.arg(format!(
+                "--setenv={}={}",
+                key.to_string_lossy(),
+                value.to_string_lossy()
+            ));
+        }
+    }
+    wrapped.arg("--").arg(&program).args(cmd.get_args());
+    Some(wrapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_phases_charge_the_inner_phase() {
+        {
+            let _scanning = enter(Phase::Scanning);
+            {
+                let _mounting = enter(Phase::Mounting);
+                assert_eq!(current(), Some(Phase::Mounting));
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            assert_eq!(current(), Some(Phase::Scanning));
+        }
+        assert_eq!(current(), None);
+
+        let timings = take_timings();
+        let mounting = timings
+            .iter()
+            .find(|(p, _)| *p == Phase::Mounting)
+            .map(|(_, d)| *d)
+            .unwrap();
+        assert!(mounting >= Duration::from_millis(20));
+        assert!(timings
+            .iter()
+            .all(|(p, d)| *p != Phase::Scanning || *d < mounting));
+        assert!(take_timings().is_empty());
+    }
+}
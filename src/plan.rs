@@ -0,0 +1,195 @@
+//! Reviewable change plans: `avocadoctl plan` and `avocadoctl apply`.
+//!
+//! `avocadoctl plan FILE` records what a refresh would do right now — the
+//! extensions it merges and unmerges, the link changes and the
+//! AVOCADO_ON_MERGE / AVOCADO_ON_UNMERGE commands it runs — together with a
+//! [`StateSnapshot`] of the state it was computed from. After the plan has
+//! been reviewed, `avocadoctl apply FILE` computes a fresh plan and refreshes
+//! only when it matches the reviewed one; any drift (a new extension, a
+//! changed image, a different enable state) refuses the apply.
+
+use crate::snapshot::{self, SnapshotDifference, StateSnapshot};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Current plan schema version. Bumped only on non-additive changes.
+pub const PLAN_VERSION: u32 = 1;
+
+/// A hook command the refresh runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedHook {
+    /// `on-unmerge` or `on-merge`.
+    pub phase: String,
+    pub command: String,
+    /// Extensions declaring the command.
+    pub extensions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangePlan {
+    pub version: u32,
+    /// Planning time in seconds since the Unix epoch.
+    #[serde(default)]
+    pub created_at: u64,
+    /// Extensions merged by the apply that are not merged now.
+    #[serde(default)]
+    pub merge: Vec<String>,
+    /// Extensions merged now that the apply leaves unmerged.
+    #[serde(default)]
+    pub unmerge: Vec<String>,
+    /// Extensions merged before and after the apply.
+    #[serde(default)]
+    pub keep: Vec<String>,
+    /// Link changes in /run/extensions and /run/confexts, in order.
+    #[serde(default)]
+    pub actions: Vec<String>,
+    /// Hook commands, on-unmerge first.
+    #[serde(default)]
+    pub hooks: Vec<PlannedHook>,
+    /// State the plan was computed from.
+    pub state: StateSnapshot,
+}
+
+impl ChangePlan {
+    /// Load a plan from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read plan '{}': {e}", path.display()))?;
+        Self::from_json(&content)
+            .map_err(|e| format!("Failed to parse plan '{}': {e}", path.display()))
+    }
+
+    /// Parse a plan from its JSON representation.
+    pub fn from_json(content: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(content)
+    }
+
+    /// Serialize the plan as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Write the plan to `path` as pretty-printed JSON.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, self.to_json() + "\n")
+    }
+
+    /// Whether applying the plan changes which extensions are merged.
+    pub fn has_changes(&self) -> bool {
+        !self.merge.is_empty() || !self.unmerge.is_empty()
+    }
+
+    /// How the freshly computed `current` plan differs from this reviewed
+    /// one. Empty when the plan can be applied as reviewed.
+    pub fn drift(&self, current: &ChangePlan) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if self.version != PLAN_VERSION {
+            reasons.push(format!(
+                "plan format version {} is not supported (expected {PLAN_VERSION})",
+                self.version
+            ));
+            return reasons;
+        }
+        for difference in snapshot::compare(&self.state, &current.state) {
+            reasons.push(match difference {
+                SnapshotDifference::OnlyInLeft { name, .. } => {
+                    format!("- {name} no longer exists")
+                }
+                SnapshotDifference::OnlyInRight { name, .. } => {
+                    format!("+ {name} appeared after planning")
+                }
+                other => other.to_string(),
+            });
+        }
+        for (what, planned, now) in [
+            ("extensions to merge", &self.merge, &current.merge),
+            ("extensions to unmerge", &self.unmerge, &current.unmerge),
+            ("link changes", &self.actions, &current.actions),
+        ] {
+            if planned != now {
+                reasons.push(format!(
+                    "{what} changed: planned [{}], now [{}]",
+                    planned.join(", "),
+                    now.join(", ")
+                ));
+            }
+        }
+        if self.hooks != current.hooks {
+            reasons.push("hook commands changed".to_string());
+        }
+        reasons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::SnapshotExtension;
+
+    fn plan_with(extensions: Vec<SnapshotExtension>, merge: &[&str]) -> ChangePlan {
+        ChangePlan {
+            version: PLAN_VERSION,
+            created_at: 0,
+            merge: merge.iter().map(|s| s.to_string()).collect(),
+            unmerge: Vec::new(),
+            keep: Vec::new(),
+            actions: Vec::new(),
+            hooks: Vec::new(),
+            state: StateSnapshot {
+                version: crate::snapshot::SNAPSHOT_VERSION,
+                captured_at: 0,
+                hostname: None,
+                os_version_id: Some("1.0".to_string()),
+                runtime: None,
+                extensions,
+            },
+        }
+    }
+
+    fn extension(name: &str, version: &str) -> SnapshotExtension {
+        SnapshotExtension {
+            name: name.to_string(),
+            version: Some(version.to_string()),
+            enabled: Some(true),
+            merged: false,
+            is_sysext: true,
+            is_confext: false,
+            origin: None,
+            image_id: None,
+            provenance: None,
+            lifecycle: None,
+        }
+    }
+
+    #[test]
+    fn test_identical_plan_has_no_drift() {
+        let plan = plan_with(vec![extension("app", "1.0")], &["app-1.0"]);
+        let mut later = plan.clone();
+        later.created_at = 100;
+        later.state.captured_at = 100;
+        assert!(plan.drift(&later).is_empty());
+        assert!(plan.has_changes());
+    }
+
+    #[test]
+    fn test_drift_reports_state_and_plan_changes() {
+        let plan = plan_with(vec![extension("app", "1.0")], &["app-1.0"]);
+        let now = plan_with(
+            vec![extension("app", "1.1"), extension("tools", "2.0")],
+            &["app-1.1", "tools-2.0"],
+        );
+        let drift = plan.drift(&now);
+        assert!(drift.iter().any(|r| r.contains("app: version 1.0 -> 1.1")));
+        assert!(drift.iter().any(|r| r.contains("tools appeared")));
+        assert!(drift
+            .iter()
+            .any(|r| r.starts_with("extensions to merge changed")));
+    }
+
+    #[test]
+    fn test_plan_roundtrips_through_json() {
+        let plan = plan_with(vec![extension("app", "1.0")], &["app-1.0"]);
+        assert_eq!(ChangePlan::from_json(&plan.to_json()).unwrap(), plan);
+    }
+}
@@ -0,0 +1,82 @@
+//! Runtimes downloaded ahead of time by `ext prefetch`.
+//!
+//! `ext prefetch` downloads and verifies the runtime the update repository
+//! offers and stages it without activating it, so a device with a narrow
+//! maintenance window and a slow link can fetch during the window and
+//! switch later without touching the network. The staged runtime is
+//! recorded in `prefetch.json` in the avocado base directory, and garbage
+//! collection keeps it until it is activated or replaced.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Prefetch record file (in the avocado base directory).
+pub const PREFETCH_FILENAME: &str = "prefetch.json";
+
+/// A runtime staged by `ext prefetch`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrefetchRecord {
+    pub runtime_id: String,
+    pub name: String,
+    pub version: String,
+    /// Repository the runtime was downloaded from
+    pub url: String,
+    /// Seconds since the Unix epoch
+    pub fetched_at: u64,
+}
+
+impl PrefetchRecord {
+    pub fn new(runtime_id: &str, name: &str, version: &str, url: &str) -> Self {
+        Self {
+            runtime_id: runtime_id.to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+            url: url.to_string(),
+            fetched_at: crate::clock::now(),
+        }
+    }
+}
+
+/// The last prefetched runtime, if its runtime directory still exists.
+pub fn load(base_dir: &Path) -> Option<PrefetchRecord> {
+    let content = fs::read_to_string(base_dir.join(PREFETCH_FILENAME)).ok()?;
+    let record: PrefetchRecord = serde_json::from_str(&content).ok()?;
+    base_dir
+        .join("runtimes")
+        .join(&record.runtime_id)
+        .is_dir()
+        .then_some(record)
+}
+
+pub fn save(base_dir: &Path, record: &PrefetchRecord) -> std::io::Result<()> {
+    fs::create_dir_all(base_dir)?;
+    fs::write(
+        base_dir.join(PREFETCH_FILENAME),
+        serde_json::to_string_pretty(record).unwrap_or_default(),
+    )
+}
+
+/// Forget the prefetched runtime (it stays staged).
+pub fn clear(base_dir: &Path) {
+    let _ = fs::remove_file(base_dir.join(PREFETCH_FILENAME));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_roundtrip_requires_runtime_dir() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let record = PrefetchRecord::new("abc123", "dev", "1.2.0", "https://updates.example");
+        save(tmp.path(), &record).unwrap();
+        assert!(load(tmp.path()).is_none(), "runtime dir does not exist yet");
+
+        fs::create_dir_all(tmp.path().join("runtimes/abc123")).unwrap();
+        assert_eq!(load(tmp.path()), Some(record));
+
+        clear(tmp.path());
+        assert!(load(tmp.path()).is_none());
+    }
+}
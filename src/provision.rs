@@ -0,0 +1,152 @@
+//! Device provisioning from one manifest, for `avocadoctl provision`.
+//!
+//! A factory line provisions each device with a single invocation that
+//! reads a JSON or TOML manifest from stdin (or a file):
+//!
+//! ```toml
+//! os_release = "1.4.0"                  # optional, defaults to the running VERSION_ID
+//! install = ["/mnt/factory/camera-2.1.0.raw"]   # copied into the extensions directory
+//! enable = ["camera-2.1.0", "sensor-*"] # names, glob patterns or absolute paths
+//! force = false                         # enable despite release data mismatches
+//!
+//! [repository]                          # written to [avocado.update] url
+//! url = "https://updates.example.com/repo"
+//!
+//! [config.avocado.gc]                   # merged into the configuration file
+//! runtime_retention = 2
+//! ```
+//!
+//! Every part is idempotent: configuration that is already present, images
+//! already installed with the same content and extensions already enabled
+//! are left alone, so running the same manifest twice changes nothing.
+
+use serde::{Deserialize, Serialize};
+
+/// What a device is provisioned with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProvisionManifest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_release: Option<String>,
+    /// Image files copied into the extensions directory
+    #[serde(default)]
+    pub install: Vec<String>,
+    /// Extensions enabled for the OS release
+    #[serde(default)]
+    pub enable: Vec<String>,
+    #[serde(default)]
+    pub force: bool,
+    /// Update repository `ext prefetch` and `ext upgrade` use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<Repository>,
+    /// Configuration merged into the configuration file, in its layout
+    #[serde(default, skip_serializing_if = "toml::Table::is_empty")]
+    pub config: toml::Table,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Repository {
+    pub url: String,
+}
+
+impl ProvisionManifest {
+    /// Parse a manifest: JSON when it starts with `{`, TOML otherwise.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let parsed = if content.trim_start().starts_with('{') {
+            serde_json::from_str(content).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(content).map_err(|e| e.to_string())
+        };
+        parsed.map_err(|e| format!("Invalid provisioning manifest: {e}"))
+    }
+
+    /// Configuration the manifest sets: `config` with the repository URL
+    /// added as `avocado.update.url`.
+    pub fn config_fragment(&self) -> toml::Table {
+        let mut fragment = self.config.clone();
+        if let Some(repository) = &self.repository {
+            let mut update = toml::Table::new();
+            update.insert("url".to_string(), repository.url.clone().into());
+            let mut avocado = toml::Table::new();
+            avocado.insert("update".to_string(), update.into());
+            let mut repo = toml::Table::new();
+            repo.insert("avocado".to_string(), avocado.into());
+            merge_tables(&mut fragment, &repo);
+        }
+        fragment
+    }
+}
+
+/// Merge `fragment` into `base`: tables are merged key by key, any other
+/// value replaces the one in `base`. Returns whether `base` changed.
+pub fn merge_tables(base: &mut toml::Table, fragment: &toml::Table) -> bool {
+    let mut changed = false;
+    for (key, value) in fragment {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                changed |= merge_tables(existing, table);
+            }
+            (Some(existing), _) if existing == value => {}
+            _ => {
+                base.insert(key.clone(), value.clone());
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml_and_json() {
+        let toml = ProvisionManifest::parse(
+            r#"
+enable = ["camera-2.1.0"]
+
+[repository]
+url = "https://updates.example.com/repo"
+
+[config.avocado.gc]
+runtime_retention = 2
+"#,
+        )
+        .unwrap();
+        let json = ProvisionManifest::parse(
+            r#"{"enable": ["camera-2.1.0"],
+                "repository": {"url": "https://updates.example.com/repo"},
+                "config": {"avocado": {"gc": {"runtime_retention": 2}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(toml, json);
+        assert!(ProvisionManifest::parse("enable = [\"a\"]\nbogus = 1\n").is_err());
+    }
+
+    #[test]
+    fn test_config_fragment_merges_idempotently() {
+        let manifest = ProvisionManifest::parse(
+            r#"
+[repository]
+url = "https://updates.example.com/repo"
+
+[config.avocado.update]
+stream_os_to_partition = true
+"#,
+        )
+        .unwrap();
+        let mut base: toml::Table = toml::from_str("[avocado.ext]\ndir = \"/data/ext\"\n").unwrap();
+        assert!(merge_tables(&mut base, &manifest.config_fragment()));
+        assert!(!merge_tables(&mut base, &manifest.config_fragment()));
+
+        let config: crate::config::Config = toml::from_str(&base.to_string()).unwrap();
+        assert_eq!(config.avocado.ext.dir, "/data/ext");
+        assert!(config.avocado.update.stream_os_to_partition);
+        assert_eq!(
+            config.avocado.update.url.as_deref(),
+            Some("https://updates.example.com/repo")
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Reboot requests raised by extensions.
+//!
+//! An extension opts in by setting `AVOCADO_REBOOT_REQUIRED=yes` in its
+//! extension-release file. After a merge that included such an extension,
+//! the requesting extension names are recorded in a marker file under
+//! `/run/avocado` (so the request naturally clears on the next boot) and
+//! surfaced by `status` and by the merge/refresh exit code.
+
+use crate::commands::ext::SystemdError;
+use crate::config::RebootAction;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+pub const REBOOT_REQUIRED_FILENAME: &str = "reboot-required";
+
+/// Exit code returned by merge/refresh when the operation succeeded but at
+/// least one merged extension requested a reboot.
+pub const EXIT_REBOOT_REQUIRED: i32 = 3;
+
+/// Path of the reboot-required marker, respecting AVOCADO_TEST_MODE.
+pub fn marker_path() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/{REBOOT_REQUIRED_FILENAME}"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path(&format!(
+            "/run/avocado/{REBOOT_REQUIRED_FILENAME}"
+        )))
+    }
+}
+
+/// Release file key an extension requests a reboot with.
+pub const REBOOT_REQUIRED_KEY: &str = "AVOCADO_REBOOT_REQUIRED";
+
+/// Whether release file content sets AVOCADO_REBOOT_REQUIRED (see
+/// [`crate::extension_release::flag`]).
+pub fn parse_reboot_required(content: &str) -> bool {
+    crate::extension_release::flag(
+        &crate::os_release::OsRelease::parse(content),
+        REBOOT_REQUIRED_KEY,
+    )
+}
+
+/// Record the extensions that requested a reboot, one name per line.
+/// Names already recorded since boot are kept so the request is not lost
+/// if a later merge no longer includes the extension.
+pub fn record(names: &[String]) -> std::io::Result<()> {
+    record_at(&marker_path(), names)
+}
+
+/// Record reboot requests into a specific marker file.
+pub fn record_at(path: &Path, names: &[String]) -> std::io::Result<()> {
+    let mut all = pending_from(path);
+    for name in names {
+        if !all.contains(name) {
+            all.push(name.clone());
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, all.join("\n") + "\n")
+}
+
+/// Extensions that have requested a reboot since the last boot.
+pub fn pending() -> Vec<String> {
+    pending_from(&marker_path())
+}
+
+/// Read reboot requests from a specific marker file.
+pub fn pending_from(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|content| {
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Ask systemd to perform `action` without blocking on its completion, so
+/// the caller can still flush its output. No-op for [`RebootAction::None`].
+pub fn trigger(action: RebootAction) -> Result<(), SystemdError> {
+    let verb = match action {
+        RebootAction::None => return Ok(()),
+        RebootAction::SoftReboot => "soft-reboot",
+        RebootAction::Reboot => "reboot",
+    };
+    if let Some(result) = crate::backend::simulate("systemctl", &["--no-block", verb]) {
+        return result.map(|_| ());
+    }
+    let cmd = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        "mock-systemctl"
+    } else {
+        "systemctl"
+    };
+    let output = ProcessCommand::new(cmd)
+        .args(["--no-block", verb])
+        .output()
+        .map_err(|e| SystemdError::CommandFailed {
+            command: format!("{cmd} {verb}"),
+            source: e,
+        })?;
+    if !output.status.success() {
+        return Err(SystemdError::CommandExitedWithError {
+            command: format!("{cmd} {verb}"),
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reboot_required() {
+        assert!(parse_reboot_required("AVOCADO_REBOOT_REQUIRED=yes\n"));
+        assert!(parse_reboot_required(
+            "ID=x\nAVOCADO_REBOOT_REQUIRED=\"true\"\n"
+        ));
+        assert!(parse_reboot_required("  AVOCADO_REBOOT_REQUIRED=1"));
+        assert!(!parse_reboot_required("AVOCADO_REBOOT_REQUIRED=no\n"));
+        assert!(!parse_reboot_required("ID=x\nVERSION_ID=1\n"));
+    }
+
+    #[test]
+    fn test_record_and_pending_deduplicate() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("avocado").join(REBOOT_REQUIRED_FILENAME);
+
+        assert!(pending_from(&path).is_empty());
+        record_at(&path, &["a".to_string()]).unwrap();
+        record_at(&path, &["b".to_string(), "a".to_string()]).unwrap();
+        assert_eq!(pending_from(&path), vec!["a".to_string(), "b".to_string()]);
+    }
+}
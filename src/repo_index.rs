@@ -0,0 +1,120 @@
+//! Extension versions the update repository offers, for `ext status`.
+//!
+//! Every time `ext prefetch` or `ext upgrade` fetches the repository's
+//! runtime manifest, the extension versions it lists are recorded in
+//! `repo-index.json` in the avocado base directory. When `[avocado.update]
+//! url` is configured, `ext status` compares the installed extensions
+//! against that index to show which have an update available, without
+//! touching the network itself.
+
+use crate::config::Config;
+use crate::manifest::RuntimeManifest;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Repository index file (in the avocado base directory).
+pub const INDEX_FILENAME: &str = "repo-index.json";
+
+/// Why `ext status --updates-only` cannot answer.
+pub const NO_INDEX: &str = "No repository index: set url in [avocado.update] and run 'avocadoctl ext upgrade --dry-run' to fetch it";
+
+/// Extension versions offered by the update repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoIndex {
+    /// Repository the manifest was fetched from
+    pub url: String,
+    /// Seconds since the Unix epoch
+    pub fetched_at: u64,
+    /// Offered version by extension name
+    pub extensions: BTreeMap<String, String>,
+}
+
+impl RepoIndex {
+    pub fn from_manifest(url: &str, manifest: &RuntimeManifest) -> Self {
+        Self {
+            url: url.to_string(),
+            fetched_at: crate::clock::now(),
+            extensions: manifest
+                .extensions
+                .iter()
+                .map(|e| (e.name.clone(), e.version.clone()))
+                .collect(),
+        }
+    }
+
+    /// The version the repository offers for `name`, if it has it.
+    pub fn latest(&self, name: &str) -> Option<&str> {
+        self.extensions.get(name).map(String::as_str)
+    }
+
+    /// Whether the repository offers an update for `name` at `installed`;
+    /// `None` when it does not offer the extension at all. Pre-releases
+    /// only count with `prereleases` set.
+    pub fn update_available(&self, name: &str, installed: &str, prereleases: bool) -> Option<bool> {
+        self.latest(name)
+            .map(|latest| crate::upgrade::is_newer(installed, latest, prereleases))
+    }
+}
+
+pub fn load(base_dir: &Path) -> Option<RepoIndex> {
+    let content = fs::read_to_string(base_dir.join(INDEX_FILENAME)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Record the extensions of the runtime `manifest` fetched from `url`.
+pub fn save(base_dir: &Path, url: &str, manifest: &RuntimeManifest) -> std::io::Result<()> {
+    fs::create_dir_all(base_dir)?;
+    fs::write(
+        base_dir.join(INDEX_FILENAME),
+        serde_json::to_string_pretty(&RepoIndex::from_manifest(url, manifest)).unwrap_or_default(),
+    )
+}
+
+/// The index `ext status` compares against: only when an update
+/// repository is configured and its manifest has been fetched.
+pub fn configured(config: &Config) -> Option<RepoIndex> {
+    config.avocado.update.url.as_ref()?;
+    load(Path::new(&config.get_avocado_base_dir()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{ManifestExtension, RuntimeInfo};
+
+    #[test]
+    fn test_index_roundtrip_and_update_check() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        assert!(load(tmp.path()).is_none());
+
+        let manifest = RuntimeManifest {
+            manifest_version: 1,
+            id: "new".to_string(),
+            built_at: "2026-01-01T00:00:00Z".to_string(),
+            runtime: RuntimeInfo {
+                name: "dev".to_string(),
+                version: "1.0.0".to_string(),
+            },
+            extensions: vec![ManifestExtension {
+                name: "app".to_string(),
+                version: "1.1.0".to_string(),
+                image_id: None,
+                image_type: None,
+                sha256: None,
+                sha512: None,
+                blake3: None,
+                enabled: true,
+            }],
+            os_bundle: None,
+        };
+        save(tmp.path(), "https://updates.example", &manifest).unwrap();
+        let index = load(tmp.path()).unwrap();
+        assert_eq!(index.url, "https://updates.example");
+        assert_eq!(index.latest("app"), Some("1.1.0"));
+        assert_eq!(index.update_available("app", "1.0.0", false), Some(true));
+        assert_eq!(index.update_available("app", "1.1.0", false), Some(false));
+        assert_eq!(index.update_available("base", "1.0.0", false), None);
+    }
+}
@@ -0,0 +1,137 @@
+//! Safe-mode boot: merge only essential extensions.
+//!
+//! Booting with `avocado.safe_mode` on the kernel command line, or creating
+//! `/run/avocado/safe-mode`, puts merges in safe mode: every extension whose
+//! release file does not set `AVOCADO_ESSENTIAL=yes` is skipped. The names
+//! skipped by the last merge are recorded in `/run/avocado/safe-mode-skipped`
+//! for `ext status`. Both files live on tmpfs, so an ordinary reboot leaves
+//! safe mode.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Kernel command line flag; `avocado.safe_mode=0` (or `no`, `false`,
+/// `off`) leaves it off.
+pub const CMDLINE_FLAG: &str = "avocado.safe_mode";
+
+pub const MARKER_FILENAME: &str = "safe-mode";
+pub const SKIPPED_FILENAME: &str = "safe-mode-skipped";
+
+/// What put the system in safe mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    KernelCmdline,
+    Marker(PathBuf),
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::KernelCmdline => write!(f, "{CMDLINE_FLAG} on the kernel command line"),
+            Trigger::Marker(path) => write!(f, "{} exists", path.display()),
+        }
+    }
+}
+
+/// `/run/avocado`, or `$TMPDIR/avocado` in test mode.
+fn run_dir() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path("/run/avocado"))
+    }
+}
+
+/// `/proc/cmdline`, or `$TMPDIR/avocado/cmdline` in test mode.
+fn cmdline_path() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        run_dir().join("cmdline")
+    } else {
+        PathBuf::from("/proc/cmdline")
+    }
+}
+
+/// Whether a kernel command line turns safe mode on. The last occurrence
+/// of the flag wins, as for other kernel parameters.
+pub fn cmdline_requests(cmdline: &str) -> bool {
+    cmdline
+        .split_whitespace()
+        .rev()
+        .find_map(|word| match word.split_once('=') {
+            None if word == CMDLINE_FLAG => Some(true),
+            Some((key, value)) if key == CMDLINE_FLAG => Some(!matches!(
+                value.to_ascii_lowercase().as_str(),
+                "0" | "no" | "false" | "off"
+            )),
+            _ => None,
+        })
+        .unwrap_or(false)
+}
+
+/// Whether the system is in safe mode, and why.
+pub fn detect() -> Option<Trigger> {
+    let marker = run_dir().join(MARKER_FILENAME);
+    if marker.exists() {
+        return Some(Trigger::Marker(marker));
+    }
+    fs::read_to_string(cmdline_path())
+        .is_ok_and(|cmdline| cmdline_requests(&cmdline))
+        .then_some(Trigger::KernelCmdline)
+}
+
+/// Record the extensions the current merge skipped, replacing the last
+/// merge's record; an empty list removes it.
+pub fn record_skipped(names: &[String]) -> std::io::Result<()> {
+    record_skipped_at(&run_dir().join(SKIPPED_FILENAME), names)
+}
+
+fn record_skipped_at(path: &Path, names: &[String]) -> std::io::Result<()> {
+    if names.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, names.join("\n") + "\n")
+}
+
+/// Extensions the last merge skipped in safe mode.
+pub fn skipped() -> Vec<String> {
+    crate::reboot::pending_from(&run_dir().join(SKIPPED_FILENAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmdline_requests() {
+        assert!(cmdline_requests(
+            "root=/dev/sda2 ro avocado.safe_mode quiet"
+        ));
+        assert!(cmdline_requests("avocado.safe_mode=yes"));
+        assert!(!cmdline_requests("avocado.safe_mode=0"));
+        assert!(!cmdline_requests(
+            "avocado.safe_mode=1 avocado.safe_mode=off"
+        ));
+        assert!(!cmdline_requests("avocado.safe_modex root=/dev/sda2"));
+        assert!(!cmdline_requests(""));
+    }
+
+    #[test]
+    fn test_record_skipped_replaces_previous_record() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("avocado").join(SKIPPED_FILENAME);
+        record_skipped_at(&path, &["app".to_string(), "tools".to_string()]).unwrap();
+        record_skipped_at(&path, &["tools".to_string()]).unwrap();
+        assert_eq!(crate::reboot::pending_from(&path), ["tools"]);
+        record_skipped_at(&path, &[]).unwrap();
+        assert!(!path.exists());
+        record_skipped_at(&path, &[]).unwrap();
+    }
+}
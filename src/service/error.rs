@@ -20,6 +20,22 @@ pub enum AvocadoError {
     #[error("Configuration error: {message}")]
     ConfigurationError { message: String },
 
+    #[error("Command '{command}' timed out after {seconds}s")]
+    CommandTimedOut {
+        command: String,
+        setting: &'static str,
+        seconds: u64,
+    },
+
+    #[error("HITL extensions still syncing after {waited_ms}ms: {}", extensions.join(", "))]
+    HitlSyncInProgress {
+        extensions: Vec<String>,
+        waited_ms: u64,
+    },
+
+    #[error("systemd is not running (no /run/systemd/system): cannot {operation} extensions")]
+    SystemdNotRunning { operation: String },
+
     #[error("Extension not found: {name}")]
     ExtensionNotFound { name: String },
 
@@ -50,6 +66,9 @@ pub enum AvocadoError {
     #[error("Unmount failed for '{extension}': {reason}")]
     UnmountFailed { extension: String, reason: String },
 
+    #[error("Quiesce failed for '{extension}': {reason}")]
+    QuiesceFailed { extension: String, reason: String },
+
     #[error("No root authority configured")]
     NoRootAuthority,
 
@@ -59,6 +78,12 @@ pub enum AvocadoError {
     #[error("Parse failed: {reason}")]
     ParseFailed { reason: String },
 
+    #[error("Trust store error: {reason}")]
+    TrustFailed { reason: String },
+
+    #[error("System state changed since the plan was made: {}", reasons.join("; "))]
+    PlanDrifted { reasons: Vec<String> },
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
@@ -82,6 +107,39 @@ impl From<crate::commands::ext::SystemdError> for AvocadoError {
             crate::commands::ext::SystemdError::ConfigurationError { message } => {
                 AvocadoError::ConfigurationError { message }
             }
+            crate::commands::ext::SystemdError::CommandTimedOut {
+                command,
+                setting,
+                seconds,
+            } => AvocadoError::CommandTimedOut {
+                command,
+                setting,
+                seconds,
+            },
+            crate::commands::ext::SystemdError::HitlSyncInProgress {
+                extensions,
+                waited_ms,
+            } => AvocadoError::HitlSyncInProgress {
+                extensions,
+                waited_ms,
+            },
+            crate::commands::ext::SystemdError::SystemdNotRunning { operation } => {
+                AvocadoError::SystemdNotRunning { operation }
+            }
+            crate::commands::ext::SystemdError::Io(e) => {
+                AvocadoError::Io(std::io::Error::new(e.source.kind(), e.to_string()))
+            }
+            e @ (crate::commands::ext::SystemdError::InvalidImage { .. }
+            | crate::commands::ext::SystemdError::Untrusted { .. }) => {
+                AvocadoError::ConfigurationError {
+                    message: e.to_string(),
+                }
+            }
+            // Extension context is attached while preparing extensions for
+            // a merge
+            e @ crate::commands::ext::SystemdError::Extension { .. } => AvocadoError::MergeFailed {
+                reason: e.to_string(),
+            },
         }
     }
 }
@@ -119,6 +177,15 @@ impl From<crate::update::UpdateError> for AvocadoError {
     }
 }
 
+/// Convert from trust::TrustError
+impl From<crate::trust::TrustError> for AvocadoError {
+    fn from(e: crate::trust::TrustError) -> Self {
+        AvocadoError::TrustFailed {
+            reason: e.to_string(),
+        }
+    }
+}
+
 /// Convert from commands::hitl::HitlError
 impl From<crate::commands::hitl::HitlError> for AvocadoError {
     fn from(e: crate::commands::hitl::HitlError) -> Self {
@@ -137,6 +204,19 @@ impl From<crate::commands::hitl::HitlError> for AvocadoError {
                     reason: error,
                 }
             }
+            crate::commands::hitl::HitlError::Restart { units, error } => {
+                AvocadoError::CommandExitedWithError {
+                    command: format!("systemctl try-restart {units}"),
+                    exit_code: None,
+                    stderr: error,
+                }
+            }
+            crate::commands::hitl::HitlError::NotExported {
+                extension, reason, ..
+            } => AvocadoError::MountFailed { extension, reason },
+            crate::commands::hitl::HitlError::Io(e) => {
+                AvocadoError::Io(std::io::Error::new(e.source.kind(), e.to_string()))
+            }
             other => AvocadoError::CommandFailed {
                 command: "hitl".to_string(),
                 source: std::io::Error::other(other.to_string()),
@@ -1,17 +1,43 @@
+use crate::audit::{AuditImage, AuditReport};
 use crate::commands::ext;
 use crate::config::Config;
+use crate::extension_release::{Lifecycle, Provenance};
+use crate::fault::FailPoint;
+use crate::hash::Checksummer;
+use crate::link_journal;
+use crate::manifest::RuntimeManifest;
+use crate::merge_target::MergeTarget;
 use crate::output::OutputManager;
+use crate::plan::{ChangePlan, PlannedHook};
+use crate::prefetch::{self, PrefetchRecord};
 use crate::service::error::AvocadoError;
-use crate::service::types::{DisableResult, EnableResult, ExtensionInfo, SetEnabledResult};
+use crate::service::types::{
+    ApplyResult, DisableResult, EnableResult, ExtensionDetail, ExtensionInfo, ExtensionStatus,
+    PruneOsReleasesResult, SetEnabledResult, UpgradeResult,
+};
+use crate::snapshot::{SnapshotExtension, SnapshotRuntime, StateSnapshot};
+use crate::transaction::{LinkTarget, TransactionManifest, TransactionPlan, TransactionStep};
+use std::collections::BTreeMap;
 use std::fs;
 use std::os::unix::fs as unix_fs;
 use std::path::Path;
 use std::sync::mpsc;
 use std::thread;
 
+/// Output of the scans a query makes: its warnings go to the daemon's log.
+fn query_output() -> OutputManager {
+    OutputManager::new(false, false)
+}
+
 /// List all available extensions from the extensions directory.
 pub fn list_extensions(config: &Config) -> Result<Vec<ExtensionInfo>, AvocadoError> {
     let extensions_path = config.get_extensions_dir();
+    ext::source::check_dir(
+        config,
+        "Extensions",
+        Path::new(&extensions_path),
+        &query_output(),
+    )?;
     let entries = match fs::read_dir(&extensions_path) {
         Ok(e) => e,
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
@@ -45,6 +71,15 @@ pub fn list_extensions(config: &Config) -> Result<Vec<ExtensionInfo>, AvocadoErr
                     is_confext: false,
                     is_directory: false,
                 });
+            } else if let Some(ext_name) = crate::archive::archive_stem(name) {
+                result.push(ExtensionInfo {
+                    name: ext_name.to_string(),
+                    version: None,
+                    path: path.display().to_string(),
+                    is_sysext: true,
+                    is_confext: false,
+                    is_directory: false,
+                });
             }
         }
     }
@@ -53,6 +88,12 @@ pub fn list_extensions(config: &Config) -> Result<Vec<ExtensionInfo>, AvocadoErr
     Ok(result)
 }
 
+/// List the extensions enabled for the current os-release and the installed
+/// ones that are not, with version, type, scopes and origin.
+pub fn list_extensions_detailed(config: &Config) -> Result<Vec<ExtensionDetail>, AvocadoError> {
+    ext::collect_extension_details(config, &query_output()).map_err(AvocadoError::from)
+}
+
 // ── Streaming service functions ──────────────────────────────────────────────
 
 /// Merge extensions with streaming output.
@@ -60,6 +101,7 @@ pub fn list_extensions(config: &Config) -> Result<Vec<ExtensionInfo>, AvocadoErr
 /// and a join handle for the worker thread.
 pub fn merge_extensions_streaming(
     config: &Config,
+    target: Option<MergeTarget>,
 ) -> (
     mpsc::Receiver<String>,
     thread::JoinHandle<Result<(), AvocadoError>>,
@@ -68,7 +110,11 @@ pub fn merge_extensions_streaming(
     let config = config.clone();
     let handle = thread::spawn(move || {
         let output = OutputManager::new_streaming(tx);
-        ext::merge_extensions_internal(&config, &output).map_err(AvocadoError::from)
+        match &target {
+            Some(target) => ext::merge_extensions_into(&config, Some(target), &output),
+            None => ext::merge_extensions_internal(&config, &output),
+        }
+        .map_err(AvocadoError::from)
     });
     (rx, handle)
 }
@@ -83,8 +129,9 @@ pub fn unmerge_extensions_streaming(
     let (tx, rx) = mpsc::sync_channel(4);
     let handle = thread::spawn(move || {
         let output = OutputManager::new_streaming(tx);
-        ext::unmerge_extensions_internal_with_options(true, unmount, &output)
-            .map_err(AvocadoError::from)
+        let result = ext::unmerge_extensions_internal_with_options(true, unmount, &output);
+        crate::daemon_reload::flush_or_warn(&output);
+        result.map_err(AvocadoError::from)
     });
     (rx, handle)
 }
@@ -100,17 +147,111 @@ pub fn refresh_extensions_streaming(
     let config = config.clone();
     let handle = thread::spawn(move || {
         let output = OutputManager::new_streaming(tx);
+        refresh_with_output(&config, &output)
+    });
+    (rx, handle)
+}
+
+pub(crate) fn refresh_with_output(
+    config: &Config,
+    output: &OutputManager,
+) -> Result<(), AvocadoError> {
+    crate::systemd_runtime::require("refresh")?;
+    crate::hitl_sync::wait_until_idle(config.hitl(), output)?;
+    let before = crate::merge_history::latest();
+    let span = crate::telemetry::operation("refresh", output);
+    span.record(unmerge_then_merge(config, output))?;
+    drop(span);
+    ext::run_change_hook(config, &before, output);
+    Ok(())
+}
 
-        // First unmerge (skip depmod since we'll call it after merge, don't unmount loops —
-        // the caller may be running from a loop-mounted extension like avocado-connect)
-        ext::unmerge_extensions_internal_with_options(false, false, &output)
-            .map_err(AvocadoError::from)?;
+fn unmerge_then_merge(config: &Config, output: &OutputManager) -> Result<(), AvocadoError> {
+    // First unmerge (skip depmod since we'll call it after merge, don't unmount loops —
+    // the caller may be running from a loop-mounted extension like avocado-connect)
+    ext::unmerge_extensions_internal_with_options(false, false, output)
+        .map_err(AvocadoError::from)?;
 
-        // Invalidate NFS caches for any HITL-mounted extensions
-        ext::invalidate_hitl_caches(&output);
+    // Invalidate NFS caches for any HITL-mounted extensions
+    ext::invalidate_hitl_caches(output);
 
-        // Then merge (this will call depmod via post-merge processing)
-        ext::merge_extensions_internal(&config, &output).map_err(AvocadoError::from)
+    // Then merge (this will call depmod via post-merge processing)
+    ext::merge_extensions_internal(config, output).map_err(AvocadoError::from)
+}
+
+/// Refresh with streaming output, unless nothing changed since the last
+/// merge and `force` is not set. Without `force`, only the extensions that
+/// changed are re-merged when possible. The worker returns whether it
+/// refreshed.
+pub fn refresh_if_changed_streaming(
+    config: &Config,
+    force: bool,
+) -> (
+    mpsc::Receiver<String>,
+    thread::JoinHandle<Result<bool, AvocadoError>>,
+) {
+    let (tx, rx) = mpsc::sync_channel(4);
+    let config = config.clone();
+    let handle = thread::spawn(move || {
+        let output = OutputManager::new_streaming(tx);
+        if force {
+            return refresh_with_output(&config, &output).map(|()| true);
+        }
+        crate::systemd_runtime::require("refresh")?;
+        crate::hitl_sync::wait_until_idle(config.hitl(), &output)?;
+        match ext::refresh_incrementally(&config, &output) {
+            ext::IncrementalRefresh::UpToDate => {
+                output.log_info("Nothing changed since the last merge; skipping refresh");
+                Ok(false)
+            }
+            ext::IncrementalRefresh::Refreshed => Ok(true),
+            ext::IncrementalRefresh::FullNeeded => {
+                refresh_with_output(&config, &output).map(|()| true)
+            }
+        }
+    });
+    (rx, handle)
+}
+
+/// Apply a reviewed plan with streaming output: refresh only if a plan
+/// computed now matches it, otherwise fail with [`AvocadoError::PlanDrifted`].
+pub fn apply_plan_streaming(
+    config: &Config,
+    plan: ChangePlan,
+) -> (
+    mpsc::Receiver<String>,
+    thread::JoinHandle<Result<(), AvocadoError>>,
+) {
+    let (tx, rx) = mpsc::sync_channel(4);
+    let config = config.clone();
+    let handle = thread::spawn(move || {
+        let output = OutputManager::new_streaming(tx);
+        let current = create_plan(&config)?;
+        let reasons = plan.drift(&current);
+        if !reasons.is_empty() {
+            return Err(AvocadoError::PlanDrifted { reasons });
+        }
+        if !plan.has_changes() {
+            output.progress("Plan has no changes; nothing to apply");
+            return Ok(());
+        }
+        refresh_with_output(&config, &output)
+    });
+    (rx, handle)
+}
+
+/// Sync enable state and request a systemd soft-reboot with streaming output.
+pub fn soft_reboot_refresh_streaming(
+    config: &Config,
+) -> (
+    mpsc::Receiver<String>,
+    thread::JoinHandle<Result<(), AvocadoError>>,
+) {
+    let (tx, rx) = mpsc::sync_channel(4);
+    let config = config.clone();
+    let handle = thread::spawn(move || {
+        let output = OutputManager::new_streaming(tx);
+        ext::soft_reboot_refresh_internal(&config, &output).map_err(AvocadoError::from)
     });
     (rx, handle)
 }
@@ -119,8 +260,11 @@ pub fn refresh_extensions_streaming(
 
 /// Merge extensions using systemd-sysext and systemd-confext.
 /// Returns log messages produced during the operation.
-pub fn merge_extensions(config: &Config) -> Result<Vec<String>, AvocadoError> {
-    let (rx, handle) = merge_extensions_streaming(config);
+pub fn merge_extensions(
+    config: &Config,
+    target: Option<MergeTarget>,
+) -> Result<Vec<String>, AvocadoError> {
+    let (rx, handle) = merge_extensions_streaming(config, target);
     let messages: Vec<String> = rx.into_iter().collect();
     handle.join().unwrap_or_else(|_| {
         Err(AvocadoError::MergeFailed {
@@ -156,10 +300,54 @@ pub fn refresh_extensions(config: &Config) -> Result<Vec<String>, AvocadoError>
     Ok(messages)
 }
 
+/// Refresh unless nothing changed since the last merge and `force` is not
+/// set. Returns log messages produced during the operation and whether it
+/// refreshed.
+pub fn refresh_if_changed(
+    config: &Config,
+    force: bool,
+) -> Result<(Vec<String>, bool), AvocadoError> {
+    let (rx, handle) = refresh_if_changed_streaming(config, force);
+    let messages: Vec<String> = rx.into_iter().collect();
+    let refreshed = handle.join().unwrap_or_else(|_| {
+        Err(AvocadoError::MergeFailed {
+            reason: "internal panic".into(),
+        })
+    })?;
+    Ok((messages, refreshed))
+}
+
+/// Sync enable state and request a systemd soft-reboot.
+/// Returns log messages produced during the operation.
+pub fn soft_reboot_refresh(config: &Config) -> Result<Vec<String>, AvocadoError> {
+    let (rx, handle) = soft_reboot_refresh_streaming(config);
+    let messages: Vec<String> = rx.into_iter().collect();
+    handle.join().unwrap_or_else(|_| {
+        Err(AvocadoError::MergeFailed {
+            reason: "internal panic".into(),
+        })
+    })?;
+    Ok(messages)
+}
+
+/// Apply a reviewed plan (see [`apply_plan_streaming`]).
+/// Returns log messages produced during the operation.
+pub fn apply_plan(config: &Config, plan: ChangePlan) -> Result<Vec<String>, AvocadoError> {
+    let (rx, handle) = apply_plan_streaming(config, plan);
+    let messages: Vec<String> = rx.into_iter().collect();
+    handle.join().unwrap_or_else(|_| {
+        Err(AvocadoError::MergeFailed {
+            reason: "internal panic".into(),
+        })
+    })?;
+    Ok(messages)
+}
+
 /// Enable extensions for a specific OS release version.
 pub fn enable_extensions(
     os_release_version: Option<&str>,
     extensions: &[&str],
+    force: bool,
     config: &Config,
 ) -> Result<EnableResult, AvocadoError> {
     let version_id = match os_release_version {
@@ -174,7 +362,7 @@ pub fn enable_extensions(
         let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/avocado/os-releases/{version_id}")
     } else {
-        format!("/var/lib/avocado/os-releases/{version_id}")
+        crate::user_mode::system_path(&format!("/var/lib/avocado/os-releases/{version_id}"))
     };
 
     // Create directory
@@ -191,37 +379,59 @@ pub fn enable_extensions(
 
     let mut enabled = 0;
     let mut failed = 0;
+    let mut problems = Vec::new();
 
-    for ext_name in extensions {
-        let ext_dir_path = format!("{extensions_dir}/{ext_name}");
-        let ext_raw_path = format!("{extensions_dir}/{ext_name}.raw");
+    let mut targets = Vec::new();
+    for arg in extensions {
+        match ext::resolve_enable_targets(arg, &extensions_dir) {
+            Ok(matched) => targets.extend(matched),
+            Err(message) => {
+                problems.push(message);
+                failed += 1;
+            }
+        }
+    }
 
-        let source_path = if Path::new(&ext_dir_path).exists() {
-            ext_dir_path
-        } else if Path::new(&ext_raw_path).exists() {
-            ext_raw_path
-        } else {
+    let mut links = Vec::new();
+    for ext::EnableTarget {
+        name: ext_name,
+        source_path,
+    } in &targets
+    {
+        let fatal: Vec<String> = ext::validate_enable_target(
+            ext_name,
+            Path::new(source_path),
+            &version_id,
+            config,
+            false,
+        )
+        .problems
+        .into_iter()
+        .filter(|problem| problem.is_fatal(config, force))
+        .map(|problem| problem.message)
+        .collect();
+        if !fatal.is_empty() {
+            problems.push(format!(
+                "{ext_name} failed validation for OS release {version_id} (use --force to override): {}",
+                fatal.join(", ")
+            ));
             failed += 1;
             continue;
-        };
+        }
 
-        let target_path = format!(
-            "{}/{}",
-            os_releases_dir,
-            Path::new(&source_path)
+        links.push(TransactionStep::Link {
+            file_name: Path::new(source_path)
                 .file_name()
                 .unwrap()
                 .to_string_lossy()
-        );
-
-        // Remove existing symlink
-        if Path::new(&target_path).exists() && fs::remove_file(&target_path).is_err() {
-            failed += 1;
-            continue;
-        }
+                .to_string(),
+            source: source_path.clone(),
+        });
+    }
 
-        // Create symlink
-        if unix_fs::symlink(&source_path, &target_path).is_err() {
+    let journal = begin_link_journal("enable", &os_releases_dir, &links)?;
+    for step in &links {
+        if link_journal::apply_step(Path::new(&os_releases_dir), step).is_err() {
             failed += 1;
         } else {
             enabled += 1;
@@ -232,14 +442,222 @@ pub fn enable_extensions(
     if enabled > 0 {
         ext::sync_directory(Path::new(&os_releases_dir)).map_err(AvocadoError::from)?;
     }
+    commit_link_journal(journal)?;
 
     if failed > 0 {
+        let mut reason = format!("{enabled} succeeded, {failed} failed");
+        if !problems.is_empty() {
+            reason.push_str(&format!(": {}", problems.join("; ")));
+        }
+        return Err(AvocadoError::MergeFailed { reason });
+    }
+
+    Ok(EnableResult { enabled, failed })
+}
+
+fn os_releases_dir_for(version_id: &str) -> String {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/os-releases/{version_id}")
+    } else {
+        crate::user_mode::system_path(&format!("/var/lib/avocado/os-releases/{version_id}"))
+    }
+}
+
+/// Links currently in an os-releases directory, file name to target.
+fn read_release_links(dir: &str) -> BTreeMap<String, String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_symlink())
+                .filter_map(|entry| {
+                    let target = fs::read_link(entry.path()).ok()?;
+                    Some((
+                        entry.file_name().to_string_lossy().to_string(),
+                        target.to_string_lossy().to_string(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Resolve a transaction manifest and compute its net change without
+/// touching anything. Returns the os-releases directory, the links in it
+/// and the plan. Any unresolvable or incompatible extension fails the
+/// whole transaction.
+pub fn plan_transaction(
+    manifest: &TransactionManifest,
+    config: &Config,
+) -> Result<(String, BTreeMap<String, String>, TransactionPlan), AvocadoError> {
+    let version_id = manifest
+        .os_release
+        .clone()
+        .unwrap_or_else(ext::read_os_version_id);
+    let extensions_dir = config.get_extensions_dir();
+    let os_releases_dir = os_releases_dir_for(&version_id);
+
+    let mut problems = Vec::new();
+    let mut targets = Vec::new();
+    let requests = manifest
+        .enable
+        .iter()
+        .map(|arg| (arg, false))
+        .chain(manifest.update.iter().map(|arg| (arg, true)));
+    for (arg, update) in requests {
+        let matched = match ext::resolve_enable_targets(arg, &extensions_dir) {
+            Ok(matched) => matched,
+            Err(message) => {
+                problems.push(message);
+                continue;
+            }
+        };
+        for ext::EnableTarget { name, source_path } in matched {
+            let fatal: Vec<String> = ext::validate_enable_target(
+                &name,
+                Path::new(&source_path),
+                &version_id,
+                config,
+                false,
+            )
+            .problems
+            .into_iter()
+            .filter(|problem| problem.is_fatal(config, manifest.force))
+            .map(|problem| problem.message)
+            .collect();
+            if !fatal.is_empty() {
+                problems.push(format!(
+                    "{name} failed validation for OS release {version_id}: {}",
+                    fatal.join(", ")
+                ));
+                continue;
+            }
+            let file_name = Path::new(&source_path)
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or(name);
+            targets.push(LinkTarget {
+                file_name,
+                source: source_path,
+                update,
+            });
+        }
+    }
+    if !problems.is_empty() {
+        return Err(AvocadoError::ConfigurationError {
+            message: format!("Transaction rejected: {}", problems.join("; ")),
+        });
+    }
+
+    let current = read_release_links(&os_releases_dir);
+    let plan = crate::transaction::plan_transaction(
+        &current,
+        &targets,
+        &manifest.disable,
+        ext::enable_target_name,
+        |name| ext::split_name_version(name).0,
+    )
+    .map_err(|message| AvocadoError::ConfigurationError {
+        message: format!("Transaction rejected: {message}"),
+    })?;
+    Ok((os_releases_dir, current, plan))
+}
+
+/// Put an os-releases directory back to `links`.
+fn restore_release_links(dir: &str, links: &BTreeMap<String, String>) {
+    for file_name in read_release_links(dir).keys() {
+        let _ = fs::remove_file(Path::new(dir).join(file_name));
+    }
+    for (file_name, target) in links {
+        let _ = unix_fs::symlink(target, Path::new(dir).join(file_name));
+    }
+    let _ = ext::sync_directory(Path::new(dir));
+}
+
+/// Journal `steps` for `dir` (see [`link_journal`]); nothing to journal
+/// when there are no steps.
+fn begin_link_journal(
+    operation: &str,
+    dir: &str,
+    steps: &[TransactionStep],
+) -> Result<Option<link_journal::PendingJournal>, AvocadoError> {
+    if steps.is_empty() {
+        return Ok(None);
+    }
+    link_journal::begin(&link_journal::state_dir(), operation, dir, steps)
+        .map(Some)
+        .map_err(|e| AvocadoError::ConfigurationError {
+            message: format!("Failed to write the link journal: {e}"),
+        })
+}
+
+fn commit_link_journal(journal: Option<link_journal::PendingJournal>) -> Result<(), AvocadoError> {
+    match journal {
+        Some(journal) => journal.commit().map_err(AvocadoError::from),
+        None => Ok(()),
+    }
+}
+
+fn apply_transaction_steps(dir: &str, steps: &[TransactionStep]) -> std::io::Result<()> {
+    for step in steps {
+        link_journal::apply_step(Path::new(dir), step)?;
+        // Fail with the first link changed, so the rollback has work to do
+        if crate::fault::fail_at(FailPoint::SymlinkSwap) {
+            return Err(std::io::Error::other(
+                crate::fault::injected(FailPoint::SymlinkSwap).to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Apply a transaction manifest: make every link change, then refresh once.
+/// When a link change or the refresh fails, the previous links are restored
+/// (and refreshed again if the refresh was what failed).
+pub fn apply_transaction(
+    manifest: &TransactionManifest,
+    config: &Config,
+) -> Result<ApplyResult, AvocadoError> {
+    let (os_releases_dir, previous, plan) = plan_transaction(manifest, config)?;
+    if plan.is_empty() {
+        return Ok(ApplyResult {
+            linked: 0,
+            unlinked: 0,
+            refreshed: false,
+        });
+    }
+
+    fs::create_dir_all(&os_releases_dir).map_err(|e| AvocadoError::ConfigurationError {
+        message: format!("Failed to create os-releases directory '{os_releases_dir}': {e}"),
+    })?;
+    let journal = begin_link_journal("apply", &os_releases_dir, &plan.steps)?;
+    if let Err(e) = apply_transaction_steps(&os_releases_dir, &plan.steps) {
+        restore_release_links(&os_releases_dir, &previous);
+        commit_link_journal(journal)?;
         return Err(AvocadoError::MergeFailed {
-            reason: format!("{enabled} succeeded, {failed} failed"),
+            reason: format!("Transaction rolled back: {e}"),
         });
     }
+    ext::sync_directory(Path::new(&os_releases_dir)).map_err(AvocadoError::from)?;
+    commit_link_journal(journal)?;
 
-    Ok(EnableResult { enabled, failed })
+    if let Err(e) = refresh_extensions(config) {
+        restore_release_links(&os_releases_dir, &previous);
+        let restored = match refresh_extensions(config) {
+            Ok(_) => "previous extensions restored".to_string(),
+            Err(again) => format!("restoring the previous extensions also failed: {again}"),
+        };
+        return Err(AvocadoError::MergeFailed {
+            reason: format!("Transaction rolled back after refresh failure ({e}); {restored}"),
+        });
+    }
+
+    Ok(ApplyResult {
+        linked: plan.linked(),
+        unlinked: plan.unlinked(),
+        refreshed: true,
+    })
 }
 
 /// Disable extensions for a specific OS release version.
@@ -257,7 +675,7 @@ pub fn disable_extensions(
         let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/avocado/os-releases/{version_id}")
     } else {
-        format!("/var/lib/avocado/os-releases/{version_id}")
+        crate::user_mode::system_path(&format!("/var/lib/avocado/os-releases/{version_id}"))
     };
 
     if !Path::new(&os_releases_dir).exists() {
@@ -269,6 +687,15 @@ pub fn disable_extensions(
     let mut disabled = 0;
     let mut failed = 0;
 
+    // The symlinks to remove, grouped by the extension they belong to
+    let mut groups: Vec<Vec<TransactionStep>> = Vec::new();
+    let unlink = |path: &Path| TransactionStep::Unlink {
+        file_name: path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+    };
     if all {
         let entries =
             fs::read_dir(&os_releases_dir).map_err(|e| AvocadoError::ConfigurationError {
@@ -278,56 +705,53 @@ pub fn disable_extensions(
         for entry in entries.flatten() {
             let path = entry.path();
             if path.is_symlink() {
-                match fs::remove_file(&path) {
-                    Ok(_) => disabled += 1,
-                    Err(_) => failed += 1,
-                }
+                groups.push(vec![unlink(&path)]);
             }
         }
     } else if let Some(ext_names) = extensions {
         for ext_name in ext_names {
-            let symlink_dir = format!("{os_releases_dir}/{ext_name}");
-            let symlink_raw = format!("{os_releases_dir}/{ext_name}.raw");
-            let mut found = false;
-
-            if Path::new(&symlink_dir).exists() {
-                match fs::remove_file(&symlink_dir) {
-                    Ok(_) => {
-                        disabled += 1;
-                        found = true;
-                    }
-                    Err(_) => {
-                        failed += 1;
-                        found = true;
-                    }
-                }
-            }
+            let symlinks: Vec<TransactionStep> = [
+                format!("{os_releases_dir}/{ext_name}"),
+                format!("{os_releases_dir}/{ext_name}.raw"),
+                format!(
+                    "{os_releases_dir}/{ext_name}{}",
+                    crate::archive::ARCHIVE_SUFFIX
+                ),
+            ]
+            .iter()
+            .map(Path::new)
+            .filter(|path| path.exists())
+            .map(unlink)
+            .collect();
 
-            if Path::new(&symlink_raw).exists() {
-                match fs::remove_file(&symlink_raw) {
-                    Ok(_) => {
-                        if !found {
-                            disabled += 1;
-                        }
-                        found = true;
-                    }
-                    Err(_) => {
-                        failed += 1;
-                        found = true;
-                    }
-                }
+            if symlinks.is_empty() {
+                failed += 1;
+            } else {
+                groups.push(symlinks);
             }
+        }
+    }
 
-            if !found {
-                failed += 1;
+    let steps: Vec<TransactionStep> = groups.iter().flatten().cloned().collect();
+    let journal = begin_link_journal("disable", &os_releases_dir, &steps)?;
+    for group in &groups {
+        let mut removed = false;
+        for step in group {
+            match link_journal::apply_step(Path::new(&os_releases_dir), step) {
+                Ok(()) => removed = true,
+                Err(_) => failed += 1,
             }
         }
+        if removed {
+            disabled += 1;
+        }
     }
 
     // Sync to disk
     if disabled > 0 {
         let _ = ext::sync_directory(Path::new(&os_releases_dir));
     }
+    commit_link_journal(journal)?;
 
     if failed > 0 {
         return Err(AvocadoError::UnmergeFailed {
@@ -338,11 +762,74 @@ pub fn disable_extensions(
     Ok(DisableResult { disabled, failed })
 }
 
-/// Show extension status.
+/// Remove the os-releases directories of OS versions no longer installed.
+/// The running VERSION_ID's directory is always kept, plus those selected by
+/// `keep` (see [`ext::plan_os_release_prune`]). Refuses while an OS update
+/// awaits verification: until the new slot has booted, which VERSION_IDs
+/// the A/B slots hold is not known. With `dry_run` nothing is removed.
+pub fn prune_os_releases(
+    keep: &[String],
+    dry_run: bool,
+) -> Result<PruneOsReleasesResult, AvocadoError> {
+    if let Some(pending) = crate::os_update::read_pending_update() {
+        return Err(AvocadoError::ConfigurationError {
+            message: format!(
+                "OS update to build '{}' is pending verification; prune os-releases after it has been verified or rolled back",
+                pending.os_build_id
+            ),
+        });
+    }
+    let current = ext::read_os_version_id();
+    if current == "unknown" {
+        return Err(AvocadoError::ConfigurationError {
+            message: "Cannot determine the running VERSION_ID from /etc/os-release; refusing to prune os-releases".into(),
+        });
+    }
+
+    let root = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        format!("{temp_base}/avocado/os-releases")
+    } else {
+        crate::user_mode::system_path("/var/lib/avocado/os-releases")
+    };
+    let (kept, removed) = ext::plan_os_release_prune(Path::new(&root), &current, keep);
+
+    if !dry_run {
+        for version in &removed {
+            let dir = Path::new(&root).join(version);
+            fs::remove_dir_all(&dir).map_err(|e| AvocadoError::ConfigurationError {
+                message: format!("Failed to remove '{}': {e}", dir.display()),
+            })?;
+        }
+        if !removed.is_empty() {
+            let _ = ext::sync_directory(Path::new(&root));
+        }
+    }
+
+    Ok(PruneOsReleasesResult {
+        current,
+        kept,
+        removed,
+    })
+}
+
+/// Show extension status. With `updates_only`, only the extensions the
+/// repository index offers an update for.
 pub fn status_extensions(
     config: &Config,
-) -> Result<Vec<crate::varlink::org_avocado_Extensions::ExtensionStatus>, AvocadoError> {
-    ext::collect_extension_status(config).map_err(AvocadoError::from)
+    updates_only: bool,
+) -> Result<Vec<ExtensionStatus>, AvocadoError> {
+    if !updates_only {
+        return ext::collect_extension_status(config, &query_output()).map_err(AvocadoError::from);
+    }
+    if crate::repo_index::configured(config).is_none() {
+        return Err(AvocadoError::ConfigurationError {
+            message: crate::repo_index::NO_INDEX.into(),
+        });
+    }
+    let mut extensions = ext::collect_extension_status(config, &query_output())?;
+    extensions.retain(|e| e.update_available == Some(true));
+    Ok(extensions)
 }
 
 /// Override the build-time `enabled` default for one or more extensions.
@@ -416,3 +903,396 @@ pub fn set_extensions_enabled(
 
     Ok(SetEnabledResult { updated, missing })
 }
+
+/// Capture the current extension / merge state as a [`StateSnapshot`]
+/// suitable for `ext snapshot` and `ext compare`.
+pub fn capture_snapshot(config: &Config) -> Result<StateSnapshot, AvocadoError> {
+    let statuses =
+        ext::collect_extension_status(config, &query_output()).map_err(AvocadoError::from)?;
+
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = Path::new(&base_dir);
+    let manifest = crate::manifest::RuntimeManifest::load_active(base_path);
+    let overrides = crate::overrides::RuntimeOverrides::load(
+        &base_path.join(crate::manifest::ACTIVE_LINK_NAME),
+    );
+
+    let extensions = statuses
+        .into_iter()
+        .map(|s| {
+            let enabled = manifest.as_ref().and_then(|m| {
+                m.extensions
+                    .iter()
+                    .find(|e| e.name == s.name)
+                    .map(|e| crate::overrides::effective_enabled(e, &overrides))
+            });
+            SnapshotExtension {
+                name: s.name,
+                version: s.version,
+                enabled,
+                merged: s.is_merged,
+                is_sysext: s.is_sysext,
+                is_confext: s.is_confext,
+                origin: s.origin,
+                image_id: s.image_id,
+                provenance: Some(Provenance {
+                    build_id: s.build_id,
+                    git_sha: s.git_sha,
+                    build_date: s.build_date,
+                })
+                .filter(|p| !p.is_empty()),
+                lifecycle: Some(Lifecycle {
+                    eol: s.eol,
+                    notes: s.notes,
+                })
+                .filter(|l| !l.is_empty()),
+            }
+        })
+        .collect();
+
+    let os_version_id = Some(ext::read_os_version_id()).filter(|v| v != "unknown");
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty());
+    let captured_at = crate::clock::now();
+
+    Ok(StateSnapshot {
+        version: crate::snapshot::SNAPSHOT_VERSION,
+        captured_at,
+        hostname,
+        os_version_id,
+        runtime: manifest.map(|m| SnapshotRuntime {
+            id: m.id,
+            name: m.runtime.name,
+            version: m.runtime.version,
+        }),
+        extensions,
+    })
+}
+
+/// Compute what a refresh would change now, as a [`ChangePlan`] for
+/// `avocadoctl plan`.
+pub fn create_plan(config: &Config) -> Result<ChangePlan, AvocadoError> {
+    let state = capture_snapshot(config)?;
+    let preview = ext::preview_refresh(config, &OutputManager::new(false, false))
+        .map_err(AvocadoError::from)?;
+
+    let merged_now: Vec<String> = state
+        .extensions
+        .iter()
+        .filter(|e| e.merged)
+        .map(|e| match &e.version {
+            Some(version) => format!("{}-{version}", e.name),
+            None => e.name.clone(),
+        })
+        .collect();
+    let merge = preview
+        .extensions
+        .iter()
+        .filter(|name| !merged_now.contains(name))
+        .cloned()
+        .collect();
+    let keep = preview
+        .extensions
+        .iter()
+        .filter(|name| merged_now.contains(name))
+        .cloned()
+        .collect();
+    let unmerge = merged_now
+        .iter()
+        .filter(|name| !preview.extensions.contains(name))
+        .cloned()
+        .collect();
+
+    Ok(ChangePlan {
+        version: crate::plan::PLAN_VERSION,
+        created_at: state.captured_at,
+        merge,
+        unmerge,
+        keep,
+        actions: preview.actions,
+        hooks: preview
+            .hooks
+            .into_iter()
+            .map(|(phase, command, extensions)| PlannedHook {
+                phase: phase.to_string(),
+                command,
+                extensions,
+            })
+            .collect(),
+        state,
+    })
+}
+
+/// Build an inventory report of every extension for `ext audit`: the
+/// snapshot state plus the path and SHA256 of each extension image.
+pub fn audit_report(config: &Config) -> Result<AuditReport, AvocadoError> {
+    let state = capture_snapshot(config)?;
+
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = Path::new(&base_dir);
+    let active_dir = base_path.join(crate::manifest::ACTIVE_LINK_NAME);
+    let manifest = crate::manifest::RuntimeManifest::load_active(base_path);
+    let manifest_sha256 = manifest.as_ref().and_then(|_| {
+        crate::hash::sha256_file(
+            &crate::hash::Checksummer::default(),
+            &active_dir.join(crate::manifest::MANIFEST_FILENAME),
+        )
+        .ok()
+    });
+
+    let mut images = Vec::new();
+    if let Some(ref m) = manifest {
+        for mext in &m.extensions {
+            images.push(AuditImage::inspect(
+                &mext.name,
+                Some(mext.version.clone()),
+                &mext.resolve_path(base_path),
+                mext.sha256.clone(),
+            ));
+        }
+    }
+
+    // Extensions outside the manifest: locate them in the extensions directory.
+    let extensions_dir = config.get_extensions_dir();
+    for ext in &state.extensions {
+        if images.iter().any(|i| i.name == ext.name) {
+            continue;
+        }
+        let stems: Vec<String> = match &ext.version {
+            Some(ver) => vec![format!("{}-{ver}", ext.name), ext.name.clone()],
+            None => vec![ext.name.clone()],
+        };
+        let found = stems.iter().find_map(|stem| {
+            ["", ".raw", ".kab", crate::archive::ARCHIVE_SUFFIX]
+                .iter()
+                .map(|suffix| Path::new(&extensions_dir).join(format!("{stem}{suffix}")))
+                .find(|p| p.exists())
+        });
+        if let Some(path) = found {
+            images.push(AuditImage::inspect(
+                &ext.name,
+                ext.version.clone(),
+                &path,
+                None,
+            ));
+        }
+    }
+
+    let generated_at = crate::clock::now();
+
+    let eol_reached = state
+        .extensions
+        .iter()
+        .filter(|e| {
+            e.lifecycle
+                .as_ref()
+                .is_some_and(|l| l.eol_reached_at(generated_at))
+        })
+        .map(|e| match &e.version {
+            Some(v) => format!("{}-{v}", e.name),
+            None => e.name.clone(),
+        })
+        .collect();
+
+    Ok(AuditReport {
+        version: crate::audit::AUDIT_VERSION,
+        generated_at,
+        manifest_sha256,
+        state,
+        images,
+        eol_reached,
+    })
+}
+
+/// Download the runtime the update repository at `url` (default:
+/// `[avocado.update] url`) offers and stage it without activating it, for
+/// `ext prefetch`. Returns the prefetched runtime and whether it is already
+/// the active one, in which case nothing is left to switch to.
+pub fn prefetch(
+    config: &Config,
+    url: Option<&str>,
+    auth_token: Option<&str>,
+    verbose: bool,
+) -> Result<(PrefetchRecord, bool), AvocadoError> {
+    let Some(url) = url.or(config.avocado.update.url.as_deref()) else {
+        return Err(AvocadoError::ConfigurationError {
+            message: "No update repository: pass --url or set url in [avocado.update]".into(),
+        });
+    };
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = Path::new(&base_dir);
+    let manifest = crate::update::prefetch_update(
+        url,
+        base_path,
+        auth_token,
+        None,
+        config.stream_os_to_partition(),
+        verbose,
+        config.get_spot_check_bytes(),
+        config.storage(),
+        &Checksummer::from_settings(config.checksum(), Some(url)),
+    )?;
+
+    let _ = crate::repo_index::save(base_path, url, &manifest);
+
+    let record = PrefetchRecord::new(
+        &manifest.id,
+        &manifest.runtime.name,
+        &manifest.runtime.version,
+        url,
+    );
+    let already_active = RuntimeManifest::list_all(base_path)
+        .iter()
+        .any(|(m, active)| *active && m.id == manifest.id);
+    if already_active {
+        prefetch::clear(base_path);
+    } else {
+        prefetch::save(base_path, &record)?;
+    }
+    Ok((record, already_active))
+}
+
+/// Upgrade the active runtime's extensions (all, or `names`) to the
+/// versions the update repository offers, as far as each extension's
+/// `[avocado.upgrade]` policy allows. With `offline` the runtime staged by
+/// `ext prefetch` is used instead of the repository. With `dry_run`
+/// nothing is downloaded beyond the repository manifest and nothing
+/// changes. Outside the `[avocado.maintenance]` windows the upgrade is
+/// queued for the daemon unless `force` is set.
+#[allow(clippy::too_many_arguments)]
+pub fn upgrade(
+    config: &Config,
+    names: &[String],
+    url: Option<&str>,
+    auth_token: Option<&str>,
+    offline: bool,
+    dry_run: bool,
+    force: bool,
+    verbose: bool,
+) -> Result<UpgradeResult, AvocadoError> {
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = Path::new(&base_dir);
+
+    if !dry_run && !force {
+        let schedule =
+            crate::maintenance::schedule(config).map_err(|e| AvocadoError::ConfigurationError {
+                message: e.to_string(),
+            })?;
+        let now = crate::clock::now();
+        if !schedule.is_open(now) {
+            crate::maintenance::queue(
+                base_path,
+                &crate::maintenance::QueuedUpgrade {
+                    names: names.to_vec(),
+                    url: url.map(str::to_string),
+                    offline,
+                    queued_at: now,
+                },
+            )?;
+            return Ok(UpgradeResult {
+                decisions: Vec::new(),
+                runtime_id: None,
+                queued: true,
+                queued_until: schedule.next_open(now),
+            });
+        }
+    }
+
+    let active = RuntimeManifest::load_active(base_path).ok_or_else(|| {
+        AvocadoError::ConfigurationError {
+            message: "No active runtime manifest. Provision a runtime first.".into(),
+        }
+    })?;
+
+    let url = url.or(config.avocado.update.url.as_deref());
+    let offered = if offline {
+        prefetch::load(base_path)
+            .and_then(|record| {
+                RuntimeManifest::load_from(&base_path.join("runtimes").join(record.runtime_id))
+            })
+            .ok_or_else(|| AvocadoError::ConfigurationError {
+                message: "No prefetched runtime: run 'avocadoctl ext prefetch' first".into(),
+            })?
+    } else {
+        let Some(url) = url else {
+            return Err(AvocadoError::ConfigurationError {
+                message: "No update repository: pass --url or set url in [avocado.update]".into(),
+            });
+        };
+        let manifest = crate::update::fetch_manifest(url, base_path, auth_token, verbose)?;
+        let _ = crate::repo_index::save(base_path, url, &manifest);
+        manifest
+    };
+
+    let decisions = crate::upgrade::plan(
+        &active,
+        &offered,
+        names,
+        |name| config.upgrade_policy(name),
+        config.upgrade_prereleases(),
+    )
+    .map_err(|unknown| AvocadoError::ExtensionNotFound {
+        name: unknown.join(", "),
+    })?;
+    let upgrades = decisions
+        .iter()
+        .any(|d| d.action == crate::upgrade::UpgradeAction::Upgrade);
+    if dry_run || !upgrades {
+        return Ok(UpgradeResult {
+            decisions,
+            runtime_id: None,
+            queued: false,
+            queued_until: None,
+        });
+    }
+
+    // Download the offered runtime's images; offline they are already staged
+    let offered = match url.filter(|_| !offline) {
+        Some(url) => crate::update::prefetch_update(
+            url,
+            base_path,
+            auth_token,
+            None,
+            config.stream_os_to_partition(),
+            verbose,
+            config.get_spot_check_bytes(),
+            config.storage(),
+            &Checksummer::from_settings(config.checksum(), Some(url)),
+        )?,
+        None => offered,
+    };
+    let manifest = crate::upgrade::compose(&active, &offered, &decisions);
+    if manifest.id != offered.id {
+        let manifest_json =
+            serde_json::to_string_pretty(&manifest).map_err(|e| AvocadoError::StagingFailed {
+                reason: format!("Failed to serialize manifest: {e}"),
+            })?;
+        crate::staging::validate_manifest_images(
+            &manifest,
+            base_path,
+            &Checksummer::from_settings(config.checksum(), url),
+        )?;
+        crate::staging::stage_manifest(&manifest, &manifest_json, base_path, verbose)?;
+        if let Ok(cache) = crate::staging::generate_spot_hashes(
+            &manifest,
+            base_path,
+            config.get_spot_check_bytes(),
+        ) {
+            let _ = cache.save(&base_path.join("runtimes").join(&manifest.id));
+        }
+    }
+
+    crate::staging::activate_runtime(&manifest.id, base_path)?;
+    refresh_extensions(config)?;
+    if config.auto_gc() {
+        let _ = super::runtime::garbage_collect(config);
+    }
+    Ok(UpgradeResult {
+        decisions,
+        runtime_id: Some(manifest.id),
+        queued: false,
+        queued_until: None,
+    })
+}
@@ -1,6 +1,7 @@
 use crate::commands::ext;
 use crate::commands::hitl;
 use crate::config::Config;
+use crate::hitl_health::MountType;
 use crate::output::OutputManager;
 use crate::service::error::AvocadoError;
 use std::fs;
@@ -12,93 +13,91 @@ fn quiet_output() -> OutputManager {
     OutputManager::new(false, false)
 }
 
-/// Mount NFS extensions from a remote server.
+/// Mount NFS extensions from remote servers. Each entry of `extensions` is
+/// `NAME[@SERVER[:PORT]]`; `server_ip` and `server_port` apply to the
+/// entries without their own. Fallback ports, NFS versions, retries, how
+/// many extensions are mounted at once and the control port checked before
+/// mounting come from `[avocado.hitl]` in `config`. Extensions that fail to mount are reported, not returned as an
+/// error; `policy` decides whether the others are merged.
 pub fn mount(
-    server_ip: &str,
+    config: &Config,
+    server_ip: Option<&str>,
     server_port: Option<&str>,
     extensions: &[String],
-) -> Result<(), AvocadoError> {
-    let output = quiet_output();
-    let port = server_port.unwrap_or("12049");
-
-    let extensions_base_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-        let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
-            .or_else(|_| std::env::var("TMPDIR"))
-            .unwrap_or_else(|_| "/tmp".to_string());
-        format!("{temp_base}/avocado/hitl")
-    } else {
-        "/run/avocado/hitl".to_string()
-    };
-
-    for extension in extensions {
-        let extension_dir = format!("{extensions_base_dir}/{extension}");
-
-        // Create directory
-        if !Path::new(&extension_dir).exists() {
-            fs::create_dir_all(&extension_dir)?;
-        }
-
-        // Mount NFS share
-        let nfs_source = format!("{server_ip}:/{extension}");
-        let mount_options = format!("port={port},vers=4,hard,timeo=600,retrans=2,acregmin=0,acregmax=1,acdirmin=0,acdirmax=1,lookupcache=none");
-
-        let command_name = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
-            "mock-systemd-mount"
-        } else {
-            "systemd-mount"
-        };
-
-        let result = ProcessCommand::new(command_name)
-            .args([
-                "--no-block",
-                "--collect",
-                "-t",
-                "nfs4",
-                "-o",
-                &mount_options,
-                &nfs_source,
-                &extension_dir,
-            ])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(|e| AvocadoError::MountFailed {
-                extension: extension.clone(),
-                reason: format!("Failed to run {command_name}: {e}"),
-            })?;
-
-        if !result.status.success() {
-            let stderr = String::from_utf8_lossy(&result.stderr);
-            // Clean up directory on failure
-            let _ = fs::remove_dir(&extension_dir);
-            return Err(AvocadoError::MountFailed {
-                extension: extension.clone(),
-                reason: stderr.to_string(),
-            });
-        }
-
-        // Create service drop-ins for enabled services
-        let enabled_services =
-            ext::scan_extension_for_enable_services(Path::new(&extension_dir), extension);
-        if !enabled_services.is_empty() {
-            let _ =
-                hitl::create_service_dropins(extension, &extension_dir, &enabled_services, &output);
-        }
+    mount_type: MountType,
+    policy: hitl::FailurePolicy,
+) -> Result<Vec<hitl::MountReport>, AvocadoError> {
+    let span = crate::telemetry::span("hitl.mount");
+    let reports = span.record(mount_shares(
+        config,
+        server_ip,
+        server_port,
+        extensions,
+        mount_type,
+        policy,
+    ))?;
+    if reports
+        .iter()
+        .any(|r| r.status != hitl::MountStatus::Mounted)
+    {
+        span.set_error("Some extensions failed to mount");
     }
+    Ok(reports)
+}
 
-    // Reload systemd
-    let _ = hitl::systemd_daemon_reload(&output);
+fn mount_shares(
+    config: &Config,
+    server_ip: Option<&str>,
+    server_port: Option<&str>,
+    extensions: &[String],
+    mount_type: MountType,
+    policy: hitl::FailurePolicy,
+) -> Result<Vec<hitl::MountReport>, AvocadoError> {
+    let output = quiet_output();
+    let default_port = server_port.unwrap_or(hitl::DEFAULT_NFS_PORT);
+    let specs = extensions
+        .iter()
+        .map(|spec| {
+            hitl::parse_mount_spec(spec, server_ip, default_port).map_err(|reason| {
+                AvocadoError::MountFailed {
+                    extension: hitl::spec_extension_name(spec).to_string(),
+                    reason,
+                }
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let outcomes = hitl::mount_specs(&specs, mount_type, config, policy, &output);
+    let reports: Vec<hitl::MountReport> = outcomes.iter().map(|o| o.report.clone()).collect();
+    if !hitl::merge_after_mount(&reports, policy) {
+        crate::daemon_reload::flush_or_warn(&output);
+        return Ok(reports);
+    }
 
-    // Refresh extensions
-    let config = Config::default();
-    let _ = crate::service::ext::refresh_extensions(&config);
+    // Refresh extensions with this output, so that the merge reloads
+    // systemd for the new drop-ins too
+    let _ = crate::service::ext::refresh_with_output(&Config::default(), &output);
 
-    Ok(())
+    restart_override_services(
+        outcomes.into_iter().flat_map(|o| o.restart).collect(),
+        &output,
+    )?;
+    Ok(reports)
 }
 
 /// Unmount NFS extensions.
 pub fn unmount(extensions: &[String]) -> Result<(), AvocadoError> {
+    let span = crate::telemetry::span("hitl.unmount");
+    span.record(unmount_shares(extensions))
+}
+
+fn unmount_shares(extensions: &[String]) -> Result<(), AvocadoError> {
     let output = quiet_output();
+    let extensions: Vec<String> = extensions
+        .iter()
+        .map(|spec| hitl::spec_extension_name(spec).to_string())
+        .collect();
+    crate::extension_release::invalidate();
 
     let extensions_base_dir = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
         let temp_base = std::env::var("AVOCADO_TEST_TMPDIR")
@@ -106,12 +105,12 @@ pub fn unmount(extensions: &[String]) -> Result<(), AvocadoError> {
             .unwrap_or_else(|_| "/tmp".to_string());
         format!("{temp_base}/avocado/hitl")
     } else {
-        "/run/avocado/hitl".to_string()
+        crate::user_mode::system_path("/run/avocado/hitl")
     };
 
     // Step 1: Scan for enabled services before unmounting (while mounts are accessible)
     let mut extension_services: Vec<(String, Vec<String>)> = Vec::new();
-    for extension in extensions {
+    for extension in &extensions {
         let extension_dir = format!("{extensions_base_dir}/{extension}");
         let enabled_services =
             ext::scan_extension_for_enable_services(Path::new(&extension_dir), extension);
@@ -123,24 +122,27 @@ pub fn unmount(extensions: &[String]) -> Result<(), AvocadoError> {
     // Step 2: Unmerge extensions before unmounting NFS shares.
     // Extensions must be unmerged first so the sysext/confext overlay no longer
     // references the HITL mount points we are about to remove.
-    let _ = crate::service::ext::unmerge_extensions(false);
+    // Unmerged in this thread, so that systemd reloads once for the
+    // unmerge and the drop-in removals below
+    let _ = ext::unmerge_extensions_internal_with_options(true, false, &output);
 
     // Step 3: Clean up service drop-ins
     for (extension, services) in &extension_services {
         let _ = hitl::cleanup_service_dropins(extension, services, &output);
     }
 
-    // Step 4: Reload systemd to apply drop-in removals
-    if !extension_services.is_empty() {
-        let _ = hitl::systemd_daemon_reload(&output);
-    }
+    // Step 4: Reload systemd to apply the unmerge and drop-in removals
+    let _ = hitl::systemd_daemon_reload(&output);
 
     // Step 5: Unmount each extension
-    for extension in extensions {
+    let mut restarts: Vec<String> = Vec::new();
+    for extension in &extensions {
         let mount_point = format!("{extensions_base_dir}/{extension}");
 
         // Unmount
         if Path::new(&mount_point).exists() {
+            let span = crate::telemetry::span("hitl.nfs_unmount");
+            span.set_attribute("avocado.extension", extension.as_str());
             let command_name = if std::env::var("AVOCADO_TEST_MODE").is_ok() {
                 "mock-umount"
             } else {
@@ -159,6 +161,7 @@ pub fn unmount(extensions: &[String]) -> Result<(), AvocadoError> {
 
             if !result.status.success() {
                 let stderr = String::from_utf8_lossy(&result.stderr);
+                span.set_error(stderr.to_string());
                 return Err(AvocadoError::UnmountFailed {
                     extension: extension.clone(),
                     reason: stderr.to_string(),
@@ -168,11 +171,48 @@ pub fn unmount(extensions: &[String]) -> Result<(), AvocadoError> {
             // Clean up directory
             let _ = fs::remove_dir(&mount_point);
         }
+        restarts.extend(crate::hitl_health::recorded_overrides(extension).restart_units());
+        crate::hitl_health::forget_mount(extension);
     }
 
     // Step 6: Merge remaining extensions (without the removed HITL ones)
     let config = Config::default();
-    let _ = crate::service::ext::merge_extensions(&config);
+    let _ = crate::service::ext::merge_extensions(&config, None);
 
+    restart_override_services(restarts, &output)
+}
+
+/// Restart the services listed in the extensions' `hitl.toml`.
+fn restart_override_services(
+    mut units: Vec<String>,
+    output: &OutputManager,
+) -> Result<(), AvocadoError> {
+    // The services restart with the current drop-ins, in case nothing
+    // reloaded systemd since they changed
+    crate::daemon_reload::flush_or_warn(output);
+    units.sort();
+    units.dedup();
+    crate::hitl_overrides::restart_services(&units, output).map_err(AvocadoError::from)
+}
+
+/// Hold refreshes for each of `extensions` until [`resume`].
+pub fn quiesce(extensions: &[String]) -> Result<(), AvocadoError> {
+    for extension in extensions {
+        crate::hitl_sync::quiesce(extension).map_err(|e| AvocadoError::QuiesceFailed {
+            extension: extension.clone(),
+            reason: e.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Release extensions held by [`quiesce`].
+pub fn resume(extensions: &[String]) -> Result<(), AvocadoError> {
+    for extension in extensions {
+        crate::hitl_sync::resume(extension).map_err(|e| AvocadoError::QuiesceFailed {
+            extension: extension.clone(),
+            reason: e.to_string(),
+        })?;
+    }
     Ok(())
 }
@@ -1,12 +1,19 @@
+use crate::clock;
 use crate::config::Config;
 use crate::service::error::AvocadoError;
-use crate::service::types::{RootAuthorityInfo, TrustedKey};
+#[cfg(feature = "network")]
+use crate::service::types::TrustedKey;
+use crate::service::types::{ImageTrust, RootAuthorityInfo, RotationResult};
+use crate::trust::{self, SigningKey, TrustStore, Verification};
 use std::path::Path;
 
+#[cfg(feature = "network")]
 const METADATA_DIR_NAME: &str = "metadata";
+#[cfg(feature = "network")]
 const ROOT_JSON_FILENAME: &str = "root.json";
 
 /// Show the trusted signing keys for this device.
+#[cfg(feature = "network")]
 pub fn show(config: &Config) -> Result<Option<RootAuthorityInfo>, AvocadoError> {
     let base_dir = config.get_avocado_base_dir();
     let root_path = Path::new(&base_dir)
@@ -63,6 +70,13 @@ pub fn show(config: &Config) -> Result<Option<RootAuthorityInfo>, AvocadoError>
     }))
 }
 
+/// The root of trust is TUF metadata, which only the `network` feature reads.
+#[cfg(not(feature = "network"))]
+pub fn show(_config: &Config) -> Result<Option<RootAuthorityInfo>, AvocadoError> {
+    Err(crate::update::UpdateError::NetworkDisabled.into())
+}
+
+#[cfg(feature = "network")]
 fn role_type_display(role_type: &tough::schema::RoleType) -> &'static str {
     match role_type {
         tough::schema::RoleType::Root => "authority",
@@ -73,6 +87,7 @@ fn role_type_display(role_type: &tough::schema::RoleType) -> &'static str {
     }
 }
 
+#[cfg(feature = "network")]
 fn hex_encode(bytes: &[u8]) -> String {
     use std::fmt::Write;
     bytes
@@ -82,3 +97,80 @@ fn hex_encode(bytes: &[u8]) -> String {
             acc
         })
 }
+
+/// Keys trusted to sign extension images.
+pub fn trusted_keys(config: &Config) -> Result<Vec<SigningKey>, AvocadoError> {
+    let base_dir = config.get_avocado_base_dir();
+    Ok(TrustStore::load(Path::new(&base_dir))?.keys)
+}
+
+/// Install `public_key` (hex ed25519) as an extension signing key and
+/// retire the key matching `retire` after `overlap_days`, then re-verify
+/// the installed extension images now and once the old key has expired.
+/// With `dry_run` the trust store is left unchanged.
+pub fn rotate(
+    config: &Config,
+    public_key: &str,
+    retire: Option<&str>,
+    overlap_days: u64,
+    valid_days: Option<u64>,
+    dry_run: bool,
+) -> Result<RotationResult, AvocadoError> {
+    let base_dir = config.get_avocado_base_dir();
+    let base_path = Path::new(&base_dir);
+    let public = trust::parse_public_key(public_key)?;
+
+    let mut store = TrustStore::load(base_path)?;
+    let before = store.clone();
+    let now = clock::now();
+    let (added, retired) = store.rotate(&public, valid_days, retire, overlap_days, now)?;
+    let checked_at = retired
+        .as_ref()
+        .and_then(|k| k.not_after)
+        .map_or(now, |t| t.max(now));
+
+    let report = crate::service::ext::audit_report(config)?;
+    let images = report
+        .images
+        .into_iter()
+        .filter(|image| image.sha256.is_some())
+        .map(|image| {
+            let path = Path::new(&image.path);
+            let current = trust::verify_image(path, &before, now);
+            let after = trust::verify_image(path, &store, checked_at);
+            ImageTrust {
+                name: image.name,
+                version: image.version,
+                signed_by: match &after {
+                    Verification::Valid(keyid) => Some(keyid.clone()),
+                    _ => None,
+                },
+                reason: match &after {
+                    Verification::Invalid(reason) => Some(reason.clone()),
+                    _ => None,
+                },
+                now: verification_state(&current).to_string(),
+                after: verification_state(&after).to_string(),
+                path: image.path,
+            }
+        })
+        .collect();
+
+    if !dry_run {
+        store.save(base_path)?;
+    }
+    Ok(RotationResult {
+        added,
+        retired,
+        checked_at,
+        images,
+    })
+}
+
+fn verification_state(verification: &Verification) -> &'static str {
+    match verification {
+        Verification::Valid(_) => "valid",
+        Verification::Unsigned => "unsigned",
+        Verification::Invalid(_) => "invalid",
+    }
+}
@@ -1,5 +1,6 @@
 use crate::config::Config;
 use crate::gc;
+use crate::hash::Checksummer;
 use crate::manifest::{RuntimeManifest, IMAGES_DIR_NAME};
 use crate::service::error::AvocadoError;
 use crate::service::types::{RuntimeEntry, RuntimeExtensionInfo};
@@ -89,6 +90,8 @@ pub fn add_from_url_streaming(
         config.stream_os_to_partition(),
         false,
         config.get_spot_check_bytes(),
+        config.storage(),
+        &Checksummer::from_settings(config.checksum(), Some(url)),
     )?;
 
     if reboot_required {
@@ -121,7 +124,11 @@ pub fn add_from_manifest_streaming(
             reason: format!("Invalid manifest.json: {e}"),
         })?;
 
-    staging::validate_manifest_images(&manifest, base_path)?;
+    staging::validate_manifest_images(
+        &manifest,
+        base_path,
+        &Checksummer::from_settings(config.checksum(), None),
+    )?;
     staging::stage_manifest(&manifest, &manifest_content, base_path, false)?;
 
     // Best-effort spot hash cache generation
@@ -168,6 +175,7 @@ pub fn activate_runtime_streaming(
         base_path,
         &runtime_dir,
         config.get_spot_check_bytes(),
+        &Checksummer::from_settings(config.checksum(), None),
         false,
     )?;
 
@@ -195,6 +203,8 @@ pub fn add_from_url(
         config.stream_os_to_partition(),
         false,
         config.get_spot_check_bytes(),
+        config.storage(),
+        &Checksummer::from_settings(config.checksum(), Some(url)),
     )?;
 
     if reboot_required {
@@ -230,7 +240,11 @@ pub fn add_from_manifest(
             reason: format!("Invalid manifest.json: {e}"),
         })?;
 
-    staging::validate_manifest_images(&manifest, base_path)?;
+    staging::validate_manifest_images(
+        &manifest,
+        base_path,
+        &Checksummer::from_settings(config.checksum(), None),
+    )?;
     staging::stage_manifest(&manifest, &manifest_content, base_path, false)?;
 
     // Best-effort spot hash cache generation
@@ -289,6 +303,7 @@ pub fn activate_runtime(id_prefix: &str, config: &Config) -> Result<Vec<String>,
         base_path,
         &runtime_dir,
         config.get_spot_check_bytes(),
+        &Checksummer::from_settings(config.checksum(), None),
         false,
     )?;
 
@@ -11,6 +11,59 @@ pub struct ExtensionInfo {
     pub is_directory: bool,
 }
 
+/// Entry of `ext list --detailed`: [`ExtensionInfo`] with the scopes
+/// (empty without a scope key, `None` when no release file could be read),
+/// origin and whether it is enabled for the current os-release. The JSON
+/// field names are those of the varlink `Extension`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionDetail {
+    pub name: String,
+    pub version: Option<String>,
+    pub path: String,
+    pub is_sysext: bool,
+    pub is_confext: bool,
+    pub is_directory: bool,
+    pub scopes: Option<Vec<String>>,
+    pub origin: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Status of an extension for `ext status` and `ext env`. The JSON field
+/// names are those of the varlink `ExtensionStatus`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionStatus {
+    pub name: String,
+    pub version: Option<String>,
+    pub is_sysext: bool,
+    pub is_confext: bool,
+    pub is_merged: bool,
+    pub origin: Option<String>,
+    pub image_id: Option<String>,
+    pub image_type: Option<String>,
+    pub reboot_required: Option<bool>,
+    pub build_id: Option<String>,
+    pub git_sha: Option<String>,
+    pub build_date: Option<String>,
+    pub eol: Option<String>,
+    pub notes: Option<String>,
+    pub path: Option<String>,
+    pub partitions: Option<Vec<crate::ddi::PartitionStatus>>,
+    /// Set when the repository index offers the extension
+    pub latest_version: Option<String>,
+    pub update_available: Option<bool>,
+    pub loop_device: Option<crate::loop_device::LoopDevice>,
+    /// Whether the last merge left the extension out in safe mode
+    pub safe_mode_skipped: Option<bool>,
+    pub scopes: Option<Vec<String>>,
+    /// Whether the extension is in scope in the current environment
+    pub applicable: Option<bool>,
+    /// When systemd merged the extension, in seconds since the Unix epoch
+    pub merged_since: Option<i64>,
+    pub aliases: Option<Vec<String>>,
+}
+
 /// Result of an enable operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnableResult {
@@ -25,6 +78,15 @@ pub struct DisableResult {
     pub failed: usize,
 }
 
+/// Result of applying a transaction manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplyResult {
+    pub linked: usize,
+    pub unlinked: usize,
+    /// Whether the extensions were refreshed (false when nothing changed).
+    pub refreshed: bool,
+}
+
 /// Result of `set_extensions_enabled` — the override-based enable/disable
 /// path that writes to the active runtime's `overrides.json`. `updated`
 /// counts names successfully written (whether or not they matched a
@@ -36,6 +98,30 @@ pub struct SetEnabledResult {
     pub missing: usize,
 }
 
+/// Result of `ext upgrade`: what happened to each extension considered,
+/// and the runtime activated for the upgrades (none on a dry run or when
+/// nothing was upgraded). Outside the maintenance windows the upgrade is
+/// queued instead and nothing is considered yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpgradeResult {
+    pub decisions: Vec<crate::upgrade::UpgradeDecision>,
+    pub runtime_id: Option<String>,
+    pub queued: bool,
+    /// Start of the window the queued upgrade waits for, in seconds since
+    /// the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queued_until: Option<u64>,
+}
+
+/// Result of `ext prune-os-releases`: the running VERSION_ID and the
+/// os-releases directories kept and removed (to be removed on a dry run).
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneOsReleasesResult {
+    pub current: String,
+    pub kept: Vec<String>,
+    pub removed: Vec<String>,
+}
+
 /// Runtime summary for status display
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuntimeSummary {
@@ -81,6 +167,41 @@ pub struct RootAuthorityInfo {
     pub keys: Vec<TrustedKey>,
 }
 
+/// Result of `trust rotate`: the installed and the retired key, and how
+/// each installed extension image verifies before and after the rotation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationResult {
+    pub added: crate::trust::SigningKey,
+    pub retired: Option<crate::trust::SigningKey>,
+    /// When the rotation is complete: the retired key's end of validity,
+    /// or now. `after` of each image is checked as of this time.
+    pub checked_at: u64,
+    pub images: Vec<ImageTrust>,
+}
+
+/// Signature state of an installed image: `valid`, `unsigned` or `invalid`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageTrust {
+    pub name: String,
+    pub version: Option<String>,
+    pub path: String,
+    /// State before the rotation
+    pub now: String,
+    /// State once the rotation is complete
+    pub after: String,
+    /// Key accepted once the rotation is complete
+    pub signed_by: Option<String>,
+    /// Why the image is invalid once the rotation is complete
+    pub reason: Option<String>,
+}
+
+impl ImageTrust {
+    /// Whether the image validates now but not once the rotation completes.
+    pub fn lost(&self) -> bool {
+        self.now == "valid" && self.after != "valid"
+    }
+}
+
 /// A trusted signing key
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrustedKey {
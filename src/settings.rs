@@ -0,0 +1,220 @@
+//! Process settings resolved from the configuration.
+//!
+//! The image policy, tool overrides, command time limits, phase units,
+//! telemetry, messages, hook log rotation and the process modes (user,
+//! container, read-only) are needed deep inside scanning, mounting and
+//! merging. `main` resolves them once from the loaded [`Config`], the
+//! environment variables that override it and the command line, and
+//! installs them with [`install`]; those code paths read them through
+//! [`current`] instead of having the configuration threaded through every
+//! call.
+//!
+//! The environment is only read, never written: commands avocadoctl runs
+//! do not inherit the settings, and installing new ones (see [`reload`])
+//! takes effect for the next operation. Until settings are installed, as
+//! in unit tests, [`current`] resolves the defaults and the environment.
+
+use crate::config::{
+    Config, LogSettings, MessageSettings, TelemetrySettings, TimeoutSettings, ToolSettings,
+    VerityMode,
+};
+use crate::timeouts::TimeoutKind;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+static INSTALLED: RwLock<Option<Arc<Settings>>> = RwLock::new(None);
+
+/// Settings of this process.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// Explicit systemd image policy for extension images
+    pub image_policy: Option<String>,
+    /// dm-verity requirement for extension images
+    pub verity: Option<VerityMode>,
+    /// `--noexec=` value for merges ("yes" or "no")
+    pub noexec: Option<String>,
+    /// Paths or names of the external tools
+    pub tools: ToolSettings,
+    /// Time limits of external commands
+    pub timeouts: TimeoutSettings,
+    /// Run each merge phase's commands in transient units
+    pub phase_units: bool,
+    /// OpenTelemetry trace export
+    pub telemetry: TelemetrySettings,
+    /// Locale and translation directory of messages
+    pub messages: MessageSettings,
+    /// Rotation of the hook logs
+    pub logs: LogSettings,
+    /// Whether systemd runs as PID 1, when detection is overridden
+    pub systemd_running: Option<bool>,
+    /// `--user` mode
+    pub user: bool,
+    /// Root prefix of `--user` mode, when not the XDG default
+    pub user_root: Option<PathBuf>,
+    /// Container mode
+    pub container: bool,
+    /// Read-only mode for unprivileged callers
+    pub read_only: bool,
+}
+
+fn env(var: &str) -> Option<String> {
+    std::env::var(var).ok()
+}
+
+fn env_u64(var: &str) -> Option<u64> {
+    env(var).and_then(|v| v.trim().parse().ok())
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.is_empty())
+}
+
+/// `configured`, unless `var` overrides it.
+fn text(var: &str, configured: &Option<String>) -> Option<String> {
+    non_empty(env(var)).or_else(|| non_empty(configured.clone()))
+}
+
+fn tool(name: &str, configured: &Option<String>) -> Option<String> {
+    match crate::tools::override_env(name) {
+        Some(var) => text(var, configured),
+        None => non_empty(configured.clone()),
+    }
+}
+
+fn verity(value: &str) -> Option<VerityMode> {
+    match value {
+        "enforce" => Some(VerityMode::Enforce),
+        "warn" => Some(VerityMode::Warn),
+        "off" => Some(VerityMode::Off),
+        _ => None,
+    }
+}
+
+impl Settings {
+    /// Settings from `config`, with the environment overriding it. The
+    /// modes selected on the command line are left to the caller.
+    pub fn resolve(config: &Config) -> Self {
+        use crate::{container, hook_log, image_policy, messages, phases, telemetry, unprivileged};
+
+        let tools = config.tools();
+        let timeout = |kind: TimeoutKind| {
+            env_u64(kind.env()).unwrap_or_else(|| kind.configured(config.timeouts()))
+        };
+        let logs = config.logs();
+        let configured_telemetry = config.telemetry();
+        Self {
+            image_policy: non_empty(env(image_policy::IMAGE_POLICY_ENV))
+                .or_else(|| config.image_policy().map(str::to_string)),
+            verity: match env(image_policy::VERITY_ENV) {
+                Some(value) => verity(&value),
+                None => config.verity(),
+            },
+            noexec: env(image_policy::NOEXEC_ENV).or_else(|| {
+                config
+                    .noexec()
+                    .map(|v| if v { "yes" } else { "no" }.to_string())
+            }),
+            tools: ToolSettings {
+                systemd_sysext: tool("systemd-sysext", &tools.systemd_sysext),
+                systemd_confext: tool("systemd-confext", &tools.systemd_confext),
+                systemd_dissect: tool("systemd-dissect", &tools.systemd_dissect),
+                depmod: tool("depmod", &tools.depmod),
+                modprobe: tool("modprobe", &tools.modprobe),
+            },
+            timeouts: TimeoutSettings {
+                systemd_cmd: timeout(TimeoutKind::SystemdCmd),
+                hook_cmd: timeout(TimeoutKind::HookCmd),
+                nfs_mount: timeout(TimeoutKind::NfsMount),
+                loop_mount: timeout(TimeoutKind::LoopMount),
+            },
+            phase_units: match env(phases::PHASE_UNITS_ENV) {
+                Some(value) => value == "1" || value == "true",
+                None => config.profiling().phase_units,
+            },
+            telemetry: TelemetrySettings {
+                otlp_endpoint: text(telemetry::ENDPOINT_ENV, &configured_telemetry.otlp_endpoint),
+                service_name: non_empty(env(telemetry::SERVICE_NAME_ENV))
+                    .unwrap_or_else(|| configured_telemetry.service_name.clone()),
+                timeout_ms: env_u64(telemetry::TIMEOUT_ENV)
+                    .unwrap_or(configured_telemetry.timeout_ms),
+            },
+            messages: MessageSettings {
+                locale: text(messages::LOCALE_ENV, &config.messages().locale),
+                dir: text(messages::DIR_ENV, &config.messages().dir),
+            },
+            logs: LogSettings {
+                max_size_kb: env_u64(hook_log::MAX_SIZE_ENV).unwrap_or(logs.max_size_kb),
+                keep: env_u64(hook_log::KEEP_ENV).map_or(logs.keep, |keep| keep as usize),
+                max_age_days: env_u64(hook_log::MAX_AGE_ENV).unwrap_or(logs.max_age_days),
+            },
+            systemd_running: env(crate::systemd_runtime::SYSTEMD_RUNNING_ENV)
+                .map(|value| value.trim() != "0"),
+            user: false,
+            user_root: non_empty(env(crate::user_mode::USER_ROOT_ENV))
+                .or_else(|| config.avocado.user.root.clone())
+                .map(PathBuf::from),
+            container: env(container::CONTAINER_MODE_ENV).is_some()
+                || (config.container().auto && container::detect().is_some()),
+            read_only: env(unprivileged::READ_ONLY_ENV).is_some(),
+        }
+    }
+}
+
+/// Make `settings` the settings of this process.
+pub fn install(settings: Settings) {
+    *INSTALLED.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(settings));
+}
+
+/// Re-resolve the settings from a reloaded `config`, keeping the modes
+/// selected on the command line.
+pub fn reload(config: &Config) {
+    let previous = current();
+    let mut settings = Settings::resolve(config);
+    settings.user = previous.user;
+    settings.container |= previous.container;
+    settings.read_only |= previous.read_only;
+    install(settings);
+}
+
+/// The installed settings, or the defaults and the environment when none
+/// are installed.
+pub fn current() -> Arc<Settings> {
+    if let Some(settings) = INSTALLED.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return Arc::clone(settings);
+    }
+    Arc::new(Settings::resolve(&Config::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_env::ENV_VAR_MUTEX;
+
+    #[test]
+    fn test_environment_overrides_configuration() {
+        let _lock = ENV_VAR_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let mut config = Config::default();
+        config.avocado.tools.depmod = Some("/usr/sbin/depmod".to_string());
+        config.avocado.timeouts.hook_cmd = 5;
+        config.avocado.logs.keep = 7;
+
+        let settings = Settings::resolve(&config);
+        assert_eq!(settings.tools.depmod.as_deref(), Some("/usr/sbin/depmod"));
+        assert_eq!(settings.timeouts.hook_cmd, 5);
+        assert_eq!(settings.logs.keep, 7);
+
+        std::env::set_var("AVOCADO_TOOL_DEPMOD", "/bin/busybox-depmod");
+        std::env::set_var("AVOCADO_TIMEOUT_HOOK_CMD", "0");
+        std::env::set_var("AVOCADO_LOG_KEEP", "not a number");
+        let settings = Settings::resolve(&config);
+        std::env::remove_var("AVOCADO_TOOL_DEPMOD");
+        std::env::remove_var("AVOCADO_TIMEOUT_HOOK_CMD");
+        std::env::remove_var("AVOCADO_LOG_KEEP");
+        assert_eq!(
+            settings.tools.depmod.as_deref(),
+            Some("/bin/busybox-depmod")
+        );
+        assert_eq!(settings.timeouts.hook_cmd, 0);
+        assert_eq!(settings.logs.keep, 7);
+    }
+}
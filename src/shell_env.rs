@@ -0,0 +1,139 @@
+//! Shell exports describing the merged extensions, for `ext env`.
+//!
+//! Shell scripts and legacy init fragments consume extension state with
+//! `eval "$(avocadoctl ext env)"` instead of parsing `ext status`:
+//!
+//! ```sh
+//! export AVOCADO_MERGED_EXTS='app-1.2.0 base-2.0'
+//! export AVOCADO_EXT_APP_VERSION='1.2.0'
+//! export AVOCADO_EXT_APP_PATH='/run/avocado/extensions/app-1.2.0'
+//! ```
+//!
+//! Per-extension variables use the extension name without its version,
+//! upper-cased, with every character other than a letter or digit
+//! replaced by `_`.
+
+use crate::service::types::ExtensionStatus;
+
+fn versioned_name(ext: &ExtensionStatus) -> String {
+    match &ext.version {
+        Some(version) => format!("{}-{version}", ext.name),
+        None => ext.name.clone(),
+    }
+}
+
+/// Variable name fragment for an extension name.
+pub fn var_key(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Exports for the merged extensions, or only for `name` (with or without
+/// its version), which must be merged.
+pub fn exports(
+    extensions: &[ExtensionStatus],
+    name: Option<&str>,
+) -> Result<Vec<(String, String)>, String> {
+    let mut merged: Vec<&ExtensionStatus> = extensions
+        .iter()
+        .filter(|e| e.is_merged)
+        .filter(|e| name.is_none_or(|n| e.name == n || versioned_name(e) == n))
+        .collect();
+    merged.sort_by_key(|e| versioned_name(e));
+    if let (Some(name), true) = (name, merged.is_empty()) {
+        return Err(format!("Extension '{name}' is not merged"));
+    }
+
+    let names: Vec<String> = merged.iter().map(|e| versioned_name(e)).collect();
+    let mut vars = vec![("AVOCADO_MERGED_EXTS".to_string(), names.join(" "))];
+    for ext in merged {
+        let key = var_key(&ext.name);
+        if let Some(version) = &ext.version {
+            vars.push((format!("AVOCADO_EXT_{key}_VERSION"), version.clone()));
+        }
+        if let Some(path) = &ext.path {
+            vars.push((format!("AVOCADO_EXT_{key}_PATH"), path.clone()));
+        }
+    }
+    Ok(vars)
+}
+
+/// `export NAME='value'` lines, single-quoted for any POSIX shell.
+pub fn render(vars: &[(String, String)]) -> String {
+    vars.iter()
+        .map(|(name, value)| format!("export {name}='{}'\n", value.replace('\'', "'\\''")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(name: &str, version: Option<&str>, merged: bool) -> ExtensionStatus {
+        ExtensionStatus {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+            is_sysext: true,
+            is_confext: false,
+            is_merged: merged,
+            origin: None,
+            image_id: None,
+            image_type: None,
+            reboot_required: None,
+            build_id: None,
+            git_sha: None,
+            build_date: None,
+            eol: None,
+            notes: None,
+            path: Some(format!("/run/avocado/extensions/{name}")),
+            partitions: None,
+            latest_version: None,
+            update_available: None,
+            loop_device: None,
+            safe_mode_skipped: None,
+            scopes: None,
+            applicable: None,
+            merged_since: None,
+            aliases: None,
+        }
+    }
+
+    #[test]
+    fn test_exports_merged_extensions() {
+        let extensions = [
+            status("kernel-modules", Some("6.1"), true),
+            status("app", Some("1.2.0"), true),
+            status("debug", None, false),
+        ];
+        let vars = exports(&extensions, None).unwrap();
+        assert_eq!(
+            vars[0],
+            (
+                "AVOCADO_MERGED_EXTS".to_string(),
+                "app-1.2.0 kernel-modules-6.1".to_string()
+            )
+        );
+        assert!(vars.contains(&(
+            "AVOCADO_EXT_KERNEL_MODULES_VERSION".to_string(),
+            "6.1".to_string()
+        )));
+        assert!(!vars.iter().any(|(k, _)| k.contains("DEBUG")));
+
+        let app = exports(&extensions, Some("app-1.2.0")).unwrap();
+        assert_eq!(app.len(), 3);
+        assert!(exports(&extensions, Some("debug")).is_err());
+    }
+
+    #[test]
+    fn test_render_quotes_values() {
+        let vars = [("AVOCADO_EXT_APP_PATH".to_string(), "/it's".to_string())];
+        assert_eq!(render(&vars), "export AVOCADO_EXT_APP_PATH='/it'\\''s'\n");
+    }
+}
@@ -0,0 +1,398 @@
+//! Point-in-time captures of a device's extension / merge state.
+//!
+//! `avocadoctl ext snapshot` serializes a [`StateSnapshot`] to JSON so it
+//! can be copied off a device; `avocadoctl ext compare` loads two of them
+//! (or one of them and the live state) and reports what differs. The
+//! comparison is keyed on the bare extension name so a version bump shows
+//! up as a version change rather than as one removal plus one addition.
+
+use crate::extension_release::{Lifecycle, Provenance};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Current snapshot schema version. Bumped only on non-additive changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    #[serde(default = "StateSnapshot::default_version")]
+    pub version: u32,
+    /// Capture time in seconds since the Unix epoch.
+    #[serde(default)]
+    pub captured_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// VERSION_ID of the running OS at capture time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_version_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<SnapshotRuntime>,
+    #[serde(default)]
+    pub extensions: Vec<SnapshotExtension>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotRuntime {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotExtension {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Effective enablement (manifest default + overrides). `None` for
+    /// extensions that are not part of the active runtime manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    pub merged: bool,
+    pub is_sysext: bool,
+    pub is_confext: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_id: Option<String>,
+    /// Build provenance from the extension's release file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// End of life and notes from the extension's release file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<Lifecycle>,
+}
+
+impl StateSnapshot {
+    fn default_version() -> u32 {
+        SNAPSHOT_VERSION
+    }
+
+    /// Load a snapshot from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read snapshot '{}': {e}", path.display()))?;
+        Self::from_json(&content)
+            .map_err(|e| format!("Failed to parse snapshot '{}': {e}", path.display()))
+    }
+
+    /// Parse a snapshot from its JSON representation.
+    pub fn from_json(content: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(content)
+    }
+
+    /// Serialize the snapshot as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Write the snapshot to `path` as pretty-printed JSON.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, self.to_json() + "\n")
+    }
+
+    /// Human-readable one-line description of the runtime, if any.
+    fn runtime_label(&self) -> Option<String> {
+        self.runtime.as_ref().map(|r| {
+            let short_id = &r.id[..r.id.len().min(8)];
+            format!("{} {} ({short_id})", r.name, r.version)
+        })
+    }
+}
+
+/// A single difference between two snapshots ("left" and "right").
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SnapshotDifference {
+    Runtime {
+        left: Option<String>,
+        right: Option<String>,
+    },
+    OsVersion {
+        left: Option<String>,
+        right: Option<String>,
+    },
+    OnlyInLeft {
+        name: String,
+        version: Option<String>,
+    },
+    OnlyInRight {
+        name: String,
+        version: Option<String>,
+    },
+    Version {
+        name: String,
+        left: Option<String>,
+        right: Option<String>,
+    },
+    Enabled {
+        name: String,
+        left: Option<bool>,
+        right: Option<bool>,
+    },
+    Merged {
+        name: String,
+        left: bool,
+        right: bool,
+    },
+    ImageId {
+        name: String,
+        left: Option<String>,
+        right: Option<String>,
+    },
+}
+
+fn opt_str(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("-")
+}
+
+fn opt_enabled(value: &Option<bool>) -> &'static str {
+    match value {
+        Some(true) => "enabled",
+        Some(false) => "disabled",
+        None => "-",
+    }
+}
+
+fn merged_str(value: bool) -> &'static str {
+    if value {
+        "merged"
+    } else {
+        "not merged"
+    }
+}
+
+impl fmt::Display for SnapshotDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotDifference::Runtime { left, right } => {
+                write!(f, "runtime: {} -> {}", opt_str(left), opt_str(right))
+            }
+            SnapshotDifference::OsVersion { left, right } => {
+                write!(f, "os VERSION_ID: {} -> {}", opt_str(left), opt_str(right))
+            }
+            SnapshotDifference::OnlyInLeft { name, version } => match version {
+                Some(v) => write!(f, "- {name} {v} (only in left)"),
+                None => write!(f, "- {name} (only in left)"),
+            },
+            SnapshotDifference::OnlyInRight { name, version } => match version {
+                Some(v) => write!(f, "+ {name} {v} (only in right)"),
+                None => write!(f, "+ {name} (only in right)"),
+            },
+            SnapshotDifference::Version { name, left, right } => {
+                write!(
+                    f,
+                    "~ {name}: version {} -> {}",
+                    opt_str(left),
+                    opt_str(right)
+                )
+            }
+            SnapshotDifference::Enabled { name, left, right } => write!(
+                f,
+                "~ {name}: {} -> {}",
+                opt_enabled(left),
+                opt_enabled(right)
+            ),
+            SnapshotDifference::Merged { name, left, right } => write!(
+                f,
+                "~ {name}: {} -> {}",
+                merged_str(*left),
+                merged_str(*right)
+            ),
+            SnapshotDifference::ImageId { name, left, right } => {
+                write!(f, "~ {name}: image {} -> {}", opt_str(left), opt_str(right))
+            }
+        }
+    }
+}
+
+/// Compare two snapshots and return every difference, runtime-level
+/// differences first, then per-extension differences sorted by name.
+pub fn compare(left: &StateSnapshot, right: &StateSnapshot) -> Vec<SnapshotDifference> {
+    let mut diffs = Vec::new();
+
+    let (left_rt, right_rt) = (left.runtime_label(), right.runtime_label());
+    if left_rt != right_rt {
+        diffs.push(SnapshotDifference::Runtime {
+            left: left_rt,
+            right: right_rt,
+        });
+    }
+    if left.os_version_id != right.os_version_id {
+        diffs.push(SnapshotDifference::OsVersion {
+            left: left.os_version_id.clone(),
+            right: right.os_version_id.clone(),
+        });
+    }
+
+    let left_map: BTreeMap<&str, &SnapshotExtension> = left
+        .extensions
+        .iter()
+        .map(|e| (e.name.as_str(), e))
+        .collect();
+    let right_map: BTreeMap<&str, &SnapshotExtension> = right
+        .extensions
+        .iter()
+        .map(|e| (e.name.as_str(), e))
+        .collect();
+
+    let mut names: Vec<&str> = left_map.keys().chain(right_map.keys()).copied().collect();
+    names.sort_unstable();
+    names.dedup();
+
+    for name in names {
+        match (left_map.get(name), right_map.get(name)) {
+            (Some(l), None) => diffs.push(SnapshotDifference::OnlyInLeft {
+                name: name.to_string(),
+                version: l.version.clone(),
+            }),
+            (None, Some(r)) => diffs.push(SnapshotDifference::OnlyInRight {
+                name: name.to_string(),
+                version: r.version.clone(),
+            }),
+            (Some(l), Some(r)) => {
+                if l.version != r.version {
+                    diffs.push(SnapshotDifference::Version {
+                        name: name.to_string(),
+                        left: l.version.clone(),
+                        right: r.version.clone(),
+                    });
+                } else if l.image_id != r.image_id {
+                    // Same version but a different image: a rebuild.
+                    diffs.push(SnapshotDifference::ImageId {
+                        name: name.to_string(),
+                        left: l.image_id.clone(),
+                        right: r.image_id.clone(),
+                    });
+                }
+                if l.enabled != r.enabled {
+                    diffs.push(SnapshotDifference::Enabled {
+                        name: name.to_string(),
+                        left: l.enabled,
+                        right: r.enabled,
+                    });
+                }
+                if l.merged != r.merged {
+                    diffs.push(SnapshotDifference::Merged {
+                        name: name.to_string(),
+                        left: l.merged,
+                        right: r.merged,
+                    });
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn ext(name: &str, version: &str, enabled: bool, merged: bool) -> SnapshotExtension {
+        SnapshotExtension {
+            name: name.to_string(),
+            version: Some(version.to_string()),
+            enabled: Some(enabled),
+            merged,
+            is_sysext: true,
+            is_confext: false,
+            origin: Some("runtime".to_string()),
+            image_id: None,
+            provenance: None,
+            lifecycle: None,
+        }
+    }
+
+    fn snapshot(extensions: Vec<SnapshotExtension>) -> StateSnapshot {
+        StateSnapshot {
+            version: SNAPSHOT_VERSION,
+            captured_at: 0,
+            hostname: None,
+            os_version_id: Some("1.0".to_string()),
+            runtime: Some(SnapshotRuntime {
+                id: "0123456789abcdef".to_string(),
+                name: "dev".to_string(),
+                version: "1.0.0".to_string(),
+            }),
+            extensions,
+        }
+    }
+
+    #[test]
+    fn test_identical_snapshots_have_no_differences() {
+        let a = snapshot(vec![ext("app", "1.0", true, true)]);
+        assert!(compare(&a, &a.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_version_and_enablement_differences() {
+        let a = snapshot(vec![ext("app", "1.0", true, true)]);
+        let b = snapshot(vec![ext("app", "1.1", false, false)]);
+        let diffs = compare(&a, &b);
+        assert_eq!(diffs.len(), 3);
+        assert!(matches!(diffs[0], SnapshotDifference::Version { .. }));
+        assert!(matches!(
+            diffs[1],
+            SnapshotDifference::Enabled {
+                left: Some(true),
+                right: Some(false),
+                ..
+            }
+        ));
+        assert!(matches!(diffs[2], SnapshotDifference::Merged { .. }));
+    }
+
+    #[test]
+    fn test_added_removed_and_runtime_differences() {
+        let a = snapshot(vec![ext("a", "1", true, true)]);
+        let mut b = snapshot(vec![ext("b", "1", true, true)]);
+        b.os_version_id = Some("2.0".to_string());
+        b.runtime = None;
+        let diffs = compare(&a, &b);
+        assert_eq!(
+            diffs[0],
+            SnapshotDifference::Runtime {
+                left: Some("dev 1.0.0 (01234567)".to_string()),
+                right: None,
+            }
+        );
+        assert!(matches!(diffs[1], SnapshotDifference::OsVersion { .. }));
+        assert!(matches!(diffs[2], SnapshotDifference::OnlyInLeft { .. }));
+        assert!(matches!(diffs[3], SnapshotDifference::OnlyInRight { .. }));
+    }
+
+    #[test]
+    fn test_image_id_difference_only_when_version_matches() {
+        let mut l = ext("app", "1.0", true, true);
+        let mut r = l.clone();
+        l.image_id = Some("aaa".to_string());
+        r.image_id = Some("bbb".to_string());
+        let diffs = compare(&snapshot(vec![l]), &snapshot(vec![r]));
+        assert_eq!(diffs.len(), 1);
+        assert!(matches!(diffs[0], SnapshotDifference::ImageId { .. }));
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("state.json");
+        let a = snapshot(vec![ext("app", "1.0", true, true)]);
+        a.save(&path).unwrap();
+        assert_eq!(StateSnapshot::load(&path).unwrap(), a);
+    }
+
+    #[test]
+    fn test_load_reports_invalid_json() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("bad.json");
+        fs::write(&path, "not json").unwrap();
+        let err = StateSnapshot::load(&path).unwrap_err();
+        assert!(err.contains("Failed to parse snapshot"));
+    }
+}
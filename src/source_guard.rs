@@ -0,0 +1,199 @@
+//! Protection of the extensions directory against writes during a merge.
+//!
+//! An OTA client that rewrites a `.raw` image while the merge loop-mounts it
+//! can get a torn image mounted. `[avocado.ext] protect_source` keeps the
+//! images stable from the scan until systemd-sysext/confext have merged
+//! them:
+//!
+//! - `lock`: a shared `flock(2)` on every file at the top of the extensions
+//!   directory. Writers that take an exclusive lock, as avocadoctl's own
+//!   image installs do ([`copy_locked`]), wait for the merge, and the merge
+//!   waits for writers already holding one.
+//! - `read-only`: the directory is bind-mounted read-only onto itself, so
+//!   any write fails with `EROFS` until the merge is done.
+
+use crate::config::{Config, SourceProtection};
+use crate::error::{IoContext, IoError, SystemdError};
+use crate::output::OutputManager;
+use crate::timeouts::TimeoutKind;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+/// Held while the extensions directory is protected; dropping it lifts the
+/// protection.
+#[must_use]
+pub struct SourceGuard {
+    held: Held,
+}
+
+enum Held {
+    None,
+    /// Open files carrying a shared lock
+    Locks(Vec<File>),
+    /// Directory with a read-only bind mount on top, and where to warn when
+    /// it cannot be lifted
+    ReadOnly(PathBuf, Box<OutputManager>),
+}
+
+/// Protect the extensions directory of `config` as `[avocado.ext]
+/// protect_source` says. A directory that does not exist is not protected.
+pub fn protect(config: &Config, output: &OutputManager) -> Result<SourceGuard, SystemdError> {
+    let dir = PathBuf::from(config.get_extensions_dir());
+    let held = match config.protect_source() {
+        SourceProtection::Off => Held::None,
+        _ if !dir.is_dir() => Held::None,
+        SourceProtection::Lock => Held::Locks(lock_images(&dir, output)?),
+        SourceProtection::ReadOnly => {
+            bind_read_only(&dir)?;
+            output.log_info(&format!(
+                "Mounted {} read-only for the merge",
+                dir.display()
+            ));
+            Held::ReadOnly(dir, Box::new(output.detached()))
+        }
+    };
+    Ok(SourceGuard { held })
+}
+
+/// Take a shared lock on each file at the top of `dir`, waiting for
+/// writers that hold an exclusive one.
+fn lock_images(dir: &Path, output: &OutputManager) -> Result<Vec<File>, SystemdError> {
+    let mut locks = Vec::new();
+    for entry in fs::read_dir(dir).context("read directory", dir)? {
+        let path = entry.context("read directory", dir)?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let file = File::open(&path).context("open", &path)?;
+        match file.try_lock_shared() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                output.log_info(&format!(
+                    "Waiting for the writer of {} to finish",
+                    path.display()
+                ));
+                file.lock_shared().context("lock", &path)?;
+            }
+            Err(TryLockError::Error(source)) => {
+                return Err(IoError {
+                    action: "lock".to_string(),
+                    path,
+                    source,
+                }
+                .into())
+            }
+        }
+        locks.push(file);
+    }
+    output.log_info(&format!(
+        "Locked {} image(s) in {} for the merge",
+        locks.len(),
+        dir.display()
+    ));
+    Ok(locks)
+}
+
+/// Bind-mount `dir` onto itself and make the bind read-only.
+fn bind_read_only(dir: &Path) -> Result<(), SystemdError> {
+    let dir = dir.to_string_lossy();
+    run_mount("mount", &["--bind", &dir, &dir])?;
+    if let Err(e) = run_mount("mount", &["-o", "remount,bind,ro", &dir]) {
+        let _ = run_mount("umount", &[&dir]);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn run_mount(program: &str, args: &[&str]) -> Result<(), SystemdError> {
+    let command = format!("{program} {}", args.join(" "));
+    if let Some(result) = crate::backend::simulate(program, args) {
+        return result.map(|_| ());
+    }
+    let output = crate::timeouts::output(
+        ProcessCommand::new(crate::tools::program(program)).args(args),
+        TimeoutKind::SystemdCmd,
+    )
+    .map_err(|e| e.into_systemd_error(&command))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(SystemdError::CommandExitedWithError {
+            command,
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+impl Drop for SourceGuard {
+    fn drop(&mut self) {
+        match &self.held {
+            Held::None => {}
+            Held::Locks(files) => {
+                for file in files {
+                    let _ = file.unlock();
+                }
+            }
+            Held::ReadOnly(dir, output) => {
+                if let Err(e) = run_mount("umount", &[&dir.to_string_lossy()]) {
+                    output.warning(&format!(
+                        "Failed to lift the read-only mount of {}: {e}",
+                        dir.display()
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Copy `from` to `to` under an exclusive lock on `to`, so a merge holding
+/// shared locks never reads a half-written image.
+pub fn copy_locked(from: &Path, to: &Path) -> io::Result<u64> {
+    let mut source = File::open(from)?;
+    let mut target = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(to)?;
+    target.lock()?;
+    target.set_len(0)?;
+    let copied = io::copy(&mut source, &mut target)?;
+    target.set_permissions(source.metadata()?.permissions())?;
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_images_blocks_writers_until_dropped() {
+        let dir = TempDir::new().unwrap();
+        let image = dir.path().join("app.raw");
+        fs::write(&image, b"image").unwrap();
+        fs::create_dir(dir.path().join("unpacked")).unwrap();
+
+        let locks = lock_images(dir.path(), &OutputManager::new(false, false)).unwrap();
+        assert_eq!(locks.len(), 1);
+        let writer = OpenOptions::new().write(true).open(&image).unwrap();
+        assert!(matches!(writer.try_lock(), Err(TryLockError::WouldBlock)));
+
+        drop(locks);
+        assert!(writer.try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_copy_locked_replaces_content() {
+        let dir = TempDir::new().unwrap();
+        let staged = dir.path().join("staged.raw");
+        let installed = dir.path().join("app.raw");
+        fs::write(&staged, b"new").unwrap();
+        fs::write(&installed, b"old image content").unwrap();
+
+        assert_eq!(copy_locked(&staged, &installed).unwrap(), 3);
+        assert_eq!(fs::read(&installed).unwrap(), b"new");
+    }
+}
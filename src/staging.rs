@@ -1,7 +1,8 @@
-use crate::hash::{sha256_file, spot_hash_file};
+use crate::config::ChecksumAlgorithm;
+use crate::hash::{spot_hash_file, Checksummer};
 use crate::manifest::{RuntimeManifest, ACTIVE_LINK_NAME, IMAGES_DIR_NAME, MANIFEST_FILENAME};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use thiserror::Error;
@@ -45,7 +46,7 @@ pub struct ImageHashMismatch {
 pub struct SpotHashCache {
     pub version: u32,
     pub spot_check_bytes: u64,
-    pub hashes: HashMap<String, String>,
+    pub hashes: BTreeMap<String, String>,
 }
 
 impl SpotHashCache {
@@ -78,7 +79,7 @@ pub fn generate_spot_hashes(
     base_dir: &Path,
     spot_check_bytes: u64,
 ) -> Result<SpotHashCache, StagingError> {
-    let mut hashes = HashMap::new();
+    let mut hashes = BTreeMap::new();
 
     for ext in &manifest.extensions {
         let path = ext.resolve_path(base_dir);
@@ -125,24 +126,25 @@ pub fn generate_spot_hashes(
 /// Verify integrity of a runtime's extension images before activation or merge.
 ///
 /// If a `spot_hashes.json` cache exists in `runtime_dir`, uses the fast spot check.
-/// Otherwise falls back to full SHA256 validation against manifest hashes, then
+/// Otherwise falls back to full validation against manifest hashes, then
 /// generates and saves a spot cache for future checks.
 pub fn verify_runtime_integrity(
     manifest: &RuntimeManifest,
     base_dir: &Path,
     runtime_dir: &Path,
     spot_check_bytes: u64,
+    checksum: &Checksummer,
     verbose: bool,
 ) -> Result<(), StagingError> {
     if let Some(cache) = SpotHashCache::load(runtime_dir) {
         return verify_with_spot_cache(manifest, base_dir, &cache, verbose);
     }
 
-    // No spot cache — fall back to full SHA256 validation
+    // No spot cache — fall back to full digest validation
     if verbose {
-        eprintln!("Note: No spot hash cache found — falling back to full SHA256 verification");
+        eprintln!("Note: No spot hash cache found — falling back to full image verification");
     }
-    validate_manifest_images(manifest, base_dir)?;
+    validate_manifest_images(manifest, base_dir, checksum)?;
 
     // Full check passed — generate and save spot cache for next time
     if let Ok(cache) = generate_spot_hashes(manifest, base_dir, spot_check_bytes) {
@@ -157,10 +159,18 @@ pub fn verify_spot_hashes(
     manifest: &RuntimeManifest,
     base_dir: &Path,
     spot_check_bytes: u64,
+    checksum: &Checksummer,
     verbose: bool,
 ) -> Result<(), StagingError> {
     let active_dir = base_dir.join(ACTIVE_LINK_NAME);
-    verify_runtime_integrity(manifest, base_dir, &active_dir, spot_check_bytes, verbose)
+    verify_runtime_integrity(
+        manifest,
+        base_dir,
+        &active_dir,
+        spot_check_bytes,
+        checksum,
+        verbose,
+    )
 }
 
 /// Verify images against a loaded spot hash cache.
@@ -250,10 +260,12 @@ fn verify_with_spot_cache(
 }
 
 /// Check that all extension and OS bundle images referenced by the manifest
-/// exist on disk and, when SHA256 hashes are present, match their expected values.
+/// exist on disk and, when digests are present, match their expected values.
+/// The digest checked is the one `checksum` prefers, else the SHA256.
 pub fn validate_manifest_images(
     manifest: &RuntimeManifest,
     base_dir: &Path,
+    checksum: &Checksummer,
 ) -> Result<(), StagingError> {
     let mut missing: Vec<MissingImage> = Vec::new();
     let mut hash_errors: Vec<ImageHashMismatch> = Vec::new();
@@ -267,15 +279,15 @@ pub fn validate_manifest_images(
             });
             continue;
         }
-        if let Some(ref expected_sha) = ext.sha256 {
-            let actual = sha256_file(&path).map_err(|e| {
+        if let Some((algorithm, expected)) = checksum.pick(|a| ext.digest(a)) {
+            let actual = checksum.file_digest(algorithm, &path).map_err(|e| {
                 StagingError::StagingFailed(format!("Failed to hash {}: {e}", path.display()))
             })?;
-            if actual != *expected_sha {
+            if actual != expected {
                 hash_errors.push(ImageHashMismatch {
                     image_name: format!("{} {}", ext.name, ext.version),
                     path: path.display().to_string(),
-                    expected: expected_sha.clone(),
+                    expected: expected.to_string(),
                     actual,
                 });
             }
@@ -292,18 +304,18 @@ pub fn validate_manifest_images(
                 extension_name: format!("os_bundle ({})", os_bundle.image_id),
                 expected_path: path.display().to_string(),
             });
-        } else {
-            let actual = sha256_file(&path).map_err(|e| {
+        } else if let Some((algorithm, expected)) = checksum.pick(|a| os_bundle.digest(a)) {
+            let actual = checksum.file_digest(algorithm, &path).map_err(|e| {
                 StagingError::StagingFailed(format!(
                     "Failed to hash OS bundle {}: {e}",
                     path.display()
                 ))
             })?;
-            if actual != os_bundle.sha256 {
+            if actual != expected {
                 hash_errors.push(ImageHashMismatch {
                     image_name: format!("os_bundle ({})", os_bundle.image_id),
                     path: path.display().to_string(),
-                    expected: os_bundle.sha256.clone(),
+                    expected: expected.to_string(),
                     actual,
                 });
             }
@@ -364,13 +376,14 @@ pub fn stage_manifest(
 }
 
 /// Copy extension images from a staging directory into the shared image pool.
-/// Verifies SHA256 hashes after copying when hashes are present in the manifest.
+/// Verifies digests after copying when the manifest has them.
 /// Used by the TUF update path after downloading targets.
 pub fn install_images_from_staging(
     manifest: &RuntimeManifest,
     staging_dir: &Path,
     base_dir: &Path,
     skip_os_bundle: bool,
+    checksum: &Checksummer,
     verbose: bool,
 ) -> Result<(), StagingError> {
     let images_dir = base_dir.join(IMAGES_DIR_NAME);
@@ -388,23 +401,23 @@ pub fn install_images_from_staging(
                         ext.name, ext.version, image_id
                     );
                 }
-                // Verify hash of existing image if sha256 is available
-                if let Some(ref expected_sha) = ext.sha256 {
-                    verify_installed_hash(&dest, expected_sha, &ext.name)?;
+                // Verify hash of existing image if a digest is available
+                if let Some(expected) = checksum.pick(|a| ext.digest(a)) {
+                    verify_installed_hash(&dest, expected, &ext.name, checksum)?;
                 }
                 continue;
             }
             let staged_file = staging_dir.join(format!("{image_id}.raw"));
             if staged_file.exists() {
-                fs::copy(&staged_file, &dest).map_err(|e| {
+                crate::source_guard::copy_locked(&staged_file, &dest).map_err(|e| {
                     StagingError::StagingFailed(format!(
                         "Failed to install image for {}: {e}",
                         ext.name
                     ))
                 })?;
-                // Verify hash after copy if sha256 is available
-                if let Some(ref expected_sha) = ext.sha256 {
-                    verify_installed_hash(&dest, expected_sha, &ext.name)?;
+                // Verify hash after copy if a digest is available
+                if let Some(expected) = checksum.pick(|a| ext.digest(a)) {
+                    verify_installed_hash(&dest, expected, &ext.name, checksum)?;
                 }
                 if verbose {
                     println!(
@@ -431,21 +444,24 @@ pub fn install_images_from_staging(
             );
         } else {
             let image_id = &os_bundle.image_id;
+            let os_bundle_digest = checksum
+                .pick(|a| os_bundle.digest(a))
+                .unwrap_or((ChecksumAlgorithm::Sha256, &os_bundle.sha256));
             let dest = images_dir.join(format!("{image_id}.raw"));
             if dest.exists() {
                 if verbose {
                     println!("    OS bundle image already present: {image_id}");
                 }
-                verify_installed_hash(&dest, &os_bundle.sha256, "os_bundle")?;
+                verify_installed_hash(&dest, os_bundle_digest, "os_bundle", checksum)?;
             } else {
                 let staged_file = staging_dir.join(format!("{image_id}.raw"));
                 if staged_file.exists() {
-                    fs::copy(&staged_file, &dest).map_err(|e| {
+                    crate::source_guard::copy_locked(&staged_file, &dest).map_err(|e| {
                         StagingError::StagingFailed(format!(
                             "Failed to install OS bundle image: {e}"
                         ))
                     })?;
-                    verify_installed_hash(&dest, &os_bundle.sha256, "os_bundle")?;
+                    verify_installed_hash(&dest, os_bundle_digest, "os_bundle", checksum)?;
                     if verbose {
                         println!("    Installed OS bundle image: {image_id}");
                     }
@@ -470,13 +486,14 @@ pub fn install_images_from_staging(
     Ok(())
 }
 
-/// Verify the SHA256 hash of an installed image file.
+/// Verify the digest of an installed image file.
 fn verify_installed_hash(
     path: &Path,
-    expected: &str,
+    (algorithm, expected): (ChecksumAlgorithm, &str),
     image_name: &str,
+    checksum: &Checksummer,
 ) -> Result<(), StagingError> {
-    let actual = sha256_file(path).map_err(|e| {
+    let actual = checksum.file_digest(algorithm, path).map_err(|e| {
         StagingError::StagingFailed(format!("Failed to hash {}: {e}", path.display()))
     })?;
     if actual != expected {
@@ -541,6 +558,7 @@ fn resolve_active_id(base_dir: &Path) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::ChecksumBackend;
     use crate::manifest::{ManifestExtension, RuntimeInfo};
     use std::os::unix::fs as unix_fs;
     use tempfile::TempDir;
@@ -560,6 +578,8 @@ mod tests {
                 image_id: Some("a1b2c3d4-e5f6-5789-abcd-ef0123456789".to_string()),
                 image_type: None,
                 sha256: None,
+                sha512: None,
+                blake3: None,
                 enabled: true,
             }],
             os_bundle: None,
@@ -578,14 +598,14 @@ mod tests {
         .unwrap();
 
         let manifest = make_manifest("test-id", "dev", "0.1.0");
-        assert!(validate_manifest_images(&manifest, tmp.path()).is_ok());
+        assert!(validate_manifest_images(&manifest, tmp.path(), &Checksummer::default()).is_ok());
     }
 
     #[test]
     fn test_validate_manifest_images_missing() {
         let tmp = TempDir::new().unwrap();
         let manifest = make_manifest("test-id", "dev", "0.1.0");
-        let result = validate_manifest_images(&manifest, tmp.path());
+        let result = validate_manifest_images(&manifest, tmp.path(), &Checksummer::default());
         assert!(result.is_err());
         let err = result.unwrap_err().to_string();
         assert!(err.contains("app 0.1.0"));
@@ -689,7 +709,15 @@ mod tests {
         fs::create_dir_all(&base).unwrap();
 
         let manifest = make_manifest("test-id", "dev", "0.1.0");
-        install_images_from_staging(&manifest, &staging, &base, false, false).unwrap();
+        install_images_from_staging(
+            &manifest,
+            &staging,
+            &base,
+            false,
+            &Checksummer::default(),
+            false,
+        )
+        .unwrap();
 
         let installed = base.join("images").join(format!("{image_id}.raw"));
         assert!(installed.exists());
@@ -710,7 +738,15 @@ mod tests {
         fs::write(images_dir.join(format!("{image_id}.raw")), b"old content").unwrap();
 
         let manifest = make_manifest("test-id", "dev", "0.1.0");
-        install_images_from_staging(&manifest, &staging, &base, false, false).unwrap();
+        install_images_from_staging(
+            &manifest,
+            &staging,
+            &base,
+            false,
+            &Checksummer::default(),
+            false,
+        )
+        .unwrap();
 
         let content = fs::read_to_string(images_dir.join(format!("{image_id}.raw"))).unwrap();
         assert_eq!(content, "old content");
@@ -743,6 +779,8 @@ mod tests {
                 image_id: Some("a1b2c3d4-e5f6-5789-abcd-ef0123456789".to_string()),
                 image_type: None,
                 sha256,
+                sha512: None,
+                blake3: None,
                 enabled: true,
             }],
             os_bundle: None,
@@ -764,7 +802,7 @@ mod tests {
         .unwrap();
 
         let manifest = make_manifest_with_hash("test-id", "dev", "0.1.0", Some(hash));
-        assert!(validate_manifest_images(&manifest, tmp.path()).is_ok());
+        assert!(validate_manifest_images(&manifest, tmp.path(), &Checksummer::default()).is_ok());
     }
 
     #[test]
@@ -785,13 +823,43 @@ mod tests {
             "0.1.0",
             Some("0000000000000000000000000000000000000000000000000000000000000000".to_string()),
         );
-        let result = validate_manifest_images(&manifest, tmp.path());
+        let result = validate_manifest_images(&manifest, tmp.path(), &Checksummer::default());
         assert!(result.is_err());
         let err = result.unwrap_err().to_string();
         assert!(err.contains("integrity check failed"));
         assert!(err.contains("app 0.1.0"));
     }
 
+    #[test]
+    fn test_validate_manifest_images_checks_preferred_digest() {
+        let tmp = TempDir::new().unwrap();
+        let images_dir = tmp.path().join("images");
+        fs::create_dir_all(&images_dir).unwrap();
+        fs::write(
+            images_dir.join("a1b2c3d4-e5f6-5789-abcd-ef0123456789.raw"),
+            b"hello world",
+        )
+        .unwrap();
+
+        // The BLAKE3 digest is right and the SHA256 wrong: only a
+        // checksummer preferring BLAKE3 accepts the image
+        let mut manifest =
+            make_manifest_with_hash("test-id", "dev", "0.1.0", Some("00".repeat(32)));
+        manifest.extensions[0].blake3 =
+            Some("d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24".to_string());
+        let blake3 = Checksummer::new(ChecksumAlgorithm::Blake3, ChecksumBackend::Software);
+        // A build without BLAKE3 checks the SHA256 instead
+        assert_eq!(
+            validate_manifest_images(&manifest, tmp.path(), &blake3).is_ok(),
+            cfg!(feature = "blake3")
+        );
+        assert!(validate_manifest_images(&manifest, tmp.path(), &Checksummer::default()).is_err());
+
+        // Without a SHA512 digest, a SHA512 checksummer checks the SHA256
+        let sha512 = Checksummer::new(ChecksumAlgorithm::Sha512, ChecksumBackend::Software);
+        assert!(validate_manifest_images(&manifest, tmp.path(), &sha512).is_err());
+    }
+
     #[test]
     fn test_validate_manifest_images_no_hash_skips_check() {
         let tmp = TempDir::new().unwrap();
@@ -804,7 +872,7 @@ mod tests {
         .unwrap();
 
         let manifest = make_manifest_with_hash("test-id", "dev", "0.1.0", None);
-        assert!(validate_manifest_images(&manifest, tmp.path()).is_ok());
+        assert!(validate_manifest_images(&manifest, tmp.path(), &Checksummer::default()).is_ok());
     }
 
     #[test]
@@ -829,10 +897,12 @@ mod tests {
         manifest.os_bundle = Some(OsBundleRef {
             image_id: "deadbeef-1234-5678-abcd-000000000000".to_string(),
             sha256: "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            sha512: None,
+            blake3: None,
             os_build_id: None,
             initramfs_build_id: None,
         });
-        let result = validate_manifest_images(&manifest, tmp.path());
+        let result = validate_manifest_images(&manifest, tmp.path(), &Checksummer::default());
         assert!(result.is_err());
         let err = result.unwrap_err().to_string();
         assert!(err.contains("integrity check failed"));
@@ -857,7 +927,14 @@ mod tests {
             "0.1.0",
             Some("0000000000000000000000000000000000000000000000000000000000000000".to_string()),
         );
-        let result = install_images_from_staging(&manifest, &staging, &base, false, false);
+        let result = install_images_from_staging(
+            &manifest,
+            &staging,
+            &base,
+            false,
+            &Checksummer::default(),
+            false,
+        );
         assert!(result.is_err());
         let err = result.unwrap_err().to_string();
         assert!(err.contains("integrity check failed"));
@@ -878,7 +955,15 @@ mod tests {
         fs::create_dir_all(&base).unwrap();
 
         let manifest = make_manifest_with_hash("test-id", "dev", "0.1.0", Some(hash));
-        assert!(install_images_from_staging(&manifest, &staging, &base, false, false).is_ok());
+        assert!(install_images_from_staging(
+            &manifest,
+            &staging,
+            &base,
+            false,
+            &Checksummer::default(),
+            false,
+        )
+        .is_ok());
 
         let installed = base.join("images").join(format!("{image_id}.raw"));
         assert!(installed.exists());
@@ -887,7 +972,7 @@ mod tests {
     #[test]
     fn test_spot_hash_cache_roundtrip() {
         let tmp = TempDir::new().unwrap();
-        let mut hashes = HashMap::new();
+        let mut hashes = BTreeMap::new();
         hashes.insert("test.raw".to_string(), "abcdef".to_string());
         let cache = SpotHashCache {
             version: 1,
@@ -951,7 +1036,9 @@ mod tests {
         unix_fs::symlink("runtimes/test-id", tmp.path().join("active")).unwrap();
 
         // Verification should pass
-        assert!(verify_spot_hashes(&manifest, tmp.path(), 4096, false).is_ok());
+        assert!(
+            verify_spot_hashes(&manifest, tmp.path(), 4096, &Checksummer::default(), false).is_ok()
+        );
     }
 
     #[test]
@@ -980,7 +1067,8 @@ mod tests {
         .unwrap();
 
         // Verification should fail
-        let result = verify_spot_hashes(&manifest, tmp.path(), 4096, false);
+        let result =
+            verify_spot_hashes(&manifest, tmp.path(), 4096, &Checksummer::default(), false);
         assert!(result.is_err());
         let err = result.unwrap_err().to_string();
         assert!(err.contains("integrity check failed"));
@@ -1003,7 +1091,9 @@ mod tests {
         unix_fs::symlink("runtimes/test-id", tmp.path().join("active")).unwrap();
 
         // Should fall back to full SHA256 and pass (no sha256 in manifest = skip)
-        assert!(verify_spot_hashes(&manifest, tmp.path(), 4096, false).is_ok());
+        assert!(
+            verify_spot_hashes(&manifest, tmp.path(), 4096, &Checksummer::default(), false).is_ok()
+        );
 
         // Should have generated the spot cache as a side effect
         assert!(SpotHashCache::load(&runtime_dir).is_some());
@@ -1028,7 +1118,8 @@ mod tests {
         unix_fs::symlink("runtimes/test-id", tmp.path().join("active")).unwrap();
 
         // No spot cache, falls back to full SHA256, which should fail
-        let result = verify_spot_hashes(&manifest, tmp.path(), 4096, false);
+        let result =
+            verify_spot_hashes(&manifest, tmp.path(), 4096, &Checksummer::default(), false);
         assert!(result.is_err());
     }
 }
@@ -0,0 +1,191 @@
+//! Free-space preflight for runtime updates.
+//!
+//! `runtime add --url` downloads extension images into the avocado base
+//! directory. On devices whose data partition was never expanded to fill
+//! the disk, that used to fail part-way with ENOSPC. Before downloading,
+//! [`ensure_space`] compares the bytes the update still needs with the
+//! space `df` reports and fails up front when they do not fit.
+//!
+//! With `[avocado.storage] grow` set, the filesystem is grown first
+//! instead: `repart` runs `systemd-repart` to grow the partition as the
+//! repart.d definitions allow and `systemd-growfs` on its mount point;
+//! `resize2fs` grows an ext4 filesystem into a partition that is already
+//! larger. Growth is opt-in because it rewrites the partition table.
+
+#[cfg(feature = "network")]
+use crate::config::{StorageGrowth, StorageSettings};
+#[cfg(feature = "network")]
+use crate::timeouts::{self, TimeoutKind};
+#[cfg(feature = "network")]
+use std::path::Path;
+#[cfg(feature = "network")]
+use std::process::Command;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Not enough space in {path}: {needed} bytes needed, {available} available")]
+    Insufficient {
+        path: String,
+        needed: u64,
+        available: u64,
+    },
+
+    #[error("Failed to grow {target} with {command}: {reason}")]
+    GrowFailed {
+        target: String,
+        command: String,
+        reason: String,
+    },
+}
+
+/// Space on the filesystem holding a path, as reported by `df`.
+#[cfg(feature = "network")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Usage {
+    pub device: String,
+    pub mount_point: String,
+    /// Bytes available to unprivileged users
+    pub available: u64,
+}
+
+/// Parse `df -Pk` output for a single path.
+#[cfg(feature = "network")]
+pub fn parse_df(stdout: &str) -> Option<Usage> {
+    // The mount point is the last column and may contain spaces
+    let line = stdout.lines().nth(1)?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 6 {
+        return None;
+    }
+    let available = fields[3].parse::<u64>().ok()?;
+    Some(Usage {
+        device: fields[0].to_string(),
+        mount_point: fields[5..].join(" "),
+        available: available.saturating_mul(1024),
+    })
+}
+
+/// Space on the filesystem holding `path`.
+#[cfg(feature = "network")]
+pub fn usage(path: &Path) -> Result<Usage, String> {
+    let output = timeouts::output(
+        Command::new(crate::tools::program("df"))
+            .arg("-Pk")
+            .arg(path),
+        TimeoutKind::SystemdCmd,
+    )
+    .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| "unexpected df output".to_string())
+}
+
+#[cfg(feature = "network")]
+fn run_grow(target: &str, tool: &str, args: &[&str]) -> Result<(), StorageError> {
+    let program = crate::tools::program(tool);
+    let grow_failed = |reason: String| StorageError::GrowFailed {
+        target: target.to_string(),
+        command: tool.to_string(),
+        reason,
+    };
+    let output = timeouts::output(Command::new(&program).args(args), TimeoutKind::SystemdCmd)
+        .map_err(|e| grow_failed(e.to_string()))?;
+    if !output.status.success() {
+        return Err(grow_failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Grow the filesystem described by `usage` as `settings` allow.
+#[cfg(feature = "network")]
+fn grow(usage: &Usage, settings: &StorageSettings) -> Result<(), StorageError> {
+    match settings.grow {
+        StorageGrowth::None => Ok(()),
+        StorageGrowth::Repart => {
+            run_grow(&usage.device, "systemd-repart", &["--dry-run=no"])?;
+            run_grow(&usage.mount_point, "systemd-growfs", &[&usage.mount_point])
+        }
+        StorageGrowth::Resize2fs => {
+            let device = settings.device.as_deref().unwrap_or(&usage.device);
+            run_grow(device, "resize2fs", &[device])
+        }
+    }
+}
+
+/// Make sure `needed` bytes (plus `reserve_mb`) fit below `path`, growing
+/// the filesystem when configured. When the free space cannot be
+/// determined the update goes ahead with a warning.
+#[cfg(feature = "network")]
+pub fn ensure_space(
+    path: &Path,
+    needed: u64,
+    settings: &StorageSettings,
+) -> Result<(), StorageError> {
+    let needed = needed.saturating_add(settings.reserve_mb.saturating_mul(1024 * 1024));
+    let mut current = match usage(path) {
+        Ok(usage) => usage,
+        Err(e) => {
+            println!(
+                "    WARNING: Could not check free space in {}: {e}",
+                path.display()
+            );
+            return Ok(());
+        }
+    };
+    if current.available >= needed {
+        return Ok(());
+    }
+
+    if settings.grow != StorageGrowth::None {
+        println!(
+            "  Growing {} ({} bytes needed, {} available)...",
+            current.mount_point, needed, current.available
+        );
+        grow(&current, settings)?;
+        if let Ok(grown) = usage(path) {
+            current = grown;
+        }
+        if current.available >= needed {
+            println!("  Grown to {} bytes available", current.available);
+            return Ok(());
+        }
+    }
+
+    Err(StorageError::Insufficient {
+        path: path.display().to_string(),
+        needed,
+        available: current.available,
+    })
+}
+
+#[cfg(all(test, feature = "network"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_df() {
+        let usage = parse_df(
+            "Filesystem     1024-blocks   Used Available Capacity Mounted on\n\
+             /dev/mmcblk0p4      507904 410112     97792      81% /var/lib/avocado\n",
+        )
+        .unwrap();
+        assert_eq!(usage.device, "/dev/mmcblk0p4");
+        assert_eq!(usage.mount_point, "/var/lib/avocado");
+        assert_eq!(usage.available, 97792 * 1024);
+
+        let spaced = parse_df(
+            "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+             /dev/sda1 100 50 50 50% /mnt/my data\n",
+        )
+        .unwrap();
+        assert_eq!(spaced.mount_point, "/mnt/my data");
+
+        assert!(parse_df("Filesystem 1024-blocks Used Available\n").is_none());
+        assert!(parse_df("").is_none());
+    }
+}
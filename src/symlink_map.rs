@@ -0,0 +1,135 @@
+//! Which extension each merge symlink belongs to.
+//!
+//! The symlinks in `/run/extensions` and `/run/confexts` carry merge-order
+//! prefixes and point at loop mounts, staging directories or HITL mounts,
+//! so `readlink` alone rarely says where one came from. Every merge records
+//! each symlink it made, with its extension, version and origin, in
+//! `/run/avocado/symlink-map.json`; unmerge clears it. `ext status -o json`
+//! and `ext info` read it back.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SYMLINK_MAP_FILENAME: &str = "symlink-map.json";
+
+/// A symlink created for systemd-sysext or systemd-confext.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    /// Full path of the symlink
+    pub path: String,
+    /// `sysext` or `confext`
+    pub kind: String,
+    pub extension: String,
+    pub version: Option<String>,
+    /// Where the extension came from, as `ext status` shows it
+    /// (`Dir`, `Loop:<image>`, `KAB:<image>`, `HITL`)
+    pub origin: String,
+    /// What the symlink points at
+    pub source: String,
+}
+
+impl Link {
+    /// `<name>-<version>`, or the bare name of an unversioned extension.
+    pub fn versioned_name(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}-{version}", self.extension),
+            None => self.extension.clone(),
+        }
+    }
+}
+
+/// `/run/avocado/symlink-map.json`, or under `$TMPDIR/avocado` in test mode.
+fn path() -> PathBuf {
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        let temp_base = std::env::var("TMPDIR").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(format!("{temp_base}/avocado/{SYMLINK_MAP_FILENAME}"))
+    } else {
+        PathBuf::from(crate::user_mode::system_path(&format!(
+            "/run/avocado/{SYMLINK_MAP_FILENAME}"
+        )))
+    }
+}
+
+/// Record the symlinks of the current merge, replacing the last merge's
+/// map; none removes it.
+pub fn record(links: &[Link]) -> std::io::Result<()> {
+    record_at(&path(), links)
+}
+
+fn record_at(path: &Path, links: &[Link]) -> std::io::Result<()> {
+    if links.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(links).map_err(std::io::Error::other)?;
+    fs::write(path, json + "\n")
+}
+
+/// The symlinks of the last merge.
+pub fn recorded() -> Vec<Link> {
+    recorded_from(&path())
+}
+
+fn recorded_from(path: &Path) -> Vec<Link> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// The recorded symlinks of one extension, given as `<name>-<version>` or
+/// its bare name.
+pub fn links_of<'a>(links: &'a [Link], name: &str) -> Vec<&'a Link> {
+    links
+        .iter()
+        .filter(|link| link.extension == name || link.versioned_name() == name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(kind: &str, extension: &str, version: Option<&str>) -> Link {
+        Link {
+            path: format!("/run/extensions/01-{extension}"),
+            kind: kind.to_string(),
+            extension: extension.to_string(),
+            version: version.map(str::to_string),
+            origin: "Dir".to_string(),
+            source: format!("/var/lib/avocado/extensions/{extension}"),
+        }
+    }
+
+    #[test]
+    fn test_record_replaces_previous_map() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("avocado").join(SYMLINK_MAP_FILENAME);
+        let app = link("sysext", "app", Some("1.0"));
+        record_at(&path, &[app.clone(), link("confext", "tools", None)]).unwrap();
+        record_at(&path, std::slice::from_ref(&app)).unwrap();
+        assert_eq!(recorded_from(&path), [app]);
+        record_at(&path, &[]).unwrap();
+        assert!(!path.exists());
+        assert!(recorded_from(&path).is_empty());
+    }
+
+    #[test]
+    fn test_links_of_matches_versioned_and_bare_name() {
+        let links = [
+            link("sysext", "app", Some("1.0")),
+            link("confext", "app", Some("1.0")),
+            link("sysext", "tools", None),
+        ];
+        assert_eq!(links_of(&links, "app-1.0").len(), 2);
+        assert_eq!(links_of(&links, "app").len(), 2);
+        assert_eq!(links_of(&links, "tools").len(), 1);
+        assert!(links_of(&links, "app-2.0").is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! Parsing of `systemd-sysext status --json` / `systemd-confext status --json`.
+//!
+//! The layout of the status JSON changed across systemd releases, so it is
+//! read tolerantly rather than into a fixed schema:
+//!
+//! - the top level is an array of hierarchies, a single hierarchy object, or
+//!   an object wrapping the array in `hierarchies`
+//! - `extensions` is `"none"`, one name as a string, an array of names, an
+//!   array of objects (`{"name": ..., "since": ...}`), or an object mapping
+//!   each name to its `since` (or to an object carrying one)
+//! - `since` is a number in microseconds (seconds and milliseconds are
+//!   recognised by magnitude), a digit string, a date such as
+//!   `Tue 2025-01-14 15:30:00 UTC`, or `null`
+//!
+//! An extension without its own `since` gets the one of its hierarchy.
+//! Entries that fit none of these shapes are skipped.
+
+use serde_json::Value;
+
+/// An extension merged into a hierarchy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merged {
+    /// Name as systemd reports it, including any order prefix
+    pub name: String,
+    pub hierarchy: String,
+    /// When the extension was merged, in seconds since the Unix epoch
+    pub since: Option<u64>,
+}
+
+/// Parse the output of `status --json`. Empty output means nothing is
+/// merged; only output that is not JSON at all is an error.
+pub fn parse(output: &str) -> Result<Vec<Merged>, serde_json::Error> {
+    if output.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let json: Value = serde_json::from_str(output)?;
+    let hierarchies = match &json {
+        Value::Array(items) => items.as_slice(),
+        Value::Object(object) => match object.get("hierarchies") {
+            Some(Value::Array(items)) => items.as_slice(),
+            _ => std::slice::from_ref(&json),
+        },
+        _ => &[],
+    };
+
+    let mut merged = Vec::new();
+    for entry in hierarchies {
+        let hierarchy = entry["hierarchy"].as_str().unwrap_or("unknown").to_string();
+        let since = parse_since(&entry["since"]);
+        let mut push = |name: &str, own: Option<u64>| {
+            if !name.is_empty() && name != "none" {
+                merged.push(Merged {
+                    name: name.to_string(),
+                    hierarchy: hierarchy.clone(),
+                    since: own.or(since),
+                });
+            }
+        };
+        match &entry["extensions"] {
+            Value::String(names) => {
+                for name in names.split(|c: char| c.is_whitespace() || c == ',') {
+                    push(name, None);
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    match item {
+                        Value::String(name) => push(name, None),
+                        Value::Object(_) => {
+                            if let Some(name) = item_name(item) {
+                                push(name, parse_since(&item["since"]));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Value::Object(map) => {
+                for (name, value) in map {
+                    let own = match value {
+                        Value::Object(_) => parse_since(&value["since"]),
+                        other => parse_since(other),
+                    };
+                    push(name, own);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(merged)
+}
+
+fn item_name(item: &Value) -> Option<&str> {
+    ["name", "extension", "image"]
+        .iter()
+        .find_map(|key| item[key].as_str())
+}
+
+/// A `since` value as seconds since the Unix epoch.
+fn parse_since(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(number) => number
+            .as_u64()
+            .or_else(|| number.as_f64().filter(|f| *f >= 0.0).map(|f| f as u64))
+            .and_then(epoch_seconds),
+        Value::String(text) => {
+            let text = text.trim();
+            if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) {
+                return text.parse().ok().and_then(epoch_seconds);
+            }
+            // systemd's human-readable timestamps lead with the weekday
+            let text = match text.split_once(' ') {
+                Some((day, rest))
+                    if day.len() == 3 && day.bytes().all(|b| b.is_ascii_alphabetic()) =>
+                {
+                    rest
+                }
+                _ => text,
+            };
+            crate::clock::parse_utc(text)
+        }
+        _ => None,
+    }
+}
+
+/// Scale a timestamp in seconds, milliseconds or microseconds to seconds;
+/// zero means unset.
+fn epoch_seconds(value: u64) -> Option<u64> {
+    match value {
+        0 => None,
+        v if v >= 100_000_000_000_000 => Some(v / 1_000_000),
+        v if v >= 100_000_000_000 => Some(v / 1_000),
+        v => Some(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(merged: &[Merged]) -> Vec<(&str, &str, Option<u64>)> {
+        merged
+            .iter()
+            .map(|m| (m.name.as_str(), m.hierarchy.as_str(), m.since))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_classic_layout() {
+        let merged = parse(
+            r#"[{"hierarchy":"/opt","extensions":"none","since":null},
+                {"hierarchy":"/usr","extensions":["00-base","app"],"since":1705243805000000}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            names(&merged),
+            [
+                ("00-base", "/usr", Some(1_705_243_805)),
+                ("app", "/usr", Some(1_705_243_805)),
+            ]
+        );
+        assert_eq!(
+            names(&parse(r#"{"hierarchy":"/etc","extensions":"conf"}"#).unwrap()),
+            [("conf", "/etc", None)]
+        );
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("not json").is_err());
+    }
+
+    #[test]
+    fn test_parse_per_extension_since() {
+        let objects = parse(
+            r#"{"hierarchies":[{"hierarchy":"/usr","since":1705243805,"extensions":[
+                {"name":"app","since":"Tue 2025-01-14 15:30:00 UTC"},
+                {"extension":"tools"}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            names(&objects),
+            [
+                ("app", "/usr", Some(1_736_868_600)),
+                ("tools", "/usr", Some(1_705_243_805)),
+            ]
+        );
+
+        let map = parse(
+            r#"[{"hierarchy":"/usr","extensions":{"app":{"since":1736868600000},"tools":"1705243805000000","debug":null}}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            names(&map),
+            [
+                ("app", "/usr", Some(1_736_868_600)),
+                ("debug", "/usr", None),
+                ("tools", "/usr", Some(1_705_243_805)),
+            ]
+        );
+    }
+}
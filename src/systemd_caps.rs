@@ -0,0 +1,162 @@
+//! systemd version detection and the extension-tool features it implies.
+//!
+//! Devices in the field run systemd 250 through 255 and newer, and
+//! systemd-sysext / systemd-confext grew options over that range. The version
+//! is probed once per process from `systemd-sysext --version`, and options
+//! the installed tools do not understand are dropped from their command lines
+//! instead of making every merge fail on an older device.
+//!
+//! `AVOCADO_SYSTEMD_VERSION` pins the version without probing. When the probe
+//! fails (or the mock backend is active) every feature is assumed available,
+//! which matches the behavior before detection existed.
+
+use serde::Serialize;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// Environment variable pinning the systemd version instead of probing.
+pub const SYSTEMD_VERSION_ENV: &str = "AVOCADO_SYSTEMD_VERSION";
+
+/// First release shipping systemd-confext.
+const CONFEXT_SINCE: u32 = 254;
+/// First release accepting `--image-policy=` and `--noexec=` for sysext/confext.
+const IMAGE_POLICY_SINCE: u32 = 254;
+/// First release accepting `--no-reload`.
+const NO_RELOAD_SINCE: u32 = 255;
+/// First release accepting `--mutable=`.
+const MUTABLE_SINCE: u32 = 256;
+
+/// Features of the installed systemd-sysext / systemd-confext.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SystemdCapabilities {
+    /// Detected systemd version, `None` when it could not be determined.
+    pub version: Option<u32>,
+    /// systemd-confext is available.
+    pub confext: bool,
+    /// `--mutable=` is accepted.
+    pub mutable: bool,
+    /// `--no-reload` is accepted.
+    pub no_reload: bool,
+    /// `--image-policy=` and `--noexec=` are accepted.
+    pub image_policy: bool,
+}
+
+impl SystemdCapabilities {
+    /// Capabilities of a given systemd version. An unknown version is
+    /// assumed to support everything.
+    pub fn for_version(version: Option<u32>) -> Self {
+        let since = |min: u32| version.is_none_or(|v| v >= min);
+        SystemdCapabilities {
+            version,
+            confext: since(CONFEXT_SINCE),
+            mutable: since(MUTABLE_SINCE),
+            no_reload: since(NO_RELOAD_SINCE),
+            image_policy: since(IMAGE_POLICY_SINCE),
+        }
+    }
+
+    /// Whether the extension tools accept `arg`.
+    pub fn supports_arg(&self, arg: &str) -> bool {
+        if arg.starts_with("--mutable=") {
+            self.mutable
+        } else if arg == "--no-reload" {
+            self.no_reload
+        } else if arg.starts_with("--image-policy=") || arg.starts_with("--noexec=") {
+            self.image_policy
+        } else {
+            true
+        }
+    }
+
+    /// `args` without the options the extension tools do not accept.
+    pub fn filter_args<'a>(&self, args: &[&'a str]) -> Vec<&'a str> {
+        args.iter()
+            .copied()
+            .filter(|arg| self.supports_arg(arg))
+            .collect()
+    }
+}
+
+/// Parse the version from `systemd-sysext --version` output, whose first
+/// line reads `systemd 255 (255.4-1ubuntu8)`.
+pub fn parse_version(output: &str) -> Option<u32> {
+    let mut words = output.lines().next()?.split_whitespace();
+    if words.next()? != "systemd" {
+        return None;
+    }
+    let version = words.next()?;
+    let digits = version
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(version, |end| &version[..end]);
+    digits.parse().ok()
+}
+
+fn probe_version() -> Option<u32> {
+    if let Ok(pinned) = std::env::var(SYSTEMD_VERSION_ENV) {
+        return pinned.trim().parse().ok();
+    }
+    if crate::backend::is_mock() {
+        return None;
+    }
+    let output = Command::new(crate::tools::program("systemd-sysext"))
+        .arg("--version")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Capabilities of the installed systemd, probed once per process.
+pub fn detect() -> &'static SystemdCapabilities {
+    static CAPABILITIES: OnceLock<SystemdCapabilities> = OnceLock::new();
+    CAPABILITIES.get_or_init(|| SystemdCapabilities::for_version(probe_version()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("systemd 255 (255.4-1ubuntu8)\n+PAM +AUDIT -SELINUX"),
+            Some(255)
+        );
+        assert_eq!(parse_version("systemd 250~rc1 (250~rc1)"), Some(250));
+        assert_eq!(parse_version("systemd-sysext 1.0"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn test_capabilities_by_version() {
+        let v250 = SystemdCapabilities::for_version(Some(250));
+        assert!(!v250.confext && !v250.mutable && !v250.no_reload && !v250.image_policy);
+
+        let v255 = SystemdCapabilities::for_version(Some(255));
+        assert!(v255.confext && v255.no_reload && v255.image_policy);
+        assert!(!v255.mutable);
+
+        let unknown = SystemdCapabilities::for_version(None);
+        assert!(unknown.confext && unknown.mutable && unknown.no_reload);
+    }
+
+    #[test]
+    fn test_filter_args_drops_unsupported_options() {
+        let caps = SystemdCapabilities::for_version(Some(252));
+        assert_eq!(
+            caps.filter_args(&[
+                "merge",
+                "--mutable=ephemeral",
+                "--no-reload",
+                "--json=short",
+                "--image-policy=root=verity",
+                "--root=/tmp/r",
+            ]),
+            vec!["merge", "--json=short", "--root=/tmp/r"]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Detection of a running systemd.
+//!
+//! Merging needs systemd as PID 1: the daemon-reload after a merge, the
+//! services extensions enable and most AVOCADO_ON_MERGE commands talk to it
+//! over D-Bus. In a chroot or a minimal container nothing answers, and
+//! each of those steps used to fail with its own D-Bus error. Like
+//! `sd_booted()`, systemd counts as running when `/run/systemd/system`
+//! exists; merge, unmerge and refresh check that first and fail with one
+//! error, while `merge --mount-only` still prepares the extensions (links
+//! and loop mounts) without running systemd-sysext or systemd-confext.
+//!
+//! `AVOCADO_SYSTEMD_RUNNING` (`1` or `0`) overrides detection. In test mode
+//! and with the mock backend systemd is assumed to be running.
+
+use crate::commands::ext::SystemdError;
+use std::path::Path;
+
+/// Environment variable overriding detection (`1` running, `0` not).
+pub const SYSTEMD_RUNNING_ENV: &str = "AVOCADO_SYSTEMD_RUNNING";
+
+/// Directory systemd creates when it runs as PID 1.
+const RUNTIME_DIR: &str = "/run/systemd/system";
+
+/// Whether systemd runs as PID 1.
+pub fn is_running() -> bool {
+    if let Some(running) = crate::settings::current().systemd_running {
+        return running;
+    }
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() || crate::backend::is_mock() {
+        return true;
+    }
+    Path::new(RUNTIME_DIR).is_dir()
+}
+
+/// Whether a command line merges, unmerges or refreshes extensions.
+#[cfg(feature = "daemon")]
+pub fn is_merge_command(matches: &clap::ArgMatches) -> bool {
+    let merging = |name: Option<&str>| matches!(name, Some("merge" | "unmerge" | "refresh"));
+    match matches.subcommand() {
+        Some(("ext", ext)) => merging(ext.subcommand_name()),
+        _ => merging(matches.subcommand_name()),
+    }
+}
+
+/// Fail `operation` (such as "merge") when systemd is not running.
+pub fn require(operation: &str) -> Result<(), SystemdError> {
+    if is_running() {
+        return Ok(());
+    }
+    Err(SystemdError::SystemdNotRunning {
+        operation: operation.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_env::ENV_VAR_MUTEX;
+
+    #[test]
+    fn test_override_decides_detection() {
+        let _lock = ENV_VAR_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        std::env::set_var(SYSTEMD_RUNNING_ENV, "0");
+        assert!(!is_running());
+        let err = require("merge").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "systemd is not running (no /run/systemd/system): cannot merge extensions"
+        );
+
+        std::env::set_var(SYSTEMD_RUNNING_ENV, "1");
+        assert!(is_running());
+        assert!(require("merge").is_ok());
+        std::env::remove_var(SYSTEMD_RUNNING_ENV);
+    }
+}
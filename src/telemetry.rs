@@ -0,0 +1,445 @@
+//! OpenTelemetry traces of the merge pipeline and HITL operations.
+//!
+//! With an OTLP endpoint configured (`[avocado.telemetry] otlp_endpoint`,
+//! overridden by the standard `OTEL_EXPORTER_OTLP_ENDPOINT`), merge, unmerge, refresh and HITL
+//! mount/unmount each produce a trace: one span for the operation, nested
+//! spans for the scanning, mounting, merging and hooks phases (see
+//! [`crate::phases`]), and spans for every image mounted and every hook
+//! command run. When the operation finishes the trace is posted as
+//! OTLP/HTTP JSON to `<endpoint>/v1/traces`. Export is best effort: a
+//! collector that is down or slow (beyond `timeout_ms`) loses the trace
+//! but never fails the operation.
+//!
+//! A `TRACEPARENT` in the environment (W3C trace context) makes the
+//! operation a child of the caller's span. The trace and span ids of the
+//! operation are added to its JSON result (`trace_id`, `span_id`) so a
+//! device log line can be matched with the trace in the backend.
+//!
+//! Spans are kept per thread: an operation and its phases run on one
+//! thread, in-process or on a daemon worker. Without an endpoint, or in a
+//! build without the `network` feature, nothing is recorded.
+
+use crate::output::OutputManager;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Environment variable overriding the OTLP/HTTP collector base URL.
+pub const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+/// Environment variable overriding the `service.name` of exported spans.
+pub const SERVICE_NAME_ENV: &str = "OTEL_SERVICE_NAME";
+/// Environment variable overriding the export timeout in milliseconds.
+pub const TIMEOUT_ENV: &str = "OTEL_EXPORTER_OTLP_TIMEOUT";
+/// W3C trace context of the caller.
+pub const TRACEPARENT_ENV: &str = "TRACEPARENT";
+
+/// Trace and span id of an operation, as lowercase hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceIds {
+    pub trace_id: String,
+    pub span_id: String,
+}
+
+impl TraceIds {
+    /// Parse the `<trace_id> <span_id>` form streamed by the daemon.
+    pub fn parse(line: &str) -> Option<Self> {
+        let (trace_id, span_id) = line.trim().split_once(' ')?;
+        (is_hex_id(trace_id, 32) && is_hex_id(span_id, 16)).then(|| Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for TraceIds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.trace_id, self.span_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SpanData {
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: String,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(String, String)>,
+    error: Option<String>,
+}
+
+struct Trace {
+    trace_id: String,
+    /// Spans still running, innermost last
+    open: Vec<SpanData>,
+    finished: Vec<SpanData>,
+}
+
+thread_local! {
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+}
+
+/// A running span, ended (and, for the outermost span, exported) when
+/// dropped. Inert when telemetry is disabled.
+pub struct Span {
+    /// Position in the stack of open spans, `None` when not recording
+    depth: Option<usize>,
+    /// Operation whose finish is recorded in the event log on drop, with
+    /// its error, whether or not the span is recording
+    event: Option<(String, RefCell<Option<String>>)>,
+    /// Spans belong to the thread that opened them
+    _thread: PhantomData<*const ()>,
+}
+
+impl Span {
+    fn inert() -> Self {
+        Self {
+            depth: None,
+            event: None,
+            _thread: PhantomData,
+        }
+    }
+
+    /// Attach `key = value` to the span.
+    pub fn set_attribute(&self, key: &str, value: impl Into<String>) {
+        self.with(|span| span.attributes.push((key.to_string(), value.into())));
+    }
+
+    /// Mark the span as failed with `message`.
+    pub fn set_error(&self, message: impl Into<String>) {
+        let message = message.into();
+        if let Some((_, error)) = &self.event {
+            *error.borrow_mut() = Some(message.clone());
+        }
+        self.with(|span| span.error = Some(message));
+    }
+
+    /// Mark the span as failed when `result` is an error, passing it on.
+    pub fn record<T, E: std::fmt::Display>(&self, result: Result<T, E>) -> Result<T, E> {
+        if let Err(e) = &result {
+            self.set_error(e.to_string());
+        }
+        result
+    }
+
+    /// Trace and span id, when recording.
+    pub fn ids(&self) -> Option<TraceIds> {
+        let depth = self.depth?;
+        TRACE.with(|t| {
+            let trace = t.borrow();
+            let trace = trace.as_ref()?;
+            trace.open.get(depth).map(|span| TraceIds {
+                trace_id: trace.trace_id.clone(),
+                span_id: span.span_id.clone(),
+            })
+        })
+    }
+
+    fn with(&self, f: impl FnOnce(&mut SpanData)) {
+        let Some(depth) = self.depth else {
+            return;
+        };
+        TRACE.with(|t| {
+            if let Some(span) = t
+                .borrow_mut()
+                .as_mut()
+                .and_then(|trace| trace.open.get_mut(depth))
+            {
+                f(span);
+            }
+        });
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some((operation, error)) = &self.event {
+            crate::events::finished(operation, error.borrow().as_deref());
+        }
+        let Some(depth) = self.depth else {
+            return;
+        };
+        let done = TRACE.with(|t| {
+            let mut slot = t.borrow_mut();
+            let trace = slot.as_mut()?;
+            let now = SystemTime::now();
+            while trace.open.len() > depth {
+                let Some(mut span) = trace.open.pop() else {
+                    break;
+                };
+                span.end = now;
+                trace.finished.push(span);
+            }
+            if trace.open.is_empty() {
+                slot.take()
+            } else {
+                None
+            }
+        });
+        if let Some(trace) = done {
+            export(&trace);
+        }
+    }
+}
+
+/// The collector base URL, when export is enabled.
+fn endpoint() -> Option<String> {
+    crate::settings::current()
+        .telemetry
+        .otlp_endpoint
+        .as_deref()
+        .map(|e| e.trim().trim_end_matches('/').to_string())
+        .filter(|e| !e.is_empty())
+}
+
+/// Whether spans are recorded. Builds without the `network` feature have
+/// no exporter and never record.
+pub fn is_enabled() -> bool {
+    cfg!(feature = "network") && endpoint().is_some()
+}
+
+/// Start a span named `name`, a child of the innermost running span on
+/// this thread or, without one, the root of a new trace.
+pub fn span(name: &str) -> Span {
+    if !is_enabled() {
+        return Span::inert();
+    }
+    let depth = TRACE.with(|t| {
+        let mut slot = t.borrow_mut();
+        let trace = slot.get_or_insert_with(|| {
+            let parent = std::env::var(TRACEPARENT_ENV)
+                .ok()
+                .and_then(|tp| parse_traceparent(&tp));
+            Trace {
+                trace_id: parent
+                    .as_ref()
+                    .map(|p| p.trace_id.clone())
+                    .unwrap_or_else(new_trace_id),
+                open: Vec::new(),
+                finished: Vec::new(),
+            }
+        });
+        let parent_span_id = match trace.open.last() {
+            Some(parent) => Some(parent.span_id.clone()),
+            None => std::env::var(TRACEPARENT_ENV)
+                .ok()
+                .and_then(|tp| parse_traceparent(&tp))
+                .filter(|p| p.trace_id == trace.trace_id)
+                .map(|p| p.span_id),
+        };
+        let now = SystemTime::now();
+        trace.open.push(SpanData {
+            span_id: new_span_id(),
+            parent_span_id,
+            name: name.to_string(),
+            start: now,
+            end: now,
+            attributes: Vec::new(),
+            error: None,
+        });
+        trace.open.len() - 1
+    });
+    Span {
+        depth: Some(depth),
+        event: None,
+        _thread: PhantomData,
+    }
+}
+
+/// Start the span of a user-visible operation. When it starts a trace,
+/// its ids are handed to `output` for the JSON result. The start and the
+/// finish of the operation are recorded in the [`crate::events`] log.
+pub fn operation(name: &str, output: &OutputManager) -> Span {
+    crate::events::emit(&format!("{name}.started"), serde_json::Value::Null);
+    let mut span = span(name);
+    span.event = Some((name.to_string(), RefCell::new(None)));
+    if span.depth == Some(0) {
+        if let Some(ids) = span.ids() {
+            output.trace(ids);
+        }
+    }
+    span
+}
+
+/// Parse a W3C `traceparent` (`00-<trace_id>-<span_id>-<flags>`).
+pub fn parse_traceparent(value: &str) -> Option<TraceIds> {
+    let mut parts = value.trim().split('-');
+    let _version = parts
+        .next()
+        .filter(|v| v.len() == 2 && v.bytes().all(|b| b.is_ascii_hexdigit()))?;
+    let trace_id = parts.next().filter(|t| is_hex_id(t, 32))?;
+    let span_id = parts.next().filter(|s| is_hex_id(s, 16))?;
+    Some(TraceIds {
+        trace_id: trace_id.to_string(),
+        span_id: span_id.to_string(),
+    })
+}
+
+/// Lowercase hex of `len` digits, not all zero (invalid in W3C context).
+fn is_hex_id(id: &str, len: usize) -> bool {
+    id.len() == len
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        && id.bytes().any(|b| b != b'0')
+}
+
+fn new_trace_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn new_span_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..16].to_string()
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn string_attribute(key: &str, value: &str) -> serde_json::Value {
+    serde_json::json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// The OTLP/HTTP JSON request body for a finished trace.
+fn otlp_body(trace: &Trace, service_name: &str) -> serde_json::Value {
+    let spans: Vec<serde_json::Value> = trace
+        .finished
+        .iter()
+        .map(|span| {
+            let status = match &span.error {
+                // STATUS_CODE_ERROR
+                Some(message) => serde_json::json!({ "code": 2, "message": message }),
+                // STATUS_CODE_OK
+                None => serde_json::json!({ "code": 1 }),
+            };
+            serde_json::json!({
+                "traceId": trace.trace_id,
+                "spanId": span.span_id,
+                "parentSpanId": span.parent_span_id.as_deref().unwrap_or(""),
+                "name": span.name,
+                // SPAN_KIND_INTERNAL
+                "kind": 1,
+                "startTimeUnixNano": unix_nanos(span.start),
+                "endTimeUnixNano": unix_nanos(span.end),
+                "attributes": span
+                    .attributes
+                    .iter()
+                    .map(|(k, v)| string_attribute(k, v))
+                    .collect::<Vec<_>>(),
+                "status": status,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    string_attribute("service.name", service_name),
+                    string_attribute("service.version", env!("CARGO_PKG_VERSION")),
+                ]
+            },
+            "scopeSpans": [{
+                "scope": { "name": "avocadoctl", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }]
+        }]
+    })
+}
+
+/// Post a finished trace to the collector. Errors are ignored.
+fn export(trace: &Trace) {
+    let Some(endpoint) = endpoint() else {
+        return;
+    };
+    let settings = crate::settings::current();
+    let body = otlp_body(trace, &settings.telemetry.service_name).to_string();
+    post(
+        &format!("{endpoint}/v1/traces"),
+        body,
+        Duration::from_millis(settings.telemetry.timeout_ms),
+    );
+}
+
+#[cfg(feature = "network")]
+fn post(url: &str, body: String, timeout: Duration) {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .build()
+        .into();
+    let _ = agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(body);
+}
+
+/// Unreachable: without the `network` feature no span is recorded.
+#[cfg(not(feature = "network"))]
+fn post(_url: &str, _body: String, _timeout: Duration) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_ids_and_traceparent() {
+        let ids = TraceIds {
+            trace_id: new_trace_id(),
+            span_id: new_span_id(),
+        };
+        assert!(is_hex_id(&ids.trace_id, 32));
+        assert!(is_hex_id(&ids.span_id, 16));
+        assert_eq!(TraceIds::parse(&ids.to_string()), Some(ids));
+        assert_eq!(TraceIds::parse("abc def"), None);
+
+        let parent =
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(parent.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parent.span_id, "00f067aa0ba902b7");
+        assert!(
+            parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_spans_nest_and_serialize_as_otlp() {
+        let _lock = crate::commands::test_env::ENV_VAR_MUTEX
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        assert!(span("disabled").ids().is_none());
+
+        // Nothing listens on port 9 (discard); the export fails quietly
+        std::env::set_var(ENDPOINT_ENV, "http://127.0.0.1:9");
+        std::env::set_var(TIMEOUT_ENV, "100");
+        let root = span("merge");
+        let root_ids = root.ids().unwrap();
+        {
+            let child = span("mounting");
+            child.set_attribute("avocado.extension", "app");
+            child.set_error("loop device busy");
+            let child_ids = child.ids().unwrap();
+            assert_eq!(child_ids.trace_id, root_ids.trace_id);
+            assert_ne!(child_ids.span_id, root_ids.span_id);
+        }
+        let body = TRACE.with(|t| {
+            let slot = t.borrow();
+            let trace = slot.as_ref().unwrap();
+            otlp_body(trace, "avocadoctl")
+        });
+        drop(root);
+        assert!(TRACE.with(|t| t.borrow().is_none()));
+        std::env::remove_var(ENDPOINT_ENV);
+        std::env::remove_var(TIMEOUT_ENV);
+
+        let spans = &body["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans.as_array().unwrap().len(), 1);
+        assert_eq!(spans[0]["name"], "mounting");
+        assert_eq!(spans[0]["traceId"], root_ids.trace_id.as_str());
+        assert_eq!(spans[0]["parentSpanId"], root_ids.span_id.as_str());
+        assert_eq!(spans[0]["status"]["code"], 2);
+        assert_eq!(spans[0]["attributes"][0]["value"]["stringValue"], "app");
+    }
+}
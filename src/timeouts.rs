@@ -0,0 +1,382 @@
+//! Time limits for the external commands avocadoctl runs.
+//!
+//! `[avocado.timeouts]` bounds systemd commands, hook commands (including
+//! depmod and modprobe), HITL NFS mounts and loop mounts, so one stuck
+//! command cannot stall a merge or refresh indefinitely.
+//! `AVOCADO_TIMEOUT_<KIND>` overrides one limit.
+//!
+//! A command still running at its limit is sent SIGTERM and, if it has not
+//! exited after [`GRACE_PERIOD`], SIGKILL. Callers report it as timed out,
+//! distinct from a command that failed.
+
+use crate::commands::ext::SystemdError;
+use crate::config::TimeoutSettings;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a command has to exit after SIGTERM before it is killed.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The class of command a limit applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    SystemdCmd,
+    HookCmd,
+    NfsMount,
+    LoopMount,
+}
+
+impl TimeoutKind {
+    /// Key in `[avocado.timeouts]`.
+    pub fn setting(self) -> &'static str {
+        match self {
+            TimeoutKind::SystemdCmd => "systemd_cmd",
+            TimeoutKind::HookCmd => "hook_cmd",
+            TimeoutKind::NfsMount => "nfs_mount",
+            TimeoutKind::LoopMount => "loop_mount",
+        }
+    }
+
+    /// Environment variable overriding the limit.
+    pub fn env(self) -> &'static str {
+        match self {
+            TimeoutKind::SystemdCmd => "AVOCADO_TIMEOUT_SYSTEMD_CMD",
+            TimeoutKind::HookCmd => "AVOCADO_TIMEOUT_HOOK_CMD",
+            TimeoutKind::NfsMount => "AVOCADO_TIMEOUT_NFS_MOUNT",
+            TimeoutKind::LoopMount => "AVOCADO_TIMEOUT_LOOP_MOUNT",
+        }
+    }
+
+    /// The limit in `settings`, in seconds.
+    pub fn configured(self, settings: &TimeoutSettings) -> u64 {
+        match self {
+            TimeoutKind::SystemdCmd => settings.systemd_cmd,
+            TimeoutKind::HookCmd => settings.hook_cmd,
+            TimeoutKind::NfsMount => settings.nfs_mount,
+            TimeoutKind::LoopMount => settings.loop_mount,
+        }
+    }
+}
+
+/// Limit for `kind`, or `None` when disabled.
+pub fn limit(kind: TimeoutKind) -> Option<Duration> {
+    let seconds = kind.configured(&crate::settings::current().timeouts);
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Why a command produced no output.
+#[derive(Debug)]
+pub enum RunError {
+    /// The command could not be started
+    Spawn(io::Error),
+    /// The command was stopped at its limit
+    TimedOut { kind: TimeoutKind, limit: Duration },
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Spawn(e) => write!(f, "{e}"),
+            RunError::TimedOut { limit, .. } => {
+                write!(f, "timed out after {}s", limit.as_secs())
+            }
+        }
+    }
+}
+
+impl RunError {
+    /// The matching [`SystemdError`] for `command`.
+    pub fn into_systemd_error(self, command: impl Into<String>) -> SystemdError {
+        match self {
+            RunError::Spawn(source) => SystemdError::CommandFailed {
+                command: command.into(),
+                source,
+            },
+            RunError::TimedOut { kind, limit } => SystemdError::CommandTimedOut {
+                command: command.into(),
+                setting: kind.setting(),
+                seconds: limit.as_secs(),
+            },
+        }
+    }
+}
+
+/// The pipe a line of command output was written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Receives each line of command output as it is written.
+pub type LineSink<'a> = &'a dyn Fn(Stream, &str);
+
+/// Run `cmd` to completion like [`Command::output`], stopping it once the
+/// `kind` limit passes. Stdout and stderr are always captured.
+/// During a merge phase with phase units enabled, `cmd` runs in its own
+/// transient unit (see [`crate::phases`]).
+pub fn output(cmd: &mut Command, kind: TimeoutKind) -> Result<Output, RunError> {
+    run(cmd, kind, None, None)
+}
+
+/// Like [`output`], also handing each line to `on_line` as the command
+/// writes it, so progress shows while the command runs and what it wrote
+/// before being stopped is not lost.
+pub fn output_streaming(
+    cmd: &mut Command,
+    kind: TimeoutKind,
+    on_line: LineSink,
+) -> Result<Output, RunError> {
+    run(cmd, kind, None, Some(on_line))
+}
+
+/// Like [`output_streaming`], writing `input` to the command's stdin and
+/// closing it, for data too large or too private for an argument or an
+/// environment variable.
+pub fn output_streaming_with_input(
+    cmd: &mut Command,
+    kind: TimeoutKind,
+    input: &[u8],
+    on_line: LineSink,
+) -> Result<Output, RunError> {
+    run(cmd, kind, Some(input), Some(on_line))
+}
+
+fn run(
+    cmd: &mut Command,
+    kind: TimeoutKind,
+    input: Option<&[u8]>,
+    on_line: Option<LineSink>,
+) -> Result<Output, RunError> {
+    let limit = limit(kind);
+    if let Some(mut unit) = crate::phases::unit_command(cmd, limit) {
+        return output_within(&mut unit, kind, limit, input, on_line);
+    }
+    output_within(cmd, kind, limit, input, on_line)
+}
+
+fn output_within(
+    cmd: &mut Command,
+    kind: TimeoutKind,
+    limit: Option<Duration>,
+    input: Option<&[u8]>,
+    on_line: Option<LineSink>,
+) -> Result<Output, RunError> {
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    if input.is_some() {
+        cmd.stdin(Stdio::piped());
+    } else if limit.is_none() && on_line.is_none() {
+        return cmd.output().map_err(RunError::Spawn);
+    }
+
+    let mut child = cmd.spawn().map_err(RunError::Spawn)?;
+    // Written from its own thread, so a command that reads its input late
+    // cannot hold up the wait below; one that exits without reading it
+    // fails the write, which is ignored.
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        let input = input.to_vec();
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+    // Drain the pipes while waiting so a chatty command cannot block on a
+    // full pipe and be mistaken for a stuck one. Lines are passed back
+    // here, so `on_line` runs on the calling thread.
+    let (tx, lines) = mpsc::channel();
+    let stream_tx = on_line.is_some().then_some(tx);
+    let stdout = drain(child.stdout.take(), Stream::Stdout, stream_tx.clone());
+    let stderr = drain(child.stderr.take(), Stream::Stderr, stream_tx);
+    let forward = || {
+        if let Some(on_line) = on_line {
+            for (stream, line) in lines.try_iter() {
+                on_line(stream, &line);
+            }
+        }
+    };
+
+    let deadline = limit.map(|limit| Instant::now() + limit);
+    loop {
+        forward();
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                let output = Output {
+                    status,
+                    stdout: stdout.join().unwrap_or_default(),
+                    stderr: stderr.join().unwrap_or_default(),
+                };
+                forward();
+                return Ok(output);
+            }
+            Ok(None) if deadline.is_none_or(|d| Instant::now() < d) => thread::sleep(POLL_INTERVAL),
+            Ok(None) => break,
+            Err(e) => return Err(RunError::Spawn(e)),
+        }
+    }
+
+    // The readers are left behind: a descendant of the stopped command may
+    // still hold the pipes open
+    terminate(&mut child);
+    forward();
+    Err(RunError::TimedOut {
+        kind,
+        limit: limit.unwrap_or_default(),
+    })
+}
+
+fn drain(
+    pipe: Option<impl Read + Send + 'static>,
+    stream: Stream,
+    lines: Option<mpsc::Sender<(Stream, String)>>,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let Some(pipe) = pipe else {
+            return buf;
+        };
+        let mut reader = BufReader::new(pipe);
+        loop {
+            let start = buf.len();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if let Some(lines) = &lines {
+                        let line = String::from_utf8_lossy(&buf[start..]);
+                        let _ =
+                            lines.send((stream, line.trim_end_matches(['\n', '\r']).to_string()));
+                    }
+                }
+            }
+        }
+        buf
+    })
+}
+
+/// SIGTERM, then SIGKILL once the grace period has passed.
+fn terminate(child: &mut Child) {
+    let _ = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    let deadline = Instant::now() + GRACE_PERIOD;
+    while Instant::now() < deadline {
+        if let Ok(Some(_)) = child.try_wait() {
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_within_limit() {
+        let kind = TimeoutKind::HookCmd;
+        let output = output_within(
+            Command::new("echo").arg("hi"),
+            kind,
+            Some(Duration::from_secs(5)),
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hi\n");
+
+        let started = Instant::now();
+        let err = output_within(
+            Command::new("sleep").arg("30"),
+            kind,
+            Some(Duration::from_millis(100)),
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, RunError::TimedOut { .. }));
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        assert!(matches!(
+            output_within(
+                &mut Command::new("/nonexistent/tool"),
+                kind,
+                None,
+                None,
+                None
+            ),
+            Err(RunError::Spawn(_))
+        ));
+    }
+
+    #[test]
+    fn test_output_within_writes_input() {
+        let output = output_within(
+            &mut Command::new("cat"),
+            TimeoutKind::HookCmd,
+            Some(Duration::from_secs(5)),
+            Some(b"{\"added\":[]}"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(output.stdout, b"{\"added\":[]}");
+
+        // A command that does not read its input still completes
+        let output = output_within(
+            &mut Command::new("true"),
+            TimeoutKind::HookCmd,
+            None,
+            Some(&[b'x'; 1 << 20]),
+            None,
+        )
+        .unwrap();
+        assert!(output.status.success());
+    }
+
+    #[test]
+    fn test_output_streaming_passes_lines() {
+        let seen = std::cell::RefCell::new(Vec::new());
+        let on_line =
+            |stream: Stream, line: &str| seen.borrow_mut().push((stream, line.to_string()));
+        let output = output_within(
+            Command::new("sh").args(["-c", "echo one; echo two >&2; printf three"]),
+            TimeoutKind::HookCmd,
+            None,
+            None,
+            Some(&on_line),
+        )
+        .unwrap();
+        assert_eq!(output.stdout, b"one\nthree");
+        let seen = seen.into_inner();
+        let lines = |stream| -> Vec<&str> {
+            seen.iter()
+                .filter(|(s, _)| *s == stream)
+                .map(|(_, line)| line.as_str())
+                .collect()
+        };
+        assert_eq!(lines(Stream::Stdout), vec!["one", "three"]);
+        assert_eq!(lines(Stream::Stderr), vec!["two"]);
+
+        // Lines written before a command is stopped are still passed on
+        let seen = std::cell::RefCell::new(Vec::new());
+        let on_line = |_: Stream, line: &str| seen.borrow_mut().push(line.to_string());
+        let err = output_within(
+            Command::new("sh").args(["-c", "echo started; exec sleep 30"]),
+            TimeoutKind::HookCmd,
+            Some(Duration::from_millis(300)),
+            None,
+            Some(&on_line),
+        )
+        .unwrap_err();
+        assert!(matches!(err, RunError::TimedOut { .. }));
+        assert_eq!(seen.into_inner(), vec!["started"]);
+    }
+}
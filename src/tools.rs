@@ -0,0 +1,125 @@
+//! Resolution of the external tools avocadoctl runs.
+//!
+//! `[avocado.tools]` overrides the path or name of systemd-sysext,
+//! systemd-confext, systemd-dissect, depmod and modprobe, for busybox
+//! variants, vendor wrappers or distros that keep the systemd tools in
+//! `/usr/lib/systemd`. `AVOCADO_TOOL_<NAME>` overrides the entry for one
+//! tool.
+//!
+//! Without an override a tool is run by name when it is on PATH, from
+//! `/usr/lib/systemd` when only that exists, and as `mock-<name>` in test
+//! mode. An override is used as-is, in test mode too.
+
+use crate::config::ToolSettings;
+use std::path::Path;
+
+/// Directory some distros install the systemd tools to instead of PATH.
+pub const SYSTEMD_LIBEXEC_DIR: &str = "/usr/lib/systemd";
+
+/// Configurable tools with the environment variable carrying each override.
+const TOOLS: &[(&str, &str)] = &[
+    ("systemd-sysext", "AVOCADO_TOOL_SYSTEMD_SYSEXT"),
+    ("systemd-confext", "AVOCADO_TOOL_SYSTEMD_CONFEXT"),
+    ("systemd-dissect", "AVOCADO_TOOL_SYSTEMD_DISSECT"),
+    ("depmod", "AVOCADO_TOOL_DEPMOD"),
+    ("modprobe", "AVOCADO_TOOL_MODPROBE"),
+];
+
+/// Environment variable overriding `tool`, if it is configurable.
+pub fn override_env(tool: &str) -> Option<&'static str> {
+    TOOLS
+        .iter()
+        .find(|(name, _)| *name == tool)
+        .map(|(_, var)| *var)
+}
+
+/// The override for `tool` in `tools`, if any.
+fn configured<'a>(tools: &'a ToolSettings, tool: &str) -> Option<&'a str> {
+    let value = match tool {
+        "systemd-sysext" => &tools.systemd_sysext,
+        "systemd-confext" => &tools.systemd_confext,
+        "systemd-dissect" => &tools.systemd_dissect,
+        "depmod" => &tools.depmod,
+        "modprobe" => &tools.modprobe,
+        _ => return None,
+    };
+    value.as_deref().filter(|v| !v.is_empty())
+}
+
+fn on_path(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(name).is_file()))
+}
+
+/// Program to run for `tool`. Tools without an override entry resolve to
+/// their own name (or `mock-<name>` in test mode).
+pub fn program(tool: &str) -> String {
+    if let Some(value) = configured(&crate::settings::current().tools, tool) {
+        return value.to_string();
+    }
+    if std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        return if tool.starts_with("mock-") {
+            tool.to_string()
+        } else {
+            format!("mock-{tool}")
+        };
+    }
+    if tool.starts_with("systemd-") && !on_path(tool) {
+        let libexec = Path::new(SYSTEMD_LIBEXEC_DIR).join(tool);
+        if libexec.is_file() {
+            return libexec.to_string_lossy().to_string();
+        }
+    }
+    tool.to_string()
+}
+
+/// Where [`program`] finds `tool`: its path, or `None` when it is neither an
+/// existing file nor on PATH.
+pub fn locate(tool: &str) -> Option<String> {
+    let program = program(tool);
+    if program.contains('/') {
+        return Path::new(&program).is_file().then_some(program);
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(&program))
+        .find(|candidate| candidate.is_file())
+        .map(|candidate| candidate.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_env::ENV_VAR_MUTEX;
+
+    #[test]
+    fn test_program_prefers_override() {
+        let _guard = ENV_VAR_MUTEX.lock().unwrap_or_else(|e| e.into_inner());
+        let original_test_mode = std::env::var("AVOCADO_TEST_MODE").ok();
+        std::env::set_var("AVOCADO_TEST_MODE", "1");
+        std::env::remove_var("AVOCADO_TOOL_DEPMOD");
+        assert_eq!(program("depmod"), "mock-depmod");
+        assert_eq!(program("mock-depmod"), "mock-depmod");
+
+        std::env::set_var("AVOCADO_TOOL_DEPMOD", "/bin/busybox-depmod");
+        assert_eq!(program("depmod"), "/bin/busybox-depmod");
+        std::env::remove_var("AVOCADO_TOOL_DEPMOD");
+        if original_test_mode.is_none() {
+            std::env::remove_var("AVOCADO_TEST_MODE");
+        }
+    }
+
+    #[test]
+    fn test_every_configurable_tool_has_a_variable() {
+        for tool in [
+            "systemd-sysext",
+            "systemd-confext",
+            "systemd-dissect",
+            "depmod",
+            "modprobe",
+        ] {
+            assert!(override_env(tool).is_some(), "{tool}");
+        }
+        assert!(override_env("systemctl").is_none());
+    }
+}
@@ -0,0 +1,305 @@
+//! Multi-extension changes applied as one transaction by `avocadoctl ext apply`.
+//!
+//! A manifest lists extensions to enable, disable and update for one OS
+//! release. The net change against the current os-releases links is
+//! computed up front (entries already in the requested state are skipped),
+//! every link change is made, and the extensions are refreshed once. A
+//! fleet update touching five extensions therefore costs one
+//! unmerge/merge cycle instead of five. If the refresh fails the previous
+//! links are restored and refreshed again.
+//!
+//! ```toml
+//! os_release = "1.4.0"          # optional, defaults to the running VERSION_ID
+//! enable = ["sensor-*"]         # names, glob patterns or absolute paths
+//! disable = ["debug-tools"]
+//! update = ["camera-2.1.0"]     # enable, replacing other enabled camera-* versions
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Requested changes, read from a TOML or JSON manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransactionManifest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_release: Option<String>,
+    #[serde(default)]
+    pub enable: Vec<String>,
+    #[serde(default)]
+    pub disable: Vec<String>,
+    #[serde(default)]
+    pub update: Vec<String>,
+    /// Enable extensions whose release data does not match the OS release.
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl TransactionManifest {
+    /// Load a manifest; `.json` files are JSON, anything else TOML.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read manifest '{}': {e}", path.display()))?;
+        let parsed = if path.extension().is_some_and(|e| e == "json") {
+            serde_json::from_str(&content).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&content).map_err(|e| e.to_string())
+        };
+        parsed.map_err(|e| format!("Invalid manifest '{}': {e}", path.display()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.enable.is_empty() && self.disable.is_empty() && self.update.is_empty()
+    }
+}
+
+/// An enable or update request resolved to the link it needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkTarget {
+    /// File name of the link in the os-releases directory.
+    pub file_name: String,
+    /// Directory, image or archive the link points to.
+    pub source: String,
+    /// Replace other enabled versions of the same extension.
+    pub update: bool,
+}
+
+/// One change to the os-releases directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum TransactionStep {
+    Unlink { file_name: String },
+    Link { file_name: String, source: String },
+}
+
+impl fmt::Display for TransactionStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unlink { file_name } => write!(f, "disable {file_name}"),
+            Self::Link { file_name, source } => write!(f, "enable {file_name} -> {source}"),
+        }
+    }
+}
+
+/// Net change of a transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TransactionPlan {
+    /// Unlinks first, then links, each in file name order.
+    pub steps: Vec<TransactionStep>,
+    /// Names asked to be disabled that are not enabled.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub not_enabled: Vec<String>,
+}
+
+impl TransactionPlan {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn linked(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|s| matches!(s, TransactionStep::Link { .. }))
+            .count()
+    }
+
+    pub fn unlinked(&self) -> usize {
+        self.steps.len() - self.linked()
+    }
+}
+
+/// Compute the net change from the `current` links (file name to target)
+/// to the requested state. `name_of` maps a link file name to its
+/// extension name and `base_of` an extension name to its unversioned name.
+pub fn plan_transaction(
+    current: &BTreeMap<String, String>,
+    targets: &[LinkTarget],
+    disable: &[String],
+    name_of: impl Fn(&str) -> String,
+    base_of: impl Fn(&str) -> String,
+) -> Result<TransactionPlan, String> {
+    let mut wanted: BTreeMap<&str, &LinkTarget> = BTreeMap::new();
+    for target in targets {
+        if disable.contains(&name_of(&target.file_name)) {
+            return Err(format!(
+                "'{}' is both enabled and disabled",
+                name_of(&target.file_name)
+            ));
+        }
+        if let Some(other) = wanted.insert(&target.file_name, target) {
+            if other.source != target.source {
+                return Err(format!(
+                    "'{}' is requested from both {} and {}",
+                    target.file_name, other.source, target.source
+                ));
+            }
+        }
+    }
+
+    let replaced: BTreeSet<String> = targets
+        .iter()
+        .filter(|t| t.update)
+        .map(|t| base_of(&name_of(&t.file_name)))
+        .collect();
+
+    let mut steps = Vec::new();
+    for file_name in current.keys() {
+        let name = name_of(file_name);
+        let superseded =
+            replaced.contains(&base_of(&name)) && !wanted.contains_key(file_name.as_str());
+        if disable.contains(&name) || superseded {
+            steps.push(TransactionStep::Unlink {
+                file_name: file_name.clone(),
+            });
+        }
+    }
+    for (file_name, target) in &wanted {
+        if current.get(*file_name) != Some(&target.source) {
+            steps.push(TransactionStep::Link {
+                file_name: file_name.to_string(),
+                source: target.source.clone(),
+            });
+        }
+    }
+
+    let enabled_names: BTreeSet<String> = current.keys().map(|f| name_of(f)).collect();
+    let not_enabled = disable
+        .iter()
+        .filter(|name| !enabled_names.contains(*name))
+        .cloned()
+        .collect();
+
+    Ok(TransactionPlan { steps, not_enabled })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_of(file_name: &str) -> String {
+        file_name.trim_end_matches(".raw").to_string()
+    }
+
+    fn base_of(name: &str) -> String {
+        name.rsplit_once('-')
+            .map(|(base, _)| base.to_string())
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    fn target(file_name: &str, update: bool) -> LinkTarget {
+        LinkTarget {
+            file_name: file_name.to_string(),
+            source: format!("/var/lib/avocado/extensions/{file_name}"),
+            update,
+        }
+    }
+
+    fn current(files: &[&str]) -> BTreeMap<String, String> {
+        files
+            .iter()
+            .map(|f| (f.to_string(), format!("/var/lib/avocado/extensions/{f}")))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_transaction_net_change() {
+        let current = current(&["camera-1.0.raw", "debug-1.0", "sensor-1.0.raw"]);
+        let plan = plan_transaction(
+            &current,
+            &[
+                target("camera-2.0.raw", true),
+                target("sensor-1.0.raw", false),
+                target("net-3.0", false),
+            ],
+            &["debug-1.0".to_string(), "absent".to_string()],
+            name_of,
+            base_of,
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.steps,
+            vec![
+                TransactionStep::Unlink {
+                    file_name: "camera-1.0.raw".to_string()
+                },
+                TransactionStep::Unlink {
+                    file_name: "debug-1.0".to_string()
+                },
+                TransactionStep::Link {
+                    file_name: "camera-2.0.raw".to_string(),
+                    source: "/var/lib/avocado/extensions/camera-2.0.raw".to_string(),
+                },
+                TransactionStep::Link {
+                    file_name: "net-3.0".to_string(),
+                    source: "/var/lib/avocado/extensions/net-3.0".to_string(),
+                },
+            ]
+        );
+        assert_eq!(plan.linked(), 2);
+        assert_eq!(plan.unlinked(), 2);
+        assert_eq!(plan.not_enabled, vec!["absent".to_string()]);
+    }
+
+    #[test]
+    fn test_plan_transaction_noop_and_conflicts() {
+        let current = current(&["camera-1.0.raw"]);
+        let plan = plan_transaction(
+            &current,
+            &[target("camera-1.0.raw", true)],
+            &[],
+            name_of,
+            base_of,
+        )
+        .unwrap();
+        assert!(plan.is_empty());
+
+        let err = plan_transaction(
+            &current,
+            &[target("camera-2.0.raw", false)],
+            &["camera-2.0".to_string()],
+            name_of,
+            base_of,
+        )
+        .unwrap_err();
+        assert!(err.contains("both enabled and disabled"));
+
+        let mut elsewhere = target("camera-2.0.raw", false);
+        elsewhere.source = "/data/camera-2.0.raw".to_string();
+        assert!(plan_transaction(
+            &current,
+            &[target("camera-2.0.raw", false), elsewhere],
+            &[],
+            name_of,
+            base_of,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_manifest_load_toml_and_json() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let toml_path = dir.path().join("tx.toml");
+        fs::write(
+            &toml_path,
+            "os_release = \"1.4.0\"\nenable = [\"a\"]\nupdate = [\"b-2.0\"]\n",
+        )
+        .unwrap();
+        let manifest = TransactionManifest::load(&toml_path).unwrap();
+        assert_eq!(manifest.os_release.as_deref(), Some("1.4.0"));
+        assert_eq!(manifest.update, vec!["b-2.0".to_string()]);
+        assert!(manifest.disable.is_empty());
+
+        let json_path = dir.path().join("tx.json");
+        fs::write(&json_path, r#"{"disable": ["c"], "force": true}"#).unwrap();
+        let manifest = TransactionManifest::load(&json_path).unwrap();
+        assert_eq!(manifest.disable, vec!["c".to_string()]);
+        assert!(manifest.force);
+
+        fs::write(&json_path, r#"{"enabel": ["c"]}"#).unwrap();
+        assert!(TransactionManifest::load(&json_path).is_err());
+    }
+}
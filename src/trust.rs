@@ -0,0 +1,462 @@
+//! Trust store of extension signing keys.
+//!
+//! Extension images can carry a detached signature: next to `app-1.0.raw`
+//! lies `app-1.0.raw.sig`, a JSON envelope of ed25519 signatures over the
+//! image's SHA256 (lowercase hex), with the same key ids as `ext audit`
+//! reports and the TUF metadata:
+//!
+//! ```json
+//! {"sha256": "9f86d0...", "signatures": [{"keyid": "3f2a...", "sig": "a1b2..."}]}
+//! ```
+//!
+//! The trust store (`<base_dir>/trust/keys.json`) holds the public keys such
+//! signatures are checked against. Each key has an optional validity window,
+//! so several keys can be valid at once while one replaces another:
+//! [`TrustStore::rotate`] installs a new key and limits the validity of the
+//! one it retires, and [`verify_image`] tells which images the store accepts
+//! at a given time.
+//!
+//! Once the store holds a key, [`check_image`] keeps images it does not
+//! accept from being installed (`provision`) or merged. An empty store
+//! accepts every image.
+
+use crate::audit::{key_id, ReportSignature};
+use crate::clock::SECONDS_PER_DAY;
+use crate::hash::{hex_decode, hex_encode, sha256_file, Checksummer};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const TRUST_DIR_NAME: &str = "trust";
+const KEYS_FILENAME: &str = "keys.json";
+
+/// Suffix of the detached signature next to an extension image.
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+#[derive(Error, Debug)]
+pub enum TrustError {
+    #[error("Failed to read trust store {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse trust store {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+
+    #[error("Failed to write trust store {path}: {source}")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid public key: {0}")]
+    InvalidKey(String),
+
+    #[error("Key {0} is already in the trust store")]
+    DuplicateKey(String),
+
+    #[error("No key in the trust store matches '{0}'")]
+    UnknownKey(String),
+
+    #[error("Key id prefix '{0}' matches several keys")]
+    AmbiguousKey(String),
+}
+
+/// A public key trusted to sign extension images.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningKey {
+    pub keyid: String,
+    /// Hex-encoded ed25519 public key
+    pub public: String,
+    /// Start of the validity window, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<u64>,
+    /// End of the validity window (exclusive), in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<u64>,
+}
+
+impl SigningKey {
+    /// Whether signatures by this key are accepted at `at`.
+    pub fn is_valid_at(&self, at: u64) -> bool {
+        self.not_before.is_none_or(|t| t <= at) && self.not_after.is_none_or(|t| at < t)
+    }
+
+    /// The key's state at `at`: `valid`, `expired` or `pending`.
+    pub fn state_at(&self, at: u64) -> &'static str {
+        if self.not_after.is_some_and(|t| t <= at) {
+            "expired"
+        } else if self.not_before.is_some_and(|t| at < t) {
+            "pending"
+        } else {
+            "valid"
+        }
+    }
+
+    fn public_key(&self) -> Option<ed25519_compact::PublicKey> {
+        ed25519_compact::PublicKey::from_slice(&hex_decode(&self.public)?).ok()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustStore {
+    #[serde(default)]
+    pub keys: Vec<SigningKey>,
+}
+
+impl TrustStore {
+    /// Path of the trust store below the avocado base directory.
+    pub fn path(base_dir: &Path) -> PathBuf {
+        base_dir.join(TRUST_DIR_NAME).join(KEYS_FILENAME)
+    }
+
+    /// Load the trust store; a missing store is empty.
+    pub fn load(base_dir: &Path) -> Result<Self, TrustError> {
+        let path = Self::path(base_dir);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(source) => return Err(TrustError::Read { path, source }),
+        };
+        serde_json::from_str(&content).map_err(|e| TrustError::Parse {
+            path,
+            message: e.to_string(),
+        })
+    }
+
+    /// Atomically persist the trust store (write `keys.json.tmp`, rename).
+    pub fn save(&self, base_dir: &Path) -> Result<(), TrustError> {
+        let path = Self::path(base_dir);
+        let write_err = |source| TrustError::Write {
+            path: path.clone(),
+            source,
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(write_err)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(self).unwrap_or_else(|_| "{}".to_string());
+        fs::write(&tmp, json + "\n").map_err(write_err)?;
+        fs::rename(&tmp, &path).map_err(write_err)
+    }
+
+    /// The key whose id starts with `prefix`.
+    pub fn find(&self, prefix: &str) -> Result<&SigningKey, TrustError> {
+        let mut matches = self.keys.iter().filter(|k| k.keyid.starts_with(prefix));
+        match (matches.next(), matches.next()) {
+            (Some(key), None) if !prefix.is_empty() => Ok(key),
+            (Some(_), _) => Err(TrustError::AmbiguousKey(prefix.to_string())),
+            (None, _) => Err(TrustError::UnknownKey(prefix.to_string())),
+        }
+    }
+
+    /// Install `public` as a key valid from `now`, for `valid_days` if
+    /// given, and end the validity of the key matching `retire` (a key id
+    /// prefix) `overlap_days` after `now`. Returns the new and the retired
+    /// key. The store is unchanged on error.
+    pub fn rotate(
+        &mut self,
+        public: &ed25519_compact::PublicKey,
+        valid_days: Option<u64>,
+        retire: Option<&str>,
+        overlap_days: u64,
+        now: u64,
+    ) -> Result<(SigningKey, Option<SigningKey>), TrustError> {
+        let keyid = key_id(public);
+        if self.keys.iter().any(|k| k.keyid == keyid) {
+            return Err(TrustError::DuplicateKey(keyid));
+        }
+        let retired_id = retire
+            .map(|prefix| self.find(prefix).map(|k| k.keyid.clone()))
+            .transpose()?;
+
+        let added = SigningKey {
+            keyid,
+            public: hex_encode(public.as_ref()),
+            not_before: Some(now),
+            not_after: valid_days.map(|days| now + days * SECONDS_PER_DAY),
+        };
+        self.keys.push(added.clone());
+
+        let retired = retired_id.and_then(|id| {
+            let key = self.keys.iter_mut().find(|k| k.keyid == id)?;
+            let end = now + overlap_days * SECONDS_PER_DAY;
+            key.not_after = Some(key.not_after.map_or(end, |t| t.min(end)));
+            Some(key.clone())
+        });
+        Ok((added, retired))
+    }
+}
+
+/// Parse a hex-encoded ed25519 public key.
+pub fn parse_public_key(content: &str) -> Result<ed25519_compact::PublicKey, TrustError> {
+    let bytes = hex_decode(content.trim())
+        .ok_or_else(|| TrustError::InvalidKey("expected a hex-encoded ed25519 key".into()))?;
+    ed25519_compact::PublicKey::from_slice(&bytes)
+        .map_err(|_| TrustError::InvalidKey(format!("expected 32 bytes, got {}", bytes.len())))
+}
+
+/// Detached signature of an extension image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageSignature {
+    pub sha256: String,
+    pub signatures: Vec<ReportSignature>,
+}
+
+/// Path of the detached signature of `image`.
+pub fn signature_path(image: &Path) -> PathBuf {
+    let mut name = image.as_os_str().to_owned();
+    name.push(SIGNATURE_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Outcome of checking an image against the trust store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// Signed by the key with this id
+    Valid(String),
+    /// No signature file
+    Unsigned,
+    /// Signed, but not acceptably; the reason says why
+    Invalid(String),
+}
+
+/// Check the detached signature of `image` against the keys of `store`
+/// that are valid at `at`.
+pub fn verify_image(image: &Path, store: &TrustStore, at: u64) -> Verification {
+    let signature = match read_signature(image) {
+        Ok(signature) => signature,
+        Err(verification) => return verification,
+    };
+    match sha256_file(&Checksummer::default(), image) {
+        Ok(actual) if actual.eq_ignore_ascii_case(&signature.sha256) => {}
+        Ok(_) => return Verification::Invalid("image does not match its signature".into()),
+        Err(e) => return Verification::Invalid(format!("cannot hash image: {e}")),
+    }
+    verify_signers(&signature, store, at)
+}
+
+/// The detached signature of `image`, or why there is none to check.
+fn read_signature(image: &Path) -> Result<ImageSignature, Verification> {
+    let content = fs::read_to_string(signature_path(image)).map_err(|_| Verification::Unsigned)?;
+    serde_json::from_str(&content)
+        .map_err(|e| Verification::Invalid(format!("unreadable signature: {e}")))
+}
+
+/// Whether `signature` was made over its digest by a key of `store` valid
+/// at `at`. The digest is not compared with the image.
+fn verify_signers(signature: &ImageSignature, store: &TrustStore, at: u64) -> Verification {
+    let message = signature.sha256.to_ascii_lowercase();
+    let mut expired = false;
+    for sig in &signature.signatures {
+        let Some(key) = store.keys.iter().find(|k| k.keyid == sig.keyid) else {
+            continue;
+        };
+        let verified = key.public_key().is_some_and(|pk| {
+            hex_decode(&sig.sig)
+                .and_then(|b| ed25519_compact::Signature::from_slice(&b).ok())
+                .is_some_and(|s| pk.verify(message.as_bytes(), &s).is_ok())
+        });
+        if !verified {
+            continue;
+        }
+        if key.is_valid_at(at) {
+            return Verification::Valid(key.keyid.clone());
+        }
+        expired = true;
+    }
+    Verification::Invalid(if expired {
+        "signing key is outside its validity window".into()
+    } else {
+        "no signature by a trusted key".into()
+    })
+}
+
+/// Check `image` before it is installed or merged: any image passes while
+/// `store` is empty; otherwise it must be signed by a key valid at `at`.
+/// The error says why the image is refused.
+pub fn check_image(image: &Path, store: &TrustStore, at: u64) -> Result<(), String> {
+    if store.keys.is_empty() {
+        return Ok(());
+    }
+    refusal(verify_image(image, store, at))
+}
+
+/// [`check_image`] without hashing the image: the signature must be made by
+/// a key valid at `at`, but its digest is not compared with the image. Cheap
+/// enough for listings, which only report what a merge would refuse.
+pub fn precheck_image(image: &Path, store: &TrustStore, at: u64) -> Result<(), String> {
+    if store.keys.is_empty() {
+        return Ok(());
+    }
+    match read_signature(image) {
+        Ok(signature) => refusal(verify_signers(&signature, store, at)),
+        Err(verification) => refusal(verification),
+    }
+}
+
+fn refusal(verification: Verification) -> Result<(), String> {
+    match verification {
+        Verification::Valid(_) => Ok(()),
+        Verification::Unsigned => Err("it is not signed".to_string()),
+        Verification::Invalid(reason) => Err(reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn keypair(seed: u8) -> ed25519_compact::KeyPair {
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([seed; 32]))
+    }
+
+    fn sign(image: &Path, keys: &[&ed25519_compact::KeyPair]) {
+        let sha256 = sha256_file(&Checksummer::default(), image).unwrap();
+        let signature = ImageSignature {
+            signatures: keys
+                .iter()
+                .map(|kp| ReportSignature {
+                    keyid: key_id(&kp.pk),
+                    sig: hex_encode(kp.sk.sign(sha256.as_bytes(), None).as_ref()),
+                })
+                .collect(),
+            sha256,
+        };
+        fs::write(
+            signature_path(image),
+            serde_json::to_string(&signature).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_rotate_overlaps_old_and_new_key() {
+        let tmp = TempDir::new().unwrap();
+        let (old, new) = (keypair(1), keypair(2));
+        let mut store = TrustStore::default();
+        store.rotate(&old.pk, None, None, 0, 0).unwrap();
+        store.save(tmp.path()).unwrap();
+
+        let mut store = TrustStore::load(tmp.path()).unwrap();
+        let old_id = key_id(&old.pk);
+        let (added, retired) = store
+            .rotate(&new.pk, None, Some(&old_id[..8]), 30, 1000)
+            .unwrap();
+        assert_eq!(added.not_before, Some(1000));
+        let retired = retired.unwrap();
+        assert_eq!(retired.keyid, old_id);
+        assert_eq!(retired.not_after, Some(1000 + 30 * SECONDS_PER_DAY));
+
+        // Both keys are valid during the overlap; only the new one after it
+        let valid = |at| store.keys.iter().filter(|k| k.is_valid_at(at)).count();
+        assert_eq!(valid(1000), 2);
+        assert_eq!(valid(1000 + 30 * SECONDS_PER_DAY), 1);
+        assert_eq!(retired.state_at(1000 + 30 * SECONDS_PER_DAY), "expired");
+
+        assert!(matches!(
+            store.rotate(&new.pk, None, None, 0, 2000),
+            Err(TrustError::DuplicateKey(_))
+        ));
+        assert!(matches!(
+            store.rotate(&keypair(3).pk, None, Some("zz"), 0, 2000),
+            Err(TrustError::UnknownKey(_))
+        ));
+        assert_eq!(store.keys.len(), 2);
+    }
+
+    #[test]
+    fn test_verify_image_respects_validity_windows() {
+        let tmp = TempDir::new().unwrap();
+        let (old, new) = (keypair(1), keypair(2));
+        let mut store = TrustStore::default();
+        store.rotate(&old.pk, None, None, 0, 0).unwrap();
+        store
+            .rotate(&new.pk, None, Some(&key_id(&old.pk)), 1, 100)
+            .unwrap();
+        let after = 100 + SECONDS_PER_DAY;
+
+        let legacy = tmp.path().join("legacy-1.0.raw");
+        fs::write(&legacy, b"legacy").unwrap();
+        sign(&legacy, &[&old]);
+        assert_eq!(
+            verify_image(&legacy, &store, 100),
+            Verification::Valid(key_id(&old.pk))
+        );
+        assert_eq!(
+            verify_image(&legacy, &store, after),
+            Verification::Invalid("signing key is outside its validity window".into())
+        );
+
+        let resigned = tmp.path().join("app-1.0.raw");
+        fs::write(&resigned, b"app").unwrap();
+        sign(&resigned, &[&old, &new]);
+        assert_eq!(
+            verify_image(&resigned, &store, after),
+            Verification::Valid(key_id(&new.pk))
+        );
+
+        let unsigned = tmp.path().join("tools.raw");
+        fs::write(&unsigned, b"tools").unwrap();
+        assert_eq!(verify_image(&unsigned, &store, 100), Verification::Unsigned);
+
+        fs::write(&resigned, b"tampered").unwrap();
+        assert_eq!(
+            verify_image(&resigned, &store, 100),
+            Verification::Invalid("image does not match its signature".into())
+        );
+    }
+
+    #[test]
+    fn test_check_image_once_the_store_has_keys() {
+        let tmp = TempDir::new().unwrap();
+        let key = keypair(1);
+        let signed = tmp.path().join("app-1.0.raw");
+        fs::write(&signed, b"app").unwrap();
+        sign(&signed, &[&key]);
+        let unsigned = tmp.path().join("tools.raw");
+        fs::write(&unsigned, b"tools").unwrap();
+
+        let mut store = TrustStore::default();
+        assert_eq!(check_image(&unsigned, &store, 0), Ok(()));
+
+        store.rotate(&key.pk, Some(1), None, 0, 0).unwrap();
+        assert_eq!(check_image(&signed, &store, 0), Ok(()));
+        assert_eq!(
+            check_image(&unsigned, &store, 0),
+            Err("it is not signed".into())
+        );
+        assert_eq!(
+            check_image(&signed, &store, SECONDS_PER_DAY),
+            Err("signing key is outside its validity window".into())
+        );
+    }
+
+    #[test]
+    fn test_precheck_image_does_not_hash() {
+        let tmp = TempDir::new().unwrap();
+        let key = keypair(1);
+        let image = tmp.path().join("app-1.0.raw");
+        fs::write(&image, b"app").unwrap();
+        sign(&image, &[&key]);
+        let mut store = TrustStore::default();
+        store.rotate(&key.pk, None, None, 0, 0).unwrap();
+
+        // A changed image still passes the precheck, but not the full check
+        fs::write(&image, b"tampered").unwrap();
+        assert_eq!(precheck_image(&image, &store, 0), Ok(()));
+        assert_eq!(
+            check_image(&image, &store, 0),
+            Err("image does not match its signature".into())
+        );
+
+        fs::remove_file(signature_path(&image)).unwrap();
+        assert_eq!(
+            precheck_image(&image, &store, 0),
+            Err("it is not signed".into())
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Read-only commands for unprivileged callers.
+//!
+//! Monitoring agents run `ext list`, `ext status`, `ext info` and friends
+//! without root and without access to the daemon socket (group `avocado`).
+//! For them those commands run in-process in read-only mode, which never
+//! needs privileges:
+//!
+//! - images are not loop-mounted: an image that is already mounted is read
+//!   through its mount point, one that is not is described from its GPT
+//!   partition table (see [`crate::ddi`]) and its file name;
+//! - stale mounts are not cleaned up and archives are only read from the
+//!   unpack cache, which is never written or pruned.
+//!
+//! What could not be determined is reported as a warning instead of an error.
+
+use crate::output::OutputManager;
+use std::sync::Mutex;
+
+/// Environment variable forcing read-only mode.
+pub const READ_ONLY_ENV: &str = "AVOCADO_READ_ONLY";
+
+static NOTES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Whether read-only mode is active.
+pub fn is_read_only() -> bool {
+    crate::settings::current().read_only
+}
+
+/// Effective uid of this process.
+fn effective_uid() -> Option<u32> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let uids = status.lines().find_map(|l| l.strip_prefix("Uid:"))?;
+    uids.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Whether this process runs without root on the system's state. `--user`
+/// mode owns its state and test mode runs against mocks, so neither is.
+pub fn is_unprivileged() -> bool {
+    if crate::user_mode::is_user() || std::env::var("AVOCADO_TEST_MODE").is_ok() {
+        return false;
+    }
+    effective_uid().is_some_and(|uid| uid != 0)
+}
+
+/// Whether a command line only reads extension state: `status`, and
+/// `ext list`, `status`, `info`, `env`, `graph`, `diff` and `compare`.
+pub fn is_read_only_command(matches: &clap::ArgMatches) -> bool {
+    match matches.subcommand() {
+        Some(("status", _)) => true,
+        Some(("ext", ext)) => matches!(
+            ext.subcommand_name(),
+            Some("list" | "status" | "info" | "env" | "graph" | "diff" | "compare")
+        ),
+        _ => false,
+    }
+}
+
+/// Record something read-only mode could not determine. Repeated notes are
+/// kept once.
+pub fn note(message: impl Into<String>) {
+    let message = message.into();
+    let mut notes = NOTES.lock().unwrap_or_else(|e| e.into_inner());
+    if !notes.contains(&message) {
+        notes.push(message);
+    }
+}
+
+/// The notes recorded so far, clearing them.
+pub fn take_notes() -> Vec<String> {
+    std::mem::take(&mut *NOTES.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Report the notes recorded so far as warnings.
+pub fn print_notes(output: &OutputManager) {
+    for note in take_notes() {
+        output.warning(&note);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_commands() {
+        let cli = || {
+            clap::Command::new("avocadoctl")
+                .subcommand(clap::Command::new("status"))
+                .subcommand(clap::Command::new("merge"))
+                .subcommand(
+                    clap::Command::new("ext")
+                        .subcommand(clap::Command::new("list"))
+                        .subcommand(clap::Command::new("enable")),
+                )
+        };
+        let read_only = |args: &[&str]| is_read_only_command(&cli().get_matches_from(args));
+        assert!(read_only(&["avocadoctl", "status"]));
+        assert!(read_only(&["avocadoctl", "ext", "list"]));
+        assert!(!read_only(&["avocadoctl", "ext", "enable"]));
+        assert!(!read_only(&["avocadoctl", "merge"]));
+    }
+
+    #[test]
+    fn test_notes_are_deduplicated() {
+        note("app-1.0: not mounted");
+        note("app-1.0: not mounted");
+        let notes = take_notes();
+        assert_eq!(
+            notes
+                .iter()
+                .filter(|n| *n == "app-1.0: not mounted")
+                .count(),
+            1
+        );
+        assert!(!take_notes().contains(&"app-1.0: not mounted".to_string()));
+    }
+}
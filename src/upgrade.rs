@@ -0,0 +1,435 @@
+//! Policy-driven extension upgrades for `ext upgrade`.
+//!
+//! The update repository offers one runtime manifest. `ext upgrade`
+//! compares each extension of the active runtime with the version that
+//! manifest lists and moves it only as far as its `[avocado.upgrade]`
+//! policy allows: `pin` never, `patch` within the same major.minor,
+//! `minor` within the same major version, `latest` to any newer version.
+//! Versions follow semver precedence, and pre-releases are only taken
+//! when `prereleases` is set.
+//! The result is a new runtime: the active manifest with the upgraded
+//! extensions swapped in, so the OS bundle and every held extension stay
+//! exactly as they are.
+
+use crate::config::UpgradePolicy;
+use crate::manifest::{RuntimeManifest, AVOCADO_IMAGE_NAMESPACE};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// What `ext upgrade` does with one extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpgradeAction {
+    /// Moves to the offered version
+    Upgrade,
+    /// Already at the offered version
+    UpToDate,
+    /// A newer version is offered but the policy does not allow it
+    Held,
+    /// The offered version is older; extensions are never downgraded
+    Older,
+    /// The repository's runtime does not contain the extension
+    NotOffered,
+}
+
+impl UpgradeAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UpgradeAction::Upgrade => "upgrade",
+            UpgradeAction::UpToDate => "up-to-date",
+            UpgradeAction::Held => "held",
+            UpgradeAction::Older => "older",
+            UpgradeAction::NotOffered => "not-offered",
+        }
+    }
+}
+
+/// Planned upgrade of one extension.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpgradeDecision {
+    pub name: String,
+    pub current: String,
+    /// Version the repository offers, if it has the extension
+    pub available: Option<String>,
+    pub policy: UpgradePolicy,
+    pub action: UpgradeAction,
+}
+
+/// A version: numeric major.minor.patch and the dot-separated pre-release
+/// identifiers after `-` (or `~`). A leading `v` and build metadata after
+/// `+` are ignored; missing parts count as 0.
+#[derive(Debug, PartialEq, Eq)]
+struct Version {
+    core: [u64; 3],
+    pre: Vec<String>,
+}
+
+impl Version {
+    fn parse(version: &str) -> Option<Version> {
+        let version = version.trim_start_matches('v').split('+').next()?;
+        let (core, pre) = match version.split_once(['-', '~']) {
+            Some((core, pre)) => (core, pre.split('.').map(str::to_string).collect()),
+            None => (version, Vec::new()),
+        };
+        let mut parts = [0u64; 3];
+        for (i, part) in core.split('.').enumerate() {
+            if i >= 3 {
+                break;
+            }
+            parts[i] = part.parse().ok()?;
+        }
+        Some(Version { core: parts, pre })
+    }
+
+    fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+}
+
+/// Semver precedence of two pre-release identifiers: numeric ones compare
+/// numerically and sort below alphanumeric ones, which compare as text.
+fn compare_identifiers(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+impl Ord for Version {
+    /// Semver precedence: a pre-release sorts below its release.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.core.cmp(&other.core).then_with(|| {
+            match (self.is_prerelease(), other.is_prerelease()) {
+                (false, false) => Ordering::Equal,
+                (false, true) => Ordering::Greater,
+                (true, false) => Ordering::Less,
+                (true, true) => self
+                    .pre
+                    .iter()
+                    .zip(&other.pre)
+                    .map(|(a, b)| compare_identifiers(a, b))
+                    .find(|order| order.is_ne())
+                    .unwrap_or_else(|| self.pre.len().cmp(&other.pre.len())),
+            }
+        })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Whether `available` is an update for an extension at `current`:
+/// a higher version, or a different one when the two cannot be ordered
+/// (the same rule `latest` upgrades by). Pre-releases only count with
+/// `prereleases` set.
+pub fn is_newer(current: &str, available: &str, prereleases: bool) -> bool {
+    match (Version::parse(current), Version::parse(available)) {
+        (Some(cur), Some(new)) => new > cur && (prereleases || !new.is_prerelease()),
+        _ => current != available,
+    }
+}
+
+/// Decide what to do with an extension at `current` when `available` is
+/// offered. Pre-releases are held unless `prereleases` is set.
+fn decide(
+    policy: UpgradePolicy,
+    current: &str,
+    available: &str,
+    prereleases: bool,
+) -> UpgradeAction {
+    if current == available {
+        return UpgradeAction::UpToDate;
+    }
+    let (Some(cur), Some(new)) = (Version::parse(current), Version::parse(available)) else {
+        // Versions we cannot order only move under `latest`
+        return if policy == UpgradePolicy::Latest {
+            UpgradeAction::Upgrade
+        } else {
+            UpgradeAction::Held
+        };
+    };
+    match new.cmp(&cur) {
+        Ordering::Less => return UpgradeAction::Older,
+        Ordering::Equal => return UpgradeAction::UpToDate,
+        Ordering::Greater => {}
+    }
+    let allowed = (prereleases || !new.is_prerelease())
+        && match policy {
+            UpgradePolicy::Pin => false,
+            UpgradePolicy::Patch => new.core[..2] == cur.core[..2],
+            UpgradePolicy::Minor => new.core[0] == cur.core[0],
+            UpgradePolicy::Latest => true,
+        };
+    if allowed {
+        UpgradeAction::Upgrade
+    } else {
+        UpgradeAction::Held
+    }
+}
+
+/// Plan the upgrade of the active runtime's extensions to the versions
+/// `offered` lists. With `names` empty every extension is considered;
+/// otherwise only those, and unknown names are returned as the error.
+/// Pre-releases are only taken with `prereleases` set.
+pub fn plan(
+    active: &RuntimeManifest,
+    offered: &RuntimeManifest,
+    names: &[String],
+    policy: impl Fn(&str) -> UpgradePolicy,
+    prereleases: bool,
+) -> Result<Vec<UpgradeDecision>, Vec<String>> {
+    let unknown: Vec<String> = names
+        .iter()
+        .filter(|n| !active.extensions.iter().any(|e| &e.name == *n))
+        .cloned()
+        .collect();
+    if !unknown.is_empty() {
+        return Err(unknown);
+    }
+
+    Ok(active
+        .extensions
+        .iter()
+        .filter(|ext| names.is_empty() || names.contains(&ext.name))
+        .map(|ext| {
+            let policy = policy(&ext.name);
+            let available = offered
+                .extensions
+                .iter()
+                .find(|o| o.name == ext.name)
+                .map(|o| o.version.clone());
+            let action = match &available {
+                Some(version) => decide(policy, &ext.version, version, prereleases),
+                None => UpgradeAction::NotOffered,
+            };
+            UpgradeDecision {
+                name: ext.name.clone(),
+                current: ext.version.clone(),
+                available,
+                policy,
+                action,
+            }
+        })
+        .collect())
+}
+
+/// The runtime to activate for `decisions`: the active manifest with the
+/// upgraded extensions taken from `offered`. When that is exactly the
+/// offered runtime, the offered runtime itself is returned.
+pub fn compose(
+    active: &RuntimeManifest,
+    offered: &RuntimeManifest,
+    decisions: &[UpgradeDecision],
+) -> RuntimeManifest {
+    let mut manifest = active.clone();
+    for decision in decisions
+        .iter()
+        .filter(|d| d.action == UpgradeAction::Upgrade)
+    {
+        let Some(new) = offered.extensions.iter().find(|e| e.name == decision.name) else {
+            continue;
+        };
+        if let Some(ext) = manifest
+            .extensions
+            .iter_mut()
+            .find(|e| e.name == decision.name)
+        {
+            *ext = new.clone();
+        }
+    }
+
+    let key = |m: &RuntimeManifest| {
+        let mut exts: Vec<(String, String, Option<String>)> = m
+            .extensions
+            .iter()
+            .map(|e| (e.name.clone(), e.version.clone(), e.image_id.clone()))
+            .collect();
+        exts.sort();
+        (exts, m.os_bundle.as_ref().map(|b| b.image_id.clone()))
+    };
+    if key(&manifest) == key(offered) {
+        return offered.clone();
+    }
+
+    let mut seed = active.id.clone();
+    for ext in &manifest.extensions {
+        seed.push_str(&format!(
+            "\n{}={}:{}",
+            ext.name,
+            ext.version,
+            ext.image_id.as_deref().unwrap_or("")
+        ));
+    }
+    manifest.id = uuid::Uuid::new_v5(&AVOCADO_IMAGE_NAMESPACE, seed.as_bytes()).to_string();
+    manifest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{ManifestExtension, RuntimeInfo};
+
+    /// Order `available` relative to `current`
+    fn compare_versions(current: &str, available: &str) -> Option<Ordering> {
+        Some(Version::parse(available)?.cmp(&Version::parse(current)?))
+    }
+
+    fn manifest(id: &str, exts: &[(&str, &str)]) -> RuntimeManifest {
+        RuntimeManifest {
+            manifest_version: 1,
+            id: id.to_string(),
+            built_at: "2026-01-01T00:00:00Z".to_string(),
+            runtime: RuntimeInfo {
+                name: "dev".to_string(),
+                version: "1.0.0".to_string(),
+            },
+            extensions: exts
+                .iter()
+                .map(|(name, version)| ManifestExtension {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                    image_id: Some(format!("{name}-{version}")),
+                    image_type: None,
+                    sha256: None,
+                    sha512: None,
+                    blake3: None,
+                    enabled: true,
+                })
+                .collect(),
+            os_bundle: None,
+        }
+    }
+
+    #[test]
+    fn test_decide_by_policy() {
+        use UpgradeAction::*;
+        let decide = |policy, current, available| decide(policy, current, available, false);
+        assert_eq!(decide(UpgradePolicy::Pin, "1.2.3", "1.2.4"), Held);
+        assert_eq!(decide(UpgradePolicy::Patch, "1.2.3", "1.2.4"), Upgrade);
+        assert_eq!(decide(UpgradePolicy::Patch, "1.2.3", "1.3.0"), Held);
+        assert_eq!(decide(UpgradePolicy::Minor, "1.2.3", "1.3.0"), Upgrade);
+        assert_eq!(decide(UpgradePolicy::Minor, "1.2.3", "2.0.0"), Held);
+        assert_eq!(decide(UpgradePolicy::Latest, "1.2.3", "2.0.0"), Upgrade);
+        assert_eq!(decide(UpgradePolicy::Latest, "1.2.3", "1.2.3"), UpToDate);
+        assert_eq!(decide(UpgradePolicy::Latest, "1.2.3", "1.2.0"), Older);
+        assert_eq!(decide(UpgradePolicy::Minor, "nightly", "weekly"), Held);
+        assert_eq!(decide(UpgradePolicy::Latest, "nightly", "weekly"), Upgrade);
+    }
+
+    #[test]
+    fn test_decide_prereleases() {
+        use UpgradeAction::*;
+        // rc -> final: the release sorts above its pre-release
+        assert_eq!(
+            decide(UpgradePolicy::Patch, "1.2.0-rc1", "1.2.0", false),
+            Upgrade
+        );
+        // final -> rc: the pre-release of the same version is older
+        assert_eq!(
+            decide(UpgradePolicy::Latest, "1.2.0", "1.2.0-rc1", true),
+            Older
+        );
+        // Newer pre-releases only move with the opt-in
+        assert_eq!(
+            decide(UpgradePolicy::Patch, "v1.2", "1.2.1-rc1", false),
+            Held
+        );
+        assert_eq!(
+            decide(UpgradePolicy::Patch, "v1.2", "1.2.1-rc1", true),
+            Upgrade
+        );
+        assert_eq!(
+            decide(UpgradePolicy::Latest, "1.2.0", "2.0.0-beta.1", false),
+            Held
+        );
+        assert_eq!(
+            decide(UpgradePolicy::Minor, "1.2.0-rc1", "1.2.0-rc2", true),
+            Upgrade
+        );
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.2.3", "1.10.0"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("v1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("2.0.0", "1.9.9"), Some(Ordering::Less));
+        assert_eq!(compare_versions("nightly", "weekly"), None);
+        assert_eq!(
+            compare_versions("1.0.0-rc1", "1.0.0"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare_versions("1.0.0", "1.0.0-rc1"), Some(Ordering::Less));
+        assert_eq!(compare_versions("1.0.0+b1", "1.0.0"), Some(Ordering::Equal));
+        // Semver's own precedence example
+        let order = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+        ];
+        for pair in order.windows(2) {
+            assert_eq!(compare_versions(pair[0], pair[1]), Some(Ordering::Greater));
+        }
+
+        assert!(is_newer("1.0.0", "1.0.1", false));
+        assert!(!is_newer("1.0.1", "1.0.0", false));
+        assert!(!is_newer("nightly", "nightly", false));
+        assert!(is_newer("nightly", "weekly", false));
+        assert!(is_newer("1.0.0-rc1", "1.0.0", false));
+        assert!(!is_newer("1.0.0", "1.0.0-rc1", true));
+        assert!(!is_newer("1.0.0", "1.1.0-rc1", false));
+        assert!(is_newer("1.0.0", "1.1.0-rc1", true));
+    }
+
+    #[test]
+    fn test_plan_and_compose() {
+        let active = manifest("old", &[("app", "1.0.0"), ("base", "2.0.0")]);
+        let offered = manifest("new", &[("app", "1.1.0"), ("base", "3.0.0")]);
+
+        let decisions = plan(
+            &active,
+            &offered,
+            &[],
+            |name| {
+                if name == "base" {
+                    UpgradePolicy::Minor
+                } else {
+                    UpgradePolicy::Latest
+                }
+            },
+            false,
+        )
+        .unwrap();
+        assert_eq!(decisions[0].action, UpgradeAction::Upgrade);
+        assert_eq!(decisions[1].action, UpgradeAction::Held);
+
+        let composed = compose(&active, &offered, &decisions);
+        assert_ne!(composed.id, active.id);
+        assert_ne!(composed.id, offered.id);
+        assert_eq!(composed.extensions[0].version, "1.1.0");
+        assert_eq!(composed.extensions[1].version, "2.0.0");
+
+        let all = plan(&active, &offered, &[], |_| UpgradePolicy::Latest, false).unwrap();
+        assert_eq!(compose(&active, &offered, &all).id, "new");
+
+        assert_eq!(
+            plan(
+                &active,
+                &offered,
+                &["missing".to_string()],
+                |_| UpgradePolicy::Latest,
+                false
+            ),
+            Err(vec!["missing".to_string()])
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Unprivileged `--user` mode for development.
+//!
+//! Selected with the global `--user` flag. Configuration is read from
+//! `$XDG_CONFIG_HOME/avocado/avocadoctl.conf` and every system path avocadoctl
+//! manages (`/var/lib/avocado`, `/run/extensions`, `/run/confexts`,
+//! `/run/avocado`, ...) is relocated under a user-owned root prefix,
+//! `$XDG_STATE_HOME/avocado/root` unless `[avocado.user] root` says otherwise.
+//! systemd-sysext and systemd-confext are invoked with `--root=<prefix>`, so
+//! merges target the prefix instead of the host `/usr` and `/etc`. Commands run
+//! in-process rather than through the system daemon, and host-wide side effects
+//! (daemon-reload, depmod, modprobe) are skipped.
+//!
+//! Merging still needs permission to mount overlayfs on the prefix; run
+//! avocadoctl inside a user namespace (e.g. `unshare -rm`) where the kernel
+//! allows unprivileged overlay mounts.
+
+use crate::config::Config;
+use std::path::PathBuf;
+
+/// Environment variable overriding the user-mode root prefix.
+pub const USER_ROOT_ENV: &str = "AVOCADO_USER_ROOT";
+
+/// Whether user mode is active.
+pub fn is_user() -> bool {
+    crate::settings::current().user
+}
+
+fn home_relative(var: &str, fallback: &str) -> PathBuf {
+    match std::env::var(var) {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+            PathBuf::from(home).join(fallback)
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME`, defaulting to `~/.config`.
+pub fn config_home() -> PathBuf {
+    home_relative("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_STATE_HOME`, defaulting to `~/.local/state`.
+pub fn state_home() -> PathBuf {
+    home_relative("XDG_STATE_HOME", ".local/state")
+}
+
+/// Configuration file used in user mode when `--config` is not given.
+pub fn config_path() -> PathBuf {
+    config_home().join("avocado/avocadoctl.conf")
+}
+
+/// Root prefix all system paths are relocated under.
+pub fn root() -> PathBuf {
+    crate::settings::current()
+        .user_root
+        .clone()
+        .unwrap_or_else(|| state_home().join("avocado/root"))
+}
+
+/// Relocate an absolute system path under [`root`] when user mode is active.
+pub fn system_path(path: &str) -> String {
+    if is_user() {
+        format!("{}{path}", root().display())
+    } else {
+        path.to_string()
+    }
+}
+
+/// Adjust a loaded configuration for user mode: move the default extensions
+/// and base directories under the prefix.
+pub fn apply_to_config(config: &mut Config) {
+    if config.avocado.ext.dir == Config::default().avocado.ext.dir {
+        config.avocado.ext.dir = system_path(&config.avocado.ext.dir);
+    }
+    if config.avocado.runtimes_dir.is_none() {
+        config.avocado.runtimes_dir = Some(system_path(crate::manifest::DEFAULT_AVOCADO_DIR));
+    }
+}
+
+/// Extra arguments for systemd-sysext / systemd-confext in user mode.
+pub fn extension_tool_args() -> Vec<String> {
+    if is_user() {
+        vec![format!("--root={}", root().display())]
+    } else {
+        Vec::new()
+    }
+}
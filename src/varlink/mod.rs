@@ -1,6 +1,6 @@
-#[allow(clippy::uninlined_format_args)]
+#[allow(clippy::uninlined_format_args, clippy::too_many_arguments)]
 pub mod org_avocado_Extensions;
-#[allow(clippy::uninlined_format_args)]
+#[allow(clippy::uninlined_format_args, clippy::too_many_arguments)]
 pub mod org_avocado_Hitl;
 #[allow(clippy::uninlined_format_args)]
 pub mod org_avocado_RootAuthority;
@@ -15,6 +15,7 @@ pub enum ErrorKind {
     ConfigurationError(Option<ConfigurationError_Args>),
     ExtensionNotFound(Option<ExtensionNotFound_Args>),
     MergeFailed(Option<MergeFailed_Args>),
+    PlanDrifted(Option<PlanDrifted_Args>),
     UnmergeFailed(Option<UnmergeFailed_Args>),
 }
 impl ::std::fmt::Display for ErrorKind {
@@ -32,6 +33,7 @@ impl ::std::fmt::Display for ErrorKind {
                 write!(f, "org.avocado.Extensions.ExtensionNotFound: {:#?}", v)
             }
             ErrorKind::MergeFailed(v) => write!(f, "org.avocado.Extensions.MergeFailed: {:#?}", v),
+            ErrorKind::PlanDrifted(v) => write!(f, "org.avocado.Extensions.PlanDrifted: {:#?}", v),
             ErrorKind::UnmergeFailed(v) => {
                 write!(f, "org.avocado.Extensions.UnmergeFailed: {:#?}", v)
             }
@@ -174,6 +176,18 @@ impl From<&varlink::Reply> for ErrorKind {
                     _ => ErrorKind::MergeFailed(None),
                 }
             }
+            varlink::Reply { error: Some(t), .. } if t == "org.avocado.Extensions.PlanDrifted" => {
+                match e {
+                    varlink::Reply {
+                        parameters: Some(p),
+                        ..
+                    } => match serde_json::from_value(p.clone()) {
+                        Ok(v) => ErrorKind::PlanDrifted(v),
+                        Err(_) => ErrorKind::PlanDrifted(None),
+                    },
+                    _ => ErrorKind::PlanDrifted(None),
+                }
+            }
             varlink::Reply { error: Some(t), .. }
                 if t == "org.avocado.Extensions.UnmergeFailed" =>
             {
@@ -237,6 +251,15 @@ pub trait VarlinkCallError: varlink::CallTrait {
             ),
         ))
     }
+    fn reply_plan_drifted(&mut self, r#reasons: Vec<String>) -> varlink::Result<()> {
+        self.reply_struct(varlink::Reply::error(
+            "org.avocado.Extensions.PlanDrifted",
+            Some(
+                serde_json::to_value(PlanDrifted_Args { r#reasons })
+                    .map_err(varlink::map_context!())?,
+            ),
+        ))
+    }
     fn reply_unmerge_failed(&mut self, r#reason: String) -> varlink::Result<()> {
         self.reply_struct(varlink::Reply::error(
             "org.avocado.Extensions.UnmergeFailed",
@@ -249,6 +272,19 @@ pub trait VarlinkCallError: varlink::CallTrait {
 }
 impl VarlinkCallError for varlink::Call<'_> {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct r#AutoRefreshStats {
+    pub r#enabled: bool,
+    pub r#triggers: i64,
+    pub r#coalesced: i64,
+    pub r#throttled: i64,
+    pub r#refreshes: i64,
+    pub r#skipped: i64,
+    pub r#deferred: i64,
+    pub r#queued: i64,
+    pub r#failures: i64,
+    pub r#lastRefresh: Option<i64>,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct r#Extension {
     pub r#name: String,
     pub r#version: Option<String>,
@@ -256,6 +292,9 @@ pub struct r#Extension {
     pub r#isSysext: bool,
     pub r#isConfext: bool,
     pub r#isDirectory: bool,
+    pub r#scopes: Option<Vec<String>>,
+    pub r#origin: Option<String>,
+    pub r#enabled: Option<bool>,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct r#ExtensionStatus {
@@ -267,6 +306,47 @@ pub struct r#ExtensionStatus {
     pub r#origin: Option<String>,
     pub r#imageId: Option<String>,
     pub r#imageType: Option<String>,
+    pub r#rebootRequired: Option<bool>,
+    pub r#buildId: Option<String>,
+    pub r#gitSha: Option<String>,
+    pub r#buildDate: Option<String>,
+    pub r#eol: Option<String>,
+    pub r#notes: Option<String>,
+    pub r#path: Option<String>,
+    pub r#partitions: Option<Vec<ImagePartition>>,
+    pub r#latestVersion: Option<String>,
+    pub r#updateAvailable: Option<bool>,
+    pub r#loopDevice: Option<LoopDevice>,
+    pub r#safeModeSkipped: Option<bool>,
+    pub r#scopes: Option<Vec<String>>,
+    pub r#applicable: Option<bool>,
+    pub r#mergedSince: Option<i64>,
+    pub r#aliases: Option<Vec<String>>,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct r#ExtensionUpgrade {
+    pub r#name: String,
+    pub r#current: String,
+    pub r#available: Option<String>,
+    pub r#policy: String,
+    pub r#action: String,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct r#ImagePartition {
+    pub r#designator: String,
+    pub r#hierarchy: Option<String>,
+    pub r#architecture: String,
+    pub r#uuid: String,
+    pub r#label: Option<String>,
+    pub r#size: i64,
+    pub r#verity: bool,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct r#LoopDevice {
+    pub r#device: String,
+    pub r#backingFile: Option<String>,
+    pub r#readOnly: bool,
+    pub r#verity: bool,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct CommandFailed_Args {
@@ -286,10 +366,90 @@ pub struct MergeFailed_Args {
     pub r#reason: String,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PlanDrifted_Args {
+    pub r#reasons: Vec<String>,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct UnmergeFailed_Args {
     pub r#reason: String,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Apply_Reply {
+    pub r#linked: i64,
+    pub r#unlinked: i64,
+    pub r#refreshed: bool,
+}
+impl varlink::VarlinkReply for Apply_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Apply_Args {
+    pub r#enable: Vec<String>,
+    pub r#disable: Vec<String>,
+    pub r#update: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#osRelease: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#force: Option<bool>,
+}
+#[allow(dead_code)]
+pub trait Call_Apply: VarlinkCallError {
+    fn reply(&mut self, r#linked: i64, r#unlinked: i64, r#refreshed: bool) -> varlink::Result<()> {
+        self.reply_struct(
+            Apply_Reply {
+                r#linked,
+                r#unlinked,
+                r#refreshed,
+            }
+            .into(),
+        )
+    }
+}
+impl Call_Apply for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ApplyPlan_Reply {
+    pub r#message: String,
+    pub r#done: bool,
+}
+impl varlink::VarlinkReply for ApplyPlan_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ApplyPlan_Args {
+    pub r#plan: String,
+}
+#[allow(dead_code)]
+pub trait Call_ApplyPlan: VarlinkCallError {
+    fn reply(&mut self, r#message: String, r#done: bool) -> varlink::Result<()> {
+        self.reply_struct(ApplyPlan_Reply { r#message, r#done }.into())
+    }
+}
+impl Call_ApplyPlan for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Audit_Reply {
+    pub r#report: String,
+}
+impl varlink::VarlinkReply for Audit_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Audit_Args {}
+#[allow(dead_code)]
+pub trait Call_Audit: VarlinkCallError {
+    fn reply(&mut self, r#report: String) -> varlink::Result<()> {
+        self.reply_struct(Audit_Reply { r#report }.into())
+    }
+}
+impl Call_Audit for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct AutoRefreshStatus_Reply {
+    pub r#stats: AutoRefreshStats,
+}
+impl varlink::VarlinkReply for AutoRefreshStatus_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct AutoRefreshStatus_Args {}
+#[allow(dead_code)]
+pub trait Call_AutoRefreshStatus: VarlinkCallError {
+    fn reply(&mut self, r#stats: AutoRefreshStats) -> varlink::Result<()> {
+        self.reply_struct(AutoRefreshStatus_Reply { r#stats }.into())
+    }
+}
+impl Call_AutoRefreshStatus for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Disable_Reply {
     pub r#disabled: i64,
     pub r#failed: i64,
@@ -328,6 +488,8 @@ pub struct Enable_Args {
     pub r#extensions: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub r#osRelease: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#force: Option<bool>,
 }
 #[allow(dead_code)]
 pub trait Call_Enable: VarlinkCallError {
@@ -348,7 +510,10 @@ pub struct List_Reply {
 }
 impl varlink::VarlinkReply for List_Reply {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct List_Args {}
+pub struct List_Args {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#detailed: Option<bool>,
+}
 #[allow(dead_code)]
 pub trait Call_List: VarlinkCallError {
     fn reply(&mut self, r#extensions: Vec<Extension>) -> varlink::Result<()> {
@@ -363,7 +528,12 @@ pub struct Merge_Reply {
 }
 impl varlink::VarlinkReply for Merge_Reply {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct Merge_Args {}
+pub struct Merge_Args {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#keepGoing: Option<bool>,
+}
 #[allow(dead_code)]
 pub trait Call_Merge: VarlinkCallError {
     fn reply(&mut self, r#message: String, r#done: bool) -> varlink::Result<()> {
@@ -372,17 +542,119 @@ pub trait Call_Merge: VarlinkCallError {
 }
 impl Call_Merge for varlink::Call<'_> {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Plan_Reply {
+    pub r#plan: String,
+}
+impl varlink::VarlinkReply for Plan_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Plan_Args {}
+#[allow(dead_code)]
+pub trait Call_Plan: VarlinkCallError {
+    fn reply(&mut self, r#plan: String) -> varlink::Result<()> {
+        self.reply_struct(Plan_Reply { r#plan }.into())
+    }
+}
+impl Call_Plan for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Prefetch_Reply {
+    pub r#runtimeId: String,
+    pub r#name: String,
+    pub r#version: String,
+    pub r#alreadyActive: bool,
+}
+impl varlink::VarlinkReply for Prefetch_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Prefetch_Args {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#authToken: Option<String>,
+}
+#[allow(dead_code)]
+pub trait Call_Prefetch: VarlinkCallError {
+    fn reply(
+        &mut self,
+        r#runtimeId: String,
+        r#name: String,
+        r#version: String,
+        r#alreadyActive: bool,
+    ) -> varlink::Result<()> {
+        self.reply_struct(
+            Prefetch_Reply {
+                r#runtimeId,
+                r#name,
+                r#version,
+                r#alreadyActive,
+            }
+            .into(),
+        )
+    }
+}
+impl Call_Prefetch for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PruneOsReleases_Reply {
+    pub r#current: String,
+    pub r#kept: Vec<String>,
+    pub r#removed: Vec<String>,
+}
+impl varlink::VarlinkReply for PruneOsReleases_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PruneOsReleases_Args {
+    pub r#keep: Vec<String>,
+    pub r#dryRun: bool,
+}
+#[allow(dead_code)]
+pub trait Call_PruneOsReleases: VarlinkCallError {
+    fn reply(
+        &mut self,
+        r#current: String,
+        r#kept: Vec<String>,
+        r#removed: Vec<String>,
+    ) -> varlink::Result<()> {
+        self.reply_struct(
+            PruneOsReleases_Reply {
+                r#current,
+                r#kept,
+                r#removed,
+            }
+            .into(),
+        )
+    }
+}
+impl Call_PruneOsReleases for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Refresh_Reply {
     pub r#message: String,
     pub r#done: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#upToDate: Option<bool>,
 }
 impl varlink::VarlinkReply for Refresh_Reply {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct Refresh_Args {}
+pub struct Refresh_Args {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#softReboot: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#force: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#keepGoing: Option<bool>,
+}
 #[allow(dead_code)]
 pub trait Call_Refresh: VarlinkCallError {
-    fn reply(&mut self, r#message: String, r#done: bool) -> varlink::Result<()> {
-        self.reply_struct(Refresh_Reply { r#message, r#done }.into())
+    fn reply(
+        &mut self,
+        r#message: String,
+        r#done: bool,
+        r#upToDate: Option<bool>,
+    ) -> varlink::Result<()> {
+        self.reply_struct(
+            Refresh_Reply {
+                r#message,
+                r#done,
+                r#upToDate,
+            }
+            .into(),
+        )
     }
 }
 impl Call_Refresh for varlink::Call<'_> {}
@@ -411,12 +683,29 @@ pub trait Call_SetEnabled: VarlinkCallError {
 }
 impl Call_SetEnabled for varlink::Call<'_> {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Snapshot_Reply {
+    pub r#snapshot: String,
+}
+impl varlink::VarlinkReply for Snapshot_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Snapshot_Args {}
+#[allow(dead_code)]
+pub trait Call_Snapshot: VarlinkCallError {
+    fn reply(&mut self, r#snapshot: String) -> varlink::Result<()> {
+        self.reply_struct(Snapshot_Reply { r#snapshot }.into())
+    }
+}
+impl Call_Snapshot for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Status_Reply {
     pub r#extensions: Vec<ExtensionStatus>,
 }
 impl varlink::VarlinkReply for Status_Reply {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct Status_Args {}
+pub struct Status_Args {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#updatesOnly: Option<bool>,
+}
 #[allow(dead_code)]
 pub trait Call_Status: VarlinkCallError {
     fn reply(&mut self, r#extensions: Vec<ExtensionStatus>) -> varlink::Result<()> {
@@ -442,8 +731,64 @@ pub trait Call_Unmerge: VarlinkCallError {
     }
 }
 impl Call_Unmerge for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Upgrade_Reply {
+    pub r#upgrades: Vec<ExtensionUpgrade>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#runtimeId: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#queued: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#queuedUntil: Option<i64>,
+}
+impl varlink::VarlinkReply for Upgrade_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Upgrade_Args {
+    pub r#names: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#authToken: Option<String>,
+    pub r#offline: bool,
+    pub r#dryRun: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#force: Option<bool>,
+}
+#[allow(dead_code)]
+pub trait Call_Upgrade: VarlinkCallError {
+    fn reply(
+        &mut self,
+        r#upgrades: Vec<ExtensionUpgrade>,
+        r#runtimeId: Option<String>,
+        r#queued: Option<bool>,
+        r#queuedUntil: Option<i64>,
+    ) -> varlink::Result<()> {
+        self.reply_struct(
+            Upgrade_Reply {
+                r#upgrades,
+                r#runtimeId,
+                r#queued,
+                r#queuedUntil,
+            }
+            .into(),
+        )
+    }
+}
+impl Call_Upgrade for varlink::Call<'_> {}
 #[allow(dead_code)]
 pub trait VarlinkInterface {
+    fn apply(
+        &self,
+        call: &mut dyn Call_Apply,
+        r#enable: Vec<String>,
+        r#disable: Vec<String>,
+        r#update: Vec<String>,
+        r#osRelease: Option<String>,
+        r#force: Option<bool>,
+    ) -> varlink::Result<()>;
+    fn apply_plan(&self, call: &mut dyn Call_ApplyPlan, r#plan: String) -> varlink::Result<()>;
+    fn audit(&self, call: &mut dyn Call_Audit) -> varlink::Result<()>;
+    fn auto_refresh_status(&self, call: &mut dyn Call_AutoRefreshStatus) -> varlink::Result<()>;
     fn disable(
         &self,
         call: &mut dyn Call_Disable,
@@ -456,18 +801,58 @@ pub trait VarlinkInterface {
         call: &mut dyn Call_Enable,
         r#extensions: Vec<String>,
         r#osRelease: Option<String>,
+        r#force: Option<bool>,
+    ) -> varlink::Result<()>;
+    fn list(&self, call: &mut dyn Call_List, r#detailed: Option<bool>) -> varlink::Result<()>;
+    fn merge(
+        &self,
+        call: &mut dyn Call_Merge,
+        r#target: Option<String>,
+        r#keepGoing: Option<bool>,
+    ) -> varlink::Result<()>;
+    fn plan(&self, call: &mut dyn Call_Plan) -> varlink::Result<()>;
+    fn prefetch(
+        &self,
+        call: &mut dyn Call_Prefetch,
+        r#url: Option<String>,
+        r#authToken: Option<String>,
+    ) -> varlink::Result<()>;
+    fn prune_os_releases(
+        &self,
+        call: &mut dyn Call_PruneOsReleases,
+        r#keep: Vec<String>,
+        r#dryRun: bool,
+    ) -> varlink::Result<()>;
+    fn refresh(
+        &self,
+        call: &mut dyn Call_Refresh,
+        r#softReboot: Option<bool>,
+        r#force: Option<bool>,
+        r#keepGoing: Option<bool>,
     ) -> varlink::Result<()>;
-    fn list(&self, call: &mut dyn Call_List) -> varlink::Result<()>;
-    fn merge(&self, call: &mut dyn Call_Merge) -> varlink::Result<()>;
-    fn refresh(&self, call: &mut dyn Call_Refresh) -> varlink::Result<()>;
     fn set_enabled(
         &self,
         call: &mut dyn Call_SetEnabled,
         r#extensions: Vec<String>,
         r#enabled: bool,
     ) -> varlink::Result<()>;
-    fn status(&self, call: &mut dyn Call_Status) -> varlink::Result<()>;
+    fn snapshot(&self, call: &mut dyn Call_Snapshot) -> varlink::Result<()>;
+    fn status(
+        &self,
+        call: &mut dyn Call_Status,
+        r#updatesOnly: Option<bool>,
+    ) -> varlink::Result<()>;
     fn unmerge(&self, call: &mut dyn Call_Unmerge, r#unmount: Option<bool>) -> varlink::Result<()>;
+    fn upgrade(
+        &self,
+        call: &mut dyn Call_Upgrade,
+        r#names: Vec<String>,
+        r#url: Option<String>,
+        r#authToken: Option<String>,
+        r#offline: bool,
+        r#dryRun: bool,
+        r#force: Option<bool>,
+    ) -> varlink::Result<()>;
     fn call_upgraded(
         &self,
         _call: &mut varlink::Call,
@@ -478,6 +863,22 @@ pub trait VarlinkInterface {
 }
 #[allow(dead_code)]
 pub trait VarlinkClientInterface {
+    fn apply(
+        &mut self,
+        r#enable: Vec<String>,
+        r#disable: Vec<String>,
+        r#update: Vec<String>,
+        r#osRelease: Option<String>,
+        r#force: Option<bool>,
+    ) -> varlink::MethodCall<Apply_Args, Apply_Reply, Error>;
+    fn apply_plan(
+        &mut self,
+        r#plan: String,
+    ) -> varlink::MethodCall<ApplyPlan_Args, ApplyPlan_Reply, Error>;
+    fn audit(&mut self) -> varlink::MethodCall<Audit_Args, Audit_Reply, Error>;
+    fn auto_refresh_status(
+        &mut self,
+    ) -> varlink::MethodCall<AutoRefreshStatus_Args, AutoRefreshStatus_Reply, Error>;
     fn disable(
         &mut self,
         r#extensions: Option<Vec<String>>,
@@ -488,20 +889,57 @@ pub trait VarlinkClientInterface {
         &mut self,
         r#extensions: Vec<String>,
         r#osRelease: Option<String>,
+        r#force: Option<bool>,
     ) -> varlink::MethodCall<Enable_Args, Enable_Reply, Error>;
-    fn list(&mut self) -> varlink::MethodCall<List_Args, List_Reply, Error>;
-    fn merge(&mut self) -> varlink::MethodCall<Merge_Args, Merge_Reply, Error>;
-    fn refresh(&mut self) -> varlink::MethodCall<Refresh_Args, Refresh_Reply, Error>;
+    fn list(
+        &mut self,
+        r#detailed: Option<bool>,
+    ) -> varlink::MethodCall<List_Args, List_Reply, Error>;
+    fn merge(
+        &mut self,
+        r#target: Option<String>,
+        r#keepGoing: Option<bool>,
+    ) -> varlink::MethodCall<Merge_Args, Merge_Reply, Error>;
+    fn plan(&mut self) -> varlink::MethodCall<Plan_Args, Plan_Reply, Error>;
+    fn prefetch(
+        &mut self,
+        r#url: Option<String>,
+        r#authToken: Option<String>,
+    ) -> varlink::MethodCall<Prefetch_Args, Prefetch_Reply, Error>;
+    fn prune_os_releases(
+        &mut self,
+        r#keep: Vec<String>,
+        r#dryRun: bool,
+    ) -> varlink::MethodCall<PruneOsReleases_Args, PruneOsReleases_Reply, Error>;
+    fn refresh(
+        &mut self,
+        r#softReboot: Option<bool>,
+        r#force: Option<bool>,
+        r#keepGoing: Option<bool>,
+    ) -> varlink::MethodCall<Refresh_Args, Refresh_Reply, Error>;
     fn set_enabled(
         &mut self,
         r#extensions: Vec<String>,
         r#enabled: bool,
     ) -> varlink::MethodCall<SetEnabled_Args, SetEnabled_Reply, Error>;
-    fn status(&mut self) -> varlink::MethodCall<Status_Args, Status_Reply, Error>;
+    fn snapshot(&mut self) -> varlink::MethodCall<Snapshot_Args, Snapshot_Reply, Error>;
+    fn status(
+        &mut self,
+        r#updatesOnly: Option<bool>,
+    ) -> varlink::MethodCall<Status_Args, Status_Reply, Error>;
     fn unmerge(
         &mut self,
         r#unmount: Option<bool>,
     ) -> varlink::MethodCall<Unmerge_Args, Unmerge_Reply, Error>;
+    fn upgrade(
+        &mut self,
+        r#names: Vec<String>,
+        r#url: Option<String>,
+        r#authToken: Option<String>,
+        r#offline: bool,
+        r#dryRun: bool,
+        r#force: Option<bool>,
+    ) -> varlink::MethodCall<Upgrade_Args, Upgrade_Reply, Error>;
 }
 #[allow(dead_code)]
 pub struct VarlinkClient {
@@ -514,6 +952,52 @@ impl VarlinkClient {
     }
 }
 impl VarlinkClientInterface for VarlinkClient {
+    fn apply(
+        &mut self,
+        r#enable: Vec<String>,
+        r#disable: Vec<String>,
+        r#update: Vec<String>,
+        r#osRelease: Option<String>,
+        r#force: Option<bool>,
+    ) -> varlink::MethodCall<Apply_Args, Apply_Reply, Error> {
+        varlink::MethodCall::<Apply_Args, Apply_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.Apply",
+            Apply_Args {
+                r#enable,
+                r#disable,
+                r#update,
+                r#osRelease,
+                r#force,
+            },
+        )
+    }
+    fn apply_plan(
+        &mut self,
+        r#plan: String,
+    ) -> varlink::MethodCall<ApplyPlan_Args, ApplyPlan_Reply, Error> {
+        varlink::MethodCall::<ApplyPlan_Args, ApplyPlan_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.ApplyPlan",
+            ApplyPlan_Args { r#plan },
+        )
+    }
+    fn audit(&mut self) -> varlink::MethodCall<Audit_Args, Audit_Reply, Error> {
+        varlink::MethodCall::<Audit_Args, Audit_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.Audit",
+            Audit_Args {},
+        )
+    }
+    fn auto_refresh_status(
+        &mut self,
+    ) -> varlink::MethodCall<AutoRefreshStatus_Args, AutoRefreshStatus_Reply, Error> {
+        varlink::MethodCall::<AutoRefreshStatus_Args, AutoRefreshStatus_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.AutoRefreshStatus",
+            AutoRefreshStatus_Args {},
+        )
+    }
     fn disable(
         &mut self,
         r#extensions: Option<Vec<String>>,
@@ -534,6 +1018,7 @@ impl VarlinkClientInterface for VarlinkClient {
         &mut self,
         r#extensions: Vec<String>,
         r#osRelease: Option<String>,
+        r#force: Option<bool>,
     ) -> varlink::MethodCall<Enable_Args, Enable_Reply, Error> {
         varlink::MethodCall::<Enable_Args, Enable_Reply, Error>::new(
             self.connection.clone(),
@@ -541,28 +1026,77 @@ impl VarlinkClientInterface for VarlinkClient {
             Enable_Args {
                 r#extensions,
                 r#osRelease,
+                r#force,
             },
         )
     }
-    fn list(&mut self) -> varlink::MethodCall<List_Args, List_Reply, Error> {
+    fn list(
+        &mut self,
+        r#detailed: Option<bool>,
+    ) -> varlink::MethodCall<List_Args, List_Reply, Error> {
         varlink::MethodCall::<List_Args, List_Reply, Error>::new(
             self.connection.clone(),
             "org.avocado.Extensions.List",
-            List_Args {},
+            List_Args { r#detailed },
         )
     }
-    fn merge(&mut self) -> varlink::MethodCall<Merge_Args, Merge_Reply, Error> {
+    fn merge(
+        &mut self,
+        r#target: Option<String>,
+        r#keepGoing: Option<bool>,
+    ) -> varlink::MethodCall<Merge_Args, Merge_Reply, Error> {
         varlink::MethodCall::<Merge_Args, Merge_Reply, Error>::new(
             self.connection.clone(),
             "org.avocado.Extensions.Merge",
-            Merge_Args {},
+            Merge_Args {
+                r#target,
+                r#keepGoing,
+            },
+        )
+    }
+    fn plan(&mut self) -> varlink::MethodCall<Plan_Args, Plan_Reply, Error> {
+        varlink::MethodCall::<Plan_Args, Plan_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.Plan",
+            Plan_Args {},
+        )
+    }
+    fn prefetch(
+        &mut self,
+        r#url: Option<String>,
+        r#authToken: Option<String>,
+    ) -> varlink::MethodCall<Prefetch_Args, Prefetch_Reply, Error> {
+        varlink::MethodCall::<Prefetch_Args, Prefetch_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.Prefetch",
+            Prefetch_Args { r#url, r#authToken },
         )
     }
-    fn refresh(&mut self) -> varlink::MethodCall<Refresh_Args, Refresh_Reply, Error> {
+    fn prune_os_releases(
+        &mut self,
+        r#keep: Vec<String>,
+        r#dryRun: bool,
+    ) -> varlink::MethodCall<PruneOsReleases_Args, PruneOsReleases_Reply, Error> {
+        varlink::MethodCall::<PruneOsReleases_Args, PruneOsReleases_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.PruneOsReleases",
+            PruneOsReleases_Args { r#keep, r#dryRun },
+        )
+    }
+    fn refresh(
+        &mut self,
+        r#softReboot: Option<bool>,
+        r#force: Option<bool>,
+        r#keepGoing: Option<bool>,
+    ) -> varlink::MethodCall<Refresh_Args, Refresh_Reply, Error> {
         varlink::MethodCall::<Refresh_Args, Refresh_Reply, Error>::new(
             self.connection.clone(),
             "org.avocado.Extensions.Refresh",
-            Refresh_Args {},
+            Refresh_Args {
+                r#softReboot,
+                r#force,
+                r#keepGoing,
+            },
         )
     }
     fn set_enabled(
@@ -579,11 +1113,21 @@ impl VarlinkClientInterface for VarlinkClient {
             },
         )
     }
-    fn status(&mut self) -> varlink::MethodCall<Status_Args, Status_Reply, Error> {
+    fn snapshot(&mut self) -> varlink::MethodCall<Snapshot_Args, Snapshot_Reply, Error> {
+        varlink::MethodCall::<Snapshot_Args, Snapshot_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.Snapshot",
+            Snapshot_Args {},
+        )
+    }
+    fn status(
+        &mut self,
+        r#updatesOnly: Option<bool>,
+    ) -> varlink::MethodCall<Status_Args, Status_Reply, Error> {
         varlink::MethodCall::<Status_Args, Status_Reply, Error>::new(
             self.connection.clone(),
             "org.avocado.Extensions.Status",
-            Status_Args {},
+            Status_Args { r#updatesOnly },
         )
     }
     fn unmerge(
@@ -596,6 +1140,28 @@ impl VarlinkClientInterface for VarlinkClient {
             Unmerge_Args { r#unmount },
         )
     }
+    fn upgrade(
+        &mut self,
+        r#names: Vec<String>,
+        r#url: Option<String>,
+        r#authToken: Option<String>,
+        r#offline: bool,
+        r#dryRun: bool,
+        r#force: Option<bool>,
+    ) -> varlink::MethodCall<Upgrade_Args, Upgrade_Reply, Error> {
+        varlink::MethodCall::<Upgrade_Args, Upgrade_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Extensions.Upgrade",
+            Upgrade_Args {
+                r#names,
+                r#url,
+                r#authToken,
+                r#offline,
+                r#dryRun,
+                r#force,
+            },
+        )
+    }
 }
 #[allow(dead_code)]
 pub struct VarlinkInterfaceProxy {
@@ -607,7 +1173,7 @@ pub fn new(inner: Box<dyn VarlinkInterface + Send + Sync>) -> VarlinkInterfacePr
 }
 impl varlink::Interface for VarlinkInterfaceProxy {
     fn get_description(&self) -> &'static str {
-        "# Extension management for Avocado Linux system extensions\ninterface org.avocado.Extensions\n\ntype Extension (\n    name: string,\n    version: ?string,\n    path: string,\n    isSysext: bool,\n    isConfext: bool,\n    isDirectory: bool\n)\n\ntype ExtensionStatus (\n    name: string,\n    version: ?string,\n    isSysext: bool,\n    isConfext: bool,\n    isMerged: bool,\n    origin: ?string,\n    imageId: ?string,\n    imageType: ?string\n)\n\n# List all available extensions in the extensions directory\nmethod List() -> (extensions: []Extension)\n\n# Merge extensions using systemd-sysext and systemd-confext\n# Supports streaming: client may set more=true to receive per-message progress\nmethod Merge() -> (message: string, done: bool)\n\n# Unmerge extensions\n# Supports streaming: client may set more=true to receive per-message progress\nmethod Unmerge(unmount: ?bool) -> (message: string, done: bool)\n\n# Refresh extensions (unmerge then merge)\n# Supports streaming: client may set more=true to receive per-message progress\nmethod Refresh() -> (message: string, done: bool)\n\n# Enable extensions for a specific OS release version\nmethod Enable(extensions: []string, osRelease: ?string) -> (enabled: int, failed: int)\n\n# Disable extensions for a specific OS release version\nmethod Disable(extensions: ?[]string, all: ?bool, osRelease: ?string) -> (disabled: int, failed: int)\n\n# Override the build-time `enabled` default for one or more extensions in\n# the active runtime. Writes to <runtime_dir>/overrides.json; takes effect\n# on the next merge/refresh. Names may be the bare extension name\n# (`microclaw`) or the versioned form shown by `ext list`\n# (`microclaw-0.1.57`). `updated` counts names that resolved + were\n# written; `missing` counts names not found in the active manifest\n# (still recorded for future use).\nmethod SetEnabled(extensions: []string, enabled: bool) -> (updated: int, missing: int)\n\n# Show status of merged extensions\nmethod Status() -> (extensions: []ExtensionStatus)\n\nerror ExtensionNotFound (name: string)\nerror MergeFailed (reason: string)\nerror UnmergeFailed (reason: string)\nerror ConfigurationError (message: string)\nerror CommandFailed (command: string, message: string)\n"
+        "# Extension management for Avocado Linux system extensions\ninterface org.avocado.Extensions\n\n# scopes, origin and enabled are only set by List(detailed: true); scopes\n# is empty for an extension without a scope key and unset when no release\n# file could be read\ntype Extension (\n    name: string,\n    version: ?string,\n    path: string,\n    isSysext: bool,\n    isConfext: bool,\n    isDirectory: bool,\n    scopes: ?[]string,\n    origin: ?string,\n    enabled: ?bool\n)\n\n# latestVersion and updateAvailable are only set when [avocado.update] url\n# is configured and the repository index offers the extension;\n# safeModeSkipped is true for an extension the last merge left out in safe\n# mode; scopes (empty without a scope key) and applicable, whether the\n# extension is in scope in the current environment, are unset when no\n# release file could be read; mergedSince is when systemd merged the\n# extension, in seconds since the Unix epoch\ntype ExtensionStatus (\n    name: string,\n    version: ?string,\n    isSysext: bool,\n    isConfext: bool,\n    isMerged: bool,\n    origin: ?string,\n    imageId: ?string,\n    imageType: ?string,\n    rebootRequired: ?bool,\n    buildId: ?string,\n    gitSha: ?string,\n    buildDate: ?string,\n    eol: ?string,\n    notes: ?string,\n    path: ?string,\n    partitions: ?[]ImagePartition,\n    latestVersion: ?string,\n    updateAvailable: ?bool,\n    loopDevice: ?LoopDevice,\n    safeModeSkipped: ?bool,\n    scopes: ?[]string,\n    applicable: ?bool,\n    mergedSince: ?int,\n    aliases: ?[]string\n)\n\n# The loop device a mounted .raw or KAB extension is attached to\ntype LoopDevice (\n    device: string,\n    backingFile: ?string,\n    readOnly: bool,\n    verity: bool\n)\n\n# A data partition of a GPT image; hierarchy is set for combined\n# sysext + confext images\ntype ImagePartition (\n    designator: string,\n    hierarchy: ?string,\n    architecture: string,\n    uuid: string,\n    label: ?string,\n    size: int,\n    verity: bool\n)\n\ntype AutoRefreshStats (\n    enabled: bool,\n    triggers: int,\n    coalesced: int,\n    throttled: int,\n    refreshes: int,\n    skipped: int,\n    deferred: int,\n    queued: int,\n    failures: int,\n    lastRefresh: ?int\n)\n\n# Planned or applied upgrade of one extension. action is upgrade,\n# up-to-date, held (the policy does not allow the offered version), older\n# or not-offered\ntype ExtensionUpgrade (\n    name: string,\n    current: string,\n    available: ?string,\n    policy: string,\n    action: string\n)\n\n# List all available extensions in the extensions directory\n# With detailed=true, list the extensions enabled for the current os-release\n# followed by the installed ones that are not, with scopes, origin and\n# enabled set\nmethod List(detailed: ?bool) -> (extensions: []Extension)\n\n# Merge extensions using systemd-sysext and systemd-confext\n# target: merge inside a systemd-nspawn machine or an absolute chroot path instead of the host\n# keepGoing: skip extensions that fail to mount or have an invalid release\n# file and merge the rest; the skipped ones are recorded in\n# /run/avocado/merge-failures\n# Supports streaming: client may set more=true to receive per-message progress\nmethod Merge(target: ?string, keepGoing: ?bool) -> (message: string, done: bool)\n\n# Unmerge extensions\n# Supports streaming: client may set more=true to receive per-message progress\nmethod Unmerge(unmount: ?bool) -> (message: string, done: bool)\n\n# Refresh extensions (unmerge then merge)\n# With softReboot=true, sync the enable state to disk and request a systemd\n# soft-reboot instead; the new extension set is merged on the next boot.\n# Unless force=true, nothing is unmerged when the last merge was made from the\n# current extensions, images and HITL mounts; the final reply then has\n# upToDate=true. keepGoing is as for Merge.\n# Supports streaming: client may set more=true to receive per-message progress\nmethod Refresh(softReboot: ?bool, force: ?bool, keepGoing: ?bool) -> (message: string, done: bool, upToDate: ?bool)\n\n# Enable extensions for a specific OS release version\n# Extensions whose release file ID/VERSION_ID does not match the target\n# os-release are counted as failed unless force is set\nmethod Enable(extensions: []string, osRelease: ?string, force: ?bool) -> (enabled: int, failed: int)\n\n# Disable extensions for a specific OS release version\nmethod Disable(extensions: ?[]string, all: ?bool, osRelease: ?string) -> (disabled: int, failed: int)\n\n# Enable, disable and update several extensions for an OS release as one\n# transaction: the net change is applied and extensions are refreshed once.\n# Update entries also disable other enabled versions of the same extension.\n# Nothing is changed if any entry cannot be resolved; a failed refresh\n# restores the previous links. refreshed is false when nothing changed.\nmethod Apply(enable: []string, disable: []string, update: []string, osRelease: ?string, force: ?bool) -> (linked: int, unlinked: int, refreshed: bool)\n\n# Override the build-time `enabled` default for one or more extensions in\n# the active runtime. Writes to <runtime_dir>/overrides.json; takes effect\n# on the next merge/refresh. Names may be the bare extension name\n# (`microclaw`) or the versioned form shown by `ext list`\n# (`microclaw-0.1.57`). `updated` counts names that resolved + were\n# written; `missing` counts names not found in the active manifest\n# (still recorded for future use).\nmethod SetEnabled(extensions: []string, enabled: bool) -> (updated: int, missing: int)\n\n# Show status of merged extensions; with updatesOnly only those the\n# repository index offers an update for (ConfigurationError without an index)\nmethod Status(updatesOnly: ?bool) -> (extensions: []ExtensionStatus)\n\n# Capture the full extension/merge state as a JSON snapshot document\n# (the same format written by `avocadoctl ext snapshot`)\nmethod Snapshot() -> (snapshot: string)\n\n# Build an unsigned extension inventory report as JSON (the `signed` part of\n# the document written by `avocadoctl ext audit`); signing happens client-side\nmethod Audit() -> (report: string)\n\n# Compute what a refresh would change now, as a JSON plan document (the\n# format written by `avocadoctl plan`), including the state it was computed from\nmethod Plan() -> (plan: string)\n\n# Refresh extensions only if a plan computed now matches the given plan;\n# otherwise fail with PlanDrifted listing what changed since planning\n# Supports streaming: client may set more=true to receive per-message progress\nmethod ApplyPlan(plan: string) -> (message: string, done: bool)\n\n# Counters of the daemon's auto-refresh loop ([avocado.auto_refresh])\nmethod AutoRefreshStatus() -> (stats: AutoRefreshStats)\n\n# Download and verify the runtime the update repository offers and stage it\n# without activating it. url defaults to [avocado.update] url. alreadyActive\n# is true when the repository offers the runtime that is already active.\nmethod Prefetch(url: ?string, authToken: ?string) -> (runtimeId: string, name: string, version: string, alreadyActive: bool)\n\n# Upgrade the active runtime's extensions (all when names is empty) to the\n# versions the update repository offers, as far as each extension's\n# [avocado.upgrade] policy allows, and activate the resulting runtime.\n# offline uses the runtime staged by Prefetch. runtimeId is the activated\n# runtime, null on a dry run or when nothing was upgraded. Outside the\n# [avocado.maintenance] windows the upgrade is queued unless force is true:\n# queued is true, upgrades is empty and queuedUntil is the start of the next\n# window (seconds since the Unix epoch).\nmethod Upgrade(names: []string, url: ?string, authToken: ?string, offline: bool, dryRun: bool, force: ?bool) -> (upgrades: []ExtensionUpgrade, runtimeId: ?string, queued: ?bool, queuedUntil: ?int)\n\n# Remove the os-releases directories of OS versions no longer installed,\n# keeping the running VERSION_ID's and those selected by keep: \"current\",\n# \"previous\" (the closest earlier VERSION_ID) and explicit VERSION_IDs.\n# Fails with ConfigurationError while an OS update awaits verification.\nmethod PruneOsReleases(keep: []string, dryRun: bool) -> (current: string, kept: []string, removed: []string)\n\nerror ExtensionNotFound (name: string)\nerror MergeFailed (reason: string)\nerror UnmergeFailed (reason: string)\nerror ConfigurationError (message: string)\nerror CommandFailed (command: string, message: string)\nerror PlanDrifted (reasons: []string)\n"
     }
     fn get_name(&self) -> &'static str {
         "org.avocado.Extensions"
@@ -622,6 +1188,48 @@ impl varlink::Interface for VarlinkInterfaceProxy {
     fn call(&self, call: &mut varlink::Call) -> varlink::Result<()> {
         let req = call.request.unwrap();
         match req.method.as_ref() {
+            "org.avocado.Extensions.Apply" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Apply_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner.apply(
+                        call as &mut dyn Call_Apply,
+                        args.r#enable,
+                        args.r#disable,
+                        args.r#update,
+                        args.r#osRelease,
+                        args.r#force,
+                    )
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
+            "org.avocado.Extensions.ApplyPlan" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: ApplyPlan_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner
+                        .apply_plan(call as &mut dyn Call_ApplyPlan, args.r#plan)
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
+            "org.avocado.Extensions.Audit" => self.inner.audit(call as &mut dyn Call_Audit),
+            "org.avocado.Extensions.AutoRefreshStatus" => self
+                .inner
+                .auto_refresh_status(call as &mut dyn Call_AutoRefreshStatus),
             "org.avocado.Extensions.Disable" => {
                 if let Some(args) = req.parameters.clone() {
                     let args: Disable_Args = match serde_json::from_value(args) {
@@ -656,14 +1264,102 @@ impl varlink::Interface for VarlinkInterfaceProxy {
                         call as &mut dyn Call_Enable,
                         args.r#extensions,
                         args.r#osRelease,
+                        args.r#force,
+                    )
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
+            "org.avocado.Extensions.List" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: List_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner.list(call as &mut dyn Call_List, args.r#detailed)
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
+            "org.avocado.Extensions.Merge" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Merge_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner
+                        .merge(call as &mut dyn Call_Merge, args.r#target, args.r#keepGoing)
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
+            "org.avocado.Extensions.Plan" => self.inner.plan(call as &mut dyn Call_Plan),
+            "org.avocado.Extensions.Prefetch" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Prefetch_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner.prefetch(
+                        call as &mut dyn Call_Prefetch,
+                        args.r#url,
+                        args.r#authToken,
+                    )
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
+            "org.avocado.Extensions.PruneOsReleases" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: PruneOsReleases_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner.prune_os_releases(
+                        call as &mut dyn Call_PruneOsReleases,
+                        args.r#keep,
+                        args.r#dryRun,
+                    )
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
+            "org.avocado.Extensions.Refresh" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Refresh_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner.refresh(
+                        call as &mut dyn Call_Refresh,
+                        args.r#softReboot,
+                        args.r#force,
+                        args.r#keepGoing,
                     )
                 } else {
                     call.reply_invalid_parameter("parameters".into())
                 }
             }
-            "org.avocado.Extensions.List" => self.inner.list(call as &mut dyn Call_List),
-            "org.avocado.Extensions.Merge" => self.inner.merge(call as &mut dyn Call_Merge),
-            "org.avocado.Extensions.Refresh" => self.inner.refresh(call as &mut dyn Call_Refresh),
             "org.avocado.Extensions.SetEnabled" => {
                 if let Some(args) = req.parameters.clone() {
                     let args: SetEnabled_Args = match serde_json::from_value(args) {
@@ -683,7 +1379,25 @@ impl varlink::Interface for VarlinkInterfaceProxy {
                     call.reply_invalid_parameter("parameters".into())
                 }
             }
-            "org.avocado.Extensions.Status" => self.inner.status(call as &mut dyn Call_Status),
+            "org.avocado.Extensions.Snapshot" => {
+                self.inner.snapshot(call as &mut dyn Call_Snapshot)
+            }
+            "org.avocado.Extensions.Status" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Status_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner
+                        .status(call as &mut dyn Call_Status, args.r#updatesOnly)
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
             "org.avocado.Extensions.Unmerge" => {
                 if let Some(args) = req.parameters.clone() {
                     let args: Unmerge_Args = match serde_json::from_value(args) {
@@ -700,6 +1414,29 @@ impl varlink::Interface for VarlinkInterfaceProxy {
                     call.reply_invalid_parameter("parameters".into())
                 }
             }
+            "org.avocado.Extensions.Upgrade" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Upgrade_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner.upgrade(
+                        call as &mut dyn Call_Upgrade,
+                        args.r#names,
+                        args.r#url,
+                        args.r#authToken,
+                        args.r#offline,
+                        args.r#dryRun,
+                        args.r#force,
+                    )
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
             m => call.reply_method_not_found(String::from(m)),
         }
     }
@@ -12,6 +12,7 @@ pub enum ErrorKind {
     Varlink_Error,
     VarlinkReply_Error,
     MountFailed(Option<MountFailed_Args>),
+    QuiesceFailed(Option<QuiesceFailed_Args>),
     UnmountFailed(Option<UnmountFailed_Args>),
 }
 impl ::std::fmt::Display for ErrorKind {
@@ -20,6 +21,7 @@ impl ::std::fmt::Display for ErrorKind {
             ErrorKind::Varlink_Error => write!(f, "Varlink Error"),
             ErrorKind::VarlinkReply_Error => write!(f, "Varlink error reply"),
             ErrorKind::MountFailed(v) => write!(f, "org.avocado.Hitl.MountFailed: {:#?}", v),
+            ErrorKind::QuiesceFailed(v) => write!(f, "org.avocado.Hitl.QuiesceFailed: {:#?}", v),
             ErrorKind::UnmountFailed(v) => write!(f, "org.avocado.Hitl.UnmountFailed: {:#?}", v),
         }
     }
@@ -117,6 +119,18 @@ impl From<&varlink::Reply> for ErrorKind {
                 },
                 _ => ErrorKind::MountFailed(None),
             },
+            varlink::Reply { error: Some(t), .. } if t == "org.avocado.Hitl.QuiesceFailed" => {
+                match e {
+                    varlink::Reply {
+                        parameters: Some(p),
+                        ..
+                    } => match serde_json::from_value(p.clone()) {
+                        Ok(v) => ErrorKind::QuiesceFailed(v),
+                        Err(_) => ErrorKind::QuiesceFailed(None),
+                    },
+                    _ => ErrorKind::QuiesceFailed(None),
+                }
+            }
             varlink::Reply { error: Some(t), .. } if t == "org.avocado.Hitl.UnmountFailed" => {
                 match e {
                     varlink::Reply {
@@ -147,6 +161,22 @@ pub trait VarlinkCallError: varlink::CallTrait {
             ),
         ))
     }
+    fn reply_quiesce_failed(
+        &mut self,
+        r#extension: String,
+        r#reason: String,
+    ) -> varlink::Result<()> {
+        self.reply_struct(varlink::Reply::error(
+            "org.avocado.Hitl.QuiesceFailed",
+            Some(
+                serde_json::to_value(QuiesceFailed_Args {
+                    r#extension,
+                    r#reason,
+                })
+                .map_err(varlink::map_context!())?,
+            ),
+        ))
+    }
     fn reply_unmount_failed(
         &mut self,
         r#extension: String,
@@ -166,32 +196,82 @@ pub trait VarlinkCallError: varlink::CallTrait {
 }
 impl VarlinkCallError for varlink::Call<'_> {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct r#MountResult {
+    pub r#extension: String,
+    pub r#server: String,
+    pub r#status: String,
+    pub r#error: Option<String>,
+    pub r#elapsedMs: i64,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct MountFailed_Args {
     pub r#extension: String,
     pub r#reason: String,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct QuiesceFailed_Args {
+    pub r#extension: String,
+    pub r#reason: String,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct UnmountFailed_Args {
     pub r#extension: String,
     pub r#reason: String,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct Mount_Reply {}
+pub struct Mount_Reply {
+    pub r#results: Vec<MountResult>,
+}
 impl varlink::VarlinkReply for Mount_Reply {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Mount_Args {
-    pub r#serverIp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#serverIp: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub r#serverPort: Option<String>,
     pub r#extensions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#mountType: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#failFast: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#controlPort: Option<i64>,
 }
 #[allow(dead_code)]
 pub trait Call_Mount: VarlinkCallError {
+    fn reply(&mut self, r#results: Vec<MountResult>) -> varlink::Result<()> {
+        self.reply_struct(Mount_Reply { r#results }.into())
+    }
+}
+impl Call_Mount for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Quiesce_Reply {}
+impl varlink::VarlinkReply for Quiesce_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Quiesce_Args {
+    pub r#extensions: Vec<String>,
+}
+#[allow(dead_code)]
+pub trait Call_Quiesce: VarlinkCallError {
     fn reply(&mut self) -> varlink::Result<()> {
         self.reply_struct(varlink::Reply::parameters(None))
     }
 }
-impl Call_Mount for varlink::Call<'_> {}
+impl Call_Quiesce for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Resume_Reply {}
+impl varlink::VarlinkReply for Resume_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Resume_Args {
+    pub r#extensions: Vec<String>,
+}
+#[allow(dead_code)]
+pub trait Call_Resume: VarlinkCallError {
+    fn reply(&mut self) -> varlink::Result<()> {
+        self.reply_struct(varlink::Reply::parameters(None))
+    }
+}
+impl Call_Resume for varlink::Call<'_> {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Unmount_Reply {}
 impl varlink::VarlinkReply for Unmount_Reply {}
@@ -211,10 +291,19 @@ pub trait VarlinkInterface {
     fn mount(
         &self,
         call: &mut dyn Call_Mount,
-        r#serverIp: String,
+        r#serverIp: Option<String>,
         r#serverPort: Option<String>,
         r#extensions: Vec<String>,
+        r#mountType: Option<String>,
+        r#failFast: Option<bool>,
+        r#controlPort: Option<i64>,
+    ) -> varlink::Result<()>;
+    fn quiesce(
+        &self,
+        call: &mut dyn Call_Quiesce,
+        r#extensions: Vec<String>,
     ) -> varlink::Result<()>;
+    fn resume(&self, call: &mut dyn Call_Resume, r#extensions: Vec<String>) -> varlink::Result<()>;
     fn unmount(
         &self,
         call: &mut dyn Call_Unmount,
@@ -232,10 +321,21 @@ pub trait VarlinkInterface {
 pub trait VarlinkClientInterface {
     fn mount(
         &mut self,
-        r#serverIp: String,
+        r#serverIp: Option<String>,
         r#serverPort: Option<String>,
         r#extensions: Vec<String>,
+        r#mountType: Option<String>,
+        r#failFast: Option<bool>,
+        r#controlPort: Option<i64>,
     ) -> varlink::MethodCall<Mount_Args, Mount_Reply, Error>;
+    fn quiesce(
+        &mut self,
+        r#extensions: Vec<String>,
+    ) -> varlink::MethodCall<Quiesce_Args, Quiesce_Reply, Error>;
+    fn resume(
+        &mut self,
+        r#extensions: Vec<String>,
+    ) -> varlink::MethodCall<Resume_Args, Resume_Reply, Error>;
     fn unmount(
         &mut self,
         r#extensions: Vec<String>,
@@ -254,9 +354,12 @@ impl VarlinkClient {
 impl VarlinkClientInterface for VarlinkClient {
     fn mount(
         &mut self,
-        r#serverIp: String,
+        r#serverIp: Option<String>,
         r#serverPort: Option<String>,
         r#extensions: Vec<String>,
+        r#mountType: Option<String>,
+        r#failFast: Option<bool>,
+        r#controlPort: Option<i64>,
     ) -> varlink::MethodCall<Mount_Args, Mount_Reply, Error> {
         varlink::MethodCall::<Mount_Args, Mount_Reply, Error>::new(
             self.connection.clone(),
@@ -265,9 +368,32 @@ impl VarlinkClientInterface for VarlinkClient {
                 r#serverIp,
                 r#serverPort,
                 r#extensions,
+                r#mountType,
+                r#failFast,
+                r#controlPort,
             },
         )
     }
+    fn quiesce(
+        &mut self,
+        r#extensions: Vec<String>,
+    ) -> varlink::MethodCall<Quiesce_Args, Quiesce_Reply, Error> {
+        varlink::MethodCall::<Quiesce_Args, Quiesce_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Hitl.Quiesce",
+            Quiesce_Args { r#extensions },
+        )
+    }
+    fn resume(
+        &mut self,
+        r#extensions: Vec<String>,
+    ) -> varlink::MethodCall<Resume_Args, Resume_Reply, Error> {
+        varlink::MethodCall::<Resume_Args, Resume_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.Hitl.Resume",
+            Resume_Args { r#extensions },
+        )
+    }
     fn unmount(
         &mut self,
         r#extensions: Vec<String>,
@@ -289,7 +415,7 @@ pub fn new(inner: Box<dyn VarlinkInterface + Send + Sync>) -> VarlinkInterfacePr
 }
 impl varlink::Interface for VarlinkInterfaceProxy {
     fn get_description(&self) -> &'static str {
-        "# Hardware-in-the-loop testing support\ninterface org.avocado.Hitl\n\n# Mount NFS extensions from a remote server\nmethod Mount(serverIp: string, serverPort: ?string, extensions: []string) -> ()\n\n# Unmount NFS extensions\nmethod Unmount(extensions: []string) -> ()\n\nerror MountFailed (extension: string, reason: string)\nerror UnmountFailed (extension: string, reason: string)\n"
+        "# Hardware-in-the-loop testing support\ninterface org.avocado.Hitl\n\n# How mounting one extension went\n# status is \"mounted\", \"failed\" or \"skipped\" (not started after a failure\n# with failFast)\ntype MountResult (\n    extension: string,\n    server: string,\n    status: string,\n    error: ?string,\n    elapsedMs: int\n)\n\n# Mount NFS extensions from remote servers, several at a time\n# Each extension is \"name\" or \"name@server[:port]\"; serverIp and serverPort\n# apply to extensions without their own server or port\n# mountType is \"sysext\", \"confext\" or \"auto\" (default: detect from the tree)\n# Extensions that fail to mount are reported in results; the ones that\n# mounted are merged unless failFast is set, which also stops starting mounts\n# after the first failure\n# With controlPort (or [avocado.hitl] control_port), each server is first\n# asked for its protocol version and exported extensions on that port; an\n# outdated server or an extension it does not export fails before mounting\nmethod Mount(serverIp: ?string, serverPort: ?string, extensions: []string, mountType: ?string, failFast: ?bool, controlPort: ?int) -> (results: []MountResult)\n\n# Unmount NFS extensions (a \"@server[:port]\" suffix is ignored)\nmethod Unmount(extensions: []string) -> ()\n\n# Hold refreshes while the extensions are being synced, until Resume\nmethod Quiesce(extensions: []string) -> ()\n\n# Release extensions held by Quiesce\nmethod Resume(extensions: []string) -> ()\n\nerror MountFailed (extension: string, reason: string)\nerror UnmountFailed (extension: string, reason: string)\nerror QuiesceFailed (extension: string, reason: string)\n"
     }
     fn get_name(&self) -> &'static str {
         "org.avocado.Hitl"
@@ -319,11 +445,46 @@ impl varlink::Interface for VarlinkInterfaceProxy {
                         args.r#serverIp,
                         args.r#serverPort,
                         args.r#extensions,
+                        args.r#mountType,
+                        args.r#failFast,
+                        args.r#controlPort,
                     )
                 } else {
                     call.reply_invalid_parameter("parameters".into())
                 }
             }
+            "org.avocado.Hitl.Quiesce" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Quiesce_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner
+                        .quiesce(call as &mut dyn Call_Quiesce, args.r#extensions)
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
+            "org.avocado.Hitl.Resume" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Resume_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner
+                        .resume(call as &mut dyn Call_Resume, args.r#extensions)
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
             "org.avocado.Hitl.Unmount" => {
                 if let Some(args) = req.parameters.clone() {
                     let args: Unmount_Args = match serde_json::from_value(args) {
@@ -13,6 +13,7 @@ pub enum ErrorKind {
     VarlinkReply_Error,
     NoRootAuthority(Option<NoRootAuthority_Args>),
     ParseFailed(Option<ParseFailed_Args>),
+    TrustFailed(Option<TrustFailed_Args>),
 }
 impl ::std::fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
@@ -25,6 +26,9 @@ impl ::std::fmt::Display for ErrorKind {
             ErrorKind::ParseFailed(v) => {
                 write!(f, "org.avocado.RootAuthority.ParseFailed: {:#?}", v)
             }
+            ErrorKind::TrustFailed(v) => {
+                write!(f, "org.avocado.RootAuthority.TrustFailed: {:#?}", v)
+            }
         }
     }
 }
@@ -138,6 +142,20 @@ impl From<&varlink::Reply> for ErrorKind {
                     _ => ErrorKind::ParseFailed(None),
                 }
             }
+            varlink::Reply { error: Some(t), .. }
+                if t == "org.avocado.RootAuthority.TrustFailed" =>
+            {
+                match e {
+                    varlink::Reply {
+                        parameters: Some(p),
+                        ..
+                    } => match serde_json::from_value(p.clone()) {
+                        Ok(v) => ErrorKind::TrustFailed(v),
+                        Err(_) => ErrorKind::TrustFailed(None),
+                    },
+                    _ => ErrorKind::TrustFailed(None),
+                }
+            }
             _ => ErrorKind::VarlinkReply_Error,
         }
     }
@@ -159,15 +177,41 @@ pub trait VarlinkCallError: varlink::CallTrait {
             ),
         ))
     }
+    fn reply_trust_failed(&mut self, r#reason: String) -> varlink::Result<()> {
+        self.reply_struct(varlink::Reply::error(
+            "org.avocado.RootAuthority.TrustFailed",
+            Some(
+                serde_json::to_value(TrustFailed_Args { r#reason })
+                    .map_err(varlink::map_context!())?,
+            ),
+        ))
+    }
 }
 impl VarlinkCallError for varlink::Call<'_> {}
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct r#ImageTrust {
+    pub r#name: String,
+    pub r#version: Option<String>,
+    pub r#path: String,
+    pub r#now: String,
+    pub r#after: String,
+    pub r#signedBy: Option<String>,
+    pub r#reason: Option<String>,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct r#RootAuthorityInfo {
     pub r#version: i64,
     pub r#expires: String,
     pub r#keys: Vec<TrustedKey>,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct r#SigningKey {
+    pub r#keyId: String,
+    pub r#publicKey: String,
+    pub r#notBefore: Option<i64>,
+    pub r#notAfter: Option<i64>,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct r#TrustedKey {
     pub r#keyId: String,
     pub r#keyType: String,
@@ -180,6 +224,51 @@ pub struct ParseFailed_Args {
     pub r#reason: String,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TrustFailed_Args {
+    pub r#reason: String,
+}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Rotate_Reply {
+    pub r#added: SigningKey,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#retired: Option<SigningKey>,
+    pub r#checkedAt: i64,
+    pub r#images: Vec<ImageTrust>,
+}
+impl varlink::VarlinkReply for Rotate_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Rotate_Args {
+    pub r#publicKey: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#retire: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#overlapDays: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#validDays: Option<i64>,
+    pub r#dryRun: bool,
+}
+#[allow(dead_code)]
+pub trait Call_Rotate: VarlinkCallError {
+    fn reply(
+        &mut self,
+        r#added: SigningKey,
+        r#retired: Option<SigningKey>,
+        r#checkedAt: i64,
+        r#images: Vec<ImageTrust>,
+    ) -> varlink::Result<()> {
+        self.reply_struct(
+            Rotate_Reply {
+                r#added,
+                r#retired,
+                r#checkedAt,
+                r#images,
+            }
+            .into(),
+        )
+    }
+}
+impl Call_Rotate for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Show_Reply {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub r#authority: Option<RootAuthorityInfo>,
@@ -194,9 +283,33 @@ pub trait Call_Show: VarlinkCallError {
     }
 }
 impl Call_Show for varlink::Call<'_> {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TrustedKeys_Reply {
+    pub r#keys: Vec<SigningKey>,
+}
+impl varlink::VarlinkReply for TrustedKeys_Reply {}
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TrustedKeys_Args {}
+#[allow(dead_code)]
+pub trait Call_TrustedKeys: VarlinkCallError {
+    fn reply(&mut self, r#keys: Vec<SigningKey>) -> varlink::Result<()> {
+        self.reply_struct(TrustedKeys_Reply { r#keys }.into())
+    }
+}
+impl Call_TrustedKeys for varlink::Call<'_> {}
 #[allow(dead_code)]
 pub trait VarlinkInterface {
+    fn rotate(
+        &self,
+        call: &mut dyn Call_Rotate,
+        r#publicKey: String,
+        r#retire: Option<String>,
+        r#overlapDays: Option<i64>,
+        r#validDays: Option<i64>,
+        r#dryRun: bool,
+    ) -> varlink::Result<()>;
     fn show(&self, call: &mut dyn Call_Show) -> varlink::Result<()>;
+    fn trusted_keys(&self, call: &mut dyn Call_TrustedKeys) -> varlink::Result<()>;
     fn call_upgraded(
         &self,
         _call: &mut varlink::Call,
@@ -207,7 +320,16 @@ pub trait VarlinkInterface {
 }
 #[allow(dead_code)]
 pub trait VarlinkClientInterface {
+    fn rotate(
+        &mut self,
+        r#publicKey: String,
+        r#retire: Option<String>,
+        r#overlapDays: Option<i64>,
+        r#validDays: Option<i64>,
+        r#dryRun: bool,
+    ) -> varlink::MethodCall<Rotate_Args, Rotate_Reply, Error>;
     fn show(&mut self) -> varlink::MethodCall<Show_Args, Show_Reply, Error>;
+    fn trusted_keys(&mut self) -> varlink::MethodCall<TrustedKeys_Args, TrustedKeys_Reply, Error>;
 }
 #[allow(dead_code)]
 pub struct VarlinkClient {
@@ -220,6 +342,26 @@ impl VarlinkClient {
     }
 }
 impl VarlinkClientInterface for VarlinkClient {
+    fn rotate(
+        &mut self,
+        r#publicKey: String,
+        r#retire: Option<String>,
+        r#overlapDays: Option<i64>,
+        r#validDays: Option<i64>,
+        r#dryRun: bool,
+    ) -> varlink::MethodCall<Rotate_Args, Rotate_Reply, Error> {
+        varlink::MethodCall::<Rotate_Args, Rotate_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.RootAuthority.Rotate",
+            Rotate_Args {
+                r#publicKey,
+                r#retire,
+                r#overlapDays,
+                r#validDays,
+                r#dryRun,
+            },
+        )
+    }
     fn show(&mut self) -> varlink::MethodCall<Show_Args, Show_Reply, Error> {
         varlink::MethodCall::<Show_Args, Show_Reply, Error>::new(
             self.connection.clone(),
@@ -227,6 +369,13 @@ impl VarlinkClientInterface for VarlinkClient {
             Show_Args {},
         )
     }
+    fn trusted_keys(&mut self) -> varlink::MethodCall<TrustedKeys_Args, TrustedKeys_Reply, Error> {
+        varlink::MethodCall::<TrustedKeys_Args, TrustedKeys_Reply, Error>::new(
+            self.connection.clone(),
+            "org.avocado.RootAuthority.TrustedKeys",
+            TrustedKeys_Args {},
+        )
+    }
 }
 #[allow(dead_code)]
 pub struct VarlinkInterfaceProxy {
@@ -238,7 +387,7 @@ pub fn new(inner: Box<dyn VarlinkInterface + Send + Sync>) -> VarlinkInterfacePr
 }
 impl varlink::Interface for VarlinkInterfaceProxy {
     fn get_description(&self) -> &'static str {
-        "# Trust anchor / root authority information\ninterface org.avocado.RootAuthority\n\ntype TrustedKey (\n    keyId: string,\n    keyType: string,\n    roles: []string\n)\n\ntype RootAuthorityInfo (\n    version: int,\n    expires: string,\n    keys: []TrustedKey\n)\n\n# Show the trusted signing keys for this device\nmethod Show() -> (authority: ?RootAuthorityInfo)\n\nerror NoRootAuthority ()\nerror ParseFailed (reason: string)\n"
+        "# Trust anchor / root authority information\ninterface org.avocado.RootAuthority\n\ntype TrustedKey (\n    keyId: string,\n    keyType: string,\n    roles: []string\n)\n\ntype RootAuthorityInfo (\n    version: int,\n    expires: string,\n    keys: []TrustedKey\n)\n\n# A key trusted to sign extension images. notBefore and notAfter bound its\n# validity, in seconds since the Unix epoch (notAfter exclusive).\ntype SigningKey (\n    keyId: string,\n    publicKey: string,\n    notBefore: ?int,\n    notAfter: ?int\n)\n\n# Signature state of an installed extension image before a key rotation\n# (now) and once it is complete (after): valid, unsigned or invalid.\n# signedBy is the key accepted after the rotation; reason says why the\n# image is invalid then.\ntype ImageTrust (\n    name: string,\n    version: ?string,\n    path: string,\n    now: string,\n    after: string,\n    signedBy: ?string,\n    reason: ?string\n)\n\n# Show the trusted signing keys for this device\nmethod Show() -> (authority: ?RootAuthorityInfo)\n\n# List the keys trusted to sign extension images\nmethod TrustedKeys() -> (keys: []SigningKey)\n\n# Install publicKey (hex-encoded ed25519) as an extension signing key, valid\n# for validDays when set, and end the validity of the key whose id starts\n# with retire overlapDays from now. Installed images are re-verified as of\n# checkedAt, when the retired key expires. With dryRun nothing is saved.\nmethod Rotate(publicKey: string, retire: ?string, overlapDays: ?int, validDays: ?int, dryRun: bool) -> (added: SigningKey, retired: ?SigningKey, checkedAt: int, images: []ImageTrust)\n\nerror NoRootAuthority ()\nerror ParseFailed (reason: string)\nerror TrustFailed (reason: string)\n"
     }
     fn get_name(&self) -> &'static str {
         "org.avocado.RootAuthority"
@@ -253,7 +402,32 @@ impl varlink::Interface for VarlinkInterfaceProxy {
     fn call(&self, call: &mut varlink::Call) -> varlink::Result<()> {
         let req = call.request.unwrap();
         match req.method.as_ref() {
+            "org.avocado.RootAuthority.Rotate" => {
+                if let Some(args) = req.parameters.clone() {
+                    let args: Rotate_Args = match serde_json::from_value(args) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            let es = format!("{}", e);
+                            let _ = call.reply_invalid_parameter(es.clone());
+                            return Err(varlink::context!(varlink::ErrorKind::SerdeJsonDe(es)));
+                        }
+                    };
+                    self.inner.rotate(
+                        call as &mut dyn Call_Rotate,
+                        args.r#publicKey,
+                        args.r#retire,
+                        args.r#overlapDays,
+                        args.r#validDays,
+                        args.r#dryRun,
+                    )
+                } else {
+                    call.reply_invalid_parameter("parameters".into())
+                }
+            }
             "org.avocado.RootAuthority.Show" => self.inner.show(call as &mut dyn Call_Show),
+            "org.avocado.RootAuthority.TrustedKeys" => {
+                self.inner.trusted_keys(call as &mut dyn Call_TrustedKeys)
+            }
             m => call.reply_method_not_found(String::from(m)),
         }
     }
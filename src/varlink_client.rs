@@ -1,4 +1,7 @@
+use crate::commands::ext::display::type_label;
+use crate::diagnostics::{self, Diagnostic};
 use crate::output::OutputManager;
+use crate::service::types::{ExtensionDetail, ExtensionStatus};
 use crate::varlink::{
     org_avocado_Extensions as vl_ext, org_avocado_Hitl as vl_hitl,
     org_avocado_RootAuthority as vl_ra, org_avocado_Runtimes as vl_rt,
@@ -17,49 +20,108 @@ pub fn connect_or_exit(address: &str, output: &OutputManager) -> Arc<RwLock<Conn
     match varlink::Connection::with_address(address) {
         Ok(conn) => conn,
         Err(e) => {
-            output.error(
+            output.error_with(
                 "Daemon Not Running",
-                &format!(
-                    "Cannot connect to avocadoctl daemon at {address}: {e}\n   \
-                     Start it with: systemctl start avocadoctl"
+                &format!("Cannot connect to avocadoctl daemon at {address}: {e}"),
+                &Diagnostic::new(
+                    diagnostics::DAEMON_UNAVAILABLE,
+                    Some("start it with: systemctl start avocadoctl".to_string()),
                 ),
             );
-            std::process::exit(1);
+            output.exit(1);
         }
     }
 }
 
+/// Whether the daemon socket at `address` accepts a connection.
+pub fn daemon_reachable(address: &str) -> bool {
+    varlink::Connection::with_address(address).is_ok()
+}
+
 /// Print an RPC error and exit with code 1.
 pub fn exit_with_rpc_error(
     err: impl std::fmt::Display + std::fmt::Debug,
     output: &OutputManager,
 ) -> ! {
+    let diagnostic = diagnostics::diagnose_remote(&err.to_string());
     if output.is_verbose() {
-        output.error("RPC Error", &format!("{err:?}"));
+        output.error_with("RPC Error", &format!("{err:?}"), &diagnostic);
     } else {
-        output.error("RPC Error", &err.to_string());
+        output.error_with("RPC Error", &err.to_string(), &diagnostic);
     }
-    std::process::exit(1);
+    output.exit(1);
 }
 
-// ── Log output helpers ───────────────────────────────────────────────────────
+// ── Extension output helpers ─────────────────────────────────────────────────
 
-/// Print a single log message from a streaming varlink reply.
-/// Re-colorizes `[INFO]` and `[SUCCESS]` prefixes for the caller's terminal.
-pub fn print_single_log(message: &str, output: &OutputManager) {
-    if message.is_empty() {
-        return;
-    }
-    if let Some(rest) = message.strip_prefix("[INFO] ") {
-        output.log_info(rest);
-    } else if let Some(rest) = message.strip_prefix("[SUCCESS] ") {
-        output.log_success(rest);
-    } else {
-        println!("{message}");
-    }
+/// The entries of a detailed `List` reply as the service layer describes them.
+pub fn details_from_varlink(extensions: Vec<vl_ext::Extension>) -> Vec<ExtensionDetail> {
+    extensions
+        .into_iter()
+        .map(|e| ExtensionDetail {
+            name: e.name,
+            version: e.version,
+            path: e.path,
+            is_sysext: e.isSysext,
+            is_confext: e.isConfext,
+            is_directory: e.isDirectory,
+            scopes: e.scopes,
+            origin: e.origin,
+            enabled: e.enabled,
+        })
+        .collect()
 }
 
-// ── Extension output helpers ─────────────────────────────────────────────────
+/// The entries of a `Status` reply as the service layer describes them.
+pub fn statuses_from_varlink(extensions: Vec<vl_ext::ExtensionStatus>) -> Vec<ExtensionStatus> {
+    extensions
+        .into_iter()
+        .map(|s| ExtensionStatus {
+            name: s.name,
+            version: s.version,
+            is_sysext: s.isSysext,
+            is_confext: s.isConfext,
+            is_merged: s.isMerged,
+            origin: s.origin,
+            image_id: s.imageId,
+            image_type: s.imageType,
+            reboot_required: s.rebootRequired,
+            build_id: s.buildId,
+            git_sha: s.gitSha,
+            build_date: s.buildDate,
+            eol: s.eol,
+            notes: s.notes,
+            path: s.path,
+            partitions: s.partitions.map(|partitions| {
+                partitions
+                    .into_iter()
+                    .map(|p| crate::ddi::PartitionStatus {
+                        designator: p.designator,
+                        hierarchy: p.hierarchy,
+                        architecture: p.architecture,
+                        uuid: p.uuid,
+                        label: p.label,
+                        size: p.size.max(0) as u64,
+                        verity: p.verity,
+                    })
+                    .collect()
+            }),
+            latest_version: s.latestVersion,
+            update_available: s.updateAvailable,
+            loop_device: s.loopDevice.map(|l| crate::loop_device::LoopDevice {
+                device: l.device,
+                backing_file: l.backingFile,
+                read_only: l.readOnly,
+                verity: l.verity,
+            }),
+            safe_mode_skipped: s.safeModeSkipped,
+            scopes: s.scopes,
+            applicable: s.applicable,
+            merged_since: s.mergedSince,
+            aliases: s.aliases,
+        })
+        .collect()
+}
 
 pub fn print_extensions(extensions: &[vl_ext::Extension], output: &OutputManager) {
     if output.is_json() {
@@ -67,7 +129,7 @@ pub fn print_extensions(extensions: &[vl_ext::Extension], output: &OutputManager
             Ok(json) => println!("{json}"),
             Err(e) => {
                 output.error("Output", &format!("JSON serialization failed: {e}"));
-                std::process::exit(1);
+                output.exit(1);
             }
         }
         return;
@@ -94,23 +156,10 @@ pub fn print_extensions(extensions: &[vl_ext::Extension], output: &OutputManager
             None => ext.name.clone(),
         };
 
-        let mut types = Vec::new();
-        if ext.isSysext {
-            types.push("sys");
-        }
-        if ext.isConfext {
-            types.push("conf");
-        }
-        let type_str = if types.is_empty() {
-            "?".to_string()
-        } else {
-            types.join("+")
-        };
-
         println!(
             "{:<nw$} {:<12} {}",
             versioned_name,
-            type_str,
+            type_label(ext.isSysext, ext.isConfext),
             ext.path,
             nw = name_width
         );
@@ -120,76 +169,67 @@ pub fn print_extensions(extensions: &[vl_ext::Extension], output: &OutputManager
     println!("Total: {} extension(s)", extensions.len());
 }
 
-pub fn print_extension_status(extensions: &[vl_ext::ExtensionStatus], output: &OutputManager) {
+pub fn print_auto_refresh_stats(stats: &vl_ext::AutoRefreshStats, output: &OutputManager) {
     if output.is_json() {
-        match serde_json::to_string(extensions) {
-            Ok(json) => println!("{json}"),
-            Err(e) => {
-                output.error("Output", &format!("JSON serialization failed: {e}"));
-                std::process::exit(1);
-            }
-        }
+        output.result_fields(stats);
         return;
     }
 
-    if extensions.is_empty() {
-        println!("No extensions currently merged.");
-        return;
-    }
-
-    let name_width = extensions
-        .iter()
-        .map(|e| e.name.len() + e.version.as_ref().map(|v| v.len() + 1).unwrap_or(0))
-        .max()
-        .unwrap_or(9)
-        .max(9);
-
     println!(
-        "{:<nw$} {:<12} {:<8} Origin",
-        "Extension",
-        "Type",
-        "Merged",
-        nw = name_width
+        "Auto-refresh: {}",
+        if stats.enabled { "enabled" } else { "disabled" }
     );
-    println!("{}", "=".repeat(name_width + 1 + 12 + 1 + 8 + 1 + 20));
-
-    for ext in extensions {
-        let versioned_name = match &ext.version {
-            Some(v) => format!("{}-{}", ext.name, v),
-            None => ext.name.clone(),
-        };
+    println!("  Triggers:   {}", stats.triggers);
+    println!("  Coalesced:  {}", stats.coalesced);
+    println!("  Throttled:  {}", stats.throttled);
+    println!("  Refreshes:  {}", stats.refreshes);
+    println!("  Skipped:    {}", stats.skipped);
+    println!("  Deferred:   {}", stats.deferred);
+    println!("  Queued:     {}", stats.queued);
+    println!("  Failures:   {}", stats.failures);
+    match stats.lastRefresh {
+        Some(t) => println!("  Last:       {t} (unix time)"),
+        None => println!("  Last:       never"),
+    }
+}
 
-        let mut types = Vec::new();
-        if ext.isSysext {
-            types.push("sys");
-        }
-        if ext.isConfext {
-            types.push("conf");
+// ── HITL output helpers ──────────────────────────────────────────────────────
+
+/// Print the per-extension results of a HITL mount and the error of each
+/// extension that failed. Returns whether all extensions mounted.
+pub fn print_mount_results(results: Vec<vl_hitl::MountResult>, output: &OutputManager) -> bool {
+    use crate::commands::hitl::{MountReport, MountStatus};
+
+    let reports: Vec<MountReport> = results
+        .into_iter()
+        .map(|r| MountReport {
+            status: MountStatus::parse(&r.status).unwrap_or(MountStatus::Failed),
+            extension: r.extension,
+            server: r.server,
+            error: r.error,
+            elapsed_ms: r.elapsedMs.max(0) as u64,
+        })
+        .collect();
+    crate::commands::hitl::print_mount_summary(&reports, output);
+    for report in &reports {
+        if let Some(e) = &report.error {
+            output.error(
+                "HITL Mount",
+                &format!("Failed to mount extension {}: {e}", report.extension),
+            );
         }
-        let type_str = if types.is_empty() {
-            "?".to_string()
-        } else {
-            let base = types.join("+");
-            if ext.imageType.as_deref() == Some("kab") {
-                format!("kab:{base}")
-            } else {
-                base
-            }
-        };
-
-        let merged_str = if ext.isMerged { "yes" } else { "no" };
-        let origin = ext.origin.as_deref().unwrap_or("-");
-
-        println!("{versioned_name:<name_width$} {type_str:<12} {merged_str:<8} {origin}");
     }
-
-    println!();
-    let merged_count = extensions.iter().filter(|e| e.isMerged).count();
-    println!(
-        "Total: {} extension(s), {} merged",
-        extensions.len(),
-        merged_count
-    );
+    let failed = reports
+        .iter()
+        .filter(|r| r.status != MountStatus::Mounted)
+        .count();
+    if failed > 0 {
+        output.error(
+            "HITL Mount",
+            &format!("{failed} of {} extension(s) failed to mount", reports.len()),
+        );
+    }
+    failed == 0
 }
 
 // ── Runtime output helpers ────────────────────────────────────────────────────
@@ -200,7 +240,7 @@ pub fn print_runtimes(runtimes: &[vl_rt::Runtime], output: &OutputManager) {
             Ok(json) => println!("{json}"),
             Err(e) => {
                 output.error("Output", &format!("JSON serialization failed: {e}"));
-                std::process::exit(1);
+                output.exit(1);
             }
         }
         return;
@@ -232,7 +272,7 @@ pub fn print_runtime_detail(rt: &vl_rt::Runtime, output: &OutputManager) {
             Ok(json) => println!("{json}"),
             Err(e) => {
                 output.error("Output", &format!("JSON serialization failed: {e}"));
-                std::process::exit(1);
+                output.exit(1);
             }
         }
         return;
@@ -295,7 +335,7 @@ pub fn print_metadata_list(entries: &[vl_rt::MetadataEntry], output: &OutputMana
             Ok(json) => println!("{json}"),
             Err(e) => {
                 output.error("Output", &format!("JSON serialization failed: {e}"));
-                std::process::exit(1);
+                output.exit(1);
             }
         }
         return;
@@ -321,13 +361,43 @@ pub fn print_metadata_list(entries: &[vl_rt::MetadataEntry], output: &OutputMana
 
 // ── Root authority output helper ──────────────────────────────────────────────
 
+pub fn signing_key_from_varlink(key: vl_ra::SigningKey) -> crate::trust::SigningKey {
+    crate::trust::SigningKey {
+        keyid: key.keyId,
+        public: key.publicKey,
+        not_before: key.notBefore.map(|t| t as u64),
+        not_after: key.notAfter.map(|t| t as u64),
+    }
+}
+
+pub fn rotation_from_varlink(reply: vl_ra::Rotate_Reply) -> crate::service::types::RotationResult {
+    crate::service::types::RotationResult {
+        added: signing_key_from_varlink(reply.added),
+        retired: reply.retired.map(signing_key_from_varlink),
+        checked_at: reply.checkedAt as u64,
+        images: reply
+            .images
+            .into_iter()
+            .map(|i| crate::service::types::ImageTrust {
+                name: i.name,
+                version: i.version,
+                path: i.path,
+                now: i.now,
+                after: i.after,
+                signed_by: i.signedBy,
+                reason: i.reason,
+            })
+            .collect(),
+    }
+}
+
 pub fn print_root_authority(info: &Option<vl_ra::RootAuthorityInfo>, output: &OutputManager) {
     if output.is_json() {
         match serde_json::to_string(info) {
             Ok(json) => println!("{json}"),
             Err(e) => {
                 output.error("Output", &format!("JSON serialization failed: {e}"));
-                std::process::exit(1);
+                output.exit(1);
             }
         }
         return;
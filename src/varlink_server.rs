@@ -1,7 +1,10 @@
 #![allow(non_snake_case)]
 
+use crate::auto_refresh;
 use crate::config::Config;
 use crate::manifest::RuntimeManifest;
+use crate::merge_target::MergeTarget;
+use crate::output::OutputManager;
 use crate::service;
 use crate::service::error::AvocadoError;
 use crate::varlink::{
@@ -20,11 +23,13 @@ use varlink::CallTrait;
 /// send a final reply (success or error).
 ///
 /// The `reply_fn` sends one intermediate message. The `done_fn` sends the
-/// final success reply. The `error_fn` sends an error reply.
-fn drain_stream<C, R, D, E>(
+/// final success reply, given the worker's result. The `error_fn` sends an
+/// error reply.
+fn drain_stream<C, T, R, D, E>(
+    log: &OutputManager,
     call: &mut C,
     rx: mpsc::Receiver<String>,
-    handle: thread::JoinHandle<Result<(), AvocadoError>>,
+    handle: thread::JoinHandle<Result<T, AvocadoError>>,
     reply_fn: R,
     done_fn: D,
     error_fn: E,
@@ -32,7 +37,7 @@ fn drain_stream<C, R, D, E>(
 where
     C: CallTrait + ?Sized,
     R: Fn(&mut C, String) -> varlink::Result<()>,
-    D: Fn(&mut C) -> varlink::Result<()>,
+    D: Fn(&mut C, T) -> varlink::Result<()>,
     E: Fn(&mut C, AvocadoError) -> varlink::Result<()>,
 {
     call.set_continues(true);
@@ -47,9 +52,9 @@ where
     });
     call.set_continues(false);
     match result {
-        Ok(()) => done_fn(call),
+        Ok(value) => done_fn(call, value),
         Err(e) => {
-            eprintln!("  Error: {e}");
+            log.error("Varlink Call", &e.to_string());
             error_fn(call, e)
         }
     }
@@ -59,6 +64,9 @@ where
 
 pub struct ExtensionsHandler {
     config: Config,
+    auto_refresh: auto_refresh::SharedStats,
+    /// Where the daemon reports the errors of the calls it serves
+    log: OutputManager,
 }
 
 macro_rules! map_ext_error {
@@ -70,13 +78,87 @@ macro_rules! map_ext_error {
             AvocadoError::ConfigurationError { message } => {
                 $call.reply_configuration_error(message)
             }
+            AvocadoError::PlanDrifted { reasons } => $call.reply_plan_drifted(reasons),
             e => $call.reply_command_failed("avocadoctl".to_string(), e.to_string()),
         }
     };
 }
 
+fn detail_to_varlink(e: service::types::ExtensionDetail) -> vl_ext::Extension {
+    vl_ext::Extension {
+        r#name: e.name,
+        r#version: e.version,
+        r#path: e.path,
+        r#isSysext: e.is_sysext,
+        r#isConfext: e.is_confext,
+        r#isDirectory: e.is_directory,
+        r#scopes: e.scopes,
+        r#origin: e.origin,
+        r#enabled: e.enabled,
+    }
+}
+
+fn status_to_varlink(s: service::types::ExtensionStatus) -> vl_ext::ExtensionStatus {
+    vl_ext::ExtensionStatus {
+        r#name: s.name,
+        r#version: s.version,
+        r#isSysext: s.is_sysext,
+        r#isConfext: s.is_confext,
+        r#isMerged: s.is_merged,
+        r#origin: s.origin,
+        r#imageId: s.image_id,
+        r#imageType: s.image_type,
+        r#rebootRequired: s.reboot_required,
+        r#buildId: s.build_id,
+        r#gitSha: s.git_sha,
+        r#buildDate: s.build_date,
+        r#eol: s.eol,
+        r#notes: s.notes,
+        r#path: s.path,
+        r#partitions: s.partitions.map(|partitions| {
+            partitions
+                .into_iter()
+                .map(|p| vl_ext::ImagePartition {
+                    r#designator: p.designator,
+                    r#hierarchy: p.hierarchy,
+                    r#architecture: p.architecture,
+                    r#uuid: p.uuid,
+                    r#label: p.label,
+                    r#size: p.size as i64,
+                    r#verity: p.verity,
+                })
+                .collect()
+        }),
+        r#latestVersion: s.latest_version,
+        r#updateAvailable: s.update_available,
+        r#loopDevice: s.loop_device.map(|l| vl_ext::LoopDevice {
+            r#device: l.device,
+            r#backingFile: l.backing_file,
+            r#readOnly: l.read_only,
+            r#verity: l.verity,
+        }),
+        r#safeModeSkipped: s.safe_mode_skipped,
+        r#scopes: s.scopes,
+        r#applicable: s.applicable,
+        r#mergedSince: s.merged_since,
+        r#aliases: s.aliases,
+    }
+}
+
 impl vl_ext::VarlinkInterface for ExtensionsHandler {
-    fn list(&self, call: &mut dyn vl_ext::Call_List) -> varlink::Result<()> {
+    fn list(
+        &self,
+        call: &mut dyn vl_ext::Call_List,
+        r#detailed: Option<bool>,
+    ) -> varlink::Result<()> {
+        if detailed.unwrap_or(false) {
+            return match service::ext::list_extensions_detailed(&self.config) {
+                Ok(extensions) => {
+                    call.reply(extensions.into_iter().map(detail_to_varlink).collect())
+                }
+                Err(e) => map_ext_error!(call, e),
+            };
+        }
         match service::ext::list_extensions(&self.config) {
             Ok(extensions) => {
                 let vl: Vec<vl_ext::Extension> = extensions
@@ -88,6 +170,9 @@ impl vl_ext::VarlinkInterface for ExtensionsHandler {
                         r#isSysext: e.is_sysext,
                         r#isConfext: e.is_confext,
                         r#isDirectory: e.is_directory,
+                        r#scopes: None,
+                        r#origin: None,
+                        r#enabled: None,
                     })
                     .collect();
                 call.reply(vl)
@@ -96,19 +181,31 @@ impl vl_ext::VarlinkInterface for ExtensionsHandler {
         }
     }
 
-    fn merge(&self, call: &mut dyn vl_ext::Call_Merge) -> varlink::Result<()> {
+    fn merge(
+        &self,
+        call: &mut dyn vl_ext::Call_Merge,
+        r#target: Option<String>,
+        r#keepGoing: Option<bool>,
+    ) -> varlink::Result<()> {
+        let target = match target.as_deref().map(MergeTarget::parse).transpose() {
+            Ok(target) => target,
+            Err(message) => return call.reply_configuration_error(message),
+        };
+        let config =
+            crate::commands::ext::with_keep_going(&self.config, keepGoing.unwrap_or(false));
         if call.wants_more() {
-            let (rx, handle) = service::ext::merge_extensions_streaming(&self.config);
+            let (rx, handle) = service::ext::merge_extensions_streaming(&config, target);
             drain_stream(
+                &self.log,
                 call,
                 rx,
                 handle,
                 |c, msg| c.reply(msg, false),
-                |c| c.reply(String::new(), true),
+                |c, ()| c.reply(String::new(), true),
                 |c, e| map_ext_error!(c, e),
             )
         } else {
-            match service::ext::merge_extensions(&self.config) {
+            match service::ext::merge_extensions(&config, target) {
                 Ok(log) => call.reply(log.join("\n"), true),
                 Err(e) => map_ext_error!(call, e),
             }
@@ -123,11 +220,12 @@ impl vl_ext::VarlinkInterface for ExtensionsHandler {
         if call.wants_more() {
             let (rx, handle) = service::ext::unmerge_extensions_streaming(unmount.unwrap_or(false));
             drain_stream(
+                &self.log,
                 call,
                 rx,
                 handle,
                 |c, msg| c.reply(msg, false),
-                |c| c.reply(String::new(), true),
+                |c, ()| c.reply(String::new(), true),
                 |c, e| map_ext_error!(c, e),
             )
         } else {
@@ -138,20 +236,49 @@ impl vl_ext::VarlinkInterface for ExtensionsHandler {
         }
     }
 
-    fn refresh(&self, call: &mut dyn vl_ext::Call_Refresh) -> varlink::Result<()> {
+    fn refresh(
+        &self,
+        call: &mut dyn vl_ext::Call_Refresh,
+        r#softReboot: Option<bool>,
+        r#force: Option<bool>,
+        r#keepGoing: Option<bool>,
+    ) -> varlink::Result<()> {
+        let soft_reboot = softReboot.unwrap_or(false);
+        let force = force.unwrap_or(false);
+        let config =
+            crate::commands::ext::with_keep_going(&self.config, keepGoing.unwrap_or(false));
         if call.wants_more() {
-            let (rx, handle) = service::ext::refresh_extensions_streaming(&self.config);
-            drain_stream(
-                call,
-                rx,
-                handle,
-                |c, msg| c.reply(msg, false),
-                |c| c.reply(String::new(), true),
-                |c, e| map_ext_error!(c, e),
-            )
+            if soft_reboot {
+                let (rx, handle) = service::ext::soft_reboot_refresh_streaming(&self.config);
+                drain_stream(
+                    &self.log,
+                    call,
+                    rx,
+                    handle,
+                    |c, msg| c.reply(msg, false, None),
+                    |c, ()| c.reply(String::new(), true, None),
+                    |c, e| map_ext_error!(c, e),
+                )
+            } else {
+                let (rx, handle) = service::ext::refresh_if_changed_streaming(&config, force);
+                drain_stream(
+                    &self.log,
+                    call,
+                    rx,
+                    handle,
+                    |c, msg| c.reply(msg, false, None),
+                    |c, refreshed| c.reply(String::new(), true, Some(!refreshed)),
+                    |c, e| map_ext_error!(c, e),
+                )
+            }
+        } else if soft_reboot {
+            match service::ext::soft_reboot_refresh(&self.config) {
+                Ok(log) => call.reply(log.join("\n"), true, None),
+                Err(e) => map_ext_error!(call, e),
+            }
         } else {
-            match service::ext::refresh_extensions(&self.config) {
-                Ok(log) => call.reply(log.join("\n"), true),
+            match service::ext::refresh_if_changed(&config, force) {
+                Ok((log, refreshed)) => call.reply(log.join("\n"), true, Some(!refreshed)),
                 Err(e) => map_ext_error!(call, e),
             }
         }
@@ -162,14 +289,46 @@ impl vl_ext::VarlinkInterface for ExtensionsHandler {
         call: &mut dyn vl_ext::Call_Enable,
         r#extensions: Vec<String>,
         r#osRelease: Option<String>,
+        r#force: Option<bool>,
     ) -> varlink::Result<()> {
         let ext_refs: Vec<&str> = extensions.iter().map(|s| s.as_str()).collect();
-        match service::ext::enable_extensions(osRelease.as_deref(), &ext_refs, &self.config) {
+        match service::ext::enable_extensions(
+            osRelease.as_deref(),
+            &ext_refs,
+            force.unwrap_or(false),
+            &self.config,
+        ) {
             Ok(result) => call.reply(result.enabled as i64, result.failed as i64),
             Err(e) => map_ext_error!(call, e),
         }
     }
 
+    fn apply(
+        &self,
+        call: &mut dyn vl_ext::Call_Apply,
+        r#enable: Vec<String>,
+        r#disable: Vec<String>,
+        r#update: Vec<String>,
+        r#osRelease: Option<String>,
+        r#force: Option<bool>,
+    ) -> varlink::Result<()> {
+        let manifest = crate::transaction::TransactionManifest {
+            os_release: osRelease,
+            enable,
+            disable,
+            update,
+            force: force.unwrap_or(false),
+        };
+        match service::ext::apply_transaction(&manifest, &self.config) {
+            Ok(result) => call.reply(
+                result.linked as i64,
+                result.unlinked as i64,
+                result.refreshed,
+            ),
+            Err(e) => map_ext_error!(call, e),
+        }
+    }
+
     fn disable(
         &self,
         call: &mut dyn vl_ext::Call_Disable,
@@ -190,13 +349,158 @@ impl vl_ext::VarlinkInterface for ExtensionsHandler {
         }
     }
 
-    fn status(&self, call: &mut dyn vl_ext::Call_Status) -> varlink::Result<()> {
-        match service::ext::status_extensions(&self.config) {
-            Ok(extensions) => call.reply(extensions),
+    fn status(
+        &self,
+        call: &mut dyn vl_ext::Call_Status,
+        r#updates_only: Option<bool>,
+    ) -> varlink::Result<()> {
+        match service::ext::status_extensions(&self.config, updates_only.unwrap_or(false)) {
+            Ok(extensions) => call.reply(extensions.into_iter().map(status_to_varlink).collect()),
+            Err(e) => map_ext_error!(call, e),
+        }
+    }
+
+    fn snapshot(&self, call: &mut dyn vl_ext::Call_Snapshot) -> varlink::Result<()> {
+        match service::ext::capture_snapshot(&self.config) {
+            Ok(snapshot) => call.reply(snapshot.to_json()),
+            Err(e) => map_ext_error!(call, e),
+        }
+    }
+
+    fn plan(&self, call: &mut dyn vl_ext::Call_Plan) -> varlink::Result<()> {
+        match service::ext::create_plan(&self.config) {
+            Ok(plan) => call.reply(plan.to_json()),
+            Err(e) => map_ext_error!(call, e),
+        }
+    }
+
+    fn apply_plan(
+        &self,
+        call: &mut dyn vl_ext::Call_ApplyPlan,
+        r#plan: String,
+    ) -> varlink::Result<()> {
+        let plan = match crate::plan::ChangePlan::from_json(&plan) {
+            Ok(plan) => plan,
+            Err(e) => return call.reply_configuration_error(format!("Invalid plan: {e}")),
+        };
+        if call.wants_more() {
+            let (rx, handle) = service::ext::apply_plan_streaming(&self.config, plan);
+            drain_stream(
+                &self.log,
+                call,
+                rx,
+                handle,
+                |c, msg| c.reply(msg, false),
+                |c, ()| c.reply(String::new(), true),
+                |c, e| map_ext_error!(c, e),
+            )
+        } else {
+            match service::ext::apply_plan(&self.config, plan) {
+                Ok(log) => call.reply(log.join("\n"), true),
+                Err(e) => map_ext_error!(call, e),
+            }
+        }
+    }
+
+    fn audit(&self, call: &mut dyn vl_ext::Call_Audit) -> varlink::Result<()> {
+        match service::ext::audit_report(&self.config) {
+            Ok(report) => call.reply(report.to_json()),
+            Err(e) => map_ext_error!(call, e),
+        }
+    }
+
+    fn prefetch(
+        &self,
+        call: &mut dyn vl_ext::Call_Prefetch,
+        r#url: Option<String>,
+        r#authToken: Option<String>,
+    ) -> varlink::Result<()> {
+        match service::ext::prefetch(&self.config, url.as_deref(), authToken.as_deref(), false) {
+            Ok((record, already_active)) => call.reply(
+                record.runtime_id,
+                record.name,
+                record.version,
+                already_active,
+            ),
+            Err(e) => map_ext_error!(call, e),
+        }
+    }
+
+    fn upgrade(
+        &self,
+        call: &mut dyn vl_ext::Call_Upgrade,
+        r#names: Vec<String>,
+        r#url: Option<String>,
+        r#authToken: Option<String>,
+        r#offline: bool,
+        r#dryRun: bool,
+        r#force: Option<bool>,
+    ) -> varlink::Result<()> {
+        match service::ext::upgrade(
+            &self.config,
+            &names,
+            url.as_deref(),
+            authToken.as_deref(),
+            offline,
+            dryRun,
+            force.unwrap_or(false),
+            false,
+        ) {
+            Ok(result) => call.reply(
+                result
+                    .decisions
+                    .into_iter()
+                    .map(|d| vl_ext::ExtensionUpgrade {
+                        r#name: d.name,
+                        r#current: d.current,
+                        r#available: d.available,
+                        r#policy: d.policy.as_str().to_string(),
+                        r#action: d.action.as_str().to_string(),
+                    })
+                    .collect(),
+                result.runtime_id,
+                Some(result.queued),
+                result.queued_until.map(|t| t as i64),
+            ),
+            Err(e) => map_ext_error!(call, e),
+        }
+    }
+
+    fn prune_os_releases(
+        &self,
+        call: &mut dyn vl_ext::Call_PruneOsReleases,
+        r#keep: Vec<String>,
+        r#dryRun: bool,
+    ) -> varlink::Result<()> {
+        match service::ext::prune_os_releases(&keep, dryRun) {
+            Ok(result) => call.reply(result.current, result.kept, result.removed),
             Err(e) => map_ext_error!(call, e),
         }
     }
 
+    fn auto_refresh_status(
+        &self,
+        call: &mut dyn vl_ext::Call_AutoRefreshStatus,
+    ) -> varlink::Result<()> {
+        let stats = self
+            .auto_refresh
+            .lock()
+            .map(|s| s.clone())
+            .unwrap_or_default();
+        call.reply(vl_ext::AutoRefreshStats {
+            r#enabled: stats.enabled,
+            r#triggers: stats.triggers as i64,
+            r#coalesced: stats.coalesced as i64,
+            r#throttled: stats.throttled as i64,
+            r#refreshes: stats.refreshes as i64,
+            r#skipped: stats.skipped as i64,
+            r#deferred: stats.deferred as i64,
+            r#queued: stats.queued as i64,
+            r#failures: stats.failures as i64,
+            r#lastRefresh: stats.last_refresh.map(|t| t as i64),
+        })
+    }
+
     fn set_enabled(
         &self,
         call: &mut dyn vl_ext::Call_SetEnabled,
@@ -215,11 +519,13 @@ impl vl_ext::VarlinkInterface for ExtensionsHandler {
 
 pub struct RuntimesHandler {
     config: Config,
+    /// Where the daemon reports the errors of the calls it serves
+    log: OutputManager,
 }
 
 macro_rules! map_rt_error {
-    ($call:expr, $err:expr) => {{
-        eprintln!("  Error: {}", $err);
+    ($log:expr, $call:expr, $err:expr) => {{
+        $log.error("Varlink Call", &$err.to_string());
         match $err {
             AvocadoError::RuntimeNotFound { id } => $call.reply_runtime_not_found(id),
             AvocadoError::AmbiguousRuntimeId { id, candidates } => {
@@ -279,7 +585,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
                     runtimes.into_iter().map(runtime_entry_to_varlink).collect();
                 call.reply(vl)
             }
-            Err(e) => map_rt_error!(call, e),
+            Err(e) => map_rt_error!(self.log, call, e),
         }
     }
 
@@ -299,17 +605,18 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
                 &self.config,
             ) {
                 Ok((rx, handle)) => drain_stream(
+                    &self.log,
                     call,
                     rx,
                     handle,
                     |c, msg| c.reply(msg, false, None),
-                    |c| {
+                    |c, ()| {
                         let rt = load_active_runtime_varlink(&config);
                         c.reply(String::new(), true, rt)
                     },
-                    |c, e| map_rt_error!(c, e),
+                    |c, e| map_rt_error!(self.log, c, e),
                 ),
-                Err(e) => map_rt_error!(call, e),
+                Err(e) => map_rt_error!(self.log, call, e),
             }
         } else {
             match service::runtime::add_from_url(
@@ -322,7 +629,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
                     let rt = load_active_runtime_varlink(&self.config);
                     call.reply(log.join("\n"), true, rt)
                 }
-                Err(e) => map_rt_error!(call, e),
+                Err(e) => map_rt_error!(self.log, call, e),
             }
         }
     }
@@ -336,17 +643,18 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
             let config = self.config.clone();
             match service::runtime::add_from_manifest_streaming(&manifestPath, &self.config) {
                 Ok((rx, handle)) => drain_stream(
+                    &self.log,
                     call,
                     rx,
                     handle,
                     |c, msg| c.reply(msg, false, None),
-                    |c| {
+                    |c, ()| {
                         let rt = load_active_runtime_varlink(&config);
                         c.reply(String::new(), true, rt)
                     },
-                    |c, e| map_rt_error!(c, e),
+                    |c, e| map_rt_error!(self.log, c, e),
                 ),
-                Err(e) => map_rt_error!(call, e),
+                Err(e) => map_rt_error!(self.log, call, e),
             }
         } else {
             match service::runtime::add_from_manifest(&manifestPath, &self.config) {
@@ -354,7 +662,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
                     let rt = load_active_runtime_varlink(&self.config);
                     call.reply(log.join("\n"), true, rt)
                 }
-                Err(e) => map_rt_error!(call, e),
+                Err(e) => map_rt_error!(self.log, call, e),
             }
         }
     }
@@ -362,7 +670,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
     fn remove(&self, call: &mut dyn vl_rt::Call_Remove, r#id: String) -> varlink::Result<()> {
         match service::runtime::remove_runtime(&id, &self.config) {
             Ok(()) => call.reply(),
-            Err(e) => map_rt_error!(call, e),
+            Err(e) => map_rt_error!(self.log, call, e),
         }
     }
 
@@ -371,22 +679,23 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
             let config = self.config.clone();
             match service::runtime::activate_runtime_streaming(&id, &self.config) {
                 Ok(Some((rx, handle))) => drain_stream(
+                    &self.log,
                     call,
                     rx,
                     handle,
                     |c, msg| c.reply(msg, false, None),
-                    |c| {
+                    |c, ()| {
                         let rt = load_active_runtime_varlink(&config);
                         c.reply(String::new(), true, rt)
                     },
-                    |c, e| map_rt_error!(c, e),
+                    |c, e| map_rt_error!(self.log, c, e),
                 ),
                 Ok(None) => {
                     // Already active, return current runtime info
                     let rt = load_active_runtime_varlink(&self.config);
                     call.reply(String::new(), true, rt)
                 }
-                Err(e) => map_rt_error!(call, e),
+                Err(e) => map_rt_error!(self.log, call, e),
             }
         } else {
             match service::runtime::activate_runtime(&id, &self.config) {
@@ -394,7 +703,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
                     let rt = load_active_runtime_varlink(&self.config);
                     call.reply(log.join("\n"), true, rt)
                 }
-                Err(e) => map_rt_error!(call, e),
+                Err(e) => map_rt_error!(self.log, call, e),
             }
         }
     }
@@ -406,7 +715,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
     ) -> varlink::Result<()> {
         match service::runtime::inspect_runtime(id.as_deref(), &self.config) {
             Ok(entry) => call.reply(runtime_entry_to_varlink(entry)),
-            Err(e) => map_rt_error!(call, e),
+            Err(e) => map_rt_error!(self.log, call, e),
         }
     }
 
@@ -419,7 +728,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
     ) -> varlink::Result<()> {
         match service::runtime::metadata_set(&id, &key, &value, &self.config) {
             Ok(()) => call.reply(),
-            Err(e) => map_rt_error!(call, e),
+            Err(e) => map_rt_error!(self.log, call, e),
         }
     }
 
@@ -431,7 +740,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
     ) -> varlink::Result<()> {
         match service::runtime::metadata_get(&id, &key, &self.config) {
             Ok(value) => call.reply(value),
-            Err(e) => map_rt_error!(call, e),
+            Err(e) => map_rt_error!(self.log, call, e),
         }
     }
 
@@ -451,7 +760,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
                     .collect();
                 call.reply(vl_entries)
             }
-            Err(e) => map_rt_error!(call, e),
+            Err(e) => map_rt_error!(self.log, call, e),
         }
     }
 
@@ -463,7 +772,7 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
     ) -> varlink::Result<()> {
         match service::runtime::metadata_delete(&id, &key, &self.config) {
             Ok(()) => call.reply(),
-            Err(e) => map_rt_error!(call, e),
+            Err(e) => map_rt_error!(self.log, call, e),
         }
     }
 
@@ -473,14 +782,16 @@ impl vl_rt::VarlinkInterface for RuntimesHandler {
                 r#removedRuntimes: result.removed_runtimes,
                 r#removedImages: result.removed_images,
             }),
-            Err(e) => map_rt_error!(call, e),
+            Err(e) => map_rt_error!(self.log, call, e),
         }
     }
 }
 
 // ── HITL handler ────────────────────────────────────────────────────
 
-pub struct HitlHandler;
+pub struct HitlHandler {
+    config: Config,
+}
 
 macro_rules! map_hitl_error {
     ($call:expr, $err:expr) => {
@@ -491,6 +802,9 @@ macro_rules! map_hitl_error {
             AvocadoError::UnmountFailed { extension, reason } => {
                 $call.reply_unmount_failed(extension, reason)
             }
+            AvocadoError::QuiesceFailed { extension, reason } => {
+                $call.reply_quiesce_failed(extension, reason)
+            }
             e => $call.reply_mount_failed("unknown".to_string(), e.to_string()),
         }
     };
@@ -500,12 +814,60 @@ impl vl_hitl::VarlinkInterface for HitlHandler {
     fn mount(
         &self,
         call: &mut dyn vl_hitl::Call_Mount,
-        r#serverIp: String,
+        r#serverIp: Option<String>,
         r#serverPort: Option<String>,
         r#extensions: Vec<String>,
+        r#mountType: Option<String>,
+        r#failFast: Option<bool>,
+        r#controlPort: Option<i64>,
     ) -> varlink::Result<()> {
-        match service::hitl::mount(&serverIp, serverPort.as_deref(), &extensions) {
-            Ok(()) => call.reply(),
+        let mount_type = match mountType
+            .as_deref()
+            .map(crate::hitl_health::MountType::parse)
+        {
+            None => crate::hitl_health::MountType::Auto,
+            Some(Some(mount_type)) => mount_type,
+            Some(None) => {
+                return call.reply_mount_failed(
+                    "unknown".to_string(),
+                    format!(
+                        "Invalid mount type '{}': expected sysext, confext or auto",
+                        mountType.unwrap_or_default()
+                    ),
+                )
+            }
+        };
+        let control_port = match controlPort.map(u16::try_from) {
+            None => None,
+            Some(Ok(port)) if port > 0 => Some(port),
+            Some(_) => {
+                return call.reply_mount_failed(
+                    "unknown".to_string(),
+                    format!("Invalid control port {}", controlPort.unwrap_or_default()),
+                )
+            }
+        };
+        let config = crate::commands::hitl::with_control_port(&self.config, control_port);
+        match service::hitl::mount(
+            &config,
+            serverIp.as_deref(),
+            serverPort.as_deref(),
+            &extensions,
+            mount_type,
+            crate::commands::hitl::FailurePolicy::from_fail_fast(failFast.unwrap_or(false)),
+        ) {
+            Ok(reports) => call.reply(
+                reports
+                    .into_iter()
+                    .map(|r| vl_hitl::MountResult {
+                        r#extension: r.extension,
+                        r#server: r.server,
+                        r#status: r.status.as_str().to_string(),
+                        r#error: r.error,
+                        r#elapsedMs: r.elapsed_ms as i64,
+                    })
+                    .collect(),
+            ),
             Err(e) => map_hitl_error!(call, e),
         }
     }
@@ -520,6 +882,28 @@ impl vl_hitl::VarlinkInterface for HitlHandler {
             Err(e) => map_hitl_error!(call, e),
         }
     }
+
+    fn quiesce(
+        &self,
+        call: &mut dyn vl_hitl::Call_Quiesce,
+        r#extensions: Vec<String>,
+    ) -> varlink::Result<()> {
+        match service::hitl::quiesce(&extensions) {
+            Ok(()) => call.reply(),
+            Err(e) => map_hitl_error!(call, e),
+        }
+    }
+
+    fn resume(
+        &self,
+        call: &mut dyn vl_hitl::Call_Resume,
+        r#extensions: Vec<String>,
+    ) -> varlink::Result<()> {
+        match service::hitl::resume(&extensions) {
+            Ok(()) => call.reply(),
+            Err(e) => map_hitl_error!(call, e),
+        }
+    }
 }
 
 // ── Root Authority handler ──────────────────────────────────────────
@@ -553,18 +937,82 @@ impl vl_ra::VarlinkInterface for RootAuthorityHandler {
             Err(e) => call.reply_parse_failed(e.to_string()),
         }
     }
+
+    fn trusted_keys(&self, call: &mut dyn vl_ra::Call_TrustedKeys) -> varlink::Result<()> {
+        match service::root_authority::trusted_keys(&self.config) {
+            Ok(keys) => call.reply(keys.into_iter().map(signing_key_to_varlink).collect()),
+            Err(e) => call.reply_trust_failed(e.to_string()),
+        }
+    }
+
+    fn rotate(
+        &self,
+        call: &mut dyn vl_ra::Call_Rotate,
+        r#publicKey: String,
+        r#retire: Option<String>,
+        r#overlapDays: Option<i64>,
+        r#validDays: Option<i64>,
+        r#dryRun: bool,
+    ) -> varlink::Result<()> {
+        match service::root_authority::rotate(
+            &self.config,
+            &publicKey,
+            retire.as_deref(),
+            overlapDays.unwrap_or(0).max(0) as u64,
+            validDays.map(|d| d.max(0) as u64),
+            dryRun,
+        ) {
+            Ok(result) => call.reply(
+                signing_key_to_varlink(result.added),
+                result.retired.map(signing_key_to_varlink),
+                result.checked_at as i64,
+                result
+                    .images
+                    .into_iter()
+                    .map(|i| vl_ra::ImageTrust {
+                        r#name: i.name,
+                        r#version: i.version,
+                        r#path: i.path,
+                        r#now: i.now,
+                        r#after: i.after,
+                        r#signedBy: i.signed_by,
+                        r#reason: i.reason,
+                    })
+                    .collect(),
+            ),
+            Err(e) => call.reply_trust_failed(e.to_string()),
+        }
+    }
+}
+
+fn signing_key_to_varlink(key: crate::trust::SigningKey) -> vl_ra::SigningKey {
+    vl_ra::SigningKey {
+        r#keyId: key.keyid,
+        r#publicKey: key.public,
+        r#notBefore: key.not_before.map(|t| t as i64),
+        r#notAfter: key.not_after.map(|t| t as i64),
+    }
 }
 
 // ── Server entry point ──────────────────────────────────────────────
 
-pub fn run_server(address: &str, config: Config) -> varlink::Result<()> {
+pub fn run_server(address: &str, config: Config, output: &OutputManager) -> varlink::Result<()> {
+    crate::hitl_health::spawn(&config, output);
+    crate::maintenance::spawn(&config, output);
+    auto_refresh::spawn_os_release_watch(&config, output);
+
     let ext_handler = ExtensionsHandler {
         config: config.clone(),
+        auto_refresh: auto_refresh::spawn(&config, output),
+        log: output.detached(),
     };
     let rt_handler = RuntimesHandler {
         config: config.clone(),
+        log: output.detached(),
+    };
+    let hitl_handler = HitlHandler {
+        config: config.clone(),
     };
-    let hitl_handler = HitlHandler;
     let ra_handler = RootAuthorityHandler { config };
 
     let service = varlink::VarlinkService::new(
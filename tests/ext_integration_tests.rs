@@ -483,6 +483,136 @@ fn test_ext_merge_with_mocks() {
     );
 }
 
+/// Test that merge drops options and tools an older systemd lacks, and that
+/// status JSON reports the detected capabilities
+#[test]
+fn test_ext_merge_adapts_to_older_systemd() {
+    let env = [("AVOCADO_SYSTEMD_VERSION", "252")];
+    let (output, _temp_dir) =
+        run_avocadoctl_with_isolated_env(&["ext", "merge", "--verbose"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "merge should succeed: {stdout}");
+    assert!(stdout.contains("systemd-sysext merge"));
+    assert!(stdout.contains("does not support --mutable"));
+    assert!(stdout.contains("has no systemd-confext"));
+    assert!(!stdout.contains("systemd-confext merge"));
+
+    let (output, _temp_dir) =
+        run_avocadoctl_with_isolated_env(&["ext", "status", "-o", "json"], &env);
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("status JSON: {e}: {:?}", output));
+    assert_eq!(status["systemd"]["version"], 252);
+    assert_eq!(status["systemd"]["confext"], false);
+    assert_eq!(status["systemd"]["mutable"], false);
+
+    let (output, _temp_dir) =
+        run_avocadoctl_with_isolated_env(&["ext", "status", "-o", "json"], &[]);
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["systemd"]["version"], 256);
+    assert_eq!(status["systemd"]["mutable"], true);
+}
+
+/// Test that `--json` prints one result document on stdout and the
+/// progress as JSON events on stderr
+#[test]
+fn test_json_flag_result_and_events() {
+    let (output, _temp_dir) =
+        run_avocadoctl_with_isolated_env(&["--json", "ext", "merge", "--verbose"], &[]);
+    assert!(output.status.success(), "merge should succeed: {output:?}");
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("result JSON: {e}: {output:?}"));
+    assert_eq!(result["status"], "ok");
+    assert_eq!(result["message_id"], "ext.merged");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let events: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("event {line:?}: {e}")))
+        .collect();
+    assert!(events.iter().any(|e| e["event"] == "step"), "{stderr}");
+    assert_eq!(events.last().unwrap()["event"], "success");
+
+    let (output, _temp_dir) = run_avocadoctl_with_isolated_env(&["--json", "ext", "list"], &[]);
+    assert!(output.status.success());
+    let list: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("list JSON: {e}: {output:?}"));
+    assert!(list.is_array());
+
+    // Several errors still make one result, and only JSON on stderr
+    let (output, _temp_dir) =
+        run_avocadoctl_with_isolated_env(&["--json", "enable", "nope-1.0"], &[]);
+    assert!(!output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("error JSON: {e}: {output:?}"));
+    assert_eq!(result["status"], "error");
+    assert_eq!(result["failed"], 1);
+    assert_eq!(result["errors"].as_array().unwrap().len(), 2);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for line in stderr.lines() {
+        let event: serde_json::Value =
+            serde_json::from_str(line).unwrap_or_else(|e| panic!("event {line:?}: {e}"));
+        assert_eq!(event["event"], "error", "{stderr}");
+    }
+
+    // A status that cannot be shown fails with the error result
+    let base_dir = TempDir::new().expect("Failed to create temp directory");
+    fs::create_dir_all(base_dir.path().join("trust")).expect("Failed to create trust dir");
+    fs::write(base_dir.path().join("trust/keys.json"), "not json")
+        .expect("Failed to write trust store");
+    let (output, _temp_dir) = run_avocadoctl_with_isolated_env(
+        &["--json", "ext", "status"],
+        &[("AVOCADO_BASE_DIR", base_dir.path().to_str().unwrap())],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("status error JSON: {e}: {output:?}"));
+    assert_eq!(result["status"], "error");
+}
+
+/// Test that `--json` reports warnings as JSON events, leaving nothing but
+/// JSON on stderr
+#[test]
+fn test_json_flag_warnings_are_events() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("missing");
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\nmissing_dir = \"warn\"\n",
+            extensions_dir.display()
+        ),
+    )
+    .expect("Failed to write config");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            config_path.to_str().unwrap(),
+            "--json",
+            "ext",
+            "merge",
+        ],
+        &[
+            ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+            ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ],
+    );
+    assert!(output.status.success(), "merge should succeed: {output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let events: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("event {line:?}: {e}")))
+        .collect();
+    assert!(
+        events.iter().any(|e| e["event"] == "warning"
+            && e["message"]
+                .as_str()
+                .is_some_and(|m| m.starts_with("Extensions directory"))),
+        "{stderr}"
+    );
+}
+
 /// Test ext unmerge command with mock systemd binaries
 #[test]
 fn test_ext_unmerge_with_mocks() {
@@ -594,6 +724,65 @@ fn test_environment_preparation_with_mock_extensions() {
     let _ = fs::remove_dir_all(format!("{temp_base}/test_confexts"));
 }
 
+/// Test that a merge running out of its time budget puts back the links
+/// that were in place before it started
+#[test]
+fn test_merge_time_budget_exceeded_restores_links() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let extensions_path = temp_dir.path().join("extensions");
+    fs::create_dir_all(extensions_path.join("new-ext")).unwrap();
+    let sysext_dir = temp_dir.path().join("test_extensions");
+    fs::create_dir_all(&sysext_dir).unwrap();
+    std::os::unix::fs::symlink(
+        "/var/lib/avocado/images/old-ext",
+        sysext_dir.join("old-ext"),
+    )
+    .unwrap();
+
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\n\n[avocado.limits]\nmerge_time_budget_ms = 0\n",
+            extensions_path.display()
+        ),
+    )
+    .unwrap();
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            config_path.to_str().unwrap(),
+            "ext",
+            "merge",
+            "--verbose",
+        ],
+        &[
+            ("AVOCADO_EXTENSIONS_PATH", extensions_path.to_str().unwrap()),
+            ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "stdout: {stdout}");
+    assert!(
+        stderr.contains("Merge time budget of 0ms exceeded")
+            || stdout.contains("Merge time budget of 0ms exceeded"),
+        "stdout: {stdout}\nstderr: {stderr}"
+    );
+
+    let mut links: Vec<String> = fs::read_dir(&sysext_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    links.sort();
+    assert_eq!(links, vec!["old-ext"]);
+    assert_eq!(
+        fs::read_link(sysext_dir.join("old-ext")).unwrap(),
+        PathBuf::from("/var/lib/avocado/images/old-ext")
+    );
+}
+
 /// Test ext unmerge help
 #[test]
 fn test_ext_unmerge_help() {
@@ -681,6 +870,158 @@ fn test_ext_refresh_with_mocks() {
     );
 }
 
+/// Test refresh skips the unmerge/merge cycle when nothing changed since the last merge
+#[test]
+fn test_ext_refresh_skips_when_up_to_date() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+    fs::write(
+        release_dir.join("extension-release.app-1.0.0"),
+        "ID=_any\nVERSION_ID=1.0",
+    )
+    .expect("Failed to write release file");
+
+    let state_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_path = extensions_dir.to_str().unwrap();
+    let run = |args: &[&str]| {
+        let (output, _) = run_avocadoctl_with_isolated_env(
+            args,
+            &[
+                ("TMPDIR", state_dir.path().to_str().unwrap()),
+                ("AVOCADO_EXTENSIONS_PATH", extensions_path),
+                (
+                    "MOCK_SYSEXT_STATUS_JSON",
+                    r#"[{"hierarchy":"/usr","extensions":["app-1.0.0"]}]"#,
+                ),
+                (
+                    "MOCK_CONFEXT_STATUS_JSON",
+                    r#"[{"hierarchy":"/etc","extensions":"none"}]"#,
+                ),
+            ],
+        );
+        assert!(output.status.success(), "{args:?}: {output:?}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    // Without a record of the last merge, refresh always runs
+    let stdout = run(&["ext", "refresh"]);
+    assert!(
+        stdout.contains("Extensions refreshed successfully"),
+        "{stdout}"
+    );
+
+    let stdout = run(&["ext", "refresh", "--verbose"]);
+    assert!(stdout.contains("Extensions already up to date"), "{stdout}");
+    assert!(!stdout.contains("systemd-sysext unmerge"), "{stdout}");
+
+    // A changed image triggers a real refresh
+    fs::write(extensions_dir.join("app-1.0.0/usr/lib/app.conf"), "x").unwrap();
+    let stdout = run(&["refresh", "--verbose"]);
+    assert!(
+        stdout.contains("Refresh needed: app-1.0.0 changed"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("Extensions refreshed successfully"),
+        "{stdout}"
+    );
+
+    let stdout = run(&["refresh"]);
+    assert!(stdout.contains("Extensions already up to date"), "{stdout}");
+
+    let stdout = run(&["ext", "refresh", "--force", "--verbose"]);
+    assert!(stdout.contains("systemd-sysext unmerge"), "{stdout}");
+    assert!(
+        stdout.contains("Extensions refreshed successfully"),
+        "{stdout}"
+    );
+
+    // Unmerging forgets the last merge
+    run(&["ext", "unmerge"]);
+    let stdout = run(&["ext", "refresh"]);
+    assert!(
+        stdout.contains("Extensions refreshed successfully"),
+        "{stdout}"
+    );
+}
+
+/// Test that refresh re-merges only the changed extension, scoping hooks to it
+#[test]
+fn test_ext_refresh_partial() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let merged_release_dir = temp_dir.path().join("merged/usr/lib/extension-release.d");
+    fs::create_dir_all(&merged_release_dir).expect("Failed to create release dir");
+    for name in ["app", "base"] {
+        let release_dir = extensions_dir.join(format!("{name}-1.0.0/usr/lib/extension-release.d"));
+        fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+        let content = format!(
+            "ID=_any\nVERSION_ID=1.0\nAVOCADO_ON_MERGE=\"systemctl restart {name}.service\"\nAVOCADO_ON_UNMERGE=\"systemctl stop {name}.service\"\n"
+        );
+        let file_name = format!("extension-release.{name}-1.0.0");
+        fs::write(release_dir.join(&file_name), &content).expect("Failed to write release file");
+        fs::write(merged_release_dir.join(&file_name), &content)
+            .expect("Failed to write release file");
+    }
+
+    let state_dir = TempDir::new().expect("Failed to create temp directory");
+    let release_root = temp_dir.path().join("merged");
+    let run = |args: &[&str]| {
+        let (output, _) = run_avocadoctl_with_isolated_env(
+            args,
+            &[
+                ("TMPDIR", state_dir.path().to_str().unwrap()),
+                ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+                (
+                    "AVOCADO_EXTENSION_RELEASE_DIR",
+                    release_root.to_str().unwrap(),
+                ),
+                (
+                    "MOCK_SYSEXT_STATUS_JSON",
+                    r#"[{"hierarchy":"/usr","extensions":["app-1.0.0","base-1.0.0"]}]"#,
+                ),
+                (
+                    "MOCK_CONFEXT_STATUS_JSON",
+                    r#"[{"hierarchy":"/etc","extensions":"none"}]"#,
+                ),
+            ],
+        );
+        assert!(output.status.success(), "{args:?}: {output:?}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    run(&["ext", "merge"]);
+
+    fs::write(extensions_dir.join("app-1.0.0/usr/lib/app.conf"), "x").unwrap();
+    let stdout = run(&["ext", "refresh", "--verbose"]);
+    assert!(
+        stdout.contains("Refresh needed: app-1.0.0 changed"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("systemd-sysext refresh"), "{stdout}");
+    assert!(!stdout.contains("systemd-sysext unmerge"), "{stdout}");
+    assert!(stdout.contains("systemctl stop app.service"), "{stdout}");
+    assert!(stdout.contains("systemctl restart app.service"), "{stdout}");
+    assert!(!stdout.contains("base.service"), "{stdout}");
+    assert!(
+        stdout.contains("Extensions refreshed successfully"),
+        "{stdout}"
+    );
+
+    let stdout = run(&["ext", "refresh"]);
+    assert!(stdout.contains("Extensions already up to date"), "{stdout}");
+
+    // --force keeps the full cycle, running every extension's hooks
+    let stdout = run(&["ext", "refresh", "--force", "--verbose"]);
+    assert!(stdout.contains("systemd-sysext unmerge"), "{stdout}");
+    assert!(
+        stdout.contains("systemctl restart base.service"),
+        "{stdout}"
+    );
+}
+
 /// Test ext refresh help
 #[test]
 fn test_ext_refresh_help() {
@@ -887,6 +1228,40 @@ fn test_ext_merge_with_modprobe_processing() {
     );
 }
 
+/// Test a hanging modprobe is stopped at the hook_cmd limit without failing the merge
+#[test]
+fn test_ext_merge_modprobe_timeout() {
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let release_dir = current_dir.join("tests/fixtures/extension-release.d");
+
+    let started = std::time::Instant::now();
+    let (output, _temp_dir) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge", "--verbose"],
+        &[
+            (
+                "AVOCADO_EXTENSION_RELEASE_DIR",
+                &release_dir.to_string_lossy(),
+            ),
+            ("AVOCADO_TIMEOUT_HOOK_CMD", "1"),
+            ("MOCK_MODPROBE_HANG", "60"),
+        ],
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "merge should survive a hanging modprobe: {stderr}"
+    );
+    assert!(
+        stderr.contains("timed out after 1s; stopped modprobe"),
+        "timeout should be reported distinctly: {stderr}"
+    );
+    assert!(!stderr.contains("Failed to load module"));
+    assert!(stdout.contains("Extensions merged successfully"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(30));
+}
+
 /// Test post-merge processing with no depmod needed
 #[test]
 fn test_ext_merge_no_depmod_needed() {
@@ -1106,6 +1481,46 @@ fn test_ext_unmerge_does_not_execute_on_merge_commands() {
     );
 }
 
+/// Test that hooks restricted to the initrd do not run on the booted system
+#[test]
+fn test_ext_merge_runs_only_hooks_for_current_environment() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let release_dir = temp_dir.path().join("extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.env-hooks"),
+        "ID=_any\nAVOCADO_ON_MERGE_SYSTEM=\"systemctl restart system-only.service\"\nAVOCADO_ON_MERGE_INITRD=\"systemctl restart initrd-only.service\"\nAVOCADO_ON_MERGE=\"[initrd] modprobe initrd_mod\"\n",
+    )
+    .unwrap();
+    let fixtures_path = std::env::current_dir().unwrap().join("tests/fixtures");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge", "--verbose"],
+        &[
+            (
+                "AVOCADO_EXTENSION_RELEASE_DIR",
+                &release_dir.to_string_lossy(),
+            ),
+            (
+                "PATH",
+                &format!(
+                    "{}:{}",
+                    fixtures_path.to_string_lossy(),
+                    std::env::var("PATH").unwrap_or_default()
+                ),
+            ),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Running command: systemctl restart system-only.service"),
+        "stdout: {stdout}"
+    );
+    assert!(!stdout.contains("initrd-only"), "stdout: {stdout}");
+    assert!(!stdout.contains("initrd_mod"), "stdout: {stdout}");
+}
+
 /// Test deduplication of AVOCADO_ON_MERGE commands
 #[test]
 fn test_avocado_on_merge_command_deduplication() {
@@ -1376,67 +1791,407 @@ fn test_enable_nonexistent_extension() {
     );
 }
 
-/// Test enable command help
+/// Test that enable rejects extensions built for another OS release unless forced
 #[test]
-fn test_enable_help() {
-    let output = run_avocadoctl(&["enable", "--help"]);
-    assert!(output.status.success(), "Enable help should succeed");
+fn test_enable_rejects_incompatible_os_release() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+    fs::write(
+        release_dir.join("extension-release.app-1.0.0"),
+        "ID=avocado\nVERSION_ID=1.0\n",
+    )
+    .expect("Failed to write release file");
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Host os-release used by test mode
+    let avocado_dir = temp_dir.path().join("avocado");
+    fs::create_dir_all(&avocado_dir).expect("Failed to create avocado dir");
+    fs::write(
+        avocado_dir.join("os-release"),
+        "ID=avocado\nVERSION_ID=2.0\n",
+    )
+    .expect("Failed to write os-release");
+
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_TEST_MODE", "1"),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let link = avocado_dir.join("os-releases/2.0/app-1.0.0");
+
+    let output = run_avocadoctl_with_env(&["enable", "--os-release", "2.0", "app-1.0.0"], &env);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "incompatible enable should fail");
     assert!(
-        stdout.contains("Enable extensions for a specific runtime version"),
-        "Should contain enable description"
+        stderr.contains("VERSION_ID=1.0 does not match target VERSION_ID=2.0"),
+        "Should explain the mismatch. STDERR: {stderr}"
+    );
+    assert!(!link.exists(), "No symlink should be created");
+
+    let output = run_avocadoctl_with_env(
+        &["enable", "--force", "--os-release", "2.0", "app-1.0.0"],
+        &env,
     );
+    assert!(output.status.success(), "--force should bypass the check");
+    assert!(link.exists(), "Forced enable should create the symlink");
+
+    let output = run_avocadoctl_with_env(&["enable", "--os-release", "1.0", "app-1.0.0"], &env);
     assert!(
-        stdout.contains("--os-release"),
-        "Should mention --os-release flag"
+        output.status.success(),
+        "Matching VERSION_ID should be accepted"
     );
 }
 
-/// Test disable command with specific extensions
+/// Test that the strictness policy decides which validation problems abort enable
 #[test]
-fn test_disable_extensions() {
-    // Create a temporary directory for extensions
+fn test_enable_strictness_policy() {
     let temp_dir = TempDir::new().expect("Failed to create temp directory");
     let extensions_dir = temp_dir.path().join("extensions");
-    fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
+    let release_dir = extensions_dir.join("app-1.0.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+    fs::write(
+        release_dir.join("extension-release.app-1.0.0"),
+        "ID=avocado\nVERSION_ID=1.0\nAVOCADO_ON_MEGRE=depmod\n",
+    )
+    .expect("Failed to write release file");
+    let avocado_dir = temp_dir.path().join("avocado");
+    fs::create_dir_all(&avocado_dir).expect("Failed to create avocado dir");
+    fs::write(
+        avocado_dir.join("os-release"),
+        "ID=avocado\nVERSION_ID=1.0\n",
+    )
+    .expect("Failed to write os-release");
 
-    // Create test extensions
-    fs::create_dir(extensions_dir.join("ext1-1.0.0"))
-        .expect("Failed to create test extension directory");
-    fs::write(extensions_dir.join("ext2-1.0.0.raw"), b"mock raw data")
-        .expect("Failed to create test raw extension");
-    fs::write(extensions_dir.join("ext3-1.0.0.raw"), b"mock raw data")
-        .expect("Failed to create test raw extension");
+    let write_config = |policy: &str| {
+        let path = temp_dir.path().join("config.toml");
+        fs::write(
+            &path,
+            format!(
+                "{policy}\n[avocado.ext]\ndir = \"{}\"\n",
+                extensions_dir.display()
+            ),
+        )
+        .unwrap();
+        path.to_string_lossy().to_string()
+    };
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_TEST_MODE", "1"),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let link = avocado_dir.join("os-releases/1.0/app-1.0.0");
 
-    // First enable extensions
-    let enable_output = run_avocadoctl_with_env(
+    // The unknown key only warns by default
+    let config = write_config("");
+    let output = run_avocadoctl_with_env(
         &[
+            "-c",
+            &config,
             "enable",
             "--verbose",
             "--os-release",
-            "2.0.0",
-            "ext1-1.0.0",
-            "ext2-1.0.0",
-            "ext3-1.0.0",
-        ],
-        &[
-            ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
-            ("AVOCADO_TEST_MODE", "1"),
-            ("TMPDIR", temp_dir.path().to_str().unwrap()),
+            "1.0",
+            "app-1.0.0",
         ],
+        &env,
     );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "default policy should warn");
+    assert!(stdout.contains("unknown key AVOCADO_ON_MEGRE"), "{stdout}");
+    assert!(link.exists());
+    fs::remove_file(&link).unwrap();
 
-    assert!(enable_output.status.success(), "Enable should succeed");
+    // strict = true makes it fatal
+    let config = write_config("[avocado]\nstrict = true");
+    let output = run_avocadoctl_with_env(
+        &["-c", &config, "enable", "--os-release", "1.0", "app-1.0.0"],
+        &env,
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "strict policy should reject");
+    assert!(
+        stderr.contains("failed the unknown_keys check"),
+        "STDERR: {stderr}"
+    );
+    assert!(!link.exists());
 
-    // Now disable some extensions
-    let disable_output = run_avocadoctl_with_env(
-        &[
-            "disable",
-            "--verbose",
-            "--os-release",
-            "2.0.0",
-            "ext1-1.0.0",
+    // A per-check override wins over strict, and strict = false downgrades
+    // the OS release mismatch to a warning
+    let config = write_config(
+        "[avocado]\nstrict = false\n\n[avocado.strictness]\nmissing_release_file = \"error\"",
+    );
+    let output = run_avocadoctl_with_env(
+        &["-c", &config, "enable", "--os-release", "2.0", "app-1.0.0"],
+        &env,
+    );
+    assert!(
+        output.status.success(),
+        "strict = false should allow the mismatch: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(avocado_dir.join("os-releases/2.0/app-1.0.0").exists());
+
+    fs::create_dir_all(extensions_dir.join("bare-1.0")).unwrap();
+    let output = run_avocadoctl_with_env(
+        &["-c", &config, "enable", "--os-release", "1.0", "bare-1.0"],
+        &env,
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("failed the missing_release_file check"),
+        "STDERR: {stderr}"
+    );
+}
+
+/// Test that a strict merge leaves out an extension failing a strictness check
+#[test]
+fn test_merge_strictness_policy() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    for (name, release) in [
+        (
+            "app",
+            "ID=avocado\nVERSION_ID=1.0\nAVOCADO_ON_MEGRE=depmod\n",
+        ),
+        ("good", "ID=avocado\nVERSION_ID=1.0\n"),
+    ] {
+        let release_dir = extensions_dir.join(format!("{name}/usr/lib/extension-release.d"));
+        fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            release,
+        )
+        .expect("Failed to write release file");
+    }
+    let avocado_dir = temp_dir.path().join("avocado");
+    fs::create_dir_all(&avocado_dir).expect("Failed to create avocado dir");
+    fs::write(
+        avocado_dir.join("os-release"),
+        "ID=avocado\nVERSION_ID=1.0\n",
+    )
+    .expect("Failed to write os-release");
+
+    let write_config = |policy: &str| {
+        let path = temp_dir.path().join("config.toml");
+        fs::write(
+            &path,
+            format!(
+                "{policy}\n[avocado.ext]\ndir = \"{}\"\n",
+                extensions_dir.display()
+            ),
+        )
+        .unwrap();
+        path.to_string_lossy().to_string()
+    };
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let sysext_dir = temp_dir.path().join("test_extensions");
+
+    // The unknown key only warns by default
+    let config = write_config("");
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["-c", &config, "ext", "merge", "--verbose"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "default policy should warn: {stdout}"
+    );
+    assert!(stdout.contains("unknown key AVOCADO_ON_MEGRE"), "{stdout}");
+    assert!(sysext_dir.join("app").is_symlink());
+
+    // strict = true leaves the extension out
+    let config = write_config("[avocado]\nstrict = true");
+    let (output, _) = run_avocadoctl_with_isolated_env(&["-c", &config, "ext", "merge"], &env);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Skipping extension app: failed the unknown_keys check"),
+        "STDERR: {stderr}"
+    );
+    assert!(!sysext_dir.join("app").exists());
+    assert!(sysext_dir.join("good").is_symlink());
+}
+
+/// Test enabling by glob pattern and by absolute path outside the extensions dir
+#[test]
+fn test_enable_by_glob_and_path() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(extensions_dir.join("sensor-temp-1.0"))
+        .expect("Failed to create test extension directory");
+    fs::write(extensions_dir.join("sensor-gps-1.0.raw"), b"mock raw data")
+        .expect("Failed to create test raw extension");
+    fs::create_dir_all(extensions_dir.join("app-1.0"))
+        .expect("Failed to create test extension directory");
+    let outside = temp_dir.path().join("images/custom-2.0.raw");
+    fs::create_dir_all(outside.parent().unwrap()).expect("Failed to create images directory");
+    fs::write(&outside, b"mock raw data").expect("Failed to create outside image");
+
+    let output = run_avocadoctl_with_env(
+        &[
+            "enable",
+            "--verbose",
+            "--os-release",
+            "1.0",
+            "sensor-*",
+            outside.to_str().unwrap(),
+        ],
+        &[
+            ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+            ("AVOCADO_TEST_MODE", "1"),
+            ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "enable should succeed. STDOUT: {stdout} STDERR: {stderr}"
+    );
+    assert!(
+        stdout
+            .contains("Pattern 'sensor-*' matched 2 extension(s): sensor-gps-1.0, sensor-temp-1.0"),
+        "Should report what the pattern matched. STDOUT: {stdout}"
+    );
+    assert!(stdout.contains("Successfully enabled 3 extension(s)"));
+
+    let release_dir = temp_dir.path().join("avocado/os-releases/1.0");
+    assert!(release_dir.join("sensor-temp-1.0").exists());
+    assert!(release_dir.join("sensor-gps-1.0.raw").exists());
+    assert!(!release_dir.join("app-1.0").exists());
+    assert_eq!(
+        fs::read_link(release_dir.join("custom-2.0.raw")).unwrap(),
+        outside
+    );
+
+    // A pattern that matches nothing is an error
+    let output = run_avocadoctl_with_env(
+        &["enable", "--os-release", "1.0", "nomatch-*"],
+        &[
+            ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+            ("AVOCADO_TEST_MODE", "1"),
+            ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ],
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Pattern 'nomatch-*' matched no extensions"));
+}
+
+/// Test --user mode relocating config and state under XDG and a user root prefix
+#[test]
+fn test_user_mode_enable_uses_xdg_paths() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let config_home = temp_dir.path().join("config");
+    let state_home = temp_dir.path().join("state");
+    let prefix = temp_dir.path().join("prefix");
+
+    // Extensions live in the default images dir under the configured prefix
+    let images_dir = prefix.join("var/lib/avocado/images");
+    fs::create_dir_all(images_dir.join("app-1.0")).expect("Failed to create extension");
+    fs::create_dir_all(config_home.join("avocado")).expect("Failed to create config dir");
+    fs::write(
+        config_home.join("avocado/avocadoctl.conf"),
+        format!(
+            "[avocado.ext]\ndir = \"/var/lib/avocado/images\"\n\n[avocado.user]\nroot = \"{}\"\n",
+            prefix.display()
+        ),
+    )
+    .expect("Failed to write user config");
+
+    let output = run_avocadoctl_with_env(
+        &[
+            "--user",
+            "enable",
+            "--verbose",
+            "--os-release",
+            "1.0",
+            "app-1.0",
+        ],
+        &[
+            ("XDG_CONFIG_HOME", config_home.to_str().unwrap()),
+            ("XDG_STATE_HOME", state_home.to_str().unwrap()),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "user-mode enable should succeed without root. STDOUT: {stdout} STDERR: {stderr}"
+    );
+
+    let link = prefix.join("var/lib/avocado/os-releases/1.0/app-1.0");
+    assert_eq!(fs::read_link(&link).unwrap(), images_dir.join("app-1.0"));
+    assert!(
+        !state_home.join("avocado/root").exists(),
+        "The configured root should replace the XDG default"
+    );
+}
+
+/// Test enable command help
+#[test]
+fn test_enable_help() {
+    let output = run_avocadoctl(&["enable", "--help"]);
+    assert!(output.status.success(), "Enable help should succeed");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Enable extensions for a specific runtime version"),
+        "Should contain enable description"
+    );
+    assert!(
+        stdout.contains("--os-release"),
+        "Should mention --os-release flag"
+    );
+}
+
+/// Test disable command with specific extensions
+#[test]
+fn test_disable_extensions() {
+    // Create a temporary directory for extensions
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
+
+    // Create test extensions
+    fs::create_dir(extensions_dir.join("ext1-1.0.0"))
+        .expect("Failed to create test extension directory");
+    fs::write(extensions_dir.join("ext2-1.0.0.raw"), b"mock raw data")
+        .expect("Failed to create test raw extension");
+    fs::write(extensions_dir.join("ext3-1.0.0.raw"), b"mock raw data")
+        .expect("Failed to create test raw extension");
+
+    // First enable extensions
+    let enable_output = run_avocadoctl_with_env(
+        &[
+            "enable",
+            "--verbose",
+            "--os-release",
+            "2.0.0",
+            "ext1-1.0.0",
+            "ext2-1.0.0",
+            "ext3-1.0.0",
+        ],
+        &[
+            ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+            ("AVOCADO_TEST_MODE", "1"),
+            ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ],
+    );
+
+    assert!(enable_output.status.success(), "Enable should succeed");
+
+    // Now disable some extensions
+    let disable_output = run_avocadoctl_with_env(
+        &[
+            "disable",
+            "--verbose",
+            "--os-release",
+            "2.0.0",
+            "ext1-1.0.0",
             "ext2-1.0.0",
         ],
         &[
@@ -2069,6 +2824,15 @@ fn test_disable_all_then_refresh() {
         .expect("Failed to write release file");
     }
 
+    // Test mode reads the os-release of $TMPDIR/avocado
+    let avocado_dir = temp_dir.path().join("avocado");
+    fs::create_dir_all(&avocado_dir).expect("Failed to create avocado directory");
+    fs::write(
+        avocado_dir.join("os-release"),
+        "ID=avocado\nVERSION_ID=1.0\n",
+    )
+    .expect("Failed to write os-release");
+
     let test_env = [
         ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
         ("AVOCADO_TEST_MODE", "1"),
@@ -2113,17 +2877,7 @@ fn test_disable_all_then_refresh() {
     );
 
     // The os-releases directory should still exist but be empty, so base directory should still be skipped
-    // Read the actual VERSION_ID from the system to make the test environment-agnostic
-    let os_release_content = std::fs::read_to_string("/etc/os-release").unwrap_or_default();
-    let version_id = os_release_content
-        .lines()
-        .find(|line| line.starts_with("VERSION_ID="))
-        .map(|line| {
-            line.trim_start_matches("VERSION_ID=")
-                .trim_matches('"')
-                .trim_matches('\'')
-        })
-        .unwrap_or("unknown");
+    let version_id = "1.0";
 
     let os_releases_dir = temp_dir
         .path()
@@ -2226,187 +2980,237 @@ fn test_stale_symlink_cleanup() {
     );
 }
 
+/// Test that `ext merge --dry-run` prints the plan without touching the link directories
 #[test]
-fn test_hitl_mount_masks_versioned_extensions() {
+fn test_merge_dry_run_prints_plan_without_changes() {
     let temp_dir = TempDir::new().expect("Failed to create temp directory");
     let extensions_dir = temp_dir.path().join("extensions");
-    let hitl_dir = temp_dir.path().join("avocado/hitl");
-    fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
-
-    // Create a versioned extension (myext-1.0.0) in the regular extensions directory
-    let versioned_ext_dir = extensions_dir.join("myext-1.0.0");
-    fs::create_dir(&versioned_ext_dir).expect("Failed to create versioned extension directory");
-    let versioned_release_dir = versioned_ext_dir.join("usr/lib/extension-release.d");
-    fs::create_dir_all(&versioned_release_dir).expect("Failed to create release dir");
+    let release_dir = extensions_dir.join("app-1.0.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).expect("Failed to create release dir");
     fs::write(
-        versioned_release_dir.join("extension-release.myext-1.0.0"),
-        "ID=avocado\nVERSION_ID=1.0",
+        release_dir.join("extension-release.app-1.0.0"),
+        "ID=_any\nVERSION_ID=1.0",
     )
     .expect("Failed to write release file");
 
+    let sysext_dir = temp_dir.path().join("test_extensions");
+    fs::create_dir_all(&sysext_dir).expect("Failed to create sysext dir");
+    std::os::unix::fs::symlink(extensions_dir.join("gone"), sysext_dir.join("gone-0.1"))
+        .expect("Failed to create stale symlink");
+
     let test_env = [
         ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
         ("AVOCADO_TEST_MODE", "1"),
         ("TMPDIR", temp_dir.path().to_str().unwrap()),
     ];
-
-    // Enable the versioned extension first
-    let enable_output = run_avocadoctl_with_env(&["enable", "--verbose", "myext-1.0.0"], &test_env);
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge", "--dry-run"], &test_env);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(
-        enable_output.status.success(),
-        "Enable command should succeed"
+        stdout.contains("unlink sysext/gone-0.1"),
+        "stdout: {stdout}"
     );
-
-    // Refresh to create symlinks for the versioned extension (WITHOUT HITL mount yet)
-    let (refresh1, _) =
-        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
-    assert!(refresh1.status.success(), "First refresh should succeed");
-
-    let sysext_dir = temp_dir.path().join("test_extensions");
-
-    // Verify that the versioned symlink was created
     assert!(
-        sysext_dir.join("myext-1.0.0").exists(),
-        "Versioned symlink (myext-1.0.0) should exist after initial refresh"
+        stdout.contains("link sysext/app-1.0.0 ->"),
+        "stdout: {stdout}"
     );
-
-    // Now create a HITL extension with the same base name (myext) but no version
-    fs::create_dir_all(&hitl_dir).expect("Failed to create HITL directory");
-    let hitl_ext_dir = hitl_dir.join("myext");
-    fs::create_dir(&hitl_ext_dir).expect("Failed to create HITL extension directory");
-    let hitl_release_dir = hitl_ext_dir.join("usr/lib/extension-release.d");
-    fs::create_dir_all(&hitl_release_dir).expect("Failed to create HITL release dir");
-    fs::write(
-        hitl_release_dir.join("extension-release.myext"),
-        "ID=avocado\nVERSION_ID=1.0",
-    )
-    .expect("Failed to write HITL release file");
-
-    // Refresh again - this should detect the HITL mount and remove the versioned symlink
-    let (refresh2, _) =
-        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
-    assert!(refresh2.status.success(), "Second refresh should succeed");
-    let stdout2 = String::from_utf8_lossy(&refresh2.stdout);
-
-    // Verify that the versioned symlink was removed (masked by HITL)
     assert!(
-        !sysext_dir.join("myext-1.0.0").exists(),
-        "Versioned symlink (myext-1.0.0) should be removed when HITL mount (myext) exists"
+        sysext_dir.join("gone-0.1").is_symlink(),
+        "dry run must not remove links"
     );
-
-    // Verify that the non-versioned HITL symlink exists
     assert!(
-        sysext_dir.join("myext").exists(),
-        "HITL symlink (myext) should exist"
+        !sysext_dir.join("app-1.0.0").exists(),
+        "dry run must not create links"
     );
 
-    // Check for cleanup message in verbose output
-    assert!(
-        stdout2.contains("Removed stale") || stdout2.contains("myext"),
-        "Should mention cleanup or the extension name in verbose output"
-    );
+    let (json_output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--dry-run", "-o", "json"], &test_env);
+    assert!(json_output.status.success());
+    let plan: serde_json::Value =
+        serde_json::from_slice(&json_output.stdout).expect("dry run JSON should parse");
+    assert_eq!(plan["actions"][0]["action"], "unlink");
+    assert_eq!(plan["actions"][1]["action"], "link");
+    assert_eq!(plan["actions"][1]["kind"], "sysext");
 }
 
+/// Test that `ext merge --dry-run` lists the post-merge tasks in the order they run
 #[test]
-fn test_hitl_mount_masks_multiple_versions() {
-    // Test that HITL mount masks multiple different versions of the same extension
+fn test_merge_dry_run_lists_post_merge_tasks() {
     let temp_dir = TempDir::new().expect("Failed to create temp directory");
     let extensions_dir = temp_dir.path().join("extensions");
-    let hitl_dir = temp_dir.path().join("avocado/hitl");
-    fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
-
-    // Create multiple versioned extensions (myext-1.0.0 and myext-2.0.0)
-    for version in &["1.0.0", "2.0.0"] {
-        let ext_name = format!("myext-{version}");
-        let versioned_ext_dir = extensions_dir.join(&ext_name);
-        fs::create_dir(&versioned_ext_dir).expect("Failed to create versioned extension directory");
-        let versioned_release_dir = versioned_ext_dir.join("usr/lib/extension-release.d");
-        fs::create_dir_all(&versioned_release_dir).expect("Failed to create release dir");
-        fs::write(
-            versioned_release_dir.join(format!("extension-release.{ext_name}")),
-            "ID=avocado\nVERSION_ID=1.0",
-        )
-        .expect("Failed to write release file");
-    }
+    let release_dir = extensions_dir.join("drivers-1.0.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+    fs::write(
+        release_dir.join("extension-release.drivers-1.0.0"),
+        "ID=_any\nVERSION_ID=1.0\nAVOCADO_ON_MERGE=depmod\nAVOCADO_ON_MERGE=\"systemctl restart net\"\nAVOCADO_MODPROBE=\"nfsd\"\n",
+    )
+    .expect("Failed to write release file");
 
     let test_env = [
         ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
-        ("AVOCADO_TEST_MODE", "1"),
         ("TMPDIR", temp_dir.path().to_str().unwrap()),
     ];
-
-    // Enable both versioned extensions
-    let enable_output = run_avocadoctl_with_env(
-        &["enable", "--verbose", "myext-1.0.0", "myext-2.0.0"],
-        &test_env,
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge", "--dry-run"], &test_env);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let tasks = stdout
+        .split_once("Then: merge with systemd-sysext and systemd-confext, and:\n")
+        .map(|(_, tasks)| tasks.lines().collect::<Vec<_>>())
+        .unwrap_or_default();
+    assert_eq!(
+        tasks,
+        vec![
+            "  run on-merge command: depmod before daemon-reload (drivers-1.0.0)",
+            "  load kernel modules nfsd",
+            "  daemon-reload",
+            "  run on-merge command: systemctl restart net (drivers-1.0.0)",
+        ],
+        "stdout: {stdout}"
     );
-    assert!(enable_output.status.success(), "Enable should succeed");
 
-    // Refresh to create symlinks
-    let (refresh1, _) =
-        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
-    assert!(refresh1.status.success(), "First refresh should succeed");
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "merge", "--dry-run", "-o", "json"], &test_env);
+    let plan: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("dry run JSON should parse");
+    assert_eq!(plan["tasks"][0]["action"], "hook");
+    assert_eq!(plan["tasks"][0]["stage"], "before-reload");
+    assert_eq!(plan["tasks"][1]["modules"], serde_json::json!(["nfsd"]));
+}
 
-    let sysext_dir = temp_dir.path().join("test_extensions");
+/// Test `ext diff` before any merge, right after one and after an extension changes
+#[test]
+fn test_ext_diff() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let add_extension = |name: &str| {
+        let release_dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\nVERSION_ID=1.0",
+        )
+        .expect("Failed to write release file");
+    };
+    add_extension("app-1.0.0");
+    add_extension("base-2.0");
 
-    // Verify both versioned symlinks exist (only one would be active, but both should be in os-releases)
-    // Note: Only the last enabled one should actually be symlinked since they have the same base name
-    // and the extension_map uses the base name as key
+    let test_env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let diff = || {
+        let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "diff"], &test_env);
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = diff();
     assert!(
-        sysext_dir.join("myext-1.0.0").exists() || sysext_dir.join("myext-2.0.0").exists(),
-        "At least one versioned symlink should exist"
+        stdout.contains("No record of the last merge"),
+        "stdout: {stdout}"
     );
+    assert!(stdout.contains("  + app-1.0.0\n"), "stdout: {stdout}");
+    assert!(stdout.contains("  + base-2.0\n"), "stdout: {stdout}");
 
-    // Create HITL mount
-    fs::create_dir_all(&hitl_dir).expect("Failed to create HITL directory");
-    let hitl_ext_dir = hitl_dir.join("myext");
-    fs::create_dir(&hitl_ext_dir).expect("Failed to create HITL extension directory");
-    let hitl_release_dir = hitl_ext_dir.join("usr/lib/extension-release.d");
-    fs::create_dir_all(&hitl_release_dir).expect("Failed to create HITL release dir");
-    fs::write(
-        hitl_release_dir.join("extension-release.myext"),
-        "ID=avocado\nVERSION_ID=1.0",
-    )
-    .expect("Failed to write HITL release file");
-
-    // Refresh with HITL mount
-    let (refresh2, _) =
-        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
-    assert!(refresh2.status.success(), "Second refresh should succeed");
-
-    // Verify ALL versioned symlinks are removed
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &test_env);
     assert!(
-        !sysext_dir.join("myext-1.0.0").exists(),
-        "myext-1.0.0 should be masked by HITL mount"
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
     );
+    let stdout = diff();
     assert!(
-        !sysext_dir.join("myext-2.0.0").exists(),
-        "myext-2.0.0 should be masked by HITL mount"
+        stdout.contains("No changes since the last merge."),
+        "stdout: {stdout}"
     );
-    assert!(
-        sysext_dir.join("myext").exists(),
-        "HITL symlink should exist"
+
+    fs::remove_dir_all(extensions_dir.join("base-2.0")).expect("Failed to remove extension");
+    add_extension("app-1.1.0");
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "diff", "-o", "json"], &test_env);
+    let diff: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("diff JSON should parse");
+    assert_eq!(diff["recorded"], true);
+    assert_eq!(
+        diff["changes"],
+        serde_json::json!([
+            {"change": "added", "name": "app-1.1.0"},
+            {"change": "removed", "name": "base-2.0"},
+        ])
     );
 }
 
+/// Test `ext graph` in text, DOT and JSON form with a missing dependency
 #[test]
-fn test_hitl_mount_only_masks_same_base_name() {
-    // Test that HITL mount for "myext" doesn't mask "otherext-1.0.0"
+fn test_ext_graph() {
     let temp_dir = TempDir::new().expect("Failed to create temp directory");
     let extensions_dir = temp_dir.path().join("extensions");
-    let hitl_dir = temp_dir.path().join("avocado/hitl");
-    fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
+    for (name, extra) in [
+        (
+            "web",
+            "AVOCADO_REQUIRES=\"base tls\"\nAVOCADO_ENABLE_SERVICES=nginx",
+        ),
+        ("base", ""),
+    ] {
+        let release_dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            format!("ID=_any\nVERSION_ID=1.0\n{extra}\n"),
+        )
+        .expect("Failed to write release file");
+    }
 
-    // Create two different extensions
-    for (name, version) in &[("myext", "1.0.0"), ("otherext", "2.0.0")] {
-        let ext_name = format!("{name}-{version}");
-        let ext_dir = extensions_dir.join(&ext_name);
-        fs::create_dir(&ext_dir).expect("Failed to create extension directory");
-        let release_dir = ext_dir.join("usr/lib/extension-release.d");
+    let test_env = [("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap())];
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "graph"], &test_env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "stdout: {stdout}");
+    assert!(
+        stdout.contains(
+            "web\n  ├── requires base\n  ├── requires tls (missing)\n  └── enables nginx.service\n"
+        ),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains("web requires tls, which is not enabled"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "graph", "--dot"], &test_env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"web\" -> \"base\";"), "stdout: {stdout}");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "graph", "-o", "json"], &test_env);
+    let graph: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("graph JSON should parse");
+    assert_eq!(graph["nodes"][1]["name"], "web");
+    assert_eq!(graph["missing"][0]["requires"], "tls");
+    assert_eq!(graph["cycles"], serde_json::json!([]));
+}
+
+/// Test that a merge plan lists extensions in the same, sorted order every run
+#[test]
+fn test_merge_plan_order_is_stable() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let names = [
+        "kiwi-1.0",
+        "apple-2.1",
+        "mango-0.3",
+        "banana-1.0",
+        "cherry-4.2",
+    ];
+    for name in names {
+        let release_dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
         fs::create_dir_all(&release_dir).expect("Failed to create release dir");
         fs::write(
-            release_dir.join(format!("extension-release.{ext_name}")),
-            "ID=avocado\nVERSION_ID=1.0",
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\nVERSION_ID=1.0",
         )
         .expect("Failed to write release file");
     }
@@ -2416,69 +3220,311 @@ fn test_hitl_mount_only_masks_same_base_name() {
         ("AVOCADO_TEST_MODE", "1"),
         ("TMPDIR", temp_dir.path().to_str().unwrap()),
     ];
+    let plan = || {
+        let (output, _) = run_avocadoctl_with_isolated_env(
+            &["ext", "merge", "--dry-run", "-o", "json"],
+            &test_env,
+        );
+        assert!(output.status.success());
+        output.stdout
+    };
+    let first = plan();
+    assert_eq!(first, plan(), "plan output must not change between runs");
+
+    let plan: serde_json::Value = serde_json::from_slice(&first).expect("plan JSON should parse");
+    let mut sorted = names.to_vec();
+    sorted.sort();
+    assert_eq!(plan["extensions"], serde_json::json!(sorted));
+}
 
-    // Enable both extensions
-    let enable_output = run_avocadoctl_with_env(
-        &["enable", "--verbose", "myext-1.0.0", "otherext-2.0.0"],
-        &test_env,
+/// Test `ext status --check` exit codes for in-sync, drifted and failed checks
+#[test]
+fn test_ext_status_check_exit_codes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+    fs::write(
+        release_dir.join("extension-release.app-1.0.0"),
+        "ID=_any\nVERSION_ID=1.0",
+    )
+    .expect("Failed to write release file");
+
+    let extensions_path = extensions_dir.to_str().unwrap();
+    let check_with = |sysext_status: &str, args: &[&str]| {
+        run_avocadoctl_with_isolated_env(
+            &[&["ext", "status", "--check"], args].concat(),
+            &[
+                ("AVOCADO_EXTENSIONS_PATH", extensions_path),
+                ("MOCK_SYSEXT_STATUS_JSON", sysext_status),
+                (
+                    "MOCK_CONFEXT_STATUS_JSON",
+                    r#"[{"hierarchy":"/etc","extensions":"none"}]"#,
+                ),
+            ],
+        )
+        .0
+    };
+    let check = |sysext_status: &str| check_with(sysext_status, &[]);
+
+    let output = check(r#"[{"hierarchy":"/usr","extensions":["01-app-1.0.0"]}]"#);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    assert!(stdout.starts_with("in-sync:"), "stdout: {stdout}");
+
+    let output = check(r#"[{"hierarchy":"/usr","extensions":["old-0.9"]}]"#);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "stdout: {stdout}");
+    assert_eq!(
+        stdout.trim(),
+        "refresh-needed: sysext app-1.0.0 is enabled but not merged; sysext old-0.9 is merged but not enabled"
     );
-    assert!(enable_output.status.success(), "Enable should succeed");
 
-    // Refresh to create symlinks
-    let (refresh1, _) =
-        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
-    assert!(refresh1.status.success(), "First refresh should succeed");
+    // The verdict is the one JSON document on stdout
+    let output = check_with(
+        r#"[{"hierarchy":"/usr","extensions":["old-0.9"]}]"#,
+        &["-o", "json"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let body: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("status JSON: {e}: {output:?}"));
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["state"], "refresh-needed");
 
-    let sysext_dir = temp_dir.path().join("test_extensions");
+    let output = check("not json");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "stdout: {stdout}");
+    assert!(stdout.starts_with("error:"), "stdout: {stdout}");
 
-    // Verify both symlinks exist
+    // A configuration that cannot be loaded is an error, not a needed refresh
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(&config_path, "[avocado.ext\nnot toml").expect("Failed to write config");
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            config_path.to_str().unwrap(),
+            "ext",
+            "status",
+            "--check",
+        ],
+        &[("AVOCADO_EXTENSIONS_PATH", extensions_path)],
+    );
+    assert_eq!(
+        output.status.code(),
+        Some(2),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Test that the permission audit keeps offending extensions out of a merge
+#[test]
+fn test_merge_permission_audit() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    for name in ["good-1.0.0", "bad-1.0.0"] {
+        let release_dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\nVERSION_ID=1.0",
+        )
+        .expect("Failed to write release file");
+    }
+    let shared = extensions_dir.join("bad-1.0.0/usr/lib/shared.conf");
+    fs::write(&shared, "").expect("Failed to write file");
+    fs::set_permissions(&shared, fs::Permissions::from_mode(0o666))
+        .expect("Failed to set permissions");
+
+    let uid = fs::metadata(&extensions_dir).unwrap().uid();
+    let write_config = |on_finding: &str| {
+        let config_path = temp_dir.path().join(format!("{on_finding}.toml"));
+        fs::write(
+            &config_path,
+            format!(
+                "[avocado.ext]\ndir = \"{}\"\n\n[avocado.permissions]\nenabled = true\non_finding = \"{on_finding}\"\nallowed_uids = [{uid}]\n",
+                extensions_dir.display()
+            ),
+        )
+        .expect("Failed to write config");
+        config_path
+    };
+
+    let test_env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_TEST_MODE", "1"),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let skip_config = write_config("skip");
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            skip_config.to_str().unwrap(),
+            "ext",
+            "merge",
+            "--dry-run",
+        ],
+        &test_env,
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let combined = format!("{stdout}{stderr}");
     assert!(
-        sysext_dir.join("myext-1.0.0").exists(),
-        "myext-1.0.0 should exist"
+        combined.contains("/usr/lib/shared.conf is world-writable (0666)"),
+        "output: {combined}"
     );
     assert!(
-        sysext_dir.join("otherext-2.0.0").exists(),
-        "otherext-2.0.0 should exist"
+        stdout.contains("link sysext/good-1.0.0 ->"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        !stdout.contains("link sysext/bad-1.0.0"),
+        "stdout: {stdout}"
     );
 
-    // Create HITL mount for myext only
-    fs::create_dir_all(&hitl_dir).expect("Failed to create HITL directory");
-    let hitl_ext_dir = hitl_dir.join("myext");
-    fs::create_dir(&hitl_ext_dir).expect("Failed to create HITL extension directory");
-    let hitl_release_dir = hitl_ext_dir.join("usr/lib/extension-release.d");
-    fs::create_dir_all(&hitl_release_dir).expect("Failed to create HITL release dir");
+    let warn_config = write_config("warn");
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            warn_config.to_str().unwrap(),
+            "ext",
+            "merge",
+            "--dry-run",
+        ],
+        &test_env,
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("link sysext/bad-1.0.0 ->"),
+        "stdout: {stdout}"
+    );
+}
+
+/// Test that unsigned image files are refused once the trust store holds a key
+#[test]
+fn test_merge_signature_check() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("tools-1.0.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).expect("Failed to create release dir");
     fs::write(
-        hitl_release_dir.join("extension-release.myext"),
-        "ID=avocado\nVERSION_ID=1.0",
+        release_dir.join("extension-release.tools-1.0.0"),
+        "ID=_any\nVERSION_ID=1.0",
     )
-    .expect("Failed to write HITL release file");
+    .expect("Failed to write release file");
+    fs::write(extensions_dir.join("app-1.0.0.raw"), b"mock raw data")
+        .expect("Failed to write raw image");
 
-    // Refresh with HITL mount
-    let (refresh2, _) =
-        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
-    assert!(refresh2.status.success(), "Second refresh should succeed");
+    let base_dir = temp_dir.path().join("base");
+    let test_env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_BASE_DIR", base_dir.to_str().unwrap()),
+    ];
+    // Extension directories cannot be signed; this config accepts them
+    let allow_config = temp_dir.path().join("allow-directories.toml");
+    fs::write(
+        &allow_config,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\nallow_unsigned_directories = true\n",
+            extensions_dir.display()
+        ),
+    )
+    .expect("Failed to write config");
+    let run_merge = |config: Option<&PathBuf>, extra: &[&str]| {
+        let mut args = vec!["--backend", "mock"];
+        if let Some(config) = config {
+            args.extend_from_slice(&["-c", config.to_str().unwrap()]);
+        }
+        args.extend_from_slice(&["ext", "merge", "--dry-run"]);
+        args.extend_from_slice(extra);
+        let (output, _) = run_avocadoctl_with_isolated_env(&args, &test_env);
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        (output.status.success(), stdout, stderr)
+    };
+    let dry_run = |extra: &[&str]| run_merge(Some(&allow_config), extra);
+
+    // No trust store: every image is merged
+    let (success, stdout, _) = dry_run(&[]);
+    assert!(success);
+    assert!(
+        stdout.contains("link sysext/app-1.0.0 ->"),
+        "stdout: {stdout}"
+    );
+
+    fs::create_dir_all(base_dir.join("trust")).expect("Failed to create trust dir");
+    fs::write(
+        base_dir.join("trust/keys.json"),
+        format!(
+            r#"{{"keys": [{{"keyid": "7ab6b86cb2c9684b", "public": "{}"}}]}}"#,
+            "11".repeat(32)
+        ),
+    )
+    .expect("Failed to write trust store");
+    let refused = "not accepted by the trust store: it is not signed";
 
-    // Verify myext-1.0.0 is masked but otherext-2.0.0 remains
+    let (success, stdout, stderr) = dry_run(&[]);
+    assert!(!success, "stdout: {stdout}");
     assert!(
-        !sysext_dir.join("myext-1.0.0").exists(),
-        "myext-1.0.0 should be masked"
+        format!("{stdout}{stderr}").contains(refused),
+        "stdout: {stdout}\nstderr: {stderr}"
     );
-    assert!(sysext_dir.join("myext").exists(), "HITL myext should exist");
+
+    let (success, stdout, _) = dry_run(&["--keep-going"]);
+    assert!(success);
     assert!(
-        sysext_dir.join("otherext-2.0.0").exists(),
-        "otherext-2.0.0 should NOT be masked (different base name)"
+        !stdout.contains("link sysext/app-1.0.0"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains("link sysext/tools-1.0.0 ->"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains("Skipped (--keep-going): app-1.0.0") && stdout.contains(refused),
+        "stdout: {stdout}"
+    );
+
+    // Without the setting the directory is refused as well
+    let (success, stdout, stderr) = run_merge(None, &[]);
+    assert!(!success, "stdout: {stdout}");
+    assert!(
+        stderr.contains("tools-1.0.0") && stderr.contains("directories cannot be signed"),
+        "stdout: {stdout}\nstderr: {stderr}"
+    );
+
+    // Listings mark the image instead of failing
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["--backend", "mock", "ext", "list"], &test_env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        stdout.contains("app-1.0.0") && stdout.contains("REJECTED"),
+        "stdout: {stdout}"
+    );
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["--backend", "mock", "ext", "status"], &test_env);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("A merge would refuse: app-1.0.0"),
+        "{output:?}"
     );
 }
 
 #[test]
-fn test_hitl_mount_removal_restores_versioned() {
-    // Test that removing HITL mount allows the versioned extension to be used again
+fn test_hitl_mount_masks_versioned_extensions() {
     let temp_dir = TempDir::new().expect("Failed to create temp directory");
     let extensions_dir = temp_dir.path().join("extensions");
     let hitl_dir = temp_dir.path().join("avocado/hitl");
     fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
 
-    // Create a versioned extension
+    // Create a versioned extension (myext-1.0.0) in the regular extensions directory
     let versioned_ext_dir = extensions_dir.join("myext-1.0.0");
     fs::create_dir(&versioned_ext_dir).expect("Failed to create versioned extension directory");
     let versioned_release_dir = versioned_ext_dir.join("usr/lib/extension-release.d");
@@ -2495,11 +3541,27 @@ fn test_hitl_mount_removal_restores_versioned() {
         ("TMPDIR", temp_dir.path().to_str().unwrap()),
     ];
 
-    // Enable the versioned extension
+    // Enable the versioned extension first
     let enable_output = run_avocadoctl_with_env(&["enable", "--verbose", "myext-1.0.0"], &test_env);
-    assert!(enable_output.status.success(), "Enable should succeed");
+    assert!(
+        enable_output.status.success(),
+        "Enable command should succeed"
+    );
 
-    // Create and use HITL mount
+    // Refresh to create symlinks for the versioned extension (WITHOUT HITL mount yet)
+    let (refresh1, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
+    assert!(refresh1.status.success(), "First refresh should succeed");
+
+    let sysext_dir = temp_dir.path().join("test_extensions");
+
+    // Verify that the versioned symlink was created
+    assert!(
+        sysext_dir.join("myext-1.0.0").exists(),
+        "Versioned symlink (myext-1.0.0) should exist after initial refresh"
+    );
+
+    // Now create a HITL extension with the same base name (myext) but no version
     fs::create_dir_all(&hitl_dir).expect("Failed to create HITL directory");
     let hitl_ext_dir = hitl_dir.join("myext");
     fs::create_dir(&hitl_ext_dir).expect("Failed to create HITL extension directory");
@@ -2511,15 +3573,244 @@ fn test_hitl_mount_removal_restores_versioned() {
     )
     .expect("Failed to write HITL release file");
 
-    // Refresh with HITL
-    let (refresh1, _) =
+    // Refresh again - this should detect the HITL mount and remove the versioned symlink
+    let (refresh2, _) =
         run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
-    assert!(
-        refresh1.status.success(),
-        "Refresh with HITL should succeed"
-    );
+    assert!(refresh2.status.success(), "Second refresh should succeed");
+    let stdout2 = String::from_utf8_lossy(&refresh2.stdout);
 
-    let sysext_dir = temp_dir.path().join("test_extensions");
+    // Verify that the versioned symlink was removed (masked by HITL)
+    assert!(
+        !sysext_dir.join("myext-1.0.0").exists(),
+        "Versioned symlink (myext-1.0.0) should be removed when HITL mount (myext) exists"
+    );
+
+    // Verify that the non-versioned HITL symlink exists
+    assert!(
+        sysext_dir.join("myext").exists(),
+        "HITL symlink (myext) should exist"
+    );
+
+    // Check for cleanup message in verbose output
+    assert!(
+        stdout2.contains("Removed stale") || stdout2.contains("myext"),
+        "Should mention cleanup or the extension name in verbose output"
+    );
+}
+
+#[test]
+fn test_hitl_mount_masks_multiple_versions() {
+    // Test that HITL mount masks multiple different versions of the same extension
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let hitl_dir = temp_dir.path().join("avocado/hitl");
+    fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
+
+    // Create multiple versioned extensions (myext-1.0.0 and myext-2.0.0)
+    for version in &["1.0.0", "2.0.0"] {
+        let ext_name = format!("myext-{version}");
+        let versioned_ext_dir = extensions_dir.join(&ext_name);
+        fs::create_dir(&versioned_ext_dir).expect("Failed to create versioned extension directory");
+        let versioned_release_dir = versioned_ext_dir.join("usr/lib/extension-release.d");
+        fs::create_dir_all(&versioned_release_dir).expect("Failed to create release dir");
+        fs::write(
+            versioned_release_dir.join(format!("extension-release.{ext_name}")),
+            "ID=avocado\nVERSION_ID=1.0",
+        )
+        .expect("Failed to write release file");
+    }
+
+    let test_env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_TEST_MODE", "1"),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    // Enable both versioned extensions
+    let enable_output = run_avocadoctl_with_env(
+        &["enable", "--verbose", "myext-1.0.0", "myext-2.0.0"],
+        &test_env,
+    );
+    assert!(enable_output.status.success(), "Enable should succeed");
+
+    // Refresh to create symlinks
+    let (refresh1, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
+    assert!(refresh1.status.success(), "First refresh should succeed");
+
+    let sysext_dir = temp_dir.path().join("test_extensions");
+
+    // Verify both versioned symlinks exist (only one would be active, but both should be in os-releases)
+    // Note: Only the last enabled one should actually be symlinked since they have the same base name
+    // and the extension_map uses the base name as key
+    assert!(
+        sysext_dir.join("myext-1.0.0").exists() || sysext_dir.join("myext-2.0.0").exists(),
+        "At least one versioned symlink should exist"
+    );
+
+    // Create HITL mount
+    fs::create_dir_all(&hitl_dir).expect("Failed to create HITL directory");
+    let hitl_ext_dir = hitl_dir.join("myext");
+    fs::create_dir(&hitl_ext_dir).expect("Failed to create HITL extension directory");
+    let hitl_release_dir = hitl_ext_dir.join("usr/lib/extension-release.d");
+    fs::create_dir_all(&hitl_release_dir).expect("Failed to create HITL release dir");
+    fs::write(
+        hitl_release_dir.join("extension-release.myext"),
+        "ID=avocado\nVERSION_ID=1.0",
+    )
+    .expect("Failed to write HITL release file");
+
+    // Refresh with HITL mount
+    let (refresh2, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
+    assert!(refresh2.status.success(), "Second refresh should succeed");
+
+    // Verify ALL versioned symlinks are removed
+    assert!(
+        !sysext_dir.join("myext-1.0.0").exists(),
+        "myext-1.0.0 should be masked by HITL mount"
+    );
+    assert!(
+        !sysext_dir.join("myext-2.0.0").exists(),
+        "myext-2.0.0 should be masked by HITL mount"
+    );
+    assert!(
+        sysext_dir.join("myext").exists(),
+        "HITL symlink should exist"
+    );
+}
+
+#[test]
+fn test_hitl_mount_only_masks_same_base_name() {
+    // Test that HITL mount for "myext" doesn't mask "otherext-1.0.0"
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let hitl_dir = temp_dir.path().join("avocado/hitl");
+    fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
+
+    // Create two different extensions
+    for (name, version) in &[("myext", "1.0.0"), ("otherext", "2.0.0")] {
+        let ext_name = format!("{name}-{version}");
+        let ext_dir = extensions_dir.join(&ext_name);
+        fs::create_dir(&ext_dir).expect("Failed to create extension directory");
+        let release_dir = ext_dir.join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+        fs::write(
+            release_dir.join(format!("extension-release.{ext_name}")),
+            "ID=avocado\nVERSION_ID=1.0",
+        )
+        .expect("Failed to write release file");
+    }
+
+    let test_env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_TEST_MODE", "1"),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    // Enable both extensions
+    let enable_output = run_avocadoctl_with_env(
+        &["enable", "--verbose", "myext-1.0.0", "otherext-2.0.0"],
+        &test_env,
+    );
+    assert!(enable_output.status.success(), "Enable should succeed");
+
+    // Refresh to create symlinks
+    let (refresh1, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
+    assert!(refresh1.status.success(), "First refresh should succeed");
+
+    let sysext_dir = temp_dir.path().join("test_extensions");
+
+    // Verify both symlinks exist
+    assert!(
+        sysext_dir.join("myext-1.0.0").exists(),
+        "myext-1.0.0 should exist"
+    );
+    assert!(
+        sysext_dir.join("otherext-2.0.0").exists(),
+        "otherext-2.0.0 should exist"
+    );
+
+    // Create HITL mount for myext only
+    fs::create_dir_all(&hitl_dir).expect("Failed to create HITL directory");
+    let hitl_ext_dir = hitl_dir.join("myext");
+    fs::create_dir(&hitl_ext_dir).expect("Failed to create HITL extension directory");
+    let hitl_release_dir = hitl_ext_dir.join("usr/lib/extension-release.d");
+    fs::create_dir_all(&hitl_release_dir).expect("Failed to create HITL release dir");
+    fs::write(
+        hitl_release_dir.join("extension-release.myext"),
+        "ID=avocado\nVERSION_ID=1.0",
+    )
+    .expect("Failed to write HITL release file");
+
+    // Refresh with HITL mount
+    let (refresh2, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
+    assert!(refresh2.status.success(), "Second refresh should succeed");
+
+    // Verify myext-1.0.0 is masked but otherext-2.0.0 remains
+    assert!(
+        !sysext_dir.join("myext-1.0.0").exists(),
+        "myext-1.0.0 should be masked"
+    );
+    assert!(sysext_dir.join("myext").exists(), "HITL myext should exist");
+    assert!(
+        sysext_dir.join("otherext-2.0.0").exists(),
+        "otherext-2.0.0 should NOT be masked (different base name)"
+    );
+}
+
+#[test]
+fn test_hitl_mount_removal_restores_versioned() {
+    // Test that removing HITL mount allows the versioned extension to be used again
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let hitl_dir = temp_dir.path().join("avocado/hitl");
+    fs::create_dir_all(&extensions_dir).expect("Failed to create extensions directory");
+
+    // Create a versioned extension
+    let versioned_ext_dir = extensions_dir.join("myext-1.0.0");
+    fs::create_dir(&versioned_ext_dir).expect("Failed to create versioned extension directory");
+    let versioned_release_dir = versioned_ext_dir.join("usr/lib/extension-release.d");
+    fs::create_dir_all(&versioned_release_dir).expect("Failed to create release dir");
+    fs::write(
+        versioned_release_dir.join("extension-release.myext-1.0.0"),
+        "ID=avocado\nVERSION_ID=1.0",
+    )
+    .expect("Failed to write release file");
+
+    let test_env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_TEST_MODE", "1"),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    // Enable the versioned extension
+    let enable_output = run_avocadoctl_with_env(&["enable", "--verbose", "myext-1.0.0"], &test_env);
+    assert!(enable_output.status.success(), "Enable should succeed");
+
+    // Create and use HITL mount
+    fs::create_dir_all(&hitl_dir).expect("Failed to create HITL directory");
+    let hitl_ext_dir = hitl_dir.join("myext");
+    fs::create_dir(&hitl_ext_dir).expect("Failed to create HITL extension directory");
+    let hitl_release_dir = hitl_ext_dir.join("usr/lib/extension-release.d");
+    fs::create_dir_all(&hitl_release_dir).expect("Failed to create HITL release dir");
+    fs::write(
+        hitl_release_dir.join("extension-release.myext"),
+        "ID=avocado\nVERSION_ID=1.0",
+    )
+    .expect("Failed to write HITL release file");
+
+    // Refresh with HITL
+    let (refresh1, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--verbose"], &test_env);
+    assert!(
+        refresh1.status.success(),
+        "Refresh with HITL should succeed"
+    );
+
+    let sysext_dir = temp_dir.path().join("test_extensions");
     assert!(
         sysext_dir.join("myext").exists(),
         "HITL symlink should exist"
@@ -2746,3 +4037,3357 @@ fn test_ext_refresh_executes_on_unmerge_before_unmerge() {
     // Verify that both pre-unmerge and post-merge commands are executed in order
     // Pre-unmerge commands should appear before unmerge, post-merge should appear after merge
 }
+
+/// Test ext snapshot writes a file and ext compare reports differences
+#[test]
+fn test_ext_snapshot_and_compare() {
+    let snapshot_dir = TempDir::new().expect("Failed to create temp directory");
+    let left = snapshot_dir.path().join("left.json");
+    let left_str = left.to_string_lossy().to_string();
+
+    let (output, _temp_dir) =
+        run_avocadoctl_with_isolated_env(&["ext", "snapshot", &left_str], &[]);
+    assert!(output.status.success(), "ext snapshot should succeed");
+    let content = fs::read_to_string(&left).expect("snapshot file should be written");
+    let snapshot: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert!(snapshot["extensions"].is_array());
+
+    // Comparing against the live state right away should show nothing.
+    let (output, _temp_dir) = run_avocadoctl_with_isolated_env(&["ext", "compare", &left_str], &[]);
+    assert!(output.status.success(), "ext compare should succeed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("No differences"), "stdout: {stdout}");
+
+    // A second snapshot with a version bump and a disabled extension.
+    let right = snapshot_dir.path().join("right.json");
+    fs::write(
+        &right,
+        r#"{"version":1,"extensions":[
+            {"name":"test-ext-1","version":"2.0","enabled":false,"merged":false,
+             "is_sysext":true,"is_confext":false}
+        ]}"#,
+    )
+    .unwrap();
+    let right_str = right.to_string_lossy().to_string();
+    let (output, _temp_dir) = run_avocadoctl_with_isolated_env(
+        &["ext", "compare", &left_str, &right_str, "-o", "json"],
+        &[],
+    );
+    assert!(output.status.success(), "ext compare should succeed");
+    let json: serde_json::Value =
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+    let kinds: Vec<&str> = json["differences"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|d| d["kind"].as_str())
+        .collect();
+    assert!(kinds.contains(&"version"), "kinds: {kinds:?}");
+    assert!(kinds.contains(&"only_in_left"), "kinds: {kinds:?}");
+}
+
+/// Test that AVOCADO_REBOOT_REQUIRED=yes is recorded and reported via exit code
+#[test]
+fn test_ext_merge_reports_reboot_required() {
+    let release_dir = TempDir::new().expect("Failed to create temp directory");
+    fs::write(
+        release_dir.path().join("extension-release.kernel-tweaks"),
+        "ID=_any\nAVOCADO_REBOOT_REQUIRED=yes\n",
+    )
+    .unwrap();
+    fs::write(
+        release_dir.path().join("extension-release.plain"),
+        "ID=_any\n",
+    )
+    .unwrap();
+
+    let (output, temp_dir) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge"],
+        &[(
+            "AVOCADO_EXTENSION_RELEASE_DIR",
+            &release_dir.path().to_string_lossy(),
+        )],
+    );
+
+    assert_eq!(
+        output.status.code(),
+        Some(3),
+        "merge should exit with the reboot-required code; stdout: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Extensions merged successfully"));
+    assert!(stdout.contains("Reboot required by: kernel-tweaks"));
+
+    let marker = temp_dir.path().join("avocado/reboot-required");
+    let content = fs::read_to_string(marker).expect("reboot marker should be written");
+    assert_eq!(content.trim(), "kernel-tweaks");
+
+    // In JSON mode the request is part of the one result document
+    let (output, _temp_dir) = run_avocadoctl_with_isolated_env(
+        &["-o", "json", "ext", "merge"],
+        &[(
+            "AVOCADO_EXTENSION_RELEASE_DIR",
+            &release_dir.path().to_string_lossy(),
+        )],
+    );
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("merge JSON: {e}: {output:?}"));
+    assert_eq!(result["status"], "ok");
+    assert_eq!(
+        result["reboot_required"],
+        serde_json::json!(["kernel-tweaks"])
+    );
+}
+
+/// Test ext refresh --soft-reboot syncs state and requests a soft-reboot without a live refresh
+#[test]
+fn test_ext_refresh_soft_reboot() {
+    let (output, _temp_dir) =
+        run_avocadoctl_with_isolated_env(&["ext", "refresh", "--soft-reboot", "--verbose"], &[]);
+
+    assert!(
+        output.status.success(),
+        "ext refresh --soft-reboot should succeed with mocks: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("soft-reboot requested"),
+        "Should report the soft-reboot request"
+    );
+    assert!(
+        !stdout.contains("Extensions unmerged"),
+        "Should not unmerge extensions on the live system"
+    );
+}
+
+/// Test that ext test reports checks for an extension directory and fails on a bad health check
+#[test]
+fn test_ext_test_runs_checks_in_isolation() {
+    let ext_root = TempDir::new().expect("Failed to create temp directory");
+    let ext_dir = ext_root.path().join("probe-ext");
+    let release_dir = ext_dir.join("usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    let release_file = release_dir.join("extension-release.probe-ext");
+    fs::write(
+        &release_file,
+        "ID=_any\nAVOCADO_ON_MERGE=\"sh -c true\"\nAVOCADO_HEALTH_CHECK=\"true\"\n",
+    )
+    .unwrap();
+    let ext_str = ext_dir.to_string_lossy().to_string();
+
+    let (output, _temp_dir) =
+        run_avocadoctl_with_isolated_env(&["ext", "test", &ext_str, "-o", "json"], &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "ext test should pass: {stdout}");
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json["passed"], true);
+    let checks: Vec<&str> = json["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|c| c["check"].as_str())
+        .collect();
+    assert!(checks.contains(&"sysext-merge"), "checks: {checks:?}");
+    assert!(checks.contains(&"on-merge"), "checks: {checks:?}");
+    assert!(checks.contains(&"health-check"), "checks: {checks:?}");
+
+    fs::write(&release_file, "ID=_any\nAVOCADO_HEALTH_CHECK=\"false\"\n").unwrap();
+    let (output, _temp_dir) = run_avocadoctl_with_isolated_env(&["ext", "test", &ext_str], &[]);
+    assert!(!output.status.success(), "failing health check should fail");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("FAIL"), "stdout: {stdout}");
+}
+
+/// Test that --backend mock runs merge without systemd binaries and records intended actions
+#[test]
+fn test_mock_backend_records_actions() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("images");
+    let release_dir = extensions_dir.join("devext/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.devext"),
+        "ID=_any\nAVOCADO_ON_MERGE=\"systemctl restart devext.service\"\n",
+    )
+    .unwrap();
+
+    // No mock executables on PATH: everything must be simulated in-process.
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("PATH", "/usr/bin:/bin"),
+    ];
+    let output = run_avocadoctl_with_env(&["--backend", "mock", "ext", "merge"], &env);
+    assert!(
+        output.status.success(),
+        "mock merge should succeed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let log = fs::read_to_string(temp_dir.path().join("avocado/mock-backend/actions.log"))
+        .expect("action log should be written");
+    assert!(log.contains("\"systemd-sysext\""), "log: {log}");
+    assert!(log.contains("\"systemd-confext\""), "log: {log}");
+    assert!(log.contains("devext.service"), "log: {log}");
+
+    let output = run_avocadoctl_with_env(&["--backend", "mock", "ext", "status"], &env);
+    assert!(output.status.success(), "mock status should succeed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("devext"), "stdout: {stdout}");
+}
+
+/// Test that a merge and a forced refresh (unmerge, then merge) each reload
+/// systemd once
+#[test]
+fn test_mock_backend_reloads_systemd_once() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("images");
+    let release_dir = extensions_dir.join("devext/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.devext"), "ID=_any\n").unwrap();
+
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("PATH", "/usr/bin:/bin"),
+    ];
+    let log_path = temp_dir.path().join("avocado/mock-backend/actions.log");
+    let reloads = || {
+        fs::read_to_string(&log_path)
+            .unwrap()
+            .lines()
+            .filter(|line| line.contains("daemon-reload"))
+            .count()
+    };
+
+    let output = run_avocadoctl_with_env(&["--backend", "mock", "ext", "merge"], &env);
+    assert!(output.status.success(), "mock merge should succeed");
+    assert_eq!(reloads(), 1);
+
+    let output = run_avocadoctl_with_env(&["--backend", "mock", "ext", "refresh", "--force"], &env);
+    assert!(output.status.success(), "mock refresh should succeed");
+    assert_eq!(reloads(), 2);
+    let log = fs::read_to_string(&log_path).unwrap();
+    assert!(
+        log.contains(r#"["unmerge","--no-reload","--json=short"]"#),
+        "log: {log}"
+    );
+}
+
+/// Test that .tar.zst archives in the extensions dir are unpacked into the cache and merged
+#[test]
+fn test_ext_merge_unpacks_archive_extension() {
+    let images = TempDir::new().expect("Failed to create temp directory");
+    let archive_path = images.path().join("bundle-1.0.tar.zst");
+    {
+        let file = fs::File::create(&archive_path).unwrap();
+        let encoder = zstd::stream::Encoder::new(file, 3).unwrap();
+        let mut builder = tar::Builder::new(encoder);
+        let body = b"ID=_any\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(body.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(
+                &mut header,
+                "usr/lib/extension-release.d/extension-release.bundle-1.0",
+                &body[..],
+            )
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    let (output, temp_dir) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge"],
+        &[("AVOCADO_EXTENSIONS_PATH", images.path().to_str().unwrap())],
+    );
+    assert!(
+        output.status.success(),
+        "merge should succeed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let cached = temp_dir.path().join("avocado/archive-cache/bundle-1.0");
+    assert!(
+        cached
+            .join("usr/lib/extension-release.d/extension-release.bundle-1.0")
+            .exists(),
+        "archive should be unpacked into the cache"
+    );
+    let link = temp_dir.path().join("test_extensions/bundle-1.0");
+    assert_eq!(fs::read_link(&link).unwrap(), cached);
+}
+
+/// Test that os_release_fallback = "none" ignores the base extensions dir when no os-releases dir exists
+#[test]
+fn test_os_release_fallback_none_skips_base_dir() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("images");
+    fs::create_dir_all(extensions_dir.join("base_only_ext")).unwrap();
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(
+        &config_path,
+        "[avocado.ext]\ndir = \"/var/lib/avocado/images\"\nos_release_fallback = \"none\"\n",
+    )
+    .unwrap();
+
+    let extensions = extensions_dir.to_str().unwrap();
+    let (output, _tmp) = run_avocadoctl_with_isolated_env(
+        &["ext", "list"],
+        &[("AVOCADO_EXTENSIONS_PATH", extensions)],
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("base_only_ext"));
+
+    let (output, _tmp) = run_avocadoctl_with_isolated_env(
+        &["-c", config_path.to_str().unwrap(), "ext", "list"],
+        &[("AVOCADO_EXTENSIONS_PATH", extensions)],
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("base_only_ext"), "stdout: {stdout}");
+}
+
+/// Test that missing_dir = "error" fails ext list and merge on a missing
+/// extensions directory, and that create_dirs creates the directories instead
+#[test]
+fn test_missing_dir_policy_and_create_dirs() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("images");
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(
+        &config_path,
+        "[avocado.ext]\ndir = \"/var/lib/avocado/images\"\nmissing_dir = \"error\"\n",
+    )
+    .unwrap();
+    let test_env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let config = config_path.to_str().unwrap();
+
+    for command in ["list", "merge"] {
+        let (output, _) =
+            run_avocadoctl_with_isolated_env(&["-c", config, "ext", command], &test_env);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "ext {command} should fail");
+        assert!(stderr.contains("does not exist"), "stderr: {stderr}");
+    }
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "list"], &test_env);
+    assert!(
+        output.status.success(),
+        "the default policy ignores missing directories: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    fs::write(
+        &config_path,
+        "[avocado.ext]\ndir = \"/var/lib/avocado/images\"\nmissing_dir = \"error\"\ncreate_dirs = true\n",
+    )
+    .unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(&["-c", config, "ext", "list"], &test_env);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(extensions_dir.is_dir());
+    assert!(temp_dir.path().join("avocado/os-releases").is_dir());
+}
+
+/// Test that the top-level merge and refresh use the loaded configuration
+#[test]
+fn test_top_level_commands_use_config() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("images");
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(
+        &config_path,
+        "[avocado.ext]\ndir = \"/var/lib/avocado/images\"\nmissing_dir = \"error\"\n",
+    )
+    .unwrap();
+    let test_env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let config = config_path.to_str().unwrap();
+
+    for command in ["merge", "refresh"] {
+        let (output, _) = run_avocadoctl_with_isolated_env(&["-c", config, command], &test_env);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "{command} should fail");
+        assert!(stderr.contains("does not exist"), "stderr: {stderr}");
+    }
+}
+
+/// Test that ext audit writes an inventory with image hashes and an optional signature
+#[test]
+fn test_ext_audit_writes_signed_report() {
+    let work = TempDir::new().expect("Failed to create temp directory");
+    let images = work.path().join("images");
+    fs::create_dir_all(images.join("audited")).unwrap();
+    let report_path = work.path().join("report.json");
+    let key_path = work.path().join("audit.key");
+    fs::write(&key_path, format!("{}\n", "11".repeat(32))).unwrap();
+
+    let (output, _tmp) = run_avocadoctl_with_isolated_env(
+        &[
+            "ext",
+            "audit",
+            report_path.to_str().unwrap(),
+            "--sign",
+            key_path.to_str().unwrap(),
+        ],
+        &[("AVOCADO_EXTENSIONS_PATH", images.to_str().unwrap())],
+    );
+    assert!(
+        output.status.success(),
+        "ext audit should succeed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+    let signatures = report["signatures"].as_array().unwrap();
+    assert_eq!(signatures.len(), 1);
+    assert_eq!(signatures[0]["sig"].as_str().unwrap().len(), 128);
+    assert!(report["signed"]["state"]["extensions"].is_array());
+    let audited = report["signed"]["images"].as_array().unwrap();
+    assert!(
+        audited.iter().any(|i| i["name"] == "audited"),
+        "images: {audited:?}"
+    );
+
+    // A bad key is rejected without writing anything.
+    fs::write(&key_path, "zz").unwrap();
+    let (output, _tmp) = run_avocadoctl_with_isolated_env(
+        &["ext", "audit", "--sign", key_path.to_str().unwrap()],
+        &[("AVOCADO_EXTENSIONS_PATH", images.to_str().unwrap())],
+    );
+    assert!(!output.status.success());
+}
+
+/// Test AVOCADO_MODPROBE_BLACKLIST writes modprobe.d entries on merge and removes them on unmerge
+#[test]
+fn test_modprobe_blacklist_written_on_merge_and_removed_on_unmerge() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("camera/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).expect("Failed to create release dir");
+    fs::write(
+        release_dir.join("extension-release.camera"),
+        "ID=_any\nAVOCADO_MODPROBE_BLACKLIST=\"uvcvideo\"\n",
+    )
+    .expect("Failed to write release file");
+
+    let tmpdir = temp_dir.path().to_str().unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", tmpdir),
+    ];
+    let blacklist = temp_dir
+        .path()
+        .join("avocado/modprobe.d/avocado-ext-camera.conf");
+
+    let (merge, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(
+        merge.status.success(),
+        "merge failed: {}",
+        String::from_utf8_lossy(&merge.stderr)
+    );
+    let content = fs::read_to_string(&blacklist).expect("blacklist file should be written");
+    assert!(content.contains("blacklist uvcvideo"), "content: {content}");
+
+    let (unmerge, _) = run_avocadoctl_with_isolated_env(&["ext", "unmerge"], &env);
+    assert!(unmerge.status.success());
+    assert!(
+        !blacklist.exists(),
+        "blacklist should be removed on unmerge"
+    );
+}
+
+/// Test `ext run` executes a tool from an unmerged extension and passes its exit code through
+#[test]
+fn test_ext_run_uses_extension_tools_without_merging() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let bin_dir = extensions_dir.join("tools-1.0/usr/bin");
+    fs::create_dir_all(&bin_dir).expect("Failed to create bin dir");
+    let tool = bin_dir.join("ext-tool");
+    fs::write(&tool, "#!/bin/sh\necho \"ext-tool $*\"\nexit 3\n").expect("Failed to write tool");
+    fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).expect("chmod failed");
+
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "run", "tools-1.0", "--", "ext-tool", "--flag", "x"],
+        &env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("ext-tool --flag x"), "stdout: {stdout}");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        !temp_dir.path().join("test_extensions/tools-1.0").exists(),
+        "ext run must not link the extension"
+    );
+
+    let (missing, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "run", "nope", "--", "true"], &env);
+    assert!(!missing.status.success());
+    assert!(
+        String::from_utf8_lossy(&missing.stderr).contains("not found")
+            || String::from_utf8_lossy(&missing.stdout).contains("not found")
+    );
+}
+
+/// Test that [avocado.ext] image_policy and noexec are passed to systemd-dissect and merge
+#[test]
+fn test_image_policy_and_noexec_passed_through() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("images");
+    fs::create_dir_all(&extensions_dir).unwrap();
+    fs::write(extensions_dir.join("signed-1.0.raw"), b"raw").unwrap();
+
+    let config_path = temp_dir.path().join("avocadoctl.conf");
+    fs::write(
+        &config_path,
+        "[avocado.ext]\ndir = \"/var/lib/avocado/images\"\nimage_policy = \"usr=verity+signed\"\nnoexec = true\n",
+    )
+    .unwrap();
+
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("PATH", "/usr/bin:/bin"),
+    ];
+    let output = run_avocadoctl_with_env(
+        &[
+            "--backend",
+            "mock",
+            "--config",
+            config_path.to_str().unwrap(),
+            "ext",
+            "merge",
+        ],
+        &env,
+    );
+    assert!(
+        output.status.success(),
+        "mock merge should succeed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let log = fs::read_to_string(temp_dir.path().join("avocado/mock-backend/actions.log"))
+        .expect("action log should be written");
+    let lines_for = |program: &str| -> Vec<&str> {
+        log.lines()
+            .filter(|l| l.contains(&format!("\"program\":\"{program}\"")))
+            .collect()
+    };
+    assert!(
+        lines_for("systemd-dissect")
+            .iter()
+            .any(|l| l.contains("--image-policy=usr=verity+signed")),
+        "log: {log}"
+    );
+    for program in ["systemd-sysext", "systemd-confext"] {
+        let merge = lines_for(program)
+            .into_iter()
+            .find(|l| l.contains("\"merge\""))
+            .unwrap_or_else(|| panic!("no {program} merge in log: {log}"));
+        assert!(
+            merge.contains("--image-policy=usr=verity+signed"),
+            "{merge}"
+        );
+        assert!(merge.contains("--noexec=yes"), "{merge}");
+    }
+}
+
+/// Test `ext top --once` runs without a daemon and reports when no images are mounted
+#[test]
+fn test_ext_top_once_without_mounted_images() {
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "top", "--once"], &[]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("No image-backed extensions are mounted"),
+        "stdout: {stdout}"
+    );
+
+    let (json, _) = run_avocadoctl_with_isolated_env(&["ext", "top", "--once", "-o", "json"], &[]);
+    assert!(json.status.success());
+    assert_eq!(String::from_utf8_lossy(&json.stdout).trim(), "[]");
+}
+
+/// Test `ext apply` computes the net change and applies it with one refresh
+#[test]
+fn test_ext_apply_manifest_net_change() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    for name in ["cam-1.0", "cam-2.0", "dbg-1.0", "net-1.0"] {
+        let release_dir = extensions_dir.join(format!("{name}/usr/lib/extension-release.d"));
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\nVERSION_ID=1.0",
+        )
+        .unwrap();
+    }
+    let releases_dir = temp_dir.path().join("avocado/os-releases/1.0");
+    fs::create_dir_all(&releases_dir).unwrap();
+    for name in ["cam-1.0", "dbg-1.0", "net-1.0"] {
+        std::os::unix::fs::symlink(extensions_dir.join(name), releases_dir.join(name)).unwrap();
+    }
+
+    let manifest = temp_dir.path().join("tx.toml");
+    fs::write(
+        &manifest,
+        "os_release = \"1.0\"\nenable = [\"net-1.0\"]\ndisable = [\"dbg-1.0\"]\nupdate = [\"cam-2.0\"]\n",
+    )
+    .unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let manifest_arg = manifest.to_str().unwrap();
+
+    let (dry_run, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "apply", "--manifest", manifest_arg, "--dry-run"],
+        &env,
+    );
+    assert!(dry_run.status.success());
+    let stdout = String::from_utf8_lossy(&dry_run.stdout);
+    assert!(stdout.contains("disable cam-1.0"), "stdout: {stdout}");
+    assert!(stdout.contains("disable dbg-1.0"), "stdout: {stdout}");
+    assert!(stdout.contains("enable cam-2.0 ->"), "stdout: {stdout}");
+    assert!(!stdout.contains("net-1.0"), "already enabled: {stdout}");
+    assert!(releases_dir.join("cam-1.0").is_symlink());
+
+    let (applied, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "apply", "--manifest", manifest_arg], &env);
+    assert!(
+        applied.status.success(),
+        "apply should succeed: {}",
+        String::from_utf8_lossy(&applied.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&applied.stdout);
+    assert!(stdout.contains("1 enabled, 2 disabled"), "stdout: {stdout}");
+    let mut links: Vec<String> = fs::read_dir(&releases_dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    links.sort();
+    assert_eq!(links, vec!["cam-2.0", "net-1.0"]);
+
+    // Applying again is a no-op and does not refresh
+    let (again, _) = run_avocadoctl_with_isolated_env(
+        &["-o", "json", "ext", "apply", "--manifest", manifest_arg],
+        &env,
+    );
+    assert!(again.status.success());
+    let stdout = String::from_utf8_lossy(&again.stdout);
+    assert!(stdout.contains("\"refreshed\":false"), "stdout: {stdout}");
+
+    // A conflicting or unresolvable manifest changes nothing
+    fs::write(
+        &manifest,
+        "os_release = \"1.0\"\nenable = [\"dbg-1.0\", \"missing-9\"]\ndisable = [\"net-1.0\"]\n",
+    )
+    .unwrap();
+    let (rejected, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "apply", "--manifest", manifest_arg], &env);
+    assert!(!rejected.status.success());
+    assert!(releases_dir.join("net-1.0").is_symlink());
+    assert!(!releases_dir.join("dbg-1.0").exists());
+}
+
+/// Test that errors carry a catalogue code and hint, also in JSON output
+#[test]
+fn test_missing_tool_error_has_code_and_hint() {
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "merge"], &[("PATH", "/nonexistent")]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[E0001]"), "stderr: {stderr}");
+    assert!(
+        stderr.contains("Hint: systemd-sysext not found: install systemd >= 251"),
+        "stderr: {stderr}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["-o", "json", "ext", "merge"],
+        &[("PATH", "/nonexistent")],
+    );
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let error: serde_json::Value = stdout
+        .lines()
+        .find_map(|l| serde_json::from_str(l).ok())
+        .unwrap_or_else(|| panic!("no JSON error object in: {stdout}"));
+    assert_eq!(error["status"], "error");
+    assert_eq!(error["code"], "E0001");
+    assert!(error["hint"].as_str().unwrap().contains("systemd >= 251"));
+}
+
+/// Test that a traced merge posts its spans to the OTLP endpoint and that
+/// the JSON result carries the trace id
+#[cfg(feature = "network")]
+#[test]
+fn test_merge_exports_trace_and_reports_trace_id() {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let collector = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n{}")
+            .unwrap();
+        (request_line, String::from_utf8(body).unwrap())
+    });
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["-o", "json", "merge"],
+        &[("OTEL_EXPORTER_OTLP_ENDPOINT", &endpoint)],
+    );
+    assert!(output.status.success(), "merge: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result: serde_json::Value = stdout
+        .lines()
+        .find_map(|l| serde_json::from_str(l).ok())
+        .unwrap_or_else(|| panic!("no JSON result in: {stdout}"));
+    let trace_id = result["trace_id"].as_str().expect("trace_id");
+    assert_eq!(trace_id.len(), 32);
+
+    let (request_line, body) = collector.join().unwrap();
+    assert!(
+        request_line.starts_with("POST /v1/traces "),
+        "{request_line}"
+    );
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let spans = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .unwrap();
+    let merge = spans.iter().find(|s| s["name"] == "merge").unwrap();
+    assert_eq!(merge["traceId"], trace_id);
+    assert_eq!(merge["spanId"], result["span_id"]);
+    assert!(spans.iter().any(|s| s["name"] == "scanning"));
+    assert!(spans.iter().all(|s| s["traceId"] == trace_id));
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["-o", "json", "merge"], &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("trace_id"), "stdout: {stdout}");
+}
+
+/// Test that JSON results carry the message ID and that messages are localized
+#[test]
+fn test_messages_have_ids_and_translations() {
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["-o", "json", "merge"], &[("AVOCADO_LOCALE", "C")]);
+    assert!(output.status.success(), "merge: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result: serde_json::Value = stdout
+        .lines()
+        .find_map(|l| serde_json::from_str(l).ok())
+        .unwrap_or_else(|| panic!("no JSON result in: {stdout}"));
+    assert_eq!(result["status"], "ok");
+    assert_eq!(result["message_id"], "ext.merged");
+    assert_eq!(result["message"], "Extensions merged successfully");
+
+    let messages_dir = TempDir::new().expect("Failed to create temp directory");
+    fs::write(
+        messages_dir.path().join("de.toml"),
+        "\"ext.merged\" = \"Erweiterungen zusammengeführt\"\n",
+    )
+    .unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["merge"],
+        &[
+            ("AVOCADO_LOCALE", "de_DE.UTF-8"),
+            (
+                "AVOCADO_MESSAGES_DIR",
+                messages_dir.path().to_str().unwrap(),
+            ),
+        ],
+    );
+    assert!(output.status.success(), "merge: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("[SUCCESS] Erweiterungen zusammengeführt"),
+        "stdout: {stdout}"
+    );
+}
+
+/// Test merging into a container and a chroot target
+#[test]
+fn test_ext_merge_into_target() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.app-1.0"),
+        "ID=_any\nVERSION_ID=1.0",
+    )
+    .unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "merge", "--target", "web", "--verbose"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(stdout.contains("container 'web'"), "stdout: {stdout}");
+    assert!(stdout.contains("Published sysext"), "stdout: {stdout}");
+    assert!(stdout.contains("systemd-sysext merge"), "stdout: {stdout}");
+    assert!(
+        stdout.contains("skipping host module loading"),
+        "stdout: {stdout}"
+    );
+
+    let chroot = temp_dir.path().join("chroot");
+    fs::create_dir_all(&chroot).unwrap();
+    let chroot_arg = chroot.to_str().unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge", "--target", chroot_arg, "--verbose"],
+        &env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Extensions merged into chroot"),
+        "stdout: {stdout}"
+    );
+    let mount_points: Vec<_> = fs::read_dir(chroot.join("run/extensions"))
+        .unwrap()
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    assert!(
+        mount_points.iter().any(|name| name.contains("app")),
+        "mount points: {mount_points:?}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge", "--target", "a b"], &env);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid merge target"));
+}
+
+/// Test that hook command output is recorded per extension and replayed by ext info
+#[test]
+fn test_hook_output_recorded_and_replayed() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let release_dir = temp_dir.path().join("releases");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.app-1.0"),
+        "ID=_any\nAVOCADO_ON_MERGE=\"failing_hook --now\"\n",
+    )
+    .unwrap();
+    let env = [
+        (
+            "AVOCADO_EXTENSION_RELEASE_DIR",
+            release_dir.to_str().unwrap(),
+        ),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("failed with exit code 3: error: hook gave up"),
+        "stderr: {stderr}"
+    );
+    assert!(stderr.contains("hooks/app-1.0.log"), "stderr: {stderr}");
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "info", "app-1.0", "--replay"], &env);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("on-merge"), "stdout: {stdout}");
+    assert!(stdout.contains("exit 3"), "stdout: {stdout}");
+    assert!(stdout.contains("failing_hook --now"), "stdout: {stdout}");
+    assert!(stdout.contains("detail: first problem"), "stdout: {stdout}");
+    assert!(
+        stdout.contains("mock-failing_hook starting"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "info", "app-1.0", "-o", "json"], &env);
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["hook_runs"][0]["exit_code"], 3);
+}
+
+/// Test that hook logs are rotated at the configured caps and can be listed,
+/// tailed and purged with `logs`
+#[test]
+fn test_hook_logs_rotate_and_purge() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let release_dir = temp_dir.path().join("releases");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.app-1.0"),
+        "ID=_any\nAVOCADO_ON_MERGE=\"failing_hook --now\"\n",
+    )
+    .unwrap();
+    // A size cap of 0 rotates before every record
+    let env = [
+        (
+            "AVOCADO_EXTENSION_RELEASE_DIR",
+            release_dir.to_str().unwrap(),
+        ),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_LOG_MAX_SIZE_KB", "0"),
+        ("AVOCADO_LOG_KEEP", "1"),
+    ];
+    for _ in 0..3 {
+        let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+        assert!(output.status.success());
+    }
+    let hooks = temp_dir.path().join("avocado/log/hooks");
+    assert!(hooks.join("app-1.0.log").exists());
+    assert!(hooks.join("app-1.0.log.1").exists());
+    assert!(!hooks.join("app-1.0.log.2").exists());
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["logs", "list", "-o", "json"], &env);
+    assert!(output.status.success());
+    let body: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let logs = &body["logs"];
+    assert_eq!(logs[0]["name"], "app-1.0");
+    assert_eq!(logs[0]["files"], 2);
+    assert_eq!(logs[0]["records"], 2);
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["logs", "tail", "app-1.0", "-n", "1"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 1, "stdout: {stdout}");
+    assert!(stdout.contains("exit 3"), "stdout: {stdout}");
+    assert!(stdout.contains("failing_hook --now"), "stdout: {stdout}");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["logs", "view", "app-1.0"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("error: hook gave up"), "stdout: {stdout}");
+
+    // Nothing is older than a day yet
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["logs", "purge", "--older-than", "1"], &env);
+    assert!(output.status.success());
+    assert!(hooks.join("app-1.0.log").exists());
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["logs", "purge", "app-1.0"], &env);
+    assert!(output.status.success());
+    assert!(!hooks.join("app-1.0.log").exists());
+    assert!(!hooks.join("app-1.0.log.1").exists());
+    let (output, _) = run_avocadoctl_with_isolated_env(&["logs", "list", "-o", "json"], &env);
+    let body: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(body["logs"], serde_json::json!([]));
+}
+
+/// Test that hook output is shown line by line with --verbose and not otherwise
+#[test]
+fn test_ext_merge_verbose_streams_hook_output() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let release_dir = temp_dir.path().join("releases");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.app-1.0"),
+        "ID=_any\nAVOCADO_ON_MERGE=\"failing_hook --now\"\n",
+    )
+    .unwrap();
+    let env = [(
+        "AVOCADO_EXTENSION_RELEASE_DIR",
+        release_dir.to_str().unwrap(),
+    )];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge", "--verbose"], &env);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("failing_hook: [TEST] mock-failing_hook starting"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains("failing_hook: error: hook gave up"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        !stdout.contains("failing_hook: error: hook gave up"),
+        "stdout: {stdout}"
+    );
+}
+
+/// Test that merging without a running systemd fails with one clear error,
+/// while `--mount-only` still links the extensions
+#[test]
+fn test_merge_without_systemd() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(extensions_dir.join("app-1.0/usr/lib/extension-release.d")).unwrap();
+    fs::write(
+        extensions_dir.join("app-1.0/usr/lib/extension-release.d/extension-release.app-1.0"),
+        "ID=_any\n",
+    )
+    .unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_SYSTEMD_RUNNING", "0"),
+    ];
+
+    for args in [&["merge"][..], &["ext", "unmerge"], &["refresh"]] {
+        let (output, _) = run_avocadoctl_with_isolated_env(args, &env);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{args:?}: {stderr}");
+        assert!(
+            stderr.contains("systemd is not running (no /run/systemd/system)"),
+            "{args:?}: {stderr}"
+        );
+        assert!(stderr.contains("[E0028]"), "{args:?}: {stderr}");
+        assert!(stderr.contains("--mount-only"), "{args:?}: {stderr}");
+    }
+
+    let (output, tmp) =
+        run_avocadoctl_with_isolated_env(&["merge", "--mount-only", "--verbose"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        stdout.contains("Prepared 1 extension(s) without merging them (--mount-only)"),
+        "stdout: {stdout}"
+    );
+    assert!(!stdout.contains("systemd-sysext merge"), "stdout: {stdout}");
+    assert!(tmp.path().join("test_extensions/app-1.0").exists());
+}
+
+/// Test that ext lint reports rule findings as JSON and SARIF and fails on errors
+#[test]
+fn test_ext_lint_reports_findings() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let ext = temp_dir.path().join("app");
+    let release_dir = ext.join("usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.app"),
+        "ID=avocado\nVERSION_ID=0.9\nAVOCADO_ON_MERGE=\"bash -c true\"\n",
+    )
+    .unwrap();
+    let ext_str = ext.to_string_lossy().to_string();
+    let target = ["--os-id", "avocado", "--version-id", "1.0"];
+
+    let mut args = vec!["ext", "lint", &ext_str, "-o", "json"];
+    args.extend(target);
+    let (output, _) = run_avocadoctl_with_isolated_env(&args, &[]);
+    assert!(!output.status.success(), "VERSION_ID mismatch is an error");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], false);
+    let rules: Vec<&str> = report["findings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["rule"].as_str().unwrap())
+        .collect();
+    assert!(rules.contains(&"AVL005"), "missing scope: {rules:?}");
+    assert!(rules.contains(&"AVL007"), "VERSION_ID mismatch: {rules:?}");
+    assert!(rules.contains(&"AVL009"), "broad hook: {rules:?}");
+
+    let mut args = vec!["ext", "lint", &ext_str, "--sarif"];
+    args.extend(target);
+    let (output, _) = run_avocadoctl_with_isolated_env(&args, &[]);
+    let sarif: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let result = sarif["runs"][0]["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["ruleId"] == "AVL007")
+        .unwrap();
+    assert_eq!(result["level"], "error");
+    assert_eq!(
+        result["locations"][0]["physicalLocation"]["region"]["startLine"],
+        2
+    );
+
+    fs::write(
+        release_dir.join("extension-release.app"),
+        "ID=avocado\nVERSION_ID=1.0\nSYSEXT_SCOPE=system\n",
+    )
+    .unwrap();
+    let mut args = vec!["ext", "lint", &ext_str];
+    args.extend(target);
+    let (output, _) = run_avocadoctl_with_isolated_env(&args, &[]);
+    assert!(
+        output.status.success(),
+        "stdout: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+/// Test that [avocado.tools] overrides replace the default tool names
+#[test]
+fn test_configured_tool_paths() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let marker = temp_dir.path().join("vendor-depmod-ran");
+    let depmod = temp_dir.path().join("vendor-depmod");
+    fs::write(
+        &depmod,
+        format!("#!/bin/sh\necho \"$@\" > {}\n", marker.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&depmod, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let config_path = temp_dir.path().join("avocadoctl.conf");
+    fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"/var/lib/avocado/images\"\n\n[avocado.tools]\ndepmod = \"{}\"\n",
+            depmod.display()
+        ),
+    )
+    .unwrap();
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["-c", config_path.to_str().unwrap(), "ext", "unmerge"],
+        &[],
+    );
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        marker.exists(),
+        "configured depmod should run instead of mock-depmod"
+    );
+}
+
+/// Test that apply executes a reviewed plan and refuses once the state drifted
+#[test]
+fn test_plan_and_apply_refuses_drift() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let add_extension = |name: &str| {
+        let release_dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\n",
+        )
+        .unwrap();
+    };
+    add_extension("app-1.0");
+    let plan_path = temp_dir.path().join("plan.json");
+    let plan_str = plan_path.to_string_lossy().to_string();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["plan", &plan_str], &env);
+    assert!(output.status.success(), "plan: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("+ merge   app-1.0"), "stdout: {stdout}");
+    let plan: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&plan_path).unwrap()).unwrap();
+    assert_eq!(plan["merge"], serde_json::json!(["app-1.0"]));
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["apply", &plan_str], &env);
+    assert!(
+        output.status.success(),
+        "apply of an unchanged plan should succeed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    add_extension("tools-2.0");
+    let (output, _) = run_avocadoctl_with_isolated_env(&["apply", &plan_str], &env);
+    assert!(!output.status.success(), "apply must refuse a drifted plan");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("tools-2.0 appeared after planning"),
+        "stdout: {stdout}"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("E0023"), "stderr: {stderr}");
+}
+
+/// Test that build provenance from the release file shows up in status,
+/// ext info and the audit report
+#[test]
+fn test_extension_provenance_reported() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.app"),
+        "ID=_any\nAVOCADO_BUILD_ID=ci-4312\nAVOCADO_GIT_SHA=\"9f1c2ab\"\nAVOCADO_BUILD_DATE=2026-10-01T12:00:00Z\n",
+    )
+    .unwrap();
+    let env = [("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status", "-o", "json"], &env);
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("status JSON: {e}: {output:?}"));
+    let app = status["extensions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["name"] == "app")
+        .unwrap_or_else(|| panic!("app missing from status: {status}"));
+    assert_eq!(app["provenance"]["build_id"], "ci-4312");
+    assert_eq!(app["provenance"]["git_sha"], "9f1c2ab");
+    assert_eq!(app["provenance"]["build_date"], "2026-10-01T12:00:00Z");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "info", "app"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Build ID:  ci-4312"), "stdout: {stdout}");
+    assert!(stdout.contains("Git SHA:   9f1c2ab"), "stdout: {stdout}");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "audit"], &env);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("audit JSON: {e}: {output:?}"));
+    let app = report["state"]["extensions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["name"] == "app")
+        .unwrap_or_else(|| panic!("app missing from audit: {report}"));
+    assert_eq!(app["provenance"]["build_id"], "ci-4312");
+}
+
+/// Test that AVOCADO_EOL and AVOCADO_NOTES show up in ext info, status and
+/// the audit report, with a warning for an extension past its end of life
+#[test]
+fn test_extension_eol_reported() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    for (name, eol) in [("legacy", "2020-01-31"), ("current", "2999-12-31")] {
+        let release_dir = extensions_dir.join(format!("{name}/usr/lib/extension-release.d"));
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            format!("ID=_any\nAVOCADO_EOL={eol}\nAVOCADO_NOTES=\"Replaced by app2\"\n"),
+        )
+        .unwrap();
+    }
+    let env = [("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Warning: legacy reached its end of life on 2020-01-31"),
+        "stdout: {stdout}"
+    );
+    assert!(!stdout.contains("Warning: current"), "stdout: {stdout}");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status", "-o", "json"], &env);
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("status JSON: {e}: {output:?}"));
+    assert_eq!(status["eol_reached"], serde_json::json!(["legacy"]));
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "info", "legacy"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("EOL:       2020-01-31 (reached)"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains("Notes:     Replaced by app2"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "audit"], &env);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("audit JSON: {e}: {output:?}"));
+    assert_eq!(report["eol_reached"], serde_json::json!(["legacy"]));
+    let current = report["state"]["extensions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["name"] == "current")
+        .unwrap_or_else(|| panic!("current missing from audit: {report}"));
+    assert_eq!(current["lifecycle"]["eol"], "2999-12-31");
+    assert_eq!(current["lifecycle"]["notes"], "Replaced by app2");
+}
+
+/// Test that `ext list --detailed` reports scopes and the enabled state
+#[test]
+fn test_ext_list_detailed() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.app-1.0"),
+        "ID=_any\nSYSEXT_SCOPE=\"system initrd\"\n",
+    )
+    .unwrap();
+    let release_dir = extensions_dir.join("tools-2.0/etc/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.tools-2.0"), "ID=_any\n").unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_TEST_MODE", "1"),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let output = run_avocadoctl_with_env(&["enable", "app-1.0"], &env);
+    assert!(output.status.success(), "enable failed: {output:?}");
+
+    let output = run_avocadoctl_with_env(&["ext", "list", "--detailed", "-o", "json"], &env);
+    let extensions: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("list JSON: {e}: {output:?}"));
+    let entry = |name: &str| {
+        extensions
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["name"] == name)
+            .unwrap_or_else(|| panic!("{name} missing from list: {extensions}"))
+            .clone()
+    };
+    let app = entry("app-1.0");
+    assert_eq!(app["enabled"], true);
+    assert_eq!(app["isSysext"], true);
+    assert_eq!(app["scopes"], serde_json::json!(["system", "initrd"]));
+    let tools = entry("tools-2.0");
+    assert_eq!(tools["enabled"], false);
+    assert_eq!(tools["isConfext"], true);
+    assert_eq!(tools["scopes"], serde_json::json!([]));
+
+    let output = run_avocadoctl_with_env(&["ext", "list", "--detailed"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Total: 2 extension(s), 1 enabled for this os-release"),
+        "stdout: {stdout}"
+    );
+}
+
+/// Test that `ext prefetch` needs a repository and a trust anchor
+#[cfg(feature = "network")]
+#[test]
+fn test_ext_prefetch_requires_repository() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let base_dir = temp_dir.path().join("avocado");
+    let test_env = [("AVOCADO_BASE_DIR", base_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "prefetch"], &test_env);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "stderr: {stderr}");
+    assert!(stderr.contains("No update repository"), "stderr: {stderr}");
+    assert!(stderr.contains("E0005"), "stderr: {stderr}");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "prefetch", "--url", "http://127.0.0.1:9"],
+        &test_env,
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "stderr: {stderr}");
+    assert!(stderr.contains("No root authority"), "stderr: {stderr}");
+    assert!(!base_dir.join("prefetch.json").exists());
+}
+
+/// Write a runtime manifest with the given extension versions under
+/// `runtimes/<id>`.
+fn write_runtime(base_dir: &std::path::Path, id: &str, extensions: &[(&str, &str)]) {
+    let runtime_dir = base_dir.join("runtimes").join(id);
+    fs::create_dir_all(&runtime_dir).unwrap();
+    let extensions: Vec<serde_json::Value> = extensions
+        .iter()
+        .map(|(name, version)| serde_json::json!({ "name": name, "version": version }))
+        .collect();
+    let manifest = serde_json::json!({
+        "manifest_version": 1,
+        "id": id,
+        "built_at": "2026-01-01T00:00:00Z",
+        "runtime": { "name": "dev", "version": "1.0.0" },
+        "extensions": extensions,
+    });
+    fs::write(runtime_dir.join("manifest.json"), manifest.to_string()).unwrap();
+}
+
+#[test]
+fn test_json_status_with_active_runtime() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let base_dir = temp_dir.path().join("avocado");
+    write_runtime(&base_dir, "0123456789abcdef", &[]);
+    std::os::unix::fs::symlink("runtimes/0123456789abcdef", base_dir.join("active")).unwrap();
+    let test_env = [("AVOCADO_BASE_DIR", base_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["status"], &test_env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Runtime: dev 1.0.0 (01234567)"),
+        "stdout: {stdout}"
+    );
+
+    // The runtime lines would put text ahead of the JSON document
+    let (output, _) = run_avocadoctl_with_isolated_env(&["--json", "status"], &test_env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    let result: serde_json::Value = serde_json::from_str(&stdout).expect("one JSON document");
+    assert!(result["extensions"].is_array(), "stdout: {stdout}");
+}
+
+#[test]
+fn test_ext_upgrade_offline_dry_run_follows_policy() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let base_dir = temp_dir.path().join("avocado");
+    write_runtime(
+        &base_dir,
+        "old",
+        &[("app", "1.0.0"), ("base", "2.0.0"), ("tools", "0.3.1")],
+    );
+    write_runtime(
+        &base_dir,
+        "new",
+        &[("app", "1.1.0"), ("base", "3.0.0"), ("tools", "0.3.1")],
+    );
+    std::os::unix::fs::symlink("runtimes/old", base_dir.join("active")).unwrap();
+    fs::write(
+        base_dir.join("prefetch.json"),
+        r#"{"runtime_id":"new","name":"dev","version":"1.0.0","url":"http://updates","fetched_at":0}"#,
+    )
+    .unwrap();
+    let config_path = temp_dir.path().join("avocadoctl.conf");
+    fs::write(
+        &config_path,
+        "[avocado.ext]\ndir = \"/tmp/ext\"\n\n[avocado.upgrade]\npolicy = \"minor\"\n",
+    )
+    .unwrap();
+    let test_env = [("AVOCADO_BASE_DIR", base_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            config_path.to_str().unwrap(),
+            "ext",
+            "upgrade",
+            "--offline",
+            "--dry-run",
+        ],
+        &test_env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("app 1.0.0 -> 1.1.0"), "stdout: {stdout}");
+    assert!(
+        stdout.contains("base 2.0.0 (3.0.0 held by 'minor' policy)"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains("tools 0.3.1 (up to date)"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains("1 extension(s) would be upgraded"),
+        "stdout: {stdout}"
+    );
+    assert_eq!(
+        fs::read_link(base_dir.join("active")).unwrap(),
+        std::path::PathBuf::from("runtimes/old")
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "upgrade", "--offline", "--dry-run", "missing"],
+        &test_env,
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "stderr: {stderr}");
+    assert!(
+        stderr.contains("Extension not found: missing"),
+        "stderr: {stderr}"
+    );
+}
+
+/// Test that `ext check-update` exits 100 when updates are available, 0 when
+/// up to date, and 101 when it cannot tell, as with a broken config
+#[test]
+fn test_ext_check_update_exit_codes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let base_dir = temp_dir.path().join("avocado");
+    write_runtime(&base_dir, "old", &[("app", "1.0.0"), ("tools", "0.3.1")]);
+    write_runtime(&base_dir, "new", &[("app", "1.1.0"), ("tools", "0.3.1")]);
+    std::os::unix::fs::symlink("runtimes/old", base_dir.join("active")).unwrap();
+    let test_env = [("AVOCADO_BASE_DIR", base_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "check-update", "--offline", "-o", "json"],
+        &test_env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(101), "stdout: {stdout}");
+    let body: serde_json::Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(body["state"], "unknown");
+
+    fs::write(
+        base_dir.join("prefetch.json"),
+        r#"{"runtime_id":"new","name":"dev","version":"1.0.0","url":"http://updates","fetched_at":0}"#,
+    )
+    .unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "check-update", "--offline", "-o", "json"],
+        &test_env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(100), "stdout: {stdout}");
+    let body: serde_json::Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(body["state"], "updates-available");
+    assert_eq!(body["reason"], "app 1.0.0 -> 1.1.0");
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "check-update", "--offline", "tools"], &test_env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    assert!(stdout.contains("up-to-date:"), "stdout: {stdout}");
+    assert_eq!(
+        fs::read_link(base_dir.join("active")).unwrap(),
+        std::path::PathBuf::from("runtimes/old")
+    );
+
+    let config_path = temp_dir.path().join("broken.toml");
+    fs::write(&config_path, "[avocado\n").unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            config_path.to_str().unwrap(),
+            "ext",
+            "check-update",
+            "--offline",
+        ],
+        &test_env,
+    );
+    assert_eq!(
+        output.status.code(),
+        Some(101),
+        "a broken config leaves the updates unknown: {output:?}"
+    );
+}
+
+/// Test that `ext upgrade` outside the maintenance windows is queued, and
+/// that `--force` upgrades anyway
+#[test]
+fn test_ext_upgrade_queued_outside_maintenance_window() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let base_dir = temp_dir.path().join("avocado");
+    write_runtime(&base_dir, "old", &[("app", "1.0.0")]);
+    write_runtime(&base_dir, "new", &[("app", "1.1.0")]);
+    std::os::unix::fs::symlink("runtimes/old", base_dir.join("active")).unwrap();
+    fs::write(
+        base_dir.join("prefetch.json"),
+        r#"{"runtime_id":"new","name":"dev","version":"1.0.0","url":"http://updates","fetched_at":0}"#,
+    )
+    .unwrap();
+    // February 30th never comes
+    let config_path = temp_dir.path().join("avocadoctl.conf");
+    fs::write(
+        &config_path,
+        "[avocado.ext]\ndir = \"/tmp/ext\"\n\n[avocado.maintenance]\nwindows = [\"* * 30 feb *\"]\n",
+    )
+    .unwrap();
+    let config = config_path.to_str().unwrap();
+    let test_env = [("AVOCADO_BASE_DIR", base_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["-c", config, "ext", "upgrade", "--offline", "app"],
+        &test_env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        stdout.contains("upgrade queued until the next maintenance window"),
+        "stdout: {stdout}"
+    );
+    let queued: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(base_dir.join("maintenance-queue.json")).unwrap())
+            .unwrap();
+    assert_eq!(queued["names"], serde_json::json!(["app"]));
+    assert_eq!(queued["offline"], true);
+    assert_eq!(
+        fs::read_link(base_dir.join("active")).unwrap(),
+        std::path::PathBuf::from("runtimes/old")
+    );
+
+    // A dry run is not held back
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["-c", config, "ext", "upgrade", "--offline", "--dry-run"],
+        &test_env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("app 1.0.0 -> 1.1.0"), "stdout: {stdout}");
+
+    // The runtime is activated before the (imageless) refresh fails
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["-c", config, "ext", "upgrade", "--offline", "--force"],
+        &test_env,
+    );
+    assert!(
+        !String::from_utf8_lossy(&output.stdout).contains("queued"),
+        "{output:?}"
+    );
+    assert_eq!(
+        fs::read_link(base_dir.join("active")).unwrap(),
+        std::path::PathBuf::from("runtimes/new")
+    );
+}
+
+/// Test that `--fail-at` stops a merge at the requested step
+#[cfg(feature = "fault-injection")]
+#[test]
+fn test_fail_at_merge_steps() {
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge", "--verbose", "--fail-at", "after-merge"],
+        &[],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "stdout: {stdout}");
+    assert!(stderr.contains("injected failure"), "stderr: {stderr}");
+    assert!(stdout.contains("systemd-sysext merge"), "stdout: {stdout}");
+    assert!(
+        !stdout.contains("systemd-confext merge"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge", "--verbose"],
+        &[("AVOCADO_FAIL_AT", "before-hooks")],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "stdout: {stdout}");
+    assert!(
+        stderr.contains("--fail-at before-hooks"),
+        "stderr: {stderr}"
+    );
+    assert!(stdout.contains("systemd-confext merge"), "stdout: {stdout}");
+}
+
+/// Test that a failure halfway through `ext apply` restores the previous links
+#[cfg(feature = "fault-injection")]
+#[test]
+fn test_fail_at_symlink_swap_rolls_back_apply() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    for name in ["cam-1.0", "cam-2.0", "dbg-1.0"] {
+        let release_dir = extensions_dir.join(format!("{name}/usr/lib/extension-release.d"));
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\nVERSION_ID=1.0",
+        )
+        .unwrap();
+    }
+    let releases_dir = temp_dir.path().join("avocado/os-releases/1.0");
+    fs::create_dir_all(&releases_dir).unwrap();
+    for name in ["cam-1.0", "dbg-1.0"] {
+        std::os::unix::fs::symlink(extensions_dir.join(name), releases_dir.join(name)).unwrap();
+    }
+    let manifest = temp_dir.path().join("tx.toml");
+    fs::write(
+        &manifest,
+        "os_release = \"1.0\"\ndisable = [\"dbg-1.0\"]\nupdate = [\"cam-2.0\"]\n",
+    )
+    .unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "ext",
+            "apply",
+            "--manifest",
+            manifest.to_str().unwrap(),
+            "--fail-at",
+            "symlink-swap",
+        ],
+        &env,
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("Transaction rolled back"),
+        "stderr: {stderr}"
+    );
+    let mut links: Vec<String> = fs::read_dir(&releases_dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    links.sort();
+    assert_eq!(links, vec!["cam-1.0", "dbg-1.0"]);
+}
+
+/// Test that a link journal left behind by an interrupted enable is
+/// completed, or rolled back when a link target is gone, by the next command
+#[test]
+fn test_interrupted_enable_is_recovered_at_startup() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(&extensions_dir).unwrap();
+    for name in ["app-1.0.raw", "app-2.0.raw"] {
+        fs::write(extensions_dir.join(name), b"mock raw data").unwrap();
+    }
+    let releases_dir = temp_dir.path().join("avocado/os-releases/1.0");
+    fs::create_dir_all(&releases_dir).unwrap();
+    let journal = temp_dir.path().join("avocado/link-journal.json");
+    let write_journal = |source: &str| {
+        fs::write(
+            &journal,
+            format!(
+                r#"{{"operation": "enable", "dir": "{}", "steps": [{{"action": "link", "file_name": "app-2.0.raw", "source": "{source}"}}], "previous": {{"app-2.0.raw": null}}}}"#,
+                releases_dir.display()
+            ),
+        )
+        .unwrap();
+    };
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    write_journal(extensions_dir.join("app-2.0.raw").to_str().unwrap());
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "list"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Completed an interrupted 'enable'"),
+        "stdout: {stdout}"
+    );
+    assert_eq!(
+        fs::read_link(releases_dir.join("app-2.0.raw")).unwrap(),
+        extensions_dir.join("app-2.0.raw")
+    );
+    assert!(!journal.exists());
+
+    fs::remove_file(releases_dir.join("app-2.0.raw")).unwrap();
+    write_journal(extensions_dir.join("app-3.0.raw").to_str().unwrap());
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "list"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Rolled back an interrupted 'enable'"),
+        "stdout: {stdout}"
+    );
+    assert!(releases_dir.join("app-2.0.raw").symlink_metadata().is_err());
+    assert!(!journal.exists());
+}
+
+/// Test that an enable cut off after its first link change is completed by
+/// the next command
+#[cfg(feature = "fault-injection")]
+#[test]
+fn test_fail_at_link_journal_completes_enable_on_next_command() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(&extensions_dir).unwrap();
+    for name in ["app-1.0.raw", "tools-1.0.raw"] {
+        fs::write(extensions_dir.join(name), b"mock raw data").unwrap();
+    }
+    let releases_dir = temp_dir.path().join("avocado/os-releases/1.0");
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "enable",
+            "--os-release",
+            "1.0",
+            "app-1.0",
+            "tools-1.0",
+            "--fail-at",
+            "link-journal",
+        ],
+        &env,
+    );
+    assert!(!output.status.success());
+    assert!(releases_dir.join("app-1.0.raw").is_symlink());
+    assert!(!releases_dir.join("tools-1.0.raw").is_symlink());
+    assert!(temp_dir.path().join("avocado/link-journal.json").exists());
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "list"], &env);
+    assert!(output.status.success());
+    assert!(releases_dir.join("tools-1.0.raw").is_symlink());
+    assert!(!temp_dir.path().join("avocado/link-journal.json").exists());
+}
+
+/// Test that with phase units enabled the commands of each merge phase run
+/// in transient units named after the phase
+#[test]
+fn test_ext_merge_phase_units() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let units_log = temp_dir.path().join("units.log");
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge", "--verbose"],
+        &[
+            ("AVOCADO_PHASE_UNITS", "1"),
+            ("MOCK_SYSTEMD_RUN_UNITS", units_log.to_str().unwrap()),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(stdout.contains("Merge phases: "), "stdout: {stdout}");
+
+    let units = fs::read_to_string(&units_log).unwrap_or_default();
+    assert!(
+        units
+            .lines()
+            .any(|u| u.starts_with("avocado-merge-merging-")),
+        "units: {units}"
+    );
+    assert!(
+        units.lines().any(|u| u.starts_with("avocado-merge-hooks-")),
+        "units: {units}"
+    );
+}
+
+/// Test that provision --stdin applies configuration, images and enabled
+/// extensions, and that running the same manifest again changes nothing
+#[test]
+fn test_provision_stdin_is_idempotent() {
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("cam-1.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.cam-1.0"),
+        "ID=_any\nVERSION_ID=1.0",
+    )
+    .unwrap();
+    let image = temp_dir.path().join("factory/net-1.0.raw");
+    fs::create_dir_all(image.parent().unwrap()).unwrap();
+    fs::write(&image, b"image").unwrap();
+    let config_path = temp_dir.path().join("avocadoctl.conf");
+
+    let manifest = format!(
+        "os_release = \"1.0\"\ninstall = [\"{}\"]\nenable = [\"cam-1.0\"]\n\n\
+         [repository]\nurl = \"https://updates.example.com/repo\"\n",
+        image.display()
+    );
+    let fixtures = std::env::current_dir().unwrap().join("tests/fixtures");
+    let path = format!(
+        "{}:{}",
+        fixtures.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let provision = || {
+        let mut child = Command::new(get_binary_path())
+            .args([
+                "--config",
+                config_path.to_str().unwrap(),
+                "provision",
+                "--stdin",
+                "-o",
+                "json",
+            ])
+            .env("AVOCADO_TEST_MODE", "1")
+            .env("PATH", &path)
+            .env("TMPDIR", temp_dir.path())
+            .env("AVOCADO_EXTENSIONS_PATH", &extensions_dir)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .expect("Failed to execute avocadoctl");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(manifest.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "stdout: {}\nstderr: {}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+
+    let report = provision();
+    let states: Vec<(&str, &str)> = report["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["item"].as_str().unwrap(), s["state"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        states,
+        [
+            ("config", "written"),
+            ("install", "installed"),
+            ("enable", "enabled")
+        ]
+    );
+    assert_eq!(report["changed"], 3);
+    assert!(fs::read_to_string(&config_path)
+        .unwrap()
+        .contains("https://updates.example.com/repo"));
+    assert_eq!(
+        fs::read(extensions_dir.join("net-1.0.raw")).unwrap(),
+        b"image"
+    );
+    assert!(temp_dir
+        .path()
+        .join("avocado/os-releases/1.0/cam-1.0")
+        .is_symlink());
+
+    let again = provision();
+    assert_eq!(again["changed"], 0, "report: {again}");
+    assert_eq!(again["ok"], true);
+}
+
+/// Test that ext env prints shell exports for the merged extensions
+#[test]
+fn test_ext_env_exports_merged_extensions() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("test-ext-1/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.test-ext-1"), "ID=_any").unwrap();
+    let env = [("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "env"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("export AVOCADO_MERGED_EXTS='config-ext-1 test-ext-1 test-ext-2'\n"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains(&format!(
+            "export AVOCADO_EXT_TEST_EXT_1_PATH='{}'\n",
+            extensions_dir.join("test-ext-1").display()
+        )),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "env", "test-ext-2"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert_eq!(stdout, "export AVOCADO_MERGED_EXTS='test-ext-2'\n");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "env", "missing"], &env);
+    assert!(!output.status.success());
+}
+
+/// Container mode adds the host's extensions and merges confexts only
+#[test]
+fn test_ext_merge_container_mode() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(&extensions_dir).unwrap();
+    let host_dir = temp_dir.path().join("host");
+    let release_dir = host_dir.join("extensions/host-config/etc/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.host-config"), "ID=_any").unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_HOST_DIR", host_dir.to_str().unwrap()),
+    ];
+
+    // Without /run/host/container-manager this is not a container
+    let (output, _) = run_avocadoctl_with_isolated_env(&["--container", "ext", "merge"], &env);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not running in a container"));
+
+    fs::write(host_dir.join("container-manager"), "systemd-nspawn\n").unwrap();
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["--container", "ext", "merge", "--verbose"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "stdout: {stdout}\nstderr: {stderr}"
+    );
+    let all = format!("{stdout}{stderr}");
+    assert!(all.contains("Found host extension: host-config"), "{all}");
+    assert!(all.contains("skipping systemd-sysext merge"), "{all}");
+    assert!(!all.contains("systemd-sysext merge:"), "{all}");
+}
+
+/// Write a GPT image with 512-byte sectors holding partitions of the given
+/// type UUIDs, each 2048 sectors long.
+fn write_gpt_image(path: &std::path::Path, type_guids: &[&str]) {
+    let guid_bytes = |guid: &str| {
+        let hex: String = guid.chars().filter(|c| *c != '-').collect();
+        let mut raw: Vec<u8> = (0..16)
+            .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap())
+            .collect();
+        raw[..4].reverse();
+        raw[4..6].reverse();
+        raw[6..8].reverse();
+        raw
+    };
+    let mut image = vec![0u8; 1024 + 128 * type_guids.len()];
+    image[512..520].copy_from_slice(b"EFI PART");
+    image[584..592].copy_from_slice(&2u64.to_le_bytes());
+    image[592..596].copy_from_slice(&(type_guids.len() as u32).to_le_bytes());
+    image[596..600].copy_from_slice(&128u32.to_le_bytes());
+    for (i, guid) in type_guids.iter().enumerate() {
+        let entry = 1024 + i * 128;
+        let first = 34 + i as u64 * 2048;
+        image[entry..entry + 16].copy_from_slice(&guid_bytes(guid));
+        image[entry + 16..entry + 32].copy_from_slice(&guid_bytes(&format!(
+            "00000000-0000-4000-8000-{:012x}",
+            i + 1
+        )));
+        image[entry + 32..entry + 40].copy_from_slice(&first.to_le_bytes());
+        image[entry + 40..entry + 48].copy_from_slice(&(first + 2047).to_le_bytes());
+    }
+    fs::write(path, image).unwrap();
+    // The partitions lie within the image, as a real DDI's do
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_len((34 + type_guids.len() as u64 * 2048) * 512)
+        .unwrap();
+}
+
+/// A combined sysext + confext DDI is reported per partition in status
+#[test]
+fn test_ext_status_reports_combined_image_partitions() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(&extensions_dir).unwrap();
+    write_gpt_image(
+        &extensions_dir.join("test-ext-combined-1.0.raw"),
+        &[
+            // usr-x86-64, usr-x86-64-verity, root-x86-64
+            "8484680c-9521-48c6-9c11-b0720656f69e",
+            "77ff5f63-e7b6-4633-acf4-1565b864c0e6",
+            "4f68bce3-e8cd-4db1-96e7-fbcaf984b709",
+        ],
+    );
+    let env = [("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap())];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("usr partition: sysext (x86-64, 1.0M, verity)"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains("root partition: confext (x86-64, 1.0M)"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status", "-o", "json"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("\"designator\": \"usr\""),
+        "stdout: {stdout}"
+    );
+}
+
+/// The loop device a mounted .raw extension is attached to is reported in
+/// status JSON
+#[test]
+fn test_ext_status_reports_loop_device() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(&extensions_dir).unwrap();
+    let image = extensions_dir.join("app-1.0.raw");
+    fs::write(&image, b"mock raw data").unwrap();
+
+    // udev's loop-ref link and the loop device's sysfs entries
+    let by_loop_ref = temp_dir.path().join("dev/disk/by-loop-ref");
+    fs::create_dir_all(&by_loop_ref).unwrap();
+    std::os::unix::fs::symlink("../../loop5", by_loop_ref.join("app-1.0")).unwrap();
+    let loop5 = temp_dir.path().join("sys/block/loop5");
+    fs::create_dir_all(loop5.join("loop")).unwrap();
+    fs::write(
+        loop5.join("loop/backing_file"),
+        format!("{}\n", image.display()),
+    )
+    .unwrap();
+    fs::write(loop5.join("ro"), "1\n").unwrap();
+
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status", "-o", "json"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    let status: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let app = status["extensions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["name"] == "app-1.0")
+        .unwrap_or_else(|| panic!("app-1.0 missing: {stdout}"));
+    assert_eq!(app["loop_device"]["device"], "/dev/loop5");
+    assert_eq!(app["loop_device"]["backingFile"], image.to_str().unwrap());
+    assert_eq!(app["loop_device"]["readOnly"], true);
+    assert_eq!(app["loop_device"]["verity"], false);
+}
+
+/// Test that `ext status` reports scopes and skips extensions scoped to
+/// another environment
+#[test]
+fn test_ext_status_reports_scope() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    for (name, scope) in [("early-1.0", "initrd"), ("app-1.0", "system")] {
+        let release_dir = extensions_dir.join(format!("{name}/usr/lib/extension-release.d"));
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            format!("ID=_any\nSYSEXT_SCOPE={scope}\n"),
+        )
+        .unwrap();
+    }
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status", "-o", "json"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    let status: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(status["environment"], "system");
+    let entry = |name: &str| {
+        status["extensions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["name"] == name)
+            .unwrap_or_else(|| panic!("{name} missing: {stdout}"))
+            .clone()
+    };
+    let early = entry("early-1.0");
+    assert_eq!(early["status"], "SKIPPED(scope)");
+    assert_eq!(early["scopes"], serde_json::json!(["initrd"]));
+    assert_eq!(early["applicable"], false);
+    let app = entry("app-1.0");
+    assert_eq!(app["scopes"], serde_json::json!(["system"]));
+    assert_eq!(app["applicable"], true);
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("SKIPPED(scope)"), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Out of scope in system, not merged: early-1.0"),
+        "stdout: {stdout}"
+    );
+}
+
+/// Test that `ext status` reads newer systemd status layouts and reports
+/// when each extension was merged
+#[test]
+fn test_ext_status_reports_merged_since() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.app-1.0"), "ID=_any\n").unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        (
+            "MOCK_SYSEXT_STATUS_JSON",
+            r#"{"hierarchies":[{"hierarchy":"/usr","extensions":{"01-app-1.0":{"since":1736868600000000}}}]}"#,
+        ),
+        (
+            "MOCK_CONFEXT_STATUS_JSON",
+            r#"[{"hierarchy":"/etc","extensions":[{"name":"01-app-1.0","since":"Tue 2025-01-14 15:30:00 UTC"}]}]"#,
+        ),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status", "-o", "json"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    let status: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let app = status["extensions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["name"] == "app-1.0")
+        .unwrap_or_else(|| panic!("app-1.0 missing: {stdout}"));
+    assert_eq!(app["status"], "MERGED");
+    assert_eq!(app["merged_since"], 1_736_868_600);
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("System extensions: 1 (since 2025-01-14 15:30 UTC)"),
+        "stdout: {stdout}"
+    );
+}
+
+/// Test that `--keep-going` merges past an image that fails to mount and a
+/// release file without ID=, and exits 4 with a summary
+#[test]
+fn test_ext_merge_keep_going_skips_broken_extensions() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    for (name, release) in [("app-1.0", "ID=_any\n"), ("noid-1.0", "VERSION_ID=1.0\n")] {
+        let release_dir = extensions_dir.join(format!("{name}/usr/lib/extension-release.d"));
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            release,
+        )
+        .unwrap();
+    }
+    fs::write(extensions_dir.join("broken-1.0.raw"), b"mock raw data").unwrap();
+    let tmpdir = temp_dir.path().to_str().unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", tmpdir),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge", "--keep-going"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(4), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Merged with failures: 2 extension(s) skipped"),
+        "stdout: {stdout}"
+    );
+    assert!(stdout.contains("broken-1.0: "), "stdout: {stdout}");
+    assert!(
+        stdout.contains("noid-1.0: invalid release file"),
+        "stdout: {stdout}"
+    );
+    let sysext_dir = temp_dir.path().join("test_extensions");
+    assert!(sysext_dir.join("app-1.0").exists());
+    assert!(!sysext_dir.join("noid-1.0").exists());
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["-o", "json", "refresh", "--keep-going"], &env);
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("refresh JSON: {e}: {output:?}"));
+    assert_eq!(summary["status"], "partial");
+    let failed: Vec<&str> = summary["failed"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["extension"].as_str().unwrap())
+        .collect();
+    assert_eq!(failed, ["broken-1.0", "noid-1.0"]);
+
+    // keep_going in the configuration does the same for merges and lets
+    // status scan past the broken image
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\nkeep_going = true\n",
+            extensions_dir.display()
+        ),
+    )
+    .unwrap();
+    let config = config_path.to_str().unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(&["-c", config, "ext", "merge"], &env);
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["-c", config, "ext", "status", "-o", "json"], &env);
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("status JSON: {e}: {output:?}"));
+    assert_eq!(status["merge_failures"].as_array().unwrap().len(), 2);
+}
+
+/// Test that `ext status` shows the updates the repository index offers
+/// and that `--updates-only` filters on them
+#[test]
+fn test_ext_status_shows_repository_updates() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    for name in ["app-1.0.0", "base-2.0.0", "tools-0.3.1"] {
+        fs::create_dir_all(
+            extensions_dir
+                .join(name)
+                .join("usr/lib/extension-release.d"),
+        )
+        .unwrap();
+    }
+    let base_dir = temp_dir.path().join("avocado");
+    fs::create_dir_all(&base_dir).unwrap();
+    fs::write(
+        base_dir.join("repo-index.json"),
+        r#"{"url":"http://updates","fetched_at":0,"extensions":{"app":"1.1.0","base":"1.9.0"}}"#,
+    )
+    .unwrap();
+    let config_path = temp_dir.path().join("avocadoctl.conf");
+    fs::write(
+        &config_path,
+        "[avocado.ext]\ndir = \"/tmp/ext\"\n\n[avocado.update]\nurl = \"http://updates\"\n",
+    )
+    .unwrap();
+    let config = config_path.to_str().unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_BASE_DIR", base_dir.to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["-c", config, "ext", "status"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(stdout.contains("Update"), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Updates available: 1 (repository index from http://updates)"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            config,
+            "ext",
+            "status",
+            "--updates-only",
+            "-o",
+            "json",
+        ],
+        &env,
+    );
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .unwrap_or_else(|e| panic!("status JSON: {e}: {output:?}"));
+    let extensions = status["extensions"].as_array().unwrap();
+    assert_eq!(extensions.len(), 1, "status: {status}");
+    assert_eq!(extensions[0]["name"], "app-1.0.0");
+    assert_eq!(extensions[0]["latest_version"], "1.1.0");
+    assert_eq!(extensions[0]["update_available"], true);
+    assert_eq!(status["repo_index"]["url"], "http://updates");
+
+    // Without a configured repository there is nothing to compare against
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status", "--updates-only"], &env);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "stderr: {stderr}");
+    assert!(stderr.contains("No repository index"), "stderr: {stderr}");
+}
+
+#[test]
+fn test_ext_status_read_only_does_not_mount_images() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    fs::create_dir_all(&extensions_dir).unwrap();
+    fs::write(
+        extensions_dir.join("test-ext-plain-1.0.raw"),
+        vec![0u8; 8192],
+    )
+    .unwrap();
+    write_gpt_image(
+        &extensions_dir.join("test-ext-combined-1.0.raw"),
+        &[
+            "8484680c-9521-48c6-9c11-b0720656f69e",
+            "4f68bce3-e8cd-4db1-96e7-fbcaf984b709",
+        ],
+    );
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_READ_ONLY", "1"),
+    ];
+
+    let (output, tmp) = run_avocadoctl_with_isolated_env(&["ext", "status"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "stderr: {stderr}");
+    assert!(
+        stdout.contains("root partition: confext (x86-64, 1.0M)"),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stderr.contains(
+            "Warning: test-ext-plain-1.0 is not mounted; its type is unknown without root"
+        ),
+        "stderr: {stderr}"
+    );
+    assert!(!tmp
+        .path()
+        .join("avocado/extensions/test-ext-plain-1.0")
+        .exists());
+    assert!(!tmp
+        .path()
+        .join("avocado/extensions/test-ext-combined-1.0")
+        .exists());
+}
+
+#[test]
+fn test_trust_rotate_reports_images_that_no_longer_validate() {
+    use sha2::{Digest, Sha256};
+
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{b:02x}")).collect() };
+    let key_id = |pk: &ed25519_compact::PublicKey| {
+        let canonical = format!(
+            r#"{{"keytype":"ed25519","keyval":{{"public":"{}"}},"scheme":"ed25519"}}"#,
+            hex(pk.as_ref())
+        );
+        hex(&Sha256::digest(canonical.as_bytes()))
+    };
+    let old = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([1; 32]));
+    let new = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new([2; 32]));
+
+    let work = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = work.path().join("extensions");
+    let base_dir = work.path().join("base");
+    fs::create_dir_all(&extensions_dir).unwrap();
+    let image = extensions_dir.join("test-ext-signed-1.0.raw");
+    fs::write(&image, b"signed image").unwrap();
+    let sha256 = hex(&Sha256::digest(b"signed image"));
+    let sig = old.sk.sign(sha256.as_bytes(), None);
+    fs::write(
+        extensions_dir.join("test-ext-signed-1.0.raw.sig"),
+        format!(
+            r#"{{"sha256":"{sha256}","signatures":[{{"keyid":"{}","sig":"{}"}}]}}"#,
+            key_id(&old.pk),
+            hex(sig.as_ref())
+        ),
+    )
+    .unwrap();
+    let old_key = work.path().join("old.pub");
+    let new_key = work.path().join("new.pub");
+    fs::write(&old_key, hex(old.pk.as_ref())).unwrap();
+    fs::write(&new_key, hex(new.pk.as_ref())).unwrap();
+
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("AVOCADO_BASE_DIR", base_dir.to_str().unwrap()),
+    ];
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["trust", "rotate", old_key.to_str().unwrap()], &env);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Retiring the old key right away invalidates the image it signed
+    let old_id = key_id(&old.pk);
+    let rotate = [
+        "trust",
+        "rotate",
+        new_key.to_str().unwrap(),
+        "--retire",
+        &old_id[..12],
+        "--dry-run",
+    ];
+    let (output, _) = run_avocadoctl_with_isolated_env(&rotate, &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(stdout.contains("NO LONGER VALID"), "stdout: {stdout}");
+    assert!(
+        stdout.contains("1 installed image(s) would no longer validate"),
+        "stdout: {stdout}"
+    );
+
+    // With an overlap both keys stay valid until the old one expires
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&[&rotate[..5], &["--overlap", "30"]].concat(), &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("1 installed image(s) will no longer validate"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["trust", "list"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches(" valid ").count(), 2, "stdout: {stdout}");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["trust", "list", "-o", "json"], &env);
+    let body: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let keys = body["keys"].as_array().unwrap();
+    assert_eq!(keys.len(), 2, "keys: {keys:?}");
+    assert!(keys[0]["not_after"].is_u64(), "keys: {keys:?}");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "trust",
+            "rotate",
+            new_key.to_str().unwrap(),
+            "--retire",
+            "ffff",
+        ],
+        &env,
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("E0027"));
+}
+
+/// Test that `ext prune-os-releases` refuses while an OS update awaits verification
+#[test]
+fn test_prune_os_releases_refuses_with_pending_os_update() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("pending-update.json"),
+        r#"{"os_build_id":"build-2","verify":null,"rollback":null,"previous_slot":"a"}"#,
+    )
+    .unwrap();
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "prune-os-releases", "--dry-run"],
+        &[("AVOCADO_BASE_DIR", temp_dir.path().to_str().unwrap())],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "stderr: {stderr}");
+    assert!(
+        stderr.contains("OS update to build 'build-2' is pending verification"),
+        "stderr: {stderr}"
+    );
+}
+
+/// Test that `ext compat` checks each extension against the running,
+/// installed and pending OS versions and fails on ones the update breaks
+#[test]
+fn test_ext_compat_flags_extensions_broken_by_pending_update() {
+    let temp_dir = TempDir::new().unwrap();
+    let avocado = temp_dir.path().join("avocado");
+    fs::create_dir_all(avocado.join("os-releases/0.9")).unwrap();
+    fs::write(avocado.join("os-release"), "ID=avocado\nVERSION_ID=1.0\n").unwrap();
+    let extensions_dir = temp_dir.path().join("extensions");
+    for (name, release) in [
+        ("pinned", "ID=avocado\nVERSION_ID=1.0\n"),
+        ("portable", "ID=_any\n"),
+    ] {
+        let dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("extension-release.{name}")), release).unwrap();
+    }
+    fs::write(
+        temp_dir.path().join("pending-update.json"),
+        r#"{"os_build_id":"build-2","verify":{"type":"os-release","field":"VERSION_ID","expected":"2.0"},"rollback":null,"previous_slot":"a"}"#,
+    )
+    .unwrap();
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_BASE_DIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "compat"], &env);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "stderr: {stderr}");
+    assert!(
+        stderr.contains("1 extension(s) will not merge after the update to 2.0: pinned"),
+        "stderr: {stderr}"
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("BREAKS AFTER UPDATE"));
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "compat", "-o", "json"], &env);
+    assert_eq!(output.status.code(), Some(1));
+    let matrix: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let versions: Vec<(&str, &str)> = matrix["versions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| {
+            (
+                v["version_id"].as_str().unwrap(),
+                v["role"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        versions,
+        [("0.9", "installed"), ("1.0", "running"), ("2.0", "pending")]
+    );
+    let pinned = &matrix["extensions"][0];
+    assert_eq!(pinned["name"], "pinned");
+    assert_eq!(pinned["breaks_after_update"], true);
+    assert_eq!(pinned["versions"][1]["status"], "compatible");
+    assert_eq!(pinned["versions"][2]["status"], "incompatible");
+    assert_eq!(matrix["extensions"][1]["breaks_after_update"], false);
+
+    // Checked against a chosen target instead of the pending update
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "compat", "--target", "1.0"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(stdout.contains("0.9  1.0 (running)"), "stdout: {stdout}");
+    assert!(!stdout.contains("BREAKS"), "stdout: {stdout}");
+}
+
+/// Test that a merge in safe mode links only AVOCADO_ESSENTIAL extensions
+/// and that status reports the ones it skipped
+#[test]
+fn test_ext_merge_safe_mode_skips_non_essential_extensions() {
+    let temp_dir = TempDir::new().unwrap();
+    let extensions_dir = temp_dir.path().join("extensions");
+    for (name, release) in [
+        ("base", "ID=_any\nAVOCADO_ESSENTIAL=yes\n"),
+        ("app", "ID=_any\n"),
+    ] {
+        let dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("extension-release.{name}")), release).unwrap();
+    }
+    let avocado = temp_dir.path().join("avocado");
+    fs::create_dir_all(&avocado).unwrap();
+    fs::write(
+        avocado.join("cmdline"),
+        "root=/dev/sda2 avocado.safe_mode\n",
+    )
+    .unwrap();
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Safe mode (avocado.safe_mode on the kernel command line): skipping 1 non-essential extension(s): app"),
+        "stdout: {stdout}"
+    );
+    let links = temp_dir.path().join("test_extensions");
+    assert!(links.join("base").exists());
+    assert!(!links.join("app").exists());
+    assert_eq!(
+        fs::read_to_string(avocado.join("safe-mode-skipped")).unwrap(),
+        "app\n"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Skipped in safe mode: app"),
+        "stdout: {stdout}"
+    );
+
+    // Leaving safe mode merges everything again and clears the record
+    fs::write(avocado.join("cmdline"), "root=/dev/sda2\n").unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    assert!(links.join("app").exists());
+    assert!(!avocado.join("safe-mode-skipped").exists());
+}
+
+/// Test that AVOCADO_ON_MERGE hooks run by AVOCADO_ON_MERGE_PRIORITY, then
+/// dependencies first, rather than in merge order
+#[test]
+fn test_ext_merge_orders_hooks_by_priority_and_dependencies() {
+    let temp_dir = TempDir::new().unwrap();
+    let extensions_dir = temp_dir.path().join("extensions");
+    for (name, release) in [
+        (
+            "app",
+            "ID=_any\nAVOCADO_REQUIRES=db\nAVOCADO_ON_MERGE=\"systemctl restart app.service\"\n",
+        ),
+        (
+            "db",
+            "ID=_any\nAVOCADO_ON_MERGE=\"systemctl restart db.service\"\n",
+        ),
+        (
+            "drivers",
+            "ID=_any\nAVOCADO_ON_MERGE_PRIORITY=-10\nAVOCADO_ON_MERGE=\"systemctl start drivers-modules.service\"\n",
+        ),
+    ] {
+        let dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("extension-release.{name}")), release).unwrap();
+    }
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "merge", "--verbose"],
+        &[
+            ("TMPDIR", temp_dir.path().to_str().unwrap()),
+            ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    let commands: Vec<&str> = stdout
+        .lines()
+        .filter_map(|line| line.split_once("Running command: ").map(|(_, c)| c))
+        .collect();
+    assert_eq!(
+        commands,
+        [
+            "systemctl start drivers-modules.service",
+            "systemctl restart db.service",
+            "systemctl restart app.service",
+        ],
+        "stdout: {stdout}"
+    );
+}
+
+/// Test that an AVOCADO_MIGRATE script runs once per data version, and
+/// again on the next merge after it fails
+#[test]
+fn test_ext_merge_runs_migration_once_per_data_version() {
+    let temp_dir = TempDir::new().unwrap();
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    let write_release = |data_version: &str| {
+        fs::write(
+            release_dir.join("extension-release.app"),
+            format!("ID=_any\nAVOCADO_MIGRATE=\"app_migrate --db\"\nAVOCADO_DATA_VERSION={data_version}\n"),
+        )
+        .unwrap();
+    };
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+    ];
+    let runs = || {
+        fs::read_to_string(temp_dir.path().join("migrate.log"))
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let migrated = || {
+        fs::read_to_string(temp_dir.path().join("avocado/migrations/app"))
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+
+    write_release("1");
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    assert_eq!(runs(), ["->1 --db"]);
+    assert_eq!(migrated(), "1");
+
+    // Same data version: not re-run
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    assert_eq!(runs().len(), 1);
+
+    // A failed migration leaves the recorded version alone
+    write_release("2");
+    fs::write(temp_dir.path().join("migrate-fail"), "").unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("failed with exit code 1: error: schema locked"),
+        "stderr: {stderr}"
+    );
+    assert_eq!(migrated(), "1");
+
+    fs::remove_file(temp_dir.path().join("migrate-fail")).unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    assert_eq!(runs(), ["->1 --db", "1->2 --db", "1->2 --db"]);
+    assert_eq!(migrated(), "2");
+}
+
+/// Test ext status --since boot compares with the last merge of the previous boot
+#[test]
+fn test_ext_status_since_boot_shows_merged_and_unmerged() {
+    let temp_dir = TempDir::new().unwrap();
+    let extensions_dir = temp_dir.path().join("extensions");
+    let add_extension = |name: &str| {
+        let release_dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\n",
+        )
+        .unwrap();
+    };
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+    ];
+    let boot_id_path = temp_dir.path().join("avocado/boot_id");
+    fs::create_dir_all(boot_id_path.parent().unwrap()).unwrap();
+
+    add_extension("app");
+    add_extension("tools");
+    fs::write(&boot_id_path, "boot-1\n").unwrap();
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+
+    // Reboot, then merge a different set
+    fs::write(&boot_id_path, "boot-2\n").unwrap();
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "status", "--since", "boot", "-o", "json"], &env);
+    assert!(output.status.success());
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["merged"], serde_json::json!([]));
+    assert_eq!(status["unchanged"], serde_json::json!(["app", "tools"]));
+
+    fs::remove_dir_all(extensions_dir.join("tools")).unwrap();
+    add_extension("debug");
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "status", "--since", "boot", "-o", "json"], &env);
+    assert!(output.status.success());
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["since"], "boot");
+    assert_eq!(status["merged"], serde_json::json!(["debug"]));
+    assert_eq!(status["unmerged"], serde_json::json!(["tools"]));
+    assert_eq!(status["unchanged"], serde_json::json!(["app"]));
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "status", "--since", "boot"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("+ debug (merged)"), "stdout: {stdout}");
+    assert!(stdout.contains("- tools (unmerged)"), "stdout: {stdout}");
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "status", "--since", "last week"], &env);
+    assert!(!output.status.success());
+}
+
+/// Test that on_change_exec runs with a JSON summary on stdin only when a refresh
+/// changes the merged extension set
+#[test]
+fn test_ext_refresh_runs_on_change_exec() {
+    let temp_dir = TempDir::new().unwrap();
+    let extensions_dir = temp_dir.path().join("extensions");
+    let add_extension = |name: &str| {
+        let release_dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\n",
+        )
+        .unwrap();
+    };
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\non_change_exec = \"on_change --notify\"\n",
+            extensions_dir.display()
+        ),
+    )
+    .unwrap();
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+    ];
+    let refresh = || {
+        let (output, _) = run_avocadoctl_with_isolated_env(
+            &["-c", config_path.to_str().unwrap(), "ext", "refresh"],
+            &env,
+        );
+        assert!(output.status.success(), "{output:?}");
+    };
+    let runs = || {
+        fs::read_to_string(temp_dir.path().join("on-change.log"))
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    add_extension("app");
+    add_extension("tools");
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+
+    // Nothing changed
+    refresh();
+    assert!(runs().is_empty(), "{:?}", runs());
+
+    fs::remove_dir_all(extensions_dir.join("tools")).unwrap();
+    add_extension("debug");
+    refresh();
+    let runs = runs();
+    assert_eq!(runs.len(), 1, "{runs:?}");
+    let (args, summary) = runs[0].split_once(' ').unwrap();
+    assert_eq!(args, "--notify");
+    let summary: serde_json::Value = serde_json::from_str(summary).unwrap();
+    assert_eq!(summary["added"], serde_json::json!(["debug"]));
+    assert_eq!(summary["removed"], serde_json::json!(["tools"]));
+    assert_eq!(summary["updated"], serde_json::json!([]));
+    assert_eq!(summary["extensions"], serde_json::json!(["app", "debug"]));
+}
+
+/// Test that protect_source locks the images or mounts the extensions
+/// directory read-only for the duration of the merge
+#[test]
+fn test_ext_merge_protects_source_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.app"), "ID=_any\n").unwrap();
+    fs::write(extensions_dir.join("README"), "not an image\n").unwrap();
+    let env = [
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+    ];
+    let merge = |mode: &str| {
+        let config_path = temp_dir.path().join(format!("{mode}.toml"));
+        fs::write(
+            &config_path,
+            format!(
+                "[avocado.ext]\ndir = \"{}\"\nprotect_source = \"{mode}\"\n",
+                extensions_dir.display()
+            ),
+        )
+        .unwrap();
+        let (output, _) = run_avocadoctl_with_isolated_env(
+            &[
+                "-c",
+                config_path.to_str().unwrap(),
+                "ext",
+                "merge",
+                "--verbose",
+            ],
+            &env,
+        );
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = merge("lock");
+    assert!(
+        stdout.contains(&format!(
+            "Locked 1 image(s) in {} for the merge",
+            extensions_dir.display()
+        )),
+        "stdout: {stdout}"
+    );
+
+    let stdout = merge("read-only");
+    assert!(
+        stdout.contains(&format!(
+            "Mounted {} read-only for the merge",
+            extensions_dir.display()
+        )),
+        "stdout: {stdout}"
+    );
+}
+
+/// Test that unmerge only runs depmod when an extension merged since the
+/// last depmod shipped kernel modules
+#[test]
+fn test_ext_unmerge_skips_depmod_without_module_extensions() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.app-1.0"), "ID=_any\n").unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let run = |args: &[&str]| {
+        let (output, _) = run_avocadoctl_with_isolated_env(args, &env);
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(output.status.success(), "{args:?} stdout: {stdout}");
+        stdout
+    };
+
+    run(&["ext", "merge", "--verbose"]);
+    let stdout = run(&["ext", "unmerge", "--verbose"]);
+    assert!(stdout.contains("skipping depmod"), "stdout: {stdout}");
+    assert!(!stdout.contains("Running depmod"), "stdout: {stdout}");
+
+    let drivers_dir = extensions_dir.join("drivers-1.0");
+    let release_dir = drivers_dir.join("usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.drivers-1.0"),
+        "ID=_any\n",
+    )
+    .unwrap();
+    let module_dir = drivers_dir.join("usr/lib/modules/6.6.0/extra");
+    fs::create_dir_all(&module_dir).unwrap();
+    fs::write(module_dir.join("drivers.ko"), b"mock module").unwrap();
+
+    run(&["ext", "merge", "--verbose"]);
+    let recorded = fs::read_to_string(temp_dir.path().join("avocado/module-extensions")).unwrap();
+    assert!(recorded.contains("drivers-1.0"), "recorded: {recorded}");
+    assert!(!recorded.contains("app-1.0"), "recorded: {recorded}");
+    let stdout = run(&["ext", "unmerge", "--verbose"]);
+    assert!(stdout.contains("Running depmod"), "stdout: {stdout}");
+
+    let stdout = run(&["ext", "unmerge", "--verbose"]);
+    assert!(stdout.contains("skipping depmod"), "stdout: {stdout}");
+}
+
+/// Test that merge records which extension each symlink belongs to, that
+/// `ext info` and `ext status` report it, and that unmerge clears it
+#[test]
+fn test_ext_merge_records_symlink_map() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.app-1.0"), "ID=_any\n").unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let run = |args: &[&str]| {
+        let (output, _) = run_avocadoctl_with_isolated_env(args, &env);
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(output.status.success(), "{args:?} stdout: {stdout}");
+        stdout
+    };
+
+    run(&["ext", "merge"]);
+    let map_path = temp_dir.path().join("avocado/symlink-map.json");
+    let map: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&map_path).unwrap()).unwrap();
+    let links = map.as_array().unwrap();
+    assert!(!links.is_empty(), "map: {map}");
+    for link in links {
+        assert_eq!(link["extension"], "app-1.0");
+        assert_eq!(link["origin"], "Dir");
+        let path = link["path"].as_str().unwrap();
+        assert!(
+            fs::symlink_metadata(path).unwrap().file_type().is_symlink(),
+            "{path} is not a symlink"
+        );
+        assert_eq!(
+            fs::read_link(path).unwrap().to_str().unwrap(),
+            link["source"].as_str().unwrap()
+        );
+    }
+
+    let stdout = run(&["ext", "info", "app-1.0"]);
+    assert!(stdout.contains("Symlink:   "), "stdout: {stdout}");
+    let stdout = run(&["ext", "status", "-o", "json"]);
+    let status: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let app = status["extensions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["name"] == "app-1.0")
+        .unwrap_or_else(|| panic!("app-1.0 missing: {stdout}"));
+    assert_eq!(app["symlinks"].as_array().unwrap().len(), links.len());
+
+    run(&["ext", "unmerge"]);
+    assert!(!map_path.exists(), "unmerge should clear the symlink map");
+}
+
+/// Test that `ext preview-etc` lists the /etc paths a confext adds and
+/// overrides, and refuses a system extension
+#[test]
+fn test_ext_preview_etc_lists_confext_changes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let conf = extensions_dir.join("conf-1.0");
+    fs::create_dir_all(conf.join("etc/extension-release.d")).unwrap();
+    fs::write(
+        conf.join("etc/extension-release.d/extension-release.conf-1.0"),
+        "ID=_any\n",
+    )
+    .unwrap();
+    fs::create_dir_all(conf.join("etc/app")).unwrap();
+    fs::write(conf.join("etc/app/app.conf"), "port=80\n").unwrap();
+    fs::write(conf.join("etc/hosts"), "127.0.0.1 app\n").unwrap();
+    let tool_release = extensions_dir.join("tool-1.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&tool_release).unwrap();
+    fs::write(tool_release.join("extension-release.tool-1.0"), "ID=_any\n").unwrap();
+    let etc = temp_dir.path().join("test_etc");
+    fs::create_dir_all(&etc).unwrap();
+    fs::write(etc.join("hosts"), "127.0.0.1 localhost\n").unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "preview-etc", "conf-1.0"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("conf-1.0: 1 added, 1 overridden, 0 unchanged in /etc"),
+        "stdout: {stdout}"
+    );
+    assert!(stdout.contains("+ /etc/app/app.conf"), "stdout: {stdout}");
+    assert!(stdout.contains("~ /etc/hosts"), "stdout: {stdout}");
+    assert!(!stdout.contains("extension-release"), "stdout: {stdout}");
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["ext", "preview-etc", "conf-1.0", "-o", "json"], &env);
+    let preview: serde_json::Value =
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+    assert_eq!(preview["paths"][1]["path"], "/etc/hosts");
+    assert_eq!(preview["paths"][1]["change"], "overridden");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "preview-etc", "tool-1.0"], &env);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("not a configuration extension"),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_ext_events_lists_merge_lifecycle() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(release_dir.join("extension-release.app-1.0"), "ID=_any\n").unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "events", "-o", "json"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("one JSON object per line"))
+        .collect();
+    let names: Vec<&str> = events.iter().filter_map(|e| e["event"].as_str()).collect();
+    assert_eq!(names.first(), Some(&"merge.started"), "events: {names:?}");
+    assert!(names.contains(&"scan.finished"), "events: {names:?}");
+    let finished = events
+        .iter()
+        .find(|e| e["event"] == "merge.finished")
+        .expect("merge.finished recorded");
+    assert_eq!(finished["status"], "ok");
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "events", "-n", "1"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 1, "stdout: {stdout}");
+    assert!(
+        stdout.contains("merge.finished status=ok"),
+        "stdout: {stdout}"
+    );
+}
+
+#[test]
+fn test_extension_aliases_resolve_and_show_in_status() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("vendor-camera-stack-1.0.0/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::write(
+        release_dir.join("extension-release.vendor-camera-stack-1.0.0"),
+        "ID=_any\n",
+    )
+    .unwrap();
+    let config_path = temp_dir.path().join("avocadoctl.conf");
+    fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\n\n[avocado.ext.aliases]\ncamera = \"vendor-camera-stack-1.0.0\"\n",
+            extensions_dir.display()
+        ),
+    )
+    .unwrap();
+    let config = config_path.to_str().unwrap();
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            config,
+            "enable",
+            "--verbose",
+            "--os-release",
+            "2.0.0",
+            "camera",
+        ],
+        &env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Enabled extension: vendor-camera-stack-1.0.0"),
+        "stdout: {stdout}"
+    );
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["-c", config, "ext", "info", "camera", "-o", "json"],
+        &env,
+    );
+    let info: serde_json::Value =
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+    assert_eq!(info["extension"], "vendor-camera-stack-1.0.0");
+    assert_eq!(info["aliases"], serde_json::json!(["camera"]));
+
+    let (output, _) =
+        run_avocadoctl_with_isolated_env(&["-c", config, "ext", "status", "-o", "json"], &env);
+    let status: serde_json::Value =
+        serde_json::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+    let camera = status["extensions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["name"] == "vendor-camera-stack-1.0.0")
+        .expect("extension listed");
+    assert_eq!(camera["aliases"], serde_json::json!(["camera"]));
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["-c", config, "ext", "status"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("alias: camera"), "stdout: {stdout}");
+}
+
+#[test]
+fn test_hook_commands_receive_extension_environment() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let release_dir = extensions_dir.join("app-1.0.0/usr/lib/extension-release.d");
+    let merged_release_dir = temp_dir.path().join("merged/usr/lib/extension-release.d");
+    fs::create_dir_all(&release_dir).unwrap();
+    fs::create_dir_all(&merged_release_dir).unwrap();
+    let content = "ID=_any\nAVOCADO_ON_MERGE=hook_env\nAVOCADO_ON_UNMERGE=hook_env\n";
+    fs::write(release_dir.join("extension-release.app-1.0.0"), content).unwrap();
+    fs::write(
+        merged_release_dir.join("extension-release.app-1.0.0"),
+        content,
+    )
+    .unwrap();
+    let release_root = temp_dir.path().join("merged");
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        (
+            "AVOCADO_EXTENSION_RELEASE_DIR",
+            release_root.to_str().unwrap(),
+        ),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "unmerge"], &env);
+    assert!(output.status.success());
+
+    let log = fs::read_to_string(temp_dir.path().join("hook-env.log")).unwrap();
+    let mount_point = format!(
+        "AVOCADO_MOUNT_POINT={}",
+        extensions_dir.join("app-1.0.0").display()
+    );
+    let runs: Vec<&str> = log.split("AVOCADO_OPERATION=").skip(1).collect();
+    assert_eq!(runs.len(), 2, "log: {log}");
+    assert!(runs[0].starts_with("merge\n"), "log: {log}");
+    assert!(runs[1].starts_with("unmerge\n"), "log: {log}");
+    for run in runs {
+        assert!(run.contains("AVOCADO_EXTENSION=app-1.0.0\n"), "log: {log}");
+        assert!(run.contains(&mount_point), "log: {log}");
+        assert!(!run.contains("AVOCADO_OS_VERSION=\n"), "log: {log}");
+    }
+}
+
+#[test]
+fn test_shared_hook_command_runs_for_each_extension() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let extensions_dir = temp_dir.path().join("extensions");
+    let merged_release_dir = temp_dir.path().join("merged/usr/lib/extension-release.d");
+    fs::create_dir_all(&merged_release_dir).unwrap();
+    let content = "ID=_any\nAVOCADO_ON_MERGE=hook_env\nAVOCADO_ON_UNMERGE=hook_env\n";
+    for name in ["app-1.0.0", "tools-2.0.0"] {
+        let release_dir = extensions_dir
+            .join(name)
+            .join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            content,
+        )
+        .unwrap();
+        fs::write(
+            merged_release_dir.join(format!("extension-release.{name}")),
+            content,
+        )
+        .unwrap();
+    }
+    let release_root = temp_dir.path().join("merged");
+    let env = [
+        ("AVOCADO_EXTENSIONS_PATH", extensions_dir.to_str().unwrap()),
+        (
+            "AVOCADO_EXTENSION_RELEASE_DIR",
+            release_root.to_str().unwrap(),
+        ),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "merge"], &env);
+    assert!(output.status.success());
+    let (output, _) = run_avocadoctl_with_isolated_env(&["ext", "unmerge"], &env);
+    assert!(output.status.success());
+
+    let log = fs::read_to_string(temp_dir.path().join("hook-env.log")).unwrap();
+    let runs: Vec<&str> = log.split("AVOCADO_OPERATION=").skip(1).collect();
+    assert_eq!(runs.len(), 4, "log: {log}");
+    for operation in ["merge", "unmerge"] {
+        let seen: Vec<&str> = runs
+            .iter()
+            .filter(|run| run.starts_with(&format!("{operation}\n")))
+            .copied()
+            .collect();
+        for name in ["app-1.0.0", "tools-2.0.0"] {
+            let mount_point = format!(
+                "AVOCADO_MOUNT_POINT={}\n",
+                extensions_dir.join(name).display()
+            );
+            assert!(
+                seen.iter().any(|run| {
+                    run.contains(&format!("AVOCADO_EXTENSION={name}\n"))
+                        && run.contains(&mount_point)
+                }),
+                "{operation} of {name} in log: {log}"
+            );
+        }
+    }
+}
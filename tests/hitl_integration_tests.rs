@@ -181,6 +181,261 @@ fn test_hitl_mount_with_mocks() {
     );
 }
 
+/// Test that hitl mount/unmount maintain the server registry used by the
+/// daemon's health monitor
+#[test]
+fn test_hitl_mount_records_server_registry() {
+    let env_extra: [(&str, &str); 0] = [];
+    let (output, temp_dir) = run_avocadoctl_with_isolated_env(
+        &[
+            "hitl",
+            "mount",
+            "-s",
+            "192.168.1.10",
+            "-p",
+            "2049",
+            "-e",
+            "foo",
+        ],
+        &env_extra,
+    );
+    assert!(
+        output.status.success(),
+        "Hitl mount should succeed with mocks: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let registry = temp_dir.path().join("avocado/hitl-servers.json");
+    let content = std::fs::read_to_string(&registry).expect("registry should be written");
+    let mounts: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(mounts[0]["extension"], "foo");
+    assert_eq!(mounts[0]["server"], "192.168.1.10");
+    assert_eq!(mounts[0]["port"], "2049");
+
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let new_path = format!(
+        "{}:{}",
+        current_dir.join("tests/fixtures").to_string_lossy(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let output = run_avocadoctl_with_env(
+        &["hitl", "unmount", "-e", "foo"],
+        &[
+            ("AVOCADO_TEST_MODE", "1"),
+            ("PATH", &new_path),
+            ("TMPDIR", &temp_dir.path().to_string_lossy()),
+        ],
+    );
+    assert!(output.status.success(), "Hitl unmount should succeed");
+    let content = std::fs::read_to_string(&registry).unwrap();
+    assert_eq!(content.trim(), "[]", "unmount should forget the server");
+}
+
+/// Test mounting extensions from different servers in one invocation
+#[test]
+fn test_hitl_mount_multiple_servers() {
+    let env_extra: [(&str, &str); 0] = [];
+    let (output, temp_dir) = run_avocadoctl_with_isolated_env(
+        &[
+            "hitl",
+            "mount",
+            "-s",
+            "192.168.1.10",
+            "-e",
+            "app",
+            "-e",
+            "fw-tools@192.168.1.20:2049",
+            "-v",
+        ],
+        &env_extra,
+    );
+    assert!(
+        output.status.success(),
+        "Hitl mount should succeed with mocks: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Mounting extensions from 192.168.1.10:12049"));
+    assert!(stdout.contains("Mounting extensions from 192.168.1.20:2049"));
+
+    let registry = temp_dir.path().join("avocado/hitl-servers.json");
+    let content = std::fs::read_to_string(&registry).expect("registry should be written");
+    let mounts: serde_json::Value = serde_json::from_str(&content).unwrap();
+    assert_eq!(mounts[0]["extension"], "app");
+    assert_eq!(mounts[0]["server"], "192.168.1.10");
+    assert_eq!(mounts[0]["port"], "12049");
+    assert_eq!(mounts[1]["extension"], "fw-tools");
+    assert_eq!(mounts[1]["server"], "192.168.1.20");
+    assert_eq!(mounts[1]["port"], "2049");
+
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let new_path = format!(
+        "{}:{}",
+        current_dir.join("tests/fixtures").to_string_lossy(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let output = run_avocadoctl_with_env(
+        &["hitl", "unmount", "-e", "fw-tools@192.168.1.20:2049"],
+        &[
+            ("AVOCADO_TEST_MODE", "1"),
+            ("PATH", &new_path),
+            ("TMPDIR", &temp_dir.path().to_string_lossy()),
+        ],
+    );
+    assert!(output.status.success(), "Hitl unmount should succeed");
+    let mounts: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&registry).unwrap()).unwrap();
+    assert_eq!(mounts.as_array().unwrap().len(), 1);
+    assert_eq!(mounts[0]["extension"], "app");
+    assert!(!temp_dir.path().join("avocado/hitl/fw-tools").exists());
+    assert!(temp_dir.path().join("avocado/hitl/app").exists());
+
+    // Without -s, every extension needs its own server
+    let (output, _) = run_avocadoctl_with_isolated_env(&["hitl", "mount", "-e", "app"], &env_extra);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No server for extension 'app'"));
+}
+
+/// Test falling back to another port and NFS version when the requested
+/// combination does not work
+#[test]
+fn test_hitl_mount_falls_back_to_port_and_version() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let open_port = listener.local_addr().unwrap().port();
+    let closed_port = {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        closed.local_addr().unwrap().port()
+    };
+
+    let config_dir = TempDir::new().unwrap();
+    let config_path = config_dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\n\n[avocado.hitl]\nfallback_ports = [{open_port}]\nnfs_versions = [\"4.2\", \"3\"]\nprobe_timeout_ms = 500\n",
+            config_dir.path().join("images").display()
+        ),
+    )
+    .unwrap();
+
+    let closed = closed_port.to_string();
+    let (output, temp_dir) = run_avocadoctl_with_isolated_env(
+        &[
+            "-c",
+            config_path.to_str().unwrap(),
+            "hitl",
+            "mount",
+            "-s",
+            "127.0.0.1",
+            "-p",
+            &closed,
+            "-e",
+            "app",
+        ],
+        &[("MOCK_NFS_UNSUPPORTED_VERS", "4.2")],
+    );
+    assert!(
+        output.status.success(),
+        "Hitl mount should fall back: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!(
+            "Mounted app from 127.0.0.1:{open_port} using NFSv3"
+        )),
+        "stdout: {stdout}"
+    );
+
+    let registry = temp_dir.path().join("avocado/hitl-servers.json");
+    let mounts: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&registry).unwrap()).unwrap();
+    assert_eq!(mounts[0]["port"], open_port.to_string());
+    assert_eq!(mounts[0]["nfs_version"], "3");
+
+    let transports = temp_dir.path().join("avocado/hitl-transports.json");
+    let transports: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&transports).unwrap()).unwrap();
+    assert_eq!(transports["127.0.0.1"]["port"], open_port.to_string());
+    assert_eq!(transports["127.0.0.1"]["version"], "3");
+    drop(listener);
+}
+
+/// Answer every handshake on a local control port with `reply`.
+fn serve_handshake(reply: &'static str) -> u16 {
+    use std::io::{BufRead, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut hello = String::new();
+            let _ = std::io::BufReader::new(&stream).read_line(&mut hello);
+            let _ = (&stream).write_all(format!("{reply}\n").as_bytes());
+        }
+    });
+    port
+}
+
+/// Test that the control port handshake rejects extensions the server does
+/// not export and servers too old to speak the protocol, before mounting
+#[test]
+fn test_hitl_mount_handshake_validates_server() {
+    let mount = |control_port: u16, extensions: &[&str]| {
+        let port = control_port.to_string();
+        let mut args = vec!["hitl", "mount", "-s", "127.0.0.1", "--control-port", &port];
+        for extension in extensions {
+            args.extend(["-e", extension]);
+        }
+        let (output, temp_dir) = run_avocadoctl_with_isolated_env(&args, &[]);
+        (
+            output.status.success(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+            temp_dir,
+        )
+    };
+
+    let current = serve_handshake(
+        r#"{"protocol":1,"server":"avocado","version":"0.21.0","capabilities":["overlay"],"extensions":[{"name":"app","version":"1.0.0"}]}"#,
+    );
+    let (success, stderr, temp_dir) = mount(current, &["app", "debug", "app-2.0.0"]);
+    assert!(!success);
+    assert!(temp_dir.path().join("avocado/hitl/app").exists());
+    assert!(!temp_dir.path().join("avocado/hitl/debug").exists());
+    assert!(
+        stderr.contains("Extension 'debug' is not available from 127.0.0.1: not exported by the server (exports: app-1.0.0)"),
+        "stderr: {stderr}"
+    );
+    assert!(
+        stderr.contains("the server exports app-1.0.0, not app-2.0.0"),
+        "stderr: {stderr}"
+    );
+    assert!(
+        stderr.contains("2 of 3 extension(s) failed to mount"),
+        "stderr: {stderr}"
+    );
+
+    let outdated = serve_handshake(r#"{"server":"avocado","version":"0.9.0"}"#);
+    let (success, stderr, temp_dir) = mount(outdated, &["app"]);
+    assert!(!success);
+    assert!(!temp_dir.path().join("avocado/hitl/app").exists());
+    assert!(
+        stderr.contains("runs avocado 0.9.0, which does not speak handshake protocol 1"),
+        "stderr: {stderr}"
+    );
+    assert!(stderr.contains("[E0031]"), "stderr: {stderr}");
+
+    let closed = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let (success, stderr, _) = mount(closed, &["app"]);
+    assert!(!success);
+    assert!(
+        stderr.contains("may predate the handshake"),
+        "stderr: {stderr}"
+    );
+}
+
 /// Test hitl mount with short options
 #[test]
 fn test_hitl_mount_short_options() {
@@ -500,6 +755,115 @@ exit 1
     );
 }
 
+/// Test that mounting several extensions reports each result and either
+/// keeps going past a failure or stops at it
+#[test]
+fn test_hitl_mount_partial_failure_summary() {
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let fixtures_path = current_dir.join("tests/fixtures");
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    // A mock-systemd-mount that fails for the "broken" share only
+    let temp_bin_dir = temp_dir.path().join("bin");
+    std::fs::create_dir_all(&temp_bin_dir).unwrap();
+    let mock_mount_path = temp_bin_dir.join("mock-systemd-mount");
+    std::fs::write(
+        &mock_mount_path,
+        format!(
+            r#"#!/bin/bash
+if [[ "$*" == *":/broken "* ]]; then
+    echo "mount.nfs: access denied by server" >&2
+    exit 32
+fi
+exec {} "$@"
+"#,
+            fixtures_path.join("mock-systemd-mount").to_string_lossy()
+        ),
+    )
+    .unwrap();
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&mock_mount_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let new_path = format!(
+        "{}:{}:{}",
+        temp_bin_dir.to_string_lossy(),
+        fixtures_path.to_string_lossy(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\n\n[avocado.hitl]\nparallel_mounts = 1\n",
+            temp_dir.path().join("avocado/extensions").to_string_lossy()
+        ),
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        run_avocadoctl_with_env(
+            args,
+            &[
+                ("AVOCADO_TEST_MODE", "1"),
+                ("PATH", &new_path),
+                ("TMPDIR", &temp_dir.path().to_string_lossy()),
+            ],
+        )
+    };
+
+    // --keep-going (the default) mounts the rest and fails at the end
+    let output = run(&[
+        "hitl", "mount", "-s", "10.0.2.2", "-e", "app", "-e", "broken", "-e", "tools",
+    ]);
+    assert!(
+        !output.status.success(),
+        "A failed mount should fail the command"
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("EXTENSION"),
+        "Should print a summary: {stdout}"
+    );
+    let row = |name: &str| {
+        stdout
+            .lines()
+            .find(|l| l.starts_with(name))
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert!(row("app").contains("mounted"), "{stdout}");
+    assert!(row("broken").contains("failed"), "{stdout}");
+    assert!(row("tools").contains("mounted"), "{stdout}");
+    assert!(stderr.contains("Failed to mount extension broken"));
+    assert!(stderr.contains("1 of 3 extension(s) failed to mount"));
+    assert!(temp_dir.path().join("avocado/hitl/tools").exists());
+    assert!(!temp_dir.path().join("avocado/hitl/broken").exists());
+
+    // --fail-fast does not start mounts after the first failure
+    let output = run(&[
+        "-c",
+        config_path.to_str().unwrap(),
+        "hitl",
+        "mount",
+        "-s",
+        "10.0.2.2",
+        "-e",
+        "broken",
+        "-e",
+        "other",
+        "--fail-fast",
+    ]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout
+        .lines()
+        .any(|l| l.starts_with("other") && l.contains("skipped")));
+    assert!(!temp_dir.path().join("avocado/hitl/other").exists());
+
+    let output = run(&["hitl", "mount", "-e", "a", "--keep-going", "--fail-fast"]);
+    assert!(!output.status.success(), "The policies are exclusive");
+}
+
 /// Test that HITL mount creates service drop-ins when extension has AVOCADO_ENABLE_SERVICES
 #[test]
 fn test_hitl_mount_creates_service_dropins() {
@@ -596,6 +960,83 @@ AVOCADO_ENABLE_SERVICES="nginx prometheus"
     );
 }
 
+/// Test that hitl.toml on the share remounts with extra options, adds
+/// environment to the drop-ins and restarts services on mount and unmount
+#[test]
+fn test_hitl_mount_applies_extension_overrides() {
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let fixtures_path = current_dir.join("tests/fixtures");
+    let original_path = std::env::var("PATH").unwrap_or_default();
+    let new_path = format!("{}:{}", fixtures_path.to_string_lossy(), original_path);
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+    let extension_dir = temp_dir.path().join("avocado/hitl/test-ext");
+    let release_dir = extension_dir.join("usr/lib/extension-release.d");
+    std::fs::create_dir_all(&release_dir).expect("Failed to create release directory");
+    std::fs::write(
+        release_dir.join("extension-release.test-ext"),
+        "ID=_any\nAVOCADO_ENABLE_SERVICES=\"nginx\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        extension_dir.join("hitl.toml"),
+        r#"mount_options = ["noac"]
+restart = ["app"]
+
+[environment]
+APP_LOG_LEVEL = "debug"
+"#,
+    )
+    .unwrap();
+    let env = [
+        ("AVOCADO_TEST_MODE", "1"),
+        ("PATH", new_path.as_str()),
+        ("TMPDIR", &temp_dir.path().to_string_lossy()),
+    ];
+
+    let output = run_avocadoctl_with_env(
+        &[
+            "hitl",
+            "mount",
+            "-s",
+            "10.0.2.2",
+            "-e",
+            "test-ext",
+            "--verbose",
+        ],
+        &env,
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "mount: {output:?}");
+    assert!(stdout.contains("Remounting test-ext with noac"), "{stdout}");
+    assert!(
+        stdout.contains("mock-systemctl called with args: try-restart app.service"),
+        "{stdout}"
+    );
+
+    let dropin = std::fs::read_to_string(
+        temp_dir
+            .path()
+            .join("run/systemd/system/nginx.service.d/10-hitl-test-ext.conf"),
+    )
+    .unwrap();
+    assert!(
+        dropin.contains("[Service]\nEnvironment=\"APP_LOG_LEVEL=debug\"\n"),
+        "{dropin}"
+    );
+    let registry =
+        std::fs::read_to_string(temp_dir.path().join("avocado/hitl-servers.json")).unwrap();
+    assert!(registry.contains("APP_LOG_LEVEL"), "{registry}");
+
+    let output = run_avocadoctl_with_env(&["hitl", "unmount", "-e", "test-ext", "--verbose"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "unmount: {output:?}");
+    assert!(
+        stdout.contains("mock-systemctl called with args: try-restart app.service"),
+        "{stdout}"
+    );
+}
+
 /// Test that HITL unmount cleans up service drop-ins
 #[test]
 fn test_hitl_unmount_cleans_service_dropins() {
@@ -763,3 +1204,163 @@ fn test_ext_refresh_no_hitl_mounts() {
         "Refresh should complete successfully. stdout: {stdout}"
     );
 }
+
+/// Test that a confext-only HITL tree is only planned for /run/confexts,
+/// and that `--type` overrides detection
+#[test]
+fn test_hitl_confext_only_detection_and_type_override() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let tmp = temp_dir.path().to_string_lossy().to_string();
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let path = format!(
+        "{}:{}",
+        current_dir.join("tests/fixtures").to_string_lossy(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let empty_images = temp_dir.path().join("images");
+    std::fs::create_dir_all(&empty_images).unwrap();
+    let env = [
+        ("AVOCADO_TEST_MODE", "1"),
+        ("PATH", path.as_str()),
+        ("TMPDIR", tmp.as_str()),
+        ("AVOCADO_EXTENSIONS_PATH", empty_images.to_str().unwrap()),
+    ];
+
+    let hitl_dir = temp_dir.path().join("avocado/hitl");
+    std::fs::create_dir_all(hitl_dir.join("settings/etc/settings.d")).unwrap();
+
+    let output = run_avocadoctl_with_env(&["ext", "merge", "--dry-run"], &env);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("link confext/settings"), "stdout: {stdout}");
+    assert!(!stdout.contains("link sysext/settings"), "stdout: {stdout}");
+
+    // A tree with both usr/ and etc/ is forced to sysext only
+    let output = run_avocadoctl_with_env(
+        &[
+            "hitl", "mount", "-s", "10.0.0.1", "-e", "tools", "--type", "sysext",
+        ],
+        &env,
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let registry =
+        std::fs::read_to_string(temp_dir.path().join("avocado/hitl-servers.json")).unwrap();
+    assert!(
+        registry.contains("\"mount_type\": \"sysext\""),
+        "{registry}"
+    );
+    std::fs::create_dir_all(hitl_dir.join("tools/usr/bin")).unwrap();
+    std::fs::create_dir_all(hitl_dir.join("tools/etc")).unwrap();
+
+    let output = run_avocadoctl_with_env(&["ext", "merge", "--dry-run"], &env);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("link sysext/tools"), "stdout: {stdout}");
+    assert!(!stdout.contains("link confext/tools"), "stdout: {stdout}");
+}
+
+/// Test that a quiesced HITL extension holds refreshes until it is resumed
+#[test]
+fn test_hitl_quiesce_holds_refresh() {
+    let config_dir = TempDir::new().unwrap();
+    let config_path = config_dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "[avocado.ext]\ndir = \"{}\"\n\n[avocado.hitl]\nsync_wait_ms = 0\n",
+            config_dir.path().join("images").display()
+        ),
+    )
+    .unwrap();
+    let config = config_path.to_str().unwrap();
+
+    let (output, temp_dir) =
+        run_avocadoctl_with_isolated_env(&["-c", config, "hitl", "quiesce", "app"], &[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let quiesced =
+        std::fs::read_to_string(temp_dir.path().join("avocado/hitl-quiesced.json")).unwrap();
+    assert!(quiesced.contains("\"app\""), "{quiesced}");
+
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let new_path = format!(
+        "{}:{}",
+        current_dir.join("tests/fixtures").to_string_lossy(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let temp_path = temp_dir.path().to_string_lossy().to_string();
+    let env = [
+        ("AVOCADO_TEST_MODE", "1"),
+        ("PATH", new_path.as_str()),
+        ("TMPDIR", temp_path.as_str()),
+    ];
+
+    let output = run_avocadoctl_with_env(&["-c", config, "ext", "refresh"], &env);
+    assert!(!output.status.success(), "refresh should wait for 'app'");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("E0025"), "stderr: {stderr}");
+
+    let output = run_avocadoctl_with_env(&["-c", config, "hitl", "resume", "app"], &env);
+    assert!(output.status.success());
+    let quiesced =
+        std::fs::read_to_string(temp_dir.path().join("avocado/hitl-quiesced.json")).unwrap();
+    assert_eq!(quiesced.trim(), "[]");
+
+    let output = run_avocadoctl_with_env(&["-c", config, "ext", "refresh"], &env);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Test that drop-ins of HITL extensions that are no longer mounted, as a
+/// crashed hitl unmount leaves them, are removed by the next unmerge but
+/// not by a status query
+#[test]
+fn test_orphaned_hitl_dropins_are_removed() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let systemd_dir = temp_dir.path().join("run/systemd/system");
+    let header = "# Auto-generated by avocadoctl hitl mount for extension: ";
+    std::fs::create_dir_all(systemd_dir.join("nginx.service.d")).unwrap();
+    std::fs::write(
+        systemd_dir.join("nginx.service.d/10-hitl-gone.conf"),
+        format!("{header}gone\n[Unit]\nRequiresMountsFor=/run/avocado/hitl/gone\n"),
+    )
+    .unwrap();
+    std::fs::create_dir_all(systemd_dir.join("app.service.d")).unwrap();
+    std::fs::write(
+        systemd_dir.join("app.service.d/10-hitl-live.conf"),
+        format!("{header}live\n[Unit]\nRequiresMountsFor=/run/avocado/hitl/live\n"),
+    )
+    .unwrap();
+    std::fs::create_dir_all(temp_dir.path().join("avocado/hitl/live")).unwrap();
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "list", "--verbose"],
+        &[("TMPDIR", &temp_dir.path().to_string_lossy())],
+    );
+    assert!(output.status.success());
+    assert!(systemd_dir
+        .join("nginx.service.d/10-hitl-gone.conf")
+        .exists());
+
+    let (output, _) = run_avocadoctl_with_isolated_env(
+        &["ext", "unmerge", "--verbose"],
+        &[("TMPDIR", &temp_dir.path().to_string_lossy())],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "stdout: {stdout}");
+    assert!(
+        stdout.contains("HITL extension 'gone', which is no longer mounted"),
+        "stdout: {stdout}"
+    );
+    assert!(!systemd_dir.join("nginx.service.d").exists());
+    assert!(systemd_dir.join("app.service.d/10-hitl-live.conf").exists());
+}
@@ -0,0 +1,59 @@
+//! Size budget of the initrd build.
+//!
+//! `cargo build --profile initrd --no-default-features` is the binary
+//! embedded in the initramfs. This test builds it and fails when it
+//! outgrows [`INITRD_SIZE_BUDGET`] or when a gated subsystem leaks back in.
+//! The build takes minutes, so the test is ignored by default; CI runs it
+//! with `--ignored`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Largest acceptable initrd binary, in bytes. Raise it in the change that
+/// needs the room, with the reason.
+const INITRD_SIZE_BUDGET: u64 = 3_500_000;
+
+#[test]
+#[ignore = "builds the initrd profile; run with --ignored"]
+fn test_initrd_build_fits_size_budget() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // A target directory of its own: the running `cargo test` may hold the
+    // lock on the default one
+    let target_dir = manifest_dir.join("target").join("size-budget");
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "build",
+            "--profile",
+            "initrd",
+            "--no-default-features",
+            "--bin",
+            "avocadoctl",
+            "--target-dir",
+        ])
+        .arg(&target_dir)
+        .current_dir(&manifest_dir)
+        .status()
+        .expect("Failed to run cargo");
+    assert!(status.success(), "initrd build failed");
+
+    let binary = target_dir.join("initrd").join("avocadoctl");
+    let size = fs::metadata(&binary)
+        .expect("Failed to stat the initrd binary")
+        .len();
+    assert!(
+        size <= INITRD_SIZE_BUDGET,
+        "{} is {size} bytes, over the initrd budget of {INITRD_SIZE_BUDGET} bytes",
+        binary.display()
+    );
+
+    let help = Command::new(&binary)
+        .arg("--help")
+        .output()
+        .expect("Failed to run the initrd binary");
+    let help = String::from_utf8_lossy(&help.stdout);
+    assert!(help.contains("ext"), "help: {help}");
+    for gated in ["serve", "root-authority"] {
+        assert!(!help.contains(gated), "{gated} in the initrd build: {help}");
+    }
+}
@@ -208,3 +208,264 @@ fn test_top_level_aliases() {
     let refresh_help = run_avocadoctl(&["refresh", "--help"]);
     assert!(refresh_help.status.success(), "Refresh help should succeed");
 }
+
+/// Test that init writes the configuration, directories and units, and keeps
+/// existing files on a second run
+#[test]
+fn test_init_sets_up_device() {
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let path = format!(
+        "{}:{}",
+        current_dir.join("tests/fixtures").display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let config_path = temp_dir.path().join("etc/avocadoctl.conf");
+    let extensions_dir = temp_dir.path().join("images");
+    let env = [
+        ("AVOCADO_TEST_MODE", "1"),
+        ("PATH", path.as_str()),
+        ("TMPDIR", temp_dir.path().to_str().unwrap()),
+    ];
+    let args = [
+        "-c",
+        config_path.to_str().unwrap(),
+        "init",
+        "--yes",
+        "--extensions-dir",
+        extensions_dir.to_str().unwrap(),
+        "--confext-mutable",
+        "no",
+        "-o",
+        "json",
+    ];
+
+    let output = run_avocadoctl_with_env(&args, &env);
+    assert!(
+        output.status.success(),
+        "init should succeed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["ok"], true);
+    assert_eq!(summary["steps"][0]["state"], "written");
+    assert_eq!(summary["tools"][0]["tool"], "systemd-sysext");
+    assert!(summary["tools"][0]["program"]
+        .as_str()
+        .unwrap()
+        .ends_with("mock-systemd-sysext"));
+
+    let config = fs::read_to_string(&config_path).unwrap();
+    assert!(config.contains(&format!("dir = \"{}\"", extensions_dir.display())));
+    assert!(config.contains("confext_mutable = \"no\""));
+    assert!(extensions_dir.is_dir());
+    assert!(temp_dir.path().join("avocado/os-releases").is_dir());
+    let units = temp_dir.path().join("avocado/systemd/system");
+    assert!(units.join("avocadoctl.socket").is_file());
+    assert!(units.join("avocadoctl.service").is_file());
+
+    // A second run keeps what is there
+    fs::write(&config_path, "[avocado.ext]\ndir = \"/custom\"\n").unwrap();
+    let output = run_avocadoctl_with_env(&args, &env);
+    assert!(output.status.success());
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["steps"][0]["state"], "kept");
+    assert_eq!(summary["steps"][1]["state"], "kept");
+    assert!(fs::read_to_string(&config_path)
+        .unwrap()
+        .contains("/custom"));
+}
+
+/// remote uploads local images, runs the command over ssh and exits with
+/// the remote command's exit code
+#[test]
+fn test_remote_uploads_and_runs_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let fixtures_path = std::env::current_dir().unwrap().join("tests/fixtures");
+    let path = format!(
+        "{}:{}",
+        fixtures_path.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let log = temp_dir.path().join("ssh.log");
+    let image = temp_dir.path().join("app-1.0.raw");
+    fs::write(&image, b"raw").unwrap();
+    let image = image.to_string_lossy().to_string();
+    let tmpdir = temp_dir.path().to_string_lossy().to_string();
+    let log_str = log.to_string_lossy().to_string();
+    let env = [
+        ("AVOCADO_TEST_MODE", "1"),
+        ("PATH", path.as_str()),
+        ("TMPDIR", tmpdir.as_str()),
+        ("MOCK_SSH_LOG", log_str.as_str()),
+        ("MOCK_SSH_EXIT", "3"),
+    ];
+
+    let output = run_avocadoctl_with_env(
+        &[
+            "remote",
+            "--host",
+            "dev@10.0.0.5",
+            "--port",
+            "2222",
+            "ext",
+            "enable",
+            &image,
+            "sensor-*",
+        ],
+        &env,
+    );
+    assert_eq!(
+        output.status.code(),
+        Some(3),
+        "remote exit code is passed on"
+    );
+
+    let log = fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 3, "mkdir, upload and command: {log}");
+    assert!(
+        lines[0].starts_with("ssh ") && lines[0].ends_with("mkdir -p /var/lib/avocado/uploads")
+    );
+    assert!(lines[1].starts_with("scp -o BatchMode=yes -P 2222"));
+    assert!(lines[1].ends_with(&format!("{image} dev@10.0.0.5:/var/lib/avocado/uploads/")));
+    assert!(lines[2].ends_with(
+        "dev@10.0.0.5 -- avocadoctl ext enable /var/lib/avocado/uploads/app-1.0.raw 'sensor-*'"
+    ));
+}
+
+/// remote --sudo uploads to a staging directory of the SSH user and
+/// installs the files into the root-owned upload directory with sudo
+#[test]
+fn test_remote_sudo_installs_from_staging() {
+    let temp_dir = TempDir::new().unwrap();
+    let fixtures_path = std::env::current_dir().unwrap().join("tests/fixtures");
+    let path = format!(
+        "{}:{}",
+        fixtures_path.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let log = temp_dir.path().join("ssh.log");
+    let image = temp_dir.path().join("app-1.0.raw");
+    fs::write(&image, b"raw").unwrap();
+    let image = image.to_string_lossy().to_string();
+    let tmpdir = temp_dir.path().to_string_lossy().to_string();
+    let log_str = log.to_string_lossy().to_string();
+    let env = [
+        ("AVOCADO_TEST_MODE", "1"),
+        ("PATH", path.as_str()),
+        ("TMPDIR", tmpdir.as_str()),
+        ("MOCK_SSH_LOG", log_str.as_str()),
+    ];
+    let args = [
+        "remote",
+        "--host",
+        "dev@10.0.0.5",
+        "--sudo",
+        "ext",
+        "enable",
+        &image,
+    ];
+
+    let output = run_avocadoctl_with_env(&args, &env);
+    assert!(output.status.success());
+    let log_text = fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = log_text.lines().collect();
+    assert_eq!(
+        lines.len(),
+        4,
+        "mkdir, upload, install and command: {log_text}"
+    );
+    let staging = lines[0]
+        .split_once("-- mkdir -m 700 ")
+        .map(|(_, dir)| dir)
+        .expect("staging directory created without sudo");
+    assert!(staging.starts_with("/tmp/avocadoctl-upload-"));
+    assert!(lines[1].ends_with(&format!("{image} dev@10.0.0.5:{staging}/")));
+    assert!(lines[2].ends_with(&format!(
+        "sudo -n mkdir -p /var/lib/avocado/uploads && sudo -n install -m 0644 \
+         {staging}/app-1.0.raw /var/lib/avocado/uploads/; status=$?; rm -rf {staging}; exit $status"
+    )));
+    assert!(
+        lines[3].ends_with("-- sudo -n avocadoctl ext enable /var/lib/avocado/uploads/app-1.0.raw")
+    );
+
+    // A directory that cannot be created stops before anything is copied
+    fs::remove_file(&log).unwrap();
+    let env = [env.as_slice(), &[("MOCK_SSH_SETUP_EXIT", "1")]].concat();
+    let output = run_avocadoctl_with_env(&args, &env);
+    assert_eq!(output.status.code(), Some(255));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Failed to create dev@10.0.0.5:"),
+        "{stderr}"
+    );
+    assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 1);
+}
+
+/// remote reports an unreachable device with its error code
+#[test]
+fn test_remote_unreachable() {
+    let temp_dir = TempDir::new().unwrap();
+    let fixtures_path = std::env::current_dir().unwrap().join("tests/fixtures");
+    let path = format!(
+        "{}:{}",
+        fixtures_path.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let tmpdir = temp_dir.path().to_string_lossy().to_string();
+    let env = [
+        ("AVOCADO_TEST_MODE", "1"),
+        ("PATH", path.as_str()),
+        ("TMPDIR", tmpdir.as_str()),
+        ("MOCK_SSH_EXIT", "255"),
+    ];
+
+    let output =
+        run_avocadoctl_with_env(&["remote", "--host", "dev@10.0.0.5", "ext", "list"], &env);
+    assert_eq!(output.status.code(), Some(255));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Cannot reach dev@10.0.0.5"), "{stderr}");
+    assert!(stderr.contains("E0029"), "{stderr}");
+}
+
+/// remote passes the global flags on, verbosity also in JSON mode
+#[test]
+fn test_remote_forwards_global_flags() {
+    let temp_dir = TempDir::new().unwrap();
+    let fixtures_path = std::env::current_dir().unwrap().join("tests/fixtures");
+    let path = format!(
+        "{}:{}",
+        fixtures_path.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let log = temp_dir.path().join("ssh.log");
+    let tmpdir = temp_dir.path().to_string_lossy().to_string();
+    let log_str = log.to_string_lossy().to_string();
+    let env = [
+        ("AVOCADO_TEST_MODE", "1"),
+        ("PATH", path.as_str()),
+        ("TMPDIR", tmpdir.as_str()),
+        ("MOCK_SSH_LOG", log_str.as_str()),
+    ];
+
+    let output = run_avocadoctl_with_env(
+        &[
+            "--json",
+            "-v",
+            "remote",
+            "--host",
+            "dev@10.0.0.5",
+            "ext",
+            "list",
+        ],
+        &env,
+    );
+    assert!(output.status.success());
+    let log = fs::read_to_string(&log).unwrap();
+    assert!(
+        log.trim_end()
+            .ends_with("-- avocadoctl --verbose --output json ext list"),
+        "{log}"
+    );
+}
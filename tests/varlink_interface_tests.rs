@@ -5,6 +5,9 @@
 //!   - The CLI routes requests through the daemon (not direct service calls)
 //!   - The daemon serialises concurrent callers on the socket
 //!   - Error messages are correct when the daemon is not running
+//!
+//! A build without the `daemon` feature has neither, so the tests need it.
+#![cfg(feature = "daemon")]
 
 use std::fs;
 use std::path::PathBuf;
@@ -282,3 +285,99 @@ fn test_concurrent_requests_serialised_by_daemon() {
         );
     }
 }
+
+/// Start `avocadoctl test-daemon` scripted by `scenario`, serving the
+/// extensions in `images`. No mock executables are put on PATH.
+#[cfg(feature = "dev")]
+fn start_test_daemon(scenario: &str, images: &std::path::Path) -> TestDaemon {
+    let temp_dir = TempDir::new().expect("temp dir");
+    let socket_path = temp_dir.path().join("avocadoctl-test.sock");
+    let scenario_path = temp_dir.path().join("scenario.toml");
+    fs::write(&scenario_path, scenario).expect("write scenario");
+
+    let child = Command::new(get_binary_path())
+        .args([
+            "test-daemon",
+            "--address",
+            &format!("unix:{}", socket_path.display()),
+            "--scenario",
+            scenario_path.to_str().unwrap(),
+        ])
+        .env("TMPDIR", temp_dir.path())
+        .env("AVOCADO_EXTENSIONS_PATH", images)
+        .env("PATH", "/usr/bin:/bin")
+        .spawn()
+        .expect("Failed to spawn test daemon");
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline && !socket_path.exists() {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(socket_path.exists(), "test daemon socket should appear");
+
+    TestDaemon {
+        child,
+        socket_path,
+        _temp_dir: temp_dir,
+    }
+}
+
+/// A scenario leaving an extension out of the merge shows up as a partial
+/// merge in `ext status`, and scripted failures and malformed status output
+/// reach the client.
+#[cfg(feature = "dev")]
+#[test]
+fn test_test_daemon_scenarios() {
+    let images = TempDir::new().expect("temp dir");
+    for name in ["app-1.0", "broken-1.0"] {
+        let release_dir = images.path().join(name).join("usr/lib/extension-release.d");
+        fs::create_dir_all(&release_dir).unwrap();
+        fs::write(
+            release_dir.join(format!("extension-release.{name}")),
+            "ID=_any\n",
+        )
+        .unwrap();
+    }
+
+    let daemon = start_test_daemon("[sysext]\nskip = [\"broken-1.0\"]\n", images.path());
+    let output = daemon.run(&["ext", "merge"]);
+    assert!(output.status.success(), "{output:?}");
+    let output = daemon.run(&["ext", "status", "-o", "json"]);
+    assert!(output.status.success(), "{output:?}");
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let merged = |name: &str| {
+        status
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["name"] == name)
+            .map(|e| e["isMerged"].clone())
+    };
+    assert_eq!(merged("app-1.0"), Some(serde_json::json!(true)));
+    assert_eq!(merged("broken-1.0"), Some(serde_json::json!(false)));
+    drop(daemon);
+
+    let daemon = start_test_daemon(
+        "[sysext]\nfail = [\"merge\"]\nstderr = \"Failed to merge: Device or resource busy\"\n",
+        images.path(),
+    );
+    let output = daemon.run(&["ext", "merge"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success(),
+        "scripted merge failure should fail"
+    );
+    assert!(
+        stderr.contains("Device or resource busy"),
+        "stderr: {stderr}"
+    );
+
+    let daemon = start_test_daemon(
+        "[sysext]\nstatus_json = '{\"hierarchy\": \"/usr\"'\n",
+        images.path(),
+    );
+    let output = daemon.run(&["ext", "status"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "malformed status should fail");
+    assert!(stderr.contains("status --json=short"), "stderr: {stderr}");
+}